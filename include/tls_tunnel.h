@@ -0,0 +1,84 @@
+#ifndef TLS_TUNNEL_H
+#define TLS_TUNNEL_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdint.h>
+
+// 嵌入的隧道客户端（对 C 不透明）
+typedef struct TtClient TtClient;
+
+// 会话事件回调（可以为 NULL）
+//
+// `event_json` 为单个事件的 JSON 对象（与 `events_socket` 镜像的格式相同），只在回调期间有效
+typedef void (*TtEventCallback)(const char *event_json, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// 从 TOML 配置文本创建客户端（不连接服务器）
+//
+// 配置无效或创建失败时返回 NULL。返回的客户端必须通过 [`tt_client_free`] 释放
+//
+// # Safety
+//
+// `config_toml` 必须是以 NUL 结尾的 UTF-8 字符串
+struct TtClient *tt_client_new(const char *config_toml);
+
+// 在后台启动客户端（自动重连），成功返回 0，已在运行或失败返回 -1
+//
+// # Safety
+//
+// `client` 必须是 [`tt_client_new`] 返回且尚未释放的指针
+int tt_client_start(struct TtClient *client);
+
+// 停止客户端并等待后台会话结束，成功返回 0（未运行时同样返回 0）
+//
+// 停止后可以再次调用 [`tt_client_start`]
+//
+// # Safety
+//
+// `client` 必须是 [`tt_client_new`] 返回且尚未释放的指针；不能在事件回调中调用
+int tt_client_stop(struct TtClient *client);
+
+// 以 JSON 返回客户端状态（`running`、`state`、`last_event`、`error`）
+//
+// 返回的字符串由调用方通过 [`tt_string_free`] 释放，失败返回 NULL
+//
+// # Safety
+//
+// `client` 必须是 [`tt_client_new`] 返回且尚未释放的指针
+char *tt_client_status_json(const struct TtClient *client);
+
+// 设置会话事件回调（`callback` 为 NULL 时取消），成功返回 0
+//
+// 返回后旧回调不会再被调用
+//
+// # Safety
+//
+// `client` 必须是 [`tt_client_new`] 返回且尚未释放的指针；不能在事件回调中调用。
+// `user_data` 在回调被替换或客户端释放前必须保持有效，且可以在其他线程中使用
+int tt_client_set_event_callback(struct TtClient *client,
+                                 TtEventCallback callback,
+                                 void *user_data);
+
+// 停止并释放客户端（NULL 时忽略）
+//
+// # Safety
+//
+// `client` 必须是 [`tt_client_new`] 返回的指针，释放后不能再使用
+void tt_client_free(struct TtClient *client);
+
+// 释放本库返回的字符串（NULL 时忽略）
+//
+// # Safety
+//
+// `s` 必须是本库返回且尚未释放的字符串
+void tt_string_free(char *s);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* TLS_TUNNEL_H */
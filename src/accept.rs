@@ -0,0 +1,213 @@
+/// 监听器 accept 错误的分类与退避
+///
+/// accept 错误分为三类：
+/// - 单个连接的临时错误（ECONNABORTED、握手失败等）：立即继续接受
+/// - 资源耗尽（EMFILE/ENFILE/ENOMEM/ENOBUFS）：指数退避后重试，错误日志限频输出
+/// - 监听套接字不可用（已关闭等）：停止监听，避免空转
+use std::fmt::Display;
+use std::io;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::time::Instant;
+use tracing::{error, warn};
+
+/// 资源耗尽时的初始退避时间
+const INITIAL_BACKOFF: Duration = Duration::from_millis(10);
+/// 资源耗尽时的最大退避时间
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+/// 资源耗尽错误日志的最短输出间隔
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+#[cfg(unix)]
+mod errno {
+    pub const EBADF: i32 = 9;
+    pub const ENOMEM: i32 = 12;
+    pub const EINVAL: i32 = 22;
+    pub const ENFILE: i32 = 23;
+    pub const EMFILE: i32 = 24;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub const ENOBUFS: i32 = 105;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub const ENOBUFS: i32 = 55;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub const ENOTSOCK: i32 = 88;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub const ENOTSOCK: i32 = 38;
+}
+
+#[cfg(windows)]
+mod errno {
+    pub const EBADF: i32 = 10009; // WSAEBADF
+    pub const ENOMEM: i32 = 8; // ERROR_NOT_ENOUGH_MEMORY
+    pub const EINVAL: i32 = 10022; // WSAEINVAL
+    pub const ENFILE: i32 = 4; // ERROR_TOO_MANY_OPEN_FILES
+    pub const EMFILE: i32 = 10024; // WSAEMFILE
+    pub const ENOBUFS: i32 = 10055; // WSAENOBUFS
+    pub const ENOTSOCK: i32 = 10038; // WSAENOTSOCK
+}
+
+/// 监听套接字本身的 accept 错误（区别于握手等单个连接的错误）
+///
+/// 传输层服务器用它包装 `TcpListener::accept` 的错误，以便调用方分类处理
+#[derive(Debug, Error)]
+#[error("Failed to accept TCP connection: {0}")]
+pub struct ListenerError(pub io::Error);
+
+/// accept 错误分类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcceptErrorKind {
+    /// 单个连接的临时错误，立即继续
+    Transient,
+    /// 资源耗尽，退避后重试
+    Resource,
+    /// 监听套接字不可用，停止监听
+    Fatal,
+}
+
+impl AcceptErrorKind {
+    /// 对监听套接字返回的 I/O 错误分类
+    pub fn of_io(error: &io::Error) -> Self {
+        if error.kind() == io::ErrorKind::OutOfMemory {
+            return Self::Resource;
+        }
+        match error.raw_os_error() {
+            Some(errno::EMFILE | errno::ENFILE | errno::ENOMEM | errno::ENOBUFS) => Self::Resource,
+            Some(errno::EBADF | errno::EINVAL | errno::ENOTSOCK) => Self::Fatal,
+            _ => Self::Transient,
+        }
+    }
+
+    /// 对传输层服务器的 accept 错误分类（只有 [`ListenerError`] 可能是资源或致命错误）
+    pub fn of_transport(error: &anyhow::Error) -> Self {
+        error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<ListenerError>())
+            .map_or(Self::Transient, |e| Self::of_io(&e.0))
+    }
+}
+
+/// accept 循环的退避状态（每个监听循环一个）
+pub struct AcceptBackoff {
+    /// 日志中标识监听器
+    label: String,
+    /// 下一次资源耗尽时的退避时间
+    delay: Duration,
+    /// 上一次输出资源耗尽日志的时间
+    logged_at: Option<Instant>,
+    /// 上次输出日志后未输出的资源耗尽错误数
+    unlogged: u64,
+}
+
+impl AcceptBackoff {
+    pub fn new(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            delay: INITIAL_BACKOFF,
+            logged_at: None,
+            unlogged: 0,
+        }
+    }
+
+    /// accept 成功后重置退避时间
+    pub fn reset(&mut self) {
+        self.delay = INITIAL_BACKOFF;
+    }
+
+    /// 处理一次 accept 错误，返回是否继续监听
+    ///
+    /// 资源耗尽时在返回前等待退避时间
+    pub async fn on_error(&mut self, kind: AcceptErrorKind, error: &(dyn Display + Sync)) -> bool {
+        match kind {
+            AcceptErrorKind::Transient => {
+                warn!("{}: accept error (continuing): {}", self.label, error);
+                true
+            }
+            AcceptErrorKind::Resource => {
+                let now = Instant::now();
+                if self
+                    .logged_at
+                    .is_none_or(|at| now.duration_since(at) >= LOG_INTERVAL)
+                {
+                    if self.unlogged > 0 {
+                        error!(
+                            "{}: accept failed, resources exhausted: {} ({} more since last report), backing off {:?}",
+                            self.label, error, self.unlogged, self.delay
+                        );
+                    } else {
+                        error!(
+                            "{}: accept failed, resources exhausted: {}, backing off {:?}",
+                            self.label, error, self.delay
+                        );
+                    }
+                    self.logged_at = Some(now);
+                    self.unlogged = 0;
+                } else {
+                    self.unlogged += 1;
+                }
+                tokio::time::sleep(self.delay).await;
+                self.delay = (self.delay * 2).min(MAX_BACKOFF);
+                true
+            }
+            AcceptErrorKind::Fatal => {
+                error!("{}: listener is no longer usable: {}", self.label, error);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_io_errors() {
+        let emfile = io::Error::from_raw_os_error(errno::EMFILE);
+        assert_eq!(AcceptErrorKind::of_io(&emfile), AcceptErrorKind::Resource);
+        let enobufs = io::Error::from_raw_os_error(errno::ENOBUFS);
+        assert_eq!(AcceptErrorKind::of_io(&enobufs), AcceptErrorKind::Resource);
+        let ebadf = io::Error::from_raw_os_error(errno::EBADF);
+        assert_eq!(AcceptErrorKind::of_io(&ebadf), AcceptErrorKind::Fatal);
+        let aborted = io::Error::from(io::ErrorKind::ConnectionAborted);
+        assert_eq!(AcceptErrorKind::of_io(&aborted), AcceptErrorKind::Transient);
+    }
+
+    #[test]
+    fn test_classify_transport_errors() {
+        use anyhow::Context;
+
+        // 握手失败即使包含 I/O 错误也只影响单个连接
+        let handshake: anyhow::Error = Err::<(), _>(io::Error::from_raw_os_error(errno::EINVAL))
+            .context("TLS handshake failed")
+            .unwrap_err();
+        assert_eq!(
+            AcceptErrorKind::of_transport(&handshake),
+            AcceptErrorKind::Transient
+        );
+
+        let listener: anyhow::Error =
+            ListenerError(io::Error::from_raw_os_error(errno::EMFILE)).into();
+        assert_eq!(
+            AcceptErrorKind::of_transport(&listener),
+            AcceptErrorKind::Resource
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_resource_backoff_grows_and_resets() {
+        let mut backoff = AcceptBackoff::new("test");
+        let start = Instant::now();
+        for _ in 0..10 {
+            assert!(backoff.on_error(AcceptErrorKind::Resource, &"EMFILE").await);
+        }
+        // 10 + 20 + ... + 640 毫秒，之后每次 1 秒
+        assert_eq!(start.elapsed(), Duration::from_millis(1270 + 3000));
+
+        backoff.reset();
+        let before = Instant::now();
+        backoff.on_error(AcceptErrorKind::Resource, &"EMFILE").await;
+        assert_eq!(before.elapsed(), INITIAL_BACKOFF);
+
+        assert!(!backoff.on_error(AcceptErrorKind::Fatal, &"EBADF").await);
+    }
+}
@@ -0,0 +1,270 @@
+/// 故障注入点（`chaos` 特性）
+///
+/// 启用 `chaos` 特性后，代码中的注入点（[`fail`]、[`delay`]）按名称查询全局注册表，测试通过
+/// [`enable`] 激活故障场景（配置确认写入失败、转发中途出错、会话清理变慢等），然后检查注册表
+/// 清空、统计计数归零、后台任务全部退出等不变量；[`task`] 返回的守卫按名称统计存活的后台任务。
+/// 未启用特性时注入点都是空操作，[`TaskGuard`] 是零大小类型。
+///
+/// 注入点：
+/// - `server.config_ack.before` / `server.config_ack.after`：服务器发送配置确认响应之前/之后
+/// - `client.config_accepted`：客户端处理服务器的配置确认时
+/// - `yamux.outbound`：出站 stream 创建后交给请求方之前（stream 随即被丢弃）
+/// - `server.proxy_bind`：绑定代理的公开端口
+/// - `relay.transfer`：转发循环读到数据之后、写出之前
+/// - `relay.corrupt`：分帧转发循环计算完整性校验和之后、写入 stream 之前（翻转一个字节）
+/// - `server.session_cleanup`：会话清理注销代理之前（延迟）
+/// - `stats.listener`：统计服务器接受下一个连接之前（监听套接字失效，由监督任务重新绑定）
+///
+/// 任务名称：`server.session`、`server.listener`、`server.relay`、`client.session`、`client.relay`
+use std::io;
+#[cfg(feature = "chaos")]
+use std::time::Duration;
+
+/// 注入点被激活时执行的动作
+#[cfg(feature = "chaos")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// [`fail`] 返回错误
+    Error,
+    /// [`delay`] 等待指定时长
+    Delay(Duration),
+    /// [`corrupt`] 翻转数据的第一个字节
+    Corrupt,
+}
+
+#[cfg(feature = "chaos")]
+mod registry {
+    use super::Action;
+    use std::collections::HashMap;
+    use std::sync::{LazyLock, Mutex};
+
+    pub(super) struct Failpoint {
+        pub action: Action,
+        /// 剩余触发次数（None 表示不限）
+        pub remaining: Option<u32>,
+        pub hits: u64,
+    }
+
+    pub(super) static FAILPOINTS: LazyLock<Mutex<HashMap<String, Failpoint>>> =
+        LazyLock::new(Default::default);
+
+    pub(super) static TASKS: LazyLock<Mutex<HashMap<&'static str, usize>>> =
+        LazyLock::new(Default::default);
+
+    /// 同一时间只运行一个故障场景（注入点是进程级的）
+    pub(super) static SCENARIO: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    /// 按名称触发注入点，动作类型匹配且仍有剩余次数时返回该动作
+    pub(super) fn trigger(name: &str, matches: fn(&Action) -> bool) -> Option<Action> {
+        let mut failpoints = FAILPOINTS.lock().unwrap();
+        let failpoint = failpoints.get_mut(name)?;
+        if !matches(&failpoint.action) || failpoint.remaining == Some(0) {
+            return None;
+        }
+        if let Some(remaining) = failpoint.remaining.as_mut() {
+            *remaining -= 1;
+        }
+        failpoint.hits += 1;
+        Some(failpoint.action)
+    }
+}
+
+/// 激活注入点，`times` 为触发次数（None 表示直到 [`disable`] 为止）
+#[cfg(feature = "chaos")]
+pub fn enable(name: &str, action: Action, times: Option<u32>) {
+    registry::FAILPOINTS.lock().unwrap().insert(
+        name.to_string(),
+        registry::Failpoint {
+            action,
+            remaining: times,
+            hits: 0,
+        },
+    );
+}
+
+/// 停用注入点
+#[cfg(feature = "chaos")]
+pub fn disable(name: &str) {
+    registry::FAILPOINTS.lock().unwrap().remove(name);
+}
+
+/// 停用所有注入点
+#[cfg(feature = "chaos")]
+pub fn reset() {
+    registry::FAILPOINTS.lock().unwrap().clear();
+}
+
+/// 注入点被触发的次数（未激活时为 0）
+#[cfg(feature = "chaos")]
+pub fn hits(name: &str) -> u64 {
+    registry::FAILPOINTS
+        .lock()
+        .unwrap()
+        .get(name)
+        .map_or(0, |failpoint| failpoint.hits)
+}
+
+/// 名为 `name` 的后台任务的存活数
+#[cfg(feature = "chaos")]
+pub fn live_tasks(name: &str) -> usize {
+    registry::TASKS
+        .lock()
+        .unwrap()
+        .get(name)
+        .copied()
+        .unwrap_or(0)
+}
+
+/// 所有存活的后台任务（按名称排序，不含已归零的名称）
+#[cfg(feature = "chaos")]
+pub fn all_live_tasks() -> Vec<(&'static str, usize)> {
+    let mut tasks: Vec<_> = registry::TASKS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, count)| **count > 0)
+        .map(|(name, count)| (*name, *count))
+        .collect();
+    tasks.sort();
+    tasks
+}
+
+/// 独占的故障场景：持有期间其他场景等待，开始和结束时停用所有注入点
+#[cfg(feature = "chaos")]
+pub struct Scenario {
+    _guard: tokio::sync::MutexGuard<'static, ()>,
+}
+
+/// 开始一个故障场景（同一进程内的多个测试依次运行）
+#[cfg(feature = "chaos")]
+pub async fn scenario() -> Scenario {
+    let guard = registry::SCENARIO.lock().await;
+    reset();
+    Scenario { _guard: guard }
+}
+
+#[cfg(feature = "chaos")]
+impl Drop for Scenario {
+    fn drop(&mut self) {
+        reset();
+    }
+}
+
+/// 错误注入点：激活时返回错误
+#[cfg(feature = "chaos")]
+pub fn fail(name: &str) -> io::Result<()> {
+    match registry::trigger(name, |action| matches!(action, Action::Error)) {
+        Some(_) => {
+            tracing::warn!("Chaos failpoint '{}' triggered", name);
+            Err(io::Error::other(format!("chaos failpoint '{}'", name)))
+        }
+        None => Ok(()),
+    }
+}
+
+/// 错误注入点（未启用 `chaos` 特性，空操作）
+#[cfg(not(feature = "chaos"))]
+#[inline(always)]
+pub fn fail(_name: &str) -> io::Result<()> {
+    Ok(())
+}
+
+/// 延迟注入点：激活时等待配置的时长
+#[cfg(feature = "chaos")]
+pub async fn delay(name: &str) {
+    if let Some(Action::Delay(duration)) =
+        registry::trigger(name, |action| matches!(action, Action::Delay(_)))
+    {
+        tracing::warn!("Chaos failpoint '{}' delaying {:?}", name, duration);
+        tokio::time::sleep(duration).await;
+    }
+}
+
+/// 延迟注入点（未启用 `chaos` 特性，空操作）
+#[cfg(not(feature = "chaos"))]
+#[inline(always)]
+pub async fn delay(_name: &str) {}
+
+/// 数据损坏注入点：激活时翻转 `data` 的第一个字节（数据为空时不触发）
+#[cfg(feature = "chaos")]
+pub fn corrupt(name: &str, data: &mut [u8]) {
+    if data.is_empty() {
+        return;
+    }
+    if registry::trigger(name, |action| matches!(action, Action::Corrupt)).is_some() {
+        tracing::warn!("Chaos failpoint '{}' flipping one byte", name);
+        data[0] ^= 0xff;
+    }
+}
+
+/// 数据损坏注入点（未启用 `chaos` 特性，空操作）
+#[cfg(not(feature = "chaos"))]
+#[inline(always)]
+pub fn corrupt(_name: &str, _data: &mut [u8]) {}
+
+/// 存活的后台任务守卫，被丢弃时计数减一
+#[must_use = "the task is counted only while the guard is alive"]
+pub struct TaskGuard {
+    #[cfg(feature = "chaos")]
+    name: &'static str,
+}
+
+/// 登记一个存活的后台任务（未启用 `chaos` 特性时不计数）
+#[inline(always)]
+pub fn task(name: &'static str) -> TaskGuard {
+    #[cfg(feature = "chaos")]
+    {
+        *registry::TASKS.lock().unwrap().entry(name).or_insert(0) += 1;
+        TaskGuard { name }
+    }
+    #[cfg(not(feature = "chaos"))]
+    {
+        let _ = name;
+        TaskGuard {}
+    }
+}
+
+#[cfg(feature = "chaos")]
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        if let Some(count) = registry::TASKS.lock().unwrap().get_mut(self.name) {
+            *count -= 1;
+        }
+    }
+}
+
+#[cfg(all(test, feature = "chaos"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_failpoint_times_and_tasks() {
+        let _scenario = scenario().await;
+
+        assert!(fail("test.point").is_ok());
+        enable("test.point", Action::Error, Some(2));
+        assert!(fail("test.point").is_err());
+        assert!(fail("test.point").is_err());
+        assert!(fail("test.point").is_ok());
+        assert_eq!(hits("test.point"), 2);
+
+        // 动作类型不匹配的注入点不触发
+        enable("test.point", Action::Delay(Duration::from_millis(1)), None);
+        assert!(fail("test.point").is_ok());
+        delay("test.point").await;
+        assert_eq!(hits("test.point"), 1);
+        disable("test.point");
+        assert_eq!(hits("test.point"), 0);
+
+        let first = task("test.task");
+        let second = task("test.task");
+        assert_eq!(live_tasks("test.task"), 2);
+        drop(first);
+        assert!(all_live_tasks().contains(&("test.task", 1)));
+        drop(second);
+        assert_eq!(live_tasks("test.task"), 0);
+        assert!(!all_live_tasks()
+            .iter()
+            .any(|(name, _)| *name == "test.task"));
+    }
+}
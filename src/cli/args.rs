@@ -1,4 +1,6 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueHint};
+
+use super::keygen::KeyAlphabet;
 
 #[derive(Parser, Debug)]
 #[command(name = "tls-tunnel")]
@@ -17,13 +19,18 @@ pub enum Commands {
     /// Run server mode
     Server {
         /// Configuration file path
-        #[arg(short, long, default_value = "server.toml")]
+        #[arg(short, long, default_value = "server.toml", value_hint = ValueHint::FilePath)]
         config: String,
+
+        /// On SIGUSR2, hand listening sockets over to a freshly started process and exit
+        /// after draining sessions (Linux only)
+        #[arg(long)]
+        upgrade_handover: bool,
     },
     /// Run client mode
     Client {
         /// Configuration file path
-        #[arg(short, long, default_value = "client.toml")]
+        #[arg(short, long, default_value = "client.toml", value_hint = ValueHint::FilePath)]
         config: String,
     },
     /// Generate configuration template
@@ -33,27 +40,73 @@ pub enum Commands {
         template_type: String,
 
         /// Output file path (prints to stdout if not specified)
-        #[arg(short, long)]
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
         output: Option<String>,
+
+        /// Transport written to the template (default: tls)
+        #[arg(short, long, value_parser = TRANSPORT_VALUES)]
+        transport: Option<String>,
     },
-    /// Generate self-signed TLS certificate
+    /// Generate, inspect, verify or renew self-signed TLS certificates
+    #[command(args_conflicts_with_subcommands = true)]
     Cert {
+        #[command(subcommand)]
+        action: Option<CertAction>,
+
         /// Certificate output path
-        #[arg(long, default_value = "cert.pem")]
+        #[arg(long, default_value = "cert.pem", value_hint = ValueHint::FilePath)]
         cert_out: String,
 
         /// Private key output path
-        #[arg(long, default_value = "key.pem")]
+        #[arg(long, default_value = "key.pem", value_hint = ValueHint::FilePath)]
         key_out: String,
 
         /// Certificate Common Name
-        #[arg(long, default_value = "localhost")]
+        #[arg(long, default_value = "localhost", value_hint = ValueHint::Hostname)]
         common_name: String,
 
         /// Certificate SubjectAltName (comma-separated)
-        #[arg(long, value_delimiter = ',', value_name = "DNS,...")]
+        #[arg(long, value_delimiter = ',', value_name = "DNS,...", value_hint = ValueHint::Hostname)]
         alt_names: Vec<String>,
     },
+    /// Generate a random auth key, optionally as a TOML snippet or written into a config file
+    Keygen {
+        /// Key length in characters
+        #[arg(
+            short,
+            long,
+            default_value = "32",
+            value_parser = clap::value_parser!(u16).range(16..),
+            value_hint = ValueHint::Other
+        )]
+        length: u16,
+
+        /// Characters the key is drawn from
+        #[arg(short, long, value_enum, default_value = "base58")]
+        alphabet: KeyAlphabet,
+
+        /// Output format (plain key, or a TOML snippet for `auth_key` / `[[server.auth_keys]]`)
+        #[arg(short, long, default_value = "plain", value_parser = ["plain", "toml"])]
+        format: String,
+
+        /// Key name: emit or write a `[[server.auth_keys]]` entry instead of `auth_key`
+        #[arg(short, long, value_hint = ValueHint::Other)]
+        name: Option<String>,
+
+        /// Publish ports the key may use (comma-separated ports or ranges, e.g. 8000-8100,9000)
+        #[arg(
+            long,
+            requires = "name",
+            value_delimiter = ',',
+            value_name = "PORT[-PORT],...",
+            value_hint = ValueHint::Other
+        )]
+        allowed_ports: Vec<String>,
+
+        /// Config file to update in place (comments and formatting are kept)
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
+        write_to: Option<String>,
+    },
     /// Register as systemd service (Linux only)
     Register {
         /// Service type (server, client)
@@ -61,15 +114,15 @@ pub enum Commands {
         service_type: String,
 
         /// Configuration file path
-        #[arg(short, long)]
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
         config: String,
 
         /// Service name (default: tls-tunnel-server or tls-tunnel-client)
-        #[arg(short, long)]
+        #[arg(short, long, value_hint = ValueHint::Other)]
         name: Option<String>,
 
         /// Executable path (default: current executable)
-        #[arg(long)]
+        #[arg(long, value_hint = ValueHint::ExecutablePath)]
         exec: Option<String>,
     },
     /// Unregister systemd service (Linux only)
@@ -79,31 +132,154 @@ pub enum Commands {
         service_type: String,
 
         /// Service name (default: tls-tunnel-server or tls-tunnel-client)
-        #[arg(short, long)]
+        #[arg(short, long, value_hint = ValueHint::Other)]
         name: Option<String>,
     },
     /// Check configuration file validity
     Check {
         /// Configuration file path
-        #[arg(short, long)]
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
         config: String,
 
         /// Output format (text, json)
         #[arg(short, long, default_value = "text", value_parser = ["text", "json"])]
         format: String,
     },
+    /// Ask the server whether it would accept a client configuration (dry-run, registers nothing)
+    CheckRemote {
+        /// Client configuration file path
+        #[arg(short, long, default_value = "client.toml", value_hint = ValueHint::FilePath)]
+        config: String,
+    },
+    /// Show the client identity fingerprint (creates the identity key if missing)
+    Identity {
+        /// Client configuration file path
+        #[arg(short, long, default_value = "client.toml", value_hint = ValueHint::FilePath)]
+        config: String,
+    },
     /// View real-time server statistics (requires stats_port configured)
     Top {
         /// Server configuration file path (reads stats_addr and stats_port from server config)
-        #[arg(short, long, conflicts_with = "url")]
+        #[arg(short, long, conflicts_with = "url", value_hint = ValueHint::FilePath)]
         config: Option<String>,
 
-        /// Server statistics URL (e.g., http://localhost:9090)
-        #[arg(short, long, conflicts_with = "config")]
+        /// Server statistics endpoint (e.g., http://localhost:9090, unix:/path/to/stats.sock
+        /// or pipe:\\.\pipe\name)
+        #[arg(short, long, conflicts_with = "config", value_hint = ValueHint::Url)]
         url: Option<String>,
 
         /// Refresh interval in seconds
-        #[arg(short, long, default_value = "2")]
+        #[arg(short, long, default_value = "2", value_hint = ValueHint::Other)]
         interval: u64,
     },
+    /// Print client statistics as JSON
+    Stats {
+        /// Client configuration file path (uses stats_socket, stats_pipe or stats_port)
+        #[arg(short, long, conflicts_with = "url", value_hint = ValueHint::FilePath)]
+        config: Option<String>,
+
+        /// Statistics endpoint (e.g., http://localhost:9091, unix:/run/tls-tunnel/stats.sock
+        /// or pipe:\\.\pipe\tls-tunnel-client)
+        #[arg(short, long, conflicts_with = "config", value_hint = ValueHint::Url)]
+        url: Option<String>,
+    },
+    /// Inspect protocol trace files written by `protocol_trace_path`
+    Trace {
+        #[command(subcommand)]
+        action: TraceAction,
+    },
+    /// Print a shell completion script (e.g. `tls-tunnel completions bash > /etc/bash_completion.d/tls-tunnel`)
+    Completions {
+        /// Target shell
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Generate roff man pages for tls-tunnel and each subcommand
+    Manpage {
+        /// Directory to write `tls-tunnel.1`, `tls-tunnel-<subcommand>.1`... into
+        /// (prints the top-level page to stdout if not specified)
+        #[arg(short, long, value_hint = ValueHint::DirPath)]
+        out_dir: Option<String>,
+    },
+}
+
+/// Transport values accepted by `--transport` (same names as `transport` in the config files)
+pub const TRANSPORT_VALUES: [&str; 3] = ["tls", "http2", "wss"];
+
+/// Protocol trace actions (`tls-tunnel trace <ACTION>`)
+#[derive(Subcommand, Debug)]
+pub enum TraceAction {
+    /// Reconstruct per-session timelines (auth, config, streams) and flag anomalies
+    Analyze {
+        /// Trace file path (rotated files `<file>.1`, `<file>.2`... are analyzed separately)
+        #[arg(value_hint = ValueHint::FilePath)]
+        file: String,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Certificate maintenance actions (`tls-tunnel cert <ACTION>`)
+///
+/// Exit codes: 0 success, 1 error, 2 key mismatch, 3 hostname not covered,
+/// 4 certificate expired or not yet valid
+#[derive(Subcommand, Debug)]
+pub enum CertAction {
+    /// Print subject, SANs, validity period and fingerprint of a certificate
+    Inspect {
+        /// Certificate path (PEM)
+        #[arg(long, default_value = "cert.pem", value_hint = ValueHint::FilePath)]
+        cert: String,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check that the key matches the certificate and the certificate covers a hostname
+    Verify {
+        /// Certificate path (PEM)
+        #[arg(long, default_value = "cert.pem", value_hint = ValueHint::FilePath)]
+        cert: String,
+
+        /// Private key path (PEM)
+        #[arg(long, default_value = "key.pem", value_hint = ValueHint::FilePath)]
+        key: String,
+
+        /// Hostname or IP address the certificate must cover
+        #[arg(long, value_hint = ValueHint::Hostname)]
+        hostname: Option<String>,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Regenerate a self-signed certificate keeping its subject and SANs
+    Renew {
+        /// Certificate path (PEM), the old file is kept as <cert>.bak
+        #[arg(long, default_value = "cert.pem", value_hint = ValueHint::FilePath)]
+        cert: String,
+
+        /// Private key path (PEM)
+        #[arg(long, default_value = "key.pem", value_hint = ValueHint::FilePath)]
+        key: String,
+
+        /// Validity period of the renewed certificate in days
+        #[arg(
+            long,
+            default_value = "365",
+            value_parser = clap::value_parser!(u32).range(1..),
+            value_hint = ValueHint::Other
+        )]
+        days: u32,
+
+        /// Generate a new private key instead of reusing the existing one
+        #[arg(long)]
+        new_key: bool,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
 }
@@ -1,8 +1,14 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use rcgen::{CertificateParams, DistinguishedName, DnType, KeyPair, PublicKeyData, SerialNumber};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::info;
+use x509_parser::extensions::GeneralName;
 
+use super::args::CertAction;
 use crate::{config, tls};
 
 /// Generate self-signed TLS certificate
@@ -65,3 +71,566 @@ pub fn ensure_server_certs(config: &config::ServerConfig) -> Result<(PathBuf, Pa
         ),
     }
 }
+
+/// Certificate check failures, each mapped to a dedicated process exit code
+#[derive(Debug, thiserror::Error)]
+pub enum CertCheckError {
+    #[error("private key does not match certificate")]
+    KeyMismatch,
+    #[error("certificate does not cover hostname '{0}'")]
+    HostnameNotCovered(String),
+    #[error("certificate is not valid at present (valid from {not_before} to {not_after})")]
+    NotValidNow {
+        not_before: String,
+        not_after: String,
+    },
+}
+
+impl CertCheckError {
+    /// Process exit code for this failure
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CertCheckError::KeyMismatch => 2,
+            CertCheckError::HostnameNotCovered(_) => 3,
+            CertCheckError::NotValidNow { .. } => 4,
+        }
+    }
+}
+
+/// Map a command error to a process exit code (1 unless it is a certificate check failure)
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    err.downcast_ref::<CertCheckError>()
+        .map_or(1, CertCheckError::exit_code)
+}
+
+/// Summary of a certificate as printed by `cert inspect`
+#[derive(Debug, Clone, Serialize)]
+pub struct CertInfo {
+    pub subject: String,
+    pub issuer: String,
+    pub common_name: Option<String>,
+    pub dns_names: Vec<String>,
+    pub ip_addresses: Vec<IpAddr>,
+    pub serial: String,
+    pub not_before: String,
+    pub not_after: String,
+    pub not_before_unix: i64,
+    pub not_after_unix: i64,
+    pub expires_in_days: i64,
+    pub self_signed: bool,
+    pub fingerprint_sha256: String,
+    #[serde(skip)]
+    public_key: Vec<u8>,
+}
+
+impl CertInfo {
+    /// Whether the certificate is valid at the given unix timestamp
+    pub fn is_valid_at(&self, now: i64) -> bool {
+        self.not_before_unix <= now && now <= self.not_after_unix
+    }
+
+    /// Whether the SANs cover a hostname or IP address (wildcards match a single label)
+    ///
+    /// The Common Name is ignored, as rustls only checks SANs
+    pub fn covers(&self, hostname: &str) -> bool {
+        let host = hostname
+            .strip_prefix('[')
+            .and_then(|h| h.strip_suffix(']'))
+            .unwrap_or(hostname);
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return self.ip_addresses.contains(&ip);
+        }
+
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.dns_names.iter().any(|name| {
+            let name = name.trim_end_matches('.').to_ascii_lowercase();
+            match name.strip_prefix("*.") {
+                Some(suffix) => host
+                    .split_once('.')
+                    .is_some_and(|(label, rest)| !label.is_empty() && rest == suffix),
+                None => name == host,
+            }
+        })
+    }
+
+    fn validity_error(&self) -> CertCheckError {
+        CertCheckError::NotValidNow {
+            not_before: self.not_before.clone(),
+            not_after: self.not_after.clone(),
+        }
+    }
+}
+
+/// Result of `cert verify`
+#[derive(Debug, Serialize)]
+pub struct VerifyReport {
+    pub key_matches: bool,
+    pub hostname: Option<String>,
+    pub hostname_covered: Option<bool>,
+    pub valid_now: bool,
+    pub expires_in_days: i64,
+}
+
+impl VerifyReport {
+    /// First failed check, in exit code order
+    fn failure(&self, info: &CertInfo) -> Option<CertCheckError> {
+        if !self.key_matches {
+            Some(CertCheckError::KeyMismatch)
+        } else if self.hostname_covered == Some(false) {
+            Some(CertCheckError::HostnameNotCovered(
+                self.hostname.clone().unwrap_or_default(),
+            ))
+        } else if !self.valid_now {
+            Some(info.validity_error())
+        } else {
+            None
+        }
+    }
+}
+
+/// Result of `cert renew`
+#[derive(Debug, Serialize)]
+pub struct RenewReport {
+    pub cert: String,
+    pub key: String,
+    pub backups: Vec<String>,
+    pub new_key: bool,
+    pub not_before: String,
+    pub not_after: String,
+    /// No certificate hot-reload yet: running servers must be restarted
+    pub restart_required: bool,
+}
+
+/// Execute a `tls-tunnel cert <ACTION>` subcommand
+pub fn execute_cert_action(action: &CertAction) -> Result<()> {
+    match action {
+        CertAction::Inspect { cert, json } => {
+            let info = inspect_certificate(Path::new(cert))?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&info)?);
+            } else {
+                print_cert_info(&info);
+            }
+        }
+        CertAction::Verify {
+            cert,
+            key,
+            hostname,
+            json,
+        } => {
+            let info = inspect_certificate(Path::new(cert))?;
+            let report = verify_certificate(&info, Path::new(key), hostname.as_deref())?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print_verify_report(&report);
+            }
+            if let Some(err) = report.failure(&info) {
+                return Err(err.into());
+            }
+        }
+        CertAction::Renew {
+            cert,
+            key,
+            days,
+            new_key,
+            json,
+        } => {
+            let report = renew_certificate(Path::new(cert), Path::new(key), *days, *new_key)?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                println!("Renewed certificate: {}", report.cert);
+                if report.new_key {
+                    println!("Generated new private key: {}", report.key);
+                }
+                println!("Valid from {} to {}", report.not_before, report.not_after);
+                for backup in &report.backups {
+                    println!("Backup: {}", backup);
+                }
+                println!("Restart running tls-tunnel servers to load the renewed certificate");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse the first certificate of a PEM file
+pub fn inspect_certificate(path: &Path) -> Result<CertInfo> {
+    let der = read_first_cert(path)?;
+    let (_, cert) = x509_parser::parse_x509_certificate(&der)
+        .map_err(|e| anyhow::anyhow!("Failed to parse certificate {:?}: {}", path, e))?;
+
+    let mut dns_names = Vec::new();
+    let mut ip_addresses = Vec::new();
+    if let Some(san) = cert
+        .subject_alternative_name()
+        .map_err(|e| anyhow::anyhow!("Invalid SubjectAltName in {:?}: {}", path, e))?
+    {
+        for name in &san.value.general_names {
+            match name {
+                GeneralName::DNSName(dns) => dns_names.push(dns.to_string()),
+                GeneralName::IPAddress(bytes) => {
+                    if let Some(ip) = ip_from_bytes(bytes) {
+                        ip_addresses.push(ip);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let common_name = cert
+        .subject()
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+        .map(str::to_string);
+    let validity = cert.validity();
+    let not_after_unix = validity.not_after.timestamp();
+    let now = unix_now();
+
+    Ok(CertInfo {
+        subject: cert.subject().to_string(),
+        issuer: cert.issuer().to_string(),
+        common_name,
+        dns_names,
+        ip_addresses,
+        serial: cert.raw_serial_as_string(),
+        not_before: validity.not_before.to_string(),
+        not_after: validity.not_after.to_string(),
+        not_before_unix: validity.not_before.timestamp(),
+        not_after_unix,
+        expires_in_days: (not_after_unix - now).div_euclid(86400),
+        self_signed: cert.subject().as_raw() == cert.issuer().as_raw(),
+        fingerprint_sha256: hex_colon(ring::digest::digest(&ring::digest::SHA256, &der).as_ref()),
+        public_key: cert.public_key().subject_public_key.data.to_vec(),
+    })
+}
+
+/// Check key, hostname and validity period of a certificate
+pub fn verify_certificate(
+    info: &CertInfo,
+    key_path: &Path,
+    hostname: Option<&str>,
+) -> Result<VerifyReport> {
+    let key = load_key_pair(key_path)?;
+
+    Ok(VerifyReport {
+        key_matches: key.der_bytes() == info.public_key.as_slice(),
+        hostname: hostname.map(str::to_string),
+        hostname_covered: hostname.map(|h| info.covers(h)),
+        valid_now: info.is_valid_at(unix_now()),
+        expires_in_days: info.expires_in_days,
+    })
+}
+
+/// Regenerate a self-signed certificate for `days` days, keeping its subject CN and SANs
+///
+/// Existing files are kept as `<file>.bak`; new files are written atomically
+pub fn renew_certificate(
+    cert_path: &Path,
+    key_path: &Path,
+    days: u32,
+    new_key: bool,
+) -> Result<RenewReport> {
+    let info = inspect_certificate(cert_path)?;
+
+    let key = if new_key {
+        KeyPair::generate().context("Failed to generate private key")?
+    } else {
+        let key = load_key_pair(key_path)?;
+        if key.der_bytes() != info.public_key.as_slice() {
+            return Err(CertCheckError::KeyMismatch.into());
+        }
+        key
+    };
+
+    let mut sans = info.dns_names.clone();
+    sans.extend(info.ip_addresses.iter().map(IpAddr::to_string));
+    if sans.is_empty() {
+        match &info.common_name {
+            Some(cn) => sans.push(cn.clone()),
+            None => anyhow::bail!(
+                "Certificate {:?} has neither SANs nor a Common Name",
+                cert_path
+            ),
+        }
+    }
+
+    let mut params = CertificateParams::new(sans).context("Invalid SubjectAltName")?;
+    params.distinguished_name = DistinguishedName::new();
+    if let Some(cn) = &info.common_name {
+        params
+            .distinguished_name
+            .push(DnType::CommonName, cn.as_str());
+    }
+    let now = time::OffsetDateTime::now_utc();
+    params.not_before = now;
+    params.not_after = now + time::Duration::days(i64::from(days));
+    params.serial_number = Some(random_serial()?);
+
+    let cert = params
+        .self_signed(&key)
+        .context("Failed to generate self-signed certificate")?;
+
+    let mut backups = Vec::new();
+    backups.extend(write_with_backup(cert_path, &cert.pem())?);
+    if new_key {
+        backups.extend(write_with_backup(key_path, &key.serialize_pem())?);
+    }
+
+    let renewed = inspect_certificate(cert_path)?;
+    Ok(RenewReport {
+        cert: cert_path.display().to_string(),
+        key: key_path.display().to_string(),
+        backups,
+        new_key,
+        not_before: renewed.not_before,
+        not_after: renewed.not_after,
+        restart_required: true,
+    })
+}
+
+fn print_cert_info(info: &CertInfo) {
+    println!("Subject:       {}", info.subject);
+    println!("Issuer:        {}", info.issuer);
+    if !info.dns_names.is_empty() {
+        println!("DNS names:     {}", info.dns_names.join(", "));
+    }
+    if !info.ip_addresses.is_empty() {
+        let ips: Vec<String> = info.ip_addresses.iter().map(IpAddr::to_string).collect();
+        println!("IP addresses:  {}", ips.join(", "));
+    }
+    println!("Serial:        {}", info.serial);
+    println!("Not before:    {}", info.not_before);
+    println!(
+        "Not after:     {} ({} days left)",
+        info.not_after, info.expires_in_days
+    );
+    println!("Self-signed:   {}", info.self_signed);
+    println!("SHA-256:       {}", info.fingerprint_sha256);
+}
+
+fn print_verify_report(report: &VerifyReport) {
+    let status = |ok: bool| if ok { "OK" } else { "FAILED" };
+    println!("Key matches certificate: {}", status(report.key_matches));
+    if let (Some(hostname), Some(covered)) = (&report.hostname, report.hostname_covered) {
+        println!("Covers {}: {}", hostname, status(covered));
+    }
+    println!(
+        "Valid now: {} ({} days left)",
+        status(report.valid_now),
+        report.expires_in_days
+    );
+}
+
+fn read_first_cert(path: &Path) -> Result<Vec<u8>> {
+    let data =
+        std::fs::read(path).with_context(|| format!("Failed to read certificate {:?}", path))?;
+    let cert = rustls_pemfile::certs(&mut data.as_slice())
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("No certificate found in {:?}", path))?
+        .with_context(|| format!("Failed to parse certificate {:?}", path))?;
+    Ok(cert.to_vec())
+}
+
+fn load_key_pair(path: &Path) -> Result<KeyPair> {
+    let pem = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read private key {:?}", path))?;
+    KeyPair::from_pem(&pem).with_context(|| format!("Unsupported private key {:?}", path))
+}
+
+fn ip_from_bytes(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => Some(IpAddr::from(<[u8; 4]>::try_from(bytes).ok()?)),
+        16 => Some(IpAddr::from(<[u8; 16]>::try_from(bytes).ok()?)),
+        _ => None,
+    }
+}
+
+fn hex_colon(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn random_serial() -> Result<SerialNumber> {
+    let mut bytes = [0u8; 16];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow::anyhow!("Failed to generate certificate serial number"))?;
+    // 保持序列号为正数
+    bytes[0] &= 0x7f;
+    Ok(SerialNumber::from_slice(&bytes))
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// Write `content` atomically, keeping the previous file as `<path>.bak`
+fn write_with_backup(path: &Path, content: &str) -> Result<Option<String>> {
+    let tmp = sibling_path(path, "tmp");
+    std::fs::write(&tmp, content).with_context(|| format!("Failed to write {:?}", tmp))?;
+
+    let backup = if path.exists() {
+        let backup = sibling_path(path, "bak");
+        std::fs::copy(path, &backup)
+            .with_context(|| format!("Failed to back up {:?} to {:?}", path, backup))?;
+        // 保留原文件权限（私钥通常为 0600）
+        std::fs::set_permissions(&tmp, std::fs::metadata(path)?.permissions())?;
+        Some(backup.display().to_string())
+    } else {
+        None
+    };
+
+    std::fs::rename(&tmp, path)
+        .with_context(|| format!("Failed to replace {:?} with {:?}", path, tmp))?;
+    Ok(backup)
+}
+
+fn sibling_path(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestCert {
+        dir: PathBuf,
+        cert: PathBuf,
+        key: PathBuf,
+    }
+
+    impl TestCert {
+        fn generate(name: &str, sans: &[&str]) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "tls-tunnel-cert-test-{}-{}",
+                name,
+                uuid::Uuid::new_v4()
+            ));
+            std::fs::create_dir_all(&dir).unwrap();
+            let cert = dir.join("cert.pem");
+            let key = dir.join("key.pem");
+            let sans: Vec<String> = sans.iter().map(|s| s.to_string()).collect();
+            tls::generate_self_signed_cert("localhost", &sans, &cert, &key).unwrap();
+            Self { dir, cert, key }
+        }
+    }
+
+    impl Drop for TestCert {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn test_inspect_certificate() {
+        let test = TestCert::generate("inspect", &["localhost", "*.example.com", "10.0.0.1"]);
+        let info = inspect_certificate(&test.cert).unwrap();
+
+        assert_eq!(info.dns_names, vec!["localhost", "*.example.com"]);
+        assert_eq!(
+            info.ip_addresses,
+            vec!["10.0.0.1".parse::<IpAddr>().unwrap()]
+        );
+        assert!(info.self_signed);
+        assert!(info.is_valid_at(unix_now()));
+        assert_eq!(info.fingerprint_sha256.split(':').count(), 32);
+
+        assert!(info.covers("localhost"));
+        assert!(info.covers("api.example.com"));
+        assert!(info.covers("API.Example.com."));
+        assert!(!info.covers("example.com"));
+        assert!(!info.covers("a.b.example.com"));
+        assert!(info.covers("10.0.0.1"));
+        assert!(!info.covers("10.0.0.2"));
+    }
+
+    #[test]
+    fn test_verify_certificate() {
+        let test = TestCert::generate("verify", &["localhost"]);
+        let other = TestCert::generate("verify-other", &["localhost"]);
+        let info = inspect_certificate(&test.cert).unwrap();
+
+        let report = verify_certificate(&info, &test.key, Some("localhost")).unwrap();
+        assert!(report.key_matches);
+        assert_eq!(report.hostname_covered, Some(true));
+        assert!(report.failure(&info).is_none());
+
+        let report = verify_certificate(&info, &test.key, Some("example.com")).unwrap();
+        let err: anyhow::Error = report.failure(&info).unwrap().into();
+        assert_eq!(exit_code(&err), 3);
+
+        let report = verify_certificate(&info, &other.key, None).unwrap();
+        assert!(!report.key_matches);
+        let err: anyhow::Error = report.failure(&info).unwrap().into();
+        assert_eq!(exit_code(&err), 2);
+
+        assert_eq!(exit_code(&anyhow::anyhow!("other")), 1);
+    }
+
+    #[test]
+    fn test_renew_certificate() {
+        let test = TestCert::generate("renew", &["localhost", "127.0.0.1"]);
+        let old = inspect_certificate(&test.cert).unwrap();
+        let old_key = std::fs::read_to_string(&test.key).unwrap();
+
+        let report = renew_certificate(&test.cert, &test.key, 30, false).unwrap();
+        assert!(report.restart_required);
+        assert_eq!(
+            report.backups,
+            vec![sibling_path(&test.cert, "bak").display().to_string()]
+        );
+
+        let renewed = inspect_certificate(&test.cert).unwrap();
+        assert_eq!(renewed.dns_names, old.dns_names);
+        assert_eq!(renewed.ip_addresses, old.ip_addresses);
+        assert_eq!(renewed.common_name, old.common_name);
+        assert_ne!(renewed.serial, old.serial);
+        assert!((29..=30).contains(&renewed.expires_in_days));
+        assert_eq!(std::fs::read_to_string(&test.key).unwrap(), old_key);
+        assert!(verify_certificate(&renewed, &test.key, Some("127.0.0.1"))
+            .unwrap()
+            .failure(&renewed)
+            .is_none());
+
+        // 旧证书保留为备份
+        let backup = inspect_certificate(&sibling_path(&test.cert, "bak")).unwrap();
+        assert_eq!(backup.fingerprint_sha256, old.fingerprint_sha256);
+
+        // 生成新私钥
+        let report = renew_certificate(&test.cert, &test.key, 365, true).unwrap();
+        assert!(report.new_key);
+        assert_ne!(std::fs::read_to_string(&test.key).unwrap(), old_key);
+        let renewed = inspect_certificate(&test.cert).unwrap();
+        assert!(
+            verify_certificate(&renewed, &test.key, None)
+                .unwrap()
+                .key_matches
+        );
+        assert!(!sibling_path(&test.cert, "tmp").exists());
+    }
+
+    #[test]
+    fn test_renew_rejects_mismatched_key() {
+        let test = TestCert::generate("renew-mismatch", &["localhost"]);
+        let other = TestCert::generate("renew-mismatch-other", &["localhost"]);
+        let before = std::fs::read(&test.cert).unwrap();
+
+        let err = renew_certificate(&test.cert, &other.key, 365, false).unwrap_err();
+        assert_eq!(exit_code(&err), 2);
+        assert_eq!(std::fs::read(&test.cert).unwrap(), before);
+    }
+}
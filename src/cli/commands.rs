@@ -2,11 +2,12 @@ use anyhow::Result;
 use tokio_rustls::{TlsAcceptor, TlsConnector};
 use tracing::info;
 
-use crate::{client, config::AppConfig, server, tls, top, transport};
+use crate::stats_http::StatsEndpoint;
+use crate::{client, config::AppConfig, identity, server, tls, top, transport};
 
 use super::cert;
 use super::config::{check_config, check_config_file_permissions, expand_path};
-use super::{service, template};
+use super::{completions, keygen, service, template, trace};
 
 /// Execute CLI commands
 pub async fn execute_command(cli: &super::Cli) -> Result<()> {
@@ -20,10 +21,22 @@ pub async fn execute_command(cli: &super::Cli) -> Result<()> {
         Commands::Template {
             template_type,
             output,
+            transport,
         } => {
-            template::generate_config_template(template_type, output.as_deref())?;
+            template::generate_config_template(
+                template_type,
+                output.as_deref(),
+                transport.as_deref(),
+            )?;
         }
         Commands::Cert {
+            action: Some(action),
+            ..
+        } => {
+            cert::execute_cert_action(action)?;
+        }
+        Commands::Cert {
+            action: None,
             cert_out,
             key_out,
             common_name,
@@ -31,6 +44,23 @@ pub async fn execute_command(cli: &super::Cli) -> Result<()> {
         } => {
             cert::generate_certificate(cert_out, key_out, common_name, alt_names)?;
         }
+        Commands::Keygen {
+            length,
+            alphabet,
+            format,
+            name,
+            allowed_ports,
+            write_to,
+        } => {
+            keygen::run_keygen(
+                *length as usize,
+                *alphabet,
+                format,
+                name.as_deref(),
+                allowed_ports,
+                write_to.as_deref(),
+            )?;
+        }
         Commands::Register {
             service_type,
             config,
@@ -48,12 +78,21 @@ pub async fn execute_command(cli: &super::Cli) -> Result<()> {
         Commands::Unregister { service_type, name } => {
             service::unregister_systemd_service(service_type, name.as_deref())?;
         }
-        Commands::Server { config } => {
-            run_server(config).await?;
+        Commands::Server {
+            config,
+            upgrade_handover,
+        } => {
+            run_server(config, *upgrade_handover).await?;
         }
         Commands::Client { config } => {
             run_client(config).await?;
         }
+        Commands::CheckRemote { config } => {
+            run_check_remote(config).await?;
+        }
+        Commands::Identity { config } => {
+            run_identity(config)?;
+        }
         Commands::Top {
             config,
             url,
@@ -61,13 +100,35 @@ pub async fn execute_command(cli: &super::Cli) -> Result<()> {
         } => {
             run_top(config.as_deref(), url.as_deref(), *interval).await?;
         }
+        Commands::Stats { config, url } => {
+            run_stats(config.as_deref(), url.as_deref()).await?;
+        }
+        Commands::Trace { action } => {
+            trace::execute_trace_action(action)?;
+        }
+        Commands::Completions { shell } => {
+            completions::print_completions(*shell, &mut std::io::stdout())?;
+        }
+        Commands::Manpage { out_dir } => match out_dir {
+            Some(dir) => {
+                let dir = expand_path(dir)?;
+                let pages = completions::write_manpages(std::path::Path::new(&dir))?;
+                println!("Generated {} man pages in {}", pages, dir);
+            }
+            None => completions::print_manpage(&mut std::io::stdout())?,
+        },
     }
 
     Ok(())
 }
 
 /// Run TLS tunnel server
-async fn run_server(config: &str) -> Result<()> {
+async fn run_server(config: &str, upgrade_handover: bool) -> Result<()> {
+    #[cfg(not(target_os = "linux"))]
+    if upgrade_handover {
+        anyhow::bail!("--upgrade-handover is only supported on Linux");
+    }
+
     let config_path = expand_path(config)?;
 
     // 检查配置文件权限
@@ -86,11 +147,21 @@ async fn run_server(config: &str) -> Result<()> {
         None
     };
 
-    let tls_config = tls::load_server_config_with_alpn(&cert_path, &key_path, alpn_protocols)?;
+    let tls_config = tls::load_server_config_with_client_auth(
+        &cert_path,
+        &key_path,
+        alpn_protocols,
+        server_config.client_ca_path.as_deref(),
+        server_config.require_client_cert,
+    )?;
     let acceptor = TlsAcceptor::from(tls_config);
 
     // Run server
-    server::run_server(server_config, acceptor).await?;
+    #[cfg(target_os = "linux")]
+    if upgrade_handover {
+        return server::run_server_with_handover(server_config, config_path.into(), acceptor).await;
+    }
+    server::run_server_with_source(server_config, config_path.into(), acceptor).await?;
 
     Ok(())
 }
@@ -103,29 +174,105 @@ async fn run_client(config: &str) -> Result<()> {
     check_config_file_permissions(&config_path)?;
 
     info!("Loading client configuration from: {}", config_path);
+    // 配置文件无效且开启了 fallback_to_last_good 时使用最近一次被服务器接受的配置
+    let loaded = client::load_with_fallback(&config_path)?;
+    let connector = client_tls_connector(&loaded.config.client)?;
+
+    // Run client
+    match loaded.fallback {
+        Some(_) => {
+            client::run_client_from_last_good(loaded.config, config_path.into(), connector).await?
+        }
+        None => {
+            client::run_client_with_source(loaded.config, config_path.into(), connector).await?
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the client identity fingerprint, e.g. for the server's `known_clients`
+fn run_identity(config: &str) -> Result<()> {
+    let config_path = expand_path(config)?;
     let client_config = AppConfig::load_client_config(&config_path)?;
+    let identity_path = client_config
+        .client
+        .identity_path
+        .unwrap_or_else(|| identity::default_identity_path(config_path.as_ref()));
 
-    // Load TLS configuration
-    // Set ALPN protocols based on transport type
-    let alpn_protocols = if client_config.client.transport == transport::TransportType::Http2 {
-        Some(vec![b"h2".to_vec()])
-    } else {
-        None
-    };
+    let identity = identity::ClientIdentity::load_or_create(&identity_path)?;
+    println!("Identity file: {}", identity_path.display());
+    println!("Fingerprint:   {}", identity.fingerprint());
+    println!("Short:         {}", identity.short_fingerprint());
+    Ok(())
+}
 
-    let tls_config = tls::load_client_config_with_alpn(
-        client_config.client.ca_cert_path.as_deref(),
-        client_config.client.skip_verify,
-        alpn_protocols,
-    )?;
-    let connector = TlsConnector::from(tls_config);
+/// Ask the server to validate the client configuration without registering anything
+async fn run_check_remote(config: &str) -> Result<()> {
+    let config_path = expand_path(config)?;
 
-    // Run client
-    client::run_client(client_config, connector).await?;
+    info!("Loading client configuration from: {}", config_path);
+    let mut client_config = AppConfig::load_client_config(&config_path)?;
+    let connector = client_tls_connector(&client_config.client)?;
+    // Authenticate with the same identity the client uses when it runs
+    client_config
+        .client
+        .identity_path
+        .get_or_insert_with(|| identity::default_identity_path(config_path.as_ref()));
+
+    let server = format!(
+        "{}:{}",
+        client_config.client.server_addr, client_config.client.server_port
+    );
+    let report = client::check_remote(client_config, connector).await?;
+
+    println!("Dry-run validation by {} (nothing was registered)", server);
+    println!();
+    let name_width = report
+        .items
+        .iter()
+        .map(|item| item.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("NAME".len());
+    println!(
+        "{:<8} {:<name_width$} {:>5}  {:<8} REASON",
+        "TYPE", "NAME", "PORT", "RESULT"
+    );
+    for item in &report.items {
+        println!(
+            "{:<8} {:<name_width$} {:>5}  {:<8} {}",
+            item.kind,
+            item.name,
+            item.publish_port,
+            if item.is_accepted() {
+                "accepted"
+            } else {
+                "rejected"
+            },
+            item.rejection.as_deref().unwrap_or("")
+        );
+    }
+
+    let rejected = report.items.iter().filter(|i| !i.is_accepted()).count();
+    println!();
+    if rejected > 0 {
+        anyhow::bail!(
+            "{} of {} item(s) would be rejected",
+            rejected,
+            report.items.len()
+        );
+    }
+    println!("All {} item(s) would be accepted", report.items.len());
 
     Ok(())
 }
 
+/// 按客户端配置创建 TLS 连接器
+fn client_tls_connector(config: &crate::config::ClientConfig) -> Result<TlsConnector> {
+    Ok(TlsConnector::from(tls::load_client_config_from(config)?))
+}
+
 /// Run statistics dashboard
 async fn run_top(config: Option<&str>, url: Option<&str>, interval: u64) -> Result<()> {
     let stats_url = if let Some(config_path) = config {
@@ -145,10 +292,10 @@ async fn run_top(config: Option<&str>, url: Option<&str>, interval: u64) -> Resu
             .cloned()
             .unwrap_or_else(|| server_config.bind_addr.clone());
 
-        format!("http://{}:{}", stats_addr, stats_port)
+        StatsEndpoint::Http(format!("http://{}:{}", stats_addr, stats_port))
     } else if let Some(url) = url {
-        // 直接使用提供的 URL
-        url.to_string()
+        // 直接使用提供的地址（HTTP URL、unix: 或 pipe:）
+        StatsEndpoint::parse(url)?
     } else {
         anyhow::bail!("Either --config or --url must be provided");
     };
@@ -159,3 +306,54 @@ async fn run_top(config: Option<&str>, url: Option<&str>, interval: u64) -> Resu
 
     Ok(())
 }
+
+/// Print client statistics
+async fn run_stats(config: Option<&str>, url: Option<&str>) -> Result<()> {
+    let endpoint = if let Some(config_path) = config {
+        let config_path = expand_path(config_path)?;
+        info!("Loading client configuration from: {}", config_path);
+        let client_config = AppConfig::load_client_config(&config_path)?;
+        client_stats_endpoint(&client_config.client)?
+    } else if let Some(url) = url {
+        StatsEndpoint::parse(url)?
+    } else {
+        anyhow::bail!("Either --config or --url must be provided");
+    };
+
+    info!("Fetching statistics from: {}", endpoint);
+    let body = endpoint.get("/stats").await?;
+    println!("{}", body.trim_end());
+
+    Ok(())
+}
+
+/// 客户端统计服务器地址：优先使用本地 IPC（stats_socket / stats_pipe），其次是统计端口
+fn client_stats_endpoint(config: &crate::config::ClientConfig) -> Result<StatsEndpoint> {
+    if cfg!(unix) {
+        if let Some(ref path) = config.stats_socket {
+            return Ok(StatsEndpoint::Unix(path.clone()));
+        }
+    }
+    if cfg!(windows) {
+        if let Some(ref name) = config.stats_pipe {
+            return Ok(StatsEndpoint::Pipe(name.clone()));
+        }
+    }
+
+    let stats_port = config.stats_port.ok_or_else(|| {
+        anyhow::anyhow!(
+            "None of stats_socket, stats_pipe or stats_port is configured in the client configuration file"
+        )
+    })?;
+    // 监听所有地址时从本机连接
+    let stats_addr = match config.stats_addr.as_deref().map(str::trim) {
+        None | Some("") | Some("0.0.0.0") => "127.0.0.1".to_string(),
+        Some("::") => "[::1]".to_string(),
+        Some(addr) if addr.contains(':') && !addr.starts_with('[') => format!("[{}]", addr),
+        Some(addr) => addr.to_string(),
+    };
+    Ok(StatsEndpoint::Http(format!(
+        "http://{}:{}",
+        stats_addr, stats_port
+    )))
+}
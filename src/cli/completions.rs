@@ -0,0 +1,174 @@
+use anyhow::{Context, Result};
+use clap::CommandFactory;
+use clap_complete::Shell;
+use std::io::Write;
+use std::path::Path;
+
+use super::Cli;
+
+/// Write the completion script for `shell`
+pub fn print_completions(shell: Shell, out: &mut dyn Write) -> Result<()> {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, out);
+    Ok(())
+}
+
+/// Write the top-level man page
+pub fn print_manpage(out: &mut dyn Write) -> Result<()> {
+    clap_mangen::Man::new(Cli::command())
+        .render(out)
+        .context("Failed to render man page")
+}
+
+/// Write `tls-tunnel.1` and one page per subcommand (`tls-tunnel-cert-inspect.1`...) into `dir`
+///
+/// Returns the number of pages written
+pub fn write_manpages(dir: &Path) -> Result<usize> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let mut command = Cli::command();
+    command.build();
+    write_manpage_tree(&command, dir)
+}
+
+fn write_manpage_tree(command: &clap::Command, dir: &Path) -> Result<usize> {
+    let path = dir.join(format!(
+        "{}.1",
+        command.get_display_name().unwrap_or(command.get_name())
+    ));
+    let mut page = Vec::new();
+    clap_mangen::Man::new(command.clone())
+        .render(&mut page)
+        .context("Failed to render man page")?;
+    std::fs::write(&path, page).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    let mut pages = 1;
+    for subcommand in command.get_subcommands() {
+        if subcommand.is_hide_set() || subcommand.get_name() == "help" {
+            continue;
+        }
+        pages += write_manpage_tree(subcommand, dir)?;
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ValueHint;
+
+    fn completions(shell: Shell) -> String {
+        let mut out = Vec::new();
+        print_completions(shell, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// Every argument that takes a value must tell the shell what to complete: either a fixed
+    /// set of values or an explicit hint (`ValueHint::Other` for free text)
+    #[test]
+    fn test_value_args_are_annotated() {
+        fn check(command: &clap::Command, path: &str, missing: &mut Vec<String>) {
+            for arg in command.get_arguments() {
+                let takes_value = arg.get_num_args().is_some_and(|n| n.takes_values());
+                if !takes_value || arg.get_id() == "help" || arg.get_id() == "version" {
+                    continue;
+                }
+                if arg.get_possible_values().is_empty()
+                    && arg.get_value_hint() == ValueHint::Unknown
+                {
+                    missing.push(format!("{} {}", path, arg.get_id()));
+                }
+            }
+            for subcommand in command.get_subcommands() {
+                check(
+                    subcommand,
+                    &format!("{} {}", path, subcommand.get_name()),
+                    missing,
+                );
+            }
+        }
+
+        let mut command = Cli::command();
+        command.build();
+        let mut missing = Vec::new();
+        check(&command, "tls-tunnel", &mut missing);
+        assert!(
+            missing.is_empty(),
+            "Arguments without value_hint or possible values: {:?}",
+            missing
+        );
+    }
+
+    #[test]
+    fn test_completion_scripts() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+            let script = completions(shell);
+            for word in [
+                "server",
+                "client",
+                "template",
+                "cert",
+                "inspect",
+                "renew",
+                "register",
+                "check-remote",
+                "identity",
+                "keygen",
+                "completions",
+                "manpage",
+                // fish 的长选项写作 `-l name`，只检查选项名
+                "config",
+                "transport",
+                "cert-out",
+                "upgrade-handover",
+                "out-dir",
+            ] {
+                assert!(script.contains(word), "{:?} script lacks {}", shell, word);
+            }
+        }
+
+        // 枚举值（PowerShell 脚本不补全参数值）
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let script = completions(shell);
+            for value in crate::cli::args::TRANSPORT_VALUES
+                .iter()
+                .chain(&["server", "client", "json"])
+            {
+                assert!(script.contains(value), "{:?} script lacks {}", shell, value);
+            }
+        }
+
+        // 文件路径参数交给 shell 的文件补全
+        assert!(
+            completions(Shell::Zsh).contains("--config=[Configuration file path]:CONFIG:_files")
+        );
+        assert!(completions(Shell::Fish).contains("-l config -d 'Configuration file path' -r -F"));
+    }
+
+    #[test]
+    fn test_transport_values_match_transport_type() {
+        use crate::transport::TransportType;
+
+        for value in crate::cli::args::TRANSPORT_VALUES {
+            let transport: TransportType = value.parse().unwrap();
+            assert_ne!(transport, TransportType::Unknown);
+            assert_eq!(transport.to_string(), value);
+        }
+    }
+
+    #[test]
+    fn test_write_manpages() {
+        let dir = std::env::temp_dir().join(format!("tls-tunnel-man-{}", uuid::Uuid::new_v4()));
+        let pages = write_manpages(&dir).unwrap();
+
+        let main = std::fs::read_to_string(dir.join("tls-tunnel.1")).unwrap();
+        assert!(main.contains(".TH tls-tunnel"));
+        assert!(main.contains("check\\-remote"));
+        let inspect = std::fs::read_to_string(dir.join("tls-tunnel-cert-inspect.1")).unwrap();
+        assert!(inspect.contains("\\-\\-cert"));
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), pages);
+        assert!(dir.join("tls-tunnel-trace-analyze.1").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,7 +2,17 @@ use anyhow::{Context, Result};
 use serde::Serialize;
 use std::path::{Path, PathBuf};
 
-use crate::config::AppConfig;
+use crate::config::{AppConfig, ConfigValidator};
+
+/// 低熵 auth_key 的提示（指向 `tls-tunnel keygen`）
+fn weak_key_warning(auth_key: &str) -> Option<String> {
+    ConfigValidator::auth_key_weakness(auth_key).map(|reason| {
+        format!(
+            "auth_key looks weak ({}); generate a strong one with `tls-tunnel keygen`",
+            reason
+        )
+    })
+}
 
 /// 检查配置文件权限（仅Unix系统）
 #[cfg(unix)]
@@ -111,6 +121,8 @@ pub fn check_config(config_path: &str, format: &str) -> Result<()> {
             "bind_port": server_config.bind_port,
             "auth_key_length": server_config.auth_key.len(),
         });
+        let weak_key = weak_key_warning(&server_config.auth_key);
+        warnings.extend(weak_key.clone());
 
         match (&server_config.cert_path, &server_config.key_path) {
             (Some(cert), Some(key)) => {
@@ -162,6 +174,9 @@ pub fn check_config(config_path: &str, format: &str) -> Result<()> {
             println!("✓ Bind address: {}", server_config.bind_addr);
             println!("✓ Bind port: {}", server_config.bind_port);
             println!("✓ Auth key: {} characters", server_config.auth_key.len());
+            if let Some(warning) = &weak_key {
+                println!("⚠ Warning: {}", warning);
+            }
             match (&server_config.cert_path, &server_config.key_path) {
                 (Some(cert), Some(key)) => {
                     println!("✓ Certificate path: {:?}", cert);
@@ -206,6 +221,8 @@ pub fn check_config(config_path: &str, format: &str) -> Result<()> {
             if client_config.proxies.is_empty() {
                 warnings.push("No proxy configurations defined".to_string());
             }
+            let weak_key = weak_key_warning(&client_config.client.auth_key);
+            warnings.extend(weak_key.clone());
 
             let mut details = serde_json::json!({
                 "server_addr": client_config.client.server_addr,
@@ -241,6 +258,9 @@ pub fn check_config(config_path: &str, format: &str) -> Result<()> {
                     "✓ Auth key: {} characters",
                     client_config.client.auth_key.len()
                 );
+                if let Some(warning) = &weak_key {
+                    println!("⚠ Warning: {}", warning);
+                }
 
                 if let Some(ref ca_path) = client_config.client.ca_cert_path {
                     println!("✓ CA certificate path: {:?}", ca_path);
@@ -0,0 +1,347 @@
+use crate::config::PortRange;
+use anyhow::{Context, Result};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::path::Path;
+use toml_edit::{Array, ArrayOfTables, DocumentMut, Item, Table, Value};
+
+/// Character sets `keygen` draws keys from
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAlphabet {
+    /// Letters and digits without look-alikes (0, O, I, l)
+    Base58,
+    /// Letters, digits, `-` and `_`
+    Base64url,
+    /// Lowercase hexadecimal digits
+    Hex,
+    /// Letters and digits
+    Alnum,
+}
+
+impl KeyAlphabet {
+    fn chars(self) -> &'static [u8] {
+        match self {
+            KeyAlphabet::Base58 => b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz",
+            KeyAlphabet::Base64url => {
+                b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_"
+            }
+            KeyAlphabet::Hex => b"0123456789abcdef",
+            KeyAlphabet::Alnum => b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+        }
+    }
+}
+
+/// A named `[[server.auth_keys]]` entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenEntry {
+    pub name: String,
+    /// Publish port ranges the key may use (`8000` or `8000-8100`), empty for no restriction
+    pub allowed_ports: Vec<String>,
+}
+
+/// Generate a key of `length` characters drawn uniformly from `alphabet`
+pub fn generate_key(length: usize, alphabet: KeyAlphabet) -> Result<String> {
+    let chars = alphabet.chars();
+    // Reject bytes past the largest multiple of the alphabet size so every character is equally likely
+    let limit = 256 - 256 % chars.len();
+    let rng = SystemRandom::new();
+    let mut key = String::with_capacity(length);
+    let mut buf = [0u8; 64];
+    while key.len() < length {
+        rng.fill(&mut buf)
+            .map_err(|_| anyhow::anyhow!("System random number generator failed"))?;
+        for &byte in buf.iter().filter(|&&b| (b as usize) < limit) {
+            if key.len() == length {
+                break;
+            }
+            key.push(chars[byte as usize % chars.len()] as char);
+        }
+    }
+    Ok(key)
+}
+
+/// Check an `allowed_ports` entry (`8000` or `8000-8100`)
+fn validate_port_range(spec: &str) -> Result<()> {
+    spec.parse::<PortRange>().map_err(anyhow::Error::msg)?;
+    Ok(())
+}
+
+/// Build the `[[server.auth_keys]]` table for a named key
+fn token_table(key: &str, token: &TokenEntry) -> Table {
+    let mut table = Table::new();
+    table["name"] = toml_edit::value(token.name.as_str());
+    table["key"] = toml_edit::value(key);
+    if !token.allowed_ports.is_empty() {
+        table["allowed_ports"] = toml_edit::value(ports_array(&token.allowed_ports));
+    }
+    table
+}
+
+fn ports_array(ports: &[String]) -> Array {
+    ports.iter().map(|p| p.trim()).collect()
+}
+
+/// Replace a string value, keeping the whitespace and comments around it
+fn set_str(table: &mut dyn toml_edit::TableLike, key: &str, new: &str) {
+    match table.get_mut(key).and_then(Item::as_value_mut) {
+        Some(value) => {
+            let decor = value.decor().clone();
+            *value = Value::from(new);
+            *value.decor_mut() = decor;
+        }
+        None => {
+            table.insert(key, toml_edit::value(new));
+        }
+    }
+}
+
+/// Ready-to-paste TOML for `auth_key`, or for a `[[server.auth_keys]]` entry when `token` is set
+pub fn toml_snippet(key: &str, token: Option<&TokenEntry>) -> String {
+    let mut doc = DocumentMut::new();
+    match token {
+        Some(token) => {
+            let mut tokens = ArrayOfTables::new();
+            tokens.push(token_table(key, token));
+            let mut server = Table::new();
+            server.set_implicit(true);
+            server.insert("auth_keys", Item::ArrayOfTables(tokens));
+            doc.insert("server", Item::Table(server));
+        }
+        None => {
+            doc["auth_key"] = toml_edit::value(key);
+        }
+    }
+    doc.to_string()
+}
+
+/// Write a key into a config file's content, preserving comments and formatting
+///
+/// Without `token` the key replaces `auth_key` in the `[server]` (or `[client]`) section.
+/// With `token` it is stored in the `[[server.auth_keys]]` entry of that name, which is
+/// appended if missing; an existing entry keeps its other fields (key rotation).
+pub fn update_config(content: &str, key: &str, token: Option<&TokenEntry>) -> Result<String> {
+    let mut doc: DocumentMut = content.parse().context("Failed to parse TOML")?;
+    match token {
+        None => {
+            let section = ["server", "client"]
+                .into_iter()
+                .find(|s| doc.get(s).is_some_and(Item::is_table_like))
+                .context("No [server] or [client] section to write auth_key into")?;
+            let table = doc[section].as_table_like_mut().unwrap();
+            set_str(table, "auth_key", key);
+        }
+        Some(token) => {
+            let tokens = doc
+                .get_mut("server")
+                .and_then(Item::as_table_mut)
+                .context("No [server] section to write auth_keys into")?
+                .entry("auth_keys")
+                .or_insert(Item::ArrayOfTables(ArrayOfTables::new()))
+                .as_array_of_tables_mut()
+                .context("auth_keys must be an array of tables ([[server.auth_keys]])")?;
+            let existing = tokens
+                .iter_mut()
+                .find(|t| t.get("name").and_then(Item::as_str) == Some(token.name.as_str()));
+            match existing {
+                Some(table) => {
+                    set_str(table, "key", key);
+                    if !token.allowed_ports.is_empty() {
+                        table["allowed_ports"] =
+                            toml_edit::value(ports_array(&token.allowed_ports));
+                    }
+                }
+                None => tokens.push(token_table(key, token)),
+            }
+        }
+    }
+    Ok(doc.to_string())
+}
+
+/// Run `tls-tunnel keygen`
+pub fn run_keygen(
+    length: usize,
+    alphabet: KeyAlphabet,
+    format: &str,
+    name: Option<&str>,
+    allowed_ports: &[String],
+    write_to: Option<&str>,
+) -> Result<()> {
+    for spec in allowed_ports {
+        validate_port_range(spec)?;
+    }
+    let token = name.map(|name| TokenEntry {
+        name: name.to_string(),
+        allowed_ports: allowed_ports.to_vec(),
+    });
+    let key = generate_key(length, alphabet)?;
+
+    if let Some(path) = write_to {
+        let content = std::fs::read_to_string(Path::new(path))
+            .with_context(|| format!("Failed to read {}", path))?;
+        let updated = update_config(&content, &key, token.as_ref())
+            .with_context(|| format!("Failed to update {}", path))?;
+        std::fs::write(path, updated).with_context(|| format!("Failed to write {}", path))?;
+        match &token {
+            Some(token) => eprintln!("Wrote auth key '{}' to {}", token.name, path),
+            None => eprintln!("Wrote auth_key to {}", path),
+        }
+    }
+
+    if format == "toml" {
+        print!("{}", toml_snippet(&key, token.as_ref()));
+    } else {
+        println!("{}", key);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigValidator;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_generate_key_uses_alphabet_uniformly() {
+        for alphabet in [
+            KeyAlphabet::Base58,
+            KeyAlphabet::Base64url,
+            KeyAlphabet::Hex,
+            KeyAlphabet::Alnum,
+        ] {
+            let key = generate_key(4096, alphabet).unwrap();
+            assert_eq!(key.len(), 4096);
+            let chars = alphabet.chars();
+            assert!(key.bytes().all(|b| chars.contains(&b)), "{:?}", alphabet);
+            // Every character shows up and none dominates
+            let expected = 4096 / chars.len();
+            for &c in chars {
+                let count = key.bytes().filter(|&b| b == c).count();
+                assert!(
+                    count > 0 && count < expected * 3,
+                    "{:?}: '{}' appeared {} times",
+                    alphabet,
+                    c as char,
+                    count
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_generated_keys_are_distinct_and_strong() {
+        let keys: HashSet<String> = (0..100)
+            .map(|_| generate_key(32, KeyAlphabet::Base58).unwrap())
+            .collect();
+        assert_eq!(keys.len(), 100);
+        for key in &keys {
+            assert!(ConfigValidator::validate_auth_key(key).is_ok());
+            assert_eq!(ConfigValidator::auth_key_weakness(key), None, "{}", key);
+        }
+    }
+
+    #[test]
+    fn test_toml_snippets() {
+        assert_eq!(toml_snippet("abc", None), "auth_key = \"abc\"\n");
+        let token = TokenEntry {
+            name: "officeA".to_string(),
+            allowed_ports: vec!["8000-8100".to_string(), "9000".to_string()],
+        };
+        let snippet = toml_snippet("abc", Some(&token));
+        assert_eq!(
+            snippet,
+            "[[server.auth_keys]]\nname = \"officeA\"\nkey = \"abc\"\nallowed_ports = [\"8000-8100\", \"9000\"]\n"
+        );
+        let parsed: toml::Table = toml::from_str(&snippet).unwrap();
+        assert_eq!(
+            parsed["server"]["auth_keys"][0]["key"].as_str(),
+            Some("abc")
+        );
+    }
+
+    const SERVER_CONFIG: &str = r#"# Production server
+[server]
+bind_addr = "0.0.0.0"   # all interfaces
+bind_port = 8443
+
+# Change me!
+auth_key = "12345678901234567890"  # weak
+
+[server.rate_limit]
+requests_per_second = 100
+"#;
+
+    #[test]
+    fn test_update_auth_key_preserves_content() {
+        let updated = update_config(SERVER_CONFIG, "newkey", None).unwrap();
+        assert_eq!(
+            updated,
+            SERVER_CONFIG.replace("\"12345678901234567890\"", "\"newkey\"")
+        );
+
+        let client = "[client]\nserver_addr = \"example.com\"\n";
+        let updated = update_config(client, "k", None).unwrap();
+        assert_eq!(
+            updated,
+            "[client]\nserver_addr = \"example.com\"\nauth_key = \"k\"\n"
+        );
+
+        assert!(update_config("[other]\n", "k", None).is_err());
+        assert!(update_config("[server\n", "k", None).is_err());
+    }
+
+    #[test]
+    fn test_update_auth_keys_appends_and_rotates() {
+        let token = TokenEntry {
+            name: "officeA".to_string(),
+            allowed_ports: vec!["8000-8100".to_string()],
+        };
+        let added = update_config(SERVER_CONFIG, "first", Some(&token)).unwrap();
+        assert!(added.starts_with(SERVER_CONFIG), "{}", added);
+        assert!(added.ends_with(
+            "[[server.auth_keys]]\nname = \"officeA\"\nkey = \"first\"\nallowed_ports = [\"8000-8100\"]\n"
+        ));
+
+        // Rotating keeps the entry in place along with its port restriction
+        let rotate = TokenEntry {
+            name: "officeA".to_string(),
+            allowed_ports: vec![],
+        };
+        let rotated = update_config(&added, "second", Some(&rotate)).unwrap();
+        assert_eq!(rotated, added.replace("\"first\"", "\"second\""));
+
+        let other = TokenEntry {
+            name: "officeB".to_string(),
+            allowed_ports: vec![],
+        };
+        let both = update_config(&rotated, "third", Some(&other)).unwrap();
+        let parsed: toml::Table = toml::from_str(&both).unwrap();
+        let tokens = parsed["server"]["auth_keys"].as_array().unwrap();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[1]["name"].as_str(), Some("officeB"));
+        assert!(tokens[1].get("allowed_ports").is_none());
+
+        // The written entries load as server auth keys
+        let keys: Vec<crate::config::AuthKeyConfig> =
+            parsed["server"]["auth_keys"].clone().try_into().unwrap();
+        assert_eq!(keys[0].key, "second");
+        assert_eq!(
+            keys[0].allowed_ports.as_ref().unwrap()[0].to_string(),
+            "8000-8100"
+        );
+        assert_eq!(keys[1].allowed_ports, None);
+
+        // Named keys belong to the server
+        let client = "[client]\nserver_addr = \"example.com\"\n";
+        assert!(update_config(client, "k", Some(&other)).is_err());
+    }
+
+    #[test]
+    fn test_validate_port_range() {
+        assert!(validate_port_range("8000").is_ok());
+        assert!(validate_port_range("8000-8100").is_ok());
+        assert!(validate_port_range("0").is_err());
+        assert!(validate_port_range("8100-8000").is_err());
+        assert!(validate_port_range("80-x").is_err());
+        assert!(validate_port_range("70000").is_err());
+    }
+}
@@ -1,10 +1,13 @@
 pub mod args;
 pub mod cert;
 pub mod commands;
+pub mod completions;
 pub mod config;
+pub mod keygen;
 pub mod service;
 pub mod template;
+pub mod trace;
 
 // Re-export commonly used items
-pub use args::{Cli, Commands};
+pub use args::{CertAction, Cli, Commands, TraceAction};
 pub use commands::execute_command;
@@ -42,6 +42,12 @@ pub fn register_systemd_service(
             _ => unreachable!(),
         };
 
+        // 客户端收到 SIGHUP 时重新加载配置文件（systemctl reload）
+        let exec_reload = match _service_type {
+            "client" => "ExecReload=/bin/kill -HUP $MAINPID\n",
+            _ => "",
+        };
+
         let unit_content = format!(
             "[Unit]\n\
             Description={}\n\
@@ -51,13 +57,14 @@ pub fn register_systemd_service(
             [Service]\n\
             Type=simple\n\
             ExecStart={} {} --config {}\n\
+            {}\
             Restart=on-failure\n\
             RestartSec=3\n\
             Environment=RUST_LOG=info\n\
             \n\
             [Install]\n\
             WantedBy=multi-user.target\n",
-            description, exec_path, _service_type, config_path
+            description, exec_path, _service_type, config_path, exec_reload
         );
 
         let unit_file = format!("/etc/systemd/system/{}.service", service_name);
@@ -1,12 +1,23 @@
 use anyhow::{Context, Result};
 
 /// Generate configuration template
-pub fn generate_config_template(template_type: &str, output: Option<&str>) -> Result<()> {
-    let content = match template_type {
+///
+/// `transport` (tls, http2, wss) is written right below the `[server]`/`[client]` header;
+/// without it the template keeps the default TLS transport
+pub fn generate_config_template(
+    template_type: &str,
+    output: Option<&str>,
+    transport: Option<&str>,
+) -> Result<()> {
+    let template = match template_type {
         "server" => include_str!("../../examples/server-template.toml"),
         "client" => include_str!("../../examples/client-template.toml"),
         _ => unreachable!(),
     };
+    let content = match transport {
+        Some(transport) => with_transport(template, transport),
+        None => template.to_string(),
+    };
 
     if let Some(path) = output {
         std::fs::write(path, content)
@@ -21,3 +32,42 @@ pub fn generate_config_template(template_type: &str, output: Option<&str>) -> Re
 
     Ok(())
 }
+
+/// Insert a `transport` setting after the first section header
+fn with_transport(template: &str, transport: &str) -> String {
+    // Top-level keys (such as the commented `strict`) come before the header
+    let header_start = if template.starts_with('[') {
+        0
+    } else {
+        template.find("\n[").map_or(0, |i| i + 1)
+    };
+    let (header, rest) = template[header_start..]
+        .split_once('\n')
+        .unwrap_or((&template[header_start..], ""));
+    format!(
+        "{}{}\n# Transport (tls, http2, wss); server and client must use the same one\ntransport = \"{}\"\n{}",
+        &template[..header_start],
+        header,
+        transport,
+        rest
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ClientFullConfig, ServerConfig};
+    use crate::transport::TransportType;
+
+    #[test]
+    fn test_template_with_transport() {
+        let server = with_transport(include_str!("../../examples/server-template.toml"), "wss");
+        let config: toml::Value = toml::from_str(&server).unwrap();
+        let config: ServerConfig = config["server"].clone().try_into().unwrap();
+        assert_eq!(config.transport, TransportType::Wss);
+
+        let client = with_transport(include_str!("../../examples/client-template.toml"), "http2");
+        let config: ClientFullConfig = toml::from_str(&client).unwrap();
+        assert_eq!(config.client.transport, TransportType::Http2);
+    }
+}
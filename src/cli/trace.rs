@@ -0,0 +1,74 @@
+use anyhow::Result;
+use std::path::Path;
+
+use super::args::TraceAction;
+use crate::protocol_trace::analyze::{analyze_file, SessionTimeline, TraceAnalysis};
+
+/// Execute a `tls-tunnel trace <ACTION>` subcommand
+pub fn execute_trace_action(action: &TraceAction) -> Result<()> {
+    match action {
+        TraceAction::Analyze { file, json } => {
+            let analysis = analyze_file(Path::new(file))?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&analysis)?);
+            } else {
+                print_analysis(&analysis);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the reconstructed timelines in human-readable form
+fn print_analysis(analysis: &TraceAnalysis) {
+    if analysis.sessions.is_empty() {
+        println!("No sessions found in trace");
+    }
+    for (index, session) in analysis.sessions.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+        print_session(session);
+    }
+    if analysis.malformed_lines > 0 {
+        println!();
+        println!("Skipped {} malformed line(s)", analysis.malformed_lines);
+    }
+
+    println!();
+    println!(
+        "{} session(s), {} anomaly(ies)",
+        analysis.sessions.len(),
+        analysis.anomaly_count()
+    );
+}
+
+fn print_session(session: &SessionTimeline) {
+    println!(
+        "{} session {} (started at {} ms)",
+        session.side.as_str(),
+        session.session,
+        session.started_ms
+    );
+    for step in &session.steps {
+        println!(
+            "  +{:<8} {:<8} {:<4} {:<40} {}",
+            format!("{}ms", step.offset_ms),
+            step.phase.as_str(),
+            step.direction.as_str(),
+            step.label,
+            step.outcome
+        );
+    }
+    if session.heartbeats > 0 {
+        println!("  heartbeats: {}", session.heartbeats);
+    }
+    match &session.end_reason {
+        Some(reason) => println!("  ended: {}", reason),
+        None => println!("  ended: <trace ends before session end>"),
+    }
+    for anomaly in &session.anomalies {
+        println!("  ! {}", anomaly);
+    }
+}
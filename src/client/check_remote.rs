@@ -0,0 +1,189 @@
+/// 远程配置校验（`tls-tunnel check-remote`）
+///
+/// 连接服务器并认证后发送 `validate_config`，服务器按提交配置的规则与当前注册表比对，
+/// 但不绑定端口也不注册代理；得到结果后立即断开，会话不会进入运行状态
+use super::control_channel::{ClientControlChannel, ControlEvent};
+use crate::config::ClientFullConfig;
+use crate::control_protocol::CAPABILITY_VALIDATE_CONFIG;
+use crate::identity::ClientIdentity;
+use crate::transport::{create_transport_client, limit_write_chunk};
+use ::yamux::{Config as YamuxConfig, Connection as YamuxConnection, Mode as YamuxMode};
+use anyhow::{Context, Result};
+use futures::future::poll_fn;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::time::Duration;
+use tokio_rustls::TlsConnector;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+use tracing::{debug, info};
+
+/// 整个校验过程（连接、认证、校验）的最长时间
+const CHECK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 单个代理或 visitor 的校验结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteCheckItem {
+    /// 类型："proxy" 或 "visitor"
+    pub kind: &'static str,
+    /// 名称
+    pub name: String,
+    /// 发布端口
+    pub publish_port: u16,
+    /// 拒绝原因（None 表示服务器会接受）
+    pub rejection: Option<String>,
+}
+
+impl RemoteCheckItem {
+    /// 服务器是否会接受该项
+    pub fn is_accepted(&self) -> bool {
+        self.rejection.is_none()
+    }
+}
+
+/// 远程配置校验结果（按配置文件中的顺序，先代理后 visitor）
+#[derive(Debug, Clone, Default)]
+pub struct RemoteCheckReport {
+    pub items: Vec<RemoteCheckItem>,
+}
+
+impl RemoteCheckReport {
+    /// 按服务器返回的拒绝列表生成每一项的结果
+    fn new(
+        config: &ClientFullConfig,
+        rejected: &[String],
+        reasons: &BTreeMap<String, String>,
+    ) -> Self {
+        let item = |kind, name: &str, publish_port| {
+            let key = format!("{}:{}", name, publish_port);
+            let rejection = rejected.contains(&key).then(|| {
+                reasons
+                    .get(&key)
+                    .cloned()
+                    .unwrap_or_else(|| "rejected".to_string())
+            });
+            RemoteCheckItem {
+                kind,
+                name: name.to_string(),
+                publish_port,
+                rejection,
+            }
+        };
+
+        let mut items: Vec<RemoteCheckItem> = config
+            .proxies
+            .iter()
+            .map(|p| item("proxy", &p.name, p.publish_port))
+            .collect();
+        items.extend(
+            config
+                .visitors
+                .iter()
+                .map(|v| item("visitor", &v.name, v.publish_port)),
+        );
+        Self { items }
+    }
+
+    /// 是否所有项都会被接受
+    pub fn is_accepted(&self) -> bool {
+        self.items.iter().all(RemoteCheckItem::is_accepted)
+    }
+}
+
+/// 请求服务器校验客户端配置（dry-run），不注册任何代理
+pub async fn check_remote(
+    config: ClientFullConfig,
+    tls_connector: TlsConnector,
+) -> Result<RemoteCheckReport> {
+    tokio::time::timeout(CHECK_TIMEOUT, check_remote_inner(config, tls_connector))
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out waiting for the server to validate the config"))?
+}
+
+async fn check_remote_inner(
+    config: ClientFullConfig,
+    tls_connector: TlsConnector,
+) -> Result<RemoteCheckReport> {
+    let client_config = &config.client;
+    info!(
+        "Connecting to {}:{} using {} transport",
+        client_config.server_addr, client_config.server_port, client_config.transport
+    );
+
+    let transport_client = create_transport_client(client_config, tls_connector)
+        .context("Failed to create transport client")?;
+    let transport_stream = transport_client.connect().await.with_context(|| {
+        format!(
+            "Failed to connect to server via {} transport",
+            transport_client.transport_type()
+        )
+    })?;
+    let tls_stream = limit_write_chunk(transport_stream, client_config.max_write_chunk);
+
+    let mut yamux_conn = YamuxConnection::new(
+        tls_stream.compat(),
+        YamuxConfig::default(),
+        YamuxMode::Client,
+    );
+    let mut control_stream = poll_fn(|cx| yamux_conn.poll_new_outbound(cx))
+        .await
+        .context("Failed to create control stream")?;
+
+    // 与正常运行时使用同一身份，known_clients 限制的服务器才会接受校验会话
+    let identity = ClientIdentity::for_config(client_config.identity_path.as_deref(), None)?;
+    let (control_channel, mut event_rx) = ClientControlChannel::new(config.clone());
+    let mut control_channel = control_channel.with_identity(Arc::new(identity));
+    control_channel
+        .send_authenticate(&mut control_stream)
+        .await?;
+
+    let result = loop {
+        tokio::select! {
+            // 驱动 yamux 连接（校验会话不接受服务器发起的 stream）
+            inbound = poll_fn(|cx| yamux_conn.poll_next_inbound(cx)) => match inbound {
+                Some(Ok(stream)) => drop(stream),
+                Some(Err(e)) => break Err(anyhow::anyhow!("Yamux error: {}", e)),
+                None => break Err(anyhow::anyhow!("Connection closed by server")),
+            },
+
+            read_result = control_channel.read_message(&mut control_stream) => match read_result {
+                // 服务器推送的通知（例如异常通知）与校验无关
+                Ok(Some(request)) => debug!("Ignoring control message: {}", request.method),
+                Ok(None) => break Err(anyhow::anyhow!("Control stream closed by server")),
+                Err(e) => break Err(e),
+            },
+
+            Some(event) = event_rx.recv() => match event {
+                ControlEvent::AuthenticationSuccess { capabilities, .. } => {
+                    if !capabilities.iter().any(|c| c == CAPABILITY_VALIDATE_CONFIG) {
+                        break Err(anyhow::anyhow!(
+                            "Server does not support config validation (validate_config)"
+                        ));
+                    }
+                    control_channel.send_validate_config(&mut control_stream).await?;
+                }
+                ControlEvent::IdentityChallenged { proof } => {
+                    control_channel.send_authenticate_with(&mut control_stream, proof).await?;
+                }
+                ControlEvent::AuthenticationFailed { reason } => {
+                    break Err(anyhow::anyhow!("Authentication failed: {}", reason));
+                }
+                ControlEvent::ConfigValidated { rejected_proxies, reasons } => {
+                    break Ok(RemoteCheckReport::new(&config, &rejected_proxies, &reasons));
+                }
+                ControlEvent::ConfigValidationFailed { reason } => {
+                    break Err(anyhow::anyhow!("Config validation failed: {}", reason));
+                }
+                other => debug!("Ignoring control event: {:?}", other),
+            },
+        }
+    };
+
+    // 尽量正常关闭连接，服务器随即结束会话
+    let _ = tokio::time::timeout(
+        Duration::from_secs(1),
+        poll_fn(|cx| yamux_conn.poll_close(cx)),
+    )
+    .await;
+
+    result
+}
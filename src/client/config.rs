@@ -1,21 +1,83 @@
+use crate::config::ClientTimeoutsConfig;
+use crate::limited_reader::LimitedReader;
+pub use crate::protocol::MAX_ERROR_MESSAGE_SIZE;
+use crate::protocol::{StreamPreamble, MAX_STREAM_NAME_LEN};
 use anyhow::Result;
-use tokio::io::AsyncReadExt;
+use rand::Rng;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 /// 环境变量前缀
 pub const ENV_PREFIX: &str = "TLS_TUNNEL_";
 
-/// 重连延迟（秒）- 可通过环境变量 TLS_TUNNEL_RECONNECT_DELAY_SECS 覆盖
-pub const RECONNECT_DELAY_SECS: u64 = 5;
 /// 本地服务连接重试次数 - 可通过环境变量 TLS_TUNNEL_LOCAL_CONNECT_RETRIES 覆盖
 pub const LOCAL_CONNECT_RETRIES: u32 = 3;
-/// 本地服务连接重试延迟（毫秒）- 可通过环境变量 TLS_TUNNEL_LOCAL_RETRY_DELAY_MS 覆盖
-pub const LOCAL_RETRY_DELAY_MS: u64 = 1000;
 
-pub fn get_reconnect_delay() -> u64 {
+/// 重连延迟（秒），默认取 `timeouts.reconnect_delay_secs`，可通过环境变量 TLS_TUNNEL_RECONNECT_DELAY_SECS 覆盖
+pub fn get_reconnect_delay(default_secs: u64) -> u64 {
     std::env::var(format!("{}RECONNECT_DELAY_SECS", ENV_PREFIX))
         .ok()
         .and_then(|v| v.parse().ok())
-        .unwrap_or(RECONNECT_DELAY_SECS)
+        .unwrap_or(default_secs)
+}
+
+/// 会话运行超过该时长后结束时重连等待时间从初始值重新开始
+pub const BACKOFF_RESET_AFTER: Duration = Duration::from_secs(60);
+
+/// 重连等待时间的随机抖动幅度（±20%），避免大量客户端同时重连
+const BACKOFF_JITTER: f64 = 0.2;
+
+/// 重连退避：每次重连失败后等待时间乘以倍数，直到上限；实际等待时间带 ±20% 的随机抖动
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    multiplier: f64,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, multiplier: f64, max: Duration) -> Self {
+        // 环境变量覆盖的初始等待时间可能超过配置的上限
+        let max = max.max(initial);
+        Self {
+            initial,
+            multiplier,
+            max,
+            current: initial,
+        }
+    }
+
+    /// 按客户端超时设置创建（初始等待时间的环境变量覆盖同样生效）
+    pub fn from_timeouts(timeouts: &ClientTimeoutsConfig) -> Self {
+        Self::new(
+            Duration::from_secs(get_reconnect_delay(timeouts.reconnect_delay_secs)),
+            timeouts.reconnect_multiplier,
+            timeouts.reconnect_max_delay(),
+        )
+    }
+
+    /// 下一次重连前的等待时间（带抖动），之后的等待时间按倍数增长
+    pub fn next_delay(&mut self) -> Duration {
+        jitter(self.advance(), rand::rng().random_range(0.0..1.0))
+    }
+
+    /// 重新从初始等待时间开始
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+    }
+
+    /// 返回当前的等待时间（不带抖动）并增长到下一次的等待时间
+    fn advance(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = self.max.min(delay.mul_f64(self.multiplier));
+        delay
+    }
+}
+
+/// 对等待时间加上抖动，`random` 在 [0, 1) 内均匀分布
+fn jitter(delay: Duration, random: f64) -> Duration {
+    delay.mul_f64(1.0 - BACKOFF_JITTER + 2.0 * BACKOFF_JITTER * random)
 }
 
 pub fn get_local_retries() -> u32 {
@@ -25,28 +87,151 @@ pub fn get_local_retries() -> u32 {
         .unwrap_or(LOCAL_CONNECT_RETRIES)
 }
 
-pub fn get_local_retry_delay() -> u64 {
+/// 本地服务连接重试延迟（毫秒），默认取 `timeouts.local_retry_delay_ms`，
+/// 可通过环境变量 TLS_TUNNEL_LOCAL_RETRY_DELAY_MS 覆盖
+pub fn get_local_retry_delay(default_ms: u64) -> u64 {
     std::env::var(format!("{}LOCAL_RETRY_DELAY_MS", ENV_PREFIX))
         .ok()
         .and_then(|v| v.parse().ok())
-        .unwrap_or(LOCAL_RETRY_DELAY_MS)
+        .unwrap_or(default_ms)
 }
 
 /// 读取服务器返回的错误消息
+///
+/// 消息内容通过 `LimitedReader` 读取，缓冲区随实际收到的数据增长，
+/// 而不是按对端声明的长度预先分配
 pub async fn read_error_message<T>(stream: &mut T) -> Result<String>
 where
-    T: AsyncReadExt + Unpin,
+    T: AsyncRead + Unpin,
 {
     let mut msg_len_buf = [0u8; 2];
     stream.read_exact(&mut msg_len_buf).await?;
     let msg_len = u16::from_be_bytes(msg_len_buf) as usize;
 
-    if msg_len > 4096 {
-        anyhow::bail!("Error message too long");
+    if msg_len > MAX_ERROR_MESSAGE_SIZE {
+        anyhow::bail!(
+            "Error message too long ({} bytes, max {})",
+            msg_len,
+            MAX_ERROR_MESSAGE_SIZE
+        );
     }
 
-    let mut msg_buf = vec![0u8; msg_len];
-    stream.read_exact(&mut msg_buf).await?;
+    let mut reader = LimitedReader::new(stream, msg_len);
+    let mut msg_buf = Vec::new();
+    while msg_buf.len() < msg_len {
+        if reader.read_buf(&mut msg_buf).await? == 0 {
+            anyhow::bail!("Unexpected EOF while reading error message");
+        }
+    }
     let message = String::from_utf8(msg_buf)?;
     Ok(message)
 }
+
+/// 检查 stream 前导名称长度（服务器拒绝超过 [`MAX_STREAM_NAME_LEN`] 字节的名称）
+pub fn check_stream_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.len() > MAX_STREAM_NAME_LEN {
+        anyhow::bail!(
+            "Stream name '{}' is {} bytes, must be 1-{} bytes",
+            name,
+            name.len(),
+            MAX_STREAM_NAME_LEN
+        );
+    }
+    Ok(())
+}
+
+/// 发送 stream 前导：u16 名称长度 + 名称 + u16 publish_port
+///
+/// 名称长度不合法时在本地报错，不写入任何数据
+pub async fn write_stream_preamble<T>(stream: &mut T, name: &str, publish_port: u16) -> Result<()>
+where
+    T: AsyncWrite + Unpin,
+{
+    check_stream_name(name)?;
+
+    let preamble = StreamPreamble {
+        name: name.to_string(),
+        publish_port,
+    };
+    stream.write_all(&preamble.encode()).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_progression() {
+        let mut backoff = Backoff::new(Duration::from_secs(5), 2.0, Duration::from_secs(60));
+        let delays: Vec<u64> = (0..6).map(|_| backoff.advance().as_secs()).collect();
+        assert_eq!(delays, [5, 10, 20, 40, 60, 60]);
+
+        backoff.reset();
+        assert_eq!(backoff.advance(), Duration::from_secs(5));
+
+        // 倍数为 1 时等待时间不变；初始值为 0 时立即重连
+        let mut fixed = Backoff::new(Duration::from_secs(3), 1.0, Duration::from_secs(60));
+        assert!((0..3).all(|_| fixed.advance() == Duration::from_secs(3)));
+        let mut immediate = Backoff::new(Duration::ZERO, 2.0, Duration::from_secs(60));
+        assert_eq!(immediate.next_delay(), Duration::ZERO);
+
+        // 上限小于初始等待时间时以初始等待时间为上限
+        let mut capped = Backoff::new(Duration::from_secs(10), 2.0, Duration::from_secs(5));
+        assert_eq!(capped.advance(), Duration::from_secs(10));
+        assert_eq!(capped.advance(), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_backoff_jitter() {
+        let delay = Duration::from_secs(10);
+        assert_eq!(jitter(delay, 0.0), Duration::from_secs(8));
+        assert_eq!(jitter(delay, 0.5), delay);
+        assert!(jitter(delay, 0.9999) < Duration::from_secs(12));
+
+        let mut backoff = Backoff::new(Duration::from_secs(10), 2.0, Duration::from_secs(300));
+        for base in [10.0, 20.0, 40.0] {
+            let delay = backoff.next_delay().as_secs_f64();
+            assert!(
+                delay >= base * 0.8 && delay < base * 1.2,
+                "{} not within 20% of {}",
+                delay,
+                base
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_error_message() {
+        let mut data = 5u16.to_be_bytes().to_vec();
+        data.extend_from_slice(b"oops!trailing");
+        let mut reader = data.as_slice();
+        assert_eq!(read_error_message(&mut reader).await.unwrap(), "oops!");
+        assert_eq!(reader, b"trailing");
+
+        // 声明的长度超过上限时不读取内容
+        let data = ((MAX_ERROR_MESSAGE_SIZE + 1) as u16).to_be_bytes();
+        assert!(read_error_message(&mut data.as_slice()).await.is_err());
+
+        // 内容不足声明的长度
+        let mut data = 100u16.to_be_bytes().to_vec();
+        data.extend_from_slice(b"short");
+        assert!(read_error_message(&mut data.as_slice()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_stream_preamble() {
+        let mut out = Vec::new();
+        write_stream_preamble(&mut out, "web", 8080).await.unwrap();
+        assert_eq!(out, [&[0, 3][..], b"web", &8080u16.to_be_bytes()].concat());
+
+        let mut out = Vec::new();
+        let long_name = "a".repeat(MAX_STREAM_NAME_LEN + 1);
+        assert!(write_stream_preamble(&mut out, &long_name, 0)
+            .await
+            .is_err());
+        assert!(write_stream_preamble(&mut out, "", 0).await.is_err());
+        assert!(out.is_empty());
+    }
+}
@@ -1,30 +1,140 @@
 use super::config::{get_local_retries, get_local_retry_delay, ENV_PREFIX};
-use crate::config::ProxyType;
+use super::stats::ClientStatsTracker;
+use crate::config::{ClientTimeoutsConfig, ProxyConfig};
 use crate::connection_pool::{ConnectionPool, PoolConfig};
 use anyhow::Result;
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::net::TcpStream;
-use tokio::time::{sleep, Duration};
+use tokio::time::{sleep, timeout, Duration, Instant};
 use tracing::info;
 
+/// 连接失败的本地目标在该时长内排到其他目标之后
+pub const UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(10);
+
 pub struct LocalConn {
     pub stream: TcpStream,
     pub pooled: bool,
+    /// 连接的本地目标地址
+    pub addr: String,
+}
+
+/// 代理的本地后端：连接池（按目标地址分别维护）和本地目标
+pub struct LocalBackend {
+    pub pool: Arc<ConnectionPool>,
+    pub targets: LocalTargets,
+}
+
+/// 代理的本地目标
+///
+/// 按轮询顺序选择目标，最近连接失败的目标在 [`UNHEALTHY_COOLDOWN`] 内排在其他目标之后
+/// （所有目标都失败时仍会依次尝试）
+pub struct LocalTargets {
+    addrs: Vec<String>,
+    next: AtomicUsize,
+    /// 各目标最近一次连接失败的时间
+    failed_at: Vec<Mutex<Option<Instant>>>,
+}
+
+impl LocalTargets {
+    pub fn new(addrs: Vec<String>) -> Self {
+        let failed_at = addrs.iter().map(|_| Mutex::new(None)).collect();
+        Self {
+            addrs,
+            next: AtomicUsize::new(0),
+            failed_at,
+        }
+    }
+
+    pub fn addrs(&self) -> &[String] {
+        &self.addrs
+    }
+
+    /// 目标是否可用（没有失败记录或已过冷却时间）
+    fn is_healthy(&self, index: usize) -> bool {
+        self.failed_at[index]
+            .lock()
+            .is_none_or(|failed_at| failed_at.elapsed() >= UNHEALTHY_COOLDOWN)
+    }
+
+    /// 本次连接尝试目标的顺序：从轮询位置开始，最近失败的目标排在最后
+    fn attempt_order(&self) -> Vec<usize> {
+        let count = self.addrs.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let (healthy, unhealthy): (Vec<usize>, Vec<usize>) = (0..count)
+            .map(|offset| (start + offset) % count)
+            .partition(|&index| self.is_healthy(index));
+        healthy.into_iter().chain(unhealthy).collect()
+    }
+
+    fn mark_failed(&self, index: usize) {
+        *self.failed_at[index].lock() = Some(Instant::now());
+    }
+
+    fn mark_healthy(&self, index: usize) {
+        *self.failed_at[index].lock() = None;
+    }
+}
+
+/// 连接代理的本地目标
+///
+/// 只有一个目标时按配置的次数重试；有多个目标时每个目标只尝试一次，
+/// 连接失败后改用下一个目标，全部失败才返回错误
+pub async fn connect_targets(
+    targets: &LocalTargets,
+    pool: &Arc<ConnectionPool>,
+    tracker: Option<&ClientStatsTracker>,
+) -> Result<LocalConn> {
+    let retries = if targets.addrs.len() > 1 {
+        1
+    } else {
+        get_local_retries()
+    };
+
+    let mut last_error = None;
+    for index in targets.attempt_order() {
+        let addr = &targets.addrs[index];
+        match connect_local(addr, pool, retries).await {
+            Ok(conn) => {
+                targets.mark_healthy(index);
+                return Ok(conn);
+            }
+            Err(e) => {
+                targets.mark_failed(index);
+                if let Some(tracker) = tracker {
+                    tracker.record_target_failure(addr);
+                }
+                if targets.addrs.len() > 1 {
+                    tracing::warn!(
+                        "Local target {} unavailable: {}, trying next target",
+                        addr,
+                        e
+                    );
+                }
+                last_error = Some(e);
+            }
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No local targets configured")))
 }
 
+/// 连接单个本地目标（连接池允许复用时先从池中获取），失败时最多尝试 `max_retries` 次
 pub async fn connect_local(
     local_addr: &str,
     pool: &Arc<ConnectionPool>,
-    proxy_type: ProxyType,
+    max_retries: u32,
 ) -> Result<LocalConn> {
-    // 如果该代理类型应该复用连接，则尝试从池中获取
-    if proxy_type.should_reuse_connections() {
+    // 如果该代理的连接池策略允许复用连接，则尝试从池中获取
+    if pool.config().reuse_connections {
         match pool.get(local_addr).await {
             Ok(stream) => {
                 info!("Got connection to {} from pool", local_addr);
                 return Ok(LocalConn {
                     stream,
                     pooled: true,
+                    addr: local_addr.to_string(),
                 });
             }
             Err(e) => {
@@ -36,25 +146,22 @@ pub async fn connect_local(
         }
     }
 
-    // 建立新连接
-    let max_retries = get_local_retries();
-    let retry_delay = get_local_retry_delay();
+    // 建立新连接（超时与连接池新建连接相同）
+    let connect_timeout = pool.config().connect_timeout;
+    let retry_delay = pool.config().retry_delay;
 
     for attempt in 1..=max_retries {
-        match TcpStream::connect(local_addr).await {
+        let result = timeout(connect_timeout, TcpStream::connect(local_addr))
+            .await
+            .unwrap_or_else(|_| {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("connect timed out after {:?}", connect_timeout),
+                ))
+            });
+        match result {
             Ok(stream) => {
-                // 为需要低延迟的代理类型（如 SSH）启用 TCP_NODELAY
-                if proxy_type.needs_nodelay() {
-                    if let Err(e) = stream.set_nodelay(true) {
-                        tracing::warn!("Failed to set TCP_NODELAY for {}: {}", local_addr, e);
-                    } else {
-                        tracing::debug!(
-                            "Enabled TCP_NODELAY for {} (proxy type: {:?})",
-                            local_addr,
-                            proxy_type
-                        );
-                    }
-                }
+                crate::socket_options::configure(&stream, Some(&pool.config().socket), local_addr);
 
                 info!(
                     "Connected to local service: {} (attempt {})",
@@ -63,6 +170,7 @@ pub async fn connect_local(
                 return Ok(LocalConn {
                     stream,
                     pooled: false,
+                    addr: local_addr.to_string(),
                 });
             }
             Err(err) => {
@@ -73,7 +181,7 @@ pub async fn connect_local(
                         attempt,
                         err
                     );
-                    sleep(Duration::from_millis(retry_delay)).await;
+                    sleep(retry_delay).await;
                 } else {
                     tracing::error!(
                         "Failed to connect to {} after {} attempts: {}",
@@ -94,7 +202,8 @@ pub async fn connect_local(
     unreachable!()
 }
 
-pub async fn get_pool_config() -> PoolConfig {
+/// 连接池配置：默认值取自客户端的 `timeouts`，环境变量优先
+pub async fn get_pool_config(timeouts: &ClientTimeoutsConfig) -> PoolConfig {
     let defaults = PoolConfig::default();
     PoolConfig {
         min_idle: std::env::var(format!("{}POOL_MIN_IDLE", ENV_PREFIX))
@@ -115,8 +224,9 @@ pub async fn get_pool_config() -> PoolConfig {
             std::env::var(format!("{}POOL_CONNECT_TIMEOUT_MS", ENV_PREFIX))
                 .ok()
                 .and_then(|v| v.parse().ok())
-                .unwrap_or(defaults.connect_timeout.as_millis() as u64),
+                .unwrap_or(timeouts.local_connect_ms),
         ),
+        retry_delay: Duration::from_millis(get_local_retry_delay(timeouts.local_retry_delay_ms)),
         keepalive_time: std::env::var(format!("{}POOL_KEEPALIVE_SECS", ENV_PREFIX))
             .ok()
             .and_then(|v| v.parse().ok())
@@ -127,6 +237,268 @@ pub async fn get_pool_config() -> PoolConfig {
             .and_then(|v| v.parse().ok())
             .map(Duration::from_secs)
             .or(defaults.keepalive_interval),
-        reuse_connections: true, // 默认值，会在下面根据代理类型调整
+        reuse_connections: true, // 默认值，会在 resolve_pool_config 中根据代理类型调整
+        warmup: true,
+        max_lifetime: std::env::var(format!("{}POOL_MAX_LIFETIME_SECS", ENV_PREFIX))
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|secs: &u64| *secs > 0)
+            .map(Duration::from_secs)
+            .or(defaults.max_lifetime),
+        socket: defaults.socket,
+    }
+}
+
+/// 根据代理类型推导连接池策略，并应用代理配置中的 `pool` 覆盖项
+pub fn resolve_pool_config(base: &PoolConfig, proxy: &ProxyConfig) -> PoolConfig {
+    let mut config = base.clone();
+    config.reuse_connections = proxy.proxy_type.should_reuse_connections();
+    config.warmup = proxy.proxy_type.should_warmup();
+    // 多路复用类型默认只保持单个持久连接
+    if proxy.proxy_type.is_multiplexed() {
+        config.max_size = 1;
+        config.min_idle = 1;
+    }
+
+    if let Some(pool) = &proxy.pool {
+        if let Some(max_size) = pool.max_size {
+            config.max_size = max_size;
+        }
+        if let Some(min_idle) = pool.min_idle {
+            config.min_idle = min_idle;
+        }
+        if let Some(warmup) = pool.warmup {
+            config.warmup = warmup;
+        }
+        if let Some(reuse) = pool.reuse {
+            config.reuse_connections = reuse;
+        }
+        if let Some(max_lifetime_secs) = pool.max_lifetime_secs {
+            config.max_lifetime = Some(Duration::from_secs(max_lifetime_secs));
+        }
+    }
+
+    if let Some(socket) = &proxy.socket {
+        config.socket = socket.clone();
+    }
+
+    // 只覆盖了 max_size 时，避免继承的 min_idle 超过上限
+    config.min_idle = config.min_idle.min(config.max_size);
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ProxyPoolConfig, ProxyType, ProxyVisibility, SocketOptionsConfig};
+    use tokio::net::TcpListener;
+
+    fn make_proxy(proxy_type: ProxyType, pool: Option<ProxyPoolConfig>) -> ProxyConfig {
+        ProxyConfig {
+            name: "test".to_string(),
+            proxy_type,
+            publish_addr: "0.0.0.0".to_string(),
+            publish_port: 9000,
+            local_port: 8080,
+            pool,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }
+    }
+
+    #[test]
+    fn test_resolve_pool_config_type_defaults() {
+        let base = PoolConfig::default();
+
+        let http2 = resolve_pool_config(&base, &make_proxy(ProxyType::Http2, None));
+        assert_eq!(http2.max_size, 1);
+        assert_eq!(http2.min_idle, 1);
+        assert!(http2.reuse_connections);
+        assert!(http2.warmup);
+
+        let tcp = resolve_pool_config(&base, &make_proxy(ProxyType::Tcp, None));
+        assert_eq!(tcp.max_size, base.max_size);
+        assert!(!tcp.reuse_connections);
+        assert!(!tcp.warmup);
+        assert_eq!(tcp.max_lifetime, None);
+
+        let aged = resolve_pool_config(
+            &base,
+            &make_proxy(
+                ProxyType::Http11,
+                Some(ProxyPoolConfig {
+                    max_lifetime_secs: Some(3300),
+                    ..Default::default()
+                }),
+            ),
+        );
+        assert_eq!(aged.max_lifetime, Some(Duration::from_secs(3300)));
+    }
+
+    #[tokio::test]
+    async fn test_pool_override_honored() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut accepted = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                accepted.push(stream);
+            }
+        });
+
+        let proxy = make_proxy(
+            ProxyType::Http2,
+            Some(ProxyPoolConfig {
+                max_size: Some(8),
+                min_idle: Some(3),
+                ..Default::default()
+            }),
+        );
+        let pool = ConnectionPool::new(resolve_pool_config(&PoolConfig::default(), &proxy));
+        pool.warmup(&addr).await.unwrap();
+
+        let stats = pool.stats(&addr).await.unwrap();
+        assert_eq!(stats.max_size, 8);
+        assert_eq!(stats.idle, 3);
+    }
+
+    #[tokio::test]
+    async fn test_warmup_disabled_connects_nothing() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let proxy = make_proxy(
+            ProxyType::Http11,
+            Some(ProxyPoolConfig {
+                warmup: Some(false),
+                ..Default::default()
+            }),
+        );
+        let pool = ConnectionPool::new(resolve_pool_config(&PoolConfig::default(), &proxy));
+        pool.warmup(&addr).await.unwrap();
+
+        assert_eq!(pool.stats(&addr).await.unwrap().total, 0);
+        let accepted = tokio::time::timeout(Duration::from_millis(200), listener.accept()).await;
+        assert!(accepted.is_err(), "backend should not see any connection");
+    }
+
+    #[tokio::test]
+    async fn test_socket_options_applied_to_local_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut accepted = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                accepted.push(stream);
+            }
+        });
+
+        let socket = SocketOptionsConfig {
+            nodelay: false,
+            send_buffer_bytes: Some(8 * 1024),
+            recv_buffer_bytes: None,
+            keepalive_secs: Some(30),
+            user_timeout_ms: None,
+        };
+        // 直接连接（TCP 不复用）和连接池（HTTP/2 复用）两条路径
+        for proxy_type in [ProxyType::Tcp, ProxyType::Http2] {
+            let mut proxy = make_proxy(proxy_type, None);
+            proxy.socket = Some(socket.clone());
+            let pool = Arc::new(ConnectionPool::new(resolve_pool_config(
+                &PoolConfig::default(),
+                &proxy,
+            )));
+
+            let conn = connect_local(&addr, &pool, 1).await.unwrap();
+            assert_eq!(conn.pooled, proxy_type == ProxyType::Http2);
+            assert!(!conn.stream.nodelay().unwrap());
+            let sock = socket2::SockRef::from(&conn.stream);
+            let factor = if cfg!(target_os = "linux") { 2 } else { 1 };
+            assert_eq!(sock.send_buffer_size().unwrap(), 8 * 1024 * factor);
+            assert!(sock.keepalive().unwrap());
+        }
+
+        // 未配置时默认启用 TCP_NODELAY
+        let proxy = make_proxy(ProxyType::Tcp, None);
+        let pool = Arc::new(ConnectionPool::new(resolve_pool_config(
+            &PoolConfig::default(),
+            &proxy,
+        )));
+        let conn = connect_local(&addr, &pool, 1).await.unwrap();
+        assert!(conn.stream.nodelay().unwrap());
+    }
+
+    #[test]
+    fn test_targets_round_robin_skips_failed() {
+        let targets = LocalTargets::new(vec!["a:1".into(), "b:1".into(), "c:1".into()]);
+        let firsts: Vec<_> = (0..4).map(|_| targets.attempt_order()[0]).collect();
+        assert_eq!(firsts, vec![0, 1, 2, 0]);
+
+        // 最近失败的目标排在最后，连接成功后恢复
+        targets.mark_failed(2);
+        assert_eq!(targets.attempt_order(), vec![1, 0, 2]);
+        assert_eq!(targets.attempt_order(), vec![0, 1, 2]);
+        assert_eq!(targets.attempt_order(), vec![0, 1, 2]);
+        targets.mark_healthy(2);
+        assert_eq!(targets.attempt_order(), vec![1, 2, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_connect_targets_falls_back_to_next_target() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live = listener.local_addr().unwrap().to_string();
+        let dead = {
+            let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            closed.local_addr().unwrap().to_string()
+        };
+        tokio::spawn(async move {
+            let mut accepted = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                accepted.push(stream);
+            }
+        });
+
+        let mut proxy = make_proxy(ProxyType::Tcp, None);
+        proxy.local_targets = Some(vec![dead.clone(), live.clone()]);
+        let pool = Arc::new(ConnectionPool::new(resolve_pool_config(
+            &PoolConfig::default(),
+            &proxy,
+        )));
+        let tracker = ClientStatsTracker::new(
+            "test".to_string(),
+            ProxyType::Tcp,
+            "127.0.0.1".to_string(),
+            8080,
+            "server".to_string(),
+            9000,
+        )
+        .with_targets();
+        let targets = LocalTargets::new(proxy.local_addrs());
+
+        // 第一个目标不可用时在同一次连接中改用下一个目标，之后优先选择可用的目标
+        for _ in 0..3 {
+            let conn = connect_targets(&targets, &pool, Some(&tracker))
+                .await
+                .unwrap();
+            assert_eq!(conn.addr, live);
+        }
+        let stats = tracker.snapshot().targets.unwrap();
+        assert_eq!(stats[&dead].failures, 1);
+        assert!(!stats.contains_key(&live));
+
+        // 全部不可用时返回错误
+        let targets = LocalTargets::new(vec![dead.clone()]);
+        assert!(connect_targets(&targets, &pool, None).await.is_err());
     }
 }
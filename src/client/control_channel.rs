@@ -3,9 +3,11 @@
 /// 提供控制流的读写操作，但不独立运行，而是集成到主事件循环中
 use crate::config::ClientFullConfig;
 use crate::control_protocol::*;
-use anyhow::{Context, Result};
+use crate::identity::{self, ClientIdentity};
+use crate::protocol_trace::{SessionTrace, TraceDirection};
+use anyhow::Result;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::{mpsc, oneshot, RwLock};
@@ -17,25 +19,89 @@ use yamux::Stream as YamuxStream;
 #[derive(Debug, Clone)]
 pub enum ControlEvent {
     /// 认证成功
-    AuthenticationSuccess { client_id: String },
+    AuthenticationSuccess {
+        client_id: String,
+        /// 服务器声明的协议能力
+        capabilities: Vec<String>,
+    },
 
     /// 认证失败
     AuthenticationFailed { reason: String },
 
-    /// 配置已接受
-    ConfigAccepted,
+    /// 收到身份挑战（`proof` 为对随机数的签名，服务器不支持客户端身份时为空），
+    /// 事件循环随后发送认证请求
+    IdentityChallenged { proof: Option<IdentityProof> },
 
-    /// 配置被部分拒绝
-    ConfigPartiallyRejected { rejected_proxies: Vec<String> },
+    /// 配置已接受（assigned_ports 为服务器分配了发布端口的代理）
+    ConfigAccepted { assigned_ports: Vec<AssignedPort> },
+
+    /// 配置被部分拒绝（reasons 为服务器给出的拒绝原因，entries 为区分代理和 visitor 的
+    /// 结构化条目，旧版本服务器不发送时为空）
+    ConfigPartiallyRejected {
+        rejected_proxies: Vec<String>,
+        reasons: BTreeMap<String, String>,
+        entries: Vec<RejectedEntry>,
+        assigned_ports: Vec<AssignedPort>,
+    },
 
     /// 配置完全被拒绝
-    ConfigRejected { rejected_proxies: Vec<String> },
+    ConfigRejected {
+        rejected_proxies: Vec<String>,
+        reasons: BTreeMap<String, String>,
+    },
+
+    /// 增量配置更新的结果（rejected_proxies 为仍被拒绝的代理）
+    ConfigUpdated {
+        origin: UpdateOrigin,
+        rejected_proxies: Vec<String>,
+        reasons: BTreeMap<String, String>,
+        assigned_ports: Vec<AssignedPort>,
+    },
+
+    /// 增量配置更新失败（超时或服务器返回错误）
+    ConfigUpdateFailed {
+        origin: UpdateOrigin,
+        reason: String,
+    },
+
+    /// 主动下线代理的结果（rejected_proxies 为未由本会话注册的代理）
+    ProxiesRemoved {
+        rejected_proxies: Vec<String>,
+        reasons: BTreeMap<String, String>,
+    },
+
+    /// 主动下线代理失败（超时或服务器返回错误）
+    ProxyRemovalFailed { reason: String },
+
+    /// 配置校验（dry-run）的结果（rejected_proxies 包含被拒绝的代理和 visitor）
+    ConfigValidated {
+        rejected_proxies: Vec<String>,
+        reasons: BTreeMap<String, String>,
+    },
+
+    /// 配置校验失败（超时或服务器返回错误）
+    ConfigValidationFailed { reason: String },
+
+    /// 服务器确认了心跳（回显的心跳参数）
+    HeartbeatAcked(HeartbeatParams),
+
+    /// 代理空闲超过服务器的过期时间被注销
+    ProxyExpired(ProxyExpiredData),
 
     /// 连接关闭
     #[allow(dead_code)]
     ConnectionClosed,
 }
 
+/// 增量配置更新的来源
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateOrigin {
+    /// 重试被拒绝的代理
+    Retry,
+    /// 配置热重载新增的代理（`name:port`）
+    Reload { proxies: Vec<String> },
+}
+
 /// 客户端控制通道
 pub struct ClientControlChannel {
     /// 配置
@@ -49,6 +115,15 @@ pub struct ClientControlChannel {
 
     /// 待处理的请求（用于匹配响应）
     pending_requests: Arc<RwLock<HashMap<u64, oneshot::Sender<JsonRpcResponse>>>>,
+
+    /// 协议跟踪（未启用时为空操作）
+    trace: SessionTrace,
+
+    /// 控制帧读取缓冲（读取可在 select! 中安全取消）
+    frames: FrameReader,
+
+    /// 客户端身份（认证时对服务器下发的随机数签名）
+    identity: Option<Arc<ClientIdentity>>,
 }
 
 impl ClientControlChannel {
@@ -61,62 +136,92 @@ impl ClientControlChannel {
             event_tx,
             request_id: Arc::new(AtomicU64::new(1)),
             pending_requests: Arc::new(RwLock::new(HashMap::new())),
+            trace: SessionTrace::default(),
+            frames: FrameReader::new(),
+            identity: None,
         };
 
         (channel, event_rx)
     }
 
+    /// 设置协议跟踪，收发的控制帧都会记录到跟踪文件
+    pub fn with_trace(mut self, trace: SessionTrace) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// 设置客户端身份，认证前先向服务器请求挑战随机数并签名
+    pub fn with_identity(mut self, identity: Arc<ClientIdentity>) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    /// 写入一条控制消息并记录到协议跟踪
+    async fn write_message<S>(&self, stream: &mut S, data: &[u8]) -> Result<()>
+    where
+        S: futures::AsyncWrite + Unpin,
+    {
+        self.trace.control(TraceDirection::Out, data);
+        write_frame(stream, data).await?;
+        Ok(())
+    }
+
     /// 从控制流读取一条消息
     /// 自动处理响应消息，返回请求/通知消息
-    pub async fn read_message(
-        &mut self,
-        stream: &mut YamuxStream,
-    ) -> Result<Option<JsonRpcRequest>> {
-        use futures::AsyncReadExt;
-
+    ///
+    /// 单条消息无法解析时回复 JSON-RPC 错误并继续读取下一条；只有超长消息、
+    /// 帧不同步和 I/O 错误会返回 Err（结束会话），此时尽量先把原因告知服务器
+    pub async fn read_message<S>(&mut self, stream: &mut S) -> Result<Option<JsonRpcRequest>>
+    where
+        S: futures::AsyncRead + futures::AsyncWrite + Unpin,
+    {
         loop {
-            // 读取长度前缀
-            let mut len_buf = [0u8; 4];
-            match stream.read_exact(&mut len_buf).await {
-                Ok(_) => {}
-                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-                    return Ok(None);
+            let frame = match self.frames.read_frame(stream).await {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return Ok(None),
+                Err(e) => {
+                    if !matches!(e, ControlFrameError::Io(_)) {
+                        let _ = self
+                            .send_error_response(stream, Value::Null, e.to_rpc_error())
+                            .await;
+                    }
+                    return Err(e.into());
                 }
-                Err(e) => return Err(e.into()),
-            }
-
-            let msg_len = u32::from_be_bytes(len_buf) as usize;
-
-            if msg_len > 10 * 1024 * 1024 {
-                return Err(anyhow::anyhow!("Message too large: {} bytes", msg_len));
-            }
+            };
+            self.trace.control(TraceDirection::In, &frame);
 
-            // 读取消息内容
-            let mut msg_buf = vec![0u8; msg_len];
-            stream.read_exact(&mut msg_buf).await?;
-
-            // 先尝试解析为响应
-            if let Ok(response) = serde_json::from_slice::<JsonRpcResponse>(&msg_buf) {
-                self.handle_response(response).await?;
-                continue; // 继续读取下一条消息
+            match parse_message(&frame) {
+                ControlMessage::Response(response) => {
+                    self.handle_response(response).await?;
+                }
+                ControlMessage::Request(request) => return Ok(Some(request)),
+                ControlMessage::Malformed { id, error } => {
+                    warn!("Ignoring malformed control message: {}", error.message);
+                    self.send_error_response(stream, id, error).await?;
+                }
             }
-
-            // 否则解析为请求（通知）
-            let request: JsonRpcRequest =
-                serde_json::from_slice(&msg_buf).context("Failed to parse JSON-RPC message")?;
-
-            return Ok(Some(request));
         }
     }
 
     /// 处理响应消息
     async fn handle_response(&mut self, response: JsonRpcResponse) -> Result<()> {
         // 从 Value 中提取 u64
-        let request_id = if let Value::Number(n) = &response.id {
-            n.as_u64().unwrap_or(0)
-        } else {
-            warn!("Invalid response ID type: {:?}", response.id);
-            return Ok(());
+        let request_id = match &response.id {
+            Value::Number(n) => n.as_u64().unwrap_or(0),
+            // 服务器回报的无法关联到请求的错误（例如无法解析的消息）
+            Value::Null => {
+                if let Some(error) = response.error {
+                    warn!(
+                        "Server reported control error ({}): {}",
+                        error.code, error.message
+                    );
+                }
+                return Ok(());
+            }
+            _ => {
+                warn!("Invalid response ID type: {:?}", response.id);
+                return Ok(());
+            }
         };
 
         // 查找并移除待处理的请求
@@ -149,15 +254,27 @@ impl ClientControlChannel {
             }
             "push_exception" => {
                 // 解析异常通知
-                if let Ok(exception) =
-                    serde_json::from_value::<ExceptionNotification>(request.params.clone())
-                {
+                if let Ok(exception) = request.decode_params::<ExceptionNotification>() {
+                    if exception.code.as_deref() == Some(EXCEPTION_PROXY_EXPIRED_IDLE) {
+                        match exception.decode_data::<ProxyExpiredData>() {
+                            Some(Ok(expired)) => {
+                                let _ = self.event_tx.send(ControlEvent::ProxyExpired(expired));
+                            }
+                            _ => warn!("Proxy expiry notification without valid data"),
+                        }
+                    }
+                    // 服务器合并的重复通知附带次数
+                    let repeated = exception
+                        .occurrences
+                        .map(|n| format!(" (x{})", n))
+                        .unwrap_or_default();
                     match exception.level.as_str() {
                         "error" => {
                             error!(
-                                "Server exception: {} {}",
+                                "Server exception: {} {}{}",
                                 exception.code.as_deref().unwrap_or(""),
-                                exception.message
+                                exception.message,
+                                repeated
                             );
                             if let Some(data) = exception.data {
                                 error!("Exception data: {}", data);
@@ -165,9 +282,10 @@ impl ClientControlChannel {
                         }
                         "warning" => {
                             warn!(
-                                "Server warning: {} {}",
+                                "Server warning: {} {}{}",
                                 exception.code.as_deref().unwrap_or(""),
-                                exception.message
+                                exception.message,
+                                repeated
                             );
                             if let Some(data) = exception.data {
                                 warn!("Warning data: {}", data);
@@ -175,9 +293,10 @@ impl ClientControlChannel {
                         }
                         _ => {
                             info!(
-                                "Server notification: {} {}",
+                                "Server notification: {} {}{}",
                                 exception.code.as_deref().unwrap_or(""),
-                                exception.message
+                                exception.message,
+                                repeated
                             );
                             if let Some(data) = exception.data {
                                 info!("Notification data: {}", data);
@@ -199,30 +318,109 @@ impl ClientControlChannel {
         Ok(())
     }
 
-    /// 发送认证请求
+    /// 请求服务器下发身份挑战随机数
+    ///
+    /// 收到随机数后以 [`ControlEvent::IdentityChallenged`] 携带签名通知事件循环；
+    /// 服务器不支持客户端身份时通知中不带签名，随后不带身份认证
+    async fn send_identity_challenge(
+        &mut self,
+        stream: &mut YamuxStream,
+        identity: Arc<ClientIdentity>,
+    ) -> Result<()> {
+        let request_id = self.request_id.fetch_add(1, Ordering::SeqCst);
+        let request = JsonRpcRequest::call(
+            ControlMethod::IdentityChallenge,
+            &serde_json::Map::new(),
+            request_id,
+        )?;
+
+        // 注册待处理的请求
+        let (response_tx, response_rx) = oneshot::channel();
+        self.pending_requests
+            .write()
+            .await
+            .insert(request_id, response_tx);
+
+        self.write_message(stream, &serde_json::to_vec(&request)?)
+            .await?;
+
+        debug!("Sent identity challenge request");
+
+        // 等待响应
+        tokio::spawn({
+            let event_tx = self.event_tx.clone();
+            let pending = self.pending_requests.clone();
+            async move {
+                let event = match tokio::time::timeout(Duration::from_secs(10), response_rx).await {
+                    Ok(Ok(response)) => {
+                        let nonce = response
+                            .result
+                            .and_then(|result| {
+                                serde_json::from_value::<IdentityChallengeResult>(result).ok()
+                            })
+                            .and_then(|challenge| identity::from_hex(&challenge.nonce).ok());
+                        match nonce {
+                            Some(nonce) => ControlEvent::IdentityChallenged {
+                                proof: Some(identity.prove(&nonce)),
+                            },
+                            None => {
+                                warn!(
+                                    "Server does not support client identity, authenticating without identity {}",
+                                    identity.short_fingerprint()
+                                );
+                                ControlEvent::IdentityChallenged { proof: None }
+                            }
+                        }
+                    }
+                    Ok(Err(_)) => ControlEvent::AuthenticationFailed {
+                        reason: "Response channel closed".to_string(),
+                    },
+                    Err(_) => {
+                        pending.write().await.remove(&request_id);
+                        ControlEvent::AuthenticationFailed {
+                            reason: "Timeout waiting for identity challenge".to_string(),
+                        }
+                    }
+                };
+                let _ = event_tx.send(event);
+            }
+        });
+
+        Ok(())
+    }
+
+    /// 开始认证
+    ///
+    /// 设置了客户端身份时先发送 `identity_challenge` 请求，收到
+    /// [`ControlEvent::IdentityChallenged`] 后再调用 [`Self::send_authenticate_with`]；
+    /// 否则直接发送认证请求
     pub async fn send_authenticate(&mut self, stream: &mut YamuxStream) -> Result<()> {
-        use futures::AsyncWriteExt;
+        match self.identity.clone() {
+            Some(identity) => self.send_identity_challenge(stream, identity).await,
+            None => self.send_authenticate_with(stream, None).await,
+        }
+    }
 
+    /// 发送认证请求（`identity` 为对身份挑战随机数的签名）
+    pub async fn send_authenticate_with(
+        &mut self,
+        stream: &mut YamuxStream,
+        identity: Option<IdentityProof>,
+    ) -> Result<()> {
         let request_id = self.request_id.fetch_add(1, Ordering::SeqCst);
 
         let params = AuthenticateParams {
             auth_key: self.config.client.auth_key.clone(),
             protocol_version: env!("CARGO_PKG_VERSION").to_string(),
+            capabilities: supported_capabilities(),
+            peer_id: self.config.client.peer_id.clone(),
+            identity,
         };
 
-        let request = JsonRpcRequest {
-            jsonrpc: "2.0".to_string(),
-            method: "authenticate".to_string(),
-            params: serde_json::to_value(params)?,
-            id: Some(Value::Number(request_id.into())),
-        };
-
-        let data = serde_json::to_vec(&request)?;
-        let len = data.len() as u32;
+        let request = JsonRpcRequest::call(ControlMethod::Authenticate, &params, request_id)?;
 
-        stream.write_all(&len.to_be_bytes()).await?;
-        stream.write_all(&data).await?;
-        stream.flush().await?;
+        self.write_message(stream, &serde_json::to_vec(&request)?)
+            .await?;
 
         debug!("Sent authentication request");
 
@@ -254,6 +452,7 @@ impl ClientControlChannel {
 
                                 let _ = event_tx.send(ControlEvent::AuthenticationSuccess {
                                     client_id: auth_result.client_id,
+                                    capabilities: auth_result.capabilities,
                                 });
                             }
                         } else if let Some(error) = response.error {
@@ -281,29 +480,36 @@ impl ClientControlChannel {
     }
 
     /// 发送配置提交请求
-    pub async fn send_submit_config(&mut self, stream: &mut YamuxStream) -> Result<()> {
-        use futures::AsyncWriteExt;
-
+    ///
+    /// 服务器不支持私有代理时不提交私有代理（避免旧版本服务器为其绑定公开端口）；
+    /// 服务器不支持 SNI 路由时不提交 SNI 路由代理（旧版本服务器不会发送选中的本地端口）；
+    /// 服务器不支持分配发布端口时不提交 publish_port 为 0 的代理（旧版本服务器会拒绝整个配置）
+    pub async fn send_submit_config(
+        &mut self,
+        stream: &mut YamuxStream,
+        private_proxies: bool,
+        sni_routing: bool,
+        auto_publish_port: bool,
+    ) -> Result<()> {
         let request_id = self.request_id.fetch_add(1, Ordering::SeqCst);
 
         let params = SubmitConfigParams {
-            proxies: self.config.proxies.clone(),
+            proxies: self
+                .config
+                .proxies
+                .iter()
+                .filter(|p| private_proxies || p.visibility.is_public())
+                .filter(|p| sni_routing || p.sni_routing.is_none())
+                .filter(|p| auto_publish_port || p.publish_port != 0)
+                .map(|p| p.for_server())
+                .collect(),
             visitors: self.config.visitors.clone(),
         };
 
-        let request = JsonRpcRequest {
-            jsonrpc: "2.0".to_string(),
-            method: "submit_config".to_string(),
-            params: serde_json::to_value(params)?,
-            id: Some(Value::Number(request_id.into())),
-        };
+        let request = JsonRpcRequest::call(ControlMethod::SubmitConfig, &params, request_id)?;
 
-        let data = serde_json::to_vec(&request)?;
-        let len = data.len() as u32;
-
-        stream.write_all(&len.to_be_bytes()).await?;
-        stream.write_all(&data).await?;
-        stream.flush().await?;
+        self.write_message(stream, &serde_json::to_vec(&request)?)
+            .await?;
 
         debug!("Sent config submission request");
 
@@ -326,36 +532,38 @@ impl ClientControlChannel {
                                 serde_json::from_value::<SubmitConfigResult>(result)
                             {
                                 if config_result.rejected_proxies.is_empty() {
-                                    let _ = event_tx.send(ControlEvent::ConfigAccepted);
+                                    let _ = event_tx.send(ControlEvent::ConfigAccepted {
+                                        assigned_ports: config_result.assigned_ports,
+                                    });
                                 } else {
                                     let _ = event_tx.send(ControlEvent::ConfigPartiallyRejected {
                                         rejected_proxies: config_result.rejected_proxies,
+                                        reasons: config_result.reasons,
+                                        entries: config_result.rejected_entries,
+                                        assigned_ports: config_result.assigned_ports,
                                     });
                                 }
                             }
                         } else if let Some(error) = response.error {
-                            // 从错误数据中提取 rejected_proxies
-                            let rejected_proxies = if let Some(data) = error.data {
-                                if let Ok(rejected) = serde_json::from_value::<Vec<String>>(
-                                    data.get("rejected_proxies")
-                                        .cloned()
-                                        .unwrap_or(Value::Array(vec![])),
-                                ) {
-                                    rejected
-                                } else {
-                                    vec![error.message.clone()]
-                                }
-                            } else {
-                                vec![error.message.clone()]
+                            // 从错误数据中提取 rejected_proxies 和拒绝原因
+                            let data = match error.decode_data::<ConfigRejectedData>() {
+                                Some(Ok(data)) => data,
+                                _ => ConfigRejectedData {
+                                    rejected_proxies: vec![error.message.clone()],
+                                    reasons: BTreeMap::new(),
+                                },
                             };
 
-                            let _ =
-                                event_tx.send(ControlEvent::ConfigRejected { rejected_proxies });
+                            let _ = event_tx.send(ControlEvent::ConfigRejected {
+                                rejected_proxies: data.rejected_proxies,
+                                reasons: data.reasons,
+                            });
                         }
                     }
                     Ok(Err(_)) => {
                         let _ = event_tx.send(ControlEvent::ConfigRejected {
                             rejected_proxies: vec!["Response channel closed".to_string()],
+                            reasons: BTreeMap::new(),
                         });
                     }
                     Err(_) => {
@@ -363,6 +571,7 @@ impl ClientControlChannel {
                             rejected_proxies: vec![
                                 "Timeout waiting for config response".to_string()
                             ],
+                            reasons: BTreeMap::new(),
                         });
                         pending.write().await.remove(&request_id);
                     }
@@ -373,24 +582,352 @@ impl ClientControlChannel {
         Ok(())
     }
 
-    /// 发送心跳通知
-    pub async fn send_heartbeat(&mut self, stream: &mut YamuxStream) -> Result<()> {
-        use futures::AsyncWriteExt;
+    /// 发送增量配置更新请求（只包含需要追加注册的代理）
+    pub async fn send_update_config(
+        &mut self,
+        stream: &mut YamuxStream,
+        proxies: Vec<crate::config::ProxyConfig>,
+        origin: UpdateOrigin,
+    ) -> Result<()> {
+        let params = UpdateConfigParams {
+            proxies: proxies.iter().map(|p| p.for_server()).collect(),
+        };
+        self.send_proxy_request(
+            stream,
+            ControlMethod::UpdateConfig,
+            &params,
+            move |result| match result {
+                Ok(result) => ControlEvent::ConfigUpdated {
+                    origin,
+                    rejected_proxies: result.rejected_proxies,
+                    reasons: result.reasons,
+                    assigned_ports: result.assigned_ports,
+                },
+                Err(reason) => ControlEvent::ConfigUpdateFailed { origin, reason },
+            },
+        )
+        .await?;
+        debug!("Sent config update request");
+        Ok(())
+    }
 
-        let request = JsonRpcRequest {
-            jsonrpc: "2.0".to_string(),
-            method: "heartbeat".to_string(),
-            params: Value::Null,
-            id: None, // 通知，无需响应
+    /// 发送主动下线代理请求
+    pub async fn send_remove_proxies(
+        &mut self,
+        stream: &mut YamuxStream,
+        proxies: Vec<ProxyRef>,
+    ) -> Result<()> {
+        let params = RemoveProxiesParams { proxies };
+        self.send_proxy_request(
+            stream,
+            ControlMethod::RemoveProxies,
+            &params,
+            |result| match result {
+                Ok(result) => ControlEvent::ProxiesRemoved {
+                    rejected_proxies: result.rejected_proxies,
+                    reasons: result.reasons,
+                },
+                Err(reason) => ControlEvent::ProxyRemovalFailed { reason },
+            },
+        )
+        .await?;
+        debug!("Sent proxy removal request");
+        Ok(())
+    }
+
+    /// 发送结果为 [`SubmitConfigResult`] 的请求（`update_config`、`remove_proxies`），
+    /// 收到响应、超时或服务器返回错误后由 `event` 生成控制事件
+    async fn send_proxy_request<P, F>(
+        &mut self,
+        stream: &mut YamuxStream,
+        method: ControlMethod,
+        params: &P,
+        event: F,
+    ) -> Result<()>
+    where
+        P: serde::Serialize,
+        F: FnOnce(std::result::Result<SubmitConfigResult, String>) -> ControlEvent + Send + 'static,
+    {
+        let request_id = self.request_id.fetch_add(1, Ordering::SeqCst);
+        let request = JsonRpcRequest::call(method, params, request_id)?;
+
+        // 先注册待处理的请求，避免响应先于注册到达
+        let (response_tx, response_rx) = oneshot::channel();
+        self.pending_requests
+            .write()
+            .await
+            .insert(request_id, response_tx);
+
+        if let Err(e) = self
+            .write_message(stream, &serde_json::to_vec(&request)?)
+            .await
+        {
+            self.pending_requests.write().await.remove(&request_id);
+            return Err(e);
+        }
+
+        // 等待响应
+        tokio::spawn({
+            let event_tx = self.event_tx.clone();
+            let pending = self.pending_requests.clone();
+            async move {
+                let result = match tokio::time::timeout(Duration::from_secs(10), response_rx).await
+                {
+                    Ok(Ok(response)) => match (response.result, response.error) {
+                        (Some(result), _) => serde_json::from_value::<SubmitConfigResult>(result)
+                            .map_err(|e| format!("Invalid {} result: {}", method.as_str(), e)),
+                        (None, Some(error)) => Err(error.message),
+                        (None, None) => Err(format!("Empty {} response", method.as_str())),
+                    },
+                    Ok(Err(_)) => Err("Response channel closed".to_string()),
+                    Err(_) => {
+                        pending.write().await.remove(&request_id);
+                        Err(format!("Timeout waiting for {} response", method.as_str()))
+                    }
+                };
+                let _ = event_tx.send(event(result));
+            }
+        });
+
+        Ok(())
+    }
+
+    /// 发送配置校验请求（参数与 submit_config 相同，服务器只校验不注册）
+    pub async fn send_validate_config(&mut self, stream: &mut YamuxStream) -> Result<()> {
+        let request_id = self.request_id.fetch_add(1, Ordering::SeqCst);
+        let params = SubmitConfigParams {
+            proxies: self.config.proxies.iter().map(|p| p.for_server()).collect(),
+            visitors: self.config.visitors.clone(),
         };
+        let request = JsonRpcRequest::call(ControlMethod::ValidateConfig, &params, request_id)?;
 
-        let data = serde_json::to_vec(&request)?;
-        let len = data.len() as u32;
+        // 先注册待处理的请求，避免响应先于注册到达
+        let (response_tx, response_rx) = oneshot::channel();
+        self.pending_requests
+            .write()
+            .await
+            .insert(request_id, response_tx);
+
+        if let Err(e) = self
+            .write_message(stream, &serde_json::to_vec(&request)?)
+            .await
+        {
+            self.pending_requests.write().await.remove(&request_id);
+            return Err(e);
+        }
 
-        stream.write_all(&len.to_be_bytes()).await?;
-        stream.write_all(&data).await?;
-        stream.flush().await?;
+        debug!("Sent config validation request");
+
+        // 等待响应
+        tokio::spawn({
+            let event_tx = self.event_tx.clone();
+            let pending = self.pending_requests.clone();
+            async move {
+                let event = match tokio::time::timeout(Duration::from_secs(10), response_rx).await {
+                    Ok(Ok(response)) => match (response.result, response.error) {
+                        (Some(result), _) => {
+                            match serde_json::from_value::<SubmitConfigResult>(result) {
+                                Ok(result) if result.dry_run => ControlEvent::ConfigValidated {
+                                    rejected_proxies: result.rejected_proxies,
+                                    reasons: result.reasons,
+                                },
+                                Ok(_) => ControlEvent::ConfigValidationFailed {
+                                    reason: "Server result is not marked as dry-run".to_string(),
+                                },
+                                Err(e) => ControlEvent::ConfigValidationFailed {
+                                    reason: format!("Invalid config validation result: {}", e),
+                                },
+                            }
+                        }
+                        (None, Some(error)) => ControlEvent::ConfigValidationFailed {
+                            reason: error.message,
+                        },
+                        (None, None) => ControlEvent::ConfigValidationFailed {
+                            reason: "Empty config validation response".to_string(),
+                        },
+                    },
+                    Ok(Err(_)) => ControlEvent::ConfigValidationFailed {
+                        reason: "Response channel closed".to_string(),
+                    },
+                    Err(_) => {
+                        pending.write().await.remove(&request_id);
+                        ControlEvent::ConfigValidationFailed {
+                            reason: "Timeout waiting for config validation response".to_string(),
+                        }
+                    }
+                };
+                let _ = event_tx.send(event);
+            }
+        });
 
         Ok(())
     }
+
+    /// 发送直连 forwarder 连接的报告（通知，无需响应）
+    pub async fn send_forward_report(
+        &mut self,
+        stream: &mut YamuxStream,
+        params: ForwardReportParams,
+    ) -> Result<()> {
+        let request = JsonRpcRequest::notify(ControlMethod::ForwardReport, &params)?;
+        self.write_message(stream, &serde_json::to_vec(&request)?)
+            .await
+    }
+
+    /// 发送心跳
+    ///
+    /// `ack` 为空时作为通知发送（服务器不支持心跳确认）；否则作为请求发送，
+    /// 在下一次心跳之前收到的确认以 [`ControlEvent::HeartbeatAcked`] 通知事件循环
+    pub async fn send_heartbeat(
+        &mut self,
+        stream: &mut YamuxStream,
+        ack: Option<HeartbeatParams>,
+    ) -> Result<()> {
+        let Some(params) = ack else {
+            // 通知，无需响应
+            let request = JsonRpcRequest::notify(ControlMethod::Heartbeat, &())?;
+            return self
+                .write_message(stream, &serde_json::to_vec(&request)?)
+                .await;
+        };
+
+        let request_id = self.request_id.fetch_add(1, Ordering::SeqCst);
+        let request = JsonRpcRequest::call(ControlMethod::Heartbeat, &params, request_id)?;
+
+        // 先注册待处理的请求，避免响应先于注册到达
+        let (response_tx, response_rx) = oneshot::channel();
+        self.pending_requests
+            .write()
+            .await
+            .insert(request_id, response_tx);
+
+        if let Err(e) = self
+            .write_message(stream, &serde_json::to_vec(&request)?)
+            .await
+        {
+            self.pending_requests.write().await.remove(&request_id);
+            return Err(e);
+        }
+
+        // 等待确认（超时的心跳由事件循环在发送下一次心跳时计为丢失）
+        tokio::spawn({
+            let event_tx = self.event_tx.clone();
+            let pending = self.pending_requests.clone();
+            let ack_timeout = self.config.client.timeouts().heartbeat_interval();
+            async move {
+                match tokio::time::timeout(ack_timeout, response_rx).await {
+                    Ok(Ok(response)) => {
+                        match response
+                            .result
+                            .map(serde_json::from_value::<HeartbeatParams>)
+                        {
+                            Some(Ok(ack)) => {
+                                let _ = event_tx.send(ControlEvent::HeartbeatAcked(ack));
+                            }
+                            _ => warn!("Invalid heartbeat ack for request {}", request_id),
+                        }
+                    }
+                    Ok(Err(_)) => {}
+                    Err(_) => {
+                        pending.write().await.remove(&request_id);
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// 向服务器回复 JSON-RPC 错误
+    async fn send_error_response<S>(
+        &self,
+        stream: &mut S,
+        id: Value,
+        error: JsonRpcError,
+    ) -> Result<()>
+    where
+        S: futures::AsyncWrite + Unpin,
+    {
+        let response = JsonRpcResponse::error(id, error);
+        self.write_message(stream, &serde_json::to_vec(&response)?)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::AsyncWriteExt;
+    use tokio_util::compat::TokioAsyncReadCompatExt;
+
+    fn test_channel() -> ClientControlChannel {
+        let client = crate::config::ClientConfig::builder()
+            .server_addr("127.0.0.1")
+            .server_port(8443)
+            .auth_key("1234567890123456")
+            .build()
+            .unwrap();
+        let config = ClientFullConfig {
+            client,
+            proxies: vec![],
+            visitors: vec![],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        };
+        ClientControlChannel::new(config).0
+    }
+
+    fn notification(message: &str) -> Vec<u8> {
+        serde_json::to_vec(&JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "push_exception".to_string(),
+            params: serde_json::json!({ "level": "info", "message": message }),
+            id: None,
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_malformed_frame_does_not_end_session() {
+        let mut channel = test_channel();
+        let (local, remote) = tokio::io::duplex(64 * 1024);
+        let (mut local, mut remote) = (local.compat(), remote.compat());
+
+        write_frame(&mut remote, &notification("first"))
+            .await
+            .unwrap();
+        write_frame(&mut remote, b"\xff\xfe").await.unwrap();
+        write_frame(&mut remote, &notification("second"))
+            .await
+            .unwrap();
+
+        let first = channel.read_message(&mut local).await.unwrap().unwrap();
+        assert_eq!(first.params["message"], "first");
+        let second = channel.read_message(&mut local).await.unwrap().unwrap();
+        assert_eq!(second.params["message"], "second");
+
+        // 服务器收到 parse error 响应
+        let frame = read_frame(&mut remote).await.unwrap().unwrap();
+        let response: JsonRpcResponse = serde_json::from_slice(&frame).unwrap();
+        assert_eq!(response.error.unwrap().code, PARSE_ERROR);
+
+        drop(remote);
+        assert!(channel.read_message(&mut local).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_truncated_frame_ends_session_with_reason() {
+        let mut channel = test_channel();
+        let (local, remote) = tokio::io::duplex(64 * 1024);
+        let (mut local, mut remote) = (local.compat(), remote.compat());
+
+        // 声明 100 字节但只发送 3 字节后关闭写端
+        remote.write_all(&100u32.to_be_bytes()).await.unwrap();
+        remote.write_all(b"{\"a").await.unwrap();
+        remote.close().await.unwrap();
+
+        let err = channel.read_message(&mut local).await.unwrap_err();
+        assert!(err.to_string().contains("framing desync"));
+    }
 }
@@ -0,0 +1,142 @@
+/// Forwarder 直连出口
+///
+/// 按 [`DirectEgressConfig`] 创建直连套接字：设置 SO_MARK、绑定网卡（SO_BINDTODEVICE）
+/// 或在连接前绑定源地址，配合策略路由让直连流量从指定上行链路离开
+use crate::config::DirectEgressConfig;
+use crate::target_addr::TargetAddr;
+use std::io;
+use std::net::SocketAddr;
+use tokio::net::{lookup_host, TcpSocket, TcpStream};
+
+/// 按出口设置连接目标，依次尝试解析出的地址（跳过与源地址协议族不同的地址）
+pub async fn connect(target: &TargetAddr, egress: &DirectEgressConfig) -> io::Result<TcpStream> {
+    let addrs: Vec<SocketAddr> = match target {
+        TargetAddr::Ip(addr) => vec![*addr],
+        TargetAddr::Domain(domain, port) => lookup_host((domain.as_str(), *port)).await?.collect(),
+    };
+
+    let mut last_error = None;
+    for addr in addrs {
+        if egress
+            .bind_address
+            .is_some_and(|source| source.is_ipv4() != addr.is_ipv4())
+        {
+            continue;
+        }
+        let result = async {
+            let socket = egress_socket(addr, egress)?;
+            socket.connect(addr).await
+        }
+        .await;
+        match result {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            format!("No address of {} matches the egress source address", target),
+        )
+    }))
+}
+
+/// 创建连接 `addr` 用的套接字并应用出口设置
+pub fn egress_socket(addr: SocketAddr, egress: &DirectEgressConfig) -> io::Result<TcpSocket> {
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    apply_socket_options(&socket, egress)?;
+    if let Some(source) = egress.bind_address {
+        socket.bind(SocketAddr::new(source, 0))?;
+    }
+    Ok(socket)
+}
+
+#[cfg(target_os = "linux")]
+fn apply_socket_options(socket: &TcpSocket, egress: &DirectEgressConfig) -> io::Result<()> {
+    let sock = socket2::SockRef::from(socket);
+    if egress.fwmark != 0 {
+        sock.set_mark(egress.fwmark)?;
+    }
+    if let Some(ref interface) = egress.bind_interface {
+        sock.bind_device(Some(interface.as_bytes()))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn apply_socket_options(_socket: &TcpSocket, egress: &DirectEgressConfig) -> io::Result<()> {
+    // 配置校验已拒绝这些选项，这里兜底返回明确的错误
+    if egress.fwmark != 0 || egress.bind_interface.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "direct_egress.fwmark and direct_egress.bind_interface are only supported on Linux",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{IpAddr, Ipv4Addr};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_connect_binds_source_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = TargetAddr::Ip(listener.local_addr().unwrap());
+        let egress = DirectEgressConfig {
+            bind_address: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            ..Default::default()
+        };
+
+        let stream = connect(&target, &egress).await.unwrap();
+        assert_eq!(
+            stream.local_addr().unwrap().ip(),
+            IpAddr::V4(Ipv4Addr::LOCALHOST)
+        );
+        let (_, peer) = listener.accept().await.unwrap();
+        assert_eq!(peer, stream.local_addr().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_connect_skips_other_address_family() {
+        let target = TargetAddr::Ip("[::1]:9".parse().unwrap());
+        let egress = DirectEgressConfig {
+            bind_address: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            ..Default::default()
+        };
+        let err = connect(&target, &egress).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrNotAvailable);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_socket_mark_applied() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let _guard = runtime.enter();
+
+        let egress = DirectEgressConfig {
+            fwmark: 0x2a,
+            ..Default::default()
+        };
+        let socket = match egress_socket("127.0.0.1:9".parse().unwrap(), &egress) {
+            Ok(socket) => socket,
+            // 设置 SO_MARK 需要 CAP_NET_ADMIN
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                eprintln!("Skipping SO_MARK check: {}", e);
+                return;
+            }
+            Err(e) => panic!("Failed to create egress socket: {}", e),
+        };
+        assert_eq!(socket2::SockRef::from(&socket).mark().unwrap(), 0x2a);
+    }
+}
@@ -0,0 +1,261 @@
+/// 客户端会话生命周期事件
+///
+/// 事件通过 `tokio::sync::broadcast` 发布，外部监管程序可以订阅以实时感知
+/// 隧道的断开与恢复；也可以配置 `events_socket` 将事件以 JSON 行镜像到本地套接字
+use serde_json::json;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tracing::{debug, warn};
+
+use super::health::HealthStatus;
+
+/// 会话事件广播通道的默认容量
+pub const SESSION_EVENT_CAPACITY: usize = 64;
+
+/// 会话生命周期事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEvent {
+    /// 正在连接服务器
+    Connecting,
+    /// 已连接到服务器的该地址（`server_addr` 解析后的地址）
+    Connected(SocketAddr),
+    /// 认证成功
+    Authenticated,
+    /// 服务器接受了全部配置
+    ConfigAccepted,
+    /// 会话进入运行状态
+    Running,
+    /// 会话仍在运行但功能受损（例如部分代理被拒绝、心跳发送失败）
+    Degraded(String),
+    /// 会话断开
+    Disconnected(String),
+    /// 即将进行第 attempt 次重连（attempt 在会话成功运行后重置）
+    Reconnecting { attempt: u32, delay: Duration },
+    /// 健康评分的总体状态发生变化（`[health] notify = true`）
+    HealthChanged {
+        previous: HealthStatus,
+        current: HealthStatus,
+        score: u32,
+    },
+}
+
+impl SessionEvent {
+    /// 事件名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            SessionEvent::Connecting => "connecting",
+            SessionEvent::Connected(_) => "connected",
+            SessionEvent::Authenticated => "authenticated",
+            SessionEvent::ConfigAccepted => "config_accepted",
+            SessionEvent::Running => "running",
+            SessionEvent::Degraded(_) => "degraded",
+            SessionEvent::Disconnected(_) => "disconnected",
+            SessionEvent::Reconnecting { .. } => "reconnecting",
+            SessionEvent::HealthChanged { .. } => "health_changed",
+        }
+    }
+
+    /// 序列化为单行 JSON（用于镜像到 events_socket）
+    pub fn to_json_line(&self) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut value = json!({
+            "event": self.name(),
+            "timestamp": timestamp,
+        });
+        match self {
+            SessionEvent::Degraded(reason) | SessionEvent::Disconnected(reason) => {
+                value["reason"] = json!(reason);
+            }
+            SessionEvent::Connected(server_addr) => {
+                value["server_addr"] = json!(server_addr.to_string());
+            }
+            SessionEvent::Reconnecting { attempt, delay } => {
+                value["attempt"] = json!(attempt);
+                value["delay_ms"] = json!(delay.as_millis() as u64);
+            }
+            SessionEvent::HealthChanged {
+                previous,
+                current,
+                score,
+            } => {
+                value["previous"] = json!(previous.as_str());
+                value["current"] = json!(current.as_str());
+                value["score"] = json!(score);
+            }
+            _ => {}
+        }
+        let mut line = value.to_string();
+        line.push('\n');
+        line
+    }
+}
+
+/// 发布会话事件（没有订阅者时忽略）
+pub(crate) fn emit(events: &broadcast::Sender<SessionEvent>, event: SessionEvent) {
+    debug!("Session event: {:?}", event);
+    let _ = events.send(event);
+}
+
+/// 将会话事件镜像到 events_socket
+///
+/// 支持 `tcp://host:port`（JSON 行，断开后在下一个事件时重连）
+/// 以及 Unix 平台上的 `unix:///path/to/socket`（Unix datagram，每个事件一个数据报）
+pub(crate) fn spawn_event_mirror(target: String, events: &broadcast::Sender<SessionEvent>) {
+    let mut rx = events.subscribe();
+    tokio::spawn(async move {
+        let mut sink = EventSink::new(&target);
+        loop {
+            match rx.recv().await {
+                Ok(event) => sink.send(event.to_json_line().as_bytes()).await,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("Session event mirror lagged, {} event(s) dropped", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+enum EventSink {
+    Tcp {
+        addr: String,
+        stream: Option<TcpStream>,
+    },
+    #[cfg(unix)]
+    Unix {
+        path: String,
+        socket: Option<tokio::net::UnixDatagram>,
+    },
+    Invalid,
+}
+
+impl EventSink {
+    fn new(target: &str) -> Self {
+        if let Some(addr) = target.strip_prefix("tcp://") {
+            return EventSink::Tcp {
+                addr: addr.to_string(),
+                stream: None,
+            };
+        }
+        #[cfg(unix)]
+        if let Some(path) = target.strip_prefix("unix://") {
+            return EventSink::Unix {
+                path: path.to_string(),
+                socket: None,
+            };
+        }
+        warn!(
+            "Unsupported events_socket '{}', events will not be mirrored",
+            target
+        );
+        EventSink::Invalid
+    }
+
+    async fn send(&mut self, data: &[u8]) {
+        match self {
+            EventSink::Tcp { addr, stream } => {
+                if stream.is_none() {
+                    match TcpStream::connect(addr.as_str()).await {
+                        Ok(s) => *stream = Some(s),
+                        Err(e) => {
+                            debug!("Failed to connect events_socket {}: {}", addr, e);
+                            return;
+                        }
+                    }
+                }
+                if let Some(s) = stream {
+                    if let Err(e) = s.write_all(data).await {
+                        debug!("Failed to write to events_socket {}: {}", addr, e);
+                        *stream = None;
+                    }
+                }
+            }
+            #[cfg(unix)]
+            EventSink::Unix { path, socket } => {
+                if socket.is_none() {
+                    match tokio::net::UnixDatagram::unbound() {
+                        Ok(s) => *socket = Some(s),
+                        Err(e) => {
+                            debug!("Failed to create unix datagram socket: {}", e);
+                            return;
+                        }
+                    }
+                }
+                if let Some(s) = socket {
+                    if let Err(e) = s.send_to(data, path.as_str()).await {
+                        debug!("Failed to send to events_socket {}: {}", path, e);
+                    }
+                }
+            }
+            EventSink::Invalid => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_event_json_line() {
+        let line = SessionEvent::Reconnecting {
+            attempt: 2,
+            delay: Duration::from_secs(5),
+        }
+        .to_json_line();
+        assert!(line.ends_with('\n'));
+        let value: serde_json::Value = serde_json::from_str(line.trim()).unwrap();
+        assert_eq!(value["event"], "reconnecting");
+        assert_eq!(value["attempt"], 2);
+        assert_eq!(value["delay_ms"], 5000);
+
+        let line = SessionEvent::Disconnected("closed".to_string()).to_json_line();
+        let value: serde_json::Value = serde_json::from_str(line.trim()).unwrap();
+        assert_eq!(value["event"], "disconnected");
+        assert_eq!(value["reason"], "closed");
+
+        let line = SessionEvent::Connected("[2001:db8::1]:8443".parse().unwrap()).to_json_line();
+        let value: serde_json::Value = serde_json::from_str(line.trim()).unwrap();
+        assert_eq!(value["event"], "connected");
+        assert_eq!(value["server_addr"], "[2001:db8::1]:8443");
+
+        let line = SessionEvent::HealthChanged {
+            previous: HealthStatus::Green,
+            current: HealthStatus::Yellow,
+            score: 72,
+        }
+        .to_json_line();
+        let value: serde_json::Value = serde_json::from_str(line.trim()).unwrap();
+        assert_eq!(value["event"], "health_changed");
+        assert_eq!(value["previous"], "green");
+        assert_eq!(value["current"], "yellow");
+        assert_eq!(value["score"], 72);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_event_mirror() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (events, _) = broadcast::channel(SESSION_EVENT_CAPACITY);
+        spawn_event_mirror(format!("tcp://{}", addr), &events);
+
+        emit(&events, SessionEvent::Connecting);
+        let (stream, _) = listener.accept().await.unwrap();
+        emit(&events, SessionEvent::Running);
+
+        let mut lines = BufReader::new(stream).lines();
+        let first = lines.next_line().await.unwrap().unwrap();
+        let second = lines.next_line().await.unwrap().unwrap();
+        assert!(first.contains("\"connecting\""));
+        assert!(second.contains("\"running\""));
+    }
+}
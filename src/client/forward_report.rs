@@ -0,0 +1,117 @@
+/// 直连 forwarder 连接的上报
+///
+/// 开启 `report_direct` 的 forwarder 在直连（不经隧道）的连接结束时记录一条报告，会话事件循环
+/// 每隔 [`FORWARD_REPORT_INTERVAL`] 把期间积累的报告合并为一条 `forward_report` 通知发给服务器。
+/// 只有服务器声明了 `forward_report` 能力的会话运行期间才收集报告，其余时间报告直接丢弃
+use crate::control_protocol::{ForwardReport, ForwardReportParams};
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 发送报告的最短间隔
+pub const FORWARD_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 等待发送的报告上限（超出的报告丢弃并计数）
+pub const MAX_PENDING_REPORTS: usize = 1000;
+
+#[derive(Debug, Default)]
+struct Pending {
+    enabled: bool,
+    reports: Vec<ForwardReport>,
+    dropped: u64,
+}
+
+/// 等待发送的直连报告（跨会话共享）
+#[derive(Debug, Clone, Default)]
+pub struct ForwardReports {
+    pending: Arc<Mutex<Pending>>,
+}
+
+impl ForwardReports {
+    /// 会话协商了 `forward_report` 能力时开始收集；停止时丢弃尚未发送的报告
+    pub fn set_enabled(&self, enabled: bool) {
+        let mut pending = self.pending.lock();
+        pending.enabled = enabled;
+        if !enabled {
+            pending.reports.clear();
+            pending.dropped = 0;
+        }
+    }
+
+    /// 记录一条报告
+    pub fn record(&self, report: ForwardReport) {
+        let mut pending = self.pending.lock();
+        if !pending.enabled {
+            return;
+        }
+        if pending.reports.len() < MAX_PENDING_REPORTS {
+            pending.reports.push(report);
+        } else {
+            pending.dropped += 1;
+        }
+    }
+
+    /// 取出等待发送的报告（没有报告时为 None）
+    pub fn take(&self) -> Option<ForwardReportParams> {
+        let mut pending = self.pending.lock();
+        if pending.reports.is_empty() && pending.dropped == 0 {
+            return None;
+        }
+        Some(ForwardReportParams {
+            reports: std::mem::take(&mut pending.reports),
+            dropped: std::mem::take(&mut pending.dropped),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(target: &str) -> ForwardReport {
+        ForwardReport {
+            forwarder: "fwd".to_string(),
+            target: target.to_string(),
+            decision: "direct".to_string(),
+            rule: Some("direct_by_domain".to_string()),
+            matched: Some("*.example.com".to_string()),
+            bytes_up: 10,
+            bytes_down: 20,
+            duration_ms: 5,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_reports_collected_only_while_enabled() {
+        let reports = ForwardReports::default();
+        reports.record(report("a.example.com:443"));
+        assert_eq!(reports.take(), None);
+
+        reports.set_enabled(true);
+        reports.record(report("a.example.com:443"));
+        reports.record(report("b.example.com:443"));
+        let batch = reports.take().unwrap();
+        assert_eq!(batch.reports.len(), 2);
+        assert_eq!(batch.dropped, 0);
+        assert_eq!(reports.take(), None);
+
+        // 会话结束时丢弃未发送的报告
+        reports.record(report("c.example.com:443"));
+        reports.set_enabled(false);
+        reports.set_enabled(true);
+        assert_eq!(reports.take(), None);
+    }
+
+    #[test]
+    fn test_excess_reports_are_counted() {
+        let reports = ForwardReports::default();
+        reports.set_enabled(true);
+        for _ in 0..MAX_PENDING_REPORTS + 5 {
+            reports.record(report("a.example.com:443"));
+        }
+        let batch = reports.take().unwrap();
+        assert_eq!(batch.reports.len(), MAX_PENDING_REPORTS);
+        assert_eq!(batch.dropped, 5);
+    }
+}
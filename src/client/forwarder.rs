@@ -1,223 +1,346 @@
-use crate::config::{ForwarderConfig, ProxyType};
+use crate::accept::{AcceptBackoff, AcceptErrorKind};
+use crate::config::{
+    ClientTimeoutsConfig, DirectEgressConfig, FastFailConfig, ForwarderConfig, ProxyType,
+    SocketOptionsConfig,
+};
+use crate::control_protocol::ForwardReport;
+use crate::io_util::{IdleReader, IdleTimeout};
+use crate::protocol::{FORWARD_FORBIDDEN, FORWARD_NAME_PREFIX, MAX_STREAM_NAME_LEN};
+use crate::protocol_trace::{CurrentTrace, SessionTrace, TraceDirection};
+use crate::relay_memory::{RelayBuffer, RelayMemory};
+use crate::socket_options;
+use crate::stats::{PendingBytes, STATS_FLUSH_BYTES};
+use crate::target_addr::TargetAddr;
+use crate::traffic::{TrafficMeter, TrafficTotals};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::num::NonZeroUsize;
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
+use tokio::net::TcpStream;
 use tokio::sync::{RwLock, Semaphore};
 use tokio::time::{sleep, Duration};
 use tokio_util::compat::FuturesAsyncReadCompatExt;
 use tracing::{debug, error, info, warn};
 
-use super::config::read_error_message;
-use super::geoip::GeoIpRouter;
-use super::stats::ClientStatsTracker;
+use super::config::{read_error_message, write_stream_preamble};
+use super::egress;
+use super::geoip::{GeoIpRouter, RouteDecision};
+use super::header_rules::HeaderRules;
+use super::http_message::{self, BodyFraming, HeadRead, ResponseHead};
+use super::listeners::bind_listener;
+use super::stats::{ClientStatsTracker, StreamFailure};
 use super::ProxyHandler;
 
 /// 每个 forwarder 的最大并发连接数（防止 DoS 攻击）
 const MAX_CONCURRENT_CONNECTIONS: usize = 1000;
 
-/// 协议解析超时时间（防止慢速攻击）
-const PROTOCOL_PARSE_TIMEOUT: Duration = Duration::from_secs(30);
-
-/// 连接空闲超时时间（防止资源泄漏）
-const CONNECTION_IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60); // 5分钟
-
 /// 数据复制缓冲区大小（64KB 适合高吞吐）
 const COPY_BUFFER_SIZE: usize = 65536;
 
-/// HTTP 协议解析缓冲区大小
-const HTTP_PARSE_BUFFER_SIZE: usize = 16384;
+/// HTTP 请求头默认最大大小（客户端未配置 size_limits 时使用）
+pub const DEFAULT_MAX_HTTP_HEADER_SIZE: usize = 16384;
+
+/// 请求头过大时返回给本地客户端的响应
+const HTTP_HEADER_TOO_LARGE_RESPONSE: &[u8] =
+    b"HTTP/1.1 431 Request Header Fields Too Large\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+/// 目标地址过长时返回给本地客户端的响应
+const HTTP_TARGET_TOO_LONG_RESPONSE: &[u8] =
+    b"HTTP/1.1 414 URI Too Long\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
 
-/// 快速失败配置
-const FAILED_TARGET_THRESHOLD: u32 = 3; // 失败次数阈值
-const FAILED_TARGET_TIMEOUT: Duration = Duration::from_secs(30 * 60); // 黑名单过期时间（30分钟）
-const FAILED_TARGET_CLEANUP_INTERVAL: Duration = Duration::from_secs(60); // 清理间隔（1分钟）
+/// 快速失败状态的清理间隔
+const FAILED_TARGET_CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 统计信息中列出的失败目标数量上限
+const FAILED_TARGET_TOP_N: usize = 20;
 
 /// 失败目标的信息
 #[derive(Debug, Clone)]
 struct FailedTarget {
-    /// 失败次数
+    /// 连续失败次数
     failure_count: u32,
+    /// 最近一次失败的时间戳（秒）
+    last_failure: u64,
     /// 加入黑名单的时间戳（秒）
-    blacklist_time: u64,
+    blacklisted_at: Option<u64>,
+}
+
+/// 快速失败黑名单快照（用于统计接口）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FastFailSnapshot {
+    /// 当前记录的失败目标数
+    pub tracked: usize,
+    /// 处于黑名单期内的目标数
+    pub blacklisted: usize,
+    /// 失败次数最多的目标（最多 20 个）
+    pub entries: Vec<FailedTargetEntry>,
+}
+
+/// 单个失败目标的快照
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedTargetEntry {
+    /// 目标地址
+    pub target: String,
+    /// 连续失败次数
+    pub failures: u32,
+    /// 最近一次失败的时间（Unix 时间戳）
+    pub last_failure: u64,
+    /// 黑名单到期时间（Unix 时间戳，未加入黑名单时为空）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blacklisted_until: Option<u64>,
 }
 
 /// 快速失败管理器
+///
+/// 按 `FastFailConfig` 记录失败目标，最多保留 `max_entries` 个（淘汰最久未访问的），
+/// 连接成功或距上次失败超过 `decay_secs` 后失败计数清零
 #[derive(Clone)]
 pub struct FailedTargetManager {
+    config: FastFailConfig,
     /// 失败目标映射表：目标地址 -> 失败信息
-    targets: Arc<RwLock<HashMap<String, FailedTarget>>>,
+    targets: Arc<parking_lot::Mutex<LruCache<String, FailedTarget>>>,
 }
 
 impl FailedTargetManager {
     /// 创建新的快速失败管理器
-    pub fn new() -> Self {
+    pub fn new(config: FastFailConfig) -> Self {
+        let capacity = NonZeroUsize::new(config.max_entries).unwrap_or(NonZeroUsize::MIN);
         Self {
-            targets: Arc::new(RwLock::new(HashMap::new())),
+            config,
+            targets: Arc::new(parking_lot::Mutex::new(LruCache::new(capacity))),
         }
     }
 
-    /// 启动清理任务（删除过期的黑名单条目）
-    pub fn start_cleanup_task(self) {
+    /// 启动清理任务（删除过期的条目，管理器的其他副本都被丢弃后退出）
+    pub fn start_cleanup_task(&self) {
+        let manager = self.clone();
         tokio::spawn(async move {
-            loop {
+            while Arc::strong_count(&manager.targets) > 1 {
                 sleep(FAILED_TARGET_CLEANUP_INTERVAL).await;
-                self.cleanup_expired_targets().await;
+                manager.cleanup_expired_targets(unix_now());
             }
         });
     }
 
     /// 检查目标是否在黑名单中
-    pub async fn is_blacklisted(&self, target: &str) -> bool {
-        let targets = self.targets.read().await;
-        if let Some(failed) = targets.get(target) {
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs();
-            // 检查是否还在黑名单期内
-            if now < failed.blacklist_time + FAILED_TARGET_TIMEOUT.as_secs() {
-                return true;
-            }
-        }
-        false
+    pub fn is_blacklisted(&self, target: &str) -> bool {
+        self.is_blacklisted_at(target, unix_now())
+    }
+
+    fn is_blacklisted_at(&self, target: &str, now: u64) -> bool {
+        self.targets
+            .lock()
+            .get(target)
+            .is_some_and(|failed| self.in_blacklist(failed, now))
     }
 
     /// 记录连接失败
-    pub async fn record_failure(&self, target: &str) {
-        let mut targets = self.targets.write().await;
-        let entry = targets.entry(target.to_string()).or_insert_with(|| {
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs();
-            FailedTarget {
-                failure_count: 0,
-                blacklist_time: now,
-            }
+    pub fn record_failure(&self, target: &str) {
+        self.record_failure_at(target, unix_now());
+    }
+
+    fn record_failure_at(&self, target: &str, now: u64) {
+        let mut targets = self.targets.lock();
+        let entry = targets.get_or_insert_mut(target.to_string(), || FailedTarget {
+            failure_count: 0,
+            last_failure: now,
+            blacklisted_at: None,
         });
 
+        // 黑名单到期或距上次失败已久，重新计数
+        let expired = entry.blacklisted_at.is_some() && !self.in_blacklist(entry, now);
+        if expired || now.saturating_sub(entry.last_failure) >= self.config.decay_secs {
+            entry.failure_count = 0;
+            entry.blacklisted_at = None;
+        }
+
         entry.failure_count += 1;
+        entry.last_failure = now;
 
-        if entry.failure_count >= FAILED_TARGET_THRESHOLD {
-            // 更新黑名单时间为当前时间
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs();
-            entry.blacklist_time = now;
-            warn!(
-                "Target '{}' added to blacklist due to {} consecutive failures",
-                target, entry.failure_count
-            );
+        if entry.failure_count >= self.config.threshold {
+            if entry.blacklisted_at.is_none() {
+                warn!(
+                    "Target '{}' added to blacklist due to {} consecutive failures",
+                    target, entry.failure_count
+                );
+            }
+            entry.blacklisted_at = Some(now);
         }
     }
 
-    /// 清理过期的黑名单条目
-    async fn cleanup_expired_targets(&self) {
-        let mut targets = self.targets.write().await;
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
+    /// 记录连接成功（立即清除该目标的失败记录）
+    pub fn record_success(&self, target: &str) {
+        let removed = self.targets.lock().pop(target);
+        if removed.is_some_and(|failed| failed.blacklisted_at.is_some()) {
+            info!("Target '{}' recovered, removed from blacklist", target);
+        }
+    }
 
-        let expired_targets: Vec<String> = targets
+    fn in_blacklist(&self, failed: &FailedTarget, now: u64) -> bool {
+        failed
+            .blacklisted_at
+            .is_some_and(|at| now < at + self.config.blacklist_secs)
+    }
+
+    /// 清理黑名单过期或失败计数已衰减的条目
+    fn cleanup_expired_targets(&self, now: u64) {
+        let mut targets = self.targets.lock();
+        let expired: Vec<String> = targets
             .iter()
-            .filter(|(_, failed)| now >= failed.blacklist_time + FAILED_TARGET_TIMEOUT.as_secs())
+            .filter(|(_, failed)| match failed.blacklisted_at {
+                Some(_) => !self.in_blacklist(failed, now),
+                None => now.saturating_sub(failed.last_failure) >= self.config.decay_secs,
+            })
             .map(|(target, _)| target.clone())
             .collect();
 
-        for target in expired_targets {
-            targets.remove(&target);
-            info!("Removed expired target '{}' from blacklist", target);
+        for target in expired {
+            if targets
+                .pop(&target)
+                .is_some_and(|failed| failed.blacklisted_at.is_some())
+            {
+                info!("Removed expired target '{}' from blacklist", target);
+            }
         }
     }
 
-    /// 获取失败目标数量（用于统计）
-    #[allow(dead_code)]
-    pub async fn failed_targets_count(&self) -> usize {
-        let targets = self.targets.read().await;
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-
-        targets
+    /// 获取黑名单快照（用于统计）
+    pub fn snapshot(&self) -> FastFailSnapshot {
+        self.snapshot_at(unix_now())
+    }
+
+    fn snapshot_at(&self, now: u64) -> FastFailSnapshot {
+        let targets = self.targets.lock();
+        let mut entries: Vec<FailedTargetEntry> = targets
             .iter()
-            .filter(|(_, failed)| now < failed.blacklist_time + FAILED_TARGET_TIMEOUT.as_secs())
-            .count()
+            .map(|(target, failed)| FailedTargetEntry {
+                target: target.clone(),
+                failures: failed.failure_count,
+                last_failure: failed.last_failure,
+                blacklisted_until: failed
+                    .blacklisted_at
+                    .filter(|_| self.in_blacklist(failed, now))
+                    .map(|at| at + self.config.blacklist_secs),
+            })
+            .collect();
+        let blacklisted = entries
+            .iter()
+            .filter(|e| e.blacklisted_until.is_some())
+            .count();
+        entries.sort_by(|a, b| {
+            b.failures
+                .cmp(&a.failures)
+                .then(b.last_failure.cmp(&a.last_failure))
+        });
+        entries.truncate(FAILED_TARGET_TOP_N);
+        FastFailSnapshot {
+            tracked: targets.len(),
+            blacklisted,
+            entries,
+        }
     }
 }
 
 impl Default for FailedTargetManager {
     fn default() -> Self {
-        Self::new()
+        Self::new(FastFailConfig::default())
     }
 }
 
-/// 实时统计的数据复制函数（带超时保护）
-/// 相比 tokio::io::copy，这个函数会在每次复制数据后立即更新统计信息
-/// 并在连接空闲超过 CONNECTION_IDLE_TIMEOUT 时自动关闭
-async fn copy_with_stats<R, W>(
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// 带统计的数据复制函数（带超时保护）
+/// 字节数先累加在本地，每满 STATS_FLUSH_BYTES 批量更新一次统计，结束时（包括出错）补上剩余部分，
+/// 并在连接的两个方向都空闲超过 `idle`（防止资源泄漏）时自动关闭
+pub(super) async fn copy_with_stats<R, W>(
     reader: &mut R,
     writer: &mut W,
-    stats_tracker: Option<&ClientStatsTracker>,
-    record_fn: impl Fn(&ClientStatsTracker, u64),
+    buf: &mut [u8],
+    idle: &IdleTimeout,
+    meter: Option<&TrafficMeter>,
+    record_fn: impl Fn(&TrafficMeter, u64),
 ) -> std::io::Result<u64>
 where
     R: tokio::io::AsyncRead + Unpin,
     W: tokio::io::AsyncWrite + Unpin,
 {
-    use tokio::time::timeout;
+    let mut pending = PendingBytes::new(STATS_FLUSH_BYTES);
+    // 连接空闲超时后读取返回 TimedOut 错误
+    let mut reader = IdleReader::new(reader, Some(idle));
 
-    let mut buf = vec![0u8; COPY_BUFFER_SIZE];
-    let mut total_copied = 0u64;
+    let result = async {
+        let mut total_copied = 0u64;
+        loop {
+            let n = reader.read(buf).await?;
 
-    loop {
-        // 使用 timeout 防止连接永久挂起（连接空闲超时保护）
-        let result = timeout(CONNECTION_IDLE_TIMEOUT, reader.read(&mut buf)).await;
-
-        let n = match result {
-            Ok(Ok(n)) => n,
-            Ok(Err(e)) => return Err(e),
-            Err(_) => {
-                // 超时：连接5分钟无数据传输，主动关闭
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::TimedOut,
-                    "Connection idle timeout",
-                ));
+            if n == 0 {
+                break;
             }
-        };
-
-        if n == 0 {
-            break;
-        }
 
-        writer.write_all(&buf[..n]).await?;
-        total_copied += n as u64;
+            writer.write_all(&buf[..n]).await?;
+            total_copied += n as u64;
 
-        // 实时更新统计信息
-        if let Some(tracker) = stats_tracker {
-            record_fn(tracker, n as u64);
+            // 累加到本地计数，满批量阈值时更新统计
+            if let (Some(meter), Some(batch)) = (meter, pending.add(n as u64)) {
+                record_fn(meter, batch);
+            }
         }
+        Ok(total_copied)
+    }
+    .await;
+
+    if let (Some(meter), Some(rest)) = (meter, pending.take()) {
+        record_fn(meter, rest);
     }
+    result
+}
 
-    Ok(total_copied)
+/// 领取双向转发的拷贝缓冲区
+///
+/// 跟踪器关联了内存预算时从预算领取（预算紧张时缓冲区变小或等待释放），否则按默认大小分配
+pub(super) async fn relay_buffers(
+    tracker: Option<&ClientStatsTracker>,
+) -> (RelayBuffer, RelayBuffer) {
+    match tracker.and_then(ClientStatsTracker::relay_memory) {
+        Some(memory) => memory.reserve_pair(COPY_BUFFER_SIZE).await,
+        None => RelayMemory::default().reserve_pair(COPY_BUFFER_SIZE).await,
+    }
 }
 
 /// 运行 forwarder 监听器
 /// 在客户端本地监听端口，接受连接后解析目标地址并通过 yamux 转发到服务器
+///
+/// `max_header_size` 限制 HTTP 代理解析的请求头大小（来自客户端 `size_limits`），
+/// 协议解析、连接空闲和等待服务器确认的超时取自 `timeouts`，
+/// 经服务器转发的 stream 前导和确认记录到 `trace`
+#[allow(clippy::too_many_arguments)]
 pub async fn run_forwarder_listener(
     forwarder: ForwarderConfig,
     stream_tx: tokio::sync::mpsc::Sender<tokio::sync::oneshot::Sender<Result<yamux::Stream>>>,
     router: Option<Arc<GeoIpRouter>>,
     stats_tracker: Option<ClientStatsTracker>,
+    max_header_size: usize,
+    timeouts: ClientTimeoutsConfig,
+    trace: CurrentTrace,
     mut shutdown_rx: tokio::sync::broadcast::Receiver<()>,
 ) -> Result<()> {
-    let bind_addr = format!("{}:{}", forwarder.bind_addr, forwarder.bind_port);
+    let bind_addr = crate::listen_addr::format(&forwarder.bind_addr, forwarder.bind_port);
+
+    // 请求头改写规则在绑定前解析密钥引用，引用失效时不启动监听
+    let header_rules = Arc::new(
+        HeaderRules::from_config(forwarder.header_rules.as_deref().unwrap_or_default())
+            .with_context(|| format!("Forwarder '{}'", forwarder.name))?,
+    );
 
     info!(
         "Forwarder '{}': Binding to {} ({})",
@@ -226,9 +349,13 @@ pub async fn run_forwarder_listener(
         format_proxy_type(forwarder.proxy_type)
     );
 
-    let listener = TcpListener::bind(&bind_addr)
-        .await
-        .with_context(|| format!("Failed to bind forwarder to {}", bind_addr))?;
+    let listener = bind_listener(
+        &forwarder.bind_addr,
+        forwarder.bind_port,
+        &format!("Forwarder '{}'", forwarder.name),
+    )
+    .await
+    .with_context(|| format!("Failed to bind forwarder to {}", bind_addr))?;
 
     info!("Forwarder '{}': Listening on {}", forwarder.name, bind_addr);
 
@@ -240,10 +367,20 @@ pub async fn run_forwarder_listener(
     );
 
     // 创建连接池缓存
-    let connection_pool = Arc::new(ConnectionPool::new(
-        100,                      // 最多缓存 100 个目标的连接
-        Duration::from_secs(300), // 连接空闲 5 分钟后过期
-    ));
+    let connection_pool = Arc::new(
+        ConnectionPool::new(
+            100,                      // 最多缓存 100 个目标的连接
+            Duration::from_secs(300), // 连接空闲 5 分钟后过期
+        )
+        .with_egress(forwarder.direct_egress.clone())
+        .with_socket(forwarder.socket.clone()),
+    );
+    if let Some(ref egress) = forwarder.direct_egress {
+        info!(
+            "Forwarder '{}': Direct connections use egress {}",
+            forwarder.name, egress
+        );
+    }
     info!(
         "Forwarder '{}': Connection pool initialized (max targets: 100, idle timeout: 5min)",
         forwarder.name
@@ -258,23 +395,34 @@ pub async fn run_forwarder_listener(
         }
     });
 
-    // 创建快速失败管理器并启动清理任务
-    let failed_target_manager = FailedTargetManager::new();
-    failed_target_manager.clone().start_cleanup_task();
+    // 快速失败管理器（统计跟踪器上已关联时共用同一个，便于统计接口查看黑名单）并启动清理任务
+    let failed_target_manager = stats_tracker
+        .as_ref()
+        .and_then(|t| t.fast_fail().cloned())
+        .unwrap_or_else(|| {
+            FailedTargetManager::new(forwarder.fast_fail.clone().unwrap_or_default())
+        });
+    failed_target_manager.start_cleanup_task();
     info!(
-        "Forwarder '{}': Fast-fail manager initialized (threshold: {}, timeout: {:?})",
-        forwarder.name, FAILED_TARGET_THRESHOLD, FAILED_TARGET_TIMEOUT
+        "Forwarder '{}': Fast-fail manager initialized (threshold: {}, blacklist: {}s, max entries: {})",
+        forwarder.name,
+        failed_target_manager.config.threshold,
+        failed_target_manager.config.blacklist_secs,
+        failed_target_manager.config.max_entries
     );
 
+    let mut backoff = AcceptBackoff::new(format!("Forwarder '{}'", forwarder.name));
     loop {
         tokio::select! {
             accept_result = listener.accept() => {
                 match accept_result {
                     Ok((local_stream, peer_addr)) => {
-                        // 优化 TCP 选项以降低延迟和防止连接断开
-                        if let Err(e) = local_stream.set_nodelay(true) {
-                            warn!("Failed to set TCP_NODELAY: {}", e);
-                        }
+                        backoff.reset();
+                        socket_options::configure(
+                            &local_stream,
+                            forwarder.socket.as_ref(),
+                            &format!("Forwarder '{}'", forwarder.name),
+                        );
 
                         // 尝试获取连接许可
                         let permit = match connection_limiter.clone().try_acquire_owned() {
@@ -301,6 +449,8 @@ pub async fn run_forwarder_listener(
                         let stats_tracker_clone = stats_tracker.clone();
                         let failed_target_manager_clone = failed_target_manager.clone();
                         let connection_pool_clone = connection_pool.clone();
+                        let header_rules = header_rules.clone();
+                        let trace = trace.get();
 
                         tokio::spawn(async move {
                             // 持有 permit 直到任务结束，自动释放
@@ -313,6 +463,10 @@ pub async fn run_forwarder_listener(
                                 stats_tracker_clone,
                                 failed_target_manager_clone,
                                 connection_pool_clone,
+                                &header_rules,
+                                max_header_size,
+                                &timeouts,
+                                trace,
                             )
                             .await
                             {
@@ -324,8 +478,15 @@ pub async fn run_forwarder_listener(
                         });
                     }
                     Err(e) => {
-                        error!("Forwarder '{}': Accept error: {}", forwarder.name, e);
-                        sleep(Duration::from_millis(100)).await;
+                        let kind = AcceptErrorKind::of_io(&e);
+                        if kind == AcceptErrorKind::Resource {
+                            if let Some(ref t) = stats_tracker {
+                                t.record_accept_error();
+                            }
+                        }
+                        if !backoff.on_error(kind, &e).await {
+                            break Err(e).with_context(|| format!("Forwarder '{}' listener stopped", forwarder.name));
+                        }
                     }
                 }
             }
@@ -340,6 +501,7 @@ pub async fn run_forwarder_listener(
 
 /// 处理 forwarder 连接
 /// 根据协议类型解析目标地址，然后通过 yamux stream 转发到服务器或直连
+#[allow(clippy::too_many_arguments)]
 async fn handle_forwarder_connection(
     mut local_stream: TcpStream,
     forwarder: &ForwarderConfig,
@@ -348,169 +510,83 @@ async fn handle_forwarder_connection(
     stats_tracker: Option<ClientStatsTracker>,
     failed_target_manager: FailedTargetManager,
     connection_pool: Arc<ConnectionPool>,
+    header_rules: &HeaderRules,
+    max_header_size: usize,
+    timeouts: &ClientTimeoutsConfig,
+    trace: SessionTrace,
 ) -> Result<()> {
+    let started = Instant::now();
+    let parse_timeout = timeouts.protocol_parse();
+    let idle = timeouts.forwarder_idle(forwarder);
     // 获取客户端地址用于审计日志
     let peer_addr = local_stream
         .peer_addr()
         .map(|addr| addr.to_string())
         .unwrap_or_else(|_| "unknown".to_string());
 
+    // auto 类型先根据首字节识别协议（识别失败直接关闭连接）
+    let proxy_type = match forwarder.proxy_type {
+        ProxyType::AutoProxy => detect_proxy_protocol(&local_stream, parse_timeout).await?,
+        proxy_type => proxy_type,
+    };
+
     // 记录连接开始
     if let Some(ref tracker) = stats_tracker {
         tracker.connection_started();
     }
 
     // 1. 根据 proxy_type 解析目标地址
-    let (target, http_direct_request) = match forwarder.proxy_type {
+    // CONNECT 的 200 响应推迟到确定可以连接目标时发送，被拒绝时本地应用收到的是错误状态码
+    let mut connect_reply_pending = false;
+    let target = match proxy_type {
         ProxyType::HttpProxy => {
             // 解析 HTTP 请求（支持 CONNECT 和直接转发）
-            let req = parse_http_request(&mut local_stream).await?;
-
-            match req.method.as_str() {
-                "CONNECT" => {
-                    // CONNECT 隧道模式
-                    handle_http_connect(&mut local_stream, &req.target).await?;
-                    (req.target.clone(), None)
-                }
-                _ => {
-                    // HTTP 直接转发（GET, POST 等）
-                    let (modified_request, target) =
-                        handle_http_direct(&mut local_stream, &req).await?;
-                    (target, Some(modified_request))
+            let mut buffer = Vec::new();
+            let req = parse_http_request(
+                &mut local_stream,
+                &mut buffer,
+                max_header_size,
+                parse_timeout,
+            )
+            .await?;
+
+            if req.method != "CONNECT" {
+                // HTTP 直接转发（GET, POST 等），同一连接上的后续请求继续在这里处理
+                let result = HttpDirectSession {
+                    forwarder_name: &forwarder.name,
+                    stats_tracker: stats_tracker.as_ref(),
+                    failed_target_manager: &failed_target_manager,
+                    connection_pool: &connection_pool,
+                    header_rules,
+                    max_header_size,
+                    parse_timeout,
+                    idle,
                 }
-            }
-        }
-        ProxyType::Socks5Proxy => {
-            let target = parse_socks5(&mut local_stream).await?;
-            // SOCKS5 始终是隧道模式
-            (target, None)
-        }
-        _ => anyhow::bail!(
-            "Invalid proxy type for forwarder: {:?}",
-            forwarder.proxy_type
-        ),
-    };
-
-    // 如果是 HTTP 直接转发（而非 CONNECT），需要直接转发修改后的请求
-    if let Some(request_data) = http_direct_request {
-        // 检查目标是否在黑名单中
-        if failed_target_manager.is_blacklisted(&target).await {
-            warn!(
-                "Forwarder '{}': Target '{}' is blacklisted due to previous failures, rejecting immediately",
-                forwarder.name, target
-            );
-            let error_response = b"HTTP/1.1 503 Service Unavailable\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\nService temporarily unavailable";
-            local_stream.write_all(error_response).await.ok();
-
-            if let Some(ref tracker) = stats_tracker {
-                tracker.connection_ended();
-            }
-            return Err(anyhow::anyhow!("Target blacklisted"));
-        }
-
-        // 直接连接目标并转发请求（使用连接池）
-        let mut remote_stream = match connection_pool.get_or_create(&target).await {
-            Ok(stream) => {
-                info!(
-                    "Forwarder '{}': Got connection from pool to {}",
-                    forwarder.name, target
-                );
-                ReusableConnection::new(stream, target.clone(), connection_pool.clone())
-            }
-            Err(e) => {
-                failed_target_manager.record_failure(&target).await;
-                local_stream.write_all(b"HTTP/1.1 502 Bad Gateway\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\nConnection failed").await.ok();
-
+                .run(local_stream, req, buffer)
+                .await;
                 if let Some(ref tracker) = stats_tracker {
                     tracker.connection_ended();
                 }
-                return Err(e);
+                return result;
             }
-        };
-
-        // 发送修改后的请求
-        if let Some(stream) = remote_stream.get_mut() {
-            stream.write_all(&request_data).await?;
-        }
-
-        // 双向数据转发
-        let (mut local_read, mut local_write) = local_stream.split();
-
-        // 为了支持可复用连接，我们需要手动处理转发
-        // 而不是使用 split()（split 会消耗所有权）
-        // 因此我们采用循环转发的方式
 
-        if let Some(stream) = remote_stream.get_mut() {
-            let (mut remote_read, mut remote_write) = stream.split();
-
-            let stats_c2r = stats_tracker.clone();
-            let forwarder_msg_1 = forwarder.name.clone();
-            let c2r = async {
-                let result = copy_with_stats(
-                    &mut local_read,
-                    &mut remote_write,
-                    stats_c2r.as_ref(),
-                    |t, n| t.record_bytes_sent(n),
-                )
-                .await;
-
-                if let Err(e) = &result {
-                    warn!(
-                        "Forwarder '{}': Client to remote copy error: {}",
-                        forwarder_msg_1, e
-                    );
-                }
-
-                // 注意：不调用 shutdown()，让连接保持可复用状态
-                result
-            };
-
-            let stats_r2c = stats_tracker.clone();
-            let forwarder_msg_2 = forwarder.name.clone();
-            let r2c = async {
-                let result = copy_with_stats(
-                    &mut remote_read,
-                    &mut local_write,
-                    stats_r2c.as_ref(),
-                    |t, n| t.record_bytes_received(n),
-                )
-                .await;
-
-                if let Err(e) = &result {
-                    warn!(
-                        "Forwarder '{}': Remote to client copy error: {}",
-                        forwarder_msg_2, e
-                    );
-                }
-
-                // 注意：不调用 shutdown()，让连接保持可复用状态
-                result
-            };
-
-            let (c2r_result, r2c_result) = tokio::join!(c2r, r2c);
-
-            // 如果发生错误，标记连接以便不返还到池
-            if c2r_result.is_err() || r2c_result.is_err() {
-                warn!(
-                    "Forwarder '{}': Data transfer completed with some errors",
-                    forwarder.name
-                );
-                remote_stream.mark_error();
-            }
+            // CONNECT 隧道模式
+            let target = handle_http_connect(&mut local_stream, &req.target).await?;
+            connect_reply_pending = true;
+            target
         }
-
-        if let Some(ref tracker) = stats_tracker {
-            tracker.connection_ended();
+        ProxyType::Socks5Proxy => {
+            // SOCKS5 始终是隧道模式
+            parse_socks5(&mut local_stream, parse_timeout).await?
         }
+        _ => anyhow::bail!("Invalid proxy type for forwarder: {:?}", proxy_type),
+    };
 
-        // ReusableConnection 会在 drop 时自动返还连接到池
-        drop(remote_stream);
-
-        return Ok(());
-    }
+    // 黑名单和统计使用规范化的目标字符串（IPv6 带方括号）
+    let target_key = target.to_string();
 
     // 检查目标是否在黑名单中（快速失败）
-    if failed_target_manager.is_blacklisted(&target).await {
+    if failed_target_manager.is_blacklisted(&target_key) {
         warn!(
             "Forwarder '{}': Target '{}' is blacklisted due to previous failures, rejecting immediately",
             forwarder.name, target
@@ -535,10 +611,25 @@ async fn handle_forwarder_connection(
     }
 
     // 2. 判断是否应该直连
-    let should_direct = router
-        .as_ref()
-        .map(|r| r.should_direct_connect(&target))
-        .unwrap_or(false);
+    let decision = router.as_ref().map(|r| r.route(&target));
+    if let (Some(decision), Some(tracker)) = (&decision, &stats_tracker) {
+        tracker.record_route_decision(&target_key, decision);
+    }
+    if decision.as_ref().is_some_and(|d| d.is_blocked()) {
+        // 月配额用尽且配置为 block：直接关闭本地连接
+        warn!(
+            "Forwarder '{}': Connection from {} to {} -> BLOCKED (routing quota exhausted)",
+            forwarder.name, peer_addr, target
+        );
+        if let Some(ref tracker) = stats_tracker {
+            tracker.connection_ended();
+        }
+        return Err(anyhow::anyhow!(
+            "Routing quota exhausted, connection to '{}' blocked",
+            target
+        ));
+    }
+    let should_direct = decision.as_ref().is_some_and(|d| d.is_direct());
 
     // 审计日志：记录连接详情和路由决策
     if should_direct {
@@ -546,6 +637,9 @@ async fn handle_forwarder_connection(
             "Forwarder '{}': Connection from {} to {} -> DIRECT (bypassing proxy)",
             forwarder.name, peer_addr, target
         );
+        if connect_reply_pending {
+            write_connect_established(&mut local_stream).await?;
+        }
         let result = handle_direct_connection(
             local_stream,
             &target,
@@ -553,14 +647,23 @@ async fn handle_forwarder_connection(
             stats_tracker.clone(),
             failed_target_manager.clone(),
             connection_pool.clone(),
+            idle,
             true, // 路由规则明确指定直连，跳过安全检查
         )
         .await;
+        report_direct(
+            stats_tracker.as_ref(),
+            &forwarder.name,
+            &target,
+            decision.as_ref(),
+            started,
+            &result,
+        );
         // 无论成功或失败，都记录连接结束
         if let Some(ref tracker) = stats_tracker {
             tracker.connection_ended();
         }
-        return result;
+        return result.map(drop);
     } else {
         info!(
             "Forwarder '{}': Connection from {} to {} -> PROXY (via server)",
@@ -568,37 +671,50 @@ async fn handle_forwarder_connection(
         );
     }
 
-    // 3. 请求创建新的 yamux stream
-    let (response_tx, response_rx) = tokio::sync::oneshot::channel();
-    stream_tx
-        .send(response_tx)
-        .await
-        .context("Failed to request yamux stream")?;
-
-    // 等待 yamux stream 创建完成
-    let server_stream = response_rx
-        .await
-        .context("Failed to receive yamux stream")??;
+    let record_failure = |kind: StreamFailure, e: &anyhow::Error| {
+        if let Some(ref tracker) = stats_tracker {
+            tracker.record_failure(StreamFailure::classify(e, kind), e);
+        }
+    };
 
-    info!(
-        "Forwarder '{}': Opened stream to server for target {}",
-        forwarder.name, target
-    );
+    // 3. 请求创建新的 yamux stream，发送特殊 name 携带目标地址：
+    // @forward:target（规范格式，IPv6 带方括号），publish_port = 0（占位，不使用）
+    let forward_name = forward_stream_name(&target)?;
+    let opened = async {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+        stream_tx
+            .send(response_tx)
+            .await
+            .context("Failed to request yamux stream")?;
 
-    // 将 yamux stream 转换为兼容的 tokio stream
-    let mut server_stream_tokio = server_stream.compat();
+        // 等待 yamux stream 创建完成
+        let server_stream = response_rx
+            .await
+            .context("Failed to receive yamux stream")??;
 
-    // 3. 发送特殊 name 携带目标地址：@forward:target
-    let forward_name = format!("@forward:{}", target);
-    let name_bytes = forward_name.as_bytes();
-    let name_len = (name_bytes.len() as u16).to_be_bytes();
-    server_stream_tokio.write_all(&name_len).await?;
-    server_stream_tokio.write_all(name_bytes).await?;
+        info!(
+            "Forwarder '{}': Opened stream to server for target {}",
+            forwarder.name, target
+        );
 
-    // 发送 publish_port = 0（占位，不使用）
-    let port_bytes = 0u16.to_be_bytes();
-    server_stream_tokio.write_all(&port_bytes).await?;
-    server_stream_tokio.flush().await?;
+        // 将 yamux stream 转换为兼容的 tokio stream
+        let stream_trace = trace.stream(server_stream.id());
+        let mut server_stream_tokio = server_stream.compat();
+        write_stream_preamble(&mut server_stream_tokio, &forward_name, 0).await?;
+        stream_trace.preamble(TraceDirection::Out, &forward_name, 0);
+        Ok::<_, anyhow::Error>((server_stream_tokio, stream_trace))
+    }
+    .await;
+    let (mut server_stream_tokio, stream_trace) = match opened {
+        Ok(stream) => stream,
+        Err(e) => {
+            record_failure(StreamFailure::StreamOpen, &e);
+            if let Some(ref tracker) = stats_tracker {
+                tracker.connection_ended();
+            }
+            return Err(e);
+        }
+    };
 
     info!(
         "Forwarder '{}': Sent forward request for target {}",
@@ -607,7 +723,20 @@ async fn handle_forwarder_connection(
 
     // 4. 等待服务器确认（1 字节：1=成功，0=失败）
     let mut confirm = [0u8; 1];
-    server_stream_tokio.read_exact(&mut confirm).await?;
+    let confirmed = tokio::time::timeout(
+        timeouts.stream_open(),
+        server_stream_tokio.read_exact(&mut confirm),
+    )
+    .await
+    .context("Timeout waiting for server confirmation")
+    .and_then(|result| result.context("Failed to read server confirmation"));
+    if let Err(e) = confirmed {
+        record_failure(StreamFailure::StreamOpen, &e);
+        if let Some(ref tracker) = stats_tracker {
+            tracker.connection_ended();
+        }
+        return Err(e);
+    }
 
     if confirm[0] != 1 {
         // 读取错误消息
@@ -615,23 +744,36 @@ async fn handle_forwarder_connection(
             Ok(msg) => msg,
             Err(_) => "Unknown error".to_string(),
         };
+        stream_trace.confirm(TraceDirection::In, false, Some(&error_msg));
         error!(
             "Forwarder '{}': Server rejected connection to '{}': {}",
             forwarder.name, target, error_msg
         );
 
-        // 记录连接失败
-        failed_target_manager.record_failure(&target).await;
+        // 记录连接失败（被服务器策略禁止的目标不是连接故障，不计入失败目标）
+        let forbidden = error_msg.starts_with(FORWARD_FORBIDDEN);
+        if !forbidden {
+            failed_target_manager.record_failure(&target_key);
+        }
+        if let Some(ref tracker) = stats_tracker {
+            tracker.record_failure(StreamFailure::ServerRejected, &error_msg);
+        }
 
         // 如果是 HTTP 代理，返回错误给客户端
-        if forwarder.proxy_type == ProxyType::HttpProxy {
+        if proxy_type == ProxyType::HttpProxy {
+            let status = if forbidden {
+                "403 Forbidden"
+            } else {
+                "502 Bad Gateway"
+            };
             let error_response = format!(
-                "HTTP/1.1 502 Bad Gateway\r\n\
+                "HTTP/1.1 {}\r\n\
                  Content-Type: text/plain\r\n\
                  Content-Length: {}\r\n\
                  Connection: close\r\n\
                  \r\n\
                  {}",
+                status,
                 error_msg.len(),
                 error_msg
             );
@@ -648,7 +790,12 @@ async fn handle_forwarder_connection(
             error_msg
         ));
     }
+    stream_trace.confirm(TraceDirection::In, true, None);
+    if connect_reply_pending {
+        write_connect_established(&mut local_stream).await?;
+    }
 
+    failed_target_manager.record_success(&target_key);
     info!(
         "Forwarder '{}': Server accepted connection, starting data transfer",
         forwarder.name
@@ -657,27 +804,33 @@ async fn handle_forwarder_connection(
     // 5. 双向转发数据
     let (mut local_read, mut local_write) = local_stream.split();
     let (mut server_read, mut server_write) = tokio::io::split(server_stream_tokio);
+    let (mut up_buf, mut down_buf) = relay_buffers(stats_tracker.as_ref()).await;
+    let idle = &IdleTimeout::new(idle);
 
-    let stats_tracker_c2s = stats_tracker.clone();
+    let meter = stats_tracker.as_ref().map(|t| t.route_meter(false));
+    let meter = meter.as_ref();
     let client_to_server = async move {
         let bytes = copy_with_stats(
             &mut local_read,
             &mut server_write,
-            stats_tracker_c2s.as_ref(),
-            |tracker, n| tracker.record_bytes_sent(n),
+            &mut up_buf,
+            idle,
+            meter,
+            TrafficMeter::add_sent,
         )
         .await?;
         server_write.shutdown().await?;
         Ok::<_, std::io::Error>(bytes)
     };
 
-    let stats_tracker_s2c = stats_tracker.clone();
     let server_to_client = async move {
         let bytes = copy_with_stats(
             &mut server_read,
             &mut local_write,
-            stats_tracker_s2c.as_ref(),
-            |tracker, n| tracker.record_bytes_received(n),
+            &mut down_buf,
+            idle,
+            meter,
+            TrafficMeter::add_received,
         )
         .await?;
         local_write.shutdown().await?;
@@ -710,120 +863,218 @@ async fn handle_forwarder_connection(
     Ok(())
 }
 
+/// 识别 auto 类型 forwarder 连接使用的代理协议
+///
+/// 只窥探首字节而不读取（数据留在套接字缓冲区中，由识别出的协议解析器完整读取）：
+/// 0x05 为 SOCKS5，ASCII 字母为 HTTP 请求方法
+async fn detect_proxy_protocol(stream: &TcpStream, parse_timeout: Duration) -> Result<ProxyType> {
+    use tokio::time::timeout;
+
+    let mut first = [0u8; 1];
+    let n = timeout(parse_timeout, stream.peek(&mut first))
+        .await
+        .map_err(|_| anyhow::anyhow!("Protocol detection timeout after {:?}", parse_timeout))??;
+    if n == 0 {
+        anyhow::bail!("Connection closed before protocol detection");
+    }
+    match first[0] {
+        0x05 => Ok(ProxyType::Socks5Proxy),
+        b if b.is_ascii_alphabetic() => Ok(ProxyType::HttpProxy),
+        b => anyhow::bail!("Unrecognized proxy protocol (first byte 0x{:02x})", b),
+    }
+}
+
 /// HTTP 请求方法和元数据
 #[derive(Debug)]
 struct HttpRequest {
     method: String,
     target: String,
+    version: String,
     headers: std::collections::HashMap<String, String>,
     #[allow(dead_code)]
     raw_request: Vec<u8>,
 }
 
+impl HttpRequest {
+    /// 本地客户端是否希望在响应之后保持连接
+    fn keep_alive(&self) -> bool {
+        http_message::is_keep_alive(
+            &self.version,
+            ["connection", "proxy-connection"]
+                .iter()
+                .filter_map(|name| self.headers.get(*name).map(String::as_str)),
+        )
+    }
+}
+
 /// 解析 HTTP 请求（支持 CONNECT 和直接转发）
-async fn parse_http_request(stream: &mut TcpStream) -> Result<HttpRequest> {
+///
+/// `buffer` 中是上一个请求之后多读的数据，请求头之后多读的数据（请求体或下一个请求）留在其中；
+/// 请求头超过 `max_header_size` 时向本地客户端返回 431 并结束连接
+async fn parse_http_request(
+    stream: &mut TcpStream,
+    buffer: &mut Vec<u8>,
+    max_header_size: usize,
+    parse_timeout: Duration,
+) -> Result<HttpRequest> {
     use tokio::time::timeout;
 
-    let result = timeout(PROTOCOL_PARSE_TIMEOUT, async {
-        let mut buffer = vec![0u8; HTTP_PARSE_BUFFER_SIZE];
-        let mut pos = 0;
-
+    let result = timeout(parse_timeout, async {
         // 读取到第一个 \r\n\r\n
-        loop {
-            let n = stream.read(&mut buffer[pos..]).await?;
-            if n == 0 {
-                anyhow::bail!("Unexpected EOF while reading HTTP request");
+        let header_end = match http_message::read_head(stream, buffer, max_header_size).await? {
+            HeadRead::Complete(end) => end,
+            HeadRead::Eof => anyhow::bail!("Unexpected EOF while reading HTTP request"),
+            HeadRead::TooLarge => {
+                stream.write_all(HTTP_HEADER_TOO_LARGE_RESPONSE).await.ok();
+                anyhow::bail!(
+                    "HTTP request header exceeds {} bytes, replied 431",
+                    max_header_size
+                );
             }
-            pos += n;
-
-            // 查找 \r\n\r\n
-            if pos >= 4 {
-                for i in 0..=pos - 4 {
-                    if &buffer[i..i + 4] == b"\r\n\r\n" {
-                        let request_buf = buffer[..i + 4].to_vec();
-                        let request = String::from_utf8_lossy(&request_buf);
-                        let lines: Vec<&str> = request.lines().collect();
-
-                        if lines.is_empty() {
-                            anyhow::bail!("Empty HTTP request");
-                        }
+        };
 
-                        let first_line = lines[0];
-                        let parts: Vec<&str> = first_line.split_whitespace().collect();
-                        if parts.len() < 2 {
-                            anyhow::bail!("Invalid HTTP request line");
-                        }
+        let request_buf: Vec<u8> = buffer.drain(..header_end).collect();
+        let request = String::from_utf8_lossy(&request_buf);
+        let lines: Vec<&str> = request.lines().collect();
 
-                        let method = parts[0].to_string();
-                        let target = parts[1].to_string();
+        if lines.is_empty() {
+            anyhow::bail!("Empty HTTP request");
+        }
 
-                        // 解析 headers
-                        let mut headers = std::collections::HashMap::new();
-                        for line in &lines[1..] {
-                            if let Some(colon_pos) = line.find(':') {
-                                let key = line[..colon_pos].trim().to_lowercase();
-                                let value = line[colon_pos + 1..].trim().to_string();
-                                headers.insert(key, value);
-                            }
-                        }
+        let first_line = lines[0];
+        let parts: Vec<&str> = first_line.split_whitespace().collect();
+        if parts.len() < 2 {
+            anyhow::bail!("Invalid HTTP request line");
+        }
 
-                        return Ok::<HttpRequest, anyhow::Error>(HttpRequest {
-                            method,
-                            target,
-                            headers,
-                            raw_request: request_buf,
-                        });
+        let method = parts[0].to_string();
+        let target = parts[1].to_string();
+        let version = parts.get(2).unwrap_or(&"HTTP/1.0").to_string();
+
+        // 解析 headers
+        let mut headers: std::collections::HashMap<String, String> =
+            std::collections::HashMap::new();
+        for line in &lines[1..] {
+            if let Some(colon_pos) = line.find(':') {
+                let key = line[..colon_pos].trim().to_lowercase();
+                let value = line[colon_pos + 1..].trim().to_string();
+                // 重复的消息体长度头部合并为列表，由 request_framing 检查是否冲突
+                match headers.get_mut(&key) {
+                    Some(existing)
+                        if matches!(key.as_str(), "content-length" | "transfer-encoding") =>
+                    {
+                        existing.push_str(", ");
+                        existing.push_str(&value);
+                    }
+                    _ => {
+                        headers.insert(key, value);
                     }
                 }
             }
-
-            if pos >= HTTP_PARSE_BUFFER_SIZE {
-                anyhow::bail!("HTTP request too long");
-            }
         }
+
+        Ok::<HttpRequest, anyhow::Error>(HttpRequest {
+            method,
+            target,
+            version,
+            headers,
+            raw_request: request_buf,
+        })
     })
     .await
-    .map_err(|_| anyhow::anyhow!("HTTP parsing timeout after {:?}", PROTOCOL_PARSE_TIMEOUT))??;
+    .map_err(|_| anyhow::anyhow!("HTTP parsing timeout after {:?}", parse_timeout))??;
 
     Ok(result)
 }
 
-/// 处理 HTTP CONNECT 请求（隧道模式）
-async fn handle_http_connect(stream: &mut TcpStream, target: &str) -> Result<()> {
-    if !target.contains(':') {
-        anyhow::bail!("Invalid target address: {}", target);
+/// 构造 forwarder stream 前导名称（`@forward:target`）
+///
+/// 服务器只接受不超过 [`MAX_STREAM_NAME_LEN`] 字节的名称，过长的目标在本地直接拒绝，
+/// 不经过隧道往返
+fn forward_stream_name(target: &TargetAddr) -> Result<String> {
+    let name = format!("{}{}", FORWARD_NAME_PREFIX, target);
+    if name.len() > MAX_STREAM_NAME_LEN {
+        anyhow::bail!(
+            "Forward target '{}' is too long: {} bytes, at most {} allowed",
+            target,
+            name.len() - FORWARD_NAME_PREFIX.len(),
+            MAX_STREAM_NAME_LEN - FORWARD_NAME_PREFIX.len()
+        );
     }
+    Ok(name)
+}
 
+/// 处理 HTTP CONNECT 请求（隧道模式），返回解析后的目标地址
+///
+/// 成功响应由调用方在确定可以连接目标后通过 [`write_connect_established`] 发送
+async fn handle_http_connect(stream: &mut TcpStream, target: &str) -> Result<TargetAddr> {
+    let target = match TargetAddr::parse(target) {
+        Ok(target) => target,
+        Err(e) => {
+            stream
+                .write_all(b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\n\r\n")
+                .await
+                .ok();
+            return Err(e);
+        }
+    };
+    if let Err(e) = forward_stream_name(&target) {
+        stream.write_all(HTTP_TARGET_TOO_LONG_RESPONSE).await.ok();
+        return Err(e);
+    }
+
+    Ok(target)
+}
+
+/// 向 CONNECT 请求的发起方发送成功响应，之后开始转发隧道数据
+async fn write_connect_established(stream: &mut TcpStream) -> Result<()> {
     let response = b"HTTP/1.1 200 Connection Established\r\n\r\n";
     stream.write_all(response).await?;
     stream.flush().await?;
-
     Ok(())
 }
 
 /// 处理 HTTP 直接转发（如 GET, POST 等）
+///
+/// 解析出目标后按 `header_rules` 改写请求头，再重建请求
 async fn handle_http_direct(
-    _stream: &mut TcpStream,
-    req: &HttpRequest,
-) -> Result<(Vec<u8>, String)> {
+    stream: &mut TcpStream,
+    mut req: HttpRequest,
+    header_rules: &HeaderRules,
+) -> Result<(Vec<u8>, TargetAddr)> {
     // 解析目标
     let target = if req.target.starts_with("http://") || req.target.starts_with("https://") {
         // 绝对 URL
         let url =
             url::Url::parse(&req.target).map_err(|e| anyhow::anyhow!("Invalid URL: {}", e))?;
-        let host = url
-            .host_str()
-            .ok_or_else(|| anyhow::anyhow!("No host in URL"))?;
         let port = url
             .port()
             .unwrap_or(if url.scheme() == "https" { 443 } else { 80 });
-        format!("{}:{}", host, port)
+        match url.host() {
+            Some(url::Host::Domain(domain)) => TargetAddr::from_host_port(domain, port)?,
+            Some(url::Host::Ipv4(ip)) => TargetAddr::Ip((ip, port).into()),
+            Some(url::Host::Ipv6(ip)) => TargetAddr::Ip((ip, port).into()),
+            None => anyhow::bail!("No host in URL"),
+        }
     } else if let Some(host_header) = req.headers.get("host") {
         // 使用 Host header
-        host_header.clone()
+        TargetAddr::parse_with_default_port(host_header, 80)?
     } else {
         anyhow::bail!("Cannot determine target from HTTP request");
     };
+    if let Err(e) = forward_stream_name(&target) {
+        stream.write_all(HTTP_TARGET_TOO_LONG_RESPONSE).await.ok();
+        return Err(e);
+    }
+
+    // 改写请求头（不记录头部的值，添加的头部可能是密钥）
+    let matched = header_rules.apply(&target.host(), &mut req.headers);
+    if matched > 0 {
+        debug!(
+            "Applied {} header rule(s) to HTTP request for {}",
+            matched, target
+        );
+    }
 
     // 重建请求（修改为相对路径）
     let path = if req.target.starts_with("http") {
@@ -853,17 +1104,422 @@ async fn handle_http_direct(
             modified_request.extend(format!("{}: {}\r\n", key, value).into_bytes());
         }
     }
-    modified_request.extend(b"Connection: close\r\n\r\n");
+    // 上游连接在响应之后保持，供同一本地连接的后续请求复用
+    modified_request.extend(b"Connection: keep-alive\r\n\r\n");
 
     Ok((modified_request, target))
 }
 
+/// HTTP 直接转发中当前使用的上游连接
+struct HttpUpstream {
+    conn: ReusableConnection,
+    target: TargetAddr,
+    key: String,
+    started: Instant,
+    meter: Option<TrafficMeter>,
+}
+
+/// 一次请求/响应交换的结果
+enum Exchange {
+    /// 响应转发完成
+    Done {
+        /// 上游连接能否用于下一个请求
+        upstream_reusable: bool,
+        /// 本地连接能否继续处理下一个请求
+        client_keep_alive: bool,
+    },
+    /// 上游的响应不是 HTTP，已原样双向转发到连接关闭
+    Raw,
+}
+
+/// HTTP 直接转发（keep-alive）
+///
+/// 按 `Content-Length` / chunked 确定每个请求和响应的边界，在同一本地连接上依次处理后续请求
+/// （不支持管线化）；目标不变时复用同一上游连接，目标变化时归还当前连接并切换到新目标
+struct HttpDirectSession<'a> {
+    forwarder_name: &'a str,
+    stats_tracker: Option<&'a ClientStatsTracker>,
+    failed_target_manager: &'a FailedTargetManager,
+    connection_pool: &'a Arc<ConnectionPool>,
+    header_rules: &'a HeaderRules,
+    max_header_size: usize,
+    /// 请求头和 chunked 消息体的大小行必须在此时间内读完
+    parse_timeout: Duration,
+    /// 等待下一个请求和转发数据时的空闲超时
+    idle: Duration,
+}
+
+impl HttpDirectSession<'_> {
+    /// 处理本地连接上的第一个请求（`buffer` 为请求头之后多读的数据）及之后的请求
+    async fn run(
+        &self,
+        mut local_stream: TcpStream,
+        mut req: HttpRequest,
+        mut buffer: Vec<u8>,
+    ) -> Result<()> {
+        let mut upstream = None;
+        let result = loop {
+            match self
+                .serve(&mut local_stream, req, &mut buffer, &mut upstream)
+                .await
+            {
+                Ok(true) => {}
+                Ok(false) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+            match self.next_request(&mut local_stream, &mut buffer).await {
+                Ok(Some(next)) => req = next,
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+
+        if let Some(upstream) = upstream {
+            self.finish(upstream, None);
+        }
+        result
+    }
+
+    /// 等待同一连接上的下一个请求，本地客户端关闭连接或空闲超时时返回 None
+    async fn next_request(
+        &self,
+        local_stream: &mut TcpStream,
+        buffer: &mut Vec<u8>,
+    ) -> Result<Option<HttpRequest>> {
+        if buffer.is_empty() {
+            let mut probe = [0u8; 1];
+            match tokio::time::timeout(self.idle, local_stream.peek(&mut probe)).await {
+                Ok(Ok(0)) | Err(_) => return Ok(None),
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => return Err(e.into()),
+            }
+        }
+        parse_http_request(
+            local_stream,
+            buffer,
+            self.max_header_size,
+            self.parse_timeout,
+        )
+        .await
+        .map(Some)
+    }
+
+    /// 转发一个请求及其响应，返回本地连接能否继续处理下一个请求
+    async fn serve(
+        &self,
+        local_stream: &mut TcpStream,
+        mut req: HttpRequest,
+        buffer: &mut Vec<u8>,
+        upstream: &mut Option<HttpUpstream>,
+    ) -> Result<bool> {
+        let framing = match http_message::request_framing(&req.headers) {
+            Ok(framing) => {
+                // 合并后的重复 Content-Length 以单个值转发给上游
+                if let BodyFraming::Length(length) = framing {
+                    req.headers
+                        .insert("content-length".to_string(), length.to_string());
+                }
+                framing
+            }
+            Err(e) => {
+                local_stream
+                    .write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                    .await
+                    .ok();
+                return Err(e);
+            }
+        };
+        let keep_alive = req.keep_alive();
+        let method = req.method.clone();
+        let (request_head, target) =
+            handle_http_direct(local_stream, req, self.header_rules).await?;
+
+        // 目标变化时结束当前的上游连接（返还到池）
+        if let Some(current) = upstream.take_if(|u| u.key != target.to_string()) {
+            self.finish(current, None);
+        }
+        let current = match upstream {
+            Some(current) => {
+                debug!(
+                    "Forwarder '{}': Reusing connection to {} for next request",
+                    self.forwarder_name, current.target
+                );
+                current
+            }
+            None => upstream.insert(self.connect(local_stream, target).await?),
+        };
+
+        match self
+            .exchange(
+                local_stream,
+                buffer,
+                current,
+                &request_head,
+                framing,
+                &method,
+                keep_alive,
+            )
+            .await
+        {
+            Ok(Exchange::Done {
+                upstream_reusable,
+                client_keep_alive,
+            }) => {
+                if !upstream_reusable {
+                    if let Some(mut current) = upstream.take() {
+                        current.conn.mark_error();
+                        self.finish(current, None);
+                    }
+                }
+                Ok(client_keep_alive)
+            }
+            Ok(Exchange::Raw) => {
+                if let Some(mut current) = upstream.take() {
+                    current.conn.mark_error();
+                    self.finish(current, None);
+                }
+                Ok(false)
+            }
+            Err(e) => {
+                warn!(
+                    "Forwarder '{}': HTTP request to {} failed: {:#}",
+                    self.forwarder_name, current.target, e
+                );
+                // 出错的连接不返还到池
+                if let Some(mut current) = upstream.take() {
+                    current.conn.mark_error();
+                    self.finish(current, Some(&e));
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// 从连接池获取到目标的连接，失败时向本地客户端返回错误响应
+    async fn connect(
+        &self,
+        local_stream: &mut TcpStream,
+        target: TargetAddr,
+    ) -> Result<HttpUpstream> {
+        // 黑名单和统计使用规范化的目标字符串（IPv6 带方括号）
+        let key = target.to_string();
+        let started = Instant::now();
+
+        // 检查目标是否在黑名单中
+        if self.failed_target_manager.is_blacklisted(&key) {
+            warn!(
+                "Forwarder '{}': Target '{}' is blacklisted due to previous failures, rejecting immediately",
+                self.forwarder_name, target
+            );
+            let error_response = b"HTTP/1.1 503 Service Unavailable\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\nService temporarily unavailable";
+            local_stream.write_all(error_response).await.ok();
+            return Err(anyhow::anyhow!("Target blacklisted"));
+        }
+
+        // 直接连接目标（使用连接池）
+        match self.connection_pool.get_or_create(&target).await {
+            Ok(stream) => {
+                info!(
+                    "Forwarder '{}': Got connection from pool to {}",
+                    self.forwarder_name, target
+                );
+                self.failed_target_manager.record_success(&key);
+                Ok(HttpUpstream {
+                    conn: ReusableConnection::new(
+                        stream,
+                        key.clone(),
+                        self.connection_pool.clone(),
+                    ),
+                    target,
+                    key,
+                    started,
+                    meter: self.stats_tracker.map(|t| t.route_meter(true)),
+                })
+            }
+            Err(e) => {
+                self.failed_target_manager.record_failure(&key);
+                if let Some(tracker) = self.stats_tracker {
+                    tracker.record_failure(
+                        StreamFailure::classify(&e, StreamFailure::TargetConnect),
+                        &e,
+                    );
+                }
+                report_direct(
+                    self.stats_tracker,
+                    self.forwarder_name,
+                    &target,
+                    None,
+                    started,
+                    &Err(anyhow::anyhow!("{:#}", e)),
+                );
+                local_stream.write_all(b"HTTP/1.1 502 Bad Gateway\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\nConnection failed").await.ok();
+                Err(e)
+            }
+        }
+    }
+
+    /// 在 `upstream` 上完成一次请求/响应交换
+    ///
+    /// 请求体和响应同时转发（`Expect: 100-continue` 的临时响应先转发给本地客户端）
+    #[allow(clippy::too_many_arguments)]
+    async fn exchange(
+        &self,
+        local_stream: &mut TcpStream,
+        buffer: &mut Vec<u8>,
+        upstream: &mut HttpUpstream,
+        request_head: &[u8],
+        framing: BodyFraming,
+        method: &str,
+        keep_alive: bool,
+    ) -> Result<Exchange> {
+        let Some(remote_stream) = upstream.conn.get_mut() else {
+            anyhow::bail!("Connection to {} was already released", upstream.target);
+        };
+        let (mut local_read, mut local_write) = local_stream.split();
+        let (mut remote_read, mut remote_write) = remote_stream.split();
+        // 两个方向共用连接的空闲计时
+        let idle = &IdleTimeout::new(self.idle);
+        let meter = upstream.meter.as_ref();
+
+        let send = async {
+            let mut reader = IdleReader::new(&mut local_read, Some(idle));
+            remote_write.write_all(request_head).await?;
+            let body = http_message::relay_body(
+                &mut reader,
+                buffer,
+                &mut remote_write,
+                framing,
+                self.parse_timeout,
+            )
+            .await?;
+            Ok::<_, anyhow::Error>(request_head.len() as u64 + body)
+        };
+
+        let receive = async {
+            let mut reader = IdleReader::new(&mut remote_read, Some(idle));
+            let mut response_buf = Vec::new();
+            let mut received = 0u64;
+            loop {
+                let end = match http_message::read_head(
+                    &mut reader,
+                    &mut response_buf,
+                    http_message::MAX_RESPONSE_HEAD_SIZE,
+                )
+                .await?
+                {
+                    HeadRead::Complete(end) => end,
+                    HeadRead::TooLarge => anyhow::bail!(
+                        "Response header exceeds {} bytes",
+                        http_message::MAX_RESPONSE_HEAD_SIZE
+                    ),
+                    HeadRead::Eof => anyhow::bail!("Connection closed before a complete response"),
+                };
+                let head: Vec<u8> = response_buf.drain(..end).collect();
+                let Some(response) = ResponseHead::parse(&head, method) else {
+                    // 不是 HTTP 响应：交给调用方原样转发
+                    local_write.write_all(&head).await?;
+                    local_write.write_all(&response_buf).await?;
+                    received += (head.len() + response_buf.len()) as u64;
+                    return Ok((received, Exchange::Raw));
+                };
+                if response.is_interim() {
+                    local_write.write_all(&head).await?;
+                    received += head.len() as u64;
+                    continue;
+                }
+
+                let client_keep_alive = keep_alive && response.framing != BodyFraming::UntilClose;
+                let head = response.to_bytes(client_keep_alive);
+                local_write.write_all(&head).await?;
+                received += head.len() as u64;
+                received += http_message::relay_body(
+                    &mut reader,
+                    &mut response_buf,
+                    &mut local_write,
+                    response.framing,
+                    self.parse_timeout,
+                )
+                .await?;
+                // 响应之后还有多余数据的上游连接不再复用
+                let upstream_reusable = response.keep_alive
+                    && response.framing != BodyFraming::UntilClose
+                    && response_buf.is_empty();
+                return Ok((
+                    received,
+                    Exchange::Done {
+                        upstream_reusable,
+                        client_keep_alive,
+                    },
+                ));
+            }
+        };
+
+        let (sent, received) = tokio::join!(send, receive);
+        if let (Some(meter), Ok(sent)) = (meter, &sent) {
+            meter.add_sent(*sent);
+        }
+        if let (Some(meter), Ok((received, _))) = (meter, &received) {
+            meter.add_received(*received);
+        }
+        sent?;
+        let (_, exchange) = received?;
+
+        if let Exchange::Raw = exchange {
+            // 上游不是 HTTP 服务（如 echo），退回到原样双向转发
+            remote_write.write_all(buffer).await?;
+            buffer.clear();
+            let (mut up_buf, mut down_buf) = relay_buffers(self.stats_tracker).await;
+            let (up, down) = tokio::join!(
+                copy_with_stats(
+                    &mut local_read,
+                    &mut remote_write,
+                    &mut up_buf,
+                    idle,
+                    meter,
+                    TrafficMeter::add_sent,
+                ),
+                copy_with_stats(
+                    &mut remote_read,
+                    &mut local_write,
+                    &mut down_buf,
+                    idle,
+                    meter,
+                    TrafficMeter::add_received,
+                ),
+            );
+            up?;
+            down?;
+        }
+        Ok(exchange)
+    }
+
+    /// 结束上游连接：记录直连报告，连接按状态返还到池或丢弃
+    fn finish(&self, upstream: HttpUpstream, error: Option<&anyhow::Error>) {
+        let result = match error {
+            Some(e) => Err(anyhow::anyhow!("{:#}", e)),
+            None => Ok(upstream
+                .meter
+                .as_ref()
+                .map(TrafficMeter::totals)
+                .unwrap_or_default()),
+        };
+        report_direct(
+            self.stats_tracker,
+            self.forwarder_name,
+            &upstream.target,
+            None,
+            upstream.started,
+            &result,
+        );
+    }
+}
+
 /// 解析 SOCKS5 请求
-async fn parse_socks5(stream: &mut TcpStream) -> Result<String> {
+async fn parse_socks5(stream: &mut TcpStream, parse_timeout: Duration) -> Result<TargetAddr> {
     use tokio::time::timeout;
 
     // 使用超时包装整个解析过程
-    let result = timeout(PROTOCOL_PARSE_TIMEOUT, async {
+    let result = timeout(parse_timeout, async {
         // SOCKS5 握手 - 读取客户端方法选择
         let mut header = [0u8; 2];
         stream.read_exact(&mut header).await?;
@@ -910,7 +1566,7 @@ async fn parse_socks5(stream: &mut TcpStream) -> Result<String> {
                 // IPv4
                 let mut addr = [0u8; 4];
                 stream.read_exact(&mut addr).await?;
-                format!("{}.{}.{}.{}", addr[0], addr[1], addr[2], addr[3])
+                Socks5Host::Ip(std::net::Ipv4Addr::from(addr).into())
             }
             0x03 => {
                 // 域名
@@ -924,17 +1580,13 @@ async fn parse_socks5(stream: &mut TcpStream) -> Result<String> {
 
                 let mut domain = vec![0u8; len];
                 stream.read_exact(&mut domain).await?;
-                String::from_utf8(domain)?
+                Socks5Host::Domain(String::from_utf8(domain)?)
             }
             0x04 => {
                 // IPv6
                 let mut addr = [0u8; 16];
                 stream.read_exact(&mut addr).await?;
-                format!(
-                    "{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}",
-                    addr[0], addr[1], addr[2], addr[3], addr[4], addr[5], addr[6], addr[7],
-                    addr[8], addr[9], addr[10], addr[11], addr[12], addr[13], addr[14], addr[15]
-                )
+                Socks5Host::Ip(std::net::Ipv6Addr::from(addr).into())
             }
             _ => anyhow::bail!("Unsupported address type: {}", atyp),
         };
@@ -944,7 +1596,20 @@ async fn parse_socks5(stream: &mut TcpStream) -> Result<String> {
         stream.read_exact(&mut port_bytes).await?;
         let port = u16::from_be_bytes(port_bytes);
 
-        let target = format!("{}:{}", host, port);
+        let target = match host {
+            Socks5Host::Ip(ip) => Ok(TargetAddr::Ip((ip, port).into())),
+            Socks5Host::Domain(domain) => TargetAddr::from_host_port(&domain, port),
+        };
+        // 无效或过长（无法放入 @forward: 前导）的目标地址
+        let target = match target.and_then(|t| forward_stream_name(&t).map(|_| t)) {
+            Ok(target) => target,
+            Err(e) => {
+                // Address type not supported
+                let response = [0x05, 0x08, 0x00, 0x01, 0, 0, 0, 0, 0, 0];
+                stream.write_all(&response).await?;
+                return Err(e);
+            }
+        };
 
         // 发送成功响应
         let response = [
@@ -955,58 +1620,43 @@ async fn parse_socks5(stream: &mut TcpStream) -> Result<String> {
         stream.write_all(&response).await?;
         stream.flush().await?;
 
-        Ok::<String, anyhow::Error>(target)
+        Ok::<TargetAddr, anyhow::Error>(target)
     })
     .await
-    .map_err(|_| anyhow::anyhow!("SOCKS5 parsing timeout after {:?}", PROTOCOL_PARSE_TIMEOUT))??;
+    .map_err(|_| anyhow::anyhow!("SOCKS5 parsing timeout after {:?}", parse_timeout))??;
 
     Ok(result)
 }
 
+/// SOCKS5 请求中的目标主机
+enum Socks5Host {
+    Ip(std::net::IpAddr),
+    Domain(String),
+}
+
 /// 格式化代理类型为可读字符串
 fn format_proxy_type(proxy_type: ProxyType) -> &'static str {
     match proxy_type {
         ProxyType::HttpProxy => "HTTP proxy",
         ProxyType::Socks5Proxy => "SOCKS5 proxy",
+        ProxyType::AutoProxy => "HTTP/SOCKS5 proxy",
         _ => "Unknown",
     }
 }
 
 /// 检查目标地址是否为本地或私有地址（用于客户端直连安全检查）
-fn is_unsafe_direct_target(target: &str) -> bool {
-    use std::net::{IpAddr, ToSocketAddrs};
-
-    // 提取主机名部分（移除端口）
-    let host = if let Some(colon_pos) = target.rfind(':') {
-        // 检查是否为 IPv6 地址（包含多个冒号）
-        if target.matches(':').count() > 1 {
-            // IPv6 地址，查找方括号
-            if let Some(bracket_end) = target.find(']') {
-                &target[1..bracket_end] // [ipv6]:port -> ipv6
-            } else {
-                target // 纯 IPv6 地址
-            }
-        } else {
-            &target[..colon_pos] // host:port -> host
-        }
-    } else {
-        target
-    };
+fn is_unsafe_direct_target(target: &TargetAddr) -> bool {
+    use std::net::IpAddr;
 
     // 检查是否为明确的本地主机名（完全匹配）
-    if host == "localhost" || host == "127.0.0.1" || host == "::1" {
-        return true;
+    if let TargetAddr::Domain(domain, _) = target {
+        if domain.eq_ignore_ascii_case("localhost") {
+            return true;
+        }
     }
 
-    // 解析地址（添加端口以便解析）
-    let addr_str = if target.contains(':') {
-        target.to_string()
-    } else {
-        format!("{}:80", target) // 添加默认端口以便解析
-    };
-
-    // 尝试解析域名/IP
-    match addr_str.to_socket_addrs() {
+    // 解析域名/IP
+    match target.resolve() {
         Ok(addrs) => {
             for addr in addrs {
                 let ip = addr.ip();
@@ -1062,6 +1712,10 @@ pub struct ForwarderHandler {
     pub status: Arc<RwLock<crate::client::HandlerStatus>>,
     pub shutdown_tx: Arc<RwLock<Option<tokio::sync::oneshot::Sender<()>>>>,
     pub stats_tracker: Option<ClientStatsTracker>,
+    /// HTTP 请求头最大大小
+    pub max_header_size: usize,
+    /// 客户端超时设置
+    pub timeouts: ClientTimeoutsConfig,
 }
 
 impl ForwarderHandler {
@@ -1079,20 +1733,36 @@ impl ForwarderHandler {
             status: Arc::new(RwLock::new(crate::client::HandlerStatus::Stopped)),
             shutdown_tx: Arc::new(RwLock::new(None)),
             stats_tracker,
+            max_header_size: DEFAULT_MAX_HTTP_HEADER_SIZE,
+            timeouts: ClientTimeoutsConfig::default(),
         }
     }
+
+    /// 设置 HTTP 请求头最大大小
+    pub fn with_max_header_size(mut self, max_header_size: usize) -> Self {
+        self.max_header_size = max_header_size;
+        self
+    }
+
+    /// 设置超时（协议解析、连接空闲、等待服务器确认）
+    pub fn with_timeouts(mut self, timeouts: ClientTimeoutsConfig) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
 }
 
 /// 处理直连（不通过服务器）
+#[allow(clippy::too_many_arguments)]
 async fn handle_direct_connection(
     mut local_stream: TcpStream,
-    target: &str,
+    target: &TargetAddr,
     forwarder_name: &str,
     stats_tracker: Option<ClientStatsTracker>,
     failed_target_manager: FailedTargetManager,
     connection_pool: Arc<ConnectionPool>,
+    idle: Duration,
     bypass_safety_check: bool,
-) -> Result<()> {
+) -> Result<TrafficTotals> {
     // 安全检查：禁止访问本地地址和内网地址（防止 SSRF 攻击）
     // 但如果是路由规则明确指定的，则允许（bypass_safety_check = true）
     if !bypass_safety_check && is_unsafe_direct_target(target) {
@@ -1112,6 +1782,7 @@ async fn handle_direct_connection(
                 "Forwarder '{}': Got connection from pool to {} (or created new)",
                 forwarder_name, target
             );
+            failed_target_manager.record_success(&target.to_string());
             ReusableConnection::new(stream, target.to_string(), connection_pool.clone())
         }
         Err(e) => {
@@ -1121,7 +1792,13 @@ async fn handle_direct_connection(
             );
 
             // 记录连接失败
-            failed_target_manager.record_failure(target).await;
+            failed_target_manager.record_failure(&target.to_string());
+            if let Some(ref tracker) = stats_tracker {
+                tracker.record_failure(
+                    StreamFailure::classify(&e, StreamFailure::TargetConnect),
+                    &e,
+                );
+            }
 
             return Err(anyhow::anyhow!(
                 "Failed to connect directly to {}: {}",
@@ -1133,18 +1810,24 @@ async fn handle_direct_connection(
 
     // 双向转发数据（使用可复用连接包装器）
     let (mut local_read, mut local_write) = local_stream.split();
+    let mut totals = TrafficTotals::default();
 
     if let Some(stream) = remote_stream.get_mut() {
         let (mut remote_read, mut remote_write) = stream.split();
+        let (mut up_buf, mut down_buf) = relay_buffers(stats_tracker.as_ref()).await;
+        let idle = &IdleTimeout::new(idle);
 
-        let stats_tracker_c2r = stats_tracker.clone();
+        let meter = stats_tracker.as_ref().map(|t| t.route_meter(true));
+        let meter = meter.as_ref();
         let name_msg_c2r = forwarder_name.to_string();
         let client_to_remote = async move {
             let result = copy_with_stats(
                 &mut local_read,
                 &mut remote_write,
-                stats_tracker_c2r.as_ref(),
-                |tracker, n| tracker.record_bytes_sent(n),
+                &mut up_buf,
+                idle,
+                meter,
+                TrafficMeter::add_sent,
             )
             .await;
             if let Err(e) = &result {
@@ -1157,14 +1840,15 @@ async fn handle_direct_connection(
             result
         };
 
-        let stats_tracker_r2c = stats_tracker.clone();
         let name_msg_r2c = forwarder_name.to_string();
         let remote_to_client = async move {
             let result = copy_with_stats(
                 &mut remote_read,
                 &mut local_write,
-                stats_tracker_r2c.as_ref(),
-                |tracker, n| tracker.record_bytes_received(n),
+                &mut down_buf,
+                idle,
+                meter,
+                TrafficMeter::add_received,
             )
             .await;
             if let Err(e) = &result {
@@ -1179,6 +1863,7 @@ async fn handle_direct_connection(
 
         // 使用 tokio::join! 确保两个方向的流量都被记录
         let (result_c2r, result_r2c) = tokio::join!(client_to_remote, remote_to_client);
+        totals = meter.map(TrafficMeter::totals).unwrap_or_default();
 
         if result_c2r.is_err() || result_r2c.is_err() {
             warn!(
@@ -1198,7 +1883,33 @@ async fn handle_direct_connection(
     // ReusableConnection 会在 drop 时自动将连接返还到池或丢弃坏连接
     drop(remote_stream);
 
-    Ok(())
+    Ok(totals)
+}
+
+/// 开启了 `report_direct` 时记录一条直连连接的报告（由会话事件循环批量发给服务器）
+fn report_direct(
+    stats_tracker: Option<&ClientStatsTracker>,
+    forwarder_name: &str,
+    target: &TargetAddr,
+    decision: Option<&RouteDecision>,
+    started: Instant,
+    result: &Result<TrafficTotals>,
+) {
+    let Some(reports) = stats_tracker.and_then(|t| t.forward_reports()) else {
+        return;
+    };
+    let totals = result.as_ref().copied().unwrap_or_default();
+    reports.record(ForwardReport {
+        forwarder: forwarder_name.to_string(),
+        target: target.to_string(),
+        decision: "direct".to_string(),
+        rule: decision.map(|d| d.rule.as_str().to_string()),
+        matched: decision.and_then(|d| d.matched.clone()),
+        bytes_up: totals.sent,
+        bytes_down: totals.received,
+        duration_ms: started.elapsed().as_millis() as u64,
+        error: result.as_ref().err().map(|e| format!("{:#}", e)),
+    });
 }
 
 #[async_trait]
@@ -1225,12 +1936,14 @@ impl ProxyHandler for ForwarderHandler {
         let router = self.router.clone();
         let status = self.status.clone();
         let stats_tracker = self.stats_tracker.clone();
+        let max_header_size = self.max_header_size;
+        let timeouts = self.timeouts;
 
         // 创建内部的 shutdown channel 用于 listener
         let (listener_shutdown_tx, listener_shutdown_rx) = tokio::sync::broadcast::channel::<()>(1);
 
         tokio::select! {
-            result = run_forwarder_listener(config, stream_tx, router, stats_tracker, listener_shutdown_rx) => {
+            result = run_forwarder_listener(config, stream_tx, router, stats_tracker, max_header_size, timeouts, CurrentTrace::default(), listener_shutdown_rx) => {
                 if let Err(e) = &result {
                     let mut s = status.write().await;
                     *s = crate::client::HandlerStatus::Failed(e.to_string());
@@ -1288,7 +2001,7 @@ impl ProxyHandler for ForwarderHandler {
     }
 
     fn bind_address(&self) -> String {
-        format!("{}:{}", self.config.bind_addr, self.config.bind_port)
+        crate::listen_addr::format(&self.config.bind_addr, self.config.bind_port)
     }
 }
 /// 检查连接是否还活着（简单的健康检查）
@@ -1384,6 +2097,10 @@ pub struct ConnectionPool {
     pools: Arc<RwLock<HashMap<String, Vec<PooledConnection>>>>,
     max_pool_size: usize,
     max_idle_time: Duration,
+    /// 新建直连使用的出口设置
+    egress: Option<DirectEgressConfig>,
+    /// 新建直连的套接字选项
+    socket: SocketOptionsConfig,
 }
 
 impl ConnectionPool {
@@ -1392,15 +2109,37 @@ impl ConnectionPool {
             pools: Arc::new(RwLock::new(HashMap::new())),
             max_pool_size,
             max_idle_time,
+            egress: None,
+            socket: SocketOptionsConfig::default(),
+        }
+    }
+
+    /// 设置新建直连的出口（SO_MARK、绑定网卡或源地址）
+    pub fn with_egress(mut self, egress: Option<DirectEgressConfig>) -> Self {
+        self.egress = egress;
+        self
+    }
+
+    /// 设置新建直连的套接字选项（未配置时使用默认值）
+    pub fn with_socket(mut self, socket: Option<SocketOptionsConfig>) -> Self {
+        self.socket = socket.unwrap_or_default();
+        self
+    }
+
+    /// 池中连接的键：按目标和出口设置区分，不同出口的连接不会混用
+    fn pool_key(&self, target: &str) -> String {
+        match self.egress {
+            Some(ref egress) => format!("{} via {}", target, egress),
+            None => target.to_string(),
         }
     }
 
     /// 从池中获取或创建连接
-    pub async fn get_or_create(&self, target: &str) -> Result<TcpStream> {
+    pub async fn get_or_create(&self, target: &TargetAddr) -> Result<TcpStream> {
         // 尝试从池中获取可用连接
         {
             let mut pools = self.pools.write().await;
-            if let Some(pool) = pools.get_mut(target) {
+            if let Some(pool) = pools.get_mut(&self.pool_key(&target.to_string())) {
                 while let Some(pooled) = pool.pop() {
                     // 检查连接是否过期
                     if pooled.created_at.elapsed() < self.max_idle_time {
@@ -1416,18 +2155,20 @@ impl ConnectionPool {
         }
 
         // 创建新连接
-        let stream = TcpStream::connect(target)
-            .await
-            .context(format!("Failed to connect to {}", target))?;
+        let stream = match self.egress {
+            Some(ref egress) => egress::connect(target, egress).await,
+            None => target.connect().await,
+        }
+        .context(format!("Failed to connect to {}", target))?;
 
-        stream.set_nodelay(true)?;
+        socket_options::apply(&stream, &self.socket)?;
         Ok(stream)
     }
 
     /// 将连接返还到池
     pub async fn return_connection(&self, target: String, stream: TcpStream) {
         let mut pools = self.pools.write().await;
-        let pool = pools.entry(target).or_insert_with(Vec::new);
+        let pool = pools.entry(self.pool_key(&target)).or_insert_with(Vec::new);
 
         if pool.len() < self.max_pool_size {
             pool.push(PooledConnection {
@@ -1584,10 +2325,171 @@ mod tests {
         // 注意：这里需要实际的连接才能完全测试
     }
 
+    #[tokio::test]
+    async fn test_connection_pool_keys_by_egress() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = TargetAddr::Ip(listener.local_addr().unwrap());
+
+        let plain = ConnectionPool::new(5, Duration::from_secs(60));
+        let egress = DirectEgressConfig {
+            bind_address: Some("127.0.0.1".parse().unwrap()),
+            ..Default::default()
+        };
+        let bound = ConnectionPool::new(5, Duration::from_secs(60)).with_egress(Some(egress));
+        assert_eq!(plain.pool_key(&target.to_string()), target.to_string());
+        assert_ne!(
+            bound.pool_key(&target.to_string()),
+            plain.pool_key(&target.to_string())
+        );
+
+        // 经出口设置建立的连接按带出口的键归还
+        let stream = bound.get_or_create(&target).await.unwrap();
+        bound.return_connection(target.to_string(), stream).await;
+        let pools = bound.pools.read().await;
+        assert!(pools.contains_key(&bound.pool_key(&target.to_string())));
+        assert!(!pools.contains_key(&target.to_string()));
+    }
+
+    #[test]
+    fn test_is_unsafe_direct_target() {
+        let unsafe_targets = [
+            "127.0.0.1:80",
+            "[::1]:443",
+            "localhost:8080",
+            "192.168.1.10:22",
+            "[fd00::1]:80",
+            "[fe80::1]:80",
+        ];
+        for target in unsafe_targets {
+            let target = TargetAddr::parse(target).unwrap();
+            assert!(
+                is_unsafe_direct_target(&target),
+                "{} should be unsafe",
+                target
+            );
+        }
+
+        for target in ["8.8.8.8:53", "[2001:4860:4860::8888]:53"] {
+            let target = TargetAddr::parse(target).unwrap();
+            assert!(
+                !is_unsafe_direct_target(&target),
+                "{} should be safe",
+                target
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_detect_proxy_protocol_does_not_consume() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        for (first_bytes, expected) in [
+            (&b"\x05\x01\x00"[..], Some(ProxyType::Socks5Proxy)),
+            (
+                &b"CONNECT example.com:443 HTTP/1.1\r\n"[..],
+                Some(ProxyType::HttpProxy),
+            ),
+            (&b"\x16\x03\x01"[..], None),
+        ] {
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            let (mut server, _) = listener.accept().await.unwrap();
+            client.write_all(first_bytes).await.unwrap();
+
+            let detected = detect_proxy_protocol(&server, Duration::from_secs(30))
+                .await
+                .ok();
+            assert_eq!(detected, expected);
+
+            // 识别后数据仍完整保留给协议解析器
+            let mut buf = vec![0u8; first_bytes.len()];
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, first_bytes);
+        }
+    }
+
     #[test]
     fn test_retry_config() {
         let config = RetryConfig::default();
         assert_eq!(config.max_retries, 3);
         assert_eq!(config.initial_backoff, Duration::from_millis(100));
     }
+
+    fn fast_fail(max_entries: usize) -> FailedTargetManager {
+        FailedTargetManager::new(FastFailConfig {
+            threshold: 3,
+            blacklist_secs: 600,
+            max_entries,
+            decay_secs: 60,
+        })
+    }
+
+    #[test]
+    fn test_fast_fail_evicts_least_recently_used() {
+        let manager = fast_fail(3);
+        for target in ["a:1", "b:1", "c:1"] {
+            manager.record_failure_at(target, 1000);
+        }
+        // 访问 a 后，最久未访问的是 b
+        manager.is_blacklisted_at("a:1", 1001);
+        manager.record_failure_at("d:1", 1002);
+
+        let snapshot = manager.snapshot_at(1002);
+        assert_eq!(snapshot.tracked, 3);
+        let mut targets: Vec<_> = snapshot.entries.iter().map(|e| e.target.as_str()).collect();
+        targets.sort();
+        assert_eq!(targets, ["a:1", "c:1", "d:1"]);
+
+        // 大量不同目标也不会超过上限
+        for i in 0..1000 {
+            manager.record_failure_at(&format!("scan-{}:80", i), 1003);
+        }
+        assert_eq!(manager.snapshot_at(1003).tracked, 3);
+    }
+
+    #[test]
+    fn test_fast_fail_counts_decay() {
+        let manager = fast_fail(100);
+        manager.record_failure_at("example.com:443", 1000);
+        manager.record_failure_at("example.com:443", 1010);
+        // 距上次失败超过 decay_secs，重新计数
+        manager.record_failure_at("example.com:443", 1100);
+        assert!(!manager.is_blacklisted_at("example.com:443", 1100));
+        assert_eq!(manager.snapshot_at(1100).entries[0].failures, 1);
+
+        manager.record_failure_at("example.com:443", 1110);
+        manager.record_failure_at("example.com:443", 1120);
+        assert!(manager.is_blacklisted_at("example.com:443", 1120));
+        let snapshot = manager.snapshot_at(1120);
+        assert_eq!(snapshot.blacklisted, 1);
+        assert_eq!(snapshot.entries[0].blacklisted_until, Some(1720));
+
+        // 黑名单到期后需要重新累计失败次数
+        assert!(!manager.is_blacklisted_at("example.com:443", 1720));
+        manager.record_failure_at("example.com:443", 1730);
+        assert!(!manager.is_blacklisted_at("example.com:443", 1730));
+
+        // 清理任务删除衰减后的条目
+        manager.record_failure_at("other.com:80", 1730);
+        manager.cleanup_expired_targets(1800);
+        assert_eq!(manager.snapshot_at(1800).tracked, 0);
+    }
+
+    #[test]
+    fn test_fast_fail_success_clears_target() {
+        let manager = fast_fail(100);
+        for at in [1000, 1001, 1002] {
+            manager.record_failure_at("example.com:443", at);
+        }
+        assert!(manager.is_blacklisted_at("example.com:443", 1003));
+
+        manager.record_success("example.com:443");
+        assert!(!manager.is_blacklisted_at("example.com:443", 1003));
+        assert_eq!(manager.snapshot_at(1003).tracked, 0);
+
+        // 成功后从零开始计数
+        manager.record_failure_at("example.com:443", 1004);
+        manager.record_failure_at("example.com:443", 1005);
+        assert!(!manager.is_blacklisted_at("example.com:443", 1005));
+    }
 }
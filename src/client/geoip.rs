@@ -1,38 +1,416 @@
-use crate::config::{RoutingConfig, RoutingStrategy};
+use super::quota::{QuotaStatus, QuotaTracker};
+use crate::config::{QuotaAction, RoutingConfig, RoutingStrategy};
+use crate::target_addr::TargetAddr;
+use crate::traffic::TrafficScope;
 use anyhow::Result;
 use maxminddb::{geoip2, Reader};
+use parking_lot::RwLock;
 use std::net::{IpAddr, ToSocketAddrs};
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use time::{OffsetDateTime, UtcOffset};
 use tracing::{debug, info, warn};
 
+/// 路由决策使用的时钟（时间段规则和月配额），测试中可以替换
+pub trait Clock: Send + Sync {
+    /// 当前 Unix 时间戳（秒）
+    fn now(&self) -> i64;
+}
+
+/// 系统时钟
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64)
+    }
+}
+
+/// 命中的路由规则类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteRule {
+    /// 命中 direct_domains
+    DirectByDomain,
+    /// 命中 proxy_domains
+    ProxyByDomain,
+    /// 命中 direct_ips
+    DirectByIp,
+    /// 命中 proxy_ips
+    ProxyByIp,
+    /// 命中 direct_countries
+    DirectByCountry,
+    /// 命中 proxy_countries
+    ProxyByCountry,
+    /// 未命中任何规则，默认直连
+    DirectByDefault,
+    /// 未命中任何规则，默认代理
+    ProxyByDefault,
+    /// 命中直连时间段
+    DirectBySchedule,
+    /// 命中代理时间段
+    ProxyBySchedule,
+    /// 月配额已用尽，改为直连
+    DirectByQuota,
+    /// 月配额已用尽，拒绝连接
+    BlockedByQuota,
+}
+
+impl RouteRule {
+    /// 所有规则类别（用于统计计数）
+    pub const ALL: [RouteRule; 12] = [
+        RouteRule::DirectByDomain,
+        RouteRule::ProxyByDomain,
+        RouteRule::DirectByIp,
+        RouteRule::ProxyByIp,
+        RouteRule::DirectByCountry,
+        RouteRule::ProxyByCountry,
+        RouteRule::DirectByDefault,
+        RouteRule::ProxyByDefault,
+        RouteRule::DirectBySchedule,
+        RouteRule::ProxyBySchedule,
+        RouteRule::DirectByQuota,
+        RouteRule::BlockedByQuota,
+    ];
+
+    /// 是否直连
+    pub fn is_direct(self) -> bool {
+        matches!(
+            self,
+            RouteRule::DirectByDomain
+                | RouteRule::DirectByIp
+                | RouteRule::DirectByCountry
+                | RouteRule::DirectByDefault
+                | RouteRule::DirectBySchedule
+                | RouteRule::DirectByQuota
+        )
+    }
+
+    /// 统计中使用的名称
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RouteRule::DirectByDomain => "direct_by_domain",
+            RouteRule::ProxyByDomain => "proxy_by_domain",
+            RouteRule::DirectByIp => "direct_by_ip",
+            RouteRule::ProxyByIp => "proxy_by_ip",
+            RouteRule::DirectByCountry => "direct_by_country",
+            RouteRule::ProxyByCountry => "proxy_by_country",
+            RouteRule::DirectByDefault => "direct_by_default",
+            RouteRule::ProxyByDefault => "proxy_by_default",
+            RouteRule::DirectBySchedule => "direct_by_schedule",
+            RouteRule::ProxyBySchedule => "proxy_by_schedule",
+            RouteRule::DirectByQuota => "direct_by_quota",
+            RouteRule::BlockedByQuota => "blocked_by_quota",
+        }
+    }
+}
+
+/// 路由决策（命中的规则类别及具体匹配项）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteDecision {
+    /// 规则类别
+    pub rule: RouteRule,
+    /// 具体匹配项（域名模式、CIDR、国家代码、时间段或配额月份），默认策略时为 None
+    pub matched: Option<String>,
+}
+
+impl RouteDecision {
+    fn new(rule: RouteRule, matched: impl Into<String>) -> Self {
+        Self {
+            rule,
+            matched: Some(matched.into()),
+        }
+    }
+
+    /// 是否直连
+    pub fn is_direct(&self) -> bool {
+        self.rule.is_direct()
+    }
+
+    /// 是否拒绝连接
+    pub fn is_blocked(&self) -> bool {
+        self.rule == RouteRule::BlockedByQuota
+    }
+}
+
 /// GeoIP 路由器
+///
+/// 路由规则可以通过 `reload` 在运行时整体替换，正在进行的查询继续使用旧规则。
+/// 时间段规则和月配额只在建立连接时判断，已建立的连接不受影响
 pub struct GeoIpRouter {
-    reader: Option<Arc<Reader<Vec<u8>>>>,
-    config: RoutingConfig,
-    direct_networks: Vec<ipnetwork::IpNetwork>,
-    proxy_networks: Vec<ipnetwork::IpNetwork>,
+    table: RwLock<Arc<RoutingTable>>,
+    clock: Arc<dyn Clock>,
+    /// 月配额计数（创建时配置了 quota 才有），reload 不会重置
+    quota: Option<QuotaTracker>,
 }
 
 impl GeoIpRouter {
     /// 创建新的 GeoIP 路由器
     pub fn new(config: RoutingConfig) -> Result<Self> {
-        let reader = if let Some(ref db_path) = config.geoip_db {
-            match Reader::open_readfile(db_path) {
-                Ok(reader) => {
-                    info!("GeoIP database loaded from: {}", db_path);
-                    Some(Arc::new(reader))
-                }
-                Err(e) => {
-                    warn!("Failed to load GeoIP database from {}: {}", db_path, e);
-                    warn!("Routing will use default strategy for all addresses");
-                    None
-                }
+        Self::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// 使用指定时钟创建路由器
+    pub fn with_clock(config: RoutingConfig, clock: Arc<dyn Clock>) -> Result<Self> {
+        let reader = load_reader(config.geoip_db.as_deref());
+        let table = RoutingTable::new(config, reader);
+        let quota = table.config.quota.as_ref().map(|quota| {
+            let now = clock.now();
+            QuotaTracker::load(
+                quota.state_path.clone(),
+                &table.local_time(now).period(),
+                now,
+            )
+        });
+        Ok(Self {
+            table: RwLock::new(Arc::new(table)),
+            clock,
+            quota,
+        })
+    }
+
+    /// 使用新配置替换路由规则（GeoIP 数据库路径未变时复用已加载的数据库）
+    pub fn reload(&self, config: RoutingConfig) -> Result<()> {
+        let current = self.table();
+        let reader = if current.config.geoip_db == config.geoip_db {
+            current.reader.clone()
+        } else {
+            load_reader(config.geoip_db.as_deref())
+        };
+        *self.table.write() = Arc::new(RoutingTable::new(config, reader));
+        info!("Routing rules reloaded");
+        Ok(())
+    }
+
+    /// 当前生效的路由配置
+    pub fn config(&self) -> RoutingConfig {
+        self.table().config.clone()
+    }
+
+    /// 判断目标地址是否应该直连，返回命中的路由规则
+    ///
+    /// `target` 格式为 `host[:port]`（IPv6 使用 `[addr]:port`），无法解析时使用默认策略
+    pub fn should_direct_connect(&self, target: &str) -> RouteDecision {
+        let table = self.table();
+        let decision = match TargetAddr::parse_with_default_port(target, 0) {
+            Ok(target) => table.route(&target),
+            Err(e) => {
+                debug!(
+                    "Invalid routing target '{}': {}, using default strategy",
+                    target, e
+                );
+                table.default_decision()
             }
+        };
+        self.apply_time_rules(&table, decision)
+    }
+
+    /// 判断已解析的目标地址是否应该直连，返回命中的路由规则
+    pub fn route(&self, target: &TargetAddr) -> RouteDecision {
+        let table = self.table();
+        let decision = table.route(target);
+        self.apply_time_rules(&table, decision)
+    }
+
+    /// 经隧道代理的连接的计数器挂到这个作用域上计入月配额（未配置配额时为 None）
+    pub fn quota_scope(&self) -> Option<&TrafficScope> {
+        self.quota.as_ref().map(|tracker| tracker.scope())
+    }
+
+    /// 汇总当月配额使用量（按间隔持久化），返回计费月份和使用量
+    fn refresh_quota(&self, table: &RoutingTable) -> Option<(String, u64)> {
+        let quota = table.config.quota.as_ref()?;
+        let tracker = self.quota.as_ref()?;
+        let now = self.clock.now();
+        let period = table.local_time(now).period();
+        let limit = quota.bytes_per_month;
+        let (used, crossed_limit) = tracker.refresh(&period, now, limit);
+        if crossed_limit {
+            warn!(
+                "Routing quota exhausted: {} of {} bytes used, new connections will not use the tunnel",
+                used, limit
+            );
+        }
+        Some((period, used))
+    }
+
+    /// 当前月配额的使用情况（未配置配额时为 None）
+    pub fn quota_status(&self) -> Option<QuotaStatus> {
+        let table = self.table();
+        let quota = table.config.quota.as_ref()?;
+        let (period, used_bytes) = self.refresh_quota(&table)?;
+        Some(QuotaStatus {
+            period,
+            used_bytes,
+            limit_bytes: quota.bytes_per_month,
+            exhausted: used_bytes >= quota.bytes_per_month,
+            action: quota.action,
+        })
+    }
+
+    /// 当前生效的时间段（描述），不在任何时间段内时为 None
+    pub fn active_schedule(&self) -> Option<String> {
+        let table = self.table();
+        let now = table.local_time(self.clock.now());
+        table
+            .schedules
+            .iter()
+            .find(|schedule| schedule.matches(&now))
+            .map(|schedule| schedule.label.clone())
+    }
+
+    /// 在静态规则的结果上应用时间段规则和月配额
+    fn apply_time_rules(&self, table: &RoutingTable, decision: RouteDecision) -> RouteDecision {
+        let now = table.local_time(self.clock.now());
+
+        // 时间段规则只替换默认策略
+        let decision = match decision.rule {
+            RouteRule::DirectByDefault | RouteRule::ProxyByDefault => table
+                .schedules
+                .iter()
+                .find(|schedule| schedule.matches(&now))
+                .map_or(decision, |schedule| {
+                    debug!("Schedule {} is active", schedule.label);
+                    let rule = if schedule.direct {
+                        RouteRule::DirectBySchedule
+                    } else {
+                        RouteRule::ProxyBySchedule
+                    };
+                    RouteDecision::new(rule, &schedule.label)
+                }),
+            _ => decision,
+        };
+        if decision.is_direct() {
+            return decision;
+        }
+
+        // 配额用尽后，原本走代理的连接改为直连或拒绝
+        let (Some(quota), Some((period, used))) = (&table.config.quota, self.refresh_quota(table))
+        else {
+            return decision;
+        };
+        if used < quota.bytes_per_month {
+            return decision;
+        }
+        let rule = match quota.action {
+            QuotaAction::Direct => RouteRule::DirectByQuota,
+            QuotaAction::Block => RouteRule::BlockedByQuota,
+        };
+        debug!(
+            "Routing quota for {} exhausted, using {}",
+            period,
+            rule.as_str()
+        );
+        RouteDecision::new(rule, period)
+    }
+
+    /// 根据国家代码匹配路由规则
+    #[cfg(test)]
+    pub(crate) fn match_country(&self, country_code: &str) -> RouteDecision {
+        self.table().match_country(country_code)
+    }
+
+    fn table(&self) -> Arc<RoutingTable> {
+        self.table.read().clone()
+    }
+
+    /// 检查域名是否匹配模式（支持通配符）
+    pub(crate) fn domain_matches(domain: &str, pattern: &str) -> bool {
+        if let Some(suffix) = pattern.strip_prefix("*.") {
+            // 通配符匹配：*.example.com 匹配 www.example.com、api.example.com
+            domain.ends_with(suffix) || domain == &suffix[1..] // 也匹配 example.com
+        } else if let Some(suffix) = pattern.strip_prefix('.') {
+            // .example.com 匹配 www.example.com 但不匹配 example.com
+            domain.ends_with(pattern) || domain == suffix
         } else {
-            debug!("No GeoIP database configured, routing will use default strategy");
+            // 精确匹配
+            domain == pattern
+        }
+    }
+}
+
+/// 加载 GeoIP 数据库，失败时返回 None（所有地址使用默认策略）
+fn load_reader(db_path: Option<&str>) -> Option<Arc<Reader<Vec<u8>>>> {
+    let Some(db_path) = db_path else {
+        debug!("No GeoIP database configured, routing will use default strategy");
+        return None;
+    };
+    match Reader::open_readfile(db_path) {
+        Ok(reader) => {
+            info!("GeoIP database loaded from: {}", db_path);
+            Some(Arc::new(reader))
+        }
+        Err(e) => {
+            warn!("Failed to load GeoIP database from {}: {}", db_path, e);
+            warn!("Routing will use default strategy for all addresses");
             None
-        };
+        }
+    }
+}
+
+/// 按配置时区换算后的本地时间
+struct LocalTime(OffsetDateTime);
+
+impl LocalTime {
+    /// 从周一开始的星期序号（周一为 0）
+    fn weekday(&self) -> u8 {
+        self.0.weekday().number_days_from_monday()
+    }
+
+    /// 当天已过的分钟数
+    fn minute_of_day(&self) -> u32 {
+        self.0.hour() as u32 * 60 + self.0.minute() as u32
+    }
+
+    /// 配额计费月份（"YYYY-MM"）
+    fn period(&self) -> String {
+        format!("{:04}-{:02}", self.0.year(), self.0.month() as u8)
+    }
+}
+
+/// 已解析的时间段规则
+#[derive(Debug)]
+struct Schedule {
+    /// 生效星期的位图（bit 0 为周一），全部为 0 表示每天
+    days: u8,
+    start: u32,
+    end: u32,
+    direct: bool,
+    label: String,
+}
+
+impl Schedule {
+    fn runs_on(&self, weekday: u8) -> bool {
+        self.days == 0 || self.days & (1 << weekday) != 0
+    }
+
+    /// 判断时间段是否包含给定时间；跨午夜的时间段属于开始那天
+    fn matches(&self, now: &LocalTime) -> bool {
+        let weekday = now.weekday();
+        let minute = now.minute_of_day();
+        if self.start < self.end {
+            self.runs_on(weekday) && minute >= self.start && minute < self.end
+        } else {
+            (self.runs_on(weekday) && minute >= self.start)
+                || (self.runs_on((weekday + 6) % 7) && minute < self.end)
+        }
+    }
+}
+
+/// 一份已解析的路由规则
+struct RoutingTable {
+    reader: Option<Arc<Reader<Vec<u8>>>>,
+    config: RoutingConfig,
+    direct_networks: Vec<ipnetwork::IpNetwork>,
+    proxy_networks: Vec<ipnetwork::IpNetwork>,
+    schedules: Vec<Schedule>,
+    utc_offset: UtcOffset,
+}
 
+impl RoutingTable {
+    fn new(config: RoutingConfig, reader: Option<Arc<Reader<Vec<u8>>>>) -> Self {
         // 解析直连 IP/CIDR 列表
         let mut direct_networks = Vec::new();
         for ip_str in &config.direct_ips {
@@ -61,36 +439,87 @@ impl GeoIpRouter {
             }
         }
 
-        Ok(Self {
+        // 解析时间段规则
+        let mut schedules = Vec::new();
+        for schedule in &config.schedules {
+            let direct = match schedule.strategy {
+                RoutingStrategy::Direct => true,
+                RoutingStrategy::Proxy => false,
+                RoutingStrategy::Unknown => {
+                    warn!(
+                        "Ignoring schedule {} with unknown strategy",
+                        schedule.label()
+                    );
+                    continue;
+                }
+            };
+            match schedule.window() {
+                Ok((start, end)) => {
+                    debug!("Added schedule: {}", schedule.label());
+                    schedules.push(Schedule {
+                        days: schedule
+                            .days
+                            .iter()
+                            .fold(0, |days, day| days | 1 << day.days_from_monday()),
+                        start,
+                        end,
+                        direct,
+                        label: schedule.label(),
+                    });
+                }
+                Err(e) => {
+                    warn!("Failed to parse schedule '{}': {}", schedule.label(), e);
+                }
+            }
+        }
+
+        let utc_offset = match config
+            .utc_offset_secs()
+            .and_then(|secs| Ok(UtcOffset::from_whole_seconds(secs)?))
+        {
+            Ok(offset) => offset,
+            Err(e) => {
+                warn!("Failed to parse utc_offset: {}, using UTC", e);
+                UtcOffset::UTC
+            }
+        };
+
+        Self {
             reader,
             config,
             direct_networks,
             proxy_networks,
-        })
+            schedules,
+            utc_offset,
+        }
     }
 
-    /// 判断目标地址是否应该直连
-    pub fn should_direct_connect(&self, target: &str) -> bool {
-        // 解析目标地址，提取主机名或 IP
-        let host = if let Some(colon_pos) = target.rfind(':') {
-            &target[..colon_pos]
-        } else {
-            target
+    /// 将 Unix 时间戳换算为配置时区的本地时间
+    fn local_time(&self, now: i64) -> LocalTime {
+        let utc = OffsetDateTime::from_unix_timestamp(now).unwrap_or(OffsetDateTime::UNIX_EPOCH);
+        LocalTime(utc.to_offset(self.utc_offset))
+    }
+
+    /// 判断目标地址是否应该直连，返回命中的路由规则
+    fn route(&self, target: &TargetAddr) -> RouteDecision {
+        let host = match target {
+            TargetAddr::Domain(domain, _) => domain.as_str(),
+            // 2. IP 地址直接匹配 IP/CIDR 和国家规则
+            TargetAddr::Ip(addr) => return self.should_direct_connect_ip(addr.ip()),
         };
 
         // 1. 检查域名匹配（优先级最高）
-        if let Some(should_direct) = self.match_domain(host) {
+        if let Some(decision) = self.match_domain(host) {
             debug!(
                 "Domain {} matched in routing rules, using {}",
                 host,
-                if should_direct { "direct" } else { "proxy" }
+                if decision.is_direct() {
+                    "direct"
+                } else {
+                    "proxy"
+                }
             );
-            return should_direct;
-        }
-
-        // 2. 尝试解析为 IP 地址
-        if let Ok(ip) = host.parse::<IpAddr>() {
-            return self.should_direct_connect_ip(ip);
+            return decision;
         }
 
         // 3. 如果是域名，尝试解析
@@ -98,12 +527,13 @@ impl GeoIpRouter {
             for addr in addrs {
                 let ip = addr.ip();
                 // 只要有一个 IP 符合直连条件就直连
-                if self.should_direct_connect_ip(ip) {
+                let decision = self.should_direct_connect_ip(ip);
+                if decision.is_direct() {
                     debug!(
                         "Domain {} resolved to {}, using direct connection",
                         host, ip
                     );
-                    return true;
+                    return decision;
                 }
             }
         }
@@ -113,45 +543,43 @@ impl GeoIpRouter {
             "Cannot resolve {} to IP, using default strategy: {:?}",
             host, self.config.default_strategy
         );
-        self.config.default_strategy == RoutingStrategy::Direct
+        self.default_decision()
+    }
+
+    /// 默认策略对应的路由决策
+    fn default_decision(&self) -> RouteDecision {
+        let rule = if self.config.default_strategy == RoutingStrategy::Direct {
+            RouteRule::DirectByDefault
+        } else {
+            RouteRule::ProxyByDefault
+        };
+        RouteDecision {
+            rule,
+            matched: None,
+        }
     }
 
-    /// 检查域名是否匹配路由规则
-    /// 返回 Some(true) 表示应该直连，Some(false) 表示应该代理，None 表示未匹配
-    fn match_domain(&self, host: &str) -> Option<bool> {
+    /// 检查域名是否匹配路由规则，未匹配时返回 None
+    fn match_domain(&self, host: &str) -> Option<RouteDecision> {
         // 检查直连域名列表
         for pattern in &self.config.direct_domains {
-            if Self::domain_matches(host, pattern) {
-                return Some(true);
+            if GeoIpRouter::domain_matches(host, pattern) {
+                return Some(RouteDecision::new(RouteRule::DirectByDomain, pattern));
             }
         }
 
         // 检查代理域名列表
         for pattern in &self.config.proxy_domains {
-            if Self::domain_matches(host, pattern) {
-                return Some(false);
+            if GeoIpRouter::domain_matches(host, pattern) {
+                return Some(RouteDecision::new(RouteRule::ProxyByDomain, pattern));
             }
         }
 
         None
     }
 
-    /// 检查域名是否匹配模式（支持通配符）
-    fn domain_matches(domain: &str, pattern: &str) -> bool {
-        if let Some(suffix) = pattern.strip_prefix("*.") {
-            // 通配符匹配：*.example.com 匹配 www.example.com、api.example.com
-            domain.ends_with(suffix) || domain == &suffix[1..] // 也匹配 example.com
-        } else if let Some(suffix) = pattern.strip_prefix('.') {
-            // .example.com 匹配 www.example.com 但不匹配 example.com
-            domain.ends_with(pattern) || domain == suffix
-        } else {
-            // 精确匹配
-            domain == pattern
-        }
-    }
-
     /// 判断 IP 地址是否应该直连
-    fn should_direct_connect_ip(&self, ip: IpAddr) -> bool {
+    fn should_direct_connect_ip(&self, ip: IpAddr) -> RouteDecision {
         // 1. 检查是否在直连 IP/CIDR 列表中
         for network in &self.direct_networks {
             if network.contains(ip) {
@@ -159,7 +587,7 @@ impl GeoIpRouter {
                     "IP {} matched direct network {}, using direct connection",
                     ip, network
                 );
-                return true;
+                return RouteDecision::new(RouteRule::DirectByIp, network.to_string());
             }
         }
 
@@ -167,13 +595,13 @@ impl GeoIpRouter {
         for network in &self.proxy_networks {
             if network.contains(ip) {
                 debug!("IP {} matched proxy network {}, using proxy", ip, network);
-                return false;
+                return RouteDecision::new(RouteRule::ProxyByIp, network.to_string());
             }
         }
 
         // 3. 如果没有 GeoIP 数据库，使用默认策略
         let Some(ref reader) = self.reader else {
-            return self.config.default_strategy == RoutingStrategy::Direct;
+            return self.default_decision();
         };
 
         // 4. 查询 IP 的国家代码
@@ -181,42 +609,55 @@ impl GeoIpRouter {
             Ok(Some(code)) => code,
             Ok(None) => {
                 debug!("No country found for IP {}, using default strategy", ip);
-                return self.config.default_strategy == RoutingStrategy::Direct;
+                return self.default_decision();
             }
             Err(e) => {
                 warn!("Failed to lookup IP {}: {}, using default strategy", ip, e);
-                return self.config.default_strategy == RoutingStrategy::Direct;
+                return self.default_decision();
             }
         };
 
         debug!("IP {} is from country: {}", ip, country_code);
 
-        // 5. 检查是否在直连国家列表中
-        if self.config.direct_countries.contains(&country_code) {
+        self.match_country(&country_code)
+    }
+
+    /// 根据国家代码匹配路由规则
+    fn match_country(&self, country_code: &str) -> RouteDecision {
+        // 1. 检查是否在直连国家列表中
+        if self
+            .config
+            .direct_countries
+            .iter()
+            .any(|c| c == country_code)
+        {
             debug!(
                 "Country {} is in direct_countries list, using direct connection",
                 country_code
             );
-            return true;
+            return RouteDecision::new(RouteRule::DirectByCountry, country_code);
         }
 
-        // 6. 检查是否在代理国家列表中
-        if !self.config.proxy_countries.is_empty()
-            && self.config.proxy_countries.contains(&country_code)
+        // 2. 检查是否在代理国家列表中
+        if self
+            .config
+            .proxy_countries
+            .iter()
+            .any(|c| c == country_code)
         {
             debug!(
                 "Country {} is in proxy_countries list, using proxy",
                 country_code
             );
-            return false;
+            return RouteDecision::new(RouteRule::ProxyByCountry, country_code);
         }
 
-        // 7. 不在任何列表中，使用默认策略
+        // 3. 不在任何列表中，使用默认策略
         debug!(
             "Country {} not in any list, using default strategy: {:?}",
             country_code, self.config.default_strategy
         );
-        self.config.default_strategy == RoutingStrategy::Direct
+        self.default_decision()
     }
 
     /// 查询 IP 地址的国家代码
@@ -235,11 +676,14 @@ impl GeoIpRouter {
 
 impl std::fmt::Debug for GeoIpRouter {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let table = self.table();
         f.debug_struct("GeoIpRouter")
-            .field("has_reader", &self.reader.is_some())
-            .field("config", &self.config)
-            .field("direct_networks", &self.direct_networks)
-            .field("proxy_networks", &self.proxy_networks)
+            .field("has_reader", &table.reader.is_some())
+            .field("config", &table.config)
+            .field("direct_networks", &table.direct_networks)
+            .field("proxy_networks", &table.proxy_networks)
+            .field("schedules", &table.schedules)
+            .field("has_quota", &self.quota.is_some())
             .finish()
     }
 }
@@ -289,11 +733,14 @@ mod tests {
             direct_domains: vec![],
             proxy_domains: vec![],
             default_strategy: RoutingStrategy::Direct,
+            schedules: vec![],
+            utc_offset: None,
+            quota: None,
         };
 
         let router = GeoIpRouter::new(config).unwrap();
         // 没有数据库时应该使用默认策略
-        assert!(router.should_direct_connect("8.8.8.8:80"));
+        assert!(router.should_direct_connect("8.8.8.8:80").is_direct());
     }
 
     #[test]
@@ -307,16 +754,23 @@ mod tests {
             direct_domains: vec!["*.example.com".to_string()],
             proxy_domains: vec!["*.google.com".to_string()],
             default_strategy: RoutingStrategy::Proxy, // 默认走代理
+            schedules: vec![],
+            utc_offset: None,
+            quota: None,
         };
 
         let router = GeoIpRouter::new(config).unwrap();
 
         // 直连域名列表优先
-        assert!(router.should_direct_connect("api.example.com:443"));
+        assert!(router
+            .should_direct_connect("api.example.com:443")
+            .is_direct());
         // 代理域名列表
-        assert!(!router.should_direct_connect("www.google.com:443"));
+        assert!(!router
+            .should_direct_connect("www.google.com:443")
+            .is_direct());
         // 未匹配的走默认策略（代理）
-        assert!(!router.should_direct_connect("unknown.org:443"));
+        assert!(!router.should_direct_connect("unknown.org:443").is_direct());
     }
 
     #[test]
@@ -330,19 +784,22 @@ mod tests {
             direct_domains: vec![],
             proxy_domains: vec![],
             default_strategy: RoutingStrategy::Proxy,
+            schedules: vec![],
+            utc_offset: None,
+            quota: None,
         };
 
         let router = GeoIpRouter::new(config).unwrap();
 
         // 直连 IP/CIDR
-        assert!(router.should_direct_connect("192.168.1.100:80"));
-        assert!(router.should_direct_connect("10.0.0.1:22"));
+        assert!(router.should_direct_connect("192.168.1.100:80").is_direct());
+        assert!(router.should_direct_connect("10.0.0.1:22").is_direct());
 
         // 代理 IP/CIDR
-        assert!(!router.should_direct_connect("8.8.8.8:53"));
+        assert!(!router.should_direct_connect("8.8.8.8:53").is_direct());
 
         // 默认策略
-        assert!(!router.should_direct_connect("1.1.1.1:443"));
+        assert!(!router.should_direct_connect("1.1.1.1:443").is_direct());
     }
 
     #[test]
@@ -357,16 +814,21 @@ mod tests {
             direct_domains: vec![],
             proxy_domains: vec!["*.google.com".to_string()], // 域名规则说走代理
             default_strategy: RoutingStrategy::Direct,
+            schedules: vec![],
+            utc_offset: None,
+            quota: None,
         };
 
         let router = GeoIpRouter::new(config).unwrap();
 
         // 域名优先级高于 IP，因此即使 8.8.8.8 在直连列表中，
         // dns.google.com 解析到 8.8.8.8 仍应该走代理（域名规则优先）
-        assert!(!router.should_direct_connect("dns.google.com:443"));
+        assert!(!router
+            .should_direct_connect("dns.google.com:443")
+            .is_direct());
 
         // 直接访问 8.8.8.8 时，域名规则不匹配，IP 规则生效
-        assert!(router.should_direct_connect("8.8.8.8:53"));
+        assert!(router.should_direct_connect("8.8.8.8:53").is_direct());
     }
 
     #[test]
@@ -384,21 +846,36 @@ mod tests {
             ],
             proxy_domains: vec![],
             default_strategy: RoutingStrategy::Proxy,
+            schedules: vec![],
+            utc_offset: None,
+            quota: None,
         };
 
         let router = GeoIpRouter::new(config).unwrap();
 
         // 多级通配符
-        assert!(router.should_direct_connect("img.cdn.example.com:443"));
-        assert!(router.should_direct_connect("cdn.example.com:443")); // 通配符也匹配根域名
+        assert!(router
+            .should_direct_connect("img.cdn.example.com:443")
+            .is_direct());
+        assert!(router
+            .should_direct_connect("cdn.example.com:443")
+            .is_direct()); // 通配符也匹配根域名
 
         // 点前缀（根据实现，也会匹配根域名）
-        assert!(router.should_direct_connect("api.internal.company.com:8080"));
-        assert!(router.should_direct_connect("internal.company.com:8080")); // 根据实现也匹配
+        assert!(router
+            .should_direct_connect("api.internal.company.com:8080")
+            .is_direct());
+        assert!(router
+            .should_direct_connect("internal.company.com:8080")
+            .is_direct()); // 根据实现也匹配
 
         // 精确匹配
-        assert!(router.should_direct_connect("exact-match.com:80"));
-        assert!(!router.should_direct_connect("www.exact-match.com:80"));
+        assert!(router
+            .should_direct_connect("exact-match.com:80")
+            .is_direct());
+        assert!(!router
+            .should_direct_connect("www.exact-match.com:80")
+            .is_direct());
     }
 
     #[test]
@@ -412,14 +889,216 @@ mod tests {
             direct_domains: vec![],
             proxy_domains: vec![],
             default_strategy: RoutingStrategy::Direct,
+            schedules: vec![],
+            utc_offset: None,
+            quota: None,
         };
 
         let router = GeoIpRouter::new(config).unwrap();
 
         // 无效地址应该使用默认策略（不崩溃）
-        assert!(router.should_direct_connect("invalid:port"));
-        assert!(router.should_direct_connect(":80"));
-        assert!(router.should_direct_connect("no-port"));
+        assert!(router.should_direct_connect("invalid:port").is_direct());
+        assert!(router.should_direct_connect(":80").is_direct());
+        assert!(router.should_direct_connect("no-port").is_direct());
+    }
+
+    #[test]
+    fn test_reload_replaces_rules() {
+        let config = RoutingConfig {
+            geoip_db: None,
+            direct_countries: vec![],
+            proxy_countries: vec![],
+            direct_ips: vec![],
+            proxy_ips: vec![],
+            direct_domains: vec![],
+            proxy_domains: vec![],
+            default_strategy: RoutingStrategy::Direct,
+            schedules: vec![],
+            utc_offset: None,
+            quota: None,
+        };
+
+        let router = GeoIpRouter::new(config.clone()).unwrap();
+        assert!(router.should_direct_connect("10.1.2.3:80").is_direct());
+
+        let mut updated = config;
+        updated.proxy_ips.push("10.0.0.0/8".to_string());
+        router.reload(updated).unwrap();
+
+        let decision = router.should_direct_connect("10.1.2.3:80");
+        assert_eq!(decision.rule, RouteRule::ProxyByIp);
+        assert_eq!(router.config().proxy_ips, vec!["10.0.0.0/8"]);
+    }
+
+    /// 测试用时钟
+    struct MockClock(std::sync::atomic::AtomicI64);
+
+    impl MockClock {
+        fn set(&self, now: i64) {
+            self.0.store(now, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> i64 {
+            self.0.load(std::sync::atomic::Ordering::Relaxed)
+        }
+    }
+
+    /// 2026-10-12（周一）09:00 +08:00
+    const MONDAY_0900: i64 = 1791766800;
+    const HOUR: i64 = 3600;
+    const DAY: i64 = 24 * HOUR;
+
+    fn time_routing_config() -> RoutingConfig {
+        use crate::config::{RoutingSchedule, Weekday};
+
+        let weekdays = vec![
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+        ];
+        RoutingConfig {
+            geoip_db: None,
+            direct_countries: vec![],
+            proxy_countries: vec![],
+            direct_ips: vec![],
+            proxy_ips: vec![],
+            direct_domains: vec![],
+            proxy_domains: vec!["*.vpn.example".to_string()],
+            default_strategy: RoutingStrategy::Proxy,
+            schedules: vec![
+                RoutingSchedule {
+                    days: weekdays,
+                    start: "09:00".to_string(),
+                    end: "18:00".to_string(),
+                    strategy: RoutingStrategy::Direct,
+                },
+                RoutingSchedule {
+                    days: vec![Weekday::Fri],
+                    start: "22:00".to_string(),
+                    end: "02:00".to_string(),
+                    strategy: RoutingStrategy::Direct,
+                },
+            ],
+            utc_offset: Some("+08:00".to_string()),
+            quota: None,
+        }
+    }
+
+    #[test]
+    fn test_schedule_boundaries() {
+        let clock = Arc::new(MockClock(MONDAY_0900.into()));
+        let router = GeoIpRouter::with_clock(time_routing_config(), clock.clone()).unwrap();
+        let rule_at = |now: i64| {
+            clock.set(now);
+            router.should_direct_connect("203.0.113.1:443").rule
+        };
+
+        // 工作日 09:00（含）到 18:00（不含）直连
+        assert_eq!(rule_at(MONDAY_0900 - 1), RouteRule::ProxyByDefault);
+        assert_eq!(rule_at(MONDAY_0900), RouteRule::DirectBySchedule);
+        assert_eq!(
+            rule_at(MONDAY_0900 + 9 * HOUR - 1),
+            RouteRule::DirectBySchedule
+        );
+        assert_eq!(rule_at(MONDAY_0900 + 9 * HOUR), RouteRule::ProxyByDefault);
+        // 周六白天不在工作日时间段内
+        assert_eq!(rule_at(MONDAY_0900 + 5 * DAY), RouteRule::ProxyByDefault);
+
+        // 周五 22:00 到周六 02:00 跨午夜，属于周五的时间段
+        let friday_2200 = MONDAY_0900 + 4 * DAY + 13 * HOUR;
+        assert_eq!(rule_at(friday_2200 - 1), RouteRule::ProxyByDefault);
+        assert_eq!(rule_at(friday_2200), RouteRule::DirectBySchedule);
+        assert_eq!(
+            rule_at(friday_2200 + 4 * HOUR - 1),
+            RouteRule::DirectBySchedule
+        );
+        assert_eq!(rule_at(friday_2200 + 4 * HOUR), RouteRule::ProxyByDefault);
+        // 周日 01:00 不属于任何时间段（周六没有跨午夜规则）
+        assert_eq!(
+            rule_at(friday_2200 + DAY + 3 * HOUR),
+            RouteRule::ProxyByDefault
+        );
+        assert_eq!(router.active_schedule(), None);
+
+        // 明确的域名规则优先于时间段
+        clock.set(MONDAY_0900);
+        assert_eq!(
+            router.should_direct_connect("www.vpn.example:443").rule,
+            RouteRule::ProxyByDomain
+        );
+        assert_eq!(
+            router.active_schedule().as_deref(),
+            Some("mon,tue,wed,thu,fri 09:00-18:00")
+        );
+    }
+
+    #[test]
+    fn test_quota_cutover() {
+        use crate::config::RoutingQuota;
+
+        let clock = Arc::new(MockClock(MONDAY_0900.into()));
+        let mut config = time_routing_config();
+        config.schedules.clear();
+        config.direct_ips = vec!["10.0.0.0/8".to_string()];
+        config.quota = Some(RoutingQuota {
+            bytes_per_month: 1000,
+            action: QuotaAction::Direct,
+            state_path: None,
+        });
+        let router = GeoIpRouter::with_clock(config.clone(), clock.clone()).unwrap();
+
+        // 两个连接在配额用尽前建立，走代理
+        let first = router.should_direct_connect("203.0.113.1:443");
+        let second = router.should_direct_connect("203.0.113.2:443");
+        assert_eq!(first.rule, RouteRule::ProxyByDefault);
+        assert_eq!(second.rule, RouteRule::ProxyByDefault);
+        let first_meter = router.quota_scope().unwrap().meter();
+        let second_meter = router.quota_scope().unwrap().meter();
+        first_meter.add_sent(600);
+        assert!(!router.quota_status().unwrap().exhausted);
+
+        // 已建立的连接继续传输并用尽配额，之后的新连接改为直连
+        second_meter.add_received(500);
+        drop(second_meter);
+        let status = router.quota_status().unwrap();
+        assert_eq!(status.period, "2026-10");
+        assert_eq!(status.used_bytes, 1100);
+        assert!(status.exhausted);
+        let decision = router.should_direct_connect("203.0.113.3:443");
+        assert_eq!(decision.rule, RouteRule::DirectByQuota);
+        assert_eq!(decision.matched.as_deref(), Some("2026-10"));
+        assert!(decision.is_direct());
+
+        // 已建立的连接不会被中断，其流量继续计入配额
+        first_meter.add_sent(100);
+        assert_eq!(router.quota_status().unwrap().used_bytes, 1200);
+
+        // 原本直连的规则不受配额影响
+        assert_eq!(
+            router.should_direct_connect("10.1.2.3:80").rule,
+            RouteRule::DirectByIp
+        );
+
+        // 进入下个月（按 +08:00 计算）后配额重置
+        clock.set(MONDAY_0900 + 20 * DAY - 9 * HOUR);
+        assert_eq!(router.quota_status().unwrap().period, "2026-11");
+        assert_eq!(
+            router.should_direct_connect("203.0.113.3:443").rule,
+            RouteRule::ProxyByDefault
+        );
+
+        // block 动作：配额用尽后拒绝新连接
+        config.quota.as_mut().unwrap().action = QuotaAction::Block;
+        let router = GeoIpRouter::with_clock(config, clock).unwrap();
+        router.quota_scope().unwrap().meter().add_sent(1000);
+        let decision = router.should_direct_connect("203.0.113.1:443");
+        assert_eq!(decision.rule, RouteRule::BlockedByQuota);
+        assert!(decision.is_blocked());
+        assert!(!decision.is_direct());
     }
 
     #[test]
@@ -433,12 +1112,17 @@ mod tests {
             direct_domains: vec![],
             proxy_domains: vec![],
             default_strategy: RoutingStrategy::Proxy,
+            schedules: vec![],
+            utc_offset: None,
+            quota: None,
         };
 
         let router = GeoIpRouter::new(config).unwrap();
 
         // IPv6 CIDR 匹配
-        assert!(router.should_direct_connect("[2001:db8::1]:80"));
-        assert!(!router.should_direct_connect("[2606:2800:220:1:248:1893:25c8:1946]:443"));
+        assert!(router.should_direct_connect("[2001:db8::1]:80").is_direct());
+        assert!(!router
+            .should_direct_connect("[2606:2800:220:1:248:1893:25c8:1946]:443")
+            .is_direct());
     }
 }
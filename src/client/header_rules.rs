@@ -0,0 +1,193 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+
+use super::geoip::GeoIpRouter;
+use crate::config::{resolve_secret, HeaderRuleConfig};
+
+/// forwarder HTTP 直接转发的请求头改写规则
+///
+/// 只作用于以绝对 URL 或 Host 转发的明文 HTTP 请求；CONNECT 隧道和 SOCKS5 连接不经过这里。
+/// 添加的头部值可能是密钥，只记录改写了多少个头部，从不记录值
+#[derive(Default)]
+pub(crate) struct HeaderRules {
+    rules: Vec<HeaderRule>,
+}
+
+struct HeaderRule {
+    /// 匹配的域名模式（与路由规则的域名匹配方式相同）
+    domains: Vec<String>,
+    /// 添加或覆盖的头部（小写名称，值已解析）
+    add: Vec<(String, String)>,
+    /// 删除的头部（小写名称，以 `*` 结尾时按前缀匹配）
+    remove: Vec<String>,
+}
+
+impl HeaderRules {
+    /// 解析规则中的 `env:` / `file:` 引用（错误信息只包含规则序号和头部名称）
+    pub(crate) fn from_config(rules: &[HeaderRuleConfig]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                let add = rule
+                    .add
+                    .iter()
+                    .map(|(name, value)| {
+                        let value = resolve_secret(value).with_context(|| {
+                            format!("header_rules[{}]: failed to resolve '{}'", index, name)
+                        })?;
+                        if value.contains(['\r', '\n']) {
+                            anyhow::bail!(
+                                "header_rules[{}]: value of '{}' contains a line break",
+                                index,
+                                name
+                            );
+                        }
+                        Ok((name.to_ascii_lowercase(), value))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(HeaderRule {
+                    domains: rule
+                        .match_domains
+                        .iter()
+                        .map(|d| d.to_ascii_lowercase())
+                        .collect(),
+                    add,
+                    remove: rule
+                        .remove
+                        .iter()
+                        .map(|name| name.to_ascii_lowercase())
+                        .collect(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { rules })
+    }
+
+    /// 按顺序应用与目标主机匹配的规则（每条规则先删除后添加），返回匹配的规则数
+    ///
+    /// `headers` 的名称为小写
+    pub(crate) fn apply(&self, host: &str, headers: &mut HashMap<String, String>) -> usize {
+        let host = host.to_ascii_lowercase();
+        let mut matched = 0;
+        for rule in &self.rules {
+            if !rule
+                .domains
+                .iter()
+                .any(|pattern| GeoIpRouter::domain_matches(&host, pattern))
+            {
+                continue;
+            }
+            matched += 1;
+            headers.retain(|name, _| !rule.remove.iter().any(|r| name_matches(name, r)));
+            for (name, value) in &rule.add {
+                headers.insert(name.clone(), value.clone());
+            }
+        }
+        matched
+    }
+}
+
+/// 头部名称匹配（`x-forwarded-*` 匹配所有以 `x-forwarded-` 开头的头部）
+fn name_matches(name: &str, pattern: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn rule(domains: &[&str], add: &[(&str, &str)], remove: &[&str]) -> HeaderRuleConfig {
+        HeaderRuleConfig {
+            match_domains: domains.iter().map(|d| d.to_string()).collect(),
+            add: add
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<BTreeMap<_, _>>(),
+            remove: remove.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    fn headers(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_matching_domain_is_rewritten() {
+        let rules = HeaderRules::from_config(&[rule(
+            &["*.internal.example"],
+            &[("Authorization", "Bearer literal")],
+            &["X-Forwarded-*", "Via"],
+        )])
+        .unwrap();
+        let mut request = headers(&[
+            ("host", "api.internal.example"),
+            ("x-forwarded-for", "10.0.0.1"),
+            ("x-forwarded-proto", "https"),
+            ("via", "1.1 edge"),
+            ("authorization", "Basic old"),
+        ]);
+        assert_eq!(rules.apply("API.internal.example", &mut request), 1);
+        assert_eq!(
+            request,
+            headers(&[
+                ("host", "api.internal.example"),
+                ("authorization", "Bearer literal"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_other_domains_are_untouched() {
+        let rules = HeaderRules::from_config(&[rule(
+            &["internal.example"],
+            &[("authorization", "secret")],
+            &["x-forwarded-for"],
+        )])
+        .unwrap();
+        let original = headers(&[("host", "example.com"), ("x-forwarded-for", "10.0.0.1")]);
+        let mut request = original.clone();
+        assert_eq!(rules.apply("example.com", &mut request), 0);
+        assert_eq!(request, original);
+    }
+
+    #[test]
+    fn test_secret_references() {
+        let path =
+            std::env::temp_dir().join(format!("tls-tunnel-header-secret-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "Bearer from-file\n").unwrap();
+        let file_ref = format!("file:{}", path.display());
+        std::env::set_var("TLS_TUNNEL_TEST_HEADER_SECRET", "from-env");
+        let rules = HeaderRules::from_config(&[rule(
+            &["internal.example"],
+            &[
+                ("authorization", &file_ref),
+                ("x-api-key", "env:TLS_TUNNEL_TEST_HEADER_SECRET"),
+            ],
+            &[],
+        )])
+        .unwrap();
+        std::fs::remove_file(&path).ok();
+        let mut request = HashMap::new();
+        rules.apply("internal.example", &mut request);
+        assert_eq!(request["authorization"], "Bearer from-file");
+        assert_eq!(request["x-api-key"], "from-env");
+
+        // 错误信息不包含任何值
+        let err = HeaderRules::from_config(&[rule(
+            &["internal.example"],
+            &[("x-api-key", "env:TLS_TUNNEL_TEST_HEADER_SECRET_MISSING")],
+            &[],
+        )])
+        .err()
+        .unwrap();
+        assert!(format!("{:#}", err).contains("x-api-key"), "{:#}", err);
+    }
+}
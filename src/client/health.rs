@@ -0,0 +1,722 @@
+/// 隧道健康评分
+///
+/// 由统计快照计算每个代理和整个隧道的 0-100 分和 green/yellow/red 状态，
+/// 每个扣分项带有说明，便于解释评分。评分本身是 [`evaluate`] 这个纯函数，
+/// [`HealthMonitor`] 负责把统计快照转换为窗口内的指标并保存滞后判断所需的上一次状态
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::stats::{ClientProxyStats, TunnelStatsSnapshot};
+use crate::config::HealthConfig;
+
+/// 满分
+pub const MAX_SCORE: u32 = 100;
+
+/// 往返时延达到 `rtt_critical_ms` 时的扣分（之间按比例）
+const RTT_PENALTY: u32 = 40;
+/// 连续未确认的心跳达到 `max_missed_heartbeats` 时的扣分（之间按比例）
+const MISSED_HEARTBEATS_PENALTY: u32 = 60;
+/// 窗口内出现转发缓冲区等待（内存预算紧张）时的扣分
+const RELAY_MEMORY_PENALTY: u32 = 10;
+/// 失败率达到 `failure_ratio_warn` 时的扣分（到 `failure_ratio_critical` 之间线性增加）
+const FAILURE_RATIO_WARN_PENALTY: u32 = 10;
+/// 失败率达到 `failure_ratio_critical` 时的扣分
+const FAILURE_RATIO_CRITICAL_PENALTY: u32 = 60;
+/// 窗口内因资源耗尽丢弃了连接时的扣分
+const ACCEPT_ERRORS_PENALTY: u32 = 20;
+/// 每个处于快速失败黑名单中的目标的扣分
+const BLACKLISTED_TARGET_PENALTY: u32 = 10;
+/// 黑名单扣分的上限
+const BLACKLISTED_TARGETS_MAX_PENALTY: u32 = 30;
+
+/// 两次采样的最小间隔（频繁查询时不再记录新的采样）
+const MIN_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 健康状态（按严重程度排序）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Green,
+    Yellow,
+    Red,
+}
+
+impl HealthStatus {
+    /// 状态名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HealthStatus::Green => "green",
+            HealthStatus::Yellow => "yellow",
+            HealthStatus::Red => "red",
+        }
+    }
+
+    /// 按阈值评级：低于 `red_below` 为 red，低于 `yellow_below` 为 yellow
+    fn from_score(score: u32, yellow_below: u32, red_below: u32) -> Self {
+        if score < red_below {
+            HealthStatus::Red
+        } else if score < yellow_below {
+            HealthStatus::Yellow
+        } else {
+            HealthStatus::Green
+        }
+    }
+}
+
+impl std::fmt::Display for HealthStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 隧道指标
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TunnelMetrics {
+    /// 是否已连接到服务器
+    pub connected: bool,
+    /// 最近一次心跳的往返时延（毫秒，服务器不确认心跳或还没有确认时为空）
+    pub rtt_ms: Option<f64>,
+    /// 当前连续未确认的心跳数
+    pub missed_heartbeats: u32,
+    /// 连续未确认多少次心跳后断开重连
+    pub max_missed_heartbeats: u32,
+    /// 窗口内需要等待其他连接释放转发缓冲区的次数
+    pub relay_memory_waits: u64,
+}
+
+/// 单个代理的指标（计数均为窗口内的增量）
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProxyMetrics {
+    /// 代理名称
+    pub name: String,
+    /// 连接数
+    pub connections: u64,
+    /// 建立隧道 stream 失败的次数
+    pub failures: u64,
+    /// 因资源耗尽未能接受的连接数
+    pub accept_errors: u64,
+    /// 当前处于快速失败黑名单中的目标数
+    pub blacklisted_targets: usize,
+}
+
+/// 计算健康评分的指标快照
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HealthMetrics {
+    pub tunnel: TunnelMetrics,
+    pub proxies: Vec<ProxyMetrics>,
+}
+
+/// 单个扣分项
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contribution {
+    /// 指标名称
+    pub component: String,
+    /// 扣分
+    pub penalty: u32,
+    /// 指标的取值和阈值
+    pub detail: String,
+}
+
+/// 一个评分对象（隧道或代理）的评分
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthRating {
+    /// 状态（已按滞后规则调整）
+    pub status: HealthStatus,
+    /// 分数（满分减去各项扣分）
+    pub score: u32,
+    /// 扣分项（没有扣分的指标不列出）
+    pub contributions: Vec<Contribution>,
+}
+
+impl HealthRating {
+    fn new(
+        contributions: Vec<Contribution>,
+        config: &HealthConfig,
+        previous: Option<HealthStatus>,
+    ) -> Self {
+        let penalty: u32 = contributions.iter().map(|c| c.penalty).sum();
+        let score = MAX_SCORE.saturating_sub(penalty);
+        Self {
+            status: rate(score, config, previous),
+            score,
+            contributions,
+        }
+    }
+}
+
+/// 单个代理的评分
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProxyHealth {
+    /// 代理名称
+    pub name: String,
+    #[serde(flatten)]
+    pub rating: HealthRating,
+}
+
+/// 健康评分结果（`/health/score` 端点）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthReport {
+    /// 总体状态：隧道和各代理中最差的状态
+    pub status: HealthStatus,
+    /// 总体分数：隧道和各代理中最低的分数
+    pub score: u32,
+    /// 评估时间（Unix 时间戳）
+    pub evaluated_at: u64,
+    /// 隧道本身的评分
+    pub tunnel: HealthRating,
+    /// 各代理的评分
+    pub proxies: Vec<ProxyHealth>,
+}
+
+impl HealthReport {
+    /// 本次评估的状态（作为下一次评估的滞后依据）
+    pub fn state(&self) -> HealthState {
+        HealthState {
+            tunnel: Some(self.tunnel.status),
+            proxies: self
+                .proxies
+                .iter()
+                .map(|p| (p.name.clone(), p.rating.status))
+                .collect(),
+        }
+    }
+}
+
+/// 上一次评估的状态
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HealthState {
+    pub tunnel: Option<HealthStatus>,
+    pub proxies: BTreeMap<String, HealthStatus>,
+}
+
+/// 按阈值和滞后规则评级
+///
+/// 分数变差时立即降级；好转时分数需要高出阈值 `hysteresis` 分才升级，
+/// 分数在阈值附近波动时状态保持不变
+pub fn rate(score: u32, config: &HealthConfig, previous: Option<HealthStatus>) -> HealthStatus {
+    let status = HealthStatus::from_score(score, config.yellow_below, config.red_below);
+    match previous {
+        Some(previous) if status < previous => {
+            let recovered = HealthStatus::from_score(
+                score,
+                config.yellow_below.saturating_add(config.hysteresis),
+                config.red_below.saturating_add(config.hysteresis),
+            );
+            recovered.min(previous)
+        }
+        _ => status,
+    }
+}
+
+/// 计算健康评分（`evaluated_at` 由调用方填写）
+pub fn evaluate(
+    metrics: &HealthMetrics,
+    config: &HealthConfig,
+    previous: &HealthState,
+) -> HealthReport {
+    let tunnel = HealthRating::new(
+        tunnel_contributions(&metrics.tunnel, config),
+        config,
+        previous.tunnel,
+    );
+    let proxies: Vec<ProxyHealth> = metrics
+        .proxies
+        .iter()
+        .map(|proxy| ProxyHealth {
+            name: proxy.name.clone(),
+            rating: HealthRating::new(
+                proxy_contributions(proxy, config),
+                config,
+                previous.proxies.get(&proxy.name).copied(),
+            ),
+        })
+        .collect();
+    let ratings = std::iter::once(&tunnel).chain(proxies.iter().map(|p| &p.rating));
+    let status = ratings
+        .clone()
+        .map(|r| r.status)
+        .max()
+        .unwrap_or(tunnel.status);
+    let score = ratings.map(|r| r.score).min().unwrap_or(tunnel.score);
+    HealthReport {
+        status,
+        score,
+        evaluated_at: 0,
+        tunnel,
+        proxies,
+    }
+}
+
+fn tunnel_contributions(tunnel: &TunnelMetrics, config: &HealthConfig) -> Vec<Contribution> {
+    let mut contributions = Vec::new();
+    if !tunnel.connected {
+        contributions.push(Contribution {
+            component: "connection".to_string(),
+            penalty: MAX_SCORE,
+            detail: "not connected to the server".to_string(),
+        });
+        return contributions;
+    }
+    if let Some(rtt) = tunnel.rtt_ms {
+        let (warn, critical) = (config.rtt_warn_ms as f64, config.rtt_critical_ms as f64);
+        if rtt > warn {
+            contributions.push(Contribution {
+                component: "heartbeat_rtt".to_string(),
+                penalty: scaled(RTT_PENALTY, (rtt - warn) / (critical - warn)),
+                detail: format!(
+                    "rtt {:.0}ms (warn {}ms, critical {}ms)",
+                    rtt, config.rtt_warn_ms, config.rtt_critical_ms
+                ),
+            });
+        }
+    }
+    if tunnel.missed_heartbeats > 0 {
+        let limit = tunnel.max_missed_heartbeats.max(1);
+        contributions.push(Contribution {
+            component: "missed_heartbeats".to_string(),
+            penalty: scaled(
+                MISSED_HEARTBEATS_PENALTY,
+                tunnel.missed_heartbeats as f64 / limit as f64,
+            ),
+            detail: format!(
+                "{} consecutive heartbeat(s) unacknowledged (reconnect after {})",
+                tunnel.missed_heartbeats, limit
+            ),
+        });
+    }
+    if tunnel.relay_memory_waits > 0 {
+        contributions.push(Contribution {
+            component: "relay_memory".to_string(),
+            penalty: RELAY_MEMORY_PENALTY,
+            detail: format!(
+                "{} relay buffer wait(s) within the window",
+                tunnel.relay_memory_waits
+            ),
+        });
+    }
+    contributions
+}
+
+fn proxy_contributions(proxy: &ProxyMetrics, config: &HealthConfig) -> Vec<Contribution> {
+    let mut contributions = Vec::new();
+    let attempts = proxy.connections + proxy.failures;
+    if proxy.failures > 0 && attempts > 0 {
+        let ratio = proxy.failures as f64 / attempts as f64;
+        let (warn, critical) = (config.failure_ratio_warn, config.failure_ratio_critical);
+        let penalty = if ratio >= critical {
+            FAILURE_RATIO_CRITICAL_PENALTY
+        } else if ratio >= warn {
+            FAILURE_RATIO_WARN_PENALTY
+                + scaled(
+                    FAILURE_RATIO_CRITICAL_PENALTY - FAILURE_RATIO_WARN_PENALTY,
+                    (ratio - warn) / (critical - warn),
+                )
+        } else {
+            0
+        };
+        if penalty > 0 {
+            contributions.push(Contribution {
+                component: "stream_failures".to_string(),
+                penalty,
+                detail: format!(
+                    "{} of {} stream(s) failed ({:.1}%, warn {:.1}%, critical {:.1}%)",
+                    proxy.failures,
+                    attempts,
+                    ratio * 100.0,
+                    warn * 100.0,
+                    critical * 100.0
+                ),
+            });
+        }
+    }
+    if proxy.accept_errors > 0 {
+        contributions.push(Contribution {
+            component: "accept_errors".to_string(),
+            penalty: ACCEPT_ERRORS_PENALTY,
+            detail: format!(
+                "{} connection(s) shed on resource exhaustion",
+                proxy.accept_errors
+            ),
+        });
+    }
+    if proxy.blacklisted_targets > 0 {
+        contributions.push(Contribution {
+            component: "fast_fail".to_string(),
+            penalty: (proxy.blacklisted_targets as u32)
+                .saturating_mul(BLACKLISTED_TARGET_PENALTY)
+                .min(BLACKLISTED_TARGETS_MAX_PENALTY),
+            detail: format!(
+                "{} target(s) blacklisted by fast fail",
+                proxy.blacklisted_targets
+            ),
+        });
+    }
+    contributions
+}
+
+/// `max` 按比例（截断到 0..=1）缩放后的扣分
+fn scaled(max: u32, fraction: f64) -> u32 {
+    if fraction.is_nan() {
+        return max;
+    }
+    (max as f64 * fraction.clamp(0.0, 1.0)).round() as u32
+}
+
+/// 一次采样的累计计数
+struct Sample {
+    at: Instant,
+    relay_memory_waits: u64,
+    proxies: BTreeMap<String, ProxyCounts>,
+}
+
+/// 单个代理的累计计数
+#[derive(Clone, Copy, Default)]
+struct ProxyCounts {
+    connections: u64,
+    failures: u64,
+    accept_errors: u64,
+}
+
+impl ProxyCounts {
+    fn of(stats: &ClientProxyStats) -> Self {
+        Self {
+            connections: stats.core.total_connections,
+            failures: stats.failures.total(),
+            accept_errors: stats.core.accept_errors,
+        }
+    }
+}
+
+#[derive(Default)]
+struct MonitorInner {
+    config: HealthConfig,
+    max_missed_heartbeats: u32,
+    samples: VecDeque<Sample>,
+    state: HealthState,
+    /// 最近一次通知的总体状态
+    notified: Option<HealthStatus>,
+}
+
+/// 健康评分监视器（跨会话保持，可以克隆）
+///
+/// 每次评估记录一次累计计数，失败率等按 `window_secs` 窗口内的增量计算
+#[derive(Clone, Default)]
+pub struct HealthMonitor {
+    inner: Arc<Mutex<MonitorInner>>,
+}
+
+impl HealthMonitor {
+    /// 设置阈值和心跳上限
+    pub fn configure(&self, config: HealthConfig, max_missed_heartbeats: u32) {
+        let mut inner = self.inner.lock();
+        inner.config = config;
+        inner.max_missed_heartbeats = max_missed_heartbeats;
+    }
+
+    /// 由统计快照计算健康评分，并记录本次状态
+    pub fn evaluate(
+        &self,
+        tunnel: &TunnelStatsSnapshot,
+        proxies: &[ClientProxyStats],
+    ) -> HealthReport {
+        let now = Instant::now();
+        let mut inner = self.inner.lock();
+        let window = inner.config.window();
+        // 保留窗口内的采样和窗口开始前的最后一个采样（作为增量的基准）
+        while inner.samples.len() > 1 && now.duration_since(inner.samples[1].at) >= window {
+            inner.samples.pop_front();
+        }
+
+        let baseline = inner.samples.front();
+        let metrics = HealthMetrics {
+            tunnel: TunnelMetrics {
+                connected: tunnel.server_addr.is_some(),
+                rtt_ms: tunnel.rtt_ms.as_ref().map(|rtt| rtt.current),
+                missed_heartbeats: tunnel.missed_heartbeats,
+                max_missed_heartbeats: inner.max_missed_heartbeats,
+                relay_memory_waits: tunnel
+                    .relay_memory
+                    .waits
+                    .saturating_sub(baseline.map_or(0, |b| b.relay_memory_waits)),
+            },
+            proxies: proxies
+                .iter()
+                .map(|p| {
+                    let counts = ProxyCounts::of(p);
+                    let base = baseline
+                        .and_then(|b| b.proxies.get(&p.core.name).copied())
+                        .unwrap_or_default();
+                    ProxyMetrics {
+                        name: p.core.name.clone(),
+                        connections: counts.connections.saturating_sub(base.connections),
+                        failures: counts.failures.saturating_sub(base.failures),
+                        accept_errors: counts.accept_errors.saturating_sub(base.accept_errors),
+                        blacklisted_targets: p.fast_fail.as_ref().map_or(0, |f| f.blacklisted),
+                    }
+                })
+                .collect(),
+        };
+
+        let mut report = evaluate(&metrics, &inner.config, &inner.state);
+        report.evaluated_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        inner.state = report.state();
+        if inner
+            .samples
+            .back()
+            .is_none_or(|last| now.duration_since(last.at) >= MIN_SAMPLE_INTERVAL)
+        {
+            inner.samples.push_back(Sample {
+                at: now,
+                relay_memory_waits: tunnel.relay_memory.waits,
+                proxies: proxies
+                    .iter()
+                    .map(|p| (p.core.name.clone(), ProxyCounts::of(p)))
+                    .collect(),
+            });
+        }
+        report
+    }
+
+    /// 总体状态与上一次通知的状态不同时返回变化（首次调用只记录状态）
+    pub fn take_transition(&self, status: HealthStatus) -> Option<HealthStatus> {
+        let mut inner = self.inner.lock();
+        let previous = inner.notified.replace(status)?;
+        (previous != status).then_some(previous)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connected() -> TunnelMetrics {
+        TunnelMetrics {
+            connected: true,
+            rtt_ms: Some(20.0),
+            missed_heartbeats: 0,
+            max_missed_heartbeats: 3,
+            relay_memory_waits: 0,
+        }
+    }
+
+    fn proxy(connections: u64, failures: u64) -> ProxyMetrics {
+        ProxyMetrics {
+            name: "web".to_string(),
+            connections,
+            failures,
+            ..Default::default()
+        }
+    }
+
+    fn metrics(tunnel: TunnelMetrics, proxies: Vec<ProxyMetrics>) -> HealthMetrics {
+        HealthMetrics { tunnel, proxies }
+    }
+
+    #[test]
+    fn test_healthy_tunnel_is_green() {
+        let report = evaluate(
+            &metrics(connected(), vec![proxy(100, 1)]),
+            &HealthConfig::default(),
+            &HealthState::default(),
+        );
+        assert_eq!(report.status, HealthStatus::Green);
+        assert_eq!(report.score, MAX_SCORE);
+        assert!(report.tunnel.contributions.is_empty());
+        assert!(report.proxies[0].rating.contributions.is_empty());
+    }
+
+    #[test]
+    fn test_disconnected_tunnel_is_red() {
+        let tunnel = TunnelMetrics {
+            connected: false,
+            ..connected()
+        };
+        let report = evaluate(
+            &metrics(tunnel, vec![]),
+            &HealthConfig::default(),
+            &HealthState::default(),
+        );
+        assert_eq!(report.status, HealthStatus::Red);
+        assert_eq!(report.score, 0);
+        assert_eq!(report.tunnel.contributions[0].component, "connection");
+    }
+
+    #[test]
+    fn test_rtt_and_missed_heartbeats_penalties() {
+        let config = HealthConfig::default();
+        // 往返时延在告警和严重阈值之间按比例扣分
+        let tunnel = TunnelMetrics {
+            rtt_ms: Some(650.0),
+            ..connected()
+        };
+        let report = evaluate(&metrics(tunnel, vec![]), &config, &HealthState::default());
+        assert_eq!(report.tunnel.score, MAX_SCORE - RTT_PENALTY / 2);
+        assert_eq!(report.status, HealthStatus::Green);
+
+        // 严重时延加上一次未确认的心跳
+        let tunnel = TunnelMetrics {
+            rtt_ms: Some(5000.0),
+            missed_heartbeats: 1,
+            ..connected()
+        };
+        let report = evaluate(&metrics(tunnel, vec![]), &config, &HealthState::default());
+        assert_eq!(report.tunnel.score, 100 - 40 - 20);
+        assert_eq!(report.status, HealthStatus::Red);
+        let components: Vec<&str> = report
+            .tunnel
+            .contributions
+            .iter()
+            .map(|c| c.component.as_str())
+            .collect();
+        assert_eq!(components, ["heartbeat_rtt", "missed_heartbeats"]);
+    }
+
+    #[test]
+    fn test_failure_ratio_penalties() {
+        let config = HealthConfig::default();
+        let score = |connections, failures| {
+            evaluate(
+                &metrics(connected(), vec![proxy(connections, failures)]),
+                &config,
+                &HealthState::default(),
+            )
+            .score
+        };
+        // 低于告警阈值不扣分
+        assert_eq!(score(97, 3), 100);
+        // 告警阈值处开始扣分，严重阈值处扣分最多
+        assert_eq!(score(95, 5), 100 - FAILURE_RATIO_WARN_PENALTY);
+        assert_eq!(score(85, 15), 100 - 35);
+        assert_eq!(score(75, 25), 100 - FAILURE_RATIO_CRITICAL_PENALTY);
+        assert_eq!(score(0, 10), 100 - FAILURE_RATIO_CRITICAL_PENALTY);
+    }
+
+    #[test]
+    fn test_shed_connections_and_blacklisted_targets() {
+        let web = ProxyMetrics {
+            accept_errors: 2,
+            blacklisted_targets: 5,
+            ..proxy(10, 0)
+        };
+        let report = evaluate(
+            &metrics(connected(), vec![web, proxy(10, 0)]),
+            &HealthConfig::default(),
+            &HealthState::default(),
+        );
+        let rating = &report.proxies[0].rating;
+        assert_eq!(
+            rating.score,
+            100 - ACCEPT_ERRORS_PENALTY - BLACKLISTED_TARGETS_MAX_PENALTY
+        );
+        assert_eq!(rating.status, HealthStatus::Yellow);
+        // 总体状态取最差的代理
+        assert_eq!(report.status, HealthStatus::Yellow);
+        assert_eq!(report.score, rating.score);
+        assert_eq!(report.tunnel.status, HealthStatus::Green);
+    }
+
+    #[test]
+    fn test_hysteresis() {
+        let config = HealthConfig::default();
+        // 变差时立即降级
+        assert_eq!(
+            rate(79, &config, Some(HealthStatus::Green)),
+            HealthStatus::Yellow
+        );
+        assert_eq!(
+            rate(10, &config, Some(HealthStatus::Green)),
+            HealthStatus::Red
+        );
+        // 好转时需要超过阈值加上 hysteresis 才升级
+        assert_eq!(
+            rate(82, &config, Some(HealthStatus::Yellow)),
+            HealthStatus::Yellow
+        );
+        assert_eq!(
+            rate(85, &config, Some(HealthStatus::Yellow)),
+            HealthStatus::Green
+        );
+        assert_eq!(
+            rate(52, &config, Some(HealthStatus::Red)),
+            HealthStatus::Red
+        );
+        assert_eq!(
+            rate(60, &config, Some(HealthStatus::Red)),
+            HealthStatus::Yellow
+        );
+        assert_eq!(
+            rate(100, &config, Some(HealthStatus::Red)),
+            HealthStatus::Green
+        );
+        // 没有上一次状态时直接按阈值评级
+        assert_eq!(rate(82, &config, None), HealthStatus::Green);
+    }
+
+    #[test]
+    fn test_hysteresis_across_evaluations() {
+        let config = HealthConfig::default();
+        let tunnel = |rtt_ms| TunnelMetrics {
+            rtt_ms: Some(rtt_ms),
+            ..connected()
+        };
+        let mut state = HealthState::default();
+        let mut statuses = Vec::new();
+        // 分数依次为 70、82、70、82、100：在阈值附近波动时保持 yellow
+        for rtt_ms in [825.0, 615.0, 825.0, 615.0, 300.0] {
+            let report = evaluate(&metrics(tunnel(rtt_ms), vec![]), &config, &state);
+            state = report.state();
+            statuses.push(report.status);
+        }
+        use HealthStatus::*;
+        assert_eq!(statuses, [Yellow, Yellow, Yellow, Yellow, Green]);
+    }
+
+    #[test]
+    fn test_monitor_uses_window_deltas() {
+        let monitor = HealthMonitor::default();
+        monitor.configure(HealthConfig::default(), 3);
+        let tunnel = TunnelStatsSnapshot {
+            server_addr: Some("127.0.0.1:8443".parse().unwrap()),
+            ..Default::default()
+        };
+        let mut stats: ClientProxyStats = serde_json::from_value(serde_json::json!({
+            "name": "web",
+            "proxy_type": "tcp",
+            "listen_addr": "127.0.0.1",
+            "listen_port": 8080,
+            "target_addr": "127.0.0.1",
+            "target_port": 80,
+            "total_connections": 100,
+            "active_connections": 0,
+            "bytes_sent": 0,
+            "bytes_received": 0,
+            "start_time": 0,
+            "status": "running",
+        }))
+        .unwrap();
+        stats.failures.server_rejected.count = 50;
+
+        // 第一次评估没有基准，按累计计数计算
+        let report = monitor.evaluate(&tunnel, std::slice::from_ref(&stats));
+        assert_eq!(report.status, HealthStatus::Red);
+        assert!(report.evaluated_at > 0);
+        assert_eq!(monitor.take_transition(report.status), None);
+
+        // 之后只计算窗口内的增量；在 red 之后需要足够高的分数才能恢复
+        stats.core.total_connections += 100;
+        let report = monitor.evaluate(&tunnel, std::slice::from_ref(&stats));
+        assert_eq!(report.score, MAX_SCORE);
+        assert_eq!(report.status, HealthStatus::Green);
+        assert_eq!(
+            monitor.take_transition(report.status),
+            Some(HealthStatus::Red)
+        );
+        assert_eq!(monitor.take_transition(report.status), None);
+    }
+}
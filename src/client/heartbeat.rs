@@ -0,0 +1,101 @@
+/// 心跳确认跟踪
+///
+/// 服务器支持 `heartbeat_ack` 能力时，客户端的每次心跳携带序号和发送时间，服务器原样回显作为确认。
+/// 发送下一次心跳时上一次仍未被确认即计为一次丢失，连续丢失达到上限说明服务器到客户端方向
+/// 已经不通（即使客户端到服务器方向的写入仍然成功），此时结束会话并重连
+use crate::control_protocol::HeartbeatParams;
+use tokio::time::{Duration, Instant};
+
+/// 默认连续丢失多少次确认后断开
+pub const DEFAULT_MAX_MISSED_HEARTBEATS: u32 = 3;
+
+/// 心跳确认跟踪器
+pub struct HeartbeatMonitor {
+    /// 会话开始时间，心跳的发送时间相对于它计算
+    epoch: Instant,
+    next_seq: u64,
+    /// 最近一次发送且尚未确认的心跳序号
+    outstanding: Option<u64>,
+    /// 连续未被确认的心跳数
+    missed: u32,
+    max_missed: u32,
+}
+
+impl HeartbeatMonitor {
+    pub fn new(max_missed: u32) -> Self {
+        Self {
+            epoch: Instant::now(),
+            next_seq: 1,
+            outstanding: None,
+            missed: 0,
+            max_missed: max_missed.max(1),
+        }
+    }
+
+    /// 准备下一次心跳：上一次心跳仍未被确认时计为一次丢失
+    ///
+    /// 返回要发送的心跳参数；连续丢失达到上限时返回 `Err(连续丢失次数)`，不再发送
+    pub fn next_heartbeat(&mut self, now: Instant) -> Result<HeartbeatParams, u32> {
+        if self.outstanding.take().is_some() {
+            self.missed += 1;
+            if self.missed >= self.max_missed {
+                return Err(self.missed);
+            }
+        }
+        let params = HeartbeatParams {
+            seq: self.next_seq,
+            timestamp_ms: now.duration_since(self.epoch).as_millis() as u64,
+        };
+        self.next_seq += 1;
+        self.outstanding = Some(params.seq);
+        Ok(params)
+    }
+
+    /// 记录一次确认，返回该心跳的往返时延
+    ///
+    /// 迟到的确认（已经发送了更新的心跳）同样说明连接双向可用，清零连续丢失计数
+    pub fn acked(&mut self, ack: HeartbeatParams, now: Instant) -> Duration {
+        if self.outstanding.is_some_and(|seq| ack.seq >= seq) {
+            self.outstanding = None;
+        }
+        self.missed = 0;
+        let sent = self.epoch + Duration::from_millis(ack.timestamp_ms);
+        now.saturating_duration_since(sent)
+    }
+
+    /// 当前连续未被确认的心跳数
+    pub fn missed(&self) -> u32 {
+        self.missed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rtt_and_missed_acks() {
+        let mut monitor = HeartbeatMonitor::new(3);
+        let start = Instant::now();
+        let interval = Duration::from_secs(30);
+
+        let first = monitor.next_heartbeat(start).unwrap();
+        assert_eq!(first.seq, 1);
+        let rtt = monitor.acked(first, start + Duration::from_millis(40));
+        assert_eq!(rtt, Duration::from_millis(40));
+
+        // 第二次心跳未及时确认（计为一次丢失），之后收到迟到的确认，连续丢失计数清零
+        let second = monitor.next_heartbeat(start + interval).unwrap();
+        monitor.next_heartbeat(start + interval * 2).unwrap();
+        assert_eq!(monitor.missed(), 1);
+        monitor.acked(second, start + interval * 2);
+        assert_eq!(monitor.missed(), 0);
+
+        // 第三次心跳仍未确认，再连续丢失两次后达到上限
+        assert!(monitor.next_heartbeat(start + interval * 3).is_ok());
+        assert_eq!(monitor.missed(), 1);
+        assert!(monitor.next_heartbeat(start + interval * 4).is_ok());
+        assert_eq!(monitor.missed(), 2);
+        assert_eq!(monitor.next_heartbeat(start + interval * 5), Err(3));
+    }
+}
@@ -0,0 +1,558 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::timeout;
+
+/// 读取 HTTP 头部时每次读取的块大小
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// 上游响应头的最大大小
+pub(super) const MAX_RESPONSE_HEAD_SIZE: usize = 65536;
+
+/// 转发定长消息体时每次读取的块大小
+const BODY_CHUNK_SIZE: usize = 16384;
+
+/// chunked 编码中 chunk 大小行和尾部字段行的最大长度
+const MAX_CHUNK_LINE_SIZE: usize = 4096;
+
+/// HTTP/1.x 消息体的边界
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum BodyFraming {
+    /// 没有消息体
+    Empty,
+    /// 由 `Content-Length` 指定长度
+    Length(u64),
+    /// `Transfer-Encoding: chunked`
+    Chunked,
+    /// 读到连接关闭为止（只用于响应，之后连接不能复用）
+    UntilClose,
+}
+
+/// 读取 HTTP 头部的结果
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum HeadRead {
+    /// 头部完整，值为头部长度（含结尾空行）
+    Complete(usize),
+    /// 头部超过大小限制
+    TooLarge,
+    /// 头部完整之前连接关闭（`buffer` 为空表示在消息之间正常关闭）
+    Eof,
+}
+
+/// 从 `reader` 读取到 HTTP 头部结束（`\r\n\r\n`）
+///
+/// `buffer` 中可能已有上一个消息之后多读的数据；头部之后多读的数据留在 `buffer` 中
+pub(super) async fn read_head<R>(
+    reader: &mut R,
+    buffer: &mut Vec<u8>,
+    max_size: usize,
+) -> std::io::Result<HeadRead>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut chunk = [0u8; READ_CHUNK_SIZE];
+    let mut search_from = 0;
+    loop {
+        let found = buffer[search_from..]
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .map(|i| search_from + i + 4);
+        match found {
+            Some(end) if end <= max_size => return Ok(HeadRead::Complete(end)),
+            None if buffer.len() < max_size => {}
+            _ => return Ok(HeadRead::TooLarge),
+        }
+
+        // 只需从上次读取末尾的前 3 个字节开始查找
+        search_from = buffer.len().saturating_sub(3);
+        let n = reader.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(HeadRead::Eof);
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+    }
+}
+
+/// 连接在消息之后是否保持（HTTP/1.1 默认保持，HTTP/1.0 需要 `keep-alive`）
+///
+/// `connection` 为 `Connection` / `Proxy-Connection` 头部的值
+pub(super) fn is_keep_alive<'a>(
+    version: &str,
+    connection: impl IntoIterator<Item = &'a str>,
+) -> bool {
+    let tokens: Vec<String> = connection
+        .into_iter()
+        .flat_map(|value| value.split(','))
+        .map(|token| token.trim().to_ascii_lowercase())
+        .collect();
+    if tokens.iter().any(|token| token == "close") {
+        return false;
+    }
+    !version.eq_ignore_ascii_case("HTTP/1.0") || tokens.iter().any(|token| token == "keep-alive")
+}
+
+/// 请求体的边界（两者都没有时没有请求体）
+///
+/// 重复的 `Content-Length` 合并为逗号分隔的列表。同时带 `Transfer-Encoding` 和 `Content-Length`，
+/// 或多个 `Content-Length` 不一致的请求边界有歧义（可被用于请求走私），按 RFC 9112 §6.3 作为错误处理
+pub(super) fn request_framing(headers: &HashMap<String, String>) -> Result<BodyFraming> {
+    let content_length = headers.get("content-length");
+    if let Some(encoding) = headers.get("transfer-encoding") {
+        if content_length.is_some() {
+            anyhow::bail!("Request has both Transfer-Encoding and Content-Length");
+        }
+        if !is_chunked(encoding) {
+            anyhow::bail!("Unsupported request Transfer-Encoding: {}", encoding);
+        }
+        return Ok(BodyFraming::Chunked);
+    }
+    let Some(length) = content_length else {
+        return Ok(BodyFraming::Empty);
+    };
+    let values = length
+        .split(',')
+        .map(|value| value.trim().parse::<u64>())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|_| anyhow::anyhow!("Invalid request Content-Length: {}", length))?;
+    if values.windows(2).any(|pair| pair[0] != pair[1]) {
+        anyhow::bail!("Conflicting request Content-Length values: {}", length);
+    }
+    Ok(BodyFraming::Length(values[0]))
+}
+
+/// 最后一个传输编码是否为 chunked
+fn is_chunked(encoding: &str) -> bool {
+    encoding
+        .rsplit(',')
+        .next()
+        .is_some_and(|last| last.trim().eq_ignore_ascii_case("chunked"))
+}
+
+/// 逐跳头部，转发响应时去除（`Connection` 由转发方按本地连接重新写入）
+fn is_hop_by_hop(name: &str) -> bool {
+    matches!(name, "connection" | "keep-alive" | "proxy-connection")
+}
+
+/// 上游的 HTTP 响应头
+#[derive(Debug)]
+pub(super) struct ResponseHead {
+    /// 状态码
+    pub(super) status: u16,
+    /// 上游连接在响应之后是否保持
+    pub(super) keep_alive: bool,
+    /// 响应体的边界
+    pub(super) framing: BodyFraming,
+    /// 状态行
+    status_line: String,
+    /// 去除逐跳头部后的头部行（保持原有顺序和重复的头部）
+    lines: Vec<String>,
+}
+
+impl ResponseHead {
+    /// 解析响应头（`head` 含结尾空行），不是 HTTP 响应时返回 None
+    ///
+    /// `request_method` 为对应请求的方法，HEAD 请求的响应没有响应体
+    pub(super) fn parse(head: &[u8], request_method: &str) -> Option<Self> {
+        let text = String::from_utf8_lossy(head);
+        let mut lines = text.split("\r\n").filter(|line| !line.is_empty());
+        let status_line = lines.next()?.to_string();
+        let mut parts = status_line.split_whitespace();
+        let version = parts.next().filter(|v| v.starts_with("HTTP/"))?;
+        let status: u16 = parts.next()?.parse().ok()?;
+
+        let mut connection = Vec::new();
+        let mut transfer_encoding = None;
+        let mut content_length = None;
+        let mut kept = Vec::new();
+        for line in lines {
+            let (name, value) = line.split_once(':').unwrap_or((line, ""));
+            let name = name.trim().to_ascii_lowercase();
+            let value = value.trim();
+            match name.as_str() {
+                "connection" => connection.push(value.to_string()),
+                "transfer-encoding" => transfer_encoding = Some(value.to_string()),
+                "content-length" => content_length = Some(value.parse::<u64>().ok()),
+                _ => {}
+            }
+            if !is_hop_by_hop(&name) {
+                kept.push(line.to_string());
+            }
+        }
+
+        let framing = if request_method.eq_ignore_ascii_case("HEAD")
+            || (100..200).contains(&status) && status != 101
+            || status == 204
+            || status == 304
+        {
+            BodyFraming::Empty
+        } else if status == 101 {
+            BodyFraming::UntilClose
+        } else if let Some(encoding) = transfer_encoding {
+            if is_chunked(&encoding) {
+                BodyFraming::Chunked
+            } else {
+                BodyFraming::UntilClose
+            }
+        } else {
+            match content_length {
+                Some(Some(length)) => BodyFraming::Length(length),
+                // 无效的 Content-Length 无法确定边界，读到连接关闭
+                Some(None) | None => BodyFraming::UntilClose,
+            }
+        };
+
+        Some(Self {
+            status,
+            keep_alive: is_keep_alive(version, connection.iter().map(String::as_str)),
+            framing,
+            status_line,
+            lines: kept,
+        })
+    }
+
+    /// 是否为 1xx 临时响应（之后还有最终响应）
+    pub(super) fn is_interim(&self) -> bool {
+        (100..200).contains(&self.status) && self.status != 101
+    }
+
+    /// 重建发给本地客户端的响应头，按 `keep_alive` 写入 `Connection`
+    pub(super) fn to_bytes(&self, keep_alive: bool) -> Vec<u8> {
+        let mut head = format!("{}\r\n", self.status_line);
+        for line in &self.lines {
+            head.push_str(line);
+            head.push_str("\r\n");
+        }
+        head.push_str(if keep_alive {
+            "Connection: keep-alive\r\n\r\n"
+        } else {
+            "Connection: close\r\n\r\n"
+        });
+        head.into_bytes()
+    }
+}
+
+/// 按 `framing` 把一个消息体从 `reader` 转发到 `writer`，返回转发的字节数
+///
+/// `buffer` 中是读取头部时多读的数据，先于 `reader` 使用；消息体之后多读的数据留在 `buffer` 中。
+/// chunked 编码的大小行和尾部字段必须在 `line_timeout` 内读完，格式错误或不完整的消息体
+/// 不会让连接一直挂起
+pub(super) async fn relay_body<R, W>(
+    reader: &mut R,
+    buffer: &mut Vec<u8>,
+    writer: &mut W,
+    framing: BodyFraming,
+    line_timeout: Duration,
+) -> Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    match framing {
+        BodyFraming::Empty => Ok(0),
+        BodyFraming::Length(length) => copy_exact(reader, buffer, writer, length).await,
+        BodyFraming::Chunked => relay_chunked(reader, buffer, writer, line_timeout).await,
+        BodyFraming::UntilClose => {
+            writer.write_all(buffer).await?;
+            let buffered = buffer.len() as u64;
+            buffer.clear();
+            Ok(buffered + tokio::io::copy(reader, writer).await?)
+        }
+    }
+}
+
+/// 转发正好 `length` 字节
+async fn copy_exact<R, W>(
+    reader: &mut R,
+    buffer: &mut Vec<u8>,
+    writer: &mut W,
+    length: u64,
+) -> Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let buffered = length.min(buffer.len() as u64) as usize;
+    writer.write_all(&buffer[..buffered]).await?;
+    buffer.drain(..buffered);
+
+    let mut remaining = length - buffered as u64;
+    if remaining > 0 {
+        let mut chunk = vec![0u8; BODY_CHUNK_SIZE];
+        while remaining > 0 {
+            let want = remaining.min(chunk.len() as u64) as usize;
+            let n = reader.read(&mut chunk[..want]).await?;
+            if n == 0 {
+                anyhow::bail!("Connection closed with {} body bytes remaining", remaining);
+            }
+            writer.write_all(&chunk[..n]).await?;
+            remaining -= n as u64;
+        }
+    }
+    Ok(length)
+}
+
+/// 转发 chunked 编码的消息体（含结尾的尾部字段）
+async fn relay_chunked<R, W>(
+    reader: &mut R,
+    buffer: &mut Vec<u8>,
+    writer: &mut W,
+    line_timeout: Duration,
+) -> Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut total = 0u64;
+    loop {
+        let line = read_line(reader, buffer, line_timeout).await?;
+        let size = parse_chunk_size(&line)?;
+        writer.write_all(&line).await?;
+        total += line.len() as u64;
+
+        if size == 0 {
+            // 尾部字段，以空行结束
+            loop {
+                let line = read_line(reader, buffer, line_timeout).await?;
+                writer.write_all(&line).await?;
+                total += line.len() as u64;
+                if line == b"\r\n" {
+                    return Ok(total);
+                }
+            }
+        }
+
+        total += copy_exact(reader, buffer, writer, size).await?;
+        let end = read_line(reader, buffer, line_timeout).await?;
+        if end != b"\r\n" {
+            anyhow::bail!("Malformed chunked body: missing CRLF after chunk data");
+        }
+        writer.write_all(&end).await?;
+        total += end.len() as u64;
+    }
+}
+
+/// 读取一行（含结尾的 `\r\n`），必须在 `line_timeout` 内读完
+async fn read_line<R>(
+    reader: &mut R,
+    buffer: &mut Vec<u8>,
+    line_timeout: Duration,
+) -> Result<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+{
+    timeout(line_timeout, async {
+        let mut chunk = [0u8; READ_CHUNK_SIZE];
+        loop {
+            if let Some(i) = buffer.windows(2).position(|w| w == b"\r\n") {
+                return Ok(buffer.drain(..i + 2).collect());
+            }
+            if buffer.len() > MAX_CHUNK_LINE_SIZE {
+                anyhow::bail!(
+                    "Malformed chunked body: line exceeds {} bytes",
+                    MAX_CHUNK_LINE_SIZE
+                );
+            }
+            let n = reader.read(&mut chunk).await?;
+            if n == 0 {
+                anyhow::bail!("Connection closed in the middle of a chunked body");
+            }
+            buffer.extend_from_slice(&chunk[..n]);
+        }
+    })
+    .await
+    .map_err(|_| anyhow::anyhow!("Chunked body line not received within {:?}", line_timeout))?
+}
+
+/// 解析 chunk 大小行（忽略 `;` 之后的扩展）
+fn parse_chunk_size(line: &[u8]) -> Result<u64> {
+    let line = String::from_utf8_lossy(line);
+    let size = line.split(';').next().unwrap_or_default().trim();
+    u64::from_str_radix(size, 16)
+        .map_err(|_| anyhow::anyhow!("Malformed chunked body: invalid chunk size '{}'", size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    async fn relay(input: &[u8], framing: BodyFraming) -> (Result<u64>, Vec<u8>, Vec<u8>) {
+        let mut reader = input;
+        let mut buffer = Vec::new();
+        let mut output = Vec::new();
+        let result = relay_body(&mut reader, &mut buffer, &mut output, framing, LINE_TIMEOUT).await;
+        let mut rest = buffer;
+        rest.extend_from_slice(reader);
+        (result, output, rest)
+    }
+
+    #[tokio::test]
+    async fn test_read_head_keeps_extra_bytes() {
+        let mut reader: &[u8] = b"GET / HTTP/1.1\r\nHost: a\r\n\r\nbody";
+        let mut buffer = Vec::new();
+        let read = read_head(&mut reader, &mut buffer, 1024).await.unwrap();
+        assert_eq!(read, HeadRead::Complete(27));
+        assert_eq!(&buffer[27..], b"body");
+
+        let mut reader: &[u8] = b"GET / HTTP/1.1\r\nHost: a\r\n\r\n";
+        let mut buffer = Vec::new();
+        assert_eq!(
+            read_head(&mut reader, &mut buffer, 16).await.unwrap(),
+            HeadRead::TooLarge
+        );
+
+        let mut reader: &[u8] = b"GET / HTTP/1.1\r\n";
+        let mut buffer = Vec::new();
+        assert_eq!(
+            read_head(&mut reader, &mut buffer, 1024).await.unwrap(),
+            HeadRead::Eof
+        );
+    }
+
+    #[tokio::test]
+    async fn test_relay_length_and_chunked_bodies() {
+        let (result, output, rest) = relay(b"hello world", BodyFraming::Length(5)).await;
+        assert_eq!(result.unwrap(), 5);
+        assert_eq!(output, b"hello");
+        assert_eq!(rest, b" world");
+
+        let body = b"5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nX-Trailer: 1\r\n\r\nNEXT";
+        let (result, output, rest) = relay(body, BodyFraming::Chunked).await;
+        assert_eq!(result.unwrap(), body.len() as u64 - 4);
+        assert_eq!(output, &body[..body.len() - 4]);
+        assert_eq!(rest, b"NEXT");
+
+        let (result, _, _) = relay(b"5\r\nhel", BodyFraming::Length(10)).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_relay_malformed_chunked_body() {
+        let (result, _, _) = relay(b"zz\r\nhello\r\n0\r\n\r\n", BodyFraming::Chunked).await;
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("invalid chunk size"));
+
+        let (result, _, _) = relay(b"5\r\nhelloXX0\r\n\r\n", BodyFraming::Chunked).await;
+        assert!(result.unwrap_err().to_string().contains("missing CRLF"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_incomplete_chunk_line_times_out() {
+        // 对端发送了不完整的大小行后不再发送数据（连接保持打开）
+        let (mut reader, mut peer) = tokio::io::duplex(64);
+        peer.write_all(b"5").await.unwrap();
+        let mut buffer = Vec::new();
+        let mut output = Vec::new();
+        let result = relay_body(
+            &mut reader,
+            &mut buffer,
+            &mut output,
+            BodyFraming::Chunked,
+            LINE_TIMEOUT,
+        )
+        .await;
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("not received within"));
+        drop(peer);
+    }
+
+    #[test]
+    fn test_request_framing() {
+        let headers = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert_eq!(request_framing(&headers(&[])).unwrap(), BodyFraming::Empty);
+        assert_eq!(
+            request_framing(&headers(&[("content-length", "12")])).unwrap(),
+            BodyFraming::Length(12)
+        );
+        assert_eq!(
+            request_framing(&headers(&[("transfer-encoding", "gzip, chunked")])).unwrap(),
+            BodyFraming::Chunked
+        );
+        // 重复但一致的 Content-Length 可以接受
+        assert_eq!(
+            request_framing(&headers(&[("content-length", "12, 12")])).unwrap(),
+            BodyFraming::Length(12)
+        );
+        assert!(request_framing(&headers(&[("content-length", "-1")])).is_err());
+        assert!(request_framing(&headers(&[("transfer-encoding", "gzip")])).is_err());
+    }
+
+    #[test]
+    fn test_request_framing_rejects_ambiguous_length() {
+        let headers = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let error = request_framing(&headers(&[
+            ("content-length", "12"),
+            ("transfer-encoding", "chunked"),
+        ]))
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("both Transfer-Encoding and Content-Length"));
+
+        let error = request_framing(&headers(&[("content-length", "12, 5")])).unwrap_err();
+        assert!(error.to_string().contains("Conflicting"));
+        assert!(request_framing(&headers(&[("content-length", "12, ")])).is_err());
+    }
+
+    #[test]
+    fn test_keep_alive() {
+        assert!(is_keep_alive("HTTP/1.1", []));
+        assert!(!is_keep_alive("HTTP/1.1", ["Close"]));
+        assert!(!is_keep_alive("HTTP/1.0", []));
+        assert!(is_keep_alive("HTTP/1.0", ["Keep-Alive"]));
+        assert!(!is_keep_alive("HTTP/1.0", ["keep-alive", "close"]));
+    }
+
+    #[test]
+    fn test_response_head() {
+        let head = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\nConnection: keep-alive\r\nKeep-Alive: timeout=5\r\n\r\n";
+        let response = ResponseHead::parse(head, "GET").unwrap();
+        assert_eq!(response.status, 200);
+        assert!(response.keep_alive);
+        assert_eq!(response.framing, BodyFraming::Length(5));
+        assert_eq!(
+            String::from_utf8(response.to_bytes(false)).unwrap(),
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\nConnection: close\r\n\r\n"
+        );
+
+        // HEAD 请求和 204/304 响应没有响应体
+        let response = ResponseHead::parse(head, "HEAD").unwrap();
+        assert_eq!(response.framing, BodyFraming::Empty);
+        let response = ResponseHead::parse(b"HTTP/1.1 304 Not Modified\r\n\r\n", "GET").unwrap();
+        assert_eq!(response.framing, BodyFraming::Empty);
+
+        let response = ResponseHead::parse(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+            "GET",
+        )
+        .unwrap();
+        assert_eq!(response.framing, BodyFraming::Chunked);
+
+        // 没有长度的响应读到连接关闭；HTTP/1.0 默认不保持连接
+        let response = ResponseHead::parse(b"HTTP/1.0 200 OK\r\n\r\n", "GET").unwrap();
+        assert_eq!(response.framing, BodyFraming::UntilClose);
+        assert!(!response.keep_alive);
+
+        let response = ResponseHead::parse(b"HTTP/1.1 100 Continue\r\n\r\n", "POST").unwrap();
+        assert!(response.is_interim());
+        assert_eq!(response.framing, BodyFraming::Empty);
+
+        assert!(ResponseHead::parse(b"GET / HTTP/1.1\r\n\r\n", "GET").is_none());
+    }
+}
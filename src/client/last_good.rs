@@ -0,0 +1,241 @@
+/// 最近一次被服务器接受的配置（last known good）
+///
+/// 服务器完全接受配置后，客户端把应用的配置以 JSON 写入状态目录的 `last_good_config.json`
+/// （先写临时文件再重命名，Unix 下权限 0600，内容包含认证密钥）。启动时配置文件无法加载或校验失败、
+/// 且开启了 `fallback_to_last_good` 时改用该缓存运行，并在后台定期重新检查配置文件，
+/// 修复后切换到配置文件中的配置
+use crate::config::{AppConfig, ClientFullConfig};
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use tokio::sync::watch;
+use tokio::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+/// 缓存文件名
+pub const LAST_GOOD_CONFIG_FILE: &str = "last_good_config.json";
+
+/// 回退运行期间重新检查配置文件的间隔
+pub const PRIMARY_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 缓存文件路径：`state_dir` 下，未配置时为配置文件所在目录（两者都没有时不缓存）
+pub fn cache_path(state_dir: Option<&Path>, source: Option<&Path>) -> Option<PathBuf> {
+    let dir = match state_dir {
+        Some(dir) => dir.to_path_buf(),
+        None => {
+            let parent = source?.parent()?;
+            if parent.as_os_str().is_empty() {
+                PathBuf::from(".")
+            } else {
+                parent.to_path_buf()
+            }
+        }
+    };
+    Some(dir.join(LAST_GOOD_CONFIG_FILE))
+}
+
+/// 保存配置（先写临时文件再重命名，避免留下写到一半的缓存）
+pub fn save(path: &Path, config: &ClientFullConfig) -> Result<()> {
+    let content =
+        serde_json::to_vec_pretty(config).context("Failed to serialize last good config")?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create state directory {}", dir.display()))?;
+    }
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::remove_file(&tmp_path).ok();
+    write_private(&tmp_path, &content)
+        .with_context(|| format!("Failed to write {}", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, path)
+        .with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+/// 读取并校验缓存的配置
+pub fn load(path: &Path) -> Result<ClientFullConfig> {
+    let content =
+        std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let config: ClientFullConfig = serde_json::from_slice(&content)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    config
+        .validate()
+        .with_context(|| format!("Cached configuration {} is invalid", path.display()))?;
+    Ok(config)
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+/// 启动时加载的客户端配置
+pub struct LoadedConfig {
+    pub config: ClientFullConfig,
+    /// 配置文件无效、改用缓存时的回退信息
+    pub fallback: Option<Fallback>,
+}
+
+/// 改用缓存配置的原因
+pub struct Fallback {
+    /// 使用的缓存文件
+    pub cache_path: PathBuf,
+    /// 配置文件的加载错误
+    pub error: anyhow::Error,
+}
+
+/// 加载配置文件，失败时按 `fallback_to_last_good` 改用缓存的配置
+///
+/// 配置文件仍是合法 TOML 时从中读取 `state_dir` 和 `fallback_to_last_good`，否则使用默认的
+/// 缓存位置并以缓存中记录的 `fallback_to_last_good` 为准。没有可用的缓存或未开启回退时返回
+/// 配置文件的加载错误
+pub fn load_with_fallback(path: &str) -> Result<LoadedConfig> {
+    let error = match AppConfig::load_client_config(path) {
+        Ok(config) => {
+            return Ok(LoadedConfig {
+                config,
+                fallback: None,
+            })
+        }
+        Err(e) => e,
+    };
+
+    let settings = raw_client_settings(Path::new(path));
+    let Some(cache_path) = cache_path(settings.state_dir.as_deref(), Some(Path::new(path))) else {
+        return Err(error);
+    };
+    let cached = match load(&cache_path) {
+        Ok(cached) => cached,
+        Err(cache_error) if cache_path.exists() => {
+            warn!("Last good configuration is unusable: {:#}", cache_error);
+            return Err(error);
+        }
+        Err(_) => return Err(error),
+    };
+    let enabled = settings
+        .fallback_to_last_good
+        .unwrap_or(cached.client.fallback_to_last_good);
+    if !enabled {
+        return Err(error);
+    }
+
+    warn!("==================================================================");
+    warn!("Configuration file {} is invalid: {:#}", path, error);
+    warn!(
+        "FALLING BACK to the last configuration accepted by the server: {}",
+        cache_path.display()
+    );
+    warn!("The configuration file is checked again in the background and applied once valid");
+    warn!("==================================================================");
+    Ok(LoadedConfig {
+        config: cached,
+        fallback: Some(Fallback { cache_path, error }),
+    })
+}
+
+/// 配置文件中与回退相关的设置（文件不是合法 TOML 或没有这些字段时为空）
+#[derive(Default)]
+struct RawSettings {
+    state_dir: Option<PathBuf>,
+    fallback_to_last_good: Option<bool>,
+}
+
+fn raw_client_settings(path: &Path) -> RawSettings {
+    let Some(table) = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| content.parse::<toml::Table>().ok())
+    else {
+        return RawSettings::default();
+    };
+    let client = table.get("client").and_then(toml::Value::as_table);
+    let field = |name: &str| client.and_then(|client| client.get(name));
+    RawSettings {
+        state_dir: field("state_dir")
+            .and_then(toml::Value::as_str)
+            .map(PathBuf::from),
+        fallback_to_last_good: field("fallback_to_last_good").and_then(toml::Value::as_bool),
+    }
+}
+
+/// 回退运行期间定期重新加载配置文件，加载并校验通过后发送到 `tx` 并结束
+pub fn spawn_primary_retry(
+    path: PathBuf,
+    interval: Duration,
+    tx: watch::Sender<Option<ClientFullConfig>>,
+    shutdown: CancellationToken,
+) {
+    tokio::spawn(async move {
+        let mut last_error = String::new();
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = shutdown.cancelled() => return,
+            }
+            match AppConfig::load_client_config(&path.to_string_lossy()) {
+                Ok(config) => {
+                    info!(
+                        "Configuration file {} is valid again, switching from the last good configuration",
+                        path.display()
+                    );
+                    tx.send_replace(Some(config));
+                    return;
+                }
+                Err(e) => {
+                    // 同一错误只记录一次
+                    let message = format!("{:#}", e);
+                    if message != last_error {
+                        error!(
+                            "Configuration file {} is still invalid: {}",
+                            path.display(),
+                            message
+                        );
+                        last_error = message;
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_path_defaults_to_config_directory() {
+        assert_eq!(
+            cache_path(None, Some(Path::new("/etc/tls-tunnel/client.toml"))),
+            Some(PathBuf::from("/etc/tls-tunnel/last_good_config.json"))
+        );
+        assert_eq!(
+            cache_path(None, Some(Path::new("client.toml"))),
+            Some(PathBuf::from("./last_good_config.json"))
+        );
+        assert_eq!(
+            cache_path(
+                Some(Path::new("/var/lib/tls-tunnel")),
+                Some(Path::new("/etc/tls-tunnel/client.toml"))
+            ),
+            Some(PathBuf::from("/var/lib/tls-tunnel/last_good_config.json"))
+        );
+        assert_eq!(cache_path(None, None), None);
+    }
+}
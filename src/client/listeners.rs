@@ -0,0 +1,330 @@
+/// 跨隧道会话的本地监听器管理
+///
+/// 开启 `keep_listening_on_disconnect` 时，visitor/forwarder 的本地监听在隧道断开后继续保持：
+/// 监听器通过一个不随会话变化的通道请求 yamux stream，由中继任务转交给当前运行中的会话；
+/// 没有运行中的会话时请求立即以 [`TUNNEL_DOWN`] 失败，本地连接被关闭而不是被拒绝。
+/// 重连后只启动尚未运行的监听器，已有的监听套接字继续使用。
+/// 配置热重载时可以按监听地址单独停止某个监听器（[`ListenerHost::stop`]）
+use crate::protocol_trace::{CurrentTrace, SessionTrace};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use tokio::time::{sleep, Duration};
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+use tracing::warn;
+
+/// 请求创建 yamux stream（由会话事件循环创建并回复）
+pub type StreamRequest = oneshot::Sender<Result<yamux::Stream>>;
+
+/// 隧道断开期间打开 stream 的错误
+pub const TUNNEL_DOWN: &str = "Tunnel is down, reconnecting to server";
+
+/// 监听地址被占用时的最大绑定次数
+const BIND_ATTEMPTS: u32 = 6;
+/// 绑定重试的初始退避时间
+const BIND_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+/// 绑定重试的最大退避时间
+const BIND_MAX_BACKOFF: Duration = Duration::from_secs(2);
+
+/// 绑定本地监听地址
+///
+/// 地址被占用或暂不可用（例如重连时旧监听套接字尚未关闭、网卡地址尚未就绪）时按指数退避重试，
+/// 超过 [`BIND_ATTEMPTS`] 次后返回最后一次的错误
+pub async fn bind_listener(host: &str, port: u16, label: &str) -> io::Result<TcpListener> {
+    let mut backoff = BIND_INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match crate::listen_addr::bind(host, port).await {
+            Ok(listener) => return Ok(listener),
+            Err(e)
+                if attempt < BIND_ATTEMPTS
+                    && matches!(
+                        e.kind(),
+                        io::ErrorKind::AddrInUse | io::ErrorKind::AddrNotAvailable
+                    ) =>
+            {
+                warn!(
+                    "{}: Failed to bind {} ({}), retrying in {:?} (attempt {}/{})",
+                    label,
+                    crate::listen_addr::format(host, port),
+                    e,
+                    backoff,
+                    attempt,
+                    BIND_ATTEMPTS
+                );
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(BIND_MAX_BACKOFF);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// 客户端的本地监听器集合
+pub struct ListenerHost {
+    /// 是否在隧道断开期间保持监听
+    keep_listening: bool,
+    /// 监听器使用的 stream 请求通道（跨会话不变）
+    stream_tx: mpsc::Sender<StreamRequest>,
+    /// 当前运行中会话的 stream 请求通道
+    link: watch::Sender<Option<mpsc::Sender<StreamRequest>>>,
+    /// 当前会话的协议跟踪
+    trace: CurrentTrace,
+    /// 客户端退出时通知保持中的监听器停止
+    shutdown_tx: broadcast::Sender<()>,
+    /// 正在运行的监听器（按监听地址登记）
+    running: Arc<Mutex<HashMap<String, Registration>>>,
+    /// 登记序号（区分同一地址先后启动的监听器）
+    next_id: AtomicU64,
+}
+
+/// 监听器的登记信息
+struct Registration {
+    id: u64,
+    /// 取消后监听器停止接受新连接
+    stop: CancellationToken,
+}
+
+impl ListenerHost {
+    /// 创建监听器集合，并启动 stream 请求的中继任务
+    pub fn new(keep_listening: bool) -> Arc<Self> {
+        let (stream_tx, mut stream_rx) = mpsc::channel::<StreamRequest>(100);
+        let (link, link_rx) = watch::channel(None::<mpsc::Sender<StreamRequest>>);
+        let (shutdown_tx, _) = broadcast::channel(1);
+
+        // 所有监听器和本集合都释放请求通道后结束
+        tokio::spawn(async move {
+            while let Some(request) = stream_rx.recv().await {
+                let session = link_rx.borrow().clone();
+                let undelivered = match session {
+                    Some(session) => session.send(request).await.err().map(|e| e.0),
+                    None => Some(request),
+                };
+                if let Some(request) = undelivered {
+                    let _ = request.send(Err(anyhow::anyhow!(TUNNEL_DOWN)));
+                }
+            }
+        });
+
+        Arc::new(Self {
+            keep_listening,
+            stream_tx,
+            link,
+            trace: CurrentTrace::default(),
+            shutdown_tx,
+            running: Arc::new(Mutex::new(HashMap::new())),
+            next_id: AtomicU64::new(0),
+        })
+    }
+
+    /// 会话进入运行状态，之后的 stream 请求交给该会话并记录到它的协议跟踪
+    pub fn attach(&self, session: mpsc::Sender<StreamRequest>, trace: SessionTrace) {
+        self.trace.set(trace);
+        self.link.send_replace(Some(session));
+    }
+
+    /// 会话结束，之后的 stream 请求立即失败
+    pub fn detach(&self) {
+        self.link.send_replace(None);
+        self.trace.set(SessionTrace::default());
+    }
+
+    /// 监听器使用的 stream 请求通道（不保持监听时直接使用会话的通道）
+    pub fn stream_tx(&self, session: &mpsc::Sender<StreamRequest>) -> mpsc::Sender<StreamRequest> {
+        if self.keep_listening {
+            self.stream_tx.clone()
+        } else {
+            session.clone()
+        }
+    }
+
+    /// 监听器使用的协议跟踪入口（不保持监听时固定为会话的跟踪）
+    pub fn trace(&self, session: &SessionTrace) -> CurrentTrace {
+        if self.keep_listening {
+            self.trace.clone()
+        } else {
+            CurrentTrace::new(session.clone())
+        }
+    }
+
+    /// 监听器的停止信号（不保持监听时随会话断开停止）
+    pub fn shutdown_rx(&self, session: &broadcast::Sender<()>) -> broadcast::Receiver<()> {
+        if self.keep_listening {
+            self.shutdown_tx.subscribe()
+        } else {
+            session.subscribe()
+        }
+    }
+
+    /// 监听器是否仍在运行（只在保持监听时跨会话记录）
+    pub fn is_running(&self, key: &str) -> bool {
+        self.keep_listening && self.running.lock().unwrap().contains_key(key)
+    }
+
+    /// 登记即将启动的监听器，保持监听且已在运行时返回 None
+    ///
+    /// 返回的登记在监听器任务结束时释放，下次会话会重新启动该监听器；
+    /// 不保持监听时新会话的登记替换上一个会话的登记
+    pub fn claim(&self, key: String) -> Option<ListenerClaim> {
+        let mut running = self.running.lock().unwrap();
+        if self.keep_listening && running.contains_key(&key) {
+            return None;
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let stop = CancellationToken::new();
+        running.insert(
+            key.clone(),
+            Registration {
+                id,
+                stop: stop.clone(),
+            },
+        );
+        Some(ListenerClaim {
+            running: self.running.clone(),
+            key,
+            id,
+            stop,
+        })
+    }
+
+    /// 停止该地址上的监听器（已建立的本地连接不受影响），返回是否有监听器在运行
+    ///
+    /// 登记立即移除，之后可以在同一地址上启动新的监听器
+    pub fn stop(&self, key: &str) -> bool {
+        match self.running.lock().unwrap().remove(key) {
+            Some(registration) => {
+                registration.stop.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// 运行中监听器的登记，释放时从集合中移除
+///
+/// 只持有登记集合而不持有 [`ListenerHost`]，客户端退出时监听器能收到停止信号
+pub struct ListenerClaim {
+    running: Arc<Mutex<HashMap<String, Registration>>>,
+    key: String,
+    id: u64,
+    stop: CancellationToken,
+}
+
+impl ListenerClaim {
+    /// 监听器被 [`ListenerHost::stop`] 停止时完成
+    pub fn stopped(&self) -> WaitForCancellationFutureOwned {
+        self.stop.clone().cancelled_owned()
+    }
+}
+
+impl Drop for ListenerClaim {
+    fn drop(&mut self) {
+        let mut running = self.running.lock().unwrap();
+        // 同一地址已由新的监听器登记时保留新的登记
+        if running.get(&self.key).is_some_and(|r| r.id == self.id) {
+            running.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stream_request_fails_fast_without_session() {
+        let host = ListenerHost::new(true);
+        let (session_tx, _session_rx) = mpsc::channel(1);
+        let stream_tx = host.stream_tx(&session_tx);
+
+        let (response_tx, response_rx) = oneshot::channel();
+        stream_tx.send(response_tx).await.unwrap();
+        let err = response_rx.await.unwrap().unwrap_err();
+        assert_eq!(err.to_string(), TUNNEL_DOWN);
+    }
+
+    #[tokio::test]
+    async fn test_stream_request_relayed_to_attached_session() {
+        let host = ListenerHost::new(true);
+        let (session_tx, mut session_rx) = mpsc::channel(1);
+        let stream_tx = host.stream_tx(&session_tx);
+        host.attach(session_tx, SessionTrace::default());
+
+        let (response_tx, _response_rx) = oneshot::channel();
+        stream_tx.send(response_tx).await.unwrap();
+        assert!(session_rx.recv().await.is_some());
+
+        // 会话结束后请求不再转交
+        host.detach();
+        drop(session_rx);
+        let (response_tx, response_rx) = oneshot::channel();
+        stream_tx.send(response_tx).await.unwrap();
+        assert!(response_rx.await.unwrap().is_err());
+    }
+
+    #[test]
+    fn test_claim_only_once_while_running() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let _guard = runtime.enter();
+
+        let host = ListenerHost::new(true);
+        let claim = host.claim("127.0.0.1:1080".to_string()).unwrap();
+        assert!(host.is_running("127.0.0.1:1080"));
+        assert!(host.claim("127.0.0.1:1080".to_string()).is_none());
+        drop(claim);
+        assert!(!host.is_running("127.0.0.1:1080"));
+        assert!(host.claim("127.0.0.1:1080".to_string()).is_some());
+
+        // 不保持监听时每个会话都重新启动
+        let host = ListenerHost::new(false);
+        let _first = host.claim("127.0.0.1:1080".to_string()).unwrap();
+        assert!(host.claim("127.0.0.1:1080".to_string()).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_stop_single_listener() {
+        let host = ListenerHost::new(true);
+        let old = host.claim("127.0.0.1:1080".to_string()).unwrap();
+        let other = host.claim("127.0.0.1:1081".to_string()).unwrap();
+
+        assert!(host.stop("127.0.0.1:1080"));
+        tokio::time::timeout(Duration::from_secs(1), old.stopped())
+            .await
+            .unwrap();
+        assert!(!host.is_running("127.0.0.1:1080"));
+        assert!(host.is_running("127.0.0.1:1081"));
+        assert!(!host.stop("127.0.0.1:1080"));
+
+        // 停止后立即可以在同一地址启动新的监听器，旧监听器任务结束时不影响新的登记
+        let new = host.claim("127.0.0.1:1080".to_string()).unwrap();
+        drop(old);
+        assert!(host.is_running("127.0.0.1:1080"));
+        drop(new);
+        assert!(!host.is_running("127.0.0.1:1080"));
+        drop(other);
+    }
+
+    #[tokio::test]
+    async fn test_bind_listener_retries_until_address_released() {
+        let occupied = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = occupied.local_addr().unwrap();
+
+        let release = tokio::spawn(async move {
+            sleep(Duration::from_millis(250)).await;
+            drop(occupied);
+        });
+        let listener = bind_listener("127.0.0.1", addr.port(), "Forwarder 'test'")
+            .await
+            .unwrap();
+        assert_eq!(listener.local_addr().unwrap(), addr);
+        release.await.unwrap();
+    }
+}
@@ -1,34 +1,83 @@
+mod check_remote;
 mod config;
 mod connection;
 mod control_channel;
+mod egress;
+mod events;
+mod forward_report;
 mod forwarder;
 mod geoip;
+mod header_rules;
+mod health;
+mod heartbeat;
+mod http_message;
+mod last_good;
+mod listeners;
+mod proxy_retry;
+mod quota;
+mod reload;
+mod routing_ui;
+mod running_config;
 mod stats;
 mod stream;
+mod suspend;
+mod udp;
+mod upgrade;
 mod visitor;
-
-use crate::config::{ClientFullConfig, ProxyType};
-use crate::connection_pool::ConnectionPool;
-use crate::transport::create_transport_client;
+mod visitor_gateway;
+mod visitor_mux;
+
+use crate::config::{ClientFullConfig, ForwarderConfig, ProxyConfig, ProxyType, VisitorConfig};
+use crate::connection_pool::{ConnectionPool, PoolConfig};
+use crate::control_protocol::{AssignedPort, ProxyRef, RejectedEntry, RejectedKind};
+use crate::identity::ClientIdentity;
+use crate::protocol::PeerIdentity;
+use crate::protocol_trace::{ProtocolTrace, SessionTrace, TraceSide};
+use crate::shutdown::ShutdownSignals;
+use crate::systemd::{self, Watchdog};
+use crate::transport::{create_transport_client, limit_write_chunk, TransportClient};
+use crate::yamux_driver::{OutboundQueue, YamuxEvent};
 use ::yamux::{Config as YamuxConfig, Connection as YamuxConnection, Mode as YamuxMode};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use futures::future::poll_fn;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
-use tokio::time::{interval, sleep, Duration};
+use tokio::sync::{broadcast, watch};
+use tokio::time::{interval, sleep, Duration, Instant};
 use tokio_rustls::TlsConnector;
 use tokio_util::compat::TokioAsyncReadCompatExt;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
-use config::get_reconnect_delay;
-use connection::get_pool_config;
+use config::{Backoff, BACKOFF_RESET_AFTER};
+use connection::{get_pool_config, resolve_pool_config, LocalBackend, LocalTargets};
+use control_channel::UpdateOrigin;
+use forward_report::FORWARD_REPORT_INTERVAL;
+use heartbeat::{HeartbeatMonitor, DEFAULT_MAX_MISSED_HEARTBEATS};
+use routing_ui::{RoutingManager, RoutingOverrides, RoutingUi};
+use running_config::RunningConfig;
 use stream::handle_stream;
+use suspend::SuspendDetector;
 use visitor::run_visitor_listener;
 
+pub use check_remote::{check_remote, RemoteCheckItem, RemoteCheckReport};
+pub use events::{SessionEvent, SESSION_EVENT_CAPACITY};
 pub use forwarder::ForwarderHandler;
+pub use health::{Contribution, HealthRating, HealthReport, HealthStatus, ProxyHealth};
+pub use last_good::{load_with_fallback, Fallback, LoadedConfig, LAST_GOOD_CONFIG_FILE};
+pub use stats::ClientProxyStats;
 pub use visitor::VisitorHandler;
 
+/// 收到退出信号时的会话断开原因
+const SHUTDOWN_REASON: &str = "Client shutting down";
+
+/// 检测到系统从休眠中唤醒时的会话断开原因
+const RESUME_REASON: &str = "Session invalidated after system resume";
+
+/// 连续多次心跳未被服务器确认时的会话断开原因
+const HEARTBEAT_TIMEOUT_REASON: &str = "Heartbeats not acknowledged by server";
+
 /// 代理处理器状态
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum HandlerStatus {
@@ -151,11 +200,230 @@ impl Default for ProxyManager {
 }
 
 /// 运行客户端（带自动重连）
+///
+/// 收到 Ctrl+C、SIGTERM 或 SIGQUIT 后关闭会话并返回
 pub async fn run_client(config: ClientFullConfig, tls_connector: TlsConnector) -> Result<()> {
+    let (events, _) = broadcast::channel(SESSION_EVENT_CAPACITY);
+    let signals = ShutdownSignals::new()?;
+    run_client_inner(config, None, tls_connector, events, Some(signals), false).await
+}
+
+/// 运行客户端（带自动重连），并将会话生命周期事件发布到 `events`
+///
+/// 调用方在启动前通过 `events.subscribe()` 订阅即可收到完整的事件序列。
+/// 不处理进程信号（嵌入方自行决定何时停止）
+pub async fn run_client_with_events(
+    config: ClientFullConfig,
+    tls_connector: TlsConnector,
+    events: broadcast::Sender<SessionEvent>,
+) -> Result<()> {
+    run_client_inner(config, None, tls_connector, events, None, false).await
+}
+
+/// 运行从 `source` 文件加载的配置（带自动重连）
+///
+/// 统计服务器的 /config/diff 端点会重新读取该文件并与运行中的配置比较；
+/// 收到 SIGHUP（Unix）或开启 `watch_config` 时重新加载该文件，不断开会话即可应用
+/// proxies、visitors 和 forwarders 的增删改。
+/// 收到 Ctrl+C、SIGTERM 或 SIGQUIT 后关闭会话并返回
+pub async fn run_client_with_source(
+    config: ClientFullConfig,
+    source: std::path::PathBuf,
+    tls_connector: TlsConnector,
+) -> Result<()> {
+    let (events, _) = broadcast::channel(SESSION_EVENT_CAPACITY);
+    let signals = ShutdownSignals::new()?;
+    run_client_inner(
+        config,
+        Some(source),
+        tls_connector,
+        events,
+        Some(signals),
+        false,
+    )
+    .await
+}
+
+/// 以最近一次被服务器接受的配置运行（`source` 配置文件无效时，见 [`load_with_fallback`]）
+///
+/// 后台定期重新加载 `source`，加载并校验通过后关闭当前会话，以新配置重新连接，
+/// 之后与 [`run_client_with_source`] 一样支持热重载。
+/// 统计服务器、身份密钥、路由规则等进程级设置仍使用启动时的配置，重启后生效
+pub async fn run_client_from_last_good(
+    config: ClientFullConfig,
+    source: std::path::PathBuf,
+    tls_connector: TlsConnector,
+) -> Result<()> {
+    let (events, _) = broadcast::channel(SESSION_EVENT_CAPACITY);
+    let signals = ShutdownSignals::new()?;
+    run_client_inner(
+        config,
+        Some(source),
+        tls_connector,
+        events,
+        Some(signals),
+        true,
+    )
+    .await
+}
+
+/// 定期计算健康评分，总体状态变化时发布 `HealthChanged` 事件
+fn spawn_health_notifier(
+    period: Duration,
+    stats_manager: stats::ClientStatsManager,
+    events: broadcast::Sender<SessionEvent>,
+    shutdown: CancellationToken,
+) {
+    tokio::spawn(async move {
+        // 第一次评估推迟一个周期，避免把连接建立之前的状态当作基准
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.cancelled() => break,
+            }
+            let report = stats_manager.health_report();
+            if let Some(previous) = stats_manager.health().take_transition(report.status) {
+                info!(
+                    "Tunnel health changed from {} to {} (score {})",
+                    previous, report.status, report.score
+                );
+                events::emit(
+                    &events,
+                    SessionEvent::HealthChanged {
+                        previous,
+                        current: report.status,
+                        score: report.score,
+                    },
+                );
+            }
+        }
+    });
+}
+
+/// 收到退出信号时取消 `shutdown`（`shutdown` 被取消后监听任务随之结束）
+fn spawn_signal_watcher(mut signals: ShutdownSignals, shutdown: CancellationToken) {
+    tokio::spawn(async move {
+        tokio::select! {
+            name = signals.recv() => {
+                info!("Received {}, stopping client...", name);
+                systemd::notify_stopping();
+                shutdown.cancel();
+            }
+            _ = shutdown.cancelled() => {}
+        }
+    });
+}
+
+async fn run_client_inner(
+    mut config: ClientFullConfig,
+    source: Option<std::path::PathBuf>,
+    tls_connector: TlsConnector,
+    events: broadcast::Sender<SessionEvent>,
+    signals: Option<ShutdownSignals>,
+    retry_primary: bool,
+) -> Result<()> {
+    // 退出信号取消该令牌；客户端返回（或被中止）时同样取消，结束信号监听任务
+    let shutdown = CancellationToken::new();
+    let _shutdown_guard = shutdown.clone().drop_guard();
+    if let Some(signals) = signals {
+        spawn_signal_watcher(signals, shutdown.clone());
+    }
+
+    // 以缓存的配置运行时，后台等待配置文件修复
+    let mut primary = match (&source, retry_primary) {
+        (Some(path), true) => {
+            let (tx, rx) = tokio::sync::watch::channel(None);
+            last_good::spawn_primary_retry(
+                path.clone(),
+                last_good::PRIMARY_RETRY_INTERVAL,
+                tx,
+                shutdown.clone(),
+            );
+            Some(rx)
+        }
+        _ => None,
+    };
+
+    // 客户端身份跨会话保持不变（首次运行时生成并保存到 identity_path）
+    let identity = Arc::new(ClientIdentity::for_config(
+        config.client.identity_path.as_deref(),
+        source.as_deref(),
+    )?);
+    info!("Client identity: {}", identity.short_fingerprint());
+
+    // 记录加载的配置版本（合并路由覆盖之前，与磁盘上的文件对应）
+    let running_config = RunningConfig::new(config.clone(), source.clone());
+    let generation = running_config.generation();
+    info!(
+        "Configuration generation {} (sha256 {})",
+        generation.generation,
+        &generation.hash[..12]
+    );
+
+    // 从配置文件运行时支持热重载（以缓存的配置运行时等配置文件修复后再开始）
+    let (reload_tx, reload_rx) = watch::channel(generation.generation);
+    let spawn_reload = |path: &std::path::Path, config: &ClientFullConfig| {
+        reload::spawn_config_watcher(
+            path.to_path_buf(),
+            config.client.watch_config,
+            running_config.clone(),
+            reload_tx.clone(),
+            shutdown.clone(),
+        );
+    };
+    if let (Some(path), false) = (&source, retry_primary) {
+        spawn_reload(path, &config);
+    }
+    let mut synced_generation = generation.generation;
+
+    // 合并路由规则覆盖文件（不修改主配置文件）
+    let overrides = overrides_for(&config)?;
+    overrides.apply(&mut config.forwarders);
+
+    // 路由器跨会话共享，在线修改的规则在重连后依然有效
+    let routing = Arc::new(RoutingManager::new(
+        &config.forwarders,
+        overrides,
+        config.client.routing_overrides_path.clone(),
+    ));
+
+    // 将事件镜像到 events_socket（如果配置了）
+    if let Some(ref target) = config.client.events_socket {
+        events::spawn_event_mirror(target.clone(), &events);
+    }
+
     // 创建客户端统计管理器
     let stats_manager = stats::ClientStatsManager::new();
+    stats_manager
+        .relay_memory()
+        .set_budget_mb(config.client.relay_memory_budget_mb);
+    let health = config.health();
+    stats_manager.health().configure(
+        health,
+        config
+            .client
+            .max_missed_heartbeats
+            .unwrap_or(DEFAULT_MAX_MISSED_HEARTBEATS),
+    );
+    if health.notify {
+        spawn_health_notifier(
+            health.interval(),
+            stats_manager.clone(),
+            events.clone(),
+            shutdown.clone(),
+        );
+    }
 
     // 如果配置了统计端口，启动统计 HTTP 服务器
+    let limits = config.client.stats_limits.clone().unwrap_or_default();
+    let routing_ui = || {
+        config
+            .client
+            .routing_ui_token
+            .clone()
+            .map(|token| RoutingUi::new(routing.clone(), token))
+    };
     if let Some(stats_port) = config.client.stats_port {
         let stats_addr = config
             .client
@@ -163,59 +431,337 @@ pub async fn run_client(config: ClientFullConfig, tls_connector: TlsConnector) -
             .clone()
             .unwrap_or_else(|| "0.0.0.0".to_string());
         let manager = stats_manager.clone();
+        let limits = limits.clone();
+        let routing_ui = routing_ui();
+        let running_config = running_config.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = stats::start_client_stats_server(
+                stats_addr,
+                stats_port,
+                manager,
+                limits,
+                routing_ui,
+                running_config,
+            )
+            .await
+            {
+                error!("Client stats server stopped: {:#}", e);
+            }
+        });
+    }
 
+    // 本地 IPC 监听（Unix 套接字 / Windows 命名管道），与统计端口相互独立
+    #[cfg(unix)]
+    if let Some(path) = config.client.stats_socket.clone() {
+        let manager = stats_manager.clone();
+        let limits = limits.clone();
+        let routing_ui = routing_ui();
+        let running_config = running_config.clone();
+        tokio::spawn(async move {
+            if let Err(e) =
+                stats::start_client_stats_socket(path, manager, limits, routing_ui, running_config)
+                    .await
+            {
+                error!("Client stats socket stopped: {:#}", e);
+            }
+        });
+    }
+    #[cfg(windows)]
+    if let Some(name) = config.client.stats_pipe.clone() {
+        let manager = stats_manager.clone();
+        let limits = limits.clone();
+        let routing_ui = routing_ui();
+        let running_config = running_config.clone();
         tokio::spawn(async move {
-            if let Err(e) = stats::start_client_stats_server(stats_addr, stats_port, manager).await
+            if let Err(e) =
+                stats::start_client_stats_pipe(name, manager, limits, routing_ui, running_config)
+                    .await
             {
-                error!("Client stats server error: {}", e);
+                error!("Client stats pipe stopped: {:#}", e);
             }
         });
     }
 
+    // 协议跟踪在重连之间共享同一个文件，每次会话单独编号
+    let trace = ProtocolTrace::from_config(
+        config.client.protocol_trace_path.as_deref(),
+        TraceSide::Client,
+    );
+
+    // visitor/forwarder 本地监听器，开启 keep_listening_on_disconnect 时跨会话保持
+    let listeners = listeners::ListenerHost::new(config.client.keep_listening_on_disconnect);
+
+    // 传输层客户端跨会话保持：重连时优先连接上次成功的服务器地址，跳过最近失败的地址
+    let mut transport_client = create_transport_client(&config.client, tls_connector.clone())
+        .context("Failed to create transport client")?;
+
+    // 连续未能进入运行状态的重连次数
+    let mut attempt = 0u32;
+    // 重连等待时间：连续失败时按倍数增长，会话稳定运行一段时间后重置
+    let mut backoff = Backoff::from_timeouts(&config.client.timeouts());
+    // 会话之外（连接失败、等待重连）同样需要通知看门狗
+    let mut watchdog = Watchdog::from_env();
+
     loop {
+        // 会话之间重新加载的配置在本次会话中生效
+        let current = running_config.generation().generation;
+        if current != synced_generation {
+            reload_between_sessions(
+                &mut config,
+                &running_config.config(),
+                &routing,
+                &listeners,
+                &stats_manager,
+            );
+            synced_generation = current;
+        }
+
         info!("Starting TLS tunnel client...");
+        events::emit(&events, SessionEvent::Connecting);
+        let session_started = Instant::now();
+
+        // 会话期间的热重载通知（只通知本次会话开始之后的重新加载）
+        let reload = source.as_ref().map(|_| {
+            let mut rx = reload_rx.clone();
+            rx.mark_unchanged();
+            rx
+        });
 
-        match run_client_session(config.clone(), tls_connector.clone(), stats_manager.clone()).await
-        {
-            Ok(_) => {
+        // 配置文件修复后只结束当前会话，不退出客户端
+        let session_shutdown = shutdown.child_token();
+        let result = {
+            let session = run_client_session(
+                config.clone(),
+                &transport_client,
+                stats_manager.clone(),
+                routing.clone(),
+                events.clone(),
+                running_config.clone(),
+                reload,
+                &identity,
+                &trace,
+                &listeners,
+                &session_shutdown,
+            );
+            tokio::pin!(session);
+            tokio::select! {
+                result = &mut session => result,
+                _ = primary_ready(&mut primary) => {
+                    info!("Closing session to apply the repaired configuration file");
+                    session_shutdown.cancel();
+                    session.await
+                }
+            }
+        };
+        let session_end = match result {
+            Ok(end) => {
                 info!("Client session ended normally");
+                end
             }
             Err(e) => {
                 error!("Client session error: {:#}", e);
+                SessionEnd {
+                    reason: format!("{:#}", e),
+                    was_running: false,
+                    resumed: false,
+                }
+            }
+        };
+        stats_manager.tunnel().set_server_addr(None);
+        events::emit(&events, SessionEvent::Disconnected(session_end.reason));
+        if shutdown.is_cancelled() {
+            info!("Client stopped");
+            return Ok(());
+        }
+
+        // 切换到修复后的配置文件并立即重连
+        if let Some(repaired) = primary.as_ref().and_then(|rx| rx.borrow().clone()) {
+            primary = None;
+            transport_client = create_transport_client(&repaired.client, tls_connector.clone())
+                .context("Failed to create transport client")?;
+            running_config.apply(repaired.clone());
+            if let Some(path) = &source {
+                spawn_reload(path, &repaired);
+            }
+            config = repaired;
+            overrides_for(&config)?.apply(&mut config.forwarders);
+            synced_generation = running_config.generation().generation;
+            info!(
+                "Switched to configuration file (generation {})",
+                synced_generation
+            );
+            attempt = 0;
+            backoff = Backoff::from_timeouts(&config.client.timeouts());
+            continue;
+        }
+
+        attempt = if session_end.was_running {
+            1
+        } else {
+            attempt + 1
+        };
+        if session_end.was_running && session_started.elapsed() >= BACKOFF_RESET_AFTER {
+            backoff.reset();
+        }
+        // 唤醒后立即重连
+        let delay = if session_end.resumed {
+            Duration::ZERO
+        } else {
+            backoff.next_delay()
+        };
+        events::emit(&events, SessionEvent::Reconnecting { attempt, delay });
+        if session_end.resumed {
+            info!("Reconnecting immediately after system resume");
+            continue;
+        }
+        warn!(
+            "Connection lost, reconnecting in {:.1} seconds...",
+            delay.as_secs_f64()
+        );
+        let reconnect = sleep(delay);
+        tokio::pin!(reconnect);
+        loop {
+            tokio::select! {
+                _ = &mut reconnect => break,
+                _ = watchdog.keepalive() => {}
+                _ = shutdown.cancelled() => {
+                    info!("Client stopped");
+                    return Ok(());
+                }
             }
         }
+    }
+}
+
+/// 读取配置的路由规则覆盖文件（未配置时为空）
+fn overrides_for(config: &ClientFullConfig) -> Result<RoutingOverrides> {
+    match config.client.routing_overrides_path {
+        Some(ref path) => {
+            let overrides = RoutingOverrides::load(path)?;
+            if !overrides.forwarders.is_empty() {
+                info!("Loaded routing overrides from {}", path.display());
+            }
+            Ok(overrides)
+        }
+        None => Ok(RoutingOverrides::default()),
+    }
+}
 
-        let delay = get_reconnect_delay();
-        warn!("Connection lost, reconnecting in {} seconds...", delay);
-        sleep(Duration::from_secs(delay)).await;
+/// 应用会话之间重新加载的配置（proxies、visitors 和 forwarders）
+///
+/// 停止删除或修改的 visitor/forwarder 监听器（保持监听时它们跨会话运行），下次会话按新配置启动；
+/// 删除的条目同时移除统计，修改的 forwarder 重新创建路由器
+fn reload_between_sessions(
+    config: &mut ClientFullConfig,
+    reloaded: &ClientFullConfig,
+    routing: &RoutingManager,
+    listeners: &listeners::ListenerHost,
+    stats_manager: &stats::ClientStatsManager,
+) {
+    let mut target = reload::merge(config, reloaded);
+    match overrides_for(&target) {
+        Ok(overrides) => overrides.apply(&mut target.forwarders),
+        Err(e) => warn!("Failed to load routing overrides: {:#}", e),
+    }
+    let plan = reload::ReloadPlan::new(config, &target);
+    if !plan.is_empty() {
+        info!("Applying reloaded configuration ({})", plan.summary());
+    }
+    for proxy in &plan.removed_proxies {
+        stats_manager.remove_tracker(&proxy.name);
+    }
+    for visitor in &plan.removed_visitors {
+        listeners.stop(&listener_key(&visitor.bind_addr, visitor.bind_port));
+        if !config.proxies.iter().any(|p| p.name == visitor.name) {
+            stats_manager.remove_tracker(&visitor.name);
+        }
+    }
+    for forwarder in &plan.removed_forwarders {
+        listeners.stop(&listener_key(&forwarder.bind_addr, forwarder.bind_port));
+        stats_manager.remove_tracker(&forwarder.name);
+        routing.remove(&forwarder.name);
+    }
+    for forwarder in &plan.added_forwarders {
+        routing.replace(forwarder);
+    }
+    *config = target;
+}
+
+/// 等待配置热重载（不是从配置文件运行时永不返回）
+async fn reload_requested(reload: &mut Option<watch::Receiver<u64>>) {
+    match reload {
+        Some(rx) => {
+            if rx.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// 等待修复后的配置文件（没有等待中的配置文件时永不返回）
+async fn primary_ready(
+    primary: &mut Option<tokio::sync::watch::Receiver<Option<ClientFullConfig>>>,
+) {
+    match primary {
+        Some(rx) => {
+            if rx.wait_for(Option::is_some).await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+        None => std::future::pending().await,
     }
 }
 
+/// 单次会话的结束信息
+struct SessionEnd {
+    /// 断开原因
+    reason: String,
+    /// 会话是否曾进入运行状态
+    was_running: bool,
+    /// 是否因系统从休眠中唤醒而结束（立即重连，不等待重连延迟）
+    resumed: bool,
+}
+
 /// 运行单次客户端会话
+#[allow(clippy::too_many_arguments)]
 async fn run_client_session(
     config: ClientFullConfig,
-    tls_connector: TlsConnector,
+    transport_client: &Arc<dyn TransportClient>,
     stats_manager: stats::ClientStatsManager,
-) -> Result<()> {
+    routing: Arc<RoutingManager>,
+    events: broadcast::Sender<SessionEvent>,
+    running_config: RunningConfig,
+    reload: Option<watch::Receiver<u64>>,
+    identity: &Arc<ClientIdentity>,
+    trace: &ProtocolTrace,
+    listeners: &Arc<listeners::ListenerHost>,
+    shutdown: &CancellationToken,
+) -> Result<SessionEnd> {
     let client_config = &config.client;
     info!(
         "Connecting to {}:{} using {} transport",
         client_config.server_addr, client_config.server_port, client_config.transport
     );
 
-    // 创建传输层客户端
-    let transport_client = create_transport_client(client_config, tls_connector)
-        .context("Failed to create transport client")?;
-
     info!(
         "Using transport type: {}",
         transport_client.transport_type()
     );
 
-    // 通过传输层连接到服务器
-    let transport_stream = transport_client
-        .connect()
-        .await
+    // 通过传输层连接到服务器（连接期间收到退出信号时直接结束）
+    let connect = tokio::select! {
+        result = transport_client.connect() => result,
+        _ = shutdown.cancelled() => {
+            return Ok(SessionEnd {
+                reason: SHUTDOWN_REASON.to_string(),
+                was_running: false,
+                resumed: false,
+            });
+        }
+    };
+    let transport_stream = connect
         .with_context(|| {
             format!(
                 "Failed to connect to server via {} transport.\n  \nTroubleshooting tips:\n  - Check if server is running and accessible\n  - Verify server address and port are correct\n  - For TLS: ensure certificate is valid or set skip_verify = true\n  - Check network connectivity and firewall rules",
@@ -227,9 +773,44 @@ async fn run_client_session(
         "Connected to server via {} transport",
         transport_client.transport_type()
     );
+    if let Some(server_addr) = transport_client.server_addr() {
+        stats_manager.tunnel().set_server_addr(Some(server_addr));
+        events::emit(&events, SessionEvent::Connected(server_addr));
+    }
+
+    run_session_over(
+        transport_stream,
+        config,
+        stats_manager,
+        routing,
+        events,
+        running_config,
+        reload,
+        identity,
+        trace,
+        listeners,
+        shutdown,
+    )
+    .await
+}
 
-    // 将 Pin<Box<dyn Transport>> 转换为可用的流
-    let tls_stream = transport_stream;
+/// 在已建立的传输层连接上运行会话（认证、提交配置、事件循环）
+#[allow(clippy::too_many_arguments)]
+async fn run_session_over(
+    transport_stream: std::pin::Pin<Box<dyn crate::transport::Transport>>,
+    config: ClientFullConfig,
+    stats_manager: stats::ClientStatsManager,
+    routing: Arc<RoutingManager>,
+    events: broadcast::Sender<SessionEvent>,
+    running_config: RunningConfig,
+    reload: Option<watch::Receiver<u64>>,
+    identity: &Arc<ClientIdentity>,
+    trace: &ProtocolTrace,
+    listeners: &Arc<listeners::ListenerHost>,
+    shutdown: &CancellationToken,
+) -> Result<SessionEnd> {
+    // 按配置限制单次写入（TLS 记录）大小
+    let tls_stream = limit_write_chunk(transport_stream, config.client.max_write_chunk);
 
     info!("Transport connection established");
 
@@ -248,7 +829,11 @@ async fn run_client_session(
     info!("Control stream created");
 
     // 创建控制通道
+    let trace = trace.session();
     let (control_channel, event_rx) = control_channel::ClientControlChannel::new(config.clone());
+    let control_channel = control_channel
+        .with_trace(trace.clone())
+        .with_identity(identity.clone());
     let config = Arc::new(config);
 
     // 创建channel用于visitor请求新的yamux stream
@@ -259,28 +844,78 @@ async fn run_client_session(
     let (shutdown_tx, _shutdown_rx) = tokio::sync::broadcast::channel::<()>(1);
 
     // 创建心跳定时器
-    let mut heartbeat_interval = interval(Duration::from_secs(30));
+    let mut heartbeat_interval = interval(config.client.timeouts().heartbeat_interval());
     heartbeat_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
 
+    // 直连报告的发送间隔（服务器支持 forward_report 时每秒最多发送一条）
+    let mut report_interval = interval(FORWARD_REPORT_INTERVAL);
+    report_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let forward_reports = stats_manager.forward_reports().clone();
+
+    let heartbeat = HeartbeatMonitor::new(
+        config
+            .client
+            .max_missed_heartbeats
+            .unwrap_or(DEFAULT_MAX_MISSED_HEARTBEATS),
+    );
+
     // 创建客户端世界对象
     let world = ClientWorld {
         yamux_conn,
         event_rx,
         visitor_stream_rx,
+        outbound: OutboundQueue::new(),
         config,
         stats_manager,
+        routing,
         visitor_stream_tx,
         shutdown_tx,
         state: ClientState::Authenticating,
         heartbeat_interval,
+        heartbeat,
+        heartbeat_ack: false,
+        report_interval,
+        forward_report: false,
         proxy_pools: None,
+        unassigned_pools: HashMap::new(),
+        assigned_ports: HashMap::new(),
+        events,
+        was_running: false,
+        peer_identity: PeerIdentity::Unsupported,
+        incremental_config: false,
+        proxy_drain: false,
+        private_proxies: false,
+        sni_routing: false,
+        visitor_mux: false,
+        idle_keepalive: false,
+        peer_report: false,
+        integrity_check: false,
+        visitor_any_port: false,
+        auto_publish_port: false,
+        proxy_retry: None,
+        running_config,
+        reload,
+        reload_visitors: Vec::new(),
+        trace: trace.clone(),
+        listeners: listeners.clone(),
+        shutdown: shutdown.clone(),
+        watchdog: Watchdog::from_env(),
+        suspend: SuspendDetector::new(),
     };
 
     // 运行统一事件循环
-    run_client_event_loop(world, control_stream, control_channel).await?;
+    let session_end = run_client_event_loop(world, control_stream, control_channel).await;
+    listeners.detach();
+    // 会话结束后不再收集直连报告（未发送的报告丢弃）
+    forward_reports.set_enabled(false);
+    match &session_end {
+        Ok(end) => trace.end(&end.reason),
+        Err(e) => trace.end(&format!("{:#}", e)),
+    }
+    let session_end = session_end?;
 
     info!("Client disconnected");
-    Ok(())
+    Ok(session_end)
 }
 
 /// 客户端状态
@@ -300,14 +935,71 @@ struct ClientWorld {
     event_rx: tokio::sync::mpsc::UnboundedReceiver<control_channel::ControlEvent>,
     visitor_stream_rx:
         tokio::sync::mpsc::Receiver<tokio::sync::oneshot::Sender<Result<yamux::Stream>>>,
+    /// 等待创建 stream 的 visitor/forwarder 请求（与入站 stream 在同一次 poll 中驱动）
+    outbound: OutboundQueue<tokio::sync::oneshot::Sender<Result<yamux::Stream>>>,
     config: Arc<ClientFullConfig>,
     stats_manager: stats::ClientStatsManager,
+    routing: Arc<RoutingManager>,
     visitor_stream_tx:
         tokio::sync::mpsc::Sender<tokio::sync::oneshot::Sender<Result<yamux::Stream>>>,
     shutdown_tx: tokio::sync::broadcast::Sender<()>,
     state: ClientState,
     heartbeat_interval: tokio::time::Interval,
-    proxy_pools: Option<Arc<HashMap<u16, Arc<ConnectionPool>>>>,
+    /// 心跳确认跟踪（仅服务器支持心跳确认时使用）
+    heartbeat: HeartbeatMonitor,
+    /// 服务器是否确认心跳
+    heartbeat_ack: bool,
+    /// 直连报告的发送定时器
+    report_interval: tokio::time::Interval,
+    /// 服务器是否接受直连 forwarder 连接的报告
+    forward_report: bool,
+    proxy_pools: Option<Arc<HashMap<u16, Arc<LocalBackend>>>>,
+    /// publish_port 为 0 的代理在服务器分配端口之前的本地后端（按代理名称）
+    unassigned_pools: HashMap<String, Arc<LocalBackend>>,
+    /// 服务器为 publish_port 为 0 的代理分配的端口（按代理名称，热重载时沿用）
+    assigned_ports: HashMap<String, u16>,
+    events: broadcast::Sender<SessionEvent>,
+    was_running: bool,
+    /// 与服务器协商的 proxy 注册者身份校验方式
+    peer_identity: PeerIdentity,
+    /// 服务器是否支持增量配置更新
+    incremental_config: bool,
+    /// 服务器是否支持主动下线代理
+    proxy_drain: bool,
+    /// 服务器是否支持私有代理
+    private_proxies: bool,
+    /// 服务器是否支持 SNI 路由代理
+    sni_routing: bool,
+    /// 服务器是否支持 visitor 连接复用
+    visitor_mux: bool,
+    /// 服务器是否支持代理 stream 的空闲保活
+    idle_keepalive: bool,
+    /// 服务器是否支持在代理 stream 前导中上报外部连接信息
+    peer_report: bool,
+    /// 服务器是否支持完整性校验尾帧
+    integrity_check: bool,
+    /// 服务器是否支持不限发布端口的 visitor stream（visitor 网关的 `port_policy = "ignore"`）
+    visitor_any_port: bool,
+    /// 服务器是否支持为 publish_port 为 0 的代理分配端口
+    auto_publish_port: bool,
+    /// 被拒绝代理的重试状态（未启用重试或没有被拒绝的代理时为 None）
+    proxy_retry: Option<proxy_retry::ProxyRetry>,
+    /// 运行中的配置版本
+    running_config: RunningConfig,
+    /// 配置热重载通知（不是从配置文件运行时为 None）
+    reload: Option<watch::Receiver<u64>>,
+    /// 等待热重载新增的代理注册结果的 visitor（访问本客户端发布的同名代理）
+    reload_visitors: Vec<VisitorConfig>,
+    /// 本次会话的协议跟踪
+    trace: SessionTrace,
+    /// visitor/forwarder 本地监听器（隧道断开期间可保持）
+    listeners: Arc<listeners::ListenerHost>,
+    /// 收到退出信号时取消
+    shutdown: CancellationToken,
+    /// systemd 看门狗（由事件循环通知）
+    watchdog: Watchdog,
+    /// 系统休眠/唤醒检测
+    suspend: SuspendDetector,
 }
 
 impl ClientWorld {
@@ -319,38 +1011,212 @@ impl ClientWorld {
         control_stream: &mut yamux::Stream,
     ) -> Result<bool> {
         match event {
-            control_channel::ControlEvent::AuthenticationSuccess { client_id } => {
+            control_channel::ControlEvent::AuthenticationSuccess {
+                client_id,
+                capabilities,
+            } => {
                 info!("✓ Authentication successful: {}", client_id);
                 self.state = ClientState::Authenticated;
+                self.peer_identity = PeerIdentity::from_capabilities(&capabilities);
+                self.incremental_config = capabilities
+                    .iter()
+                    .any(|c| c == crate::control_protocol::CAPABILITY_INCREMENTAL_CONFIG);
+                self.proxy_drain = capabilities
+                    .iter()
+                    .any(|c| c == crate::control_protocol::CAPABILITY_PROXY_DRAIN);
+                self.private_proxies = capabilities
+                    .iter()
+                    .any(|c| c == crate::control_protocol::CAPABILITY_PRIVATE_PROXY);
+                self.sni_routing = capabilities
+                    .iter()
+                    .any(|c| c == crate::control_protocol::CAPABILITY_SNI_ROUTING);
+                self.visitor_mux = capabilities
+                    .iter()
+                    .any(|c| c == crate::control_protocol::CAPABILITY_VISITOR_MUX);
+                self.visitor_any_port = capabilities
+                    .iter()
+                    .any(|c| c == crate::control_protocol::CAPABILITY_VISITOR_ANY_PORT);
+                self.auto_publish_port = capabilities
+                    .iter()
+                    .any(|c| c == crate::control_protocol::CAPABILITY_AUTO_PUBLISH_PORT);
+                self.idle_keepalive = capabilities
+                    .iter()
+                    .any(|c| c == crate::control_protocol::CAPABILITY_IDLE_KEEPALIVE);
+                self.peer_report = capabilities
+                    .iter()
+                    .any(|c| c == crate::control_protocol::CAPABILITY_PEER_REPORT);
+                self.integrity_check = capabilities
+                    .iter()
+                    .any(|c| c == crate::control_protocol::CAPABILITY_INTEGRITY_CHECK);
+                self.heartbeat_ack = capabilities
+                    .iter()
+                    .any(|c| c == crate::control_protocol::CAPABILITY_HEARTBEAT_ACK);
+                self.forward_report = capabilities
+                    .iter()
+                    .any(|c| c == crate::control_protocol::CAPABILITY_FORWARD_REPORT);
+                if !self.forward_report && self.config.forwarders.iter().any(|f| f.report_direct) {
+                    debug!("Server does not accept forward reports, direct connections will not be reported");
+                }
+                self.stats_manager
+                    .forward_reports()
+                    .set_enabled(self.forward_report);
+                self.stats_manager
+                    .tunnel()
+                    .session_started(self.heartbeat_ack);
+                events::emit(&self.events, SessionEvent::Authenticated);
                 self.initialize_resources().await?;
                 self.submit_config(control_channel, control_stream).await?;
                 Ok(true)
             }
 
+            control_channel::ControlEvent::IdentityChallenged { proof } => {
+                control_channel
+                    .send_authenticate_with(control_stream, proof)
+                    .await?;
+                Ok(true)
+            }
+
             control_channel::ControlEvent::AuthenticationFailed { reason } => {
                 error!("✗ Authentication failed: {}", reason);
                 Err(anyhow::anyhow!("Authentication failed: {}", reason))
             }
 
-            control_channel::ControlEvent::ConfigAccepted => {
+            control_channel::ControlEvent::ConfigAccepted { assigned_ports } => {
                 info!("✓ Configuration accepted by server");
+                self.apply_assigned_ports(&assigned_ports);
+                crate::chaos::fail("client.config_accepted")?;
+                events::emit(&self.events, SessionEvent::ConfigAccepted);
+                self.running_config.save_last_good();
                 self.state = ClientState::Running;
                 // 所有 proxy 都被接受，可以启动所有 listeners
                 self.start_listeners(Vec::new()).await?;
+                self.mark_running();
                 Ok(true)
             }
 
-            control_channel::ControlEvent::ConfigPartiallyRejected { rejected_proxies } => {
-                warn!("⚠ Some proxies rejected: {}", rejected_proxies.join(", "));
+            control_channel::ControlEvent::ConfigPartiallyRejected {
+                rejected_proxies,
+                reasons,
+                entries,
+                assigned_ports,
+            } => {
+                let rejected = describe_rejections(&rejected_proxies, &reasons);
+                warn!("⚠ Some proxies rejected: {}", rejected);
+                self.apply_assigned_ports(&assigned_ports);
+                let reason = format!("Proxies rejected by server: {}", rejected);
                 self.state = ClientState::Running;
+                // 服务器给出结构化条目时只为被拒绝的代理安排重试，旧版本服务器的列表不区分代理和 visitor
+                let proxy_items = if entries.is_empty() {
+                    rejected_proxies.clone()
+                } else {
+                    entries
+                        .iter()
+                        .filter(|entry| entry.kind == RejectedKind::Proxy)
+                        .map(RejectedEntry::item)
+                        .collect()
+                };
+                self.schedule_proxy_retry(&proxy_items, &reasons);
                 // 只启动那些对应 proxy 未被拒绝的 visitors
                 self.start_listeners(rejected_proxies).await?;
+                self.mark_rejected_visitors(&entries);
+                self.mark_running();
+                events::emit(&self.events, SessionEvent::Degraded(reason));
+                Ok(true)
+            }
+
+            control_channel::ControlEvent::ConfigUpdated {
+                origin: UpdateOrigin::Retry,
+                rejected_proxies,
+                reasons,
+                assigned_ports,
+            } => {
+                self.finish_proxy_retry(&rejected_proxies, &reasons);
+                self.apply_assigned_ports(&assigned_ports);
+                Ok(true)
+            }
+
+            control_channel::ControlEvent::ConfigUpdateFailed {
+                origin: UpdateOrigin::Retry,
+                reason,
+            } => {
+                warn!("Failed to retry rejected proxies: {}", reason);
+                if let Some(retry) = self.proxy_retry.as_mut() {
+                    retry.attempt_failed();
+                }
+                Ok(true)
+            }
+
+            control_channel::ControlEvent::ConfigUpdated {
+                origin: UpdateOrigin::Reload { proxies },
+                rejected_proxies,
+                reasons,
+                assigned_ports,
+            } => {
+                // 注册结果按提交时的 `name:port` 匹配，之后再换上分配的端口
+                self.finish_reload(&proxies, &rejected_proxies, &reasons);
+                self.apply_assigned_ports(&assigned_ports);
+                Ok(true)
+            }
+
+            control_channel::ControlEvent::ConfigUpdateFailed {
+                origin: UpdateOrigin::Reload { proxies },
+                reason,
+            } => {
+                warn!("Failed to register reloaded proxies: {}", reason);
+                let reasons = proxies
+                    .iter()
+                    .map(|p| (p.clone(), reason.clone()))
+                    .collect();
+                self.finish_reload(&proxies, &proxies, &reasons);
                 Ok(true)
             }
 
-            control_channel::ControlEvent::ConfigRejected { rejected_proxies } => {
-                error!("✗ All proxies rejected: {}", rejected_proxies.join(", "));
-                Err(anyhow::anyhow!("All proxies rejected"))
+            control_channel::ControlEvent::ProxiesRemoved {
+                rejected_proxies,
+                reasons,
+            } => {
+                if rejected_proxies.is_empty() {
+                    info!("✓ Removed proxies are draining on the server");
+                } else {
+                    warn!(
+                        "Server did not remove proxies: {}",
+                        describe_rejections(&rejected_proxies, &reasons)
+                    );
+                }
+                Ok(true)
+            }
+
+            control_channel::ControlEvent::ProxyRemovalFailed { reason } => {
+                warn!("Failed to remove proxies: {}", reason);
+                Ok(true)
+            }
+
+            control_channel::ControlEvent::ConfigRejected {
+                rejected_proxies,
+                reasons,
+            } => {
+                let rejected = describe_rejections(&rejected_proxies, &reasons);
+                error!("✗ All proxies rejected: {}", rejected);
+                Err(anyhow::anyhow!("All proxies rejected: {}", rejected))
+            }
+
+            // 运行会话不发送配置校验请求（只由 check_remote 使用）
+            control_channel::ControlEvent::ConfigValidated { .. }
+            | control_channel::ControlEvent::ConfigValidationFailed { .. } => {
+                debug!("Ignoring unexpected config validation result");
+                Ok(true)
+            }
+
+            control_channel::ControlEvent::HeartbeatAcked(ack) => {
+                let rtt = self.heartbeat.acked(ack, tokio::time::Instant::now());
+                debug!("Heartbeat {} acknowledged, RTT {:?}", ack.seq, rtt);
+                self.stats_manager.tunnel().record_heartbeat_ack(rtt);
+                Ok(true)
+            }
+
+            control_channel::ControlEvent::ProxyExpired(expired) => {
+                self.proxy_expired(expired);
+                Ok(true)
             }
 
             control_channel::ControlEvent::ConnectionClosed => {
@@ -361,61 +1227,81 @@ impl ClientWorld {
         }
     }
 
+    /// 标记会话进入运行状态
+    fn mark_running(&mut self) {
+        self.was_running = true;
+        // 进入运行状态后立即发送第一次心跳（例如唤醒后重连时尽快确认隧道可用）
+        self.heartbeat_interval.reset_immediately();
+        events::emit(&self.events, SessionEvent::Running);
+        systemd::notify_ready();
+    }
+
     /// 初始化资源（连接池、统计跟踪器）
     async fn initialize_resources(&mut self) -> Result<()> {
         info!("Initializing connection pools");
 
         // 创建连接池
-        let pool_config = get_pool_config().await;
-        let pools: HashMap<u16, Arc<ConnectionPool>> = self
+        let pool_config = get_pool_config(&self.config.client.timeouts()).await;
+        let backends: Vec<Arc<LocalBackend>> = self
             .config
             .proxies
             .iter()
-            .map(|proxy| {
-                let mut pool_cfg = pool_config.clone();
-                pool_cfg.reuse_connections = proxy.proxy_type.should_reuse_connections();
-                if proxy.proxy_type.is_multiplexed() {
-                    pool_cfg.max_size = 1;
-                    pool_cfg.min_idle = 1;
-                }
-                let pool = Arc::new(ConnectionPool::new(pool_cfg));
-                (proxy.publish_port, pool)
-            })
+            .map(|proxy| local_backend(&pool_config, proxy))
             .collect();
 
-        // 预热连接池
-        for proxy in &self.config.proxies {
-            let local_addr = format!("127.0.0.1:{}", proxy.local_port);
-            if let Some(pool) = pools.get(&proxy.publish_port) {
-                if let Err(e) = pool.warmup(&local_addr).await {
-                    warn!("Failed to warm up pool for '{}': {}", proxy.name, e);
-                }
-            }
+        // 预热连接池（每个本地目标单独一个子池，warmup = false 的代理在首次流量前不会连接后端）
+        for (proxy, backend) in self.config.proxies.iter().zip(&backends) {
+            warm_up(&proxy.name, backend).await;
         }
 
-        // 启动清理任务
-        for pool in pools.values() {
-            pool.clone().start_cleanup_task(Duration::from_secs(30));
+        // 为每个代理创建统计跟踪器
+        for (proxy, backend) in self.config.proxies.iter().zip(&backends) {
+            self.add_proxy_tracker(proxy, &backend.pool);
         }
 
-        self.proxy_pools = Some(Arc::new(pools));
-
-        // 为每个代理创建统计跟踪器
-        for proxy in &self.config.proxies {
-            let tracker = stats::ClientStatsTracker::new(
-                proxy.name.clone(),
-                proxy.proxy_type,
-                "127.0.0.1".to_string(),
-                proxy.local_port,
-                self.config.client.server_addr.clone(),
-                proxy.publish_port,
-            );
-            self.stats_manager.add_or_update_tracker(tracker);
+        // 分配端口的代理在配置确认后才登记到实际端口上
+        let mut pools = HashMap::new();
+        for (proxy, backend) in self.config.proxies.iter().zip(backends) {
+            if proxy.publish_port == 0 {
+                self.unassigned_pools.insert(proxy.name.clone(), backend);
+            } else {
+                pools.insert(proxy.publish_port, backend);
+            }
         }
+        self.proxy_pools = Some(Arc::new(pools));
 
         Ok(())
     }
 
+    /// 创建代理的统计跟踪器（替换同名的跟踪器）
+    fn add_proxy_tracker(&self, proxy: &ProxyConfig, pool: &Arc<ConnectionPool>) {
+        let mut tracker = stats::ClientStatsTracker::new(
+            proxy.name.clone(),
+            proxy.proxy_type,
+            "127.0.0.1".to_string(),
+            proxy.effective_local_port(),
+            self.config.client.server_addr.clone(),
+            proxy.publish_port,
+        );
+        // 多个本地目标时按目标地址分别统计，便于发现负载不均
+        if proxy.local_targets.is_some() {
+            tracker = tracker.with_targets();
+        }
+        // HTTP/1.1 代理统计升级为 WebSocket 的连接
+        if proxy.proxy_type.detects_upgrades() {
+            tracker = tracker.with_upgrade_counter();
+        }
+        // 服务器上报外部连接来源时记录最近的连接
+        if proxy.report_peers {
+            tracker = tracker.with_recent_connections();
+        }
+        // 复用本地连接时统计连接池的命中情况，便于发现连接频繁重建
+        if pool.config().reuse_connections {
+            tracker = tracker.with_pool(pool.clone());
+        }
+        self.stats_manager.add_or_update_tracker(tracker);
+    }
+
     /// 提交配置
     async fn submit_config(
         &mut self,
@@ -423,13 +1309,792 @@ impl ClientWorld {
         control_stream: &mut yamux::Stream,
     ) -> Result<()> {
         self.state = ClientState::ConfiguringProxy;
-        info!("Submitting proxy configuration");
-        control_channel.send_submit_config(control_stream).await
+        info!(
+            "Submitting proxy configuration (generation {})",
+            self.running_config.generation().generation
+        );
+        if !self.private_proxies {
+            for proxy in self
+                .config
+                .proxies
+                .iter()
+                .filter(|p| p.visibility.is_private())
+            {
+                error!(
+                    "Server does not support private proxies, proxy '{}' will not be registered",
+                    proxy.name
+                );
+                if let Some(tracker) = self.stats_manager.get_tracker(&proxy.name) {
+                    tracker.update_status("Rejected: server does not support private proxies");
+                }
+            }
+        }
+        if !self.sni_routing {
+            for proxy in self
+                .config
+                .proxies
+                .iter()
+                .filter(|p| p.sni_routing.is_some())
+            {
+                error!(
+                    "Server does not support SNI routing, proxy '{}' will not be registered",
+                    proxy.name
+                );
+                if let Some(tracker) = self.stats_manager.get_tracker(&proxy.name) {
+                    tracker.update_status("Rejected: server does not support SNI routing");
+                }
+            }
+        }
+        if !self.auto_publish_port {
+            for proxy in self.config.proxies.iter().filter(|p| p.publish_port == 0) {
+                error!(
+                    "Server does not support auto-assigned publish ports, proxy '{}' will not be registered",
+                    proxy.name
+                );
+                if let Some(tracker) = self.stats_manager.get_tracker(&proxy.name) {
+                    tracker.update_status(
+                        "Rejected: server does not support auto-assigned publish ports",
+                    );
+                }
+            }
+        }
+        if !self.idle_keepalive {
+            for proxy in self
+                .config
+                .proxies
+                .iter()
+                .filter(|p| p.idle_keepalive_secs.is_some())
+            {
+                warn!(
+                    "Server does not support idle keepalive, proxy '{}' will be relayed without keepalive markers",
+                    proxy.name
+                );
+            }
+        }
+        if !self.peer_report {
+            for proxy in self.config.proxies.iter().filter(|p| p.report_peers) {
+                warn!(
+                    "Server does not support peer reporting, proxy '{}' will not record connection sources",
+                    proxy.name
+                );
+            }
+        }
+        if !self.integrity_check {
+            for proxy in self.config.proxies.iter().filter(|p| p.integrity_check) {
+                warn!(
+                    "Server does not support integrity checks, proxy '{}' will be relayed without checksums",
+                    proxy.name
+                );
+            }
+        }
+        control_channel
+            .send_submit_config(
+                control_stream,
+                self.private_proxies,
+                self.sni_routing,
+                self.auto_publish_port,
+            )
+            .await
+    }
+
+    /// 换上服务器为 publish_port 为 0 的代理分配的端口：更新会话配置和统计中的端口，
+    /// 并把本地后端登记到实际端口上（服务器打开的 stream 按实际端口查找代理）
+    fn apply_assigned_ports(&mut self, assigned: &[AssignedPort]) {
+        if assigned.is_empty() {
+            return;
+        }
+        let mut config = (*self.config).clone();
+        let mut pools = self.proxy_pools.as_deref().cloned().unwrap_or_default();
+        for AssignedPort { name, publish_port } in assigned {
+            let Some(proxy) = config
+                .proxies
+                .iter_mut()
+                .find(|p| p.name == *name && p.publish_port == 0)
+            else {
+                warn!(
+                    "Server assigned publish_port {} to unknown proxy '{}'",
+                    publish_port, name
+                );
+                continue;
+            };
+            proxy.publish_port = *publish_port;
+            if let Some(backend) = self.unassigned_pools.remove(name) {
+                pools.insert(*publish_port, backend);
+            }
+            if let Some(tracker) = self.stats_manager.get_tracker(name) {
+                tracker.set_target_port(*publish_port);
+            }
+            self.assigned_ports.insert(name.clone(), *publish_port);
+            info!(
+                "✓ Proxy '{}' is published at {}",
+                name,
+                public_url(proxy, &config.client.server_addr)
+            );
+        }
+        self.config = Arc::new(config);
+        self.proxy_pools = Some(Arc::new(pools));
+    }
+
+    /// 记录被拒绝的代理并在统计中标记；配置了 proxy_retry 且服务器支持增量更新时安排重试
+    fn schedule_proxy_retry(&mut self, rejected: &[String], reasons: &BTreeMap<String, String>) {
+        let retry_config = match self.config.client.proxy_retry.clone() {
+            Some(_) if !self.incremental_config => {
+                warn!("Server does not support config updates, rejected proxies will not be retried until reconnect");
+                None
+            }
+            retry_config => retry_config,
+        };
+
+        let Some(retry_config) = retry_config else {
+            for proxy in &self.config.proxies {
+                let key = proxy_retry::proxy_key(proxy);
+                if let (true, Some(tracker)) = (
+                    rejected.contains(&key),
+                    self.stats_manager.get_tracker(&proxy.name),
+                ) {
+                    let reason = reasons
+                        .get(&key)
+                        .map_or("rejected by server", |r| r.as_str());
+                    tracker.update_status(format!("Rejected: {}", reason));
+                }
+            }
+            return;
+        };
+
+        let retry =
+            proxy_retry::ProxyRetry::new(retry_config, &self.config.proxies, rejected, reasons);
+        if retry.pending().is_empty() {
+            return;
+        }
+        info!(
+            "Will retry {} rejected proxy(ies) in {}s",
+            retry.pending().len(),
+            self.config
+                .client
+                .proxy_retry
+                .as_ref()
+                .map_or(0, |r| r.interval_secs)
+        );
+        self.update_pending_status(&retry);
+        self.proxy_retry = Some(retry);
+    }
+
+    /// 服务器因空闲注销了代理：配置了 proxy_retry 时加入重试，否则等到重连时重新注册
+    fn proxy_expired(&mut self, expired: crate::control_protocol::ProxyExpiredData) {
+        let Some(proxy) = self
+            .config
+            .proxies
+            .iter()
+            .find(|p| p.name == expired.name && p.publish_port == expired.publish_port)
+            .cloned()
+        else {
+            warn!(
+                "Server expired unknown proxy '{}:{}'",
+                expired.name, expired.publish_port
+            );
+            return;
+        };
+        let reason = format!("expired after {}s idle", expired.idle_secs);
+        warn!(
+            "Proxy '{}' was unregistered by the server after {}s without connections",
+            proxy_retry::proxy_key(&proxy),
+            expired.idle_secs
+        );
+
+        if !self.retry_later(&proxy, &reason) {
+            info!(
+                "Proxy '{}' will be registered again on reconnect",
+                proxy_retry::proxy_key(&proxy)
+            );
+            if let Some(tracker) = self.stats_manager.get_tracker(&proxy.name) {
+                tracker.update_status(format!("expired ({})", reason));
+            }
+        }
+    }
+
+    /// 配置了 proxy_retry 且服务器支持增量更新时把代理加入重试，返回是否会重试
+    fn retry_later(&mut self, proxy: &ProxyConfig, reason: &str) -> bool {
+        let retry_config = match self.config.client.proxy_retry.clone() {
+            Some(retry_config) if self.incremental_config => retry_config,
+            _ => return false,
+        };
+        let retry = self.proxy_retry.get_or_insert_with(|| {
+            proxy_retry::ProxyRetry::new(retry_config, &[], &[], &BTreeMap::new())
+        });
+        retry.add_pending(proxy.clone(), reason.to_string());
+        if let Some(retry) = &self.proxy_retry {
+            self.update_pending_status(retry);
+        }
+        true
+    }
+
+    /// 会话失效时立即回复所有等待创建 stream 的请求，visitor/forwarder 连接不必等到超时
+    fn fail_pending_streams(&mut self, reason: &str) {
+        self.visitor_stream_rx.close();
+        let pending = self.outbound.drain().chain(std::iter::from_fn(|| {
+            self.visitor_stream_rx.try_recv().ok()
+        }));
+        for response_tx in pending {
+            let _ = response_tx.send(Err(anyhow::anyhow!("{}", reason)));
+        }
+    }
+
+    /// 只重新提交被拒绝的代理
+    async fn retry_rejected_proxies(
+        &mut self,
+        control_channel: &mut control_channel::ClientControlChannel,
+        control_stream: &mut yamux::Stream,
+    ) {
+        let Some(retry) = self.proxy_retry.as_mut() else {
+            return;
+        };
+        let proxies = retry.start_attempt();
+        info!(
+            "Retrying rejected proxies: {}",
+            proxies
+                .iter()
+                .map(proxy_retry::proxy_key)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        if let Err(e) = control_channel
+            .send_update_config(control_stream, proxies, UpdateOrigin::Retry)
+            .await
+        {
+            warn!("Failed to send config update: {}", e);
+            retry.attempt_failed();
+        }
+    }
+
+    /// 处理重试结果：注册成功的代理恢复正常状态并启动对应的 visitor
+    fn finish_proxy_retry(&mut self, rejected: &[String], reasons: &BTreeMap<String, String>) {
+        let Some(mut retry) = self.proxy_retry.take() else {
+            return;
+        };
+        let accepted = retry.finish_attempt(rejected, reasons);
+
+        for proxy in &accepted {
+            info!(
+                "✓ Proxy '{}' accepted by server after retry",
+                proxy_retry::proxy_key(proxy)
+            );
+            if let Some(tracker) = self.stats_manager.get_tracker(&proxy.name) {
+                tracker.update_status("Idle");
+            }
+            let key = proxy_retry::proxy_key(proxy);
+            for visitor in &self.config.visitors {
+                if format!("{}:{}", visitor.name, visitor.publish_port) == key {
+                    self.spawn_visitor(visitor);
+                }
+            }
+        }
+
+        self.update_pending_status(&retry);
+        if retry.pending().is_empty() {
+            info!("All previously rejected proxies are registered");
+        } else {
+            self.proxy_retry = Some(retry);
+        }
+    }
+
+    /// 同步热重载后的配置：注册新增的代理、下线删除的代理，单独启动或停止 visitor/forwarder 监听器
+    ///
+    /// 未变化的代理和监听器保持现有连接；服务器使用的代理设置变化时保留原定义，重新连接后生效
+    async fn sync_config(
+        &mut self,
+        control_channel: &mut control_channel::ClientControlChannel,
+        control_stream: &mut yamux::Stream,
+    ) {
+        let mut target = reload::merge(&self.config, &self.running_config.config());
+        match overrides_for(&target) {
+            Ok(overrides) => overrides.apply(&mut target.forwarders),
+            Err(e) => warn!("Failed to load routing overrides: {:#}", e),
+        }
+        // 已分配端口的代理沿用分配的端口，不因重新加载而重新注册
+        for proxy in target.proxies.iter_mut().filter(|p| p.publish_port == 0) {
+            if let Some(port) = self.assigned_ports.get(&proxy.name) {
+                proxy.publish_port = *port;
+            }
+        }
+        let mut plan = reload::ReloadPlan::new(&self.config, &target);
+        if plan.is_empty() {
+            return;
+        }
+        info!(
+            "Applying configuration generation {} ({})",
+            self.running_config.generation().generation,
+            plan.summary()
+        );
+
+        for proxy in &plan.deferred_proxies {
+            warn!(
+                "Proxy '{}' changed settings used by the server, the change applies after reconnecting",
+                proxy_retry::proxy_key(proxy)
+            );
+        }
+        // 服务器不支持时删除的代理保持注册，新增的代理等到重新连接时注册
+        let kept: Vec<ProxyConfig> = if self.proxy_drain {
+            Vec::new()
+        } else {
+            std::mem::take(&mut plan.removed_proxies)
+        };
+        for proxy in &kept {
+            warn!(
+                "Server does not support removing proxies, '{}' stays registered until reconnect",
+                proxy_retry::proxy_key(proxy)
+            );
+        }
+        let mut submitted = Vec::new();
+        for proxy in &plan.added_proxies {
+            let unsupported = if !self.incremental_config {
+                Some("server does not support config updates")
+            } else if proxy.visibility.is_private() && !self.private_proxies {
+                Some("server does not support private proxies")
+            } else if proxy.sni_routing.is_some() && !self.sni_routing {
+                Some("server does not support SNI routing")
+            } else {
+                None
+            };
+            match unsupported {
+                Some(reason) => warn!(
+                    "Proxy '{}' will not be registered until reconnect: {}",
+                    proxy_retry::proxy_key(proxy),
+                    reason
+                ),
+                None => submitted.push(proxy.clone()),
+            }
+        }
+
+        // 先停止删除的条目，新增的条目可能使用相同的端口
+        let mut pools = self.proxy_pools.as_deref().cloned().unwrap_or_default();
+        let removed: Vec<ProxyRef> = plan
+            .removed_proxies
+            .iter()
+            .map(|p| ProxyRef {
+                name: p.name.clone(),
+                publish_port: p.publish_port,
+            })
+            .collect();
+        for proxy in &plan.removed_proxies {
+            if proxy.publish_port == 0 {
+                self.unassigned_pools.remove(&proxy.name);
+            } else {
+                pools.remove(&proxy.publish_port);
+            }
+            self.assigned_ports.remove(&proxy.name);
+            self.stats_manager.remove_tracker(&proxy.name);
+            if let Some(retry) = self.proxy_retry.as_mut() {
+                retry.forget(&proxy_retry::proxy_key(proxy));
+            }
+        }
+        if !removed.is_empty() {
+            if let Err(e) = control_channel
+                .send_remove_proxies(control_stream, removed)
+                .await
+            {
+                warn!("Failed to send proxy removal: {}", e);
+            }
+        }
+        for visitor in &plan.removed_visitors {
+            let key = listener_key(&visitor.bind_addr, visitor.bind_port);
+            self.listeners.stop(&key);
+            self.reload_visitors
+                .retain(|v| listener_key(&v.bind_addr, v.bind_port) != key);
+            if self.has_visitor_tracker(visitor) {
+                self.stats_manager.remove_tracker(&visitor.name);
+            }
+        }
+        for forwarder in &plan.removed_forwarders {
+            self.listeners
+                .stop(&listener_key(&forwarder.bind_addr, forwarder.bind_port));
+            self.stats_manager.remove_tracker(&forwarder.name);
+            self.routing.remove(&forwarder.name);
+        }
+
+        // 实际生效的配置：保留服务器设置变化的代理原定义和未能下线的代理
+        for old in &plan.deferred_proxies {
+            let key = proxy_retry::proxy_key(old);
+            if let Some(proxy) = target
+                .proxies
+                .iter_mut()
+                .find(|p| proxy_retry::proxy_key(p) == key)
+            {
+                *proxy = old.clone();
+            }
+        }
+        target.proxies.extend(kept);
+        self.config = Arc::new(target);
+
+        let pool_config = get_pool_config(&self.config.client.timeouts()).await;
+        for proxy in plan.added_proxies.iter().chain(&plan.updated_proxies) {
+            let backend = local_backend(&pool_config, proxy);
+            if proxy.publish_port == 0 {
+                self.unassigned_pools
+                    .insert(proxy.name.clone(), backend.clone());
+            } else {
+                pools.insert(proxy.publish_port, backend.clone());
+            }
+            self.add_proxy_tracker(proxy, &backend.pool);
+            let name = proxy.name.clone();
+            tokio::spawn(async move { warm_up(&name, &backend).await });
+        }
+        self.proxy_pools = Some(Arc::new(pools));
+
+        // 访问新增代理的 visitor 等代理注册后再启动
+        let pending: Vec<String> = submitted.iter().map(proxy_retry::proxy_key).collect();
+        for visitor in &plan.added_visitors {
+            if self.has_visitor_tracker(visitor) {
+                self.add_visitor_tracker(visitor);
+            }
+            if pending.contains(&format!("{}:{}", visitor.name, visitor.publish_port)) {
+                self.reload_visitors.push(visitor.clone());
+            } else {
+                self.spawn_visitor(visitor);
+            }
+        }
+        for forwarder in &plan.added_forwarders {
+            self.routing.replace(forwarder);
+            self.add_forwarder_tracker(forwarder);
+            self.spawn_forwarder(forwarder);
+        }
+
+        if submitted.is_empty() {
+            self.running_config.save_last_good();
+            return;
+        }
+        info!("Registering proxies: {}", pending.join(", "));
+        if let Err(e) = control_channel
+            .send_update_config(
+                control_stream,
+                submitted,
+                UpdateOrigin::Reload {
+                    proxies: pending.clone(),
+                },
+            )
+            .await
+        {
+            warn!("Failed to send config update: {}", e);
+            let reasons = pending.iter().map(|p| (p.clone(), e.to_string())).collect();
+            self.finish_reload(&pending, &pending, &reasons);
+        }
+    }
+
+    /// 处理热重载新增代理的注册结果：注册成功的代理启动等待中的 visitor，被拒绝的代理按配置重试
+    fn finish_reload(
+        &mut self,
+        submitted: &[String],
+        rejected: &[String],
+        reasons: &BTreeMap<String, String>,
+    ) {
+        for key in submitted {
+            let waiting: Vec<VisitorConfig> = self
+                .reload_visitors
+                .extract_if(.., |v| format!("{}:{}", v.name, v.publish_port) == *key)
+                .collect();
+            // 结果返回前代理可能已被再次删除
+            let Some(proxy) = self
+                .config
+                .proxies
+                .iter()
+                .find(|p| proxy_retry::proxy_key(p) == *key)
+                .cloned()
+            else {
+                continue;
+            };
+            if !rejected.contains(key) {
+                info!("✓ Proxy '{}' registered", key);
+                if let Some(tracker) = self.stats_manager.get_tracker(&proxy.name) {
+                    tracker.update_status("Idle");
+                }
+                for visitor in &waiting {
+                    self.spawn_visitor(visitor);
+                }
+                continue;
+            }
+
+            let reason = reasons
+                .get(key)
+                .map_or("rejected by server", |r| r.as_str());
+            warn!("⚠ Proxy '{}' rejected by server: {}", key, reason);
+            for visitor in &waiting {
+                warn!(
+                    "Visitor '{}': Skipping start - corresponding proxy '{}' was rejected by server",
+                    visitor.name, key
+                );
+            }
+            if !self.retry_later(&proxy, reason) {
+                if let Some(tracker) = self.stats_manager.get_tracker(&proxy.name) {
+                    tracker.update_status(format!("Rejected: {}", reason));
+                }
+            }
+        }
+
+        if rejected.is_empty() {
+            self.running_config.save_last_good();
+        } else {
+            events::emit(
+                &self.events,
+                SessionEvent::Degraded(format!(
+                    "Proxies rejected by server: {}",
+                    describe_rejections(rejected, reasons)
+                )),
+            );
+        }
+    }
+
+    /// 在统计中标记等待重试的代理
+    fn update_pending_status(&self, retry: &proxy_retry::ProxyRetry) {
+        for pending in retry.pending() {
+            if let Some(tracker) = self.stats_manager.get_tracker(&pending.proxy.name) {
+                tracker.update_status(pending.status());
+            }
+        }
+    }
+
+    /// 在统计中标记被服务器拒绝的 visitor（它们的监听器没有启动）
+    fn mark_rejected_visitors(&self, entries: &[RejectedEntry]) {
+        for entry in entries
+            .iter()
+            .filter(|entry| entry.kind == RejectedKind::Visitor)
+        {
+            let visitor = self.config.visitors.iter().find(|v| {
+                v.name == entry.name
+                    && v.publish_port == entry.publish_port
+                    && self.has_visitor_tracker(v)
+            });
+            if let Some(tracker) = visitor.and_then(|v| self.stats_manager.get_tracker(&v.name)) {
+                tracker.update_status(format!("Rejected: {}", entry.reason));
+            }
+        }
+    }
+
+    /// visitor 是否有自己的统计（与本客户端代理同名时没有）
+    fn has_visitor_tracker(&self, visitor: &VisitorConfig) -> bool {
+        !self.config.proxies.iter().any(|p| p.name == visitor.name)
+    }
+
+    /// 创建 visitor 的统计跟踪器（本地连接数和建立 stream 的失败，连接复用的 visitor 额外统计占用的隧道 stream 数）
+    fn add_visitor_tracker(&self, visitor: &VisitorConfig) {
+        let mut tracker = stats::ClientStatsTracker::new(
+            visitor.name.clone(),
+            visitor.proxy_type,
+            visitor.bind_addr.clone(),
+            visitor.bind_port,
+            self.config.client.server_addr.clone(),
+            visitor.publish_port,
+        );
+        if visitor.connection_reuse {
+            tracker = tracker.with_tunnel_stream_counter();
+        }
+        self.stats_manager.add_or_update_tracker(tracker);
     }
 
-    /// 启动监听器（visitor 和 forwarder）
+    /// 启动单个 visitor 监听器
+    ///
+    /// 隧道断开期间保持监听时，上次会话启动的监听器仍在运行则不再启动；
+    /// 热重载停止监听器后任务结束（已建立的本地连接不受影响）
+    fn spawn_visitor(&self, visitor: &VisitorConfig) {
+        let Some(claim) = self
+            .listeners
+            .claim(listener_key(&visitor.bind_addr, visitor.bind_port))
+        else {
+            debug!("Visitor '{}': Listener is still running", visitor.name);
+            return;
+        };
+        let visitor_clone = visitor.clone();
+        let visitor_name = visitor.name.clone();
+        let stream_tx_clone = self.listeners.stream_tx(&self.visitor_stream_tx);
+        let shutdown_rx = self.listeners.shutdown_rx(&self.shutdown_tx);
+        let peer_identity = self.peer_identity;
+        let visitor_mux = self.visitor_mux;
+        let stream_open = self.config.client.timeouts().stream_open();
+        let trace = self.listeners.trace(&self.trace);
+        let events = self.events.clone();
+        let tracker = self
+            .has_visitor_tracker(visitor)
+            .then(|| self.stats_manager.get_tracker(&visitor.name))
+            .flatten();
+
+        tokio::spawn(async move {
+            let listener = run_visitor_listener(
+                visitor_clone,
+                stream_tx_clone,
+                peer_identity,
+                visitor_mux,
+                stream_open,
+                tracker.clone(),
+                trace,
+                shutdown_rx,
+            );
+            let result = tokio::select! {
+                result = listener => result,
+                _ = claim.stopped() => {
+                    info!("Visitor '{}': Listener stopped", visitor_name);
+                    Ok(())
+                }
+            };
+            drop(claim);
+            if let Err(e) = result {
+                error!("Visitor '{}' listener error: {:#}", visitor_name, e);
+                listener_failed(&events, tracker.as_ref(), "Visitor", &visitor_name, &e);
+            }
+        });
+    }
+
+    /// 启动 visitor 网关监听器（未配置或上次会话启动的监听器仍在运行时跳过）
+    fn spawn_visitor_gateway(&self) {
+        let Some(gateway) = &self.config.visitor_gateway else {
+            return;
+        };
+        let Some(claim) = self
+            .listeners
+            .claim(listener_key(&gateway.bind_addr, gateway.bind_port))
+        else {
+            debug!("Visitor gateway: Listener is still running");
+            return;
+        };
+
+        // 网关统计本地连接数和建立 stream 的失败，并按目标代理名称分别统计
+        let tracker = stats::ClientStatsTracker::new(
+            visitor_gateway::GATEWAY_NAME.to_string(),
+            ProxyType::Socks5Proxy,
+            gateway.bind_addr.clone(),
+            gateway.bind_port,
+            self.config.client.server_addr.clone(),
+            0,
+        )
+        .with_targets()
+        .with_relay_memory(self.stats_manager.relay_memory().clone());
+        self.stats_manager.add_or_update_tracker(tracker.clone());
+
+        let gateway = gateway.clone();
+        let stream_tx = self.listeners.stream_tx(&self.visitor_stream_tx);
+        let shutdown_rx = self.listeners.shutdown_rx(&self.shutdown_tx);
+        let peer_identity = self.peer_identity;
+        let any_port = self.visitor_any_port;
+        let timeouts = self.config.client.timeouts();
+        let trace = self.listeners.trace(&self.trace);
+        let events = self.events.clone();
+
+        tokio::spawn(async move {
+            let _claim = claim;
+            if let Err(e) = visitor_gateway::run_visitor_gateway(
+                gateway,
+                stream_tx,
+                peer_identity,
+                any_port,
+                timeouts,
+                Some(tracker.clone()),
+                trace,
+                shutdown_rx,
+            )
+            .await
+            {
+                error!("Visitor gateway listener error: {:#}", e);
+                listener_failed(
+                    &events,
+                    Some(&tracker),
+                    "Visitor gateway",
+                    visitor_gateway::GATEWAY_NAME,
+                    &e,
+                );
+            }
+        });
+    }
+
+    /// 创建 forwarder 的统计跟踪器（替换同名的跟踪器）
+    fn add_forwarder_tracker(&self, forwarder: &ForwarderConfig) {
+        let mut tracker = stats::ClientStatsTracker::new(
+            forwarder.name.clone(),
+            forwarder.proxy_type,
+            forwarder.bind_addr.clone(),
+            forwarder.bind_port,
+            self.config.client.server_addr.clone(),
+            0,
+        );
+        if let Some(router) = self.routing.router(&forwarder.name) {
+            tracker = tracker.with_routing_stats().with_router(router);
+        }
+        tracker = tracker.with_fast_fail(forwarder::FailedTargetManager::new(
+            forwarder.fast_fail.clone().unwrap_or_default(),
+        ));
+        if forwarder.report_direct {
+            tracker = tracker.with_forward_reports(self.stats_manager.forward_reports().clone());
+        }
+        tracker = tracker.with_relay_memory(self.stats_manager.relay_memory().clone());
+        self.stats_manager.add_or_update_tracker(tracker);
+    }
+
+    /// 启动单个 forwarder 监听器（上次会话启动的监听器仍在运行时跳过）
+    fn spawn_forwarder(&self, forwarder: &ForwarderConfig) {
+        let Some(claim) = self
+            .listeners
+            .claim(listener_key(&forwarder.bind_addr, forwarder.bind_port))
+        else {
+            debug!("Forwarder '{}': Listener is still running", forwarder.name);
+            return;
+        };
+        let forwarder_clone = forwarder.clone();
+        let forwarder_name = forwarder.name.clone();
+        let stream_tx_clone = self.listeners.stream_tx(&self.visitor_stream_tx);
+        let shutdown_rx = self.listeners.shutdown_rx(&self.shutdown_tx);
+        let stats_tracker = self.stats_manager.get_tracker(&forwarder.name);
+        let trace = self.listeners.trace(&self.trace);
+        let events = self.events.clone();
+
+        let router = self.routing.router(&forwarder.name);
+        if router.is_some() {
+            info!("Forwarder '{}': GeoIP routing enabled", forwarder_name);
+        }
+        let max_header_size = self
+            .config
+            .client
+            .size_limits
+            .as_ref()
+            .map_or(forwarder::DEFAULT_MAX_HTTP_HEADER_SIZE, |limits| {
+                limits.max_header_size
+            });
+        let timeouts = self.config.client.timeouts();
+
+        tokio::spawn(async move {
+            let listener = forwarder::run_forwarder_listener(
+                forwarder_clone,
+                stream_tx_clone,
+                router,
+                stats_tracker.clone(),
+                max_header_size,
+                timeouts,
+                trace,
+                shutdown_rx,
+            );
+            let result = tokio::select! {
+                result = listener => result,
+                _ = claim.stopped() => {
+                    info!("Forwarder '{}': Listener stopped", forwarder_name);
+                    Ok(())
+                }
+            };
+            drop(claim);
+            if let Err(e) = result {
+                error!("Forwarder '{}' listener error: {:#}", forwarder_name, e);
+                listener_failed(
+                    &events,
+                    stats_tracker.as_ref(),
+                    "Forwarder",
+                    &forwarder_name,
+                    &e,
+                );
+            }
+        });
+    }
+
+    /// 启动监听器（visitor、visitor 网关和 forwarder）
     /// rejected_proxies: 被服务器拒绝的 proxy 名称列表（格式：name:port）
     async fn start_listeners(&mut self, rejected_proxies: Vec<String>) -> Result<()> {
+        // 保持中的监听器从此开始通过本会话打开 stream
+        self.listeners
+            .attach(self.visitor_stream_tx.clone(), self.trace.clone());
+
         // 启动 visitor 监听器（跳过对应 proxy 被拒绝的 visitors）
         if !self.config.visitors.is_empty() {
             let rejected_set: std::collections::HashSet<String> =
@@ -437,6 +2102,16 @@ impl ClientWorld {
             let mut started_count = 0;
             let mut skipped_count = 0;
 
+            // visitor 统计（与本客户端代理同名时不创建，避免覆盖代理的统计）
+            for visitor in self.config.visitors.iter().filter(|v| {
+                self.has_visitor_tracker(v)
+                    && !self
+                        .listeners
+                        .is_running(&listener_key(&v.bind_addr, v.bind_port))
+            }) {
+                self.add_visitor_tracker(visitor);
+            }
+
             for visitor in &self.config.visitors {
                 // 检查此 visitor 对应的 proxy 是否被拒绝
                 // 服务器返回的格式是 "name:port"，需要构造相同格式进行匹配
@@ -450,18 +2125,7 @@ impl ClientWorld {
                     continue;
                 }
 
-                let visitor_clone = visitor.clone();
-                let visitor_name = visitor.name.clone();
-                let stream_tx_clone = self.visitor_stream_tx.clone();
-                let shutdown_rx = self.shutdown_tx.subscribe();
-
-                tokio::spawn(async move {
-                    if let Err(e) =
-                        run_visitor_listener(visitor_clone, stream_tx_clone, shutdown_rx).await
-                    {
-                        error!("Visitor '{}' listener error: {}", visitor_name, e);
-                    }
-                });
+                self.spawn_visitor(visitor);
                 started_count += 1;
             }
 
@@ -476,6 +2140,8 @@ impl ClientWorld {
             }
         }
 
+        self.spawn_visitor_gateway();
+
         // 启动 forwarder 监听器
         if !self.config.forwarders.is_empty() {
             info!(
@@ -483,56 +2149,16 @@ impl ClientWorld {
                 self.config.forwarders.len()
             );
 
-            for forwarder in &self.config.forwarders {
-                let tracker = stats::ClientStatsTracker::new(
-                    forwarder.name.clone(),
-                    forwarder.proxy_type,
-                    forwarder.bind_addr.clone(),
-                    forwarder.bind_port,
-                    self.config.client.server_addr.clone(),
-                    0,
-                );
-                self.stats_manager.add_or_update_tracker(tracker);
+            for forwarder in self.config.forwarders.iter().filter(|f| {
+                !self
+                    .listeners
+                    .is_running(&listener_key(&f.bind_addr, f.bind_port))
+            }) {
+                self.add_forwarder_tracker(forwarder);
             }
 
             for forwarder in &self.config.forwarders {
-                let forwarder_clone = forwarder.clone();
-                let forwarder_name = forwarder.name.clone();
-                let stream_tx_clone = self.visitor_stream_tx.clone();
-                let shutdown_rx = self.shutdown_tx.subscribe();
-                let stats_tracker = self.stats_manager.get_tracker(&forwarder.name);
-
-                let router = if let Some(ref routing_config) = forwarder.routing {
-                    match geoip::GeoIpRouter::new(routing_config.clone()) {
-                        Ok(router) => {
-                            info!("Forwarder '{}': GeoIP routing enabled", forwarder_name);
-                            Some(Arc::new(router))
-                        }
-                        Err(e) => {
-                            warn!(
-                                "Forwarder '{}': Failed to initialize GeoIP router: {}",
-                                forwarder_name, e
-                            );
-                            None
-                        }
-                    }
-                } else {
-                    None
-                };
-
-                tokio::spawn(async move {
-                    if let Err(e) = forwarder::run_forwarder_listener(
-                        forwarder_clone,
-                        stream_tx_clone,
-                        router,
-                        stats_tracker,
-                        shutdown_rx,
-                    )
-                    .await
-                    {
-                        error!("Forwarder '{}' listener error: {}", forwarder_name, e);
-                    }
-                });
+                self.spawn_forwarder(forwarder);
             }
         }
 
@@ -540,14 +2166,92 @@ impl ClientWorld {
     }
 }
 
+/// 代理的公开访问地址（发布地址为通配地址时使用服务器地址）
+fn public_url(proxy: &ProxyConfig, server_addr: &str) -> String {
+    let scheme = match proxy.proxy_type {
+        ProxyType::Http11 | ProxyType::Http2 => "http",
+        ProxyType::Udp => "udp",
+        _ => "tcp",
+    };
+    let host = match crate::listen_addr::parse_ip(&proxy.publish_addr) {
+        Some(ip) if !ip.is_unspecified() => proxy.publish_addr.as_str(),
+        _ => server_addr,
+    };
+    format!(
+        "{}://{}",
+        scheme,
+        crate::listen_addr::format(host, proxy.publish_port)
+    )
+}
+
+/// 创建代理的本地后端（连接池在释放前定期清理过期连接）
+fn local_backend(pool_config: &PoolConfig, proxy: &ProxyConfig) -> Arc<LocalBackend> {
+    let backend = LocalBackend {
+        pool: Arc::new(ConnectionPool::new(resolve_pool_config(pool_config, proxy))),
+        targets: LocalTargets::new(proxy.local_addrs()),
+    };
+    backend
+        .pool
+        .clone()
+        .start_cleanup_task(Duration::from_secs(30));
+    Arc::new(backend)
+}
+
+/// 预热代理的连接池（每个本地目标单独一个子池）
+async fn warm_up(name: &str, backend: &LocalBackend) {
+    for local_addr in backend.targets.addrs() {
+        if let Err(e) = backend.pool.warmup(local_addr).await {
+            warn!(
+                "Failed to warm up pool for '{}' ({}): {}",
+                name, local_addr, e
+            );
+        }
+    }
+}
+
+/// 本地监听器的登记键（监听地址）
+fn listener_key(bind_addr: &str, bind_port: u16) -> String {
+    crate::listen_addr::format(bind_addr, bind_port)
+}
+
+/// 监听器停止（例如绑定地址持续被占用）时在统计和会话事件中报告，下次会话会重新启动
+fn listener_failed(
+    events: &broadcast::Sender<SessionEvent>,
+    tracker: Option<&stats::ClientStatsTracker>,
+    kind: &str,
+    name: &str,
+    error: &anyhow::Error,
+) {
+    if let Some(tracker) = tracker {
+        tracker.update_status(format!("listener stopped ({:#})", error));
+    }
+    events::emit(
+        events,
+        SessionEvent::Degraded(format!("{} '{}' listener stopped: {:#}", kind, name, error)),
+    );
+}
+
+/// 将被拒绝的条目和服务器给出的原因拼接为可读文本，如 `web:8080 (Port 8080 is already in use)`
+fn describe_rejections(rejected: &[String], reasons: &BTreeMap<String, String>) -> String {
+    rejected
+        .iter()
+        .map(|item| match reasons.get(item) {
+            Some(reason) => format!("{} ({})", item, reason),
+            None => item.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 /// 统一的客户端事件循环
 /// 集中处理：yamux I/O、控制通道事件、visitor 请求、心跳等
 async fn run_client_event_loop(
     mut world: ClientWorld,
     mut control_stream: yamux::Stream,
     mut control_channel: control_channel::ClientControlChannel,
-) -> Result<()> {
+) -> Result<SessionEnd> {
     info!("Starting unified client event loop");
+    let _task = crate::chaos::task("client.session");
 
     // 开始认证
     info!("Starting authentication");
@@ -556,41 +2260,53 @@ async fn run_client_event_loop(
         return Err(e);
     }
 
-    // 主事件循环
-    loop {
+    // 主事件循环（退出时记录断开原因）
+    let mut resumed = false;
+    let reason = loop {
+        let retry_at = world.proxy_retry.as_ref().and_then(|r| r.next_attempt());
         tokio::select! {
-            // 1. 驱动 yamux 连接并处理 inbound streams（始终运行以处理 ping/pong）
-            stream_result = poll_fn(|cx| world.yamux_conn.poll_next_inbound(cx)) => {
-                match stream_result {
-                    Some(Ok(stream)) if world.state == ClientState::Running => {
-                        debug!("Received new stream from server");
-
-                        if let Some(ref pools) = world.proxy_pools {
-                            let config_clone = (*world.config).clone();
-                            let pools_clone = pools.clone();
-                            let mgr_clone = world.stats_manager.clone();
-
-                            tokio::spawn(async move {
-                                if let Err(e) = handle_stream(stream, config_clone, pools_clone, mgr_clone).await {
-                                    error!("Stream handling error: {}", e);
-                                }
-                            });
-                        }
-                    }
-                    Some(Ok(_stream)) => {
-                        // 在非 Running 状态收到 stream，忽略
-                        debug!("Ignoring inbound stream in non-running state");
-                    }
-                    Some(Err(e)) => {
-                        error!("Yamux error: {}", e);
-                        let _ = world.shutdown_tx.send(());
-                        break;
-                    }
-                    None => {
-                        info!("Yamux connection closed by server");
-                        let _ = world.shutdown_tx.send(());
-                        break;
+            // 1. 驱动 yamux 连接：处理 inbound streams（始终运行以处理 ping/pong）和排队的 outbound stream 请求
+            event = poll_fn(|cx| world.outbound.poll_event(&mut world.yamux_conn, cx)) => {
+                match event {
+                    YamuxEvent::Outbound(response_tx, stream_result) => {
+                        let _ = response_tx.send(
+                            stream_result.map_err(|e| anyhow::anyhow!("Failed to create yamux stream: {}", e))
+                        );
                     }
+                    YamuxEvent::Inbound(stream_result) => match stream_result {
+                        // 服务器注册代理后即可能打开 stream，此时配置确认可能尚未送达
+                        Some(Ok(stream)) if matches!(world.state, ClientState::ConfiguringProxy | ClientState::Running) => {
+                            debug!("Received new stream from server");
+
+                            if let Some(ref pools) = world.proxy_pools {
+                                let config_clone = (*world.config).clone();
+                                let pools_clone = pools.clone();
+                                let mgr_clone = world.stats_manager.clone();
+                                let trace = world.trace.clone();
+
+                                tokio::spawn(async move {
+                                    let _task = crate::chaos::task("client.relay");
+                                    if let Err(e) = handle_stream(stream, config_clone, pools_clone, mgr_clone, trace).await {
+                                        error!("Stream handling error: {}", e);
+                                    }
+                                });
+                            }
+                        }
+                        Some(Ok(_stream)) => {
+                            // 提交配置之前收到 stream，忽略
+                            debug!("Ignoring inbound stream before configuration was submitted");
+                        }
+                        Some(Err(e)) => {
+                            error!("Yamux error: {}", e);
+                            let _ = world.shutdown_tx.send(());
+                            break format!("Yamux error: {}", e);
+                        }
+                        None => {
+                            info!("Yamux connection closed by server");
+                            let _ = world.shutdown_tx.send(());
+                            break "Yamux connection closed by server".to_string();
+                        }
+                    },
                 }
             }
 
@@ -605,12 +2321,12 @@ async fn run_client_event_loop(
                     Ok(None) => {
                         info!("Control stream closed by server");
                         let _ = world.shutdown_tx.send(());
-                        break;
+                        break "Control stream closed by server".to_string();
                     }
                     Err(e) => {
                         error!("Control stream read error: {}", e);
                         let _ = world.shutdown_tx.send(());
-                        break;
+                        break format!("Control stream read error: {}", e);
                     }
                 }
             }
@@ -619,33 +2335,353 @@ async fn run_client_event_loop(
             event = world.event_rx.recv() => {
                 if let Some(event) = event {
                     if !world.handle_control_event(event, &mut control_channel, &mut control_stream).await? {
-                        break;
+                        break "Control channel closed by server".to_string();
                     }
                 } else {
                     error!("Control event stream closed");
                     let _ = world.shutdown_tx.send(());
-                    break;
+                    break "Control event stream closed".to_string();
                 }
             }
 
-            // 4. 处理 visitor outbound stream 请求
+            // 4. 处理 visitor outbound stream 请求（只入队，由分支 1 创建 stream）
             Some(response_tx) = world.visitor_stream_rx.recv() => {
-                let stream_result = poll_fn(|cx| world.yamux_conn.poll_new_outbound(cx)).await;
-                let _ = response_tx.send(
-                    stream_result.map_err(|e| anyhow::anyhow!("Failed to create yamux stream: {}", e))
-                );
+                world.outbound.push(response_tx);
+            }
+
+            // 5. 重试被拒绝的代理
+            _ = tokio::time::sleep_until(retry_at.unwrap_or_else(tokio::time::Instant::now)), if retry_at.is_some() => {
+                world.retry_rejected_proxies(&mut control_channel, &mut control_stream).await;
             }
 
-            // 5. 定时心跳（仅在 Running 状态）
+            // 6. 定时心跳（仅在 Running 状态）
+            //    服务器支持心跳确认时，连续未确认的心跳达到上限即结束会话并重连
             _ = world.heartbeat_interval.tick(), if world.state == ClientState::Running => {
+                let ack = if world.heartbeat_ack {
+                    let next = world.heartbeat.next_heartbeat(tokio::time::Instant::now());
+                    let missed = world.heartbeat.missed();
+                    world.stats_manager.tunnel().record_missed_heartbeats(missed);
+                    match next {
+                        Ok(params) => {
+                            if missed > 0 {
+                                warn!("Heartbeat not acknowledged by server ({} missed in a row)", missed);
+                            }
+                            Some(params)
+                        }
+                        Err(missed) => {
+                            warn!("{} consecutive heartbeats not acknowledged, reconnecting", missed);
+                            world.fail_pending_streams(HEARTBEAT_TIMEOUT_REASON);
+                            let _ = world.shutdown_tx.send(());
+                            break HEARTBEAT_TIMEOUT_REASON.to_string();
+                        }
+                    }
+                } else {
+                    None
+                };
                 debug!("Sending heartbeat");
-                if let Err(e) = control_channel.send_heartbeat(&mut control_stream).await {
+                world.stats_manager.tunnel().record_heartbeat_sent();
+                if let Err(e) = control_channel.send_heartbeat(&mut control_stream, ack).await {
                     warn!("Failed to send heartbeat: {}", e);
+                    events::emit(&world.events, SessionEvent::Degraded(format!("Failed to send heartbeat: {}", e)));
+                }
+            }
+
+            // 7. 发送期间结束的直连 forwarder 连接的报告（服务器支持时）
+            _ = world.report_interval.tick(), if world.state == ClientState::Running && world.forward_report => {
+                if let Some(params) = world.stats_manager.forward_reports().take() {
+                    debug!("Sending {} direct connection report(s)", params.reports.len());
+                    if let Err(e) = control_channel.send_forward_report(&mut control_stream, params).await {
+                        warn!("Failed to send forward report: {}", e);
+                    }
                 }
             }
+
+            // 8. 事件循环仍在运行，通知 systemd 看门狗
+            _ = world.watchdog.keepalive() => {}
+
+            // 9. 收到退出信号
+            _ = world.shutdown.cancelled() => {
+                info!("Closing session for shutdown");
+                let _ = world.shutdown_tx.send(());
+                break SHUTDOWN_REASON.to_string();
+            }
+
+            // 10. 系统从休眠中唤醒：隧道多半已失效，立即结束会话并重连
+            elapsed = world.suspend.resumed() => {
+                warn!(
+                    "System resume detected ({:?} since last check), invalidating session",
+                    elapsed
+                );
+                resumed = true;
+                world.fail_pending_streams(RESUME_REASON);
+                let _ = world.shutdown_tx.send(());
+                break RESUME_REASON.to_string();
+            }
+
+            // 11. 配置文件重新加载：按差异同步代理、visitor 和 forwarder（不断开会话）
+            _ = reload_requested(&mut world.reload), if world.state == ClientState::Running => {
+                world.sync_config(&mut control_channel, &mut control_stream).await;
+            }
+        }
+    };
+
+    // 主动关闭连接，服务器随即注销本会话的代理，不必等待连接超时
+    if world.shutdown.is_cancelled() {
+        let closed = tokio::time::timeout(
+            world.config.client.timeouts().shutdown_grace(),
+            poll_fn(|cx| world.yamux_conn.poll_close(cx)),
+        )
+        .await;
+        if !matches!(closed, Ok(Ok(()))) {
+            warn!("Connection was not closed cleanly during shutdown");
         }
     }
 
     info!("Client event loop ended");
-    Ok(())
+    Ok(SessionEnd {
+        reason,
+        was_running: world.was_running,
+        resumed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ClientTimeoutsConfig;
+    use crate::control_protocol::{
+        read_frame, write_frame, AuthenticateResult, JsonRpcRequest, JsonRpcResponse,
+        SubmitConfigResult,
+    };
+
+    /// 心跳确认的模拟网络延迟
+    const ACK_DELAY: Duration = Duration::from_millis(50);
+
+    /// 内存中的服务器端：接受认证和配置，每收到一条控制消息把方法名发送到返回的通道
+    ///
+    /// `acks` 为空时模拟不支持心跳确认的旧服务器；否则声明 `heartbeat_ack` 能力，
+    /// 在标志为 true 期间延迟 [`ACK_DELAY`] 后确认心跳
+    fn spawn_fake_server(
+        remote: tokio::io::DuplexStream,
+        acks: Option<Arc<std::sync::atomic::AtomicBool>>,
+    ) -> tokio::sync::mpsc::UnboundedReceiver<String> {
+        let (method_tx, method_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut server =
+            YamuxConnection::new(remote.compat(), YamuxConfig::default(), YamuxMode::Server);
+        tokio::spawn(async move {
+            while let Some(Ok(mut stream)) = poll_fn(|cx| server.poll_next_inbound(cx)).await {
+                let method_tx = method_tx.clone();
+                let acks = acks.clone();
+                tokio::spawn(async move {
+                    while let Ok(Some(frame)) = read_frame(&mut stream).await {
+                        let request: JsonRpcRequest = serde_json::from_slice(&frame).unwrap();
+                        let _ = method_tx.send(request.method.clone());
+                        let Some(id) = request.id else {
+                            continue;
+                        };
+                        let result = if request.method == "authenticate" {
+                            let capabilities = acks
+                                .iter()
+                                .map(|_| {
+                                    crate::control_protocol::CAPABILITY_HEARTBEAT_ACK.to_string()
+                                })
+                                .collect();
+                            serde_json::to_value(AuthenticateResult {
+                                client_id: "test-client".to_string(),
+                                protocol_version: env!("CARGO_PKG_VERSION").to_string(),
+                                min_client_version: None,
+                                capabilities,
+                            })
+                        } else if request.method == "heartbeat" {
+                            let acked = acks
+                                .as_ref()
+                                .is_some_and(|flag| flag.load(std::sync::atomic::Ordering::SeqCst));
+                            if !acked {
+                                continue;
+                            }
+                            sleep(ACK_DELAY).await;
+                            Ok(request.params.clone())
+                        } else {
+                            serde_json::to_value(SubmitConfigResult {
+                                rejected_proxies: vec![],
+                                reasons: BTreeMap::new(),
+                                dry_run: false,
+                                rejected_entries: vec![],
+                                assigned_ports: vec![],
+                            })
+                        };
+                        let response = JsonRpcResponse::success(id, result.unwrap());
+                        let response = serde_json::to_vec(&response).unwrap();
+                        if write_frame(&mut stream, &response).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        method_rx
+    }
+
+    /// 系统休眠（用暂停的时钟模拟时间跳跃）后会话立即失效，并要求马上重连
+    #[tokio::test(start_paused = true)]
+    async fn test_resume_invalidates_session_promptly() {
+        let config: ClientFullConfig = toml::from_str(
+            "[client]\nserver_addr = \"127.0.0.1\"\nserver_port = 8443\nauth_key = \"test-key\"\n",
+        )
+        .unwrap();
+
+        let (local, remote) = tokio::io::duplex(64 * 1024);
+        let mut methods = spawn_fake_server(remote, None);
+
+        let (events, mut event_rx) = broadcast::channel(SESSION_EVENT_CAPACITY);
+        let session = tokio::spawn(async move {
+            run_session_over(
+                Box::pin(local),
+                config.clone(),
+                stats::ClientStatsManager::new(),
+                Arc::new(RoutingManager::new(&[], RoutingOverrides::default(), None)),
+                events,
+                RunningConfig::new(config, None),
+                None,
+                &Arc::new(ClientIdentity::ephemeral().unwrap()),
+                &ProtocolTrace::from_config(None, TraceSide::Client),
+                &listeners::ListenerHost::new(false),
+                &CancellationToken::new(),
+            )
+            .await
+        });
+
+        while !matches!(event_rx.recv().await.unwrap(), SessionEvent::Running) {}
+        // 进入运行状态后立即发送第一次心跳
+        let started = tokio::time::Instant::now();
+        while methods.recv().await.unwrap() != "heartbeat" {}
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        // 正常运行期间不会误判
+        sleep(Duration::from_secs(60)).await;
+        assert!(!session.is_finished());
+
+        let jump = Duration::from_secs(300);
+        let before = tokio::time::Instant::now();
+        tokio::time::advance(jump).await;
+        let end = session.await.unwrap().unwrap();
+        assert!(end.resumed);
+        assert!(end.was_running);
+        assert_eq!(end.reason, RESUME_REASON);
+        assert!(
+            before.elapsed() < jump + Duration::from_secs(1),
+            "Session ended {:?} after the resume",
+            before.elapsed() - jump
+        );
+    }
+    /// 在内存传输上运行一个会话，返回会话任务和统计管理器
+    fn spawn_session(
+        config: &str,
+        local: tokio::io::DuplexStream,
+    ) -> (
+        tokio::task::JoinHandle<Result<SessionEnd>>,
+        stats::ClientStatsManager,
+        broadcast::Receiver<SessionEvent>,
+    ) {
+        let config: ClientFullConfig = toml::from_str(config).unwrap();
+        let stats_manager = stats::ClientStatsManager::new();
+        let (events, event_rx) = broadcast::channel(SESSION_EVENT_CAPACITY);
+        let session = tokio::spawn({
+            let stats_manager = stats_manager.clone();
+            async move {
+                run_session_over(
+                    Box::pin(local),
+                    config.clone(),
+                    stats_manager,
+                    Arc::new(RoutingManager::new(&[], RoutingOverrides::default(), None)),
+                    events,
+                    RunningConfig::new(config, None),
+                    None,
+                    &Arc::new(ClientIdentity::ephemeral().unwrap()),
+                    &ProtocolTrace::from_config(None, TraceSide::Client),
+                    &listeners::ListenerHost::new(false),
+                    &CancellationToken::new(),
+                )
+                .await
+            }
+        });
+        (session, stats_manager, event_rx)
+    }
+
+    /// 服务器确认心跳时测量往返时延，停止确认后恰好在配置的丢失次数时结束会话
+    #[tokio::test(start_paused = true)]
+    async fn test_heartbeat_rtt_and_missed_acks() {
+        let (local, remote) = tokio::io::duplex(64 * 1024);
+        let acks = Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let mut methods = spawn_fake_server(remote, Some(acks.clone()));
+        let (session, stats_manager, mut event_rx) = spawn_session(
+            "[client]\nserver_addr = \"127.0.0.1\"\nserver_port = 8443\n\
+             auth_key = \"test-key\"\nmax_missed_heartbeats = 2\n",
+            local,
+        );
+
+        while !matches!(event_rx.recv().await.unwrap(), SessionEvent::Running) {}
+        while methods.recv().await.unwrap() != "heartbeat" {}
+        let heartbeat_interval = ClientTimeoutsConfig::default().heartbeat_interval();
+        sleep(heartbeat_interval / 2).await;
+
+        let tunnel = stats_manager.tunnel().snapshot();
+        assert!(tunnel.heartbeat_ack);
+        assert_eq!(tunnel.heartbeats_acked, 1);
+        let rtt = tunnel.rtt_ms.expect("RTT should be measured");
+        assert_eq!(rtt.current, ACK_DELAY.as_millis() as f64);
+        assert_eq!(rtt.max, rtt.current);
+
+        // 服务器停止确认：之后发出的第 1、2 次心跳都丢失，第 3 次本应发送时会话结束
+        acks.store(false, std::sync::atomic::Ordering::SeqCst);
+        let stopped = tokio::time::Instant::now();
+        let mut unacked = 0;
+        while let Some(method) = methods.recv().await {
+            if method == "heartbeat" {
+                unacked += 1;
+            }
+            if unacked == 2 {
+                break;
+            }
+        }
+        let end = session.await.unwrap().unwrap();
+        assert_eq!(end.reason, HEARTBEAT_TIMEOUT_REASON);
+        assert!(end.was_running);
+        assert!(!end.resumed);
+        assert!(
+            methods.try_recv().is_err(),
+            "No heartbeat after the last miss"
+        );
+        let elapsed = stopped.elapsed();
+        assert!(
+            elapsed >= heartbeat_interval * 2 && elapsed < heartbeat_interval * 3,
+            "Session ended {:?} after acks stopped",
+            elapsed
+        );
+        let tunnel = stats_manager.tunnel().snapshot();
+        assert_eq!(tunnel.missed_heartbeats, 2);
+        assert_eq!(tunnel.heartbeats_sent, 3);
+    }
+
+    /// 不支持心跳确认的服务器：心跳仍作为通知发送，从不因缺少确认而断开
+    #[tokio::test(start_paused = true)]
+    async fn test_heartbeat_without_ack_capability() {
+        let (local, remote) = tokio::io::duplex(64 * 1024);
+        let mut methods = spawn_fake_server(remote, None);
+        let (session, stats_manager, mut event_rx) = spawn_session(
+            "[client]\nserver_addr = \"127.0.0.1\"\nserver_port = 8443\nauth_key = \"test-key\"\n",
+            local,
+        );
+
+        while !matches!(event_rx.recv().await.unwrap(), SessionEvent::Running) {}
+        for _ in 0..5 {
+            while methods.recv().await.unwrap() != "heartbeat" {}
+        }
+        assert!(!session.is_finished());
+        let tunnel = stats_manager.tunnel().snapshot();
+        assert!(!tunnel.heartbeat_ack);
+        assert_eq!(tunnel.rtt_ms, None);
+        assert_eq!(tunnel.missed_heartbeats, 0);
+        session.abort();
+    }
 }
@@ -0,0 +1,301 @@
+/// 被服务器拒绝的代理的自动重试
+///
+/// 保存被拒绝（或被服务器因空闲注销）的代理子集，会话运行期间按退避间隔通过增量配置更新重新提交，
+/// 直到全部注册成功；同一时间只有一个重试请求在途
+use crate::config::{ProxyConfig, ProxyRetryConfig};
+use std::collections::BTreeMap;
+use tokio::time::{Duration, Instant};
+
+/// 等待重试的代理
+#[derive(Debug, Clone)]
+pub(crate) struct PendingProxy {
+    /// 代理配置
+    pub(crate) proxy: ProxyConfig,
+    /// 最近一次被拒绝的原因
+    pub(crate) reason: String,
+}
+
+impl PendingProxy {
+    /// 服务器返回的拒绝条目格式（`name:port`）
+    pub(crate) fn key(&self) -> String {
+        proxy_key(&self.proxy)
+    }
+
+    /// 统计中显示的状态
+    pub(crate) fn status(&self) -> String {
+        format!("pending (rejected: {})", self.reason)
+    }
+}
+
+/// 代理在拒绝列表中的条目（`name:port`）
+pub(crate) fn proxy_key(proxy: &ProxyConfig) -> String {
+    format!("{}:{}", proxy.name, proxy.publish_port)
+}
+
+/// 重试状态
+#[derive(Debug)]
+pub(crate) struct ProxyRetry {
+    config: ProxyRetryConfig,
+    pending: Vec<PendingProxy>,
+    /// 下一次重试之前的等待时间
+    delay: Duration,
+    /// 下一次重试的时间（请求在途或没有待重试的代理时为 None）
+    next_attempt: Option<Instant>,
+    /// 在途请求提交的代理（`name:port`）
+    in_flight: Vec<String>,
+}
+
+impl ProxyRetry {
+    /// 按配置创建，从 `proxies` 中找出被拒绝的代理并安排首次重试
+    pub(crate) fn new(
+        config: ProxyRetryConfig,
+        proxies: &[ProxyConfig],
+        rejected: &[String],
+        reasons: &BTreeMap<String, String>,
+    ) -> Self {
+        let pending = proxies
+            .iter()
+            .filter(|proxy| rejected.contains(&proxy_key(proxy)))
+            .map(|proxy| PendingProxy {
+                proxy: proxy.clone(),
+                reason: rejection_reason(reasons, &proxy_key(proxy)),
+            })
+            .collect();
+        let mut retry = Self {
+            delay: Duration::from_secs(config.interval_secs),
+            config,
+            pending,
+            next_attempt: None,
+            in_flight: Vec::new(),
+        };
+        retry.schedule();
+        retry
+    }
+
+    /// 等待重试的代理
+    pub(crate) fn pending(&self) -> &[PendingProxy] {
+        &self.pending
+    }
+
+    /// 下一次重试的时间
+    pub(crate) fn next_attempt(&self) -> Option<Instant> {
+        self.next_attempt
+    }
+
+    /// 加入一个需要重新注册的代理（已在等待时只更新原因）
+    ///
+    /// 没有请求在途时按初始间隔安排重试；请求在途时等该请求结束后一并重试
+    pub(crate) fn add_pending(&mut self, proxy: ProxyConfig, reason: String) {
+        let key = proxy_key(&proxy);
+        match self.pending.iter_mut().find(|p| p.key() == key) {
+            Some(pending) => pending.reason = reason,
+            None => self.pending.push(PendingProxy { proxy, reason }),
+        }
+        if self.in_flight.is_empty() && self.next_attempt.is_none() {
+            self.delay = Duration::from_secs(self.config.interval_secs);
+            self.schedule();
+        }
+    }
+
+    /// 不再重试该代理（例如已从配置中删除），返回它是否在等待重试
+    pub(crate) fn forget(&mut self, key: &str) -> bool {
+        let before = self.pending.len();
+        self.pending.retain(|p| p.key() != key);
+        if self.pending.is_empty() {
+            self.next_attempt = None;
+        }
+        self.pending.len() != before
+    }
+
+    /// 开始一次重试，返回要重新提交的代理
+    pub(crate) fn start_attempt(&mut self) -> Vec<ProxyConfig> {
+        self.next_attempt = None;
+        self.in_flight = self.pending.iter().map(PendingProxy::key).collect();
+        self.pending.iter().map(|p| p.proxy.clone()).collect()
+    }
+
+    /// 处理重试结果，返回本次注册成功的代理
+    ///
+    /// 有代理注册成功时退避间隔恢复为初始值，否则加倍（不超过上限）；
+    /// 请求在途期间加入的代理没有提交，继续等待
+    pub(crate) fn finish_attempt(
+        &mut self,
+        rejected: &[String],
+        reasons: &BTreeMap<String, String>,
+    ) -> Vec<ProxyConfig> {
+        let in_flight = std::mem::take(&mut self.in_flight);
+        let (still_rejected, accepted): (Vec<_>, Vec<_>) = self.pending.drain(..).partition(|p| {
+            let key = p.key();
+            rejected.contains(&key) || !in_flight.contains(&key)
+        });
+        self.pending = still_rejected
+            .into_iter()
+            .map(|mut p| {
+                if let Some(reason) = reasons.get(&p.key()) {
+                    p.reason = reason.clone();
+                } else if in_flight.contains(&p.key()) {
+                    p.reason = rejection_reason(reasons, &p.key());
+                }
+                p
+            })
+            .collect();
+
+        if accepted.is_empty() {
+            self.back_off();
+        } else {
+            self.delay = Duration::from_secs(self.config.interval_secs);
+        }
+        self.schedule();
+        accepted.into_iter().map(|p| p.proxy).collect()
+    }
+
+    /// 重试请求失败（发送失败、超时或服务器返回错误），按退避间隔再次安排
+    pub(crate) fn attempt_failed(&mut self) {
+        self.in_flight.clear();
+        self.back_off();
+        self.schedule();
+    }
+
+    fn back_off(&mut self) {
+        self.delay = (self.delay * 2).min(Duration::from_secs(self.config.max_interval_secs));
+    }
+
+    fn schedule(&mut self) {
+        self.next_attempt = (!self.pending.is_empty()).then(|| Instant::now() + self.delay);
+    }
+}
+
+fn rejection_reason(reasons: &BTreeMap<String, String>, key: &str) -> String {
+    reasons
+        .get(key)
+        .cloned()
+        .unwrap_or_else(|| "rejected by server".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ProxyType, ProxyVisibility};
+
+    fn proxy(name: &str, publish_port: u16) -> ProxyConfig {
+        ProxyConfig {
+            name: name.to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "0.0.0.0".to_string(),
+            publish_port,
+            local_port: 80,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_backoff_and_acceptance() {
+        let proxies = [proxy("web", 8080), proxy("ssh", 2222), proxy("db", 5432)];
+        let rejected = vec!["web:8080".to_string(), "db:5432".to_string()];
+        let reasons = BTreeMap::from([(
+            "web:8080".to_string(),
+            "Port 8080 is already in use".to_string(),
+        )]);
+        let mut retry = ProxyRetry::new(
+            ProxyRetryConfig {
+                interval_secs: 10,
+                max_interval_secs: 25,
+            },
+            &proxies,
+            &rejected,
+            &reasons,
+        );
+
+        assert_eq!(retry.pending().len(), 2);
+        assert_eq!(
+            retry.pending()[0].status(),
+            "pending (rejected: Port 8080 is already in use)"
+        );
+        assert_eq!(
+            retry.pending()[1].status(),
+            "pending (rejected: rejected by server)"
+        );
+        let start = Instant::now();
+        assert_eq!(retry.next_attempt(), Some(start + Duration::from_secs(10)));
+
+        // 请求在途时不再安排重试
+        assert_eq!(retry.start_attempt().len(), 2);
+        assert_eq!(retry.next_attempt(), None);
+
+        // 全部仍被拒绝：退避加倍，不超过上限
+        assert!(retry.finish_attempt(&rejected, &reasons).is_empty());
+        assert_eq!(retry.next_attempt(), Some(start + Duration::from_secs(20)));
+        retry.start_attempt();
+        retry.attempt_failed();
+        assert_eq!(retry.next_attempt(), Some(start + Duration::from_secs(25)));
+
+        // web 注册成功，db 继续等待，退避恢复为初始值
+        retry.start_attempt();
+        let accepted = retry.finish_attempt(&["db:5432".to_string()], &BTreeMap::new());
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(accepted[0].name, "web");
+        assert_eq!(retry.pending().len(), 1);
+        assert_eq!(retry.next_attempt(), Some(start + Duration::from_secs(10)));
+
+        // 全部注册成功后不再重试
+        retry.start_attempt();
+        let accepted = retry.finish_attempt(&[], &BTreeMap::new());
+        assert_eq!(accepted[0].name, "db");
+        assert!(retry.pending().is_empty());
+        assert_eq!(retry.next_attempt(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_add_pending_expired_proxy() {
+        let config = ProxyRetryConfig {
+            interval_secs: 10,
+            max_interval_secs: 60,
+        };
+        let mut retry = ProxyRetry::new(config, &[proxy("web", 8080)], &[], &BTreeMap::new());
+        assert!(retry.pending().is_empty());
+        assert_eq!(retry.next_attempt(), None);
+
+        let start = Instant::now();
+        retry.add_pending(proxy("web", 8080), "expired after 60s idle".to_string());
+        assert_eq!(
+            retry.pending()[0].status(),
+            "pending (rejected: expired after 60s idle)"
+        );
+        assert_eq!(retry.next_attempt(), Some(start + Duration::from_secs(10)));
+
+        // 重复加入只更新原因
+        retry.add_pending(proxy("web", 8080), "expired after 61s idle".to_string());
+        assert_eq!(retry.pending().len(), 1);
+        assert_eq!(retry.pending()[0].reason, "expired after 61s idle");
+
+        // 请求在途期间加入的代理不会被当作注册成功
+        assert_eq!(retry.start_attempt().len(), 1);
+        retry.add_pending(proxy("ssh", 2222), "expired after 60s idle".to_string());
+        assert_eq!(retry.next_attempt(), None);
+        let accepted = retry.finish_attempt(&[], &BTreeMap::new());
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(accepted[0].name, "web");
+        assert_eq!(retry.pending().len(), 1);
+        assert_eq!(retry.pending()[0].reason, "expired after 60s idle");
+        assert_eq!(retry.next_attempt(), Some(start + Duration::from_secs(10)));
+
+        // 从配置中删除的代理不再重试
+        assert!(retry.forget("ssh:2222"));
+        assert!(!retry.forget("ssh:2222"));
+        assert!(retry.pending().is_empty());
+        assert_eq!(retry.next_attempt(), None);
+    }
+}
@@ -0,0 +1,206 @@
+/// Forwarder 月流量配额
+///
+/// 记录每个自然月经隧道代理的字节数，可选持久化到 JSON 文件，重启后继续当月计数。
+/// 代理路径连接的计数器挂在配额的流量作用域上，使用量是持久化的基数加上作用域的总数，
+/// 与统计读取的是同一份计数
+use crate::config::QuotaAction;
+use crate::traffic::TrafficScope;
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// 两次持久化之间的最小间隔（秒）
+const QUOTA_SAVE_INTERVAL_SECS: i64 = 30;
+
+/// 某个月的配额使用量（持久化文件内容）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct QuotaUsage {
+    /// 计费月份（"YYYY-MM"）
+    period: String,
+    /// 当月已代理的字节数
+    used_bytes: u64,
+}
+
+/// 配额状态快照（用于客户端统计）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotaStatus {
+    /// 计费月份（"YYYY-MM"）
+    pub period: String,
+    /// 当月已代理的字节数
+    pub used_bytes: u64,
+    /// 每月配额
+    pub limit_bytes: u64,
+    /// 配额是否已用尽
+    pub exhausted: bool,
+    /// 配额用尽后的动作
+    pub action: QuotaAction,
+}
+
+/// 配额计数器（线程安全）
+pub struct QuotaTracker {
+    state_path: Option<PathBuf>,
+    /// 本月（进程启动或月份变化之后）代理路径连接的流量
+    traffic: TrafficScope,
+    inner: Mutex<QuotaInner>,
+}
+
+struct QuotaInner {
+    /// 计费月份和启动时从文件恢复的使用量（不含 `traffic`）
+    base: QuotaUsage,
+    /// 上次持久化的时间（Unix 时间戳）
+    last_saved: i64,
+    /// 上次刷新时的使用量
+    last_used: u64,
+}
+
+impl QuotaTracker {
+    /// 创建配额计数器，`state_path` 中保存的是当前月份时继续计数
+    pub fn load(state_path: Option<PathBuf>, period: &str, now: i64) -> Self {
+        let usage = match state_path.as_deref().map(read_usage) {
+            Some(Ok(Some(usage))) if usage.period == period => {
+                info!(
+                    "Resuming routing quota for {}: {} bytes used",
+                    usage.period, usage.used_bytes
+                );
+                usage
+            }
+            Some(Err(e)) => {
+                warn!("Failed to load routing quota state: {:#}", e);
+                QuotaUsage::new(period)
+            }
+            _ => QuotaUsage::new(period),
+        };
+        let last_used = usage.used_bytes;
+        Self {
+            state_path,
+            traffic: TrafficScope::new(),
+            inner: Mutex::new(QuotaInner {
+                base: usage,
+                last_saved: now,
+                last_used,
+            }),
+        }
+    }
+
+    /// 代理路径连接的计数器挂到这个作用域上计入配额
+    pub fn scope(&self) -> &TrafficScope {
+        &self.traffic
+    }
+
+    /// 汇总当月使用量并按间隔持久化，月份变化时从 0 重新计数
+    ///
+    /// 返回当月使用量，以及自上次刷新以来是否越过了 `limit`（越过时立即持久化）
+    pub fn refresh(&self, period: &str, now: i64, limit: u64) -> (u64, bool) {
+        let mut inner = self.inner.lock();
+        let rolled_over = inner.base.period != period;
+        if rolled_over {
+            info!(
+                "Routing quota period changed from {} to {}, usage reset",
+                inner.base.period, period
+            );
+            // 进行中的连接在此之后传输的字节计入新的月份
+            inner.base = QuotaUsage::new(period);
+            inner.last_used = 0;
+            self.traffic.reset();
+        }
+        let used = self.current(&inner.base);
+        let crossed_limit = inner.last_used < limit && used >= limit;
+        inner.last_used = used;
+
+        if rolled_over || crossed_limit || now - inner.last_saved >= QUOTA_SAVE_INTERVAL_SECS {
+            inner.last_saved = now;
+            // 持有锁写入，避免并发写同一个临时文件
+            self.save(&QuotaUsage {
+                period: period.to_string(),
+                used_bytes: used,
+            });
+        }
+        (used, crossed_limit)
+    }
+
+    fn current(&self, base: &QuotaUsage) -> u64 {
+        base.used_bytes
+            .saturating_add(self.traffic.totals().total())
+    }
+
+    fn save(&self, usage: &QuotaUsage) {
+        let Some(ref path) = self.state_path else {
+            return;
+        };
+        if let Err(e) = write_usage(path, usage) {
+            warn!("Failed to save routing quota state: {:#}", e);
+        }
+    }
+}
+
+impl QuotaUsage {
+    fn new(period: &str) -> Self {
+        Self {
+            period: period.to_string(),
+            used_bytes: 0,
+        }
+    }
+}
+
+/// 读取持久化的使用量（文件不存在时返回 None）
+fn read_usage(path: &Path) -> Result<Option<QuotaUsage>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let usage = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok(Some(usage))
+}
+
+/// 保存使用量（先写临时文件再重命名，避免写到一半的文件）
+fn write_usage(path: &Path, usage: &QuotaUsage) -> Result<()> {
+    let content = serde_json::to_string(usage).context("Failed to serialize quota state")?;
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, content)
+        .with_context(|| format!("Failed to write {}", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, path)
+        .with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_usage_persists_within_month() {
+        let path =
+            std::env::temp_dir().join(format!("tls-tunnel-quota-{}.json", uuid::Uuid::new_v4()));
+
+        let tracker = QuotaTracker::load(Some(path.clone()), "2026-10", 0);
+        let meter = tracker.scope().meter();
+        meter.add_sent(100);
+        assert_eq!(tracker.refresh("2026-10", 1, 1000), (100, false));
+        // 未到持久化间隔，但越过配额时立即保存
+        meter.add_received(950);
+        assert_eq!(tracker.refresh("2026-10", 2, 1000), (1050, true));
+        drop(meter);
+        assert_eq!(tracker.refresh("2026-10", 3, 1000), (1050, false));
+
+        // 重启后同一个月继续计数
+        let restarted = QuotaTracker::load(Some(path.clone()), "2026-10", 3);
+        assert_eq!(restarted.refresh("2026-10", 3, 1000), (1050, false));
+
+        // 进入下一个月后从 0 开始
+        let next_month = QuotaTracker::load(Some(path.clone()), "2026-11", 4);
+        assert_eq!(next_month.refresh("2026-11", 4, 1000), (0, false));
+        let meter = restarted.scope().meter();
+        meter.add_sent(10);
+        // 月份变化之前的流量不计入新月份
+        assert_eq!(restarted.refresh("2026-11", 5, 1000), (0, false));
+        meter.add_sent(10);
+        assert_eq!(restarted.refresh("2026-11", 6, 1000), (10, false));
+        assert_eq!(read_usage(&path).unwrap().unwrap().period, "2026-11");
+
+        std::fs::remove_file(&path).ok();
+    }
+}
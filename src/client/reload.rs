@@ -0,0 +1,430 @@
+/// 配置热重载
+///
+/// 从配置文件运行时，收到 SIGHUP（Unix）或开启 `watch_config` 后检测到文件变化时重新加载并校验
+/// 配置文件，把其中的 proxies、visitors 和 forwarders 应用到运行中的配置，再由会话按差异同步：
+/// 新增的代理通过 `update_config` 注册，删除的代理通过 `remove_proxies` 下线（已有连接排空后关闭），
+/// visitor/forwarder 的监听器单独启动或停止，未变化的条目保持现有连接。
+/// `[client]`、`[visitor_gateway]` 和 `[health]` 的变化需要重启客户端才能生效
+use crate::config::{
+    AppConfig, ClientFullConfig, ConfigDiff, ForwarderConfig, ProxyConfig, VisitorConfig,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::sync::watch;
+use tokio::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use super::proxy_retry::proxy_key;
+use super::running_config::RunningConfig;
+
+/// 开启 `watch_config` 时检查配置文件的间隔
+pub const CONFIG_WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 后台重新加载配置文件：校验通过且 proxies、visitors 或 forwarders 有变化时应用到 `running_config`，
+/// 并把新的配置版本发送到 `notify`（运行中的会话随之同步）
+///
+/// 收到 SIGHUP（Unix）时立即重新加载；`poll` 为 true 时每隔 [`CONFIG_WATCH_INTERVAL`]
+/// 检查文件的修改时间和大小，变化后重新加载。配置文件无效时记录错误，继续使用当前配置
+pub(crate) fn spawn_config_watcher(
+    path: PathBuf,
+    poll: bool,
+    running_config: RunningConfig,
+    notify: watch::Sender<u64>,
+    shutdown: CancellationToken,
+) {
+    tokio::spawn(async move {
+        let mut hangup = Hangup::new();
+        let mut stamp = file_stamp(&path);
+        let mut last_error = String::new();
+        loop {
+            let requested = tokio::select! {
+                _ = tokio::time::sleep(CONFIG_WATCH_INTERVAL), if poll => false,
+                _ = hangup.recv() => true,
+                _ = shutdown.cancelled() => return,
+            };
+            let current = file_stamp(&path);
+            if requested {
+                info!("Received SIGHUP, reloading {}", path.display());
+            } else if current == stamp {
+                continue;
+            }
+            stamp = current;
+
+            let loaded = match AppConfig::load_client_config(&path.to_string_lossy()) {
+                Ok(loaded) => loaded,
+                Err(e) => {
+                    // 轮询时同一错误只记录一次
+                    let message = format!("{:#}", e);
+                    if requested || message != last_error {
+                        error!(
+                            "Failed to reload {}, keeping the running configuration: {}",
+                            path.display(),
+                            message
+                        );
+                    }
+                    last_error = message;
+                    continue;
+                }
+            };
+            last_error.clear();
+
+            let running = running_config.config();
+            let restart = restart_required(&ConfigDiff::client(&running, &loaded));
+            if !restart.is_empty() {
+                warn!(
+                    "Changes to {} require restarting the client and are not applied",
+                    restart.join(", ")
+                );
+            }
+            let merged = merge(&running, &loaded);
+            if crate::config::content_hash(&merged) == running_config.generation().hash {
+                debug!(
+                    "No proxy, visitor or forwarder changes in {}",
+                    path.display()
+                );
+                continue;
+            }
+            running_config.apply(merged);
+            let generation = running_config.generation().generation;
+            info!(
+                "Reloaded {} as configuration generation {}",
+                path.display(),
+                generation
+            );
+            notify.send_replace(generation);
+        }
+    });
+}
+
+/// 文件的修改时间和大小（用于判断文件是否变化）
+fn file_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// SIGHUP 信号（非 Unix 平台上永不触发）
+struct Hangup {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangup {
+    fn new() -> Self {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            let signal = signal(SignalKind::hangup())
+                .map_err(|e| warn!("Failed to listen for SIGHUP: {}", e))
+                .ok();
+            Self { signal }
+        }
+        #[cfg(not(unix))]
+        Self {}
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = self.signal.as_mut() {
+            if signal.recv().await.is_some() {
+                return;
+            }
+            self.signal = None;
+        }
+        std::future::pending().await
+    }
+}
+
+/// 以 `running` 为基础，换上 `reloaded` 的 proxies、visitors 和 forwarders
+pub(crate) fn merge(running: &ClientFullConfig, reloaded: &ClientFullConfig) -> ClientFullConfig {
+    ClientFullConfig {
+        proxies: reloaded.proxies.clone(),
+        visitors: reloaded.visitors.clone(),
+        forwarders: reloaded.forwarders.clone(),
+        ..running.clone()
+    }
+}
+
+/// 需要重启才能生效的变更（字段路径）
+fn restart_required(diff: &ConfigDiff) -> Vec<String> {
+    let settings = diff.settings.iter().map(|c| format!("client.{}", c.field));
+    let gateway = diff
+        .visitor_gateway
+        .iter()
+        .map(|c| format!("visitor_gateway.{}", c.field));
+    let health = diff.health.iter().map(|c| format!("health.{}", c.field));
+    settings.chain(gateway).chain(health).collect()
+}
+
+/// 会话从当前配置同步到目标配置需要执行的变更
+#[derive(Debug, Default)]
+pub(crate) struct ReloadPlan {
+    /// 新增的代理
+    pub added_proxies: Vec<ProxyConfig>,
+    /// 删除的代理
+    pub removed_proxies: Vec<ProxyConfig>,
+    /// 只有本地设置（本地端口、本地目标、连接池）变化的代理，不需要重新注册
+    pub updated_proxies: Vec<ProxyConfig>,
+    /// 服务器使用的设置发生变化的代理（当前配置），重新连接后才能生效
+    pub deferred_proxies: Vec<ProxyConfig>,
+    /// 新增或修改的 visitor（修改的同时出现在 `removed_visitors` 中）
+    pub added_visitors: Vec<VisitorConfig>,
+    /// 删除或修改的 visitor（当前配置）
+    pub removed_visitors: Vec<VisitorConfig>,
+    /// 新增或修改的 forwarder（修改的同时出现在 `removed_forwarders` 中）
+    pub added_forwarders: Vec<ForwarderConfig>,
+    /// 删除或修改的 forwarder（当前配置）
+    pub removed_forwarders: Vec<ForwarderConfig>,
+}
+
+impl ReloadPlan {
+    /// 比较当前配置和目标配置（代理和 visitor 按 `name:publish_port` 匹配，forwarder 按名称匹配）
+    pub fn new(current: &ClientFullConfig, target: &ClientFullConfig) -> Self {
+        let mut plan = Self::default();
+
+        let old_proxies: BTreeMap<String, &ProxyConfig> =
+            current.proxies.iter().map(|p| (proxy_key(p), p)).collect();
+        let new_proxies: BTreeMap<String, &ProxyConfig> =
+            target.proxies.iter().map(|p| (proxy_key(p), p)).collect();
+        for proxy in &target.proxies {
+            match old_proxies.get(&proxy_key(proxy)) {
+                None => plan.added_proxies.push(proxy.clone()),
+                Some(old) if to_value(*old) == to_value(proxy) => {}
+                Some(old) if server_view(old) == server_view(proxy) => {
+                    plan.updated_proxies.push(proxy.clone())
+                }
+                Some(old) => plan.deferred_proxies.push((*old).clone()),
+            }
+        }
+        plan.removed_proxies = current
+            .proxies
+            .iter()
+            .filter(|p| !new_proxies.contains_key(&proxy_key(p)))
+            .cloned()
+            .collect();
+
+        (plan.added_visitors, plan.removed_visitors) =
+            diff_entries(&current.visitors, &target.visitors, |v| {
+                format!("{}:{}", v.name, v.publish_port)
+            });
+        (plan.added_forwarders, plan.removed_forwarders) =
+            diff_entries(&current.forwarders, &target.forwarders, |f| f.name.clone());
+        plan
+    }
+
+    /// 是否没有任何变更
+    pub fn is_empty(&self) -> bool {
+        self.added_proxies.is_empty()
+            && self.removed_proxies.is_empty()
+            && self.updated_proxies.is_empty()
+            && self.deferred_proxies.is_empty()
+            && self.added_visitors.is_empty()
+            && self.removed_visitors.is_empty()
+            && self.added_forwarders.is_empty()
+            && self.removed_forwarders.is_empty()
+    }
+
+    /// 变更摘要，如 `+1 proxy, -1 visitor, ~1 forwarder`
+    pub fn summary(&self) -> String {
+        let section = |added: usize, removed: usize, changed: usize, kind: &str| {
+            [
+                ("+", added - changed),
+                ("-", removed - changed),
+                ("~", changed),
+            ]
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(sign, count)| format!("{}{} {}", sign, count, kind))
+            .collect::<Vec<_>>()
+        };
+        let changed_visitors = changed_count(&self.added_visitors, &self.removed_visitors, |v| {
+            format!("{}:{}", v.name, v.publish_port)
+        });
+        let changed_forwarders =
+            changed_count(&self.added_forwarders, &self.removed_forwarders, |f| {
+                f.name.clone()
+            });
+        let mut parts = section(
+            self.added_proxies.len(),
+            self.removed_proxies.len(),
+            0,
+            "proxy",
+        );
+        if !self.updated_proxies.is_empty() || !self.deferred_proxies.is_empty() {
+            parts.push(format!(
+                "~{} proxy",
+                self.updated_proxies.len() + self.deferred_proxies.len()
+            ));
+        }
+        parts.extend(section(
+            self.added_visitors.len(),
+            self.removed_visitors.len(),
+            changed_visitors,
+            "visitor",
+        ));
+        parts.extend(section(
+            self.added_forwarders.len(),
+            self.removed_forwarders.len(),
+            changed_forwarders,
+            "forwarder",
+        ));
+        parts.join(", ")
+    }
+}
+
+fn to_value<T: Serialize>(value: &T) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+/// 服务器使用的代理设置（本地端口、本地目标和连接池只在客户端使用）
+fn server_view(proxy: &ProxyConfig) -> Value {
+    to_value(&ProxyConfig {
+        local_port: 0,
+        local_targets: None,
+        pool: None,
+        ..proxy.clone()
+    })
+}
+
+/// 按标识比较两组条目，返回 (新增或修改的条目, 删除或修改前的条目)
+fn diff_entries<T: Clone + Serialize>(
+    current: &[T],
+    target: &[T],
+    key: impl Fn(&T) -> String,
+) -> (Vec<T>, Vec<T>) {
+    let old: BTreeMap<String, Value> = current.iter().map(|e| (key(e), to_value(e))).collect();
+    let new: BTreeMap<String, Value> = target.iter().map(|e| (key(e), to_value(e))).collect();
+    let added = target
+        .iter()
+        .filter(|e| old.get(&key(e)) != Some(&to_value(*e)))
+        .cloned()
+        .collect();
+    let removed = current
+        .iter()
+        .filter(|e| new.get(&key(e)) != Some(&to_value(*e)))
+        .cloned()
+        .collect();
+    (added, removed)
+}
+
+/// 同时出现在新增和删除中的条目数（即修改的条目）
+fn changed_count<T>(added: &[T], removed: &[T], key: impl Fn(&T) -> String) -> usize {
+    added
+        .iter()
+        .filter(|a| removed.iter().any(|r| key(r) == key(a)))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+[client]
+server_addr = "127.0.0.1"
+server_port = 8443
+auth_key = "reload-secret-0123456789"
+
+[[proxies]]
+name = "web"
+publish_port = 8080
+local_port = 3000
+
+[[proxies]]
+name = "ssh"
+publish_port = 2222
+local_port = 22
+
+[[visitors]]
+name = "db"
+bind_port = 15432
+publish_port = 5432
+
+[[forwarders]]
+name = "out"
+proxy_type = "socks5"
+bind_port = 1080
+"#;
+
+    fn parse(content: &str) -> ClientFullConfig {
+        toml::from_str(content).unwrap()
+    }
+
+    fn names<T>(entries: &[T], name: impl Fn(&T) -> &str) -> Vec<String> {
+        entries.iter().map(|e| name(e).to_string()).collect()
+    }
+
+    #[test]
+    fn test_unchanged_config_has_empty_plan() {
+        let plan = ReloadPlan::new(&parse(CONFIG), &parse(CONFIG));
+        assert!(plan.is_empty());
+        assert_eq!(plan.summary(), "");
+    }
+
+    #[test]
+    fn test_plan_classifies_proxy_changes() {
+        let edited = CONFIG
+            // 只改本地端口：不需要重新注册
+            .replace("local_port = 3000", "local_port = 3001")
+            // 改发布端口：等同于删除后新增
+            .replace("publish_port = 2222", "publish_port = 2223")
+            + r#"
+[[proxies]]
+name = "api"
+publish_port = 9090
+local_port = 9000
+"#;
+        let plan = ReloadPlan::new(&parse(CONFIG), &parse(&edited));
+        assert_eq!(names(&plan.updated_proxies, |p| &p.name), ["web"]);
+        assert_eq!(plan.updated_proxies[0].local_port, 3001);
+        assert_eq!(names(&plan.added_proxies, |p| &p.name), ["ssh", "api"]);
+        assert_eq!(plan.added_proxies[0].publish_port, 2223);
+        assert_eq!(names(&plan.removed_proxies, |p| &p.name), ["ssh"]);
+        assert_eq!(plan.removed_proxies[0].publish_port, 2222);
+        assert!(plan.deferred_proxies.is_empty());
+        assert_eq!(plan.summary(), "+2 proxy, -1 proxy, ~1 proxy");
+
+        // 服务器使用的设置变化时保留当前配置，重新连接后生效
+        let private = CONFIG.replace(
+            "publish_port = 8080\n",
+            "publish_port = 8080\nvisibility = \"private\"\n",
+        );
+        let plan = ReloadPlan::new(&parse(CONFIG), &parse(&private));
+        assert_eq!(names(&plan.deferred_proxies, |p| &p.name), ["web"]);
+        assert!(plan.deferred_proxies[0].visibility.is_public());
+        assert!(plan.added_proxies.is_empty() && plan.removed_proxies.is_empty());
+    }
+
+    #[test]
+    fn test_plan_restarts_changed_listeners() {
+        let edited = CONFIG
+            .replace("bind_port = 15432", "bind_port = 15433")
+            .replace("name = \"out\"", "name = \"egress\"");
+        let plan = ReloadPlan::new(&parse(CONFIG), &parse(&edited));
+        assert!(plan.added_proxies.is_empty() && plan.removed_proxies.is_empty());
+        assert_eq!(plan.added_visitors[0].bind_port, 15433);
+        assert_eq!(plan.removed_visitors[0].bind_port, 15432);
+        assert_eq!(names(&plan.added_forwarders, |f| &f.name), ["egress"]);
+        assert_eq!(names(&plan.removed_forwarders, |f| &f.name), ["out"]);
+        assert_eq!(plan.summary(), "~1 visitor, +1 forwarder, -1 forwarder");
+    }
+
+    #[test]
+    fn test_merge_keeps_running_settings() {
+        let running = parse(CONFIG);
+        let reloaded = parse(
+            &CONFIG
+                .replace("server_port = 8443", "server_port = 9443")
+                .replace("name = \"ssh\"", "name = \"shell\""),
+        );
+        let merged = merge(&running, &reloaded);
+        assert_eq!(merged.client.server_port, 8443);
+        assert_eq!(merged.proxies[1].name, "shell");
+
+        let restart = restart_required(&ConfigDiff::client(&running, &reloaded));
+        assert_eq!(restart, ["client.server_port"]);
+    }
+}
@@ -0,0 +1,781 @@
+/// 路由规则在线编辑
+///
+/// 挂载在客户端统计服务器的 `/routing` 路径下，允许通过网页为 forwarder
+/// 增删直连/代理的域名和 IP/CIDR 规则。修改通过 `GeoIpRouter::reload` 立即生效，
+/// 并可保存到独立的覆盖文件（`routing_overrides_path`），启动时合并到主配置之上，
+/// 不会改写用户的主配置文件
+use super::geoip::GeoIpRouter;
+use crate::config::{ConfigValidator, ForwarderConfig, RoutingConfig, RoutingStrategy};
+use crate::stats_http::{escape_html, HttpRequest, HttpResponse};
+use anyhow::{bail, Context, Result};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Basic 认证的 realm
+const AUTH_REALM: &str = "tls-tunnel routing";
+
+/// 路由规则覆盖文件
+///
+/// 以 forwarder 名称为键，例如：
+///
+/// ```toml
+/// [forwarders.web-proxy]
+/// proxy_domains = ["*.corp.example"]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoutingOverrides {
+    #[serde(default)]
+    pub forwarders: BTreeMap<String, RoutingRuleOverride>,
+}
+
+/// 单个 forwarder 的规则覆盖
+///
+/// 设置了的列表整体替换主配置中的同名列表，未设置的列表沿用主配置
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoutingRuleOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direct_domains: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy_domains: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direct_ips: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy_ips: Option<Vec<String>>,
+}
+
+impl RoutingOverrides {
+    /// 加载覆盖文件（文件不存在时返回空覆盖）
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read routing overrides: {}", path.display()))?;
+        let overrides: Self = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse routing overrides: {}", path.display()))?;
+
+        for (name, rules) in &overrides.forwarders {
+            for list in RuleList::ALL {
+                for value in list.get(rules).into_iter().flatten() {
+                    list.validate(value).with_context(|| {
+                        format!(
+                            "Invalid entry in routing overrides for forwarder '{}'",
+                            name
+                        )
+                    })?;
+                }
+            }
+        }
+        Ok(overrides)
+    }
+
+    /// 保存覆盖文件（先写临时文件再重命名，避免写到一半的文件）
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content =
+            toml::to_string_pretty(self).context("Failed to serialize routing overrides")?;
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, content)
+            .with_context(|| format!("Failed to write {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to replace {}", path.display()))?;
+        Ok(())
+    }
+
+    /// 将覆盖合并到 forwarder 配置
+    pub fn apply(&self, forwarders: &mut [ForwarderConfig]) {
+        for (name, rules) in &self.forwarders {
+            let Some(forwarder) = forwarders.iter_mut().find(|f| &f.name == name) else {
+                warn!(
+                    "Routing overrides reference unknown forwarder '{}', ignored",
+                    name
+                );
+                continue;
+            };
+            let Some(routing) = forwarder.routing.as_mut() else {
+                warn!(
+                    "Forwarder '{}' has no routing section, overrides ignored",
+                    name
+                );
+                continue;
+            };
+            for list in RuleList::ALL {
+                if let Some(values) = list.get(rules) {
+                    *list.list_mut(routing) = values.clone();
+                }
+            }
+        }
+    }
+}
+
+/// 可编辑的规则列表
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleList {
+    DirectDomains,
+    ProxyDomains,
+    DirectIps,
+    ProxyIps,
+}
+
+impl RuleList {
+    pub const ALL: [RuleList; 4] = [
+        RuleList::DirectDomains,
+        RuleList::ProxyDomains,
+        RuleList::DirectIps,
+        RuleList::ProxyIps,
+    ];
+
+    /// 从表单字段名解析
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|list| list.as_str() == name)
+    }
+
+    /// 配置中的字段名
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RuleList::DirectDomains => "direct_domains",
+            RuleList::ProxyDomains => "proxy_domains",
+            RuleList::DirectIps => "direct_ips",
+            RuleList::ProxyIps => "proxy_ips",
+        }
+    }
+
+    /// 校验规则语法
+    pub fn validate(self, value: &str) -> Result<()> {
+        match self {
+            RuleList::DirectDomains | RuleList::ProxyDomains => {
+                ConfigValidator::validate_routing_domain(value)
+            }
+            RuleList::DirectIps | RuleList::ProxyIps => {
+                ConfigValidator::validate_routing_cidr(value)
+            }
+        }
+    }
+
+    fn list_mut(self, config: &mut RoutingConfig) -> &mut Vec<String> {
+        match self {
+            RuleList::DirectDomains => &mut config.direct_domains,
+            RuleList::ProxyDomains => &mut config.proxy_domains,
+            RuleList::DirectIps => &mut config.direct_ips,
+            RuleList::ProxyIps => &mut config.proxy_ips,
+        }
+    }
+
+    fn list(self, config: &RoutingConfig) -> &[String] {
+        match self {
+            RuleList::DirectDomains => &config.direct_domains,
+            RuleList::ProxyDomains => &config.proxy_domains,
+            RuleList::DirectIps => &config.direct_ips,
+            RuleList::ProxyIps => &config.proxy_ips,
+        }
+    }
+
+    fn get(self, rules: &RoutingRuleOverride) -> Option<&Vec<String>> {
+        match self {
+            RuleList::DirectDomains => rules.direct_domains.as_ref(),
+            RuleList::ProxyDomains => rules.proxy_domains.as_ref(),
+            RuleList::DirectIps => rules.direct_ips.as_ref(),
+            RuleList::ProxyIps => rules.proxy_ips.as_ref(),
+        }
+    }
+
+    fn slot_mut(self, rules: &mut RoutingRuleOverride) -> &mut Option<Vec<String>> {
+        match self {
+            RuleList::DirectDomains => &mut rules.direct_domains,
+            RuleList::ProxyDomains => &mut rules.proxy_domains,
+            RuleList::DirectIps => &mut rules.direct_ips,
+            RuleList::ProxyIps => &mut rules.proxy_ips,
+        }
+    }
+}
+
+/// 各 forwarder 的路由器（跨会话共享，重连后保留在线修改的规则）
+pub struct RoutingManager {
+    /// 配置热重载时增删
+    routers: RwLock<BTreeMap<String, Arc<GeoIpRouter>>>,
+    overrides_path: Option<PathBuf>,
+    /// 当前覆盖内容，同时用于串行化规则修改
+    overrides: Mutex<RoutingOverrides>,
+}
+
+impl RoutingManager {
+    /// 为配置了 routing 的 forwarder 创建路由器
+    ///
+    /// `forwarders` 应当已经合并了 `overrides`
+    pub fn new(
+        forwarders: &[ForwarderConfig],
+        overrides: RoutingOverrides,
+        overrides_path: Option<PathBuf>,
+    ) -> Self {
+        let routers = forwarders
+            .iter()
+            .filter_map(|forwarder| Some((forwarder.name.clone(), build_router(forwarder)?)))
+            .collect();
+        Self {
+            routers: RwLock::new(routers),
+            overrides_path,
+            overrides: Mutex::new(overrides),
+        }
+    }
+
+    /// 获取 forwarder 的路由器
+    pub fn router(&self, forwarder: &str) -> Option<Arc<GeoIpRouter>> {
+        self.routers.read().get(forwarder).cloned()
+    }
+
+    /// 配置热重载新增或修改了 forwarder：按新的 routing 配置重新创建路由器（未配置时移除）
+    ///
+    /// `forwarder` 应当已经合并了覆盖
+    pub fn replace(&self, forwarder: &ForwarderConfig) {
+        let mut routers = self.routers.write();
+        match build_router(forwarder) {
+            Some(router) => {
+                routers.insert(forwarder.name.clone(), router);
+            }
+            None => {
+                routers.remove(&forwarder.name);
+            }
+        }
+    }
+
+    /// 配置热重载删除了 forwarder：移除它的路由器
+    pub fn remove(&self, forwarder: &str) {
+        self.routers.write().remove(forwarder);
+    }
+
+    /// 添加规则
+    pub fn add_rule(&self, forwarder: &str, list: RuleList, value: &str) -> Result<()> {
+        let value = value.trim();
+        list.validate(value)?;
+        self.update(forwarder, list, |entries| {
+            if entries.iter().any(|entry| entry == value) {
+                bail!("'{}' is already in {}", value, list.as_str());
+            }
+            entries.push(value.to_string());
+            Ok(())
+        })?;
+        info!(
+            "Forwarder '{}': added '{}' to {}",
+            forwarder,
+            value,
+            list.as_str()
+        );
+        Ok(())
+    }
+
+    /// 删除规则
+    pub fn remove_rule(&self, forwarder: &str, list: RuleList, value: &str) -> Result<()> {
+        let value = value.trim();
+        self.update(forwarder, list, |entries| {
+            let before = entries.len();
+            entries.retain(|entry| entry != value);
+            if entries.len() == before {
+                bail!("'{}' is not in {}", value, list.as_str());
+            }
+            Ok(())
+        })?;
+        info!(
+            "Forwarder '{}': removed '{}' from {}",
+            forwarder,
+            value,
+            list.as_str()
+        );
+        Ok(())
+    }
+
+    /// 修改规则列表：先持久化覆盖文件，成功后再让新规则生效
+    fn update<F>(&self, forwarder: &str, list: RuleList, modify: F) -> Result<()>
+    where
+        F: FnOnce(&mut Vec<String>) -> Result<()>,
+    {
+        let Some(router) = self.router(forwarder) else {
+            bail!("Forwarder '{}' has no routing configured", forwarder);
+        };
+
+        let mut overrides = self.overrides.lock();
+        let mut config = router.config();
+        modify(list.list_mut(&mut config))?;
+
+        let mut updated = overrides.clone();
+        *list.slot_mut(updated.forwarders.entry(forwarder.to_string()).or_default()) =
+            Some(list.list(&config).to_vec());
+        if let Some(ref path) = self.overrides_path {
+            updated.save(path)?;
+        }
+
+        router.reload(config)?;
+        *overrides = updated;
+        Ok(())
+    }
+}
+
+/// 为配置了 routing 的 forwarder 创建路由器（未配置或创建失败时返回 None）
+fn build_router(forwarder: &ForwarderConfig) -> Option<Arc<GeoIpRouter>> {
+    let routing_config = forwarder.routing.as_ref()?;
+    match GeoIpRouter::new(routing_config.clone()) {
+        Ok(router) => Some(Arc::new(router)),
+        Err(e) => {
+            warn!(
+                "Forwarder '{}': Failed to initialize GeoIP router: {}",
+                forwarder.name, e
+            );
+            None
+        }
+    }
+}
+
+/// `/routing` 页面
+pub(crate) struct RoutingUi {
+    manager: Arc<RoutingManager>,
+    token: String,
+}
+
+impl RoutingUi {
+    pub(crate) fn new(manager: Arc<RoutingManager>, token: String) -> Self {
+        Self { manager, token }
+    }
+
+    /// 处理 `/routing` 下的请求
+    pub(crate) fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        let authorized = request
+            .basic_auth_password()
+            .is_some_and(|password| constant_time_eq(password.as_bytes(), self.token.as_bytes()));
+        if !authorized {
+            return HttpResponse::unauthorized(AUTH_REALM);
+        }
+
+        match (request.method.as_str(), request.path()) {
+            ("GET", "/routing") | ("GET", "/routing/") => HttpResponse::html(self.render_page()),
+            ("GET", "/routing/test") => self.handle_test(request),
+            ("POST", "/routing/add") | ("POST", "/routing/remove") => {
+                if !same_origin(request) {
+                    return HttpResponse::error(403);
+                }
+                self.handle_edit(request)
+            }
+            (_, "/routing")
+            | (_, "/routing/")
+            | (_, "/routing/test")
+            | (_, "/routing/add")
+            | (_, "/routing/remove") => HttpResponse::error(405),
+            _ => HttpResponse::not_found(),
+        }
+    }
+
+    fn handle_edit(&self, request: &HttpRequest) -> HttpResponse {
+        let forwarder = request.form_param("forwarder").unwrap_or_default();
+        let value = request.form_param("value").unwrap_or_default();
+        let Some(list) = request
+            .form_param("list")
+            .as_deref()
+            .and_then(RuleList::parse)
+        else {
+            return error_page("Unknown rule list");
+        };
+
+        let result = if request.path() == "/routing/add" {
+            self.manager.add_rule(&forwarder, list, &value)
+        } else {
+            self.manager.remove_rule(&forwarder, list, &value)
+        };
+        match result {
+            Ok(()) => HttpResponse::redirect("/routing"),
+            Err(e) => error_page(&format!("{:#}", e)),
+        }
+    }
+
+    fn handle_test(&self, request: &HttpRequest) -> HttpResponse {
+        let forwarder = request.query_param("forwarder").unwrap_or_default();
+        let Some(target) = request.query_param("target").filter(|t| !t.is_empty()) else {
+            return json_error("Missing 'target' parameter");
+        };
+        let Some(router) = self.manager.router(&forwarder) else {
+            return json_error(&format!(
+                "Forwarder '{}' has no routing configured",
+                forwarder
+            ));
+        };
+
+        let decision = router.should_direct_connect(&target);
+        HttpResponse::json(
+            serde_json::json!({
+                "forwarder": forwarder,
+                "target": target,
+                "direct": decision.is_direct(),
+                "rule": decision.rule.as_str(),
+                "matched": decision.matched,
+            })
+            .to_string(),
+        )
+    }
+
+    fn render_page(&self) -> String {
+        let mut sections = String::new();
+        let routers = self.manager.routers.read().clone();
+        for (name, router) in &routers {
+            let config = router.config();
+            let name = escape_html(name);
+            let strategy = match config.default_strategy {
+                RoutingStrategy::Direct => "direct",
+                _ => "proxy",
+            };
+
+            let mut lists = String::new();
+            for list in RuleList::ALL {
+                let mut items = String::new();
+                for value in list.list(&config) {
+                    items.push_str(&format!(
+                        r#"<li><code>{value}</code>
+<form method="post" action="/routing/remove"><input type="hidden" name="forwarder" value="{name}"><input type="hidden" name="list" value="{list}"><input type="hidden" name="value" value="{value}"><button>删除</button></form></li>"#,
+                        value = escape_html(value),
+                        name = name,
+                        list = list.as_str()
+                    ));
+                }
+                if items.is_empty() {
+                    items.push_str("<li class=\"empty\">（空）</li>");
+                }
+                lists.push_str(&format!(
+                    "<div class=\"list\"><h3>{}</h3><ul>{}</ul></div>",
+                    list.as_str(),
+                    items
+                ));
+            }
+
+            let options: String = RuleList::ALL
+                .iter()
+                .map(|list| format!("<option>{}</option>", list.as_str()))
+                .collect();
+
+            sections.push_str(&format!(
+                r#"<section>
+<h2>{name} <small>默认策略：{strategy}</small></h2>
+<div class="lists">{lists}</div>
+<form method="post" action="/routing/add">
+<input type="hidden" name="forwarder" value="{name}">
+<select name="list">{options}</select>
+<input name="value" placeholder="*.corp.example 或 10.0.0.0/8" required>
+<button>添加</button>
+</form>
+<form method="get" action="/routing/test">
+<input type="hidden" name="forwarder" value="{name}">
+<input name="target" placeholder="host:port" required>
+<button>测试</button>
+</form>
+</section>"#
+            ));
+        }
+        if sections.is_empty() {
+            sections.push_str("<p>没有配置 routing 的 forwarder。</p>");
+        }
+
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="zh-CN">
+<head>
+<meta charset="UTF-8">
+<meta name="viewport" content="width=device-width, initial-scale=1.0">
+<title>TLS Tunnel - 路由规则</title>
+<style>
+body {{ font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', sans-serif; margin: 20px; color: #333; }}
+section {{ border: 1px solid #ddd; border-radius: 6px; padding: 12px 16px; margin-bottom: 16px; }}
+small {{ color: #888; font-weight: normal; }}
+.lists {{ display: flex; flex-wrap: wrap; gap: 16px; }}
+.list {{ min-width: 220px; }}
+.list h3 {{ font-size: 14px; margin: 8px 0; }}
+ul {{ padding-left: 18px; margin: 0; }}
+li form {{ display: inline; }}
+.empty {{ color: #aaa; }}
+section > form {{ margin-top: 10px; }}
+</style>
+</head>
+<body>
+<h1>路由规则</h1>
+<p>修改立即生效{persist}。</p>
+{sections}
+</body>
+</html>"#,
+            persist = if self.manager.overrides_path.is_some() {
+                "，并保存到覆盖文件"
+            } else {
+                "，重启后恢复为配置文件中的规则"
+            },
+            sections = sections
+        )
+    }
+}
+
+/// 规则修改失败时的错误页面
+fn error_page(message: &str) -> HttpResponse {
+    HttpResponse::html(format!(
+        r#"<!DOCTYPE html>
+<html lang="zh-CN">
+<head><meta charset="UTF-8"><title>TLS Tunnel - 路由规则</title></head>
+<body>
+<h1>操作失败</h1>
+<p>{}</p>
+<p><a href="/routing">返回</a></p>
+</body>
+</html>"#,
+        escape_html(message)
+    ))
+    .with_status(400)
+}
+
+fn json_error(message: &str) -> HttpResponse {
+    HttpResponse::json(serde_json::json!({ "error": message }).to_string()).with_status(400)
+}
+
+/// 拒绝来自其他站点的表单提交（浏览器会附带 Basic 认证信息）
+fn same_origin(request: &HttpRequest) -> bool {
+    let Some(origin) = request.header("Origin") else {
+        return true;
+    };
+    let Some(host) = request.header("Host") else {
+        return false;
+    };
+    origin
+        .split_once("://")
+        .is_some_and(|(_, origin_host)| origin_host == host)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProxyType;
+
+    /// "user:s3cret"
+    const AUTH_HEADER: &str = "Basic dXNlcjpzM2NyZXQ=";
+
+    fn routing_config() -> RoutingConfig {
+        RoutingConfig {
+            geoip_db: None,
+            direct_countries: vec![],
+            proxy_countries: vec![],
+            direct_ips: vec!["192.168.0.0/16".to_string()],
+            proxy_ips: vec![],
+            direct_domains: vec!["*.internal.example".to_string()],
+            proxy_domains: vec!["blocked.example".to_string()],
+            default_strategy: RoutingStrategy::Direct,
+            schedules: vec![],
+            utc_offset: None,
+            quota: None,
+        }
+    }
+
+    fn forwarders() -> Vec<ForwarderConfig> {
+        vec![
+            ForwarderConfig {
+                name: "web".to_string(),
+                proxy_type: ProxyType::HttpProxy,
+                bind_addr: "127.0.0.1".to_string(),
+                bind_port: 8080,
+                routing: Some(routing_config()),
+                fast_fail: None,
+                direct_egress: None,
+                socket: None,
+                report_direct: false,
+                idle_timeout_secs: None,
+                header_rules: None,
+            },
+            ForwarderConfig {
+                name: "plain".to_string(),
+                proxy_type: ProxyType::Socks5Proxy,
+                bind_addr: "127.0.0.1".to_string(),
+                bind_port: 1080,
+                routing: None,
+                fast_fail: None,
+                direct_egress: None,
+                socket: None,
+                report_direct: false,
+                idle_timeout_secs: None,
+                header_rules: None,
+            },
+        ]
+    }
+
+    fn request(method: &str, target: &str, body: &str) -> HttpRequest {
+        HttpRequest {
+            method: method.to_string(),
+            target: target.to_string(),
+            headers: vec![("Authorization".to_string(), AUTH_HEADER.to_string())],
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("tls-tunnel-routing-{}.toml", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_overrides_merge_precedence() {
+        let mut overrides = RoutingOverrides::default();
+        overrides.forwarders.insert(
+            "web".to_string(),
+            RoutingRuleOverride {
+                proxy_domains: Some(vec!["*.corp.example".to_string()]),
+                direct_ips: Some(vec![]),
+                ..Default::default()
+            },
+        );
+        overrides.forwarders.insert(
+            "plain".to_string(),
+            RoutingRuleOverride {
+                proxy_ips: Some(vec!["10.0.0.0/8".to_string()]),
+                ..Default::default()
+            },
+        );
+
+        let mut forwarders = forwarders();
+        overrides.apply(&mut forwarders);
+
+        let routing = forwarders[0].routing.as_ref().unwrap();
+        // 覆盖中设置的列表整体替换主配置（包括清空）
+        assert_eq!(routing.proxy_domains, vec!["*.corp.example"]);
+        assert!(routing.direct_ips.is_empty());
+        // 未设置的列表沿用主配置
+        assert_eq!(routing.direct_domains, vec!["*.internal.example"]);
+        assert!(routing.proxy_ips.is_empty());
+        // 没有 routing 的 forwarder 不会被创建 routing
+        assert!(forwarders[1].routing.is_none());
+    }
+
+    #[test]
+    fn test_overrides_persistence_round_trip() {
+        let path = temp_path();
+        let manager = RoutingManager::new(
+            &forwarders(),
+            RoutingOverrides::default(),
+            Some(path.clone()),
+        );
+        manager
+            .add_rule("web", RuleList::ProxyDomains, " *.corp.example ")
+            .unwrap();
+        manager
+            .remove_rule("web", RuleList::DirectIps, "192.168.0.0/16")
+            .unwrap();
+
+        let loaded = RoutingOverrides::load(&path).unwrap();
+        assert_eq!(loaded, *manager.overrides.lock());
+
+        // 重新启动：主配置合并覆盖文件后得到与运行时一致的规则
+        let mut restarted = forwarders();
+        loaded.apply(&mut restarted);
+        let routing = restarted[0].routing.as_ref().unwrap();
+        let live = manager.router("web").unwrap().config();
+        assert_eq!(routing.proxy_domains, live.proxy_domains);
+        assert_eq!(
+            routing.proxy_domains,
+            vec!["blocked.example", "*.corp.example"]
+        );
+        assert!(routing.direct_ips.is_empty());
+        assert_eq!(routing.direct_domains, live.direct_domains);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_applied_rule_changes_test_endpoint() {
+        let manager = Arc::new(RoutingManager::new(
+            &forwarders(),
+            RoutingOverrides::default(),
+            None,
+        ));
+        let ui = RoutingUi::new(manager, "s3cret".to_string());
+        let test_target = "/routing/test?forwarder=web&target=203.0.113.7%3A443";
+
+        let decision = |ui: &RoutingUi| {
+            let response = ui.handle(&request("GET", test_target, ""));
+            assert_eq!(response.status, 200);
+            serde_json::from_str::<serde_json::Value>(&response.body).unwrap()
+        };
+
+        let before = decision(&ui);
+        assert_eq!(before["direct"], true);
+        assert_eq!(before["rule"], "direct_by_default");
+
+        let response = ui.handle(&request(
+            "POST",
+            "/routing/add",
+            "forwarder=web&list=proxy_ips&value=203.0.113.0%2F24",
+        ));
+        assert_eq!(response.status, 303);
+
+        let after = decision(&ui);
+        assert_eq!(after["direct"], false);
+        assert_eq!(after["rule"], "proxy_by_ip");
+        assert_eq!(after["matched"], "203.0.113.0/24");
+    }
+
+    #[test]
+    fn test_auth_and_validation() {
+        let manager = Arc::new(RoutingManager::new(
+            &forwarders(),
+            RoutingOverrides::default(),
+            None,
+        ));
+        let ui = RoutingUi::new(manager.clone(), "s3cret".to_string());
+
+        // 未认证
+        let response = ui.handle(&HttpRequest::get("/routing"));
+        assert_eq!(response.status, 401);
+        assert!(response
+            .headers
+            .iter()
+            .any(|(name, _)| *name == "WWW-Authenticate"));
+
+        let page = ui.handle(&request("GET", "/routing", ""));
+        assert_eq!(page.status, 200);
+        assert!(page.body.contains("*.internal.example"));
+
+        // 非法输入在服务端被拒绝，规则不变
+        let response = ui.handle(&request(
+            "POST",
+            "/routing/add",
+            "forwarder=web&list=direct_ips&value=10.0.0.0%2F33",
+        ));
+        assert_eq!(response.status, 400);
+        assert!(response.body.contains("Invalid IP/CIDR"));
+
+        let response = ui.handle(&request(
+            "POST",
+            "/routing/add",
+            "forwarder=web&list=proxy_domains&value=bad+domain",
+        ));
+        assert_eq!(response.status, 400);
+        assert!(response.body.contains("Invalid domain"));
+
+        let response = ui.handle(&request(
+            "POST",
+            "/routing/add",
+            "forwarder=plain&list=proxy_domains&value=example.com",
+        ));
+        assert_eq!(response.status, 400);
+
+        assert_eq!(
+            manager.router("web").unwrap().config().direct_ips,
+            vec!["192.168.0.0/16"]
+        );
+
+        // 跨站表单提交
+        let mut cross_site = request(
+            "POST",
+            "/routing/add",
+            "forwarder=web&list=proxy_domains&value=evil.example",
+        );
+        cross_site
+            .headers
+            .push(("Host".to_string(), "127.0.0.1:9090".to_string()));
+        cross_site
+            .headers
+            .push(("Origin".to_string(), "https://evil.example".to_string()));
+        assert_eq!(ui.handle(&cross_site).status, 403);
+    }
+}
@@ -0,0 +1,214 @@
+/// 运行中的客户端配置
+///
+/// 记录当前应用的配置及其版本信息，供统计服务器的 `/config` 和 `/config/diff` 端点使用
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::config::{compat, ClientFullConfig, ConfigDiff, ConfigGeneration, ConfigValidator};
+use crate::stats_http::{HttpRequest, HttpResponse};
+use tracing::{debug, warn};
+
+/// 运行中的配置及其版本信息（跨会话共享）
+#[derive(Clone)]
+pub(crate) struct RunningConfig {
+    inner: Arc<parking_lot::RwLock<Applied>>,
+}
+
+struct Applied {
+    config: Arc<ClientFullConfig>,
+    generation: ConfigGeneration,
+}
+
+/// `/config/diff` 的响应
+#[derive(Debug, Serialize)]
+struct DiskDiff {
+    /// 运行中配置的版本信息
+    running: ConfigGeneration,
+    /// 磁盘上配置的内容哈希
+    on_disk_hash: String,
+    /// 磁盘上的配置是否与运行中的不同
+    changed: bool,
+    diff: ConfigDiff,
+}
+
+/// 读取磁盘配置失败
+struct DiskError {
+    status: u16,
+    message: String,
+}
+
+impl RunningConfig {
+    /// 记录首次加载的配置（`source` 为配置文件路径）
+    pub fn new(config: ClientFullConfig, source: Option<PathBuf>) -> Self {
+        let generation = ConfigGeneration::new(&config, source);
+        Self {
+            inner: Arc::new(parking_lot::RwLock::new(Applied {
+                config: Arc::new(config),
+                generation,
+            })),
+        }
+    }
+
+    /// 当前应用的配置版本
+    pub fn generation(&self) -> ConfigGeneration {
+        self.inner.read().generation.clone()
+    }
+
+    /// 当前应用的配置
+    pub fn config(&self) -> Arc<ClientFullConfig> {
+        self.inner.read().config.clone()
+    }
+
+    /// 应用新的配置（版本序号加一，来源不变）
+    pub fn apply(&self, config: ClientFullConfig) {
+        let mut applied = self.inner.write();
+        applied.generation = applied.generation.next(&config);
+        applied.config = Arc::new(config);
+    }
+
+    /// 服务器接受配置后保存为最近一次可用的配置（仅开启 `fallback_to_last_good` 时）
+    pub fn save_last_good(&self) {
+        let (config, source) = {
+            let applied = self.inner.read();
+            (applied.config.clone(), applied.generation.source.clone())
+        };
+        if !config.client.fallback_to_last_good {
+            return;
+        }
+        let Some(path) =
+            super::last_good::cache_path(config.client.state_dir.as_deref(), source.as_deref())
+        else {
+            return;
+        };
+        match super::last_good::save(&path, &config) {
+            Ok(()) => debug!("Saved last good configuration to {}", path.display()),
+            Err(e) => warn!("Failed to save last good configuration: {:#}", e),
+        }
+    }
+
+    /// 处理 `/config` 和 `/config/diff` 请求
+    pub fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        match request.path() {
+            "/config" | "/config/" => HttpResponse::json(
+                serde_json::to_string_pretty(&self.generation()).unwrap_or_default(),
+            ),
+            "/config/diff" => match self.diff_on_disk() {
+                Ok(diff) => {
+                    HttpResponse::json(serde_json::to_string_pretty(&diff).unwrap_or_default())
+                }
+                Err(e) => HttpResponse::json(serde_json::json!({ "error": e.message }).to_string())
+                    .with_status(e.status),
+            },
+            _ => HttpResponse::not_found(),
+        }
+    }
+
+    /// 重新读取并校验磁盘上的配置文件，与运行中的配置比较（不应用）
+    fn diff_on_disk(&self) -> Result<DiskDiff, DiskError> {
+        let (running, generation) = {
+            let applied = self.inner.read();
+            (applied.config.clone(), applied.generation.clone())
+        };
+        let source = generation.source.clone().ok_or_else(|| DiskError {
+            status: 404,
+            message: "Configuration was not loaded from a file".to_string(),
+        })?;
+
+        let content = std::fs::read_to_string(&source).map_err(|e| DiskError {
+            status: 422,
+            message: format!("Failed to read {}: {}", source.display(), e),
+        })?;
+        // 只输出解析错误的描述，不输出错误所在行的内容（可能包含密钥）
+        let (on_disk, compat) = compat::parse_client_config(&content).map_err(|e| DiskError {
+            status: 422,
+            message: format!("Failed to parse client configuration: {}", e.message()),
+        })?;
+        ConfigValidator::validate_field_aliases(&compat)
+            .and_then(|_| on_disk.validate())
+            .map_err(|e| DiskError {
+                status: 422,
+                message: format!("Configuration validation failed: {:#}", e),
+            })?;
+
+        let diff = ConfigDiff::client(&running, &on_disk);
+        Ok(DiskDiff {
+            on_disk_hash: crate::config::content_hash(&on_disk),
+            changed: !diff.is_empty(),
+            running: generation,
+            diff,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+[client]
+server_addr = "127.0.0.1"
+server_port = 8443
+auth_key = "running-secret-0123456789"
+
+[[proxies]]
+name = "web"
+publish_port = 8080
+local_port = 3000
+"#;
+
+    fn request(target: &str) -> HttpRequest {
+        HttpRequest::get(target)
+    }
+
+    fn body(response: &HttpResponse) -> serde_json::Value {
+        serde_json::from_str(&response.body).unwrap()
+    }
+
+    #[test]
+    fn test_config_endpoint_reports_generation() {
+        let running = RunningConfig::new(toml::from_str(CONFIG).unwrap(), None);
+        let response = running.handle(&request("/config"));
+        assert_eq!(response.status, 200);
+        let json = body(&response);
+        assert_eq!(json["generation"], 1);
+        assert_eq!(json["hash"].as_str().unwrap().len(), 64);
+        assert!(json["source"].is_null());
+
+        // 非文件来源无法比较
+        assert_eq!(running.handle(&request("/config/diff")).status, 404);
+    }
+
+    #[test]
+    fn test_diff_against_edited_file() {
+        let path =
+            std::env::temp_dir().join(format!("tls-tunnel-diff-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, CONFIG).unwrap();
+        let running = RunningConfig::new(toml::from_str(CONFIG).unwrap(), Some(path.clone()));
+
+        let json = body(&running.handle(&request("/config/diff")));
+        assert_eq!(json["changed"], false);
+        assert_eq!(json["on_disk_hash"], json["running"]["hash"]);
+
+        let edited = CONFIG
+            .replace("running-secret-0123456789", "edited-secret-0123456789")
+            .replace("local_port = 3000", "local_port = 3001");
+        std::fs::write(&path, edited).unwrap();
+        let response = running.handle(&request("/config/diff"));
+        assert_eq!(response.status, 200);
+        assert!(!response.body.contains("running-secret"));
+        assert!(!response.body.contains("edited-secret"));
+        let json = body(&response);
+        assert_eq!(json["changed"], true);
+        assert_eq!(json["diff"]["proxies"]["changed"][0]["key"], "web:8080");
+        assert_eq!(json["diff"]["settings"][0]["field"], "auth_key");
+
+        // 磁盘上的配置无效时报告错误，不泄露内容
+        std::fs::write(&path, "[client]\nauth_key = \"leaked-secret").unwrap();
+        let response = running.handle(&request("/config/diff"));
+        assert_eq!(response.status, 422);
+        assert!(!response.body.contains("leaked-secret"));
+
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -2,44 +2,520 @@
 ///
 /// 提供客户端代理的实时统计信息跟踪和 HTTP 服务器
 use anyhow::{Context, Result};
+use arc_swap::ArcSwap;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
 use std::net::SocketAddr;
-use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicU16, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::net::TcpListener;
-use tracing::{error, info};
+use tracing::info;
 
-use crate::config::ProxyType;
+use super::forward_report::ForwardReports;
+use super::forwarder::{FailedTargetManager, FastFailSnapshot};
+use super::geoip::{GeoIpRouter, RouteDecision, RouteRule};
+use super::health::{HealthMonitor, HealthReport, HealthStatus};
+use super::quota::QuotaStatus;
+use super::routing_ui::RoutingUi;
+use super::running_config::RunningConfig;
+use crate::config::{ProxyType, StatsLimitConfig};
+use crate::connection_pool::{ConnectionPool, PoolMetrics};
+use crate::integrity::IntegrityCounters;
+use crate::protocol::PeerInfo;
+use crate::relay_memory::{RelayMemory, RelayMemoryStats};
+use crate::stats::{ProxyStatsCore, StatsFingerprint, StatsRole};
+use crate::stats_http::{
+    self, HttpRequest, HttpResponse, StatsListener, StatsQuery, StatsServerHealth, StatsServers,
+};
+use crate::stats_ws::{self, StatsFeed};
+use crate::traffic::{TrafficMeter, TrafficScope};
+
+/// 最近路由决策列表的最大长度
+const RECENT_DECISIONS_CAPACITY: usize = 50;
+
+/// 最近连接列表的最大长度
+const RECENT_CONNECTIONS_CAPACITY: usize = 50;
+
+/// 统计"最近失败次数"的时间窗口（秒）
+const RECENT_FAILURE_WINDOW_SECS: u64 = 300;
+
+/// 保留的最近失败时间戳数量上限
+const RECENT_FAILURES_CAPACITY: usize = 100;
+
+/// 分别统计的目标数量上限（超出后新目标只计入代理总数）
+const TARGETS_CAPACITY: usize = 256;
+
+/// 最近失败次数达到该值时，统计面板以警告颜色显示
+pub const STREAM_FAILURE_WARN_THRESHOLD: u64 = 3;
 
 /// 客户端代理统计信息
+///
+/// 序列化时展开 [`ProxyStatsCore`] 的公共字段，并继续输出旧字段名
+/// `bind_addr`/`bind_port` 以兼容现有的监控脚本
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "ClientProxyStatsRepr", from = "ClientProxyStatsRepr")]
 pub struct ClientProxyStats {
-    /// 代理名称
-    pub name: String,
+    /// 与服务端统计共用的字段
+    pub core: ProxyStatsCore,
     /// 代理类型
     pub proxy_type: String,
-    /// 本地监听地址
-    pub bind_addr: String,
-    /// 本地监听端口
-    pub bind_port: u16,
-    /// 目标地址（服务器端发布地址）
-    pub target_addr: String,
-    /// 目标端口（服务器端发布端口）
-    pub target_port: u16,
-    /// 当前活跃连接数
-    pub active_connections: usize,
-    /// 总连接数
+    /// 路由决策统计（仅启用了路由规则的 forwarder）
+    pub routing: Option<RoutingStatsSnapshot>,
+    /// 打开的隧道 stream 数（仅启用 connection_reuse 的 visitor，与 total_connections 对比可看出复用效果）
+    pub tunnel_streams: Option<u64>,
+    /// 升级为 WebSocket 的本地连接数（仅 http/1.1 代理）
+    pub upgraded_connections: Option<u64>,
+    /// 建立隧道 stream 失败的分类统计（仅 visitor 和 forwarder）
+    pub failures: StreamFailureStats,
+    /// 快速失败黑名单（仅 forwarder）
+    pub fast_fail: Option<FastFailSnapshot>,
+    /// 按目标分别统计（visitor 网关按目标代理名称，配置了 local_targets 的代理按本地地址）
+    pub targets: Option<BTreeMap<String, TargetStats>>,
+    /// 最近结束的外部连接（仅开启了 report_peers 的代理，最新的在最后）
+    pub recent_connections: Option<Vec<RecentConnection>>,
+    /// 本地连接池的复用指标（仅复用本地连接的代理）
+    pub pool: Option<PoolMetrics>,
+}
+
+/// 单条外部连接记录（来源由服务器在 stream 前导中上报）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecentConnection {
+    /// 服务器看到的来源地址
+    pub peer_addr: String,
+    /// 服务器接受连接的时间（Unix 毫秒时间戳）
+    pub accepted_at_ms: u64,
+    /// 客户端处理该连接的时长（毫秒）
+    pub duration_ms: u64,
+    /// 发往服务器的字节数（本地服务的响应）
+    pub bytes_sent: u64,
+    /// 从服务器收到的字节数（外部连接的请求）
+    pub bytes_received: u64,
+}
+
+/// 单个目标的统计
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetStats {
+    /// 累计连接数
     pub total_connections: u64,
-    /// 发送字节数
+    /// 活跃连接数
+    pub active_connections: u64,
+    /// 发往目标的字节数
     pub bytes_sent: u64,
-    /// 接收字节数
+    /// 从目标收到的字节数
     pub bytes_received: u64,
-    /// 启动时间（Unix 时间戳）
-    pub start_time: u64,
-    /// 连接状态
-    pub status: String,
+    /// 连接目标失败次数（visitor 网关为建立 visitor stream 失败，包括服务器拒绝）
+    pub failures: u64,
+}
+
+/// [`ClientProxyStats`] 的序列化格式
+#[derive(Serialize, Deserialize)]
+struct ClientProxyStatsRepr {
+    #[serde(flatten)]
+    core: ProxyStatsCore,
+    proxy_type: String,
+    /// 已弃用，同 `listen_addr`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bind_addr: Option<String>,
+    /// 已弃用，同 `listen_port`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bind_port: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    routing: Option<RoutingStatsSnapshot>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tunnel_streams: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    upgraded_connections: Option<u64>,
+    #[serde(default, skip_serializing_if = "StreamFailureStats::is_empty")]
+    failures: StreamFailureStats,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fast_fail: Option<FastFailSnapshot>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    targets: Option<BTreeMap<String, TargetStats>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    recent_connections: Option<Vec<RecentConnection>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pool: Option<PoolMetrics>,
+}
+
+impl From<ClientProxyStats> for ClientProxyStatsRepr {
+    fn from(stats: ClientProxyStats) -> Self {
+        Self {
+            bind_addr: Some(stats.core.listen_addr.clone()),
+            bind_port: Some(stats.core.listen_port),
+            core: stats.core,
+            proxy_type: stats.proxy_type,
+            routing: stats.routing,
+            tunnel_streams: stats.tunnel_streams,
+            upgraded_connections: stats.upgraded_connections,
+            failures: stats.failures,
+            fast_fail: stats.fast_fail,
+            targets: stats.targets,
+            recent_connections: stats.recent_connections,
+            pool: stats.pool,
+        }
+    }
+}
+
+impl From<ClientProxyStatsRepr> for ClientProxyStats {
+    fn from(repr: ClientProxyStatsRepr) -> Self {
+        let mut core = repr.core;
+        core.role = StatsRole::Client;
+        if let Some(addr) = repr.bind_addr {
+            core.listen_addr = addr;
+        }
+        if let Some(port) = repr.bind_port {
+            core.listen_port = port;
+        }
+        Self {
+            core,
+            proxy_type: repr.proxy_type,
+            routing: repr.routing,
+            tunnel_streams: repr.tunnel_streams,
+            upgraded_connections: repr.upgraded_connections,
+            failures: repr.failures,
+            fast_fail: repr.fast_fail,
+            targets: repr.targets,
+            recent_connections: repr.recent_connections,
+            pool: repr.pool,
+        }
+    }
+}
+
+/// 单条路由决策记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentRouteDecision {
+    /// 目标地址
+    pub target: String,
+    /// 决策结果（direct、proxy 或 block）
+    pub decision: String,
+    /// 命中的规则类别
+    pub rule: String,
+    /// 具体匹配项（域名模式、CIDR 或国家代码）
+    pub matched: Option<String>,
+    /// 决策时间（Unix 时间戳）
+    pub timestamp: u64,
+}
+
+/// 路由决策统计快照
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoutingStatsSnapshot {
+    /// 各规则类别的命中次数
+    pub decisions: BTreeMap<String, u64>,
+    /// 直连路径的字节数
+    pub direct_bytes: u64,
+    /// 经隧道代理路径的字节数
+    pub proxied_bytes: u64,
+    /// 最近的路由决策（最新的在最后）
+    pub recent: Vec<RecentRouteDecision>,
+    /// 月配额使用情况（仅配置了 quota 的 forwarder）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<QuotaStatus>,
+    /// 当前生效的时间段
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_schedule: Option<String>,
+}
+
+/// 路由决策统计（线程安全）
+pub struct RoutingStats {
+    counters: [AtomicU64; RouteRule::ALL.len()],
+    direct: TrafficScope,
+    proxied: TrafficScope,
+    recent: parking_lot::Mutex<VecDeque<RecentRouteDecision>>,
+}
+
+impl RoutingStats {
+    /// 创建新的路由统计
+    pub fn new() -> Self {
+        Self {
+            counters: Default::default(),
+            direct: TrafficScope::new(),
+            proxied: TrafficScope::new(),
+            recent: parking_lot::Mutex::new(VecDeque::with_capacity(RECENT_DECISIONS_CAPACITY)),
+        }
+    }
+
+    /// 记录一次路由决策
+    pub fn record_decision(&self, target: &str, decision: &RouteDecision) {
+        self.counters[decision.rule as usize].fetch_add(1, Ordering::Relaxed);
+
+        let record = RecentRouteDecision {
+            target: target.to_string(),
+            decision: if decision.is_direct() {
+                "direct".to_string()
+            } else if decision.is_blocked() {
+                "block".to_string()
+            } else {
+                "proxy".to_string()
+            },
+            rule: decision.rule.as_str().to_string(),
+            matched: decision.matched.clone(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        };
+
+        let mut recent = self.recent.lock();
+        if recent.len() >= RECENT_DECISIONS_CAPACITY {
+            recent.pop_front();
+        }
+        recent.push_back(record);
+    }
+
+    /// 直连或代理路径的流量
+    pub fn path(&self, direct: bool) -> &TrafficScope {
+        if direct {
+            &self.direct
+        } else {
+            &self.proxied
+        }
+    }
+
+    /// 获取某个规则类别的命中次数
+    pub fn decision_count(&self, rule: RouteRule) -> u64 {
+        self.counters[rule as usize].load(Ordering::Relaxed)
+    }
+
+    /// 获取统计快照
+    pub fn snapshot(&self) -> RoutingStatsSnapshot {
+        RoutingStatsSnapshot {
+            decisions: RouteRule::ALL
+                .iter()
+                .map(|rule| (rule.as_str().to_string(), self.decision_count(*rule)))
+                .collect(),
+            direct_bytes: self.direct.totals().total(),
+            proxied_bytes: self.proxied.totals().total(),
+            recent: self.recent.lock().iter().cloned().collect(),
+            quota: None,
+            active_schedule: None,
+        }
+    }
+
+    /// 重置统计计数器
+    pub fn reset(&self) {
+        for counter in &self.counters {
+            counter.store(0, Ordering::Relaxed);
+        }
+        self.direct.reset();
+        self.proxied.reset();
+        self.recent.lock().clear();
+    }
+}
+
+impl Default for RoutingStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 隧道往返时延（毫秒）
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RttSnapshot {
+    /// 最近一次心跳的往返时延
+    pub current: f64,
+    /// 平均往返时延
+    pub avg: f64,
+    /// 最大往返时延
+    pub max: f64,
+}
+
+/// 隧道统计快照（`/tunnel` 端点）
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TunnelStatsSnapshot {
+    /// 当前会话的服务器是否确认心跳（不支持时无法测量往返时延）
+    pub heartbeat_ack: bool,
+    /// 心跳往返时延（还没有收到过确认时为空）
+    pub rtt_ms: Option<RttSnapshot>,
+    /// 累计发送的心跳数
+    pub heartbeats_sent: u64,
+    /// 累计收到确认的心跳数
+    pub heartbeats_acked: u64,
+    /// 当前连续未被确认的心跳数
+    pub missed_heartbeats: u32,
+    /// 当前连接的服务器地址（`server_addr` 解析后的地址，未连接时为空）
+    #[serde(default)]
+    pub server_addr: Option<SocketAddr>,
+    /// 转发缓冲区的内存用量（预算为 `relay_memory_budget_mb`）
+    #[serde(default)]
+    pub relay_memory: RelayMemoryStats,
+}
+
+#[derive(Default)]
+struct TunnelStatsInner {
+    heartbeat_ack: bool,
+    current_rtt: Option<Duration>,
+    rtt_sum: Duration,
+    max_rtt: Duration,
+    heartbeats_sent: u64,
+    heartbeats_acked: u64,
+    missed_heartbeats: u32,
+    server_addr: Option<SocketAddr>,
+}
+
+/// 隧道连接统计（跨重连累计，往返时延的当前值随会话重置）
+#[derive(Default)]
+pub struct TunnelStats {
+    inner: parking_lot::Mutex<TunnelStatsInner>,
+}
+
+impl TunnelStats {
+    /// 新会话开始
+    pub fn session_started(&self, heartbeat_ack: bool) {
+        let mut inner = self.inner.lock();
+        inner.heartbeat_ack = heartbeat_ack;
+        inner.current_rtt = None;
+        inner.missed_heartbeats = 0;
+    }
+
+    /// 记录当前连接的服务器地址（断开时为 None）
+    pub fn set_server_addr(&self, server_addr: Option<SocketAddr>) {
+        self.inner.lock().server_addr = server_addr;
+    }
+
+    /// 记录一次发送的心跳
+    pub fn record_heartbeat_sent(&self) {
+        self.inner.lock().heartbeats_sent += 1;
+    }
+
+    /// 记录一次心跳确认及其往返时延
+    pub fn record_heartbeat_ack(&self, rtt: Duration) {
+        let mut inner = self.inner.lock();
+        inner.current_rtt = Some(rtt);
+        inner.rtt_sum += rtt;
+        inner.max_rtt = inner.max_rtt.max(rtt);
+        inner.heartbeats_acked += 1;
+        inner.missed_heartbeats = 0;
+    }
+
+    /// 记录当前连续未被确认的心跳数
+    pub fn record_missed_heartbeats(&self, missed: u32) {
+        self.inner.lock().missed_heartbeats = missed;
+    }
+
+    /// 获取统计快照
+    pub fn snapshot(&self) -> TunnelStatsSnapshot {
+        let inner = self.inner.lock();
+        let millis = |d: Duration| d.as_secs_f64() * 1000.0;
+        TunnelStatsSnapshot {
+            heartbeat_ack: inner.heartbeat_ack,
+            rtt_ms: inner.current_rtt.map(|current| RttSnapshot {
+                current: millis(current),
+                avg: millis(inner.rtt_sum) / inner.heartbeats_acked as f64,
+                max: millis(inner.max_rtt),
+            }),
+            heartbeats_sent: inner.heartbeats_sent,
+            heartbeats_acked: inner.heartbeats_acked,
+            missed_heartbeats: inner.missed_heartbeats,
+            server_addr: inner.server_addr,
+            relay_memory: RelayMemoryStats::default(),
+        }
+    }
+}
+
+/// 建立隧道 stream 失败的类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFailure {
+    /// 无法打开 yamux stream 或发送前导（隧道已断开等）
+    StreamOpen,
+    /// 服务器拒绝了请求（目标 proxy 不存在、禁止转发等）
+    ServerRejected,
+    /// 连接目标地址失败（forwarder 直连）
+    TargetConnect,
+    /// 等待服务器确认或连接目标超时
+    Timeout,
+}
+
+impl StreamFailure {
+    /// 按错误链分类：包含超时的 IO 错误时归为 [`StreamFailure::Timeout`]，否则为 `default`
+    pub fn classify(error: &anyhow::Error, default: StreamFailure) -> StreamFailure {
+        let timed_out = error.chain().any(|cause| {
+            cause.is::<tokio::time::error::Elapsed>()
+                || cause
+                    .downcast_ref::<std::io::Error>()
+                    .is_some_and(|e| e.kind() == std::io::ErrorKind::TimedOut)
+        });
+        if timed_out {
+            StreamFailure::Timeout
+        } else {
+            default
+        }
+    }
+}
+
+/// 单个失败类别的计数
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FailureCounter {
+    /// 失败次数
+    pub count: u64,
+    /// 最近一次失败的错误消息
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// 最近一次失败的时间（Unix 时间戳）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_at: Option<u64>,
+}
+
+impl FailureCounter {
+    fn record(&mut self, error: String, now: u64) {
+        self.count += 1;
+        self.last_error = Some(error);
+        self.last_at = Some(now);
+    }
+}
+
+/// 建立隧道 stream 失败的分类统计快照
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StreamFailureStats {
+    /// 无法打开 yamux stream
+    pub stream_open_failed: FailureCounter,
+    /// 服务器拒绝
+    pub server_rejected: FailureCounter,
+    /// 连接目标失败
+    pub target_connect_failed: FailureCounter,
+    /// 超时
+    pub timeouts: FailureCounter,
+    /// 最近 5 分钟内的失败次数
+    pub recent: u64,
+}
+
+impl StreamFailureStats {
+    /// 是否从未失败
+    pub fn is_empty(&self) -> bool {
+        self.total() == 0
+    }
+
+    /// 所有类别的失败总数
+    pub fn total(&self) -> u64 {
+        self.stream_open_failed.count
+            + self.server_rejected.count
+            + self.target_connect_failed.count
+            + self.timeouts.count
+    }
+
+    /// 最近一次失败（类别名称和错误消息）
+    pub fn last_failure(&self) -> Option<(&'static str, &str)> {
+        [
+            ("stream_open_failed", &self.stream_open_failed),
+            ("server_rejected", &self.server_rejected),
+            ("target_connect_failed", &self.target_connect_failed),
+            ("timeouts", &self.timeouts),
+        ]
+        .into_iter()
+        .filter_map(|(name, counter)| {
+            Some((name, counter.last_at?, counter.last_error.as_deref()?))
+        })
+        .max_by_key(|(_, at, _)| *at)
+        .map(|(name, _, error)| (name, error))
+    }
+}
+
+/// 失败统计的内部状态
+#[derive(Default)]
+struct StreamFailures {
+    stats: StreamFailureStats,
+    /// 最近失败的时间戳（最新的在最后）
+    recent: VecDeque<u64>,
 }
 
 /// 客户端统计跟踪器（线程安全）
@@ -50,13 +526,35 @@ pub struct ClientStatsTracker {
     bind_addr: String,
     bind_port: u16,
     target_addr: String,
-    target_port: u16,
-    active_connections: Arc<AtomicUsize>,
+    /// 代理由服务器分配发布端口时在配置确认后更新
+    target_port: Arc<AtomicU16>,
+    active_connections: Arc<AtomicU64>,
     total_connections: Arc<AtomicU64>,
-    bytes_sent: Arc<AtomicU64>,
-    bytes_received: Arc<AtomicU64>,
+    traffic: TrafficScope,
     start_time: u64,
     status: Arc<parking_lot::RwLock<String>>,
+    /// 每次更新状态时递增（用于统计响应的 ETag）
+    status_version: Arc<AtomicU64>,
+    routing: Option<Arc<RoutingStats>>,
+    router: Option<Arc<GeoIpRouter>>,
+    tunnel_streams: Option<Arc<AtomicU64>>,
+    upgraded_connections: Option<Arc<AtomicU64>>,
+    accept_errors: Arc<AtomicU64>,
+    integrity: IntegrityCounters,
+    failures: Arc<parking_lot::Mutex<StreamFailures>>,
+    fast_fail: Option<FailedTargetManager>,
+    targets: Option<Arc<parking_lot::Mutex<BTreeMap<String, TargetCounters>>>>,
+    forward_reports: Option<ForwardReports>,
+    relay_memory: Option<RelayMemory>,
+    recent_connections: Option<Arc<parking_lot::Mutex<VecDeque<RecentConnection>>>>,
+    pool: Option<Arc<ConnectionPool>>,
+}
+
+/// 单个目标的计数（字节数在快照时从流量作用域读取）
+#[derive(Default)]
+struct TargetCounters {
+    stats: TargetStats,
+    traffic: TrafficScope,
 }
 
 impl ClientStatsTracker {
@@ -75,19 +573,207 @@ impl ClientStatsTracker {
             bind_addr,
             bind_port,
             target_addr,
-            target_port,
-            active_connections: Arc::new(AtomicUsize::new(0)),
+            target_port: Arc::new(AtomicU16::new(target_port)),
+            active_connections: Arc::new(AtomicU64::new(0)),
             total_connections: Arc::new(AtomicU64::new(0)),
-            bytes_sent: Arc::new(AtomicU64::new(0)),
-            bytes_received: Arc::new(AtomicU64::new(0)),
+            traffic: TrafficScope::new(),
             start_time: SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .unwrap_or_default()
                 .as_secs(),
             status: Arc::new(parking_lot::RwLock::new("Idle".to_string())),
+            status_version: Arc::new(AtomicU64::new(0)),
+            routing: None,
+            router: None,
+            tunnel_streams: None,
+            upgraded_connections: None,
+            accept_errors: Arc::new(AtomicU64::new(0)),
+            integrity: IntegrityCounters::default(),
+            failures: Arc::new(parking_lot::Mutex::new(StreamFailures::default())),
+            fast_fail: None,
+            targets: None,
+            forward_reports: None,
+            relay_memory: None,
+            recent_connections: None,
+            pool: None,
         }
     }
 
+    /// 启用路由决策统计（用于配置了路由规则的 forwarder）
+    pub fn with_routing_stats(mut self) -> Self {
+        self.routing = Some(Arc::new(RoutingStats::new()));
+        self
+    }
+
+    /// 关联 forwarder 的路由器：代理字节计入其月配额，快照包含配额和时间段状态
+    pub fn with_router(mut self, router: Arc<GeoIpRouter>) -> Self {
+        self.router = Some(router);
+        self
+    }
+
+    /// 关联 forwarder 的快速失败管理器：快照包含黑名单状态
+    pub fn with_fast_fail(mut self, manager: FailedTargetManager) -> Self {
+        self.fast_fail = Some(manager);
+        self
+    }
+
+    /// 获取关联的快速失败管理器
+    pub fn fast_fail(&self) -> Option<&FailedTargetManager> {
+        self.fast_fail.as_ref()
+    }
+
+    /// 上报 forwarder 的直连连接（用于开启了 report_direct 的 forwarder）
+    pub fn with_forward_reports(mut self, reports: ForwardReports) -> Self {
+        self.forward_reports = Some(reports);
+        self
+    }
+
+    /// 获取直连报告的收集器（未开启上报时为 None）
+    pub fn forward_reports(&self) -> Option<&ForwardReports> {
+        self.forward_reports.as_ref()
+    }
+
+    /// 转发缓冲区从共享的内存预算领取（用于 forwarder 和 visitor 网关）
+    pub fn with_relay_memory(mut self, memory: RelayMemory) -> Self {
+        self.relay_memory = Some(memory);
+        self
+    }
+
+    /// 转发缓冲区的内存预算（未关联时为 None）
+    pub fn relay_memory(&self) -> Option<&RelayMemory> {
+        self.relay_memory.as_ref()
+    }
+
+    /// 启用隧道 stream 计数（用于启用 connection_reuse 的 visitor）
+    pub fn with_tunnel_stream_counter(mut self) -> Self {
+        self.tunnel_streams = Some(Arc::new(AtomicU64::new(0)));
+        self
+    }
+
+    /// 启用 WebSocket 升级计数（用于 http/1.1 代理）
+    pub fn with_upgrade_counter(mut self) -> Self {
+        self.upgraded_connections = Some(Arc::new(AtomicU64::new(0)));
+        self
+    }
+
+    /// 启用按目标的分别统计（用于 visitor 网关和配置了 local_targets 的代理）
+    pub fn with_targets(mut self) -> Self {
+        self.targets = Some(Arc::new(parking_lot::Mutex::new(BTreeMap::new())));
+        self
+    }
+
+    /// 启用最近连接列表（用于开启了 report_peers 的代理）
+    pub fn with_recent_connections(mut self) -> Self {
+        self.recent_connections = Some(Arc::new(parking_lot::Mutex::new(VecDeque::with_capacity(
+            RECENT_CONNECTIONS_CAPACITY,
+        ))));
+        self
+    }
+
+    /// 关联代理的本地连接池：快照包含其复用指标（用于复用本地连接的代理）
+    pub fn with_pool(mut self, pool: Arc<ConnectionPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// 开始记录一条外部连接：连接的计数器挂到返回值的流量作用域上，返回值丢弃时写入最近连接列表
+    /// （未启用最近连接列表时返回 None）
+    pub fn peer_connection(&self, peer: &PeerInfo) -> Option<PeerConnection> {
+        Some(PeerConnection {
+            recent: self.recent_connections.clone()?,
+            peer: peer.clone(),
+            traffic: TrafficScope::new(),
+            started: Instant::now(),
+        })
+    }
+
+    /// 更新目标的统计（未启用分别统计或已达数量上限的新目标时忽略）
+    fn update_target<T>(
+        &self,
+        target: &str,
+        update: impl FnOnce(&mut TargetCounters) -> T,
+    ) -> Option<T> {
+        let mut targets = self.targets.as_ref()?.lock();
+        if let Some(counters) = targets.get_mut(target) {
+            Some(update(counters))
+        } else if targets.len() < TARGETS_CAPACITY {
+            Some(update(targets.entry(target.to_string()).or_default()))
+        } else {
+            None
+        }
+    }
+
+    /// 目标的连接开始
+    pub fn target_connection_started(&self, target: &str) {
+        self.update_target(target, |counters| {
+            counters.stats.total_connections += 1;
+            counters.stats.active_connections += 1;
+        });
+    }
+
+    /// 目标的连接结束
+    pub fn target_connection_ended(&self, target: &str) {
+        self.update_target(target, |counters| {
+            counters.stats.active_connections = counters.stats.active_connections.saturating_sub(1);
+        });
+    }
+
+    /// 创建转发到 `target` 的连接的计数器，同时计入代理和该目标（启用了分别统计时）
+    ///
+    /// `reversed` 表示代理的发送方向是从目标收到的数据（内网代理的本地服务）
+    pub fn target_meter(&self, target: &str, reversed: bool) -> TrafficMeter {
+        let meter = self.meter();
+        match self.update_target(target, |counters| counters.traffic.clone()) {
+            Some(scope) if reversed => meter.attach_reversed(&scope),
+            Some(scope) => meter.attach(&scope),
+            None => meter,
+        }
+    }
+
+    /// 记录一次连接目标失败
+    pub fn record_target_failure(&self, target: &str) {
+        self.update_target(target, |counters| counters.stats.failures += 1);
+    }
+
+    /// 记录打开了一条隧道 stream（未启用计数时忽略）
+    pub fn record_tunnel_stream(&self) {
+        if let Some(counter) = &self.tunnel_streams {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 记录一条本地连接升级为 WebSocket（未启用计数时忽略）
+    pub fn record_upgraded_connection(&self) {
+        if let Some(counter) = &self.upgraded_connections {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 获取路由决策统计
+    pub fn routing_stats(&self) -> Option<&Arc<RoutingStats>> {
+        self.routing.as_ref()
+    }
+
+    /// 记录路由决策（未启用路由统计时忽略）
+    pub fn record_route_decision(&self, target: &str, decision: &RouteDecision) {
+        if let Some(routing) = &self.routing {
+            routing.record_decision(target, decision);
+        }
+    }
+
+    /// 创建 forwarder 连接的计数器，同时计入直连/代理路径（启用了路由统计时）
+    /// 和月配额（代理路径）
+    pub fn route_meter(&self, direct: bool) -> TrafficMeter {
+        let mut meter = self.meter();
+        if let Some(routing) = &self.routing {
+            meter = meter.attach(routing.path(direct));
+        }
+        if let (false, Some(scope)) = (direct, self.router.as_ref().and_then(|r| r.quota_scope())) {
+            meter = meter.attach(scope);
+        }
+        meter
+    }
+
     /// 连接开始
     pub fn connection_started(&self) {
         self.active_connections.fetch_add(1, Ordering::Relaxed);
@@ -104,37 +790,148 @@ impl ClientStatsTracker {
         }
     }
 
-    /// 记录发送字节数
-    pub fn record_bytes_sent(&self, bytes: u64) {
-        self.bytes_sent.fetch_add(bytes, Ordering::Relaxed);
-    }
-
-    /// 记录接收字节数
-    pub fn record_bytes_received(&self, bytes: u64) {
-        self.bytes_received.fetch_add(bytes, Ordering::Relaxed);
+    /// 创建一个连接的计数器（转发循环的每个字节只通过它记录一次）
+    pub fn meter(&self) -> TrafficMeter {
+        self.traffic.meter()
     }
 
     /// 更新状态
     pub fn update_status(&self, status: impl Into<String>) {
         let mut s = self.status.write();
         *s = status.into();
+        self.status_version.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 更新服务器分配的发布端口
+    pub fn set_target_port(&self, port: u16) {
+        self.target_port.store(port, Ordering::Relaxed);
+        self.status_version.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 记录一次因资源耗尽导致的接受连接失败
+    pub fn record_accept_error(&self) {
+        self.accept_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 完整性校验的计数（开启了 integrity_check 的代理）
+    pub fn integrity(&self) -> &IntegrityCounters {
+        &self.integrity
+    }
+
+    /// 记录一次建立隧道 stream 失败
+    pub fn record_failure(&self, kind: StreamFailure, error: impl std::fmt::Display) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut failures = self.failures.lock();
+        let counter = match kind {
+            StreamFailure::StreamOpen => &mut failures.stats.stream_open_failed,
+            StreamFailure::ServerRejected => &mut failures.stats.server_rejected,
+            StreamFailure::TargetConnect => &mut failures.stats.target_connect_failed,
+            StreamFailure::Timeout => &mut failures.stats.timeouts,
+        };
+        counter.record(format!("{:#}", error), now);
+        if failures.recent.len() >= RECENT_FAILURES_CAPACITY {
+            failures.recent.pop_front();
+        }
+        failures.recent.push_back(now);
+    }
+
+    /// 获取失败统计快照（`recent` 为最近 5 分钟内的失败次数）
+    fn failure_stats(&self) -> StreamFailureStats {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let failures = self.failures.lock();
+        let mut stats = failures.stats.clone();
+        stats.recent = failures
+            .recent
+            .iter()
+            .filter(|at| now.saturating_sub(**at) < RECENT_FAILURE_WINDOW_SECS)
+            .count() as u64;
+        stats
+    }
+
+    /// 将计数器累加到指纹中（不生成快照）
+    pub fn fingerprint_into(&self, fingerprint: &mut StatsFingerprint) {
+        fingerprint.add(
+            self.total_connections.load(Ordering::Relaxed),
+            self.active_connections.load(Ordering::Relaxed),
+            self.status_version.load(Ordering::Relaxed)
+                + self.accept_errors.load(Ordering::Relaxed)
+                + self.integrity.verified()
+                + self.integrity.mismatches()
+                + self.pool.as_ref().map_or(0, |pool| {
+                    let metrics = pool.metrics();
+                    metrics.hits + metrics.misses + metrics.discarded + metrics.idle
+                }),
+            self.traffic.totals().total(),
+        );
     }
 
     /// 获取统计快照
     pub fn snapshot(&self) -> ClientProxyStats {
+        let traffic = self.traffic.totals();
         ClientProxyStats {
-            name: self.name.clone(),
+            core: ProxyStatsCore {
+                name: self.name.clone(),
+                role: StatsRole::Client,
+                listen_addr: self.bind_addr.clone(),
+                listen_port: self.bind_port,
+                target_addr: self.target_addr.clone(),
+                target_port: self.target_port.load(Ordering::Relaxed),
+                total_connections: self.total_connections.load(Ordering::Relaxed),
+                active_connections: self.active_connections.load(Ordering::Relaxed),
+                bytes_sent: traffic.sent,
+                bytes_received: traffic.received,
+                start_time: self.start_time,
+                status: self.status.read().clone(),
+                accept_errors: self.accept_errors.load(Ordering::Relaxed),
+                integrity_verified: self.integrity.verified(),
+                integrity_mismatches: self.integrity.mismatches(),
+                connection_limited: 0,
+            },
             proxy_type: format!("{:?}", self.proxy_type),
-            bind_addr: self.bind_addr.clone(),
-            bind_port: self.bind_port,
-            target_addr: self.target_addr.clone(),
-            target_port: self.target_port,
-            active_connections: self.active_connections.load(Ordering::Relaxed),
-            total_connections: self.total_connections.load(Ordering::Relaxed),
-            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
-            bytes_received: self.bytes_received.load(Ordering::Relaxed),
-            start_time: self.start_time,
-            status: self.status.read().clone(),
+            routing: self.routing.as_ref().map(|r| {
+                let mut snapshot = r.snapshot();
+                if let Some(router) = &self.router {
+                    snapshot.quota = router.quota_status();
+                    snapshot.active_schedule = router.active_schedule();
+                }
+                snapshot
+            }),
+            tunnel_streams: self
+                .tunnel_streams
+                .as_ref()
+                .map(|c| c.load(Ordering::Relaxed)),
+            upgraded_connections: self
+                .upgraded_connections
+                .as_ref()
+                .map(|c| c.load(Ordering::Relaxed)),
+            failures: self.failure_stats(),
+            fast_fail: self.fast_fail.as_ref().map(|m| m.snapshot()),
+            targets: self.targets.as_ref().map(|targets| {
+                targets
+                    .lock()
+                    .iter()
+                    .map(|(target, counters)| {
+                        let traffic = counters.traffic.totals();
+                        let stats = TargetStats {
+                            bytes_sent: traffic.sent,
+                            bytes_received: traffic.received,
+                            ..counters.stats.clone()
+                        };
+                        (target.clone(), stats)
+                    })
+                    .collect()
+            }),
+            recent_connections: self
+                .recent_connections
+                .as_ref()
+                .map(|recent| recent.lock().iter().cloned().collect()),
+            pool: self.pool.as_ref().map(|pool| pool.metrics()),
         }
     }
 
@@ -142,63 +939,233 @@ impl ClientStatsTracker {
     pub fn reset(&self) {
         self.active_connections.store(0, Ordering::Relaxed);
         self.total_connections.store(0, Ordering::Relaxed);
-        self.bytes_sent.store(0, Ordering::Relaxed);
-        self.bytes_received.store(0, Ordering::Relaxed);
+        self.traffic.reset();
+        if let Some(routing) = &self.routing {
+            routing.reset();
+        }
+        if let Some(counter) = &self.tunnel_streams {
+            counter.store(0, Ordering::Relaxed);
+        }
+        if let Some(counter) = &self.upgraded_connections {
+            counter.store(0, Ordering::Relaxed);
+        }
+        self.accept_errors.store(0, Ordering::Relaxed);
+        self.integrity.reset();
+        *self.failures.lock() = StreamFailures::default();
+        if let Some(targets) = &self.targets {
+            targets.lock().clear();
+        }
+        if let Some(recent) = &self.recent_connections {
+            recent.lock().clear();
+        }
         self.update_status("Reset");
     }
 }
 
+/// 正在转发的外部连接，丢弃时把来源、时长和字节数写入最近连接列表
+pub struct PeerConnection {
+    recent: Arc<parking_lot::Mutex<VecDeque<RecentConnection>>>,
+    peer: PeerInfo,
+    traffic: TrafficScope,
+    started: Instant,
+}
+
+impl PeerConnection {
+    /// 该连接的流量作用域（转发前把连接的计数器挂上来）
+    pub fn traffic(&self) -> &TrafficScope {
+        &self.traffic
+    }
+}
+
+impl Drop for PeerConnection {
+    fn drop(&mut self) {
+        let traffic = self.traffic.totals();
+        let record = RecentConnection {
+            peer_addr: std::mem::take(&mut self.peer.addr),
+            accepted_at_ms: self.peer.accepted_at_ms,
+            duration_ms: self.started.elapsed().as_millis() as u64,
+            bytes_sent: traffic.sent,
+            bytes_received: traffic.received,
+        };
+        let mut recent = self.recent.lock();
+        if recent.len() >= RECENT_CONNECTIONS_CAPACITY {
+            recent.pop_front();
+        }
+        recent.push_back(record);
+    }
+}
+
 /// 全局客户端统计管理器
+///
+/// 跟踪器列表以写时复制的方式保存：读取（统计端点、HTML 页面、WebSocket 推送）只取得当前列表的
+/// 引用，生成快照和格式化都不持有锁；添加或替换跟踪器时复制列表（只复制 `Arc`）后原子替换，
+/// 不会等待正在生成页面的读取方
 #[derive(Clone)]
 pub struct ClientStatsManager {
-    trackers: Arc<parking_lot::RwLock<Vec<ClientStatsTracker>>>,
+    trackers: Arc<ArcSwap<Vec<Arc<ClientStatsTracker>>>>,
+    /// 隧道连接统计
+    tunnel: Arc<TunnelStats>,
+    /// 每次添加或替换跟踪器时递增
+    generation: Arc<AtomicU64>,
+    /// 等待发给服务器的直连报告
+    forward_reports: ForwardReports,
+    /// 所有转发连接的拷贝缓冲区
+    relay_memory: RelayMemory,
+    /// 统计服务器（端口、Unix 套接字、命名管道）的运行状态
+    stats_servers: StatsServers,
+    /// 健康评分（`/health/score`）
+    health: HealthMonitor,
 }
 
 impl ClientStatsManager {
     /// 创建新的统计管理器
     pub fn new() -> Self {
         Self {
-            trackers: Arc::new(parking_lot::RwLock::new(Vec::new())),
+            trackers: Arc::new(ArcSwap::from_pointee(Vec::new())),
+            tunnel: Arc::new(TunnelStats::default()),
+            generation: Arc::new(AtomicU64::new(0)),
+            forward_reports: ForwardReports::default(),
+            relay_memory: RelayMemory::default(),
+            stats_servers: StatsServers::default(),
+            health: HealthMonitor::default(),
         }
     }
 
     /// 添加统计跟踪器
     #[allow(dead_code)]
     pub fn add_tracker(&self, tracker: ClientStatsTracker) {
-        let mut trackers = self.trackers.write();
-        trackers.push(tracker);
+        let tracker = Arc::new(tracker);
+        self.trackers.rcu(|trackers| {
+            let mut trackers = Vec::clone(trackers);
+            trackers.push(tracker.clone());
+            trackers
+        });
+        self.generation.fetch_add(1, Ordering::Relaxed);
     }
 
     /// 添加或更新统计跟踪器（如果已存在相同名称的跟踪器则替换）
     pub fn add_or_update_tracker(&self, tracker: ClientStatsTracker) {
-        let mut trackers = self.trackers.write();
-        // 查找是否已存在相同名称的跟踪器
-        if let Some(pos) = trackers.iter().position(|t| t.name == tracker.name) {
-            // 替换现有的跟踪器
-            trackers[pos] = tracker;
-        } else {
-            // 添加新的跟踪器
-            trackers.push(tracker);
+        let tracker = Arc::new(tracker);
+        self.trackers.rcu(|trackers| {
+            let mut trackers = Vec::clone(trackers);
+            // 查找是否已存在相同名称的跟踪器
+            if let Some(pos) = trackers.iter().position(|t| t.name == tracker.name) {
+                // 替换现有的跟踪器
+                trackers[pos] = tracker.clone();
+            } else {
+                // 添加新的跟踪器
+                trackers.push(tracker.clone());
+            }
+            trackers
+        });
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 移除统计跟踪器（例如配置热重载删除了对应的条目），返回是否存在
+    pub fn remove_tracker(&self, name: &str) -> bool {
+        let previous = self.trackers.rcu(|trackers| {
+            let mut trackers = Vec::clone(trackers);
+            trackers.retain(|t| t.name != name);
+            trackers
+        });
+        let removed = previous.iter().any(|t| t.name == name);
+        if removed {
+            self.generation.fetch_add(1, Ordering::Relaxed);
         }
+        removed
+    }
+
+    /// 当前的跟踪器列表（之后的添加和替换不影响返回的列表）
+    fn trackers(&self) -> Arc<Vec<Arc<ClientStatsTracker>>> {
+        self.trackers.load_full()
     }
 
     /// 获取所有统计信息
     pub fn get_all_stats(&self) -> Vec<ClientProxyStats> {
-        let trackers = self.trackers.read();
-        trackers.iter().map(|t| t.snapshot()).collect()
+        self.trackers().iter().map(|t| t.snapshot()).collect()
+    }
+
+    /// 获取一页统计信息（按添加顺序），先按名称过滤和分页再生成快照
+    ///
+    /// 返回本页统计和满足过滤条件的总数
+    pub fn get_stats_page(&self, query: &StatsQuery) -> (Vec<ClientProxyStats>, usize) {
+        let trackers = self.trackers();
+        let total = trackers.iter().filter(|t| query.matches(&t.name)).count();
+        let page = query
+            .paginate(trackers.iter().filter(|t| query.matches(&t.name)))
+            .map(|t| t.snapshot())
+            .collect();
+        (page, total)
+    }
+
+    /// 全部统计的指纹（统计明显变化时随之变化）
+    pub fn fingerprint(&self) -> StatsFingerprint {
+        let mut fingerprint = StatsFingerprint::new(self.generation.load(Ordering::Relaxed));
+        for tracker in self.trackers().iter() {
+            tracker.fingerprint_into(&mut fingerprint);
+        }
+        fingerprint
+    }
+
+    /// 隧道连接统计
+    pub fn tunnel(&self) -> &TunnelStats {
+        &self.tunnel
+    }
+
+    /// 等待发给服务器的直连报告
+    pub fn forward_reports(&self) -> &ForwardReports {
+        &self.forward_reports
+    }
+
+    /// 转发缓冲区的内存记账（预算为 `relay_memory_budget_mb`）
+    pub fn relay_memory(&self) -> &RelayMemory {
+        &self.relay_memory
+    }
+
+    /// 统计服务器的运行状态（`/healthz`）
+    pub fn stats_servers(&self) -> &StatsServers {
+        &self.stats_servers
+    }
+
+    /// `/tunnel` 端点的内容：隧道统计加上转发缓冲区的内存用量
+    pub fn tunnel_snapshot(&self) -> TunnelStatsSnapshot {
+        TunnelStatsSnapshot {
+            relay_memory: self.relay_memory.snapshot(),
+            ..self.tunnel.snapshot()
+        }
+    }
+
+    /// `/pool` 端点的内容：按代理名称列出本地连接池的复用指标（不复用本地连接的代理不列出）
+    pub fn pool_snapshot(&self) -> BTreeMap<String, PoolMetrics> {
+        self.trackers()
+            .iter()
+            .filter_map(|t| Some((t.name.clone(), t.pool.as_ref()?.metrics())))
+            .collect()
+    }
+
+    /// 健康评分监视器
+    pub fn health(&self) -> &HealthMonitor {
+        &self.health
+    }
+
+    /// 由当前统计计算健康评分（`/health/score` 端点）
+    pub fn health_report(&self) -> HealthReport {
+        self.health
+            .evaluate(&self.tunnel_snapshot(), &self.get_all_stats())
     }
 
     /// 根据名称获取统计跟踪器
     pub fn get_tracker(&self, name: &str) -> Option<ClientStatsTracker> {
-        let trackers = self.trackers.read();
-        trackers.iter().find(|t| t.name == name).cloned()
+        self.trackers()
+            .iter()
+            .find(|t| t.name == name)
+            .map(|t| ClientStatsTracker::clone(t))
     }
 
     /// 重置所有统计信息
     #[allow(dead_code)]
     pub fn reset_all(&self) {
-        let trackers = self.trackers.read();
-        for tracker in trackers.iter() {
+        for tracker in self.trackers().iter() {
             tracker.reset();
         }
     }
@@ -212,91 +1179,201 @@ impl Default for ClientStatsManager {
 
 /// 启动客户端统计 HTTP 服务器
 ///
-/// 提供 /stats 端点返回所有客户端代理的统计信息
-pub async fn start_client_stats_server(
+/// 提供 /stats 端点返回所有客户端代理的统计信息，/tunnel 端点返回隧道心跳和往返时延，
+/// /config 端点返回配置版本信息，/healthz 返回各统计服务器的运行状态，/health/score 返回健康评分，
+/// /stats/ws 以 WebSocket 推送统计变化，
+/// 配置了 routing_ui_token 时额外提供 /routing 路由规则编辑页面。
+/// 端口被占用或监听套接字失效时退避后重新绑定（见 [`stats_http::supervise`]）
+pub(crate) async fn start_client_stats_server(
     bind_addr: String,
     port: u16,
     manager: ClientStatsManager,
+    limits: StatsLimitConfig,
+    routing_ui: Option<RoutingUi>,
+    running_config: RunningConfig,
 ) -> Result<()> {
-    let listener = TcpListener::bind(format!("{}:{}", bind_addr, port))
-        .await
-        .context("Failed to bind client stats server port")?;
-
-    info!(
-        "Client stats server listening on http://{}:{}",
-        bind_addr, port
-    );
-
-    loop {
-        match listener.accept().await {
-            Ok((mut stream, addr)) => {
-                let manager = manager.clone();
+    let address = format!("{}:{}", bind_addr, port);
+    let health = manager
+        .stats_servers()
+        .register(format!("Client stats server {}", address));
+    let bind = move || {
+        let address = address.clone();
+        async move {
+            let listener = TcpListener::bind(&address)
+                .await
+                .context("Failed to bind client stats server port")?;
+            info!("Client stats server listening on http://{}", address);
+            Ok(listener)
+        }
+    };
 
-                tokio::spawn(async move {
-                    handle_client_stats_request(&mut stream, addr, &manager).await;
-                });
-            }
-            Err(e) => {
-                error!("Failed to accept client stats connection: {}", e);
-            }
+    serve_client_stats(health, bind, manager, limits, routing_ui, running_config).await
+}
+
+/// 在 Unix 套接字上启动客户端统计服务器（响应与 TCP 端口相同）
+///
+/// 清理上次运行残留的套接字文件，套接字权限设为仅当前用户可读写
+#[cfg(unix)]
+pub(crate) async fn start_client_stats_socket(
+    path: std::path::PathBuf,
+    manager: ClientStatsManager,
+    limits: StatsLimitConfig,
+    routing_ui: Option<RoutingUi>,
+    running_config: RunningConfig,
+) -> Result<()> {
+    let health = manager
+        .stats_servers()
+        .register(format!("Client stats socket unix:{}", path.display()));
+    let bind = move || std::future::ready(bind_stats_socket(&path));
+
+    serve_client_stats(health, bind, manager, limits, routing_ui, running_config).await
+}
+
+/// 绑定 Unix 套接字（重新绑定时同样先清理失效的套接字文件）
+#[cfg(unix)]
+fn bind_stats_socket(path: &std::path::Path) -> Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            anyhow::bail!("{} exists and is not a socket", path.display());
+        }
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            anyhow::bail!("{} is already in use by another process", path.display());
         }
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
     }
+
+    let listener = tokio::net::UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind client stats socket {}", path.display()))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+        .with_context(|| format!("Failed to set permissions on {}", path.display()))?;
+
+    info!("Client stats server listening on unix:{}", path.display());
+    Ok(listener)
 }
 
-/// 处理单个统计请求
-async fn handle_client_stats_request(
-    stream: &mut tokio::net::TcpStream,
-    _addr: SocketAddr,
-    manager: &ClientStatsManager,
-) {
-    let mut buffer = vec![0u8; 4096];
-    let n = match stream.read(&mut buffer).await {
-        Ok(n) => n,
-        Err(e) => {
-            error!("Failed to read from client stats client {}: {}", _addr, e);
-            return;
+/// 在 Windows 命名管道上启动客户端统计服务器（响应与 TCP 端口相同）
+#[cfg(windows)]
+pub(crate) async fn start_client_stats_pipe(
+    name: String,
+    manager: ClientStatsManager,
+    limits: StatsLimitConfig,
+    routing_ui: Option<RoutingUi>,
+    running_config: RunningConfig,
+) -> Result<()> {
+    let health = manager
+        .stats_servers()
+        .register(format!("Client stats pipe pipe:{}", name));
+    let bind = move || {
+        let listener = stats_http::NamedPipeListener::bind(name.clone())
+            .with_context(|| format!("Failed to create client stats pipe {}", name));
+        if listener.is_ok() {
+            info!("Client stats server listening on pipe:{}", name);
         }
+        std::future::ready(listener)
     };
 
-    // 解析HTTP请求
-    let request = String::from_utf8_lossy(&buffer[..n]);
-    let path = request
-        .lines()
-        .next()
-        .and_then(|line| line.split_whitespace().nth(1))
-        .unwrap_or("/");
+    serve_client_stats(health, bind, manager, limits, routing_ui, running_config).await
+}
 
-    let response = if path == "/stats" || path == "/stats/" {
-        // 返回JSON格式的统计信息
-        let stats = manager.get_all_stats();
-        let json = serde_json::to_string_pretty(&stats).unwrap_or_default();
+async fn serve_client_stats<L, B, Fut>(
+    health: StatsServerHealth,
+    bind: B,
+    manager: ClientStatsManager,
+    limits: StatsLimitConfig,
+    routing_ui: Option<RoutingUi>,
+    running_config: RunningConfig,
+) -> Result<()>
+where
+    L: StatsListener,
+    B: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<L>>,
+{
+    stats_http::supervise(health, limits, bind, move |request| {
+        handle_client_stats_request(request, &manager, routing_ui.as_ref(), &running_config)
+    })
+    .await
+}
 
-        format!(
-            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
-            json.len(),
-            json
+/// 处理单个统计请求
+fn handle_client_stats_request(
+    request: &HttpRequest,
+    manager: &ClientStatsManager,
+    routing_ui: Option<&RoutingUi>,
+    running_config: &RunningConfig,
+) -> HttpResponse {
+    let path = request.target.as_str();
+    if request.path() == "/routing" || request.path().starts_with("/routing/") {
+        match routing_ui {
+            Some(ui) => ui.handle(request),
+            None => HttpResponse::not_found(),
+        }
+    } else if request.path() == "/config" || request.path().starts_with("/config/") {
+        running_config.handle(request)
+    } else if request.path() == "/healthz" {
+        // 各统计服务器的运行状态（某个监听器失效时可从其他监听器查看）
+        manager.stats_servers().respond()
+    } else if request.path() == "/health/score" {
+        // 隧道和各代理的健康评分及扣分说明
+        HttpResponse::json(
+            serde_json::to_string_pretty(&manager.health_report()).unwrap_or_default(),
+        )
+    } else if request.path() == "/tunnel" || request.path() == "/tunnel/" {
+        // 隧道心跳、往返时延和转发缓冲区的内存用量
+        HttpResponse::json(
+            serde_json::to_string_pretty(&manager.tunnel_snapshot()).unwrap_or_default(),
         )
+    } else if request.path() == "/pool" || request.path() == "/pool/" {
+        // 各代理本地连接池的命中、新建和丢弃次数
+        HttpResponse::json(
+            serde_json::to_string_pretty(&manager.pool_snapshot()).unwrap_or_default(),
+        )
+    } else if request.path() == "/stats/ws" {
+        // 实时推送统计变化（客户端没有注册表，只推送统计）
+        let config = running_config.config();
+        stats_ws::respond(
+            request,
+            config.client.stats_stream.clone().unwrap_or_default(),
+            &config.client.stats_limits.clone().unwrap_or_default(),
+            ClientStatsFeed(manager.clone()),
+        )
+    } else if request.path() == "/stats" || request.path() == "/stats/" {
+        // 返回JSON格式的统计信息（支持 limit/offset/name_prefix/pretty 查询参数）
+        match StatsQuery::parse(request) {
+            Some(query) => query.respond(request, manager.fingerprint().etag(), |query| {
+                manager.get_stats_page(query)
+            }),
+            None => HttpResponse::error(400),
+        }
     } else if path == "/" || path.starts_with("/?") {
         // 返回HTML页面
-        let html = generate_client_stats_html(manager);
-
-        format!(
-            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\r\n{}",
-            html.len(),
-            html
-        )
+        HttpResponse::html(generate_client_stats_html(manager))
     } else {
-        // 404
-        let body = "404 Not Found";
-        format!(
-            "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
-            body.len(),
-            body
-        )
-    };
+        HttpResponse::not_found()
+    }
+}
+
+/// `/stats/ws` 推送的数据来源
+struct ClientStatsFeed(ClientStatsManager);
+
+impl StatsFeed for ClientStatsFeed {
+    type Event = ();
 
-    if let Err(e) = stream.write_all(response.as_bytes()).await {
-        error!("Failed to write response to {}: {}", _addr, e);
+    fn snapshot(&self) -> Vec<(String, serde_json::Value)> {
+        self.0
+            .get_all_stats()
+            .into_iter()
+            .map(|stat| {
+                let value = serde_json::to_value(&stat).unwrap_or_default();
+                (stat.core.name, value)
+            })
+            .collect()
+    }
+
+    fn subscribe(&self) -> Option<tokio::sync::broadcast::Receiver<()>> {
+        None
     }
 }
 
@@ -310,10 +1387,10 @@ fn generate_client_stats_html(manager: &ClientStatsManager) -> String {
 
     let mut rows = String::new();
     for stat in &stats {
-        let uptime_seconds = now.saturating_sub(stat.start_time);
+        let uptime_seconds = now.saturating_sub(stat.core.start_time);
         let uptime = format_duration(uptime_seconds);
-        let bytes_sent = format_bytes(stat.bytes_sent);
-        let bytes_received = format_bytes(stat.bytes_received);
+        let bytes_sent = format_bytes(stat.core.bytes_sent);
+        let bytes_received = format_bytes(stat.core.bytes_received);
 
         rows.push_str(&format!(
             r#"
@@ -328,20 +1405,24 @@ fn generate_client_stats_html(manager: &ClientStatsManager) -> String {
                 <td>{}</td>
                 <td>{}</td>
                 <td>{}</td>
+                <td>{}</td>
+                <td>{}</td>
             </tr>
             "#,
-            stat.name,
+            stat.core.name,
             stat.proxy_type,
-            stat.bind_addr,
-            stat.bind_port,
-            stat.target_addr,
-            stat.target_port,
-            stat.active_connections,
-            stat.total_connections,
+            stat.core.listen_addr,
+            stat.core.listen_port,
+            stat.core.target_addr,
+            stat.core.target_port,
+            stat.core.active_connections,
+            stat.core.total_connections,
             bytes_sent,
             bytes_received,
             uptime,
-            stat.status
+            failures_cell_html(&stat.failures),
+            pool_cell_html(stat.pool.as_ref()),
+            status_cell_html(&stat.core.status)
         ));
     }
 
@@ -451,6 +1532,30 @@ fn generate_client_stats_html(manager: &ClientStatsManager) -> String {
             font-size: 24px;
             font-weight: 600;
         }}
+        .health-banner {{
+            border-radius: 10px;
+            padding: 15px 20px;
+            margin-bottom: 20px;
+            font-weight: 600;
+            box-shadow: 0 2px 10px rgba(0,0,0,0.1);
+        }}
+        .health-banner ul {{
+            margin: 8px 0 0 20px;
+            font-weight: normal;
+            font-size: 13px;
+        }}
+        .health-green {{
+            background-color: #d4edda;
+            color: #155724;
+        }}
+        .health-yellow {{
+            background-color: #fff3cd;
+            color: #856404;
+        }}
+        .health-red {{
+            background-color: #f8d7da;
+            color: #721c24;
+        }}
     </style>
 </head>
 <body>
@@ -459,7 +1564,7 @@ fn generate_client_stats_html(manager: &ClientStatsManager) -> String {
             <h1>🚀 TLS Tunnel - 客户端统计面板</h1>
             <p class="subtitle">实时监控客户端代理连接状态和流量统计 (每 5 秒自动刷新)</p>
         </div>
-        
+        {}
         <div class="summary">
             <div class="summary-card">
                 <h3>总代理数</h3>
@@ -488,6 +1593,8 @@ fn generate_client_stats_html(manager: &ClientStatsManager) -> String {
                         <th>发送流量</th>
                         <th>接收流量</th>
                         <th>运行时长</th>
+                        <th>失败</th>
+                        <th>连接池命中率</th>
                         <th>状态</th>
                     </tr>
                 </thead>
@@ -496,16 +1603,167 @@ fn generate_client_stats_html(manager: &ClientStatsManager) -> String {
                 </tbody>
             </table>
         </div>
+        {}
     </div>
 </body>
 </html>"#,
+        health_banner_html(&manager.health_report()),
         stats.len(),
-        stats.iter().map(|s| s.active_connections).sum::<usize>(),
-        stats.iter().map(|s| s.total_connections).sum::<u64>(),
-        rows
+        stats.iter().map(|s| s.core.active_connections).sum::<u64>(),
+        stats.iter().map(|s| s.core.total_connections).sum::<u64>(),
+        rows,
+        generate_routing_html(&stats)
+    )
+}
+
+/// 健康状态横幅：按状态着色，列出各扣分项
+fn health_banner_html(report: &HealthReport) -> String {
+    let mut items = String::new();
+    let tunnel = std::iter::repeat("隧道").zip(&report.tunnel.contributions);
+    let proxies = report
+        .proxies
+        .iter()
+        .flat_map(|proxy| std::iter::repeat(proxy.name.as_str()).zip(&proxy.rating.contributions));
+    for (owner, contribution) in tunnel.chain(proxies) {
+        items.push_str(&format!(
+            "<li>{}: {} -{} ({})</li>",
+            stats_http::escape_html(owner),
+            contribution.component,
+            contribution.penalty,
+            stats_http::escape_html(&contribution.detail)
+        ));
+    }
+    let label = match report.status {
+        HealthStatus::Green => "健康",
+        HealthStatus::Yellow => "警告",
+        HealthStatus::Red => "异常",
+    };
+    format!(
+        r#"<div class="health-banner health-{}">健康状态: {} ({}, 分数 {}){}</div>"#,
+        report.status,
+        label,
+        report.status,
+        report.score,
+        if items.is_empty() {
+            String::new()
+        } else {
+            format!("<ul>{}</ul>", items)
+        }
+    )
+}
+
+/// 失败统计单元格：最近失败次数达到阈值时以警告颜色显示，悬停显示最近一次错误
+fn failures_cell_html(failures: &StreamFailureStats) -> String {
+    if failures.is_empty() {
+        return "0".to_string();
+    }
+    let title = failures
+        .last_failure()
+        .map(|(kind, error)| stats_http::escape_html(&format!("{}: {}", kind, error)))
+        .unwrap_or_default();
+    let class = if failures.recent >= STREAM_FAILURE_WARN_THRESHOLD {
+        "badge badge-warning"
+    } else {
+        "badge badge-info"
+    };
+    format!(
+        r#"<span class="{}" title="{}">{} (最近 5 分钟: {})</span>"#,
+        class,
+        title,
+        failures.total(),
+        failures.recent
+    )
+}
+
+/// 状态单元格（被服务器拒绝的条目高亮显示）
+fn status_cell_html(status: &str) -> String {
+    let status = stats_http::escape_html(status);
+    if status.starts_with("Rejected") {
+        format!(r#"<span class="badge badge-warning">{}</span>"#, status)
+    } else {
+        status
+    }
+}
+
+/// 连接池命中率单元格（鼠标悬停显示各项计数，不复用本地连接的代理显示 "-"）
+fn pool_cell_html(pool: Option<&PoolMetrics>) -> String {
+    let Some(pool) = pool else {
+        return "-".to_string();
+    };
+    let rate = pool
+        .hit_rate()
+        .map(|rate| format!("{:.1}%", rate * 100.0))
+        .unwrap_or_else(|| "-".to_string());
+    format!(
+        r#"<span title="命中 {} / 未命中 {} / 新建 {} / 丢弃 {} / 空闲 {} / 预热失败 {}">{}</span>"#,
+        pool.hits, pool.misses, pool.created, pool.discarded, pool.idle, pool.warmup_failures, rate
     )
 }
 
+/// 生成 forwarder 路由决策统计的 HTML 片段（没有启用路由的 forwarder 时为空）
+fn generate_routing_html(stats: &[ClientProxyStats]) -> String {
+    let mut sections = String::new();
+    for stat in stats {
+        let Some(routing) = &stat.routing else {
+            continue;
+        };
+
+        let mut rows = String::new();
+        for (rule, count) in &routing.decisions {
+            if *count > 0 {
+                rows.push_str(&format!("<tr><td>{}</td><td>{}</td></tr>", rule, count));
+            }
+        }
+        if rows.is_empty() {
+            rows.push_str("<tr><td colspan=\"2\">暂无路由决策</td></tr>");
+        }
+
+        sections.push_str(&format!(
+            r#"
+        <div class="stats-table" style="margin-top: 20px;">
+            <h3>🧭 {} 路由统计</h3>
+            <p class="subtitle">直连流量: {} &nbsp; 代理流量: {}{}</p>
+            <table>
+                <thead>
+                    <tr>
+                        <th>规则</th>
+                        <th>命中次数</th>
+                    </tr>
+                </thead>
+                <tbody>
+                    {}
+                </tbody>
+            </table>
+        </div>
+            "#,
+            stat.core.name,
+            format_bytes(routing.direct_bytes),
+            format_bytes(routing.proxied_bytes),
+            routing_state_html(routing),
+            rows
+        ));
+    }
+    sections
+}
+
+/// 月配额和当前时间段的 HTML 片段
+fn routing_state_html(routing: &RoutingStatsSnapshot) -> String {
+    let mut html = String::new();
+    if let Some(quota) = &routing.quota {
+        html.push_str(&format!(
+            " &nbsp; 本月配额 ({}): {} / {}{}",
+            quota.period,
+            format_bytes(quota.used_bytes),
+            format_bytes(quota.limit_bytes),
+            if quota.exhausted { " (已用尽)" } else { "" }
+        ));
+    }
+    if let Some(schedule) = &routing.active_schedule {
+        html.push_str(&format!(" &nbsp; 当前时间段: {}", schedule));
+    }
+    html
+}
+
 /// 计算运行时长（格式化）
 pub fn format_duration(seconds: u64) -> String {
     let duration = Duration::from_secs(seconds);
@@ -561,11 +1819,11 @@ mod tests {
         );
 
         let stats = tracker.snapshot();
-        assert_eq!(stats.name, "test-proxy");
-        assert_eq!(stats.bind_port, 8080);
-        assert_eq!(stats.target_port, 3080);
-        assert_eq!(stats.active_connections, 0);
-        assert_eq!(stats.total_connections, 0);
+        assert_eq!(stats.core.name, "test-proxy");
+        assert_eq!(stats.core.listen_port, 8080);
+        assert_eq!(stats.core.target_port, 3080);
+        assert_eq!(stats.core.active_connections, 0);
+        assert_eq!(stats.core.total_connections, 0);
     }
 
     #[test]
@@ -581,18 +1839,18 @@ mod tests {
 
         tracker.connection_started();
         let stats = tracker.snapshot();
-        assert_eq!(stats.active_connections, 1);
-        assert_eq!(stats.total_connections, 1);
+        assert_eq!(stats.core.active_connections, 1);
+        assert_eq!(stats.core.total_connections, 1);
 
         tracker.connection_started();
         let stats = tracker.snapshot();
-        assert_eq!(stats.active_connections, 2);
-        assert_eq!(stats.total_connections, 2);
+        assert_eq!(stats.core.active_connections, 2);
+        assert_eq!(stats.core.total_connections, 2);
 
         tracker.connection_ended();
         let stats = tracker.snapshot();
-        assert_eq!(stats.active_connections, 1);
-        assert_eq!(stats.total_connections, 2);
+        assert_eq!(stats.core.active_connections, 1);
+        assert_eq!(stats.core.total_connections, 2);
     }
 
     #[test]
@@ -606,12 +1864,23 @@ mod tests {
             3080,
         );
 
-        tracker.record_bytes_sent(1024);
-        tracker.record_bytes_received(2048);
+        let meter = tracker.meter();
+        meter.add_sent(1024);
+        meter.add_received(2048);
+
+        // 连接进行中的快照已包含计数器的当前值
+        let stats = tracker.snapshot();
+        assert_eq!(stats.core.bytes_sent, 1024);
+        assert_eq!(stats.core.bytes_received, 2048);
 
+        drop(meter);
+        tracker.meter().add_sent(1);
         let stats = tracker.snapshot();
-        assert_eq!(stats.bytes_sent, 1024);
-        assert_eq!(stats.bytes_received, 2048);
+        assert_eq!(stats.core.bytes_sent, 1025);
+        assert_eq!(stats.core.bytes_received, 2048);
+
+        tracker.reset();
+        assert_eq!(tracker.snapshot().core.bytes_sent, 0);
     }
 
     #[test]
@@ -664,4 +1933,499 @@ mod tests {
         assert!(found.is_some());
         assert_eq!(found.unwrap().name, "proxy1");
     }
+
+    #[test]
+    fn test_routing_stats_disabled_by_default() {
+        let tracker = ClientStatsTracker::new(
+            "fwd".to_string(),
+            ProxyType::Socks5Proxy,
+            "127.0.0.1".to_string(),
+            1080,
+            "server".to_string(),
+            0,
+        );
+
+        let decision = RouteDecision {
+            rule: RouteRule::DirectByDefault,
+            matched: None,
+        };
+        tracker.record_route_decision("example.com:443", &decision);
+        tracker.route_meter(true).add_sent(100);
+
+        assert!(tracker.snapshot().routing.is_none());
+    }
+
+    #[test]
+    fn test_routing_decision_counters() {
+        use super::super::geoip::GeoIpRouter;
+        use crate::config::{RoutingConfig, RoutingStrategy};
+
+        let router = GeoIpRouter::new(RoutingConfig {
+            geoip_db: None,
+            direct_countries: vec!["CN".to_string()],
+            proxy_countries: vec!["US".to_string()],
+            direct_ips: vec!["10.0.0.0/8".to_string()],
+            proxy_ips: vec!["8.8.8.0/24".to_string()],
+            direct_domains: vec!["*.example.com".to_string()],
+            proxy_domains: vec!["*.google.com".to_string()],
+            default_strategy: RoutingStrategy::Proxy,
+            schedules: vec![],
+            utc_offset: None,
+            quota: None,
+        })
+        .unwrap();
+
+        let tracker = ClientStatsTracker::new(
+            "fwd".to_string(),
+            ProxyType::Socks5Proxy,
+            "127.0.0.1".to_string(),
+            1080,
+            "server".to_string(),
+            0,
+        )
+        .with_routing_stats();
+
+        let cases = [
+            ("api.example.com:443", RouteRule::DirectByDomain),
+            ("www.google.com:443", RouteRule::ProxyByDomain),
+            ("10.1.2.3:22", RouteRule::DirectByIp),
+            ("8.8.8.8:53", RouteRule::ProxyByIp),
+            ("1.1.1.1:443", RouteRule::ProxyByDefault),
+        ];
+        for (target, expected) in cases {
+            let decision = router.should_direct_connect(target);
+            assert_eq!(decision.rule, expected, "target {}", target);
+            tracker.record_route_decision(target, &decision);
+        }
+        // 国家规则需要 GeoIP 数据库查询，这里直接按国家代码匹配
+        tracker.record_route_decision("cn-host:80", &router.match_country("CN"));
+        tracker.record_route_decision("us-host:80", &router.match_country("US"));
+        tracker.record_route_decision("jp-host:80", &router.match_country("JP"));
+
+        let routing = tracker.routing_stats().unwrap();
+        for rule in [
+            RouteRule::DirectByDomain,
+            RouteRule::ProxyByDomain,
+            RouteRule::DirectByIp,
+            RouteRule::ProxyByIp,
+            RouteRule::DirectByCountry,
+            RouteRule::ProxyByCountry,
+        ] {
+            assert_eq!(routing.decision_count(rule), 1, "rule {:?}", rule);
+        }
+        assert_eq!(routing.decision_count(RouteRule::ProxyByDefault), 2);
+        assert_eq!(routing.decision_count(RouteRule::DirectByDefault), 0);
+
+        let snapshot = tracker.snapshot().routing.unwrap();
+        assert_eq!(snapshot.decisions["direct_by_domain"], 1);
+        assert_eq!(snapshot.recent.len(), 8);
+        assert_eq!(snapshot.recent[0].target, "api.example.com:443");
+        assert_eq!(snapshot.recent[0].matched.as_deref(), Some("*.example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_stats_routing_and_quota_totals_agree() {
+        use super::super::forwarder::copy_with_stats;
+        use super::super::geoip::GeoIpRouter;
+        use crate::config::{QuotaAction, RoutingConfig, RoutingQuota, RoutingStrategy};
+        use crate::io_util::IdleTimeout;
+        use tokio::io::AsyncWriteExt;
+
+        /// 通过 duplex 管道转发 `len` 字节，按 `record` 记录到连接的计数器
+        async fn relay(meter: &TrafficMeter, len: u64, record: fn(&TrafficMeter, u64)) {
+            let (mut source, mut relay_side) = tokio::io::duplex(16 * 1024);
+            let writer = async {
+                source.write_all(&vec![7u8; len as usize]).await.unwrap();
+                source.shutdown().await.unwrap();
+            };
+            let mut sink = tokio::io::sink();
+            let mut buf = [0u8; 4096];
+            let idle = &IdleTimeout::new(Duration::from_secs(300));
+            let copy = copy_with_stats(
+                &mut relay_side,
+                &mut sink,
+                &mut buf,
+                idle,
+                Some(meter),
+                record,
+            );
+            let ((), copied) = tokio::join!(writer, copy);
+            assert_eq!(copied.unwrap(), len);
+        }
+
+        let router = Arc::new(
+            GeoIpRouter::new(RoutingConfig {
+                geoip_db: None,
+                direct_countries: vec![],
+                proxy_countries: vec![],
+                direct_ips: vec![],
+                proxy_ips: vec![],
+                direct_domains: vec![],
+                proxy_domains: vec![],
+                default_strategy: RoutingStrategy::Proxy,
+                schedules: vec![],
+                utc_offset: None,
+                quota: Some(RoutingQuota {
+                    bytes_per_month: 1 << 40,
+                    action: QuotaAction::Direct,
+                    state_path: None,
+                }),
+            })
+            .unwrap(),
+        );
+        let tracker = ClientStatsTracker::new(
+            "fwd".to_string(),
+            ProxyType::Socks5Proxy,
+            "127.0.0.1".to_string(),
+            1080,
+            "server".to_string(),
+            0,
+        )
+        .with_routing_stats()
+        .with_router(router.clone());
+
+        // 经隧道和直连各转发一个连接，大小跨过若干个批量阈值
+        for (direct, upload, download) in [(false, 700_001u64, 90_000u64), (true, 5_000, 300_000)] {
+            let meter = tracker.route_meter(direct);
+            relay(&meter, upload, TrafficMeter::add_sent).await;
+            relay(&meter, download, TrafficMeter::add_received).await;
+            // 连接进行中和结束后的快照一致
+            let live = tracker.snapshot();
+            drop(meter);
+            assert_eq!(tracker.snapshot().core, live.core);
+        }
+
+        let snapshot = tracker.snapshot();
+        let routing = snapshot.routing.unwrap();
+        let quota = routing.quota.unwrap();
+        assert_eq!(snapshot.core.bytes_sent, 705_001);
+        assert_eq!(snapshot.core.bytes_received, 390_000);
+        assert_eq!(routing.proxied_bytes, 790_001);
+        assert_eq!(quota.used_bytes, routing.proxied_bytes);
+        assert_eq!(
+            routing.direct_bytes + routing.proxied_bytes,
+            snapshot.core.bytes_sent + snapshot.core.bytes_received
+        );
+    }
+
+    #[test]
+    fn test_target_totals_match_proxy_totals() {
+        let tracker = ClientStatsTracker::new(
+            "web".to_string(),
+            ProxyType::Tcp,
+            "server".to_string(),
+            8080,
+            "127.0.0.1".to_string(),
+            80,
+        )
+        .with_targets();
+
+        // 内网代理发往服务器的数据是从本地目标收到的数据
+        let first = tracker.target_meter("127.0.0.1:80", true);
+        let second = tracker.target_meter("127.0.0.1:81", true);
+        first.add_sent(300);
+        first.add_received(20);
+        second.add_sent(5);
+        drop(first);
+
+        let snapshot = tracker.snapshot();
+        let targets = snapshot.targets.unwrap();
+        assert_eq!(targets["127.0.0.1:80"].bytes_received, 300);
+        assert_eq!(targets["127.0.0.1:80"].bytes_sent, 20);
+        assert_eq!(targets["127.0.0.1:81"].bytes_received, 5);
+        let target_received: u64 = targets.values().map(|t| t.bytes_received).sum();
+        let target_sent: u64 = targets.values().map(|t| t.bytes_sent).sum();
+        assert_eq!(snapshot.core.bytes_sent, target_received);
+        assert_eq!(snapshot.core.bytes_received, target_sent);
+    }
+
+    #[test]
+    fn test_routing_bytes_and_recent_bound() {
+        let routing = RoutingStats::new();
+        routing.path(true).meter().add_sent(100);
+        routing.path(false).meter().add_received(50);
+
+        let decision = RouteDecision {
+            rule: RouteRule::DirectByDefault,
+            matched: None,
+        };
+        for i in 0..(RECENT_DECISIONS_CAPACITY + 10) {
+            routing.record_decision(&format!("host{}:80", i), &decision);
+        }
+
+        let snapshot = routing.snapshot();
+        assert_eq!(snapshot.direct_bytes, 100);
+        assert_eq!(snapshot.proxied_bytes, 50);
+        assert_eq!(snapshot.recent.len(), RECENT_DECISIONS_CAPACITY);
+        assert_eq!(snapshot.recent[0].target, "host10:80");
+    }
+
+    #[test]
+    fn test_recent_connections_are_bounded() {
+        let tracker = ClientStatsTracker::new(
+            "web".to_string(),
+            ProxyType::Tcp,
+            "127.0.0.1".to_string(),
+            8080,
+            "server".to_string(),
+            3080,
+        );
+        let peer = PeerInfo {
+            addr: "203.0.113.7:51234".to_string(),
+            accepted_at_ms: 1_700_000_000_000,
+        };
+        assert!(tracker.peer_connection(&peer).is_none());
+
+        let tracker = tracker.with_recent_connections();
+        for i in 0..(RECENT_CONNECTIONS_CAPACITY + 10) {
+            let peer = PeerInfo {
+                addr: format!("203.0.113.7:{}", 50000 + i),
+                ..peer.clone()
+            };
+            let connection = tracker.peer_connection(&peer).unwrap();
+            let meter = tracker.meter().attach(connection.traffic());
+            meter.add_sent(3);
+            meter.add_received(5);
+        }
+
+        let recent = tracker.snapshot().recent_connections.unwrap();
+        assert_eq!(recent.len(), RECENT_CONNECTIONS_CAPACITY);
+        assert_eq!(recent[0].peer_addr, "203.0.113.7:50010");
+        assert_eq!(recent[0].accepted_at_ms, peer.accepted_at_ms);
+        assert_eq!((recent[0].bytes_sent, recent[0].bytes_received), (3, 5));
+        assert_eq!(
+            tracker.snapshot().core.bytes_sent,
+            3 * recent.len() as u64 + 30
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pool_metrics_in_snapshot() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let pool = Arc::new(ConnectionPool::new(crate::connection_pool::PoolConfig {
+            reuse_connections: true,
+            ..Default::default()
+        }));
+        let tracker = |name: &str| {
+            ClientStatsTracker::new(
+                name.to_string(),
+                ProxyType::Http2,
+                "127.0.0.1".to_string(),
+                8080,
+                "server".to_string(),
+                3080,
+            )
+        };
+        let manager = ClientStatsManager::new();
+        manager.add_tracker(tracker("plain"));
+        manager.add_tracker(tracker("pooled").with_pool(pool.clone()));
+
+        let stream = pool.get(&addr).await.unwrap();
+        pool.return_connection(&addr, stream).await;
+        let stream = pool.get(&addr).await.unwrap();
+        pool.discard_connection(&addr, stream).await;
+
+        let pools = manager.pool_snapshot();
+        assert_eq!(pools.keys().collect::<Vec<_>>(), vec!["pooled"]);
+        assert_eq!((pools["pooled"].hits, pools["pooled"].misses), (1, 1));
+
+        let stats = manager.get_all_stats();
+        assert!(stats[0].pool.is_none());
+        let json = serde_json::to_value(&stats[1]).unwrap();
+        assert_eq!(json["pool"]["created"], 1);
+        assert_eq!(json["pool"]["discarded"], 1);
+        let parsed: ClientProxyStats = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.pool, stats[1].pool);
+
+        let html = generate_client_stats_html(&manager);
+        assert!(html.contains("连接池命中率"));
+        assert!(html.contains(">50.0%</span>"));
+    }
+
+    #[test]
+    fn test_stream_failure_counters() {
+        let tracker = ClientStatsTracker::new(
+            "test".to_string(),
+            ProxyType::Tcp,
+            "127.0.0.1".to_string(),
+            8080,
+            "server".to_string(),
+            3080,
+        );
+        assert!(tracker.snapshot().failures.is_empty());
+        let json = serde_json::to_value(tracker.snapshot()).unwrap();
+        assert!(json.get("failures").is_none());
+
+        tracker.record_failure(StreamFailure::ServerRejected, "Proxy 'db' not found");
+        tracker.record_failure(StreamFailure::ServerRejected, "Proxy 'web' not found");
+        tracker.record_failure(StreamFailure::StreamOpen, "Failed to request yamux stream");
+
+        let failures = tracker.snapshot().failures;
+        assert_eq!(failures.server_rejected.count, 2);
+        assert_eq!(
+            failures.server_rejected.last_error.as_deref(),
+            Some("Proxy 'web' not found")
+        );
+        assert!(failures.server_rejected.last_at.is_some());
+        assert_eq!(failures.stream_open_failed.count, 1);
+        assert_eq!(failures.timeouts.count, 0);
+        assert_eq!(failures.total(), 3);
+        assert_eq!(failures.recent, 3);
+        assert!(failures_cell_html(&failures).contains("badge-warning"));
+        assert_eq!(status_cell_html("Idle"), "Idle");
+        assert!(status_cell_html("Rejected: <port> in use").contains("badge-warning"));
+        assert!(status_cell_html("Rejected: <port> in use").contains("&lt;port&gt;"));
+
+        let json = serde_json::to_value(tracker.snapshot()).unwrap();
+        assert_eq!(json["failures"]["server_rejected"]["count"], 2);
+
+        tracker.reset();
+        assert!(tracker.snapshot().failures.is_empty());
+    }
+
+    #[test]
+    fn test_stream_failure_classify() {
+        let timed_out = anyhow::Error::from(std::io::Error::from(std::io::ErrorKind::TimedOut))
+            .context("Failed to connect to example.com:443");
+        assert_eq!(
+            StreamFailure::classify(&timed_out, StreamFailure::TargetConnect),
+            StreamFailure::Timeout
+        );
+        let refused =
+            anyhow::Error::from(std::io::Error::from(std::io::ErrorKind::ConnectionRefused));
+        assert_eq!(
+            StreamFailure::classify(&refused, StreamFailure::TargetConnect),
+            StreamFailure::TargetConnect
+        );
+    }
+
+    fn numbered_tracker(i: usize) -> ClientStatsTracker {
+        ClientStatsTracker::new(
+            format!("proxy-{}", i),
+            ProxyType::Tcp,
+            "127.0.0.1".to_string(),
+            10_000,
+            "server".to_string(),
+            20_000,
+        )
+    }
+
+    /// 多个线程同时添加和替换跟踪器：每次 `rcu` 交换都基于最新的列表，没有更新丢失
+    #[test]
+    fn test_concurrent_tracker_updates_are_all_visible() {
+        const THREADS: usize = 8;
+        const PER_THREAD: usize = 200;
+
+        let manager = ClientStatsManager::new();
+        let workers: Vec<_> = (0..THREADS)
+            .map(|thread| {
+                let manager = manager.clone();
+                std::thread::spawn(move || {
+                    for i in 0..PER_THREAD {
+                        let index = thread * PER_THREAD + i;
+                        manager.add_or_update_tracker(numbered_tracker(index));
+                        // 替换刚添加的跟踪器不会产生重复的条目
+                        manager.add_or_update_tracker(numbered_tracker(index));
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        let stats = manager.get_all_stats();
+        assert_eq!(stats.len(), THREADS * PER_THREAD);
+        let names: std::collections::HashSet<_> = stats.iter().map(|s| &s.core.name).collect();
+        assert_eq!(names.len(), THREADS * PER_THREAD);
+        assert!(names.contains(&format!("proxy-{}", THREADS * PER_THREAD - 1)));
+    }
+
+    /// 生成页面时持有的跟踪器列表不阻塞更新：更新在同一线程中完成（需要等待读者时会死锁），
+    /// 已取得的列表保持不变，之后取得的列表包含更新
+    #[test]
+    fn test_tracker_updates_do_not_wait_for_rendering() {
+        let manager = ClientStatsManager::new();
+        for i in 0..3 {
+            manager.add_tracker(numbered_tracker(i));
+        }
+
+        let rendering = manager.trackers();
+        manager.add_or_update_tracker(numbered_tracker(3));
+        assert!(manager.remove_tracker("proxy-0"));
+        assert!(generate_client_stats_html(&manager).contains("proxy-3"));
+
+        let names = |trackers: &[Arc<ClientStatsTracker>]| {
+            trackers.iter().map(|t| t.name.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(names(&rendering), ["proxy-0", "proxy-1", "proxy-2"]);
+        assert_eq!(
+            names(&manager.trackers()),
+            ["proxy-1", "proxy-2", "proxy-3"]
+        );
+    }
+
+    /// 反复生成 5000 个跟踪器的 HTML 页面期间，替换跟踪器仍在微秒级完成
+    ///
+    /// 与全部测试并行运行时线程调度会干扰计时，因此默认不运行
+    #[test]
+    #[ignore = "timing stress test; run with --ignored"]
+    fn test_tracker_updates_do_not_wait_for_html_rendering() {
+        use std::sync::atomic::AtomicBool;
+
+        const TRACKERS: usize = 5_000;
+        let manager = ClientStatsManager::new();
+        for i in 0..TRACKERS {
+            manager.add_tracker(numbered_tracker(i));
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let render_times = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let renderer = {
+            let manager = manager.clone();
+            let stop = stop.clone();
+            let render_times = render_times.clone();
+            std::thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let started = Instant::now();
+                    let html = generate_client_stats_html(&manager);
+                    render_times.lock().push(started.elapsed());
+                    assert!(html.contains("proxy-4999"));
+                }
+            })
+        };
+
+        // 至少跨越几次完整的页面生成；按中位数判断，单核机器上线程切换只会让个别更新变慢，
+        // 而更新需要等待页面生成时中位数就是一次页面生成的时间
+        let mut latencies = Vec::new();
+        let mut i = 0;
+        while latencies.len() < 2_000 || render_times.lock().len() < 4 {
+            let replacement = numbered_tracker(i * 7 % TRACKERS);
+            let started = Instant::now();
+            manager.add_or_update_tracker(replacement);
+            latencies.push(started.elapsed());
+            i += 1;
+        }
+        stop.store(true, Ordering::Relaxed);
+        renderer.join().unwrap();
+
+        assert_eq!(manager.get_all_stats().len(), TRACKERS);
+        let median = |times: &mut Vec<Duration>| {
+            times.sort();
+            times[times.len() / 2]
+        };
+        let update_median = median(&mut latencies);
+        let p99 = latencies[latencies.len() * 99 / 100];
+        let render_median = median(&mut render_times.lock());
+        let message = format!(
+            "update latency median {:?}, p99 {:?} over {} updates; render median {:?}",
+            update_median,
+            p99,
+            latencies.len(),
+            render_median
+        );
+        assert!(update_median < Duration::from_millis(1), "{}", message);
+        assert!(update_median * 10 < render_median, "{}", message);
+    }
 }
@@ -1,64 +1,114 @@
-use crate::config::ClientFullConfig;
-use crate::connection_pool::ConnectionPool;
+use crate::config::{ClientFullConfig, ProxyConfig};
+use crate::integrity::{self, IntegrityCheck};
+use crate::io_util::{copy_counted_with_stall, StallDetector, StallMonitor};
+use crate::keepalive::{self, FrameReader, StreamFraming};
+use crate::protocol::{
+    PeerInfo, FRAMED_STREAM_MARKER, PEER_INFO_FLAG_FRAMED, PEER_INFO_FLAG_INTEGRITY,
+    PEER_INFO_STREAM_MARKER, VISITOR_MUX_STREAM_MARKER,
+};
+use crate::protocol_trace::{SessionTrace, TraceDirection};
+use crate::relay_memory::RELAY_BUFFER_SIZE;
+use crate::stats::STATS_FLUSH_BYTES;
+use crate::traffic::TrafficMeter;
 use anyhow::Result;
 use futures::io::{AsyncReadExt as FuturesAsyncReadExt, AsyncWriteExt as FuturesAsyncWriteExt};
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+use tokio_util::compat::{
+    FuturesAsyncReadCompatExt, TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt,
+};
 use tracing::{error, info, warn};
 
+use super::connection::{connect_targets, LocalBackend, LocalTargets};
 use super::stats::ClientStatsTracker;
+use super::upgrade::{UpgradeState, UpgradeWatch};
+use super::visitor_mux::MuxSession;
 
-/// 拷贝数据并记录统计
+/// 拷贝数据并分批记录到连接的计数器（`is_upload` 为本地服务发往服务器的方向）
 async fn copy_with_stats<R, W>(
     reader: &mut R,
     writer: &mut W,
-    tracker: &Option<ClientStatsTracker>,
+    buf: &mut [u8],
+    meter: Option<&TrafficMeter>,
     is_upload: bool,
+    stall: Option<&StallMonitor>,
 ) -> std::io::Result<u64>
 where
     R: FuturesAsyncReadExt + Unpin,
     W: FuturesAsyncWriteExt + Unpin,
 {
-    let mut total = 0u64;
-    let mut buf = vec![0u8; 8192];
-
-    loop {
-        let n = reader.read(&mut buf).await?;
-        if n == 0 {
-            break;
-        }
-
-        writer.write_all(&buf[..n]).await?;
-
-        // 记录统计
-        total += n as u64;
-        if let Some(ref t) = tracker {
-            if is_upload {
-                t.record_bytes_sent(n as u64);
-            } else {
-                t.record_bytes_received(n as u64);
-            }
-        }
-    }
+    copy_counted_with_stall(
+        reader,
+        writer,
+        buf,
+        STATS_FLUSH_BYTES,
+        |bytes| match meter {
+            Some(m) if is_upload => m.add_sent(bytes),
+            Some(m) => m.add_received(bytes),
+            None => {}
+        },
+        stall,
+    )
+    .await
+}
 
-    writer.flush().await?;
-    Ok(total)
+/// 读取前导中的外部连接信息（[`PeerInfo::encode`] 的格式）
+async fn read_peer_info<R>(reader: &mut R) -> Result<PeerInfo>
+where
+    R: FuturesAsyncReadExt + Unpin,
+{
+    let mut len = [0u8; 1];
+    reader.read_exact(&mut len).await?;
+    let mut addr = vec![0u8; len[0] as usize];
+    reader.read_exact(&mut addr).await?;
+    let mut accepted_at = [0u8; 8];
+    reader.read_exact(&mut accepted_at).await?;
+    Ok(PeerInfo {
+        addr: String::from_utf8_lossy(&addr).into_owned(),
+        accepted_at_ms: u64::from_be_bytes(accepted_at),
+    })
 }
 
 /// 处理yamux流
 pub async fn handle_stream(
     stream: yamux::Stream,
     config: ClientFullConfig,
-    proxy_pools: Arc<HashMap<u16, Arc<ConnectionPool>>>,
+    proxy_pools: Arc<HashMap<u16, Arc<LocalBackend>>>,
     stats_manager: super::stats::ClientStatsManager,
+    trace: SessionTrace,
 ) -> Result<()> {
     let mut stream = stream;
+    let trace = trace.stream(stream.id());
 
-    // 从 stream 读取 publish_port
+    // 从 stream 读取 publish_port（复用模式的 visitor stream 先发送标记，
+    // 分帧的代理 stream 在复用模式标记之后再发送分帧标记，携带外部连接信息的代理 stream
+    // 在分帧标记之后再发送一个标记和标志位）
     let mut port_buf = [0u8; 2];
     stream.read_exact(&mut port_buf).await?;
-    let publish_port = u16::from_be_bytes(port_buf);
+    let mut publish_port = u16::from_be_bytes(port_buf);
+    let mut mux = publish_port == VISITOR_MUX_STREAM_MARKER;
+    let mut framed = false;
+    let mut integrity = false;
+    let mut peer = None;
+    if mux {
+        stream.read_exact(&mut port_buf).await?;
+        publish_port = u16::from_be_bytes(port_buf);
+        if publish_port == FRAMED_STREAM_MARKER {
+            mux = false;
+            framed = true;
+            stream.read_exact(&mut port_buf).await?;
+            publish_port = u16::from_be_bytes(port_buf);
+            if publish_port == PEER_INFO_STREAM_MARKER {
+                let mut flags = [0u8; 1];
+                stream.read_exact(&mut flags).await?;
+                framed = flags[0] & PEER_INFO_FLAG_FRAMED != 0;
+                integrity = framed && flags[0] & PEER_INFO_FLAG_INTEGRITY != 0;
+                stream.read_exact(&mut port_buf).await?;
+                publish_port = u16::from_be_bytes(port_buf);
+                peer = Some(read_peer_info(&mut stream).await?);
+            }
+        }
+    }
 
     info!(
         "Stream requests connection for publish_port {}",
@@ -69,19 +119,98 @@ pub async fn handle_stream(
     let proxy = config
         .proxies
         .iter()
-        .find(|p| p.publish_port == publish_port)
-        .ok_or_else(|| {
-            anyhow::anyhow!("No proxy config found for publish_port {}", publish_port)
-        })?;
+        .find(|p| p.publish_port == publish_port);
+    // 前导长度：publish_port、各个标记和 SNI 选中的本地端口
+    let mut header_len = 2;
+    if mux {
+        header_len += 2;
+    } else {
+        if let Some(peer) = &peer {
+            header_len += 7 + peer.encoded_len();
+        } else if framed {
+            header_len += 4;
+        }
+        if proxy.is_some_and(|p| p.sni_routing.is_some()) {
+            header_len += 2;
+        }
+    }
+    trace.proxy(
+        TraceDirection::In,
+        proxy.map_or("", |p| p.name.as_str()),
+        publish_port,
+        Some(header_len),
+        peer.as_ref().map(|p| p.addr.as_str()),
+    );
+    let proxy = proxy.ok_or_else(|| {
+        anyhow::anyhow!("No proxy config found for publish_port {}", publish_port)
+    })?;
 
     info!(
-        "Found proxy '{}' (local_port: {}) for publish_port {}",
-        proxy.name, proxy.local_port, publish_port
+        "Found proxy '{}' (local targets: {}) for publish_port {}",
+        proxy.name,
+        proxy.local_addrs().join(", "),
+        publish_port
     );
 
+    if let Some(peer) = &peer {
+        info!("Proxy '{}' connection from {}", proxy.name, peer.addr);
+    }
+
     // 获取统计跟踪器
     let tracker = stats_manager.get_tracker(&proxy.name);
 
+    // 获取该代理对应的连接池和本地目标（键是 publish_port）
+    let backend = proxy_pools
+        .get(&publish_port)
+        .ok_or_else(|| {
+            anyhow::anyhow!("No connection pool found for publish_port {}", publish_port)
+        })?
+        .clone();
+
+    // SNI 路由代理的 stream 在 publish_port 之后携带服务器按 SNI 选中的本地端口（0 表示 local_port），
+    // 只接受路由表中的端口（SNI 路由代理不能配置 local_targets）
+    let mut sni_targets = None;
+    if let (false, Some(sni_routing)) = (mux, &proxy.sni_routing) {
+        let mut local_port = proxy.local_port;
+        stream.read_exact(&mut port_buf).await?;
+        let selected = u16::from_be_bytes(port_buf);
+        if selected != 0 {
+            if selected != proxy.local_port && !sni_routing.routes_to(selected) {
+                anyhow::bail!(
+                    "Proxy '{}' has no SNI route to local port {}",
+                    proxy.name,
+                    selected
+                );
+            }
+            local_port = selected;
+        }
+        info!(
+            "SNI routing selected local port {} for proxy '{}'",
+            local_port, proxy.name
+        );
+        sni_targets = Some(LocalTargets::new(vec![format!("127.0.0.1:{}", local_port)]));
+    }
+    let targets = sni_targets.as_ref().unwrap_or(&backend.targets);
+    let pool = &backend.pool;
+
+    if mux {
+        info!(
+            "Visitor mux stream opened for proxy '{}', serving substreams",
+            proxy.name
+        );
+        serve_mux_stream(stream, proxy, backend.clone(), tracker).await;
+        return Ok(());
+    }
+
+    // UDP 代理的 stream 按数据报分帧，转发到唯一的本地目标
+    if proxy.proxy_type.is_datagram() {
+        let target = targets
+            .addrs()
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("Proxy '{}' has no local target", proxy.name))?;
+        return super::udp::relay_datagrams(stream, proxy, target, tracker).await;
+    }
+
     // 连接开始
     if let Some(ref t) = tracker {
         t.connection_started();
@@ -101,44 +230,137 @@ pub async fn handle_stream(
     let _guard = ConnectionGuard {
         tracker: tracker.clone(),
     };
+    // 服务器上报了来源时，连接结束后写入最近连接列表（重试本地连接的字节一并计入）
+    let peer_connection = peer
+        .as_ref()
+        .and_then(|peer| tracker.as_ref()?.peer_connection(peer));
 
-    // 获取该代理对应的连接池（连接池键是 publish_port）
-    let pool = proxy_pools
-        .get(&publish_port)
-        .ok_or_else(|| {
-            anyhow::anyhow!("No connection pool found for publish_port {}", publish_port)
-        })?
-        .clone();
+    // 开启停滞诊断时按 stream 标识报告
+    let monitor =
+        StallDetector::from_config(config.client.debug_stalls, config.client.max_write_chunk)
+            .map(|detector| detector.monitor(format!("{}#{}", proxy.name, stream.id())));
 
-    let local_addr = format!("127.0.0.1:{}", proxy.local_port);
+    // 分帧的 stream 按本地配置的间隔发送保活标记（未配置时只分帧），并丢弃服务器发送的标记；
+    // 服务器要求完整性校验时两个方向都计算校验和并交换尾帧
+    let framing = framed.then(|| {
+        let framing = StreamFraming::new(
+            proxy
+                .idle_keepalive_secs
+                .map(std::time::Duration::from_secs),
+        );
+        if !integrity {
+            return framing;
+        }
+        framing.with_integrity(IntegrityCheck::new(
+            format!("{}#{}", proxy.name, stream.id()),
+            integrity::Direction::ClientToServer,
+            tracker
+                .as_ref()
+                .map(|t| t.integrity().clone())
+                .unwrap_or_default(),
+        ))
+    });
+    let (stream_read, mut stream_write) = futures::io::AsyncReadExt::split(stream);
+    let mut stream_read = FrameReader::new(stream_read, framing.as_ref());
 
-    let (mut stream_read, mut stream_write) = futures::io::AsyncReadExt::split(stream);
+    // 两个方向的缓冲区从全局预算领取，重试本地连接时继续使用
+    let (mut upload_buf, mut download_buf) = stats_manager
+        .relay_memory()
+        .reserve_pair(RELAY_BUFFER_SIZE)
+        .await;
 
     // 尝试一次自动重连（本地转发失败时重建本地连接并重试）
     let mut attempted_retry = false;
 
     loop {
-        let mut local_conn =
-            super::connection::connect_local(&local_addr, &pool, proxy.proxy_type).await?;
+        // 多个本地目标时连接失败会改用下一个目标
+        let mut local_conn = connect_targets(targets, pool, tracker.as_ref()).await?;
+        let local_addr = local_conn.addr.clone();
+        if let Some(ref t) = tracker {
+            t.target_connection_started(&local_addr);
+        }
 
+        // HTTP/1.1 代理检测本地服务响应中的 WebSocket 升级
+        let upgrade = UpgradeState::new(proxy, tracker.clone());
         let (local_read, local_write) = local_conn.stream.split();
-        let mut local_read = local_read.compat();
-        let mut local_write = local_write.compat_write();
+        let mut local_read = UpgradeWatch::new(local_read, &upgrade).compat();
+        let mut local_write = UpgradeWatch::new(local_write, &upgrade).compat_write();
 
-        // 使用 copy_with_stats 记录流量统计
-        let local_to_stream = copy_with_stats(&mut local_read, &mut stream_write, &tracker, true);
-        let stream_to_local = copy_with_stats(&mut stream_read, &mut local_write, &tracker, false);
+        // 代理发往服务器的数据是从本地目标收到的数据
+        let meter = tracker.as_ref().map(|t| {
+            let meter = t.target_meter(&local_addr, true);
+            match &peer_connection {
+                Some(connection) => meter.attach(connection.traffic()),
+                None => meter,
+            }
+        });
+        let mut local_closed = false;
+        let result = {
+            // 使用 copy_with_stats 记录流量统计
+            let local_to_stream = async {
+                keepalive::copy_to_stream(
+                    &mut local_read,
+                    &mut stream_write,
+                    &mut upload_buf,
+                    framing.as_ref(),
+                    STATS_FLUSH_BYTES,
+                    |bytes| {
+                        if let Some(m) = &meter {
+                            m.add_sent(bytes);
+                        }
+                    },
+                    monitor.as_ref(),
+                )
+                .await?;
+                // 本地服务关闭连接后向服务器发送 FIN（先写出缓冲的数据）
+                stream_write.close().await
+            };
+            let stream_to_local = async {
+                copy_with_stats(
+                    &mut stream_read,
+                    &mut local_write,
+                    &mut download_buf,
+                    meter.as_ref(),
+                    false,
+                    monitor.as_ref(),
+                )
+                .await?;
+                local_write.close().await?;
+                local_closed = true;
+                Ok(())
+            };
+            tokio::pin!(local_to_stream, stream_to_local);
 
-        let result = tokio::select! {
-            result = local_to_stream => result,
-            result = stream_to_local => result,
+            tokio::select! {
+                // 本地服务关闭了连接：等待服务器关闭 stream 再丢弃，避免 RST 中断对端的转发
+                result = &mut local_to_stream => {
+                    if result.is_ok() {
+                        crate::io_util::linger(&mut stream_to_local).await;
+                    }
+                    result
+                }
+                // 服务器发送 FIN（外部连接关闭了写方向）时关闭本地连接的写方向，
+                // 继续转发本地服务的响应直到其关闭连接
+                result = &mut stream_to_local => match result {
+                    Ok(()) => local_to_stream.await,
+                    Err(e) => Err(e),
+                },
+            }
         };
+        if let Some(ref t) = tracker {
+            t.target_connection_ended(&local_addr);
+        }
 
+        let upgraded = upgrade.is_upgraded();
         match result {
             Ok(_) => {
                 info!("Stream closed for proxy '{}'", proxy.name);
-                if local_conn.pooled && proxy.proxy_type.should_reuse_connections() {
-                    // 根据代理类型决定是否复用连接
+                if local_conn.pooled
+                    && pool.config().reuse_connections
+                    && !local_closed
+                    && !upgraded
+                {
+                    // 根据连接池策略决定是否复用连接（已关闭写方向和升级为 WebSocket 的连接不复用）
                     pool.return_connection(&local_addr, local_conn.stream).await;
                 } else {
                     pool.discard_connection(&local_addr, local_conn.stream)
@@ -153,6 +375,15 @@ pub async fn handle_stream(
                         .await;
                 }
 
+                // 升级后的连接已交换过 WebSocket 数据，不能在新连接上重试
+                if upgraded {
+                    info!(
+                        "WebSocket connection closed for proxy '{}': {}",
+                        proxy.name, e
+                    );
+                    let _ = stream_write.close().await;
+                    return Err(e.into());
+                }
                 if attempted_retry {
                     error!("Stream handling error after retry: {}", e);
                     return Err(anyhow::anyhow!("Stream handling failed after retry: {}", e));
@@ -164,3 +395,77 @@ pub async fn handle_stream(
         }
     }
 }
+
+/// 处理复用模式的 visitor stream：每个子流连接到本地服务并独立转发
+///
+/// 单个子流连接本地服务失败或转发出错时只重置该子流
+async fn serve_mux_stream(
+    stream: yamux::Stream,
+    proxy: &ProxyConfig,
+    backend: Arc<LocalBackend>,
+    tracker: Option<ClientStatsTracker>,
+) {
+    let (_session, mut substreams) = MuxSession::start(stream.compat());
+
+    while let Some(substream) = substreams.recv().await {
+        let backend = backend.clone();
+        let tracker = tracker.clone();
+        let proxy_name = proxy.name.clone();
+        let upgrade = UpgradeState::new(proxy, tracker.clone());
+
+        tokio::spawn(async move {
+            let id = substream.id();
+            let pool = &backend.pool;
+            let mut local_conn =
+                match connect_targets(&backend.targets, pool, tracker.as_ref()).await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        warn!(
+                            "Proxy '{}' substream {}: failed to connect local service: {}",
+                            proxy_name, id, e
+                        );
+                        substream.reset(&e.to_string()).await;
+                        return;
+                    }
+                };
+
+            let local_addr = local_conn.addr.clone();
+            if let Some(ref t) = tracker {
+                t.connection_started();
+                t.target_connection_started(&local_addr);
+            }
+            let meter = tracker.as_ref().map(|t| t.target_meter(&local_addr, true));
+            let result = substream
+                .relay(
+                    &mut UpgradeWatch::new(&mut local_conn.stream, &upgrade),
+                    meter.as_ref(),
+                )
+                .await;
+            if let Some(ref t) = tracker {
+                t.connection_ended();
+                t.target_connection_ended(&local_addr);
+            }
+
+            match result {
+                Ok(())
+                    if local_conn.pooled
+                        && pool.config().reuse_connections
+                        && !upgrade.is_upgraded() =>
+                {
+                    pool.return_connection(&local_addr, local_conn.stream).await;
+                }
+                Ok(()) => {
+                    pool.discard_connection(&local_addr, local_conn.stream)
+                        .await;
+                }
+                Err(e) => {
+                    warn!("Proxy '{}' substream {} error: {}", proxy_name, id, e);
+                    pool.discard_connection(&local_addr, local_conn.stream)
+                        .await;
+                }
+            }
+        });
+    }
+
+    info!("Visitor mux stream for proxy '{}' closed", proxy.name);
+}
@@ -0,0 +1,110 @@
+/// 系统休眠/唤醒检测
+///
+/// 事件循环每隔 [`CHECK_INTERVAL`] 比较相邻两次检查之间经过的单调时钟和挂钟时间：
+/// Linux/macOS 的单调时钟在休眠期间停止而挂钟继续走，Windows 的单调时钟在休眠期间继续走，
+/// 任一时钟的间隔超出预期 [`SUSPEND_THRESHOLD`] 以上即认为系统刚从休眠中唤醒。
+/// 此时隧道连接多半已经失效，客户端立即结束会话并重连，而不是等待心跳和写超时
+use std::time::SystemTime;
+use tokio::time::{Duration, Instant, Interval, MissedTickBehavior};
+
+/// 检查间隔
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 两次检查的间隔超出 [`CHECK_INTERVAL`] 该时长以上时认为发生了休眠
+pub const SUSPEND_THRESHOLD: Duration = Duration::from_secs(10);
+
+/// 休眠检测器
+pub struct SuspendDetector {
+    interval: Interval,
+    /// 上次检查时的单调时钟和挂钟时间
+    last: Option<(Instant, SystemTime)>,
+}
+
+impl SuspendDetector {
+    pub fn new() -> Self {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Self {
+            interval,
+            last: None,
+        }
+    }
+
+    /// 等到检测到休眠后返回两次检查之间经过的时间（可安全地在 `select!` 中取消）
+    pub async fn resumed(&mut self) -> Duration {
+        loop {
+            self.interval.tick().await;
+            if let Some(elapsed) = self.check(Instant::now(), SystemTime::now()) {
+                return elapsed;
+            }
+        }
+    }
+
+    /// 记录一次检查，距上次检查的间隔超出阈值时返回该间隔
+    fn check(&mut self, now: Instant, wall: SystemTime) -> Option<Duration> {
+        let (last, last_wall) = self.last.replace((now, wall))?;
+        // 挂钟被往回调整时不视为休眠
+        let elapsed = now
+            .duration_since(last)
+            .max(wall.duration_since(last_wall).unwrap_or_default());
+        (elapsed > CHECK_INTERVAL + SUSPEND_THRESHOLD).then_some(elapsed)
+    }
+}
+
+impl Default for SuspendDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_check_detects_monotonic_and_wall_clock_gaps() {
+        let mut detector = SuspendDetector::new();
+        let now = Instant::now();
+        let wall = SystemTime::now();
+        assert_eq!(detector.check(now, wall), None);
+
+        // 正常的检查间隔
+        let now = now + CHECK_INTERVAL;
+        let wall = wall + CHECK_INTERVAL;
+        assert_eq!(detector.check(now, wall), None);
+
+        // 单调时钟停止、挂钟走了 10 分钟（Linux/macOS 休眠）
+        let wall = wall + Duration::from_secs(600);
+        assert_eq!(
+            detector.check(now + CHECK_INTERVAL, wall),
+            Some(Duration::from_secs(600))
+        );
+
+        // 两个时钟都走了 1 分钟（Windows 休眠或事件循环长时间阻塞）
+        let now = now + CHECK_INTERVAL + Duration::from_secs(60);
+        assert_eq!(
+            detector.check(now, wall + Duration::from_secs(60)),
+            Some(Duration::from_secs(60))
+        );
+
+        // 挂钟被往回调整
+        let wall = wall + Duration::from_secs(60) - Duration::from_secs(3600);
+        assert_eq!(detector.check(now + CHECK_INTERVAL, wall), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_resumed_fires_after_time_jump() {
+        let mut detector = SuspendDetector::new();
+        let resumed = tokio::time::timeout(Duration::from_secs(60), detector.resumed()).await;
+        assert!(
+            resumed.is_err(),
+            "Regular ticks must not look like a resume"
+        );
+
+        let jump = tokio::spawn(async move { detector.resumed().await });
+        tokio::task::yield_now().await;
+        tokio::time::advance(Duration::from_secs(120)).await;
+        let elapsed = jump.await.unwrap();
+        assert!(elapsed >= Duration::from_secs(100), "{:?}", elapsed);
+    }
+}
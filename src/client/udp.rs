@@ -0,0 +1,102 @@
+/// UDP 代理的本地转发
+///
+/// 服务器为每个外部来源地址打开一个 stream，客户端为每个 stream 绑定一个连接到本地服务的
+/// UDP 套接字：stream 上的数据报帧（[`crate::datagram`]）解开后发往本地服务，本地服务的回复
+/// 按帧写回 stream，服务器再从发布端口发往该来源。来源会话的空闲超时由服务器控制，
+/// 服务器关闭 stream 时本地套接字随之关闭
+use super::stats::ClientStatsTracker;
+use crate::config::ProxyConfig;
+use crate::datagram::{read_datagram, write_datagram};
+use crate::protocol::MAX_DATAGRAM_SIZE;
+use anyhow::{Context, Result};
+use futures::io::AsyncWriteExt;
+use std::io;
+use std::net::SocketAddr;
+use tokio::net::UdpSocket;
+use tracing::{debug, info};
+
+/// 在 stream 与本地 UDP 服务之间转发数据报，直到服务器关闭 stream
+pub async fn relay_datagrams(
+    stream: yamux::Stream,
+    proxy: &ProxyConfig,
+    target: &str,
+    tracker: Option<ClientStatsTracker>,
+) -> Result<()> {
+    let socket = connect_local(target)
+        .await
+        .with_context(|| format!("Proxy '{}': cannot reach udp target {}", proxy.name, target))?;
+    info!(
+        "UDP stream opened for proxy '{}', relaying to {}",
+        proxy.name, target
+    );
+
+    if let Some(ref t) = tracker {
+        t.connection_started();
+        t.target_connection_started(target);
+    }
+    // 代理发往服务器的数据是从本地服务收到的数据
+    let meter = tracker.as_ref().map(|t| t.target_meter(target, true));
+
+    let (mut stream_read, mut stream_write) = futures::io::AsyncReadExt::split(stream);
+    let result: io::Result<()> = {
+        let to_local = async {
+            let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+            while let Some(len) = read_datagram(&mut stream_read, &mut buf).await? {
+                match socket.send(&buf[..len]).await {
+                    Ok(_) => {}
+                    // 本地服务尚未监听（之前的数据报收到了 ICMP 端口不可达），丢弃该数据报
+                    Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                        debug!("Proxy '{}': {} refused a datagram", proxy.name, target);
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                }
+                if let Some(m) = &meter {
+                    m.add_received(len as u64);
+                }
+            }
+            Ok(())
+        };
+        let to_stream = async {
+            let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+            loop {
+                let len = match socket.recv(&mut buf).await {
+                    Ok(len) => len,
+                    Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => continue,
+                    Err(e) => return Err(e),
+                };
+                write_datagram(&mut stream_write, &buf[..len]).await?;
+                if let Some(m) = &meter {
+                    m.add_sent(len as u64);
+                }
+            }
+        };
+
+        tokio::select! {
+            result = to_local => result,
+            result = to_stream => result,
+        }
+    };
+    let _ = stream_write.close().await;
+    if let Some(ref t) = tracker {
+        t.target_connection_ended(target);
+        t.connection_ended();
+    }
+    info!("UDP stream closed for proxy '{}'", proxy.name);
+    result.map_err(Into::into)
+}
+
+/// 绑定与目标地址族相同的临时端口并连接到本地服务（只接收该服务的数据报）
+async fn connect_local(target: &str) -> io::Result<UdpSocket> {
+    let addr = tokio::net::lookup_host(target)
+        .await?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No address resolved"))?;
+    let bind: SocketAddr = match addr {
+        SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+        SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+    };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(addr).await?;
+    Ok(socket)
+}
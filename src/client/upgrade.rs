@@ -0,0 +1,353 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+use super::stats::ClientStatsTracker;
+use crate::config::ProxyConfig;
+use crate::keepalive::IdleClock;
+
+/// 响应头的最大长度（超出后不再检测该连接）
+const MAX_RESPONSE_HEAD: usize = 16 * 1024;
+
+/// HTTP/1.1 本地服务响应的协议升级检测
+///
+/// 按响应头和 Content-Length 跟踪消息边界：遇到 `101 Switching Protocols` 且
+/// `Upgrade: websocket` 的响应时连接切换为纯转发。分块编码或以关闭连接结束的响应体无法确定
+/// 下一个响应的位置，之后不再检测
+#[derive(Debug, Default)]
+pub(crate) struct UpgradeDetector {
+    state: DetectState,
+    head: Vec<u8>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum DetectState {
+    /// 读取响应头
+    #[default]
+    Head,
+    /// 跳过响应体的剩余字节
+    Body(u64),
+    /// 已升级为 WebSocket
+    Upgraded,
+    /// 无法确定消息边界，停止检测
+    Stopped,
+}
+
+impl UpgradeDetector {
+    /// 处理本地服务发出的数据，返回连接是否已升级
+    pub(crate) fn feed(&mut self, mut data: &[u8]) -> bool {
+        while !data.is_empty() {
+            match self.state {
+                DetectState::Upgraded | DetectState::Stopped => break,
+                DetectState::Body(remaining) => {
+                    let n = remaining.min(data.len() as u64);
+                    data = &data[n as usize..];
+                    self.state = match remaining - n {
+                        0 => DetectState::Head,
+                        left => DetectState::Body(left),
+                    };
+                }
+                DetectState::Head => {
+                    // 头部结束标记可能跨越两次读取，从上次末尾的前 3 个字节开始查找
+                    let scanned = self.head.len().saturating_sub(3);
+                    let take = data.len().min(MAX_RESPONSE_HEAD + 4 - self.head.len());
+                    self.head.extend_from_slice(&data[..take]);
+                    match find_head_end(&self.head[scanned..]) {
+                        Some(end) => {
+                            let end = scanned + end;
+                            let consumed = take - (self.head.len() - end);
+                            data = &data[consumed..];
+                            self.state = parse_response_head(&self.head[..end]);
+                            self.head.clear();
+                        }
+                        None if self.head.len() > MAX_RESPONSE_HEAD => {
+                            self.state = DetectState::Stopped;
+                        }
+                        None => data = &data[take..],
+                    }
+                }
+            }
+        }
+        self.is_upgraded()
+    }
+
+    /// 连接是否已升级
+    pub(crate) fn is_upgraded(&self) -> bool {
+        self.state == DetectState::Upgraded
+    }
+
+    /// 是否已停止检测（已升级或无法确定消息边界）
+    fn is_finished(&self) -> bool {
+        matches!(self.state, DetectState::Upgraded | DetectState::Stopped)
+    }
+}
+
+/// 查找头部结束标记，返回包含标记的头部长度
+fn find_head_end(data: &[u8]) -> Option<usize> {
+    data.windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|pos| pos + 4)
+}
+
+/// 解析一个响应头，返回之后的检测状态
+fn parse_response_head(head: &[u8]) -> DetectState {
+    let Ok(head) = std::str::from_utf8(head) else {
+        return DetectState::Stopped;
+    };
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.strip_prefix("HTTP/1."))
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|code| code.parse::<u16>().ok());
+    let Some(status) = status else {
+        return DetectState::Stopped;
+    };
+
+    let mut upgrade_websocket = false;
+    let mut content_length = None;
+    let mut chunked = false;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "upgrade" => {
+                upgrade_websocket = value
+                    .split(',')
+                    .any(|p| p.trim().eq_ignore_ascii_case("websocket"));
+            }
+            "content-length" => content_length = value.parse::<u64>().ok(),
+            "transfer-encoding" => chunked = true,
+            _ => {}
+        }
+    }
+
+    match status {
+        101 if upgrade_websocket => DetectState::Upgraded,
+        // 升级到其他协议后的数据不再是 HTTP 消息
+        101 => DetectState::Stopped,
+        // 其他 1xx、204 和 304 响应没有响应体
+        100..=199 | 204 | 304 => DetectState::Head,
+        _ if chunked => DetectState::Stopped,
+        _ => match content_length {
+            Some(0) => DetectState::Head,
+            Some(len) => DetectState::Body(len),
+            None => DetectState::Stopped,
+        },
+    }
+}
+
+/// 到本地服务的一条连接的升级状态（两个转发方向共用）
+pub(crate) struct UpgradeState {
+    /// 代理类型是否检测协议升级
+    enabled: bool,
+    upgraded: AtomicBool,
+    /// 升级后的空闲超时（代理的 `ws_idle_timeout_secs`）
+    idle_timeout: Option<Duration>,
+    clock: IdleClock,
+    tracker: Option<ClientStatsTracker>,
+}
+
+impl UpgradeState {
+    /// 按代理配置创建（只有 HTTP/1.1 代理检测升级）
+    pub(crate) fn new(proxy: &ProxyConfig, tracker: Option<ClientStatsTracker>) -> Arc<Self> {
+        Arc::new(Self {
+            enabled: proxy.proxy_type.detects_upgrades(),
+            upgraded: AtomicBool::new(false),
+            idle_timeout: proxy.ws_idle_timeout_secs.map(Duration::from_secs),
+            clock: IdleClock::new(),
+            tracker,
+        })
+    }
+
+    /// 连接是否已升级（升级的连接之后只做纯转发，不归还连接池）
+    pub(crate) fn is_upgraded(&self) -> bool {
+        self.upgraded.load(Ordering::Relaxed)
+    }
+
+    fn mark_upgraded(&self) {
+        if !self.upgraded.swap(true, Ordering::Relaxed) {
+            if let Some(tracker) = &self.tracker {
+                tracker.record_upgraded_connection();
+            }
+        }
+    }
+}
+
+/// 到本地服务的连接（或其读写半边）的包装：检测读到的响应中的协议升级，
+/// 并在升级后两个方向都空闲超过 `ws_idle_timeout_secs` 时让读取返回超时错误
+pub(crate) struct UpgradeWatch<S> {
+    inner: S,
+    state: Arc<UpgradeState>,
+    detector: Option<UpgradeDetector>,
+    idle_sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> UpgradeWatch<S> {
+    /// 包装连接（代理类型不检测升级时只记录活动时间）
+    pub(crate) fn new(inner: S, state: &Arc<UpgradeState>) -> Self {
+        Self {
+            inner,
+            detector: (state.enabled && !state.is_upgraded()).then(UpgradeDetector::default),
+            state: state.clone(),
+            idle_sleep: None,
+        }
+    }
+
+    /// 升级后的空闲超时检查：两个方向都空闲超过超时时间时返回错误
+    fn poll_idle(&mut self, cx: &mut Context<'_>) -> Poll<io::Error> {
+        let Some(idle) = self.state.idle_timeout else {
+            return Poll::Pending;
+        };
+        if !self.state.is_upgraded() {
+            return Poll::Pending;
+        }
+        loop {
+            let deadline = self.state.clock.last_activity() + idle;
+            if Instant::now() >= deadline {
+                return Poll::Ready(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "WebSocket idle timeout",
+                ));
+            }
+            let sleep = self
+                .idle_sleep
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(deadline)));
+            if sleep.deadline() != deadline {
+                sleep.as_mut().reset(deadline);
+            }
+            futures::ready!(sleep.as_mut().poll(cx));
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for UpgradeWatch<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let filled = buf.filled().len();
+        match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) => {
+                let data = &buf.filled()[filled..];
+                if !data.is_empty() {
+                    this.state.clock.touch();
+                    if let Some(detector) = &mut this.detector {
+                        if detector.feed(data) {
+                            this.state.mark_upgraded();
+                        }
+                        if detector.is_finished() {
+                            this.detector = None;
+                        }
+                    }
+                }
+                Poll::Ready(Ok(()))
+            }
+            Poll::Pending => this.poll_idle(cx).map(Err),
+            result => result,
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for UpgradeWatch<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            if n > 0 {
+                self.state.clock.touch();
+            }
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPGRADE: &[u8] = b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+        Connection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n";
+
+    #[test]
+    fn test_detects_websocket_upgrade() {
+        let mut detector = UpgradeDetector::default();
+        assert!(detector.feed(UPGRADE));
+        // 升级后的数据不再解析
+        assert!(detector.feed(b"\x81\x05hello"));
+    }
+
+    #[test]
+    fn test_detects_upgrade_split_across_reads() {
+        let mut detector = UpgradeDetector::default();
+        for chunk in UPGRADE.chunks(3) {
+            detector.feed(chunk);
+        }
+        assert!(detector.is_upgraded());
+    }
+
+    #[test]
+    fn test_detects_upgrade_after_keep_alive_responses() {
+        let mut detector = UpgradeDetector::default();
+        let mut data = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello".to_vec();
+        data.extend_from_slice(b"HTTP/1.1 304 Not Modified\r\n\r\n");
+        data.extend_from_slice(b"HTTP/1.1 100 Continue\r\n\r\n");
+        data.extend_from_slice(UPGRADE);
+        assert!(detector.feed(&data));
+    }
+
+    #[test]
+    fn test_body_resembling_upgrade_is_skipped() {
+        let mut detector = UpgradeDetector::default();
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+            UPGRADE.len()
+        );
+        assert!(!detector.feed(head.as_bytes()));
+        assert!(!detector.feed(UPGRADE));
+        assert_eq!(detector.state, DetectState::Head);
+    }
+
+    #[test]
+    fn test_stops_without_message_boundaries() {
+        // 分块编码和以关闭连接结束的响应体之后无法确定下一个响应的位置
+        for head in [
+            &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n"[..],
+            b"HTTP/1.0 200 OK\r\n\r\n",
+            b"SSH-2.0-OpenSSH_9.6\r\n\r\n",
+        ] {
+            let mut detector = UpgradeDetector::default();
+            assert!(!detector.feed(head));
+            assert!(!detector.feed(UPGRADE));
+            assert!(detector.is_finished());
+        }
+    }
+
+    #[test]
+    fn test_non_websocket_upgrade_is_not_counted() {
+        let mut detector = UpgradeDetector::default();
+        assert!(!detector.feed(b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: h2c\r\n\r\n"));
+        assert!(detector.is_finished());
+    }
+}
@@ -1,41 +1,83 @@
-use crate::config::{ProxyType, VisitorConfig};
+use crate::accept::{AcceptBackoff, AcceptErrorKind};
+use crate::config::{ClientTimeoutsConfig, ProxyType, VisitorConfig};
+use crate::io_util::linger;
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use std::sync::Arc;
+use thiserror::Error;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpListener;
-use tokio::time::{sleep, Duration};
-use tokio_util::compat::FuturesAsyncReadCompatExt;
+use tokio::time::{Duration, Instant};
+use tokio_util::compat::{Compat, FuturesAsyncReadCompatExt};
 use tracing::{error, info, warn};
 
-use super::config::read_error_message;
+use super::config::{read_error_message, write_stream_preamble};
+use super::listeners::bind_listener;
+use super::stats::{ClientStatsTracker, StreamFailure};
+use super::visitor_mux::MuxSession;
 use super::ProxyHandler;
+use crate::identity;
+use crate::protocol::{PeerIdentity, VISITOR_MUX_NAME_PREFIX};
+use crate::protocol_trace::{CurrentTrace, SessionTrace, TraceDirection};
+
+/// 服务器拒绝了 visitor stream（目标代理不存在、未连接或不允许访问）
+#[derive(Debug, Error)]
+#[error("Server rejected visitor connection: {0}")]
+pub struct ServerRejected(pub String);
 
 /// 运行 visitor 监听器
 /// 在客户端本地监听端口，接受连接后通过 yamux 连接到服务器
+///
+/// `peer_identity` 为与服务器协商的 proxy 注册者身份校验方式；
+/// `visitor_mux` 表示服务器是否支持 visitor 连接复用，
+/// 两者都满足且配置了 `connection_reuse` 时所有本地连接共享一条 stream；
+/// `stream_open` 为等待服务器确认 stream 的超时，打开的 stream 前导和确认记录到 `trace`
+#[allow(clippy::too_many_arguments)]
 pub async fn run_visitor_listener(
     visitor: VisitorConfig,
     stream_tx: tokio::sync::mpsc::Sender<tokio::sync::oneshot::Sender<Result<yamux::Stream>>>,
+    peer_identity: PeerIdentity,
+    visitor_mux: bool,
+    stream_open: Duration,
+    tracker: Option<ClientStatsTracker>,
+    trace: CurrentTrace,
     mut shutdown_rx: tokio::sync::broadcast::Receiver<()>,
 ) -> Result<()> {
-    let bind_addr = format!("{}:{}", visitor.bind_addr, visitor.bind_port);
+    let bind_addr = crate::listen_addr::format(&visitor.bind_addr, visitor.bind_port);
 
     info!(
         "Visitor '{}': Binding to {} -> proxy name '{}' port {}",
         visitor.name, visitor.bind_addr, visitor.name, visitor.publish_port
     );
 
-    let listener = TcpListener::bind(&bind_addr)
-        .await
-        .with_context(|| format!("Failed to bind visitor to {}", bind_addr))?;
+    let listener = bind_listener(
+        &visitor.bind_addr,
+        visitor.bind_port,
+        &format!("Visitor '{}'", visitor.name),
+    )
+    .await
+    .with_context(|| format!("Failed to bind visitor to {}", bind_addr))?;
 
     info!("Visitor '{}': Listening on {}", visitor.name, bind_addr);
 
+    let tunnel = if visitor.connection_reuse && !visitor_mux {
+        warn!(
+            "Visitor '{}': Server does not support connection reuse, each connection opens its own stream",
+            visitor.name
+        );
+        None
+    } else {
+        visitor
+            .connection_reuse
+            .then(|| Arc::new(MuxTunnel::default()))
+    };
+
+    let mut backoff = AcceptBackoff::new(format!("Visitor '{}'", visitor.name));
     loop {
         tokio::select! {
             accept_result = listener.accept() => {
                 match accept_result {
                     Ok((local_stream, peer_addr)) => {
+                        backoff.reset();
                         info!(
                             "Visitor '{}': Accepted connection from {}",
                             visitor.name, peer_addr
@@ -43,12 +85,48 @@ pub async fn run_visitor_listener(
 
                         let visitor_clone = visitor.clone();
                         let stream_tx_clone = stream_tx.clone();
+                        let tunnel = tunnel.clone();
+                        let tracker = tracker.clone();
+                        let trace = trace.get();
 
                         tokio::spawn(async move {
-                            if let Err(e) =
-                                handle_visitor_connection(local_stream, &visitor_clone, stream_tx_clone)
+                            if let Some(ref t) = tracker {
+                                t.connection_started();
+                            }
+                            let result = match tunnel {
+                                Some(tunnel) => {
+                                    handle_reused_connection(
+                                        local_stream,
+                                        &visitor_clone,
+                                        &tunnel,
+                                        stream_tx_clone,
+                                        peer_identity,
+                                        stream_open,
+                                        tracker.as_ref(),
+                                        &trace,
+                                    )
                                     .await
-                            {
+                                }
+                                None => {
+                                    if let Some(ref t) = tracker {
+                                        t.record_tunnel_stream();
+                                    }
+                                    handle_visitor_connection(
+                                        local_stream,
+                                        &visitor_clone,
+                                        stream_tx_clone,
+                                        peer_identity,
+                                        stream_open,
+                                        tracker.as_ref(),
+                                        &trace,
+                                    )
+                                    .await
+                                }
+                            };
+                            if let Some(ref t) = tracker {
+                                t.connection_ended();
+                            }
+                            if let Err(e) = result {
                                 error!(
                                     "Visitor '{}' connection handling error: {}",
                                     visitor_clone.name, e
@@ -57,8 +135,15 @@ pub async fn run_visitor_listener(
                         });
                     }
                     Err(e) => {
-                        error!("Visitor '{}': Accept error: {}", visitor.name, e);
-                        sleep(Duration::from_millis(100)).await;
+                        let kind = AcceptErrorKind::of_io(&e);
+                        if kind == AcceptErrorKind::Resource {
+                            if let Some(ref t) = tracker {
+                                t.record_accept_error();
+                            }
+                        }
+                        if !backoff.on_error(kind, &e).await {
+                            break Err(e).with_context(|| format!("Visitor '{}' listener stopped", visitor.name));
+                        }
                     }
                 }
             }
@@ -71,68 +156,183 @@ pub async fn run_visitor_listener(
     }
 }
 
-/// 处理 visitor 连接
-/// 创建 yamux stream 到服务器，发送目标 proxy 名称，然后双向转发数据
-pub async fn handle_visitor_connection(
-    mut local_stream: tokio::net::TcpStream,
-    visitor: &VisitorConfig,
-    stream_tx: tokio::sync::mpsc::Sender<tokio::sync::oneshot::Sender<Result<yamux::Stream>>>,
+/// 校验 proxy 注册者的 peer_id
+///
+/// 未设置 `expected` 时总是通过；设置后要求服务器支持 peer_identity 能力且 peer_id 完全一致
+pub fn verify_peer_id(
+    expected: Option<&str>,
+    actual: Option<&str>,
+    peer_identity: bool,
 ) -> Result<()> {
-    // 为需要低延迟的代理类型（如 SSH）启用 TCP_NODELAY
-    if visitor.proxy_type.needs_nodelay() {
-        if let Err(e) = local_stream.set_nodelay(true) {
-            tracing::warn!(
-                "Visitor '{}': Failed to set TCP_NODELAY: {}",
-                visitor.name,
-                e
-            );
-        } else {
-            tracing::debug!(
-                "Visitor '{}': Enabled TCP_NODELAY (proxy type: {:?})",
-                visitor.name,
-                visitor.proxy_type
-            );
-        }
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+    if !peer_identity {
+        anyhow::bail!(
+            "expected_peer_id '{}' is set but the server does not support peer identity verification",
+            expected
+        );
+    }
+    match actual {
+        Some(actual) if actual == expected => Ok(()),
+        Some(actual) => anyhow::bail!(
+            "Peer identity mismatch: expected '{}', proxy registered by '{}'",
+            expected,
+            actual
+        ),
+        None => anyhow::bail!(
+            "Peer identity mismatch: expected '{}', proxy registered without peer_id",
+            expected
+        ),
     }
+}
 
-    // 请求创建新的 yamux stream
-    let (response_tx, response_rx) = tokio::sync::oneshot::channel();
-    stream_tx
-        .send(response_tx)
-        .await
-        .context("Failed to request yamux stream")?;
+/// 校验 proxy 注册者身份
+///
+/// `expected` 为身份指纹（`SHA256:...`）时与注册者的身份指纹比较，要求服务器支持 client_identity 能力；
+/// 其他值按 [`verify_peer_id`] 与注册者的 peer_id 比较
+pub fn verify_registrant(
+    expected: Option<&str>,
+    peer_id: Option<&str>,
+    fingerprint: Option<&str>,
+    peer_identity: PeerIdentity,
+) -> Result<()> {
+    let Some(expected) = expected.filter(|e| identity::is_fingerprint(e)) else {
+        return verify_peer_id(expected, peer_id, peer_identity.sends_peer_id());
+    };
+    if !peer_identity.sends_fingerprint() {
+        anyhow::bail!(
+            "expected_peer_id '{}' is a key fingerprint but the server does not support client identity verification",
+            expected
+        );
+    }
+    match fingerprint {
+        Some(actual) if identity::fingerprint_eq(actual, expected) => Ok(()),
+        Some(actual) => anyhow::bail!(
+            "Peer identity mismatch: expected '{}', proxy registered by key '{}'",
+            expected,
+            actual
+        ),
+        None => anyhow::bail!(
+            "Peer identity mismatch: expected '{}', proxy registered without a client identity",
+            expected
+        ),
+    }
+}
 
-    // 等待 yamux stream 创建完成
-    let server_stream = response_rx
-        .await
-        .context("Failed to receive yamux stream")??;
+/// 服务器是否能提供校验 `expected` 所需的注册者身份
+fn can_verify(expected: Option<&str>, peer_identity: PeerIdentity) -> bool {
+    match expected {
+        Some(expected) if identity::is_fingerprint(expected) => peer_identity.sends_fingerprint(),
+        Some(_) => peer_identity.sends_peer_id(),
+        None => true,
+    }
+}
 
-    info!(
-        "Visitor '{}': Opened stream to server for proxy '{}' port {}",
-        visitor.name, visitor.name, visitor.publish_port
+/// 读取服务器确认帧中携带的 peer_id 或身份指纹（u16 长度 + 内容，长度为 0 表示未配置）
+async fn read_peer_id<T>(stream: &mut T) -> Result<Option<String>>
+where
+    T: AsyncReadExt + Unpin,
+{
+    let mut len_buf = [0u8; 2];
+    stream.read_exact(&mut len_buf).await?;
+    let len = u16::from_be_bytes(len_buf) as usize;
+    if len == 0 {
+        return Ok(None);
+    }
+
+    let mut id_buf = vec![0u8; len];
+    stream.read_exact(&mut id_buf).await?;
+    Ok(Some(
+        String::from_utf8(id_buf).context("Invalid UTF-8 in peer_id")?,
+    ))
+}
+
+/// 按 visitor 的套接字选项配置本地接入的连接
+fn configure_local_stream(local_stream: &tokio::net::TcpStream, visitor: &VisitorConfig) {
+    crate::socket_options::configure(
+        local_stream,
+        visitor.socket.as_ref(),
+        &format!("Visitor '{}'", visitor.name),
     );
+}
+
+/// 打开到目标 proxy 的 visitor stream
+///
+/// 发送前导并等待服务器确认，协商了 peer_identity 能力时校验 proxy 注册者身份（peer_id 或身份指纹）；
+/// `mux` 为 true 时请求复用模式的 stream，等待确认超过 `stream_open` 时失败。失败按类别记录到 `tracker`，
+/// 服务器拒绝时返回 [`ServerRejected`]
+pub(super) async fn open_visitor_stream(
+    visitor: &VisitorConfig,
+    stream_tx: &tokio::sync::mpsc::Sender<tokio::sync::oneshot::Sender<Result<yamux::Stream>>>,
+    peer_identity: PeerIdentity,
+    mux: bool,
+    stream_open: Duration,
+    tracker: Option<&ClientStatsTracker>,
+    trace: &SessionTrace,
+) -> Result<Compat<yamux::Stream>> {
+    let record_failure = |kind: StreamFailure, e: &anyhow::Error| {
+        if let Some(t) = tracker {
+            t.record_failure(StreamFailure::classify(e, kind), e);
+        }
+    };
 
-    // 将 yamux stream 转换为兼容的 tokio stream
-    let mut server_stream_tokio = server_stream.compat();
+    // 服务器不支持所需的身份校验时，设置了 expected_peer_id 的 visitor 直接拒绝
+    let expected = visitor.expected_peer_id.as_deref();
+    if !can_verify(expected, peer_identity) {
+        if let Err(e) = verify_registrant(expected, None, None, peer_identity) {
+            error!("Visitor '{}': {}", visitor.name, e);
+            return Err(e);
+        }
+    }
 
-    // 发送目标 proxy 名称长度、名称和 publish_port
-    let name_bytes = visitor.name.as_bytes();
-    let name_len = (name_bytes.len() as u16).to_be_bytes();
-    server_stream_tokio.write_all(&name_len).await?;
-    server_stream_tokio.write_all(name_bytes).await?;
+    // 请求创建新的 yamux stream 并发送前导
+    let target_name = if mux {
+        format!("{}{}", VISITOR_MUX_NAME_PREFIX, visitor.name)
+    } else {
+        visitor.name.clone()
+    };
+    let (mut server_stream_tokio, stream_trace) = async {
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+        stream_tx
+            .send(response_tx)
+            .await
+            .context("Failed to request yamux stream")?;
+
+        // 等待 yamux stream 创建完成
+        let server_stream = response_rx
+            .await
+            .context("Failed to receive yamux stream")??;
+
+        info!(
+            "Visitor '{}': Opened stream to server for proxy '{}' port {}",
+            visitor.name, visitor.name, visitor.publish_port
+        );
 
-    let port_bytes = visitor.publish_port.to_be_bytes();
-    server_stream_tokio.write_all(&port_bytes).await?;
-    server_stream_tokio.flush().await?;
+        // 将 yamux stream 转换为兼容的 tokio stream
+        let stream_trace = trace.stream(server_stream.id());
+        let mut server_stream_tokio = server_stream.compat();
+
+        // 发送目标 proxy 名称长度、名称和 publish_port
+        write_stream_preamble(&mut server_stream_tokio, &target_name, visitor.publish_port).await?;
+        stream_trace.preamble(TraceDirection::Out, &target_name, visitor.publish_port);
+        Ok((server_stream_tokio, stream_trace))
+    }
+    .await
+    .inspect_err(|e| record_failure(StreamFailure::StreamOpen, e))?;
 
     info!(
         "Visitor '{}': Sent target proxy name '{}' port {}",
-        visitor.name, visitor.name, visitor.publish_port
+        visitor.name, target_name, visitor.publish_port
     );
 
     // 等待服务器确认（1 字节：1=成功，0=失败）
     let mut confirm = [0u8; 1];
-    server_stream_tokio.read_exact(&mut confirm).await?;
+    tokio::time::timeout(stream_open, server_stream_tokio.read_exact(&mut confirm))
+        .await
+        .context("Timeout waiting for server confirmation")
+        .and_then(|result| result.context("Failed to read server confirmation"))
+        .inspect_err(|e| record_failure(StreamFailure::StreamOpen, e))?;
 
     if confirm[0] != 1 {
         // 读取错误消息
@@ -140,16 +340,73 @@ pub async fn handle_visitor_connection(
             Ok(msg) => msg,
             Err(_) => "Unknown error".to_string(),
         };
+        stream_trace.confirm(TraceDirection::In, false, Some(&error_msg));
         error!(
             "Visitor '{}': Server rejected connection: {}",
             visitor.name, error_msg
         );
-        return Err(anyhow::anyhow!(
-            "Server rejected visitor connection: {}",
-            error_msg
-        ));
+        if let Some(t) = tracker {
+            t.record_failure(StreamFailure::ServerRejected, &error_msg);
+        }
+        return Err(ServerRejected(error_msg).into());
+    }
+    stream_trace.confirm(TraceDirection::In, true, None);
+
+    // 校验 proxy 注册者身份，并将结果回复给服务器（1=接受，0=拒绝）
+    if peer_identity.sends_peer_id() {
+        let peer_id = read_peer_id(&mut server_stream_tokio).await?;
+        let fingerprint = if peer_identity.sends_fingerprint() {
+            read_peer_id(&mut server_stream_tokio).await?
+        } else {
+            None
+        };
+        let verdict = verify_registrant(
+            expected,
+            peer_id.as_deref(),
+            fingerprint.as_deref(),
+            peer_identity,
+        );
+        server_stream_tokio
+            .write_all(&[verdict.is_ok() as u8])
+            .await?;
+        server_stream_tokio.flush().await?;
+
+        if let Err(e) = verdict {
+            error!(
+                "Visitor '{}': Refusing stream to proxy '{}' port {}: {}",
+                visitor.name, visitor.name, visitor.publish_port, e
+            );
+            return Err(e);
+        }
     }
 
+    Ok(server_stream_tokio)
+}
+
+/// 处理 visitor 连接
+/// 创建 yamux stream 到服务器，发送目标 proxy 名称，然后双向转发数据
+pub async fn handle_visitor_connection(
+    mut local_stream: tokio::net::TcpStream,
+    visitor: &VisitorConfig,
+    stream_tx: tokio::sync::mpsc::Sender<tokio::sync::oneshot::Sender<Result<yamux::Stream>>>,
+    peer_identity: PeerIdentity,
+    stream_open: Duration,
+    tracker: Option<&ClientStatsTracker>,
+    trace: &SessionTrace,
+) -> Result<()> {
+    configure_local_stream(&local_stream, visitor);
+
+    let server_stream_tokio = open_visitor_stream(
+        visitor,
+        &stream_tx,
+        peer_identity,
+        false,
+        stream_open,
+        tracker,
+        trace,
+    )
+    .await?;
+
     info!(
         "Visitor '{}': Server accepted connection, starting data transfer",
         visitor.name
@@ -171,29 +428,159 @@ pub async fn handle_visitor_connection(
         Ok::<_, std::io::Error>(())
     };
 
+    // 一个方向结束（已关闭写方向）后，等待另一方向读到对端的 EOF 再丢弃连接
+    tokio::pin!(client_to_server, server_to_client);
     tokio::select! {
-        result = client_to_server => {
-            if let Err(e) = result {
-                warn!("Visitor '{}': Client to server copy error: {}", visitor.name, e);
+        result = &mut client_to_server => match result {
+            Ok(()) => {
+                linger(&mut server_to_client).await;
             }
-        }
-        result = server_to_client => {
-            if let Err(e) = result {
-                warn!("Visitor '{}': Server to client copy error: {}", visitor.name, e);
+            Err(e) => warn!("Visitor '{}': Client to server copy error: {}", visitor.name, e),
+        },
+        result = &mut server_to_client => match result {
+            Ok(()) => {
+                linger(&mut client_to_server).await;
             }
-        }
+            Err(e) => warn!("Visitor '{}': Server to client copy error: {}", visitor.name, e),
+        },
     }
 
     info!("Visitor '{}': Connection closed", visitor.name);
     Ok(())
 }
 
+/// 复用通道建立失败后，退回每个连接一条 stream 的时长
+const MUX_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// `connection_reuse` 模式下 visitor 的所有本地连接共享的复用通道
+#[derive(Default)]
+struct MuxTunnel {
+    state: tokio::sync::Mutex<MuxTunnelState>,
+}
+
+#[derive(Default)]
+struct MuxTunnelState {
+    session: Option<Arc<MuxSession>>,
+    /// 在此之前不再尝试建立复用通道
+    fallback_until: Option<Instant>,
+}
+
+impl MuxTunnel {
+    /// 获取可用的复用通道（断开后重新建立），处于退回期间返回 None
+    async fn session(
+        &self,
+        visitor: &VisitorConfig,
+        stream_tx: &tokio::sync::mpsc::Sender<tokio::sync::oneshot::Sender<Result<yamux::Stream>>>,
+        peer_identity: PeerIdentity,
+        stream_open: Duration,
+        tracker: Option<&ClientStatsTracker>,
+        trace: &SessionTrace,
+    ) -> Option<Arc<MuxSession>> {
+        let mut state = self.state.lock().await;
+        if let Some(session) = state.session.as_ref().filter(|s| !s.is_closed()) {
+            return Some(session.clone());
+        }
+        if state.fallback_until.is_some_and(|t| Instant::now() < t) {
+            return None;
+        }
+
+        let opened = open_visitor_stream(
+            visitor,
+            stream_tx,
+            peer_identity,
+            true,
+            stream_open,
+            tracker,
+            trace,
+        )
+        .await;
+        match opened {
+            Ok(stream) => {
+                info!(
+                    "Visitor '{}': Established connection reuse tunnel",
+                    visitor.name
+                );
+                if let Some(t) = tracker {
+                    t.record_tunnel_stream();
+                }
+                let (session, _) = MuxSession::start(stream);
+                let session = Arc::new(session);
+                state.session = Some(session.clone());
+                state.fallback_until = None;
+                Some(session)
+            }
+            Err(e) => {
+                warn!(
+                    "Visitor '{}': Connection reuse unavailable ({}), using one stream per connection for {:?}",
+                    visitor.name, e, MUX_RETRY_INTERVAL
+                );
+                state.session = None;
+                state.fallback_until = Some(Instant::now() + MUX_RETRY_INTERVAL);
+                None
+            }
+        }
+    }
+}
+
+/// 处理 `connection_reuse` 模式的 visitor 连接：作为子流在共享的复用通道上转发
+#[allow(clippy::too_many_arguments)]
+async fn handle_reused_connection(
+    mut local_stream: tokio::net::TcpStream,
+    visitor: &VisitorConfig,
+    tunnel: &MuxTunnel,
+    stream_tx: tokio::sync::mpsc::Sender<tokio::sync::oneshot::Sender<Result<yamux::Stream>>>,
+    peer_identity: PeerIdentity,
+    stream_open: Duration,
+    tracker: Option<&ClientStatsTracker>,
+    trace: &SessionTrace,
+) -> Result<()> {
+    let Some(session) = tunnel
+        .session(
+            visitor,
+            &stream_tx,
+            peer_identity,
+            stream_open,
+            tracker,
+            trace,
+        )
+        .await
+    else {
+        if let Some(t) = tracker {
+            t.record_tunnel_stream();
+        }
+        return handle_visitor_connection(
+            local_stream,
+            visitor,
+            stream_tx,
+            peer_identity,
+            stream_open,
+            tracker,
+            trace,
+        )
+        .await;
+    };
+
+    configure_local_stream(&local_stream, visitor);
+    let substream = session.open().await?;
+    let id = substream.id();
+    let meter = tracker.map(|t| t.meter());
+    let result = substream.relay(&mut local_stream, meter.as_ref()).await;
+    match &result {
+        Ok(()) => info!("Visitor '{}': Substream {} closed", visitor.name, id),
+        Err(e) => warn!("Visitor '{}': Substream {} error: {}", visitor.name, id, e),
+    }
+    result
+}
+
 /// Visitor 处理器（实现 ProxyHandler trait）
 pub struct VisitorHandler {
     config: VisitorConfig,
     stream_tx: tokio::sync::mpsc::Sender<tokio::sync::oneshot::Sender<Result<yamux::Stream>>>,
     status: Arc<tokio::sync::RwLock<crate::client::HandlerStatus>>,
     shutdown_tx: Arc<tokio::sync::RwLock<Option<tokio::sync::oneshot::Sender<()>>>>,
+    peer_identity: PeerIdentity,
+    visitor_mux: bool,
+    stream_open: Duration,
 }
 
 impl VisitorHandler {
@@ -208,8 +595,29 @@ impl VisitorHandler {
                 crate::client::HandlerStatus::Stopped,
             )),
             shutdown_tx: Arc::new(tokio::sync::RwLock::new(None)),
+            peer_identity: PeerIdentity::Unsupported,
+            visitor_mux: false,
+            stream_open: ClientTimeoutsConfig::default().stream_open(),
         }
     }
+
+    /// 设置与服务器协商的 proxy 注册者身份校验方式
+    pub fn with_peer_identity(mut self, peer_identity: PeerIdentity) -> Self {
+        self.peer_identity = peer_identity;
+        self
+    }
+
+    /// 设置服务器是否支持 visitor 连接复用
+    pub fn with_visitor_mux(mut self, visitor_mux: bool) -> Self {
+        self.visitor_mux = visitor_mux;
+        self
+    }
+
+    /// 设置等待服务器确认 stream 的超时
+    pub fn with_stream_open_timeout(mut self, stream_open: Duration) -> Self {
+        self.stream_open = stream_open;
+        self
+    }
 }
 
 #[async_trait]
@@ -239,7 +647,7 @@ impl ProxyHandler for VisitorHandler {
         let (listener_shutdown_tx, listener_shutdown_rx) = tokio::sync::broadcast::channel::<()>(1);
 
         tokio::select! {
-            result = run_visitor_listener(config, stream_tx, listener_shutdown_rx) => {
+            result = run_visitor_listener(config, stream_tx, self.peer_identity, self.visitor_mux, self.stream_open, None, CurrentTrace::default(), listener_shutdown_rx) => {
                 if let Err(e) = &result {
                     let mut s = status.write().await;
                     *s = crate::client::HandlerStatus::Failed(e.to_string());
@@ -296,6 +704,37 @@ impl ProxyHandler for VisitorHandler {
     }
 
     fn bind_address(&self) -> String {
-        format!("{}:{}", self.config.bind_addr, self.config.bind_port)
+        crate::listen_addr::format(&self.config.bind_addr, self.config.bind_port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_peer_id() {
+        // 未设置 expected_peer_id 时不校验
+        assert!(verify_peer_id(None, None, false).is_ok());
+        assert!(verify_peer_id(None, Some("tenant-a"), true).is_ok());
+
+        assert!(verify_peer_id(Some("tenant-a"), Some("tenant-a"), true).is_ok());
+        assert!(verify_peer_id(Some("tenant-a"), Some("tenant-b"), true).is_err());
+        assert!(verify_peer_id(Some("tenant-a"), None, true).is_err());
+
+        // 服务器不支持 peer_identity 能力时严格拒绝
+        assert!(verify_peer_id(Some("tenant-a"), Some("tenant-a"), false).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_peer_id() {
+        let mut data: &[u8] = &[0, 8, b't', b'e', b'n', b'a', b'n', b't', b'-', b'a'];
+        assert_eq!(
+            read_peer_id(&mut data).await.unwrap().as_deref(),
+            Some("tenant-a")
+        );
+
+        let mut empty: &[u8] = &[0, 0];
+        assert_eq!(read_peer_id(&mut empty).await.unwrap(), None);
     }
 }
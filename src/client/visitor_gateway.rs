@@ -0,0 +1,437 @@
+/// Visitor 网关：内置的 SOCKS5 → visitor 桥接
+///
+/// 本地应用通过同一个 SOCKS5 端口访问服务器上的任意代理：目标主机名 `name.<domain>`
+/// 选择代理名称，目标端口按 `port_policy` 匹配 publish_port 或忽略。每个连接按普通
+/// visitor 打开 stream（包括 peer_id 校验），打开结果映射为 SOCKS5 回复
+use crate::accept::{AcceptBackoff, AcceptErrorKind};
+use crate::config::{
+    ClientTimeoutsConfig, GatewayPortPolicy, ProxyType, VisitorConfig, VisitorGatewayConfig,
+};
+use crate::io_util::IdleTimeout;
+use crate::protocol::{PeerIdentity, ANY_PUBLISH_PORT};
+use crate::protocol_trace::{CurrentTrace, SessionTrace};
+use crate::traffic::TrafficMeter;
+use anyhow::{Context, Result};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::{error, info, warn};
+
+use super::forwarder::{copy_with_stats, relay_buffers};
+use super::listeners::bind_listener;
+use super::stats::ClientStatsTracker;
+use super::visitor::{open_visitor_stream, ServerRejected};
+
+/// 网关在日志和统计中使用的名称
+pub const GATEWAY_NAME: &str = "visitor_gateway";
+
+/// SOCKS5 回复码：成功
+const REP_SUCCEEDED: u8 = 0x00;
+/// SOCKS5 回复码：一般性失败
+const REP_GENERAL_FAILURE: u8 = 0x01;
+/// SOCKS5 回复码：主机不可达（未知的代理名称或服务器拒绝）
+const REP_HOST_UNREACHABLE: u8 = 0x04;
+/// SOCKS5 回复码：不支持的命令
+const REP_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+/// SOCKS5 回复码：不支持的地址类型
+const REP_ADDRESS_TYPE_NOT_SUPPORTED: u8 = 0x08;
+
+/// 运行 visitor 网关监听器
+///
+/// `peer_identity` 为与服务器协商的 proxy 注册者身份校验方式；`any_port` 表示服务器是否支持
+/// 不限发布端口的 visitor stream（`port_policy = "ignore"` 需要该能力，否则仍按端口匹配）；
+/// SOCKS5 解析、等待服务器确认和连接空闲的超时取自 `timeouts`
+#[allow(clippy::too_many_arguments)]
+pub async fn run_visitor_gateway(
+    gateway: VisitorGatewayConfig,
+    stream_tx: tokio::sync::mpsc::Sender<tokio::sync::oneshot::Sender<Result<yamux::Stream>>>,
+    peer_identity: PeerIdentity,
+    any_port: bool,
+    timeouts: ClientTimeoutsConfig,
+    tracker: Option<ClientStatsTracker>,
+    trace: CurrentTrace,
+    mut shutdown_rx: tokio::sync::broadcast::Receiver<()>,
+) -> Result<()> {
+    let bind_addr = crate::listen_addr::format(&gateway.bind_addr, gateway.bind_port);
+
+    info!(
+        "Visitor gateway: Binding to {} (SOCKS5, domain '{}')",
+        bind_addr, gateway.domain
+    );
+
+    let listener = bind_listener(&gateway.bind_addr, gateway.bind_port, "Visitor gateway")
+        .await
+        .with_context(|| format!("Failed to bind visitor gateway to {}", bind_addr))?;
+
+    info!("Visitor gateway: Listening on {}", bind_addr);
+
+    let any_port = match gateway.port_policy {
+        GatewayPortPolicy::Match => false,
+        GatewayPortPolicy::Ignore if !any_port => {
+            warn!("Visitor gateway: Server does not support port_policy 'ignore', target ports must match publish ports");
+            false
+        }
+        GatewayPortPolicy::Ignore => true,
+    };
+
+    let mut backoff = AcceptBackoff::new("Visitor gateway".to_string());
+    loop {
+        tokio::select! {
+            accept_result = listener.accept() => {
+                match accept_result {
+                    Ok((local_stream, peer_addr)) => {
+                        backoff.reset();
+                        info!("Visitor gateway: Accepted connection from {}", peer_addr);
+
+                        let gateway = gateway.clone();
+                        let stream_tx = stream_tx.clone();
+                        let tracker = tracker.clone();
+                        let trace = trace.get();
+
+                        tokio::spawn(async move {
+                            if let Some(ref t) = tracker {
+                                t.connection_started();
+                            }
+                            let result = handle_gateway_connection(
+                                local_stream,
+                                &gateway,
+                                stream_tx,
+                                peer_identity,
+                                any_port,
+                                &timeouts,
+                                tracker.as_ref(),
+                                &trace,
+                            )
+                            .await;
+                            if let Some(ref t) = tracker {
+                                t.connection_ended();
+                            }
+                            if let Err(e) = result {
+                                error!("Visitor gateway connection handling error: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        let kind = AcceptErrorKind::of_io(&e);
+                        if kind == AcceptErrorKind::Resource {
+                            if let Some(ref t) = tracker {
+                                t.record_accept_error();
+                            }
+                        }
+                        if !backoff.on_error(kind, &e).await {
+                            break Err(e).context("Visitor gateway listener stopped");
+                        }
+                    }
+                }
+            }
+            // 监听 shutdown 信号
+            _ = shutdown_rx.recv() => {
+                info!("Visitor gateway: Shutting down due to connection loss");
+                break Ok(());
+            }
+        }
+    }
+}
+
+/// 由网关配置和 SOCKS5 目标生成本次连接使用的 visitor 配置
+fn gateway_visitor(
+    gateway: &VisitorGatewayConfig,
+    name: &str,
+    port: u16,
+    any_port: bool,
+) -> VisitorConfig {
+    VisitorConfig {
+        name: name.to_string(),
+        proxy_type: ProxyType::Tcp,
+        bind_addr: gateway.bind_addr.clone(),
+        bind_port: gateway.bind_port,
+        publish_port: if any_port { ANY_PUBLISH_PORT } else { port },
+        expected_peer_id: gateway.expected_peer_ids.get(name).cloned(),
+        connection_reuse: false,
+        socket: gateway.socket.clone(),
+    }
+}
+
+/// 处理网关连接：解析 SOCKS5 请求，打开到目标代理的 visitor stream 后双向转发数据
+#[allow(clippy::too_many_arguments)]
+async fn handle_gateway_connection(
+    mut local_stream: TcpStream,
+    gateway: &VisitorGatewayConfig,
+    stream_tx: tokio::sync::mpsc::Sender<tokio::sync::oneshot::Sender<Result<yamux::Stream>>>,
+    peer_identity: PeerIdentity,
+    any_port: bool,
+    timeouts: &ClientTimeoutsConfig,
+    tracker: Option<&ClientStatsTracker>,
+    trace: &SessionTrace,
+) -> Result<()> {
+    crate::socket_options::configure(&local_stream, gateway.socket.as_ref(), "Visitor gateway");
+
+    let parse_timeout = timeouts.protocol_parse();
+    let (host, port) = tokio::time::timeout(parse_timeout, read_request(&mut local_stream))
+        .await
+        .map_err(|_| anyhow::anyhow!("SOCKS5 parsing timeout after {:?}", parse_timeout))??;
+
+    let Some(name) = gateway.proxy_name(&host) else {
+        send_reply(&mut local_stream, REP_HOST_UNREACHABLE).await?;
+        anyhow::bail!(
+            "Target host '{}' does not name a proxy under '.{}'",
+            host,
+            gateway.domain
+        );
+    };
+    let visitor = gateway_visitor(gateway, name, port, any_port);
+    info!(
+        "Visitor gateway: {}:{} -> proxy '{}' port {}",
+        host, port, visitor.name, visitor.publish_port
+    );
+
+    if let Some(t) = tracker {
+        t.target_connection_started(name);
+    }
+    let result = relay(
+        &mut local_stream,
+        &visitor,
+        &stream_tx,
+        peer_identity,
+        timeouts,
+        tracker,
+        trace,
+    )
+    .await;
+    if let Some(t) = tracker {
+        t.target_connection_ended(name);
+    }
+    result
+}
+
+/// 打开 visitor stream 并回复 SOCKS5 请求，成功后双向转发数据
+async fn relay(
+    local_stream: &mut TcpStream,
+    visitor: &VisitorConfig,
+    stream_tx: &tokio::sync::mpsc::Sender<tokio::sync::oneshot::Sender<Result<yamux::Stream>>>,
+    peer_identity: PeerIdentity,
+    timeouts: &ClientTimeoutsConfig,
+    tracker: Option<&ClientStatsTracker>,
+    trace: &SessionTrace,
+) -> Result<()> {
+    let opened = open_visitor_stream(
+        visitor,
+        stream_tx,
+        peer_identity,
+        false,
+        timeouts.stream_open(),
+        tracker,
+        trace,
+    )
+    .await;
+    let server_stream = match opened {
+        Ok(stream) => stream,
+        Err(e) => {
+            if let Some(t) = tracker {
+                t.record_target_failure(&visitor.name);
+            }
+            let rep = if e.is::<ServerRejected>() {
+                REP_HOST_UNREACHABLE
+            } else {
+                REP_GENERAL_FAILURE
+            };
+            send_reply(local_stream, rep).await.ok();
+            return Err(e);
+        }
+    };
+    send_reply(local_stream, REP_SUCCEEDED).await?;
+
+    info!(
+        "Visitor gateway: Server accepted connection to proxy '{}', starting data transfer",
+        visitor.name
+    );
+
+    let name = visitor.name.as_str();
+    let (mut local_read, mut local_write) = local_stream.split();
+    let (mut server_read, mut server_write) = tokio::io::split(server_stream);
+    let (mut up_buf, mut down_buf) = relay_buffers(tracker).await;
+
+    let meter = tracker.map(|t| t.target_meter(name, false));
+    let idle = &IdleTimeout::new(timeouts.connection_idle());
+    let client_to_server = async {
+        copy_with_stats(
+            &mut local_read,
+            &mut server_write,
+            &mut up_buf,
+            idle,
+            meter.as_ref(),
+            TrafficMeter::add_sent,
+        )
+        .await?;
+        server_write.shutdown().await
+    };
+
+    let server_to_client = async {
+        copy_with_stats(
+            &mut server_read,
+            &mut local_write,
+            &mut down_buf,
+            idle,
+            meter.as_ref(),
+            TrafficMeter::add_received,
+        )
+        .await?;
+        local_write.shutdown().await
+    };
+
+    // 使用 tokio::join! 确保两个方向的流量都被记录
+    let (result_c2s, result_s2c) = tokio::join!(client_to_server, server_to_client);
+    if let Err(e) = result_c2s {
+        warn!(
+            "Visitor gateway: Client to proxy '{}' copy error: {}",
+            name, e
+        );
+    }
+    if let Err(e) = result_s2c {
+        warn!(
+            "Visitor gateway: Proxy '{}' to client copy error: {}",
+            name, e
+        );
+    }
+
+    info!("Visitor gateway: Connection to proxy '{}' closed", name);
+    Ok(())
+}
+
+/// 读取 SOCKS5 握手（仅无认证）和 CONNECT 请求，返回目标主机名和端口
+///
+/// 不支持的命令或 IP 地址目标回复对应的错误码后返回错误
+async fn read_request<S>(stream: &mut S) -> Result<(String, u16)>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // 方法选择
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header).await?;
+    if header[0] != 0x05 {
+        anyhow::bail!("Unsupported SOCKS version: {}", header[0]);
+    }
+    let mut methods = vec![0u8; header[1] as usize];
+    stream.read_exact(&mut methods).await?;
+    if !methods.contains(&0x00) {
+        stream.write_all(&[0x05, 0xFF]).await?;
+        anyhow::bail!("SOCKS5 client does not offer the no-authentication method");
+    }
+    stream.write_all(&[0x05, 0x00]).await?;
+    stream.flush().await?;
+
+    // 请求：VER, CMD, RSV, ATYP
+    let mut request = [0u8; 4];
+    stream.read_exact(&mut request).await?;
+    if request[0] != 0x05 {
+        anyhow::bail!("Invalid SOCKS5 request version");
+    }
+
+    let host = match request[3] {
+        0x01 | 0x04 => {
+            // IP 地址无法选择代理，读完地址和端口后拒绝
+            let len = if request[3] == 0x01 { 4 } else { 16 };
+            let mut addr = vec![0u8; len + 2];
+            stream.read_exact(&mut addr).await?;
+            send_reply(stream, REP_ADDRESS_TYPE_NOT_SUPPORTED).await?;
+            anyhow::bail!("Visitor gateway targets must be host names, not IP addresses");
+        }
+        0x03 => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).await?;
+            let mut domain = vec![0u8; len[0] as usize];
+            stream.read_exact(&mut domain).await?;
+            String::from_utf8(domain).context("Invalid UTF-8 in SOCKS5 host name")?
+        }
+        atyp => {
+            send_reply(stream, REP_ADDRESS_TYPE_NOT_SUPPORTED).await?;
+            anyhow::bail!("Unsupported address type: {}", atyp);
+        }
+    };
+
+    let mut port = [0u8; 2];
+    stream.read_exact(&mut port).await?;
+
+    if request[1] != 0x01 {
+        // 只支持 CONNECT 命令
+        send_reply(stream, REP_COMMAND_NOT_SUPPORTED).await?;
+        anyhow::bail!("Unsupported SOCKS5 command: {}", request[1]);
+    }
+
+    Ok((host, u16::from_be_bytes(port)))
+}
+
+/// 发送 SOCKS5 回复（BND.ADDR 固定为 0.0.0.0:0）
+async fn send_reply<S>(stream: &mut S, rep: u8) -> std::io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    stream
+        .write_all(&[0x05, rep, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+        .await?;
+    stream.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gateway() -> VisitorGatewayConfig {
+        toml::from_str("bind_port = 1080\n[expected_peer_ids]\ndb = \"tenant-a\"\n").unwrap()
+    }
+
+    #[test]
+    fn test_gateway_visitor_port_policy() {
+        let gateway = gateway();
+
+        let db = gateway_visitor(&gateway, "db", 5432, false);
+        assert_eq!(db.name, "db");
+        assert_eq!(db.publish_port, 5432);
+        assert_eq!(db.expected_peer_id.as_deref(), Some("tenant-a"));
+        assert!(!db.connection_reuse);
+
+        let web = gateway_visitor(&gateway, "web", 80, true);
+        assert_eq!(web.publish_port, ANY_PUBLISH_PORT);
+        assert_eq!(web.expected_peer_id, None);
+    }
+
+    #[tokio::test]
+    async fn test_read_request() {
+        let (mut client, mut server) = tokio::io::duplex(256);
+        let mut request = vec![0x05, 0x01, 0x00, 0x05, 0x01, 0x00, 0x03, 9];
+        request.extend_from_slice(b"db.tunnel");
+        request.extend_from_slice(&5432u16.to_be_bytes());
+        client.write_all(&request).await.unwrap();
+
+        let (host, port) = read_request(&mut server).await.unwrap();
+        assert_eq!((host.as_str(), port), ("db.tunnel", 5432));
+        let mut reply = [0u8; 2];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply, [0x05, 0x00]);
+    }
+
+    #[tokio::test]
+    async fn test_read_request_rejects_ip_targets_and_bind() {
+        // IPv4 目标
+        let (mut client, mut server) = tokio::io::duplex(256);
+        client
+            .write_all(&[
+                0x05, 0x01, 0x00, 0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1, 0, 80,
+            ])
+            .await
+            .unwrap();
+        assert!(read_request(&mut server).await.is_err());
+        let mut reply = [0u8; 12];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[3], REP_ADDRESS_TYPE_NOT_SUPPORTED);
+
+        // BIND 命令
+        let (mut client, mut server) = tokio::io::duplex(256);
+        let mut request = vec![0x05, 0x01, 0x00, 0x05, 0x02, 0x00, 0x03, 2];
+        request.extend_from_slice(b"db");
+        request.extend_from_slice(&80u16.to_be_bytes());
+        client.write_all(&request).await.unwrap();
+        assert!(read_request(&mut server).await.is_err());
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[3], REP_COMMAND_NOT_SUPPORTED);
+    }
+}
@@ -0,0 +1,554 @@
+/// visitor 连接复用（子流多路复用）
+///
+/// 开启 `connection_reuse` 的 visitor 与 proxy 客户端之间只保持一条长期的 yamux stream，
+/// 本地的每个连接作为一个子流在这条 stream 上传输，避免为每个短连接创建和关闭 stream。
+///
+/// 帧格式：u32 子流 ID + u8 帧类型 + u16 数据长度 + 数据。子流由 visitor 端发起（Open），
+/// 双方各自用 Close 表示本方向数据结束，Reset 表示子流异常终止（只影响该子流）
+use crate::stats::{PendingBytes, STATS_FLUSH_BYTES};
+use crate::traffic::TrafficMeter;
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+/// 帧头长度
+const FRAME_HEADER_LEN: usize = 7;
+/// 单帧最大数据长度
+pub const MAX_FRAME_PAYLOAD: usize = 16 * 1024;
+/// 每个子流缓存的入站数据帧数
+const SUBSTREAM_QUEUE: usize = 32;
+/// 待写出帧的队列长度
+const OUTBOUND_QUEUE: usize = 256;
+
+/// 帧类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameKind {
+    /// 打开子流
+    Open = 0,
+    /// 子流数据
+    Data = 1,
+    /// 本方向数据结束（半关闭）
+    Close = 2,
+    /// 子流异常终止（数据为错误信息）
+    Reset = 3,
+}
+
+impl FrameKind {
+    fn from_u8(value: u8) -> Result<Self> {
+        Ok(match value {
+            0 => FrameKind::Open,
+            1 => FrameKind::Data,
+            2 => FrameKind::Close,
+            3 => FrameKind::Reset,
+            other => bail!("Unknown mux frame kind {}", other),
+        })
+    }
+}
+
+/// 复用通道上的一帧
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Frame {
+    id: u32,
+    kind: FrameKind,
+    payload: Vec<u8>,
+}
+
+impl Frame {
+    fn new(id: u32, kind: FrameKind, payload: Vec<u8>) -> Self {
+        Self { id, kind, payload }
+    }
+
+    fn reset(id: u32, reason: &str) -> Self {
+        let mut payload = reason.as_bytes().to_vec();
+        payload.truncate(MAX_FRAME_PAYLOAD);
+        Self::new(id, FrameKind::Reset, payload)
+    }
+
+    async fn write_to<W>(&self, writer: &mut W) -> std::io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut buf = Vec::with_capacity(FRAME_HEADER_LEN + self.payload.len());
+        buf.extend_from_slice(&self.id.to_be_bytes());
+        buf.push(self.kind as u8);
+        buf.extend_from_slice(&(self.payload.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&self.payload);
+        writer.write_all(&buf).await
+    }
+
+    /// 读取一帧，在帧边界遇到 EOF 时返回 None
+    async fn read_from<R>(reader: &mut R) -> Result<Option<Self>>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut header = [0u8; FRAME_HEADER_LEN];
+        let first = reader.read(&mut header[..1]).await?;
+        if first == 0 {
+            return Ok(None);
+        }
+        reader.read_exact(&mut header[1..]).await?;
+
+        let id = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let kind = FrameKind::from_u8(header[4])?;
+        let len = u16::from_be_bytes([header[5], header[6]]) as usize;
+        if len > MAX_FRAME_PAYLOAD {
+            bail!(
+                "Mux frame too large ({} bytes, max {})",
+                len,
+                MAX_FRAME_PAYLOAD
+            );
+        }
+        let mut payload = vec![0u8; len];
+        reader.read_exact(&mut payload).await?;
+        Ok(Some(Self { id, kind, payload }))
+    }
+}
+
+/// 子流收到的数据
+#[derive(Debug)]
+enum Inbound {
+    Data(Vec<u8>),
+    Reset(String),
+}
+
+/// 会话内各子流共享的状态
+#[derive(Default)]
+struct Shared {
+    substreams: Mutex<HashMap<u32, mpsc::Sender<Inbound>>>,
+    closed: AtomicBool,
+}
+
+impl Shared {
+    /// 复用通道断开：终止所有子流
+    fn fail_all(&self, reason: &str) {
+        self.closed.store(true, Ordering::Relaxed);
+        let substreams = std::mem::take(&mut *self.substreams.lock().unwrap());
+        for (_, tx) in substreams {
+            let _ = tx.try_send(Inbound::Reset(reason.to_string()));
+        }
+    }
+
+    fn sender(&self, id: u32) -> Option<mpsc::Sender<Inbound>> {
+        self.substreams.lock().unwrap().get(&id).cloned()
+    }
+
+    fn remove(&self, id: u32) -> Option<mpsc::Sender<Inbound>> {
+        self.substreams.lock().unwrap().remove(&id)
+    }
+}
+
+/// 一条复用通道（底层 stream）上的会话
+pub struct MuxSession {
+    outbound: mpsc::Sender<Frame>,
+    shared: Arc<Shared>,
+    next_id: AtomicU32,
+}
+
+impl MuxSession {
+    /// 在 stream 上启动复用会话，返回会话和对端打开的子流
+    ///
+    /// 只有 proxy 端需要接受子流；不读取返回的接收端时，对端打开的子流会被直接重置
+    pub fn start<T>(stream: T) -> (Self, mpsc::Receiver<SubStream>)
+    where
+        T: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        let (outbound, mut outbound_rx) = mpsc::channel::<Frame>(OUTBOUND_QUEUE);
+        let (accept_tx, accept_rx) = mpsc::channel(SUBSTREAM_QUEUE);
+        let shared = Arc::new(Shared::default());
+
+        // 写出：所有子流的帧串行写入底层 stream
+        let writer_shared = shared.clone();
+        let write_loop = async move {
+            while let Some(frame) = outbound_rx.recv().await {
+                if let Err(e) = frame.write_to(&mut writer).await {
+                    warn!("Visitor mux write error: {}", e);
+                    writer_shared.fail_all("tunnel stream write failed");
+                    return;
+                }
+                // 队列中暂时没有更多帧时才刷新，减少小包
+                if outbound_rx.is_empty() {
+                    if let Err(e) = writer.flush().await {
+                        warn!("Visitor mux flush error: {}", e);
+                        writer_shared.fail_all("tunnel stream write failed");
+                        return;
+                    }
+                }
+            }
+            let _ = writer.shutdown().await;
+        };
+
+        // 读取：按子流 ID 分发（只持有弱引用，会话和子流都释放后写出结束并关闭 stream）
+        let reader_shared = shared.clone();
+        let weak_outbound = outbound.downgrade();
+        let read_loop = async move {
+            let reason = loop {
+                let frame = match Frame::read_from(&mut reader).await {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => break "tunnel stream closed".to_string(),
+                    Err(e) => break format!("tunnel stream error: {}", e),
+                };
+                match frame.kind {
+                    FrameKind::Open => {
+                        let Some(outbound) = weak_outbound.upgrade() else {
+                            break "session dropped".to_string();
+                        };
+                        let (tx, rx) = mpsc::channel(SUBSTREAM_QUEUE);
+                        reader_shared
+                            .substreams
+                            .lock()
+                            .unwrap()
+                            .insert(frame.id, tx);
+                        let substream =
+                            SubStream::new(frame.id, outbound, rx, reader_shared.clone());
+                        if let Err(mpsc::error::SendError(substream)) =
+                            accept_tx.send(substream).await
+                        {
+                            substream.reset("substreams are not accepted").await;
+                        }
+                    }
+                    FrameKind::Data => {
+                        // 子流已结束时丢弃在途数据；子流处理缓慢时反压整个通道
+                        if let Some(tx) = reader_shared.sender(frame.id) {
+                            let _ = tx.send(Inbound::Data(frame.payload)).await;
+                        }
+                    }
+                    FrameKind::Close => {
+                        reader_shared.remove(frame.id);
+                    }
+                    FrameKind::Reset => {
+                        if let Some(tx) = reader_shared.remove(frame.id) {
+                            let reason = String::from_utf8_lossy(&frame.payload).into_owned();
+                            let _ = tx.send(Inbound::Reset(reason)).await;
+                        }
+                    }
+                }
+            };
+            debug!("Visitor mux reader finished: {}", reason);
+            reader_shared.fail_all(&reason);
+        };
+
+        // 读写必须在同一个任务中驱动：yamux stream 的读（发送窗口更新）和写共用一个命令通道，
+        // 通道满时只保留最后一次注册的唤醒器，分属两个任务会导致其中一方永远不被唤醒
+        tokio::spawn(async move {
+            tokio::join!(write_loop, read_loop);
+        });
+
+        let session = Self {
+            outbound,
+            shared,
+            next_id: AtomicU32::new(1),
+        };
+        (session, accept_rx)
+    }
+
+    /// 复用通道是否已断开
+    pub fn is_closed(&self) -> bool {
+        self.shared.closed.load(Ordering::Relaxed) || self.outbound.is_closed()
+    }
+
+    /// 打开新的子流
+    pub async fn open(&self) -> Result<SubStream> {
+        if self.is_closed() {
+            bail!("Visitor mux tunnel is closed");
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::channel(SUBSTREAM_QUEUE);
+        self.shared.substreams.lock().unwrap().insert(id, tx);
+        let substream = SubStream::new(id, self.outbound.clone(), rx, self.shared.clone());
+        self.outbound
+            .send(Frame::new(id, FrameKind::Open, Vec::new()))
+            .await
+            .map_err(|_| anyhow!("Visitor mux tunnel is closed"))?;
+        Ok(substream)
+    }
+}
+
+/// 复用通道上的一个子流（对应一个本地连接）
+pub struct SubStream {
+    id: u32,
+    outbound: mpsc::Sender<Frame>,
+    inbound: mpsc::Receiver<Inbound>,
+    shared: Arc<Shared>,
+}
+
+impl SubStream {
+    fn new(
+        id: u32,
+        outbound: mpsc::Sender<Frame>,
+        inbound: mpsc::Receiver<Inbound>,
+        shared: Arc<Shared>,
+    ) -> Self {
+        Self {
+            id,
+            outbound,
+            inbound,
+            shared,
+        }
+    }
+
+    /// 子流 ID
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// 异常终止子流，通知对端关闭对应的连接
+    pub async fn reset(self, reason: &str) {
+        let _ = self.outbound.send(Frame::reset(self.id, reason)).await;
+    }
+
+    /// 在子流和本地连接之间双向转发，直到两个方向都结束或任一方向出错
+    ///
+    /// 本地 → 子流的字节记为 `meter` 的发送，子流 → 本地的字节记为接收
+    pub async fn relay<S>(mut self, local: &mut S, meter: Option<&TrafficMeter>) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let id = self.id;
+        let outbound = self.outbound.clone();
+        let (mut local_read, mut local_write) = tokio::io::split(local);
+
+        let upstream = async {
+            let mut pending = PendingBytes::new(STATS_FLUSH_BYTES);
+            let mut buf = vec![0u8; MAX_FRAME_PAYLOAD];
+            let result = loop {
+                let n = match local_read.read(&mut buf).await {
+                    Ok(0) => {
+                        let _ = outbound
+                            .send(Frame::new(id, FrameKind::Close, Vec::new()))
+                            .await;
+                        break Ok(());
+                    }
+                    Ok(n) => n,
+                    Err(e) => break Err(anyhow!("Local read error: {}", e)),
+                };
+                if outbound
+                    .send(Frame::new(id, FrameKind::Data, buf[..n].to_vec()))
+                    .await
+                    .is_err()
+                {
+                    break Err(anyhow!("Visitor mux tunnel is closed"));
+                }
+                if let (Some(bytes), Some(m)) = (pending.add(n as u64), meter) {
+                    m.add_sent(bytes);
+                }
+            };
+            if let (Some(bytes), Some(m)) = (pending.take(), meter) {
+                m.add_sent(bytes);
+            }
+            result
+        };
+
+        let inbound = &mut self.inbound;
+        let downstream = async {
+            let mut pending = PendingBytes::new(STATS_FLUSH_BYTES);
+            let result = loop {
+                match inbound.recv().await {
+                    Some(Inbound::Data(data)) => {
+                        if let Err(e) = local_write.write_all(&data).await {
+                            break Err(anyhow!("Local write error: {}", e));
+                        }
+                        if let (Some(bytes), Some(m)) = (pending.add(data.len() as u64), meter) {
+                            m.add_received(bytes);
+                        }
+                    }
+                    Some(Inbound::Reset(reason)) => {
+                        break Err(anyhow!("Substream reset by peer: {}", reason));
+                    }
+                    None => {
+                        let _ = local_write.shutdown().await;
+                        break Ok(());
+                    }
+                }
+            };
+            if let (Some(bytes), Some(m)) = (pending.take(), meter) {
+                m.add_received(bytes);
+            }
+            result
+        };
+
+        let result = tokio::try_join!(upstream, downstream).map(|_| ());
+        if let Err(e) = &result {
+            // 只重置本子流，其他子流不受影响
+            let _ = self.outbound.send(Frame::reset(id, &e.to_string())).await;
+        }
+        result
+    }
+}
+
+impl Drop for SubStream {
+    fn drop(&mut self) {
+        self.shared.remove(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[tokio::test]
+    async fn test_frame_roundtrip() {
+        let frame = Frame::new(7, FrameKind::Data, b"hello".to_vec());
+        let mut buf = Vec::new();
+        frame.write_to(&mut buf).await.unwrap();
+        assert_eq!(buf.len(), FRAME_HEADER_LEN + 5);
+
+        let mut reader = buf.as_slice();
+        assert_eq!(Frame::read_from(&mut reader).await.unwrap(), Some(frame));
+        assert_eq!(Frame::read_from(&mut reader).await.unwrap(), None);
+
+        // 未知帧类型
+        let mut bad: &[u8] = &[0, 0, 0, 1, 9, 0, 0];
+        assert!(Frame::read_from(&mut bad).await.is_err());
+    }
+
+    /// proxy 端：每个子流连接到本地服务
+    fn serve(mut accept_rx: mpsc::Receiver<SubStream>, local_port: u16) {
+        tokio::spawn(async move {
+            while let Some(substream) = accept_rx.recv().await {
+                tokio::spawn(async move {
+                    match TcpStream::connect(("127.0.0.1", local_port)).await {
+                        Ok(mut local) => {
+                            let _ = substream.relay(&mut local, None).await;
+                        }
+                        Err(e) => substream.reset(&e.to_string()).await,
+                    }
+                });
+            }
+        });
+    }
+
+    /// 本地服务：读取全部请求后原样返回并加上前缀
+    async fn start_tagging_echo() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut data = Vec::new();
+                    if socket.read_to_end(&mut data).await.is_ok() {
+                        let _ = socket.write_all(b"echo:").await;
+                        let _ = socket.write_all(&data).await;
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_interleaved_substreams_do_not_cross() {
+        let local_port = start_tagging_echo().await;
+        let (visitor_side, proxy_side) = tokio::io::duplex(4096);
+        let (visitor, _) = MuxSession::start(visitor_side);
+        let (_proxy, accept_rx) = MuxSession::start(proxy_side);
+        serve(accept_rx, local_port);
+
+        let visitor = Arc::new(visitor);
+        let mut tasks = Vec::new();
+        for i in 0..20u8 {
+            let visitor = visitor.clone();
+            tasks.push(tokio::spawn(async move {
+                let substream = visitor.open().await.unwrap();
+                let (mut app, mut tunnel_end) = tokio::io::duplex(64 * 1024);
+                let relay =
+                    tokio::spawn(async move { substream.relay(&mut tunnel_end, None).await });
+
+                // 分多次写入较大的数据，让各子流的帧交错
+                let payload: Vec<u8> = (0..40_000u32).map(|n| (n as u8) ^ i).collect();
+                for chunk in payload.chunks(3000) {
+                    app.write_all(chunk).await.unwrap();
+                    tokio::task::yield_now().await;
+                }
+                app.shutdown().await.unwrap();
+
+                let mut response = Vec::new();
+                app.read_to_end(&mut response).await.unwrap();
+                assert_eq!(&response[..5], b"echo:");
+                assert_eq!(&response[5..], &payload[..], "substream {} crossed", i);
+                relay.await.unwrap().unwrap();
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert!(!visitor.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_substream_error_does_not_affect_others() {
+        let local_port = start_tagging_echo().await;
+        let (visitor_side, proxy_side) = tokio::io::duplex(4096);
+        let (visitor, _) = MuxSession::start(visitor_side);
+        let (_proxy, accept_rx) = MuxSession::start(proxy_side);
+        serve(accept_rx, local_port);
+
+        // 一个健康的子流先写入部分数据
+        let healthy = visitor.open().await.unwrap();
+        let (mut healthy_app, mut healthy_end) = tokio::io::duplex(1024);
+        let healthy_relay =
+            tokio::spawn(async move { healthy.relay(&mut healthy_end, None).await });
+        healthy_app.write_all(b"first half ").await.unwrap();
+
+        // 另一个子流在本地出错后被重置
+        let failing = visitor.open().await.unwrap();
+        failing.reset("local application error").await;
+
+        // 健康的子流继续正常完成
+        healthy_app.write_all(b"second half").await.unwrap();
+        healthy_app.shutdown().await.unwrap();
+        let mut response = Vec::new();
+        healthy_app.read_to_end(&mut response).await.unwrap();
+        assert_eq!(response, b"echo:first half second half");
+        healthy_relay.await.unwrap().unwrap();
+
+        // 复用通道仍然可以打开新的子流
+        let next = visitor.open().await.unwrap();
+        let (mut app, mut end) = tokio::io::duplex(1024);
+        let relay = tokio::spawn(async move { next.relay(&mut end, None).await });
+        app.write_all(b"again").await.unwrap();
+        app.shutdown().await.unwrap();
+        let mut response = Vec::new();
+        app.read_to_end(&mut response).await.unwrap();
+        assert_eq!(response, b"echo:again");
+        relay.await.unwrap().unwrap();
+        assert!(!visitor.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_local_connect_failure_resets_substream() {
+        // 没有服务监听的端口
+        let unused = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = unused.local_addr().unwrap().port();
+        drop(unused);
+
+        let (visitor_side, proxy_side) = tokio::io::duplex(4096);
+        let (visitor, _) = MuxSession::start(visitor_side);
+        let (_proxy, accept_rx) = MuxSession::start(proxy_side);
+        serve(accept_rx, port);
+
+        let substream = visitor.open().await.unwrap();
+        let (_app, mut end) = tokio::io::duplex(1024);
+        let err = substream.relay(&mut end, None).await.unwrap_err();
+        assert!(err.to_string().contains("reset by peer"), "{}", err);
+        assert!(!visitor.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_loss_closes_session() {
+        let (visitor_side, proxy_side) = tokio::io::duplex(4096);
+        let (visitor, _) = MuxSession::start(visitor_side);
+        let substream = visitor.open().await.unwrap();
+        drop(proxy_side);
+
+        let (_app, mut end) = tokio::io::duplex(1024);
+        assert!(substream.relay(&mut end, None).await.is_err());
+        assert!(visitor.is_closed());
+        assert!(visitor.open().await.is_err());
+    }
+}
@@ -4,8 +4,9 @@ use std::path::PathBuf;
 use crate::transport::TransportType;
 
 use super::{
-    validator::ConfigValidator, ClientConfig, ClientFullConfig, ForwarderConfig, ProxyConfig,
-    ServerConfig, VisitorConfig,
+    validator::ConfigValidator, AuthKeyConfig, ClientConfig, ClientFullConfig,
+    ClientTimeoutsConfig, ForwardPolicyConfig, ForwarderConfig, HealthConfig, ProxyConfig,
+    ServerConfig, ServerTimeoutsConfig, VisitorAclConfig, VisitorConfig, VisitorGatewayConfig,
 };
 
 /// ServerConfig Builder
@@ -17,10 +18,18 @@ pub struct ServerConfigBuilder {
     behind_proxy: bool,
     cert_path: Option<PathBuf>,
     key_path: Option<PathBuf>,
+    client_ca_path: Option<PathBuf>,
+    require_client_cert: bool,
     auth_key: Option<String>,
+    auth_keys: Option<Vec<AuthKeyConfig>>,
     stats_port: Option<u16>,
     stats_addr: Option<String>,
+    stats_path: Option<String>,
     allow_forward: bool,
+    forward_policy: Option<ForwardPolicyConfig>,
+    known_clients: Option<Vec<String>>,
+    visitor_acl: Option<VisitorAclConfig>,
+    timeouts: Option<ServerTimeoutsConfig>,
 }
 
 impl ServerConfigBuilder {
@@ -65,6 +74,18 @@ impl ServerConfigBuilder {
         self
     }
 
+    /// 设置验证客户端证书的 CA 证书路径（启用 mTLS）
+    pub fn client_ca_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.client_ca_path = Some(path.into());
+        self
+    }
+
+    /// 设置是否要求客户端出示证书
+    pub fn require_client_cert(mut self, require: bool) -> Self {
+        self.require_client_cert = require;
+        self
+    }
+
     /// 设置认证密钥
     pub fn auth_key(mut self, key: impl Into<String>) -> Self {
         self.auth_key = Some(key.into());
@@ -83,12 +104,48 @@ impl ServerConfigBuilder {
         self
     }
 
+    /// 设置隧道端口上的统计路径（仅 http2/wss 传输）
+    pub fn stats_path(mut self, path: impl Into<String>) -> Self {
+        self.stats_path = Some(path.into());
+        self
+    }
+
     /// 设置是否允许 forward proxy
     pub fn allow_forward(mut self, allow: bool) -> Self {
         self.allow_forward = allow;
         self
     }
 
+    /// 设置附加的认证密钥（每个密钥单独限制发布端口和 forward 权限）
+    pub fn auth_keys(mut self, keys: Vec<AuthKeyConfig>) -> Self {
+        self.auth_keys = Some(keys);
+        self
+    }
+
+    /// 设置 forward 目标访问策略
+    pub fn forward_policy(mut self, policy: ForwardPolicyConfig) -> Self {
+        self.forward_policy = Some(policy);
+        self
+    }
+
+    /// 设置已知客户端身份指纹（设置后只接受列表中的客户端）
+    pub fn known_clients(mut self, fingerprints: Vec<String>) -> Self {
+        self.known_clients = Some(fingerprints);
+        self
+    }
+
+    /// 设置 visitor 访问控制
+    pub fn visitor_acl(mut self, acl: VisitorAclConfig) -> Self {
+        self.visitor_acl = Some(acl);
+        self
+    }
+
+    /// 设置超时配置
+    pub fn timeouts(mut self, timeouts: ServerTimeoutsConfig) -> Self {
+        self.timeouts = Some(timeouts);
+        self
+    }
+
     /// 构建 ServerConfig 并验证
     pub fn build(self) -> Result<ServerConfig> {
         let config = ServerConfig {
@@ -101,9 +158,39 @@ impl ServerConfigBuilder {
             auth_key: self.auth_key.context("auth_key is required")?,
             stats_port: self.stats_port,
             stats_addr: self.stats_addr,
+            stats_path: self.stats_path,
             allow_forward: self.allow_forward,
             rate_limit: None,  // Builder 默认不设置速率限制
             size_limits: None, // Builder 默认不设置大小限制
+            stats_limits: None,
+            stats_stream: None,
+            interface_prefer_ipv6: false,
+            event_export: None,
+            max_write_chunk: None,
+            debug_stalls: false,
+            debug_tls_handshakes: false,
+            exception_limits: None,
+            drain_timeout_secs: None,
+            protocol_trace_path: None,
+            forward_limits: None,
+            forward_policy: self.forward_policy,
+            max_concurrent_handshakes: None,
+            handshake_timeout_secs: None,
+            idle_registration_expiry_secs: None,
+            relay_memory_budget_mb: None,
+            known_clients: self.known_clients,
+            visitor_acl: self.visitor_acl,
+            timeouts: self.timeouts,
+            flow_export: None,
+            shutdown_grace_period_secs: None,
+            visitor_retry_timeout_secs: None,
+            tcp_keepalive_secs: None,
+            idle_timeout_secs: None,
+            max_streams_per_client: None,
+            max_connections_per_proxy: None,
+            auth_keys: self.auth_keys,
+            client_ca_path: self.client_ca_path,
+            require_client_cert: self.require_client_cert,
         };
 
         // 验证配置
@@ -122,7 +209,15 @@ pub struct ClientConfigBuilder {
     transport: Option<TransportType>,
     skip_verify: bool,
     ca_cert_path: Option<PathBuf>,
+    client_cert_path: Option<PathBuf>,
+    client_key_path: Option<PathBuf>,
     auth_key: Option<String>,
+    peer_id: Option<String>,
+    identity_path: Option<PathBuf>,
+    state_dir: Option<PathBuf>,
+    fallback_to_last_good: bool,
+    watch_config: bool,
+    timeouts: Option<ClientTimeoutsConfig>,
 }
 
 impl ClientConfigBuilder {
@@ -170,12 +265,59 @@ impl ClientConfigBuilder {
         self
     }
 
+    /// 设置客户端证书和私钥路径（服务器启用 mTLS 时使用）
+    pub fn client_cert(
+        mut self,
+        cert_path: impl Into<PathBuf>,
+        key_path: impl Into<PathBuf>,
+    ) -> Self {
+        self.client_cert_path = Some(cert_path.into());
+        self.client_key_path = Some(key_path.into());
+        self
+    }
+
     /// 设置认证密钥
     pub fn auth_key(mut self, key: impl Into<String>) -> Self {
         self.auth_key = Some(key.into());
         self
     }
 
+    /// 设置客户端稳定身份（peer_id）
+    pub fn peer_id(mut self, peer_id: impl Into<String>) -> Self {
+        self.peer_id = Some(peer_id.into());
+        self
+    }
+
+    /// 设置客户端身份密钥文件路径（不存在时自动生成）
+    pub fn identity_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.identity_path = Some(path.into());
+        self
+    }
+
+    /// 设置客户端状态目录（保存最近一次被服务器接受的配置）
+    pub fn state_dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.state_dir = Some(path.into());
+        self
+    }
+
+    /// 设置配置文件无效时是否改用最近一次被服务器接受的配置
+    pub fn fallback_to_last_good(mut self, enabled: bool) -> Self {
+        self.fallback_to_last_good = enabled;
+        self
+    }
+
+    /// 设置是否监视配置文件并热重载 proxies、visitors 和 forwarders
+    pub fn watch_config(mut self, enabled: bool) -> Self {
+        self.watch_config = enabled;
+        self
+    }
+
+    /// 设置超时配置
+    pub fn timeouts(mut self, timeouts: ClientTimeoutsConfig) -> Self {
+        self.timeouts = Some(timeouts);
+        self
+    }
+
     /// 构建 ClientConfig
     pub fn build(self) -> Result<ClientConfig> {
         let config = ClientConfig {
@@ -188,6 +330,29 @@ impl ClientConfigBuilder {
             auth_key: self.auth_key.context("auth_key is required")?,
             stats_port: None,
             stats_addr: None,
+            stats_limits: None,
+            stats_stream: None,
+            events_socket: None,
+            peer_id: self.peer_id,
+            routing_overrides_path: None,
+            routing_ui_token: None,
+            size_limits: None,
+            stats_socket: None,
+            stats_pipe: None,
+            proxy_retry: None,
+            max_write_chunk: None,
+            debug_stalls: false,
+            protocol_trace_path: None,
+            keep_listening_on_disconnect: true,
+            max_missed_heartbeats: None,
+            relay_memory_budget_mb: None,
+            identity_path: self.identity_path,
+            state_dir: self.state_dir,
+            fallback_to_last_good: self.fallback_to_last_good,
+            watch_config: self.watch_config,
+            client_cert_path: self.client_cert_path,
+            client_key_path: self.client_key_path,
+            timeouts: self.timeouts,
         };
 
         // 验证认证密钥
@@ -204,6 +369,8 @@ pub struct ClientFullConfigBuilder {
     proxies: Vec<ProxyConfig>,
     visitors: Vec<VisitorConfig>,
     forwarders: Vec<ForwarderConfig>,
+    visitor_gateway: Option<VisitorGatewayConfig>,
+    health: Option<HealthConfig>,
 }
 
 impl ClientFullConfigBuilder {
@@ -254,6 +421,18 @@ impl ClientFullConfigBuilder {
         self
     }
 
+    /// 设置 visitor 网关
+    pub fn visitor_gateway(mut self, gateway: VisitorGatewayConfig) -> Self {
+        self.visitor_gateway = Some(gateway);
+        self
+    }
+
+    /// 设置健康评分
+    pub fn health(mut self, health: HealthConfig) -> Self {
+        self.health = Some(health);
+        self
+    }
+
     /// 构建 ClientFullConfig 并验证
     pub fn build(self) -> Result<ClientFullConfig> {
         let config = ClientFullConfig {
@@ -261,6 +440,8 @@ impl ClientFullConfigBuilder {
             proxies: self.proxies,
             visitors: self.visitors,
             forwarders: self.forwarders,
+            visitor_gateway: self.visitor_gateway,
+            health: self.health,
         };
 
         // 验证配置
@@ -273,6 +454,7 @@ impl ClientFullConfigBuilder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::ProxyVisibility;
 
     #[test]
     fn test_server_config_builder() {
@@ -339,6 +521,20 @@ mod tests {
             publish_addr: "0.0.0.0".to_string(),
             publish_port: 9000,
             local_port: 8080,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
         };
 
         let config = ClientFullConfigBuilder::new()
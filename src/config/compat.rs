@@ -0,0 +1,333 @@
+use std::collections::BTreeMap;
+use toml::{Table, Value};
+
+use super::{strict, ClientFullConfig};
+
+/// 旧字段名（或其他工具中的字段名）到规范字段名的映射
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldAlias {
+    /// 所在的配置列表（`proxies`、`visitors` 或 `forwarders`）
+    pub section: &'static str,
+    /// 别名
+    pub alias: &'static str,
+    /// 规范字段名
+    pub canonical: &'static str,
+}
+
+/// 接受的字段别名（结构体上有对应的 `#[serde(alias)]`）
+pub const FIELD_ALIASES: &[FieldAlias] = &[
+    FieldAlias {
+        section: "proxies",
+        alias: "remote_port",
+        canonical: "publish_port",
+    },
+    FieldAlias {
+        section: "visitors",
+        alias: "listen_addr",
+        canonical: "bind_addr",
+    },
+    FieldAlias {
+        section: "visitors",
+        alias: "listen_port",
+        canonical: "bind_port",
+    },
+    FieldAlias {
+        section: "forwarders",
+        alias: "listen_addr",
+        canonical: "bind_addr",
+    },
+    FieldAlias {
+        section: "forwarders",
+        alias: "listen_port",
+        canonical: "bind_port",
+    },
+];
+
+/// 别名与规范字段同时设置且取值不同
+#[derive(Debug, Clone, PartialEq)]
+pub struct AliasConflict {
+    /// 别名定义
+    pub alias: FieldAlias,
+    /// 条目名称（没有 name 时为 `#序号`）
+    pub entry: String,
+    /// 别名的取值
+    pub alias_value: String,
+    /// 规范字段的取值
+    pub canonical_value: String,
+}
+
+/// 加载配置时发现的别名使用情况
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompatReport {
+    /// 每个使用过的别名及使用次数
+    pub used: BTreeMap<(&'static str, &'static str), (FieldAlias, usize)>,
+    /// 取值冲突的别名
+    pub conflicts: Vec<AliasConflict>,
+}
+
+impl CompatReport {
+    /// 没有使用任何别名
+    pub fn is_empty(&self) -> bool {
+        self.used.is_empty()
+    }
+
+    /// 汇总的弃用警告（每个别名一项，带规范字段名和使用次数），没有使用别名时为 None
+    pub fn deprecation_warning(&self) -> Option<String> {
+        if self.used.is_empty() {
+            return None;
+        }
+        let fields: Vec<String> = self
+            .used
+            .values()
+            .map(|(alias, count)| {
+                format!(
+                    "{}.{} -> {} ({} use{})",
+                    alias.section,
+                    alias.alias,
+                    alias.canonical,
+                    count,
+                    if *count == 1 { "" } else { "s" }
+                )
+            })
+            .collect();
+        Some(format!(
+            "Configuration uses deprecated field names, rename them to the canonical names: {}",
+            fields.join(", ")
+        ))
+    }
+}
+
+/// 解析客户端配置，同时记录使用的字段别名
+///
+/// 配置开启了严格模式（`strict = true`）时先拒绝未知字段
+///
+/// 只用别名的条目由 serde 别名直接处理；别名与规范字段同时出现时 serde 会报重复字段，
+/// 此时去掉别名后再反序列化，取值冲突交给 [`ConfigValidator::validate_field_aliases`] 拒绝
+///
+/// [`ConfigValidator::validate_field_aliases`]: super::ConfigValidator::validate_field_aliases
+pub fn parse_client_config(
+    content: &str,
+) -> Result<(ClientFullConfig, CompatReport), toml::de::Error> {
+    let mut table: Table = toml::from_str(content)?;
+    strict::check(&table, &strict::client_schema())
+        .map_err(<toml::de::Error as serde::de::Error>::custom)?;
+    let (report, rewritten) = normalize_aliases(&mut table);
+    // 没有改写时从原文反序列化，保留错误信息中的行号
+    let config = if rewritten {
+        table.try_into()?
+    } else {
+        toml::from_str(content)?
+    };
+    Ok((config, report))
+}
+
+/// 检查配置表中的别名，同时设置了规范字段的条目去掉别名；返回报告和是否改写了配置表
+fn normalize_aliases(table: &mut Table) -> (CompatReport, bool) {
+    let mut report = CompatReport::default();
+    let mut rewritten = false;
+
+    for alias in FIELD_ALIASES {
+        let Some(Value::Array(entries)) = table.get_mut(alias.section) else {
+            continue;
+        };
+        for (index, entry) in entries.iter_mut().enumerate() {
+            let Value::Table(entry) = entry else {
+                continue;
+            };
+            if !entry.contains_key(alias.alias) {
+                continue;
+            }
+            report
+                .used
+                .entry((alias.section, alias.alias))
+                .or_insert((*alias, 0))
+                .1 += 1;
+
+            let Some(canonical_value) = entry.get(alias.canonical).cloned() else {
+                continue;
+            };
+            let alias_value = entry.remove(alias.alias).expect("alias key checked above");
+            rewritten = true;
+            if alias_value != canonical_value {
+                let name = match entry.get("name") {
+                    Some(Value::String(name)) => name.clone(),
+                    _ => format!("#{}", index),
+                };
+                report.conflicts.push(AliasConflict {
+                    alias: *alias,
+                    entry: name,
+                    alias_value: alias_value.to_string(),
+                    canonical_value: canonical_value.to_string(),
+                });
+            }
+        }
+    }
+
+    (report, rewritten)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigValidator;
+
+    const CLIENT: &str = r#"
+[client]
+server_addr = "tunnel.example.com"
+server_port = 8443
+auth_key = "Xk9#mP2$vL7@qR4!"
+"#;
+
+    fn parse(sections: &str) -> (ClientFullConfig, CompatReport) {
+        parse_client_config(&format!("{}{}", CLIENT, sections)).unwrap()
+    }
+
+    #[test]
+    fn test_remote_port_alias() {
+        let (config, report) =
+            parse("[[proxies]]\nname = \"web\"\nremote_port = 8080\nlocal_port = 80\n");
+        assert_eq!(config.proxies[0].publish_port, 8080);
+        assert_eq!(config.proxies[0].local_port, 80);
+        let (alias, count) = report.used[&("proxies", "remote_port")];
+        assert_eq!(alias.canonical, "publish_port");
+        assert_eq!(count, 1);
+        assert!(report.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_visitor_listen_aliases() {
+        let (config, report) = parse(
+            "[[visitors]]\nname = \"db\"\nlisten_addr = \"0.0.0.0\"\nlisten_port = 15432\npublish_port = 5432\n",
+        );
+        assert_eq!(config.visitors[0].bind_addr, "0.0.0.0");
+        assert_eq!(config.visitors[0].bind_port, 15432);
+        assert!(report.used.contains_key(&("visitors", "listen_addr")));
+        assert!(report.used.contains_key(&("visitors", "listen_port")));
+    }
+
+    #[test]
+    fn test_forwarder_listen_aliases() {
+        let (config, report) = parse(
+            "[[forwarders]]\nname = \"socks\"\nproxy_type = \"socks5\"\nlisten_addr = \"127.0.0.2\"\nlisten_port = 1080\n",
+        );
+        assert_eq!(config.forwarders[0].bind_addr, "127.0.0.2");
+        assert_eq!(config.forwarders[0].bind_port, 1080);
+        assert!(report.used.contains_key(&("forwarders", "listen_addr")));
+        assert!(report.used.contains_key(&("forwarders", "listen_port")));
+    }
+
+    #[test]
+    fn test_every_alias_is_accepted() {
+        for alias in FIELD_ALIASES {
+            let required: &[&str] = match alias.section {
+                "proxies" => &["name = \"e\"", "local_port = 1", "publish_port = 1"],
+                "visitors" => &["name = \"e\"", "publish_port = 1", "bind_port = 3000"],
+                _ => &["name = \"e\"", "proxy_type = \"http\"", "bind_port = 3000"],
+            };
+            let value = if alias.canonical.ends_with("_port") {
+                "2000"
+            } else {
+                "\"127.0.0.3\""
+            };
+            let mut lines: Vec<String> = required
+                .iter()
+                .filter(|line| !line.starts_with(alias.canonical))
+                .map(|line| line.to_string())
+                .collect();
+            lines.push(format!("{} = {}", alias.alias, value));
+
+            let (config, report) = parse(&format!("[[{}]]\n{}\n", alias.section, lines.join("\n")));
+            let config = serde_json::to_value(&config).unwrap();
+            let field = &config[alias.section][0][alias.canonical];
+            assert_eq!(field.to_string(), value, "{:?}", alias);
+            assert_eq!(report.used.len(), 1, "{:?}", alias);
+        }
+    }
+
+    #[test]
+    fn test_same_value_is_not_a_conflict() {
+        let (config, report) = parse(
+            "[[proxies]]\nname = \"web\"\nremote_port = 8080\npublish_port = 8080\nlocal_port = 80\n",
+        );
+        assert_eq!(config.proxies[0].publish_port, 8080);
+        assert!(report.conflicts.is_empty());
+        assert!(report.deprecation_warning().is_some());
+        ConfigValidator::validate_field_aliases(&report).unwrap();
+    }
+
+    #[test]
+    fn test_conflicting_values_are_rejected() {
+        let (config, report) = parse(
+            "[[proxies]]\nname = \"web\"\nremote_port = 8080\npublish_port = 9000\nlocal_port = 80\n\
+             [[forwarders]]\nproxy_type = \"http\"\nname = \"fwd\"\nlisten_port = 3128\nbind_port = 3129\n",
+        );
+        // 规范字段优先
+        assert_eq!(config.proxies[0].publish_port, 9000);
+        assert_eq!(config.forwarders[0].bind_port, 3129);
+        assert_eq!(report.conflicts.len(), 2);
+        assert_eq!(report.conflicts[0].entry, "web");
+        assert_eq!(report.conflicts[0].alias_value, "8080");
+        assert_eq!(report.conflicts[0].canonical_value, "9000");
+
+        let err = ConfigValidator::validate_field_aliases(&report)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("remote_port"), "{}", err);
+        assert!(err.contains("publish_port"), "{}", err);
+        assert!(err.contains("'web'"), "{}", err);
+    }
+
+    #[test]
+    fn test_conflict_entry_without_name_uses_index() {
+        let mut table: Table =
+            toml::from_str("[[visitors]]\nlisten_addr = \"0.0.0.0\"\nbind_addr = \"127.0.0.1\"\n")
+                .unwrap();
+        let (report, rewritten) = normalize_aliases(&mut table);
+        assert!(rewritten);
+        assert_eq!(report.conflicts[0].entry, "#0");
+        assert!(!table["visitors"][0]
+            .as_table()
+            .unwrap()
+            .contains_key("listen_addr"));
+    }
+
+    #[test]
+    fn test_deprecation_warning_aggregates_uses() {
+        let (_, report) = parse(
+            "[[proxies]]\nname = \"a\"\nremote_port = 8001\nlocal_port = 80\n\
+             [[proxies]]\nname = \"b\"\nremote_port = 8002\nlocal_port = 80\n\
+             [[forwarders]]\nname = \"fwd\"\nproxy_type = \"http\"\nlisten_port = 3128\n",
+        );
+        let warning = report.deprecation_warning().unwrap();
+        assert!(
+            warning.contains("proxies.remote_port -> publish_port (2 uses)"),
+            "{}",
+            warning
+        );
+        assert!(
+            warning.contains("forwarders.listen_port -> bind_port (1 use)"),
+            "{}",
+            warning
+        );
+        // 每个别名只出现一次
+        assert_eq!(warning.matches("remote_port").count(), 1);
+    }
+
+    #[test]
+    fn test_no_aliases_no_warning() {
+        let (_, report) =
+            parse("[[proxies]]\nname = \"web\"\npublish_port = 8080\nlocal_port = 80\n");
+        assert!(report.is_empty());
+        assert_eq!(report.deprecation_warning(), None);
+    }
+
+    #[test]
+    fn test_parse_errors_keep_line_numbers() {
+        let err = parse_client_config(&format!(
+            "{}[[proxies]]\nname = \"web\"\nremote_port = \"not a port\"\n",
+            CLIENT
+        ))
+        .unwrap_err();
+        assert!(err.span().is_some());
+    }
+}
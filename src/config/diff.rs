@@ -0,0 +1,393 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+use super::ClientFullConfig;
+
+/// 输出时需要脱敏的字段名
+///
+/// forwarder 的 `header_rules` 可能直接写出注入的凭据，整体脱敏
+const SECRET_FIELDS: &[&str] = &["auth_key", "routing_ui_token", "header_rules", "auth_token"];
+
+/// 脱敏后的占位值
+const REDACTED: &str = "<redacted>";
+
+/// 单个字段的变更（嵌套字段用 `.` 连接，如 `stats_limits.max_connections`）
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    /// 字段路径
+    pub field: String,
+    /// 原值（不存在时为 null）
+    pub old: Value,
+    /// 新值（不存在时为 null）
+    pub new: Value,
+}
+
+/// 列表项的变更
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EntryChange {
+    /// 列表项标识（proxy / visitor 为 `name:publish_port`，forwarder 为 `name`）
+    pub key: String,
+    /// 变更的字段
+    pub fields: Vec<FieldChange>,
+}
+
+/// 一组配置列表（proxies / visitors / forwarders）的差异
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SectionDiff {
+    /// 新增的列表项
+    pub added: Vec<String>,
+    /// 删除的列表项
+    pub removed: Vec<String>,
+    /// 修改的列表项
+    pub changed: Vec<EntryChange>,
+}
+
+impl SectionDiff {
+    /// 是否没有任何差异
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// 两份客户端配置之间的结构化差异（密钥字段已脱敏）
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ConfigDiff {
+    /// `[client]` 段的标量设置变更
+    pub settings: Vec<FieldChange>,
+    pub proxies: SectionDiff,
+    pub visitors: SectionDiff,
+    pub forwarders: SectionDiff,
+    /// `[visitor_gateway]` 段的设置变更（新增或删除时为所有字段的变更）
+    pub visitor_gateway: Vec<FieldChange>,
+    /// `[health]` 段的设置变更
+    pub health: Vec<FieldChange>,
+}
+
+impl ConfigDiff {
+    /// 比较两份客户端配置
+    pub fn client(old: &ClientFullConfig, new: &ClientFullConfig) -> Self {
+        Self {
+            settings: diff_values(&to_value(&old.client), &to_value(&new.client)),
+            proxies: diff_section(
+                old.proxies
+                    .iter()
+                    .map(|p| (format!("{}:{}", p.name, p.publish_port), to_value(p))),
+                new.proxies
+                    .iter()
+                    .map(|p| (format!("{}:{}", p.name, p.publish_port), to_value(p))),
+            ),
+            visitors: diff_section(
+                old.visitors
+                    .iter()
+                    .map(|v| (format!("{}:{}", v.name, v.publish_port), to_value(v))),
+                new.visitors
+                    .iter()
+                    .map(|v| (format!("{}:{}", v.name, v.publish_port), to_value(v))),
+            ),
+            forwarders: diff_section(
+                old.forwarders.iter().map(|f| (f.name.clone(), to_value(f))),
+                new.forwarders.iter().map(|f| (f.name.clone(), to_value(f))),
+            ),
+            visitor_gateway: diff_values(
+                &to_value(&old.visitor_gateway),
+                &to_value(&new.visitor_gateway),
+            ),
+            health: diff_values(&to_value(&old.health), &to_value(&new.health)),
+        }
+    }
+
+    /// 是否没有任何差异
+    pub fn is_empty(&self) -> bool {
+        self.settings.is_empty()
+            && self.proxies.is_empty()
+            && self.visitors.is_empty()
+            && self.forwarders.is_empty()
+            && self.visitor_gateway.is_empty()
+            && self.health.is_empty()
+    }
+}
+
+fn to_value<T: Serialize>(value: &T) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+/// 比较两组列表项（按标识匹配，保留配置中的顺序）
+fn diff_section(
+    old: impl Iterator<Item = (String, Value)>,
+    new: impl Iterator<Item = (String, Value)>,
+) -> SectionDiff {
+    let old: Vec<(String, Value)> = old.collect();
+    let new: Vec<(String, Value)> = new.collect();
+    let old_map: BTreeMap<&str, &Value> = old.iter().map(|(k, v)| (k.as_str(), v)).collect();
+    let new_map: BTreeMap<&str, &Value> = new.iter().map(|(k, v)| (k.as_str(), v)).collect();
+
+    let mut diff = SectionDiff::default();
+    for (key, value) in &new {
+        match old_map.get(key.as_str()) {
+            None => diff.added.push(key.clone()),
+            Some(old_value) => {
+                let fields = diff_values(old_value, value);
+                if !fields.is_empty() {
+                    diff.changed.push(EntryChange {
+                        key: key.clone(),
+                        fields,
+                    });
+                }
+            }
+        }
+    }
+    diff.removed = old
+        .iter()
+        .filter(|(key, _)| !new_map.contains_key(key.as_str()))
+        .map(|(key, _)| key.clone())
+        .collect();
+    diff
+}
+
+/// 逐字段比较两个 JSON 对象（数组作为整体比较）
+fn diff_values(old: &Value, new: &Value) -> Vec<FieldChange> {
+    let mut old_fields = BTreeMap::new();
+    let mut new_fields = BTreeMap::new();
+    flatten("", old, &mut old_fields);
+    flatten("", new, &mut new_fields);
+
+    let mut paths: Vec<&String> = old_fields.keys().chain(new_fields.keys()).collect();
+    paths.sort();
+    paths.dedup();
+
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let old = old_fields.get(path).cloned().unwrap_or(Value::Null);
+            let new = new_fields.get(path).cloned().unwrap_or(Value::Null);
+            (old != new).then(|| FieldChange {
+                field: path.clone(),
+                old: redact(path, old),
+                new: redact(path, new),
+            })
+        })
+        .collect()
+}
+
+/// 将嵌套对象展开为 `路径 -> 值`（null 视为不存在）
+fn flatten(prefix: &str, value: &Value, out: &mut BTreeMap<String, Value>) {
+    match value {
+        Value::Object(map) => flatten_object(prefix, map, out),
+        Value::Null => {}
+        other => {
+            out.insert(prefix.to_string(), other.clone());
+        }
+    }
+}
+
+fn flatten_object(prefix: &str, map: &Map<String, Value>, out: &mut BTreeMap<String, Value>) {
+    for (key, value) in map {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        flatten(&path, value, out);
+    }
+}
+
+/// 密钥字段替换为占位值（仍然报告发生了变化）
+fn redact(path: &str, value: Value) -> Value {
+    let field = path.rsplit('.').next().unwrap_or(path);
+    if SECRET_FIELDS.contains(&field) && !value.is_null() {
+        Value::String(REDACTED.to_string())
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> ClientFullConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    const BASE: &str = r#"
+[client]
+server_addr = "tunnel.example.com"
+server_port = 8443
+auth_key = "old-secret"
+
+[[proxies]]
+name = "web"
+publish_port = 8080
+local_port = 3000
+
+[[proxies]]
+name = "ssh"
+proxy_type = "ssh"
+publish_port = 2222
+local_port = 22
+
+[[visitors]]
+name = "db"
+bind_port = 15432
+publish_port = 5432
+
+[[forwarders]]
+name = "http-out"
+proxy_type = "http"
+bind_port = 8118
+"#;
+
+    #[test]
+    fn test_identical_configs_have_no_diff() {
+        let diff = ConfigDiff::client(&parse(BASE), &parse(BASE));
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn test_added_removed_and_changed_entries() {
+        let new = BASE
+            .replace("local_port = 3000", "local_port = 3001")
+            .replace(
+                "[[proxies]]\nname = \"ssh\"\nproxy_type = \"ssh\"\npublish_port = 2222\nlocal_port = 22\n",
+                "[[proxies]]\nname = \"api\"\npublish_port = 9090\nlocal_port = 4000\n",
+            );
+        let diff = ConfigDiff::client(&parse(BASE), &parse(&new));
+
+        assert_eq!(diff.proxies.added, vec!["api:9090"]);
+        assert_eq!(diff.proxies.removed, vec!["ssh:2222"]);
+        assert_eq!(
+            diff.proxies.changed,
+            vec![EntryChange {
+                key: "web:8080".to_string(),
+                fields: vec![FieldChange {
+                    field: "local_port".to_string(),
+                    old: Value::from(3000),
+                    new: Value::from(3001),
+                }],
+            }]
+        );
+        assert!(diff.settings.is_empty());
+        assert!(diff.visitors.is_empty());
+        assert!(diff.forwarders.is_empty());
+    }
+
+    #[test]
+    fn test_publish_port_change_is_remove_and_add() {
+        let new = BASE.replace("publish_port = 5432", "publish_port = 5433");
+        let diff = ConfigDiff::client(&parse(BASE), &parse(&new));
+        assert_eq!(diff.visitors.added, vec!["db:5433"]);
+        assert_eq!(diff.visitors.removed, vec!["db:5432"]);
+        assert!(diff.visitors.changed.is_empty());
+    }
+
+    #[test]
+    fn test_scalar_and_nested_settings() {
+        let new = BASE.replace(
+            "auth_key = \"old-secret\"",
+            "auth_key = \"new-secret\"\nstats_port = 9100\n\n[client.stats_limits]\nmax_connections = 8",
+        );
+        let diff = ConfigDiff::client(&parse(BASE), &parse(&new));
+
+        let fields: Vec<&str> = diff.settings.iter().map(|c| c.field.as_str()).collect();
+        assert!(fields.contains(&"auth_key"));
+        assert!(fields.contains(&"stats_port"));
+        assert!(fields.contains(&"stats_limits.max_connections"));
+
+        let stats_port = diff
+            .settings
+            .iter()
+            .find(|c| c.field == "stats_port")
+            .unwrap();
+        assert_eq!(stats_port.old, Value::Null);
+        assert_eq!(stats_port.new, Value::from(9100));
+    }
+
+    #[test]
+    fn test_secrets_are_redacted() {
+        let new = BASE
+            .replace("auth_key = \"old-secret\"", "auth_key = \"new-secret\"")
+            .replace(
+                "server_port = 8443",
+                "server_port = 8443\nrouting_ui_token = \"ui-token\"",
+            );
+        let diff = ConfigDiff::client(&parse(BASE), &parse(&new));
+
+        let auth = diff
+            .settings
+            .iter()
+            .find(|c| c.field == "auth_key")
+            .unwrap();
+        assert_eq!(auth.old, Value::from(REDACTED));
+        assert_eq!(auth.new, Value::from(REDACTED));
+        let token = diff
+            .settings
+            .iter()
+            .find(|c| c.field == "routing_ui_token")
+            .unwrap();
+        assert_eq!(token.old, Value::Null);
+        assert_eq!(token.new, Value::from(REDACTED));
+
+        let output = serde_json::to_string(&diff).unwrap();
+        assert!(!output.contains("secret"));
+        assert!(!output.contains("ui-token"));
+
+        // forwarder 请求头规则中的值
+        let rules = format!(
+            "{}\n[[forwarders.header_rules]]\nmatch_domains = [\"internal.example\"]\nadd = {{ Authorization = \"Bearer secret-token\" }}\n",
+            BASE
+        );
+        let diff = ConfigDiff::client(&parse(BASE), &parse(&rules));
+        let change = &diff.forwarders.changed[0].fields[0];
+        assert_eq!(change.field, "header_rules");
+        assert_eq!(change.new, Value::from(REDACTED));
+        assert!(!serde_json::to_string(&diff).unwrap().contains("secret"));
+    }
+
+    #[test]
+    fn test_forwarder_routing_lists_compare_as_whole() {
+        let old = format!(
+            "{}\n[forwarders.routing]\ndirect_countries = [\"CN\"]\n",
+            BASE
+        );
+        let new = format!(
+            "{}\n[forwarders.routing]\ndirect_countries = [\"CN\", \"HK\"]\n",
+            BASE
+        );
+        let diff = ConfigDiff::client(&parse(&old), &parse(&new));
+        assert_eq!(diff.forwarders.changed.len(), 1);
+        let change = &diff.forwarders.changed[0];
+        assert_eq!(change.key, "http-out");
+        assert_eq!(change.fields.len(), 1);
+        assert_eq!(change.fields[0].field, "routing.direct_countries");
+        assert_eq!(change.fields[0].new, serde_json::json!(["CN", "HK"]));
+    }
+
+    #[test]
+    fn test_visitor_gateway_settings() {
+        let old = format!("{}\n[visitor_gateway]\nbind_port = 1080\n", BASE);
+        let new = format!(
+            "{}\n[visitor_gateway]\nbind_port = 1080\nport_policy = \"ignore\"\n",
+            BASE
+        );
+        let diff = ConfigDiff::client(&parse(&old), &parse(&new));
+        assert_eq!(
+            diff.visitor_gateway,
+            vec![FieldChange {
+                field: "port_policy".to_string(),
+                old: Value::from("match"),
+                new: Value::from("ignore"),
+            }]
+        );
+
+        // 新增网关时报告其所有字段
+        let diff = ConfigDiff::client(&parse(BASE), &parse(&old));
+        let fields: Vec<&str> = diff
+            .visitor_gateway
+            .iter()
+            .map(|c| c.field.as_str())
+            .collect();
+        assert!(fields.contains(&"bind_port"));
+        assert!(fields.contains(&"domain"));
+    }
+}
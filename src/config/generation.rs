@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 已加载配置的版本信息
+///
+/// 每次加载或应用配置时生成，用于确认运行中的配置对应哪一份文件内容
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigGeneration {
+    /// 应用序号（首次加载为 1，每次应用新配置加一）
+    pub generation: u64,
+    /// 配置内容的 SHA-256（十六进制）
+    pub hash: String,
+    /// 加载时间（Unix 时间戳，秒）
+    pub loaded_at: u64,
+    /// 配置文件路径（非文件来源时为空）
+    pub source: Option<PathBuf>,
+}
+
+impl ConfigGeneration {
+    /// 为首次加载的配置生成版本信息
+    pub fn new<T: Serialize>(config: &T, source: Option<PathBuf>) -> Self {
+        Self {
+            generation: 1,
+            hash: content_hash(config),
+            loaded_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            source,
+        }
+    }
+
+    /// 应用新配置后的版本信息（序号加一，来源不变）
+    pub fn next<T: Serialize>(&self, config: &T) -> Self {
+        Self {
+            generation: self.generation + 1,
+            ..Self::new(config, self.source.clone())
+        }
+    }
+}
+
+/// 计算配置内容哈希（基于序列化结果，与文件中的格式和注释无关）
+pub fn content_hash<T: Serialize>(config: &T) -> String {
+    let bytes = serde_json::to_vec(config).unwrap_or_default();
+    ring::digest::digest(&ring::digest::SHA256, &bytes)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_tracks_content() {
+        let a = serde_json::json!({ "port": 1 });
+        let b = serde_json::json!({ "port": 2 });
+        assert_eq!(content_hash(&a), content_hash(&a.clone()));
+        assert_ne!(content_hash(&a), content_hash(&b));
+        assert_eq!(content_hash(&a).len(), 64);
+    }
+
+    #[test]
+    fn test_next_increments_generation() {
+        let first = ConfigGeneration::new(&1u32, Some(PathBuf::from("/etc/client.toml")));
+        assert_eq!(first.generation, 1);
+        let second = first.next(&2u32);
+        assert_eq!(second.generation, 2);
+        assert_eq!(second.source, first.source);
+        assert_ne!(second.hash, first.hash);
+    }
+}
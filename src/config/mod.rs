@@ -1,16 +1,24 @@
 // 配置管理模块 - 使用模块化设计
 
 mod builder;
+pub mod compat;
+pub mod diff;
+mod generation;
+pub mod strict;
 mod validator;
 
 // 重新导出 builder 和 validator
 pub use builder::{ClientConfigBuilder, ClientFullConfigBuilder, ServerConfigBuilder};
+pub use diff::ConfigDiff;
+pub use generation::{content_hash, ConfigGeneration};
 pub use validator::ConfigValidator;
 
 use crate::transport::TransportType;
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::time::Duration;
 
 /// 代理类型
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
@@ -34,6 +42,11 @@ pub enum ProxyType {
     /// SOCKS5 代理（用于 forwarder）
     #[serde(rename = "socks5")]
     Socks5Proxy,
+    /// 根据首字节自动识别 HTTP 或 SOCKS5 代理（用于 forwarder，单端口同时支持两种协议）
+    #[serde(rename = "auto")]
+    AutoProxy,
+    /// UDP 数据报（服务器在发布端口绑定 UDP 套接字，每个外部来源地址对应一个 stream）
+    Udp,
 }
 
 impl ProxyType {
@@ -44,19 +57,69 @@ impl ProxyType {
             ProxyType::Http11 => true,
             ProxyType::Http2 => true,
             ProxyType::Ssh => false,
-            ProxyType::HttpProxy | ProxyType::Socks5Proxy => false,
+            ProxyType::HttpProxy | ProxyType::Socks5Proxy | ProxyType::AutoProxy => false,
+            ProxyType::Udp => false,
         }
     }
 
+    /// 是否转发 UDP 数据报（stream 上按 [`crate::protocol::encode_datagram`] 分帧）
+    pub fn is_datagram(self) -> bool {
+        matches!(self, ProxyType::Udp)
+    }
+
+    /// 是否为 forwarder 使用的代理协议类型
+    pub fn is_forwarder_type(self) -> bool {
+        matches!(
+            self,
+            ProxyType::HttpProxy | ProxyType::Socks5Proxy | ProxyType::AutoProxy
+        )
+    }
+
     /// 是否需要单一长连接多路复用
     pub fn is_multiplexed(self) -> bool {
         matches!(self, ProxyType::Http2)
     }
 
-    /// 是否需要启用 TCP_NODELAY（禁用 Nagle 算法）
-    /// 适用于交互式应用（如 SSH），优先降低延迟而非提高吞吐量
-    pub fn needs_nodelay(self) -> bool {
-        matches!(self, ProxyType::Ssh)
+    /// 是否默认预热连接池（只有复用连接的类型预热才有意义）
+    pub fn should_warmup(self) -> bool {
+        self.should_reuse_connections()
+    }
+
+    /// 是否检测本地服务响应中的 WebSocket 升级（升级后的连接只做纯转发，不归还连接池）
+    pub fn detects_upgrades(self) -> bool {
+        matches!(self, ProxyType::Http11)
+    }
+}
+
+/// 代理可见性
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyVisibility {
+    /// 服务器绑定发布端口，外部可以直接访问
+    #[default]
+    Public,
+    /// 不绑定发布端口，只能通过 visitor 访问
+    Private,
+}
+
+impl ProxyVisibility {
+    /// 是否为公开代理
+    pub fn is_public(&self) -> bool {
+        matches!(self, ProxyVisibility::Public)
+    }
+
+    /// 是否为私有代理
+    pub fn is_private(&self) -> bool {
+        matches!(self, ProxyVisibility::Private)
+    }
+}
+
+impl std::fmt::Display for ProxyVisibility {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProxyVisibility::Public => write!(f, "public"),
+            ProxyVisibility::Private => write!(f, "private"),
+        }
     }
 }
 
@@ -68,13 +131,155 @@ pub struct ProxyConfig {
     /// 代理类型
     #[serde(default)]
     pub proxy_type: ProxyType,
-    /// 服务器发布地址（绑定地址，默认 0.0.0.0）
+    /// 服务器发布地址（绑定地址，默认 0.0.0.0；私有代理忽略该字段）
     #[serde(default = "default_publish_addr")]
     pub publish_addr: String,
-    /// 服务器发布端口（外部访问该端口）
+    /// 服务器发布端口（外部访问该端口；私有代理不绑定端口，仅作为注册表中的逻辑标识）
+    #[serde(alias = "remote_port")]
     pub publish_port: u16,
-    /// 客户端本地服务端口（转发到该端口）
+    /// 客户端本地服务端口（转发到该端口；配置了 local_targets 时可省略）
+    #[serde(default)]
     pub local_port: u16,
+    /// 多个本地目标（`host:port`，设置后取代 local_port）：轮询选择，跳过最近连接失败的目标，
+    /// 连接失败时在同一连接内改用下一个目标
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_targets: Option<Vec<String>>,
+    /// 连接池策略覆盖（不设置时使用代理类型推导的默认值）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool: Option<ProxyPoolConfig>,
+    /// 是否允许多个客户端以相同名称和端口注册该代理（服务器按权重轮询分发连接）
+    #[serde(default)]
+    pub shared: bool,
+    /// 共享代理的负载均衡权重（默认 1，仅在 shared = true 时有效）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>,
+    /// 可见性（private 时服务器不绑定发布端口，只能通过 visitor 访问）
+    #[serde(default, skip_serializing_if = "ProxyVisibility::is_public")]
+    pub visibility: ProxyVisibility,
+    /// 代理被主动下线时等待已有连接结束的最长时间（秒，不设置时使用服务器的 drain_timeout_secs）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drain_timeout_secs: Option<u64>,
+    /// 按外部客户端 TLS ClientHello 中的 SNI 选择本地端口（服务器不终止 TLS）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sni_routing: Option<SniRoutingConfig>,
+    /// 发布端口接入的连接和到本地服务的连接使用的套接字选项
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub socket: Option<SocketOptionsConfig>,
+    /// 转发的连接空闲超过该秒数时，在隧道内的 stream 上发送保活标记（默认关闭）
+    ///
+    /// 标记只存在于服务器与客户端之间的分帧 stream 上，不会注入外部连接或本地服务的数据流；
+    /// 私有代理的 visitor 转发不分帧，不支持该选项
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_keepalive_secs: Option<u64>,
+    /// 外部连接在该毫秒数内发来第一个字节后，服务器才请求隧道 stream 并连接本地服务（默认关闭）
+    ///
+    /// 只连接不发送数据的外部连接（半开连接、失联的对端）不会让客户端连接本地服务；
+    /// 服务器先发送数据的协议（SSH、SMTP 等）不能开启
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub require_first_byte_timeout_ms: Option<u64>,
+    /// 同一来源 IP 在发布端口上的最大并发连接数（超出的连接接受后立即关闭，默认不限制）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_connections_per_source: Option<u32>,
+    /// 服务器在 stream 前导中附带外部连接的来源地址和接入时间（默认关闭）
+    ///
+    /// 客户端把它记录到统计的最近连接列表和协议跟踪中，转发的数据不变，本地服务不需要支持 PROXY 协议
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub report_peers: bool,
+    /// 升级为 WebSocket 的连接两个方向都空闲超过该秒数时关闭（仅 http/1.1 代理，默认不超时）
+    ///
+    /// 升级后的连接不再按 HTTP 消息处理，也不会归还连接池
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ws_idle_timeout_secs: Option<u64>,
+    /// 诊断用：两端计算每个方向转发数据的 CRC32C 并在连接结束时比较（默认关闭）
+    ///
+    /// 用于定位数据损坏发生在隧道内还是隧道外；开启后 stream 分帧传输，服务器需要支持
+    /// integrity_check 能力。私有代理的 visitor 转发不支持
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub integrity_check: bool,
+}
+
+impl ProxyConfig {
+    /// 本地服务地址（配置了 local_targets 时为其中的全部目标，否则为 `127.0.0.1:local_port`）
+    pub fn local_addrs(&self) -> Vec<String> {
+        match &self.local_targets {
+            Some(targets) => targets.clone(),
+            None => vec![format!("127.0.0.1:{}", self.local_port)],
+        }
+    }
+
+    /// 本地服务端口（未设置 local_port 时为第一个本地目标的端口）
+    pub fn effective_local_port(&self) -> u16 {
+        if self.local_port != 0 {
+            return self.local_port;
+        }
+        self.local_targets
+            .as_ref()
+            .and_then(|targets| targets.first())
+            .and_then(|target| target.rsplit_once(':'))
+            .and_then(|(_, port)| port.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// 提交给服务器的配置：local_port 为 [`Self::effective_local_port`]（服务器要求非 0），
+    /// 本地目标只在客户端使用，不提交
+    pub fn for_server(&self) -> ProxyConfig {
+        ProxyConfig {
+            local_port: self.effective_local_port(),
+            local_targets: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// SNI 路由表中非 TLS 或不带 SNI 的连接使用的键
+pub const SNI_DEFAULT_KEY: &str = "default";
+
+/// SNI 路由配置
+///
+/// 同一个发布端口按 SNI 分发到多个本地后端，后端自行完成 TLS 握手；
+/// 未命中任何主机名时使用 `default`，未配置 `default` 时使用代理的 local_port
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SniRoutingConfig {
+    /// 主机名 -> 本地端口（主机名不区分大小写）
+    pub sni_map: BTreeMap<String, u16>,
+}
+
+impl SniRoutingConfig {
+    /// 按 SNI 选择本地端口，返回 None 表示使用代理的 local_port
+    pub fn select(&self, sni: Option<&str>) -> Option<u16> {
+        sni.and_then(|name| {
+            self.sni_map
+                .iter()
+                .find(|(host, _)| {
+                    host.as_str() != SNI_DEFAULT_KEY && host.eq_ignore_ascii_case(name)
+                })
+                .map(|(_, &port)| port)
+        })
+        .or_else(|| self.sni_map.get(SNI_DEFAULT_KEY).copied())
+    }
+
+    /// 本地端口是否是路由表中的目标
+    pub fn routes_to(&self, port: u16) -> bool {
+        self.sni_map.values().any(|&p| p == port)
+    }
+}
+
+/// 代理连接池策略覆盖
+///
+/// 未设置的字段沿用代理类型推导的默认值，例如 HTTP/2 默认单连接多路复用，
+/// 但后端能处理多个并发连接时可以通过 `max_size` 放开。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProxyPoolConfig {
+    /// 最大连接数
+    pub max_size: Option<usize>,
+    /// 最小空闲连接数（预热目标）
+    pub min_idle: Option<usize>,
+    /// 是否在会话建立时预热连接
+    pub warmup: Option<bool>,
+    /// 是否复用连接
+    pub reuse: Option<bool>,
+    /// 连接最大寿命（秒），超过后轮换为新连接
+    pub max_lifetime_secs: Option<u64>,
 }
 
 fn default_publish_addr() -> String {
@@ -94,12 +299,79 @@ pub struct VisitorConfig {
     #[serde(default)]
     pub proxy_type: ProxyType,
     /// 客户端本地绑定地址（默认 127.0.0.1）
-    #[serde(default = "default_bind_addr")]
+    #[serde(default = "default_bind_addr", alias = "listen_addr")]
     pub bind_addr: String,
     /// 客户端本地绑定端口（本地应用连接此端口）
+    #[serde(alias = "listen_port")]
     pub bind_port: u16,
     /// 目标 proxy 的 publish_port（用于精确匹配，当有多个同名 proxy 时）
     pub publish_port: u16,
+    /// 期望的 proxy 注册者 peer_id（可选），设置后注册者身份不符时拒绝连接
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_peer_id: Option<String>,
+    /// 是否在一条长期 stream 上复用本地连接（需要服务器和 proxy 客户端都支持，默认关闭）
+    #[serde(default)]
+    pub connection_reuse: bool,
+    /// 本地接入的连接使用的套接字选项
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub socket: Option<SocketOptionsConfig>,
+}
+
+/// Visitor 网关的目标端口匹配策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum GatewayPortPolicy {
+    /// SOCKS5 目标端口即目标代理的 publish_port
+    #[default]
+    Match,
+    /// 忽略 SOCKS5 目标端口，按名称匹配唯一的代理（需要服务器支持 visitor_any_port）
+    Ignore,
+}
+
+/// Visitor 网关配置：一个本地 SOCKS5 监听端口，按目标主机名访问服务器上的代理
+///
+/// 连接 `myservice.tunnel:5432` 相当于一个 name = "myservice"、publish_port = 5432 的 visitor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VisitorGatewayConfig {
+    /// 客户端本地绑定地址（默认 127.0.0.1）
+    #[serde(default = "default_bind_addr")]
+    pub bind_addr: String,
+    /// 客户端本地绑定端口（SOCKS5）
+    pub bind_port: u16,
+    /// 目标主机名的域名后缀（默认 "tunnel"），不带后缀的单段主机名同样接受
+    #[serde(default = "default_gateway_domain")]
+    pub domain: String,
+    /// 目标端口匹配策略
+    #[serde(default)]
+    pub port_policy: GatewayPortPolicy,
+    /// 按代理名称指定期望的注册者 peer_id，身份不符时拒绝连接
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub expected_peer_ids: BTreeMap<String, String>,
+    /// 本地接入的连接使用的套接字选项
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub socket: Option<SocketOptionsConfig>,
+}
+
+fn default_gateway_domain() -> String {
+    "tunnel".to_string()
+}
+
+impl VisitorGatewayConfig {
+    /// 由 SOCKS5 目标主机名得到代理名称（`name.<domain>` 或单段的 `name`），不匹配时返回 None
+    pub fn proxy_name<'a>(&self, host: &'a str) -> Option<&'a str> {
+        let host = host.strip_suffix('.').unwrap_or(host);
+        let name = match host.len().checked_sub(self.domain.len() + 1) {
+            Some(split)
+                if host.is_char_boundary(split)
+                    && host[split..].starts_with('.')
+                    && host[split + 1..].eq_ignore_ascii_case(&self.domain) =>
+            {
+                &host[..split]
+            }
+            _ => host,
+        };
+        (!name.is_empty() && !name.contains('.')).then_some(name)
+    }
 }
 
 /// Forwarder 配置（客户端转发到外部网络）
@@ -107,16 +379,165 @@ pub struct VisitorConfig {
 pub struct ForwarderConfig {
     /// Forwarder 名称
     pub name: String,
-    /// 代理类型（http 或 socks5）
+    /// 代理类型（http、socks5 或 auto）
     pub proxy_type: ProxyType,
     /// 客户端本地绑定地址（默认 127.0.0.1）
-    #[serde(default = "default_bind_addr")]
+    #[serde(default = "default_bind_addr", alias = "listen_addr")]
     pub bind_addr: String,
     /// 客户端本地绑定端口（本地应用连接此端口）
+    #[serde(alias = "listen_port")]
     pub bind_port: u16,
     /// 路由策略（可选）
     #[serde(default)]
     pub routing: Option<RoutingConfig>,
+    /// 目标快速失败配置（未配置时使用默认值）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fast_fail: Option<FastFailConfig>,
+    /// 直连（不经隧道）目标时的出口设置（可选）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direct_egress: Option<DirectEgressConfig>,
+    /// 本地接入的连接和直连目标的连接使用的套接字选项
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub socket: Option<SocketOptionsConfig>,
+    /// 直连（不经隧道）的连接结束时向服务器上报目标、命中的规则和流量（仅用于审计，
+    /// 服务器不支持时不上报）
+    #[serde(default)]
+    pub report_direct: bool,
+    /// 该 forwarder 的连接空闲超时（秒，可选，未配置时使用客户端 `timeouts.connection_idle_secs`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_timeout_secs: Option<u64>,
+    /// HTTP 直接转发的请求头改写规则（可选，仅 http / auto 类型；CONNECT 隧道不受影响）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header_rules: Option<Vec<HeaderRuleConfig>>,
+}
+
+/// forwarder 请求头改写规则
+///
+/// 目标主机匹配 `match_domains` 中任一模式（`*.example.com`、`.example.com` 或完整域名/IP）时，
+/// 先删除 `remove` 中的头部，再添加（覆盖）`add` 中的头部
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderRuleConfig {
+    /// 匹配的目标域名
+    pub match_domains: Vec<String>,
+    /// 添加或覆盖的头部，值可以写作 `env:NAME` 或 `file:/path` 引用密钥
+    #[serde(default)]
+    pub add: BTreeMap<String, String>,
+    /// 删除的头部（不区分大小写，以 `*` 结尾时按前缀匹配，如 `X-Forwarded-*`）
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+/// 解析可能引用密钥的配置值
+///
+/// `env:NAME` 读取环境变量，`file:/path` 读取文件内容（去掉末尾换行），其他值原样返回
+pub fn resolve_secret(value: &str) -> anyhow::Result<String> {
+    use anyhow::Context;
+
+    if let Some(name) = value.strip_prefix("env:") {
+        std::env::var(name).with_context(|| format!("Environment variable '{}' is not set", name))
+    } else if let Some(path) = value.strip_prefix("file:") {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read secret file '{}'", path))?;
+        Ok(content.trim_end_matches(['\r', '\n']).to_string())
+    } else {
+        Ok(value.to_string())
+    }
+}
+
+/// TCP 套接字选项
+///
+/// 未配置时启用 TCP_NODELAY，收发缓冲区、keepalive 和 TCP_USER_TIMEOUT 使用系统默认值
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SocketOptionsConfig {
+    /// 是否启用 TCP_NODELAY（禁用 Nagle 算法）
+    pub nodelay: bool,
+    /// 发送缓冲区大小（SO_SNDBUF，字节）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send_buffer_bytes: Option<u32>,
+    /// 接收缓冲区大小（SO_RCVBUF，字节）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recv_buffer_bytes: Option<u32>,
+    /// 启用 TCP keepalive：连接空闲该时长（秒）后开始发送探测，探测间隔相同
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keepalive_secs: Option<u64>,
+    /// TCP_USER_TIMEOUT（毫秒，仅 Linux）：已发送的数据超过该时长未被确认时关闭连接
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_timeout_ms: Option<u32>,
+}
+
+impl Default for SocketOptionsConfig {
+    fn default() -> Self {
+        Self {
+            nodelay: true,
+            send_buffer_bytes: None,
+            recv_buffer_bytes: None,
+            keepalive_secs: None,
+            user_timeout_ms: None,
+        }
+    }
+}
+
+/// 套接字缓冲区大小上限（64MB）
+pub const MAX_SOCKET_BUFFER_BYTES: u32 = 64 * 1024 * 1024;
+
+/// TCP keepalive 空闲时间上限（秒，Linux TCP_KEEPIDLE 的上限）
+pub const MAX_SOCKET_KEEPALIVE_SECS: u64 = 32767;
+
+/// Forwarder 直连出口配置
+///
+/// 配合策略路由让直连流量从指定上行链路离开；fwmark 和 bind_interface 仅支持 Linux
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DirectEgressConfig {
+    /// 直连套接字的 SO_MARK（0 表示不设置，需要 CAP_NET_ADMIN）
+    #[serde(default)]
+    pub fwmark: u32,
+    /// 直连套接字绑定的网卡（SO_BINDTODEVICE）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bind_interface: Option<String>,
+    /// 直连套接字在连接前绑定的源地址
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bind_address: Option<std::net::IpAddr>,
+}
+
+impl std::fmt::Display for DirectEgressConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "fwmark={:#x}", self.fwmark)?;
+        if let Some(ref interface) = self.bind_interface {
+            write!(f, " dev={}", interface)?;
+        }
+        if let Some(ref address) = self.bind_address {
+            write!(f, " src={}", address)?;
+        }
+        Ok(())
+    }
+}
+
+/// Forwarder 目标快速失败配置
+///
+/// 目标连续失败达到阈值后加入黑名单，黑名单期内的连接直接拒绝
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FastFailConfig {
+    /// 加入黑名单所需的连续失败次数
+    pub threshold: u32,
+    /// 黑名单有效期（秒）
+    pub blacklist_secs: u64,
+    /// 最多记录的目标数，超出时淘汰最久未访问的目标
+    pub max_entries: usize,
+    /// 距上次失败超过该时间（秒）后失败计数清零
+    pub decay_secs: u64,
+}
+
+impl Default for FastFailConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 3,
+            blacklist_secs: 30 * 60,
+            max_entries: 10_000,
+            decay_secs: 5 * 60,
+        }
+    }
 }
 
 /// 路由策略配置
@@ -145,6 +566,167 @@ pub struct RoutingConfig {
     /// 默认策略：direct（直连）或 proxy（代理），默认 proxy
     #[serde(default = "default_routing_strategy")]
     pub default_strategy: RoutingStrategy,
+    /// 时间段规则（按顺序匹配，未命中域名/IP/国家规则时先于默认策略生效）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schedules: Vec<RoutingSchedule>,
+    /// 时间段规则和月配额使用的时区偏移（如 "+08:00"），默认 UTC
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utc_offset: Option<String>,
+    /// 每月代理流量配额（可选）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<RoutingQuota>,
+}
+
+impl RoutingConfig {
+    /// 解析 `utc_offset`，返回相对 UTC 的秒数（未设置时为 0）
+    pub fn utc_offset_secs(&self) -> anyhow::Result<i32> {
+        let Some(ref offset) = self.utc_offset else {
+            return Ok(0);
+        };
+        let (sign, rest) = match offset.as_bytes().first() {
+            Some(b'+') => (1, &offset[1..]),
+            Some(b'-') => (-1, &offset[1..]),
+            _ => anyhow::bail!("Invalid utc_offset '{}': expected +HH:MM or -HH:MM", offset),
+        };
+        let minutes = parse_clock_time(rest)
+            .filter(|minutes| *minutes < 24 * 60)
+            .with_context(|| {
+                format!("Invalid utc_offset '{}': expected +HH:MM or -HH:MM", offset)
+            })?;
+        Ok(sign * minutes as i32 * 60)
+    }
+}
+
+/// 时间段路由规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoutingSchedule {
+    /// 生效的星期（为空表示每天）；跨午夜的时间段以开始那天为准
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    /// 开始时间（"HH:MM"，包含）
+    pub start: String,
+    /// 结束时间（"HH:MM"，不包含，可以为 "24:00"；早于开始时间表示跨午夜）
+    pub end: String,
+    /// 时间段内使用的策略
+    pub strategy: RoutingStrategy,
+}
+
+impl RoutingSchedule {
+    /// 解析起止时间，返回当天的分钟数 `(start, end)`
+    pub fn window(&self) -> anyhow::Result<(u32, u32)> {
+        let start = parse_clock_time(&self.start)
+            .filter(|minutes| *minutes < 24 * 60)
+            .with_context(|| format!("Invalid schedule start '{}': expected HH:MM", self.start))?;
+        let end = parse_clock_time(&self.end)
+            .with_context(|| format!("Invalid schedule end '{}': expected HH:MM", self.end))?;
+        if start == end {
+            anyhow::bail!(
+                "Invalid schedule {}-{}: start and end must differ",
+                self.start,
+                self.end
+            );
+        }
+        Ok((start, end))
+    }
+
+    /// 用于日志和统计的简短描述，如 "mon,tue 09:00-18:00"
+    pub fn label(&self) -> String {
+        let days = if self.days.is_empty() {
+            "daily".to_string()
+        } else {
+            self.days
+                .iter()
+                .map(|day| day.as_str())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        format!("{} {}-{}", days, self.start, self.end)
+    }
+}
+
+/// 解析 "HH:MM"（00:00 - 24:00），返回分钟数
+fn parse_clock_time(value: &str) -> Option<u32> {
+    let (hours, minutes) = value.split_once(':')?;
+    if hours.len() != 2 || minutes.len() != 2 {
+        return None;
+    }
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    if minutes >= 60 || hours > 24 || (hours == 24 && minutes != 0) {
+        return None;
+    }
+    Some(hours * 60 + minutes)
+}
+
+/// 星期
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    /// 周一
+    #[serde(alias = "monday")]
+    Mon,
+    /// 周二
+    #[serde(alias = "tuesday")]
+    Tue,
+    /// 周三
+    #[serde(alias = "wednesday")]
+    Wed,
+    /// 周四
+    #[serde(alias = "thursday")]
+    Thu,
+    /// 周五
+    #[serde(alias = "friday")]
+    Fri,
+    /// 周六
+    #[serde(alias = "saturday")]
+    Sat,
+    /// 周日
+    #[serde(alias = "sunday")]
+    Sun,
+}
+
+impl Weekday {
+    /// 从周一开始的序号（周一为 0）
+    pub fn days_from_monday(self) -> u8 {
+        self as u8
+    }
+
+    /// 配置中使用的名称
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Weekday::Mon => "mon",
+            Weekday::Tue => "tue",
+            Weekday::Wed => "wed",
+            Weekday::Thu => "thu",
+            Weekday::Fri => "fri",
+            Weekday::Sat => "sat",
+            Weekday::Sun => "sun",
+        }
+    }
+}
+
+/// 每月代理流量配额
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoutingQuota {
+    /// 每个自然月允许经隧道代理的字节数（双向合计）
+    pub bytes_per_month: u64,
+    /// 配额用尽后的动作：direct（改为直连）或 block（拒绝连接），默认 direct
+    #[serde(default)]
+    pub action: QuotaAction,
+    /// 配额使用量的持久化文件（JSON），未设置时重启后从 0 开始计数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_path: Option<PathBuf>,
+}
+
+/// 配额用尽后的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaAction {
+    /// 改为直连
+    #[default]
+    Direct,
+    /// 拒绝新连接
+    Block,
 }
 
 /// 路由策略
@@ -183,14 +765,30 @@ pub struct ServerConfig {
     /// TLS 私钥路径
     #[serde(default)]
     pub key_path: Option<PathBuf>,
+    /// 验证客户端证书的 CA 证书路径（可选，设置后启用 mTLS）
+    #[serde(default)]
+    pub client_ca_path: Option<PathBuf>,
+    /// 是否要求客户端出示由 `client_ca_path` 签发的证书（默认 false：出示的证书仍会被验证）
+    #[serde(default)]
+    pub require_client_cert: bool,
     /// 认证密钥（用于客户端认证）
     pub auth_key: String,
+    /// 附加的认证密钥（可选），每个密钥可以单独限制发布端口和 forward 权限
+    ///
+    /// `auth_key` 始终有效，不受端口限制，forward 权限由 `allow_forward` 决定
+    #[serde(default)]
+    pub auth_keys: Option<Vec<AuthKeyConfig>>,
     /// 统计信息 HTTP 服务器端口（可选）
     #[serde(default)]
     pub stats_port: Option<u16>,
     /// 统计信息服务器绑定地址（可选，默认使用 bind_addr）
     #[serde(default)]
     pub stats_addr: Option<String>,
+    /// 在隧道端口上提供统计页面的路径（可选，仅 http2/wss 传输，如 "/stats"）
+    ///
+    /// 该路径下的普通 HTTP 请求由统计服务处理，隧道请求不受影响
+    #[serde(default)]
+    pub stats_path: Option<String>,
     /// 是否允许 forward proxy 功能（默认 false）
     #[serde(default)]
     pub allow_forward: bool,
@@ -200,6 +798,189 @@ pub struct ServerConfig {
     /// 请求大小限制配置（可选）
     #[serde(default)]
     pub size_limits: Option<SizeLimitConfig>,
+    /// 统计 HTTP 服务器防护配置（可选，不设置时使用默认值）
+    #[serde(default)]
+    pub stats_limits: Option<StatsLimitConfig>,
+    /// 统计服务器 `/stats/ws` 实时推送配置（可选，不设置时使用默认值）
+    #[serde(default)]
+    pub stats_stream: Option<StatsStreamConfig>,
+    /// `publish_addr = "iface:<name>"` 解析网卡地址时优先使用 IPv6（默认优先 IPv4）
+    #[serde(default)]
+    pub interface_prefer_ipv6: bool,
+    /// 连接事件导出配置（可选，用于 SIEM 等外部采集器）
+    #[serde(default)]
+    pub event_export: Option<EventExportConfig>,
+    /// 单次写入传输连接的最大字节数，即每条 TLS 记录的上限（可选，默认不限制）
+    ///
+    /// 路径 MTU 异常（如 PPPoE）导致传输停滞时可尝试调小，如 1200
+    #[serde(default)]
+    pub max_write_chunk: Option<usize>,
+    /// 转发停滞诊断：有数据待写出但长时间无进展时记录警告
+    #[serde(default)]
+    pub debug_stalls: bool,
+    /// TLS 握手诊断：握手失败时记录客户端 ClientHello 的摘要（版本、密码套件、ALPN、SNI）
+    #[serde(default)]
+    pub debug_tls_handshakes: bool,
+    /// 推送给客户端的异常通知的限流配置（可选，不设置时使用默认值）
+    #[serde(default)]
+    pub exception_limits: Option<ExceptionLimitConfig>,
+    /// 代理被主动下线时等待已有连接结束的默认时长（秒，可选，默认 30；0 表示立即关闭）
+    #[serde(default)]
+    pub drain_timeout_secs: Option<u64>,
+    /// 协议跟踪文件（可选）：控制帧和 stream 前导以 JSON 行追加写入，不记录转发的数据
+    #[serde(default)]
+    pub protocol_trace_path: Option<PathBuf>,
+    /// forward 连接的服务端限制（可选，不受客户端配置影响）
+    #[serde(default)]
+    pub forward_limits: Option<ForwardLimitConfig>,
+    /// forward 目标的访问策略（可选），设置后只允许访问策略中列出的域名和地址
+    #[serde(default)]
+    pub forward_policy: Option<ForwardPolicyConfig>,
+    /// 同时进行的握手（TLS、HTTP/2 或 WebSocket）数上限（可选，默认不限制）
+    ///
+    /// 超出上限的连接排队等待，握手的 CPU 开销不会挤占已建立隧道的转发
+    #[serde(default)]
+    pub max_concurrent_handshakes: Option<usize>,
+    /// 接受连接后完成握手的最长时间，包括排队等待（秒，可选，默认 10）
+    #[serde(default)]
+    pub handshake_timeout_secs: Option<u64>,
+    /// 公开代理连续没有连接超过该秒数时注销并通知客户端（可选，默认不过期）
+    ///
+    /// 私有代理只能通过 visitor 访问，不受该选项影响
+    #[serde(default)]
+    pub idle_registration_expiry_secs: Option<u64>,
+    /// 所有转发连接的拷贝缓冲区合计上限（MB，可选，默认不限制）
+    ///
+    /// 余量紧张时新连接使用更小的缓冲区（不小于 4KB），用尽时等待其他连接释放
+    #[serde(default)]
+    pub relay_memory_budget_mb: Option<u64>,
+    /// 允许连接的客户端身份指纹（`SHA256:...`，可选），设置后客户端除认证密钥外
+    /// 还必须证明持有列表中某个身份的私钥，否则拒绝认证
+    #[serde(default)]
+    pub known_clients: Option<Vec<String>>,
+    /// visitor 可访问的代理（可选，未配置时任何已认证的客户端都可以访问所有代理）
+    #[serde(default)]
+    pub visitor_acl: Option<VisitorAclConfig>,
+    /// 超时设置（可选，未配置的项使用默认值）
+    #[serde(default)]
+    pub timeouts: Option<ServerTimeoutsConfig>,
+    /// 转发连接的流量记录导出配置（可选，用于计费等流量核算）
+    #[serde(default)]
+    pub flow_export: Option<FlowExportConfig>,
+    /// 收到退出信号后等待进行中的转发结束的最长时间（秒，可选，默认 30；0 表示不等待）
+    ///
+    /// 期间不再接受新的客户端连接和代理连接，再次收到退出信号时立即停止
+    #[serde(default, alias = "shutdown_grace_period")]
+    pub shutdown_grace_period_secs: Option<u64>,
+    /// visitor 请求的代理未注册时等待其注册的最长时间（秒，可选，默认 10；0 表示立即拒绝）
+    ///
+    /// 注册代理的客户端断线重连期间到达的 visitor 请求在此期间等待代理重新注册，
+    /// 超时后仍未找到代理时返回“未找到”错误
+    #[serde(default, alias = "visitor_retry_timeout")]
+    pub visitor_retry_timeout_secs: Option<u64>,
+    /// 发布端口接入连接的 TCP keepalive 空闲时间（秒，可选，默认不启用）
+    ///
+    /// 代理的 `socket.keepalive_secs` 优先；用于防止经过 NAT 的长时间空闲连接（如 SSH）被丢弃
+    #[serde(default)]
+    pub tcp_keepalive_secs: Option<u64>,
+    /// 发布端口接入连接和 visitor 转发的空闲超时（秒，可选，默认 300；0 表示不限制）
+    ///
+    /// 两个方向都没有数据超过该时间时关闭连接，避免端口扫描等遗弃的连接一直占用资源；
+    /// 配置了 `idle_keepalive_secs` 的代理和复用的 visitor 连接不受此限制
+    #[serde(default, alias = "idle_timeout")]
+    pub idle_timeout_secs: Option<u64>,
+    /// 每个客户端会话同时转发的 yamux stream 上限（可选，默认 1024；0 表示不限制）
+    ///
+    /// 包括服务器为代理和 visitor 打开的 stream 以及客户端打开的 visitor / forwarder stream；
+    /// 超出上限的 stream 请求被拒绝，并通过控制通道向客户端发送警告
+    #[serde(default)]
+    pub max_streams_per_client: Option<u32>,
+    /// 每个代理发布端口同时接入的连接上限（可选，默认 512；0 表示不限制）
+    ///
+    /// 超出上限的连接在接受后立即关闭，计入代理统计的 `connection_limited`
+    #[serde(default)]
+    pub max_connections_per_proxy: Option<u32>,
+}
+
+/// 未配置 `shutdown_grace_period_secs` 时的默认值（秒）
+pub const DEFAULT_SHUTDOWN_GRACE_PERIOD_SECS: u64 = 30;
+
+/// 未配置 `visitor_retry_timeout_secs` 时的默认值（秒）
+pub const DEFAULT_VISITOR_RETRY_TIMEOUT_SECS: u64 = 10;
+
+/// 未配置 `idle_timeout_secs` 时的默认值（秒）
+pub const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 300;
+
+/// 未配置 `max_streams_per_client` 时的默认值
+pub const DEFAULT_MAX_STREAMS_PER_CLIENT: u32 = 1024;
+
+/// 未配置 `max_connections_per_proxy` 时的默认值
+pub const DEFAULT_MAX_CONNECTIONS_PER_PROXY: u32 = 512;
+
+impl ServerConfig {
+    /// 生效的超时设置
+    pub fn timeouts(&self) -> ServerTimeoutsConfig {
+        self.timeouts.unwrap_or_default()
+    }
+
+    /// 停止时排空转发的最长时间
+    pub fn shutdown_grace_period(&self) -> Duration {
+        Duration::from_secs(
+            self.shutdown_grace_period_secs
+                .unwrap_or(DEFAULT_SHUTDOWN_GRACE_PERIOD_SECS),
+        )
+    }
+
+    /// 发布端口接入连接使用的套接字选项：代理未配置 keepalive 时使用 `tcp_keepalive_secs`
+    pub fn publish_socket_options(
+        &self,
+        socket: Option<&SocketOptionsConfig>,
+    ) -> Option<SocketOptionsConfig> {
+        let Some(secs) = self.tcp_keepalive_secs else {
+            return socket.cloned();
+        };
+        let mut options = socket.cloned().unwrap_or_default();
+        options.keepalive_secs.get_or_insert(secs);
+        Some(options)
+    }
+
+    /// visitor 等待代理注册的最长时间
+    pub fn visitor_retry_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.visitor_retry_timeout_secs
+                .unwrap_or(DEFAULT_VISITOR_RETRY_TIMEOUT_SECS),
+        )
+    }
+
+    /// 转发连接的空闲超时（配置为 0 时不限制，返回 None）
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        match self.idle_timeout_secs.unwrap_or(DEFAULT_IDLE_TIMEOUT_SECS) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    /// 每个客户端会话的 stream 上限（配置为 0 时不限制，返回 None）
+    pub fn max_streams_per_client(&self) -> Option<u32> {
+        match self
+            .max_streams_per_client
+            .unwrap_or(DEFAULT_MAX_STREAMS_PER_CLIENT)
+        {
+            0 => None,
+            max => Some(max),
+        }
+    }
+
+    /// 每个代理发布端口的连接上限（配置为 0 时不限制，返回 None）
+    pub fn max_connections_per_proxy(&self) -> Option<u32> {
+        match self
+            .max_connections_per_proxy
+            .unwrap_or(DEFAULT_MAX_CONNECTIONS_PER_PROXY)
+        {
+            0 => None,
+            max => Some(max),
+        }
+    }
 }
 
 /// 速率限制配置
@@ -238,6 +1019,656 @@ impl Default for SizeLimitConfig {
     }
 }
 
+/// 统计 HTTP 服务器防护配置（防止慢速连接耗尽资源）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatsLimitConfig {
+    /// 读取完整请求的超时时间（秒）
+    pub read_timeout_secs: u64,
+    /// 写入响应的超时时间（秒）
+    pub write_timeout_secs: u64,
+    /// 最大并发连接数
+    pub max_connections: usize,
+    /// 最大请求大小（字节）
+    pub max_request_size: usize,
+    /// 监听失败（端口被占用、监听套接字失效）后连续重新绑定的最大次数（可选，默认不限；0 表示不重新绑定）
+    pub max_rebind_attempts: Option<u32>,
+}
+
+impl Default for StatsLimitConfig {
+    fn default() -> Self {
+        Self {
+            read_timeout_secs: 10,
+            write_timeout_secs: 10,
+            max_connections: 64,
+            max_request_size: 8 * 1024, // 8KB
+            max_rebind_attempts: None,
+        }
+    }
+}
+
+/// 统计服务器 `/stats/ws` WebSocket 推送配置
+///
+/// 连接后先推送完整快照，之后按最小间隔推送变化的代理和注册表事件；
+/// 每个连接最多缓存 `max_pending_events` 个待发送事件，超出时改为推送完整快照
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatsStreamConfig {
+    /// 两次推送之间的最小间隔（毫秒）
+    pub min_interval_ms: u64,
+    /// 每个连接缓存的待发送事件上限
+    pub max_pending_events: usize,
+    /// 认证令牌（可选）：设置后客户端的第一条消息必须是
+    /// `{"type":"auth","token":"..."}`，否则以 1008 关闭连接
+    pub auth_token: Option<String>,
+}
+
+impl Default for StatsStreamConfig {
+    fn default() -> Self {
+        Self {
+            min_interval_ms: 1000,
+            max_pending_events: 256,
+            auth_token: None,
+        }
+    }
+}
+
+/// 异常通知限流配置（防止异常通知淹没控制通道）
+///
+/// 窗口内相同（code, message）的通知合并为一条并带上 `occurrences` 计数，
+/// 超出速率的通知被抑制，并定期以一条汇总通知报告抑制数量
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExceptionLimitConfig {
+    /// 每分钟允许发送的通知数
+    pub per_minute: u32,
+    /// 突发容量
+    pub burst: u32,
+    /// 相同通知的合并窗口（秒）
+    pub coalesce_window_secs: u64,
+    /// 抑制汇总的最短间隔（秒）
+    pub summary_interval_secs: u64,
+    /// 待发送通知队列的容量，队列满时新通知计入抑制数量
+    pub queue_size: usize,
+}
+
+impl Default for ExceptionLimitConfig {
+    fn default() -> Self {
+        Self {
+            per_minute: 30,
+            burst: 10,
+            coalesce_window_secs: 10,
+            summary_interval_secs: 60,
+            queue_size: 64,
+        }
+    }
+}
+
+/// forward（`@forward`）连接的服务端限制
+///
+/// 单个连接超过存活时间或双向传输字节数时被关闭，每个客户端会话同时进行的
+/// forward 连接数超过上限时新请求被拒绝；这三项未设置时不限制。
+/// 连接外部目标的超时、失败目标的冷却时间和同时连接中的目标数始终生效，未设置时使用默认值
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ForwardLimitConfig {
+    /// 单个 forward 连接的最长存活时间（秒）
+    pub max_duration_secs: Option<u64>,
+    /// 单个 forward 连接双向合计的最大传输字节数
+    pub max_bytes: Option<u64>,
+    /// 每个客户端会话同时进行的 forward 连接数上限
+    pub max_concurrent_per_client: Option<usize>,
+    /// 连接外部目标的超时（秒，默认 10，远短于系统的 TCP 连接超时）
+    pub connect_timeout_secs: Option<u64>,
+    /// 连接失败的目标在该时长内被同一客户端会话再次请求时立即拒绝
+    /// （秒，默认 30，0 表示不拒绝）
+    pub failure_cooldown_secs: Option<u64>,
+    /// 每个客户端会话同时在连接中（尚未建立）的 forward 目标数上限（默认 16）
+    pub max_connecting_per_client: Option<usize>,
+}
+
+/// forward 目标访问策略
+///
+/// 目标先按端口和拒绝规则检查：端口不在 `allowed_ports` 中（列表非空时）、域名匹配
+/// `denied_domains` 或解析出的任一地址落在 `denied_cidrs` 中时拒绝。其余目标只有在域名匹配
+/// `allowed_domains`，或解析出的所有地址都落在 `allowed_cidrs` 中时才允许，否则拒绝。
+/// 域名规则支持通配符（`*.internal.corp` 同时匹配 `internal.corp`），地址规则为 IP 或 CIDR。
+///
+/// 配置策略后由策略决定可以访问的目标，不再使用内置的本地和内网地址检查；
+/// 允许通配域名时建议在 `denied_cidrs` 中列出 `169.254.0.0/16` 等元数据服务地址
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ForwardPolicyConfig {
+    /// 允许访问的域名
+    pub allowed_domains: Vec<String>,
+    /// 禁止访问的域名（优先于允许规则）
+    pub denied_domains: Vec<String>,
+    /// 允许访问的 IP/CIDR
+    pub allowed_cidrs: Vec<String>,
+    /// 禁止访问的 IP/CIDR（优先于允许规则）
+    pub denied_cidrs: Vec<String>,
+    /// 允许访问的端口（为空时不限制端口）
+    pub allowed_ports: Vec<u16>,
+}
+
+/// 被服务器拒绝的代理的自动重试配置
+///
+/// 会话运行期间按退避间隔只重新提交被拒绝的代理，直到全部注册成功
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxyRetryConfig {
+    /// 首次重试前的等待时间（秒）
+    pub interval_secs: u64,
+    /// 连续失败时退避的最大等待时间（秒）
+    pub max_interval_secs: u64,
+}
+
+impl Default for ProxyRetryConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 30,
+            max_interval_secs: 300,
+        }
+    }
+}
+
+/// 服务器超时设置
+///
+/// 握手超时（`handshake_timeout_secs`）和代理下线的排空时长（`drain_timeout_secs`，可按代理覆盖）
+/// 仍在服务器配置的顶层设置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerTimeoutsConfig {
+    /// 会话完成认证和配置提交的最长时间（秒），超时未进入运行状态的会话被关闭
+    pub session_setup_secs: u64,
+    /// 会话运行期间超过该时长未收到心跳或其他控制消息即结束会话并注销其代理（秒），
+    /// 必须大于客户端的心跳间隔
+    pub heartbeat_timeout_secs: u64,
+    /// 读取 visitor/forward stream 前导和身份校验结果的超时（秒）
+    pub preamble_read_secs: u64,
+    /// 共享代理向单个后端请求 stream 的超时（秒），超时后尝试下一个后端
+    pub stream_open_secs: u64,
+    /// SNI 路由代理等待 TLS ClientHello 的超时（毫秒），超时后按默认路由转发
+    pub client_hello_ms: u64,
+    /// 服务器停止时（排空 `shutdown_grace_period_secs` 之后）等待会话清理的最长时间（秒）
+    pub shutdown_grace_secs: u64,
+    /// UDP 代理的来源会话在两个方向都没有数据报超过该时长后结束（秒）
+    pub udp_session_idle_secs: u64,
+}
+
+impl Default for ServerTimeoutsConfig {
+    fn default() -> Self {
+        Self {
+            session_setup_secs: 30,
+            heartbeat_timeout_secs: 120,
+            preamble_read_secs: 30,
+            stream_open_secs: 5,
+            client_hello_ms: 3000,
+            shutdown_grace_secs: 10,
+            udp_session_idle_secs: 60,
+        }
+    }
+}
+
+impl ServerTimeoutsConfig {
+    pub fn session_setup(&self) -> Duration {
+        Duration::from_secs(self.session_setup_secs)
+    }
+
+    pub fn heartbeat_timeout(&self) -> Duration {
+        Duration::from_secs(self.heartbeat_timeout_secs)
+    }
+
+    pub fn preamble_read(&self) -> Duration {
+        Duration::from_secs(self.preamble_read_secs)
+    }
+
+    pub fn stream_open(&self) -> Duration {
+        Duration::from_secs(self.stream_open_secs)
+    }
+
+    pub fn client_hello(&self) -> Duration {
+        Duration::from_millis(self.client_hello_ms)
+    }
+
+    pub fn shutdown_grace(&self) -> Duration {
+        Duration::from_secs(self.shutdown_grace_secs)
+    }
+
+    pub fn udp_session_idle(&self) -> Duration {
+        Duration::from_secs(self.udp_session_idle_secs)
+    }
+}
+
+/// 客户端超时设置
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientTimeoutsConfig {
+    /// forwarder 和 visitor 网关解析 HTTP/SOCKS5 请求的超时（秒），必须小于连接空闲超时
+    pub protocol_parse_secs: u64,
+    /// forwarder 和 visitor 网关的连接空闲超时（秒），forwarder 可以用 `idle_timeout_secs` 单独覆盖
+    pub connection_idle_secs: u64,
+    /// 心跳间隔（秒），必须小于服务器的心跳超时（默认 120 秒）
+    pub heartbeat_interval_secs: u64,
+    /// 打开 visitor/forward stream 后等待服务器确认的超时（秒）
+    pub stream_open_secs: u64,
+    /// 连接本地服务的超时（毫秒，环境变量 `TLS_TUNNEL_POOL_CONNECT_TIMEOUT_MS` 优先）
+    pub local_connect_ms: u64,
+    /// 连接本地服务失败后重试的间隔（毫秒，环境变量 `TLS_TUNNEL_LOCAL_RETRY_DELAY_MS` 优先）
+    pub local_retry_delay_ms: u64,
+    /// 断线后第一次重连前的等待时间（秒，环境变量 `TLS_TUNNEL_RECONNECT_DELAY_SECS` 优先）
+    pub reconnect_delay_secs: u64,
+    /// 连续重连失败时等待时间的倍数
+    pub reconnect_multiplier: f64,
+    /// 重连等待时间的上限（秒）
+    pub reconnect_max_delay_secs: u64,
+    /// 退出时等待隧道连接关闭的最长时间（秒）
+    pub shutdown_grace_secs: u64,
+}
+
+impl Default for ClientTimeoutsConfig {
+    fn default() -> Self {
+        Self {
+            protocol_parse_secs: 30,
+            connection_idle_secs: 300,
+            heartbeat_interval_secs: 30,
+            stream_open_secs: 60,
+            local_connect_ms: 5000,
+            local_retry_delay_ms: 1000,
+            reconnect_delay_secs: 5,
+            reconnect_multiplier: 2.0,
+            reconnect_max_delay_secs: 300,
+            shutdown_grace_secs: 2,
+        }
+    }
+}
+
+impl ClientTimeoutsConfig {
+    pub fn protocol_parse(&self) -> Duration {
+        Duration::from_secs(self.protocol_parse_secs)
+    }
+
+    pub fn connection_idle(&self) -> Duration {
+        Duration::from_secs(self.connection_idle_secs)
+    }
+
+    pub fn heartbeat_interval(&self) -> Duration {
+        Duration::from_secs(self.heartbeat_interval_secs)
+    }
+
+    pub fn stream_open(&self) -> Duration {
+        Duration::from_secs(self.stream_open_secs)
+    }
+
+    pub fn local_connect(&self) -> Duration {
+        Duration::from_millis(self.local_connect_ms)
+    }
+
+    pub fn local_retry_delay(&self) -> Duration {
+        Duration::from_millis(self.local_retry_delay_ms)
+    }
+
+    pub fn reconnect_delay(&self) -> Duration {
+        Duration::from_secs(self.reconnect_delay_secs)
+    }
+
+    pub fn reconnect_max_delay(&self) -> Duration {
+        Duration::from_secs(self.reconnect_max_delay_secs)
+    }
+
+    pub fn shutdown_grace(&self) -> Duration {
+        Duration::from_secs(self.shutdown_grace_secs)
+    }
+
+    /// forwarder 的连接空闲超时（forwarder 配置的 `idle_timeout_secs` 优先）
+    pub fn forwarder_idle(&self, forwarder: &ForwarderConfig) -> Duration {
+        forwarder
+            .idle_timeout_secs
+            .map_or(self.connection_idle(), Duration::from_secs)
+    }
+}
+
+/// 健康评分设置（`[health]`）
+///
+/// 分数满分 100，低于 `yellow_below` 为 yellow，低于 `red_below` 为 red
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// 分数低于该值时状态为 yellow
+    pub yellow_below: u32,
+    /// 分数低于该值时状态为 red
+    pub red_below: u32,
+    /// 状态好转时分数需要高出阈值的分数，避免在阈值附近来回切换
+    pub hysteresis: u32,
+    /// 失败率等计数按最近多少秒的增量计算
+    pub window_secs: u64,
+    /// 心跳往返时延超过该值（毫秒）开始扣分
+    pub rtt_warn_ms: u64,
+    /// 心跳往返时延达到该值（毫秒）时扣分最多
+    pub rtt_critical_ms: u64,
+    /// 代理建立隧道 stream 的失败率超过该值开始扣分
+    pub failure_ratio_warn: f64,
+    /// 失败率达到该值时扣分最多
+    pub failure_ratio_critical: f64,
+    /// 后台评估的间隔（秒，仅 `notify = true` 时使用）
+    pub interval_secs: u64,
+    /// 总体状态变化时发布 `health_changed` 会话事件（同样镜像到 events_socket）
+    pub notify: bool,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            yellow_below: 80,
+            red_below: 50,
+            hysteresis: 5,
+            window_secs: 300,
+            rtt_warn_ms: 300,
+            rtt_critical_ms: 1000,
+            failure_ratio_warn: 0.05,
+            failure_ratio_critical: 0.25,
+            interval_secs: 10,
+            notify: false,
+        }
+    }
+}
+
+impl HealthConfig {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+}
+
+/// 连接事件导出配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventExportConfig {
+    /// 采集器地址：`unix:/path/to/collector.sock` 或 `tcp:host:port`
+    pub target: String,
+    /// 导出格式（目前仅支持 jsonl）
+    #[serde(default)]
+    pub format: EventExportFormat,
+    /// 内部事件队列容量，队列满时丢弃新事件并计数
+    #[serde(default = "default_event_queue_size")]
+    pub queue_size: usize,
+}
+
+/// 事件导出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventExportFormat {
+    /// 每行一个 JSON 对象
+    #[default]
+    Jsonl,
+}
+
+fn default_event_queue_size() -> usize {
+    1024
+}
+
+/// 事件采集器地址
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventExportTarget {
+    /// Unix 域套接字（流式）
+    Unix(PathBuf),
+    /// TCP 地址（host:port）
+    Tcp(String),
+}
+
+impl EventExportConfig {
+    /// 解析 `target`
+    pub fn parse_target(&self) -> anyhow::Result<EventExportTarget> {
+        let target = self.target.trim();
+        if let Some(path) = target.strip_prefix("unix:") {
+            if path.is_empty() {
+                anyhow::bail!("event_export.target '{}' has an empty socket path", target);
+            }
+            if !cfg!(unix) {
+                anyhow::bail!("event_export.target 'unix:' is only supported on Unix platforms");
+            }
+            return Ok(EventExportTarget::Unix(PathBuf::from(path)));
+        }
+        if let Some(addr) = target.strip_prefix("tcp:") {
+            let valid = addr
+                .rsplit_once(':')
+                .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok());
+            if !valid {
+                anyhow::bail!(
+                    "event_export.target '{}' must be in the form tcp:host:port",
+                    target
+                );
+            }
+            return Ok(EventExportTarget::Tcp(addr.to_string()));
+        }
+        anyhow::bail!(
+            "event_export.target '{}' must start with 'unix:' or 'tcp:'",
+            target
+        )
+    }
+}
+
+/// 流量记录导出配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowExportConfig {
+    /// 记录去向：`file:/path/to/flows.jsonl`（追加写入 JSON 行）或 `events`（经 `event_export` 发送）
+    pub sink: String,
+    /// 长连接输出中间记录的间隔（秒，默认 300；0 表示只在连接结束时输出记录）
+    #[serde(default = "default_flow_interim_interval_secs")]
+    pub interim_interval_secs: u64,
+    /// 每批写出的最大记录数（默认 100）
+    #[serde(default = "default_flow_batch_size")]
+    pub batch_size: usize,
+    /// 未攒满一批时最长等待多久写出（毫秒，默认 1000）
+    #[serde(default = "default_flow_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+    /// 内部记录队列容量，队列满时丢弃新记录并计数
+    #[serde(default = "default_flow_queue_size")]
+    pub queue_size: usize,
+}
+
+fn default_flow_interim_interval_secs() -> u64 {
+    300
+}
+
+fn default_flow_batch_size() -> usize {
+    100
+}
+
+fn default_flow_flush_interval_ms() -> u64 {
+    1000
+}
+
+fn default_flow_queue_size() -> usize {
+    4096
+}
+
+/// 流量记录去向
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FlowExportSink {
+    /// 追加写入的 JSONL 文件
+    File(PathBuf),
+    /// 经事件导出发送给采集器
+    Events,
+}
+
+impl FlowExportConfig {
+    /// 解析 `sink`
+    pub fn parse_sink(&self) -> anyhow::Result<FlowExportSink> {
+        let sink = self.sink.trim();
+        if sink == "events" {
+            return Ok(FlowExportSink::Events);
+        }
+        if let Some(path) = sink.strip_prefix("file:") {
+            if path.is_empty() {
+                anyhow::bail!("flow_export.sink '{}' has an empty file path", sink);
+            }
+            return Ok(FlowExportSink::File(PathBuf::from(path)));
+        }
+        anyhow::bail!(
+            "flow_export.sink '{}' must be 'events' or start with 'file:'",
+            sink
+        )
+    }
+
+    /// 中间记录的间隔（未启用时为 None）
+    pub fn interim_interval(&self) -> Option<Duration> {
+        (self.interim_interval_secs > 0).then(|| Duration::from_secs(self.interim_interval_secs))
+    }
+
+    /// 未攒满一批时的最长等待
+    pub fn flush_interval(&self) -> Duration {
+        Duration::from_millis(self.flush_interval_ms)
+    }
+}
+
+/// Visitor 访问控制配置
+///
+/// 规则是代理名称的 glob（`*` 匹配任意个字符，`?` 匹配一个字符），`visitor_deny` 优先于
+/// `visitor_allow`；未设置 `visitor_allow` 时允许访问所有未被拒绝的代理。
+/// `@forward` 请求不受这里的规则约束（见 `allow_forward`、`forward_limits`）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VisitorAclConfig {
+    /// 默认规则：允许访问的代理名称（身份不在 `clients` 中的客户端使用）
+    #[serde(default)]
+    pub visitor_allow: Option<Vec<String>>,
+    /// 默认规则：禁止访问的代理名称
+    #[serde(default)]
+    pub visitor_deny: Vec<String>,
+    /// 按客户端身份指纹设置的规则（取代默认规则）
+    #[serde(default)]
+    pub clients: Vec<ClientVisitorAclConfig>,
+}
+
+/// 单个客户端身份的 visitor 访问规则
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientVisitorAclConfig {
+    /// 客户端身份指纹（`SHA256:...`，同 `known_clients`）
+    pub identity: String,
+    /// 允许访问的代理名称（未设置时允许所有未被拒绝的代理）
+    #[serde(default)]
+    pub visitor_allow: Option<Vec<String>>,
+    /// 禁止访问的代理名称
+    #[serde(default)]
+    pub visitor_deny: Vec<String>,
+}
+
+impl VisitorAclConfig {
+    /// 客户端身份适用的规则（allow, deny），身份未单独配置时为默认规则
+    pub fn rules_for(&self, identity: Option<&str>) -> (Option<&[String]>, &[String]) {
+        let client = identity.and_then(|identity| {
+            self.clients
+                .iter()
+                .find(|c| crate::identity::fingerprint_eq(&c.identity, identity))
+        });
+        match client {
+            Some(client) => (client.visitor_allow.as_deref(), &client.visitor_deny),
+            None => (self.visitor_allow.as_deref(), &self.visitor_deny),
+        }
+    }
+}
+
+/// 附加的认证密钥及其权限
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthKeyConfig {
+    /// 密钥名称（用于日志和拒绝原因）
+    pub name: String,
+    /// 认证密钥
+    pub key: String,
+    /// 允许发布的端口（`8000` 或 `"8000-8100"`，可选，未设置时不限制）
+    #[serde(default)]
+    pub allowed_ports: Option<Vec<PortRange>>,
+    /// 是否允许 forward（可选，未设置时使用服务器的 `allow_forward`）
+    #[serde(default)]
+    pub allow_forward: Option<bool>,
+}
+
+/// 端口范围（包含两端），配置中写作端口号或 `"起始-结束"` 字符串
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl PortRange {
+    /// 端口是否在范围内
+    pub fn contains(&self, port: u16) -> bool {
+        (self.start..=self.end).contains(&port)
+    }
+}
+
+impl std::fmt::Display for PortRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+impl std::str::FromStr for PortRange {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let parse = |s: &str| match s.trim().parse::<u16>() {
+            Ok(port) if port != 0 => Ok(port),
+            _ => Err(format!(
+                "Invalid port '{}' in port range '{}'",
+                s.trim(),
+                spec
+            )),
+        };
+        let (start, end) = match spec.split_once('-') {
+            Some((start, end)) => (parse(start)?, parse(end)?),
+            None => {
+                let port = parse(spec)?;
+                (port, port)
+            }
+        };
+        if start > end {
+            return Err(format!("Port range '{}' starts after it ends", spec));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl Serialize for PortRange {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PortRange {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PortRangeVisitor;
+
+        impl serde::de::Visitor<'_> for PortRangeVisitor {
+            type Value = PortRange;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a port number or a \"start-end\" port range")
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, port: i64) -> Result<PortRange, E> {
+                port.to_string().parse().map_err(E::custom)
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, port: u64) -> Result<PortRange, E> {
+                port.to_string().parse().map_err(E::custom)
+            }
+
+            fn visit_str<E: serde::de::Error>(self, spec: &str) -> Result<PortRange, E> {
+                spec.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(PortRangeVisitor)
+    }
+}
+
 impl ServerConfig {
     /// 创建 Builder
     pub fn builder() -> ServerConfigBuilder {
@@ -268,15 +1699,101 @@ pub struct ClientConfig {
     pub skip_verify: bool,
     /// CA 证书路径（可选）
     pub ca_cert_path: Option<PathBuf>,
+    /// 客户端证书路径（可选，服务器启用 mTLS 时使用）
+    #[serde(default)]
+    pub client_cert_path: Option<PathBuf>,
+    /// 客户端证书私钥路径（与 `client_cert_path` 同时设置）
+    #[serde(default)]
+    pub client_key_path: Option<PathBuf>,
     /// 认证密钥（用于服务器认证）
     pub auth_key: String,
     /// HTTP 统计信息服务器端口（可选）
     pub stats_port: Option<u16>,
     /// HTTP 统计信息服务器绑定地址（可选，默认为 127.0.0.1）
     pub stats_addr: Option<String>,
+    /// 统计 HTTP 服务器防护配置（可选，不设置时使用默认值）
+    #[serde(default)]
+    pub stats_limits: Option<StatsLimitConfig>,
+    /// 统计服务器 `/stats/ws` 实时推送配置（可选，不设置时使用默认值）
+    #[serde(default)]
+    pub stats_stream: Option<StatsStreamConfig>,
+    /// 统计服务器的 Unix 套接字路径（可选，仅 Unix），与 stats_port 相互独立，
+    /// 本机工具无需占用 TCP 端口即可查询
+    #[serde(default)]
+    pub stats_socket: Option<PathBuf>,
+    /// 统计服务器的命名管道名称（可选，仅 Windows，如 `\\.\pipe\tls-tunnel-client`）
+    #[serde(default)]
+    pub stats_pipe: Option<String>,
+    /// 会话事件镜像目标（可选，tcp://host:port 或 unix:///path）
+    #[serde(default)]
+    pub events_socket: Option<String>,
+    /// 客户端稳定身份（可选），注册 proxy 时上报，供 visitor 通过 expected_peer_id 校验
+    #[serde(default)]
+    pub peer_id: Option<String>,
+    /// 客户端身份密钥文件（可选，默认为配置文件旁的 `<文件名>.identity.pem`），
+    /// 不存在时自动生成 Ed25519 密钥对（Unix 下权限 0600）
+    #[serde(default)]
+    pub identity_path: Option<PathBuf>,
+    /// 客户端状态目录（可选，默认为配置文件所在目录），保存最近一次被服务器接受的配置
+    /// （`last_good_config.json`）
+    #[serde(default)]
+    pub state_dir: Option<PathBuf>,
+    /// 启动时配置文件无法加载或校验失败时，改用最近一次被服务器接受的配置（默认 false），
+    /// 并在后台继续检查配置文件，修复后切换过去
+    #[serde(default)]
+    pub fallback_to_last_good: bool,
+    /// 定期检查配置文件（默认 false），内容变化时在不断开会话的情况下应用 proxies、visitors
+    /// 和 forwarders 的增删改；Unix 下也可以发送 SIGHUP 立即重新加载
+    #[serde(default)]
+    pub watch_config: bool,
+    /// 路由规则覆盖文件（可选），启动时合并到 forwarder 的 routing 配置之上，
+    /// 通过 `/routing` 页面修改的规则也保存到该文件
+    #[serde(default)]
+    pub routing_overrides_path: Option<PathBuf>,
+    /// 路由设置页面（统计服务器 `/routing`）的访问口令（可选，未设置时不启用该页面），
+    /// 使用 HTTP Basic 认证，用户名任意
+    #[serde(default)]
+    pub routing_ui_token: Option<String>,
+    /// 请求大小限制配置（可选），限制 HTTP forwarder 解析的请求头大小
+    #[serde(default)]
+    pub size_limits: Option<SizeLimitConfig>,
+    /// 被服务器拒绝的代理的自动重试（可选，未设置时不重试，直到重新连接）
+    #[serde(default)]
+    pub proxy_retry: Option<ProxyRetryConfig>,
+    /// 单次写入传输连接的最大字节数，即每条 TLS 记录的上限（可选，默认不限制）
+    ///
+    /// 路径 MTU 异常（如 PPPoE）导致传输停滞时可尝试调小，如 1200
+    #[serde(default)]
+    pub max_write_chunk: Option<usize>,
+    /// 转发停滞诊断：有数据待写出但长时间无进展时记录警告
+    #[serde(default)]
+    pub debug_stalls: bool,
+    /// 协议跟踪文件（可选）：控制帧和 stream 前导以 JSON 行追加写入，不记录转发的数据
+    #[serde(default)]
+    pub protocol_trace_path: Option<PathBuf>,
+    /// 隧道断开期间保持 visitor/forwarder 的本地监听（默认 true）：
+    /// 断开期间的本地连接立即以隧道不可用失败，重连后继续使用同一个监听套接字
+    #[serde(default = "default_keep_listening_on_disconnect")]
+    pub keep_listening_on_disconnect: bool,
+    /// 连续多少次心跳未被服务器确认后断开会话并重连（可选，默认 3；服务器不支持心跳确认时不生效）
+    #[serde(default)]
+    pub max_missed_heartbeats: Option<u32>,
+    /// 所有转发连接的拷贝缓冲区合计上限（MB，可选，默认不限制）
+    ///
+    /// 余量紧张时新连接使用更小的缓冲区（不小于 4KB），用尽时等待其他连接释放
+    #[serde(default)]
+    pub relay_memory_budget_mb: Option<u64>,
+    /// 超时设置（可选，未配置的项使用默认值）
+    #[serde(default)]
+    pub timeouts: Option<ClientTimeoutsConfig>,
 }
 
 impl ClientConfig {
+    /// 生效的超时设置
+    pub fn timeouts(&self) -> ClientTimeoutsConfig {
+        self.timeouts.unwrap_or_default()
+    }
+
     /// 创建 Builder
     pub fn builder() -> ClientConfigBuilder {
         ClientConfigBuilder::new()
@@ -287,6 +1804,10 @@ fn default_server_path() -> String {
     "/".to_string()
 }
 
+fn default_keep_listening_on_disconnect() -> bool {
+    true
+}
+
 /// 客户端完整配置（包含代理列表）
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClientFullConfig {
@@ -300,6 +1821,12 @@ pub struct ClientFullConfig {
     /// Forwarder 配置列表（转发到外部网络）
     #[serde(default)]
     pub forwarders: Vec<ForwarderConfig>,
+    /// Visitor 网关（按 SOCKS5 目标主机名访问代理，可选）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visitor_gateway: Option<VisitorGatewayConfig>,
+    /// 健康评分设置（可选，未配置的项使用默认值）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<HealthConfig>,
 }
 
 impl ClientFullConfig {
@@ -308,6 +1835,11 @@ impl ClientFullConfig {
         ClientFullConfigBuilder::new()
     }
 
+    /// 生效的健康评分设置
+    pub fn health(&self) -> HealthConfig {
+        self.health.unwrap_or_default()
+    }
+
     /// 验证配置（保持向后兼容）
     pub fn validate(&self) -> anyhow::Result<()> {
         ConfigValidator::validate_client_full_config(self)
@@ -347,6 +1879,11 @@ impl AppConfig {
         }
 
         let content = std::fs::read_to_string(path)?;
+        let table: toml::Table =
+            toml::from_str(&content).context("Failed to parse server configuration")?;
+        strict::check(&table, &strict::server_schema())
+            .map_err(anyhow::Error::msg)
+            .context("Failed to parse server configuration")?;
         let wrapper: ServerConfigWrapper =
             toml::from_str(&content).context("Failed to parse server configuration")?;
         wrapper
@@ -359,11 +1896,16 @@ impl AppConfig {
     /// 从文件加载客户端配置
     pub fn load_client_config(path: &str) -> anyhow::Result<ClientFullConfig> {
         let content = std::fs::read_to_string(path)?;
-        let config: ClientFullConfig =
-            toml::from_str(&content).context("Failed to parse client configuration")?;
+        let (config, compat) = compat::parse_client_config(&content)
+            .context("Failed to parse client configuration")?;
+        ConfigValidator::validate_field_aliases(&compat)
+            .context("Configuration validation failed")?;
         config
             .validate()
             .context("Configuration validation failed")?;
+        if let Some(warning) = compat.deprecation_warning() {
+            tracing::warn!("{}", warning);
+        }
         Ok(config)
     }
 }
@@ -408,6 +1950,11 @@ mod tests {
 
         let http11: ProxyType = serde_json::from_str("\"http/1.1\"").unwrap();
         assert_eq!(http11, ProxyType::Http11);
+
+        let auto: ProxyType = serde_json::from_str("\"auto\"").unwrap();
+        assert_eq!(auto, ProxyType::AutoProxy);
+        assert!(auto.is_forwarder_type());
+        assert!(!ProxyType::Tcp.is_forwarder_type());
     }
 
     #[test]
@@ -429,9 +1976,39 @@ mod tests {
             auth_key: "a".repeat(20),
             stats_port: None,
             stats_addr: None,
+            stats_path: None,
             allow_forward: false,
             rate_limit: None,
             size_limits: None,
+            stats_limits: None,
+            stats_stream: None,
+            interface_prefer_ipv6: false,
+            event_export: None,
+            max_write_chunk: None,
+            debug_stalls: false,
+            debug_tls_handshakes: false,
+            exception_limits: None,
+            drain_timeout_secs: None,
+            protocol_trace_path: None,
+            forward_limits: None,
+            forward_policy: None,
+            max_concurrent_handshakes: None,
+            handshake_timeout_secs: None,
+            idle_registration_expiry_secs: None,
+            relay_memory_budget_mb: None,
+            known_clients: None,
+            visitor_acl: None,
+            timeouts: None,
+            flow_export: None,
+            shutdown_grace_period_secs: None,
+            visitor_retry_timeout_secs: None,
+            tcp_keepalive_secs: None,
+            idle_timeout_secs: None,
+            max_streams_per_client: None,
+            max_connections_per_proxy: None,
+            auth_keys: None,
+            client_ca_path: None,
+            require_client_cert: false,
         };
 
         // 有效配置
@@ -444,6 +2021,16 @@ mod tests {
         // 修复密钥后继续测试
         config.auth_key = "a".repeat(20);
 
+        // 无效：要求客户端证书但没有 CA
+        config.require_client_cert = true;
+        assert!(config
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("client_ca_path"));
+        config.client_ca_path = Some(PathBuf::from("/client-ca.pem"));
+        assert!(config.validate().is_ok());
+
         // 无效：证书路径不匹配
         config.cert_path = Some(PathBuf::from("/cert.pem"));
         config.key_path = None;
@@ -462,6 +2049,29 @@ mod tests {
             auth_key: "a".repeat(20),
             stats_port: None,
             stats_addr: None,
+            stats_limits: None,
+            stats_stream: None,
+            events_socket: None,
+            peer_id: None,
+            routing_overrides_path: None,
+            routing_ui_token: None,
+            size_limits: None,
+            stats_socket: None,
+            stats_pipe: None,
+            proxy_retry: None,
+            max_write_chunk: None,
+            debug_stalls: false,
+            protocol_trace_path: None,
+            keep_listening_on_disconnect: true,
+            max_missed_heartbeats: None,
+            relay_memory_budget_mb: None,
+            identity_path: None,
+            state_dir: None,
+            fallback_to_last_good: false,
+            watch_config: false,
+            client_cert_path: None,
+            client_key_path: None,
+            timeouts: None,
         };
 
         assert_eq!(config.server_port, 8443);
@@ -479,6 +2089,88 @@ mod tests {
         assert!(config.is_ok());
     }
 
+    #[test]
+    fn test_publish_socket_options_default_keepalive() {
+        let mut config = ServerConfig::builder()
+            .bind_addr("0.0.0.0")
+            .bind_port(8443)
+            .auth_key("1234567890123456")
+            .build()
+            .unwrap();
+        let proxy_socket = SocketOptionsConfig {
+            nodelay: false,
+            ..Default::default()
+        };
+
+        // 未配置服务器默认值时保持代理的设置
+        assert_eq!(config.publish_socket_options(None), None);
+        assert_eq!(
+            config.publish_socket_options(Some(&proxy_socket)),
+            Some(proxy_socket.clone())
+        );
+
+        config.tcp_keepalive_secs = Some(60);
+        let merged = config.publish_socket_options(None).unwrap();
+        assert!(merged.nodelay);
+        assert_eq!(merged.keepalive_secs, Some(60));
+        let merged = config.publish_socket_options(Some(&proxy_socket)).unwrap();
+        assert!(!merged.nodelay);
+        assert_eq!(merged.keepalive_secs, Some(60));
+
+        // 代理自己的 keepalive 优先
+        let own = SocketOptionsConfig {
+            keepalive_secs: Some(15),
+            ..Default::default()
+        };
+        assert_eq!(
+            config
+                .publish_socket_options(Some(&own))
+                .unwrap()
+                .keepalive_secs,
+            Some(15)
+        );
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        let mut config = ServerConfig::builder()
+            .bind_addr("0.0.0.0")
+            .bind_port(8443)
+            .auth_key("1234567890123456")
+            .build()
+            .unwrap();
+        assert_eq!(
+            config.idle_timeout(),
+            Some(Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SECS))
+        );
+        config.idle_timeout_secs = Some(30);
+        assert_eq!(config.idle_timeout(), Some(Duration::from_secs(30)));
+        config.idle_timeout_secs = Some(0);
+        assert_eq!(config.idle_timeout(), None);
+    }
+
+    #[test]
+    fn test_session_limits() {
+        let mut config = ServerConfig::builder()
+            .bind_addr("0.0.0.0")
+            .bind_port(8443)
+            .auth_key("1234567890123456")
+            .build()
+            .unwrap();
+        assert_eq!(
+            config.max_streams_per_client(),
+            Some(DEFAULT_MAX_STREAMS_PER_CLIENT)
+        );
+        assert_eq!(
+            config.max_connections_per_proxy(),
+            Some(DEFAULT_MAX_CONNECTIONS_PER_PROXY)
+        );
+        config.max_streams_per_client = Some(0);
+        config.max_connections_per_proxy = Some(8);
+        assert_eq!(config.max_streams_per_client(), None);
+        assert_eq!(config.max_connections_per_proxy(), Some(8));
+    }
+
     #[test]
     fn test_client_config_builder() {
         let config = ClientConfig::builder()
@@ -521,9 +2213,39 @@ mod tests {
             auth_key: "a".repeat(20),
             stats_port: None,
             stats_addr: None,
+            stats_path: None,
             allow_forward: false,
             rate_limit: Some(rate_limit),
             size_limits: None,
+            stats_limits: None,
+            stats_stream: None,
+            interface_prefer_ipv6: false,
+            event_export: None,
+            max_write_chunk: None,
+            debug_stalls: false,
+            debug_tls_handshakes: false,
+            exception_limits: None,
+            drain_timeout_secs: None,
+            protocol_trace_path: None,
+            forward_limits: None,
+            forward_policy: None,
+            max_concurrent_handshakes: None,
+            handshake_timeout_secs: None,
+            idle_registration_expiry_secs: None,
+            relay_memory_budget_mb: None,
+            known_clients: None,
+            visitor_acl: None,
+            timeouts: None,
+            flow_export: None,
+            shutdown_grace_period_secs: None,
+            visitor_retry_timeout_secs: None,
+            tcp_keepalive_secs: None,
+            idle_timeout_secs: None,
+            max_streams_per_client: None,
+            max_connections_per_proxy: None,
+            auth_keys: None,
+            client_ca_path: None,
+            require_client_cert: false,
         };
 
         assert!(config.validate().is_ok());
@@ -547,9 +2269,39 @@ mod tests {
             auth_key: "a".repeat(20),
             stats_port: None,
             stats_addr: None,
+            stats_path: None,
             allow_forward: false,
             rate_limit: None,
             size_limits: Some(size_limits),
+            stats_limits: None,
+            stats_stream: None,
+            interface_prefer_ipv6: false,
+            event_export: None,
+            max_write_chunk: None,
+            debug_stalls: false,
+            debug_tls_handshakes: false,
+            exception_limits: None,
+            drain_timeout_secs: None,
+            protocol_trace_path: None,
+            forward_limits: None,
+            forward_policy: None,
+            max_concurrent_handshakes: None,
+            handshake_timeout_secs: None,
+            idle_registration_expiry_secs: None,
+            relay_memory_budget_mb: None,
+            known_clients: None,
+            visitor_acl: None,
+            timeouts: None,
+            flow_export: None,
+            shutdown_grace_period_secs: None,
+            visitor_retry_timeout_secs: None,
+            tcp_keepalive_secs: None,
+            idle_timeout_secs: None,
+            max_streams_per_client: None,
+            max_connections_per_proxy: None,
+            auth_keys: None,
+            client_ca_path: None,
+            require_client_cert: false,
         };
 
         assert!(config.validate().is_ok());
@@ -592,4 +2344,178 @@ mod tests {
         assert_eq!(size_limits.max_request_size, 2097152);
         assert_eq!(size_limits.max_header_size, 16384);
     }
+
+    #[test]
+    fn test_toml_deserialization_with_sni_routing() {
+        let toml_str = r#"
+            name = "https"
+            publish_port = 443
+            local_port = 8080
+            sni_routing = { sni_map = { "app1.example.com" = 8081, default = 8080 } }
+        "#;
+
+        let proxy: ProxyConfig = toml::from_str(toml_str).unwrap();
+        let sni_routing = proxy.sni_routing.unwrap();
+        assert_eq!(sni_routing.select(Some("app1.example.com")), Some(8081));
+        assert_eq!(sni_routing.select(Some("app2.example.com")), Some(8080));
+        assert!(sni_routing.routes_to(8081));
+        assert!(!sni_routing.routes_to(9000));
+    }
+
+    #[test]
+    fn test_toml_deserialization_with_socket_options() {
+        let toml_str = r#"
+            name = "feed"
+            publish_port = 9000
+            local_port = 9001
+            socket = { send_buffer_bytes = 65536 }
+        "#;
+
+        let proxy: ProxyConfig = toml::from_str(toml_str).unwrap();
+        let socket = proxy.socket.unwrap();
+        // 未写出的 nodelay 保持默认启用
+        assert!(socket.nodelay);
+        assert_eq!(socket.send_buffer_bytes, Some(65536));
+        assert_eq!(socket.recv_buffer_bytes, None);
+
+        let toml_str = r#"
+            name = "web"
+            proxy_type = "http"
+            bind_port = 1080
+            socket = { nodelay = false, recv_buffer_bytes = 4194304, keepalive_secs = 60, user_timeout_ms = 30000 }
+        "#;
+        let forwarder: ForwarderConfig = toml::from_str(toml_str).unwrap();
+        let socket = forwarder.socket.unwrap();
+        assert!(!socket.nodelay);
+        assert_eq!(socket.recv_buffer_bytes, Some(4194304));
+        assert_eq!(socket.keepalive_secs, Some(60));
+        assert_eq!(socket.user_timeout_ms, Some(30000));
+    }
+
+    #[test]
+    fn test_toml_deserialization_with_auth_keys() {
+        let toml_str = r#"
+            bind_addr = "0.0.0.0"
+            bind_port = 8443
+            auth_key = "test-key-12345678"
+
+            [[auth_keys]]
+            name = "teamA"
+            key = "team-a-key-123456"
+            allowed_ports = ["8000-8100", 9000]
+            allow_forward = true
+
+            [[auth_keys]]
+            name = "teamB"
+            key = "team-b-key-123456"
+        "#;
+
+        let config: ServerConfig = toml::from_str(toml_str).unwrap();
+        let keys = config.auth_keys.unwrap();
+        let ports = keys[0].allowed_ports.as_ref().unwrap();
+        assert_eq!(
+            ports[0],
+            PortRange {
+                start: 8000,
+                end: 8100
+            }
+        );
+        assert!(ports[0].contains(8000) && ports[0].contains(8100) && !ports[0].contains(8101));
+        assert_eq!(
+            ports[1],
+            PortRange {
+                start: 9000,
+                end: 9000
+            }
+        );
+        assert_eq!(keys[0].allow_forward, Some(true));
+        assert_eq!(keys[1].allowed_ports, None);
+        assert_eq!(keys[1].allow_forward, None);
+
+        // 序列化为字符串，可以原样读回
+        assert_eq!(
+            toml::Value::try_from(ports[0]).unwrap().as_str(),
+            Some("8000-8100")
+        );
+        assert_eq!(ports[1].to_string(), "9000");
+
+        for invalid in ["0", "\"8100-8000\"", "\"80-x\"", "70000"] {
+            let toml_str = format!("name = \"t\"\nkey = \"k\"\nallowed_ports = [{}]\n", invalid);
+            assert!(
+                toml::from_str::<AuthKeyConfig>(&toml_str).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_toml_deserialization_with_timeouts() {
+        let toml_str = r#"
+            bind_addr = "0.0.0.0"
+            bind_port = 8443
+            auth_key = "test-key-12345678"
+
+            [timeouts]
+            heartbeat_timeout_secs = 300
+        "#;
+
+        let config: ServerConfig = toml::from_str(toml_str).unwrap();
+        // 未写出的超时保持默认值
+        let timeouts = config.timeouts();
+        assert_eq!(timeouts.heartbeat_timeout(), Duration::from_secs(300));
+        assert_eq!(
+            timeouts.session_setup(),
+            ServerTimeoutsConfig::default().session_setup()
+        );
+
+        let toml_str = r#"
+            server_addr = "tunnel.example.com"
+            server_port = 8443
+            auth_key = "test-key-12345678"
+
+            [timeouts]
+            connection_idle_secs = 600
+        "#;
+        let config: ClientConfig = toml::from_str(toml_str).unwrap();
+        let timeouts = config.timeouts();
+        assert_eq!(timeouts.connection_idle(), Duration::from_secs(600));
+        assert_eq!(timeouts.heartbeat_interval(), Duration::from_secs(30));
+
+        let config: ClientConfig = toml::from_str(
+            r#"
+            server_addr = "tunnel.example.com"
+            server_port = 8443
+            auth_key = "test-key-12345678"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(config.timeouts(), ClientTimeoutsConfig::default());
+    }
+
+    #[test]
+    fn test_visitor_gateway_proxy_name() {
+        let toml_str = r#"
+            [client]
+            server_addr = "example.com"
+            server_port = 8443
+            auth_key = "test-auth-key-1234567890"
+
+            [visitor_gateway]
+            bind_port = 1080
+            port_policy = "ignore"
+        "#;
+        let config: ClientFullConfig = toml::from_str(toml_str).unwrap();
+        let gateway = config.visitor_gateway.unwrap();
+        assert_eq!(gateway.bind_addr, "127.0.0.1");
+        assert_eq!(gateway.port_policy, GatewayPortPolicy::Ignore);
+
+        assert_eq!(gateway.proxy_name("myservice.tunnel"), Some("myservice"));
+        assert_eq!(gateway.proxy_name("myservice.TUNNEL."), Some("myservice"));
+        assert_eq!(gateway.proxy_name("myservice"), Some("myservice"));
+        assert_eq!(gateway.proxy_name("tunnel"), Some("tunnel"));
+        assert_eq!(gateway.proxy_name(".tunnel"), None);
+        assert_eq!(gateway.proxy_name("a.b.tunnel"), None);
+        assert_eq!(gateway.proxy_name("example.com"), None);
+    }
 }
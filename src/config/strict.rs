@@ -0,0 +1,638 @@
+use super::{ClientFullConfig, ServerConfig};
+use serde::de::value::{Error, StrDeserializer};
+use serde::de::{self, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use toml::{Table, Value};
+
+/// 开启严格模式的顶层字段
+///
+/// TOML 反序列化忽略未知字段，拼错的字段名（如 `allow_foward`）会静默使用默认值；
+/// 配置文件顶层设置 `strict = true` 时，加载前对照已知字段检查整个配置表
+pub const STRICT_KEY: &str = "strict";
+
+/// 配置值的结构
+///
+/// 已知字段不单独维护：[`Schema::of`] 用只记录结构的反序列化器驱动配置类型的
+/// `Deserialize` 实现，收集 serde 传入的字段名（包括别名）
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Schema {
+    /// 结构体：已知字段（按声明顺序）和各字段值的结构
+    Struct(Vec<(&'static str, Schema)>),
+    /// 列表，元素的结构
+    Seq(Box<Schema>),
+    /// 标量或键不固定的映射，不检查
+    #[default]
+    Any,
+}
+
+impl Schema {
+    /// 由 `T` 的 `Deserialize` 实现推导结构
+    pub fn of<'de, T: Deserialize<'de>>() -> Self {
+        // 占位值不一定能通过字段类型的解析（如 IP 地址），字段名列表中的别名和规范字段
+        // 都送入时 serde 也会报重复字段：记下出错的字段和已推导出的结构，跳过它重新推导
+        let mut skipped = BTreeMap::new();
+        loop {
+            let mut schema = Schema::Any;
+            let before = skipped.len();
+            // 推导过程中用占位值构造 `T`，构造结果和错误都不需要
+            let _ = T::deserialize(Introspector {
+                schema: &mut schema,
+                skipped: &mut skipped,
+            });
+            if skipped.len() == before {
+                return schema;
+            }
+        }
+    }
+
+    /// 增加一个字段（用于配置类型之外的顶层字段）
+    pub fn with_field(mut self, name: &'static str, schema: Schema) -> Self {
+        if let Schema::Struct(fields) = &mut self {
+            fields.push((name, schema));
+        }
+        self
+    }
+
+    fn field(&self, name: &str) -> Option<&Schema> {
+        match self {
+            Schema::Struct(fields) => fields.iter().find(|(n, _)| *n == name).map(|(_, s)| s),
+            _ => None,
+        }
+    }
+}
+
+/// 未知字段
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownKey {
+    /// 字段路径（如 `server.allow_foward`、`proxies[0].publish_prot`）
+    pub path: String,
+    /// 同一配置段中最接近的已知字段
+    pub suggestion: Option<&'static str>,
+}
+
+impl std::fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.suggestion {
+            Some(suggestion) => write!(f, "{} (did you mean '{}'?)", self.path, suggestion),
+            None => write!(f, "{}", self.path),
+        }
+    }
+}
+
+/// 服务器配置文件的结构
+pub fn server_schema() -> Schema {
+    Schema::Struct(vec![("server", Schema::of::<ServerConfig>())])
+        .with_field(STRICT_KEY, Schema::Any)
+}
+
+/// 客户端配置文件的结构
+pub fn client_schema() -> Schema {
+    Schema::of::<ClientFullConfig>().with_field(STRICT_KEY, Schema::Any)
+}
+
+/// 配置表是否开启了严格模式
+pub fn is_strict(table: &Table) -> Result<bool, String> {
+    match table.get(STRICT_KEY) {
+        None => Ok(false),
+        Some(Value::Boolean(strict)) => Ok(*strict),
+        Some(other) => Err(format!(
+            "'{}' must be a boolean, found {}",
+            STRICT_KEY,
+            other.type_str()
+        )),
+    }
+}
+
+/// 开启了严格模式时检查未知字段，有未知字段时返回列出所有未知字段的错误信息
+pub fn check(table: &Table, schema: &Schema) -> Result<(), String> {
+    if !is_strict(table)? {
+        return Ok(());
+    }
+    let unknown = unknown_keys(table, schema);
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    let lines: Vec<String> = unknown.iter().map(|key| format!("  {}", key)).collect();
+    Err(format!(
+        "Unknown configuration key{} (strict mode):\n{}",
+        if unknown.len() == 1 { "" } else { "s" },
+        lines.join("\n")
+    ))
+}
+
+/// 找出配置表中 `schema` 之外的字段
+pub fn unknown_keys(table: &Table, schema: &Schema) -> Vec<UnknownKey> {
+    let mut unknown = Vec::new();
+    check_table(table, schema, "", &mut unknown);
+    unknown
+}
+
+fn check_table(table: &Table, schema: &Schema, path: &str, unknown: &mut Vec<UnknownKey>) {
+    let Schema::Struct(fields) = schema else {
+        return;
+    };
+    for (key, value) in table {
+        let key_path = if path.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", path, key)
+        };
+        match schema.field(key) {
+            Some(field) => check_value(value, field, &key_path, unknown),
+            None => unknown.push(UnknownKey {
+                path: key_path,
+                suggestion: suggest(key, fields.iter().map(|(name, _)| *name)),
+            }),
+        }
+    }
+}
+
+fn check_value(value: &Value, schema: &Schema, path: &str, unknown: &mut Vec<UnknownKey>) {
+    match (value, schema) {
+        (Value::Table(table), Schema::Struct(_)) => check_table(table, schema, path, unknown),
+        (Value::Array(items), Schema::Seq(item)) => {
+            for (index, value) in items.iter().enumerate() {
+                check_value(value, item, &format!("{}[{}]", path, index), unknown);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 编辑距离足够小的最接近字段名
+fn suggest(key: &str, candidates: impl Iterator<Item = &'static str>) -> Option<&'static str> {
+    let max_distance = (key.chars().count() / 3).max(2);
+    candidates
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein 编辑距离
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// 只记录结构的反序列化器：结构体字段逐个以占位值反序列化，同时记录字段值的结构
+struct Introspector<'a> {
+    schema: &'a mut Schema,
+    /// 不再送入的字段（结构体名称，字段名）及其结构
+    skipped: &'a mut Skipped,
+}
+
+type Skipped = BTreeMap<(&'static str, &'static str), Schema>;
+
+impl<'de> de::Deserializer<'de> for Introspector<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(de::Error::custom("untyped value"))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_bool(false)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i64(0)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i64(0)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i64(0)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i64(0)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u64(0)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u64(0)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u64(0)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u64(0)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_f64(0.0)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_f64(0.0)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_char(' ')
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_str("")
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_str("")
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_bytes(&[])
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_bytes(&[])
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let mut item = Schema::Any;
+        let result = visitor.visit_seq(OneItem {
+            schema: Some(&mut item),
+            skipped: self.skipped,
+        });
+        *self.schema = Schema::Seq(Box::new(item));
+        result
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        // 键不固定的映射（如 SNI 路由表），保持 Any
+        visitor.visit_map(de::value::MapDeserializer::new(std::iter::empty::<(
+            &str,
+            &str,
+        )>()))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        *self.schema = Schema::Struct(fields.iter().map(|f| (*f, Schema::Any)).collect());
+        let Schema::Struct(entries) = self.schema else {
+            unreachable!()
+        };
+        let last_key = Cell::new(None);
+        let value_failed = Cell::new(false);
+        let result = visitor.visit_map(Fields {
+            name,
+            entries: entries.iter_mut(),
+            current: None,
+            last_key: &last_key,
+            value_failed: &value_failed,
+            skipped: &mut *self.skipped,
+        });
+        // 字段值的错误已在送入时记录，这里只处理本层的重复字段
+        if let (Err(e), Some(key)) = (&result, last_key.get()) {
+            if !value_failed.get() && e.to_string().starts_with("duplicate field") {
+                self.skipped.insert((name, key), Schema::Any);
+            }
+        }
+        for (key, schema) in entries.iter_mut() {
+            if let Some(skipped) = self.skipped.get(&(name, *key)) {
+                *schema = skipped.clone();
+            }
+        }
+        result
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let variant = variants.first().copied().unwrap_or_default();
+        visitor.visit_enum(IntoDeserializer::<Error>::into_deserializer(variant))
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_str("")
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+}
+
+/// 结构体的字段，按字段名列表逐个送入
+struct Fields<'a> {
+    name: &'static str,
+    entries: std::slice::IterMut<'a, (&'static str, Schema)>,
+    current: Option<&'a mut Schema>,
+    last_key: &'a Cell<Option<&'static str>>,
+    value_failed: &'a Cell<bool>,
+    skipped: &'a mut Skipped,
+}
+
+impl<'de> MapAccess<'de> for Fields<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        for (key, schema) in self.entries.by_ref() {
+            if self.skipped.contains_key(&(self.name, *key)) {
+                continue;
+            }
+            self.current = Some(schema);
+            self.last_key.set(Some(*key));
+            return seed.deserialize(StrDeserializer::new(key)).map(Some);
+        }
+        Ok(None)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let schema = self
+            .current
+            .take()
+            .ok_or_else(|| de::Error::custom("value requested before its key"))?;
+        let before = self.skipped.len();
+        let result = seed.deserialize(Introspector {
+            schema: &mut *schema,
+            skipped: &mut *self.skipped,
+        });
+        if result.is_err() {
+            self.value_failed.set(true);
+            // 内层没有记录新的出错字段时，出错的就是这个字段本身
+            if let (true, Some(key)) = (self.skipped.len() == before, self.last_key.get()) {
+                self.skipped.insert((self.name, key), schema.clone());
+            }
+        }
+        result
+    }
+}
+
+/// 只有一个元素的列表
+struct OneItem<'a> {
+    schema: Option<&'a mut Schema>,
+    skipped: &'a mut Skipped,
+}
+
+impl<'de> SeqAccess<'de> for OneItem<'_> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.schema.take() {
+            Some(schema) => seed
+                .deserialize(Introspector {
+                    schema,
+                    skipped: &mut *self.skipped,
+                })
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::compat::parse_client_config;
+
+    const SERVER: &str = r#"
+strict = true
+
+[server]
+bind_addr = "0.0.0.0"
+bind_port = 8443
+auth_key = "secret"
+"#;
+
+    const CLIENT: &str = r#"
+strict = true
+
+[client]
+server_addr = "example.com"
+server_port = 8443
+auth_key = "secret"
+
+[[proxies]]
+name = "web"
+publish_port = 8080
+local_port = 3000
+
+[[proxies]]
+name = "ssh"
+remote_port = 2222
+local_port = 22
+
+[[visitors]]
+name = "db"
+listen_port = 5432
+publish_port = 15432
+
+[[forwarders]]
+name = "office"
+proxy_type = "socks5"
+bind_port = 1080
+
+[forwarders.routing]
+direct_ips = ["10.0.0.0/8"]
+"#;
+
+    fn unknown(content: &str, schema: &Schema) -> Vec<String> {
+        let table: Table = toml::from_str(content).unwrap();
+        unknown_keys(&table, schema)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_known_keys_and_aliases_are_accepted() {
+        assert!(unknown(SERVER, &server_schema()).is_empty());
+        assert!(unknown(CLIENT, &client_schema()).is_empty());
+        let (config, _) = parse_client_config(CLIENT).unwrap();
+        assert_eq!(config.proxies[1].publish_port, 2222);
+    }
+
+    #[test]
+    fn test_server_typos_report_path_and_suggestion() {
+        let content = format!(
+            "{}allow_foward = true\n\n[server.rate_limit]\nburst_sise = 10\n\n\
+             [server.timeouts]\nheartbeat_timout_secs = 30\n\n\
+             [server.flow_export]\nsinc = \"events\"\n",
+            SERVER
+        );
+        assert_eq!(
+            unknown(&content, &server_schema()),
+            vec![
+                "server.allow_foward (did you mean 'allow_forward'?)",
+                "server.flow_export.sinc (did you mean 'sink'?)",
+                "server.rate_limit.burst_sise (did you mean 'burst_size'?)",
+                "server.timeouts.heartbeat_timout_secs (did you mean 'heartbeat_timeout_secs'?)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_client_typos_report_path_and_suggestion() {
+        let content = CLIENT
+            .replace("server_port", "server_prot")
+            .replace("name = \"ssh\"", "name = \"ssh\"\nlocal_prot = 22")
+            .replace("listen_port", "listen_prot")
+            .replace("direct_ips", "direct_ip")
+            + "\n[client.timeouts]\nreconect_delay_secs = 5\n\n\
+               [visitor_gateway]\nbind_prot = 1081\n";
+        assert_eq!(
+            unknown(&content, &client_schema()),
+            vec![
+                "client.server_prot (did you mean 'server_port'?)",
+                "client.timeouts.reconect_delay_secs (did you mean 'reconnect_delay_secs'?)",
+                "forwarders[0].routing.direct_ip (did you mean 'direct_ips'?)",
+                "proxies[1].local_prot (did you mean 'local_port'?)",
+                "visitor_gateway.bind_prot (did you mean 'bind_port'?)",
+                "visitors[0].listen_prot (did you mean 'listen_port'?)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unrelated_key_has_no_suggestion() {
+        let content = format!("{}completely_unrelated = 1\n", SERVER);
+        assert_eq!(
+            unknown(&content, &server_schema()),
+            vec!["server.completely_unrelated"]
+        );
+        let content = format!("mode = \"server\"\n{}", SERVER);
+        assert_eq!(unknown(&content, &server_schema()), vec!["mode"]);
+    }
+
+    #[test]
+    fn test_strict_mode_rejects_unknown_keys_on_load() {
+        let content = CLIENT.replace("auth_key", "auth_kye");
+        let err = parse_client_config(&content).unwrap_err().to_string();
+        assert!(
+            err.contains("Unknown configuration key (strict mode)"),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains("client.auth_kye (did you mean 'auth_key'?)"),
+            "{}",
+            err
+        );
+
+        let content = content.replace("name = \"web\"", "name = \"web\"\npublish_adr = \"::\"");
+        let err = parse_client_config(&content).unwrap_err().to_string();
+        assert!(
+            err.contains("Unknown configuration keys (strict mode)"),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains("proxies[0].publish_adr (did you mean 'publish_addr'?)"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_non_strict_config_keeps_ignoring_unknown_keys() {
+        let typo = CLIENT.replace("auth_key", "auth_kye = \"typo\"\nauth_key");
+        let relaxed = typo.replace("strict = true", "strict = false");
+        assert!(parse_client_config(&relaxed).is_ok());
+        let absent = typo.replace("strict = true", "");
+        assert!(parse_client_config(&absent).is_ok());
+
+        let table: Table = toml::from_str(&relaxed).unwrap();
+        assert_eq!(check(&table, &server_schema()), Ok(()));
+    }
+
+    #[test]
+    fn test_strict_must_be_boolean() {
+        let table: Table = toml::from_str("strict = \"yes\"\n[server]\n").unwrap();
+        assert_eq!(
+            check(&table, &server_schema()),
+            Err("'strict' must be a boolean, found string".to_string())
+        );
+    }
+
+    #[test]
+    fn test_example_configs_have_no_unknown_keys() {
+        let examples = concat!(env!("CARGO_MANIFEST_DIR"), "/examples");
+        for entry in std::fs::read_dir(examples).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|e| e.to_str()) != Some("toml") {
+                continue;
+            }
+            let table: Table = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            let schema = if table.contains_key("server") {
+                server_schema()
+            } else {
+                client_schema()
+            };
+            let unknown = unknown_keys(&table, &schema);
+            assert!(unknown.is_empty(), "{}: {:?}", path.display(), unknown);
+        }
+    }
+}
@@ -1,8 +1,59 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use std::collections::HashSet;
 use tracing::warn;
 
-use super::{ClientFullConfig, ForwarderConfig, ProxyConfig, ServerConfig, VisitorConfig};
+use super::{
+    AuthKeyConfig, ClientFullConfig, ClientTimeoutsConfig, ForwarderConfig, HeaderRuleConfig,
+    HealthConfig, ProxyConfig, ProxyPoolConfig, ProxyType, RoutingConfig, RoutingStrategy,
+    ServerConfig, ServerTimeoutsConfig, SniRoutingConfig, VisitorAclConfig, VisitorConfig,
+    VisitorGatewayConfig,
+};
+
+/// 请求头改写规则不能添加的头部（决定报文边界或由 forwarder 自己管理）
+const PROTECTED_HEADERS: &[&str] = &[
+    "host",
+    "content-length",
+    "transfer-encoding",
+    "connection",
+    "proxy-connection",
+    "keep-alive",
+];
+
+/// 是否为合法的 HTTP 头部名称（RFC 9110 token）
+fn is_header_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// max_write_chunk 的下限（过小会让每条记录的开销占主导）
+const MIN_WRITE_CHUNK: usize = 512;
+/// TLS 记录的最大明文长度
+const MAX_TLS_RECORD_SIZE: usize = 16 * 1024;
+/// `/stats/ws` 推送间隔的下限（毫秒）
+const MIN_STATS_STREAM_INTERVAL_MS: u64 = 100;
+
+/// 弱密钥中常见的单词（不区分大小写）
+const WEAK_KEY_WORDS: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "changeme",
+    "change-me",
+    "qwerty",
+    "letmein",
+    "admin",
+    "default",
+    "example",
+    "test",
+];
+
+/// 同一字符连续出现或连续递增/递减达到该长度时视为弱密钥
+const WEAK_KEY_RUN: usize = 5;
+
+/// 不同字符少于该数量时视为弱密钥
+const WEAK_KEY_MIN_DISTINCT: usize = 8;
 
 /// 配置验证器 - 负责所有配置验证逻辑
 pub struct ConfigValidator;
@@ -16,9 +67,53 @@ impl ConfigValidator {
                 auth_key.len()
             );
         }
+        if let Some(reason) = Self::auth_key_weakness(auth_key) {
+            warn!(
+                "auth_key looks weak ({}); generate a strong one with `tls-tunnel keygen`",
+                reason
+            );
+        }
         Ok(())
     }
 
+    /// 检查密钥是否为低熵密钥（重复字符、连续序列、重复片段或常见单词），返回原因
+    ///
+    /// 只用于提示，长度足够的弱密钥仍然可以使用
+    pub fn auth_key_weakness(auth_key: &str) -> Option<String> {
+        let chars: Vec<char> = auth_key.chars().collect();
+        let distinct = chars.iter().collect::<HashSet<_>>().len();
+        if distinct < WEAK_KEY_MIN_DISTINCT {
+            return Some(format!("only {} distinct characters", distinct));
+        }
+
+        let lower = auth_key.to_lowercase();
+        if let Some(word) = WEAK_KEY_WORDS.iter().find(|w| lower.contains(*w)) {
+            return Some(format!("contains the common word '{}'", word));
+        }
+
+        // 相邻字符的差值相同（0 为重复字符，±1 为 "12345"、"abcde" 这样的序列）
+        let mut run = 1;
+        for pair in chars.windows(3) {
+            let step = pair[1] as i64 - pair[0] as i64;
+            if step.abs() <= 1 && pair[2] as i64 - pair[1] as i64 == step {
+                run += 1;
+                if run + 1 >= WEAK_KEY_RUN {
+                    let kind = if step == 0 { "repeated" } else { "sequential" };
+                    return Some(format!("{} characters", kind));
+                }
+            } else {
+                run = 1;
+            }
+        }
+
+        // 由较短的片段重复组成
+        let len = chars.len();
+        if let Some(period) = (1..=len / 2).find(|&p| (p..len).all(|i| chars[i] == chars[i - p])) {
+            return Some(format!("repeats a {}-character pattern", period));
+        }
+        None
+    }
+
     /// 验证端口号
     pub fn validate_port(port: u16, context: &str) -> Result<()> {
         if port == 0 {
@@ -28,10 +123,25 @@ impl ConfigValidator {
     }
 
     /// 验证地址不为空
+    ///
+    /// IPv6 地址可以带或不带方括号（`::1`、`[::1]`），方括号中必须是 IPv6 地址且不能带端口
     pub fn validate_address(addr: &str, context: &str) -> Result<()> {
-        if addr.trim().is_empty() {
+        let addr = addr.trim();
+        if addr.is_empty() {
             bail!("{}: address cannot be empty", context);
         }
+        if let Some(rest) = addr.strip_prefix('[') {
+            let valid = rest
+                .strip_suffix(']')
+                .is_some_and(|ip| ip.parse::<std::net::Ipv6Addr>().is_ok());
+            if !valid {
+                bail!(
+                    "{}: invalid address '{}', brackets must enclose an IPv6 address without a port (e.g. \"[::1]\" or \"::1\")",
+                    context,
+                    addr
+                );
+            }
+        }
         Ok(())
     }
 
@@ -50,12 +160,20 @@ impl ConfigValidator {
 
         // 验证认证密钥
         Self::validate_auth_key(&config.auth_key)?;
+        if let Some(ref auth_keys) = config.auth_keys {
+            Self::validate_auth_keys(auth_keys, &config.auth_key)?;
+        }
 
         // 验证统计服务器地址（如果配置了）
         if let Some(ref addr) = config.stats_addr {
             Self::validate_address(addr, "Server stats_addr")?;
         }
 
+        // 验证同端口统计路径
+        if let Some(ref path) = config.stats_path {
+            Self::validate_stats_path(path, config.transport)?;
+        }
+
         // 验证证书配置
         match (&config.cert_path, &config.key_path) {
             (Some(_), Some(_)) | (None, None) => {}
@@ -72,6 +190,14 @@ impl ConfigValidator {
             bail!("Certificates are not needed when running behind a proxy (TLS is terminated by the proxy).");
         }
 
+        // 验证客户端证书配置（mTLS）
+        if config.require_client_cert && config.client_ca_path.is_none() {
+            bail!("require_client_cert needs client_ca_path to verify client certificates");
+        }
+        if config.behind_proxy && config.client_ca_path.is_some() {
+            bail!("client_ca_path cannot be used behind a proxy (client certificates must be verified by the proxy that terminates TLS).");
+        }
+
         // 验证速率限制配置
         if let Some(ref rate_limit) = config.rate_limit {
             Self::validate_rate_limit_config(rate_limit)?;
@@ -82,320 +208,2807 @@ impl ConfigValidator {
             Self::validate_size_limit_config(size_limits)?;
         }
 
+        // 验证统计服务器防护配置
+        if let Some(ref stats_limits) = config.stats_limits {
+            Self::validate_stats_limit_config(stats_limits)?;
+        }
+        if let Some(ref stats_stream) = config.stats_stream {
+            Self::validate_stats_stream_config(stats_stream)?;
+        }
+
+        // 验证事件导出配置
+        if let Some(ref event_export) = config.event_export {
+            Self::validate_event_export_config(event_export)?;
+        }
+
+        // 验证流量记录导出配置
+        if let Some(ref flow_export) = config.flow_export {
+            Self::validate_flow_export_config(flow_export, config.event_export.is_some())?;
+        }
+
+        // 验证异常通知限流配置
+        if let Some(ref exception_limits) = config.exception_limits {
+            Self::validate_exception_limit_config(exception_limits)?;
+        }
+
+        // 验证 forward 限制配置
+        if let Some(ref forward_limits) = config.forward_limits {
+            Self::validate_forward_limit_config(forward_limits)?;
+        }
+        if let Some(ref forward_policy) = config.forward_policy {
+            Self::validate_forward_policy(forward_policy)?;
+        }
+
+        Self::validate_max_write_chunk(config.max_write_chunk)?;
+
+        if config.max_concurrent_handshakes == Some(0) {
+            bail!("max_concurrent_handshakes must be greater than 0");
+        }
+        if config.handshake_timeout_secs == Some(0) {
+            bail!("handshake_timeout_secs must be greater than 0");
+        }
+        if config.idle_registration_expiry_secs == Some(0) {
+            bail!("idle_registration_expiry_secs must be greater than 0");
+        }
+        if let Some(secs) = config.tcp_keepalive_secs {
+            Self::validate_keepalive_secs(secs, "tcp_keepalive_secs")?;
+        }
+        Self::validate_relay_memory_budget(config.relay_memory_budget_mb)?;
+
+        if let Some(ref known_clients) = config.known_clients {
+            Self::validate_known_clients(known_clients)?;
+        }
+        if let Some(ref visitor_acl) = config.visitor_acl {
+            Self::validate_visitor_acl(visitor_acl)?;
+        }
+
+        if let Some(ref timeouts) = config.timeouts {
+            Self::validate_server_timeouts(timeouts)?;
+        }
+
         Ok(())
     }
 
-    /// 验证速率限制配置
-    pub fn validate_rate_limit_config(config: &super::RateLimitConfig) -> Result<()> {
-        if config.requests_per_second == 0 {
-            bail!("rate_limit.requests_per_second must be greater than 0");
-        }
-        if config.burst_size == 0 {
-            bail!("rate_limit.burst_size must be greater than 0");
+    /// 验证服务器超时设置
+    ///
+    /// 心跳超时必须大于客户端的默认心跳间隔，否则正常发送心跳的客户端也会被断开
+    pub fn validate_server_timeouts(timeouts: &ServerTimeoutsConfig) -> Result<()> {
+        for (name, value) in [
+            ("session_setup_secs", timeouts.session_setup_secs),
+            ("heartbeat_timeout_secs", timeouts.heartbeat_timeout_secs),
+            ("preamble_read_secs", timeouts.preamble_read_secs),
+            ("stream_open_secs", timeouts.stream_open_secs),
+            ("client_hello_ms", timeouts.client_hello_ms),
+            ("shutdown_grace_secs", timeouts.shutdown_grace_secs),
+            ("udp_session_idle_secs", timeouts.udp_session_idle_secs),
+        ] {
+            if value == 0 {
+                bail!("timeouts.{} must be greater than 0", name);
+            }
         }
-        if config.burst_size < config.requests_per_second {
-            warn!(
-                "rate_limit.burst_size ({}) is less than requests_per_second ({}), \
-                 this may cause frequent rate limiting",
-                config.burst_size, config.requests_per_second
+        let heartbeat_interval = ClientTimeoutsConfig::default().heartbeat_interval_secs;
+        if timeouts.heartbeat_timeout_secs <= heartbeat_interval {
+            bail!(
+                "timeouts.heartbeat_timeout_secs ({}) must be greater than the client heartbeat interval ({}s)",
+                timeouts.heartbeat_timeout_secs,
+                heartbeat_interval
             );
         }
         Ok(())
     }
 
-    /// 验证请求大小限制配置
-    pub fn validate_size_limit_config(config: &super::SizeLimitConfig) -> Result<()> {
-        if config.max_request_size == 0 {
-            bail!("size_limits.max_request_size must be greater than 0");
+    /// 验证客户端超时设置和 forwarder 的空闲超时覆盖
+    ///
+    /// 协议解析超时必须小于连接空闲超时；心跳间隔必须小于服务器的默认心跳超时
+    pub fn validate_client_timeouts(
+        timeouts: &ClientTimeoutsConfig,
+        forwarders: &[ForwarderConfig],
+    ) -> Result<()> {
+        for (name, value) in [
+            ("protocol_parse_secs", timeouts.protocol_parse_secs),
+            ("connection_idle_secs", timeouts.connection_idle_secs),
+            ("heartbeat_interval_secs", timeouts.heartbeat_interval_secs),
+            ("stream_open_secs", timeouts.stream_open_secs),
+            ("local_connect_ms", timeouts.local_connect_ms),
+            ("local_retry_delay_ms", timeouts.local_retry_delay_ms),
+            ("shutdown_grace_secs", timeouts.shutdown_grace_secs),
+            (
+                "reconnect_max_delay_secs",
+                timeouts.reconnect_max_delay_secs,
+            ),
+        ] {
+            if value == 0 {
+                bail!("timeouts.{} must be greater than 0", name);
+            }
         }
-        if config.max_header_size == 0 {
-            bail!("size_limits.max_header_size must be greater than 0");
+        if !(timeouts.reconnect_multiplier.is_finite() && timeouts.reconnect_multiplier >= 1.0) {
+            bail!(
+                "timeouts.reconnect_multiplier ({}) must be at least 1.0",
+                timeouts.reconnect_multiplier
+            );
         }
-        if config.max_header_size > config.max_request_size {
+        if timeouts.reconnect_max_delay_secs < timeouts.reconnect_delay_secs {
             bail!(
-                "size_limits.max_header_size ({}) cannot be greater than max_request_size ({})",
-                config.max_header_size,
-                config.max_request_size
+                "timeouts.reconnect_max_delay_secs ({}) must not be less than reconnect_delay_secs ({})",
+                timeouts.reconnect_max_delay_secs,
+                timeouts.reconnect_delay_secs
             );
         }
-        // 建议值检查
-        if config.max_request_size > 100 * 1024 * 1024 {
-            warn!(
-                "size_limits.max_request_size is very large ({} bytes = {} MB), \
-                 this may lead to memory exhaustion attacks",
-                config.max_request_size,
-                config.max_request_size / (1024 * 1024)
+        if timeouts.protocol_parse_secs >= timeouts.connection_idle_secs {
+            bail!(
+                "timeouts.protocol_parse_secs ({}) must be less than connection_idle_secs ({})",
+                timeouts.protocol_parse_secs,
+                timeouts.connection_idle_secs
             );
         }
+        let heartbeat_timeout = ServerTimeoutsConfig::default().heartbeat_timeout_secs;
+        if timeouts.heartbeat_interval_secs >= heartbeat_timeout {
+            bail!(
+                "timeouts.heartbeat_interval_secs ({}) must be less than the server heartbeat timeout ({}s)",
+                timeouts.heartbeat_interval_secs,
+                heartbeat_timeout
+            );
+        }
+        for forwarder in forwarders {
+            let Some(idle) = forwarder.idle_timeout_secs else {
+                continue;
+            };
+            if idle <= timeouts.protocol_parse_secs {
+                bail!(
+                    "Forwarder '{}' idle_timeout_secs ({}) must be greater than timeouts.protocol_parse_secs ({})",
+                    forwarder.name,
+                    idle,
+                    timeouts.protocol_parse_secs
+                );
+            }
+        }
         Ok(())
     }
 
-    /// 验证 Proxy 配置列表
-    pub fn validate_proxies(proxies: &[ProxyConfig]) -> Result<()> {
-        let mut seen_names = HashSet::new();
-        let mut seen_bind = HashSet::new();
-        let mut seen_local_ports = HashSet::new();
-
-        for proxy in proxies {
-            // 验证名称
-            Self::validate_name(&proxy.name, "Proxy name")?;
+    /// 验证健康评分设置
+    ///
+    /// 阈值必须满足 0 < red_below <= yellow_below <= 100，告警值必须小于严重值
+    pub fn validate_health(health: &HealthConfig) -> Result<()> {
+        if health.red_below == 0 || health.red_below > health.yellow_below {
+            bail!(
+                "health.red_below ({}) must be greater than 0 and not exceed yellow_below ({})",
+                health.red_below,
+                health.yellow_below
+            );
+        }
+        if health.yellow_below > 100 {
+            bail!(
+                "health.yellow_below ({}) must not exceed 100",
+                health.yellow_below
+            );
+        }
+        if health.window_secs == 0 || health.interval_secs == 0 {
+            bail!("health.window_secs and health.interval_secs must be greater than 0");
+        }
+        if health.rtt_warn_ms >= health.rtt_critical_ms {
+            bail!(
+                "health.rtt_warn_ms ({}) must be less than rtt_critical_ms ({})",
+                health.rtt_warn_ms,
+                health.rtt_critical_ms
+            );
+        }
+        if !(health.failure_ratio_warn > 0.0
+            && health.failure_ratio_warn < health.failure_ratio_critical
+            && health.failure_ratio_critical <= 1.0)
+        {
+            bail!(
+                "health.failure_ratio_warn ({}) and failure_ratio_critical ({}) must satisfy 0 < warn < critical <= 1",
+                health.failure_ratio_warn,
+                health.failure_ratio_critical
+            );
+        }
+        Ok(())
+    }
 
-            // 检查 name 唯一性
-            if !seen_names.insert(&proxy.name) {
-                bail!(
-                    "Duplicate proxy name '{}': each proxy must have a unique name",
-                    proxy.name
-                );
+    /// 验证附加的认证密钥（名称和密钥都不能重复，密钥也不能与 `auth_key` 相同）
+    pub fn validate_auth_keys(auth_keys: &[AuthKeyConfig], auth_key: &str) -> Result<()> {
+        let mut names = HashSet::new();
+        for (i, entry) in auth_keys.iter().enumerate() {
+            if entry.name.trim().is_empty() {
+                bail!("auth_keys[{}]: name cannot be empty", i);
             }
-
-            // 检查 (publish_addr, publish_port) 唯一性
-            if !seen_bind.insert((proxy.publish_addr.clone(), proxy.publish_port)) {
-                bail!(
-                    "Duplicate publish binding {}:{}: each proxy must use a different server bind address/port",
-                    proxy.publish_addr,
-                    proxy.publish_port
-                );
+            if !names.insert(entry.name.as_str()) {
+                bail!("auth_keys entry '{}' is listed more than once", entry.name);
             }
-
-            // 检查 local_port 唯一性
-            if !seen_local_ports.insert(proxy.local_port) {
+            Self::validate_auth_key(&entry.key)
+                .map_err(|e| anyhow::anyhow!("auth_keys entry '{}': {}", entry.name, e))?;
+            if entry.key == auth_key {
+                bail!("auth_keys entry '{}' reuses auth_key", entry.name);
+            }
+            if let Some(other) = auth_keys[..i].iter().find(|other| other.key == entry.key) {
                 bail!(
-                    "Duplicate local_port {}: each proxy must connect to a different local service",
-                    proxy.local_port
+                    "auth_keys entry '{}' reuses the key of '{}'",
+                    entry.name,
+                    other.name
                 );
             }
-
-            // 验证端口
-            Self::validate_port(proxy.publish_port, &format!("Proxy '{}'", proxy.name))?;
-            Self::validate_port(proxy.local_port, &format!("Proxy '{}'", proxy.name))?;
-
-            // 验证地址
-            Self::validate_address(&proxy.publish_addr, &format!("Proxy '{}'", proxy.name))?;
         }
-
         Ok(())
     }
 
-    /// 验证 Visitor 配置列表
-    pub fn validate_visitors(visitors: &[VisitorConfig]) -> Result<()> {
-        let mut seen_names = HashSet::new();
-        let mut seen_binds = HashSet::new();
-
-        for visitor in visitors {
-            // 验证名称
-            Self::validate_name(&visitor.name, "Visitor name")?;
-
-            // 检查 name 唯一性
-            if !seen_names.insert(&visitor.name) {
+    /// 验证已知客户端身份列表（每项必须是完整的 `SHA256:` 指纹）
+    pub fn validate_known_clients(known_clients: &[String]) -> Result<()> {
+        if known_clients.is_empty() {
+            bail!("known_clients cannot be empty: omit it to accept clients without an identity");
+        }
+        for fingerprint in known_clients {
+            if !crate::identity::is_fingerprint(fingerprint) {
                 bail!(
-                    "Duplicate visitor name '{}': each visitor must have a unique name",
-                    visitor.name
+                    "known_clients entry '{}' is not a client identity fingerprint (expected {}<64 hex digits>)",
+                    fingerprint,
+                    crate::identity::FINGERPRINT_PREFIX
                 );
             }
+        }
+        Ok(())
+    }
 
-            // 检查 (bind_addr, bind_port) 唯一性
-            if !seen_binds.insert((visitor.bind_addr.clone(), visitor.bind_port)) {
+    /// 验证 visitor 访问控制（规则不能为空，按身份配置的规则必须使用完整指纹且不重复）
+    pub fn validate_visitor_acl(acl: &VisitorAclConfig) -> Result<()> {
+        let rules = std::iter::once((
+            "visitor_acl",
+            acl.visitor_allow.as_deref(),
+            &acl.visitor_deny,
+        ))
+        .chain(acl.clients.iter().map(|c| {
+            (
+                c.identity.as_str(),
+                c.visitor_allow.as_deref(),
+                &c.visitor_deny,
+            )
+        }));
+        for (context, allow, deny) in rules {
+            if allow
+                .into_iter()
+                .flatten()
+                .chain(deny)
+                .any(|pattern| pattern.trim().is_empty())
+            {
                 bail!(
-                    "Duplicate visitor binding {}:{}: each visitor must use a different local bind address/port",
-                    visitor.bind_addr,
-                    visitor.bind_port
+                    "{}: visitor_allow/visitor_deny patterns cannot be empty",
+                    context
                 );
             }
-
-            // 验证端口
-            Self::validate_port(visitor.bind_port, &format!("Visitor '{}'", visitor.name))?;
-            Self::validate_port(visitor.publish_port, &format!("Visitor '{}'", visitor.name))?;
-
-            // 验证地址
-            Self::validate_address(&visitor.bind_addr, &format!("Visitor '{}'", visitor.name))?;
         }
-
-        Ok(())
-    }
-
-    /// 验证 Forwarder 配置列表
-    pub fn validate_forwarders(forwarders: &[ForwarderConfig]) -> Result<()> {
-        let mut seen_names = HashSet::new();
-        let mut seen_binds = HashSet::new();
-
-        for forwarder in forwarders {
-            // 验证名称
-            Self::validate_name(&forwarder.name, "Forwarder name")?;
-
-            // 检查 name 唯一性
-            if !seen_names.insert(&forwarder.name) {
+        for (i, client) in acl.clients.iter().enumerate() {
+            if !crate::identity::is_fingerprint(&client.identity) {
                 bail!(
-                    "Duplicate forwarder name '{}': each forwarder must have a unique name",
-                    forwarder.name
+                    "visitor_acl.clients identity '{}' is not a client identity fingerprint (expected {}<64 hex digits>)",
+                    client.identity,
+                    crate::identity::FINGERPRINT_PREFIX
                 );
             }
-
-            // 检查 (bind_addr, bind_port) 唯一性
-            if !seen_binds.insert((forwarder.bind_addr.clone(), forwarder.bind_port)) {
+            if acl.clients[..i]
+                .iter()
+                .any(|other| crate::identity::fingerprint_eq(&other.identity, &client.identity))
+            {
                 bail!(
-                    "Duplicate forwarder binding {}:{}: each forwarder must use a different local bind address/port",
-                    forwarder.bind_addr,
-                    forwarder.bind_port
+                    "visitor_acl.clients identity '{}' is configured more than once",
+                    client.identity
                 );
             }
-
-            // 安全检查：警告绑定到非本地地址
-            Self::check_forwarder_security(&forwarder.name, &forwarder.bind_addr);
-
-            // 验证端口
-            Self::validate_port(
-                forwarder.bind_port,
-                &format!("Forwarder '{}'", forwarder.name),
-            )?;
-
-            // 验证地址
-            Self::validate_address(
-                &forwarder.bind_addr,
-                &format!("Forwarder '{}'", forwarder.name),
-            )?;
         }
-
         Ok(())
     }
 
-    /// 检查 forwarder 安全性（绑定地址）
-    fn check_forwarder_security(name: &str, bind_addr: &str) {
-        if bind_addr != "127.0.0.1" && bind_addr != "localhost" && bind_addr != "::1" {
-            warn!(
-                "⚠️  SECURITY WARNING: Forwarder '{}' is binding to '{}' which exposes the proxy to your network!\n\
-                 This allows anyone on the network to use your proxy, which may lead to:\n\
-                 - Abuse of your IP address\n\
-                 - Your IP being blacklisted\n\
-                 - Unauthorized use of your bandwidth\n\
-                 RECOMMENDATION: Use bind_addr = '127.0.0.1' for localhost-only access.",
-                name, bind_addr
+    /// 验证 visitor 期望的 proxy 注册者身份
+    ///
+    /// 以 `SHA256:` 开头的值按身份指纹比对，必须是完整指纹
+    pub fn validate_expected_peer_id(expected: &str, context: &str) -> Result<()> {
+        let prefix = crate::identity::FINGERPRINT_PREFIX;
+        if expected.len() >= prefix.len()
+            && expected[..prefix.len()].eq_ignore_ascii_case(prefix)
+            && !crate::identity::is_fingerprint(expected)
+        {
+            bail!(
+                "{}: expected peer_id '{}' looks like an identity fingerprint but is not a full {}<64 hex digits> value",
+                context,
+                expected,
+                prefix
             );
         }
+        Ok(())
     }
 
-    /// 验证客户端完整配置
-    pub fn validate_client_full_config(config: &ClientFullConfig) -> Result<()> {
-        // 验证认证密钥
-        Self::validate_auth_key(&config.client.auth_key)?;
+    /// 验证 forward 限制配置（设置的项必须大于 0，`failure_cooldown_secs` 除外）
+    pub fn validate_forward_limit_config(config: &super::ForwardLimitConfig) -> Result<()> {
+        if config.max_duration_secs == Some(0) {
+            bail!("forward_limits.max_duration_secs must be greater than 0");
+        }
+        if config.max_bytes == Some(0) {
+            bail!("forward_limits.max_bytes must be greater than 0");
+        }
+        if config.max_concurrent_per_client == Some(0) {
+            bail!("forward_limits.max_concurrent_per_client must be greater than 0");
+        }
+        if config.connect_timeout_secs == Some(0) {
+            bail!("forward_limits.connect_timeout_secs must be greater than 0");
+        }
+        if config.max_connecting_per_client == Some(0) {
+            bail!("forward_limits.max_connecting_per_client must be greater than 0");
+        }
+        Ok(())
+    }
 
-        // 至少要有一个配置
-        if config.proxies.is_empty() && config.visitors.is_empty() && config.forwarders.is_empty() {
-            bail!("No proxy, visitor, or forwarder configurations defined");
+    /// 验证 forward 目标访问策略（域名、IP/CIDR 和端口必须有效）
+    pub fn validate_forward_policy(policy: &super::ForwardPolicyConfig) -> Result<()> {
+        for (field, domains) in [
+            ("allowed_domains", &policy.allowed_domains),
+            ("denied_domains", &policy.denied_domains),
+        ] {
+            for domain in domains {
+                Self::validate_routing_domain(domain)
+                    .with_context(|| format!("Invalid forward_policy.{}", field))?;
+            }
+        }
+        for (field, cidrs) in [
+            ("allowed_cidrs", &policy.allowed_cidrs),
+            ("denied_cidrs", &policy.denied_cidrs),
+        ] {
+            for cidr in cidrs {
+                Self::validate_routing_cidr(cidr)
+                    .with_context(|| format!("Invalid forward_policy.{}", field))?;
+            }
         }
+        if policy.allowed_ports.contains(&0) {
+            bail!("forward_policy.allowed_ports must not contain port 0");
+        }
+        Ok(())
+    }
 
-        // 验证各个配置列表
-        Self::validate_proxies(&config.proxies)?;
-        Self::validate_visitors(&config.visitors)?;
-        Self::validate_forwarders(&config.forwarders)?;
+    /// 验证同端口统计路径：只有 http2/wss 传输能按路径区分普通 HTTP 请求
+    pub fn validate_stats_path(
+        path: &str,
+        transport: crate::transport::TransportType,
+    ) -> Result<()> {
+        if !matches!(
+            transport,
+            crate::transport::TransportType::Http2 | crate::transport::TransportType::Wss
+        ) {
+            bail!(
+                "stats_path requires http2 or wss transport, got {}",
+                transport
+            );
+        }
+        if !path.starts_with('/') || path == "/" {
+            bail!(
+                "stats_path must start with '/' and cannot be the root path, got '{}'",
+                path
+            );
+        }
+        if path.ends_with('/') || path.contains(['?', '#']) {
+            bail!(
+                "stats_path must not end with '/' or contain a query, got '{}'",
+                path
+            );
+        }
+        Ok(())
+    }
 
+    /// 验证异常通知限流配置
+    pub fn validate_exception_limit_config(config: &super::ExceptionLimitConfig) -> Result<()> {
+        if config.per_minute == 0 {
+            bail!("exception_limits.per_minute must be greater than 0");
+        }
+        if config.burst == 0 {
+            bail!("exception_limits.burst must be greater than 0");
+        }
+        if config.summary_interval_secs == 0 {
+            bail!("exception_limits.summary_interval_secs must be greater than 0");
+        }
+        if config.queue_size == 0 {
+            bail!("exception_limits.queue_size must be greater than 0");
+        }
         Ok(())
     }
-}
 
-#[cfg(test)]
+    /// 验证事件导出配置
+    pub fn validate_event_export_config(config: &super::EventExportConfig) -> Result<()> {
+        config.parse_target()?;
+        if config.queue_size == 0 {
+            bail!("event_export.queue_size must be greater than 0");
+        }
+        Ok(())
+    }
+
+    /// 验证流量记录导出配置（`sink = "events"` 需要同时配置 event_export）
+    pub fn validate_flow_export_config(
+        config: &super::FlowExportConfig,
+        has_event_export: bool,
+    ) -> Result<()> {
+        if config.parse_sink()? == super::FlowExportSink::Events && !has_event_export {
+            bail!("flow_export.sink 'events' requires event_export to be configured");
+        }
+        if config.batch_size == 0 {
+            bail!("flow_export.batch_size must be greater than 0");
+        }
+        if config.flush_interval_ms == 0 {
+            bail!("flow_export.flush_interval_ms must be greater than 0");
+        }
+        if config.queue_size == 0 {
+            bail!("flow_export.queue_size must be greater than 0");
+        }
+        Ok(())
+    }
+
+    /// 验证统计服务器防护配置
+    pub fn validate_stats_limit_config(config: &super::StatsLimitConfig) -> Result<()> {
+        if config.read_timeout_secs == 0 {
+            bail!("stats_limits.read_timeout_secs must be greater than 0");
+        }
+        if config.write_timeout_secs == 0 {
+            bail!("stats_limits.write_timeout_secs must be greater than 0");
+        }
+        if config.max_connections == 0 {
+            bail!("stats_limits.max_connections must be greater than 0");
+        }
+        if config.max_request_size == 0 {
+            bail!("stats_limits.max_request_size must be greater than 0");
+        }
+        Ok(())
+    }
+
+    /// 验证 `/stats/ws` 推送配置
+    pub fn validate_stats_stream_config(config: &super::StatsStreamConfig) -> Result<()> {
+        if config.min_interval_ms < MIN_STATS_STREAM_INTERVAL_MS {
+            bail!(
+                "stats_stream.min_interval_ms ({}) must be at least {}",
+                config.min_interval_ms,
+                MIN_STATS_STREAM_INTERVAL_MS
+            );
+        }
+        if config.max_pending_events == 0 {
+            bail!("stats_stream.max_pending_events must be greater than 0");
+        }
+        if config.auth_token.as_deref().is_some_and(str::is_empty) {
+            bail!("stats_stream.auth_token cannot be empty: omit it to disable authentication");
+        }
+        Ok(())
+    }
+
+    /// 验证客户端统计服务器的本地监听方式（Unix 套接字仅 Unix，命名管道仅 Windows）
+    pub fn validate_stats_local_endpoints(config: &super::ClientConfig) -> Result<()> {
+        if let Some(ref path) = config.stats_socket {
+            if path.as_os_str().is_empty() {
+                bail!("stats_socket cannot be empty");
+            }
+            if !cfg!(unix) {
+                bail!("stats_socket is only supported on Unix platforms, use stats_pipe instead");
+            }
+        }
+        if let Some(ref name) = config.stats_pipe {
+            if !name.starts_with(r"\\.\pipe\") || name.len() <= r"\\.\pipe\".len() {
+                bail!(
+                    "Invalid stats_pipe '{}': expected a name like \\\\.\\pipe\\tls-tunnel-client",
+                    name
+                );
+            }
+            if !cfg!(windows) {
+                bail!("stats_pipe is only supported on Windows, use stats_socket instead");
+            }
+        }
+        Ok(())
+    }
+
+    /// 验证被拒绝代理的重试配置
+    pub fn validate_proxy_retry_config(config: &super::ProxyRetryConfig) -> Result<()> {
+        if config.interval_secs == 0 {
+            bail!("proxy_retry.interval_secs must be greater than 0");
+        }
+        if config.max_interval_secs < config.interval_secs {
+            bail!("proxy_retry.max_interval_secs must not be less than interval_secs");
+        }
+        Ok(())
+    }
+
+    /// 验证 forwarder 直连出口配置（fwmark 和 bind_interface 仅支持 Linux）
+    pub fn validate_direct_egress_config(
+        name: &str,
+        config: &super::DirectEgressConfig,
+    ) -> Result<()> {
+        if let Some(ref interface) = config.bind_interface {
+            // 网卡名称上限为 IFNAMSIZ - 1
+            if interface.is_empty() || interface.len() > 15 || interface.contains('\0') {
+                bail!(
+                    "Forwarder '{}': invalid direct_egress.bind_interface '{}'",
+                    name,
+                    interface
+                );
+            }
+        }
+        if !cfg!(target_os = "linux") && (config.fwmark != 0 || config.bind_interface.is_some()) {
+            bail!(
+                "Forwarder '{}': direct_egress.fwmark and direct_egress.bind_interface are only supported on Linux",
+                name
+            );
+        }
+        Ok(())
+    }
+
+    /// 验证套接字选项（缓冲区大小必须在 1 字节到 64MB 之间，keepalive 和 TCP_USER_TIMEOUT 不能为 0）
+    pub fn validate_socket_options_config(
+        config: &super::SocketOptionsConfig,
+        context: &str,
+    ) -> Result<()> {
+        for (field, value) in [
+            ("send_buffer_bytes", config.send_buffer_bytes),
+            ("recv_buffer_bytes", config.recv_buffer_bytes),
+        ] {
+            if let Some(bytes) = value {
+                if bytes == 0 || bytes > super::MAX_SOCKET_BUFFER_BYTES {
+                    bail!(
+                        "{}: socket.{} must be between 1 and {} bytes, got {}",
+                        context,
+                        field,
+                        super::MAX_SOCKET_BUFFER_BYTES,
+                        bytes
+                    );
+                }
+            }
+        }
+        if let Some(secs) = config.keepalive_secs {
+            Self::validate_keepalive_secs(secs, &format!("{}: socket.keepalive_secs", context))?;
+        }
+        if let Some(ms) = config.user_timeout_ms {
+            if !cfg!(target_os = "linux") {
+                bail!(
+                    "{}: socket.user_timeout_ms is only supported on Linux",
+                    context
+                );
+            }
+            if ms == 0 {
+                bail!("{}: socket.user_timeout_ms must be greater than 0", context);
+            }
+        }
+        Ok(())
+    }
+
+    /// 验证 TCP keepalive 空闲时间（1 秒到 [`super::MAX_SOCKET_KEEPALIVE_SECS`]）
+    fn validate_keepalive_secs(secs: u64, field: &str) -> Result<()> {
+        if secs == 0 || secs > super::MAX_SOCKET_KEEPALIVE_SECS {
+            bail!(
+                "{} must be between 1 and {} seconds, got {}",
+                field,
+                super::MAX_SOCKET_KEEPALIVE_SECS,
+                secs
+            );
+        }
+        Ok(())
+    }
+
+    /// 验证 forwarder 快速失败配置
+    pub fn validate_fast_fail_config(name: &str, config: &super::FastFailConfig) -> Result<()> {
+        if config.threshold == 0 {
+            bail!(
+                "Forwarder '{}': fast_fail.threshold must be greater than 0",
+                name
+            );
+        }
+        if config.blacklist_secs == 0 {
+            bail!(
+                "Forwarder '{}': fast_fail.blacklist_secs must be greater than 0",
+                name
+            );
+        }
+        if config.max_entries == 0 {
+            bail!(
+                "Forwarder '{}': fast_fail.max_entries must be greater than 0",
+                name
+            );
+        }
+        if config.decay_secs == 0 {
+            bail!(
+                "Forwarder '{}': fast_fail.decay_secs must be greater than 0",
+                name
+            );
+        }
+        Ok(())
+    }
+
+    /// 验证单次写入大小上限（不超过 TLS 记录的最大明文长度）
+    pub fn validate_max_write_chunk(max_write_chunk: Option<usize>) -> Result<()> {
+        if let Some(size) = max_write_chunk {
+            if !(MIN_WRITE_CHUNK..=MAX_TLS_RECORD_SIZE).contains(&size) {
+                bail!(
+                    "max_write_chunk must be between {} and {}, got {}",
+                    MIN_WRITE_CHUNK,
+                    MAX_TLS_RECORD_SIZE,
+                    size
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// 验证转发缓冲区的内存预算
+    pub fn validate_relay_memory_budget(budget_mb: Option<u64>) -> Result<()> {
+        if budget_mb == Some(0) {
+            bail!("relay_memory_budget_mb must be greater than 0");
+        }
+        Ok(())
+    }
+
+    /// 验证速率限制配置
+    pub fn validate_rate_limit_config(config: &super::RateLimitConfig) -> Result<()> {
+        if config.requests_per_second == 0 {
+            bail!("rate_limit.requests_per_second must be greater than 0");
+        }
+        if config.burst_size == 0 {
+            bail!("rate_limit.burst_size must be greater than 0");
+        }
+        if config.burst_size < config.requests_per_second {
+            warn!(
+                "rate_limit.burst_size ({}) is less than requests_per_second ({}), \
+                 this may cause frequent rate limiting",
+                config.burst_size, config.requests_per_second
+            );
+        }
+        Ok(())
+    }
+
+    /// 验证请求大小限制配置
+    pub fn validate_size_limit_config(config: &super::SizeLimitConfig) -> Result<()> {
+        if config.max_request_size == 0 {
+            bail!("size_limits.max_request_size must be greater than 0");
+        }
+        if config.max_header_size == 0 {
+            bail!("size_limits.max_header_size must be greater than 0");
+        }
+        if config.max_header_size > config.max_request_size {
+            bail!(
+                "size_limits.max_header_size ({}) cannot be greater than max_request_size ({})",
+                config.max_header_size,
+                config.max_request_size
+            );
+        }
+        // 建议值检查
+        if config.max_request_size > 100 * 1024 * 1024 {
+            warn!(
+                "size_limits.max_request_size is very large ({} bytes = {} MB), \
+                 this may lead to memory exhaustion attacks",
+                config.max_request_size,
+                config.max_request_size / (1024 * 1024)
+            );
+        }
+        Ok(())
+    }
+
+    /// 验证 Proxy 配置列表
+    pub fn validate_proxies(proxies: &[ProxyConfig]) -> Result<()> {
+        let mut seen_names = HashSet::new();
+        let mut seen_bind = HashSet::new();
+        let mut seen_local_ports = HashSet::new();
+
+        for proxy in proxies {
+            // 验证名称
+            Self::validate_name(&proxy.name, "Proxy name")?;
+
+            // 检查 name 唯一性
+            if !seen_names.insert(&proxy.name) {
+                bail!(
+                    "Duplicate proxy name '{}': each proxy must have a unique name",
+                    proxy.name
+                );
+            }
+
+            // 私有代理不绑定发布端口，publish_port 只作为 visitor 查找的逻辑标识
+            if proxy.visibility.is_private() && proxy.publish_addr != super::default_publish_addr()
+            {
+                bail!(
+                    "Proxy '{}': publish_addr is ignored for private proxies (no listener is bound on the server), remove it",
+                    proxy.name
+                );
+            }
+
+            // publish_port 为 0 时由服务器分配端口：私有代理的端口是 visitor 查找的标识，
+            // 共享代理的各个后端需要约定相同的端口，都不能自动分配
+            let auto_port = proxy.publish_port == 0;
+            if auto_port && (proxy.visibility.is_private() || proxy.shared) {
+                bail!(
+                    "Proxy '{}': publish_port 0 (auto-assigned) is only supported for public, non-shared proxies",
+                    proxy.name
+                );
+            }
+
+            // 检查 (publish_addr, publish_port) 唯一性（分配的端口在注册前未知，不参与检查）
+            if proxy.visibility.is_public()
+                && !auto_port
+                && !seen_bind.insert((
+                    crate::listen_addr::normalize_host(&proxy.publish_addr),
+                    proxy.publish_port,
+                ))
+            {
+                bail!(
+                    "Duplicate publish binding {}:{}: each proxy must use a different server bind address/port",
+                    proxy.publish_addr,
+                    proxy.publish_port
+                );
+            }
+
+            // 配置了多个本地目标时取代 local_port
+            if let Some(targets) = &proxy.local_targets {
+                Self::validate_local_targets(targets, &proxy.name)?;
+                if proxy.sni_routing.is_some() {
+                    bail!(
+                        "Proxy '{}': local_targets cannot be combined with sni_routing",
+                        proxy.name
+                    );
+                }
+            } else {
+                // 检查 local_port 唯一性
+                if !seen_local_ports.insert(proxy.local_port) {
+                    bail!(
+                        "Duplicate local_port {}: each proxy must connect to a different local service",
+                        proxy.local_port
+                    );
+                }
+                Self::validate_port(proxy.local_port, &format!("Proxy '{}'", proxy.name))?;
+            }
+
+            // 验证地址
+            Self::validate_address(&proxy.publish_addr, &format!("Proxy '{}'", proxy.name))?;
+
+            // 验证连接池覆盖项
+            if let Some(pool) = &proxy.pool {
+                Self::validate_proxy_pool_config(pool, &proxy.name)?;
+            }
+
+            // 验证 SNI 路由表
+            if let Some(sni_routing) = &proxy.sni_routing {
+                Self::validate_sni_routing_config(sni_routing, &proxy.name)?;
+            }
+
+            if let Some(socket) = &proxy.socket {
+                Self::validate_socket_options_config(socket, &format!("Proxy '{}'", proxy.name))?;
+            }
+
+            // 空闲保活只作用于分帧的代理 stream，visitor 的 stream 原样转发数据
+            if let Some(secs) = proxy.idle_keepalive_secs {
+                if secs == 0 {
+                    bail!(
+                        "Proxy '{}': idle_keepalive_secs must be greater than 0",
+                        proxy.name
+                    );
+                }
+                if proxy.visibility.is_private() {
+                    bail!(
+                        "Proxy '{}': idle_keepalive_secs is not supported for private proxies (visitor streams are relayed without framing)",
+                        proxy.name
+                    );
+                }
+            }
+
+            // 只有 HTTP/1.1 代理检测 WebSocket 升级
+            if let Some(secs) = proxy.ws_idle_timeout_secs {
+                if secs == 0 {
+                    bail!(
+                        "Proxy '{}': ws_idle_timeout_secs must be greater than 0",
+                        proxy.name
+                    );
+                }
+                if !proxy.proxy_type.detects_upgrades() {
+                    bail!(
+                        "Proxy '{}': ws_idle_timeout_secs is only supported for http/1.1 proxies",
+                        proxy.name
+                    );
+                }
+            }
+
+            // 准入限制作用于发布端口接入的连接，私有代理没有发布端口
+            if let Some(ms) = proxy.require_first_byte_timeout_ms {
+                if ms == 0 {
+                    bail!(
+                        "Proxy '{}': require_first_byte_timeout_ms must be greater than 0",
+                        proxy.name
+                    );
+                }
+                if proxy.visibility.is_private() {
+                    bail!(
+                        "Proxy '{}': require_first_byte_timeout_ms is not supported for private proxies (no published port)",
+                        proxy.name
+                    );
+                }
+            }
+            if let Some(max) = proxy.max_connections_per_source {
+                if max == 0 {
+                    bail!(
+                        "Proxy '{}': max_connections_per_source must be greater than 0",
+                        proxy.name
+                    );
+                }
+                if proxy.visibility.is_private() {
+                    bail!(
+                        "Proxy '{}': max_connections_per_source is not supported for private proxies (no published port)",
+                        proxy.name
+                    );
+                }
+            }
+            if proxy.report_peers && proxy.visibility.is_private() {
+                bail!(
+                    "Proxy '{}': report_peers is not supported for private proxies (no published port)",
+                    proxy.name
+                );
+            }
+            if proxy.integrity_check && proxy.visibility.is_private() {
+                bail!(
+                    "Proxy '{}': integrity_check is not supported for private proxies (visitor streams are not framed)",
+                    proxy.name
+                );
+            }
+
+            if proxy.proxy_type.is_datagram() {
+                Self::validate_udp_proxy(proxy)?;
+            }
+
+            // 验证共享代理权重
+            if let Some(weight) = proxy.weight {
+                if !proxy.shared {
+                    bail!(
+                        "Proxy '{}': weight is only valid when shared = true",
+                        proxy.name
+                    );
+                }
+                if weight == 0 {
+                    bail!("Proxy '{}': weight must be greater than 0", proxy.name);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 验证 UDP 代理：只支持公开、非共享的代理，转发到单个本地目标
+    ///
+    /// 面向 TCP 连接的选项（连接池、套接字选项、SNI 路由、准入限制、分帧相关的选项）
+    /// 对数据报没有意义，配置了就拒绝，而不是静默忽略
+    pub fn validate_udp_proxy(proxy: &ProxyConfig) -> Result<()> {
+        let unsupported = [
+            ("shared", proxy.shared),
+            ("visibility = \"private\"", proxy.visibility.is_private()),
+            ("pool", proxy.pool.is_some()),
+            ("socket", proxy.socket.is_some()),
+            ("sni_routing", proxy.sni_routing.is_some()),
+            ("idle_keepalive_secs", proxy.idle_keepalive_secs.is_some()),
+            (
+                "require_first_byte_timeout_ms",
+                proxy.require_first_byte_timeout_ms.is_some(),
+            ),
+            (
+                "max_connections_per_source",
+                proxy.max_connections_per_source.is_some(),
+            ),
+            ("report_peers", proxy.report_peers),
+            ("integrity_check", proxy.integrity_check),
+        ];
+        if let Some((option, _)) = unsupported.iter().find(|(_, set)| *set) {
+            bail!(
+                "Proxy '{}': {} is not supported for udp proxies",
+                proxy.name,
+                option
+            );
+        }
+        if proxy
+            .local_targets
+            .as_ref()
+            .is_some_and(|targets| targets.len() > 1)
+        {
+            bail!(
+                "Proxy '{}': udp proxies relay to a single local target",
+                proxy.name
+            );
+        }
+        Ok(())
+    }
+
+    /// 验证代理的本地目标列表：至少一个目标，每个目标都是 `host:port`（IPv6 地址加方括号），不能重复
+    pub fn validate_local_targets(targets: &[String], proxy_name: &str) -> Result<()> {
+        if targets.is_empty() {
+            bail!(
+                "Proxy '{}': local_targets must contain at least one target",
+                proxy_name
+            );
+        }
+
+        let mut seen = HashSet::new();
+        for target in targets {
+            let invalid = || {
+                anyhow::anyhow!(
+                    "Proxy '{}': invalid local target '{}', expected host:port (e.g. 127.0.0.1:8081 or [::1]:8081)",
+                    proxy_name,
+                    target
+                )
+            };
+            let (host, port) = target.rsplit_once(':').ok_or_else(invalid)?;
+            let bracketed = host.starts_with('[') && host.ends_with(']');
+            if host.is_empty()
+                || (host.contains(':') && !bracketed)
+                || host.chars().any(|c| c.is_whitespace() || c == '/')
+            {
+                return Err(invalid());
+            }
+            let port: u16 = port.parse().map_err(|_| invalid())?;
+            Self::validate_port(
+                port,
+                &format!("Proxy '{}' local target '{}'", proxy_name, target),
+            )?;
+            if !seen.insert(target.to_ascii_lowercase()) {
+                bail!(
+                    "Proxy '{}': duplicate local target '{}'",
+                    proxy_name,
+                    target
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 验证代理的 SNI 路由表
+    pub fn validate_sni_routing_config(config: &SniRoutingConfig, proxy_name: &str) -> Result<()> {
+        if config.sni_map.is_empty() {
+            bail!(
+                "Proxy '{}': sni_routing.sni_map must contain at least one entry",
+                proxy_name
+            );
+        }
+
+        let mut seen_hosts = HashSet::new();
+        for (host, &port) in &config.sni_map {
+            if host.is_empty()
+                || host.len() > 253
+                || host
+                    .chars()
+                    .any(|c| c.is_whitespace() || matches!(c, ':' | '/' | '*'))
+            {
+                bail!(
+                    "Proxy '{}': invalid sni_routing host name '{}'",
+                    proxy_name,
+                    host
+                );
+            }
+            if !seen_hosts.insert(host.to_ascii_lowercase()) {
+                bail!(
+                    "Proxy '{}': duplicate sni_routing host name '{}' (host names are case-insensitive)",
+                    proxy_name,
+                    host
+                );
+            }
+            Self::validate_port(
+                port,
+                &format!("Proxy '{}' sni_routing entry '{}'", proxy_name, host),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// 验证代理连接池覆盖配置
+    pub fn validate_proxy_pool_config(pool: &ProxyPoolConfig, proxy_name: &str) -> Result<()> {
+        if pool.max_size == Some(0) {
+            bail!(
+                "Proxy '{}': pool.max_size must be greater than 0",
+                proxy_name
+            );
+        }
+
+        if pool.max_lifetime_secs == Some(0) {
+            bail!(
+                "Proxy '{}': pool.max_lifetime_secs must be greater than 0",
+                proxy_name
+            );
+        }
+
+        if let (Some(min_idle), Some(max_size)) = (pool.min_idle, pool.max_size) {
+            if min_idle > max_size {
+                bail!(
+                    "Proxy '{}': pool.min_idle ({}) cannot be greater than pool.max_size ({})",
+                    proxy_name,
+                    min_idle,
+                    max_size
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 验证 Visitor 配置列表
+    pub fn validate_visitors(visitors: &[VisitorConfig]) -> Result<()> {
+        let mut seen_names = HashSet::new();
+        let mut seen_binds = HashSet::new();
+
+        for visitor in visitors {
+            // 验证名称
+            Self::validate_name(&visitor.name, "Visitor name")?;
+            if visitor.name.len() > crate::protocol::MAX_STREAM_NAME_LEN {
+                bail!(
+                    "Visitor name '{}' is too long: at most {} bytes",
+                    visitor.name,
+                    crate::protocol::MAX_STREAM_NAME_LEN
+                );
+            }
+
+            // 检查 name 唯一性
+            if !seen_names.insert(&visitor.name) {
+                bail!(
+                    "Duplicate visitor name '{}': each visitor must have a unique name",
+                    visitor.name
+                );
+            }
+
+            // 检查 (bind_addr, bind_port) 唯一性
+            if !seen_binds.insert((
+                crate::listen_addr::normalize_host(&visitor.bind_addr),
+                visitor.bind_port,
+            )) {
+                bail!(
+                    "Duplicate visitor binding {}:{}: each visitor must use a different local bind address/port",
+                    visitor.bind_addr,
+                    visitor.bind_port
+                );
+            }
+
+            // 验证端口（publish_port 为 0 时按名称匹配代理，可以访问由服务器分配端口的代理）
+            Self::validate_port(visitor.bind_port, &format!("Visitor '{}'", visitor.name))?;
+
+            // 验证地址
+            Self::validate_address(&visitor.bind_addr, &format!("Visitor '{}'", visitor.name))?;
+
+            if let Some(socket) = &visitor.socket {
+                Self::validate_socket_options_config(
+                    socket,
+                    &format!("Visitor '{}'", visitor.name),
+                )?;
+            }
+
+            if let Some(expected) = &visitor.expected_peer_id {
+                Self::validate_expected_peer_id(expected, &format!("Visitor '{}'", visitor.name))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 验证 Forwarder 配置列表
+    pub fn validate_forwarders(forwarders: &[ForwarderConfig]) -> Result<()> {
+        let mut seen_names = HashSet::new();
+        let mut seen_binds = HashSet::new();
+
+        for forwarder in forwarders {
+            // 验证名称
+            Self::validate_name(&forwarder.name, "Forwarder name")?;
+
+            // 检查 name 唯一性
+            if !seen_names.insert(&forwarder.name) {
+                bail!(
+                    "Duplicate forwarder name '{}': each forwarder must have a unique name",
+                    forwarder.name
+                );
+            }
+
+            // 检查 (bind_addr, bind_port) 唯一性
+            if !seen_binds.insert((
+                crate::listen_addr::normalize_host(&forwarder.bind_addr),
+                forwarder.bind_port,
+            )) {
+                bail!(
+                    "Duplicate forwarder binding {}:{}: each forwarder must use a different local bind address/port",
+                    forwarder.bind_addr,
+                    forwarder.bind_port
+                );
+            }
+
+            // 只支持代理协议类型（routing 等选项对所有类型同样生效）
+            if !forwarder.proxy_type.is_forwarder_type() {
+                bail!(
+                    "Forwarder '{}': proxy_type must be 'http', 'socks5' or 'auto'",
+                    forwarder.name
+                );
+            }
+
+            // 安全检查：警告绑定到非本地地址
+            Self::check_forwarder_security(&forwarder.name, &forwarder.bind_addr);
+
+            // 验证端口
+            Self::validate_port(
+                forwarder.bind_port,
+                &format!("Forwarder '{}'", forwarder.name),
+            )?;
+
+            // 验证地址
+            Self::validate_address(
+                &forwarder.bind_addr,
+                &format!("Forwarder '{}'", forwarder.name),
+            )?;
+
+            // 验证时间段规则和配额
+            if let Some(ref routing) = forwarder.routing {
+                Self::validate_routing_config(routing)
+                    .with_context(|| format!("Forwarder '{}' routing", forwarder.name))?;
+            }
+
+            if let Some(ref fast_fail) = forwarder.fast_fail {
+                Self::validate_fast_fail_config(&forwarder.name, fast_fail)?;
+            }
+
+            if let Some(ref egress) = forwarder.direct_egress {
+                Self::validate_direct_egress_config(&forwarder.name, egress)?;
+            }
+
+            if let Some(ref socket) = forwarder.socket {
+                Self::validate_socket_options_config(
+                    socket,
+                    &format!("Forwarder '{}'", forwarder.name),
+                )?;
+            }
+
+            if let Some(ref rules) = forwarder.header_rules {
+                Self::validate_header_rules(forwarder, rules)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 验证 forwarder 的请求头改写规则
+    ///
+    /// 规则只作用于明文 HTTP 直接转发，socks5 forwarder 上配置规则没有意义；
+    /// 不允许改写决定报文边界和连接管理的头部
+    pub fn validate_header_rules(
+        forwarder: &ForwarderConfig,
+        rules: &[HeaderRuleConfig],
+    ) -> Result<()> {
+        if !matches!(
+            forwarder.proxy_type,
+            ProxyType::HttpProxy | ProxyType::AutoProxy
+        ) {
+            bail!(
+                "Forwarder '{}': header_rules only apply to plain HTTP requests, proxy_type must be 'http' or 'auto' (CONNECT and SOCKS5 traffic is never rewritten)",
+                forwarder.name
+            );
+        }
+        for (index, rule) in rules.iter().enumerate() {
+            let context = format!("Forwarder '{}' header_rules[{}]", forwarder.name, index);
+            if rule.match_domains.is_empty() || rule.match_domains.iter().any(|d| d.is_empty()) {
+                bail!(
+                    "{}: match_domains must list at least one non-empty domain",
+                    context
+                );
+            }
+            if rule.add.is_empty() && rule.remove.is_empty() {
+                bail!("{}: nothing to add or remove", context);
+            }
+            for name in rule.add.keys() {
+                if !is_header_name(name) {
+                    bail!("{}: invalid header name '{}'", context, name);
+                }
+                if PROTECTED_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+                    bail!("{}: header '{}' cannot be added", context, name);
+                }
+            }
+            for (name, value) in &rule.add {
+                if value.contains(['\r', '\n']) {
+                    bail!("{}: value of '{}' contains a line break", context, name);
+                }
+            }
+            for name in &rule.remove {
+                if !is_header_name(name.strip_suffix('*').unwrap_or(name)) {
+                    bail!("{}: invalid header name '{}' in remove", context, name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 验证路由的时间段规则和月配额
+    pub fn validate_routing_config(routing: &RoutingConfig) -> Result<()> {
+        routing.utc_offset_secs()?;
+
+        for schedule in &routing.schedules {
+            schedule.window()?;
+            if schedule.strategy == RoutingStrategy::Unknown {
+                bail!(
+                    "Schedule '{}' has an unknown strategy: expected direct or proxy",
+                    schedule.label()
+                );
+            }
+        }
+
+        if let Some(ref quota) = routing.quota {
+            if quota.bytes_per_month == 0 {
+                bail!("quota.bytes_per_month must be greater than 0");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 验证路由域名规则（支持 `*.example.com` 和 `.example.com` 通配形式）
+    pub fn validate_routing_domain(pattern: &str) -> Result<()> {
+        let domain = pattern
+            .strip_prefix("*.")
+            .or_else(|| pattern.strip_prefix('.'))
+            .unwrap_or(pattern);
+
+        if domain.is_empty() || domain.len() > 253 {
+            bail!(
+                "Invalid domain '{}': length must be 1-253 characters",
+                pattern
+            );
+        }
+        for label in domain.split('.') {
+            if label.is_empty() || label.len() > 63 {
+                bail!(
+                    "Invalid domain '{}': each label must be 1-63 characters",
+                    pattern
+                );
+            }
+            if !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                || label.starts_with('-')
+                || label.ends_with('-')
+            {
+                bail!(
+                    "Invalid domain '{}': labels may only contain letters, digits and inner hyphens (wildcards only as a leading '*.')",
+                    pattern
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// 验证路由 IP/CIDR 规则（如 `10.0.0.0/8`、`2001:db8::/32`、`192.168.1.1`）
+    pub fn validate_routing_cidr(value: &str) -> Result<()> {
+        if let Err(e) = value.parse::<ipnetwork::IpNetwork>() {
+            bail!("Invalid IP/CIDR '{}': {}", value, e);
+        }
+        Ok(())
+    }
+
+    /// 检查 forwarder 安全性（绑定地址）
+    fn check_forwarder_security(name: &str, bind_addr: &str) {
+        let loopback = bind_addr == "localhost"
+            || crate::listen_addr::parse_ip(bind_addr).is_some_and(|ip| ip.is_loopback());
+        if !loopback {
+            warn!(
+                "⚠️  SECURITY WARNING: Forwarder '{}' is binding to '{}' which exposes the proxy to your network!\n\
+                 This allows anyone on the network to use your proxy, which may lead to:\n\
+                 - Abuse of your IP address\n\
+                 - Your IP being blacklisted\n\
+                 - Unauthorized use of your bandwidth\n\
+                 RECOMMENDATION: Use bind_addr = '127.0.0.1' for localhost-only access.",
+                name, bind_addr
+            );
+        }
+    }
+
+    /// 验证客户端完整配置
+    pub fn validate_client_full_config(config: &ClientFullConfig) -> Result<()> {
+        // 验证认证密钥
+        Self::validate_auth_key(&config.client.auth_key)?;
+
+        // 至少要有一个配置
+        if config.proxies.is_empty()
+            && config.visitors.is_empty()
+            && config.forwarders.is_empty()
+            && config.visitor_gateway.is_none()
+        {
+            bail!("No proxy, visitor, forwarder or visitor_gateway configurations defined");
+        }
+
+        // 验证统计服务器防护配置
+        if let Some(ref stats_limits) = config.client.stats_limits {
+            Self::validate_stats_limit_config(stats_limits)?;
+        }
+        if let Some(ref stats_stream) = config.client.stats_stream {
+            Self::validate_stats_stream_config(stats_stream)?;
+        }
+
+        // 客户端证书和私钥必须同时设置
+        match (
+            &config.client.client_cert_path,
+            &config.client.client_key_path,
+        ) {
+            (Some(_), Some(_)) | (None, None) => {}
+            _ => bail!("client_cert_path and client_key_path must both be set, or both omitted"),
+        }
+
+        Self::validate_stats_local_endpoints(&config.client)?;
+
+        if let Some(ref retry) = config.client.proxy_retry {
+            Self::validate_proxy_retry_config(retry)?;
+        }
+
+        Self::validate_max_write_chunk(config.client.max_write_chunk)?;
+        Self::validate_relay_memory_budget(config.client.relay_memory_budget_mb)?;
+
+        if config.client.max_missed_heartbeats == Some(0) {
+            bail!("max_missed_heartbeats must be greater than 0");
+        }
+
+        Self::validate_client_timeouts(&config.client.timeouts(), &config.forwarders)?;
+        Self::validate_health(&config.health())?;
+
+        // 验证请求大小限制配置
+        if let Some(ref size_limits) = config.client.size_limits {
+            Self::validate_size_limit_config(size_limits)?;
+        }
+
+        // 路由设置页面挂在统计服务器上
+        if let Some(ref token) = config.client.routing_ui_token {
+            if token.is_empty() {
+                bail!("routing_ui_token cannot be empty");
+            }
+            if config.client.stats_port.is_none()
+                && config.client.stats_socket.is_none()
+                && config.client.stats_pipe.is_none()
+            {
+                bail!(
+                    "routing_ui_token requires stats_port, stats_socket or stats_pipe to be configured"
+                );
+            }
+        }
+
+        // 验证各个配置列表
+        Self::validate_proxies(&config.proxies)?;
+        Self::validate_visitors(&config.visitors)?;
+        Self::validate_forwarders(&config.forwarders)?;
+
+        if let Some(ref gateway) = config.visitor_gateway {
+            Self::validate_visitor_gateway(gateway)?;
+            // 网关与 visitor/forwarder 不能监听同一地址
+            let bind = (gateway.bind_addr.as_str(), gateway.bind_port);
+            if config
+                .visitors
+                .iter()
+                .map(|v| (v.bind_addr.as_str(), v.bind_port))
+                .chain(
+                    config
+                        .forwarders
+                        .iter()
+                        .map(|f| (f.bind_addr.as_str(), f.bind_port)),
+                )
+                .any(|other| other == bind)
+            {
+                bail!(
+                    "visitor_gateway binding {}:{} is already used by a visitor or forwarder",
+                    gateway.bind_addr,
+                    gateway.bind_port
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 拒绝别名与规范字段同时设置且取值不同的配置
+    pub fn validate_field_aliases(report: &super::compat::CompatReport) -> Result<()> {
+        if let Some(conflict) = report.conflicts.first() {
+            bail!(
+                "{} entry '{}' sets both {} = {} and {} = {}; remove the deprecated {}",
+                conflict.alias.section,
+                conflict.entry,
+                conflict.alias.alias,
+                conflict.alias_value,
+                conflict.alias.canonical,
+                conflict.canonical_value,
+                conflict.alias.alias
+            );
+        }
+        Ok(())
+    }
+
+    /// 验证 visitor 网关配置
+    pub fn validate_visitor_gateway(gateway: &VisitorGatewayConfig) -> Result<()> {
+        Self::validate_port(gateway.bind_port, "visitor_gateway")?;
+        Self::validate_address(&gateway.bind_addr, "visitor_gateway")?;
+
+        let domain = gateway.domain.trim_matches('.');
+        if domain.is_empty() || domain.len() != gateway.domain.len() {
+            bail!(
+                "visitor_gateway: domain '{}' must be a non-empty suffix without leading or trailing dots",
+                gateway.domain
+            );
+        }
+        for (name, peer_id) in &gateway.expected_peer_ids {
+            Self::validate_name(name, "visitor_gateway expected_peer_ids")?;
+            if peer_id.is_empty() {
+                bail!(
+                    "visitor_gateway: expected peer_id for '{}' cannot be empty",
+                    name
+                );
+            }
+            Self::validate_expected_peer_id(peer_id, "visitor_gateway expected_peer_ids")?;
+        }
+        if let Some(ref socket) = gateway.socket {
+            Self::validate_socket_options_config(socket, "visitor_gateway")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
 mod tests {
+    use super::super::ProxyVisibility;
     use super::*;
 
     #[test]
-    fn test_validate_auth_key() {
-        // 太短的密钥应该失败
-        assert!(ConfigValidator::validate_auth_key("short").is_err());
+    fn test_validate_auth_key() {
+        // 太短的密钥应该失败
+        assert!(ConfigValidator::validate_auth_key("short").is_err());
+
+        // 长度够的密钥应该成功
+        assert!(ConfigValidator::validate_auth_key("1234567890123456").is_ok());
+        assert!(ConfigValidator::validate_auth_key("very-long-secure-key-12345678").is_ok());
+    }
+
+    #[test]
+    fn test_auth_key_weakness() {
+        let weak = [
+            ("12345678901234567890", "sequential"),
+            ("aaaaaaaaaaaaaaaaaaaa", "distinct"),
+            ("your-secret-auth-key-change-me", "secret"),
+            ("MyPassword!2024xyz", "password"),
+            ("k3y9Q!zzzzz-Wp7vR2", "repeated"),
+            ("zyxwvB7q!Lm3Rt9Hs", "sequential"),
+            ("Xa9!Xa9!Xa9!Xa9!Xa9!", "distinct"),
+            ("q7Wx!2Lp9Zq7Wx!2Lp9Z", "pattern"),
+        ];
+        for (key, reason) in weak {
+            let found = ConfigValidator::auth_key_weakness(key)
+                .unwrap_or_else(|| panic!("{} should be flagged", key));
+            assert!(found.contains(reason), "{}: {}", key, found);
+            // 只是警告，不影响验证结果
+            assert!(ConfigValidator::validate_auth_key(key).is_ok());
+        }
+
+        for key in ["9fJ2kQx7LmP4vRt8ZsWc3Hn6", "Tq8-vL2_xR7pK4mZ9wNc"] {
+            assert_eq!(ConfigValidator::auth_key_weakness(key), None, "{}", key);
+        }
+    }
+
+    #[test]
+    fn test_validate_port() {
+        // 端口 0 应该失败
+        assert!(ConfigValidator::validate_port(0, "test").is_err());
+
+        // 有效端口应该成功
+        assert!(ConfigValidator::validate_port(8080, "test").is_ok());
+        assert!(ConfigValidator::validate_port(443, "test").is_ok());
+        assert!(ConfigValidator::validate_port(65535, "test").is_ok());
+    }
+
+    #[test]
+    fn test_validate_address() {
+        // 空地址应该失败
+        assert!(ConfigValidator::validate_address("", "test").is_err());
+        assert!(ConfigValidator::validate_address("   ", "test").is_err());
+
+        // 有效地址应该成功
+        assert!(ConfigValidator::validate_address("127.0.0.1", "test").is_ok());
+        assert!(ConfigValidator::validate_address("0.0.0.0", "test").is_ok());
+        assert!(ConfigValidator::validate_address("example.com", "test").is_ok());
+
+        // IPv6 可以带或不带方括号
+        assert!(ConfigValidator::validate_address("::", "test").is_ok());
+        assert!(ConfigValidator::validate_address("2001:db8::1", "test").is_ok());
+        assert!(ConfigValidator::validate_address("[::1]", "test").is_ok());
+        assert!(ConfigValidator::validate_address("[::1", "test").is_err());
+        assert!(ConfigValidator::validate_address("[::1]:8080", "test").is_err());
+        assert!(ConfigValidator::validate_address("[127.0.0.1]", "test").is_err());
+    }
+
+    #[test]
+    fn test_validate_name() {
+        // 空名称应该失败
+        assert!(ConfigValidator::validate_name("", "test").is_err());
+        assert!(ConfigValidator::validate_name("   ", "test").is_err());
+
+        // 有效名称应该成功
+        assert!(ConfigValidator::validate_name("proxy1", "test").is_ok());
+        assert!(ConfigValidator::validate_name("my-proxy", "test").is_ok());
+    }
+
+    #[test]
+    fn test_validate_rate_limit_config() {
+        use super::super::RateLimitConfig;
+
+        // requests_per_second = 0 应该失败
+        let invalid_config = RateLimitConfig {
+            requests_per_second: 0,
+            burst_size: 200,
+        };
+        assert!(ConfigValidator::validate_rate_limit_config(&invalid_config).is_err());
+
+        // burst_size = 0 应该失败
+        let invalid_config = RateLimitConfig {
+            requests_per_second: 100,
+            burst_size: 0,
+        };
+        assert!(ConfigValidator::validate_rate_limit_config(&invalid_config).is_err());
+
+        // 有效配置应该成功
+        let valid_config = RateLimitConfig {
+            requests_per_second: 100,
+            burst_size: 200,
+        };
+        assert!(ConfigValidator::validate_rate_limit_config(&valid_config).is_ok());
+    }
+
+    #[test]
+    fn test_validate_size_limit_config() {
+        use super::super::SizeLimitConfig;
+
+        // max_request_size = 0 应该失败
+        let invalid_config = SizeLimitConfig {
+            max_request_size: 0,
+            max_header_size: 8192,
+        };
+        assert!(ConfigValidator::validate_size_limit_config(&invalid_config).is_err());
+
+        // max_header_size = 0 应该失败
+        let invalid_config = SizeLimitConfig {
+            max_request_size: 1048576,
+            max_header_size: 0,
+        };
+        assert!(ConfigValidator::validate_size_limit_config(&invalid_config).is_err());
+
+        // max_header_size > max_request_size 应该失败
+        let invalid_config = SizeLimitConfig {
+            max_request_size: 8192,
+            max_header_size: 16384,
+        };
+        assert!(ConfigValidator::validate_size_limit_config(&invalid_config).is_err());
+
+        // 有效配置应该成功
+        let valid_config = SizeLimitConfig {
+            max_request_size: 1048576,
+            max_header_size: 8192,
+        };
+        assert!(ConfigValidator::validate_size_limit_config(&valid_config).is_ok());
+    }
+
+    #[test]
+    fn test_validate_proxy_pool_config() {
+        // max_size = 0 应该失败
+        let invalid_config = ProxyPoolConfig {
+            max_size: Some(0),
+            ..Default::default()
+        };
+        assert!(ConfigValidator::validate_proxy_pool_config(&invalid_config, "test").is_err());
+
+        // min_idle > max_size 应该失败
+        let invalid_config = ProxyPoolConfig {
+            max_size: Some(2),
+            min_idle: Some(4),
+            ..Default::default()
+        };
+        assert!(ConfigValidator::validate_proxy_pool_config(&invalid_config, "test").is_err());
+
+        // max_lifetime_secs = 0 应该失败
+        let invalid_config = ProxyPoolConfig {
+            max_lifetime_secs: Some(0),
+            ..Default::default()
+        };
+        assert!(ConfigValidator::validate_proxy_pool_config(&invalid_config, "test").is_err());
+
+        // 有效配置应该成功
+        let valid_config = ProxyPoolConfig {
+            max_size: Some(8),
+            min_idle: Some(2),
+            warmup: Some(false),
+            reuse: Some(true),
+            max_lifetime_secs: Some(3300),
+        };
+        assert!(ConfigValidator::validate_proxy_pool_config(&valid_config, "test").is_ok());
+        assert!(
+            ConfigValidator::validate_proxy_pool_config(&ProxyPoolConfig::default(), "test")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_validate_sni_routing_config() {
+        let config = |entries: &[(&str, u16)]| SniRoutingConfig {
+            sni_map: entries
+                .iter()
+                .map(|(host, port)| (host.to_string(), *port))
+                .collect(),
+        };
+
+        let valid = config(&[
+            ("app1.example.com", 8081),
+            ("app2.example.com", 8082),
+            ("default", 8080),
+        ]);
+        assert!(ConfigValidator::validate_sni_routing_config(&valid, "web").is_ok());
+        assert_eq!(valid.select(Some("APP2.example.com")), Some(8082));
+        assert_eq!(valid.select(Some("other.example.com")), Some(8080));
+        assert_eq!(valid.select(None), Some(8080));
+        assert_eq!(config(&[("app1.example.com", 8081)]).select(None), None);
+
+        // 空表、端口为 0、非法主机名和仅大小写不同的重复主机名应该失败
+        assert!(ConfigValidator::validate_sni_routing_config(&config(&[]), "web").is_err());
+        assert!(ConfigValidator::validate_sni_routing_config(
+            &config(&[("a.example.com", 0)]),
+            "web"
+        )
+        .is_err());
+        assert!(ConfigValidator::validate_sni_routing_config(
+            &config(&[("a.example.com:443", 8081)]),
+            "web"
+        )
+        .is_err());
+        assert!(ConfigValidator::validate_sni_routing_config(
+            &config(&[("A.example.com", 8081), ("a.example.com", 8082)]),
+            "web"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_validate_shared_proxy_weight() {
+        let proxy = |shared, weight| ProxyConfig {
+            name: "svc".to_string(),
+            proxy_type: Default::default(),
+            publish_addr: "0.0.0.0".to_string(),
+            publish_port: 9000,
+            local_port: 8080,
+            pool: None,
+            shared,
+            weight,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        };
+
+        assert!(ConfigValidator::validate_proxies(&[proxy(true, Some(3))]).is_ok());
+        assert!(ConfigValidator::validate_proxies(&[proxy(true, None)]).is_ok());
+        // weight = 0 应该失败
+        assert!(ConfigValidator::validate_proxies(&[proxy(true, Some(0))]).is_err());
+        // 非共享代理不能设置 weight
+        assert!(ConfigValidator::validate_proxies(&[proxy(false, Some(2))]).is_err());
+    }
+
+    #[test]
+    fn test_validate_local_targets() {
+        let proxy = |local_port, targets: Option<&[&str]>| ProxyConfig {
+            name: "svc".to_string(),
+            proxy_type: Default::default(),
+            publish_addr: "0.0.0.0".to_string(),
+            publish_port: 9000,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: targets.map(|t| t.iter().map(|s| s.to_string()).collect()),
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        };
+
+        // 配置了 local_targets 时可以省略 local_port
+        let valid = proxy(
+            0,
+            Some(&["127.0.0.1:8081", "backend.lan:8082", "[::1]:8083"]),
+        );
+        assert!(ConfigValidator::validate_proxies(std::slice::from_ref(&valid)).is_ok());
+        assert_eq!(valid.local_addrs().len(), 3);
+        let submitted = valid.for_server();
+        assert_eq!(submitted.local_port, 8081);
+        assert!(submitted.local_targets.is_none());
+        assert_eq!(proxy(8080, None).local_addrs(), vec!["127.0.0.1:8080"]);
+
+        // 配置了 local_targets 的代理不参与 local_port 唯一性检查
+        assert!(ConfigValidator::validate_proxies(&[
+            proxy(0, Some(&["127.0.0.1:8081"])),
+            ProxyConfig {
+                name: "other".to_string(),
+                publish_port: 9001,
+                ..proxy(0, Some(&["127.0.0.1:8082"]))
+            },
+        ])
+        .is_ok());
+
+        // 未配置 local_targets 时仍然要求 local_port
+        assert!(ConfigValidator::validate_proxies(&[proxy(0, None)]).is_err());
+        for targets in [
+            &[][..],
+            &["127.0.0.1"][..],
+            &["127.0.0.1:0"][..],
+            &["127.0.0.1:http"][..],
+            &[":8081"][..],
+            &["::1:8081"][..],
+            &["127.0.0.1:8081", "127.0.0.1:8081"][..],
+        ] {
+            assert!(
+                ConfigValidator::validate_proxies(&[proxy(0, Some(targets))]).is_err(),
+                "{:?}",
+                targets
+            );
+        }
+
+        // 不能与 SNI 路由同时使用
+        let mut sni = proxy(8080, Some(&["127.0.0.1:8081"]));
+        sni.sni_routing = Some(SniRoutingConfig {
+            sni_map: [("default".to_string(), 8080)].into_iter().collect(),
+        });
+        assert!(ConfigValidator::validate_proxies(&[sni]).is_err());
+    }
+
+    #[test]
+    fn test_validate_private_proxy() {
+        let proxy = |name: &str, publish_addr: &str, visibility| ProxyConfig {
+            name: name.to_string(),
+            proxy_type: Default::default(),
+            publish_addr: publish_addr.to_string(),
+            publish_port: 9000,
+            local_port: if name == "a" { 8080 } else { 8081 },
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        };
+
+        assert!(ConfigValidator::validate_proxies(&[proxy(
+            "a",
+            "0.0.0.0",
+            ProxyVisibility::Private
+        )])
+        .is_ok());
+        // 私有代理不能指定 publish_addr
+        assert!(ConfigValidator::validate_proxies(&[proxy(
+            "a",
+            "127.0.0.1",
+            ProxyVisibility::Private
+        )])
+        .is_err());
+        // 私有代理不占用发布端口，可以与公开代理使用相同的 publish_port
+        assert!(ConfigValidator::validate_proxies(&[
+            proxy("a", "0.0.0.0", ProxyVisibility::Public),
+            proxy("b", "0.0.0.0", ProxyVisibility::Private),
+        ])
+        .is_ok());
+        // 私有代理仍然需要有效的 publish_port
+        let mut missing_port = proxy("a", "0.0.0.0", ProxyVisibility::Private);
+        missing_port.publish_port = 0;
+        assert!(ConfigValidator::validate_proxies(&[missing_port]).is_err());
+    }
+
+    #[test]
+    fn test_validate_auto_publish_port() {
+        let proxy = |name: &str, local_port| ProxyConfig {
+            name: name.to_string(),
+            proxy_type: Default::default(),
+            publish_addr: "0.0.0.0".to_string(),
+            publish_port: 0,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        };
 
-        // 长度够的密钥应该成功
-        assert!(ConfigValidator::validate_auth_key("1234567890123456").is_ok());
-        assert!(ConfigValidator::validate_auth_key("very-long-secure-key-12345678").is_ok());
+        // 多个自动分配端口的代理不会被视为重复绑定
+        assert!(ConfigValidator::validate_proxies(&[proxy("a", 8080), proxy("b", 8081)]).is_ok());
+        // 共享代理不能自动分配端口
+        let mut shared = proxy("a", 8080);
+        shared.shared = true;
+        assert!(ConfigValidator::validate_proxies(&[shared]).is_err());
     }
 
     #[test]
-    fn test_validate_port() {
-        // 端口 0 应该失败
-        assert!(ConfigValidator::validate_port(0, "test").is_err());
+    fn test_validate_idle_keepalive() {
+        let proxy = |visibility, idle_keepalive_secs| ProxyConfig {
+            name: "a".to_string(),
+            proxy_type: Default::default(),
+            publish_addr: "0.0.0.0".to_string(),
+            publish_port: 9000,
+            local_port: 8080,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        };
 
-        // 有效端口应该成功
-        assert!(ConfigValidator::validate_port(8080, "test").is_ok());
-        assert!(ConfigValidator::validate_port(443, "test").is_ok());
-        assert!(ConfigValidator::validate_port(65535, "test").is_ok());
+        assert!(
+            ConfigValidator::validate_proxies(&[proxy(ProxyVisibility::Public, Some(30))]).is_ok()
+        );
+        assert!(
+            ConfigValidator::validate_proxies(&[proxy(ProxyVisibility::Public, Some(0))]).is_err()
+        );
+        // visitor 的 stream 不分帧
+        assert!(
+            ConfigValidator::validate_proxies(&[proxy(ProxyVisibility::Private, Some(30))])
+                .is_err()
+        );
     }
 
     #[test]
-    fn test_validate_address() {
-        // 空地址应该失败
-        assert!(ConfigValidator::validate_address("", "test").is_err());
-        assert!(ConfigValidator::validate_address("   ", "test").is_err());
+    fn test_validate_ws_idle_timeout() {
+        let proxy = |proxy_type, ws_idle_timeout_secs| ProxyConfig {
+            name: "a".to_string(),
+            proxy_type,
+            publish_addr: "0.0.0.0".to_string(),
+            publish_port: 9000,
+            local_port: 8080,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs,
+            integrity_check: false,
+        };
 
-        // 有效地址应该成功
-        assert!(ConfigValidator::validate_address("127.0.0.1", "test").is_ok());
-        assert!(ConfigValidator::validate_address("0.0.0.0", "test").is_ok());
-        assert!(ConfigValidator::validate_address("example.com", "test").is_ok());
+        assert!(ConfigValidator::validate_proxies(&[proxy(ProxyType::Http11, Some(600))]).is_ok());
+        assert!(ConfigValidator::validate_proxies(&[proxy(ProxyType::Http11, Some(0))]).is_err());
+        // 其他代理类型不检测升级
+        assert!(ConfigValidator::validate_proxies(&[proxy(ProxyType::Tcp, Some(600))]).is_err());
     }
 
     #[test]
-    fn test_validate_name() {
-        // 空名称应该失败
-        assert!(ConfigValidator::validate_name("", "test").is_err());
-        assert!(ConfigValidator::validate_name("   ", "test").is_err());
+    fn test_validate_udp_proxy() {
+        let udp = ProxyConfig {
+            name: "dns".to_string(),
+            proxy_type: ProxyType::Udp,
+            publish_addr: "0.0.0.0".to_string(),
+            publish_port: 5353,
+            local_port: 53,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        };
+        assert!(ConfigValidator::validate_proxies(std::slice::from_ref(&udp)).is_ok());
 
-        // 有效名称应该成功
-        assert!(ConfigValidator::validate_name("proxy1", "test").is_ok());
-        assert!(ConfigValidator::validate_name("my-proxy", "test").is_ok());
+        let shared = ProxyConfig {
+            shared: true,
+            ..udp.clone()
+        };
+        let err = ConfigValidator::validate_proxies(&[shared]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Proxy 'dns': shared is not supported for udp proxies"
+        );
+        let private = ProxyConfig {
+            visibility: ProxyVisibility::Private,
+            ..udp.clone()
+        };
+        assert!(ConfigValidator::validate_proxies(&[private]).is_err());
+        let keepalive = ProxyConfig {
+            idle_keepalive_secs: Some(30),
+            ..udp.clone()
+        };
+        assert!(ConfigValidator::validate_proxies(&[keepalive]).is_err());
+
+        // 只能转发到单个本地目标
+        let single = ProxyConfig {
+            local_targets: Some(vec!["127.0.0.1:53".to_string()]),
+            ..udp.clone()
+        };
+        assert!(ConfigValidator::validate_proxies(&[single]).is_ok());
+        let several = ProxyConfig {
+            local_targets: Some(vec!["127.0.0.1:53".to_string(), "127.0.0.1:54".to_string()]),
+            ..udp
+        };
+        assert!(ConfigValidator::validate_proxies(&[several]).is_err());
     }
 
     #[test]
-    fn test_validate_rate_limit_config() {
-        use super::super::RateLimitConfig;
+    fn test_validate_stats_limit_config() {
+        use super::super::StatsLimitConfig;
 
-        // requests_per_second = 0 应该失败
-        let invalid_config = RateLimitConfig {
-            requests_per_second: 0,
-            burst_size: 200,
+        // 任意一项为 0 应该失败
+        let invalid_config = StatsLimitConfig {
+            read_timeout_secs: 0,
+            ..Default::default()
         };
-        assert!(ConfigValidator::validate_rate_limit_config(&invalid_config).is_err());
+        assert!(ConfigValidator::validate_stats_limit_config(&invalid_config).is_err());
 
-        // burst_size = 0 应该失败
-        let invalid_config = RateLimitConfig {
-            requests_per_second: 100,
-            burst_size: 0,
+        let invalid_config = StatsLimitConfig {
+            max_connections: 0,
+            ..Default::default()
         };
-        assert!(ConfigValidator::validate_rate_limit_config(&invalid_config).is_err());
+        assert!(ConfigValidator::validate_stats_limit_config(&invalid_config).is_err());
 
-        // 有效配置应该成功
-        let valid_config = RateLimitConfig {
-            requests_per_second: 100,
-            burst_size: 200,
+        let invalid_config = StatsLimitConfig {
+            max_request_size: 0,
+            ..Default::default()
         };
-        assert!(ConfigValidator::validate_rate_limit_config(&valid_config).is_ok());
+        assert!(ConfigValidator::validate_stats_limit_config(&invalid_config).is_err());
+
+        // 默认配置应该成功
+        assert!(ConfigValidator::validate_stats_limit_config(&StatsLimitConfig::default()).is_ok());
     }
 
     #[test]
-    fn test_validate_size_limit_config() {
-        use super::super::SizeLimitConfig;
+    fn test_validate_forward_limit_config() {
+        use super::super::ForwardLimitConfig;
 
-        // max_request_size = 0 应该失败
-        let invalid_config = SizeLimitConfig {
-            max_request_size: 0,
-            max_header_size: 8192,
+        let invalid_config = ForwardLimitConfig {
+            max_bytes: Some(0),
+            ..Default::default()
         };
-        assert!(ConfigValidator::validate_size_limit_config(&invalid_config).is_err());
+        assert!(ConfigValidator::validate_forward_limit_config(&invalid_config).is_err());
 
-        // max_header_size = 0 应该失败
-        let invalid_config = SizeLimitConfig {
-            max_request_size: 1048576,
-            max_header_size: 0,
+        let invalid_config = ForwardLimitConfig {
+            max_concurrent_per_client: Some(0),
+            ..Default::default()
         };
-        assert!(ConfigValidator::validate_size_limit_config(&invalid_config).is_err());
+        assert!(ConfigValidator::validate_forward_limit_config(&invalid_config).is_err());
 
-        // max_header_size > max_request_size 应该失败
-        let invalid_config = SizeLimitConfig {
-            max_request_size: 8192,
-            max_header_size: 16384,
+        let invalid_config = ForwardLimitConfig {
+            connect_timeout_secs: Some(0),
+            ..Default::default()
         };
-        assert!(ConfigValidator::validate_size_limit_config(&invalid_config).is_err());
+        assert!(ConfigValidator::validate_forward_limit_config(&invalid_config).is_err());
 
-        // 有效配置应该成功
-        let valid_config = SizeLimitConfig {
-            max_request_size: 1048576,
-            max_header_size: 8192,
+        let invalid_config = ForwardLimitConfig {
+            max_connecting_per_client: Some(0),
+            ..Default::default()
         };
-        assert!(ConfigValidator::validate_size_limit_config(&valid_config).is_ok());
+        assert!(ConfigValidator::validate_forward_limit_config(&invalid_config).is_err());
+
+        // 未设置的项不限制
+        assert!(
+            ConfigValidator::validate_forward_limit_config(&ForwardLimitConfig::default()).is_ok()
+        );
+        let valid_config = ForwardLimitConfig {
+            max_duration_secs: Some(600),
+            max_bytes: Some(1024 * 1024 * 1024),
+            max_concurrent_per_client: Some(32),
+            connect_timeout_secs: Some(5),
+            // 0 关闭失败目标的冷却
+            failure_cooldown_secs: Some(0),
+            max_connecting_per_client: Some(8),
+        };
+        assert!(ConfigValidator::validate_forward_limit_config(&valid_config).is_ok());
+    }
+
+    #[test]
+    fn test_validate_stats_path() {
+        use crate::transport::TransportType;
+
+        assert!(ConfigValidator::validate_stats_path("/stats", TransportType::Http2).is_ok());
+        assert!(ConfigValidator::validate_stats_path("/admin/stats", TransportType::Wss).is_ok());
+
+        // TLS 传输没有 HTTP 层，无法按路径区分
+        assert!(ConfigValidator::validate_stats_path("/stats", TransportType::Tls).is_err());
+
+        for path in ["", "/", "stats", "/stats/", "/stats?x=1"] {
+            assert!(
+                ConfigValidator::validate_stats_path(path, TransportType::Http2).is_err(),
+                "{:?} should be rejected",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_stats_local_endpoints() {
+        let client = |socket: Option<&str>, pipe: Option<&str>| {
+            let mut config = super::super::ClientConfig::builder()
+                .server_addr("127.0.0.1")
+                .server_port(8443)
+                .auth_key("test-key-1234567890")
+                .build()
+                .unwrap();
+            config.stats_socket = socket.map(Into::into);
+            config.stats_pipe = pipe.map(str::to_string);
+            config
+        };
+
+        assert!(ConfigValidator::validate_stats_local_endpoints(&client(None, None)).is_ok());
+        assert!(ConfigValidator::validate_stats_local_endpoints(&client(Some(""), None)).is_err());
+        assert!(
+            ConfigValidator::validate_stats_local_endpoints(&client(None, Some("tls-tunnel")))
+                .is_err()
+        );
+        assert!(
+            ConfigValidator::validate_stats_local_endpoints(&client(None, Some(r"\\.\pipe\")))
+                .is_err()
+        );
+
+        let socket = client(Some("/run/tls-tunnel/stats.sock"), None);
+        let pipe = client(None, Some(r"\\.\pipe\tls-tunnel-client"));
+        assert_eq!(
+            ConfigValidator::validate_stats_local_endpoints(&socket).is_ok(),
+            cfg!(unix)
+        );
+        assert_eq!(
+            ConfigValidator::validate_stats_local_endpoints(&pipe).is_ok(),
+            cfg!(windows)
+        );
+    }
+
+    #[test]
+    fn test_validate_proxy_retry_config() {
+        use super::super::ProxyRetryConfig;
+
+        assert!(ConfigValidator::validate_proxy_retry_config(&ProxyRetryConfig::default()).is_ok());
+        assert!(
+            ConfigValidator::validate_proxy_retry_config(&ProxyRetryConfig {
+                interval_secs: 0,
+                max_interval_secs: 10,
+            })
+            .is_err()
+        );
+        assert!(
+            ConfigValidator::validate_proxy_retry_config(&ProxyRetryConfig {
+                interval_secs: 60,
+                max_interval_secs: 30,
+            })
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_validate_fast_fail_config() {
+        use super::super::FastFailConfig;
+
+        let valid = FastFailConfig::default();
+        assert!(ConfigValidator::validate_fast_fail_config("web", &valid).is_ok());
+        for invalid in [
+            FastFailConfig {
+                threshold: 0,
+                ..valid.clone()
+            },
+            FastFailConfig {
+                max_entries: 0,
+                ..valid.clone()
+            },
+            FastFailConfig {
+                decay_secs: 0,
+                ..valid.clone()
+            },
+        ] {
+            assert!(ConfigValidator::validate_fast_fail_config("web", &invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn test_validate_socket_options_config() {
+        use super::super::{
+            SocketOptionsConfig, MAX_SOCKET_BUFFER_BYTES, MAX_SOCKET_KEEPALIVE_SECS,
+        };
+
+        let valid = SocketOptionsConfig {
+            nodelay: false,
+            send_buffer_bytes: Some(4 * 1024 * 1024),
+            recv_buffer_bytes: Some(MAX_SOCKET_BUFFER_BYTES),
+            keepalive_secs: Some(MAX_SOCKET_KEEPALIVE_SECS),
+            user_timeout_ms: None,
+        };
+        assert!(ConfigValidator::validate_socket_options_config(&valid, "Proxy 'web'").is_ok());
+        assert!(ConfigValidator::validate_socket_options_config(
+            &SocketOptionsConfig::default(),
+            "Proxy 'web'"
+        )
+        .is_ok());
+        for invalid in [
+            SocketOptionsConfig {
+                send_buffer_bytes: Some(0),
+                ..valid.clone()
+            },
+            SocketOptionsConfig {
+                recv_buffer_bytes: Some(MAX_SOCKET_BUFFER_BYTES + 1),
+                ..valid.clone()
+            },
+            SocketOptionsConfig {
+                keepalive_secs: Some(0),
+                ..valid.clone()
+            },
+            SocketOptionsConfig {
+                keepalive_secs: Some(MAX_SOCKET_KEEPALIVE_SECS + 1),
+                ..valid.clone()
+            },
+            SocketOptionsConfig {
+                user_timeout_ms: Some(0),
+                ..valid.clone()
+            },
+        ] {
+            assert!(
+                ConfigValidator::validate_socket_options_config(&invalid, "Proxy 'web'").is_err()
+            );
+        }
+
+        let user_timeout = SocketOptionsConfig {
+            user_timeout_ms: Some(30_000),
+            ..valid.clone()
+        };
+        let result = ConfigValidator::validate_socket_options_config(&user_timeout, "Proxy 'web'");
+        if cfg!(target_os = "linux") {
+            assert!(result.is_ok());
+        } else {
+            assert!(result
+                .unwrap_err()
+                .to_string()
+                .contains("only supported on Linux"));
+        }
+    }
+
+    #[test]
+    fn test_validate_direct_egress_config() {
+        use super::super::DirectEgressConfig;
+
+        // 源地址在所有平台上都支持
+        let source_only = DirectEgressConfig {
+            bind_address: Some("192.0.2.10".parse().unwrap()),
+            ..Default::default()
+        };
+        assert!(ConfigValidator::validate_direct_egress_config("web", &source_only).is_ok());
+
+        let marked = DirectEgressConfig {
+            fwmark: 0x2a,
+            bind_interface: Some("eth1".to_string()),
+            ..Default::default()
+        };
+        let result = ConfigValidator::validate_direct_egress_config("web", &marked);
+        if cfg!(target_os = "linux") {
+            assert!(result.is_ok());
+        } else {
+            assert!(result
+                .unwrap_err()
+                .to_string()
+                .contains("only supported on Linux"));
+        }
+
+        let long_interface = DirectEgressConfig {
+            bind_interface: Some("a-very-long-interface-name".to_string()),
+            ..Default::default()
+        };
+        assert!(ConfigValidator::validate_direct_egress_config("web", &long_interface).is_err());
+    }
+
+    #[test]
+    fn test_validate_visitor_gateway() {
+        use super::super::{GatewayPortPolicy, VisitorGatewayConfig};
+
+        let gateway = || VisitorGatewayConfig {
+            bind_addr: "127.0.0.1".to_string(),
+            bind_port: 1080,
+            domain: "tunnel".to_string(),
+            port_policy: GatewayPortPolicy::Match,
+            expected_peer_ids: Default::default(),
+            socket: None,
+        };
+        assert!(ConfigValidator::validate_visitor_gateway(&gateway()).is_ok());
+
+        let mut invalid = gateway();
+        invalid.bind_port = 0;
+        assert!(ConfigValidator::validate_visitor_gateway(&invalid).is_err());
+
+        for domain in ["", ".tunnel", "tunnel."] {
+            let mut invalid = gateway();
+            invalid.domain = domain.to_string();
+            assert!(ConfigValidator::validate_visitor_gateway(&invalid).is_err());
+        }
+
+        let mut invalid = gateway();
+        invalid
+            .expected_peer_ids
+            .insert("db".to_string(), String::new());
+        assert!(ConfigValidator::validate_visitor_gateway(&invalid).is_err());
+
+        let mut invalid = gateway();
+        invalid
+            .expected_peer_ids
+            .insert("db".to_string(), "SHA256:abcd".to_string());
+        assert!(ConfigValidator::validate_visitor_gateway(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_validate_client_identity_fingerprints() {
+        let fingerprint = format!("SHA256:{}", "ab".repeat(32));
+
+        assert!(
+            ConfigValidator::validate_known_clients(std::slice::from_ref(&fingerprint)).is_ok()
+        );
+        assert!(ConfigValidator::validate_known_clients(&[fingerprint.to_uppercase()]).is_ok());
+        assert!(ConfigValidator::validate_known_clients(&[]).is_err());
+        assert!(ConfigValidator::validate_known_clients(&["SHA256:abcd".to_string()]).is_err());
+        assert!(ConfigValidator::validate_known_clients(&["laptop".to_string()]).is_err());
+
+        assert!(ConfigValidator::validate_expected_peer_id("laptop", "Visitor 'db'").is_ok());
+        assert!(ConfigValidator::validate_expected_peer_id(&fingerprint, "Visitor 'db'").is_ok());
+        assert!(ConfigValidator::validate_expected_peer_id("SHA256:abcd", "Visitor 'db'").is_err());
+        assert!(ConfigValidator::validate_expected_peer_id("sha256:zz", "Visitor 'db'").is_err());
+    }
+
+    #[test]
+    fn test_validate_visitor_acl() {
+        use super::super::ClientVisitorAclConfig;
+
+        let fingerprint = format!("SHA256:{}", "ab".repeat(32));
+        let client = |identity: &str| ClientVisitorAclConfig {
+            identity: identity.to_string(),
+            visitor_allow: Some(vec!["db-*".to_string()]),
+            visitor_deny: vec![],
+        };
+        let mut acl = VisitorAclConfig {
+            visitor_allow: Some(vec![]),
+            visitor_deny: vec!["*-admin".to_string()],
+            clients: vec![client(&fingerprint)],
+        };
+        assert!(ConfigValidator::validate_visitor_acl(&acl).is_ok());
+
+        acl.visitor_deny.push(" ".to_string());
+        assert!(ConfigValidator::validate_visitor_acl(&acl).is_err());
+        acl.visitor_deny.pop();
+
+        acl.clients.push(client(&fingerprint.to_uppercase()));
+        assert!(ConfigValidator::validate_visitor_acl(&acl)
+            .unwrap_err()
+            .to_string()
+            .contains("more than once"));
+
+        acl.clients = vec![client("laptop")];
+        assert!(ConfigValidator::validate_visitor_acl(&acl).is_err());
+    }
+
+    #[test]
+    fn test_validate_auth_keys() {
+        let entry = |name: &str, key: &str| AuthKeyConfig {
+            name: name.to_string(),
+            key: key.to_string(),
+            allowed_ports: None,
+            allow_forward: None,
+        };
+        let auth_key = "Xk3vQ9mTz7LpW2rN";
+        let mut keys = vec![
+            entry("teamA", "Hq8sL2vN5xRt9wKe"),
+            entry("teamB", "Pz4mJ7cY1dFg6bUa"),
+        ];
+        assert!(ConfigValidator::validate_auth_keys(&keys, auth_key).is_ok());
+
+        keys.push(entry("teamC", "short"));
+        assert!(ConfigValidator::validate_auth_keys(&keys, auth_key)
+            .unwrap_err()
+            .to_string()
+            .contains("'teamC'"));
+
+        keys[2] = entry("teamA", "Ra5nW8qE3tYu6iOp");
+        assert!(ConfigValidator::validate_auth_keys(&keys, auth_key)
+            .unwrap_err()
+            .to_string()
+            .contains("more than once"));
+
+        keys[2] = entry("teamC", "Pz4mJ7cY1dFg6bUa");
+        assert!(ConfigValidator::validate_auth_keys(&keys, auth_key)
+            .unwrap_err()
+            .to_string()
+            .contains("key of 'teamB'"));
+
+        keys[2] = entry("teamC", auth_key);
+        assert!(ConfigValidator::validate_auth_keys(&keys, auth_key)
+            .unwrap_err()
+            .to_string()
+            .contains("reuses auth_key"));
+
+        keys[2] = entry(" ", "Ra5nW8qE3tYu6iOp");
+        assert!(ConfigValidator::validate_auth_keys(&keys, auth_key).is_err());
+    }
+
+    #[test]
+    fn test_validate_max_write_chunk() {
+        assert!(ConfigValidator::validate_max_write_chunk(None).is_ok());
+        assert!(ConfigValidator::validate_max_write_chunk(Some(1200)).is_ok());
+        assert!(ConfigValidator::validate_max_write_chunk(Some(16384)).is_ok());
+        assert!(ConfigValidator::validate_max_write_chunk(Some(100)).is_err());
+        assert!(ConfigValidator::validate_max_write_chunk(Some(65536)).is_err());
+        assert!(ConfigValidator::validate_relay_memory_budget(None).is_ok());
+        assert!(ConfigValidator::validate_relay_memory_budget(Some(64)).is_ok());
+        assert!(ConfigValidator::validate_relay_memory_budget(Some(0)).is_err());
+    }
+
+    #[test]
+    fn test_validate_event_export_config() {
+        use super::super::{EventExportConfig, EventExportFormat, EventExportTarget};
+
+        let config = |target: &str| EventExportConfig {
+            target: target.to_string(),
+            format: EventExportFormat::Jsonl,
+            queue_size: 1024,
+        };
+
+        assert_eq!(
+            config("tcp:collector.local:5140").parse_target().unwrap(),
+            EventExportTarget::Tcp("collector.local:5140".to_string())
+        );
+        assert!(ConfigValidator::validate_event_export_config(&config("tcp:[::1]:5140")).is_ok());
+        #[cfg(unix)]
+        assert_eq!(
+            config("unix:/run/siem.sock").parse_target().unwrap(),
+            EventExportTarget::Unix("/run/siem.sock".into())
+        );
+
+        for target in [
+            "",
+            "unix:",
+            "tcp:collector",
+            "tcp::5140",
+            "udp:127.0.0.1:514",
+        ] {
+            assert!(
+                ConfigValidator::validate_event_export_config(&config(target)).is_err(),
+                "{} should be rejected",
+                target
+            );
+        }
+
+        let zero_queue = EventExportConfig {
+            queue_size: 0,
+            ..config("tcp:127.0.0.1:5140")
+        };
+        assert!(ConfigValidator::validate_event_export_config(&zero_queue).is_err());
+    }
+
+    #[test]
+    fn test_validate_flow_export_config() {
+        use super::super::{FlowExportConfig, FlowExportSink};
+
+        let config = |sink: &str| -> FlowExportConfig {
+            toml::from_str(&format!("sink = \"{}\"", sink)).unwrap()
+        };
+
+        let file = config("file:/var/log/tls-tunnel/flows.jsonl");
+        assert_eq!(
+            file.parse_sink().unwrap(),
+            FlowExportSink::File("/var/log/tls-tunnel/flows.jsonl".into())
+        );
+        assert_eq!(
+            file.interim_interval(),
+            Some(std::time::Duration::from_secs(300))
+        );
+        assert_eq!(file.batch_size, 100);
+        assert!(ConfigValidator::validate_flow_export_config(&file, false).is_ok());
+
+        // 经事件导出发送时需要配置 event_export
+        assert!(ConfigValidator::validate_flow_export_config(&config("events"), true).is_ok());
+        assert!(ConfigValidator::validate_flow_export_config(&config("events"), false).is_err());
+
+        for sink in ["", "file:", "tcp:127.0.0.1:5140"] {
+            assert!(
+                ConfigValidator::validate_flow_export_config(&config(sink), true).is_err(),
+                "{} should be rejected",
+                sink
+            );
+        }
+
+        let only_final = FlowExportConfig {
+            interim_interval_secs: 0,
+            ..file.clone()
+        };
+        assert_eq!(only_final.interim_interval(), None);
+        assert!(ConfigValidator::validate_flow_export_config(&only_final, false).is_ok());
+        let zero_batch = FlowExportConfig {
+            batch_size: 0,
+            ..file.clone()
+        };
+        assert!(ConfigValidator::validate_flow_export_config(&zero_batch, false).is_err());
+        let zero_flush = FlowExportConfig {
+            flush_interval_ms: 0,
+            ..file
+        };
+        assert!(ConfigValidator::validate_flow_export_config(&zero_flush, false).is_err());
+    }
+
+    #[test]
+    fn test_validate_forwarder_proxy_type() {
+        use crate::config::ProxyType;
+
+        let forwarder = |proxy_type| ForwarderConfig {
+            name: "egress".to_string(),
+            proxy_type,
+            bind_addr: "127.0.0.1".to_string(),
+            bind_port: 1080,
+            routing: None,
+            fast_fail: None,
+            direct_egress: None,
+            socket: None,
+            report_direct: false,
+            idle_timeout_secs: None,
+            header_rules: None,
+        };
+        for proxy_type in [
+            ProxyType::HttpProxy,
+            ProxyType::Socks5Proxy,
+            ProxyType::AutoProxy,
+        ] {
+            assert!(ConfigValidator::validate_forwarders(&[forwarder(proxy_type)]).is_ok());
+        }
+        assert!(ConfigValidator::validate_forwarders(&[forwarder(ProxyType::Tcp)]).is_err());
+    }
+
+    #[test]
+    fn test_validate_forward_policy() {
+        use super::super::ForwardPolicyConfig;
+
+        let policy = ForwardPolicyConfig {
+            allowed_domains: vec!["*.internal.corp".to_string()],
+            denied_cidrs: vec!["169.254.0.0/16".to_string()],
+            allowed_ports: vec![443],
+            ..Default::default()
+        };
+        assert!(ConfigValidator::validate_forward_policy(&policy).is_ok());
+
+        let invalid = ForwardPolicyConfig {
+            denied_domains: vec!["foo.*.com".to_string()],
+            ..Default::default()
+        };
+        assert!(ConfigValidator::validate_forward_policy(&invalid).is_err());
+        let invalid = ForwardPolicyConfig {
+            allowed_cidrs: vec!["10.0.0.0/33".to_string()],
+            ..Default::default()
+        };
+        assert!(ConfigValidator::validate_forward_policy(&invalid).is_err());
+        let invalid = ForwardPolicyConfig {
+            allowed_ports: vec![0],
+            ..Default::default()
+        };
+        assert!(ConfigValidator::validate_forward_policy(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_validate_routing_entries() {
+        assert!(ConfigValidator::validate_routing_domain("example.com").is_ok());
+        assert!(ConfigValidator::validate_routing_domain("*.corp.example").is_ok());
+        assert!(ConfigValidator::validate_routing_domain(".corp-1.example").is_ok());
+        assert!(ConfigValidator::validate_routing_domain("").is_err());
+        assert!(ConfigValidator::validate_routing_domain("*.").is_err());
+        assert!(ConfigValidator::validate_routing_domain("a..b").is_err());
+        assert!(ConfigValidator::validate_routing_domain("foo.*.com").is_err());
+        assert!(ConfigValidator::validate_routing_domain("-bad.com").is_err());
+        assert!(ConfigValidator::validate_routing_domain("bad domain.com").is_err());
+
+        assert!(ConfigValidator::validate_routing_cidr("10.0.0.0/8").is_ok());
+        assert!(ConfigValidator::validate_routing_cidr("192.168.1.1").is_ok());
+        assert!(ConfigValidator::validate_routing_cidr("2001:db8::/32").is_ok());
+        assert!(ConfigValidator::validate_routing_cidr("10.0.0.0/33").is_err());
+        assert!(ConfigValidator::validate_routing_cidr("example.com").is_err());
+    }
+
+    #[test]
+    fn test_validate_routing_schedules_and_quota() {
+        use crate::config::{QuotaAction, RoutingQuota, RoutingSchedule};
+
+        let mut routing: RoutingConfig = toml::from_str(
+            r#"
+            utc_offset = "+08:00"
+            schedules = [
+                { days = ["mon", "friday"], start = "09:00", end = "18:00", strategy = "direct" },
+                { start = "22:00", end = "06:00", strategy = "proxy" },
+                { start = "00:00", end = "24:00", strategy = "direct" },
+            ]
+            quota = { bytes_per_month = 1024, action = "block" }
+            "#,
+        )
+        .unwrap();
+        assert!(ConfigValidator::validate_routing_config(&routing).is_ok());
+        assert_eq!(routing.utc_offset_secs().unwrap(), 8 * 3600);
+        assert_eq!(routing.schedules[0].label(), "mon,fri 09:00-18:00");
+        assert_eq!(routing.schedules[1].window().unwrap(), (22 * 60, 6 * 60));
+        assert_eq!(routing.quota.as_ref().unwrap().action, QuotaAction::Block);
+
+        routing.utc_offset = Some("-05:30".to_string());
+        assert_eq!(routing.utc_offset_secs().unwrap(), -(5 * 3600 + 30 * 60));
+        for offset in ["08:00", "+8", "+24:00", "+08:60"] {
+            routing.utc_offset = Some(offset.to_string());
+            assert!(ConfigValidator::validate_routing_config(&routing).is_err());
+        }
+        routing.utc_offset = None;
+
+        for (start, end) in [("9:00", "18:00"), ("24:00", "06:00"), ("10:00", "10:00")] {
+            let mut invalid = routing.clone();
+            invalid.schedules = vec![RoutingSchedule {
+                days: vec![],
+                start: start.to_string(),
+                end: end.to_string(),
+                strategy: RoutingStrategy::Direct,
+            }];
+            assert!(ConfigValidator::validate_routing_config(&invalid).is_err());
+        }
+
+        routing.quota = Some(RoutingQuota {
+            bytes_per_month: 0,
+            action: QuotaAction::Direct,
+            state_path: None,
+        });
+        assert!(ConfigValidator::validate_routing_config(&routing).is_err());
+    }
+
+    #[test]
+    fn test_validate_server_timeouts() {
+        let defaults = ServerTimeoutsConfig::default();
+        assert!(ConfigValidator::validate_server_timeouts(&defaults).is_ok());
+
+        let zero = ServerTimeoutsConfig {
+            preamble_read_secs: 0,
+            ..defaults
+        };
+        assert!(ConfigValidator::validate_server_timeouts(&zero).is_err());
+
+        // 心跳超时不大于客户端心跳间隔时，正常的客户端也会被断开
+        for heartbeat_timeout_secs in [10, 30] {
+            let short = ServerTimeoutsConfig {
+                heartbeat_timeout_secs,
+                ..defaults
+            };
+            let err = ConfigValidator::validate_server_timeouts(&short).unwrap_err();
+            assert!(
+                err.to_string().contains("heartbeat_timeout_secs"),
+                "{}",
+                err
+            );
+        }
+        let longer = ServerTimeoutsConfig {
+            heartbeat_timeout_secs: 31,
+            ..defaults
+        };
+        assert!(ConfigValidator::validate_server_timeouts(&longer).is_ok());
+    }
+
+    #[test]
+    fn test_validate_client_timeouts() {
+        let defaults = ClientTimeoutsConfig::default();
+        assert!(ConfigValidator::validate_client_timeouts(&defaults, &[]).is_ok());
+
+        let zero = ClientTimeoutsConfig {
+            local_connect_ms: 0,
+            ..defaults
+        };
+        assert!(ConfigValidator::validate_client_timeouts(&zero, &[]).is_err());
+
+        // 协议解析超时必须小于连接空闲超时
+        let parse_not_below_idle = ClientTimeoutsConfig {
+            protocol_parse_secs: 60,
+            connection_idle_secs: 60,
+            ..defaults
+        };
+        let err =
+            ConfigValidator::validate_client_timeouts(&parse_not_below_idle, &[]).unwrap_err();
+        assert!(err.to_string().contains("protocol_parse_secs"), "{}", err);
+
+        // 心跳间隔必须小于服务器的心跳超时
+        let slow_heartbeat = ClientTimeoutsConfig {
+            heartbeat_interval_secs: 120,
+            ..defaults
+        };
+        let err = ConfigValidator::validate_client_timeouts(&slow_heartbeat, &[]).unwrap_err();
+        assert!(
+            err.to_string().contains("heartbeat_interval_secs"),
+            "{}",
+            err
+        );
+
+        // 重连退避的倍数不小于 1，上限不小于初始等待时间
+        for invalid in [
+            ClientTimeoutsConfig {
+                reconnect_multiplier: 0.5,
+                ..defaults
+            },
+            ClientTimeoutsConfig {
+                reconnect_multiplier: f64::NAN,
+                ..defaults
+            },
+            ClientTimeoutsConfig {
+                reconnect_delay_secs: 60,
+                reconnect_max_delay_secs: 30,
+                ..defaults
+            },
+        ] {
+            let err = ConfigValidator::validate_client_timeouts(&invalid, &[]).unwrap_err();
+            assert!(err.to_string().contains("reconnect_"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_validate_health() {
+        let defaults = HealthConfig::default();
+        assert!(ConfigValidator::validate_health(&defaults).is_ok());
+
+        for invalid in [
+            HealthConfig {
+                red_below: 90,
+                ..defaults
+            },
+            HealthConfig {
+                red_below: 0,
+                ..defaults
+            },
+            HealthConfig {
+                yellow_below: 101,
+                ..defaults
+            },
+            HealthConfig {
+                rtt_warn_ms: 1000,
+                ..defaults
+            },
+            HealthConfig {
+                failure_ratio_critical: 0.01,
+                ..defaults
+            },
+            HealthConfig {
+                window_secs: 0,
+                ..defaults
+            },
+        ] {
+            assert!(
+                ConfigValidator::validate_health(&invalid).is_err(),
+                "{:?}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_header_rules() {
+        let forwarder = |proxy_type| ForwarderConfig {
+            name: "egress".to_string(),
+            proxy_type,
+            bind_addr: "127.0.0.1".to_string(),
+            bind_port: 8118,
+            routing: None,
+            fast_fail: None,
+            direct_egress: None,
+            socket: None,
+            report_direct: false,
+            idle_timeout_secs: None,
+            header_rules: None,
+        };
+        let rule = |add: &[(&str, &str)], remove: &[&str]| HeaderRuleConfig {
+            match_domains: vec!["*.internal.example".to_string()],
+            add: add
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            remove: remove.iter().map(|r| r.to_string()).collect(),
+        };
+        let http = forwarder(ProxyType::HttpProxy);
+        let valid = rule(
+            &[("Authorization", "env:INTERNAL_TOKEN")],
+            &["X-Forwarded-*"],
+        );
+        assert!(
+            ConfigValidator::validate_header_rules(&http, std::slice::from_ref(&valid)).is_ok()
+        );
+        let auto = forwarder(ProxyType::AutoProxy);
+        assert!(
+            ConfigValidator::validate_header_rules(&auto, std::slice::from_ref(&valid)).is_ok()
+        );
+
+        // SOCKS5 连接不会被改写
+        let socks = forwarder(ProxyType::Socks5Proxy);
+        let err = ConfigValidator::validate_header_rules(&socks, std::slice::from_ref(&valid))
+            .unwrap_err();
+        assert!(err.to_string().contains("CONNECT"), "{}", err);
+
+        for invalid in [
+            rule(&[], &[]),
+            rule(&[("Bad Header", "x")], &[]),
+            rule(&[("Content-Length", "0")], &[]),
+            rule(&[("X-Injected", "a\r\nHost: evil")], &[]),
+            rule(&[], &["X Forwarded*"]),
+            HeaderRuleConfig {
+                match_domains: vec![],
+                ..valid.clone()
+            },
+        ] {
+            assert!(
+                ConfigValidator::validate_header_rules(&http, std::slice::from_ref(&invalid))
+                    .is_err(),
+                "{:?}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_forwarder_idle_override() {
+        let forwarder = |idle_timeout_secs| ForwarderConfig {
+            name: "egress".to_string(),
+            proxy_type: crate::config::ProxyType::Socks5Proxy,
+            bind_addr: "127.0.0.1".to_string(),
+            bind_port: 1080,
+            routing: None,
+            fast_fail: None,
+            direct_egress: None,
+            socket: None,
+            report_direct: false,
+            idle_timeout_secs,
+            header_rules: None,
+        };
+        let defaults = ClientTimeoutsConfig::default();
+        let timeouts = ClientTimeoutsConfig {
+            protocol_parse_secs: 5,
+            ..defaults
+        };
+
+        assert!(ConfigValidator::validate_client_timeouts(&timeouts, &[forwarder(None)]).is_ok());
+        assert!(
+            ConfigValidator::validate_client_timeouts(&timeouts, &[forwarder(Some(6))]).is_ok()
+        );
+        // 覆盖值同样必须大于协议解析超时（包括 0）
+        for idle in [0, 5] {
+            let err =
+                ConfigValidator::validate_client_timeouts(&timeouts, &[forwarder(Some(idle))])
+                    .unwrap_err();
+            assert!(err.to_string().contains("idle_timeout_secs"), "{}", err);
+        }
+        assert_eq!(
+            timeouts.forwarder_idle(&forwarder(Some(6))),
+            std::time::Duration::from_secs(6)
+        );
+        assert_eq!(
+            timeouts.forwarder_idle(&forwarder(None)),
+            defaults.connection_idle()
+        );
     }
 }
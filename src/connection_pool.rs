@@ -1,12 +1,23 @@
+use crate::config::SocketOptionsConfig;
+use crate::socket_options;
 use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
 use socket2::{SockRef, TcpKeepalive};
+use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use tokio::net::TcpStream;
 use tokio::sync::Mutex;
+use tokio::time::Instant;
 use tracing::{debug, info, warn};
 
+/// 连接寿命抖动比例：实际寿命在 `max_lifetime` 的 [90%, 100%] 之间随机分布
+const LIFETIME_JITTER_DIVISOR: u32 = 10;
+
 /// 连接池配置
 #[derive(Debug, Clone)]
 pub struct PoolConfig {
@@ -18,12 +29,20 @@ pub struct PoolConfig {
     pub max_idle_time: Duration,
     /// 连接建立超时（毫秒）
     pub connect_timeout: Duration,
+    /// 直接连接本地服务失败后重试的间隔
+    pub retry_delay: Duration,
     /// Keepalive 首次探测时间
     pub keepalive_time: Option<Duration>,
     /// Keepalive 探测间隔
     pub keepalive_interval: Option<Duration>,
     /// 是否复用连接（false 时每次创建新连接，适用于 TCP/HTTP/1.1 短连接）
     pub reuse_connections: bool,
+    /// 是否允许预热连接（false 时首次流量到来之前不会连接后端）
+    pub warmup: bool,
+    /// 连接最大寿命（None 表示不限制），超过后不再复用并由清理任务关闭
+    pub max_lifetime: Option<Duration>,
+    /// 新建连接的套接字选项
+    pub socket: SocketOptionsConfig,
 }
 
 impl Default for PoolConfig {
@@ -33,28 +52,64 @@ impl Default for PoolConfig {
             max_size: 10,
             max_idle_time: Duration::from_secs(60),
             connect_timeout: Duration::from_millis(5000),
+            retry_delay: Duration::from_millis(1000),
             keepalive_time: Some(Duration::from_secs(30)),
             keepalive_interval: Some(Duration::from_secs(10)),
             reuse_connections: false, // 默认不复用，避免 HTTP/1.1 问题
+            warmup: true,
+            max_lifetime: None,
+            socket: SocketOptionsConfig::default(),
+        }
+    }
+}
+
+/// 连接的创建时间与轮换时间
+#[derive(Debug, Clone, Copy)]
+struct ConnectionAge {
+    created_at: Instant,
+    /// 寿命到期时间（已应用抖动）
+    expires_at: Option<Instant>,
+}
+
+impl ConnectionAge {
+    fn new(max_lifetime: Option<Duration>) -> Self {
+        let created_at = Instant::now();
+        Self {
+            created_at,
+            expires_at: max_lifetime
+                .map(|lifetime| created_at + lifetime - lifetime_jitter(lifetime)),
         }
     }
+
+    fn is_past_lifetime(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| Instant::now() >= expires_at)
+    }
+}
+
+/// 生成 [0, max_lifetime / 10] 范围内的随机抖动，避免同时创建的连接在同一时刻轮换
+fn lifetime_jitter(max_lifetime: Duration) -> Duration {
+    let window = max_lifetime / LIFETIME_JITTER_DIVISOR;
+    if window.is_zero() {
+        return Duration::ZERO;
+    }
+    let random = RandomState::new().hash_one(Instant::now());
+    window.mul_f64((random % 10_000) as f64 / 10_000.0)
 }
 
 /// 池中的连接
 struct PooledConnection {
     stream: TcpStream,
-    #[allow(dead_code)]
-    created_at: Instant,
+    age: ConnectionAge,
     last_used: Instant,
 }
 
 impl PooledConnection {
-    fn new(stream: TcpStream) -> Self {
-        let now = Instant::now();
+    fn new(stream: TcpStream, age: ConnectionAge) -> Self {
         Self {
             stream,
-            created_at: now,
-            last_used: now,
+            age,
+            last_used: Instant::now(),
         }
     }
 
@@ -67,33 +122,85 @@ impl PooledConnection {
     }
 }
 
+/// 连接池的累计计数（所有地址共用）
+#[derive(Default)]
+struct PoolCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    created: AtomicU64,
+    discarded: AtomicU64,
+    warmup_failures: AtomicU64,
+    /// 当前空闲连接数（每次操作后刷新）
+    idle: AtomicU64,
+}
+
+impl PoolCounters {
+    fn add(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
 /// 单个地址的连接池
 struct AddressPool {
     address: String,
     config: PoolConfig,
+    counters: Arc<PoolCounters>,
     idle_connections: Vec<PooledConnection>,
     active_count: usize,
+    /// 已借出连接的寿命信息（按本地地址索引）
+    active_ages: HashMap<SocketAddr, ConnectionAge>,
+    /// 因超过最大寿命而轮换的连接数
+    rotations: u64,
 }
 
 impl AddressPool {
-    fn new(address: String, config: PoolConfig) -> Self {
+    fn new(address: String, config: PoolConfig, counters: Arc<PoolCounters>) -> Self {
         Self {
             address,
             config,
+            counters,
             idle_connections: Vec::new(),
             active_count: 0,
+            active_ages: HashMap::new(),
+            rotations: 0,
+        }
+    }
+
+    /// 记录借出的连接
+    fn track_active(&mut self, stream: &TcpStream, age: ConnectionAge) {
+        self.active_count += 1;
+        if let Ok(local_addr) = stream.local_addr() {
+            self.active_ages.insert(local_addr, age);
         }
     }
 
+    /// 取回借出连接的寿命信息
+    fn untrack_active(&mut self, stream: &TcpStream) -> Option<ConnectionAge> {
+        self.active_count = self.active_count.saturating_sub(1);
+        stream
+            .local_addr()
+            .ok()
+            .and_then(|local_addr| self.active_ages.remove(&local_addr))
+    }
+
     /// 获取连接
     async fn get_connection(&mut self) -> Result<TcpStream> {
         // 清理过期连接
         self.cleanup_expired();
 
-        // 尝试从空闲连接中获取
-        if let Some(mut pooled) = self.idle_connections.pop() {
+        // 尝试从空闲连接中获取（跳过超过最大寿命的连接）
+        while let Some(mut pooled) = self.idle_connections.pop() {
+            if pooled.age.is_past_lifetime() {
+                self.rotations += 1;
+                debug!(
+                    "Rotating pooled connection to {} (max lifetime reached)",
+                    self.address
+                );
+                continue;
+            }
             pooled.update_last_used();
-            self.active_count += 1;
+            self.track_active(&pooled.stream, pooled.age);
+            PoolCounters::add(&self.counters.hits);
             debug!(
                 "Reusing pooled connection to {} (active: {}, idle: {})",
                 self.address,
@@ -103,6 +210,8 @@ impl AddressPool {
             return Ok(pooled.stream);
         }
 
+        PoolCounters::add(&self.counters.misses);
+
         // 检查是否达到最大连接数
         let total_connections = self.active_count + self.idle_connections.len();
         if total_connections >= self.config.max_size {
@@ -130,13 +239,17 @@ impl AddressPool {
         .context("Failed to connect")?;
 
         apply_keepalive(&stream, &self.config);
+        socket_options::configure(&stream, Some(&self.config.socket), &self.address);
+        PoolCounters::add(&self.counters.created);
 
-        self.active_count += 1;
+        self.track_active(&stream, ConnectionAge::new(self.config.max_lifetime));
         Ok(stream)
     }
 
     fn return_connection(&mut self, stream: TcpStream) {
-        self.active_count = self.active_count.saturating_sub(1);
+        let age = self
+            .untrack_active(&stream)
+            .unwrap_or_else(|| ConnectionAge::new(self.config.max_lifetime));
 
         // 如果配置不允许复用，直接丢弃
         if !self.config.reuse_connections {
@@ -144,12 +257,24 @@ impl AddressPool {
                 "Connection reuse disabled, discarding connection to {}",
                 self.address
             );
+            PoolCounters::add(&self.counters.discarded);
+            return;
+        }
+
+        // 超过最大寿命的连接不再放回池中
+        if age.is_past_lifetime() {
+            self.rotations += 1;
+            debug!(
+                "Connection to {} reached max lifetime, closing",
+                self.address
+            );
             return;
         }
 
         // 检查连接健康状态
         if !is_connection_healthy(&stream) {
             debug!("Connection to {} is unhealthy, discarding", self.address);
+            PoolCounters::add(&self.counters.discarded);
             return;
         }
 
@@ -160,6 +285,7 @@ impl AddressPool {
                 "Dropping connection to {} (pool full, idle: {})",
                 self.address, total_idle
             );
+            PoolCounters::add(&self.counters.discarded);
             return;
         }
 
@@ -168,32 +294,45 @@ impl AddressPool {
             self.address, self.active_count, total_idle
         );
 
-        self.idle_connections.push(PooledConnection::new(stream));
+        self.idle_connections
+            .push(PooledConnection::new(stream, age));
     }
 
     /// 丢弃不可用的连接，同时修正计数
-    fn discard_connection(&mut self, _stream: TcpStream) {
-        self.active_count = self.active_count.saturating_sub(1);
+    fn discard_connection(&mut self, stream: TcpStream) {
+        self.untrack_active(&stream);
+        PoolCounters::add(&self.counters.discarded);
         debug!("Discarded bad connection to {}", self.address);
     }
 
-    /// 清理过期的空闲连接
+    /// 清理过期的空闲连接（空闲超时或超过最大寿命）
     fn cleanup_expired(&mut self) {
         let before = self.idle_connections.len();
         self.idle_connections
             .retain(|conn| !conn.is_expired(self.config.max_idle_time));
         let removed = before - self.idle_connections.len();
 
-        if removed > 0 {
+        let before = self.idle_connections.len();
+        self.idle_connections
+            .retain(|conn| !conn.age.is_past_lifetime());
+        let rotated = before - self.idle_connections.len();
+        self.rotations += rotated as u64;
+
+        if removed > 0 || rotated > 0 {
             debug!(
-                "Cleaned up {} expired connections to {}",
-                removed, self.address
+                "Cleaned up {} expired and {} aged-out connections to {}",
+                removed, rotated, self.address
             );
         }
     }
 
     /// 预热连接池
     async fn warmup(&mut self) -> Result<()> {
+        if !self.config.warmup {
+            debug!("Warmup disabled for {}", self.address);
+            return Ok(());
+        }
+
         let target = self
             .config
             .min_idle
@@ -214,13 +353,19 @@ impl AddressPool {
             {
                 Ok(Ok(stream)) => {
                     apply_keepalive(&stream, &self.config);
-                    self.idle_connections.push(PooledConnection::new(stream));
+                    socket_options::configure(&stream, Some(&self.config.socket), &self.address);
+                    let age = ConnectionAge::new(self.config.max_lifetime);
+                    self.idle_connections
+                        .push(PooledConnection::new(stream, age));
+                    PoolCounters::add(&self.counters.created);
                 }
                 Ok(Err(e)) => {
                     warn!("Failed to warm up connection to {}: {}", self.address, e);
+                    PoolCounters::add(&self.counters.warmup_failures);
                 }
                 Err(_) => {
                     warn!("Timeout warming up connection to {}", self.address);
+                    PoolCounters::add(&self.counters.warmup_failures);
                 }
             }
         }
@@ -238,11 +383,19 @@ impl AddressPool {
     /// 获取池的统计信息
     #[allow(dead_code)]
     fn stats(&self) -> PoolStats {
+        let oldest = self
+            .idle_connections
+            .iter()
+            .map(|conn| conn.age.created_at)
+            .chain(self.active_ages.values().map(|age| age.created_at))
+            .min();
         PoolStats {
             active: self.active_count,
             idle: self.idle_connections.len(),
             total: self.active_count + self.idle_connections.len(),
             max_size: self.config.max_size,
+            oldest_connection_age: oldest.map(|created_at| created_at.elapsed()),
+            rotations: self.rotations,
         }
     }
 }
@@ -302,12 +455,42 @@ pub struct PoolStats {
     pub idle: usize,
     pub total: usize,
     pub max_size: usize,
+    /// 池中（含已借出）最老连接的存活时间
+    pub oldest_connection_age: Option<Duration>,
+    /// 因超过最大寿命而轮换的连接数
+    pub rotations: u64,
+}
+
+/// 连接池的累计指标（所有地址合计），用于判断连接是否被有效复用
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolMetrics {
+    /// 从空闲连接中取到连接的次数
+    pub hits: u64,
+    /// 没有可用空闲连接的次数（随后新建连接）
+    pub misses: u64,
+    /// 新建的连接数（含预热）
+    pub created: u64,
+    /// 用完后没有放回池中的连接数（出错、不允许复用、不健康或池已满），不含寿命轮换和空闲超时
+    pub discarded: u64,
+    /// 当前空闲连接数
+    pub idle: u64,
+    /// 预热时连接失败的次数
+    pub warmup_failures: u64,
+}
+
+impl PoolMetrics {
+    /// 命中率（尚未取过连接时为 None）
+    pub fn hit_rate(&self) -> Option<f64> {
+        let lookups = self.hits + self.misses;
+        (lookups > 0).then(|| self.hits as f64 / lookups as f64)
+    }
 }
 
 /// 连接池管理器
 pub struct ConnectionPool {
     pools: Arc<Mutex<HashMap<String, AddressPool>>>,
     config: PoolConfig,
+    counters: Arc<PoolCounters>,
 }
 
 impl ConnectionPool {
@@ -316,6 +499,39 @@ impl ConnectionPool {
         Self {
             pools: Arc::new(Mutex::new(HashMap::new())),
             config,
+            counters: Arc::new(PoolCounters::default()),
+        }
+    }
+
+    /// 获取连接池配置
+    pub fn config(&self) -> &PoolConfig {
+        &self.config
+    }
+
+    fn new_address_pool(&self, address: &str) -> AddressPool {
+        AddressPool::new(
+            address.to_string(),
+            self.config.clone(),
+            self.counters.clone(),
+        )
+    }
+
+    /// 刷新空闲连接数（调用方持有锁）
+    fn update_idle(&self, pools: &HashMap<String, AddressPool>) {
+        let idle: usize = pools.values().map(|p| p.idle_connections.len()).sum();
+        self.counters.idle.store(idle as u64, Ordering::Relaxed);
+    }
+
+    /// 获取累计指标快照（不需要等待连接池的锁）
+    pub fn metrics(&self) -> PoolMetrics {
+        let counters = &self.counters;
+        PoolMetrics {
+            hits: counters.hits.load(Ordering::Relaxed),
+            misses: counters.misses.load(Ordering::Relaxed),
+            created: counters.created.load(Ordering::Relaxed),
+            discarded: counters.discarded.load(Ordering::Relaxed),
+            idle: counters.idle.load(Ordering::Relaxed),
+            warmup_failures: counters.warmup_failures.load(Ordering::Relaxed),
         }
     }
 
@@ -331,9 +547,11 @@ impl ConnectionPool {
 
         let pool = pools
             .entry(address.to_string())
-            .or_insert_with(|| AddressPool::new(address.to_string(), self.config.clone()));
+            .or_insert_with(|| self.new_address_pool(address));
 
-        pool.get_connection().await
+        let result = pool.get_connection().await;
+        self.update_idle(&pools);
+        result
     }
 
     /// 归还连接（如果连接仍然可用）
@@ -344,6 +562,7 @@ impl ConnectionPool {
         if let Some(pool) = pools.get_mut(address) {
             pool.return_connection(stream);
         }
+        self.update_idle(&pools);
     }
 
     /// 丢弃不可用的连接（例如读写错误后）
@@ -353,6 +572,7 @@ impl ConnectionPool {
         if let Some(pool) = pools.get_mut(address) {
             pool.discard_connection(stream);
         }
+        self.update_idle(&pools);
     }
 
     /// 预热指定地址的连接池
@@ -361,9 +581,11 @@ impl ConnectionPool {
 
         let pool = pools
             .entry(address.to_string())
-            .or_insert_with(|| AddressPool::new(address.to_string(), self.config.clone()));
+            .or_insert_with(|| self.new_address_pool(address));
 
-        pool.warmup().await
+        let result = pool.warmup().await;
+        self.update_idle(&pools);
+        result
     }
 
     /// 预热多个地址的连接池
@@ -400,15 +622,21 @@ impl ConnectionPool {
         for pool in pools.values_mut() {
             pool.cleanup_expired();
         }
+        self.update_idle(&pools);
     }
 
-    /// 启动后台清理任务
+    /// 启动后台清理任务（连接池释放后结束，例如热重载删除了对应的代理）
     pub fn start_cleanup_task(self: Arc<Self>, interval: Duration) {
+        let pool = Arc::downgrade(&self);
+        drop(self);
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(interval);
             loop {
                 interval.tick().await;
-                self.cleanup_expired().await;
+                let Some(pool) = pool.upgrade() else {
+                    break;
+                };
+                pool.cleanup_expired().await;
             }
         });
     }
@@ -433,6 +661,7 @@ mod tests {
             let _ = std::net::TcpStream::connect(addr);
         });
         let (stream, _) = listener.accept().unwrap();
+        stream.set_nonblocking(true).unwrap();
         TcpStream::from_std(stream).unwrap()
     }
 
@@ -441,11 +670,185 @@ mod tests {
         tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
         let conn = PooledConnection {
             stream: make_test_stream(),
-            created_at: Instant::now(),
+            age: ConnectionAge::new(None),
             last_used: Instant::now() - Duration::from_secs(120),
         };
 
         assert!(conn.is_expired(Duration::from_secs(60)));
         assert!(!conn.is_expired(Duration::from_secs(180)));
     }
+
+    fn lifetime_config() -> PoolConfig {
+        PoolConfig {
+            max_idle_time: Duration::from_secs(7200),
+            reuse_connections: true,
+            max_lifetime: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_max_lifetime_connection_not_returned() {
+        let config = lifetime_config();
+        let mut pool = AddressPool::new("127.0.0.1:1".to_string(), config.clone(), Arc::default());
+
+        let stale = make_test_stream();
+        let stale_age = ConnectionAge::new(config.max_lifetime);
+        tokio::time::advance(Duration::from_secs(3601)).await;
+
+        let fresh = make_test_stream();
+        let fresh_addr = fresh.local_addr().unwrap();
+        pool.idle_connections.push(PooledConnection::new(
+            fresh,
+            ConnectionAge::new(config.max_lifetime),
+        ));
+        pool.idle_connections
+            .push(PooledConnection::new(stale, stale_age));
+
+        let stats = pool.stats();
+        assert!(stats.oldest_connection_age.unwrap() >= Duration::from_secs(3601));
+
+        // 超过寿命的连接被轮换，返回的是新连接
+        let stream = pool.get_connection().await.unwrap();
+        assert_eq!(stream.local_addr().unwrap(), fresh_addr);
+        assert_eq!(pool.stats().rotations, 1);
+
+        // 借出期间超过寿命的连接归还时直接关闭
+        tokio::time::advance(Duration::from_secs(3601)).await;
+        pool.return_connection(stream);
+        assert!(pool.idle_connections.is_empty());
+        assert_eq!(pool.stats().rotations, 2);
+        assert_eq!(pool.stats().oldest_connection_age, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cleanup_rotates_aged_connections() {
+        let config = lifetime_config();
+        let mut pool = AddressPool::new("127.0.0.1:1".to_string(), config.clone(), Arc::default());
+        pool.idle_connections.push(PooledConnection::new(
+            make_test_stream(),
+            ConnectionAge::new(config.max_lifetime),
+        ));
+
+        // 抖动最多提前 10%，到期前不会被清理
+        tokio::time::advance(Duration::from_secs(3000)).await;
+        pool.cleanup_expired();
+        assert_eq!(pool.idle_connections.len(), 1);
+
+        tokio::time::advance(Duration::from_secs(601)).await;
+        pool.cleanup_expired();
+        assert!(pool.idle_connections.is_empty());
+        assert_eq!(pool.stats().rotations, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_lifetime_jitter_spreads_rotation() {
+        let max_lifetime = Duration::from_secs(3600);
+        let now = Instant::now();
+        let expiries: std::collections::HashSet<Instant> = (0..20)
+            .map(|_| ConnectionAge::new(Some(max_lifetime)).expires_at.unwrap())
+            .collect();
+
+        // 同一时刻创建的连接轮换时间分散在 [90%, 100%] 寿命区间内
+        assert!(expiries.len() > 1);
+        for expires_at in expiries {
+            assert!(expires_at <= now + max_lifetime);
+            assert!(expires_at >= now + max_lifetime - max_lifetime / LIFETIME_JITTER_DIVISOR);
+        }
+    }
+
+    /// 接受并保持连接的本地服务
+    async fn start_holding_server() -> (String, tokio::task::JoinHandle<()>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+        (addr, handle)
+    }
+
+    #[tokio::test]
+    async fn test_metrics_track_get_return_discard() {
+        let (addr, server) = start_holding_server().await;
+        let pool = ConnectionPool::new(PoolConfig {
+            reuse_connections: true,
+            warmup: false,
+            ..Default::default()
+        });
+        assert_eq!(pool.metrics(), PoolMetrics::default());
+        assert_eq!(pool.metrics().hit_rate(), None);
+
+        // 首次获取：未命中，新建连接；归还后进入空闲列表
+        let stream = pool.get(&addr).await.unwrap();
+        pool.return_connection(&addr, stream).await;
+        let metrics = pool.metrics();
+        assert_eq!((metrics.misses, metrics.created, metrics.idle), (1, 1, 1));
+
+        // 再次获取命中空闲连接，出错后丢弃
+        let stream = pool.get(&addr).await.unwrap();
+        assert_eq!(pool.metrics().idle, 0);
+        pool.discard_connection(&addr, stream).await;
+
+        // 池已空，下一次获取再次未命中
+        let stream = pool.get(&addr).await.unwrap();
+        pool.return_connection(&addr, stream).await;
+
+        let metrics = pool.metrics();
+        assert_eq!(
+            metrics,
+            PoolMetrics {
+                hits: 1,
+                misses: 2,
+                created: 2,
+                discarded: 1,
+                idle: 1,
+                warmup_failures: 0,
+            }
+        );
+        assert_eq!(metrics.hit_rate(), Some(1.0 / 3.0));
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_metrics_count_discarded_returns_without_reuse() {
+        let (addr, server) = start_holding_server().await;
+        let pool = ConnectionPool::new(PoolConfig {
+            reuse_connections: false,
+            ..Default::default()
+        });
+
+        let stream = pool.get(&addr).await.unwrap();
+        pool.return_connection(&addr, stream).await;
+        let metrics = pool.metrics();
+        assert_eq!(
+            (metrics.created, metrics.discarded, metrics.idle),
+            (1, 1, 0)
+        );
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_metrics_count_warmup() {
+        let (addr, server) = start_holding_server().await;
+        let pool = ConnectionPool::new(PoolConfig {
+            min_idle: 2,
+            ..Default::default()
+        });
+        pool.warmup(&addr).await.unwrap();
+        let metrics = pool.metrics();
+        assert_eq!((metrics.created, metrics.idle), (2, 2));
+        server.abort();
+
+        // 目标不可达时记录预热失败
+        let closed = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+        pool.warmup(&closed).await.unwrap();
+        let metrics = pool.metrics();
+        assert_eq!((metrics.created, metrics.warmup_failures), (2, 2));
+    }
 }
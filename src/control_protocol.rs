@@ -2,8 +2,28 @@
 ///
 /// 该模块实现了客户端与服务端之间的控制通道通信协议，
 /// 使用长度前缀（4字节大端）+ JSON-RPC 2.0 格式
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// 单条控制消息的最大长度（字节，不含 4 字节长度前缀）
+pub const MAX_CONTROL_MESSAGE_SIZE: usize = 10 * 1024 * 1024;
+
+/// JSON-RPC 错误码：消息不是合法的 JSON
+pub const PARSE_ERROR: i32 = -32700;
+/// JSON-RPC 错误码：JSON 不是合法的请求对象
+pub const INVALID_REQUEST: i32 = -32600;
+/// JSON-RPC 错误码：方法不存在
+pub const METHOD_NOT_FOUND: i32 = -32601;
+/// JSON-RPC 错误码：参数无效
+pub const INVALID_PARAMS: i32 = -32602;
+/// 错误码：服务器拒绝认证（协议版本不兼容、密钥或身份无效）
+pub const AUTHENTICATION_FAILED: i32 = -32000;
+/// 错误码：提交的代理全部被拒绝（附加数据为 [`ConfigRejectedData`]）
+pub const CONFIG_REJECTED: i32 = -32001;
 
 /// JSON-RPC 2.0 请求
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,6 +53,34 @@ impl JsonRpcRequest {
         }
     }
 
+    /// 创建带类型参数的请求
+    pub fn call<P: Serialize>(
+        method: ControlMethod,
+        params: &P,
+        id: u64,
+    ) -> serde_json::Result<Self> {
+        Ok(Self::new(
+            method.as_str().to_string(),
+            serde_json::to_value(params)?,
+            id,
+        ))
+    }
+
+    /// 创建带类型参数的通知（没有 id，对端不回复）
+    pub fn notify<P: Serialize>(method: ControlMethod, params: &P) -> serde_json::Result<Self> {
+        Ok(Self {
+            jsonrpc: "2.0".to_string(),
+            method: method.as_str().to_string(),
+            params: serde_json::to_value(params)?,
+            id: None,
+        })
+    }
+
+    /// 按方法对应的类型解析参数
+    pub fn decode_params<P: DeserializeOwned>(&self) -> serde_json::Result<P> {
+        P::deserialize(&self.params)
+    }
+
     /// 是否为通知
     pub fn is_notification(&self) -> bool {
         self.id.is_none()
@@ -77,6 +125,16 @@ impl JsonRpcResponse {
             id,
         }
     }
+
+    /// 创建带类型结果的成功响应
+    pub fn success_with<R: Serialize>(id: Value, result: &R) -> serde_json::Result<Self> {
+        Ok(Self::success(id, serde_json::to_value(result)?))
+    }
+
+    /// 按方法对应的类型解析结果（错误响应没有结果时返回 None）
+    pub fn decode_result<R: DeserializeOwned>(&self) -> Option<serde_json::Result<R>> {
+        self.result.as_ref().map(R::deserialize)
+    }
 }
 
 /// JSON-RPC 2.0 错误
@@ -93,6 +151,101 @@ pub struct JsonRpcError {
     pub data: Option<Value>,
 }
 
+impl JsonRpcError {
+    /// 创建带类型附加数据的错误
+    pub fn with_data<D: Serialize>(
+        code: i32,
+        message: String,
+        data: &D,
+    ) -> serde_json::Result<Self> {
+        Ok(Self {
+            code,
+            message,
+            data: Some(serde_json::to_value(data)?),
+        })
+    }
+
+    /// 按错误码对应的类型解析附加数据（没有附加数据时返回 None）
+    pub fn decode_data<D: DeserializeOwned>(&self) -> Option<serde_json::Result<D>> {
+        self.data.as_ref().map(D::deserialize)
+    }
+}
+
+/// [`CONFIG_REJECTED`] 错误的附加数据
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigRejectedData {
+    pub rejected_proxies: Vec<String>,
+    /// 被拒绝项的原因（键与 rejected_proxies 中的条目相同）
+    #[serde(default)]
+    pub reasons: BTreeMap<String, String>,
+}
+
+/// 协议能力：visitor 确认帧携带 proxy 注册者的 peer_id，并由 visitor 回复校验结果
+pub const CAPABILITY_PEER_IDENTITY: &str = "peer_identity";
+
+/// 协议能力：会话运行期间可以通过 `update_config` 增量提交代理配置
+pub const CAPABILITY_INCREMENTAL_CONFIG: &str = "incremental_config";
+
+/// 协议能力：支持 `visibility = "private"` 的代理（注册但不绑定发布端口）
+pub const CAPABILITY_PRIVATE_PROXY: &str = "private_proxy";
+
+/// 协议能力：visitor 可以在一条长期 stream 上复用多个本地连接（子流多路复用）
+pub const CAPABILITY_VISITOR_MUX: &str = "visitor_mux";
+
+/// 协议能力：会话运行期间可以通过 `remove_proxies` 主动下线代理，已有连接排空后才关闭
+pub const CAPABILITY_PROXY_DRAIN: &str = "proxy_drain";
+
+/// 协议能力：支持 `sni_routing` 代理（发往客户端的 stream 在 publish_port 之后携带选中的本地端口）
+pub const CAPABILITY_SNI_ROUTING: &str = "sni_routing";
+
+/// 协议能力：支持 `validate_config`（按提交配置的规则校验，但不注册、不绑定端口）
+pub const CAPABILITY_VALIDATE_CONFIG: &str = "validate_config";
+
+/// 协议能力：visitor stream 前导的 publish_port 为 0 时按名称匹配唯一的代理
+pub const CAPABILITY_VISITOR_ANY_PORT: &str = "visitor_any_port";
+
+/// 协议能力：带 id 的 `heartbeat` 请求由服务器回显参数作为确认，客户端据此测量往返时延
+pub const CAPABILITY_HEARTBEAT_ACK: &str = "heartbeat_ack";
+
+/// 协议能力：配置了 `idle_keepalive_secs` 的代理 stream 分帧传输，空闲时双方发送空负载的保活标记
+pub const CAPABILITY_IDLE_KEEPALIVE: &str = "idle_keepalive";
+
+/// 协议能力：服务器接受 `forward_report` 通知（客户端上报不经隧道的直连 forwarder 连接，仅用于审计）
+pub const CAPABILITY_FORWARD_REPORT: &str = "forward_report";
+
+/// 协议能力：visitor 确认帧在 peer_id 之后再携带 proxy 注册者的身份指纹（注册者未提供身份时为空）
+pub const CAPABILITY_CLIENT_IDENTITY: &str = "client_identity";
+
+/// 协议能力：开启了 `report_peers` 的代理 stream 以扩展前导携带外部连接的来源地址和接入时间
+pub const CAPABILITY_PEER_REPORT: &str = "peer_report";
+
+/// 协议能力：开启了 `integrity_check` 的代理 stream 两端交换各方向数据的 CRC32C 尾帧（诊断用）
+pub const CAPABILITY_INTEGRITY_CHECK: &str = "integrity_check";
+
+/// 协议能力：公开代理的 publish_port 为 0 时由服务器分配端口，确认响应中返回实际端口
+pub const CAPABILITY_AUTO_PUBLISH_PORT: &str = "auto_publish_port";
+
+/// 本端支持的全部协议能力
+pub fn supported_capabilities() -> Vec<String> {
+    vec![
+        CAPABILITY_PEER_IDENTITY.to_string(),
+        CAPABILITY_INCREMENTAL_CONFIG.to_string(),
+        CAPABILITY_PRIVATE_PROXY.to_string(),
+        CAPABILITY_VISITOR_MUX.to_string(),
+        CAPABILITY_PROXY_DRAIN.to_string(),
+        CAPABILITY_SNI_ROUTING.to_string(),
+        CAPABILITY_VALIDATE_CONFIG.to_string(),
+        CAPABILITY_VISITOR_ANY_PORT.to_string(),
+        CAPABILITY_HEARTBEAT_ACK.to_string(),
+        CAPABILITY_IDLE_KEEPALIVE.to_string(),
+        CAPABILITY_FORWARD_REPORT.to_string(),
+        CAPABILITY_CLIENT_IDENTITY.to_string(),
+        CAPABILITY_PEER_REPORT.to_string(),
+        CAPABILITY_INTEGRITY_CHECK.to_string(),
+        CAPABILITY_AUTO_PUBLISH_PORT.to_string(),
+    ]
+}
+
 /// 认证请求参数
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthenticateParams {
@@ -100,6 +253,29 @@ pub struct AuthenticateParams {
     /// 客户端协议版本（如 "1.4.1"）
     #[serde(default = "default_protocol_version")]
     pub protocol_version: String,
+    /// 客户端支持的可选协议能力（旧客户端不发送）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<String>,
+    /// 客户端稳定身份（注册 proxy 时记录，供 visitor 校验）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_id: Option<String>,
+    /// 客户端身份证明（对 `identity_challenge` 下发的随机数的签名，旧客户端和旧服务器不使用）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<IdentityProof>,
+}
+
+/// 客户端身份证明：Ed25519 公钥和对挑战随机数的签名（均为十六进制）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityProof {
+    pub public_key: String,
+    pub signature: String,
+}
+
+/// `identity_challenge` 响应结果（请求没有参数，认证之前发送）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdentityChallengeResult {
+    /// 随机数（十六进制），只能用于本会话的下一次认证
+    pub nonce: String,
 }
 
 fn default_protocol_version() -> String {
@@ -116,13 +292,16 @@ pub struct AuthenticateResult {
     /// 服务器支持的最小客户端版本（可选）
     #[serde(default)]
     pub min_client_version: Option<String>,
+    /// 服务器支持的可选协议能力（旧服务器不发送）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<String>,
 }
 
 fn default_server_protocol_version() -> String {
     env!("CARGO_PKG_VERSION").to_string()
 }
 
-/// 提交配置请求参数
+/// 提交配置请求参数（`validate_config` 使用相同的参数）
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SubmitConfigParams {
     pub proxies: Vec<crate::config::ProxyConfig>,
@@ -130,25 +309,168 @@ pub struct SubmitConfigParams {
     pub visitors: Vec<crate::config::VisitorConfig>,
 }
 
-/// 提交配置响应结果
+/// 增量配置更新请求参数（会话运行期间追加注册代理，已由本会话以相同配置注册的代理视为成功）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateConfigParams {
+    pub proxies: Vec<crate::config::ProxyConfig>,
+}
+
+/// 主动下线代理请求参数（服务器立即停止接受新连接，已有连接结束或排空超时后移除代理）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoveProxiesParams {
+    pub proxies: Vec<ProxyRef>,
+}
+
+/// 代理标识（名称和发布端口）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProxyRef {
+    pub name: String,
+    pub publish_port: u16,
+}
+
+/// 提交配置响应结果（增量配置更新和主动下线代理的响应结果相同）
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SubmitConfigResult {
     pub rejected_proxies: Vec<String>,
+    /// 被拒绝项的原因（键与 rejected_proxies 中的条目相同）
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub reasons: BTreeMap<String, String>,
+    /// 仅校验的结果（`validate_config`），服务器没有注册任何代理
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+    /// 被拒绝项的结构化信息（与 rejected_proxies 一一对应；旧版本服务器不发送，
+    /// 客户端此时按 rejected_proxies 处理）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rejected_entries: Vec<RejectedEntry>,
+    /// 服务器为 publish_port 为 0 的代理分配的端口
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub assigned_ports: Vec<AssignedPort>,
+}
+
+/// 服务器分配的发布端口（提交时 publish_port 为 0 的代理）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssignedPort {
+    pub name: String,
+    pub publish_port: u16,
+}
+
+/// 被拒绝项的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectedKind {
+    Proxy,
+    Visitor,
+}
+
+/// 被服务器拒绝的一个代理或 visitor
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RejectedEntry {
+    pub name: String,
+    pub publish_port: u16,
+    pub reason: String,
+    pub kind: RejectedKind,
+}
+
+impl RejectedEntry {
+    /// 由 `name:port` 形式的拒绝项创建，原因取自 `reasons`
+    pub fn from_item(item: &str, kind: RejectedKind, reasons: &BTreeMap<String, String>) -> Self {
+        let (name, publish_port) = match item.rsplit_once(':') {
+            Some((name, port)) => (name, port.parse().unwrap_or(0)),
+            None => (item, 0),
+        };
+        Self {
+            name: name.to_string(),
+            publish_port,
+            reason: reasons
+                .get(item)
+                .cloned()
+                .unwrap_or_else(|| "rejected by server".to_string()),
+            kind,
+        }
+    }
+
+    /// `name:port` 形式的条目（与 rejected_proxies 中的条目相同）
+    pub fn item(&self) -> String {
+        format!("{}:{}", self.name, self.publish_port)
+    }
+}
+
+/// 心跳请求参数（服务器的确认结果原样回显）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct HeartbeatParams {
+    /// 心跳序号（会话内递增）
+    pub seq: u64,
+    /// 发送时间（客户端会话开始后经过的毫秒数）
+    pub timestamp_ms: u64,
+}
+
+/// 一条直连 forwarder 连接的报告（连接结束时生成）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForwardReport {
+    /// forwarder 名称
+    pub forwarder: String,
+    /// 目标地址（IPv6 带方括号）
+    pub target: String,
+    /// 路由结果（目前只上报 `direct`）
+    pub decision: String,
+    /// 命中的路由规则类别（如 `direct_by_domain`），HTTP 普通请求（非 CONNECT）不经路由规则，为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    /// 具体匹配项（域名模式、CIDR、国家代码等）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matched: Option<String>,
+    /// 本地应用发往目标的字节数
+    pub bytes_up: u64,
+    /// 目标发回本地应用的字节数
+    pub bytes_down: u64,
+    /// 连接持续时间（毫秒）
+    pub duration_ms: u64,
+    /// 连接目标失败的原因
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// `forward_report` 通知参数（客户端每秒最多发送一条，合并期间结束的所有连接）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForwardReportParams {
+    pub reports: Vec<ForwardReport>,
+    /// 客户端因待发送的报告过多而丢弃的报告数
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub dropped: u64,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
 }
 
 /// 控制通道方法
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ControlMethod {
     // 客户端 -> 服务端
+    /// 请求身份挑战随机数（认证之前）
+    IdentityChallenge,
+
     /// 认证
     Authenticate,
 
     /// 提交配置
     SubmitConfig,
 
+    /// 校验配置（不注册代理）
+    ValidateConfig,
+
+    /// 增量配置更新
+    UpdateConfig,
+
+    /// 主动下线代理
+    RemoveProxies,
+
     /// 心跳
     Heartbeat,
 
+    /// 上报直连 forwarder 连接（通知）
+    ForwardReport,
+
     // 服务端 -> 客户端
     /// 推送配置状态
     PushConfigStatus,
@@ -160,19 +482,48 @@ pub enum ControlMethod {
     PushException,
 }
 
+impl ControlMethod {
+    /// 全部控制通道方法
+    pub const ALL: [ControlMethod; 11] = [
+        ControlMethod::IdentityChallenge,
+        ControlMethod::Authenticate,
+        ControlMethod::SubmitConfig,
+        ControlMethod::ValidateConfig,
+        ControlMethod::UpdateConfig,
+        ControlMethod::RemoveProxies,
+        ControlMethod::Heartbeat,
+        ControlMethod::ForwardReport,
+        ControlMethod::PushConfigStatus,
+        ControlMethod::PushStats,
+        ControlMethod::PushException,
+    ];
+
+    /// 线上的方法名
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ControlMethod::IdentityChallenge => "identity_challenge",
+            ControlMethod::Authenticate => "authenticate",
+            ControlMethod::SubmitConfig => "submit_config",
+            ControlMethod::ValidateConfig => "validate_config",
+            ControlMethod::UpdateConfig => "update_config",
+            ControlMethod::RemoveProxies => "remove_proxies",
+            ControlMethod::Heartbeat => "heartbeat",
+            ControlMethod::ForwardReport => "forward_report",
+            ControlMethod::PushConfigStatus => "push_config_status",
+            ControlMethod::PushStats => "push_stats",
+            ControlMethod::PushException => "push_exception",
+        }
+    }
+}
+
 impl std::str::FromStr for ControlMethod {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> anyhow::Result<Self> {
-        match s {
-            "authenticate" => Ok(ControlMethod::Authenticate),
-            "submit_config" => Ok(ControlMethod::SubmitConfig),
-            "heartbeat" => Ok(ControlMethod::Heartbeat),
-            "push_config_status" => Ok(ControlMethod::PushConfigStatus),
-            "push_stats" => Ok(ControlMethod::PushStats),
-            "push_exception" => Ok(ControlMethod::PushException),
-            _ => Err(anyhow::anyhow!("Unknown control method: {}", s)),
-        }
+        ControlMethod::ALL
+            .into_iter()
+            .find(|method| method.as_str() == s)
+            .ok_or_else(|| anyhow::anyhow!("Unknown control method: {}", s))
     }
 }
 
@@ -189,4 +540,352 @@ pub struct ExceptionNotification {
     /// 附加数据（可选）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<Value>,
+    /// 合并的相同通知次数（多于一次时才设置）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub occurrences: Option<u64>,
+}
+
+impl ExceptionNotification {
+    /// 创建带类型附加数据的通知
+    pub fn with_data<D: Serialize>(
+        level: &str,
+        message: String,
+        code: &str,
+        data: &D,
+    ) -> serde_json::Result<Self> {
+        Ok(Self {
+            level: level.to_string(),
+            message,
+            code: Some(code.to_string()),
+            data: Some(serde_json::to_value(data)?),
+            occurrences: None,
+        })
+    }
+
+    /// 按异常代码对应的类型解析附加数据（没有附加数据时返回 None）
+    pub fn decode_data<D: DeserializeOwned>(&self) -> Option<serde_json::Result<D>> {
+        self.data.as_ref().map(D::deserialize)
+    }
+}
+
+/// 异常代码：代理空闲超过服务器的 `idle_registration_expiry_secs` 被注销（附加数据为 [`ProxyExpiredData`]）
+pub const EXCEPTION_PROXY_EXPIRED_IDLE: &str = "PROXY_EXPIRED_IDLE";
+
+/// [`EXCEPTION_PROXY_EXPIRED_IDLE`] 通知的附加数据
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProxyExpiredData {
+    /// 代理名称
+    pub name: String,
+    /// 公开端口
+    pub publish_port: u16,
+    /// 注销时已空闲的秒数
+    pub idle_secs: u64,
+    /// 服务器配置的过期秒数
+    pub expiry_secs: u64,
+}
+
+/// 异常代码：提交的代理全部被拒绝（附加数据为 [`ConfigRejectedData`]）
+pub const EXCEPTION_ALL_PROXIES_REJECTED: &str = "ALL_PROXIES_REJECTED";
+
+/// 异常代码：部分代理或 visitor 被拒绝（附加数据为 [`PartialConfigRejectionData`]）
+pub const EXCEPTION_PARTIAL_CONFIG_REJECTION: &str = "PARTIAL_CONFIG_REJECTION";
+
+/// [`EXCEPTION_PARTIAL_CONFIG_REJECTION`] 通知的附加数据
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartialConfigRejectionData {
+    /// 全部被拒绝的条目（代理和 visitor）
+    pub rejected_items: Vec<String>,
+    pub rejected_proxies: Vec<String>,
+    pub rejected_visitors: Vec<String>,
+    /// 被拒绝项的原因
+    #[serde(default)]
+    pub reasons: BTreeMap<String, String>,
+}
+
+/// 异常代码：visitor 校验 proxy 注册者身份失败（附加数据为 [`PeerIdMismatchData`]）
+pub const EXCEPTION_PEER_ID_MISMATCH: &str = "PEER_ID_MISMATCH";
+
+/// [`EXCEPTION_PEER_ID_MISMATCH`] 通知的附加数据
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerIdMismatchData {
+    pub proxy_name: String,
+    pub publish_port: u16,
+    /// 注册者声明的 peer_id
+    pub registered_peer_id: Option<String>,
+    /// 注册者的身份指纹
+    pub registered_identity: Option<String>,
+}
+
+/// 异常代码：代理的发布监听器接受连接失败（附加数据为 [`ProxyAcceptErrorData`]）
+pub const EXCEPTION_PROXY_ACCEPT_ERROR: &str = "PROXY_ACCEPT_ERROR";
+
+/// [`EXCEPTION_PROXY_ACCEPT_ERROR`] 通知的附加数据
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProxyAcceptErrorData {
+    pub proxy_name: String,
+    pub publish_port: u16,
+}
+
+/// 异常代码：服务器正在关闭
+pub const EXCEPTION_SERVER_SHUTDOWN: &str = "SERVER_SHUTDOWN";
+
+/// 异常代码：forwarder 连接触发服务器的限制被关闭（附加数据为 [`ForwardLimitData`]）
+pub const EXCEPTION_FORWARD_LIMIT_EXCEEDED: &str = "FORWARD_LIMIT_EXCEEDED";
+
+/// [`EXCEPTION_FORWARD_LIMIT_EXCEEDED`] 通知的附加数据
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForwardLimitData {
+    /// 目标地址
+    pub target: String,
+    /// 触发的限制（`max_bytes_per_conn` 或 `max_duration_secs`）
+    pub limit: String,
+    /// 关闭时已转发的字节数
+    pub bytes: u64,
+    /// 关闭时连接已持续的秒数
+    pub duration_secs: u64,
+    /// 服务器配置的全部限制
+    pub limits: crate::config::ForwardLimitConfig,
+}
+
+/// 异常代码：客户端会话的 stream 数达到服务器的 `max_streams_per_client`，新的 stream 被拒绝
+/// （附加数据为 [`StreamLimitData`]）
+pub const EXCEPTION_STREAM_LIMIT_EXCEEDED: &str = "STREAM_LIMIT_EXCEEDED";
+
+/// [`EXCEPTION_STREAM_LIMIT_EXCEEDED`] 通知的附加数据
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamLimitData {
+    /// 被拒绝的 stream 所属的代理（客户端打开的 visitor / forwarder stream 为空）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish_port: Option<u16>,
+    /// 服务器配置的上限
+    pub max_streams: u32,
+}
+
+/// 异常代码：限流期间被丢弃的通知汇总（附加数据为 [`ExceptionsSuppressedData`]）
+pub const EXCEPTION_EXCEPTIONS_SUPPRESSED: &str = "EXCEPTIONS_SUPPRESSED";
+
+/// [`EXCEPTION_EXCEPTIONS_SUPPRESSED`] 通知的附加数据
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExceptionsSuppressedData {
+    /// 被丢弃的通知数
+    pub suppressed: u64,
+    /// 限流的时间窗口（秒）
+    pub interval_secs: u64,
+}
+
+/// 控制通道中不可恢复的读取错误
+///
+/// 单条消息解析失败时帧边界仍然完整，可以继续读取后续消息；
+/// 以下情况无法再可靠地定位下一帧，必须结束会话
+#[derive(Debug, Error)]
+pub enum ControlFrameError {
+    /// 消息长度超过 [`MAX_CONTROL_MESSAGE_SIZE`]
+    #[error("control message size limit exceeded: {size} bytes (limit {limit})")]
+    TooLarge { size: usize, limit: usize },
+
+    /// 帧不同步（长度前缀不合法或消息体被截断）
+    #[error("control stream framing desync: {0}")]
+    Desync(String),
+
+    /// 底层 I/O 错误
+    #[error("control stream I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl ControlFrameError {
+    /// 错误类型名称（写入发给对端的错误数据）
+    pub fn condition(&self) -> &'static str {
+        match self {
+            ControlFrameError::TooLarge { .. } => "size_limit_exceeded",
+            ControlFrameError::Desync(_) => "framing_desync",
+            ControlFrameError::Io(_) => "io_error",
+        }
+    }
+
+    /// 会话结束前通知对端的错误响应
+    pub fn to_rpc_error(&self) -> JsonRpcError {
+        JsonRpcError {
+            code: INVALID_REQUEST,
+            message: format!("Closing control channel: {}", self),
+            data: Some(json!({ "condition": self.condition() })),
+        }
+    }
+}
+
+/// 读取一帧控制消息（4 字节大端长度 + 消息体），对端正常关闭时返回 None
+pub async fn read_frame<R>(reader: &mut R) -> Result<Option<Vec<u8>>, ControlFrameError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut len_buf = [0u8; 4];
+    match reader.read_exact(&mut len_buf).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+
+    // 长度前缀以 '{' 开头说明对端直接发送了未分帧的 JSON
+    if len_buf[0] == b'{' {
+        return Err(ControlFrameError::Desync(
+            "length prefix looks like unframed JSON".to_string(),
+        ));
+    }
+
+    let msg_len = u32::from_be_bytes(len_buf) as usize;
+    if msg_len > MAX_CONTROL_MESSAGE_SIZE {
+        return Err(ControlFrameError::TooLarge {
+            size: msg_len,
+            limit: MAX_CONTROL_MESSAGE_SIZE,
+        });
+    }
+
+    let mut msg_buf = vec![0u8; msg_len];
+    match reader.read_exact(&mut msg_buf).await {
+        Ok(()) => Ok(Some(msg_buf)),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Err(ControlFrameError::Desync(
+            format!("stream ended inside a {} byte message", msg_len),
+        )),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 可取消的控制帧读取器
+///
+/// 已读到的部分帧保留在缓冲区中，在 `select!` 里读取被取消后，下次调用会从断点继续，
+/// 不会丢失长度前缀导致帧不同步。同一控制流的所有读取都应经过同一个读取器
+#[derive(Debug, Default)]
+pub struct FrameReader {
+    buf: Vec<u8>,
+}
+
+impl FrameReader {
+    /// 创建空的读取器
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 读取一帧控制消息，语义与 [`read_frame`] 相同
+    pub async fn read_frame<R>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<Option<Vec<u8>>, ControlFrameError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut chunk = [0u8; 4096];
+        loop {
+            if let Some(frame) = self.take_frame()? {
+                return Ok(Some(frame));
+            }
+            let n = reader.read(&mut chunk).await?;
+            if n == 0 {
+                // 长度前缀未读完时按对端正常关闭处理（与 read_frame 一致）
+                if self.buf.len() < 4 {
+                    self.buf.clear();
+                    return Ok(None);
+                }
+                let msg_len =
+                    u32::from_be_bytes([self.buf[0], self.buf[1], self.buf[2], self.buf[3]]);
+                return Err(ControlFrameError::Desync(format!(
+                    "stream ended inside a {} byte message",
+                    msg_len
+                )));
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    /// 缓冲区中已有完整的一帧时取出
+    fn take_frame(&mut self) -> Result<Option<Vec<u8>>, ControlFrameError> {
+        if self.buf.len() < 4 {
+            return Ok(None);
+        }
+
+        // 长度前缀以 '{' 开头说明对端直接发送了未分帧的 JSON
+        if self.buf[0] == b'{' {
+            return Err(ControlFrameError::Desync(
+                "length prefix looks like unframed JSON".to_string(),
+            ));
+        }
+
+        let msg_len =
+            u32::from_be_bytes([self.buf[0], self.buf[1], self.buf[2], self.buf[3]]) as usize;
+        if msg_len > MAX_CONTROL_MESSAGE_SIZE {
+            return Err(ControlFrameError::TooLarge {
+                size: msg_len,
+                limit: MAX_CONTROL_MESSAGE_SIZE,
+            });
+        }
+        if self.buf.len() < 4 + msg_len {
+            return Ok(None);
+        }
+
+        let frame = self.buf[4..4 + msg_len].to_vec();
+        self.buf.drain(..4 + msg_len);
+        Ok(Some(frame))
+    }
+}
+
+/// 写入一帧控制消息
+pub async fn write_frame<W>(writer: &mut W, data: &[u8]) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    writer.write_all(&(data.len() as u32).to_be_bytes()).await?;
+    writer.write_all(data).await?;
+    writer.flush().await
+}
+
+/// 解析后的单条控制消息
+#[derive(Debug)]
+pub enum ControlMessage {
+    /// 请求或通知
+    Request(JsonRpcRequest),
+    /// 响应
+    Response(JsonRpcResponse),
+    /// 无法解析的消息，附带应回复给对端的错误（能识别出请求 id 时一并带上）
+    Malformed { id: Value, error: JsonRpcError },
+}
+
+/// 解析单条控制消息
+pub fn parse_message(buf: &[u8]) -> ControlMessage {
+    let value: Value = match serde_json::from_slice(buf) {
+        Ok(value) => value,
+        Err(e) => {
+            return ControlMessage::Malformed {
+                id: Value::Null,
+                error: JsonRpcError {
+                    code: PARSE_ERROR,
+                    message: format!("Parse error: {}", e),
+                    data: None,
+                },
+            }
+        }
+    };
+
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+    let invalid = |id: Value, reason: String| ControlMessage::Malformed {
+        id,
+        error: JsonRpcError {
+            code: INVALID_REQUEST,
+            message: format!("Invalid request: {}", reason),
+            data: None,
+        },
+    };
+
+    if value.get("method").is_some() {
+        match serde_json::from_value::<JsonRpcRequest>(value) {
+            Ok(request) => ControlMessage::Request(request),
+            Err(e) => invalid(id, e.to_string()),
+        }
+    } else if value.get("result").is_some() || value.get("error").is_some() {
+        match serde_json::from_value::<JsonRpcResponse>(value) {
+            Ok(response) => ControlMessage::Response(response),
+            Err(e) => invalid(id, e.to_string()),
+        }
+    } else {
+        invalid(id, "missing method".to_string())
+    }
 }
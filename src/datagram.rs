@@ -0,0 +1,91 @@
+/// UDP 代理 stream 上的数据报分帧
+///
+/// `proxy_type = "udp"` 的代理，服务器为每个外部来源地址打开一个 stream，普通的代理 stream 头部
+/// 之后两个方向都按帧传输数据报：u16 负载长度（大端）加负载（[`encode_datagram`]），一帧对应
+/// 一个数据报，数据报的边界在隧道中保持不变
+use crate::protocol::{encode_datagram, MAX_DATAGRAM_SIZE};
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use std::io;
+
+/// 读取一个数据报帧到 `buf`（至少 [`MAX_DATAGRAM_SIZE`] 字节），返回负载长度；
+/// stream 在帧边界结束时返回 None
+pub async fn read_datagram<R>(reader: &mut R, buf: &mut [u8]) -> io::Result<Option<usize>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut header = [0u8; 2];
+    let mut filled = 0;
+    while filled < header.len() {
+        match reader.read(&mut header[filled..]).await? {
+            0 if filled == 0 => return Ok(None),
+            0 => return Err(truncated_datagram()),
+            n => filled += n,
+        }
+    }
+    let len = u16::from_be_bytes(header) as usize;
+    reader
+        .read_exact(&mut buf[..len])
+        .await
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => truncated_datagram(),
+            _ => e,
+        })?;
+    Ok(Some(len))
+}
+
+/// 把一个数据报作为一帧写入 stream
+pub async fn write_datagram<W>(writer: &mut W, payload: &[u8]) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    if payload.len() > MAX_DATAGRAM_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Datagram of {} bytes is too large", payload.len()),
+        ));
+    }
+    writer.write_all(&encode_datagram(payload)).await?;
+    writer.flush().await
+}
+
+fn truncated_datagram() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "Stream closed in the middle of a datagram",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_datagram_boundaries_are_kept() {
+        let mut wire = Vec::new();
+        for payload in [&b"first"[..], b"", b"third datagram"] {
+            write_datagram(&mut wire, payload).await.unwrap();
+        }
+        assert_eq!(&wire[..7], b"\x00\x05first");
+
+        let mut reader = &wire[..];
+        let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+        let mut received = Vec::new();
+        while let Some(len) = read_datagram(&mut reader, &mut buf).await.unwrap() {
+            received.push(buf[..len].to_vec());
+        }
+        assert_eq!(
+            received,
+            vec![b"first".to_vec(), vec![], b"third datagram".to_vec()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_truncated_datagram_is_an_error() {
+        let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+        for wire in [&b"\x00"[..], b"\x00\x05abc"] {
+            let mut reader = wire;
+            let err = read_datagram(&mut reader, &mut buf).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+}
@@ -0,0 +1,348 @@
+/// 客户端核心的 C ABI（`ffi` feature）
+///
+/// 供 C/C++ 程序在进程内嵌入隧道客户端。头文件 `include/tls_tunnel.h` 由 cbindgen 生成：
+///
+/// ```text
+/// cbindgen --config cbindgen.toml --output include/tls_tunnel.h src/ffi.rs
+/// ```
+///
+/// 约定：
+/// - 所有函数都捕获 panic，panic 时按失败返回（NULL 或 -1）
+/// - 返回的字符串由调用方通过 [`tt_string_free`] 释放
+/// - 事件回调在客户端内部的后台线程中调用，传入的字符串只在回调期间有效
+use crate::client::{self, SessionEvent, SESSION_EVENT_CAPACITY};
+use crate::config::{compat, ClientFullConfig, ConfigValidator};
+use crate::tls;
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsConnector;
+use tracing::{error, warn};
+
+/// 释放客户端时等待后台任务退出的最长时间
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 会话事件回调（可以为 NULL）
+///
+/// `event_json` 为单个事件的 JSON 对象（与 `events_socket` 镜像的格式相同），只在回调期间有效
+pub type TtEventCallback = Option<extern "C" fn(event_json: *const c_char, user_data: *mut c_void)>;
+
+/// 已注册的事件回调
+#[derive(Clone, Copy)]
+struct EventCallback {
+    func: extern "C" fn(*const c_char, *mut c_void),
+    user_data: *mut c_void,
+}
+
+// user_data 由调用方保证可以在回调线程中使用
+unsafe impl Send for EventCallback {}
+
+/// 客户端运行状态（供 `tt_client_status_json` 查询）
+#[derive(Default)]
+struct ClientStatus {
+    /// 是否已启动且尚未停止
+    running: bool,
+    /// 最近一次会话事件
+    last_event: Option<SessionEvent>,
+    /// 客户端异常退出时的错误
+    error: Option<String>,
+}
+
+/// 嵌入的隧道客户端（对 C 不透明）
+pub struct TtClient {
+    runtime: Runtime,
+    config: ClientFullConfig,
+    connector: TlsConnector,
+    events: broadcast::Sender<SessionEvent>,
+    status: Arc<Mutex<ClientStatus>>,
+    callback: Arc<Mutex<Option<EventCallback>>>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl TtClient {
+    fn new(config_toml: &str) -> Result<Self> {
+        let (config, compat) = compat::parse_client_config(config_toml)
+            .context("Failed to parse client configuration")?;
+        ConfigValidator::validate_field_aliases(&compat)
+            .context("Configuration validation failed")?;
+        config
+            .validate()
+            .context("Configuration validation failed")?;
+        if let Some(warning) = compat.deprecation_warning() {
+            warn!("{}", warning);
+        }
+
+        let tls_config = tls::load_client_config_from(&config.client)?;
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("tls-tunnel-ffi")
+            .build()
+            .context("Failed to create tokio runtime")?;
+
+        let (events, _) = broadcast::channel(SESSION_EVENT_CAPACITY);
+        let client = Self {
+            runtime,
+            config,
+            connector: TlsConnector::from(tls_config),
+            events,
+            status: Arc::new(Mutex::new(ClientStatus::default())),
+            callback: Arc::new(Mutex::new(None)),
+            task: None,
+        };
+        client.spawn_event_dispatcher();
+        Ok(client)
+    }
+
+    /// 将会话事件记录到状态并转发给回调
+    fn spawn_event_dispatcher(&self) {
+        let mut rx = self.events.subscribe();
+        let status = self.status.clone();
+        let callback = self.callback.clone();
+        self.runtime.spawn(async move {
+            loop {
+                let event = match rx.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let json =
+                    CString::new(event.to_json_line().trim_end()).expect("JSON never contains NUL");
+                status.lock().last_event = Some(event);
+                // 回调期间持有锁，保证 tt_client_set_event_callback 返回后旧回调不再被调用
+                let callback = callback.lock();
+                if let Some(cb) = *callback {
+                    (cb.func)(json.as_ptr(), cb.user_data);
+                }
+            }
+        });
+    }
+
+    fn start(&mut self) -> Result<()> {
+        if self.task.as_ref().is_some_and(|task| !task.is_finished()) {
+            anyhow::bail!("Client is already running");
+        }
+
+        let config = self.config.clone();
+        let connector = self.connector.clone();
+        let events = self.events.clone();
+        let status = self.status.clone();
+        {
+            let mut status = status.lock();
+            status.running = true;
+            status.error = None;
+        }
+        self.task = Some(self.runtime.spawn(async move {
+            let result = client::run_client_with_events(config, connector, events).await;
+            let mut status = status.lock();
+            status.running = false;
+            if let Err(e) = result {
+                status.error = Some(format!("{:#}", e));
+            }
+        }));
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+            let _ = self.runtime.block_on(task);
+        }
+        self.status.lock().running = false;
+    }
+
+    fn status_json(&self) -> String {
+        let status = self.status.lock();
+        let last_event = status.last_event.as_ref().and_then(|event| {
+            serde_json::from_str::<serde_json::Value>(&event.to_json_line()).ok()
+        });
+        serde_json::json!({
+            "running": status.running,
+            "state": status.last_event.as_ref().map(SessionEvent::name),
+            "last_event": last_event,
+            "error": status.error,
+        })
+        .to_string()
+    }
+}
+
+/// 捕获 panic，panic 时返回 `default`
+fn guard<T>(default: T, f: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        error!("Panic caught at FFI boundary");
+        default
+    })
+}
+
+/// 从 TOML 配置文本创建客户端（不连接服务器）
+///
+/// 配置无效或创建失败时返回 NULL。返回的客户端必须通过 [`tt_client_free`] 释放
+///
+/// # Safety
+///
+/// `config_toml` 必须是以 NUL 结尾的 UTF-8 字符串
+#[no_mangle]
+pub unsafe extern "C" fn tt_client_new(config_toml: *const c_char) -> *mut TtClient {
+    guard(std::ptr::null_mut(), || {
+        if config_toml.is_null() {
+            return std::ptr::null_mut();
+        }
+        let config = match CStr::from_ptr(config_toml).to_str() {
+            Ok(config) => config,
+            Err(_) => {
+                error!("Client configuration is not valid UTF-8");
+                return std::ptr::null_mut();
+            }
+        };
+        match TtClient::new(config) {
+            Ok(client) => Box::into_raw(Box::new(client)),
+            Err(e) => {
+                error!("Failed to create client: {:#}", e);
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// 在后台启动客户端（自动重连），成功返回 0，已在运行或失败返回 -1
+///
+/// # Safety
+///
+/// `client` 必须是 [`tt_client_new`] 返回且尚未释放的指针
+#[no_mangle]
+pub unsafe extern "C" fn tt_client_start(client: *mut TtClient) -> c_int {
+    guard(-1, || {
+        let Some(client) = client.as_mut() else {
+            return -1;
+        };
+        match client.start() {
+            Ok(()) => 0,
+            Err(e) => {
+                error!("Failed to start client: {:#}", e);
+                -1
+            }
+        }
+    })
+}
+
+/// 停止客户端并等待后台会话结束，成功返回 0（未运行时同样返回 0）
+///
+/// 停止后可以再次调用 [`tt_client_start`]
+///
+/// # Safety
+///
+/// `client` 必须是 [`tt_client_new`] 返回且尚未释放的指针；不能在事件回调中调用
+#[no_mangle]
+pub unsafe extern "C" fn tt_client_stop(client: *mut TtClient) -> c_int {
+    guard(-1, || {
+        let Some(client) = client.as_mut() else {
+            return -1;
+        };
+        client.stop();
+        0
+    })
+}
+
+/// 以 JSON 返回客户端状态（`running`、`state`、`last_event`、`error`）
+///
+/// 返回的字符串由调用方通过 [`tt_string_free`] 释放，失败返回 NULL
+///
+/// # Safety
+///
+/// `client` 必须是 [`tt_client_new`] 返回且尚未释放的指针
+#[no_mangle]
+pub unsafe extern "C" fn tt_client_status_json(client: *const TtClient) -> *mut c_char {
+    guard(std::ptr::null_mut(), || {
+        let Some(client) = client.as_ref() else {
+            return std::ptr::null_mut();
+        };
+        CString::new(client.status_json())
+            .map(CString::into_raw)
+            .unwrap_or(std::ptr::null_mut())
+    })
+}
+
+/// 设置会话事件回调（`callback` 为 NULL 时取消），成功返回 0
+///
+/// 返回后旧回调不会再被调用
+///
+/// # Safety
+///
+/// `client` 必须是 [`tt_client_new`] 返回且尚未释放的指针；不能在事件回调中调用。
+/// `user_data` 在回调被替换或客户端释放前必须保持有效，且可以在其他线程中使用
+#[no_mangle]
+pub unsafe extern "C" fn tt_client_set_event_callback(
+    client: *mut TtClient,
+    callback: TtEventCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    guard(-1, || {
+        let Some(client) = client.as_ref() else {
+            return -1;
+        };
+        *client.callback.lock() = callback.map(|func| EventCallback { func, user_data });
+        0
+    })
+}
+
+/// 停止并释放客户端（NULL 时忽略）
+///
+/// # Safety
+///
+/// `client` 必须是 [`tt_client_new`] 返回的指针，释放后不能再使用
+#[no_mangle]
+pub unsafe extern "C" fn tt_client_free(client: *mut TtClient) {
+    guard((), || {
+        if client.is_null() {
+            return;
+        }
+        let mut client = Box::from_raw(client);
+        client.stop();
+        *client.callback.lock() = None;
+        let TtClient { runtime, .. } = *client;
+        runtime.shutdown_timeout(SHUTDOWN_TIMEOUT);
+    })
+}
+
+/// 释放本库返回的字符串（NULL 时忽略）
+///
+/// # Safety
+///
+/// `s` 必须是本库返回且尚未释放的字符串
+#[no_mangle]
+pub unsafe extern "C" fn tt_string_free(s: *mut c_char) {
+    guard((), || {
+        if !s.is_null() {
+            drop(CString::from_raw(s));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_config_returns_null() {
+        let config = CString::new("not = [valid").unwrap();
+        assert!(unsafe { tt_client_new(config.as_ptr()) }.is_null());
+        assert!(unsafe { tt_client_new(std::ptr::null()) }.is_null());
+    }
+
+    #[test]
+    fn test_null_client_is_rejected() {
+        unsafe {
+            assert_eq!(tt_client_start(std::ptr::null_mut()), -1);
+            assert_eq!(tt_client_stop(std::ptr::null_mut()), -1);
+            assert!(tt_client_status_json(std::ptr::null()).is_null());
+            tt_client_free(std::ptr::null_mut());
+            tt_string_free(std::ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,203 @@
+/// 转发数据的完整性校验（诊断用）
+///
+/// 开启了 `integrity_check` 的代理 stream 分帧传输。两端在转发循环中累计每个方向写入 stream
+/// 和从 stream 收到的负载字节数与 CRC32C；发送方在该方向结束（读到外部连接或本地服务的 EOF）时
+/// 写入尾帧（[`IntegrityTrailer`]），接收方读到 stream 的 EOF 时与自己收到的数据比较。
+/// 校验和在两端读写外部连接和本地服务的位置计算：不一致说明数据在两端之间（隧道连接、中间设备
+/// 或转发代码）被改动；一致而应用仍看到损坏时，问题在隧道之外。不一致时记录带连接标识、方向、
+/// 字节数和双方校验和的错误日志，并计入代理统计的 `integrity_mismatches`
+use crate::protocol::IntegrityTrailer;
+use parking_lot::Mutex;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::{debug, error};
+
+/// 转发方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// 服务器发往客户端（外部连接发来的数据）
+    ServerToClient,
+    /// 客户端发往服务器（本地服务发回的数据）
+    ClientToServer,
+}
+
+impl Direction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Direction::ServerToClient => "server_to_client",
+            Direction::ClientToServer => "client_to_server",
+        }
+    }
+
+    fn reverse(self) -> Self {
+        match self {
+            Direction::ServerToClient => Direction::ClientToServer,
+            Direction::ClientToServer => Direction::ServerToClient,
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 一个方向上累计的负载字节数和 CRC32C
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Checksum {
+    bytes: u64,
+    crc32c: u32,
+}
+
+impl Checksum {
+    /// 累计一段负载
+    pub fn update(&mut self, data: &[u8]) {
+        self.bytes += data.len() as u64;
+        self.crc32c = crc32c::crc32c_append(self.crc32c, data);
+    }
+
+    /// 对应的尾帧内容
+    pub fn trailer(&self) -> IntegrityTrailer {
+        IntegrityTrailer {
+            bytes: self.bytes,
+            crc32c: self.crc32c,
+        }
+    }
+}
+
+/// 完整性校验的计数（代理的统计跟踪器持有，各连接共享）
+#[derive(Debug, Clone, Default)]
+pub struct IntegrityCounters {
+    verified: Arc<AtomicU64>,
+    mismatches: Arc<AtomicU64>,
+}
+
+impl IntegrityCounters {
+    /// 校验一致的方向数
+    pub fn verified(&self) -> u64 {
+        self.verified.load(Ordering::Relaxed)
+    }
+
+    /// 校验不一致的方向数
+    pub fn mismatches(&self) -> u64 {
+        self.mismatches.load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        self.verified.store(0, Ordering::Relaxed);
+        self.mismatches.store(0, Ordering::Relaxed);
+    }
+}
+
+/// 一个转发连接的完整性校验，两个方向共用
+///
+/// 发送方向的校验和保存在这里（连接内的多段写入，如 SNI 预读数据和客户端重试本地连接，
+/// 都计入同一个校验和），接收方向由 [`FrameReader`](crate::keepalive::FrameReader) 累计
+#[derive(Debug)]
+pub struct IntegrityCheck {
+    /// 连接标识（`代理名#stream id`，两端相同）
+    conn_id: String,
+    /// 本端写入 stream 的方向
+    outgoing: Direction,
+    sent: Mutex<Checksum>,
+    counters: IntegrityCounters,
+}
+
+impl IntegrityCheck {
+    pub fn new(conn_id: String, outgoing: Direction, counters: IntegrityCounters) -> Arc<Self> {
+        Arc::new(Self {
+            conn_id,
+            outgoing,
+            sent: Mutex::new(Checksum::default()),
+            counters,
+        })
+    }
+
+    /// 记录写入 stream 的负载
+    pub fn record_sent(&self, data: &[u8]) {
+        self.sent.lock().update(data);
+    }
+
+    /// 本端发送方向结束时写入的尾帧
+    pub fn trailer_frame(&self) -> Vec<u8> {
+        self.sent.lock().trailer().encode()
+    }
+
+    /// 比较对端的尾帧和本端收到的数据，返回是否一致（对端没有发送尾帧时不计数，返回 None）
+    pub fn verify(&self, received: Checksum, trailer: Option<IntegrityTrailer>) -> Option<bool> {
+        let direction = self.outgoing.reverse();
+        let Some(trailer) = trailer else {
+            debug!(
+                conn_id = %self.conn_id,
+                %direction,
+                received_bytes = received.bytes,
+                "Stream closed without an integrity trailer, direction not verified"
+            );
+            return None;
+        };
+        let received = received.trailer();
+        if received == trailer {
+            self.counters.verified.fetch_add(1, Ordering::Relaxed);
+            debug!(
+                conn_id = %self.conn_id,
+                %direction,
+                bytes = received.bytes,
+                crc32c = format_args!("{:08x}", received.crc32c),
+                "Integrity check passed"
+            );
+            return Some(true);
+        }
+        self.counters.mismatches.fetch_add(1, Ordering::Relaxed);
+        error!(
+            conn_id = %self.conn_id,
+            %direction,
+            sent_bytes = trailer.bytes,
+            received_bytes = received.bytes,
+            sent_crc32c = format_args!("{:08x}", trailer.crc32c),
+            received_crc32c = format_args!("{:08x}", received.crc32c),
+            "Integrity check FAILED: relayed data changed between the tunnel endpoints"
+        );
+        Some(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksum_is_incremental() {
+        let mut whole = Checksum::default();
+        whole.update(b"hello world");
+        assert_eq!(whole.trailer().crc32c, 0xc99465aa);
+        assert_eq!(whole.trailer().bytes, 11);
+
+        let mut parts = Checksum::default();
+        parts.update(b"hello");
+        parts.update(b"");
+        parts.update(b" world");
+        assert_eq!(parts, whole);
+    }
+
+    #[test]
+    fn test_verify_counts_matches_and_mismatches() {
+        let counters = IntegrityCounters::default();
+        let check = IntegrityCheck::new(
+            "web#1".to_string(),
+            Direction::ServerToClient,
+            counters.clone(),
+        );
+        let mut received = Checksum::default();
+        received.update(b"payload");
+
+        assert_eq!(check.verify(received, Some(received.trailer())), Some(true));
+        let mut flipped = Checksum::default();
+        flipped.update(b"paylobd");
+        assert_eq!(check.verify(flipped, Some(received.trailer())), Some(false));
+        assert_eq!(check.verify(received, None), None);
+        assert_eq!(counters.verified(), 1);
+        assert_eq!(counters.mismatches(), 1);
+    }
+}
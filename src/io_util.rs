@@ -1,8 +1,18 @@
+use crate::keepalive::IdleClock;
+use crate::relay_memory::RELAY_BUFFER_SIZE;
 /// 批量 I/O 优化模块
 ///
 /// 提供优化的批量写入操作，减少系统调用次数
+use std::future::Future;
 use std::io::{self, IoSlice};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
 use tokio::io::AsyncWriteExt;
+use tokio::time::{Instant, Sleep};
+use tracing::warn;
 
 /// 批量写入辅助函数 - 使用 write_vectored 减少系统调用
 ///
@@ -88,6 +98,287 @@ fn advance_slices<'a>(slices: &'a [IoSlice<'a>], mut n: usize) -> &'a [IoSlice<'
     &slices[slices.len()..]
 }
 
+/// 停滞诊断的默认阈值
+pub const DEFAULT_STALL_THRESHOLD: Duration = Duration::from_secs(10);
+
+/// 转发停滞检测（`debug_stalls`）
+///
+/// 只在有数据等待写出、对端却持续不接收时计时，空闲连接不算停滞。
+/// 同一位置反复停滞通常是 TLS 记录过大与路径 MTU 不匹配，可尝试调小 `max_write_chunk`
+#[derive(Debug, Clone)]
+pub struct StallDetector {
+    threshold: Duration,
+    max_write_chunk: Option<usize>,
+    stalls: Arc<AtomicU64>,
+}
+
+impl StallDetector {
+    /// 创建检测器，`max_write_chunk` 仅用于日志提示
+    pub fn new(threshold: Duration, max_write_chunk: Option<usize>) -> Self {
+        Self {
+            threshold,
+            max_write_chunk,
+            stalls: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// 按配置创建（未启用 debug_stalls 时为 None）
+    pub fn from_config(debug_stalls: bool, max_write_chunk: Option<usize>) -> Option<Self> {
+        debug_stalls.then(|| Self::new(DEFAULT_STALL_THRESHOLD, max_write_chunk))
+    }
+
+    /// 为一个连接创建监视器
+    pub fn monitor(&self, conn_id: impl Into<String>) -> StallMonitor {
+        StallMonitor {
+            detector: self.clone(),
+            conn_id: conn_id.into(),
+        }
+    }
+
+    /// 累计报告的停滞次数
+    pub fn stalls(&self) -> u64 {
+        self.stalls.load(Ordering::Relaxed)
+    }
+}
+
+/// 单个连接的停滞监视器
+#[derive(Debug, Clone)]
+pub struct StallMonitor {
+    detector: StallDetector,
+    conn_id: String,
+}
+
+impl StallMonitor {
+    /// 写出全部数据，距上次写入进展超过阈值时记录警告（每个阈值周期一次）
+    pub(crate) async fn write_all<W>(
+        &self,
+        writer: &mut W,
+        mut data: &[u8],
+        transferred: u64,
+    ) -> io::Result<()>
+    where
+        W: futures::io::AsyncWrite + Unpin,
+    {
+        use futures::io::AsyncWriteExt;
+
+        let mut written = 0u64;
+        let mut last_progress = Instant::now();
+        while !data.is_empty() {
+            match tokio::time::timeout(self.detector.threshold, writer.write(data)).await {
+                Ok(Ok(0)) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(Ok(n)) => {
+                    data = &data[n..];
+                    written += n as u64;
+                    last_progress = Instant::now();
+                }
+                Ok(Err(ref e)) if e.kind() == io::ErrorKind::Interrupted => {}
+                Ok(Err(e)) => return Err(e),
+                Err(_) => {
+                    self.detector.stalls.fetch_add(1, Ordering::Relaxed);
+                    warn!(
+                        conn_id = %self.conn_id,
+                        bytes_transferred = transferred + written,
+                        pending_bytes = data.len(),
+                        stalled_ms = last_progress.elapsed().as_millis() as u64,
+                        max_write_chunk = ?self.detector.max_write_chunk,
+                        "Relay write stalled while data is pending; if transfers repeatedly stall at the same offset, try lowering max_write_chunk"
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 转发数据并分批上报字节数
+///
+/// 每次读取的字节先累加在本地，满 `flush_bytes` 时调用一次 `on_flush`，
+/// 结束时（包括出错）上报剩余部分，避免每个数据块都写共享的统计计数器
+pub async fn copy_counted<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    flush_bytes: u64,
+    on_flush: impl FnMut(u64),
+) -> io::Result<u64>
+where
+    R: futures::io::AsyncRead + Unpin,
+    W: futures::io::AsyncWrite + Unpin,
+{
+    let mut buf = vec![0u8; RELAY_BUFFER_SIZE];
+    copy_counted_with_stall(reader, writer, &mut buf, flush_bytes, on_flush, None).await
+}
+
+/// 同 `copy_counted`，使用调用方提供的缓冲区（通常从 [`RelayMemory`] 领取），提供 `stall` 时检测写出停滞
+///
+/// [`RelayMemory`]: crate::relay_memory::RelayMemory
+pub async fn copy_counted_with_stall<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    buf: &mut [u8],
+    flush_bytes: u64,
+    mut on_flush: impl FnMut(u64),
+    stall: Option<&StallMonitor>,
+) -> io::Result<u64>
+where
+    R: futures::io::AsyncRead + Unpin,
+    W: futures::io::AsyncWrite + Unpin,
+{
+    use futures::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut pending = crate::stats::PendingBytes::new(flush_bytes);
+    let result = async {
+        let mut total = 0u64;
+        loop {
+            let n = match reader.read(buf).await {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            crate::chaos::fail("relay.transfer")?;
+            match stall {
+                Some(monitor) => monitor.write_all(writer, &buf[..n], total).await?,
+                None => writer.write_all(&buf[..n]).await?,
+            }
+            total += n as u64;
+            if let Some(batch) = pending.add(n as u64) {
+                on_flush(batch);
+            }
+        }
+        writer.flush().await?;
+        Ok(total)
+    }
+    .await;
+
+    if let Some(rest) = pending.take() {
+        on_flush(rest);
+    }
+    result
+}
+
+/// 关闭写方向之后等待对端关闭连接的期限
+pub const CLOSE_LINGER: Duration = Duration::from_secs(5);
+
+/// 转发的一个方向结束并关闭写方向之后，继续运行另一方向直到读到对端的 EOF（最多 [`CLOSE_LINGER`]）
+///
+/// 直接丢弃仍处于打开状态的 yamux stream 会向对端发送 RST，对端随后的写入失败、转发被中断；
+/// 等待期间另一方向的结果（包括出错）不再影响本次转发，超时时返回 None
+pub async fn linger<F: std::future::Future>(other: F) -> Option<F::Output> {
+    let result = tokio::time::timeout(CLOSE_LINGER, other).await.ok();
+    if result.is_none() {
+        tracing::debug!("Peer did not close within {:?}, dropping", CLOSE_LINGER);
+    }
+    result
+}
+
+/// 转发连接的空闲超时，两个方向共用
+///
+/// 任一方向读到数据都算作活动；两个方向都空闲超过超时时间后，[`IdleReader`]
+/// 包装的读取返回 `TimedOut` 错误，转发随之结束并关闭两端
+#[derive(Debug, Clone)]
+pub struct IdleTimeout {
+    timeout: Duration,
+    clock: Arc<IdleClock>,
+}
+
+impl IdleTimeout {
+    /// 创建空闲超时（从创建时开始计时）
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            clock: Arc::new(IdleClock::new()),
+        }
+    }
+}
+
+/// 记录读取活动、连接空闲超时后返回错误的读取器（同时支持 tokio 与 futures 的 `AsyncRead`）
+pub struct IdleReader<R> {
+    inner: R,
+    idle: Option<IdleTimeout>,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<R> IdleReader<R> {
+    /// 包装连接的一个读取方向（`idle` 为 None 时原样读取）
+    pub fn new(inner: R, idle: Option<&IdleTimeout>) -> Self {
+        Self {
+            inner,
+            idle: idle.cloned(),
+            sleep: None,
+        }
+    }
+
+    fn touch(&self) {
+        if let Some(idle) = &self.idle {
+            idle.clock.touch();
+        }
+    }
+
+    /// 空闲超时检查：两个方向都空闲超过超时时间时返回错误
+    fn poll_idle(&mut self, cx: &mut Context<'_>) -> Poll<io::Error> {
+        let Some(idle) = &self.idle else {
+            return Poll::Pending;
+        };
+        loop {
+            let deadline = idle.clock.last_activity() + idle.timeout;
+            if Instant::now() >= deadline {
+                return Poll::Ready(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Connection idle timeout",
+                ));
+            }
+            let sleep = self
+                .sleep
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(deadline)));
+            if sleep.deadline() != deadline {
+                sleep.as_mut().reset(deadline);
+            }
+            futures::ready!(sleep.as_mut().poll(cx));
+        }
+    }
+}
+
+impl<R: tokio::io::AsyncRead + Unpin> tokio::io::AsyncRead for IdleReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let filled = buf.filled().len();
+        match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) => {
+                if buf.filled().len() > filled {
+                    this.touch();
+                }
+                Poll::Ready(Ok(()))
+            }
+            Poll::Pending => this.poll_idle(cx).map(Err),
+            result => result,
+        }
+    }
+}
+
+impl<R: futures::io::AsyncRead + Unpin> futures::io::AsyncRead for IdleReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(n)) => {
+                if n > 0 {
+                    this.touch();
+                }
+                Poll::Ready(Ok(n))
+            }
+            Poll::Pending => this.poll_idle(cx).map(Err),
+            result => result,
+        }
+    }
+}
+
 /// 使用预分配缓冲区避免频繁分配
 ///
 /// # 示例
@@ -190,6 +481,40 @@ impl AsMut<[u8]> for VecBuffer {
 mod tests {
     use super::*;
 
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_reader_shared_timeout() {
+        use tokio::io::AsyncReadExt;
+
+        let idle = IdleTimeout::new(Duration::from_secs(10));
+        let (mut a_peer, a) = tokio::io::duplex(64);
+        let (_b_peer, b) = tokio::io::duplex(64);
+        let mut a = IdleReader::new(a, Some(&idle));
+        let mut b = IdleReader::new(b, Some(&idle));
+        let mut buf = [0u8; 8];
+
+        // 一个方向上的数据让另一方向同样保持活跃
+        tokio::time::sleep(Duration::from_secs(8)).await;
+        a_peer.write_all(b"ping").await.unwrap();
+        assert_eq!(a.read(&mut buf).await.unwrap(), 4);
+        let started = Instant::now();
+        let err = b.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(started.elapsed(), Duration::from_secs(10));
+        assert_eq!(
+            a.read(&mut buf).await.unwrap_err().kind(),
+            io::ErrorKind::TimedOut
+        );
+
+        // 未配置超时时原样读取
+        let (_peer, c) = tokio::io::duplex(64);
+        let mut c = IdleReader::new(c, None);
+        assert!(
+            tokio::time::timeout(Duration::from_secs(3600), c.read(&mut buf))
+                .await
+                .is_err()
+        );
+    }
+
     #[tokio::test]
     async fn test_write_vectored_all() {
         let mut buffer = Vec::new();
@@ -280,6 +605,46 @@ mod tests {
         assert_eq!(buf.as_slice(), &[1, 2, 3, 4, 5]);
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn test_stall_detector_fires_on_paused_reader() {
+        use tokio_util::compat::TokioAsyncWriteCompatExt;
+
+        let detector = StallDetector::new(Duration::from_secs(2), Some(4096));
+        let monitor = detector.monitor("web#127.0.0.1:50000");
+        let (writer, mut paused_reader) = tokio::io::duplex(1024);
+        let mut writer = writer.compat_write();
+        let data = vec![7u8; 8192];
+        let mut source = futures::io::AllowStdIo::new(&data[..]);
+
+        let mut buf = vec![0u8; RELAY_BUFFER_SIZE];
+        let copy = copy_counted_with_stall(
+            &mut source,
+            &mut writer,
+            &mut buf,
+            u64::MAX,
+            |_| {},
+            Some(&monitor),
+        );
+        tokio::pin!(copy);
+
+        // 对端不读取：写出停滞超过阈值后报告
+        assert!(tokio::time::timeout(Duration::from_secs(5), &mut copy)
+            .await
+            .is_err());
+        assert_eq!(detector.stalls(), 2);
+
+        // 对端恢复读取后完成转发，不再报告
+        let mut received = vec![0u8; 8192];
+        let (copied, read) = tokio::join!(
+            &mut copy,
+            tokio::io::AsyncReadExt::read_exact(&mut paused_reader, &mut received)
+        );
+        let copied = copied.unwrap();
+        assert_eq!(read.unwrap(), 8192);
+        assert_eq!(copied, 8192);
+        assert_eq!(detector.stalls(), 2);
+    }
+
     #[test]
     fn test_vec_buffer_default() {
         let buf = VecBuffer::default();
@@ -0,0 +1,565 @@
+/// 代理 stream 的分帧与空闲保活
+///
+/// 配置了 `idle_keepalive_secs` 的代理，服务器与客户端之间的 stream 在前导之后按帧传输：每帧是
+/// u16 负载长度（大端）加负载，负载长度为 0 的帧是保活标记。转发的连接在间隔内两个方向都没有
+/// 数据时，两端各自向 stream 写入标记，使承载 stream 的隧道连接保持活跃；接收方丢弃标记，
+/// 外部连接和本地服务收到的数据与不分帧时完全相同。外部连接和到本地服务的连接上
+/// 不注入任何字节。开启了完整性校验时（见 [`crate::integrity`]），发送方在结束前写入尾帧
+use crate::integrity::{Checksum, IntegrityCheck};
+use crate::io_util::{copy_counted_with_stall, StallMonitor};
+use crate::protocol::{IntegrityTrailer, INTEGRITY_TRAILER_MARKER};
+use futures::io::{AsyncRead, AsyncWrite};
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 帧头长度（u16 负载长度）
+pub const FRAME_HEADER_LEN: usize = 2;
+
+/// 每帧的最大负载
+pub const MAX_FRAME_PAYLOAD: usize = 32 * 1024;
+
+/// 保活标记（负载长度为 0 的帧）
+pub const KEEPALIVE_MARKER: [u8; FRAME_HEADER_LEN] = [0, 0];
+
+/// 转发的连接最近一次传输数据的时间（两个方向共享，保活标记不算作数据）
+#[derive(Debug)]
+pub(crate) struct IdleClock {
+    start: Instant,
+    /// 最近一次传输数据距 `start` 的毫秒数
+    last_activity_ms: AtomicU64,
+}
+
+impl IdleClock {
+    pub(crate) fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            last_activity_ms: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn touch(&self) {
+        let elapsed = self.start.elapsed().as_millis() as u64;
+        self.last_activity_ms.fetch_max(elapsed, Ordering::Relaxed);
+    }
+
+    pub(crate) fn last_activity(&self) -> Instant {
+        self.start + Duration::from_millis(self.last_activity_ms.load(Ordering::Relaxed))
+    }
+}
+
+/// 一个转发连接的分帧设置，两个方向共用
+#[derive(Debug, Clone)]
+pub struct StreamFraming {
+    /// 发送保活标记的空闲间隔（None 时只分帧，不主动发送标记）
+    interval: Option<Duration>,
+    clock: Arc<IdleClock>,
+    /// 完整性校验（未开启时转发循环不计算校验和）
+    integrity: Option<Arc<IntegrityCheck>>,
+}
+
+impl StreamFraming {
+    /// 创建分帧设置
+    pub fn new(interval: Option<Duration>) -> Self {
+        Self {
+            interval,
+            clock: Arc::new(IdleClock::new()),
+            integrity: None,
+        }
+    }
+
+    /// 两个方向都进行完整性校验
+    pub fn with_integrity(mut self, check: Arc<IntegrityCheck>) -> Self {
+        self.integrity = Some(check);
+        self
+    }
+
+    /// 完整性校验（未开启时为 None）
+    pub fn integrity(&self) -> Option<&Arc<IntegrityCheck>> {
+        self.integrity.as_ref()
+    }
+}
+
+/// 接收方向的完整性校验状态
+struct ReceiveCheck {
+    check: Arc<IntegrityCheck>,
+    received: Checksum,
+    trailer: [u8; IntegrityTrailer::ENCODED_LEN],
+    /// 正在读取的尾帧已读到的字节数（不在尾帧中时为 None）
+    trailer_filled: Option<usize>,
+    /// 对端发送的尾帧
+    peer: Option<IntegrityTrailer>,
+    finished: bool,
+}
+
+impl ReceiveCheck {
+    /// stream 在帧边界上结束：与对端的尾帧比较（只比较一次）
+    fn finish(&mut self) {
+        if !std::mem::replace(&mut self.finished, true) {
+            self.check.verify(self.received, self.peer.take());
+        }
+    }
+}
+
+/// 从 stream 读取转发数据的读取器：分帧时输出各帧的负载并丢弃保活标记，否则原样读取
+pub struct FrameReader<R> {
+    inner: R,
+    /// 分帧时的连接活动时钟（未分帧时为 None）
+    clock: Option<Arc<IdleClock>>,
+    header: [u8; FRAME_HEADER_LEN],
+    header_filled: usize,
+    /// 当前帧尚未读取的负载字节数
+    remaining: usize,
+    markers: u64,
+    integrity: Option<ReceiveCheck>,
+}
+
+impl<R> FrameReader<R> {
+    /// 创建读取器（`framing` 为 None 时不分帧）
+    pub fn new(inner: R, framing: Option<&StreamFraming>) -> Self {
+        Self {
+            inner,
+            clock: framing.map(|f| f.clock.clone()),
+            header: [0; FRAME_HEADER_LEN],
+            header_filled: 0,
+            remaining: 0,
+            markers: 0,
+            integrity: framing
+                .and_then(|f| f.integrity.clone())
+                .map(|check| ReceiveCheck {
+                    check,
+                    received: Checksum::default(),
+                    trailer: [0; IntegrityTrailer::ENCODED_LEN],
+                    trailer_filled: None,
+                    peer: None,
+                    finished: false,
+                }),
+        }
+    }
+
+    /// 已收到并丢弃的保活标记数
+    pub fn markers(&self) -> u64 {
+        self.markers
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for FrameReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let Some(clock) = &this.clock else {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        };
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        loop {
+            if let Some(receive) = &mut this.integrity {
+                if let Some(filled) = receive.trailer_filled {
+                    let n = futures::ready!(
+                        Pin::new(&mut this.inner).poll_read(cx, &mut receive.trailer[filled..])
+                    )?;
+                    if n == 0 {
+                        return Poll::Ready(Err(truncated_frame()));
+                    }
+                    if filled + n < IntegrityTrailer::ENCODED_LEN {
+                        receive.trailer_filled = Some(filled + n);
+                    } else {
+                        receive.trailer_filled = None;
+                        receive.peer = Some(IntegrityTrailer::decode(&receive.trailer));
+                    }
+                    continue;
+                }
+            }
+
+            if this.remaining > 0 {
+                let len = buf.len().min(this.remaining);
+                let n = futures::ready!(Pin::new(&mut this.inner).poll_read(cx, &mut buf[..len]))?;
+                if n == 0 {
+                    return Poll::Ready(Err(truncated_frame()));
+                }
+                this.remaining -= n;
+                clock.touch();
+                if let Some(receive) = &mut this.integrity {
+                    receive.received.update(&buf[..n]);
+                }
+                return Poll::Ready(Ok(n));
+            }
+
+            let n = futures::ready!(
+                Pin::new(&mut this.inner).poll_read(cx, &mut this.header[this.header_filled..])
+            )?;
+            if n == 0 {
+                // 只在帧边界上结束
+                if this.header_filled != 0 {
+                    return Poll::Ready(Err(truncated_frame()));
+                }
+                if let Some(receive) = &mut this.integrity {
+                    receive.finish();
+                }
+                return Poll::Ready(Ok(0));
+            }
+            this.header_filled += n;
+            if this.header_filled < FRAME_HEADER_LEN {
+                continue;
+            }
+            this.header_filled = 0;
+            let len = u16::from_be_bytes(this.header);
+            if let (INTEGRITY_TRAILER_MARKER, Some(receive)) = (len, &mut this.integrity) {
+                receive.trailer_filled = Some(0);
+                continue;
+            }
+            this.remaining = len as usize;
+            if this.remaining == 0 {
+                this.markers += 1;
+            }
+        }
+    }
+}
+
+fn truncated_frame() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "Stream closed in the middle of a frame",
+    )
+}
+
+/// 把数据分帧写入 stream（数据为空时不写入，避免被当作保活标记）
+pub async fn write_frames<W>(writer: &mut W, data: &[u8]) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    use futures::io::AsyncWriteExt;
+
+    for chunk in data.chunks(MAX_FRAME_PAYLOAD) {
+        writer
+            .write_all(&(chunk.len() as u16).to_be_bytes())
+            .await?;
+        writer.write_all(chunk).await?;
+    }
+    Ok(())
+}
+
+/// 把本地连接的数据转发到 stream，并分批上报转发的字节数
+///
+/// 分帧时每次读取的数据作为一帧写出，连接空闲超过间隔时写入保活标记（此后每个间隔一次，
+/// 直到任一方向恢复传输），开启了完整性校验时读到 EOF 后写入尾帧；上报的只是负载字节数。每帧的负载不超过 `buf` 去掉帧头后的长度。
+/// `framing` 为 None 时与 [`copy_counted_with_stall`] 相同
+pub async fn copy_to_stream<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    buf: &mut [u8],
+    framing: Option<&StreamFraming>,
+    flush_bytes: u64,
+    mut on_flush: impl FnMut(u64),
+    stall: Option<&StallMonitor>,
+) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    use futures::io::{AsyncReadExt, AsyncWriteExt};
+
+    let Some(framing) = framing else {
+        return copy_counted_with_stall(reader, writer, buf, flush_bytes, on_flush, stall).await;
+    };
+    let clock = &framing.clock;
+
+    let mut pending = crate::stats::PendingBytes::new(flush_bytes);
+    let frame_len = buf.len().min(FRAME_HEADER_LEN + MAX_FRAME_PAYLOAD);
+    let buf = &mut buf[..frame_len];
+    let result = async {
+        let mut total = 0u64;
+        let mut last_marker = Instant::now();
+        loop {
+            let read = loop {
+                let Some(interval) = framing.interval else {
+                    break reader.read(&mut buf[FRAME_HEADER_LEN..]).await;
+                };
+                let deadline = clock.last_activity().max(last_marker) + interval;
+                tokio::select! {
+                    result = reader.read(&mut buf[FRAME_HEADER_LEN..]) => break result,
+                    _ = tokio::time::sleep_until(deadline) => {
+                        // 等待期间另一方向可能传输了数据
+                        if clock.last_activity() + interval <= Instant::now() {
+                            write_all(writer, &KEEPALIVE_MARKER, total, stall).await?;
+                            writer.flush().await?;
+                            last_marker = Instant::now();
+                        }
+                    }
+                }
+            };
+            let n = match read {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            crate::chaos::fail("relay.transfer")?;
+            clock.touch();
+            if let Some(check) = &framing.integrity {
+                check.record_sent(&buf[FRAME_HEADER_LEN..FRAME_HEADER_LEN + n]);
+            }
+            crate::chaos::corrupt(
+                "relay.corrupt",
+                &mut buf[FRAME_HEADER_LEN..FRAME_HEADER_LEN + n],
+            );
+            buf[..FRAME_HEADER_LEN].copy_from_slice(&(n as u16).to_be_bytes());
+            write_all(writer, &buf[..FRAME_HEADER_LEN + n], total, stall).await?;
+            total += n as u64;
+            if let Some(batch) = pending.add(n as u64) {
+                on_flush(batch);
+            }
+        }
+        if let Some(check) = &framing.integrity {
+            write_all(writer, &check.trailer_frame(), total, stall).await?;
+        }
+        writer.flush().await?;
+        Ok(total)
+    }
+    .await;
+
+    if let Some(rest) = pending.take() {
+        on_flush(rest);
+    }
+    result
+}
+
+async fn write_all<W>(
+    writer: &mut W,
+    data: &[u8],
+    transferred: u64,
+    stall: Option<&StallMonitor>,
+) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    use futures::io::AsyncWriteExt;
+
+    match stall {
+        Some(monitor) => monitor.write_all(writer, data, transferred).await,
+        None => writer.write_all(data).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio_util::compat::TokioAsyncReadCompatExt;
+
+    const INTERVAL: Duration = Duration::from_millis(100);
+
+    /// 解析原始帧流，返回负载和每个保活标记之前已收到的负载字节数
+    fn parse_frames(mut raw: &[u8]) -> (Vec<u8>, Vec<usize>) {
+        let mut payload = Vec::new();
+        let mut markers = Vec::new();
+        while !raw.is_empty() {
+            let len = u16::from_be_bytes([raw[0], raw[1]]) as usize;
+            if len == 0 {
+                markers.push(payload.len());
+            }
+            payload.extend_from_slice(&raw[2..2 + len]);
+            raw = &raw[2 + len..];
+        }
+        (payload, markers)
+    }
+
+    #[tokio::test]
+    async fn test_frame_reader_skips_markers_and_split_headers() {
+        let mut raw = Vec::new();
+        raw.extend_from_slice(&KEEPALIVE_MARKER);
+        raw.extend_from_slice(&[0, 3]);
+        raw.extend_from_slice(b"abc");
+        raw.extend_from_slice(&KEEPALIVE_MARKER);
+        raw.extend_from_slice(&KEEPALIVE_MARKER);
+        raw.extend_from_slice(&[0, 2]);
+        raw.extend_from_slice(b"de");
+
+        // 每次只交付一个字节，帧头被拆开
+        let (mut tx, rx) = tokio::io::duplex(1);
+        let writer = tokio::spawn(async move {
+            tx.write_all(&raw).await.unwrap();
+        });
+        let framing = StreamFraming::new(None);
+        let mut reader = FrameReader::new(rx.compat(), Some(&framing));
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).await.unwrap();
+        writer.await.unwrap();
+
+        assert_eq!(out, b"abcde");
+        assert_eq!(reader.markers(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_frame_reader_rejects_truncated_frame() {
+        let raw = [0u8, 5, b'a', b'b'];
+        let framing = StreamFraming::new(None);
+        let mut reader = FrameReader::new(&raw[..], Some(&framing));
+        let mut out = Vec::new();
+        let err = reader.read_to_end(&mut out).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn test_unframed_reader_passes_data_through() {
+        let raw = [0u8, 0, 0, 3];
+        let mut reader = FrameReader::new(&raw[..], None);
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, raw);
+        assert_eq!(reader.markers(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_markers_only_while_idle() {
+        let framing = StreamFraming::new(Some(INTERVAL));
+        let (mut local, relay_side) = tokio::io::duplex(64 * 1024);
+        let source = tokio::spawn(async move {
+            local.write_all(b"hello").await.unwrap();
+            // 空闲：发送保活标记
+            tokio::time::sleep(INTERVAL * 7 / 2).await;
+            // 恢复传输：间隔远小于保活间隔，不再发送标记
+            for i in 0..40u8 {
+                local.write_all(&[i; 100]).await.unwrap();
+                tokio::time::sleep(INTERVAL / 10).await;
+            }
+            local.shutdown().await.unwrap();
+        });
+
+        let mut reader = relay_side.compat();
+        let mut raw = Vec::new();
+        let mut received = 0;
+        let copied = copy_to_stream(
+            &mut reader,
+            &mut raw,
+            &mut [0u8; 4096],
+            Some(&framing),
+            1,
+            |bytes| received += bytes,
+            None,
+        )
+        .await
+        .unwrap();
+        source.await.unwrap();
+
+        let mut expected = b"hello".to_vec();
+        for i in 0..40u8 {
+            expected.extend_from_slice(&[i; 100]);
+        }
+        let (payload, markers) = parse_frames(&raw);
+        assert_eq!(payload, expected);
+        assert_eq!(copied, expected.len() as u64);
+        assert_eq!(received, expected.len() as u64);
+        assert!(markers.len() >= 2, "markers: {:?}", markers);
+        // 所有标记都在数据恢复之前
+        assert!(markers.iter().all(|&at| at == 5), "markers: {:?}", markers);
+    }
+
+    #[tokio::test]
+    async fn test_activity_in_other_direction_suppresses_markers() {
+        let framing = StreamFraming::new(Some(INTERVAL));
+        let (mut local, relay_side) = tokio::io::duplex(1024);
+
+        // 另一方向持续有数据：本方向虽然空闲也不发送标记
+        let (mut remote, tunnel_side) = tokio::io::duplex(64 * 1024);
+        let download = {
+            let framing = framing.clone();
+            tokio::spawn(async move {
+                let mut reader = FrameReader::new(tunnel_side.compat(), Some(&framing));
+                let mut out = Vec::new();
+                reader.read_to_end(&mut out).await.unwrap();
+                out.len()
+            })
+        };
+        let upload = tokio::spawn(async move {
+            for _ in 0..30 {
+                let mut frame = Vec::new();
+                write_frames(&mut frame, b"tick").await.unwrap();
+                remote.write_all(&frame).await.unwrap();
+                tokio::time::sleep(INTERVAL / 10).await;
+            }
+            remote.shutdown().await.unwrap();
+            local.shutdown().await.unwrap();
+        });
+
+        let mut reader = relay_side.compat();
+        let mut raw = Vec::new();
+        copy_to_stream(
+            &mut reader,
+            &mut raw,
+            &mut [0u8; 4096],
+            Some(&framing),
+            1,
+            |_| {},
+            None,
+        )
+        .await
+        .unwrap();
+        upload.await.unwrap();
+        assert_eq!(download.await.unwrap(), 30 * 4);
+        assert!(parse_frames(&raw).1.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_integrity_trailer_round_trip() {
+        use crate::integrity::{Direction, IntegrityCounters};
+
+        let sender_counters = IntegrityCounters::default();
+        let sender = StreamFraming::new(None).with_integrity(IntegrityCheck::new(
+            "web#1".to_string(),
+            Direction::ServerToClient,
+            sender_counters,
+        ));
+        let data: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+        let mut raw = Vec::new();
+        copy_to_stream(
+            &mut &data[..],
+            &mut raw,
+            &mut [0u8; 4096],
+            Some(&sender),
+            1,
+            |_| {},
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            &raw[raw.len() - 2 - IntegrityTrailer::ENCODED_LEN..][..2],
+            &INTEGRITY_TRAILER_MARKER.to_be_bytes()
+        );
+
+        let receive = |raw: Vec<u8>| async move {
+            let counters = IntegrityCounters::default();
+            let framing = StreamFraming::new(None).with_integrity(IntegrityCheck::new(
+                "web#1".to_string(),
+                Direction::ClientToServer,
+                counters.clone(),
+            ));
+            let mut reader = FrameReader::new(&raw[..], Some(&framing));
+            let mut out = Vec::new();
+            reader.read_to_end(&mut out).await.unwrap();
+            (out, counters)
+        };
+
+        // 尾帧不出现在转发的数据中
+        let (out, counters) = receive(raw.clone()).await;
+        assert_eq!(out, data);
+        assert_eq!((counters.verified(), counters.mismatches()), (1, 0));
+
+        // 隧道内翻转一个字节
+        let mut corrupted = raw;
+        corrupted[FRAME_HEADER_LEN + 100] ^= 0x01;
+        let (_, counters) = receive(corrupted).await;
+        assert_eq!((counters.verified(), counters.mismatches()), (0, 1));
+    }
+}
@@ -1,21 +1,40 @@
 /// TLS Tunnel 库入口
 ///
 /// 将核心模块导出为库，方便测试和复用
+pub mod accept;
+pub mod chaos;
 pub mod cli;
 pub mod client;
 pub mod config;
 pub mod connection_pool;
 pub mod control_protocol;
+pub mod datagram;
 pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod identity;
+pub mod integrity;
 pub mod io_util;
+pub mod keepalive;
 pub mod limited_reader;
+pub mod listen_addr;
 pub mod protocol;
+pub mod protocol_trace;
 pub mod rate_limiter;
+pub mod relay_memory;
 pub mod server;
+pub mod shutdown;
+pub mod socket_options;
 pub mod stats;
+pub mod stats_http;
+pub mod stats_ws;
+pub mod systemd;
+pub mod target_addr;
 pub mod tls;
 pub mod top;
+pub mod traffic;
 pub mod transport;
+pub mod yamux_driver;
 
 // 重新导出常用类型
 pub use client::{ForwarderHandler, HandlerStatus, ProxyHandler, ProxyManager, VisitorHandler};
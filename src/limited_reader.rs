@@ -89,27 +89,27 @@ impl<R: AsyncRead + Unpin> AsyncRead for LimitedReader<R> {
             ))));
         }
 
-        //记录当前已填充的字节数
-        let before = buf.filled().len();
-
-        // 从内部读取器读取数据，但限制读取量
-        let result = {
-            // 临时限制缓冲区容量
-            let limit = self.remaining.min(buf.remaining());
-            let mut limited = buf.take(limit);
-            Pin::new(&mut self.inner).poll_read(cx, &mut limited)
-        };
-
-        // 更新剩余字节数
-        match result {
-            Poll::Ready(Ok(())) => {
-                let after = buf.filled().len();
-                let read = after - before;
+        // 缓冲区不超过剩余额度时直接读取
+        if buf.remaining() <= self.remaining {
+            let before = buf.filled().len();
+            let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+            if let Poll::Ready(Ok(())) = result {
+                let read = buf.filled().len() - before;
                 self.remaining = self.remaining.saturating_sub(read);
-                Poll::Ready(Ok(()))
             }
-            other => other,
+            return result;
+        }
+
+        // 否则读取到临时缓冲区，再复制到调用方缓冲区（take() 得到的子缓冲区不会推进 filled）
+        let mut tmp = vec![0u8; self.remaining];
+        let mut limited = ReadBuf::new(&mut tmp);
+        let result = Pin::new(&mut self.inner).poll_read(cx, &mut limited);
+        if let Poll::Ready(Ok(())) = result {
+            let read = limited.filled().len();
+            buf.put_slice(limited.filled());
+            self.remaining = self.remaining.saturating_sub(read);
         }
+        result
     }
 }
 
@@ -188,6 +188,25 @@ mod tests {
         assert_eq!(limited.limit(), 200);
     }
 
+    #[tokio::test]
+    async fn test_limited_reader_reads_data() {
+        use tokio::io::AsyncReadExt;
+
+        let data = b"hello world";
+        let mut limited = LimitedReader::new(&data[..], 5);
+        let mut buf = [0u8; 16];
+        let n = limited.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"hello");
+        assert_eq!(limited.remaining(), 0);
+        assert!(limited.read(&mut buf).await.is_err());
+
+        let mut limited = LimitedReader::new(&data[..], 100);
+        let mut out = Vec::new();
+        limited.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, data);
+        assert_eq!(limited.read_count(), data.len());
+    }
+
     #[test]
     fn test_limited_reader_constants() {
         assert_eq!(DEFAULT_MAX_REQUEST_SIZE, 1024 * 1024);
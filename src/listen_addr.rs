@@ -0,0 +1,141 @@
+/// 监听地址
+///
+/// 配置中的监听主机（代理的 `publish_addr`、visitor/forwarder/visitor_gateway 的 `bind_addr`）
+/// 可以是 IPv4、带或不带方括号的 IPv6 字面量（`::1`、`[::1]`）或主机名。IP 字面量直接构造
+/// `SocketAddr` 绑定，而不是拼接 `host:port` 字符串；绑定 IPv6 通配地址 `::` 时关闭
+/// `IPV6_V6ONLY`，在系统允许时同时接受 IPv4 连接（双栈）
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use tokio::net::{TcpListener, UdpSocket};
+use tracing::warn;
+
+/// 监听队列长度（与 tokio 的 `TcpListener::bind` 相同）
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// 去掉 IPv6 字面量两侧的方括号
+pub fn strip_brackets(host: &str) -> &str {
+    let host = host.trim();
+    host.strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .unwrap_or(host)
+}
+
+/// 解析 IP 字面量（接受带方括号的 IPv6），主机名返回 None
+pub fn parse_ip(host: &str) -> Option<IpAddr> {
+    strip_brackets(host).parse().ok()
+}
+
+/// 规范化的主机：IP 字面量统一为标准写法（`[::1]`、`0:0::1` 都变为 `::1`），主机名不变
+///
+/// 用于比较两个监听地址是否相同
+pub fn normalize_host(host: &str) -> String {
+    match parse_ip(host) {
+        Some(ip) => ip.to_string(),
+        None => host.trim().to_string(),
+    }
+}
+
+/// `host:port`（IPv6 带方括号，如 `[::1]:8080`；IP 字面量为标准写法，可以用于比较）
+pub fn format(host: &str, port: u16) -> String {
+    match parse_ip(host) {
+        Some(ip) => SocketAddr::new(ip, port).to_string(),
+        None => format!("{}:{}", host.trim(), port),
+    }
+}
+
+/// 绑定 TCP 监听地址
+///
+/// IP 字面量按 `SocketAddr` 绑定（`::` 为双栈），主机名由系统解析后绑定第一个可用的地址
+pub async fn bind(host: &str, port: u16) -> io::Result<TcpListener> {
+    match parse_ip(host) {
+        Some(IpAddr::V6(ip)) if ip.is_unspecified() => {
+            bind_dual_stack(SocketAddr::new(IpAddr::V6(ip), port))
+        }
+        Some(ip) => TcpListener::bind(SocketAddr::new(ip, port)).await,
+        None => TcpListener::bind((host.trim(), port)).await,
+    }
+}
+
+/// 绑定 UDP 地址（与 [`bind`] 相同的地址规则，`::` 为双栈）
+pub async fn bind_udp(host: &str, port: u16) -> io::Result<UdpSocket> {
+    match parse_ip(host) {
+        Some(IpAddr::V6(ip)) if ip.is_unspecified() => {
+            let addr = SocketAddr::new(IpAddr::V6(ip), port);
+            let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+            if let Err(e) = socket.set_only_v6(false) {
+                warn!(
+                    "Cannot enable dual-stack on {}, receiving IPv6 datagrams only: {}",
+                    addr, e
+                );
+            }
+            socket.set_nonblocking(true)?;
+            socket.bind(&addr.into())?;
+            UdpSocket::from_std(socket.into())
+        }
+        Some(ip) => UdpSocket::bind(SocketAddr::new(ip, port)).await,
+        None => UdpSocket::bind((host.trim(), port)).await,
+    }
+}
+
+/// 在 IPv6 通配地址上监听，关闭 `IPV6_V6ONLY` 以同时接受 IPv4 连接
+///
+/// 系统不允许关闭时（例如 OpenBSD 或禁用了双栈）记录警告，只接受 IPv6 连接
+fn bind_dual_stack(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::IPV6, Type::STREAM, Some(Protocol::TCP))?;
+    if let Err(e) = socket.set_only_v6(false) {
+        warn!(
+            "Cannot enable dual-stack on {}, accepting IPv6 connections only: {}",
+            addr, e
+        );
+    }
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+    TcpListener::from_std(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ipv6_literals_with_and_without_brackets() {
+        assert_eq!(parse_ip("[::1]"), parse_ip("::1"));
+        assert_eq!(
+            parse_ip("2001:db8::1"),
+            Some("2001:db8::1".parse().unwrap())
+        );
+        assert_eq!(parse_ip("localhost"), None);
+        assert_eq!(normalize_host("[0:0::1]"), "::1");
+        assert_eq!(normalize_host("example.com"), "example.com");
+        assert_eq!(format("[::1]", 8080), "[::1]:8080");
+        assert_eq!(format("::", 80), "[::]:80");
+        assert_eq!(format("127.0.0.1", 80), "127.0.0.1:80");
+        assert_eq!(format("localhost", 80), "localhost:80");
+    }
+
+    #[tokio::test]
+    async fn test_unspecified_ipv6_accepts_both_families() {
+        let Ok(listener) = bind("::", 0).await else {
+            // 没有 IPv6 的环境
+            return;
+        };
+        let port = listener.local_addr().unwrap().port();
+        assert!(listener.local_addr().unwrap().is_ipv6());
+        if tokio::net::TcpStream::connect(("::1", port)).await.is_ok() {
+            listener.accept().await.unwrap();
+        }
+        let v4 = tokio::net::TcpStream::connect(("127.0.0.1", port)).await;
+        if socket2::SockRef::from(&listener).only_v6().unwrap() {
+            assert!(v4.is_err());
+        } else {
+            v4.unwrap();
+            let (_, peer) = listener.accept().await.unwrap();
+            // IPv4 连接以映射地址（::ffff:127.0.0.1）出现
+            assert!(peer.is_ipv6());
+        }
+    }
+}
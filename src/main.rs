@@ -20,9 +20,7 @@ async fn main() -> Result<()> {
         .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(default_log_level));
 
     // 检测是否在 systemd 环境中运行
-    // systemd 会设置 INVOCATION_ID 或 JOURNAL_STREAM 环境变量
-    let is_systemd =
-        std::env::var("INVOCATION_ID").is_ok() || std::env::var("JOURNAL_STREAM").is_ok();
+    let is_systemd = tls_tunnel::systemd::is_systemd();
 
     let fmt_layer = tracing_subscriber::fmt()
         .with_env_filter(env_filter)
@@ -41,8 +39,11 @@ async fn main() -> Result<()> {
     // Display version information
     info!("TLS Tunnel v{}", env!("CARGO_PKG_VERSION"));
 
-    // Execute command
-    cli::execute_command(&cli).await?;
+    // Execute command; certificate checks use dedicated exit codes for scripting
+    if let Err(e) = cli::execute_command(&cli).await {
+        eprintln!("Error: {:?}", e);
+        std::process::exit(cli::cert::exit_code(&e));
+    }
 
     Ok(())
 }
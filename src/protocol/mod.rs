@@ -0,0 +1,421 @@
+/// 客户端与服务器之间的协议消息定义
+///
+/// 控制通道的 JSON-RPC 消息定义在 [`crate::control_protocol`]，stream 前导、确认字节和错误消息帧
+/// 定义在本模块；[`spec`] 汇总两者的版本化规范，供第三方实现兼容的客户端
+use serde::{Deserialize, Serialize};
+
+pub mod spec;
+
+/// stream 前导（u16 长度 + 名称 + u16 publish_port）中名称的最大字节数
+pub const MAX_STREAM_NAME_LEN: usize = 255;
+
+/// forwarder stream 前导的名称前缀，后接目标地址（`@forward:host:port`）
+pub const FORWARD_NAME_PREFIX: &str = "@forward:";
+
+/// forward 目标被服务器的 `forward_policy` 拒绝时，错误消息开头的代码
+pub const FORWARD_FORBIDDEN: &str = "FORWARD_FORBIDDEN";
+
+/// visitor stream 前导之后服务器回复的确认字节：接受
+pub const CONFIRM_ACCEPTED: u8 = 1;
+
+/// visitor stream 前导之后服务器回复的确认字节：拒绝（其后紧跟错误消息帧）
+pub const CONFIRM_REJECTED: u8 = 0;
+
+/// 错误消息帧（u16 长度 + UTF-8 文本）中文本的最大字节数
+pub const MAX_ERROR_MESSAGE_SIZE: usize = 4096;
+
+/// 客户端打开 stream 后发送的前导：u16 名称长度 + 名称 + u16 publish_port
+///
+/// 名称为 visitor 要访问的代理名称，或以 [`FORWARD_NAME_PREFIX`]、[`VISITOR_MUX_NAME_PREFIX`]
+/// 开头的特殊名称
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamPreamble {
+    pub name: String,
+    pub publish_port: u16,
+}
+
+impl StreamPreamble {
+    /// 编码为前导字节（名称长度由调用方检查，不超过 [`MAX_STREAM_NAME_LEN`]）
+    pub fn encode(&self) -> Vec<u8> {
+        let name = self.name.as_bytes();
+        let mut buf = Vec::with_capacity(2 + name.len() + 2);
+        buf.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        buf.extend_from_slice(name);
+        buf.extend_from_slice(&self.publish_port.to_be_bytes());
+        buf
+    }
+}
+
+/// 编码错误消息帧：u16 长度 + UTF-8 文本（超过 [`MAX_ERROR_MESSAGE_SIZE`] 时在字符边界截断）
+pub fn encode_error_message(message: &str) -> Vec<u8> {
+    let mut end = message.len().min(MAX_ERROR_MESSAGE_SIZE);
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut buf = Vec::with_capacity(2 + end);
+    buf.extend_from_slice(&(end as u16).to_be_bytes());
+    buf.extend_from_slice(&message.as_bytes()[..end]);
+    buf
+}
+
+/// UDP 代理 stream 上数据报帧（u16 长度 + 负载）中负载的最大字节数
+pub const MAX_DATAGRAM_SIZE: usize = u16::MAX as usize;
+
+/// 编码 UDP 代理 stream 上的数据报帧：u16 长度 + 负载（负载长度由调用方检查，不超过
+/// [`MAX_DATAGRAM_SIZE`]；长度为 0 的帧是空数据报）
+pub fn encode_datagram(payload: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(2 + payload.len());
+    buf.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    buf.extend_from_slice(payload);
+    buf
+}
+
+/// 服务器打开的代理 stream 的头部（外部连接接入后发往注册代理的客户端）
+///
+/// 普通代理只发送 publish_port；分帧传输或携带外部连接信息时以标记开头，
+/// SNI 路由代理在最后附加选中的本地端口
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProxyStreamHeader {
+    pub publish_port: u16,
+    /// 之后的数据按帧传输（代理配置了 `idle_keepalive_secs`）
+    pub framed: bool,
+    /// 外部连接的信息（客户端声明了 peer_report 能力且代理开启了 `report_peers`）
+    pub peer: Option<PeerInfo>,
+    /// SNI 路由选中的本地端口
+    pub sni_local_port: Option<u16>,
+    /// 两端交换完整性校验尾帧（代理开启了 `integrity_check`，要求分帧并携带外部连接信息）
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub integrity: bool,
+}
+
+impl ProxyStreamHeader {
+    /// 编码为头部字节
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        if self.framed || self.peer.is_some() {
+            buf.extend_from_slice(&VISITOR_MUX_STREAM_MARKER.to_be_bytes());
+            buf.extend_from_slice(&FRAMED_STREAM_MARKER.to_be_bytes());
+        }
+        match &self.peer {
+            Some(peer) => {
+                let mut flags = 0;
+                if self.framed {
+                    flags |= PEER_INFO_FLAG_FRAMED;
+                }
+                if self.integrity {
+                    flags |= PEER_INFO_FLAG_INTEGRITY;
+                }
+                buf.extend_from_slice(&PEER_INFO_STREAM_MARKER.to_be_bytes());
+                buf.push(flags);
+                buf.extend_from_slice(&self.publish_port.to_be_bytes());
+                buf.extend_from_slice(&peer.encode());
+            }
+            None => buf.extend_from_slice(&self.publish_port.to_be_bytes()),
+        }
+        if let Some(port) = self.sni_local_port {
+            buf.extend_from_slice(&port.to_be_bytes());
+        }
+        buf
+    }
+}
+
+/// 复用模式 visitor stream 前导的名称前缀，后接目标 proxy 名称（`@mux:name`）
+pub const VISITOR_MUX_NAME_PREFIX: &str = "@mux:";
+
+/// 服务器发往 proxy 客户端的 stream 以该值代替 publish_port 时，表示这是复用模式的
+/// visitor stream，其后紧跟真实的 publish_port（代理的 publish_port 不会为 0）
+pub const VISITOR_MUX_STREAM_MARKER: u16 = 0;
+
+/// 复用模式标记之后再以该值代替 publish_port 时，表示这是分帧传输的代理 stream（配置了
+/// `idle_keepalive_secs`），其后紧跟真实的 publish_port 和可选的 SNI 本地端口，之后的数据按帧传输
+pub const FRAMED_STREAM_MARKER: u16 = 0;
+
+/// 分帧标记之后再以该值代替 publish_port 时，表示前导携带外部连接的信息（客户端声明了 peer_report
+/// 能力且代理开启了 `report_peers`）：其后依次为 u8 标志（[`PEER_INFO_FLAG_FRAMED`]）、真实的
+/// publish_port、[`PeerInfo`] 和可选的 SNI 本地端口，之后的数据按标志决定是否分帧
+pub const PEER_INFO_STREAM_MARKER: u16 = 0;
+
+/// 携带外部连接信息的前导中的标志位：之后的数据按帧传输（代理配置了 `idle_keepalive_secs`）
+pub const PEER_INFO_FLAG_FRAMED: u8 = 0x01;
+
+/// 携带外部连接信息的前导中的标志位：两端在各自发送方向结束时写入完整性校验尾帧
+/// （需要客户端声明 integrity_check 能力，总是与 [`PEER_INFO_FLAG_FRAMED`] 一起设置）
+pub const PEER_INFO_FLAG_INTEGRITY: u8 = 0x02;
+
+/// 分帧 stream 上以该值代替负载长度的帧是完整性校验尾帧（正常帧的负载不超过 32KB），
+/// 其后为 [`IntegrityTrailer`]
+pub const INTEGRITY_TRAILER_MARKER: u16 = 0xFFFF;
+
+/// 完整性校验尾帧：发送方在该方向上发出的负载字节数（u64）和 CRC32C（u32），均为大端
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct IntegrityTrailer {
+    pub bytes: u64,
+    pub crc32c: u32,
+}
+
+impl IntegrityTrailer {
+    /// 尾帧在标记之后的字节数
+    pub const ENCODED_LEN: usize = 12;
+
+    /// 编码为完整的尾帧（包括 [`INTEGRITY_TRAILER_MARKER`]）
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(2 + Self::ENCODED_LEN);
+        buf.extend_from_slice(&INTEGRITY_TRAILER_MARKER.to_be_bytes());
+        buf.extend_from_slice(&self.bytes.to_be_bytes());
+        buf.extend_from_slice(&self.crc32c.to_be_bytes());
+        buf
+    }
+
+    /// 解码标记之后的字节
+    pub fn decode(buf: &[u8; Self::ENCODED_LEN]) -> Self {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&buf[..8]);
+        let mut crc32c = [0u8; 4];
+        crc32c.copy_from_slice(&buf[8..]);
+        Self {
+            bytes: u64::from_be_bytes(bytes),
+            crc32c: u32::from_be_bytes(crc32c),
+        }
+    }
+}
+
+/// 外部连接信息的地址文本最大字节数（u8 长度前缀）
+pub const MAX_PEER_ADDR_LEN: usize = 255;
+
+/// 外部连接的信息（服务器看到的来源地址和接入时间）
+///
+/// 编码为 u8 地址长度 + 地址文本（如 `203.0.113.7:51234`）+ u64 接入时间（Unix 毫秒时间戳）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerInfo {
+    /// 来源地址
+    pub addr: String,
+    /// 服务器接受连接的时间（Unix 毫秒时间戳）
+    pub accepted_at_ms: u64,
+}
+
+impl PeerInfo {
+    /// 以当前时间作为接入时间
+    pub fn accepted_now(addr: std::net::SocketAddr) -> Self {
+        let accepted_at_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        Self {
+            addr: addr.to_string(),
+            accepted_at_ms,
+        }
+    }
+
+    /// 编码为前导中的字节（地址超长时截断，正常的套接字地址不会超过上限）
+    pub fn encode(&self) -> Vec<u8> {
+        let addr = &self.addr.as_bytes()[..self.addr.len().min(MAX_PEER_ADDR_LEN)];
+        let mut buf = Vec::with_capacity(1 + addr.len() + 8);
+        buf.push(addr.len() as u8);
+        buf.extend_from_slice(addr);
+        buf.extend_from_slice(&self.accepted_at_ms.to_be_bytes());
+        buf
+    }
+
+    /// 编码后的字节数
+    pub fn encoded_len(&self) -> usize {
+        1 + self.addr.len().min(MAX_PEER_ADDR_LEN) + 8
+    }
+}
+
+/// visitor stream 前导以该值作为 publish_port 时，按名称匹配唯一的代理，不限发布端口
+/// （需要服务器支持 visitor_any_port 能力）
+pub const ANY_PUBLISH_PORT: u16 = 0;
+
+/// 与对端协商的 proxy 注册者身份校验方式（visitor 确认帧之后携带的内容）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PeerIdentity {
+    /// 不校验（对端不支持 peer_identity 能力）
+    #[default]
+    Unsupported,
+    /// 携带注册者的 peer_id
+    PeerId,
+    /// 携带注册者的 peer_id 和身份指纹（双方都支持 client_identity 能力）
+    PeerIdAndKey,
+}
+
+impl PeerIdentity {
+    /// 按对端声明的协议能力确定校验方式
+    pub fn from_capabilities(capabilities: &[String]) -> Self {
+        let has = |name: &str| capabilities.iter().any(|c| c == name);
+        if !has(crate::control_protocol::CAPABILITY_PEER_IDENTITY) {
+            PeerIdentity::Unsupported
+        } else if has(crate::control_protocol::CAPABILITY_CLIENT_IDENTITY) {
+            PeerIdentity::PeerIdAndKey
+        } else {
+            PeerIdentity::PeerId
+        }
+    }
+
+    /// 确认帧之后是否携带 peer_id（并等待 visitor 的校验结果）
+    pub fn sends_peer_id(self) -> bool {
+        self != PeerIdentity::Unsupported
+    }
+
+    /// peer_id 之后是否还携带身份指纹
+    pub fn sends_fingerprint(self) -> bool {
+        self == PeerIdentity::PeerIdAndKey
+    }
+}
+
+/// 认证请求消息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthRequest {
+    /// 认证密钥
+    pub auth_key: String,
+}
+
+/// 认证响应消息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthResponse {
+    /// 认证是否成功
+    pub success: bool,
+    /// 如果失败，错误信息
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AuthResponse {
+    /// 创建成功响应
+    pub fn success() -> Self {
+        Self {
+            success: true,
+            error: None,
+        }
+    }
+
+    /// 创建失败响应
+    pub fn failed(error: String) -> Self {
+        Self {
+            success: false,
+            error: Some(error),
+        }
+    }
+}
+
+/// 配置验证响应消息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigValidationResponse {
+    /// 配置是否有效
+    pub valid: bool,
+    /// 如果无效，错误信息
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ConfigValidationResponse {
+    /// 创建有效响应
+    pub fn valid() -> Self {
+        Self {
+            valid: true,
+            error: None,
+        }
+    }
+
+    /// 创建无效响应
+    pub fn invalid(error: String) -> Self {
+        Self {
+            valid: false,
+            error: Some(error),
+        }
+    }
+}
+
+/// 服务器发送给客户端的配置状态响应
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigStatusResponse {
+    /// 是否所有配置都被接受
+    pub accepted: bool,
+    /// 被拒绝的代理列表（已被其他客户端占用）
+    #[serde(default)]
+    pub rejected_proxies: Vec<String>,
+}
+
+impl ConfigStatusResponse {
+    /// 创建接受响应
+    pub fn accepted() -> Self {
+        Self {
+            accepted: true,
+            rejected_proxies: Vec::new(),
+        }
+    }
+
+    /// 创建部分拒绝响应
+    pub fn partially_rejected(rejected: Vec<String>) -> Self {
+        Self {
+            accepted: true,
+            rejected_proxies: rejected,
+        }
+    }
+
+    /// 创建全部拒绝响应
+    pub fn all_rejected(rejected: Vec<String>) -> Self {
+        Self {
+            accepted: false,
+            rejected_proxies: rejected,
+        }
+    }
+
+    /// 是否有被拒绝的代理
+    pub fn has_rejected(&self) -> bool {
+        !self.rejected_proxies.is_empty()
+    }
+}
+
+/// 代理健康状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyHealthStatus {
+    /// 正常工作
+    Healthy,
+    /// 暂时不可用，正在重试
+    Unhealthy,
+    /// 绑定端口失败
+    BindFailed,
+}
+
+/// 代理状态更新消息（服务器实时通知客户端代理状态变化）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxyStatusUpdate {
+    /// 代理名称
+    pub proxy_name: String,
+    /// 代理状态
+    pub status: ProxyHealthStatus,
+    /// 错误信息（如果状态为不健康）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_message: Option<String>,
+    /// 如果绑定失败，表示下一次重试的秒数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_seconds: Option<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_status_response_accepted() {
+        let resp = ConfigStatusResponse::accepted();
+        assert!(resp.accepted);
+        assert!(resp.rejected_proxies.is_empty());
+    }
+
+    #[test]
+    fn test_config_status_response_partially_rejected() {
+        let resp = ConfigStatusResponse::partially_rejected(vec!["proxy1".to_string()]);
+        assert!(resp.accepted);
+        assert_eq!(resp.rejected_proxies.len(), 1);
+        assert!(resp.has_rejected());
+    }
+
+    #[test]
+    fn test_config_status_response_all_rejected() {
+        let resp = ConfigStatusResponse::all_rejected(vec!["proxy1".to_string()]);
+        assert!(!resp.accepted);
+        assert_eq!(resp.rejected_proxies.len(), 1);
+    }
+}
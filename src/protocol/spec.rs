@@ -0,0 +1,1205 @@
+/// 版本化的协议规范
+///
+/// 汇总控制通道每个方法的规范消息（完整的 JSON-RPC 报文）、stream 前导的规范编码和帧常量，
+/// 供第三方实现兼容的客户端。`tests/fixtures/protocol/` 下的规范报文和 `docs/PROTOCOL.md`
+/// 都由本模块生成，并由 `tests/protocol_spec_tests.rs` 逐字节校验，任何线上格式的变化都会让测试失败
+use crate::control_protocol::*;
+use crate::protocol::{
+    encode_datagram, encode_error_message, IntegrityTrailer, PeerInfo, ProxyStreamHeader,
+    StreamPreamble, ANY_PUBLISH_PORT, CONFIRM_ACCEPTED, CONFIRM_REJECTED, FORWARD_NAME_PREFIX,
+    FRAMED_STREAM_MARKER, INTEGRITY_TRAILER_MARKER, MAX_DATAGRAM_SIZE, MAX_ERROR_MESSAGE_SIZE,
+    MAX_PEER_ADDR_LEN, MAX_STREAM_NAME_LEN, PEER_INFO_FLAG_FRAMED, PEER_INFO_FLAG_INTEGRITY,
+    PEER_INFO_STREAM_MARKER, VISITOR_MUX_NAME_PREFIX, VISITOR_MUX_STREAM_MARKER,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::str::FromStr;
+
+/// 规范版本（线上格式发生不兼容变化时递增，新增可选字段和方法不递增）
+pub const SPEC_VERSION: u32 = 1;
+
+/// 已定义但目前没有任何一端发送的方法（保留名称，不生成规范报文）
+pub const RESERVED_METHODS: [ControlMethod; 2] =
+    [ControlMethod::PushConfigStatus, ControlMethod::PushStats];
+
+/// 消息方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    ClientToServer,
+    ServerToClient,
+    /// 两端都发送（如各自方向结束时的尾帧）
+    Both,
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Direction::ClientToServer => "client → server",
+            Direction::ServerToClient => "server → client",
+            Direction::Both => "both directions",
+        }
+    }
+}
+
+/// JSON-RPC 报文种类（决定载荷所在的字段）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    /// 带 id 的请求，载荷为 `params`
+    Request,
+    /// 不带 id 的通知，载荷为 `params`
+    Notification,
+    /// 成功响应，载荷为 `result`
+    Response,
+    /// 错误响应，载荷为 `error.data`
+    ErrorResponse,
+}
+
+impl MessageKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            MessageKind::Request => "request",
+            MessageKind::Notification => "notification",
+            MessageKind::Response => "response",
+            MessageKind::ErrorResponse => "error response",
+        }
+    }
+}
+
+/// 一条控制通道消息的规范
+pub struct MessageSpec {
+    /// 规范报文的文件名（不含扩展名）
+    pub fixture: &'static str,
+    /// 所属方法（与方法无关的控制帧错误为 None）
+    pub method: Option<ControlMethod>,
+    pub kind: MessageKind,
+    pub direction: Direction,
+    /// 使用该消息前需要对端声明的协议能力
+    pub capability: Option<&'static str>,
+    /// 载荷对应的 Rust 类型
+    pub payload_type: &'static str,
+    pub summary: &'static str,
+    example: fn() -> Vec<u8>,
+    retype: fn(&Value) -> serde_json::Result<Value>,
+}
+
+impl MessageSpec {
+    /// 规范报文（控制帧的消息体，不含 4 字节长度前缀）
+    pub fn example(&self) -> Vec<u8> {
+        (self.example)()
+    }
+
+    /// 解析报文并经过载荷类型重新编码，返回编码后的字节
+    ///
+    /// 载荷不能按类型解析、或重新编码后丢失了字段时，结果与输入不同
+    pub fn reencode(&self, bytes: &[u8]) -> Result<Vec<u8>, String> {
+        match (parse_message(bytes), self.kind) {
+            (ControlMessage::Request(mut request), MessageKind::Request)
+            | (ControlMessage::Request(mut request), MessageKind::Notification) => {
+                if request.is_notification() != (self.kind == MessageKind::Notification) {
+                    return Err(format!("not a {}", self.kind.as_str()));
+                }
+                ControlMethod::from_str(&request.method).map_err(|e| e.to_string())?;
+                request.params = (self.retype)(&request.params).map_err(|e| e.to_string())?;
+                serde_json::to_vec(&request).map_err(|e| e.to_string())
+            }
+            (ControlMessage::Response(mut response), MessageKind::Response) => {
+                let result = response.result.as_ref().ok_or("response without result")?;
+                response.result = Some((self.retype)(result).map_err(|e| e.to_string())?);
+                serde_json::to_vec(&response).map_err(|e| e.to_string())
+            }
+            (ControlMessage::Response(mut response), MessageKind::ErrorResponse) => {
+                let error = response.error.as_mut().ok_or("response without error")?;
+                if let Some(data) = &error.data {
+                    error.data = Some((self.retype)(data).map_err(|e| e.to_string())?);
+                }
+                serde_json::to_vec(&response).map_err(|e| e.to_string())
+            }
+            (ControlMessage::Malformed { error, .. }, _) => Err(error.message),
+            _ => Err(format!("not a {}", self.kind.as_str())),
+        }
+    }
+
+    /// 规范报文中的载荷
+    fn payload(&self) -> Value {
+        let message: Value = serde_json::from_slice(&self.example()).unwrap_or_default();
+        match self.kind {
+            MessageKind::Request | MessageKind::Notification => message["params"].clone(),
+            MessageKind::Response => message["result"].clone(),
+            MessageKind::ErrorResponse => message["error"]["data"].clone(),
+        }
+    }
+}
+
+/// 一种 stream 前导或帧的规范
+pub struct FramingSpec {
+    /// 规范编码的文件名（不含扩展名）
+    pub fixture: &'static str,
+    pub direction: Direction,
+    pub summary: &'static str,
+    fields: fn() -> Value,
+    encode: fn(&Value) -> serde_json::Result<Vec<u8>>,
+}
+
+impl FramingSpec {
+    /// 规范字段（与编码一起写入规范文件）
+    pub fn fields(&self) -> Value {
+        (self.fields)()
+    }
+
+    /// 按字段编码
+    pub fn encode(&self, fields: &Value) -> serde_json::Result<Vec<u8>> {
+        (self.encode)(fields)
+    }
+
+    /// 规范文件内容：字段和十六进制编码
+    pub fn example(&self) -> Value {
+        let fields = self.fields();
+        let encoded = self.encode(&fields).unwrap_or_default();
+        json!({ "fields": fields, "encoded": crate::identity::to_hex(&encoded) })
+    }
+}
+
+fn retype<T: Serialize + DeserializeOwned>(value: &Value) -> serde_json::Result<Value> {
+    serde_json::to_value(T::deserialize(value)?)
+}
+
+fn encode_as<T: DeserializeOwned>(
+    value: &Value,
+    encode: fn(&T) -> Vec<u8>,
+) -> serde_json::Result<Vec<u8>> {
+    Ok(encode(&T::deserialize(value)?))
+}
+
+fn request<P: Serialize>(method: ControlMethod, params: P, id: u64) -> Vec<u8> {
+    let request = JsonRpcRequest::call(method, &params, id).expect("serializable params");
+    serde_json::to_vec(&request).expect("serializable request")
+}
+
+fn notification<P: Serialize>(method: ControlMethod, params: P) -> Vec<u8> {
+    let request = JsonRpcRequest::notify(method, &params).expect("serializable params");
+    serde_json::to_vec(&request).expect("serializable notification")
+}
+
+fn response<R: Serialize>(id: u64, result: R) -> Vec<u8> {
+    let response = JsonRpcResponse::success_with(id.into(), &result).expect("serializable result");
+    serde_json::to_vec(&response).expect("serializable response")
+}
+
+fn error_response<D: Serialize>(id: u64, code: i32, message: &str, data: D) -> Vec<u8> {
+    let error = JsonRpcError::with_data(code, message.to_string(), &data).expect("serializable");
+    serde_json::to_vec(&JsonRpcResponse::error(id.into(), error)).expect("serializable response")
+}
+
+fn exception<D: Serialize>(level: &str, message: &str, code: &str, data: D) -> Vec<u8> {
+    let exception = ExceptionNotification::with_data(level, message.to_string(), code, &data)
+        .expect("serializable data");
+    notification(ControlMethod::PushException, exception)
+}
+
+fn example_proxy() -> crate::config::ProxyConfig {
+    serde_json::from_value(json!({ "name": "web", "publish_port": 8080, "local_port": 3000 }))
+        .expect("valid proxy")
+}
+
+fn example_visitor() -> crate::config::VisitorConfig {
+    serde_json::from_value(json!({ "name": "db", "bind_port": 15432, "publish_port": 5432 }))
+        .expect("valid visitor")
+}
+
+fn example_reasons() -> BTreeMap<String, String> {
+    BTreeMap::from([(
+        "web".to_string(),
+        "publish_port 8080 is already in use".to_string(),
+    )])
+}
+
+fn example_heartbeat() -> HeartbeatParams {
+    HeartbeatParams {
+        seq: 42,
+        timestamp_ms: 120000,
+    }
+}
+
+fn example_peer() -> PeerInfo {
+    PeerInfo {
+        addr: "203.0.113.7:51234".to_string(),
+        accepted_at_ms: 1700000000000,
+    }
+}
+
+/// 全部控制通道消息的规范
+pub fn messages() -> Vec<MessageSpec> {
+    vec![
+        MessageSpec {
+            fixture: "identity_challenge.request",
+            method: Some(ControlMethod::IdentityChallenge),
+            kind: MessageKind::Request,
+            direction: Direction::ClientToServer,
+            capability: None,
+            payload_type: "(empty object)",
+            summary: "Request a nonce to sign before authenticating with a client identity.",
+            example: || request(ControlMethod::IdentityChallenge, serde_json::Map::new(), 1),
+            retype: retype::<serde_json::Map<String, Value>>,
+        },
+        MessageSpec {
+            fixture: "identity_challenge.response",
+            method: Some(ControlMethod::IdentityChallenge),
+            kind: MessageKind::Response,
+            direction: Direction::ServerToClient,
+            capability: None,
+            payload_type: "IdentityChallengeResult",
+            summary: "Hex nonce, valid only for the next authenticate request of this session.",
+            example: || {
+                response(
+                    1,
+                    IdentityChallengeResult {
+                        nonce: "00112233445566778899aabbccddeeff".to_string(),
+                    },
+                )
+            },
+            retype: retype::<IdentityChallengeResult>,
+        },
+        MessageSpec {
+            fixture: "authenticate.request",
+            method: Some(ControlMethod::Authenticate),
+            kind: MessageKind::Request,
+            direction: Direction::ClientToServer,
+            capability: None,
+            payload_type: "AuthenticateParams",
+            summary: "First request of a session; capabilities are negotiated here.",
+            example: || {
+                request(
+                    ControlMethod::Authenticate,
+                    AuthenticateParams {
+                        auth_key: "example-auth-key".to_string(),
+                        protocol_version: "1.5.1".to_string(),
+                        capabilities: vec![
+                            CAPABILITY_PEER_IDENTITY.to_string(),
+                            CAPABILITY_HEARTBEAT_ACK.to_string(),
+                        ],
+                        peer_id: Some("office-gateway".to_string()),
+                        identity: Some(IdentityProof {
+                            public_key: "ab".repeat(32),
+                            signature: "cd".repeat(64),
+                        }),
+                    },
+                    2,
+                )
+            },
+            retype: retype::<AuthenticateParams>,
+        },
+        MessageSpec {
+            fixture: "authenticate.response",
+            method: Some(ControlMethod::Authenticate),
+            kind: MessageKind::Response,
+            direction: Direction::ServerToClient,
+            capability: None,
+            payload_type: "AuthenticateResult",
+            summary: "Authentication accepted; carries the server capabilities.",
+            example: || {
+                response(
+                    2,
+                    AuthenticateResult {
+                        client_id: "client-1".to_string(),
+                        protocol_version: "1.5.1".to_string(),
+                        min_client_version: Some("1.4.0".to_string()),
+                        capabilities: vec![
+                            CAPABILITY_PEER_IDENTITY.to_string(),
+                            CAPABILITY_HEARTBEAT_ACK.to_string(),
+                        ],
+                    },
+                )
+            },
+            retype: retype::<AuthenticateResult>,
+        },
+        MessageSpec {
+            fixture: "authenticate.error",
+            method: Some(ControlMethod::Authenticate),
+            kind: MessageKind::ErrorResponse,
+            direction: Direction::ServerToClient,
+            capability: None,
+            payload_type: "(no data)",
+            summary: "Authentication refused (code AUTHENTICATION_FAILED, no error data).",
+            example: || {
+                let error = JsonRpcError {
+                    code: AUTHENTICATION_FAILED,
+                    message: "Invalid authentication key".to_string(),
+                    data: None,
+                };
+                serde_json::to_vec(&JsonRpcResponse::error(2.into(), error)).expect("serializable")
+            },
+            retype: |value| Ok(value.clone()),
+        },
+        MessageSpec {
+            fixture: "submit_config.request",
+            method: Some(ControlMethod::SubmitConfig),
+            kind: MessageKind::Request,
+            direction: Direction::ClientToServer,
+            capability: None,
+            payload_type: "SubmitConfigParams",
+            summary: "Register proxies and visitors after authentication.",
+            example: || {
+                request(
+                    ControlMethod::SubmitConfig,
+                    SubmitConfigParams {
+                        proxies: vec![example_proxy()],
+                        visitors: vec![example_visitor()],
+                    },
+                    3,
+                )
+            },
+            retype: retype::<SubmitConfigParams>,
+        },
+        MessageSpec {
+            fixture: "submit_config.response",
+            method: Some(ControlMethod::SubmitConfig),
+            kind: MessageKind::Response,
+            direction: Direction::ServerToClient,
+            capability: None,
+            payload_type: "SubmitConfigResult",
+            summary: "Configuration accepted, possibly with some entries rejected.",
+            example: || {
+                response(
+                    3,
+                    SubmitConfigResult {
+                        rejected_proxies: vec!["web".to_string()],
+                        reasons: example_reasons(),
+                        dry_run: false,
+                        rejected_entries: vec![RejectedEntry {
+                            name: "web".to_string(),
+                            publish_port: 8080,
+                            reason: "publish_port 8080 is already in use".to_string(),
+                            kind: RejectedKind::Proxy,
+                        }],
+                        assigned_ports: vec![AssignedPort {
+                            name: "dev".to_string(),
+                            publish_port: 40123,
+                        }],
+                    },
+                )
+            },
+            retype: retype::<SubmitConfigResult>,
+        },
+        MessageSpec {
+            fixture: "submit_config.error",
+            method: Some(ControlMethod::SubmitConfig),
+            kind: MessageKind::ErrorResponse,
+            direction: Direction::ServerToClient,
+            capability: None,
+            payload_type: "ConfigRejectedData",
+            summary: "Every proxy was rejected (code CONFIG_REJECTED).",
+            example: || {
+                error_response(
+                    3,
+                    CONFIG_REJECTED,
+                    "All proxies rejected: web",
+                    ConfigRejectedData {
+                        rejected_proxies: vec!["web".to_string()],
+                        reasons: example_reasons(),
+                    },
+                )
+            },
+            retype: retype::<ConfigRejectedData>,
+        },
+        MessageSpec {
+            fixture: "validate_config.request",
+            method: Some(ControlMethod::ValidateConfig),
+            kind: MessageKind::Request,
+            direction: Direction::ClientToServer,
+            capability: Some(CAPABILITY_VALIDATE_CONFIG),
+            payload_type: "SubmitConfigParams",
+            summary: "Check a configuration against the server rules without registering it.",
+            example: || {
+                request(
+                    ControlMethod::ValidateConfig,
+                    SubmitConfigParams {
+                        proxies: vec![example_proxy()],
+                        visitors: vec![],
+                    },
+                    4,
+                )
+            },
+            retype: retype::<SubmitConfigParams>,
+        },
+        MessageSpec {
+            fixture: "validate_config.response",
+            method: Some(ControlMethod::ValidateConfig),
+            kind: MessageKind::Response,
+            direction: Direction::ServerToClient,
+            capability: Some(CAPABILITY_VALIDATE_CONFIG),
+            payload_type: "SubmitConfigResult",
+            summary: "Validation outcome, always marked as a dry run.",
+            example: || {
+                response(
+                    4,
+                    SubmitConfigResult {
+                        rejected_proxies: vec![],
+                        reasons: BTreeMap::new(),
+                        dry_run: true,
+                        rejected_entries: vec![],
+                        assigned_ports: vec![],
+                    },
+                )
+            },
+            retype: retype::<SubmitConfigResult>,
+        },
+        MessageSpec {
+            fixture: "update_config.request",
+            method: Some(ControlMethod::UpdateConfig),
+            kind: MessageKind::Request,
+            direction: Direction::ClientToServer,
+            capability: Some(CAPABILITY_INCREMENTAL_CONFIG),
+            payload_type: "UpdateConfigParams",
+            summary: "Register additional proxies while the session is running.",
+            example: || {
+                request(
+                    ControlMethod::UpdateConfig,
+                    UpdateConfigParams {
+                        proxies: vec![example_proxy()],
+                    },
+                    5,
+                )
+            },
+            retype: retype::<UpdateConfigParams>,
+        },
+        MessageSpec {
+            fixture: "update_config.response",
+            method: Some(ControlMethod::UpdateConfig),
+            kind: MessageKind::Response,
+            direction: Direction::ServerToClient,
+            capability: Some(CAPABILITY_INCREMENTAL_CONFIG),
+            payload_type: "SubmitConfigResult",
+            summary: "Proxies from the update that were rejected.",
+            example: || {
+                response(
+                    5,
+                    SubmitConfigResult {
+                        rejected_proxies: vec![],
+                        reasons: BTreeMap::new(),
+                        dry_run: false,
+                        rejected_entries: vec![],
+                        assigned_ports: vec![],
+                    },
+                )
+            },
+            retype: retype::<SubmitConfigResult>,
+        },
+        MessageSpec {
+            fixture: "remove_proxies.request",
+            method: Some(ControlMethod::RemoveProxies),
+            kind: MessageKind::Request,
+            direction: Direction::ClientToServer,
+            capability: Some(CAPABILITY_PROXY_DRAIN),
+            payload_type: "RemoveProxiesParams",
+            summary: "Stop accepting connections for proxies and drain existing ones.",
+            example: || {
+                request(
+                    ControlMethod::RemoveProxies,
+                    RemoveProxiesParams {
+                        proxies: vec![ProxyRef {
+                            name: "web".to_string(),
+                            publish_port: 8080,
+                        }],
+                    },
+                    6,
+                )
+            },
+            retype: retype::<RemoveProxiesParams>,
+        },
+        MessageSpec {
+            fixture: "remove_proxies.response",
+            method: Some(ControlMethod::RemoveProxies),
+            kind: MessageKind::Response,
+            direction: Direction::ServerToClient,
+            capability: Some(CAPABILITY_PROXY_DRAIN),
+            payload_type: "SubmitConfigResult",
+            summary: "Proxies that could not be removed (not registered by this session).",
+            example: || {
+                response(
+                    6,
+                    SubmitConfigResult {
+                        rejected_proxies: vec![],
+                        reasons: BTreeMap::new(),
+                        dry_run: false,
+                        rejected_entries: vec![],
+                        assigned_ports: vec![],
+                    },
+                )
+            },
+            retype: retype::<SubmitConfigResult>,
+        },
+        MessageSpec {
+            fixture: "heartbeat.notification",
+            method: Some(ControlMethod::Heartbeat),
+            kind: MessageKind::Notification,
+            direction: Direction::ClientToServer,
+            capability: None,
+            payload_type: "(null)",
+            summary: "Keep the session alive when the server does not acknowledge heartbeats.",
+            example: || notification(ControlMethod::Heartbeat, ()),
+            retype: retype::<()>,
+        },
+        MessageSpec {
+            fixture: "heartbeat.request",
+            method: Some(ControlMethod::Heartbeat),
+            kind: MessageKind::Request,
+            direction: Direction::ClientToServer,
+            capability: Some(CAPABILITY_HEARTBEAT_ACK),
+            payload_type: "HeartbeatParams",
+            summary: "Heartbeat that the server echoes back so the client can measure RTT.",
+            example: || request(ControlMethod::Heartbeat, example_heartbeat(), 7),
+            retype: retype::<HeartbeatParams>,
+        },
+        MessageSpec {
+            fixture: "heartbeat.response",
+            method: Some(ControlMethod::Heartbeat),
+            kind: MessageKind::Response,
+            direction: Direction::ServerToClient,
+            capability: Some(CAPABILITY_HEARTBEAT_ACK),
+            payload_type: "HeartbeatParams",
+            summary: "Heartbeat acknowledgement echoing the request parameters.",
+            example: || response(7, example_heartbeat()),
+            retype: retype::<HeartbeatParams>,
+        },
+        MessageSpec {
+            fixture: "forward_report.notification",
+            method: Some(ControlMethod::ForwardReport),
+            kind: MessageKind::Notification,
+            direction: Direction::ClientToServer,
+            capability: Some(CAPABILITY_FORWARD_REPORT),
+            payload_type: "ForwardReportParams",
+            summary:
+                "Audit records for forwarder connections routed directly (at most one per second).",
+            example: || {
+                notification(
+                    ControlMethod::ForwardReport,
+                    ForwardReportParams {
+                        reports: vec![ForwardReport {
+                            forwarder: "http-proxy".to_string(),
+                            target: "example.com:443".to_string(),
+                            decision: "direct".to_string(),
+                            rule: Some("direct_by_domain".to_string()),
+                            matched: Some("*.example.com".to_string()),
+                            bytes_up: 512,
+                            bytes_down: 4096,
+                            duration_ms: 1500,
+                            error: Some("connection reset".to_string()),
+                        }],
+                        dropped: 3,
+                    },
+                )
+            },
+            retype: retype::<ForwardReportParams>,
+        },
+        MessageSpec {
+            fixture: "push_exception.notification",
+            method: Some(ControlMethod::PushException),
+            kind: MessageKind::Notification,
+            direction: Direction::ServerToClient,
+            capability: None,
+            payload_type: "ExceptionNotification",
+            summary: "Server-side event relevant to the client; `code` selects the data type.",
+            example: || {
+                let mut exception = ExceptionNotification::with_data(
+                    "warning",
+                    "Proxy 'web' with publish_port 8080 expired after 600s without connections"
+                        .to_string(),
+                    EXCEPTION_PROXY_EXPIRED_IDLE,
+                    &ProxyExpiredData {
+                        name: "web".to_string(),
+                        publish_port: 8080,
+                        idle_secs: 600,
+                        expiry_secs: 600,
+                    },
+                )
+                .expect("serializable data");
+                exception.occurrences = Some(2);
+                notification(ControlMethod::PushException, exception)
+            },
+            retype: retype::<ExceptionNotification>,
+        },
+        MessageSpec {
+            fixture: "push_exception.all_proxies_rejected",
+            method: Some(ControlMethod::PushException),
+            kind: MessageKind::Notification,
+            direction: Direction::ServerToClient,
+            capability: None,
+            payload_type: "ExceptionNotification<ConfigRejectedData>",
+            summary: "Sent before the CONFIG_REJECTED error response.",
+            example: || {
+                exception(
+                    "error",
+                    "所有代理配置被拒绝：web",
+                    EXCEPTION_ALL_PROXIES_REJECTED,
+                    ConfigRejectedData {
+                        rejected_proxies: vec!["web".to_string()],
+                        reasons: example_reasons(),
+                    },
+                )
+            },
+            retype: retype_exception::<ConfigRejectedData>,
+        },
+        MessageSpec {
+            fixture: "push_exception.partial_config_rejection",
+            method: Some(ControlMethod::PushException),
+            kind: MessageKind::Notification,
+            direction: Direction::ServerToClient,
+            capability: None,
+            payload_type: "ExceptionNotification<PartialConfigRejectionData>",
+            summary: "Sent before a submit_config result with rejected entries.",
+            example: || {
+                exception(
+                    "warning",
+                    "部分配置被拒绝：2 项",
+                    EXCEPTION_PARTIAL_CONFIG_REJECTION,
+                    PartialConfigRejectionData {
+                        rejected_items: vec!["web".to_string(), "db:5432".to_string()],
+                        rejected_proxies: vec!["web".to_string()],
+                        rejected_visitors: vec!["db:5432".to_string()],
+                        reasons: example_reasons(),
+                    },
+                )
+            },
+            retype: retype_exception::<PartialConfigRejectionData>,
+        },
+        MessageSpec {
+            fixture: "push_exception.peer_id_mismatch",
+            method: Some(ControlMethod::PushException),
+            kind: MessageKind::Notification,
+            direction: Direction::ServerToClient,
+            capability: Some(CAPABILITY_PEER_IDENTITY),
+            payload_type: "ExceptionNotification<PeerIdMismatchData>",
+            summary: "A visitor refused the proxy registrant's identity.",
+            example: || {
+                exception(
+                    "error",
+                    "Visitor refused proxy 'db' with publish_port 5432: peer identity mismatch (registered peer_id: office-gateway)",
+                    EXCEPTION_PEER_ID_MISMATCH,
+                    PeerIdMismatchData {
+                        proxy_name: "db".to_string(),
+                        publish_port: 5432,
+                        registered_peer_id: Some("office-gateway".to_string()),
+                        registered_identity: Some("SHA256:abcdef".to_string()),
+                    },
+                )
+            },
+            retype: retype_exception::<PeerIdMismatchData>,
+        },
+        MessageSpec {
+            fixture: "push_exception.proxy_accept_error",
+            method: Some(ControlMethod::PushException),
+            kind: MessageKind::Notification,
+            direction: Direction::ServerToClient,
+            capability: None,
+            payload_type: "ExceptionNotification<ProxyAcceptErrorData>",
+            summary: "A published listener failed to accept a connection.",
+            example: || {
+                exception(
+                    "warning",
+                    "代理 'web' 接受连接失败：Too many open files (os error 24)",
+                    EXCEPTION_PROXY_ACCEPT_ERROR,
+                    ProxyAcceptErrorData {
+                        proxy_name: "web".to_string(),
+                        publish_port: 8080,
+                    },
+                )
+            },
+            retype: retype_exception::<ProxyAcceptErrorData>,
+        },
+        MessageSpec {
+            fixture: "push_exception.forward_limit_exceeded",
+            method: Some(ControlMethod::PushException),
+            kind: MessageKind::Notification,
+            direction: Direction::ServerToClient,
+            capability: None,
+            payload_type: "ExceptionNotification<ForwardLimitData>",
+            summary: "A forwarder connection was closed by a server limit.",
+            example: || {
+                exception(
+                    "warning",
+                    "Forward connection to 'example.com:443' exceeded server limit max_bytes",
+                    EXCEPTION_FORWARD_LIMIT_EXCEEDED,
+                    ForwardLimitData {
+                        target: "example.com:443".to_string(),
+                        limit: "max_bytes".to_string(),
+                        bytes: 1048576,
+                        duration_secs: 12,
+                        limits: crate::config::ForwardLimitConfig {
+                            max_bytes: Some(1048576),
+                            ..Default::default()
+                        },
+                    },
+                )
+            },
+            retype: retype_exception::<ForwardLimitData>,
+        },
+        MessageSpec {
+            fixture: "push_exception.stream_limit_exceeded",
+            method: Some(ControlMethod::PushException),
+            kind: MessageKind::Notification,
+            direction: Direction::ServerToClient,
+            capability: None,
+            payload_type: "ExceptionNotification<StreamLimitData>",
+            summary: "A stream was refused because the session reached max_streams_per_client.",
+            example: || {
+                exception(
+                    "warning",
+                    "会话的 stream 数已达上限 1024，代理 'web'（端口 8080）的连接被拒绝",
+                    EXCEPTION_STREAM_LIMIT_EXCEEDED,
+                    StreamLimitData {
+                        proxy_name: Some("web".to_string()),
+                        publish_port: Some(8080),
+                        max_streams: 1024,
+                    },
+                )
+            },
+            retype: retype_exception::<StreamLimitData>,
+        },
+        MessageSpec {
+            fixture: "push_exception.exceptions_suppressed",
+            method: Some(ControlMethod::PushException),
+            kind: MessageKind::Notification,
+            direction: Direction::ServerToClient,
+            capability: None,
+            payload_type: "ExceptionNotification<ExceptionsSuppressedData>",
+            summary: "Summary of notifications dropped by the server's rate limit.",
+            example: || {
+                exception(
+                    "warning",
+                    "最近 60 秒内有 12 条异常通知被抑制",
+                    EXCEPTION_EXCEPTIONS_SUPPRESSED,
+                    ExceptionsSuppressedData {
+                        suppressed: 12,
+                        interval_secs: 60,
+                    },
+                )
+            },
+            retype: retype_exception::<ExceptionsSuppressedData>,
+        },
+        MessageSpec {
+            fixture: "push_exception.server_shutdown",
+            method: Some(ControlMethod::PushException),
+            kind: MessageKind::Notification,
+            direction: Direction::ServerToClient,
+            capability: None,
+            payload_type: "ExceptionNotification",
+            summary: "The server is stopping; the session ends right after.",
+            example: || {
+                notification(
+                    ControlMethod::PushException,
+                    ExceptionNotification {
+                        level: "warning".to_string(),
+                        message: "服务器正在停止".to_string(),
+                        code: Some(EXCEPTION_SERVER_SHUTDOWN.to_string()),
+                        data: None,
+                        occurrences: None,
+                    },
+                )
+            },
+            retype: retype::<ExceptionNotification>,
+        },
+        MessageSpec {
+            fixture: "control_error.framing",
+            method: None,
+            kind: MessageKind::ErrorResponse,
+            direction: Direction::ServerToClient,
+            capability: None,
+            payload_type: "{ condition }",
+            summary: "Sent by either side before closing a control stream that lost framing.",
+            example: || {
+                let error = ControlFrameError::TooLarge {
+                    size: MAX_CONTROL_MESSAGE_SIZE + 1,
+                    limit: MAX_CONTROL_MESSAGE_SIZE,
+                }
+                .to_rpc_error();
+                serde_json::to_vec(&JsonRpcResponse::error(Value::Null, error))
+                    .expect("serializable")
+            },
+            retype: retype::<FrameErrorData>,
+        },
+    ]
+}
+
+/// 控制帧错误的附加数据
+#[derive(Serialize, Deserialize)]
+struct FrameErrorData {
+    condition: String,
+}
+
+/// 异常通知的附加数据按代码对应的类型重新编码
+fn retype_exception<D: Serialize + DeserializeOwned>(value: &Value) -> serde_json::Result<Value> {
+    let mut exception = ExceptionNotification::deserialize(value)?;
+    if let Some(data) = &exception.data {
+        exception.data = Some(retype::<D>(data)?);
+    }
+    serde_json::to_value(exception)
+}
+
+/// 全部 stream 前导和帧的规范
+pub fn framings() -> Vec<FramingSpec> {
+    vec![
+        FramingSpec {
+            fixture: "stream_preamble.visitor",
+            direction: Direction::ClientToServer,
+            summary: "Visitor stream: u16 name length, name, u16 publish_port (0 = any port).",
+            fields: || {
+                json!(StreamPreamble {
+                    name: "db".to_string(),
+                    publish_port: 5432,
+                })
+            },
+            encode: |value| encode_as(value, StreamPreamble::encode),
+        },
+        FramingSpec {
+            fixture: "stream_preamble.forward",
+            direction: Direction::ClientToServer,
+            summary: "Forwarder stream: the name carries the target after `@forward:`.",
+            fields: || {
+                json!(StreamPreamble {
+                    name: format!("{}example.com:443", FORWARD_NAME_PREFIX),
+                    publish_port: 0,
+                })
+            },
+            encode: |value| encode_as(value, StreamPreamble::encode),
+        },
+        FramingSpec {
+            fixture: "stream_preamble.visitor_mux",
+            direction: Direction::ClientToServer,
+            summary: "Multiplexed visitor stream: the name carries the proxy after `@mux:`.",
+            fields: || {
+                json!(StreamPreamble {
+                    name: format!("{}db", VISITOR_MUX_NAME_PREFIX),
+                    publish_port: 5432,
+                })
+            },
+            encode: |value| encode_as(value, StreamPreamble::encode),
+        },
+        FramingSpec {
+            fixture: "error_message",
+            direction: Direction::ServerToClient,
+            summary: "Error message frame after a rejecting confirmation byte: u16 length, UTF-8 text.",
+            fields: || json!({ "message": "Proxy not found" }),
+            encode: |value| {
+                #[derive(Deserialize)]
+                struct Message {
+                    message: String,
+                }
+                Ok(encode_error_message(&Message::deserialize(value)?.message))
+            },
+        },
+        FramingSpec {
+            fixture: "proxy_stream_header.plain",
+            direction: Direction::ServerToClient,
+            summary: "Proxy stream opened by the server: u16 publish_port.",
+            fields: || {
+                json!(ProxyStreamHeader {
+                    publish_port: 8080,
+                    framed: false,
+                    peer: None,
+                    sni_local_port: None,
+                    integrity: false,
+                })
+            },
+            encode: |value| encode_as(value, ProxyStreamHeader::encode),
+        },
+        FramingSpec {
+            fixture: "proxy_stream_header.framed_sni",
+            direction: Direction::ServerToClient,
+            summary: "Framed proxy stream (idle_keepalive): two zero markers, publish_port, SNI local port.",
+            fields: || {
+                json!(ProxyStreamHeader {
+                    publish_port: 443,
+                    framed: true,
+                    peer: None,
+                    sni_local_port: Some(8443),
+                    integrity: false,
+                })
+            },
+            encode: |value| encode_as(value, ProxyStreamHeader::encode),
+        },
+        FramingSpec {
+            fixture: "proxy_stream_header.peer_info",
+            direction: Direction::ServerToClient,
+            summary: "Proxy stream with peer info (report_peers): three zero markers, flags, publish_port, peer.",
+            fields: || {
+                json!(ProxyStreamHeader {
+                    publish_port: 8080,
+                    framed: true,
+                    peer: Some(example_peer()),
+                    sni_local_port: None,
+                    integrity: false,
+                })
+            },
+            encode: |value| encode_as(value, ProxyStreamHeader::encode),
+        },
+        FramingSpec {
+            fixture: "proxy_stream_header.integrity",
+            direction: Direction::ServerToClient,
+            summary: "Proxy stream with integrity trailers (integrity_check): peer info header with the framed and integrity flags.",
+            fields: || {
+                json!(ProxyStreamHeader {
+                    publish_port: 8080,
+                    framed: true,
+                    peer: Some(example_peer()),
+                    sni_local_port: None,
+                    integrity: true,
+                })
+            },
+            encode: |value| encode_as(value, ProxyStreamHeader::encode),
+        },
+        FramingSpec {
+            fixture: "integrity_trailer",
+            direction: Direction::Both,
+            summary: "Integrity trailer frame written before closing a direction: 0xffff marker, u64 byte count, u32 CRC32C.",
+            fields: || {
+                json!(IntegrityTrailer {
+                    bytes: 11,
+                    crc32c: 0xc99465aa,
+                })
+            },
+            encode: |value| encode_as(value, IntegrityTrailer::encode),
+        },
+        FramingSpec {
+            fixture: "udp_datagram",
+            direction: Direction::Both,
+            summary: "Datagram frame on a udp proxy stream (after the plain proxy stream header): u16 length, payload.",
+            fields: || json!({ "payload": "ping" }),
+            encode: |value| {
+                #[derive(Deserialize)]
+                struct Datagram {
+                    payload: String,
+                }
+                Ok(encode_datagram(
+                    Datagram::deserialize(value)?.payload.as_bytes(),
+                ))
+            },
+        },
+    ]
+}
+
+/// 帧常量（名称、取值、说明）
+pub fn constants() -> Vec<(&'static str, String, &'static str)> {
+    vec![
+        (
+            "MAX_CONTROL_MESSAGE_SIZE",
+            MAX_CONTROL_MESSAGE_SIZE.to_string(),
+            "Control frames: u32 big-endian length prefix, then a JSON-RPC 2.0 message of at most this many bytes",
+        ),
+        (
+            "MAX_STREAM_NAME_LEN",
+            MAX_STREAM_NAME_LEN.to_string(),
+            "Maximum name length in a stream preamble",
+        ),
+        (
+            "ANY_PUBLISH_PORT",
+            ANY_PUBLISH_PORT.to_string(),
+            "Visitor preamble publish_port matching a proxy by name only (visitor_any_port)",
+        ),
+        (
+            "FORWARD_NAME_PREFIX",
+            format!("`{}`", FORWARD_NAME_PREFIX),
+            "Preamble name prefix of forwarder streams",
+        ),
+        (
+            "VISITOR_MUX_NAME_PREFIX",
+            format!("`{}`", VISITOR_MUX_NAME_PREFIX),
+            "Preamble name prefix of multiplexed visitor streams",
+        ),
+        (
+            "CONFIRM_ACCEPTED",
+            CONFIRM_ACCEPTED.to_string(),
+            "Confirmation byte after a visitor preamble: accepted",
+        ),
+        (
+            "CONFIRM_REJECTED",
+            CONFIRM_REJECTED.to_string(),
+            "Confirmation byte after a visitor preamble: rejected, an error message frame follows",
+        ),
+        (
+            "MAX_ERROR_MESSAGE_SIZE",
+            MAX_ERROR_MESSAGE_SIZE.to_string(),
+            "Maximum text length of an error message frame",
+        ),
+        (
+            "VISITOR_MUX_STREAM_MARKER",
+            VISITOR_MUX_STREAM_MARKER.to_string(),
+            "Server-opened stream: multiplexed visitor stream, real publish_port follows",
+        ),
+        (
+            "FRAMED_STREAM_MARKER",
+            FRAMED_STREAM_MARKER.to_string(),
+            "After the mux marker: framed proxy stream",
+        ),
+        (
+            "PEER_INFO_STREAM_MARKER",
+            PEER_INFO_STREAM_MARKER.to_string(),
+            "After the framed marker: header carries peer info",
+        ),
+        (
+            "PEER_INFO_FLAG_FRAMED",
+            format!("0x{:02x}", PEER_INFO_FLAG_FRAMED),
+            "Peer info flag: data after the header is framed",
+        ),
+        (
+            "PEER_INFO_FLAG_INTEGRITY",
+            format!("0x{:02x}", PEER_INFO_FLAG_INTEGRITY),
+            "Peer info flag: both ends write an integrity trailer before closing their direction",
+        ),
+        (
+            "INTEGRITY_TRAILER_MARKER",
+            format!("0x{:04x}", INTEGRITY_TRAILER_MARKER),
+            "Frame length value marking an integrity trailer on a framed stream",
+        ),
+        (
+            "MAX_DATAGRAM_SIZE",
+            MAX_DATAGRAM_SIZE.to_string(),
+            "Maximum payload of a datagram frame on a udp proxy stream (u16 length prefix)",
+        ),
+        (
+            "MAX_PEER_ADDR_LEN",
+            MAX_PEER_ADDR_LEN.to_string(),
+            "Peer info: u8 address length, address text, u64 accepted_at_ms",
+        ),
+    ]
+}
+
+/// JSON-RPC 错误码（名称、取值）
+pub fn error_codes() -> Vec<(&'static str, i32)> {
+    vec![
+        ("PARSE_ERROR", PARSE_ERROR),
+        ("INVALID_REQUEST", INVALID_REQUEST),
+        ("METHOD_NOT_FOUND", METHOD_NOT_FOUND),
+        ("INVALID_PARAMS", INVALID_PARAMS),
+        ("AUTHENTICATION_FAILED", AUTHENTICATION_FAILED),
+        ("CONFIG_REJECTED", CONFIG_REJECTED),
+    ]
+}
+
+/// JSON 值的类型名称
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// 输出一条消息的规范（载荷字段取自规范报文）
+fn render_message(out: &mut String, spec: &MessageSpec) {
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "#### {} ({}, `{}`)",
+        spec.kind.as_str(),
+        spec.direction.as_str(),
+        spec.fixture
+    );
+    let _ = writeln!(out);
+    let _ = writeln!(out, "{}", spec.summary);
+    let _ = writeln!(out);
+    let _ = writeln!(out, "- Payload: `{}`", spec.payload_type);
+    if let Some(capability) = spec.capability {
+        let _ = writeln!(out, "- Capability: `{}`", capability);
+    }
+    if let Value::Object(fields) = spec.payload() {
+        for (name, value) in &fields {
+            let _ = writeln!(out, "  - `{}`: {}", name, json_type(value));
+        }
+    }
+}
+
+/// 生成协议摘要（`docs/PROTOCOL.md` 的内容）
+pub fn render_markdown() -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# TLS Tunnel Protocol (spec version {})", SPEC_VERSION);
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "<!-- Generated from src/protocol/spec.rs by tests/protocol_spec_tests.rs; \
+         run `UPDATE_GOLDEN=1 cargo test --test protocol_spec_tests` to refresh. -->"
+    );
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "Canonical messages for every entry below are in `tests/fixtures/protocol/`."
+    );
+
+    let _ = writeln!(out);
+    let _ = writeln!(out, "## Framing constants");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "| Name | Value | Meaning |");
+    let _ = writeln!(out, "|------|-------|---------|");
+    for (name, value, meaning) in constants() {
+        let _ = writeln!(out, "| `{}` | {} | {} |", name, value, meaning);
+    }
+
+    let _ = writeln!(out);
+    let _ = writeln!(out, "## Capabilities");
+    let _ = writeln!(out);
+    for capability in supported_capabilities() {
+        let _ = writeln!(out, "- `{}`", capability);
+    }
+
+    let _ = writeln!(out);
+    let _ = writeln!(out, "## Error codes");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "| Name | Code |");
+    let _ = writeln!(out, "|------|------|");
+    for (name, code) in error_codes() {
+        let _ = writeln!(out, "| `{}` | {} |", name, code);
+    }
+
+    let _ = writeln!(out);
+    let _ = writeln!(out, "## Control methods");
+    for method in ControlMethod::ALL {
+        let _ = writeln!(out);
+        let _ = writeln!(out, "### `{}`", method.as_str());
+        if RESERVED_METHODS.contains(&method) {
+            let _ = writeln!(out);
+            let _ = writeln!(out, "Reserved; not sent by this implementation.");
+            continue;
+        }
+        for spec in messages().iter().filter(|spec| spec.method == Some(method)) {
+            render_message(&mut out, spec);
+        }
+    }
+
+    let _ = writeln!(out);
+    let _ = writeln!(out, "## Control stream errors");
+    for spec in messages().iter().filter(|spec| spec.method.is_none()) {
+        render_message(&mut out, spec);
+    }
+
+    let _ = writeln!(out);
+    let _ = writeln!(out, "## Stream preambles and frames");
+    for framing in framings() {
+        let example = framing.example();
+        let _ = writeln!(out);
+        let _ = writeln!(
+            out,
+            "### `{}` ({})",
+            framing.fixture,
+            framing.direction.as_str()
+        );
+        let _ = writeln!(out);
+        let _ = writeln!(out, "{}", framing.summary);
+        let _ = writeln!(out);
+        if let Value::Object(fields) = &example["fields"] {
+            for (name, value) in fields {
+                let _ = writeln!(out, "- `{}`: {}", name, json_type(value));
+            }
+        }
+        let _ = writeln!(
+            out,
+            "- Example: `{}`",
+            example["encoded"].as_str().unwrap_or_default()
+        );
+    }
+    out
+}
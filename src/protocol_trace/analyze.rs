@@ -0,0 +1,582 @@
+/// 跟踪文件分析
+///
+/// 按会话还原时间线（认证 → 配置 → 各 stream 及其结果），并标记异常：
+/// 没有响应的请求、找不到请求的响应、没有确认的 stream、无法解析的控制帧等
+use super::{TraceDirection, TraceEvent, TraceRecord, TraceSide, TraceStreamKind};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// 时间线步骤所属阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Auth,
+    Config,
+    Control,
+    Stream,
+}
+
+impl Phase {
+    /// 序列化时的名称
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Phase::Auth => "auth",
+            Phase::Config => "config",
+            Phase::Control => "control",
+            Phase::Stream => "stream",
+        }
+    }
+
+    fn of_method(method: &str) -> Self {
+        match method {
+            "identity_challenge" | "authenticate" => Phase::Auth,
+            "submit_config" | "update_config" | "remove_proxies" | "push_config_status" => {
+                Phase::Config
+            }
+            _ => Phase::Control,
+        }
+    }
+}
+
+/// 步骤结果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", content = "detail", rename_all = "snake_case")]
+pub enum Outcome {
+    /// 请求成功或 stream 已确认
+    Ok,
+    /// 配置被部分拒绝（拒绝的代理）
+    Rejected(String),
+    /// 请求返回错误或 stream 被拒绝
+    Failed(String),
+    /// 通知（不需要响应）
+    Notified,
+    /// 请求没有收到响应
+    Unanswered,
+    /// stream 没有收到确认
+    Unconfirmed,
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Ok => write!(f, "ok"),
+            Outcome::Rejected(detail) => write!(f, "rejected: {}", detail),
+            Outcome::Failed(detail) => write!(f, "failed: {}", detail),
+            Outcome::Notified => write!(f, "notified"),
+            Outcome::Unanswered => write!(f, "UNANSWERED"),
+            Outcome::Unconfirmed => write!(f, "UNCONFIRMED"),
+        }
+    }
+}
+
+/// 时间线中的一步
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Step {
+    /// 相对会话开始的毫秒数
+    pub offset_ms: u64,
+    pub phase: Phase,
+    pub direction: TraceDirection,
+    /// 方法名称或 stream 描述（如 `visitor web:8080`）
+    pub label: String,
+    pub outcome: Outcome,
+}
+
+/// 单个会话的时间线
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionTimeline {
+    pub side: TraceSide,
+    pub session: u64,
+    /// 会话开始时间（Unix 毫秒时间戳）
+    pub started_ms: u64,
+    pub steps: Vec<Step>,
+    /// 心跳只计数，不进入时间线
+    pub heartbeats: usize,
+    /// 会话结束原因（跟踪在会话结束前截止时为 None）
+    pub end_reason: Option<String>,
+    pub anomalies: Vec<String>,
+}
+
+/// 分析结果
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceAnalysis {
+    pub sessions: Vec<SessionTimeline>,
+    /// 无法解析的行数
+    pub malformed_lines: usize,
+}
+
+impl TraceAnalysis {
+    /// 所有会话的异常总数
+    pub fn anomaly_count(&self) -> usize {
+        self.sessions.iter().map(|s| s.anomalies.len()).sum()
+    }
+}
+
+/// 会话分析状态
+struct SessionBuilder {
+    timeline: SessionTimeline,
+    /// 待响应的请求：(请求方向, id) → 步骤下标
+    pending: HashMap<(TraceDirection, String), usize>,
+    /// 需要确认的 stream：stream id → 步骤下标
+    streams: HashMap<String, usize>,
+    /// 等待确认的心跳：(请求方向, id)
+    heartbeat_ids: HashSet<(TraceDirection, String)>,
+}
+
+impl SessionBuilder {
+    fn new(side: TraceSide, session: u64, started_ms: u64) -> Self {
+        Self {
+            timeline: SessionTimeline {
+                side,
+                session,
+                started_ms,
+                steps: Vec::new(),
+                heartbeats: 0,
+                end_reason: None,
+                anomalies: Vec::new(),
+            },
+            pending: HashMap::new(),
+            streams: HashMap::new(),
+            heartbeat_ids: HashSet::new(),
+        }
+    }
+
+    fn push(
+        &mut self,
+        timestamp_ms: u64,
+        phase: Phase,
+        direction: TraceDirection,
+        label: String,
+        outcome: Outcome,
+    ) -> usize {
+        self.timeline.steps.push(Step {
+            offset_ms: timestamp_ms.saturating_sub(self.timeline.started_ms),
+            phase,
+            direction,
+            label,
+            outcome,
+        });
+        self.timeline.steps.len() - 1
+    }
+
+    fn control(&mut self, timestamp_ms: u64, direction: TraceDirection, frame: Option<&Value>) {
+        let Some(frame) = frame else {
+            self.timeline
+                .anomalies
+                .push(format!("Malformed control frame ({})", direction.as_str()));
+            return;
+        };
+        let id = frame.get("id").filter(|id| !id.is_null());
+
+        match (frame.get("method").and_then(Value::as_str), id) {
+            (Some("heartbeat"), id) => {
+                self.timeline.heartbeats += 1;
+                if let Some(id) = id {
+                    self.heartbeat_ids.insert((direction, id.to_string()));
+                }
+            }
+            (Some(method), None) => {
+                self.push(
+                    timestamp_ms,
+                    Phase::of_method(method),
+                    direction,
+                    method.to_string(),
+                    Outcome::Notified,
+                );
+            }
+            (Some(method), Some(id)) => {
+                if self.timeline.steps.is_empty() && Phase::of_method(method) != Phase::Auth {
+                    self.timeline.anomalies.push(format!(
+                        "First request is '{}' instead of 'authenticate'",
+                        method
+                    ));
+                }
+                let index = self.push(
+                    timestamp_ms,
+                    Phase::of_method(method),
+                    direction,
+                    method.to_string(),
+                    Outcome::Unanswered,
+                );
+                self.pending.insert((direction, id.to_string()), index);
+            }
+            (None, id) => self.response(direction, id, frame),
+        }
+    }
+
+    fn response(&mut self, direction: TraceDirection, id: Option<&Value>, frame: &Value) {
+        let error = frame
+            .get("error")
+            .filter(|e| !e.is_null())
+            .map(|e| e["message"].as_str().unwrap_or("unknown error").to_string());
+
+        let Some(id) = id else {
+            // 无法关联到请求的错误（例如对端无法解析的消息）
+            self.timeline.anomalies.push(format!(
+                "Peer reported an error without request id: {}",
+                error.as_deref().unwrap_or("no error message")
+            ));
+            return;
+        };
+        let key = (direction.reverse(), id.to_string());
+        if self.heartbeat_ids.remove(&key) {
+            return;
+        }
+        let Some(index) = self.pending.remove(&key) else {
+            self.timeline
+                .anomalies
+                .push(format!("Response with id {} has no matching request", id));
+            return;
+        };
+
+        let rejected: Vec<&str> = frame
+            .pointer("/result/rejected_proxies")
+            .and_then(Value::as_array)
+            .map(|items| items.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        self.timeline.steps[index].outcome = match error {
+            Some(error) => Outcome::Failed(error),
+            None if !rejected.is_empty() => Outcome::Rejected(rejected.join(", ")),
+            None => Outcome::Ok,
+        };
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn stream_open(
+        &mut self,
+        timestamp_ms: u64,
+        direction: TraceDirection,
+        stream_id: &str,
+        kind: TraceStreamKind,
+        name: &str,
+        publish_port: u16,
+        target: Option<&str>,
+    ) {
+        let label = match (kind, target) {
+            (TraceStreamKind::Forward, Some(target)) => format!("forward {}", target),
+            (TraceStreamKind::VisitorMux, _) => format!(
+                "visitor_mux {}:{}",
+                name.trim_start_matches(crate::protocol::VISITOR_MUX_NAME_PREFIX),
+                publish_port
+            ),
+            (TraceStreamKind::Proxy, _) if name.is_empty() => format!("proxy :{}", publish_port),
+            (TraceStreamKind::Proxy, _) => format!("proxy {}:{}", name, publish_port),
+            _ => format!("visitor {}:{}", name, publish_port),
+        };
+        let outcome = if kind.expects_confirmation() {
+            Outcome::Unconfirmed
+        } else {
+            Outcome::Ok
+        };
+        let index = self.push(timestamp_ms, Phase::Stream, direction, label, outcome);
+        if kind.expects_confirmation() {
+            self.streams.insert(stream_id.to_string(), index);
+        }
+    }
+
+    fn stream_confirm(&mut self, stream_id: &str, accepted: bool, error: Option<&str>) {
+        let Some(index) = self.streams.remove(stream_id) else {
+            self.timeline.anomalies.push(format!(
+                "Confirmation for stream {} without preamble",
+                stream_id
+            ));
+            return;
+        };
+        self.timeline.steps[index].outcome = if accepted {
+            Outcome::Ok
+        } else {
+            Outcome::Failed(error.unwrap_or("rejected").to_string())
+        };
+    }
+
+    /// 结束分析，为未完成的请求和 stream 生成异常
+    fn finish(mut self) -> SessionTimeline {
+        let mut open: Vec<usize> = self
+            .pending
+            .values()
+            .chain(self.streams.values())
+            .copied()
+            .collect();
+        open.sort_unstable();
+        for index in open {
+            let step = &self.timeline.steps[index];
+            let anomaly = match step.outcome {
+                Outcome::Unanswered => format!(
+                    "Request '{}' at +{}ms was never answered",
+                    step.label, step.offset_ms
+                ),
+                _ => format!(
+                    "Stream '{}' at +{}ms was never confirmed",
+                    step.label, step.offset_ms
+                ),
+            };
+            self.timeline.anomalies.push(anomaly);
+        }
+        self.timeline
+    }
+}
+
+/// 分析跟踪记录（按文件顺序）
+///
+/// 多个会话可以交错出现；同一编号再次出现 `session_start`（例如进程重启后追加写入）时开始新的会话
+pub fn analyze(records: &[TraceRecord]) -> Vec<SessionTimeline> {
+    let mut finished = Vec::new();
+    let mut active: HashMap<(TraceSide, u64), SessionBuilder> = HashMap::new();
+
+    for record in records {
+        let key = (record.side, record.session);
+        if record.event == TraceEvent::SessionStart {
+            if let Some(previous) = active.remove(&key) {
+                finished.push(previous.finish());
+            }
+        }
+        let session = active.entry(key).or_insert_with(|| {
+            SessionBuilder::new(record.side, record.session, record.timestamp_ms)
+        });
+
+        match &record.event {
+            TraceEvent::SessionStart => {}
+            TraceEvent::SessionEnd { reason } => {
+                session.timeline.end_reason = Some(reason.clone());
+                if let Some(session) = active.remove(&key) {
+                    finished.push(session.finish());
+                }
+            }
+            TraceEvent::Control {
+                direction, frame, ..
+            } => session.control(record.timestamp_ms, *direction, frame.as_ref()),
+            TraceEvent::StreamOpen {
+                direction,
+                stream_id,
+                stream,
+                name,
+                publish_port,
+                target,
+                ..
+            } => session.stream_open(
+                record.timestamp_ms,
+                *direction,
+                stream_id,
+                *stream,
+                name,
+                *publish_port,
+                target.as_deref(),
+            ),
+            TraceEvent::StreamConfirm {
+                stream_id,
+                accepted,
+                error,
+                ..
+            } => session.stream_confirm(stream_id, *accepted, error.as_deref()),
+        }
+    }
+
+    finished.extend(active.into_values().map(SessionBuilder::finish));
+    finished.sort_by_key(|s| (s.started_ms, s.session));
+    finished
+}
+
+/// 读取并分析跟踪文件（无法解析的行计数后跳过）
+pub fn analyze_file(path: &Path) -> Result<TraceAnalysis> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read trace file {}", path.display()))?;
+
+    let mut records = Vec::new();
+    let mut malformed_lines = 0;
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        match serde_json::from_str::<TraceRecord>(line) {
+            Ok(record) => records.push(record),
+            Err(_) => malformed_lines += 1,
+        }
+    }
+
+    Ok(TraceAnalysis {
+        sessions: analyze(&records),
+        malformed_lines,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record(timestamp_ms: u64, event: TraceEvent) -> TraceRecord {
+        TraceRecord {
+            timestamp_ms,
+            side: TraceSide::Server,
+            session: 1,
+            event,
+        }
+    }
+
+    fn control(timestamp_ms: u64, direction: TraceDirection, frame: Value) -> TraceRecord {
+        record(
+            timestamp_ms,
+            TraceEvent::Control {
+                direction,
+                len: frame.to_string().len(),
+                frame: Some(frame),
+                raw: None,
+            },
+        )
+    }
+
+    fn visitor_open(timestamp_ms: u64, stream_id: &str) -> TraceRecord {
+        record(
+            timestamp_ms,
+            TraceEvent::StreamOpen {
+                direction: TraceDirection::In,
+                stream_id: stream_id.to_string(),
+                stream: TraceStreamKind::Visitor,
+                name: "web".to_string(),
+                publish_port: 8080,
+                target: None,
+                len: Some(7),
+                peer: None,
+            },
+        )
+    }
+
+    #[test]
+    fn test_reconstructs_timeline() {
+        let records = vec![
+            record(1000, TraceEvent::SessionStart),
+            control(
+                1001,
+                TraceDirection::In,
+                json!({"jsonrpc": "2.0", "method": "authenticate", "params": {}, "id": 1}),
+            ),
+            control(
+                1002,
+                TraceDirection::Out,
+                json!({"jsonrpc": "2.0", "result": {"client_id": "c"}, "id": 1}),
+            ),
+            control(
+                1003,
+                TraceDirection::In,
+                json!({"jsonrpc": "2.0", "method": "submit_config", "params": {}, "id": 2}),
+            ),
+            control(
+                1004,
+                TraceDirection::Out,
+                json!({"jsonrpc": "2.0", "result": {"rejected_proxies": ["db:5432"]}, "id": 2}),
+            ),
+            control(
+                1005,
+                TraceDirection::In,
+                json!({"jsonrpc": "2.0", "method": "heartbeat", "params": null}),
+            ),
+            control(
+                1006,
+                TraceDirection::In,
+                json!({"jsonrpc": "2.0", "method": "heartbeat", "params": {"seq": 1, "timestamp_ms": 5}, "id": 3}),
+            ),
+            control(
+                1007,
+                TraceDirection::Out,
+                json!({"jsonrpc": "2.0", "result": {"seq": 1, "timestamp_ms": 5}, "id": 3}),
+            ),
+            visitor_open(1010, "5"),
+            record(
+                1011,
+                TraceEvent::StreamConfirm {
+                    direction: TraceDirection::Out,
+                    stream_id: "5".to_string(),
+                    accepted: false,
+                    error: Some("Proxy not found".to_string()),
+                },
+            ),
+            record(
+                1020,
+                TraceEvent::SessionEnd {
+                    reason: "closed".to_string(),
+                },
+            ),
+        ];
+
+        let sessions = analyze(&records);
+        assert_eq!(sessions.len(), 1);
+        let session = &sessions[0];
+        assert!(session.anomalies.is_empty(), "{:?}", session.anomalies);
+        assert_eq!(session.heartbeats, 2);
+        assert_eq!(session.end_reason.as_deref(), Some("closed"));
+
+        let steps: Vec<_> = session
+            .steps
+            .iter()
+            .map(|s| (s.offset_ms, s.phase, s.label.as_str(), s.outcome.clone()))
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                (1, Phase::Auth, "authenticate", Outcome::Ok),
+                (
+                    3,
+                    Phase::Config,
+                    "submit_config",
+                    Outcome::Rejected("db:5432".to_string())
+                ),
+                (
+                    10,
+                    Phase::Stream,
+                    "visitor web:8080",
+                    Outcome::Failed("Proxy not found".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flags_anomalies() {
+        let records = vec![
+            record(0, TraceEvent::SessionStart),
+            control(
+                1,
+                TraceDirection::Out,
+                json!({"jsonrpc": "2.0", "method": "authenticate", "params": {}, "id": 1}),
+            ),
+            control(
+                2,
+                TraceDirection::In,
+                json!({"jsonrpc": "2.0", "result": {}, "id": 7}),
+            ),
+            visitor_open(3, "9"),
+            record(
+                4,
+                TraceEvent::Control {
+                    direction: TraceDirection::In,
+                    len: 3,
+                    frame: None,
+                    raw: Some("???".to_string()),
+                },
+            ),
+        ];
+
+        let sessions = analyze(&records);
+        let session = &sessions[0];
+        assert_eq!(session.end_reason, None);
+        assert_eq!(session.steps[0].outcome, Outcome::Unanswered);
+        assert_eq!(session.steps[1].outcome, Outcome::Unconfirmed);
+        assert_eq!(
+            session.anomalies,
+            vec![
+                "Response with id 7 has no matching request".to_string(),
+                "Malformed control frame (in)".to_string(),
+                "Request 'authenticate' at +1ms was never answered".to_string(),
+                "Stream 'visitor web:8080' at +3ms was never confirmed".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_restarted_session_numbers_start_new_sessions() {
+        let records = vec![
+            record(0, TraceEvent::SessionStart),
+            record(100, TraceEvent::SessionStart),
+        ];
+        assert_eq!(analyze(&records).len(), 2);
+    }
+}
@@ -0,0 +1,607 @@
+/// 协议跟踪
+///
+/// 配置 `protocol_trace_path` 后，控制通道的每一帧（方向、时间戳、JSON 原文）和每个 stream 前导
+/// （stream id、名称/端口/目标、确认结果）以 JSON 行追加写入跟踪文件，用于排查协议层问题。
+/// 从不记录 stream 上转发的数据，只记录长度；认证密钥写入前被替换。
+/// 文件超过大小上限时轮转为 `<path>.1`、`<path>.2` ……，`tls-tunnel trace analyze` 读取跟踪文件
+/// 还原会话时间线
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+pub mod analyze;
+
+/// 单个跟踪文件的大小上限（字节），超过后轮转
+pub const TRACE_MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// 轮转保留的历史文件数（`<path>.1` 为最近的一个）
+pub const TRACE_ROTATED_FILES: usize = 3;
+
+/// 无法解析为 JSON 的控制帧最多记录的字节数
+const MAX_RAW_FRAME_BYTES: usize = 1024;
+
+/// 跟踪中替换认证密钥的内容
+pub const REDACTED: &str = "<redacted>";
+
+/// 写入跟踪的一端
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceSide {
+    Client,
+    Server,
+}
+
+impl TraceSide {
+    /// 序列化时的名称
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TraceSide::Client => "client",
+            TraceSide::Server => "server",
+        }
+    }
+}
+
+/// 方向（相对写入跟踪的一端）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceDirection {
+    /// 收到
+    In,
+    /// 发出
+    Out,
+}
+
+impl TraceDirection {
+    /// 序列化时的名称
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TraceDirection::In => "in",
+            TraceDirection::Out => "out",
+        }
+    }
+
+    /// 相反方向（请求和响应的方向相反）
+    pub fn reverse(self) -> Self {
+        match self {
+            TraceDirection::In => TraceDirection::Out,
+            TraceDirection::Out => TraceDirection::In,
+        }
+    }
+}
+
+/// stream 类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceStreamKind {
+    /// 服务器发往代理所属客户端的 stream（只有 publish_port，没有确认）
+    Proxy,
+    /// visitor stream
+    Visitor,
+    /// 复用模式的 visitor stream（`@mux:name`）
+    VisitorMux,
+    /// forwarder 请求服务器连接外部目标（`@forward:host:port`）
+    Forward,
+}
+
+impl TraceStreamKind {
+    /// 根据前导名称判断类型，forward 请求同时返回目标地址
+    pub fn classify(name: &str) -> (Self, Option<String>) {
+        if let Some(target) = name.strip_prefix(crate::protocol::FORWARD_NAME_PREFIX) {
+            (TraceStreamKind::Forward, Some(target.to_string()))
+        } else if name.starts_with(crate::protocol::VISITOR_MUX_NAME_PREFIX) {
+            (TraceStreamKind::VisitorMux, None)
+        } else {
+            (TraceStreamKind::Visitor, None)
+        }
+    }
+
+    /// 服务器是否会回复确认字节
+    pub fn expects_confirmation(self) -> bool {
+        self != TraceStreamKind::Proxy
+    }
+}
+
+/// 跟踪文件中的一行
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceRecord {
+    /// 记录时间（Unix 毫秒时间戳）
+    pub timestamp_ms: u64,
+    pub side: TraceSide,
+    /// 会话编号（进程内从 1 开始递增，一个进程的多个会话可以交错写入同一文件）
+    pub session: u64,
+    #[serde(flatten)]
+    pub event: TraceEvent,
+}
+
+/// 跟踪事件（序列化时 `kind` 字段为事件名称）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TraceEvent {
+    /// 会话开始（传输和 yamux 连接已建立）
+    SessionStart,
+    /// 会话结束
+    SessionEnd { reason: String },
+    /// 控制通道的一帧（`frame` 为 JSON 内容；无法解析时 `raw` 为截断的原文）
+    Control {
+        direction: TraceDirection,
+        len: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        frame: Option<Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        raw: Option<String>,
+    },
+    /// stream 前导
+    StreamOpen {
+        direction: TraceDirection,
+        stream_id: String,
+        stream: TraceStreamKind,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        name: String,
+        publish_port: u16,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        target: Option<String>,
+        /// 前导字节数（未知时省略）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        len: Option<usize>,
+        /// 服务器上报的外部连接来源地址（开启了 report_peers 的代理）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        peer: Option<String>,
+    },
+    /// 服务器对 stream 前导的确认（1 字节，拒绝时附带错误消息）
+    StreamConfirm {
+        direction: TraceDirection,
+        stream_id: String,
+        accepted: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+/// 当前 Unix 毫秒时间戳
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// 第 `index` 个轮转文件的路径（`<path>.<index>`）
+pub fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", index));
+    PathBuf::from(rotated)
+}
+
+/// 替换帧中的认证密钥
+fn redact(frame: &mut Value) {
+    if let Some(auth_key) = frame
+        .get_mut("params")
+        .and_then(|params| params.get_mut("auth_key"))
+    {
+        *auth_key = Value::String(REDACTED.to_string());
+    }
+}
+
+/// 跟踪文件（按大小轮转）
+struct TraceFile {
+    path: PathBuf,
+    file: Option<File>,
+    size: u64,
+    max_size: u64,
+    /// 写入失败后只警告一次
+    failed: bool,
+}
+
+impl TraceFile {
+    fn open(path: &Path, max_size: u64) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Some(file),
+            size,
+            max_size,
+            failed: false,
+        })
+    }
+
+    fn append(&mut self, line: &[u8]) {
+        let result = (|| {
+            if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+                self.rotate()?;
+            }
+            let file = match self.file.as_mut() {
+                Some(file) => file,
+                None => self.file.insert(
+                    OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&self.path)?,
+                ),
+            };
+            file.write_all(line)?;
+            self.size += line.len() as u64;
+            Ok::<_, std::io::Error>(())
+        })();
+
+        match result {
+            Ok(()) => self.failed = false,
+            Err(e) if !self.failed => {
+                self.failed = true;
+                warn!(
+                    "Failed to write protocol trace {}: {}",
+                    self.path.display(),
+                    e
+                );
+            }
+            Err(_) => {}
+        }
+    }
+
+    /// 当前文件改名为 `<path>.1`，已有的历史文件依次后移，最旧的被覆盖
+    fn rotate(&mut self) -> std::io::Result<()> {
+        // 先关闭当前文件（Windows 不能重命名打开的文件）
+        self.file = None;
+        for index in (1..TRACE_ROTATED_FILES).rev() {
+            let from = rotated_path(&self.path, index);
+            if from.exists() {
+                std::fs::rename(&from, rotated_path(&self.path, index + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+struct TraceInner {
+    side: TraceSide,
+    file: Mutex<TraceFile>,
+    next_session: AtomicU64,
+}
+
+/// 协议跟踪（未配置 `protocol_trace_path` 时为空操作）
+#[derive(Clone, Default)]
+pub struct ProtocolTrace {
+    inner: Option<Arc<TraceInner>>,
+}
+
+impl ProtocolTrace {
+    /// 不记录任何内容的跟踪
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// 打开（追加写入）跟踪文件
+    pub fn open(path: &Path, side: TraceSide) -> std::io::Result<Self> {
+        Self::with_max_size(path, side, TRACE_MAX_FILE_SIZE)
+    }
+
+    /// 打开跟踪文件，单个文件超过 `max_size` 字节时轮转
+    pub fn with_max_size(path: &Path, side: TraceSide, max_size: u64) -> std::io::Result<Self> {
+        let file = TraceFile::open(path, max_size)?;
+        Ok(Self {
+            inner: Some(Arc::new(TraceInner {
+                side,
+                file: Mutex::new(file),
+                next_session: AtomicU64::new(1),
+            })),
+        })
+    }
+
+    /// 按配置创建（文件无法打开时记录警告并关闭跟踪，不影响隧道运行）
+    pub fn from_config(path: Option<&Path>, side: TraceSide) -> Self {
+        let Some(path) = path else {
+            return Self::disabled();
+        };
+        match Self::open(path, side) {
+            Ok(trace) => {
+                warn!(
+                    "Protocol trace enabled, writing control frames and stream preambles to {}",
+                    path.display()
+                );
+                trace
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to open protocol trace {}: {}, tracing disabled",
+                    path.display(),
+                    e
+                );
+                Self::disabled()
+            }
+        }
+    }
+
+    /// 是否在记录
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// 开始一个新会话并记录 `session_start`
+    pub fn session(&self) -> SessionTrace {
+        let Some(inner) = &self.inner else {
+            return SessionTrace::default();
+        };
+        let session = inner.next_session.fetch_add(1, Ordering::Relaxed);
+        info!("Protocol trace session {} started", session);
+        let trace = SessionTrace {
+            trace: self.clone(),
+            session,
+        };
+        trace.record(TraceEvent::SessionStart);
+        trace
+    }
+}
+
+/// 单个会话的跟踪
+#[derive(Clone, Default)]
+pub struct SessionTrace {
+    trace: ProtocolTrace,
+    session: u64,
+}
+
+impl SessionTrace {
+    /// 是否在记录
+    pub fn is_enabled(&self) -> bool {
+        self.trace.is_enabled()
+    }
+
+    fn record(&self, event: TraceEvent) {
+        let Some(inner) = &self.trace.inner else {
+            return;
+        };
+        let record = TraceRecord {
+            timestamp_ms: now_ms(),
+            side: inner.side,
+            session: self.session,
+            event,
+        };
+        let Ok(mut line) = serde_json::to_vec(&record) else {
+            return;
+        };
+        line.push(b'\n');
+        inner.file.lock().append(&line);
+    }
+
+    /// 记录一个控制帧（长度前缀之后的内容）
+    pub fn control(&self, direction: TraceDirection, frame: &[u8]) {
+        if !self.is_enabled() {
+            return;
+        }
+        let (frame_json, raw) = match serde_json::from_slice::<Value>(frame) {
+            Ok(mut value) => {
+                redact(&mut value);
+                (Some(value), None)
+            }
+            Err(_) => {
+                let raw = &frame[..frame.len().min(MAX_RAW_FRAME_BYTES)];
+                (None, Some(String::from_utf8_lossy(raw).into_owned()))
+            }
+        };
+        self.record(TraceEvent::Control {
+            direction,
+            len: frame.len(),
+            frame: frame_json,
+            raw,
+        });
+    }
+
+    /// 某个 stream 的跟踪
+    pub fn stream(&self, stream_id: impl Display) -> StreamTrace {
+        let stream_id = if self.is_enabled() {
+            stream_id.to_string()
+        } else {
+            String::new()
+        };
+        StreamTrace {
+            session: self.clone(),
+            stream_id,
+        }
+    }
+
+    /// 记录会话结束
+    pub fn end(&self, reason: &str) {
+        self.record(TraceEvent::SessionEnd {
+            reason: reason.to_string(),
+        });
+    }
+}
+
+/// 跨会话存在的组件（如隧道断开后保持的本地监听器）使用的跟踪入口，总是指向当前会话
+#[derive(Clone, Default)]
+pub struct CurrentTrace(Arc<RwLock<SessionTrace>>);
+
+impl CurrentTrace {
+    /// 指向固定会话的入口
+    pub fn new(trace: SessionTrace) -> Self {
+        Self(Arc::new(RwLock::new(trace)))
+    }
+
+    /// 切换到新的会话（会话结束后设为未启用的跟踪）
+    pub fn set(&self, trace: SessionTrace) {
+        *self.0.write() = trace;
+    }
+
+    /// 当前会话的跟踪
+    pub fn get(&self) -> SessionTrace {
+        self.0.read().clone()
+    }
+}
+
+/// 单个 stream 的跟踪
+#[derive(Clone, Default)]
+pub struct StreamTrace {
+    session: SessionTrace,
+    stream_id: String,
+}
+
+impl StreamTrace {
+    /// 记录 visitor/forwarder 前导（u16 名称长度 + 名称 + u16 publish_port）
+    pub fn preamble(&self, direction: TraceDirection, name: &str, publish_port: u16) {
+        if !self.session.is_enabled() {
+            return;
+        }
+        let (stream, target) = TraceStreamKind::classify(name);
+        self.session.record(TraceEvent::StreamOpen {
+            direction,
+            stream_id: self.stream_id.clone(),
+            stream,
+            name: name.to_string(),
+            publish_port,
+            target,
+            len: Some(4 + name.len()),
+            peer: None,
+        });
+    }
+
+    /// 记录服务器发往代理所属客户端的 stream（`len` 为前导字节数，未知时为 None；
+    /// `peer` 为前导携带的外部连接来源地址）
+    pub fn proxy(
+        &self,
+        direction: TraceDirection,
+        name: &str,
+        publish_port: u16,
+        len: Option<usize>,
+        peer: Option<&str>,
+    ) {
+        if !self.session.is_enabled() {
+            return;
+        }
+        self.session.record(TraceEvent::StreamOpen {
+            direction,
+            stream_id: self.stream_id.clone(),
+            stream: TraceStreamKind::Proxy,
+            name: name.to_string(),
+            publish_port,
+            target: None,
+            len,
+            peer: peer.map(str::to_string),
+        });
+    }
+
+    /// 记录服务器的确认结果
+    pub fn confirm(&self, direction: TraceDirection, accepted: bool, error: Option<&str>) {
+        if !self.session.is_enabled() {
+            return;
+        }
+        self.session.record(TraceEvent::StreamConfirm {
+            direction,
+            stream_id: self.stream_id.clone(),
+            accepted,
+            error: error.map(str::to_string),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{}-{}.jsonl", name, uuid::Uuid::new_v4()))
+    }
+
+    fn read_records(path: &Path) -> Vec<TraceRecord> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_records_frames_and_redacts_auth_key() {
+        let path = temp_path("trace-redact");
+        let trace = ProtocolTrace::open(&path, TraceSide::Client).unwrap();
+        let session = trace.session();
+        session.control(
+            TraceDirection::Out,
+            br#"{"jsonrpc":"2.0","method":"authenticate","params":{"auth_key":"secret"},"id":1}"#,
+        );
+        session.control(TraceDirection::In, b"not json");
+        session
+            .stream(3)
+            .preamble(TraceDirection::Out, "@forward:example.com:443", 0);
+
+        let records = read_records(&path);
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].event, TraceEvent::SessionStart);
+        assert_eq!(records[0].session, 1);
+        match &records[1].event {
+            TraceEvent::Control {
+                direction, frame, ..
+            } => {
+                assert_eq!(*direction, TraceDirection::Out);
+                assert_eq!(frame.as_ref().unwrap()["params"]["auth_key"], REDACTED);
+            }
+            other => panic!("Unexpected event: {:?}", other),
+        }
+        match &records[2].event {
+            TraceEvent::Control { len, raw, .. } => {
+                assert_eq!(*len, 8);
+                assert_eq!(raw.as_deref(), Some("not json"));
+            }
+            other => panic!("Unexpected event: {:?}", other),
+        }
+        match &records[3].event {
+            TraceEvent::StreamOpen {
+                stream,
+                target,
+                len,
+                ..
+            } => {
+                assert_eq!(*stream, TraceStreamKind::Forward);
+                assert_eq!(target.as_deref(), Some("example.com:443"));
+                assert_eq!(*len, Some(28));
+            }
+            other => panic!("Unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_rotates_by_size() {
+        let path = temp_path("trace-rotate");
+        let trace = ProtocolTrace::with_max_size(&path, TraceSide::Server, 512).unwrap();
+        let session = trace.session();
+        for _ in 0..20 {
+            session.control(
+                TraceDirection::In,
+                br#"{"jsonrpc":"2.0","method":"heartbeat"}"#,
+            );
+        }
+
+        let current = std::fs::metadata(&path).unwrap().len();
+        let rotated = rotated_path(&path, 1);
+        assert!(current <= 512);
+        assert!(rotated.exists());
+        assert!(!rotated_path(&path, TRACE_ROTATED_FILES + 1).exists());
+
+        for index in 0..=TRACE_ROTATED_FILES {
+            let file = if index == 0 {
+                path.clone()
+            } else {
+                rotated_path(&path, index)
+            };
+            std::fs::remove_file(file).ok();
+        }
+    }
+
+    #[test]
+    fn test_disabled_trace_is_noop() {
+        let session = ProtocolTrace::disabled().session();
+        assert!(!session.is_enabled());
+        session.control(TraceDirection::In, b"{}");
+        session.stream(1).confirm(TraceDirection::Out, true, None);
+    }
+}
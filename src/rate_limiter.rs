@@ -3,12 +3,17 @@
 /// 使用 token bucket 算法实现速率限制，防止 DoS 攻击
 use governor::{
     clock::{Clock, DefaultClock},
+    middleware::StateInformationMiddleware,
     state::{InMemoryState, NotKeyed},
     Quota, RateLimiter as GovernorLimiter,
 };
+use parking_lot::Mutex;
 use std::num::NonZeroU32;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+/// 统计放行/拒绝次数的窗口长度
+pub const USAGE_WINDOW: Duration = Duration::from_secs(60);
 
 /// 速率限制器配置
 #[derive(Debug, Clone)]
@@ -28,10 +33,92 @@ impl Default for RateLimiterConfig {
     }
 }
 
+/// 速率限制器的当前用量
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimiterUsage {
+    /// 当前可用的令牌数（按上次检查后的补充速率估算）
+    pub available_tokens: u32,
+    /// 最近两个统计窗口（上一个完整窗口加当前窗口）内放行的请求数
+    pub recent_allowed: u64,
+    /// 最近两个统计窗口内被拒绝的请求数
+    pub recent_rejected: u64,
+}
+
+/// 放行/拒绝计数及上次检查后剩余的令牌数
+struct UsageWindow {
+    started: Instant,
+    allowed: u64,
+    rejected: u64,
+    previous_allowed: u64,
+    previous_rejected: u64,
+    remaining: u32,
+    checked_at: Instant,
+}
+
+impl UsageWindow {
+    fn new(burst_size: u32, now: Instant) -> Self {
+        Self {
+            started: now,
+            allowed: 0,
+            rejected: 0,
+            previous_allowed: 0,
+            previous_rejected: 0,
+            remaining: burst_size,
+            checked_at: now,
+        }
+    }
+
+    /// 当前窗口结束后滚动到下一个窗口（间隔超过两个窗口时清空上一个窗口）
+    fn roll(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.started);
+        if elapsed < USAGE_WINDOW {
+            return;
+        }
+        if elapsed < USAGE_WINDOW * 2 {
+            self.previous_allowed = self.allowed;
+            self.previous_rejected = self.rejected;
+        } else {
+            self.previous_allowed = 0;
+            self.previous_rejected = 0;
+        }
+        self.allowed = 0;
+        self.rejected = 0;
+        self.started = now;
+    }
+
+    fn record(&mut self, now: Instant, remaining: Option<u32>) {
+        self.roll(now);
+        match remaining {
+            Some(remaining) => {
+                self.allowed += 1;
+                self.remaining = remaining;
+            }
+            None => {
+                self.rejected += 1;
+                self.remaining = 0;
+            }
+        }
+        self.checked_at = now;
+    }
+
+    fn usage(&mut self, now: Instant, config: &RateLimiterConfig) -> RateLimiterUsage {
+        self.roll(now);
+        let refilled = now.saturating_duration_since(self.checked_at).as_secs_f64()
+            * config.requests_per_second as f64;
+        let available = (self.remaining as f64 + refilled).min(config.burst_size as f64);
+        RateLimiterUsage {
+            available_tokens: available as u32,
+            recent_allowed: self.previous_allowed + self.allowed,
+            recent_rejected: self.previous_rejected + self.rejected,
+        }
+    }
+}
+
 /// 速率限制器包装器
 pub struct RateLimiter {
-    inner: Arc<GovernorLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    inner: Arc<GovernorLimiter<NotKeyed, InMemoryState, DefaultClock, StateInformationMiddleware>>,
     config: RateLimiterConfig,
+    usage: Arc<Mutex<UsageWindow>>,
 }
 
 impl RateLimiter {
@@ -42,10 +129,16 @@ impl RateLimiter {
         )
         .allow_burst(NonZeroU32::new(config.burst_size).expect("burst_size must be > 0"));
 
-        let limiter = Arc::new(GovernorLimiter::direct(quota));
+        let limiter = Arc::new(
+            GovernorLimiter::direct(quota).with_middleware::<StateInformationMiddleware>(),
+        );
 
         Self {
             inner: limiter,
+            usage: Arc::new(Mutex::new(UsageWindow::new(
+                config.burst_size,
+                Instant::now(),
+            ))),
             config,
         }
     }
@@ -59,8 +152,14 @@ impl RateLimiter {
     /// 返回 Ok(()) 如果允许请求，否则返回 Err(Duration) 表示需要等待的时间
     pub fn check(&self) -> Result<(), Duration> {
         match self.inner.check() {
-            Ok(_) => Ok(()),
+            Ok(snapshot) => {
+                self.usage
+                    .lock()
+                    .record(Instant::now(), Some(snapshot.remaining_burst_capacity()));
+                Ok(())
+            }
             Err(not_until) => {
+                self.usage.lock().record(Instant::now(), None);
                 let wait_time = not_until.wait_time_from(DefaultClock::default().now());
                 Err(wait_time)
             }
@@ -83,6 +182,11 @@ impl RateLimiter {
     pub fn config(&self) -> &RateLimiterConfig {
         &self.config
     }
+
+    /// 当前可用令牌数及最近的放行/拒绝次数
+    pub fn usage(&self) -> RateLimiterUsage {
+        self.usage.lock().usage(Instant::now(), &self.config)
+    }
 }
 
 impl Clone for RateLimiter {
@@ -90,6 +194,7 @@ impl Clone for RateLimiter {
         Self {
             inner: Arc::clone(&self.inner),
             config: self.config.clone(),
+            usage: Arc::clone(&self.usage),
         }
     }
 }
@@ -164,4 +269,50 @@ mod tests {
         // 取决于 burst_size，可能被限流
         assert!(result.is_ok() || result.is_err());
     }
+
+    #[test]
+    fn test_rate_limiter_usage() {
+        let limiter = RateLimiter::new(RateLimiterConfig {
+            requests_per_second: 1,
+            burst_size: 5,
+        });
+        let usage = limiter.usage();
+        assert_eq!(usage.available_tokens, 5);
+        assert_eq!((usage.recent_allowed, usage.recent_rejected), (0, 0));
+
+        for _ in 0..7 {
+            let _ = limiter.check();
+        }
+        let usage = limiter.usage();
+        assert_eq!(usage.available_tokens, 0);
+        assert_eq!((usage.recent_allowed, usage.recent_rejected), (5, 2));
+    }
+
+    #[test]
+    fn test_usage_window_roll_and_refill() {
+        let config = RateLimiterConfig {
+            requests_per_second: 10,
+            burst_size: 20,
+        };
+        let start = Instant::now();
+        let mut window = UsageWindow::new(config.burst_size, start);
+        window.record(start, Some(3));
+        window.record(start, None);
+
+        // 令牌按速率补充，不超过突发容量
+        let usage = window.usage(start + Duration::from_millis(500), &config);
+        assert_eq!(usage.available_tokens, 5);
+        let usage = window.usage(start + Duration::from_secs(30), &config);
+        assert_eq!(usage.available_tokens, 20);
+
+        // 上一个窗口的计数保留一个窗口
+        let later = start + USAGE_WINDOW + Duration::from_secs(1);
+        window.record(later, None);
+        let usage = window.usage(later, &config);
+        assert_eq!((usage.recent_allowed, usage.recent_rejected), (1, 2));
+
+        // 长时间无请求后计数清零
+        let usage = window.usage(later + USAGE_WINDOW * 3, &config);
+        assert_eq!((usage.recent_allowed, usage.recent_rejected), (0, 0));
+    }
 }
@@ -0,0 +1,315 @@
+/// 转发缓冲区的全局内存预算
+///
+/// 每个转发连接的两个方向各需要一个拷贝缓冲区，大量并发的慢连接会让缓冲区总量失控。
+/// 配置了 `relay_memory_budget_mb` 时，转发循环从 [`RelayMemory`] 领取缓冲区：预算充裕时使用
+/// 默认大小，余量紧张时新连接得到更小的缓冲区（不小于 [`RELAY_BUFFER_FLOOR`]），
+/// 连下限也放不下时等待其他连接释放，而不是让连接失败。记账只用原子操作
+use serde::{Deserialize, Serialize};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+use tracing::debug;
+
+/// 转发缓冲区的默认大小
+pub const RELAY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// 预算紧张时缓冲区的最小大小
+pub const RELAY_BUFFER_FLOOR: usize = 4 * 1024;
+
+/// 单次领取最多占用剩余预算的比例（1/N），余量越少新连接的缓冲区越小
+const REMAINING_SHARE: u64 = 8;
+
+/// 缓冲区大小的对齐粒度
+const BUFFER_ALIGN: usize = 1024;
+
+/// 预算用量快照
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelayMemoryStats {
+    /// 预算（字节，未配置时为空）
+    pub budget_bytes: Option<u64>,
+    /// 当前领取的缓冲区字节数
+    pub used_bytes: u64,
+    /// 领取字节数的最高值
+    pub high_water_bytes: u64,
+    /// 当前领取的缓冲区数
+    pub buffers: u64,
+    /// 因预算紧张而小于默认大小的缓冲区数（累计）
+    pub reduced_buffers: u64,
+    /// 需要等待其他连接释放的领取次数（累计）
+    pub waits: u64,
+    /// 正在等待的领取数
+    pub waiting: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// 预算字节数（0 表示不限制）
+    budget: AtomicU64,
+    used: AtomicU64,
+    high_water: AtomicU64,
+    buffers: AtomicU64,
+    reduced: AtomicU64,
+    waits: AtomicU64,
+    waiting: AtomicU64,
+    released: Notify,
+}
+
+/// 转发缓冲区的字节记账（克隆共享同一份预算）
+#[derive(Clone, Default)]
+pub struct RelayMemory {
+    inner: Arc<Inner>,
+}
+
+impl std::fmt::Debug for RelayMemory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("RelayMemory")
+            .field(&self.snapshot())
+            .finish()
+    }
+}
+
+impl RelayMemory {
+    /// 创建记账（`budget_bytes` 为空时不限制，只统计用量）
+    pub fn new(budget_bytes: Option<u64>) -> Self {
+        let memory = Self::default();
+        memory.set_budget(budget_bytes);
+        memory
+    }
+
+    /// 设置预算（空为不限制）；已领取的缓冲区不受影响
+    pub fn set_budget(&self, budget_bytes: Option<u64>) {
+        self.inner
+            .budget
+            .store(budget_bytes.unwrap_or(0), Ordering::SeqCst);
+        self.inner.released.notify_waiters();
+    }
+
+    /// 按 MB 设置预算
+    pub fn set_budget_mb(&self, budget_mb: Option<u64>) {
+        self.set_budget(budget_mb.map(|mb| mb.saturating_mul(1024 * 1024)));
+    }
+
+    /// 领取一个缓冲区（单向转发）
+    pub async fn reserve(&self, preferred: usize) -> RelayBuffer {
+        let size = self.acquire(preferred, 1).await;
+        RelayBuffer::new(self.clone(), size)
+    }
+
+    /// 同时领取双向转发的两个缓冲区
+    ///
+    /// 两个方向一起领取：连接要么拿到全部缓冲区开始转发，要么整体等待，
+    /// 不会出现大量连接各占一半预算、互相等待的情况
+    pub async fn reserve_pair(&self, preferred: usize) -> (RelayBuffer, RelayBuffer) {
+        let size = self.acquire(preferred, 2).await;
+        (
+            RelayBuffer::new(self.clone(), size),
+            RelayBuffer::new(self.clone(), size),
+        )
+    }
+
+    /// 用量快照
+    pub fn snapshot(&self) -> RelayMemoryStats {
+        let inner = &self.inner;
+        let budget = inner.budget.load(Ordering::Relaxed);
+        RelayMemoryStats {
+            budget_bytes: (budget > 0).then_some(budget),
+            used_bytes: inner.used.load(Ordering::Relaxed),
+            high_water_bytes: inner.high_water.load(Ordering::Relaxed),
+            buffers: inner.buffers.load(Ordering::Relaxed),
+            reduced_buffers: inner.reduced.load(Ordering::Relaxed),
+            waits: inner.waits.load(Ordering::Relaxed),
+            waiting: inner.waiting.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 领取 `count` 个缓冲区的字节数，返回每个缓冲区的大小
+    async fn acquire(&self, preferred: usize, count: usize) -> usize {
+        let preferred = preferred.max(RELAY_BUFFER_FLOOR);
+        if let Some(size) = self.try_acquire(preferred, count) {
+            return size;
+        }
+
+        let inner = &self.inner;
+        inner.waits.fetch_add(1, Ordering::Relaxed);
+        inner.waiting.fetch_add(1, Ordering::SeqCst);
+        debug!("Relay memory budget exhausted, waiting for buffers to be released");
+        let size = loop {
+            // 先登记等待再重试，避免错过重试与等待之间的释放
+            let released = inner.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+            if let Some(size) = self.try_acquire(preferred, count) {
+                break size;
+            }
+            released.await;
+        };
+        inner.waiting.fetch_sub(1, Ordering::SeqCst);
+        size
+    }
+
+    /// 不等待地领取，余量连下限都放不下时返回 None
+    fn try_acquire(&self, preferred: usize, count: usize) -> Option<usize> {
+        let inner = &self.inner;
+        let count_bytes = count as u64;
+        let mut used = inner.used.load(Ordering::SeqCst);
+        let size = loop {
+            let budget = inner.budget.load(Ordering::SeqCst);
+            let size = if budget == 0 {
+                preferred
+            } else {
+                let remaining = budget.saturating_sub(used);
+                let share = (remaining / count_bytes / REMAINING_SHARE) as usize;
+                let size = share.clamp(RELAY_BUFFER_FLOOR, preferred) / BUFFER_ALIGN * BUFFER_ALIGN;
+                if size as u64 * count_bytes > remaining {
+                    return None;
+                }
+                size
+            };
+            let total = used + size as u64 * count_bytes;
+            match inner
+                .used
+                .compare_exchange_weak(used, total, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => {
+                    inner.high_water.fetch_max(total, Ordering::Relaxed);
+                    break size;
+                }
+                Err(current) => used = current,
+            }
+        };
+        inner.buffers.fetch_add(count_bytes, Ordering::Relaxed);
+        if size < preferred {
+            inner.reduced.fetch_add(count_bytes, Ordering::Relaxed);
+        }
+        Some(size)
+    }
+
+    fn release(&self, size: usize) {
+        let inner = &self.inner;
+        inner.used.fetch_sub(size as u64, Ordering::SeqCst);
+        inner.buffers.fetch_sub(1, Ordering::Relaxed);
+        if inner.waiting.load(Ordering::SeqCst) > 0 {
+            inner.released.notify_waiters();
+        }
+    }
+}
+
+/// 领取的转发缓冲区，drop 时归还预算
+pub struct RelayBuffer {
+    memory: RelayMemory,
+    buf: Vec<u8>,
+}
+
+impl RelayBuffer {
+    fn new(memory: RelayMemory, size: usize) -> Self {
+        Self {
+            memory,
+            buf: vec![0u8; size],
+        }
+    }
+}
+
+impl Deref for RelayBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl DerefMut for RelayBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buf
+    }
+}
+
+impl Drop for RelayBuffer {
+    fn drop(&mut self) {
+        self.memory.release(self.buf.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const KB: u64 = 1024;
+
+    #[tokio::test]
+    async fn test_unlimited_only_accounts() {
+        let memory = RelayMemory::default();
+        let (a, b) = memory.reserve_pair(RELAY_BUFFER_SIZE).await;
+        assert_eq!(a.len(), RELAY_BUFFER_SIZE);
+        assert_eq!(b.len(), RELAY_BUFFER_SIZE);
+        let stats = memory.snapshot();
+        assert_eq!(stats.budget_bytes, None);
+        assert_eq!(stats.used_bytes, 128 * KB);
+        assert_eq!(stats.buffers, 2);
+
+        drop((a, b));
+        let stats = memory.snapshot();
+        assert_eq!(stats.used_bytes, 0);
+        assert_eq!(stats.buffers, 0);
+        assert_eq!(stats.high_water_bytes, 128 * KB);
+        assert_eq!(stats.reduced_buffers, 0);
+    }
+
+    #[tokio::test]
+    async fn test_buffers_shrink_as_budget_tightens() {
+        let memory = RelayMemory::new(Some(1024 * KB));
+        let mut held = Vec::new();
+        let mut sizes = Vec::new();
+        while memory.snapshot().budget_bytes.unwrap() - memory.snapshot().used_bytes
+            >= 2 * RELAY_BUFFER_FLOOR as u64
+        {
+            let (a, b) = memory.reserve_pair(RELAY_BUFFER_SIZE).await;
+            sizes.push(a.len());
+            held.push((a, b));
+        }
+
+        // 先到的连接拿到默认大小，之后逐渐缩小到下限
+        assert_eq!(sizes[0], RELAY_BUFFER_SIZE);
+        assert!(sizes.windows(2).all(|w| w[0] >= w[1]), "{:?}", sizes);
+        assert_eq!(*sizes.last().unwrap(), RELAY_BUFFER_FLOOR);
+        let stats = memory.snapshot();
+        assert!(stats.used_bytes <= 1024 * KB);
+        assert!(stats.reduced_buffers > 0);
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_budget_waits_for_release() {
+        let memory = RelayMemory::new(Some(12 * KB));
+        let first = memory.reserve_pair(RELAY_BUFFER_SIZE).await;
+        assert_eq!(first.0.len(), RELAY_BUFFER_FLOOR);
+        assert_eq!(memory.snapshot().used_bytes, 8 * KB);
+
+        // 剩余 4KB 放不下一对缓冲区，等待释放
+        let waiter = {
+            let memory = memory.clone();
+            tokio::spawn(async move { memory.reserve_pair(RELAY_BUFFER_SIZE).await.0.len() })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiter.is_finished());
+        assert_eq!(memory.snapshot().waiting, 1);
+
+        drop(first);
+        assert_eq!(waiter.await.unwrap(), RELAY_BUFFER_FLOOR);
+        let stats = memory.snapshot();
+        assert_eq!(stats.waits, 1);
+        assert_eq!(stats.waiting, 0);
+        assert_eq!(stats.used_bytes, 0);
+        assert!(stats.high_water_bytes <= 12 * KB);
+    }
+
+    #[tokio::test]
+    async fn test_single_buffer_and_budget_mb() {
+        let memory = RelayMemory::default();
+        memory.set_budget_mb(Some(1));
+        assert_eq!(memory.snapshot().budget_bytes, Some(1024 * KB));
+        let buf = memory.reserve(RELAY_BUFFER_SIZE).await;
+        assert_eq!(buf.len(), RELAY_BUFFER_SIZE);
+        assert_eq!(memory.snapshot().buffers, 1);
+    }
+}
@@ -0,0 +1,155 @@
+/// 发布端口的连接准入
+///
+/// 针对只建立连接、不发送数据的外部连接（半开连接、失联的对端）的两项可选防护：
+/// `require_first_byte_timeout_ms` 等外部连接发来第一个字节后才请求隧道 stream（客户端随之连接本地服务），
+/// `max_connections_per_source` 限制同一来源 IP 的并发连接数，超出的连接接受后立即关闭
+use anyhow::Result;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Duration};
+
+/// 等待外部连接发来第一个字节
+///
+/// 使用 peek 读取，字节留在套接字的接收缓冲区中，之后随其余数据一起转发（包括 SNI 预读）；
+/// 超时或对端先关闭连接时返回错误
+pub(super) async fn wait_first_byte(inbound: &TcpStream, timeout_ms: u64) -> Result<()> {
+    let mut byte = [0u8; 1];
+    match timeout(Duration::from_millis(timeout_ms), inbound.peek(&mut byte)).await {
+        Ok(Ok(0)) => Err(anyhow::anyhow!("Connection closed before sending any data")),
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(e.into()),
+        Err(_) => Err(anyhow::anyhow!(
+            "No data received within {}ms, connection closed",
+            timeout_ms
+        )),
+    }
+}
+
+/// 按来源 IP 计数的并发连接上限（每个发布端口的监听循环一份）
+#[derive(Clone, Default)]
+pub(super) struct SourceLimiter {
+    limit: Option<u32>,
+    counts: Arc<Mutex<HashMap<IpAddr, u32>>>,
+}
+
+impl SourceLimiter {
+    /// 创建上限（None 时不限制，也不计数）
+    pub(super) fn new(limit: Option<u32>) -> Self {
+        Self {
+            limit,
+            counts: Arc::default(),
+        }
+    }
+
+    /// 为来源 IP 占用一个名额，已达上限时返回 None
+    pub(super) fn try_acquire(&self, source: IpAddr) -> Option<SourceSlot> {
+        let Some(limit) = self.limit else {
+            return Some(SourceSlot { slot: None });
+        };
+        let mut counts = self.counts.lock().unwrap();
+        let count = counts.entry(source).or_insert(0);
+        if *count >= limit {
+            return None;
+        }
+        *count += 1;
+        Some(SourceSlot {
+            slot: Some((self.clone(), source)),
+        })
+    }
+
+    /// 来源 IP 当前的连接数
+    #[cfg(test)]
+    fn count(&self, source: IpAddr) -> u32 {
+        self.counts
+            .lock()
+            .unwrap()
+            .get(&source)
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// 连接占用的名额，drop 时归还
+pub(super) struct SourceSlot {
+    slot: Option<(SourceLimiter, IpAddr)>,
+}
+
+impl Drop for SourceSlot {
+    fn drop(&mut self) {
+        let Some((limiter, source)) = self.slot.take() else {
+            return;
+        };
+        let mut counts = limiter.counts.lock().unwrap();
+        if let Some(count) = counts.get_mut(&source) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&source);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_source_limiter_caps_each_ip() {
+        let limiter = SourceLimiter::new(Some(2));
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "10.0.0.2".parse().unwrap();
+
+        let first = limiter.try_acquire(a).unwrap();
+        let _second = limiter.try_acquire(a).unwrap();
+        assert!(limiter.try_acquire(a).is_none());
+        // 其他来源不受影响
+        let _other = limiter.try_acquire(b).unwrap();
+
+        drop(first);
+        assert_eq!(limiter.count(a), 1);
+        let _third = limiter.try_acquire(a).unwrap();
+        assert!(limiter.try_acquire(a).is_none());
+    }
+
+    #[test]
+    fn test_unlimited_source_limiter_does_not_count() {
+        let limiter = SourceLimiter::new(None);
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let slots: Vec<_> = (0..100).map(|_| limiter.try_acquire(a).unwrap()).collect();
+        assert_eq!(limiter.count(a), 0);
+        drop(slots);
+        assert!(limiter.counts.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_wait_first_byte() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // 不发送数据的连接超时
+        let _silent = TcpStream::connect(addr).await.unwrap();
+        let (inbound, _) = listener.accept().await.unwrap();
+        let error = wait_first_byte(&inbound, 50).await.unwrap_err();
+        assert!(error.to_string().contains("within 50ms"), "{}", error);
+
+        // 先关闭的连接
+        drop(TcpStream::connect(addr).await.unwrap());
+        let (inbound, _) = listener.accept().await.unwrap();
+        assert!(wait_first_byte(&inbound, 1000).await.is_err());
+
+        // 发来的字节留在接收缓冲区中
+        let mut peer = TcpStream::connect(addr).await.unwrap();
+        let (mut inbound, _) = listener.accept().await.unwrap();
+        peer.write_all(b"hi").await.unwrap();
+        wait_first_byte(&inbound, 1000).await.unwrap();
+        let mut data = [0u8; 2];
+        tokio::io::AsyncReadExt::read_exact(&mut inbound, &mut data)
+            .await
+            .unwrap();
+        assert_eq!(&data, b"hi");
+    }
+}
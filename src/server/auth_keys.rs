@@ -0,0 +1,177 @@
+/// 认证密钥及按密钥的权限
+///
+/// 除 `auth_key` 外服务器可以配置多个附加密钥（`auth_keys`），每个密钥单独限制可以发布的端口
+/// 和是否允许 forward。认证时解析出本会话所用密钥的权限（[`KeyPermissions`]），
+/// 代理注册和 forward 请求按它检查，拒绝原因中带上密钥名称
+use crate::config::{PortRange, ServerConfig};
+
+/// 会话认证所用密钥的权限
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPermissions {
+    /// 附加密钥的名称（`auth_key` 为 None）
+    name: Option<String>,
+    /// 允许发布的端口（None 表示不限制）
+    allowed_ports: Option<Vec<PortRange>>,
+    /// 是否允许 forward
+    allow_forward: bool,
+}
+
+impl KeyPermissions {
+    /// `auth_key` 的权限：不限制发布端口，forward 由服务器的 `allow_forward` 决定
+    pub fn legacy(config: &ServerConfig) -> Self {
+        Self {
+            name: None,
+            allowed_ports: None,
+            allow_forward: config.allow_forward,
+        }
+    }
+
+    /// 与密钥匹配的权限，密钥不是 `auth_key` 也不在 `auth_keys` 中时返回 None
+    pub fn resolve(config: &ServerConfig, auth_key: &str) -> Option<Self> {
+        if auth_key == config.auth_key {
+            return Some(Self::legacy(config));
+        }
+        let entry = config
+            .auth_keys
+            .as_deref()?
+            .iter()
+            .find(|entry| entry.key == auth_key)?;
+        Some(Self {
+            name: Some(entry.name.clone()),
+            allowed_ports: entry.allowed_ports.clone(),
+            allow_forward: entry.allow_forward.unwrap_or(config.allow_forward),
+        })
+    }
+
+    /// 附加密钥的名称（使用 `auth_key` 认证时为 None）
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// 是否允许 forward
+    pub fn allow_forward(&self) -> bool {
+        self.allow_forward
+    }
+
+    /// 检查代理的发布端口，不允许时返回拒绝原因
+    pub fn check_publish_port(&self, proxy: &str, port: u16) -> Result<(), String> {
+        let Some(allowed) = &self.allowed_ports else {
+            return Ok(());
+        };
+        if allowed.iter().any(|range| range.contains(port)) {
+            return Ok(());
+        }
+        let allowed = if allowed.is_empty() {
+            "none".to_string()
+        } else {
+            allowed
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        Err(format!(
+            "Proxy '{}': publish_port {} is not allowed for auth key '{}' (allowed: {})",
+            proxy,
+            port,
+            self.name.as_deref().unwrap_or("auth_key"),
+            allowed
+        ))
+    }
+
+    /// 检查 forward 权限，不允许时返回拒绝原因
+    pub fn check_forward(&self) -> Result<(), String> {
+        match (&self.name, self.allow_forward) {
+            (_, true) => Ok(()),
+            (Some(name), false) => Err(format!("Forward is not allowed for auth key '{}'", name)),
+            (None, false) => Err("Forward feature is not enabled on server".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AuthKeyConfig;
+
+    fn config() -> ServerConfig {
+        let mut config: ServerConfig = toml::from_str(
+            r#"
+            bind_addr = "127.0.0.1"
+            bind_port = 8443
+            auth_key = "legacy-key-123456"
+            allow_forward = true
+            "#,
+        )
+        .unwrap();
+        config.auth_keys = Some(vec![
+            AuthKeyConfig {
+                name: "teamA".to_string(),
+                key: "team-a-key-123456".to_string(),
+                allowed_ports: Some(vec!["8000-8100".parse().unwrap(), "9000".parse().unwrap()]),
+                allow_forward: Some(false),
+            },
+            AuthKeyConfig {
+                name: "teamB".to_string(),
+                key: "team-b-key-123456".to_string(),
+                allowed_ports: Some(vec![]),
+                allow_forward: None,
+            },
+        ]);
+        config
+    }
+
+    #[test]
+    fn test_resolve_keys() {
+        let config = config();
+        let legacy = KeyPermissions::resolve(&config, "legacy-key-123456").unwrap();
+        assert_eq!(legacy, KeyPermissions::legacy(&config));
+        assert_eq!(legacy.name(), None);
+        assert!(legacy.check_publish_port("web", 1).is_ok());
+        assert!(legacy.check_forward().is_ok());
+
+        let team_a = KeyPermissions::resolve(&config, "team-a-key-123456").unwrap();
+        assert_eq!(team_a.name(), Some("teamA"));
+        assert!(!team_a.allow_forward());
+        // 未设置 allow_forward 时沿用服务器的设置
+        let team_b = KeyPermissions::resolve(&config, "team-b-key-123456").unwrap();
+        assert!(team_b.allow_forward());
+
+        assert!(KeyPermissions::resolve(&config, "unknown-key-123456").is_none());
+    }
+
+    #[test]
+    fn test_check_publish_port() {
+        let config = config();
+        let team_a = KeyPermissions::resolve(&config, "team-a-key-123456").unwrap();
+        for port in [8000, 8050, 8100, 9000] {
+            assert!(team_a.check_publish_port("web", port).is_ok(), "{}", port);
+        }
+        assert_eq!(
+            team_a.check_publish_port("web", 8101).unwrap_err(),
+            "Proxy 'web': publish_port 8101 is not allowed for auth key 'teamA' (allowed: 8000-8100, 9000)"
+        );
+
+        let team_b = KeyPermissions::resolve(&config, "team-b-key-123456").unwrap();
+        assert!(team_b
+            .check_publish_port("db", 5432)
+            .unwrap_err()
+            .ends_with("(allowed: none)"));
+    }
+
+    #[test]
+    fn test_check_forward() {
+        let mut config = config();
+        let team_a = KeyPermissions::resolve(&config, "team-a-key-123456").unwrap();
+        assert_eq!(
+            team_a.check_forward().unwrap_err(),
+            "Forward is not allowed for auth key 'teamA'"
+        );
+
+        config.allow_forward = false;
+        assert_eq!(
+            KeyPermissions::legacy(&config).check_forward().unwrap_err(),
+            "Forward feature is not enabled on server"
+        );
+    }
+}
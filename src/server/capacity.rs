@@ -0,0 +1,663 @@
+/// 容量规划统计
+///
+/// 把分散在各处的限制与当前用量汇总到一处：客户端会话、各代理连接、速率限制令牌、
+/// 握手并发、stream 请求队列、文件描述符、内存 RSS、活跃的 yamux stream、转发缓冲区内存和注册表写锁
+/// 持有时间。配置了上限的项给出利用率百分比，整体余量取利用率最高的一项
+use super::registry::StreamRequest;
+use super::{LockHoldSnapshot, ServerState};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// 利用率达到该百分比时整体状态为 warning
+pub const WARNING_UTILIZATION: f64 = 75.0;
+
+/// 利用率达到该百分比时整体状态为 critical
+pub const CRITICAL_UTILIZATION: f64 = 90.0;
+
+/// 每个会话的 stream 请求队列容量
+pub const STREAM_REQUEST_QUEUE_SIZE: usize = 100;
+
+/// 当前用量与上限
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Gauge {
+    pub current: u64,
+    /// 上限（未配置时为空）
+    pub limit: Option<u64>,
+    /// 利用率百分比（未配置上限时为空）
+    pub utilization: Option<f64>,
+}
+
+impl Gauge {
+    pub fn new(current: u64, limit: Option<u64>) -> Self {
+        Self {
+            current,
+            limit,
+            utilization: limit.map(|limit| percent(current, limit)),
+        }
+    }
+}
+
+/// 单个代理的连接数
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProxyCapacity {
+    pub name: String,
+    pub listen_port: u16,
+    /// 活跃连接数与 max_connections_per_proxy
+    pub connections: Gauge,
+}
+
+/// 速率限制器的令牌与拒绝情况
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RateLimitCapacity {
+    pub requests_per_second: u32,
+    pub burst_size: u32,
+    pub available_tokens: u32,
+    /// 已消耗的突发容量百分比
+    pub utilization: f64,
+    /// 最近放行的连接数
+    pub recent_allowed: u64,
+    /// 最近被拒绝的连接数
+    pub recent_rejected: u64,
+    /// 最近的拒绝率百分比（没有连接时为空）
+    pub reject_rate: Option<f64>,
+}
+
+/// 握手并发与结果
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HandshakeCapacity {
+    /// 进行中的握手数与 max_concurrent_handshakes
+    pub in_progress: Gauge,
+    /// 等待握手名额的连接数
+    pub queued: u64,
+    pub completed: u64,
+    pub failed: u64,
+    /// 排队和握手超过 handshake_timeout_secs 被关闭的连接数
+    pub timed_out: u64,
+    /// 握手前被速率限制拒绝的连接数
+    pub rate_limited: u64,
+}
+
+/// 转发缓冲区的内存用量
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RelayMemoryCapacity {
+    /// 领取的缓冲区字节数与 relay_memory_budget_mb
+    pub used: Gauge,
+    /// 领取字节数的最高值
+    pub high_water_bytes: u64,
+    pub buffers: u64,
+    /// 因预算紧张而缩小的缓冲区数
+    pub reduced_buffers: u64,
+    /// 等待其他连接释放缓冲区的次数
+    pub waits: u64,
+    /// 正在等待的连接数
+    pub waiting: u64,
+}
+
+/// 整体状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HeadroomStatus {
+    Ok,
+    Warning,
+    Critical,
+}
+
+/// 整体余量：取利用率最高的一项
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Headroom {
+    pub status: HeadroomStatus,
+    /// 最高利用率百分比（没有配置上限的项时为空）
+    pub utilization: Option<f64>,
+    /// 剩余余量百分比
+    pub headroom: Option<f64>,
+    /// 利用率最高的一项
+    pub bottleneck: Option<String>,
+}
+
+/// 进程资源用量（仅 Linux 可用，其他平台各项为空）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessUsage {
+    pub open_fds: Option<u64>,
+    /// 文件描述符软限制（无限制时为空）
+    pub fd_limit: Option<u64>,
+    pub rss_bytes: Option<u64>,
+    /// 系统物理内存总量
+    pub memory_total: Option<u64>,
+}
+
+impl ProcessUsage {
+    /// 读取当前进程的资源用量
+    #[cfg(target_os = "linux")]
+    pub fn current() -> Self {
+        let open_fds = std::fs::read_dir("/proc/self/fd")
+            .ok()
+            .map(|entries| entries.count() as u64);
+
+        let mut rlimit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        let fd_limit = (unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlimit) } == 0
+            && rlimit.rlim_cur != libc::RLIM_INFINITY)
+            .then_some(rlimit.rlim_cur);
+
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        let rss_bytes = std::fs::read_to_string("/proc/self/statm")
+            .ok()
+            .and_then(|statm| parse_statm_rss(&statm, page_size.max(0) as u64));
+        let memory_total = std::fs::read_to_string("/proc/meminfo")
+            .ok()
+            .and_then(|meminfo| parse_meminfo_total(&meminfo));
+
+        Self {
+            open_fds,
+            fd_limit,
+            rss_bytes,
+            memory_total,
+        }
+    }
+
+    /// 读取当前进程的资源用量
+    #[cfg(not(target_os = "linux"))]
+    pub fn current() -> Self {
+        Self::default()
+    }
+}
+
+/// 容量快照（`/capacity` 的 JSON 内容）
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CapacitySnapshot {
+    /// 客户端会话数
+    pub sessions: Gauge,
+    /// 各代理的连接数
+    pub proxies: Vec<ProxyCapacity>,
+    /// 速率限制（未配置 rate_limit 时为空）
+    pub rate_limiter: Option<RateLimitCapacity>,
+    /// 握手并发（未配置 max_concurrent_handshakes 时没有上限）
+    pub handshakes: HandshakeCapacity,
+    /// 最繁忙会话的 stream 请求队列深度
+    pub stream_queue: Gauge,
+    /// 打开的文件描述符
+    pub file_descriptors: Option<Gauge>,
+    /// 常驻内存（字节，上限为系统物理内存）
+    pub memory_rss: Option<Gauge>,
+    /// 活跃的 yamux stream（各会话的控制流加上转发中的代理和 forward 连接）
+    pub yamux_streams: Gauge,
+    /// 转发缓冲区的内存（未配置 relay_memory_budget_mb 时没有上限）
+    pub relay_memory: RelayMemoryCapacity,
+    /// 代理注册表写锁的持有时间（未启用 lock-timing 特性时为空）
+    pub registry_lock: Option<LockHoldSnapshot>,
+    pub headroom: Headroom,
+}
+
+/// 汇总服务器当前的容量用量
+pub fn snapshot(state: &ServerState) -> CapacitySnapshot {
+    snapshot_with(state, ProcessUsage::current())
+}
+
+/// 使用给定的进程资源用量汇总容量
+pub fn snapshot_with(state: &ServerState, process: ProcessUsage) -> CapacitySnapshot {
+    let (session_count, deepest_queue) = state.sessions.usage();
+    let sessions = Gauge::new(session_count as u64, None);
+
+    let mut stats = state.stats_manager.get_all_stats();
+    stats.sort_by(|a, b| a.core.name.cmp(&b.core.name));
+    let proxy_connections: u64 = stats.iter().map(|s| s.core.active_connections).sum();
+    let max_connections = state.config.max_connections_per_proxy().map(u64::from);
+    let proxies: Vec<ProxyCapacity> = stats
+        .into_iter()
+        .map(|s| ProxyCapacity {
+            name: s.core.name,
+            listen_port: s.core.listen_port,
+            connections: Gauge::new(s.core.active_connections, max_connections),
+        })
+        .collect();
+
+    let rate_limiter = state.rate_limiter.as_ref().map(|limiter| {
+        let config = limiter.config();
+        let usage = limiter.usage();
+        let checked = usage.recent_allowed + usage.recent_rejected;
+        RateLimitCapacity {
+            requests_per_second: config.requests_per_second,
+            burst_size: config.burst_size,
+            available_tokens: usage.available_tokens,
+            utilization: percent(
+                config.burst_size.saturating_sub(usage.available_tokens) as u64,
+                config.burst_size as u64,
+            ),
+            recent_allowed: usage.recent_allowed,
+            recent_rejected: usage.recent_rejected,
+            reject_rate: (checked > 0).then(|| percent(usage.recent_rejected, checked)),
+        }
+    });
+
+    let counters = state.stats_manager.handshakes();
+    let handshakes = HandshakeCapacity {
+        in_progress: Gauge::new(
+            counters.in_progress(),
+            state.handshakes.limit().map(|limit| limit as u64),
+        ),
+        queued: counters.queued(),
+        completed: counters.completed(),
+        failed: counters.failed(),
+        timed_out: counters.timed_out(),
+        rate_limited: counters.rate_limited(),
+    };
+
+    let stream_queue = Gauge::new(deepest_queue as u64, Some(STREAM_REQUEST_QUEUE_SIZE as u64));
+
+    let file_descriptors = process
+        .open_fds
+        .map(|open| Gauge::new(open, process.fd_limit));
+    let memory_rss = process
+        .rss_bytes
+        .map(|rss| Gauge::new(rss, process.memory_total));
+
+    let forward_streams: u64 = state
+        .stats_manager
+        .get_forward_usage()
+        .iter()
+        .map(|usage| usage.active_streams)
+        .sum();
+    let yamux_streams = Gauge::new(
+        session_count as u64 + proxy_connections + forward_streams,
+        None,
+    );
+
+    let relay = state.stats_manager.relay_memory().snapshot();
+    let relay_memory = RelayMemoryCapacity {
+        used: Gauge::new(relay.used_bytes, relay.budget_bytes),
+        high_water_bytes: relay.high_water_bytes,
+        buffers: relay.buffers,
+        reduced_buffers: relay.reduced_buffers,
+        waits: relay.waits,
+        waiting: relay.waiting,
+    };
+
+    let mut candidates: Vec<(String, Option<f64>)> = vec![
+        ("sessions".to_string(), sessions.utilization),
+        (
+            "rate_limiter".to_string(),
+            rate_limiter.as_ref().map(|r| r.utilization),
+        ),
+        ("handshakes".to_string(), handshakes.in_progress.utilization),
+        ("stream_queue".to_string(), stream_queue.utilization),
+        (
+            "file_descriptors".to_string(),
+            file_descriptors.as_ref().and_then(|g| g.utilization),
+        ),
+        (
+            "memory_rss".to_string(),
+            memory_rss.as_ref().and_then(|g| g.utilization),
+        ),
+        ("yamux_streams".to_string(), yamux_streams.utilization),
+        ("relay_memory".to_string(), relay_memory.used.utilization),
+    ];
+    candidates.extend(
+        proxies
+            .iter()
+            .map(|p| (format!("proxy:{}", p.name), p.connections.utilization)),
+    );
+    let headroom = headroom(candidates);
+
+    CapacitySnapshot {
+        sessions,
+        proxies,
+        rate_limiter,
+        handshakes,
+        stream_queue,
+        file_descriptors,
+        memory_rss,
+        yamux_streams,
+        relay_memory,
+        registry_lock: state.proxy_registry.lock_holds(),
+        headroom,
+    }
+}
+
+/// 按利用率最高的一项计算整体余量
+fn headroom(candidates: Vec<(String, Option<f64>)>) -> Headroom {
+    let worst = candidates
+        .into_iter()
+        .filter_map(|(name, utilization)| Some((name, utilization?)))
+        .max_by(|a, b| a.1.total_cmp(&b.1));
+    let Some((bottleneck, utilization)) = worst else {
+        return Headroom {
+            status: HeadroomStatus::Ok,
+            utilization: None,
+            headroom: None,
+            bottleneck: None,
+        };
+    };
+    let status = if utilization >= CRITICAL_UTILIZATION {
+        HeadroomStatus::Critical
+    } else if utilization >= WARNING_UTILIZATION {
+        HeadroomStatus::Warning
+    } else {
+        HeadroomStatus::Ok
+    };
+    Headroom {
+        status,
+        utilization: Some(utilization),
+        headroom: Some(round((100.0 - utilization).max(0.0))),
+        bottleneck: Some(bottleneck),
+    }
+}
+
+/// 利用率百分比（保留一位小数，上限为 0 时视为已满）
+fn percent(current: u64, limit: u64) -> f64 {
+    if limit == 0 {
+        return 100.0;
+    }
+    round(current as f64 * 100.0 / limit as f64)
+}
+
+fn round(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
+
+/// 从 /proc/self/statm 解析常驻内存字节数（第二列为常驻页数）
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_statm_rss(statm: &str, page_size: u64) -> Option<u64> {
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    Some(pages * page_size)
+}
+
+/// 从 /proc/meminfo 解析物理内存总量（字节）
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_meminfo_total(meminfo: &str) -> Option<u64> {
+    let line = meminfo.lines().find(|l| l.starts_with("MemTotal:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// 各会话的 stream 请求队列登记（会话结束时随 [`SessionLoad`] 注销）
+#[derive(Clone, Default)]
+pub struct SessionLoads {
+    inner: Arc<Mutex<SessionLoadsInner>>,
+}
+
+#[derive(Default)]
+struct SessionLoadsInner {
+    next_id: u64,
+    sessions: HashMap<u64, mpsc::WeakSender<StreamRequest>>,
+}
+
+impl SessionLoads {
+    /// 登记一个会话的 stream 请求队列
+    pub(crate) fn register(&self, stream_tx: &mpsc::Sender<StreamRequest>) -> SessionLoad {
+        let mut inner = self.inner.lock();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.sessions.insert(id, stream_tx.downgrade());
+        SessionLoad {
+            loads: self.clone(),
+            id,
+        }
+    }
+
+    /// 会话数和最繁忙会话的队列深度
+    fn usage(&self) -> (usize, usize) {
+        let inner = self.inner.lock();
+        let deepest = inner
+            .sessions
+            .values()
+            .filter_map(|weak| weak.upgrade())
+            .map(|tx| tx.max_capacity() - tx.capacity())
+            .max()
+            .unwrap_or(0);
+        (inner.sessions.len(), deepest)
+    }
+}
+
+/// 会话登记，释放时注销
+pub(crate) struct SessionLoad {
+    loads: SessionLoads,
+    id: u64,
+}
+
+impl Drop for SessionLoad {
+    fn drop(&mut self) {
+        self.loads.inner.lock().sessions.remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ProxyVisibility, ServerConfigBuilder};
+    use crate::rate_limiter::{RateLimiter, RateLimiterConfig};
+    use crate::server::ServerDependencies;
+
+    fn state(rate_limiter: Option<RateLimiter>) -> ServerState {
+        let config = ServerConfigBuilder::new()
+            .bind_addr("127.0.0.1")
+            .bind_port(8443)
+            .auth_key("capacity-test-auth-key")
+            .build()
+            .unwrap();
+        let mut deps = ServerDependencies::new();
+        deps.rate_limiter = rate_limiter.map(Arc::new);
+        ServerState::with_dependencies(config, deps)
+    }
+
+    /// 向会话队列放入 `depth` 个未处理的请求
+    fn fill_queue(stream_tx: &mpsc::Sender<StreamRequest>, depth: usize) {
+        for _ in 0..depth {
+            let (reply_tx, _) = mpsc::channel(1);
+            stream_tx
+                .try_send((reply_tx, 80, "web".to_string()))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_idle_server_has_full_headroom() {
+        let state = state(None);
+        let snapshot = snapshot_with(&state, ProcessUsage::default());
+
+        assert_eq!(snapshot.sessions, Gauge::new(0, None));
+        assert!(snapshot.proxies.is_empty());
+        assert!(snapshot.rate_limiter.is_none());
+        assert_eq!(snapshot.handshakes.in_progress, Gauge::new(0, None));
+        assert_eq!(snapshot.stream_queue.utilization, Some(0.0));
+        assert!(snapshot.file_descriptors.is_none());
+        assert!(snapshot.memory_rss.is_none());
+        assert_eq!(snapshot.yamux_streams.current, 0);
+        assert_eq!(snapshot.headroom.status, HeadroomStatus::Ok);
+        assert_eq!(snapshot.headroom.headroom, Some(100.0));
+    }
+
+    #[test]
+    fn test_aggregates_sessions_proxies_and_streams() {
+        let state = state(None);
+        let (tx_a, _rx_a) = mpsc::channel(STREAM_REQUEST_QUEUE_SIZE);
+        let (tx_b, _rx_b) = mpsc::channel(STREAM_REQUEST_QUEUE_SIZE);
+        let _a = state.sessions.register(&tx_a);
+        let b = state.sessions.register(&tx_b);
+        fill_queue(&tx_a, 10);
+        fill_queue(&tx_b, 40);
+
+        let web = state.stats_manager.register_proxy(
+            "web".to_string(),
+            "0.0.0.0".to_string(),
+            8080,
+            80,
+            ProxyVisibility::Public,
+        );
+        web.connection_started();
+        web.connection_started();
+        let db = state.stats_manager.register_proxy(
+            "db".to_string(),
+            "0.0.0.0".to_string(),
+            5432,
+            5432,
+            ProxyVisibility::Public,
+        );
+        db.connection_started();
+
+        let snapshot = snapshot_with(
+            &state,
+            ProcessUsage {
+                open_fds: Some(200),
+                fd_limit: Some(1024),
+                rss_bytes: Some(64 << 20),
+                memory_total: Some(1 << 30),
+            },
+        );
+        assert_eq!(snapshot.sessions.current, 2);
+        let proxies: Vec<_> = snapshot
+            .proxies
+            .iter()
+            .map(|p| (p.name.as_str(), p.listen_port, p.connections.current))
+            .collect();
+        assert_eq!(proxies, vec![("db", 5432, 1), ("web", 8080, 2)]);
+        assert_eq!(snapshot.stream_queue, Gauge::new(40, Some(100)));
+        assert_eq!(snapshot.file_descriptors, Some(Gauge::new(200, Some(1024))));
+        assert_eq!(snapshot.memory_rss.as_ref().unwrap().utilization, Some(6.3));
+        // 两个控制流加三个代理连接
+        assert_eq!(snapshot.yamux_streams.current, 5);
+        assert_eq!(snapshot.headroom.status, HeadroomStatus::Ok);
+        assert_eq!(
+            snapshot.headroom.bottleneck.as_deref(),
+            Some("stream_queue")
+        );
+        assert_eq!(snapshot.headroom.headroom, Some(60.0));
+
+        // 会话结束后注销
+        drop(b);
+        let snapshot = snapshot_with(&state, ProcessUsage::default());
+        assert_eq!(snapshot.sessions.current, 1);
+        assert_eq!(snapshot.stream_queue.current, 10);
+    }
+
+    #[test]
+    fn test_rate_limiter_and_fd_pressure_lower_headroom() {
+        let limiter = RateLimiter::new(RateLimiterConfig {
+            requests_per_second: 1,
+            burst_size: 10,
+        });
+        for _ in 0..12 {
+            let _ = limiter.check();
+        }
+        let state = state(Some(limiter));
+
+        let snapshot = snapshot_with(
+            &state,
+            ProcessUsage {
+                open_fds: Some(800),
+                fd_limit: Some(1024),
+                ..Default::default()
+            },
+        );
+        let rate = snapshot.rate_limiter.as_ref().unwrap();
+        assert_eq!(rate.available_tokens, 0);
+        assert_eq!(rate.utilization, 100.0);
+        assert_eq!((rate.recent_allowed, rate.recent_rejected), (10, 2));
+        assert_eq!(rate.reject_rate, Some(16.7));
+        assert_eq!(snapshot.headroom.status, HeadroomStatus::Critical);
+        assert_eq!(
+            snapshot.headroom.bottleneck.as_deref(),
+            Some("rate_limiter")
+        );
+        assert_eq!(snapshot.headroom.headroom, Some(0.0));
+
+        // 文件描述符接近上限时为 warning
+        let headroom = headroom(vec![
+            ("file_descriptors".to_string(), Some(78.1)),
+            ("sessions".to_string(), None),
+        ]);
+        assert_eq!(headroom.status, HeadroomStatus::Warning);
+        assert_eq!(headroom.headroom, Some(21.9));
+    }
+
+    #[test]
+    fn test_handshake_cap_counts_towards_headroom() {
+        let mut config = ServerConfigBuilder::new()
+            .bind_addr("127.0.0.1")
+            .bind_port(8443)
+            .auth_key("capacity-test-auth-key")
+            .build()
+            .unwrap();
+        config.max_concurrent_handshakes = Some(4);
+        let state = ServerState::with_dependencies(config, ServerDependencies::new());
+
+        let snapshot = snapshot_with(&state, ProcessUsage::default());
+        assert_eq!(snapshot.handshakes.in_progress, Gauge::new(0, Some(4)));
+
+        let counters = state.stats_manager.handshakes();
+        let (queued, in_progress) = counters.gauges();
+        queued.fetch_add(7, std::sync::atomic::Ordering::Relaxed);
+        in_progress.fetch_add(4, std::sync::atomic::Ordering::Relaxed);
+        counters.record_rate_limited();
+        counters.record_timed_out();
+
+        let snapshot = snapshot_with(&state, ProcessUsage::default());
+        let handshakes = &snapshot.handshakes;
+        assert_eq!(handshakes.in_progress.utilization, Some(100.0));
+        assert_eq!(handshakes.queued, 7);
+        assert_eq!((handshakes.timed_out, handshakes.rate_limited), (1, 1));
+        assert_eq!(snapshot.headroom.status, HeadroomStatus::Critical);
+        assert_eq!(snapshot.headroom.bottleneck.as_deref(), Some("handshakes"));
+    }
+
+    #[tokio::test]
+    async fn test_relay_memory_budget_counts_towards_headroom() {
+        let mut config = ServerConfigBuilder::new()
+            .bind_addr("127.0.0.1")
+            .bind_port(8443)
+            .auth_key("capacity-test-auth-key")
+            .build()
+            .unwrap();
+        config.relay_memory_budget_mb = Some(1);
+        let state = ServerState::with_dependencies(config, ServerDependencies::new());
+        let snapshot = snapshot_with(&state, ProcessUsage::default());
+        assert_eq!(snapshot.relay_memory.used, Gauge::new(0, Some(1024 * 1024)));
+
+        let memory = state.stats_manager.relay_memory();
+        let mut held = Vec::new();
+        while memory.snapshot().used_bytes < 1000 * 1024 {
+            held.push(memory.reserve_pair(64 * 1024).await);
+        }
+
+        let snapshot = snapshot_with(&state, ProcessUsage::default());
+        let relay = &snapshot.relay_memory;
+        assert_eq!(relay.buffers, held.len() as u64 * 2);
+        assert_eq!(relay.high_water_bytes, relay.used.current);
+        assert!(relay.reduced_buffers > 0);
+        assert_eq!(snapshot.headroom.status, HeadroomStatus::Critical);
+        assert_eq!(
+            snapshot.headroom.bottleneck.as_deref(),
+            Some("relay_memory")
+        );
+
+        held.clear();
+        let snapshot = snapshot_with(&state, ProcessUsage::default());
+        assert_eq!(snapshot.relay_memory.used.current, 0);
+        assert_eq!(snapshot.headroom.status, HeadroomStatus::Ok);
+    }
+
+    #[test]
+    fn test_parse_proc_files() {
+        assert_eq!(
+            parse_statm_rss("12345 678 90 1 0 2 0\n", 4096),
+            Some(678 * 4096)
+        );
+        assert_eq!(parse_statm_rss("", 4096), None);
+        let meminfo = "MemTotal:        2048000 kB\nMemFree:          100000 kB\n";
+        assert_eq!(parse_meminfo_total(meminfo), Some(2048000 * 1024));
+        assert_eq!(parse_meminfo_total("MemFree: 1 kB\n"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_current_process_usage() {
+        let usage = ProcessUsage::current();
+        assert!(usage.open_fds.unwrap() > 0);
+        assert!(usage.rss_bytes.unwrap() > 0);
+        assert!(usage.memory_total.unwrap() >= usage.rss_bytes.unwrap());
+    }
+}
@@ -1,15 +1,32 @@
-use super::registry::{ConnectionGuard, ProxyInfo};
-use crate::stats::ProxyStatsTracker;
+use super::admission::{self, SourceLimiter};
+use super::events::{ConnectionKind, EventExporter, ServerEventKind};
+use super::exceptions::ExceptionSender;
+use super::flows::{Flow, FlowExporter};
+use super::registry::{
+    BackendConnectionGuard, ConnectionGuard, DrainSignals, ProxyInfo, Registry, SessionStream,
+    StreamRequestSender,
+};
+use super::sni;
+use crate::accept::{AcceptBackoff, AcceptErrorKind};
+use crate::config::{ServerTimeoutsConfig, SniRoutingConfig};
+use crate::control_protocol::{ProxyAcceptErrorData, EXCEPTION_PROXY_ACCEPT_ERROR};
+use crate::integrity::{self, IntegrityCheck};
+use crate::io_util::{copy_counted_with_stall, IdleReader, IdleTimeout, StallDetector};
+use crate::keepalive::{self, FrameReader, StreamFraming};
+use crate::protocol::{PeerInfo, ProxyStreamHeader};
+use crate::relay_memory::{RelayMemory, RELAY_BUFFER_SIZE};
+use crate::socket_options;
+use crate::stats::{ProxyStatsTracker, STATS_FLUSH_BYTES};
 use anyhow::{Context, Result};
-use tokio::net::TcpStream;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
 use tokio::sync::mpsc;
-use tokio::time::{sleep, Duration};
+use tokio::time::{sleep, timeout, Duration};
 use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
-const MAX_BIND_RETRIES: u32 = 10;
-const INITIAL_RETRY_DELAY_SECS: u64 = 2;
-const MAX_RETRY_DELAY_SECS: u64 = 60;
+/// 端口被占用时的绑定尝试次数（上一个会话的监听器可能仍在关闭中）
+const BIND_ATTEMPTS: u32 = 3;
+const BIND_RETRY_DELAY_MS: u64 = 200;
 
 /// 异常通知消息
 pub struct ExceptionNotification {
@@ -19,241 +36,691 @@ pub struct ExceptionNotification {
     pub data: Option<serde_json::Value>,
 }
 
-pub async fn start_proxy_listener_with_notify(
-    proxy: ProxyInfo,
-    stream_tx: mpsc::Sender<(mpsc::Sender<yamux::Stream>, u16, String)>,
-    tracker: ProxyStatsTracker,
-    exception_tx: Option<mpsc::UnboundedSender<ExceptionNotification>>,
-) -> Result<()> {
-    let addr = format!("{}:{}", proxy.publish_addr, proxy.publish_port);
-    let proxy_name = proxy.name.clone();
-    let mut retry_count = 0;
-    let mut retry_delay = INITIAL_RETRY_DELAY_SECS;
+/// 已绑定的代理公开端口
+pub enum PublishSocket {
+    /// TCP 代理的监听器
+    Tcp(TcpListener),
+    /// UDP 代理的套接字（见 [`super::udp`]）
+    Udp(UdpSocket),
+}
 
-    loop {
-        // 尝试绑定
-        match tokio::net::TcpListener::bind(&addr).await {
-            Ok(listener) => {
-                info!(
-                    "Proxy '{}' listening on {}:{} (after {} retries)",
-                    proxy_name, proxy.publish_addr, proxy.publish_port, retry_count
-                );
+impl PublishSocket {
+    /// 实际绑定的端口（publish_port 为 0 时由系统分配）
+    pub fn local_port(&self) -> std::io::Result<u16> {
+        match self {
+            Self::Tcp(listener) => listener.local_addr().map(|addr| addr.port()),
+            Self::Udp(socket) => socket.local_addr().map(|addr| addr.port()),
+        }
+    }
+}
+
+/// 绑定代理的公开端口（UDP 代理绑定 UDP 套接字）
+///
+/// 在向客户端确认配置之前调用，失败时返回可读的错误描述，由调用方计入被拒绝的代理
+pub async fn bind_proxy_listener(proxy: &ProxyInfo) -> std::result::Result<PublishSocket, String> {
+    let addr = crate::listen_addr::format(&proxy.publish_addr, proxy.publish_port);
+    let mut attempt = 0;
 
-                // 启动监听循环
-                return handle_listener_loop(listener, proxy, stream_tx, tracker).await;
+    loop {
+        attempt += 1;
+        let bound = match crate::chaos::fail("server.proxy_bind") {
+            Ok(()) if proxy.proxy_type.is_datagram() => {
+                crate::listen_addr::bind_udp(&proxy.publish_addr, proxy.publish_port)
+                    .await
+                    .map(PublishSocket::Udp)
             }
-            Err(e) => {
-                retry_count += 1;
-
-                let error_msg = match e.kind() {
-                    std::io::ErrorKind::AddrInUse => {
-                        format!(
-                            "Port {} is already in use by another process",
-                            proxy.publish_port
-                        )
+            Ok(()) => crate::listen_addr::bind(&proxy.publish_addr, proxy.publish_port)
+                .await
+                .map(PublishSocket::Tcp),
+            Err(e) => Err(e),
+        };
+        let error = match bound {
+            Ok(socket) => {
+                let port = socket.local_port().unwrap_or(proxy.publish_port);
+                let addr = crate::listen_addr::format(&proxy.publish_addr, port);
+                match socket {
+                    PublishSocket::Tcp(_) => {
+                        info!("Proxy '{}' listening on {}", proxy.name, addr)
                     }
-                    std::io::ErrorKind::PermissionDenied => {
-                        format!(
-                            "Permission denied to bind to {}:{} - may need administrator privileges",
-                            proxy.publish_addr, proxy.publish_port
-                        )
+                    PublishSocket::Udp(_) => {
+                        info!("Proxy '{}' listening on {} (udp)", proxy.name, addr)
                     }
-                    _ => format!("Failed to bind proxy listener on {}: {}", addr, e),
-                };
-
-                if retry_count <= MAX_BIND_RETRIES {
-                    warn!(
-                        "Proxy '{}' bind failed (attempt {}/{}): {}. Retrying in {} seconds...",
-                        proxy_name, retry_count, MAX_BIND_RETRIES, error_msg, retry_delay
-                    );
-
-                    // 发送警告级别异常通知
-                    if let Some(ref tx) = exception_tx {
-                        let _ = tx.send(ExceptionNotification {
-                            level: "warning".to_string(),
-                            message: format!(
-                                "代理 '{}' 绑定失败 (尝试 {}/{}): {}. 将在 {} 秒后重试",
-                                proxy_name, retry_count, MAX_BIND_RETRIES, error_msg, retry_delay
-                            ),
-                            code: Some("PROXY_BIND_RETRY".to_string()),
-                            data: Some(serde_json::json!({
-                                "proxy_name": proxy_name,
-                                "publish_addr": proxy.publish_addr,
-                                "publish_port": proxy.publish_port,
-                                "retry_count": retry_count,
-                                "max_retries": MAX_BIND_RETRIES,
-                                "retry_delay_secs": retry_delay,
-                                "error": error_msg
-                            })),
-                        });
-                    }
-
-                    sleep(Duration::from_secs(retry_delay)).await;
-
-                    // 指数退避：下次等待时间翻倍，但不超过最大值
-                    retry_delay = std::cmp::min(retry_delay * 2, MAX_RETRY_DELAY_SECS);
-                } else {
-                    error!(
-                        "Proxy '{}' bind failed after {} retries: {}",
-                        proxy_name, MAX_BIND_RETRIES, error_msg
-                    );
-
-                    // 发送错误级别异常通知
-                    if let Some(ref tx) = exception_tx {
-                        let _ = tx.send(ExceptionNotification {
-                            level: "error".to_string(),
-                            message: format!(
-                                "代理 '{}' 绑定失败，已达到最大重试次数 ({})",
-                                proxy_name, MAX_BIND_RETRIES
-                            ),
-                            code: Some("PROXY_BIND_FAILED".to_string()),
-                            data: Some(serde_json::json!({
-                                "proxy_name": proxy_name,
-                                "publish_addr": proxy.publish_addr,
-                                "publish_port": proxy.publish_port,
-                                "max_retries": MAX_BIND_RETRIES,
-                                "final_error": error_msg
-                            })),
-                        });
-                    }
-
-                    return Err(anyhow::anyhow!(
-                        "Failed to bind proxy '{}' after {} retries: {}",
-                        proxy_name,
-                        MAX_BIND_RETRIES,
-                        error_msg
-                    ));
                 }
+                return Ok(socket);
+            }
+            Err(e) => e,
+        };
+
+        let error_msg = match error.kind() {
+            std::io::ErrorKind::AddrInUse => {
+                format!(
+                    "Port {} is already in use by another process",
+                    proxy.publish_port
+                )
+            }
+            std::io::ErrorKind::PermissionDenied => {
+                format!(
+                    "Permission denied to bind to {} - may need administrator privileges",
+                    addr
+                )
             }
+            _ => format!("Failed to bind proxy listener on {}: {}", addr, error),
+        };
+
+        if error.kind() != std::io::ErrorKind::AddrInUse || attempt >= BIND_ATTEMPTS {
+            error!("Proxy '{}' bind failed: {}", proxy.name, error_msg);
+            return Err(error_msg);
         }
+
+        warn!(
+            "Proxy '{}' bind failed (attempt {}/{}): {}, retrying",
+            proxy.name, attempt, BIND_ATTEMPTS, error_msg
+        );
+        sleep(Duration::from_millis(BIND_RETRY_DELAY_MS)).await;
     }
 }
 
-/// 处理监听器接受连接的主循环
-async fn handle_listener_loop(
-    listener: tokio::net::TcpListener,
+/// 在已绑定的监听器上接受连接（主循环）
+///
+/// 资源耗尽时退避重试，并通过 `exceptions` 通知客户端（重复的错误由异常通知队列合并和限流）；
+/// 监听套接字不可用时返回错误。转发登记到 `drain`，排空超时后被关闭
+#[allow(clippy::too_many_arguments)]
+pub async fn run_proxy_listener(
+    listener: TcpListener,
     proxy: ProxyInfo,
-    stream_tx: mpsc::Sender<(mpsc::Sender<yamux::Stream>, u16, String)>,
+    stream_tx: StreamRequestSender,
     tracker: ProxyStatsTracker,
+    drain: DrainSignals,
+    events: EventExporter,
+    flows: FlowExporter,
+    stall: Option<StallDetector>,
+    relay_memory: RelayMemory,
+    exceptions: ExceptionSender,
+    timeouts: ServerTimeoutsConfig,
+    idle_timeout: Option<Duration>,
 ) -> Result<()> {
+    let mut backoff = AcceptBackoff::new(format!("Proxy '{}'", proxy.name));
+    let sources = SourceLimiter::new(proxy.max_connections_per_source);
     loop {
         match listener.accept().await {
-            Ok((inbound, _peer_addr)) => {
+            Ok((inbound, peer_addr)) => {
+                backoff.reset();
+                let Some(source_slot) = admit_source(&sources, &proxy, &tracker, peer_addr) else {
+                    continue;
+                };
+                let Some(connection) = admit_connection(&proxy, &tracker, peer_addr) else {
+                    continue;
+                };
+                socket_options::configure(
+                    &inbound,
+                    proxy.socket.as_ref(),
+                    &format!("Proxy '{}'", proxy.name),
+                );
                 let proxy_name = proxy.name.clone();
                 let stream_tx = stream_tx.clone();
                 let tracker_clone = tracker.clone();
-                let proxy_type = proxy.proxy_type;
+                let sni_routing = proxy.sni_routing.clone();
+                let idle_keepalive_secs = proxy.idle_keepalive_secs;
+                let integrity_check = proxy.integrity_check;
+                let first_byte_timeout_ms = proxy.require_first_byte_timeout_ms;
+                // 完整性校验的标志位只能在携带外部连接信息的前导中发送
+                let peer = (proxy.report_peers || proxy.integrity_check)
+                    .then(|| PeerInfo::accepted_now(peer_addr));
+                let events = events.clone();
+                let stall = stall.clone();
+                let relay_memory = relay_memory.clone();
+                let relay = drain.relay();
+                let relays = drain.relays.clone();
+                events.emit(accepted_event(&proxy, peer_addr));
+                let flow = flows.proxy_flow(&proxy, peer_addr);
 
                 tokio::spawn(async move {
-                    if let Err(e) = handle_proxy_connection(
-                        inbound,
-                        stream_tx,
-                        proxy_name,
-                        proxy.publish_port,
-                        tracker_clone,
-                        proxy_type,
-                    )
-                    .await
-                    {
+                    let _relay = relay;
+                    let _source_slot = source_slot;
+                    let _task = crate::chaos::task("server.relay");
+                    let result = tokio::select! {
+                        result = handle_proxy_connection(
+                            inbound,
+                            connection,
+                            stream_tx,
+                            proxy_name.clone(),
+                            proxy.publish_port,
+                            tracker_clone,
+                            sni_routing,
+                            idle_keepalive_secs,
+                            integrity_check,
+                            first_byte_timeout_ms,
+                            peer,
+                            stall,
+                            relay_memory,
+                            timeouts.client_hello(),
+                            idle_timeout,
+                            &flow,
+                        ) => result,
+                        _ = relays.cancelled() => Err(drain_timeout_error(&proxy_name)),
+                    };
+                    if let Err(e) = &result {
                         error!("Failed to handle connection: {}", e);
                     }
+                    flow.finish(termination_reason(&result));
+                    events.emit(closed_event(proxy_name, &result));
                 });
             }
             Err(e) => {
-                error!("Proxy '{}' accept error: {}", proxy.name, e);
-                // 继续接受新连接，不中断监听
+                let kind = AcceptErrorKind::of_io(&e);
+                if kind != AcceptErrorKind::Transient {
+                    if kind == AcceptErrorKind::Resource {
+                        tracker.record_accept_error();
+                    }
+                    exceptions.send(ExceptionNotification {
+                        level: "warning".to_string(),
+                        message: format!("代理 '{}' 接受连接失败：{}", proxy.name, e),
+                        code: Some(EXCEPTION_PROXY_ACCEPT_ERROR.to_string()),
+                        data: serde_json::to_value(ProxyAcceptErrorData {
+                            proxy_name: proxy.name.clone(),
+                            publish_port: proxy.publish_port,
+                        })
+                        .ok(),
+                    });
+                }
+                if !backoff.on_error(kind, &e).await {
+                    return Err(e)
+                        .with_context(|| format!("Proxy '{}' listener stopped", proxy.name));
+                }
             }
         }
     }
 }
 
-/// 处理代理连接
-pub async fn handle_proxy_connection(
-    mut inbound: TcpStream,
-    stream_tx: mpsc::Sender<(mpsc::Sender<yamux::Stream>, u16, String)>,
-    proxy_name: String,
-    publish_port: u16,
+/// 在共享代理的监听器上接受连接（主循环）
+///
+/// 监听器归注册表项所有：每个连接按加权轮询选择后端，请求 stream 失败时依次尝试其余后端
+#[allow(clippy::too_many_arguments)]
+pub async fn run_shared_proxy_listener(
+    listener: TcpListener,
+    proxy: ProxyInfo,
+    registry: Registry,
     tracker: ProxyStatsTracker,
-    proxy_type: crate::config::ProxyType,
+    drain: DrainSignals,
+    events: EventExporter,
+    flows: FlowExporter,
+    stall: Option<StallDetector>,
+    relay_memory: RelayMemory,
+    timeouts: ServerTimeoutsConfig,
+    idle_timeout: Option<Duration>,
 ) -> Result<()> {
-    // 为需要低延迟的代理类型（如 SSH）启用 TCP_NODELAY
-    if proxy_type.needs_nodelay() {
-        if let Err(e) = inbound.set_nodelay(true) {
-            tracing::warn!("Failed to set TCP_NODELAY for inbound connection: {}", e);
-        } else {
-            tracing::debug!(
-                "Enabled TCP_NODELAY for proxy '{}' (type: {:?})",
-                proxy_name,
-                proxy_type
-            );
+    let mut backoff = AcceptBackoff::new(format!("Shared proxy '{}'", proxy.name));
+    let sources = SourceLimiter::new(proxy.max_connections_per_source);
+    loop {
+        match listener.accept().await {
+            Ok((inbound, peer_addr)) => {
+                backoff.reset();
+                let Some(source_slot) = admit_source(&sources, &proxy, &tracker, peer_addr) else {
+                    continue;
+                };
+                let Some(connection) = admit_connection(&proxy, &tracker, peer_addr) else {
+                    continue;
+                };
+                socket_options::configure(
+                    &inbound,
+                    proxy.socket.as_ref(),
+                    &format!("Shared proxy '{}'", proxy.name),
+                );
+                let proxy = proxy.clone();
+                let registry = registry.clone();
+                let tracker = tracker.clone();
+                let events = events.clone();
+                let stall = stall.clone();
+                let relay_memory = relay_memory.clone();
+                let relay = drain.relay();
+                let relays = drain.relays.clone();
+                // 是否上报由选中后端的配置决定，接入时间在这里记录
+                let peer = PeerInfo::accepted_now(peer_addr);
+                events.emit(accepted_event(&proxy, peer_addr));
+                let flow = flows.proxy_flow(&proxy, peer_addr);
+
+                tokio::spawn(async move {
+                    let _relay = relay;
+                    let _source_slot = source_slot;
+                    let _task = crate::chaos::task("server.relay");
+                    let proxy_name = proxy.name.clone();
+                    let result = tokio::select! {
+                        result = handle_shared_proxy_connection(inbound, connection, proxy, registry, peer, tracker, stall, relay_memory, timeouts, idle_timeout, &flow) => result,
+                        _ = relays.cancelled() => Err(drain_timeout_error(&proxy_name)),
+                    };
+                    if let Err(e) = &result {
+                        error!("Failed to handle shared proxy connection: {}", e);
+                    }
+                    flow.finish(termination_reason(&result));
+                    events.emit(closed_event(proxy_name, &result));
+                });
+            }
+            Err(e) => {
+                let kind = AcceptErrorKind::of_io(&e);
+                if kind == AcceptErrorKind::Resource {
+                    tracker.record_accept_error();
+                }
+                if !backoff.on_error(kind, &e).await {
+                    return Err(e).with_context(|| {
+                        format!("Shared proxy '{}' listener stopped", proxy.name)
+                    });
+                }
+            }
         }
     }
+}
 
-    // 连接开始，增加计数
-    tracker.connection_started();
+/// 按来源 IP 的并发上限准入外部连接，超出上限时计数并返回 None（连接随之关闭）
+fn admit_source(
+    sources: &SourceLimiter,
+    proxy: &ProxyInfo,
+    tracker: &ProxyStatsTracker,
+    peer_addr: std::net::SocketAddr,
+) -> Option<admission::SourceSlot> {
+    let slot = sources.try_acquire(peer_addr.ip());
+    if slot.is_none() {
+        tracker.record_source_limited();
+        debug!(
+            "Proxy '{}': {} reached max_connections_per_source, connection closed",
+            proxy.name,
+            peer_addr.ip()
+        );
+    }
+    slot
+}
 
-    // 确保在函数结束时减少活跃连接数
-    let _guard = ConnectionGuard::new(tracker.clone());
+/// 按代理的连接上限（`max_connections_per_proxy`）准入外部连接，超出上限时计数并返回 None
+fn admit_connection(
+    proxy: &ProxyInfo,
+    tracker: &ProxyStatsTracker,
+    peer_addr: std::net::SocketAddr,
+) -> Option<ConnectionGuard> {
+    let guard = ConnectionGuard::admit(tracker);
+    if guard.is_none() {
+        debug!(
+            "Proxy '{}' reached max_connections_per_proxy, connection from {} closed",
+            proxy.name, peer_addr
+        );
+    }
+    guard
+}
 
-    info!("Creating yamux stream for proxy '{}'", proxy_name);
+/// 配置了 `require_first_byte_timeout_ms` 时等待外部连接的第一个字节，没有等到时计数
+async fn await_first_byte(
+    inbound: &TcpStream,
+    timeout_ms: Option<u64>,
+    tracker: &ProxyStatsTracker,
+) -> Result<()> {
+    let Some(timeout_ms) = timeout_ms else {
+        return Ok(());
+    };
+    admission::wait_first_byte(inbound, timeout_ms)
+        .await
+        .inspect_err(|_| tracker.record_silent_connection())
+}
 
-    // 请求一个新的yamux stream
+/// 外部连接接入事件
+pub(super) fn accepted_event(
+    proxy: &ProxyInfo,
+    peer_addr: std::net::SocketAddr,
+) -> ServerEventKind {
+    ServerEventKind::ConnectionAccepted {
+        name: proxy.name.clone(),
+        publish_port: proxy.publish_port,
+        peer_addr: peer_addr.to_string(),
+    }
+}
+
+/// 排空超时后被关闭的转发
+pub(super) fn drain_timeout_error(proxy_name: &str) -> anyhow::Error {
+    anyhow::anyhow!("Proxy '{}' drain timed out, connection closed", proxy_name)
+}
+
+/// 代理连接终止事件
+pub(super) fn closed_event(name: String, result: &Result<()>) -> ServerEventKind {
+    ServerEventKind::ConnectionClosed {
+        kind: ConnectionKind::Proxy,
+        name,
+        reason: termination_reason(result),
+    }
+}
+
+/// 代理连接的终止原因
+pub(super) fn termination_reason(result: &Result<()>) -> String {
+    match result {
+        Ok(()) => "closed".to_string(),
+        Err(e) => e.to_string(),
+    }
+}
+
+/// 处理共享代理连接
+///
+/// 向单个后端请求 stream 超过 `timeouts.stream_open_secs` 时尝试下一个后端
+#[allow(clippy::too_many_arguments)]
+async fn handle_shared_proxy_connection(
+    mut inbound: TcpStream,
+    _connection: ConnectionGuard,
+    proxy: ProxyInfo,
+    registry: Registry,
+    peer: PeerInfo,
+    tracker: ProxyStatsTracker,
+    stall: Option<StallDetector>,
+    relay_memory: RelayMemory,
+    timeouts: ServerTimeoutsConfig,
+    idle_timeout: Option<Duration>,
+    flow: &Flow,
+) -> Result<()> {
+    // 收到第一个字节之前不选择后端，不让后端连接本地服务
+    await_first_byte(&inbound, proxy.require_first_byte_timeout_ms, &tracker).await?;
+
+    // 各后端的 SNI 路由表可能不同，ClientHello 只预读一次
+    let hello = match proxy.sni_routing {
+        Some(_) => Some(sni::peek_client_hello(&mut inbound, timeouts.client_hello()).await),
+        None => None,
+    };
+
+    let backends = registry
+        .select_backends(&proxy.name, proxy.publish_port)
+        .map(|selection| selection.backends)
+        .unwrap_or_default();
+
+    for backend in backends {
+        let result = timeout(
+            timeouts.stream_open(),
+            request_stream(&backend.stream_tx, proxy.publish_port, &proxy.name),
+        )
+        .await
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("Timed out waiting for yamux stream")));
+
+        let backend_id = backend
+            .backend_stats
+            .as_ref()
+            .map(|b| b.id().to_string())
+            .unwrap_or_default();
+        match result {
+            Ok((stream, _stream_slot)) => {
+                info!(
+                    "Shared proxy '{}' dispatched connection to backend {}",
+                    proxy.name, backend_id
+                );
+                let _backend_guard = backend.backend_stats.map(BackendConnectionGuard::new);
+                flow.set_owner(&backend.proxy_info);
+                let route = SniRoute::select(backend.proxy_info.sni_routing.as_ref(), &hello);
+                return relay_proxy_stream(
+                    inbound,
+                    stream,
+                    &proxy.name,
+                    proxy.publish_port,
+                    route,
+                    backend.proxy_info.idle_keepalive_secs,
+                    idle_timeout,
+                    backend.proxy_info.integrity_check,
+                    (backend.proxy_info.report_peers || backend.proxy_info.integrity_check)
+                        .then_some(peer),
+                    tracker,
+                    stall,
+                    &relay_memory,
+                    flow,
+                )
+                .await;
+            }
+            Err(e) => {
+                warn!(
+                    "Shared proxy '{}' backend {} unavailable: {}, trying next",
+                    proxy.name, backend_id, e
+                );
+            }
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "No available backend for shared proxy '{}'",
+        proxy.name
+    ))
+}
+
+/// 请求客户端会话创建一个新的 yamux stream
+///
+/// 会话的 stream 数已达 `max_streams_per_client` 时请求被拒绝，返回错误
+pub(super) async fn request_stream(
+    stream_tx: &StreamRequestSender,
+    publish_port: u16,
+    proxy_name: &str,
+) -> Result<SessionStream> {
     let (response_tx, mut response_rx) = mpsc::channel(1);
     stream_tx
-        .send((response_tx, publish_port, proxy_name.clone()))
+        .send((response_tx, publish_port, proxy_name.to_string()))
         .await
         .context("Failed to request yamux stream")?;
 
-    // 等待stream
-    let mut stream = response_rx
+    response_rx
         .recv()
         .await
-        .ok_or_else(|| anyhow::anyhow!("Failed to receive yamux stream"))?;
+        .ok_or_else(|| anyhow::anyhow!("Failed to receive yamux stream"))
+}
+
+/// 处理代理连接
+#[allow(clippy::too_many_arguments)]
+pub async fn handle_proxy_connection(
+    mut inbound: TcpStream,
+    _connection: ConnectionGuard,
+    stream_tx: StreamRequestSender,
+    proxy_name: String,
+    publish_port: u16,
+    tracker: ProxyStatsTracker,
+    sni_routing: Option<SniRoutingConfig>,
+    idle_keepalive_secs: Option<u64>,
+    integrity_check: bool,
+    first_byte_timeout_ms: Option<u64>,
+    peer: Option<PeerInfo>,
+    stall: Option<StallDetector>,
+    relay_memory: RelayMemory,
+    client_hello_wait: Duration,
+    idle_timeout: Option<Duration>,
+    flow: &Flow,
+) -> Result<()> {
+    // 收到第一个字节之前不请求 stream，客户端不会连接本地服务
+    await_first_byte(&inbound, first_byte_timeout_ms, &tracker).await?;
+
+    // 配置了 SNI 路由时先预读 ClientHello，再请求 stream
+    let hello = match sni_routing {
+        Some(_) => Some(sni::peek_client_hello(&mut inbound, client_hello_wait).await),
+        None => None,
+    };
+    let route = SniRoute::select(sni_routing.as_ref(), &hello);
+
+    info!("Creating yamux stream for proxy '{}'", proxy_name);
+
+    // 请求一个新的yamux stream
+    let (stream, _stream_slot) = request_stream(&stream_tx, publish_port, &proxy_name).await?;
 
     info!("Yamux stream created for '{}'", proxy_name);
 
+    relay_proxy_stream(
+        inbound,
+        stream,
+        &proxy_name,
+        publish_port,
+        route,
+        idle_keepalive_secs,
+        idle_timeout,
+        integrity_check,
+        peer,
+        tracker,
+        stall,
+        &relay_memory,
+        flow,
+    )
+    .await
+}
+
+/// SNI 路由结果：选中的本地端口和需要先转发的预读数据
+struct SniRoute<'a> {
+    /// 选中的本地端口（0 表示代理的 local_port）
+    local_port: u16,
+    /// 预读的 ClientHello 字节
+    data: &'a [u8],
+}
+
+impl<'a> SniRoute<'a> {
+    /// 按路由表和预读结果选择本地端口，未配置 SNI 路由时返回 None
+    fn select(
+        sni_routing: Option<&SniRoutingConfig>,
+        hello: &'a Option<sni::ClientHelloPeek>,
+    ) -> Option<Self> {
+        let sni_routing = sni_routing?;
+        let data = hello.as_ref().map_or(&[][..], |h| h.data.as_slice());
+        let sni = hello.as_ref().and_then(|h| h.sni.as_deref());
+        let local_port = sni_routing.select(sni).unwrap_or(0);
+        info!(
+            "SNI {} routed to local port {}",
+            sni.unwrap_or("<none>"),
+            local_port
+        );
+        Some(Self { local_port, data })
+    }
+}
+
+/// 发送协议头并在外部连接与 yamux stream 之间双向转发
+///
+/// SNI 路由代理在发布端口之后发送选中的本地端口，再转发预读的 ClientHello；
+/// 配置了空闲保活的代理以分帧标记开头，之后的数据（包括预读的 ClientHello）按帧传输；
+/// 开启了 `report_peers` 的代理以外部连接信息标记开头，分帧与否由标志位表示；
+/// 开启了 `integrity_check` 的代理同样使用该前导，以标志位要求两端交换完整性校验尾帧
+#[allow(clippy::too_many_arguments)]
+async fn relay_proxy_stream(
+    mut inbound: TcpStream,
+    mut stream: yamux::Stream,
+    proxy_name: &str,
+    publish_port: u16,
+    sni_route: Option<SniRoute<'_>>,
+    idle_keepalive_secs: Option<u64>,
+    idle_timeout: Option<Duration>,
+    integrity_check: bool,
+    peer: Option<PeerInfo>,
+    tracker: ProxyStatsTracker,
+    stall: Option<StallDetector>,
+    relay_memory: &RelayMemory,
+    flow: &Flow,
+) -> Result<()> {
     // 发送协议头：发布端口（与 visitor 保持一致使用 publish_port）
     use futures::io::AsyncWriteExt;
-    stream.write_all(&publish_port.to_be_bytes()).await?;
+    let meter = tracker.meter().attach(flow.traffic());
+    let integrity_check = integrity_check && peer.is_some();
+    let framing = (idle_keepalive_secs.is_some() || integrity_check).then(|| {
+        let framing = StreamFraming::new(idle_keepalive_secs.map(Duration::from_secs));
+        if !integrity_check {
+            return framing;
+        }
+        framing.with_integrity(IntegrityCheck::new(
+            format!("{}#{}", proxy_name, stream.id()),
+            integrity::Direction::ServerToClient,
+            tracker.integrity().clone(),
+        ))
+    });
+    let header = ProxyStreamHeader {
+        publish_port,
+        framed: framing.is_some(),
+        peer,
+        sni_local_port: sni_route.as_ref().map(|route| route.local_port),
+        integrity: integrity_check,
+    };
+    stream.write_all(&header.encode()).await?;
+    if let Some(route) = sni_route {
+        match &framing {
+            Some(framing) => {
+                if let Some(check) = framing.integrity() {
+                    check.record_sent(route.data);
+                }
+                keepalive::write_frames(&mut stream, route.data).await?
+            }
+            None => stream.write_all(route.data).await?,
+        }
+        meter.add_received(route.data.len() as u64);
+    }
     stream.flush().await?;
 
     info!("Sent publish_port {} to client", publish_port);
 
+    // 开启停滞诊断时按连接标识报告
+    let monitor = stall.map(|detector| {
+        let peer = inbound
+            .peer_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_default();
+        detector.monitor(format!("{}#{}", proxy_name, peer))
+    });
+
+    // 两个方向的缓冲区从全局预算领取（预算紧张时缩小，用尽时等待释放）
+    let (mut inbound_buf, mut stream_buf) = relay_memory.reserve_pair(RELAY_BUFFER_SIZE).await;
+
     // 双向转发数据（使用futures的AsyncRead/Write，需要兼容层）
     let (inbound_read, inbound_write) = inbound.split();
-    let (mut stream_read, mut stream_write) = futures::io::AsyncReadExt::split(stream);
+    let (stream_read, mut stream_write) = futures::io::AsyncReadExt::split(stream);
+    // 配置了空闲保活的代理需要保持空闲连接，不受空闲超时限制；保活标记不算作活动
+    let idle = idle_timeout
+        .filter(|_| idle_keepalive_secs.is_none())
+        .map(IdleTimeout::new);
+    let mut stream_read = IdleReader::new(
+        FrameReader::new(stream_read, framing.as_ref()),
+        idle.as_ref(),
+    );
 
     // 转换tokio的split为futures兼容的
-    let mut inbound_read = inbound_read.compat();
+    let mut inbound_read = IdleReader::new(inbound_read.compat(), idle.as_ref());
     let mut inbound_write = inbound_write.compat_write();
 
     // 跟踪inbound到stream的字节数（外部客户端 → 服务器 → 内网客户端：服务器接收的数据）
-    let tracker_clone = tracker.clone();
-    let inbound_to_stream = async move {
-        let result = futures::io::copy(&mut inbound_read, &mut stream_write).await;
-        if let Ok(bytes) = result {
-            tracker_clone.add_bytes_received(bytes);
-        }
-        result
+    let inbound_to_stream = async {
+        keepalive::copy_to_stream(
+            &mut inbound_read,
+            &mut stream_write,
+            &mut inbound_buf,
+            framing.as_ref(),
+            STATS_FLUSH_BYTES,
+            |bytes| meter.add_received(bytes),
+            monitor.as_ref(),
+        )
+        .await
+        .map_err(|e| ("inbound to stream", e))?;
+        // 外部连接关闭后向客户端发送 FIN，客户端随之结束本地连接并关闭 stream
+        stream_write
+            .close()
+            .await
+            .map_err(|e| ("inbound to stream", e))
     };
 
     // 跟踪stream到inbound的字节数（内网客户端 → 服务器 → 外部客户端：服务器发送的数据）
-    let stream_to_inbound = async move {
-        let result = futures::io::copy(&mut stream_read, &mut inbound_write).await;
-        if let Ok(bytes) = result {
-            tracker.add_bytes_sent(bytes);
-        }
-        result
+    let stream_to_inbound = async {
+        copy_counted_with_stall(
+            &mut stream_read,
+            &mut inbound_write,
+            &mut stream_buf,
+            STATS_FLUSH_BYTES,
+            |bytes| meter.add_sent(bytes),
+            monitor.as_ref(),
+        )
+        .await
+        .map_err(|e| ("stream to inbound", e))?;
+        // 本地服务关闭连接后同样关闭外部连接的写方向
+        inbound_write
+            .close()
+            .await
+            .map_err(|e| ("stream to inbound", e))
     };
 
-    // 使用 join! 而不是 select!，确保两个方向都完成传输
-    let (result1, result2) = tokio::join!(inbound_to_stream, stream_to_inbound);
-
-    if let Err(e) = result1 {
-        warn!("Error copying inbound to stream: {}", e);
-    }
-    if let Err(e) = result2 {
-        warn!("Error copying stream to inbound: {}", e);
+    // 使用 try_join! 而不是 select!，正常结束时确保两个方向都完成传输；
+    // 任一方向出错时另一方向不会再结束，立即丢弃两端（用户连接关闭，stream 被重置）
+    // 空闲超时同样以错误结束转发，两端随之关闭
+    match tokio::try_join!(inbound_to_stream, stream_to_inbound) {
+        Err((_, e)) if idle.is_some() && e.kind() == std::io::ErrorKind::TimedOut => {
+            info!(
+                "Connection for proxy '{}' closed after idle timeout",
+                proxy_name
+            );
+        }
+        Err((direction, e)) => warn!("Error copying {}: {}", direction, e),
+        Ok(_) => {}
     }
 
     info!("Connection closed for proxy '{}'", proxy_name);
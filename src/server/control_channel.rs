@@ -1,18 +1,28 @@
 use crate::config::ProxyConfig;
 use crate::control_protocol::*;
-use anyhow::{Context, Result};
-use futures::io::{AsyncReadExt as FuturesAsyncReadExt, AsyncWriteExt as FuturesAsyncWriteExt};
-use serde_json::json;
+use crate::protocol_trace::{SessionTrace, TraceDirection};
+use anyhow::Result;
+use futures::io::{AsyncRead, AsyncWrite};
+use std::collections::BTreeMap;
 use std::str::FromStr;
 use tracing::{debug, warn};
 
 /// 服务端控制通道事件
 #[derive(Debug, Clone)]
 pub enum ControlEvent {
+    /// 收到身份挑战请求（认证之前）
+    IdentityChallengeRequest { id: serde_json::Value },
+
     /// 收到认证请求
     AuthenticateRequest {
         id: serde_json::Value,
         auth_key: String,
+        /// 客户端声明的 peer_id
+        peer_id: Option<String>,
+        /// 客户端声明的协议能力
+        capabilities: Vec<String>,
+        /// 客户端身份证明（对本会话挑战随机数的签名）
+        identity: Option<IdentityProof>,
     },
 
     /// 收到配置提交请求
@@ -22,9 +32,37 @@ pub enum ControlEvent {
         visitors: Vec<crate::config::VisitorConfig>,
     },
 
+    /// 收到配置校验请求（只校验，不注册代理）
+    ValidateConfigRequest {
+        id: serde_json::Value,
+        proxies: Vec<ProxyConfig>,
+        visitors: Vec<crate::config::VisitorConfig>,
+    },
+
+    /// 收到增量配置更新请求（会话运行期间追加注册代理）
+    UpdateConfigRequest {
+        id: serde_json::Value,
+        proxies: Vec<ProxyConfig>,
+    },
+
+    /// 收到主动下线代理请求
+    RemoveProxiesRequest {
+        id: serde_json::Value,
+        proxies: Vec<ProxyRef>,
+    },
+
     /// 收到心跳
     Heartbeat,
 
+    /// 收到需要确认的心跳（客户端支持心跳确认）
+    HeartbeatRequest {
+        id: serde_json::Value,
+        params: HeartbeatParams,
+    },
+
+    /// 收到直连 forwarder 连接的报告
+    ForwardReport(ForwardReportParams),
+
     /// 连接关闭
     ConnectionClosed,
 }
@@ -32,16 +70,30 @@ pub enum ControlEvent {
 /// 服务端控制通道
 pub struct ServerControlChannel {
     event_tx: tokio::sync::mpsc::UnboundedSender<ControlEvent>,
+    /// 协议跟踪（收发的每一帧）
+    trace: SessionTrace,
+    /// 控制帧读取缓冲（读取可在 select! 中安全取消）
+    frames: FrameReader,
 }
 
 impl ServerControlChannel {
     /// 创建新的服务端控制通道
     pub fn new() -> (Self, tokio::sync::mpsc::UnboundedReceiver<ControlEvent>) {
         let (event_tx, event_rx) = tokio::sync::mpsc::unbounded_channel();
-        let channel = Self { event_tx };
+        let channel = Self {
+            event_tx,
+            trace: SessionTrace::default(),
+            frames: FrameReader::new(),
+        };
         (channel, event_rx)
     }
 
+    /// 将收发的控制帧记录到协议跟踪
+    pub fn with_trace(mut self, trace: SessionTrace) -> Self {
+        self.trace = trace;
+        self
+    }
+
     /// 检查协议版本兼容性
     fn check_protocol_compatibility(&self, client_version: &str) -> Result<()> {
         // 解析版本号（简单实现，假设格式为 major.minor.patch）
@@ -93,59 +145,98 @@ impl ServerControlChannel {
     }
 
     /// 读取并处理消息（返回是否需要响应的请求）
-    pub async fn read_message(
-        &self,
-        stream: &mut ::yamux::Stream,
-    ) -> Result<Option<JsonRpcRequest>> {
-        // 读取长度前缀（4字节大端序）
-        let mut len_buf = [0u8; 4];
-        match stream.read_exact(&mut len_buf).await {
-            Ok(_) => {}
-            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-                debug!("Control stream closed");
-                let _ = self.event_tx.send(ControlEvent::ConnectionClosed);
-                return Ok(None);
-            }
-            Err(e) => {
-                return Err(anyhow::anyhow!("Failed to read message length: {}", e));
-            }
-        }
+    ///
+    /// 单条消息无法解析时回复 JSON-RPC 错误并继续读取下一条；只有超长消息、
+    /// 帧不同步和 I/O 错误会返回 Err（结束会话），此时尽量先把原因告知客户端
+    pub async fn read_message<S>(&mut self, stream: &mut S) -> Result<Option<JsonRpcRequest>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        loop {
+            let frame = match self.frames.read_frame(stream).await {
+                Ok(Some(frame)) => {
+                    self.trace.control(TraceDirection::In, &frame);
+                    frame
+                }
+                Ok(None) => {
+                    debug!("Control stream closed");
+                    let _ = self.event_tx.send(ControlEvent::ConnectionClosed);
+                    return Ok(None);
+                }
+                Err(e) => {
+                    if !matches!(e, ControlFrameError::Io(_)) {
+                        let response =
+                            JsonRpcResponse::error(serde_json::Value::Null, e.to_rpc_error());
+                        let _ = self.send_response(stream, &response).await;
+                    }
+                    return Err(e.into());
+                }
+            };
+
+            let request = match parse_message(&frame) {
+                ControlMessage::Request(request) => request,
+                ControlMessage::Response(response) => {
+                    // 服务端不向客户端发起请求，响应只可能是客户端回报的错误
+                    if let Some(error) = response.error {
+                        warn!(
+                            "Client reported control error ({}): {}",
+                            error.code, error.message
+                        );
+                    }
+                    continue;
+                }
+                ControlMessage::Malformed { id, error } => {
+                    warn!("Ignoring malformed control message: {}", error.message);
+                    self.send_response(stream, &JsonRpcResponse::error(id, error))
+                        .await?;
+                    continue;
+                }
+            };
 
-        let msg_len = u32::from_be_bytes(len_buf) as usize;
+            debug!("Received JSON-RPC request: method={}", request.method);
 
-        // 防止过大的消息
-        if msg_len > 10 * 1024 * 1024 {
-            return Err(anyhow::anyhow!("Message too large: {} bytes", msg_len));
+            // 处理请求并发送事件
+            match self.handle_request(&request) {
+                Ok(()) => return Ok(Some(request)),
+                Err(error) => {
+                    warn!(
+                        "Rejected control request '{}': {}",
+                        request.method, error.message
+                    );
+                    // 通知不需要响应
+                    if let Some(id) = request.id {
+                        self.send_response(stream, &JsonRpcResponse::error(id, error))
+                            .await?;
+                    }
+                }
+            }
         }
-
-        // 读取消息体
-        let mut msg_buf = vec![0u8; msg_len];
-        stream
-            .read_exact(&mut msg_buf)
-            .await
-            .context("Failed to read message body")?;
-
-        // 解析 JSON-RPC 消息
-        let request: JsonRpcRequest =
-            serde_json::from_slice(&msg_buf).context("Failed to parse JSON-RPC request")?;
-
-        debug!("Received JSON-RPC request: method={}", request.method);
-
-        // 处理请求并发送事件
-        self.handle_request(&request).await?;
-
-        // 返回需要响应的请求
-        Ok(Some(request))
     }
 
     /// 处理 JSON-RPC 请求
-    async fn handle_request(&self, request: &JsonRpcRequest) -> Result<()> {
-        let method = ControlMethod::from_str(&request.method)?;
+    fn handle_request(&self, request: &JsonRpcRequest) -> std::result::Result<(), JsonRpcError> {
+        let invalid_params = |e: serde_json::Error| JsonRpcError {
+            code: INVALID_PARAMS,
+            message: format!("Invalid {} params: {}", request.method, e),
+            data: None,
+        };
+
+        let method = ControlMethod::from_str(&request.method).map_err(|e| JsonRpcError {
+            code: METHOD_NOT_FOUND,
+            message: e.to_string(),
+            data: None,
+        })?;
 
         match method {
+            ControlMethod::IdentityChallenge => {
+                let id = request.id.clone().unwrap_or(serde_json::Value::Null);
+                let _ = self
+                    .event_tx
+                    .send(ControlEvent::IdentityChallengeRequest { id });
+            }
+
             ControlMethod::Authenticate => {
-                let params: AuthenticateParams = serde_json::from_value(request.params.clone())
-                    .context("Invalid authenticate params")?;
+                let params: AuthenticateParams = request.decode_params().map_err(invalid_params)?;
 
                 // 记录客户端版本信息
                 debug!(
@@ -168,12 +259,14 @@ impl ServerControlChannel {
                 let _ = self.event_tx.send(ControlEvent::AuthenticateRequest {
                     id,
                     auth_key: params.auth_key,
+                    peer_id: params.peer_id,
+                    capabilities: params.capabilities,
+                    identity: params.identity,
                 });
             }
 
             ControlMethod::SubmitConfig => {
-                let params: SubmitConfigParams = serde_json::from_value(request.params.clone())
-                    .context("Invalid submit_config params")?;
+                let params: SubmitConfigParams = request.decode_params().map_err(invalid_params)?;
 
                 let id = request.id.clone().unwrap_or(serde_json::Value::Null);
                 let _ = self.event_tx.send(ControlEvent::SubmitConfigRequest {
@@ -183,8 +276,53 @@ impl ServerControlChannel {
                 });
             }
 
+            ControlMethod::ValidateConfig => {
+                let params: SubmitConfigParams = request.decode_params().map_err(invalid_params)?;
+
+                let id = request.id.clone().unwrap_or(serde_json::Value::Null);
+                let _ = self.event_tx.send(ControlEvent::ValidateConfigRequest {
+                    id,
+                    proxies: params.proxies,
+                    visitors: params.visitors,
+                });
+            }
+
+            ControlMethod::UpdateConfig => {
+                let params: UpdateConfigParams = request.decode_params().map_err(invalid_params)?;
+
+                let id = request.id.clone().unwrap_or(serde_json::Value::Null);
+                let _ = self.event_tx.send(ControlEvent::UpdateConfigRequest {
+                    id,
+                    proxies: params.proxies,
+                });
+            }
+
+            ControlMethod::RemoveProxies => {
+                let params: RemoveProxiesParams =
+                    request.decode_params().map_err(invalid_params)?;
+
+                let id = request.id.clone().unwrap_or(serde_json::Value::Null);
+                let _ = self.event_tx.send(ControlEvent::RemoveProxiesRequest {
+                    id,
+                    proxies: params.proxies,
+                });
+            }
+
             ControlMethod::Heartbeat => {
-                let _ = self.event_tx.send(ControlEvent::Heartbeat);
+                let event = match request.id.clone() {
+                    Some(id) => ControlEvent::HeartbeatRequest {
+                        id,
+                        params: request.decode_params().map_err(invalid_params)?,
+                    },
+                    None => ControlEvent::Heartbeat,
+                };
+                let _ = self.event_tx.send(event);
+            }
+
+            ControlMethod::ForwardReport => {
+                let params: ForwardReportParams =
+                    request.decode_params().map_err(invalid_params)?;
+                let _ = self.event_tx.send(ControlEvent::ForwardReport(params));
             }
 
             _ => {
@@ -206,15 +344,25 @@ impl ServerControlChannel {
             client_id,
             protocol_version: env!("CARGO_PKG_VERSION").to_string(),
             min_client_version: Some("1.4.0".to_string()),
+            capabilities: supported_capabilities(),
         };
 
-        let response = JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
-            id,
-            result: Some(serde_json::to_value(result)?),
-            error: None,
-        };
+        let response = JsonRpcResponse::success_with(id, &result)?;
+
+        self.send_response(stream, &response).await
+    }
 
+    /// 发送身份挑战随机数
+    pub async fn send_identity_challenge(
+        &self,
+        stream: &mut ::yamux::Stream,
+        id: serde_json::Value,
+        nonce: &[u8],
+    ) -> Result<()> {
+        let result = IdentityChallengeResult {
+            nonce: crate::identity::to_hex(nonce),
+        };
+        let response = JsonRpcResponse::success_with(id, &result)?;
         self.send_response(stream, &response).await
     }
 
@@ -225,57 +373,82 @@ impl ServerControlChannel {
         id: serde_json::Value,
         reason: String,
     ) -> Result<()> {
-        let response = JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
+        let response = JsonRpcResponse::error(
             id,
-            result: None,
-            error: Some(JsonRpcError {
-                code: -32000,
+            JsonRpcError {
+                code: AUTHENTICATION_FAILED,
                 message: reason,
                 data: None,
-            }),
-        };
+            },
+        );
 
         self.send_response(stream, &response).await
     }
 
-    /// 发送配置接受响应
+    /// 发送配置接受响应（assigned_ports 为服务器分配了发布端口的代理）
     pub async fn send_config_accepted(
         &self,
         stream: &mut ::yamux::Stream,
         id: serde_json::Value,
+        assigned_ports: Vec<AssignedPort>,
     ) -> Result<()> {
         let result = SubmitConfigResult {
             rejected_proxies: vec![],
+            reasons: BTreeMap::new(),
+            dry_run: false,
+            rejected_entries: vec![],
+            assigned_ports,
         };
 
-        let response = JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
-            id,
-            result: Some(serde_json::to_value(result)?),
-            error: None,
-        };
-
-        self.send_response(stream, &response).await
+        self.send_config_result(stream, id, result).await
     }
 
-    /// 发送配置部分拒绝响应
+    /// 发送配置部分拒绝响应（响应的拒绝列表依次包含被拒绝的代理和 visitor）
     pub async fn send_config_partially_rejected(
         &self,
         stream: &mut ::yamux::Stream,
         id: serde_json::Value,
         rejected_proxies: Vec<String>,
+        rejected_visitors: Vec<String>,
+        reasons: BTreeMap<String, String>,
+        assigned_ports: Vec<AssignedPort>,
     ) -> Result<()> {
-        let result = SubmitConfigResult { rejected_proxies };
-
-        let response = JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
-            id,
-            result: Some(serde_json::to_value(result)?),
-            error: None,
+        let rejected_entries = rejected_proxies
+            .iter()
+            .map(|item| RejectedEntry::from_item(item, RejectedKind::Proxy, &reasons))
+            .chain(
+                rejected_visitors
+                    .iter()
+                    .map(|item| RejectedEntry::from_item(item, RejectedKind::Visitor, &reasons)),
+            )
+            .collect();
+        let result = SubmitConfigResult {
+            rejected_proxies: rejected_proxies
+                .into_iter()
+                .chain(rejected_visitors)
+                .collect(),
+            reasons,
+            dry_run: false,
+            rejected_entries,
+            assigned_ports,
         };
 
-        self.send_response(stream, &response).await
+        self.send_config_result(stream, id, result).await
+    }
+
+    /// 发送配置确认（全部接受或部分拒绝）
+    async fn send_config_result(
+        &self,
+        stream: &mut ::yamux::Stream,
+        id: serde_json::Value,
+        result: SubmitConfigResult,
+    ) -> Result<()> {
+        let response = JsonRpcResponse::success_with(id, &result)?;
+
+        crate::chaos::fail("server.config_ack.before")?;
+        self.send_response(stream, &response).await?;
+        crate::chaos::fail("server.config_ack.after")?;
+        Ok(())
     }
 
     /// 发送配置拒绝响应
@@ -284,21 +457,70 @@ impl ServerControlChannel {
         stream: &mut ::yamux::Stream,
         id: serde_json::Value,
         rejected_proxies: Vec<String>,
+        reasons: BTreeMap<String, String>,
     ) -> Result<()> {
-        let response = JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
+        let message = format!("All proxies rejected: {}", rejected_proxies.join(", "));
+        let data = ConfigRejectedData {
+            rejected_proxies,
+            reasons,
+        };
+        let response = JsonRpcResponse::error(
             id,
-            result: None,
-            error: Some(JsonRpcError {
-                code: -32001,
-                message: format!("All proxies rejected: {}", rejected_proxies.join(", ")),
-                data: Some(json!({ "rejected_proxies": rejected_proxies })),
-            }),
+            JsonRpcError::with_data(CONFIG_REJECTED, message, &data)?,
+        );
+
+        self.send_response(stream, &response).await
+    }
+
+    /// 发送配置校验结果（全部被拒绝时同样作为结果返回，而不是错误）
+    pub async fn send_validation_result(
+        &self,
+        stream: &mut ::yamux::Stream,
+        id: serde_json::Value,
+        rejected_proxies: Vec<String>,
+        reasons: BTreeMap<String, String>,
+    ) -> Result<()> {
+        let result = SubmitConfigResult {
+            rejected_proxies,
+            reasons,
+            dry_run: true,
+            rejected_entries: vec![],
+            assigned_ports: vec![],
         };
 
+        let response = JsonRpcResponse::success_with(id, &result)?;
+
+        self.send_response(stream, &response).await
+    }
+
+    /// 确认心跳（原样回显心跳参数）
+    pub async fn send_heartbeat_ack(
+        &self,
+        stream: &mut ::yamux::Stream,
+        id: serde_json::Value,
+        params: HeartbeatParams,
+    ) -> Result<()> {
+        let response = JsonRpcResponse::success_with(id, &params)?;
         self.send_response(stream, &response).await
     }
 
+    /// 发送错误响应（不结束会话）
+    pub async fn send_error(
+        &self,
+        stream: &mut ::yamux::Stream,
+        id: serde_json::Value,
+        code: i32,
+        message: String,
+    ) -> Result<()> {
+        let error = JsonRpcError {
+            code,
+            message,
+            data: None,
+        };
+        self.send_response(stream, &JsonRpcResponse::error(id, error))
+            .await
+    }
+
     /// 发送异常通知
     pub async fn send_exception_notification(
         &self,
@@ -308,50 +530,219 @@ impl ServerControlChannel {
         code: Option<String>,
         data: Option<serde_json::Value>,
     ) -> Result<()> {
-        use tracing::info;
+        self.send_exception(
+            stream,
+            &ExceptionNotification {
+                level: level.to_string(),
+                message,
+                code,
+                data,
+                occurrences: None,
+            },
+        )
+        .await
+    }
 
-        let notification = ExceptionNotification {
-            level: level.to_string(),
-            message: message.clone(),
-            code,
-            data,
-        };
+    /// 发送已构造好的异常通知（可带合并次数）
+    pub async fn send_exception(
+        &self,
+        stream: &mut ::yamux::Stream,
+        notification: &ExceptionNotification,
+    ) -> Result<()> {
+        use tracing::info;
 
-        let request = JsonRpcRequest {
-            jsonrpc: "2.0".to_string(),
-            method: "push_exception".to_string(),
-            params: serde_json::to_value(notification)?,
-            id: None, // 通知没有 ID
-        };
+        let request = JsonRpcRequest::notify(ControlMethod::PushException, notification)?;
 
         let request_json = serde_json::to_vec(&request)?;
-        let len_bytes = (request_json.len() as u32).to_be_bytes();
-
-        stream.write_all(&len_bytes).await?;
-        stream.write_all(&request_json).await?;
-        stream.flush().await?;
+        write_frame(stream, &request_json).await?;
+        self.trace.control(TraceDirection::Out, &request_json);
 
         info!(
             "Sent exception notification: level={}, message={}",
-            level, message
+            notification.level, notification.message
         );
         Ok(())
     }
 
     /// 发送响应
-    async fn send_response(
-        &self,
-        stream: &mut ::yamux::Stream,
-        response: &JsonRpcResponse,
-    ) -> Result<()> {
+    async fn send_response<S>(&self, stream: &mut S, response: &JsonRpcResponse) -> Result<()>
+    where
+        S: AsyncWrite + Unpin,
+    {
         let response_json = serde_json::to_vec(response)?;
-        let len_bytes = (response_json.len() as u32).to_be_bytes();
-
-        stream.write_all(&len_bytes).await?;
-        stream.write_all(&response_json).await?;
-        stream.flush().await?;
+        write_frame(stream, &response_json).await?;
+        self.trace.control(TraceDirection::Out, &response_json);
 
         debug!("Sent JSON-RPC response: id={:?}", response.id);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::io::AsyncWriteExt;
+    use serde_json::Value;
+    use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+
+    fn pipe() -> (
+        Compat<tokio::io::DuplexStream>,
+        Compat<tokio::io::DuplexStream>,
+    ) {
+        let (local, remote) = tokio::io::duplex(64 * 1024);
+        (local.compat(), remote.compat())
+    }
+
+    fn frame(method: &str, id: Option<u64>) -> Vec<u8> {
+        serde_json::to_vec(&JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params: Value::Null,
+            id: id.map(Value::from),
+        })
+        .unwrap()
+    }
+
+    async fn read_response(stream: &mut Compat<tokio::io::DuplexStream>) -> JsonRpcResponse {
+        let frame = read_frame(stream).await.unwrap().unwrap();
+        serde_json::from_slice(&frame).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_malformed_frame_does_not_end_session() {
+        let (mut channel, mut events) = ServerControlChannel::new();
+        let (mut local, mut remote) = pipe();
+
+        write_frame(&mut remote, &frame("heartbeat", None))
+            .await
+            .unwrap();
+        write_frame(&mut remote, b"{not json").await.unwrap();
+        write_frame(&mut remote, &frame("heartbeat", None))
+            .await
+            .unwrap();
+
+        // 两条合法消息都被处理
+        let first = channel.read_message(&mut local).await.unwrap().unwrap();
+        assert_eq!(first.method, "heartbeat");
+        let second = channel.read_message(&mut local).await.unwrap().unwrap();
+        assert_eq!(second.method, "heartbeat");
+        assert!(matches!(events.recv().await, Some(ControlEvent::Heartbeat)));
+        assert!(matches!(events.recv().await, Some(ControlEvent::Heartbeat)));
+
+        // 无法解析的消息收到 parse error 响应
+        let response = read_response(&mut remote).await;
+        assert_eq!(response.id, Value::Null);
+        assert_eq!(response.error.unwrap().code, PARSE_ERROR);
+
+        // 会话仍然可用，对端关闭后正常结束
+        drop(remote);
+        assert!(channel.read_message(&mut local).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_request_is_answered_with_error() {
+        let (mut channel, _events) = ServerControlChannel::new();
+        let (mut local, mut remote) = pipe();
+
+        write_frame(&mut remote, &frame("no_such_method", Some(7)))
+            .await
+            .unwrap();
+        write_frame(&mut remote, br#"{"jsonrpc":"2.0","id":8}"#)
+            .await
+            .unwrap();
+        write_frame(&mut remote, &frame("heartbeat", None))
+            .await
+            .unwrap();
+
+        let request = channel.read_message(&mut local).await.unwrap().unwrap();
+        assert_eq!(request.method, "heartbeat");
+
+        let response = read_response(&mut remote).await;
+        assert_eq!(response.id, Value::from(7));
+        assert_eq!(response.error.unwrap().code, METHOD_NOT_FOUND);
+
+        let response = read_response(&mut remote).await;
+        assert_eq!(response.id, Value::from(8));
+        assert_eq!(response.error.unwrap().code, INVALID_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_remove_proxies_request() {
+        let (mut channel, mut events) = ServerControlChannel::new();
+        let (mut local, mut remote) = pipe();
+
+        let request = JsonRpcRequest::new(
+            "remove_proxies".to_string(),
+            serde_json::json!({"proxies": [{"name": "web", "publish_port": 8080}]}),
+            3,
+        );
+        write_frame(&mut remote, &serde_json::to_vec(&request).unwrap())
+            .await
+            .unwrap();
+
+        channel.read_message(&mut local).await.unwrap().unwrap();
+        match events.recv().await {
+            Some(ControlEvent::RemoveProxiesRequest { id, proxies }) => {
+                assert_eq!(id, Value::from(3));
+                assert_eq!(
+                    proxies,
+                    vec![ProxyRef {
+                        name: "web".to_string(),
+                        publish_port: 8080,
+                    }]
+                );
+            }
+            other => panic!("Unexpected event: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_request_needs_ack() {
+        let (mut channel, mut events) = ServerControlChannel::new();
+        let (mut local, mut remote) = pipe();
+
+        let params = HeartbeatParams {
+            seq: 5,
+            timestamp_ms: 120_000,
+        };
+        let request = JsonRpcRequest::new(
+            "heartbeat".to_string(),
+            serde_json::to_value(params).unwrap(),
+            9,
+        );
+        write_frame(&mut remote, &serde_json::to_vec(&request).unwrap())
+            .await
+            .unwrap();
+        write_frame(&mut remote, &frame("heartbeat", None))
+            .await
+            .unwrap();
+
+        channel.read_message(&mut local).await.unwrap().unwrap();
+        channel.read_message(&mut local).await.unwrap().unwrap();
+        match events.recv().await {
+            Some(ControlEvent::HeartbeatRequest { id, params: echoed }) => {
+                assert_eq!(id, Value::from(9));
+                assert_eq!(echoed, params);
+            }
+            other => panic!("Unexpected event: {:?}", other),
+        }
+        // 旧客户端的心跳通知无需确认
+        assert!(matches!(events.recv().await, Some(ControlEvent::Heartbeat)));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frame_ends_session_with_reason() {
+        let (mut channel, _events) = ServerControlChannel::new();
+        let (mut local, mut remote) = pipe();
+
+        let len = (MAX_CONTROL_MESSAGE_SIZE + 1) as u32;
+        remote.write_all(&len.to_be_bytes()).await.unwrap();
+
+        let err = channel.read_message(&mut local).await.unwrap_err();
+        assert!(err.to_string().contains("size limit exceeded"));
+
+        let response = read_response(&mut remote).await;
+        let error = response.error.unwrap();
+        assert_eq!(error.data.unwrap()["condition"], "size_limit_exceeded");
+    }
+}
@@ -0,0 +1,95 @@
+/// 代理排空
+///
+/// 代理被主动下线（管理端停用、客户端移除代理）时立即停止接受新连接，已有的转发继续进行，
+/// 全部结束或排空超时后（超时时强制关闭剩余转发）才从注册表和统计中移除。
+/// 会话异常断开时代理仍然立即注销
+use super::events::ServerEventKind;
+use super::registry::{DrainSignals, ProxyState};
+use super::ServerState;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+use tracing::{info, warn};
+
+/// 未配置时的默认排空超时（秒）
+pub const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 30;
+
+/// 检查进行中转发数的间隔
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 开始排空代理，返回是否找到可排空的代理（不存在或已经在排空时返回 false）
+///
+/// 调用返回时代理已停止接受新连接，排空在后台任务中完成
+pub(super) async fn start_drain(state: &Arc<ServerState>, key: (String, u16)) -> bool {
+    let default_secs = state
+        .config
+        .drain_timeout_secs
+        .unwrap_or(DEFAULT_DRAIN_TIMEOUT_SECS);
+    if !state.proxy_registry.set_state(&key, ProxyState::Draining) {
+        return false;
+    }
+    // 期间注册会话已断开时注册表项已被移除，不再需要排空
+    let Some(proxy) = state.proxy_registry.lookup(&key.0, key.1) else {
+        return true;
+    };
+    let (signals, drain_timeout) = (proxy.drain.clone(), proxy.drain_timeout(default_secs));
+
+    let active_relays = signals.active_relays();
+    info!(
+        "Draining proxy '{}' with publish_port {}: {} active relay(s), timeout {:?}",
+        key.0, key.1, active_relays, drain_timeout
+    );
+    state.events.emit(ServerEventKind::ProxyDraining {
+        name: key.0.clone(),
+        publish_port: key.1,
+        active_relays,
+        timeout_secs: drain_timeout.as_secs(),
+    });
+
+    tokio::spawn(finish_drain(state.clone(), key, signals, drain_timeout));
+    true
+}
+
+/// 等待已有转发结束（超时后强制关闭），然后移除注册表项和统计
+async fn finish_drain(
+    state: Arc<ServerState>,
+    key: (String, u16),
+    signals: DrainSignals,
+    drain_timeout: Duration,
+) {
+    let drained = timeout(drain_timeout, async {
+        while signals.active_relays() > 0 {
+            sleep(DRAIN_POLL_INTERVAL).await;
+        }
+    })
+    .await
+    .is_ok();
+
+    let closed_relays = if drained {
+        0
+    } else {
+        let remaining = signals.active_relays();
+        warn!(
+            "Proxy '{}' with publish_port {} drain timed out, closing {} relay(s)",
+            key.0, key.1, remaining
+        );
+        signals.relays.cancel();
+        remaining
+    };
+
+    // 期间注册会话已断开时注册表项已被移除；同名代理可能已在其他端口重新注册，此时保留其统计
+    state.proxy_registry.remove_drained(&key);
+    if state.proxy_registry.lookup_by_name(&key.0).is_empty() {
+        state.stats_manager.unregister_proxy(&key.0);
+    }
+
+    info!(
+        "Proxy '{}' with publish_port {} drained and removed",
+        key.0, key.1
+    );
+    state.events.emit(ServerEventKind::ProxyDrained {
+        name: key.0,
+        publish_port: key.1,
+        closed_relays,
+    });
+}
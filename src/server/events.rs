@@ -0,0 +1,399 @@
+/// 服务器连接事件导出
+///
+/// 认证、代理注册/注销、外部连接、forward 请求、客户端上报的直连和连接终止等事件经内部有界队列
+/// 发送给独立的导出任务，由其序列化为 JSON 行写入外部采集器（SIEM）。
+/// 采集器断开时按退避间隔重连；采集器过慢导致队列满时丢弃新事件并计数，不阻塞转发路径
+use super::flows::FlowRecord;
+use crate::config::{EventExportConfig, EventExportTarget};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// 事件结构版本，字段发生不兼容变化时递增
+pub const EVENT_SCHEMA_VERSION: u32 = 1;
+
+/// 重连退避的初始间隔
+const RECONNECT_BACKOFF_INITIAL: Duration = Duration::from_millis(500);
+/// 重连退避的最大间隔
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(30);
+/// 单个事件的写入超时，超时视为采集器失效并重连
+const EXPORT_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 导出的事件（带版本和时间戳）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerEvent {
+    /// 事件结构版本
+    pub version: u32,
+    /// 事件时间（Unix 毫秒时间戳）
+    pub timestamp_ms: u64,
+    /// 事件内容
+    #[serde(flatten)]
+    pub kind: ServerEventKind,
+}
+
+/// 事件内容（序列化时 `event` 字段为事件名称）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ServerEventKind {
+    /// 客户端认证成功
+    AuthSucceeded {
+        client_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        peer_id: Option<String>,
+        /// 客户端身份指纹（客户端未提供身份时为空）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        identity: Option<String>,
+    },
+    /// 客户端认证失败
+    AuthFailed {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        peer_id: Option<String>,
+        reason: String,
+    },
+    /// 代理注册成功（共享代理的每个后端各一条）
+    ProxyRegistered {
+        client_id: String,
+        name: String,
+        publish_addr: String,
+        publish_port: u16,
+        shared: bool,
+    },
+    /// 代理后端注销（`remaining_backends` 为 0 时代理被移除）
+    ProxyUnregistered {
+        client_id: String,
+        name: String,
+        publish_port: u16,
+        remaining_backends: usize,
+    },
+    /// 代理被主动下线，开始排空（不再接受新连接）
+    ProxyDraining {
+        name: String,
+        publish_port: u16,
+        active_relays: usize,
+        timeout_secs: u64,
+    },
+    /// 代理排空结束并被移除（`closed_relays` 为超时后强制关闭的转发数）
+    ProxyDrained {
+        name: String,
+        publish_port: u16,
+        closed_relays: usize,
+    },
+    /// 代理空闲超过 `idle_registration_expiry_secs` 被注销
+    ProxyExpiredIdle {
+        client_id: String,
+        name: String,
+        publish_port: u16,
+        idle_secs: u64,
+    },
+    /// 代理端口接受了外部连接
+    ConnectionAccepted {
+        name: String,
+        publish_port: u16,
+        peer_addr: String,
+    },
+    /// 客户端请求 forward 到外部目标
+    ForwardRequested {
+        client_id: String,
+        target: String,
+        allowed: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+    },
+    /// 客户端上报的直连（不经隧道）forwarder 连接
+    DirectConnectionReported {
+        client_id: String,
+        forwarder: String,
+        target: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rule: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        matched: Option<String>,
+        bytes_up: u64,
+        bytes_down: u64,
+        duration_ms: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// 连接终止（代理连接、visitor stream 或 forward 连接）
+    ConnectionClosed {
+        kind: ConnectionKind,
+        name: String,
+        reason: String,
+    },
+    /// 转发连接的流量记录（配置 `flow_export.sink = "events"` 时经事件导出发送）
+    FlowRecord(FlowRecord),
+    /// 客户端会话结束
+    SessionClosed {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_id: Option<String>,
+        reason: String,
+    },
+}
+
+/// 终止的连接类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionKind {
+    /// 外部连接经代理端口转发到客户端
+    Proxy,
+    /// visitor 经服务器连接到其他客户端的代理
+    Visitor,
+    /// 客户端经服务器连接外部目标
+    Forward,
+}
+
+impl ServerEvent {
+    /// 以当前时间创建事件
+    pub fn new(kind: ServerEventKind) -> Self {
+        Self {
+            version: EVENT_SCHEMA_VERSION,
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            kind,
+        }
+    }
+
+    /// 序列化为单行 JSON
+    pub fn to_json_line(&self) -> String {
+        let mut line = serde_json::to_string(self).unwrap_or_default();
+        line.push('\n');
+        line
+    }
+}
+
+/// 事件导出句柄（未配置导出时为空操作）
+#[derive(Debug, Clone, Default)]
+pub struct EventExporter {
+    tx: Option<mpsc::Sender<ServerEvent>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl EventExporter {
+    /// 不导出任何事件
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// 按配置启动导出任务（需要在 tokio 运行时中调用）
+    pub fn spawn(config: &EventExportConfig) -> anyhow::Result<Self> {
+        let target = config.parse_target()?;
+        let (tx, rx) = mpsc::channel(config.queue_size.max(1));
+        let dropped = Arc::new(AtomicU64::new(0));
+        info!("Exporting connection events to {}", config.target);
+        tokio::spawn(run_export(target, rx, dropped.clone()));
+        Ok(Self {
+            tx: Some(tx),
+            dropped,
+        })
+    }
+
+    /// 按服务器配置创建（未配置或配置无效时不导出）
+    pub fn from_config(config: Option<&EventExportConfig>) -> Self {
+        match config.map(Self::spawn) {
+            Some(Ok(exporter)) => exporter,
+            Some(Err(e)) => {
+                warn!("Event export disabled: {:#}", e);
+                Self::disabled()
+            }
+            None => Self::disabled(),
+        }
+    }
+
+    /// 是否启用了导出
+    pub fn is_enabled(&self) -> bool {
+        self.tx.is_some()
+    }
+
+    /// 发送事件（队列满或导出任务已退出时丢弃并计数，不阻塞）
+    pub fn emit(&self, kind: ServerEventKind) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+        if tx.try_send(ServerEvent::new(kind)).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 累计丢弃的事件数
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+type ExportStream = std::pin::Pin<Box<dyn AsyncWrite + Send>>;
+
+/// 连接采集器
+async fn connect(target: &EventExportTarget) -> std::io::Result<ExportStream> {
+    match target {
+        EventExportTarget::Tcp(addr) => {
+            let stream = tokio::net::TcpStream::connect(addr.as_str()).await?;
+            Ok(Box::pin(stream))
+        }
+        #[cfg(unix)]
+        EventExportTarget::Unix(path) => {
+            let stream = tokio::net::UnixStream::connect(path).await?;
+            Ok(Box::pin(stream))
+        }
+        #[cfg(not(unix))]
+        EventExportTarget::Unix(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "unix sockets are not supported on this platform",
+        )),
+    }
+}
+
+/// 导出任务：逐个写出队列中的事件，断开后按退避间隔重连
+///
+/// 重连期间事件留在队列中，队列满后由 `emit` 丢弃新事件
+async fn run_export(
+    target: EventExportTarget,
+    mut rx: mpsc::Receiver<ServerEvent>,
+    dropped: Arc<AtomicU64>,
+) {
+    let mut stream: Option<ExportStream> = None;
+    let mut backoff = RECONNECT_BACKOFF_INITIAL;
+    let mut reported_dropped = 0;
+
+    while let Some(event) = rx.recv().await {
+        let line = event.to_json_line();
+        loop {
+            let writer = match stream.as_mut() {
+                Some(writer) => writer,
+                None => match connect(&target).await {
+                    Ok(writer) => {
+                        info!("Connected to event collector {:?}", target);
+                        backoff = RECONNECT_BACKOFF_INITIAL;
+                        stream.insert(writer)
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Failed to connect event collector {:?}: {}, retrying in {:?}",
+                            target, e, backoff
+                        );
+                        tokio::time::sleep(backoff).await;
+                        backoff = (backoff * 2).min(RECONNECT_BACKOFF_MAX);
+                        continue;
+                    }
+                },
+            };
+
+            match tokio::time::timeout(EXPORT_WRITE_TIMEOUT, writer.write_all(line.as_bytes()))
+                .await
+            {
+                Ok(Ok(())) => break,
+                Ok(Err(e)) => warn!("Failed to write to event collector: {}, reconnecting", e),
+                Err(_) => warn!("Event collector write timed out, reconnecting"),
+            }
+            stream = None;
+        }
+
+        let total_dropped = dropped.load(Ordering::Relaxed);
+        if total_dropped != reported_dropped {
+            warn!(
+                "Event export queue overflowed, {} event(s) dropped in total",
+                total_dropped
+            );
+            reported_dropped = total_dropped;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EventExportFormat;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    #[test]
+    fn test_event_json_line_is_versioned() {
+        let event = ServerEvent::new(ServerEventKind::ForwardRequested {
+            client_id: "client_1".to_string(),
+            target: "example.com:443".to_string(),
+            allowed: false,
+            reason: Some("Forward feature is not enabled on server".to_string()),
+        });
+        let line = event.to_json_line();
+        assert!(line.ends_with('\n'));
+
+        let value: serde_json::Value = serde_json::from_str(line.trim()).unwrap();
+        assert_eq!(value["version"], EVENT_SCHEMA_VERSION);
+        assert_eq!(value["event"], "forward_requested");
+        assert_eq!(value["target"], "example.com:443");
+        assert_eq!(value["allowed"], false);
+
+        let parsed: ServerEvent = serde_json::from_str(line.trim()).unwrap();
+        assert_eq!(parsed, event);
+    }
+
+    #[test]
+    fn test_full_queue_drops_and_counts() {
+        let (tx, _rx) = mpsc::channel(2);
+        let exporter = EventExporter {
+            tx: Some(tx),
+            dropped: Arc::new(AtomicU64::new(0)),
+        };
+        for i in 0..5 {
+            exporter.emit(ServerEventKind::SessionClosed {
+                client_id: None,
+                reason: format!("closed {}", i),
+            });
+        }
+        assert_eq!(exporter.dropped(), 3);
+
+        // 未启用时不计数
+        let disabled = EventExporter::disabled();
+        disabled.emit(ServerEventKind::SessionClosed {
+            client_id: None,
+            reason: "closed".to_string(),
+        });
+        assert!(!disabled.is_enabled());
+        assert_eq!(disabled.dropped(), 0);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_export_to_unix_socket_after_collector_starts() {
+        let path =
+            std::env::temp_dir().join(format!("tls-tunnel-events-{}.sock", uuid::Uuid::new_v4()));
+        let exporter = EventExporter::spawn(&EventExportConfig {
+            target: format!("unix:{}", path.display()),
+            format: EventExportFormat::Jsonl,
+            queue_size: 16,
+        })
+        .unwrap();
+
+        // 采集器尚未启动：事件留在队列中，采集器启动后重连并按顺序送达
+        exporter.emit(ServerEventKind::AuthFailed {
+            peer_id: None,
+            reason: "Invalid authentication key".to_string(),
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        exporter.emit(ServerEventKind::SessionClosed {
+            client_id: None,
+            reason: "Authentication failed".to_string(),
+        });
+
+        let (socket, _) = tokio::time::timeout(Duration::from_secs(10), listener.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        let mut lines = BufReader::new(socket).lines();
+        let mut names = Vec::new();
+        for _ in 0..2 {
+            let line = lines.next_line().await.unwrap().unwrap();
+            let event: ServerEvent = serde_json::from_str(&line).unwrap();
+            names.push(serde_json::to_value(&event.kind).unwrap()["event"].clone());
+        }
+        assert_eq!(names, ["auth_failed", "session_closed"]);
+        assert_eq!(exporter.dropped(), 0);
+
+        std::fs::remove_file(&path).ok();
+    }
+}
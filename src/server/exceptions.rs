@@ -0,0 +1,377 @@
+/// 异常通知队列与限流
+///
+/// 代理监听器、visitor 处理等通过 [`ExceptionSender`] 入队异常通知：队列有界，相同（code, message）
+/// 的通知在被事件循环取走前只占一个位置并累加次数。事件循环通过 [`ExceptionReceiver`] 取出待发送的通知，
+/// 合并窗口内的重复通知合并为一条带 `occurrences` 计数的通知，总体发送速率受令牌桶限制，
+/// 被抑制的通知定期汇总为一条通知，而不是静默丢弃
+use super::connection::ExceptionNotification;
+use crate::config::ExceptionLimitConfig;
+use crate::control_protocol;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant};
+
+/// 抑制汇总通知的代码
+pub use crate::control_protocol::EXCEPTION_EXCEPTIONS_SUPPRESSED as EXCEPTIONS_SUPPRESSED;
+
+/// 通知去重的标识（code, message）
+type Key = (Option<String>, String);
+
+fn key_of(notification: &ExceptionNotification) -> Key {
+    (notification.code.clone(), notification.message.clone())
+}
+
+/// 生产端与事件循环共享的队列状态
+#[derive(Default)]
+struct QueueState {
+    /// 已入队但尚未被取走的通知及其累计次数
+    pending: HashMap<Key, u64>,
+    /// 队列满时未能入队的通知数
+    dropped: u64,
+}
+
+/// 创建异常通知队列
+pub fn exception_channel(limits: ExceptionLimitConfig) -> (ExceptionSender, ExceptionReceiver) {
+    let (tx, rx) = mpsc::channel(limits.queue_size);
+    let state = Arc::new(parking_lot::Mutex::new(QueueState::default()));
+    let sender = ExceptionSender {
+        tx,
+        state: state.clone(),
+    };
+    let receiver = ExceptionReceiver {
+        rx,
+        state,
+        throttle: ExceptionThrottle::new(limits, Instant::now()),
+    };
+    (sender, receiver)
+}
+
+/// 异常通知发送端（可克隆，不阻塞）
+#[derive(Clone)]
+pub struct ExceptionSender {
+    tx: mpsc::Sender<ExceptionNotification>,
+    state: Arc<parking_lot::Mutex<QueueState>>,
+}
+
+impl ExceptionSender {
+    /// 入队一条通知
+    ///
+    /// 相同的通知已在队列中时只累加次数；队列满时计入抑制数量，由事件循环汇总报告
+    pub fn send(&self, notification: ExceptionNotification) {
+        let key = key_of(&notification);
+        let mut state = self.state.lock();
+        if let Some(count) = state.pending.get_mut(&key) {
+            *count += 1;
+            return;
+        }
+        match self.tx.try_send(notification) {
+            Ok(()) => {
+                state.pending.insert(key, 1);
+            }
+            Err(_) => state.dropped += 1,
+        }
+    }
+}
+
+/// 异常通知接收端（事件循环持有）
+pub struct ExceptionReceiver {
+    rx: mpsc::Receiver<ExceptionNotification>,
+    state: Arc<parking_lot::Mutex<QueueState>>,
+    throttle: ExceptionThrottle,
+}
+
+impl ExceptionReceiver {
+    /// 等待下一批需要发送的通知（可安全地在 `select!` 中取消）
+    pub async fn next_batch(&mut self) -> Vec<control_protocol::ExceptionNotification> {
+        let deadline = self.throttle.next_deadline();
+        tokio::select! {
+            Some(notification) = self.rx.recv() => {
+                let (occurrences, dropped) = {
+                    let mut state = self.state.lock();
+                    let occurrences = state.pending.remove(&key_of(&notification)).unwrap_or(1);
+                    (occurrences, std::mem::take(&mut state.dropped))
+                };
+                let now = Instant::now();
+                self.throttle.add_suppressed(dropped, now);
+                self.throttle.offer(notification, occurrences, now).into_iter().collect()
+            }
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                self.throttle.poll_due(Instant::now())
+            }
+            // 所有发送端都已释放且没有待处理的事件
+            else => std::future::pending().await,
+        }
+    }
+}
+
+/// 合并窗口
+struct Window {
+    /// 窗口结束时间
+    until: Instant,
+    /// 窗口内被合并的通知（保留最新一条）及其次数
+    held: Option<(ExceptionNotification, u64)>,
+}
+
+/// 异常通知的合并与限流（不涉及 I/O，时间由调用方传入）
+pub struct ExceptionThrottle {
+    limits: ExceptionLimitConfig,
+    /// 令牌桶剩余令牌数
+    tokens: f64,
+    /// 上次补充令牌的时间
+    refilled_at: Instant,
+    /// 最近发送过的通知的合并窗口
+    windows: HashMap<Key, Window>,
+    /// 尚未汇总报告的被抑制通知数
+    suppressed: u64,
+    /// 下一次汇总的时间（有被抑制的通知时才设置）
+    summary_at: Option<Instant>,
+}
+
+impl ExceptionThrottle {
+    pub fn new(limits: ExceptionLimitConfig, now: Instant) -> Self {
+        Self {
+            tokens: limits.burst as f64,
+            refilled_at: now,
+            limits,
+            windows: HashMap::new(),
+            suppressed: 0,
+            summary_at: None,
+        }
+    }
+
+    /// 处理一条通知（`occurrences` 为入队时已合并的次数），返回需要立即发送的通知
+    pub fn offer(
+        &mut self,
+        notification: ExceptionNotification,
+        occurrences: u64,
+        now: Instant,
+    ) -> Option<control_protocol::ExceptionNotification> {
+        self.windows
+            .retain(|_, window| window.until > now || window.held.is_some());
+
+        let key = key_of(&notification);
+        if let Some(window) = self.windows.get_mut(&key) {
+            if window.until > now {
+                let count = window.held.as_ref().map_or(0, |(_, count)| *count);
+                window.held = Some((notification, count + occurrences));
+                return None;
+            }
+        }
+        self.send_or_suppress(key, notification, occurrences, now)
+    }
+
+    /// 处理到期事件：合并窗口结束的通知和抑制汇总
+    pub fn poll_due(&mut self, now: Instant) -> Vec<control_protocol::ExceptionNotification> {
+        let expired: Vec<Key> = self
+            .windows
+            .iter()
+            .filter(|(_, window)| window.until <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        let mut outgoing = Vec::new();
+        for key in expired {
+            let Some(window) = self.windows.remove(&key) else {
+                continue;
+            };
+            if let Some((notification, count)) = window.held {
+                outgoing.extend(self.send_or_suppress(key, notification, count, now));
+            }
+        }
+
+        if self.summary_at.is_some_and(|at| at <= now) {
+            outgoing.push(self.summary());
+        }
+        outgoing
+    }
+
+    /// 下一次需要调用 [`poll_due`](Self::poll_due) 的时间
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.windows
+            .values()
+            .filter(|window| window.held.is_some())
+            .map(|window| window.until)
+            .chain(self.summary_at)
+            .min()
+    }
+
+    /// 记录在别处被抑制的通知（如队列已满）
+    pub fn add_suppressed(&mut self, count: u64, now: Instant) {
+        if count == 0 {
+            return;
+        }
+        self.suppressed += count;
+        if self.summary_at.is_none() {
+            self.summary_at = Some(now + Duration::from_secs(self.limits.summary_interval_secs));
+        }
+    }
+
+    fn send_or_suppress(
+        &mut self,
+        key: Key,
+        notification: ExceptionNotification,
+        occurrences: u64,
+        now: Instant,
+    ) -> Option<control_protocol::ExceptionNotification> {
+        if !self.take_token(now) {
+            self.add_suppressed(occurrences, now);
+            return None;
+        }
+        if self.limits.coalesce_window_secs > 0 {
+            self.windows.insert(
+                key,
+                Window {
+                    until: now + Duration::from_secs(self.limits.coalesce_window_secs),
+                    held: None,
+                },
+            );
+        }
+        Some(control_protocol::ExceptionNotification {
+            level: notification.level,
+            message: notification.message,
+            code: notification.code,
+            data: notification.data,
+            occurrences: (occurrences > 1).then_some(occurrences),
+        })
+    }
+
+    fn take_token(&mut self, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.refilled_at = now;
+        self.tokens = (self.tokens + elapsed * self.limits.per_minute as f64 / 60.0)
+            .min(self.limits.burst as f64);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 生成抑制汇总通知（汇总不受令牌桶限制，每个汇总间隔最多一条）
+    fn summary(&mut self) -> control_protocol::ExceptionNotification {
+        let suppressed = std::mem::take(&mut self.suppressed);
+        self.summary_at = None;
+        let interval = self.limits.summary_interval_secs;
+        control_protocol::ExceptionNotification {
+            level: "warning".to_string(),
+            message: format!("最近 {} 秒内有 {} 条异常通知被抑制", interval, suppressed),
+            code: Some(EXCEPTIONS_SUPPRESSED.to_string()),
+            data: serde_json::to_value(control_protocol::ExceptionsSuppressedData {
+                suppressed,
+                interval_secs: interval,
+            })
+            .ok(),
+            occurrences: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notification(message: &str) -> ExceptionNotification {
+        ExceptionNotification {
+            level: "warning".to_string(),
+            message: message.to_string(),
+            code: Some("PROXY_ACCEPT_ERROR".to_string()),
+            data: None,
+        }
+    }
+
+    fn limits() -> ExceptionLimitConfig {
+        ExceptionLimitConfig {
+            per_minute: 30,
+            burst: 5,
+            coalesce_window_secs: 10,
+            summary_interval_secs: 60,
+            queue_size: 16,
+        }
+    }
+
+    fn occurrences(sent: &[control_protocol::ExceptionNotification]) -> u64 {
+        sent.iter().map(|n| n.occurrences.unwrap_or(1)).sum()
+    }
+
+    #[test]
+    fn test_burst_of_identical_notifications_is_coalesced() {
+        let start = Instant::now();
+        let mut throttle = ExceptionThrottle::new(limits(), start);
+
+        let mut sent = Vec::new();
+        for i in 0..10_000u64 {
+            let now = start + Duration::from_micros(i * 100);
+            sent.extend(throttle.offer(notification("accept failed"), 1, now));
+        }
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].occurrences, None);
+
+        // 窗口结束后剩余的通知合并为一条
+        let due = throttle.next_deadline().unwrap();
+        assert_eq!(due, start + Duration::from_secs(10));
+        sent.extend(throttle.poll_due(due));
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[1].occurrences, Some(9_999));
+        assert_eq!(occurrences(&sent), 10_000);
+        assert!(throttle.next_deadline().is_none());
+    }
+
+    #[test]
+    fn test_rate_limit_summarizes_overflow() {
+        let start = Instant::now();
+        let mut throttle = ExceptionThrottle::new(limits(), start);
+
+        let sent: Vec<_> = (0..10_000)
+            .filter_map(|i| throttle.offer(notification(&format!("error {}", i)), 1, start))
+            .collect();
+        assert_eq!(sent.len(), 5);
+
+        let summary_at = start + Duration::from_secs(60);
+        assert_eq!(throttle.next_deadline(), Some(summary_at));
+        assert!(throttle
+            .poll_due(summary_at - Duration::from_secs(1))
+            .is_empty());
+
+        let summary = throttle.poll_due(summary_at);
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].code.as_deref(), Some(EXCEPTIONS_SUPPRESSED));
+        assert_eq!(summary[0].data.as_ref().unwrap()["suppressed"], 9_995);
+        assert!(throttle.next_deadline().is_none());
+
+        // 令牌按速率补充，最多补满突发容量
+        let later = summary_at + Duration::from_secs(2);
+        let resent = (0..10)
+            .filter(|i| {
+                throttle
+                    .offer(notification(&format!("again {}", i)), 1, later)
+                    .is_some()
+            })
+            .count();
+        assert_eq!(resent, 5);
+    }
+
+    #[tokio::test]
+    async fn test_queue_coalesces_at_producer() {
+        let (sender, mut receiver) = exception_channel(limits());
+        for _ in 0..10_000 {
+            sender.send(notification("accept failed"));
+        }
+        for i in 0..100 {
+            sender.send(notification(&format!("distinct {}", i)));
+        }
+
+        // 相同通知只占一个队列位置，其余通知填满队列后计入抑制数量
+        let mut sent = Vec::new();
+        for _ in 0..16 {
+            sent.extend(receiver.next_batch().await);
+        }
+        assert_eq!(sent[0].occurrences, Some(10_000));
+        assert_eq!(sent.len(), 5);
+        assert_eq!(receiver.throttle.suppressed, 85 + 11);
+        assert!(receiver.rx.is_empty());
+    }
+}
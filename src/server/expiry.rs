@@ -0,0 +1,96 @@
+/// 空闲代理注册的过期
+///
+/// 配置了 `idle_registration_expiry_secs` 时，每个会话定期检查自己注册的公开代理：连续没有连接
+/// （发布端口接入的连接和 visitor stream）超过阈值的代理按主动下线的流程注销（停止监听并排空），
+/// 并向客户端发送代码为 [`EXCEPTION_PROXY_EXPIRED_IDLE`] 的异常通知，客户端可以之后重新注册。
+/// 私有代理只能通过 visitor 访问，不会过期
+use super::connection::ExceptionNotification;
+use super::events::ServerEventKind;
+use super::registry::ProxyState;
+use super::ServerWorld;
+use crate::control_protocol::{ProxyExpiredData, EXCEPTION_PROXY_EXPIRED_IDLE};
+use std::time::Duration;
+use tracing::info;
+
+/// 检查间隔的上限
+const MAX_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 检查间隔的下限
+const MIN_CHECK_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 按过期阈值选择检查间隔（阈值的一半，限制在上下限之间）
+pub(super) fn check_interval(expiry: Duration) -> Duration {
+    (expiry / 2).clamp(MIN_CHECK_INTERVAL, MAX_CHECK_INTERVAL)
+}
+
+/// 注销本会话中空闲超过 `expiry` 的公开代理并通知客户端
+pub(super) async fn expire_idle_proxies(world: &mut ServerWorld, expiry: Duration) {
+    let registry = &world.state.proxy_registry;
+    let expired: Vec<((String, u16), Duration)> = world
+        .proxy_keys
+        .iter()
+        .filter_map(|key| {
+            let proxy = registry.lookup(&key.0, key.1)?;
+            if proxy.proxy_info.visibility.is_private() || proxy.state != ProxyState::Active {
+                return None;
+            }
+            let idle = registry.idle_for(key)?;
+            (idle >= expiry).then(|| (key.clone(), idle))
+        })
+        .collect();
+
+    for ((name, publish_port), idle) in expired {
+        info!(
+            "Proxy '{}' with publish_port {} had no connection for {}s, expiring registration",
+            name,
+            publish_port,
+            idle.as_secs()
+        );
+        super::release_session_proxy(world, (name.clone(), publish_port)).await;
+
+        world.exception_tx.send(ExceptionNotification {
+            level: "warning".to_string(),
+            message: format!(
+                "Proxy '{}' with publish_port {} expired after {}s without connections",
+                name,
+                publish_port,
+                idle.as_secs()
+            ),
+            code: Some(EXCEPTION_PROXY_EXPIRED_IDLE.to_string()),
+            data: serde_json::to_value(ProxyExpiredData {
+                name: name.clone(),
+                publish_port,
+                idle_secs: idle.as_secs(),
+                expiry_secs: expiry.as_secs(),
+            })
+            .ok(),
+        });
+        world.state.events.emit(ServerEventKind::ProxyExpiredIdle {
+            client_id: world.client_id.clone().unwrap_or_default(),
+            name,
+            publish_port,
+            idle_secs: idle.as_secs(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_interval() {
+        assert_eq!(
+            check_interval(Duration::from_secs(1)),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            check_interval(Duration::from_millis(100)),
+            MIN_CHECK_INTERVAL
+        );
+        assert_eq!(
+            check_interval(Duration::from_secs(3600)),
+            MAX_CHECK_INTERVAL
+        );
+    }
+}
@@ -0,0 +1,524 @@
+/// 转发连接的流量记录导出（类似 NetFlow/IPFIX 的流记录，供计费等流量核算使用）
+///
+/// 每个经代理端口接入的外部连接和每个 forward 连接是一条流：连接结束时输出最终记录，
+/// 存活超过 `interim_interval_secs` 的连接每隔该间隔输出一条中间记录。
+/// 字节数来自连接的 [`TrafficMeter`](crate::traffic::TrafficMeter) 额外挂接的流量范围，
+/// 每条记录都是截至记录时刻的累计值，同一流的最终记录即该连接的总流量
+/// （转发循环按 [`STATS_FLUSH_BYTES`](crate::stats::STATS_FLUSH_BYTES) 刷新计数器，中间记录可能略低于实际值）。
+///
+/// 记录经内部有界队列发送给写出任务，攒满 `batch_size` 条或等待 `flush_interval_ms` 后成批写出
+/// （追加到 JSONL 文件或转交事件导出）；队列满时丢弃新记录并计数，不阻塞转发路径
+use super::events::{ConnectionKind, EventExporter, ServerEvent, ServerEventKind};
+use super::registry::ProxyInfo;
+use crate::config::{FlowExportConfig, FlowExportSink};
+use crate::traffic::TrafficScope;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// 流记录（序列化后作为 `flow_record` 事件的内容）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlowRecord {
+    /// 流标识（同一连接的中间记录和最终记录相同）
+    pub flow_id: u64,
+    /// 记录类型
+    pub record: FlowRecordType,
+    /// 连接类型（proxy 或 forward）
+    pub kind: ConnectionKind,
+    /// 代理名称或 forward 目标
+    pub name: String,
+    /// 客户端会话标识（共享代理由多个会话提供，为空）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// 客户端声明的 peer_id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_id: Option<String>,
+    /// 客户端身份指纹
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+    /// 外部连接的来源地址（代理连接）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_addr: Option<String>,
+    /// 发布端口（代理连接）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish_port: Option<u16>,
+    /// 客户端的本地端口（代理连接）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_port: Option<u16>,
+    /// 外部目标（forward 连接）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// 连接开始时间（Unix 毫秒时间戳）
+    pub start_ms: u64,
+    /// 记录时间（最终记录为连接结束时间，Unix 毫秒时间戳）
+    pub end_ms: u64,
+    /// 截至记录时的持续时间（毫秒）
+    pub duration_ms: u64,
+    /// 由连接发起方流入隧道的累计字节数（外部用户或 forward 的客户端）
+    pub bytes_in: u64,
+    /// 流向连接发起方的累计字节数
+    pub bytes_out: u64,
+    /// 终止原因（仅最终记录）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// 流记录类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlowRecordType {
+    /// 长连接的中间记录
+    Interim,
+    /// 连接结束时的最终记录
+    Final,
+}
+
+/// 流量记录导出句柄（未配置导出时为空操作）
+#[derive(Debug, Clone, Default)]
+pub struct FlowExporter {
+    tx: Option<mpsc::Sender<FlowRecord>>,
+    interim_interval: Option<Duration>,
+    /// 记录中的客户端会话
+    session: FlowSession,
+    dropped: Arc<AtomicU64>,
+}
+
+/// 记录中标识客户端的字段
+#[derive(Debug, Clone, Default)]
+struct FlowSession {
+    client_id: Option<String>,
+    peer_id: Option<String>,
+    identity: Option<String>,
+}
+
+impl FlowExporter {
+    /// 不导出任何记录
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// 按配置启动写出任务（需要在 tokio 运行时中调用），`events` 为 `sink = "events"` 时的去向
+    pub fn spawn(config: &FlowExportConfig, events: &EventExporter) -> anyhow::Result<Self> {
+        let sink = match config.parse_sink()? {
+            FlowExportSink::File(path) => {
+                info!("Exporting flow records to {}", path.display());
+                FlowSinkWriter::File { path, file: None }
+            }
+            FlowExportSink::Events => {
+                if !events.is_enabled() {
+                    anyhow::bail!("flow_export.sink 'events' requires event_export");
+                }
+                info!("Exporting flow records through event export");
+                FlowSinkWriter::Events(events.clone())
+            }
+        };
+        let (tx, rx) = mpsc::channel(config.queue_size.max(1));
+        let dropped = Arc::new(AtomicU64::new(0));
+        tokio::spawn(run_writer(
+            sink,
+            rx,
+            config.batch_size.max(1),
+            config.flush_interval(),
+            dropped.clone(),
+        ));
+        Ok(Self {
+            tx: Some(tx),
+            interim_interval: config.interim_interval(),
+            session: FlowSession::default(),
+            dropped,
+        })
+    }
+
+    /// 按服务器配置创建（未配置或配置无效时不导出）
+    pub fn from_config(config: Option<&FlowExportConfig>, events: &EventExporter) -> Self {
+        match config.map(|config| Self::spawn(config, events)) {
+            Some(Ok(exporter)) => exporter,
+            Some(Err(e)) => {
+                warn!("Flow export disabled: {:#}", e);
+                Self::disabled()
+            }
+            None => Self::disabled(),
+        }
+    }
+
+    /// 是否启用了导出
+    pub fn is_enabled(&self) -> bool {
+        self.tx.is_some()
+    }
+
+    /// 共享写出任务，记录中带上客户端会话的标识、peer_id 和身份指纹
+    pub fn for_session(
+        &self,
+        client_id: Option<String>,
+        peer_id: Option<String>,
+        identity: Option<String>,
+    ) -> Self {
+        Self {
+            session: FlowSession {
+                client_id,
+                peer_id,
+                identity,
+            },
+            ..self.clone()
+        }
+    }
+
+    /// 代理端口接入的外部连接开始
+    pub fn proxy_flow(&self, proxy: &ProxyInfo, peer_addr: std::net::SocketAddr) -> Flow {
+        self.start(ConnectionKind::Proxy, &proxy.name, |record| {
+            record.peer_addr = Some(peer_addr.to_string());
+            record.publish_port = Some(proxy.publish_port);
+            record.local_port = Some(proxy.local_port);
+        })
+    }
+
+    /// forward 连接开始
+    pub fn forward_flow(&self, target: &str) -> Flow {
+        self.start(ConnectionKind::Forward, target, |record| {
+            record.target = Some(target.to_string());
+        })
+    }
+
+    fn start(&self, kind: ConnectionKind, name: &str, init: impl FnOnce(&mut FlowRecord)) -> Flow {
+        let traffic = TrafficScope::new();
+        let Some(tx) = &self.tx else {
+            return Flow {
+                traffic,
+                shared: None,
+                interim: None,
+            };
+        };
+
+        static NEXT_FLOW_ID: AtomicU64 = AtomicU64::new(1);
+        let mut record = FlowRecord {
+            flow_id: NEXT_FLOW_ID.fetch_add(1, Ordering::Relaxed),
+            record: FlowRecordType::Interim,
+            kind,
+            name: name.to_string(),
+            client_id: self.session.client_id.clone(),
+            peer_id: self.session.peer_id.clone(),
+            identity: self.session.identity.clone(),
+            peer_addr: None,
+            publish_port: None,
+            local_port: None,
+            target: None,
+            start_ms: unix_millis(),
+            end_ms: 0,
+            duration_ms: 0,
+            bytes_in: 0,
+            bytes_out: 0,
+            reason: None,
+        };
+        init(&mut record);
+        let shared = Arc::new(FlowShared {
+            tx: tx.clone(),
+            dropped: self.dropped.clone(),
+            traffic: traffic.clone(),
+            started: Instant::now(),
+            record: Mutex::new(record),
+        });
+        // 每个连接一个定时器，连接结束时随 Flow 一起取消
+        let interim = self
+            .interim_interval
+            .map(|interval| tokio::spawn(run_interim(shared.clone(), interval)));
+        Flow {
+            traffic,
+            shared: Some(shared),
+            interim,
+        }
+    }
+
+    /// 累计丢弃的记录数
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// 一条进行中的流，丢弃时输出最终记录
+///
+/// 连接的计数器挂接到 [`traffic`](Self::traffic) 后，记录中的字节数随转发实时更新
+#[derive(Debug)]
+pub struct Flow {
+    traffic: TrafficScope,
+    shared: Option<Arc<FlowShared>>,
+    interim: Option<JoinHandle<()>>,
+}
+
+impl Flow {
+    /// 流的流量范围（received 记为 bytes_in，sent 记为 bytes_out）
+    pub fn traffic(&self) -> &TrafficScope {
+        &self.traffic
+    }
+
+    /// 记录选中的共享代理后端（注册者的身份和本地端口）
+    pub fn set_owner(&self, proxy: &ProxyInfo) {
+        if let Some(shared) = &self.shared {
+            let mut record = shared.record.lock();
+            record.peer_id = proxy.peer_id.clone();
+            record.identity = proxy.identity.clone();
+            record.local_port = Some(proxy.local_port);
+        }
+    }
+
+    /// 连接结束，以 `reason` 作为终止原因输出最终记录
+    pub fn finish(self, reason: impl Into<String>) {
+        if let Some(shared) = &self.shared {
+            shared.record.lock().reason = Some(reason.into());
+        }
+    }
+}
+
+impl Drop for Flow {
+    fn drop(&mut self) {
+        if let Some(interim) = self.interim.take() {
+            interim.abort();
+        }
+        if let Some(shared) = self.shared.take() {
+            shared.emit(FlowRecordType::Final);
+        }
+    }
+}
+
+/// 流的共享状态（流本身和中间记录定时器持有）
+#[derive(Debug)]
+struct FlowShared {
+    tx: mpsc::Sender<FlowRecord>,
+    dropped: Arc<AtomicU64>,
+    traffic: TrafficScope,
+    started: Instant,
+    /// 记录模板（字节数和时间在输出时填写）
+    record: Mutex<FlowRecord>,
+}
+
+impl FlowShared {
+    /// 以当前的累计字节数输出一条记录（队列满时丢弃并计数）
+    fn emit(&self, record_type: FlowRecordType) {
+        let totals = self.traffic.totals();
+        let mut record = self.record.lock().clone();
+        record.record = record_type;
+        record.end_ms = unix_millis();
+        record.duration_ms = self.started.elapsed().as_millis() as u64;
+        record.bytes_in = totals.received;
+        record.bytes_out = totals.sent;
+        match record_type {
+            FlowRecordType::Interim => record.reason = None,
+            FlowRecordType::Final => {
+                record.reason.get_or_insert_with(|| "closed".to_string());
+            }
+        }
+        if self.tx.try_send(record).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// 中间记录定时器：每隔 `interval` 输出一条中间记录，直到被取消
+async fn run_interim(shared: Arc<FlowShared>, interval: Duration) {
+    let mut ticker = tokio::time::interval_at(Instant::now() + interval, interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        shared.emit(FlowRecordType::Interim);
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// 记录的写出目标
+enum FlowSinkWriter {
+    /// JSONL 文件（首次写出时打开，写入失败后下一批重新打开）
+    File {
+        path: PathBuf,
+        file: Option<tokio::fs::File>,
+    },
+    /// 转交事件导出
+    Events(EventExporter),
+}
+
+impl FlowSinkWriter {
+    /// 写出一批记录，写入失败时返回未写出的记录数
+    async fn write(&mut self, batch: Vec<FlowRecord>) -> Result<(), usize> {
+        match self {
+            FlowSinkWriter::File { path, file } => {
+                let mut lines = String::new();
+                for record in &batch {
+                    lines.push_str(
+                        &ServerEvent::new(ServerEventKind::FlowRecord(record.clone()))
+                            .to_json_line(),
+                    );
+                }
+                let result = async {
+                    let writer = match file {
+                        Some(writer) => writer,
+                        None => file.insert(
+                            tokio::fs::OpenOptions::new()
+                                .create(true)
+                                .append(true)
+                                .open(&*path)
+                                .await?,
+                        ),
+                    };
+                    writer.write_all(lines.as_bytes()).await?;
+                    writer.flush().await
+                }
+                .await;
+                result.map_err(|e| {
+                    warn!("Failed to write flow records to {}: {}", path.display(), e);
+                    *file = None;
+                    batch.len()
+                })
+            }
+            FlowSinkWriter::Events(events) => {
+                for record in batch {
+                    events.emit(ServerEventKind::FlowRecord(record));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// 写出任务：攒满 `batch_size` 条或自本批第一条起等待 `flush_interval` 后成批写出
+async fn run_writer(
+    mut sink: FlowSinkWriter,
+    mut rx: mpsc::Receiver<FlowRecord>,
+    batch_size: usize,
+    flush_interval: Duration,
+    dropped: Arc<AtomicU64>,
+) {
+    let mut reported_dropped = 0;
+    while let Some(first) = rx.recv().await {
+        let mut batch = Vec::with_capacity(batch_size);
+        batch.push(first);
+        let deadline = Instant::now() + flush_interval;
+        while batch.len() < batch_size {
+            match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(record)) => batch.push(record),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        if let Err(lost) = sink.write(batch).await {
+            dropped.fetch_add(lost as u64, Ordering::Relaxed);
+        }
+        let total_dropped = dropped.load(Ordering::Relaxed);
+        if total_dropped != reported_dropped {
+            warn!("Flow export dropped {} record(s) in total", total_dropped);
+            reported_dropped = total_dropped;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flow_config(sink: String, batch_size: usize, flush_interval_ms: u64) -> FlowExportConfig {
+        FlowExportConfig {
+            sink,
+            interim_interval_secs: 0,
+            batch_size,
+            flush_interval_ms,
+            queue_size: 64,
+        }
+    }
+
+    fn read_records(path: &std::path::Path) -> Vec<FlowRecord> {
+        std::fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .map(|line| {
+                let event: ServerEvent = serde_json::from_str(line).unwrap();
+                match event.kind {
+                    ServerEventKind::FlowRecord(record) => record,
+                    other => panic!("Unexpected event {:?}", other),
+                }
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_final_record_carries_totals_and_reason() {
+        let (tx, mut rx) = mpsc::channel(8);
+        let exporter = FlowExporter {
+            tx: Some(tx),
+            ..Default::default()
+        }
+        .for_session(Some("client_1".to_string()), None, None);
+
+        let flow = exporter.forward_flow("example.com:443");
+        // forward 的计数器以客户端上传为 sent，反向挂接后记为 bytes_in
+        let meter = crate::traffic::TrafficMeter::new().attach_reversed(flow.traffic());
+        meter.add_sent(1200);
+        meter.add_received(300);
+        drop(meter);
+        flow.finish("closed by target");
+
+        let record = rx.recv().await.unwrap();
+        assert_eq!(record.record, FlowRecordType::Final);
+        assert_eq!(record.kind, ConnectionKind::Forward);
+        assert_eq!(record.client_id.as_deref(), Some("client_1"));
+        assert_eq!(record.target.as_deref(), Some("example.com:443"));
+        assert_eq!((record.bytes_in, record.bytes_out), (1200, 300));
+        assert_eq!(record.reason.as_deref(), Some("closed by target"));
+        assert!(record.end_ms >= record.start_ms);
+
+        let line = ServerEvent::new(ServerEventKind::FlowRecord(record)).to_json_line();
+        let value: serde_json::Value = serde_json::from_str(line.trim()).unwrap();
+        assert_eq!(value["event"], "flow_record");
+        assert_eq!(value["record"], "final");
+        assert_eq!(value["kind"], "forward");
+    }
+
+    #[tokio::test]
+    async fn test_file_sink_batches_and_flushes() {
+        let path =
+            std::env::temp_dir().join(format!("tls-tunnel-flows-{}.jsonl", uuid::Uuid::new_v4()));
+        let exporter = FlowExporter::spawn(
+            &flow_config(format!("file:{}", path.display()), 3, 500),
+            &EventExporter::disabled(),
+        )
+        .unwrap();
+
+        // 攒满一批立即写出
+        for target in ["a:1", "b:2", "c:3"] {
+            exporter.forward_flow(target).finish("closed");
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(read_records(&path).len(), 3);
+
+        // 不满一批时等待刷新间隔后写出
+        exporter.forward_flow("d:4").finish("closed");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(read_records(&path).len(), 3);
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        let records = read_records(&path);
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[3].target.as_deref(), Some("d:4"));
+        assert_eq!(exporter.dropped(), 0);
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_events_sink_requires_event_export() {
+        let result = FlowExporter::spawn(
+            &flow_config("events".to_string(), 10, 1000),
+            &EventExporter::disabled(),
+        );
+        assert!(result.is_err());
+    }
+}
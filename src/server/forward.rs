@@ -0,0 +1,681 @@
+/// forward（`@forward`）连接的服务端限制
+///
+/// 每个客户端会话持有一个 [`ForwardLimiter`]：同时进行的 forward 连接数受信号量限制，
+/// 单个连接的存活时间和双向合计传输字节数超过 `forward_limits` 时关闭连接。
+/// 触发任何限制都会通过异常通知队列向客户端发送 `FORWARD_LIMIT_EXCEEDED`（已限流），
+/// `data.limit` 指明触发的限制。
+///
+/// 连接外部目标有超时，同时在连接中的目标数另受一个信号量限制；连接失败的目标在冷却时间内
+/// 再次被同一会话请求时直接以 `TARGET_RECENTLY_FAILED` 拒绝，客户端不能让服务器反复
+/// 等待无响应的目标
+use super::connection::ExceptionNotification;
+use super::exceptions::ExceptionSender;
+use super::forward_policy::ForwardPolicy;
+use crate::config::ForwardLimitConfig;
+use crate::control_protocol::ForwardLimitData;
+use crate::io_util::linger;
+use crate::relay_memory::RelayMemory;
+use crate::stats::ForwardUsageTracker;
+use crate::target_addr::TargetAddr;
+use crate::traffic::{TrafficMeter, TrafficScope};
+use lru::LruCache;
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::{Duration, Instant};
+use tracing::warn;
+
+/// 触发 forward 限制的异常通知代码
+pub use crate::control_protocol::EXCEPTION_FORWARD_LIMIT_EXCEEDED as FORWARD_LIMIT_EXCEEDED;
+
+/// 目标在冷却时间内连接失败过、请求被直接拒绝时，拒绝消息开头的代码
+pub const TARGET_RECENTLY_FAILED: &str = "TARGET_RECENTLY_FAILED";
+
+/// 连接外部目标的默认超时
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// 失败目标的默认冷却时间
+const DEFAULT_FAILURE_COOLDOWN: Duration = Duration::from_secs(30);
+/// 每个会话同时在连接中的目标数的默认上限
+const DEFAULT_MAX_CONNECTING: usize = 16;
+/// 每个会话记录的失败目标数上限（淘汰最久未访问的）
+const MAX_FAILED_TARGETS: usize = 256;
+
+/// 每个方向的转发缓冲区大小（两个方向同时转发时，字节上限最多被超出这么多）
+const COPY_BUFFER_SIZE: usize = 16 * 1024;
+
+/// 被触发的限制
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForwardLimit {
+    /// 存活时间
+    Duration,
+    /// 传输字节数
+    Bytes,
+    /// 会话并发连接数
+    Concurrency,
+}
+
+impl ForwardLimit {
+    /// 对应的配置项名称
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ForwardLimit::Duration => "max_duration_secs",
+            ForwardLimit::Bytes => "max_bytes",
+            ForwardLimit::Concurrency => "max_concurrent_per_client",
+        }
+    }
+}
+
+/// forward 连接的结束方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ForwardEnd {
+    /// 一端关闭或出错（附带原因）
+    Closed(String),
+    /// 触发限制被服务器关闭
+    LimitExceeded(ForwardLimit),
+}
+
+/// 连接外部目标失败的原因
+#[derive(Debug)]
+pub enum ConnectError {
+    /// 同时在连接中的目标数已达上限
+    TooManyConnecting,
+    /// 超时未建立连接
+    Timeout(Duration),
+    /// 连接失败
+    Io(std::io::Error),
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectError::TooManyConnecting => {
+                write!(f, "too many forward targets being connected")
+            }
+            ConnectError::Timeout(timeout) => {
+                write!(f, "connect timed out after {}s", timeout.as_secs())
+            }
+            ConnectError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// 占用的并发名额，释放时更新会话的 forward 统计
+pub struct ForwardPermit {
+    _permit: Option<OwnedSemaphorePermit>,
+    usage: ForwardUsageTracker,
+}
+
+impl Drop for ForwardPermit {
+    fn drop(&mut self) {
+        self.usage.stream_ended();
+    }
+}
+
+/// 单个客户端会话的 forward 限制（可克隆，所有 forward stream 共享）
+#[derive(Clone)]
+pub struct ForwardLimiter {
+    limits: ForwardLimitConfig,
+    slots: Option<Arc<Semaphore>>,
+    usage: ForwardUsageTracker,
+    exception_tx: ExceptionSender,
+    /// 转发缓冲区从服务器的全局预算领取
+    relay_memory: RelayMemory,
+    /// 正在连接中的目标
+    connecting: Arc<Semaphore>,
+    /// 最近连接失败的目标及失败时间
+    failed_targets: Arc<parking_lot::Mutex<LruCache<String, Instant>>>,
+    /// 目标访问策略（未配置 forward_policy 时为 None）
+    policy: Option<Arc<ForwardPolicy>>,
+}
+
+impl ForwardLimiter {
+    pub fn new(limits: ForwardLimitConfig, exception_tx: ExceptionSender) -> Self {
+        Self {
+            slots: limits
+                .max_concurrent_per_client
+                .map(|max| Arc::new(Semaphore::new(max))),
+            usage: ForwardUsageTracker::new(limits.clone()),
+            connecting: Arc::new(Semaphore::new(
+                limits
+                    .max_connecting_per_client
+                    .unwrap_or(DEFAULT_MAX_CONNECTING),
+            )),
+            failed_targets: Arc::new(parking_lot::Mutex::new(LruCache::new(
+                NonZeroUsize::new(MAX_FAILED_TARGETS).unwrap(),
+            ))),
+            limits,
+            exception_tx,
+            relay_memory: RelayMemory::default(),
+            policy: None,
+        }
+    }
+
+    /// 连接前按 `policy` 检查目标
+    pub fn with_policy(mut self, policy: Option<Arc<ForwardPolicy>>) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// 目标访问策略
+    pub fn policy(&self) -> Option<&ForwardPolicy> {
+        self.policy.as_deref()
+    }
+
+    /// 转发缓冲区从 `relay_memory` 领取
+    pub fn with_relay_memory(mut self, relay_memory: &RelayMemory) -> Self {
+        self.relay_memory = relay_memory.clone();
+        self
+    }
+
+    /// 会话的 forward 流量同时计入 `parent`（服务器总流量）
+    pub fn with_traffic_parent(mut self, parent: &TrafficScope) -> Self {
+        self.usage = self.usage.with_traffic_parent(parent);
+        self
+    }
+
+    /// 会话的 forward 统计
+    pub fn usage(&self) -> &ForwardUsageTracker {
+        &self.usage
+    }
+
+    /// 占用一个并发名额；已达上限时通知客户端并返回 None
+    pub fn try_acquire(&self, target: &str) -> Option<ForwardPermit> {
+        let permit = match &self.slots {
+            Some(slots) => match slots.clone().try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => {
+                    self.usage.record_concurrency_rejection();
+                    self.notify(ForwardLimit::Concurrency, target, 0, Duration::ZERO);
+                    return None;
+                }
+            },
+            None => None,
+        };
+        self.usage.stream_started();
+        Some(ForwardPermit {
+            _permit: permit,
+            usage: self.usage.clone(),
+        })
+    }
+
+    /// 目标在冷却时间内连接失败过时记录统计并返回剩余的冷却时间
+    pub fn recently_failed(&self, target: &str) -> Option<Duration> {
+        let cooldown = self
+            .limits
+            .failure_cooldown_secs
+            .map_or(DEFAULT_FAILURE_COOLDOWN, Duration::from_secs);
+        let mut failed_targets = self.failed_targets.lock();
+        let elapsed = failed_targets.get(target)?.elapsed();
+        if elapsed >= cooldown {
+            failed_targets.pop(target);
+            return None;
+        }
+        drop(failed_targets);
+        self.usage.record_fast_fail_rejection();
+        Some(cooldown - elapsed)
+    }
+
+    /// 在连接超时内连接外部目标，失败的目标进入冷却
+    pub async fn connect(&self, target: &TargetAddr) -> Result<TcpStream, ConnectError> {
+        self.connect_with(&target.to_string(), target.connect())
+            .await
+    }
+
+    /// 同 [`connect`](Self::connect)，但只连接已解析（并检查过）的地址
+    pub async fn connect_addrs(
+        &self,
+        target: &TargetAddr,
+        addrs: &[SocketAddr],
+    ) -> Result<TcpStream, ConnectError> {
+        self.connect_with(&target.to_string(), TcpStream::connect(addrs))
+            .await
+    }
+
+    async fn connect_with<S>(
+        &self,
+        target: &str,
+        connect: impl Future<Output = std::io::Result<S>>,
+    ) -> Result<S, ConnectError> {
+        let Ok(_connecting) = self.connecting.try_acquire() else {
+            self.usage.record_connecting_rejection();
+            return Err(ConnectError::TooManyConnecting);
+        };
+        let connect_timeout = self
+            .limits
+            .connect_timeout_secs
+            .map_or(DEFAULT_CONNECT_TIMEOUT, Duration::from_secs);
+        let result = match tokio::time::timeout(connect_timeout, connect).await {
+            Ok(Ok(stream)) => Ok(stream),
+            Ok(Err(e)) => Err(ConnectError::Io(e)),
+            Err(_) => {
+                self.usage.record_connect_timeout();
+                Err(ConnectError::Timeout(connect_timeout))
+            }
+        };
+
+        let mut failed_targets = self.failed_targets.lock();
+        match result {
+            Ok(_) => {
+                failed_targets.pop(target);
+            }
+            Err(_) => {
+                failed_targets.put(target.to_string(), Instant::now());
+            }
+        }
+        result
+    }
+
+    /// 在 visitor stream 和外部目标之间双向转发，直到一端关闭或触发限制
+    ///
+    /// 触发限制时关闭两端的写方向，记录统计并通知客户端；
+    /// 连接的字节数同时反向计入 `flow`（客户端上传记为 received）
+    pub async fn relay<V, E>(
+        &self,
+        visitor_stream: V,
+        external_stream: E,
+        target: &str,
+        flow: &TrafficScope,
+    ) -> ForwardEnd
+    where
+        V: AsyncRead + AsyncWrite + Unpin,
+        E: AsyncRead + AsyncWrite + Unpin,
+    {
+        let (mut visitor_read, mut visitor_write) = tokio::io::split(visitor_stream);
+        let (mut external_read, mut external_write) = tokio::io::split(external_stream);
+        // 统计和字节上限都读取这个连接的计数器
+        let meter = self.usage.meter().attach_reversed(flow);
+        let started = Instant::now();
+        let deadline = self
+            .limits
+            .max_duration_secs
+            .map(|secs| started + Duration::from_secs(secs));
+
+        let (mut upload_buf, mut download_buf) =
+            self.relay_memory.reserve_pair(COPY_BUFFER_SIZE).await;
+        let end = {
+            let visitor_to_external = self.copy_limited(
+                &mut visitor_read,
+                &mut external_write,
+                &mut upload_buf,
+                &meter,
+                true,
+            );
+            let external_to_visitor = self.copy_limited(
+                &mut external_read,
+                &mut visitor_write,
+                &mut download_buf,
+                &meter,
+                false,
+            );
+            tokio::pin!(visitor_to_external, external_to_visitor);
+
+            // 一方关闭连接（已关闭对应的写方向）后，等待另一方向读到对端的 EOF 再丢弃两端
+            tokio::select! {
+                result = &mut visitor_to_external => match result {
+                    Ok(false) => {
+                        linger(&mut external_to_visitor).await;
+                        ForwardEnd::Closed("closed by visitor".to_string())
+                    }
+                    Ok(true) => ForwardEnd::LimitExceeded(ForwardLimit::Bytes),
+                    Err(e) => {
+                        warn!("Forward '{}': Visitor to external copy error: {}", target, e);
+                        ForwardEnd::Closed(format!("visitor to external copy error: {}", e))
+                    }
+                },
+                result = &mut external_to_visitor => match result {
+                    Ok(false) => {
+                        linger(&mut visitor_to_external).await;
+                        ForwardEnd::Closed("closed by target".to_string())
+                    }
+                    Ok(true) => ForwardEnd::LimitExceeded(ForwardLimit::Bytes),
+                    Err(e) => {
+                        warn!("Forward '{}': External to visitor copy error: {}", target, e);
+                        ForwardEnd::Closed(format!("external to visitor copy error: {}", e))
+                    }
+                },
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    ForwardEnd::LimitExceeded(ForwardLimit::Duration)
+                }
+            }
+        };
+
+        if let ForwardEnd::LimitExceeded(limit) = end {
+            visitor_write.shutdown().await.ok();
+            external_write.shutdown().await.ok();
+            match limit {
+                ForwardLimit::Duration => self.usage.record_duration_limit_hit(),
+                ForwardLimit::Bytes => self.usage.record_byte_limit_hit(),
+                ForwardLimit::Concurrency => {}
+            }
+            self.notify(limit, target, meter.totals().total(), started.elapsed());
+        }
+        end
+    }
+
+    /// 单向转发，连接双向合计的字节额度用完时返回 Ok(true)，读到 EOF 时关闭写方向并返回 Ok(false)
+    ///
+    /// `upload` 为 visitor 到外部目标的方向（计为发送）
+    async fn copy_limited<R, W>(
+        &self,
+        reader: &mut R,
+        writer: &mut W,
+        buf: &mut [u8],
+        meter: &TrafficMeter,
+        upload: bool,
+    ) -> std::io::Result<bool>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        loop {
+            let allowed = match self.limits.max_bytes {
+                Some(max) => max
+                    .saturating_sub(meter.totals().total())
+                    .min(buf.len() as u64) as usize,
+                None => buf.len(),
+            };
+            if allowed == 0 {
+                return Ok(true);
+            }
+            let n = reader.read(&mut buf[..allowed]).await?;
+            if n == 0 {
+                writer.shutdown().await?;
+                return Ok(false);
+            }
+            writer.write_all(&buf[..n]).await?;
+            if upload {
+                meter.add_sent(n as u64);
+            } else {
+                meter.add_received(n as u64);
+            }
+        }
+    }
+
+    /// 向客户端发送触发限制的异常通知
+    fn notify(&self, limit: ForwardLimit, target: &str, bytes: u64, elapsed: Duration) {
+        let message = format!(
+            "Forward connection to '{}' exceeded server limit {}",
+            target,
+            limit.as_str()
+        );
+        warn!("{}", message);
+        self.exception_tx.send(ExceptionNotification {
+            level: "warning".to_string(),
+            message,
+            code: Some(FORWARD_LIMIT_EXCEEDED.to_string()),
+            data: serde_json::to_value(ForwardLimitData {
+                target: target.to_string(),
+                limit: limit.as_str().to_string(),
+                bytes,
+                duration_secs: elapsed.as_secs(),
+                limits: self.limits.clone(),
+            })
+            .ok(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ExceptionLimitConfig;
+    use crate::server::exceptions::{exception_channel, ExceptionReceiver};
+
+    const MAX_BYTES: u64 = 100 * 1024;
+
+    fn limiter(limits: ForwardLimitConfig) -> (ForwardLimiter, ExceptionReceiver) {
+        let (exception_tx, exception_rx) = exception_channel(ExceptionLimitConfig::default());
+        (ForwardLimiter::new(limits, exception_tx), exception_rx)
+    }
+
+    /// 下一条异常通知的 data
+    async fn next_notification(exception_rx: &mut ExceptionReceiver) -> serde_json::Value {
+        let batch = tokio::time::timeout(Duration::from_secs(5), exception_rx.next_batch())
+            .await
+            .expect("No exception notification was sent");
+        assert_eq!(batch[0].code.as_deref(), Some(FORWARD_LIMIT_EXCEEDED));
+        batch[0].data.clone().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_transfer_cut_off_at_max_bytes() {
+        let (limiter, mut exception_rx) = limiter(ForwardLimitConfig {
+            max_bytes: Some(MAX_BYTES),
+            ..Default::default()
+        });
+        let (visitor, mut client) = tokio::io::duplex(64 * 1024);
+        let (external, mut target) = tokio::io::duplex(64 * 1024);
+
+        // 客户端持续发送远超上限的数据，目标端统计收到的字节数
+        let sender = tokio::spawn(async move {
+            let chunk = vec![0x5a; 8192];
+            while client.write_all(&chunk).await.is_ok() {}
+        });
+        let receiver = tokio::spawn(async move {
+            let mut received = Vec::new();
+            target.read_to_end(&mut received).await.ok();
+            received.len() as u64
+        });
+
+        let _permit = limiter.try_acquire("example.com:443").unwrap();
+        let end = limiter
+            .relay(visitor, external, "example.com:443", &TrafficScope::new())
+            .await;
+        assert_eq!(end, ForwardEnd::LimitExceeded(ForwardLimit::Bytes));
+
+        let received = receiver.await.unwrap();
+        assert!(
+            (MAX_BYTES - COPY_BUFFER_SIZE as u64..=MAX_BYTES).contains(&received),
+            "Target received {} bytes",
+            received
+        );
+        sender.abort();
+
+        let data = next_notification(&mut exception_rx).await;
+        assert_eq!(data["limit"], "max_bytes");
+        assert_eq!(data["target"], "example.com:443");
+
+        let usage = limiter.usage().get_stats("client_1");
+        assert_eq!(usage.byte_limit_hits, 1);
+        assert_eq!(usage.bytes, received);
+    }
+
+    #[tokio::test]
+    async fn test_duration_limit_closes_idle_stream() {
+        let (limiter, mut exception_rx) = limiter(ForwardLimitConfig {
+            max_duration_secs: Some(1),
+            ..Default::default()
+        });
+        let (visitor, _client) = tokio::io::duplex(1024);
+        let (external, _target) = tokio::io::duplex(1024);
+
+        let end = tokio::time::timeout(
+            Duration::from_secs(5),
+            limiter.relay(visitor, external, "example.com:80", &TrafficScope::new()),
+        )
+        .await
+        .expect("Duration limit was not enforced");
+        assert_eq!(end, ForwardEnd::LimitExceeded(ForwardLimit::Duration));
+        assert_eq!(
+            next_notification(&mut exception_rx).await["limit"],
+            "max_duration_secs"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit_per_session() {
+        let (limiter, mut exception_rx) = limiter(ForwardLimitConfig {
+            max_concurrent_per_client: Some(2),
+            ..Default::default()
+        });
+
+        let first = limiter.try_acquire("a:1").unwrap();
+        let _second = limiter.try_acquire("a:2").unwrap();
+        assert!(limiter.try_acquire("a:3").is_none());
+        assert_eq!(
+            next_notification(&mut exception_rx).await["limit"],
+            "max_concurrent_per_client"
+        );
+
+        // 释放名额后可以再次打开
+        drop(first);
+        assert!(limiter.try_acquire("a:4").is_some());
+
+        let usage = limiter.usage().get_stats("client_1");
+        assert_eq!(usage.total_streams, 3);
+        assert_eq!(usage.active_streams, 1);
+        assert_eq!(usage.concurrency_rejections, 1);
+    }
+
+    /// 不响应的目标：连接一直挂起
+    async fn blackhole() -> std::io::Result<()> {
+        std::future::pending().await
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failed_target_rejected_until_cooldown_ends() {
+        let (limiter, _exception_rx) = limiter(ForwardLimitConfig {
+            connect_timeout_secs: Some(2),
+            failure_cooldown_secs: Some(30),
+            ..Default::default()
+        });
+        let target = "192.0.2.1:443";
+
+        // 第一次请求等到连接超时
+        let started = Instant::now();
+        let result = limiter.connect_with(target, blackhole()).await;
+        assert!(matches!(result, Err(ConnectError::Timeout(_))));
+        assert_eq!(started.elapsed(), Duration::from_secs(2));
+
+        // 冷却期内的重复请求立即被拒绝，其他目标不受影响
+        tokio::time::advance(Duration::from_secs(10)).await;
+        for _ in 0..3 {
+            assert_eq!(
+                limiter.recently_failed(target),
+                Some(Duration::from_secs(20))
+            );
+        }
+        assert_eq!(limiter.recently_failed("192.0.2.2:443"), None);
+
+        tokio::time::advance(Duration::from_secs(20)).await;
+        assert_eq!(limiter.recently_failed(target), None);
+
+        let usage = limiter.usage().get_stats("client_1");
+        assert_eq!(usage.connect_timeouts, 1);
+        assert_eq!(usage.fast_fail_rejections, 3);
+
+        // 连接成功后清除失败记录
+        let result = limiter.connect_with(target, blackhole()).await;
+        assert!(result.is_err());
+        assert!(limiter.connect_with(target, async { Ok(()) }).await.is_ok());
+        assert_eq!(limiter.recently_failed(target), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failure_cooldown_disabled() {
+        let (limiter, _exception_rx) = limiter(ForwardLimitConfig {
+            failure_cooldown_secs: Some(0),
+            ..Default::default()
+        });
+        let refused = async { Err::<(), _>(std::io::ErrorKind::ConnectionRefused.into()) };
+        assert!(matches!(
+            limiter.connect_with("a:1", refused).await,
+            Err(ConnectError::Io(_))
+        ));
+        assert_eq!(limiter.recently_failed("a:1"), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_connecting_attempts_bounded() {
+        let (limiter, _exception_rx) = limiter(ForwardLimitConfig {
+            connect_timeout_secs: Some(5),
+            max_connecting_per_client: Some(2),
+            ..Default::default()
+        });
+
+        // 大量请求同时连接不响应的目标：只有两个真正发起连接，其余立即被拒绝
+        let attempts: Vec<_> = (0..20)
+            .map(|i| {
+                let limiter = limiter.clone();
+                tokio::spawn(async move {
+                    let target = format!("192.0.2.{}:80", i);
+                    limiter.connect_with(&target, blackhole()).await
+                })
+            })
+            .collect();
+        let started = Instant::now();
+        let mut timeouts = 0;
+        let mut rejected = 0;
+        for attempt in attempts {
+            match attempt.await.unwrap() {
+                Err(ConnectError::Timeout(_)) => timeouts += 1,
+                Err(ConnectError::TooManyConnecting) => rejected += 1,
+                other => panic!("Unexpected result: {:?}", other.map(|_| ())),
+            }
+        }
+        assert_eq!(timeouts, 2);
+        assert_eq!(rejected, 18);
+        assert_eq!(started.elapsed(), Duration::from_secs(5));
+
+        let usage = limiter.usage().get_stats("client_1");
+        assert_eq!(usage.connecting_rejections, 18);
+        assert_eq!(usage.connect_timeouts, 2);
+
+        // 名额释放后可以再次连接
+        assert!(limiter.connect_with("a:1", async { Ok(()) }).await.is_ok());
+    }
+    #[tokio::test]
+    async fn test_limit_stats_and_global_totals_agree() {
+        let stats_manager = crate::stats::StatsManager::new();
+        let (limiter, mut exception_rx) = limiter(ForwardLimitConfig {
+            max_bytes: Some(MAX_BYTES),
+            ..Default::default()
+        });
+        let limiter = limiter.with_traffic_parent(stats_manager.traffic());
+        let (visitor, mut client) = tokio::io::duplex(64 * 1024);
+        let (external, mut target) = tokio::io::duplex(64 * 1024);
+
+        // 两个方向同时传输，合计超过上限
+        let client_task = tokio::spawn(async move {
+            let chunk = vec![0x11; 4096];
+            let mut downloaded = Vec::new();
+            let (mut read, mut write) = tokio::io::split(&mut client);
+            let upload = async { while write.write_all(&chunk).await.is_ok() {} };
+            let _ = tokio::join!(upload, read.read_to_end(&mut downloaded));
+            downloaded.len() as u64
+        });
+        let target_task = tokio::spawn(async move {
+            let chunk = vec![0x22; 4096];
+            let (mut read, mut write) = tokio::io::split(&mut target);
+            let mut uploaded = Vec::new();
+            let download = async { while write.write_all(&chunk).await.is_ok() {} };
+            let _ = tokio::join!(download, read.read_to_end(&mut uploaded));
+            uploaded.len() as u64
+        });
+
+        let end = limiter
+            .relay(visitor, external, "example.com:443", &TrafficScope::new())
+            .await;
+        assert_eq!(end, ForwardEnd::LimitExceeded(ForwardLimit::Bytes));
+        let notified = next_notification(&mut exception_rx).await["bytes"]
+            .as_u64()
+            .unwrap();
+
+        // 限制判断、通知、会话统计和服务器总流量读取的是同一个计数器
+        let usage = limiter.usage().get_stats("client_1");
+        let global = stats_manager.traffic().totals();
+        assert!(usage.bytes <= MAX_BYTES);
+        assert_eq!(usage.bytes, notified);
+        assert_eq!(usage.bytes, global.total());
+
+        let downloaded = client_task.await.unwrap();
+        let uploaded = target_task.await.unwrap();
+        assert_eq!(global.received, downloaded);
+        assert_eq!(global.sent, uploaded);
+    }
+}
@@ -0,0 +1,199 @@
+/// forward 目标访问策略
+///
+/// 服务器配置的 `forward_policy` 在启动时解析为 [`ForwardPolicy`]，`@forward` stream 在连接外部
+/// 目标之前按目标的端口、域名和解析出的地址检查：拒绝规则优先，未被允许规则命中的目标同样拒绝。
+/// 连接时只使用检查过的地址，避免检查后再次解析得到不同的地址
+use crate::config::ForwardPolicyConfig;
+use crate::target_addr::TargetAddr;
+use std::net::{IpAddr, SocketAddr};
+use tracing::warn;
+
+/// 检查域名是否匹配模式（支持通配符）
+///
+/// 与客户端路由规则的写法相同，但通配符只在标签边界匹配（`*.corp` 不匹配 `evilcorp`）
+fn domain_matches(domain: &str, pattern: &str) -> bool {
+    match pattern
+        .strip_prefix("*.")
+        .or_else(|| pattern.strip_prefix('.'))
+    {
+        // *.example.com 和 .example.com 匹配 example.com 及其所有子域名
+        Some(suffix) => {
+            domain == suffix
+                || domain
+                    .strip_suffix(suffix)
+                    .is_some_and(|prefix| prefix.ends_with('.'))
+        }
+        // 精确匹配
+        None => domain == pattern,
+    }
+}
+
+/// 解析 IP/CIDR 列表（配置已验证，无法解析的条目忽略）
+fn parse_networks(values: &[String]) -> Vec<ipnetwork::IpNetwork> {
+    values
+        .iter()
+        .filter_map(|value| match value.parse() {
+            Ok(network) => Some(network),
+            Err(e) => {
+                warn!("Failed to parse forward_policy IP/CIDR '{}': {}", value, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// 解析后的 forward 目标访问策略
+#[derive(Debug)]
+pub struct ForwardPolicy {
+    allowed_domains: Vec<String>,
+    denied_domains: Vec<String>,
+    allowed_networks: Vec<ipnetwork::IpNetwork>,
+    denied_networks: Vec<ipnetwork::IpNetwork>,
+    allowed_ports: Vec<u16>,
+}
+
+impl ForwardPolicy {
+    pub fn new(config: &ForwardPolicyConfig) -> Self {
+        let lowercase = |domains: &[String]| -> Vec<String> {
+            domains.iter().map(|d| d.to_ascii_lowercase()).collect()
+        };
+        Self {
+            allowed_domains: lowercase(&config.allowed_domains),
+            denied_domains: lowercase(&config.denied_domains),
+            allowed_networks: parse_networks(&config.allowed_cidrs),
+            denied_networks: parse_networks(&config.denied_cidrs),
+            allowed_ports: config.allowed_ports.clone(),
+        }
+    }
+
+    /// 检查目标及其解析出的地址，拒绝时返回原因
+    pub fn check(&self, target: &TargetAddr, addrs: &[SocketAddr]) -> Result<(), String> {
+        let port = target.port();
+        if !self.allowed_ports.is_empty() && !self.allowed_ports.contains(&port) {
+            return Err(format!("port {} is not in allowed_ports", port));
+        }
+
+        let domain = match target {
+            TargetAddr::Domain(domain, _) => {
+                Some(domain.trim_end_matches('.').to_ascii_lowercase())
+            }
+            TargetAddr::Ip(_) => None,
+        };
+        if let Some(ref domain) = domain {
+            if let Some(pattern) = self
+                .denied_domains
+                .iter()
+                .find(|pattern| domain_matches(domain, pattern))
+            {
+                return Err(format!("domain matches denied_domains entry '{}'", pattern));
+            }
+        }
+
+        // IPv4 映射的 IPv6 地址按 IPv4 地址匹配
+        let ips: Vec<IpAddr> = addrs.iter().map(|addr| addr.ip().to_canonical()).collect();
+        for ip in &ips {
+            if let Some(network) = self.denied_networks.iter().find(|n| n.contains(*ip)) {
+                return Err(format!("{} matches denied_cidrs entry '{}'", ip, network));
+            }
+        }
+
+        let domain_allowed = domain.is_some_and(|domain| {
+            self.allowed_domains
+                .iter()
+                .any(|pattern| domain_matches(&domain, pattern))
+        });
+        let addrs_allowed = !ips.is_empty()
+            && ips
+                .iter()
+                .all(|ip| self.allowed_networks.iter().any(|n| n.contains(*ip)));
+        if domain_allowed || addrs_allowed {
+            Ok(())
+        } else {
+            Err("target is not in allowed_domains or allowed_cidrs".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> ForwardPolicy {
+        ForwardPolicy::new(&ForwardPolicyConfig {
+            allowed_domains: vec!["*.internal.corp".to_string(), "Example.com".to_string()],
+            denied_domains: vec!["secret.internal.corp".to_string()],
+            allowed_cidrs: vec!["10.0.0.0/8".to_string()],
+            denied_cidrs: vec!["10.0.0.0/24".to_string(), "169.254.0.0/16".to_string()],
+            allowed_ports: vec![],
+        })
+    }
+
+    fn addr(ip: &str) -> SocketAddr {
+        SocketAddr::new(ip.parse().unwrap(), 443)
+    }
+
+    #[test]
+    fn test_domain_matches() {
+        assert!(domain_matches("api.internal.corp", "*.internal.corp"));
+        assert!(domain_matches("internal.corp", "*.internal.corp"));
+        assert!(domain_matches("a.b.internal.corp", ".internal.corp"));
+        assert!(!domain_matches("evilinternal.corp", "*.internal.corp"));
+        assert!(!domain_matches("www.example.com", "example.com"));
+        assert!(domain_matches("example.com", "example.com"));
+    }
+
+    #[test]
+    fn test_domain_rules() {
+        let policy = policy();
+        let target = TargetAddr::Domain("API.internal.corp.".to_string(), 443);
+        assert!(policy.check(&target, &[addr("203.0.113.1")]).is_ok());
+        let target = TargetAddr::parse("example.com:443").unwrap();
+        assert!(policy.check(&target, &[addr("203.0.113.1")]).is_ok());
+
+        // 拒绝规则优先于允许规则
+        let target = TargetAddr::parse("secret.internal.corp:443").unwrap();
+        let reason = policy.check(&target, &[addr("203.0.113.1")]).unwrap_err();
+        assert!(reason.contains("denied_domains"), "{}", reason);
+
+        // 允许的域名解析到被拒绝的地址时仍然拒绝
+        let target = TargetAddr::parse("meta.internal.corp:443").unwrap();
+        let reason = policy
+            .check(&target, &[addr("169.254.169.254")])
+            .unwrap_err();
+        assert!(reason.contains("denied_cidrs"), "{}", reason);
+    }
+
+    #[test]
+    fn test_address_rules() {
+        let policy = policy();
+        let target = TargetAddr::parse("10.1.2.3:443").unwrap();
+        assert!(policy.check(&target, &[addr("10.1.2.3")]).is_ok());
+        let target = TargetAddr::parse("10.0.0.5:443").unwrap();
+        assert!(policy.check(&target, &[addr("10.0.0.5")]).is_err());
+        // IPv4 映射的 IPv6 地址不能绕过 IPv4 规则
+        let target = TargetAddr::parse("[::ffff:10.0.0.5]:443").unwrap();
+        assert!(policy.check(&target, &[addr("::ffff:10.0.0.5")]).is_err());
+
+        // 未命中任何允许规则的目标默认拒绝，域名的所有地址都需要在允许的网段内
+        let target = TargetAddr::parse("other.example:443").unwrap();
+        let reason = policy
+            .check(&target, &[addr("10.1.2.3"), addr("203.0.113.1")])
+            .unwrap_err();
+        assert!(reason.contains("not in allowed"), "{}", reason);
+        assert!(policy.check(&target, &[addr("10.1.2.3")]).is_ok());
+    }
+
+    #[test]
+    fn test_allowed_ports() {
+        let policy = ForwardPolicy::new(&ForwardPolicyConfig {
+            allowed_domains: vec!["example.com".to_string()],
+            allowed_ports: vec![443],
+            ..Default::default()
+        });
+        let target = TargetAddr::parse("example.com:443").unwrap();
+        assert!(policy.check(&target, &[addr("203.0.113.1")]).is_ok());
+        let target = TargetAddr::parse("example.com:22").unwrap();
+        let reason = policy.check(&target, &[addr("203.0.113.1")]).unwrap_err();
+        assert!(reason.contains("allowed_ports"), "{}", reason);
+    }
+}
@@ -0,0 +1,211 @@
+/// 进程内运行服务器的 Builder 和句柄
+///
+/// `spawn` 返回前已完成端口绑定（支持 `bind_port = 0`），调用方可以通过句柄
+/// 获取实际监听地址和统计信息，并在需要时优雅停止服务器
+use super::{ServerDependencies, ServerState, ShutdownPhase};
+use crate::config::ServerConfig;
+use crate::stats::StatsManager;
+#[cfg(not(target_os = "linux"))]
+use crate::transport::create_transport_server;
+use crate::transport::TransportServer;
+use anyhow::{anyhow, Context, Result};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsAcceptor;
+use tracing::info;
+
+/// 进程内服务器入口
+pub struct Server;
+
+impl Server {
+    /// 创建服务器 Builder
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+}
+
+/// 服务器 Builder
+#[derive(Default)]
+pub struct ServerBuilder {
+    config: Option<ServerConfig>,
+    acceptor: Option<TlsAcceptor>,
+    deps: Option<ServerDependencies>,
+    config_source: Option<PathBuf>,
+    transport: Option<Arc<dyn TransportServer>>,
+    #[cfg(target_os = "linux")]
+    handover: Option<super::handover::HandoverState>,
+}
+
+impl ServerBuilder {
+    /// 设置服务器配置
+    pub fn config(mut self, config: ServerConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// 设置 TLS acceptor
+    pub fn acceptor(mut self, acceptor: TlsAcceptor) -> Self {
+        self.acceptor = Some(acceptor);
+        self
+    }
+
+    /// 设置自定义依赖（用于测试）
+    pub fn dependencies(mut self, deps: ServerDependencies) -> Self {
+        self.deps = Some(deps);
+        self
+    }
+
+    /// 设置配置文件路径（在统计服务器的 /config 端点中报告）
+    pub fn config_source(mut self, source: impl Into<PathBuf>) -> Self {
+        self.config_source = Some(source.into());
+        self
+    }
+
+    /// 使用已创建的传输层服务器（用于测试，设置后不需要 TLS acceptor）
+    pub fn transport(mut self, transport: Arc<dyn TransportServer>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// 启用监听套接字交接
+    #[cfg(target_os = "linux")]
+    pub(super) fn handover(mut self, handover: super::handover::HandoverState) -> Self {
+        self.handover = Some(handover);
+        self
+    }
+
+    /// 绑定监听端口并在后台运行服务器
+    ///
+    /// 返回时服务器已经在监听，可以立即连接
+    pub async fn spawn(self) -> Result<ServerHandle> {
+        let config = self
+            .config
+            .ok_or_else(|| anyhow!("Server config is required"))?;
+
+        info!(
+            "Starting TLS tunnel server on {}:{} using {} transport",
+            config.bind_addr, config.bind_port, config.transport
+        );
+
+        // 创建统一的状态管理（支持依赖注入）
+        let mut state = match self.deps {
+            Some(deps) => ServerState::with_dependencies(config, deps),
+            None => ServerState::new(config),
+        };
+        state.config_generation.source = self.config_source;
+        #[cfg(target_os = "linux")]
+        if let Some(handover) = self.handover {
+            state.handover = handover;
+        }
+        let state = Arc::new(state);
+
+        // 创建传输层服务器
+        let transport_server = match self.transport {
+            Some(transport) => transport,
+            None => {
+                let acceptor = self
+                    .acceptor
+                    .ok_or_else(|| anyhow!("TLS acceptor is required"))?;
+                #[cfg(target_os = "linux")]
+                let transport = super::handover::create_transport(&state, acceptor).await;
+                #[cfg(not(target_os = "linux"))]
+                let transport = create_transport_server(
+                    &state.config,
+                    acceptor,
+                    super::tunnel_http_route(&state),
+                )
+                .await;
+                transport.context("Failed to create transport server")?
+            }
+        };
+        let bound_addr = transport_server
+            .local_addr()
+            .context("Failed to get server listen address")?;
+
+        info!(
+            "Server listening on {} (transport: {})",
+            bound_addr,
+            transport_server.transport_type()
+        );
+
+        let stats_task = super::spawn_stats_server(&state);
+        let (shutdown_tx, shutdown_rx) = watch::channel(ShutdownPhase::Running);
+        let server_state = state.clone();
+        let task = tokio::spawn(async move {
+            let result = super::serve_clients(server_state, transport_server, shutdown_rx).await;
+            if let Some(stats_task) = stats_task {
+                stats_task.abort();
+            }
+            result
+        });
+
+        Ok(ServerHandle {
+            bound_addr,
+            state,
+            shutdown_tx,
+            task,
+        })
+    }
+}
+
+/// 运行中的服务器句柄
+///
+/// 句柄被丢弃时服务器同样会停止
+pub struct ServerHandle {
+    bound_addr: SocketAddr,
+    state: Arc<ServerState>,
+    shutdown_tx: watch::Sender<ShutdownPhase>,
+    task: JoinHandle<Result<()>>,
+}
+
+impl ServerHandle {
+    /// 实际监听的地址
+    pub fn bound_addr(&self) -> SocketAddr {
+        self.bound_addr
+    }
+
+    /// 服务器统计
+    pub fn stats(&self) -> StatsManager {
+        self.state.stats_manager.clone()
+    }
+
+    /// 停用代理：立即停止接受新连接，已有连接结束或排空超时后移除
+    ///
+    /// 代理不存在或已经在排空时返回 false
+    pub async fn disable_proxy(&self, name: &str, publish_port: u16) -> bool {
+        super::drain::start_drain(&self.state, (name.to_string(), publish_port)).await
+    }
+
+    /// 等待服务器退出（不发起停止）
+    pub async fn stopped(&mut self) -> Result<()> {
+        (&mut self.task)
+            .await
+            .map_err(|e| anyhow!("Server task failed: {}", e))?
+    }
+
+    /// 开始停止：不再接受新的客户端连接和代理连接，进行中的转发最多保留
+    /// `shutdown_grace_period_secs`，之后会话结束（不等待，用 [`stopped`](Self::stopped) 等待完成）
+    pub fn begin_shutdown(&self) {
+        self.shutdown_tx.send_if_modified(|phase| {
+            if *phase != ShutdownPhase::Running {
+                return false;
+            }
+            *phase = ShutdownPhase::Draining;
+            true
+        });
+    }
+
+    /// 立即结束所有会话，不再等待进行中的转发
+    pub fn force_shutdown(&self) {
+        let _ = self.shutdown_tx.send(ShutdownPhase::Force);
+    }
+
+    /// 停止接受新连接，等待进行中的转发结束（最多 `shutdown_grace_period_secs`）和服务器完全停止
+    pub async fn shutdown(mut self) -> Result<()> {
+        self.begin_shutdown();
+        self.stopped().await
+    }
+}
@@ -0,0 +1,548 @@
+/// 监听套接字交接（`--upgrade-handover`，仅 Linux）
+///
+/// 旧进程收到 SIGUSR2 后以相同参数启动新进程，通过 Unix 套接字（SCM_RIGHTS）把主监听端口
+/// 和所有代理发布端口的文件描述符交给新进程。新进程启动完成后回复确认，旧进程随即停止接受
+/// 新连接、等待现有会话结束后退出。交接期间监听套接字始终保持打开，新连接只会在内核队列中
+/// 等待，不会被拒绝。
+///
+/// 客户端重连到新进程并重新注册代理时认领对应的发布端口；超过 [`UNCLAIMED_LISTENER_TTL`]
+/// 仍未认领的端口被关闭。
+use super::registry::ProxyInfo;
+use super::{ServerBuilder, ServerState};
+use crate::shutdown::ShutdownSignals;
+use crate::systemd;
+use crate::transport::{
+    create_transport_server, create_transport_server_with_listener, TransportServer,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, UnixListener};
+use tokio::process::Command;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_rustls::TlsAcceptor;
+use tracing::{error, info, warn};
+
+/// 新进程通过该环境变量获得交接套接字路径
+const HANDOVER_SOCKET_ENV: &str = "TLS_TUNNEL_HANDOVER_SOCKET";
+
+/// 等待新进程连接并确认的最长时间
+const HANDOVER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 继承的发布端口等待客户端重新注册的最长时间
+const UNCLAIMED_LISTENER_TTL: Duration = Duration::from_secs(120);
+
+/// 单条消息携带的最大文件描述符数量（内核限制 SCM_MAX_FD = 253）
+const MAX_FDS_PER_MESSAGE: usize = 253;
+
+/// 交接头部的最大长度
+const MAX_HEADER_LEN: usize = 1024 * 1024;
+
+/// 交接头部（文件描述符按主监听端口、发布端口的顺序发送）
+#[derive(Debug, Serialize, Deserialize)]
+struct HandoverHeader {
+    /// 是否包含主监听端口
+    main: bool,
+    /// 发布端口的地址
+    publish: Vec<SocketAddr>,
+}
+
+/// 交接状态（未启用时为空操作）
+#[derive(Clone, Default)]
+pub(crate) struct HandoverState {
+    inner: Option<Arc<HandoverInner>>,
+}
+
+#[derive(Default)]
+struct HandoverInner {
+    /// 主监听端口（复制的文件描述符）
+    main: Mutex<Option<OwnedFd>>,
+    /// 正在使用的发布端口（复制的文件描述符）
+    publish: Arc<Mutex<HashMap<u64, (SocketAddr, OwnedFd)>>>,
+    next_id: AtomicU64,
+    /// 从旧进程继承的主监听端口
+    inherited_main: Mutex<Option<std::net::TcpListener>>,
+    /// 从旧进程继承、尚未被认领的发布端口
+    inherited: Mutex<HashMap<SocketAddr, std::net::TcpListener>>,
+}
+
+/// 发布端口的交接登记，监听循环结束时移除
+pub(crate) struct Registration {
+    id: u64,
+    publish: Arc<Mutex<HashMap<u64, (SocketAddr, OwnedFd)>>>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.publish.lock().remove(&self.id);
+    }
+}
+
+impl HandoverState {
+    /// 启用交接，`inherited` 为从旧进程接收的监听端口
+    fn enabled(inherited: Option<Inherited>) -> Self {
+        let inner = HandoverInner::default();
+        if let Some(inherited) = inherited {
+            *inner.inherited_main.lock() = inherited.main;
+            *inner.inherited.lock() = inherited.publish.into_iter().collect();
+        }
+        Self {
+            inner: Some(Arc::new(inner)),
+        }
+    }
+
+    /// 登记正在使用的发布端口（未启用时返回 None）
+    pub(crate) fn track(&self, listener: &TcpListener) -> Option<Registration> {
+        let inner = self.inner.as_ref()?;
+        let addr = listener.local_addr().ok()?;
+        let fd = match listener.as_fd().try_clone_to_owned() {
+            Ok(fd) => fd,
+            Err(e) => {
+                warn!("Failed to duplicate listener {} for handover: {}", addr, e);
+                return None;
+            }
+        };
+        let id = inner.next_id.fetch_add(1, Ordering::Relaxed);
+        inner.publish.lock().insert(id, (addr, fd));
+        Some(Registration {
+            id,
+            publish: inner.publish.clone(),
+        })
+    }
+
+    /// 认领从旧进程继承的发布端口
+    pub(crate) fn claim(&self, proxy: &ProxyInfo) -> Option<TcpListener> {
+        let inner = self.inner.as_ref()?;
+        let ip = proxy.publish_addr.parse().ok()?;
+        let addr = SocketAddr::new(ip, proxy.publish_port);
+        let listener = inner.inherited.lock().remove(&addr)?;
+        let listener = listener
+            .set_nonblocking(true)
+            .and_then(|()| TcpListener::from_std(listener));
+        match listener {
+            Ok(listener) => {
+                info!(
+                    "Proxy '{}' took over inherited listener on {}",
+                    proxy.name, addr
+                );
+                Some(listener)
+            }
+            Err(e) => {
+                warn!("Failed to use inherited listener on {}: {}", addr, e);
+                None
+            }
+        }
+    }
+
+    /// 关闭超时仍未被认领的继承端口
+    fn release_unclaimed(&self) {
+        let Some(inner) = self.inner.as_ref() else {
+            return;
+        };
+        for addr in inner.inherited.lock().drain().map(|(addr, _)| addr) {
+            info!("Closing unclaimed inherited listener on {}", addr);
+        }
+    }
+
+    /// 需要交给新进程的头部和文件描述符
+    fn snapshot(&self) -> Result<(HandoverHeader, Vec<OwnedFd>)> {
+        let inner = self
+            .inner
+            .as_ref()
+            .ok_or_else(|| anyhow!("Listener handover is not enabled"))?;
+        let mut fds = Vec::new();
+        let main = match inner.main.lock().as_ref() {
+            Some(fd) => {
+                fds.push(fd.try_clone()?);
+                true
+            }
+            None => false,
+        };
+        let mut publish = Vec::new();
+        for (addr, fd) in inner.publish.lock().values() {
+            publish.push(*addr);
+            fds.push(fd.try_clone()?);
+        }
+        for (addr, listener) in inner.inherited.lock().iter() {
+            publish.push(*addr);
+            fds.push(listener.as_fd().try_clone_to_owned()?);
+        }
+        Ok((HandoverHeader { main, publish }, fds))
+    }
+}
+
+/// 创建传输层服务器（启用交接时使用继承的主监听端口，并登记以便再次交接）
+pub(super) async fn create_transport(
+    state: &Arc<ServerState>,
+    acceptor: TlsAcceptor,
+) -> Result<Arc<dyn TransportServer>> {
+    let Some(inner) = state.handover.inner.as_ref() else {
+        return create_transport_server(&state.config, acceptor, super::tunnel_http_route(state))
+            .await;
+    };
+
+    let inherited = inner.inherited_main.lock().take();
+    let listener = match inherited {
+        Some(listener) => {
+            listener.set_nonblocking(true)?;
+            let listener = TcpListener::from_std(listener)?;
+            info!(
+                "Using inherited listener on {}",
+                listener
+                    .local_addr()
+                    .context("Inherited listener is invalid")?
+            );
+            listener
+        }
+        None => TcpListener::bind((state.config.bind_addr.as_str(), state.config.bind_port))
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to bind {}:{}",
+                    state.config.bind_addr, state.config.bind_port
+                )
+            })?,
+    };
+    *inner.main.lock() = Some(listener.as_fd().try_clone_to_owned()?);
+    create_transport_server_with_listener(
+        &state.config,
+        acceptor,
+        listener,
+        super::tunnel_http_route(state),
+    )
+}
+
+/// 运行支持交接的服务器：收到 SIGUSR2 时把监听端口交给新进程，成功后优雅退出
+pub(super) async fn run(builder: ServerBuilder) -> Result<()> {
+    let (inherited, ack) = match std::env::var_os(HANDOVER_SOCKET_ENV) {
+        Some(path) => {
+            let (inherited, stream) = receive(PathBuf::from(path)).await?;
+            (Some(inherited), Some(stream))
+        }
+        None => (None, None),
+    };
+    let has_inherited = inherited.is_some();
+    let handover = HandoverState::enabled(inherited);
+
+    let mut signals = ShutdownSignals::new()?;
+    let mut handle = builder.handover(handover.clone()).spawn().await?;
+    if let Some(mut stream) = ack {
+        tokio::task::spawn_blocking(move || stream.write_all(&[1]))
+            .await?
+            .context("Failed to acknowledge handover")?;
+        info!("Took over listeners from previous process");
+        notify_main_pid();
+    }
+    systemd::notify_ready();
+    if has_inherited {
+        let handover = handover.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(UNCLAIMED_LISTENER_TTL).await;
+            handover.release_unclaimed();
+        });
+    }
+
+    info!("Waiting for client connections... (Press Ctrl+C to stop, SIGUSR2 to upgrade)");
+    let mut usr2 = signal(SignalKind::user_defined2()).context("Failed to listen for SIGUSR2")?;
+    loop {
+        tokio::select! {
+            result = handle.stopped() => return result,
+            name = signals.recv() => {
+                info!("Received {}, stopping server...", name);
+                // 交接给新进程时 MAINPID 已转移，只在自行停止时报告 STOPPING
+                systemd::notify_stopping();
+                break;
+            }
+            _ = usr2.recv() => {
+                info!("Received SIGUSR2, handing over listeners to a new process...");
+                match hand_over(&handover).await {
+                    Ok(pid) => {
+                        info!("Listeners handed over to new process (pid {}), draining sessions...", pid);
+                        break;
+                    }
+                    Err(e) => error!("Listener handover failed, keep serving: {:#}", e),
+                }
+            }
+        }
+    }
+
+    super::stop_gracefully(handle, &mut signals).await
+}
+
+/// 启动新进程并交出监听端口，返回新进程的 PID
+async fn hand_over(handover: &HandoverState) -> Result<u32> {
+    let path =
+        std::env::temp_dir().join(format!("tls-tunnel-handover-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("Failed to bind handover socket {}", path.display()))?;
+    let _cleanup = RemoveOnDrop(&path);
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    }
+
+    let mut args = std::env::args_os();
+    let program = match args.next() {
+        Some(program) => PathBuf::from(program),
+        None => std::env::current_exe()?,
+    };
+    let mut child = Command::new(&program)
+        .args(args)
+        .env(HANDOVER_SOCKET_ENV, &path)
+        .stdin(std::process::Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to start {}", program.display()))?;
+    let pid = child.id().unwrap_or_default();
+    info!("Started new process (pid {})", pid);
+
+    let result = tokio::select! {
+        result = tokio::time::timeout(HANDOVER_TIMEOUT, send(listener, handover)) => {
+            result.map_err(|_| anyhow!("Timed out waiting for new process")).and_then(|r| r)
+        }
+        status = child.wait() => Err(anyhow!("New process exited during handover: {:?}", status)),
+    };
+    if result.is_err() {
+        let _ = child.kill().await;
+    }
+    result.map(|()| pid)
+}
+
+/// 等待新进程连接，发送监听端口并等待确认
+async fn send(listener: UnixListener, handover: &HandoverState) -> Result<()> {
+    let (stream, _) = listener.accept().await?;
+    let (header, fds) = handover.snapshot()?;
+    let stream = stream.into_std()?;
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(HANDOVER_TIMEOUT))?;
+
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        let mut stream = stream;
+        let header = serde_json::to_vec(&header)?;
+        stream.write_all(&(header.len() as u32).to_be_bytes())?;
+        stream.write_all(&header)?;
+        let raw: Vec<RawFd> = fds.iter().map(|fd| fd.as_raw_fd()).collect();
+        send_fds(&stream, &raw).context("Failed to send listeners")?;
+
+        let mut ack = [0u8; 1];
+        stream
+            .read_exact(&mut ack)
+            .context("New process did not acknowledge handover")?;
+        Ok(())
+    })
+    .await?
+}
+
+/// 从旧进程接收的监听端口
+struct Inherited {
+    main: Option<std::net::TcpListener>,
+    publish: Vec<(SocketAddr, std::net::TcpListener)>,
+}
+
+/// 连接旧进程并接收监听端口（返回的连接用于确认）
+async fn receive(path: PathBuf) -> Result<(Inherited, UnixStream)> {
+    tokio::task::spawn_blocking(move || -> Result<(Inherited, UnixStream)> {
+        let mut stream = UnixStream::connect(&path)
+            .with_context(|| format!("Failed to connect to handover socket {}", path.display()))?;
+        stream.set_read_timeout(Some(HANDOVER_TIMEOUT))?;
+
+        let mut len = [0u8; 4];
+        stream.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_HEADER_LEN {
+            bail!("Handover header too large: {} bytes", len);
+        }
+        let mut header = vec![0u8; len];
+        stream.read_exact(&mut header)?;
+        let header: HandoverHeader =
+            serde_json::from_slice(&header).context("Invalid handover header")?;
+
+        let count = usize::from(header.main) + header.publish.len();
+        let mut fds = recv_fds(&stream, count)
+            .context("Failed to receive listeners")?
+            .into_iter()
+            .map(std::net::TcpListener::from);
+        let main = if header.main { fds.next() } else { None };
+        let publish = header.publish.into_iter().zip(fds).collect();
+        Ok((Inherited { main, publish }, stream))
+    })
+    .await?
+}
+
+/// 通过 SCM_RIGHTS 发送文件描述符（每条消息附带 1 字节数据）
+fn send_fds(stream: &UnixStream, fds: &[RawFd]) -> io::Result<()> {
+    for chunk in fds.chunks(MAX_FDS_PER_MESSAGE) {
+        let mut data = [0u8; 1];
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr().cast(),
+            iov_len: data.len(),
+        };
+        let payload = std::mem::size_of_val(chunk) as u32;
+        let space = unsafe { libc::CMSG_SPACE(payload) } as usize;
+        // u64 保证控制消息缓冲区对齐
+        let mut control = vec![0u64; space.div_ceil(8)];
+
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = space as _;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(payload) as _;
+            std::ptr::copy_nonoverlapping(
+                chunk.as_ptr(),
+                libc::CMSG_DATA(cmsg).cast::<RawFd>(),
+                chunk.len(),
+            );
+        }
+
+        loop {
+            let sent = unsafe { libc::sendmsg(stream.as_raw_fd(), &msg, libc::MSG_NOSIGNAL) };
+            if sent >= 0 {
+                break;
+            }
+            let error = io::Error::last_os_error();
+            if error.kind() != io::ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 接收 `count` 个通过 SCM_RIGHTS 发送的文件描述符
+fn recv_fds(stream: &UnixStream, count: usize) -> io::Result<Vec<OwnedFd>> {
+    let mut fds = Vec::with_capacity(count);
+    while fds.len() < count {
+        let want = (count - fds.len()).min(MAX_FDS_PER_MESSAGE);
+        let payload = (want * std::mem::size_of::<RawFd>()) as u32;
+        let space = unsafe { libc::CMSG_SPACE(payload) } as usize;
+        let mut control = vec![0u64; space.div_ceil(8)];
+        let mut data = [0u8; 1];
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr().cast(),
+            iov_len: data.len(),
+        };
+
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = space as _;
+
+        let received =
+            unsafe { libc::recvmsg(stream.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC) };
+        if received < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(error);
+        }
+        if received == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        // 先接管所有收到的描述符，出错时它们会被关闭
+        let before = fds.len();
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                    let len = (*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+                    let data = libc::CMSG_DATA(cmsg).cast::<RawFd>();
+                    for i in 0..len / std::mem::size_of::<RawFd>() {
+                        fds.push(OwnedFd::from_raw_fd(data.add(i).read_unaligned()));
+                    }
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+        if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+            return Err(io::Error::other("Control message truncated"));
+        }
+        if fds.len() == before {
+            return Err(io::Error::other("Message carried no file descriptors"));
+        }
+    }
+    Ok(fds)
+}
+
+/// 在 systemd 下把主进程 PID 更新为当前进程（需要 `NotifyAccess=all`）
+fn notify_main_pid() {
+    let message = format!("MAINPID={}", std::process::id());
+    if let Err(e) = crate::systemd::notify(&message) {
+        warn!("Failed to notify systemd of new main PID: {}", e);
+    }
+}
+
+/// 离开作用域时删除交接套接字文件
+struct RemoveOnDrop<'a>(&'a Path);
+
+impl Drop for RemoveOnDrop<'_> {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fds_round_trip_in_batches() {
+        let (left, right) = UnixStream::pair().unwrap();
+        let listeners: Vec<std::net::TcpListener> = (0..MAX_FDS_PER_MESSAGE + 2)
+            .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let raw: Vec<RawFd> = listeners.iter().map(|l| l.as_raw_fd()).collect();
+
+        let sender = std::thread::spawn(move || send_fds(&left, &raw));
+        let received = recv_fds(&right, listeners.len()).unwrap();
+        sender.join().unwrap().unwrap();
+
+        assert_eq!(received.len(), listeners.len());
+        for (fd, listener) in received.into_iter().zip(&listeners) {
+            let received = std::net::TcpListener::from(fd);
+            assert_eq!(
+                received.local_addr().unwrap(),
+                listener.local_addr().unwrap()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_disabled_state_is_noop() {
+        let state = HandoverState::default();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        assert!(state.track(&listener).is_none());
+        assert!(state.snapshot().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_registration_removed_on_drop() {
+        let state = HandoverState::enabled(None);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let registration = state.track(&listener).unwrap();
+        let (header, fds) = state.snapshot().unwrap();
+        assert!(!header.main);
+        assert_eq!(header.publish, vec![listener.local_addr().unwrap()]);
+        assert_eq!(fds.len(), 1);
+
+        drop(registration);
+        let (header, _) = state.snapshot().unwrap();
+        assert!(header.publish.is_empty());
+    }
+}
@@ -0,0 +1,184 @@
+/// 握手并发控制
+///
+/// accept 循环只接受原始连接，握手在各自的任务中进行：配置了 `max_concurrent_handshakes`
+/// 时超出上限的连接排队等待，排队和握手的总时间超过 `handshake_timeout_secs` 的连接被关闭
+use crate::config::ServerConfig;
+use crate::stats::HandshakeStats;
+use crate::transport::{PendingTransport, Transport};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
+
+/// 默认握手超时（包括排队等待）
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 计量值的持有期间加一
+struct GaugeGuard<'a>(&'a AtomicU64);
+
+impl<'a> GaugeGuard<'a> {
+    fn new(gauge: &'a AtomicU64) -> Self {
+        gauge.fetch_add(1, Ordering::Relaxed);
+        Self(gauge)
+    }
+}
+
+impl Drop for GaugeGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// 限制同时进行的握手数并记录握手统计
+#[derive(Clone)]
+pub(crate) struct HandshakeGate {
+    permits: Option<Arc<Semaphore>>,
+    limit: Option<usize>,
+    timeout: Duration,
+}
+
+impl HandshakeGate {
+    /// 创建握手控制（limit 为 None 时不限制并发）
+    pub fn new(limit: Option<usize>, timeout: Duration) -> Self {
+        Self {
+            permits: limit.map(|n| Arc::new(Semaphore::new(n))),
+            limit,
+            timeout,
+        }
+    }
+
+    /// 从服务器配置创建
+    pub fn from_config(config: &ServerConfig) -> Self {
+        Self::new(
+            config.max_concurrent_handshakes,
+            config
+                .handshake_timeout_secs
+                .map_or(DEFAULT_HANDSHAKE_TIMEOUT, Duration::from_secs),
+        )
+    }
+
+    /// 同时进行的握手数上限
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// 等待握手名额并完成握手
+    ///
+    /// 失败、超时或连接被同端口的 HTTP 路由处理时返回 None（连接已关闭或已交给路由）
+    pub async fn complete(
+        &self,
+        pending: PendingTransport,
+        stats: &HandshakeStats,
+    ) -> Option<(Pin<Box<dyn Transport>>, Option<SocketAddr>)> {
+        let peer_addr = pending.peer_addr();
+        let peer = peer_addr.map_or_else(|| "unknown peer".to_string(), |a| a.to_string());
+        let (queued, in_progress) = stats.gauges();
+
+        let result = tokio::time::timeout(self.timeout, async {
+            let _permit = match &self.permits {
+                Some(permits) => {
+                    let _queued = GaugeGuard::new(queued);
+                    // 信号量不会被关闭
+                    Some(permits.acquire().await.ok()?)
+                }
+                None => None,
+            };
+            let _in_progress = GaugeGuard::new(in_progress);
+            Some(pending.handshake().await)
+        })
+        .await;
+
+        match result {
+            Ok(Some(Ok(Some(transport)))) => {
+                stats.record_completed();
+                Some((transport, peer_addr))
+            }
+            Ok(Some(Ok(None))) => {
+                debug!("Connection from {} was served by the HTTP route", peer);
+                None
+            }
+            Ok(Some(Err(e))) => {
+                stats.record_failed();
+                if crate::tls::is_client_cert_rejection(&e) {
+                    // 配置了 client_ca_path 时客户端未出示有效证书，属于正常的拒绝
+                    warn!(
+                        "Rejected {} without a valid client certificate: {:#}",
+                        peer, e
+                    );
+                } else {
+                    warn!("Handshake with {} failed: {:#}", peer, e);
+                }
+                None
+            }
+            Ok(None) => None,
+            Err(_) => {
+                stats.record_timed_out();
+                warn!(
+                    "Handshake with {} did not complete within {:?}, closing connection",
+                    peer, self.timeout
+                );
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::oneshot;
+
+    fn pending_until(rx: oneshot::Receiver<()>) -> PendingTransport {
+        PendingTransport::new(None, async move {
+            let _ = rx.await;
+            anyhow::bail!("handshake aborted")
+        })
+    }
+
+    #[tokio::test]
+    async fn test_limit_queues_excess_handshakes() {
+        let gate = HandshakeGate::new(Some(2), Duration::from_secs(5));
+        let stats = Arc::new(HandshakeStats::default());
+        let mut senders = Vec::new();
+        let mut tasks = Vec::new();
+        for _ in 0..5 {
+            let (tx, rx) = oneshot::channel();
+            senders.push(tx);
+            let gate = gate.clone();
+            let stats = Arc::clone(&stats);
+            tasks.push(tokio::spawn(async move {
+                gate.complete(pending_until(rx), &stats).await.is_none()
+            }));
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(stats.in_progress(), 2);
+        assert_eq!(stats.queued(), 3);
+
+        drop(senders);
+        for task in tasks {
+            assert!(task.await.unwrap());
+        }
+        assert_eq!(stats.in_progress(), 0);
+        assert_eq!(stats.queued(), 0);
+        assert_eq!(stats.failed(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_includes_queueing() {
+        let gate = HandshakeGate::new(Some(1), Duration::from_millis(100));
+        let stats = HandshakeStats::default();
+        let (_tx1, rx1) = oneshot::channel();
+        let (_tx2, rx2) = oneshot::channel();
+        let (first, second) = tokio::join!(
+            gate.complete(pending_until(rx1), &stats),
+            gate.complete(pending_until(rx2), &stats),
+        );
+        assert!(first.is_none() && second.is_none());
+        assert_eq!(stats.timed_out(), 2);
+        assert_eq!(stats.queued(), 0);
+        assert_eq!(stats.in_progress(), 0);
+    }
+}
@@ -0,0 +1,133 @@
+/// 旧版（1.0）字节协议客户端检测
+///
+/// 旧版客户端在传输层建立后直接发送 `[u32 大端密钥长度][密钥]` 并等待认证结果，而新服务器按 yamux
+/// 帧解析，双方一直等到超时。会话开始前预读前几个字节区分两者：yamux 帧头以版本号 0 和帧类型开头，
+/// 客户端第一个 stream id 很小（第 5、6 字节为 0）；旧版的密钥长度不超过 1024，紧随其后的是密钥文本。
+/// 识别为旧版客户端时按旧版认证失败的格式（`0x00` + `[u16 长度][消息]`）回复升级提示并关闭连接
+use crate::transport::Rewind;
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// 预读的字节数（旧版长度前缀 + 密钥开头 / yamux 帧头的前半部分）
+const PEEK_LEN: usize = 8;
+
+/// 旧版协议允许的最大密钥长度
+const LEGACY_MAX_KEY_LEN: u32 = 1024;
+
+/// 回复旧版客户端的错误消息
+pub(super) const UPGRADE_MESSAGE: &str =
+    "Client too old, please upgrade: this server only accepts tls-tunnel 2.0 clients";
+
+/// 预读结果
+pub(super) enum Handshake<S> {
+    /// 旧版字节协议客户端
+    Legacy(S),
+    /// 新版客户端，预读的数据会交还给 yamux
+    Current(Rewind<S>),
+}
+
+/// 预读连接开头的数据并判断客户端协议版本
+pub(super) async fn detect<S: AsyncRead + Unpin>(mut stream: S) -> io::Result<Handshake<S>> {
+    let mut prefix = Vec::with_capacity(PEEK_LEN);
+    while prefix.len() < PEEK_LEN {
+        let mut buf = [0u8; PEEK_LEN];
+        let n = stream.read(&mut buf[..PEEK_LEN - prefix.len()]).await?;
+        if n == 0 {
+            break;
+        }
+        prefix.extend_from_slice(&buf[..n]);
+    }
+
+    if is_legacy_handshake(&prefix) {
+        Ok(Handshake::Legacy(stream))
+    } else {
+        Ok(Handshake::Current(Rewind::new(prefix, stream)))
+    }
+}
+
+/// 连接开头是否为旧版认证帧
+fn is_legacy_handshake(prefix: &[u8]) -> bool {
+    let Some(header) = prefix.get(..PEEK_LEN) else {
+        return false;
+    };
+    let key_len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    // yamux 帧头的第 5、6 字节是 stream id 的高位，新会话中总是 0；密钥文本不含 NUL
+    (1..=LEGACY_MAX_KEY_LEN).contains(&key_len) && header[4] != 0 && header[5] != 0
+}
+
+/// 按旧版认证失败的格式发送错误消息并关闭写方向
+pub(super) async fn reject<S: AsyncWrite + Unpin>(stream: &mut S, message: &str) -> io::Result<()> {
+    let message = &message.as_bytes()[..message.len().min(u16::MAX as usize)];
+    let mut frame = Vec::with_capacity(3 + message.len());
+    frame.push(0x00);
+    frame.extend_from_slice(&(message.len() as u16).to_be_bytes());
+    frame.extend_from_slice(message);
+    stream.write_all(&frame).await?;
+    stream.flush().await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legacy_frame(key: &str) -> Vec<u8> {
+        let mut frame = (key.len() as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(key.as_bytes());
+        frame
+    }
+
+    #[test]
+    fn test_is_legacy_handshake() {
+        assert!(is_legacy_handshake(&legacy_frame("legacy-auth-key-1234")));
+
+        // yamux 打开第一个 stream：数据帧 / 窗口更新帧，带 SYN 标志，stream id 为 1
+        let data_syn = [0u8, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 12];
+        let window_syn = [0u8, 1, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0];
+        assert!(!is_legacy_handshake(&data_syn));
+        assert!(!is_legacy_handshake(&window_syn));
+
+        // 长度越界或数据不足
+        let mut too_long = legacy_frame("legacy-auth-key-1234");
+        too_long[..4].copy_from_slice(&4096u32.to_be_bytes());
+        assert!(!is_legacy_handshake(&too_long));
+        assert!(!is_legacy_handshake(&legacy_frame("k")));
+        assert!(!is_legacy_handshake(&[]));
+    }
+
+    #[tokio::test]
+    async fn test_detect_replays_prefix() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let frame = [0u8, 1, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0];
+        client.write_all(&frame).await.unwrap();
+        drop(client);
+
+        let Handshake::Current(mut stream) = detect(server).await.unwrap() else {
+            panic!("yamux frame detected as legacy handshake");
+        };
+        let mut replayed = Vec::new();
+        stream.read_to_end(&mut replayed).await.unwrap();
+        assert_eq!(replayed, frame);
+    }
+
+    #[tokio::test]
+    async fn test_reject_framing() {
+        let (mut client, server) = tokio::io::duplex(256);
+        client
+            .write_all(&legacy_frame("legacy-auth-key-1234"))
+            .await
+            .unwrap();
+
+        let Handshake::Legacy(mut stream) = detect(server).await.unwrap() else {
+            panic!("legacy handshake not detected");
+        };
+        reject(&mut stream, UPGRADE_MESSAGE).await.unwrap();
+
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        assert_eq!(response[0], 0x00);
+        let len = u16::from_be_bytes([response[1], response[2]]) as usize;
+        assert_eq!(&response[3..], UPGRADE_MESSAGE.as_bytes());
+        assert_eq!(len, UPGRADE_MESSAGE.len());
+    }
+}
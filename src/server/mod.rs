@@ -1,22 +1,63 @@
+mod admission;
+mod auth_keys;
+mod capacity;
 mod config;
 pub mod connection;
 mod control_channel;
+mod drain;
+pub mod events;
+mod exceptions;
+mod expiry;
+pub mod flows;
+mod forward;
+mod forward_policy;
+mod handle;
+#[cfg(target_os = "linux")]
+mod handover;
+mod handshakes;
+mod legacy;
+mod publish_addr;
 mod registry;
+mod sni;
 mod stats;
+mod udp;
 mod visitor;
+mod visitor_acl;
 mod yamux;
 
 pub use connection::ExceptionNotification;
-pub use registry::ProxyRegistry;
-
-use crate::config::ServerConfig;
+pub use events::EventExporter;
+pub use flows::FlowExporter;
+pub use handle::{Server, ServerBuilder, ServerHandle};
+pub use registry::{
+    LockHoldSnapshot, ProxyInfo, ProxySnapshot, ProxyState, Registry, RegistryEvent,
+};
+
+use crate::accept::{AcceptBackoff, AcceptErrorKind};
+use crate::config::{ConfigGeneration, ConfigValidator, ServerConfig};
+use crate::control_protocol::{
+    AssignedPort, ConfigRejectedData, PartialConfigRejectionData, StreamLimitData,
+    EXCEPTION_ALL_PROXIES_REJECTED, EXCEPTION_PARTIAL_CONFIG_REJECTION, EXCEPTION_SERVER_SHUTDOWN,
+    EXCEPTION_STREAM_LIMIT_EXCEEDED,
+};
+use crate::io_util::StallDetector;
+use crate::protocol::{PeerIdentity, ANY_PUBLISH_PORT};
+use crate::protocol_trace::{ProtocolTrace, SessionTrace, TraceDirection, TraceSide};
+use crate::shutdown::ShutdownSignals;
 use crate::stats::StatsManager;
-use crate::transport::create_transport_server;
+use crate::stats_http::StatsServers;
+use crate::systemd::{self, Watchdog};
+use crate::transport::{limit_write_chunk, HttpRoute, TransportServer};
+use crate::yamux_driver::{OutboundQueue, YamuxEvent};
 use ::yamux::{Config as YamuxConfig, Connection as YamuxConnection, Mode as YamuxMode};
-use anyhow::{Context, Result};
+use anyhow::Result;
+use auth_keys::KeyPermissions;
 use futures::future::poll_fn;
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use tokio::sync::{broadcast, mpsc, RwLock};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::task::{JoinHandle, JoinSet};
 use tokio_rustls::TlsAcceptor;
 use tokio_util::compat::TokioAsyncReadCompatExt;
 use tracing::{debug, error, info, warn};
@@ -24,13 +65,23 @@ use tracing::{debug, error, info, warn};
 // 导入 rate_limiter 类型
 use crate::rate_limiter::{RateLimiter, RateLimiterConfig};
 
-use connection::start_proxy_listener_with_notify;
-use stats::start_stats_server;
+use capacity::{SessionLoads, STREAM_REQUEST_QUEUE_SIZE};
+use connection::{run_proxy_listener, run_shared_proxy_listener, PublishSocket};
+use events::ServerEventKind;
+use exceptions::{ExceptionReceiver, ExceptionSender};
+use forward::ForwardLimiter;
+use forward_policy::ForwardPolicy;
+use handshakes::HandshakeGate;
+use registry::{
+    RegisterError, Registered, RegistrationCheck, StreamGuard, StreamLimiter, StreamRequest,
+};
+use stats::{start_stats_server, stats_route};
+use visitor_acl::{VisitorAccess, VisitorAcl};
 
 /// 服务器依赖（用于依赖注入）
 pub struct ServerDependencies {
     pub stats_manager: StatsManager,
-    pub proxy_registry: ProxyRegistry,
+    pub proxy_registry: Registry,
     pub rate_limiter: Option<Arc<RateLimiter>>,
 }
 
@@ -39,7 +90,7 @@ impl ServerDependencies {
     pub fn new() -> Self {
         Self {
             stats_manager: StatsManager::new(),
-            proxy_registry: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            proxy_registry: Registry::new(),
             rate_limiter: None,
         }
     }
@@ -56,8 +107,29 @@ impl Default for ServerDependencies {
 pub struct ServerState {
     pub config: Arc<ServerConfig>,
     pub stats_manager: StatsManager,
-    pub proxy_registry: ProxyRegistry,
+    pub proxy_registry: Registry,
     pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// 连接事件导出（未配置 event_export 时为空操作）
+    pub events: EventExporter,
+    /// 流量记录导出（未配置 flow_export 时为空操作）
+    pub flows: FlowExporter,
+    /// 加载的配置版本
+    pub config_generation: ConfigGeneration,
+    /// 协议跟踪（未配置 protocol_trace_path 时为空操作）
+    pub trace: ProtocolTrace,
+    /// 各会话的 stream 请求队列（用于容量统计）
+    pub(crate) sessions: SessionLoads,
+    /// 握手并发控制
+    pub(crate) handshakes: HandshakeGate,
+    /// 统计服务器的运行状态（`/healthz`）
+    pub(crate) stats_servers: StatsServers,
+    /// visitor 访问控制（未配置 visitor_acl 时不限制）
+    pub(crate) visitor_acl: VisitorAcl,
+    /// forward 目标访问策略（未配置 forward_policy 时不检查）
+    pub(crate) forward_policy: Option<Arc<ForwardPolicy>>,
+    /// 监听套接字交接（未启用 `--upgrade-handover` 时为空操作）
+    #[cfg(target_os = "linux")]
+    pub(crate) handover: handover::HandoverState,
 }
 
 impl ServerState {
@@ -76,13 +148,37 @@ impl ServerState {
         Self::with_dependencies(config, deps)
     }
 
-    /// 从配置和依赖创建状态
+    /// 从配置和依赖创建状态（配置了 event_export 或 flow_export 时启动导出任务，需要在 tokio 运行时中调用）
     pub fn with_dependencies(config: ServerConfig, deps: ServerDependencies) -> Self {
+        let events = EventExporter::from_config(config.event_export.as_ref());
+        let flows = FlowExporter::from_config(config.flow_export.as_ref(), &events);
+        let trace =
+            ProtocolTrace::from_config(config.protocol_trace_path.as_deref(), TraceSide::Server);
+        let handshakes = HandshakeGate::from_config(&config);
+        let visitor_acl = VisitorAcl::from_config(config.visitor_acl.as_ref());
+        let forward_policy = config
+            .forward_policy
+            .as_ref()
+            .map(|policy| Arc::new(ForwardPolicy::new(policy)));
+        deps.stats_manager
+            .relay_memory()
+            .set_budget_mb(config.relay_memory_budget_mb);
         Self {
+            config_generation: ConfigGeneration::new(&config, None),
+            trace,
             config: Arc::new(config),
+            events,
+            flows,
             stats_manager: deps.stats_manager,
             proxy_registry: deps.proxy_registry,
             rate_limiter: deps.rate_limiter,
+            sessions: SessionLoads::default(),
+            handshakes,
+            stats_servers: StatsServers::default(),
+            visitor_acl,
+            forward_policy,
+            #[cfg(target_os = "linux")]
+            handover: Default::default(),
         }
     }
 }
@@ -93,111 +189,236 @@ pub async fn run_server(config: ServerConfig, tls_acceptor: TlsAcceptor) -> Resu
 }
 
 /// 运行服务器（带自定义依赖，用于测试）
+///
+/// 收到 Ctrl+C、SIGTERM 或 SIGQUIT 后优雅停止，服务器完全停止后返回
 pub async fn run_server_with_dependencies(
     config: ServerConfig,
     tls_acceptor: TlsAcceptor,
     deps: Option<ServerDependencies>,
 ) -> Result<()> {
-    info!(
-        "Starting TLS tunnel server on {}:{} using {} transport",
-        config.bind_addr, config.bind_port, config.transport
-    );
+    let mut builder = Server::builder().config(config).acceptor(tls_acceptor);
+    if let Some(deps) = deps {
+        builder = builder.dependencies(deps);
+    }
+    run_until_shutdown(builder).await
+}
 
-    // 创建统一的状态管理（支持依赖注入）
-    let state = match deps {
-        Some(deps) => Arc::new(ServerState::with_dependencies(config, deps)),
-        None => Arc::new(ServerState::new(config)),
-    };
+/// 运行从 `source` 文件加载的服务器配置（统计服务器的 /config 端点报告该路径）
+pub async fn run_server_with_source(
+    config: ServerConfig,
+    source: std::path::PathBuf,
+    tls_acceptor: TlsAcceptor,
+) -> Result<()> {
+    let builder = Server::builder()
+        .config(config)
+        .acceptor(tls_acceptor)
+        .config_source(source);
+    run_until_shutdown(builder).await
+}
 
-    // 如果配置了统计端口，启动HTTP统计服务器
-    if let Some(stats_port) = state.config.stats_port {
-        // 使用 stats_addr，如果未配置则回退到 bind_addr
-        // validate() 已确保 bind_addr 和 stats_addr（如果存在）都不为空
-        let stats_addr = state
-            .config
-            .stats_addr
-            .as_ref()
-            .filter(|s| !s.trim().is_empty())
-            .cloned()
-            .unwrap_or_else(|| state.config.bind_addr.clone());
+/// 运行从 `source` 文件加载的服务器配置，收到 SIGUSR2 时把监听端口交给新启动的进程
+/// 后优雅退出（`--upgrade-handover`）
+#[cfg(target_os = "linux")]
+pub async fn run_server_with_handover(
+    config: ServerConfig,
+    source: std::path::PathBuf,
+    tls_acceptor: TlsAcceptor,
+) -> Result<()> {
+    let builder = Server::builder()
+        .config(config)
+        .acceptor(tls_acceptor)
+        .config_source(source);
+    handover::run(builder).await
+}
 
-        info!(
-            "Stats server will listen on http://{}:{}",
-            stats_addr, stats_port
-        );
+/// 启动服务器并在收到退出信号后优雅停止
+async fn run_until_shutdown(builder: ServerBuilder) -> Result<()> {
+    let mut signals = ShutdownSignals::new()?;
+    let mut handle = builder.spawn().await?;
+    systemd::notify_ready();
+    info!("Waiting for client connections... (Press Ctrl+C to stop)");
 
-        let stats_manager = state.stats_manager.clone();
-        tokio::spawn(async move {
-            if let Err(e) = start_stats_server(stats_addr, stats_port, stats_manager).await {
-                error!("Stats server error: {}", e);
-            }
-        });
+    tokio::select! {
+        result = handle.stopped() => return result,
+        signal = signals.recv() => {
+            info!("Received {}, stopping server...", signal);
+        }
     }
 
-    // 创建传输层服务器
-    let transport_server = create_transport_server(&state.config, tls_acceptor)
-        .await
-        .context("Failed to create transport server")?;
+    systemd::notify_stopping();
+    stop_gracefully(handle, &mut signals).await
+}
+
+/// 排空进行中的转发并等待服务器停止，期间再次收到退出信号时立即停止
+async fn stop_gracefully(mut handle: ServerHandle, signals: &mut ShutdownSignals) -> Result<()> {
+    handle.begin_shutdown();
+    tokio::select! {
+        result = handle.stopped() => {
+            result?;
+            info!("Server stopped gracefully");
+            return Ok(());
+        }
+        signal = signals.recv() => {
+            warn!("Received {} again, stopping immediately", signal);
+        }
+    }
+    handle.force_shutdown();
+    handle.stopped().await?;
+    info!("Server stopped");
+    Ok(())
+}
+
+/// 如果配置了统计端口，启动HTTP统计服务器
+fn spawn_stats_server(state: &Arc<ServerState>) -> Option<JoinHandle<()>> {
+    let stats_port = state.config.stats_port?;
+
+    // 使用 stats_addr，如果未配置则回退到 bind_addr
+    // validate() 已确保 bind_addr 和 stats_addr（如果存在）都不为空
+    let stats_addr = state
+        .config
+        .stats_addr
+        .as_ref()
+        .filter(|s| !s.trim().is_empty())
+        .cloned()
+        .unwrap_or_else(|| state.config.bind_addr.clone());
 
     info!(
-        "Server listening on {}:{} (transport: {})",
-        state.config.bind_addr,
-        state.config.bind_port,
-        transport_server.transport_type()
+        "Stats server will listen on http://{}:{}",
+        stats_addr, stats_port
     );
-    info!("Waiting for client connections... (Press Ctrl+C to stop)");
 
-    // 设置 Ctrl+C 处理
-    let shutdown = tokio::signal::ctrl_c();
-    tokio::pin!(shutdown);
+    let state = Arc::clone(state);
+    Some(tokio::spawn(async move {
+        if let Err(e) = start_stats_server(stats_addr, stats_port, state).await {
+            error!("Stats server stopped: {:#}", e);
+        }
+    }))
+}
 
-    // 接受客户端连接
-    loop {
+/// 隧道端口上的统计路由（配置了 `stats_path` 时），与独立统计服务器共用 StatsManager
+fn tunnel_http_route(state: &Arc<ServerState>) -> Option<HttpRoute> {
+    let stats_path = state.config.stats_path.clone()?;
+    info!(
+        "Stats are served on the tunnel port under {} ({} transport)",
+        stats_path, state.config.transport
+    );
+    Some(stats_route(stats_path, Arc::clone(state)))
+}
+
+/// 服务器停止阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ShutdownPhase {
+    /// 正常运行
+    Running,
+    /// 不再接受新连接，等待进行中的转发结束（最多 `shutdown_grace_period_secs`）
+    Draining,
+    /// 立即结束所有会话（排空期间再次收到退出信号）
+    Force,
+}
+
+/// 排空期间检查进行中转发数的间隔
+const SHUTDOWN_DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 接受客户端连接，直到收到停止信号（或句柄被丢弃）
+///
+/// 停止时先关闭监听端口，各会话停止接受代理连接、等待进行中的转发结束（最多
+/// `shutdown_grace_period_secs`）后结束，然后等待会话清理完毕（再加 `timeouts.shutdown_grace_secs`）。
+/// 资源耗尽时退避重试；监听套接字不可用时同样清理会话，然后返回错误
+async fn serve_clients(
+    state: Arc<ServerState>,
+    transport_server: Arc<dyn TransportServer>,
+    mut shutdown_rx: watch::Receiver<ShutdownPhase>,
+) -> Result<()> {
+    let mut sessions = JoinSet::new();
+    let mut backoff = AcceptBackoff::new(format!(
+        "Server {} listener",
+        transport_server.transport_type()
+    ));
+    let mut watchdog = Watchdog::from_env();
+
+    let result = loop {
         tokio::select! {
-            result = transport_server.accept() => {
+            result = transport_server.accept_pending() => {
                 match result {
-                    Ok(transport_stream) => {
+                    Ok(pending) => {
+                        backoff.reset();
                         info!("Accepted connection via {} transport", transport_server.transport_type());
 
-                        // 应用速率限制
+                        // 在握手之前应用速率限制，被拒绝的连接不产生握手开销
                         if let Some(ref limiter) = state.rate_limiter {
-                            match limiter.check() {
-                                Ok(_) => {
-                                    // 速率限制通过，继续处理
-                                }
-                                Err(wait_time) => {
-                                    warn!(
-                                        "Rate limit exceeded, rejecting connection (retry after {:?})",
-                                        wait_time
-                                    );
-                                    // 拒绝连接，不处理
-                                    continue;
-                                }
+                            if let Err(wait_time) = limiter.check() {
+                                state.stats_manager.handshakes().record_rate_limited();
+                                warn!(
+                                    "Rate limit exceeded, rejecting connection (retry after {:?})",
+                                    wait_time
+                                );
+                                // 丢弃连接，不处理
+                                continue;
                             }
                         }
 
                         let state = Arc::clone(&state);
-
-                        tokio::spawn(async move {
-                            if let Err(e) = handle_client_transport(transport_stream, state).await {
+                        let shutdown_rx = shutdown_rx.clone();
+
+                        // 握手在会话任务中进行（受 max_concurrent_handshakes 和握手超时限制）
+                        sessions.spawn(async move {
+                            let handshakes = state.stats_manager.handshakes();
+                            let Some((transport_stream, peer_addr)) =
+                                state.handshakes.complete(pending, handshakes).await
+                            else {
+                                return;
+                            };
+                            if let Err(e) = handle_client_transport(transport_stream, peer_addr, state, shutdown_rx).await {
                                 error!("Client error: {}", e);
                             }
                         });
                     }
                     Err(e) => {
-                        error!("Failed to accept connection: {}", e);
+                        let kind = AcceptErrorKind::of_transport(&e);
+                        if kind == AcceptErrorKind::Resource {
+                            state.stats_manager.record_listener_accept_error();
+                        }
+                        if !backoff.on_error(kind, &e).await {
+                            break Err(e.context("Server listener stopped"));
+                        }
                     }
                 }
             }
-            _ = &mut shutdown => {
-                info!("Received shutdown signal, stopping server...");
-                break;
+            // 回收已结束的会话
+            Some(_) = sessions.join_next(), if !sessions.is_empty() => {}
+            // 接受循环仍在运行，通知 systemd 看门狗
+            _ = watchdog.keepalive() => {}
+            _ = shutdown_rx.changed() => {
+                break Ok(());
             }
         }
-    }
+    };
 
-    info!("Server stopped gracefully");
-    Ok(())
+    // 停止接受新连接
+    drop(transport_server);
+
+    let drain_period = state.config.shutdown_grace_period();
+    if !sessions.is_empty() {
+        info!(
+            "Waiting for {} session(s) to drain (up to {:?}, press Ctrl+C again to stop immediately)",
+            sessions.len(),
+            drain_period
+        );
+    }
+    let shutdown_grace = drain_period + state.config.timeouts().shutdown_grace();
+    let drained = tokio::time::timeout(shutdown_grace, async {
+        while sessions.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_err() {
+        warn!(
+            "{} session(s) did not close within {:?}, aborting",
+            sessions.len(),
+            shutdown_grace
+        );
+        sessions.shutdown().await;
+    }
+    result
 }
 
 /// 服务器会话状态
@@ -217,40 +438,178 @@ struct ServerWorld {
 
     state: Arc<ServerState>,
     session_state: SessionState,
-    stream_tx: mpsc::Sender<(mpsc::Sender<::yamux::Stream>, u16, String)>,
-    stream_rx: mpsc::Receiver<(mpsc::Sender<::yamux::Stream>, u16, String)>,
+    stream_tx: mpsc::Sender<StreamRequest>,
+    stream_rx: mpsc::Receiver<StreamRequest>,
+    /// 等待创建 stream 的代理/visitor 请求（与入站 stream 在同一次 poll 中驱动）
+    outbound: OutboundQueue<(StreamRequest, StreamGuard)>,
+    /// 本会话转发中的 stream 数（`max_streams_per_client`）
+    streams: StreamLimiter,
     shutdown_tx: broadcast::Sender<()>,
     proxy_keys: Vec<(String, u16)>,
     client_id: Option<String>,
-    exception_tx: mpsc::UnboundedSender<connection::ExceptionNotification>,
-    exception_rx: mpsc::UnboundedReceiver<connection::ExceptionNotification>,
+    /// 客户端认证时声明的 peer_id
+    peer_id: Option<String>,
+    /// 与客户端协商的 peer 身份字段
+    peer_identity: PeerIdentity,
+    /// 本会话下发、尚未被认证消耗的身份挑战 nonce
+    identity_nonce: Option<Vec<u8>>,
+    /// 认证通过的客户端身份指纹
+    identity: Option<String>,
+    /// 客户端是否支持 visitor 连接复用
+    visitor_mux: bool,
+    /// 客户端是否支持分帧的空闲保活 stream
+    idle_keepalive: bool,
+    /// 客户端是否支持携带外部连接信息的 stream 前导
+    peer_report: bool,
+    /// 客户端是否支持完整性校验尾帧
+    integrity_check: bool,
+    exception_tx: ExceptionSender,
+    exception_rx: ExceptionReceiver,
+    /// 本会话 forward 连接的限制与统计
+    forward: ForwardLimiter,
+    /// 本会话 visitor 可访问的代理（认证后按客户端身份确定）
+    visitor_access: VisitorAccess,
+    /// 本会话认证所用密钥的权限（发布端口、forward）
+    permissions: KeyPermissions,
+    /// 服务器停止信号
+    server_shutdown: watch::Receiver<ShutdownPhase>,
+    /// 服务器停止时本会话的代理正在排空
+    draining: bool,
+    /// 本会话的协议跟踪
+    trace: SessionTrace,
 }
 
 impl ServerWorld {
+    /// 计入一个转发 stream；会话的 stream 数已达上限时向客户端发送警告并返回 None
+    ///
+    /// `proxy` 为服务器打开的 stream 所属的代理，客户端打开的 stream 为 None
+    fn acquire_stream(&self, proxy: Option<(&str, u16)>) -> Option<StreamGuard> {
+        let guard = self.streams.try_acquire();
+        if guard.is_none() {
+            let max_streams = self.streams.max().unwrap_or_default();
+            let message = match proxy {
+                Some((name, port)) => format!(
+                    "会话的 stream 数已达上限 {}，代理 '{}'（端口 {}）的连接被拒绝",
+                    max_streams, name, port
+                ),
+                None => format!(
+                    "会话的 stream 数已达上限 {}，客户端打开的 stream 被拒绝",
+                    max_streams
+                ),
+            };
+            warn!("{}", message);
+            self.exception_tx.send(ExceptionNotification {
+                level: "warning".to_string(),
+                message,
+                code: Some(EXCEPTION_STREAM_LIMIT_EXCEEDED.to_string()),
+                data: serde_json::to_value(StreamLimitData {
+                    proxy_name: proxy.map(|(name, _)| name.to_string()),
+                    publish_port: proxy.map(|(_, port)| port),
+                    max_streams,
+                })
+                .ok(),
+            });
+        }
+        guard
+    }
+
     /// 清理资源
     async fn cleanup(&mut self) {
         info!("Cleaning up server resources");
+        crate::chaos::delay("server.session_cleanup").await;
+
+        // 清理注册表：只移除本会话的后端，共享代理的最后一个后端离开时移除整个条目
+        // （同时关闭注册表项所有的共享监听器）
+        for key in self.proxy_keys.drain(..) {
+            let Some(removed) = self.state.proxy_registry.unregister(&key, &self.stream_tx) else {
+                continue;
+            };
+            self.state.events.emit(ServerEventKind::ProxyUnregistered {
+                client_id: self.client_id.clone().unwrap_or_default(),
+                name: key.0.clone(),
+                publish_port: key.1,
+                remaining_backends: removed.remaining_backends,
+            });
+            if removed.remaining_backends == 0 {
+                info!("Unregistered proxy '{}' with port {}", key.0, key.1);
+                // 私有代理没有监听循环，统计在这里注销（服务器停止排空时监听循环已经提前返回）
+                if removed.visibility.is_private() || self.draining {
+                    self.state.stats_manager.unregister_proxy(&key.0);
+                }
+            } else {
+                info!(
+                    "Backend left shared proxy '{}' with port {}, {} backend(s) remaining",
+                    key.0, key.1, removed.remaining_backends
+                );
+            }
+        }
 
-        // 清理注册表
-        let mut registry = self.state.proxy_registry.write().await;
-        for key in &self.proxy_keys {
-            info!("Unregistering proxy '{}' with port {}", key.0, key.1);
-            registry.remove(key);
+        if let Some(client_id) = &self.client_id {
+            self.state.stats_manager.unregister_forward_usage(client_id);
         }
 
         // 通知所有监听器关闭
         let _ = self.shutdown_tx.send(());
     }
+
+    /// 服务器停止时开始排空本会话的代理：停止接受新的代理连接和 visitor stream，已有的转发继续
+    fn start_shutdown_drain(&mut self) {
+        self.draining = true;
+        for key in &self.proxy_keys {
+            self.state
+                .proxy_registry
+                .set_state(key, registry::ProxyState::Draining);
+        }
+    }
+
+    /// 本会话代理进行中的转发数（代理连接和 visitor stream）
+    fn active_relays(&self) -> usize {
+        self.proxy_keys
+            .iter()
+            .filter_map(|(name, port)| self.state.proxy_registry.lookup(name, *port))
+            .map(|proxy| proxy.drain.active_relays())
+            .sum()
+    }
 }
 
 /// 处理客户端传输连接（使用传输抽象）
 async fn handle_client_transport(
     transport_stream: std::pin::Pin<Box<dyn crate::transport::Transport>>,
+    peer_addr: Option<std::net::SocketAddr>,
     state: Arc<crate::server::ServerState>,
+    mut server_shutdown: watch::Receiver<ShutdownPhase>,
 ) -> Result<()> {
-    let tls_stream = transport_stream;
+    // 按配置限制单次写入（TLS 记录）大小
+    let tls_stream = limit_write_chunk(transport_stream, state.config.max_write_chunk);
 
     info!("Transport connection established");
+    let _task = crate::chaos::task("server.session");
+
+    // 预读连接开头的数据，识别仍在使用 1.0 字节协议的旧版客户端
+    let session_setup = state.config.timeouts().session_setup();
+    let handshake = tokio::select! {
+        result = tokio::time::timeout(session_setup, legacy::detect(tls_stream)) => {
+            match result {
+                Ok(handshake) => handshake?,
+                Err(_) => anyhow::bail!("Client sent no data within {:?}", session_setup),
+            }
+        }
+        _ = server_shutdown.changed() => return Ok(()),
+    };
+    let tls_stream: std::pin::Pin<Box<dyn crate::transport::Transport>> = match handshake {
+        legacy::Handshake::Current(stream) => Box::pin(stream),
+        legacy::Handshake::Legacy(mut stream) => {
+            let peer =
+                peer_addr.map_or_else(|| "unknown peer".to_string(), |addr| addr.to_string());
+            warn!(
+                "Legacy (1.0 byte protocol) client from {} rejected, the client must be upgraded",
+                peer
+            );
+            state.stats_manager.record_legacy_client_rejected();
+            legacy::reject(&mut stream, legacy::UPGRADE_MESSAGE).await?;
+            return Ok(());
+        }
+    };
 
     // 建立 yamux 连接
     let yamux_config = YamuxConfig::default();
@@ -260,16 +619,30 @@ async fn handle_client_transport(
     info!("Yamux connection established");
 
     // 创建控制通道（在获取控制流之前）
+    let trace = state.trace.session();
     let (control_channel, event_rx) = control_channel::ServerControlChannel::new();
+    let control_channel = control_channel.with_trace(trace.clone());
 
     // 创建channel用于请求新的yamux streams
-    let (stream_tx, stream_rx) = mpsc::channel::<(mpsc::Sender<::yamux::Stream>, u16, String)>(100);
+    let (stream_tx, stream_rx) = mpsc::channel::<StreamRequest>(STREAM_REQUEST_QUEUE_SIZE);
+    let _session_load = state.sessions.register(&stream_tx);
 
     // 创建broadcast channel用于监控yamux连接状态
     let (shutdown_tx, _) = broadcast::channel::<()>(1);
 
-    // 创建异常通知通道
-    let (exception_tx, exception_rx) = mpsc::unbounded_channel();
+    // 创建异常通知队列（合并重复通知并限流，避免淹没控制通道）
+    let (exception_tx, exception_rx) =
+        exceptions::exception_channel(state.config.exception_limits.clone().unwrap_or_default());
+    let forward = ForwardLimiter::new(
+        state.config.forward_limits.clone().unwrap_or_default(),
+        exception_tx.clone(),
+    )
+    .with_traffic_parent(state.stats_manager.traffic())
+    .with_relay_memory(state.stats_manager.relay_memory())
+    .with_policy(state.forward_policy.clone());
+
+    let permissions = KeyPermissions::legacy(&state.config);
+    let streams = StreamLimiter::new(state.config.max_streams_per_client());
 
     // 创建服务器世界对象（包含 yamux_conn，用于在事件循环中 poll）
     let world = ServerWorld {
@@ -278,11 +651,27 @@ async fn handle_client_transport(
         session_state: SessionState::Authenticating,
         stream_tx,
         stream_rx,
+        outbound: OutboundQueue::new(),
+        streams,
         shutdown_tx,
         proxy_keys: Vec::new(),
         client_id: None,
+        peer_id: None,
+        peer_identity: PeerIdentity::Unsupported,
+        identity_nonce: None,
+        identity: None,
+        visitor_mux: false,
+        idle_keepalive: false,
+        peer_report: false,
+        integrity_check: false,
         exception_tx,
         exception_rx,
+        forward,
+        visitor_access: VisitorAccess::default(),
+        permissions,
+        server_shutdown,
+        draining: false,
+        trace,
     };
 
     // 运行统一事件循环
@@ -292,6 +681,44 @@ async fn handle_client_transport(
     Ok(())
 }
 
+/// 校验认证请求，成功时返回所用密钥的权限和客户端身份指纹
+///
+/// 密钥可以是 `auth_key` 或 `auth_keys` 中的任意一个；
+/// 客户端附带身份证明时必须能用本会话下发的 nonce 验证通过；
+/// 配置了 `known_clients` 时，只接受列表中的身份。
+fn authenticate_client(
+    config: &ServerConfig,
+    auth_key: &str,
+    proof: Option<&crate::control_protocol::IdentityProof>,
+    nonce: Option<Vec<u8>>,
+) -> std::result::Result<(KeyPermissions, Option<String>), &'static str> {
+    let Some(permissions) = KeyPermissions::resolve(config, auth_key) else {
+        return Err("Invalid authentication key");
+    };
+
+    let identity = match (proof, nonce) {
+        (Some(proof), Some(nonce)) => Some(
+            crate::identity::verify_proof(proof, &nonce)
+                .map_err(|_| "Invalid client identity signature")?,
+        ),
+        (Some(_), None) => return Err("Client identity proof without a challenge"),
+        (None, _) => None,
+    };
+
+    if let Some(known_clients) = &config.known_clients {
+        match &identity {
+            Some(fingerprint)
+                if known_clients
+                    .iter()
+                    .any(|known| crate::identity::fingerprint_eq(known, fingerprint)) => {}
+            Some(_) => return Err("Client identity is not in known_clients"),
+            None => return Err("Client identity is required by known_clients"),
+        }
+    }
+
+    Ok((permissions, identity))
+}
+
 /// 处理代理配置提交（独立函数避免借用冲突）
 async fn handle_proxy_config_submission(
     world: &mut ServerWorld,
@@ -301,90 +728,23 @@ async fn handle_proxy_config_submission(
     proxies: Vec<crate::config::ProxyConfig>,
     visitors: Vec<crate::config::VisitorConfig>,
 ) -> Result<bool> {
-    use std::collections::HashSet;
-
     // 验证代理配置
-    let mut seen_names = HashSet::new();
-    let mut seen_bind = HashSet::new();
-
-    for proxy in &proxies {
-        // 检查 name 唯一性
-        if !seen_names.insert(&proxy.name) {
-            error!("Duplicate proxy name '{}'", proxy.name);
-            control_channel
-                .send_config_rejected(
-                    control_stream,
-                    id,
-                    vec![format!("Duplicate proxy name: {}", proxy.name)],
-                )
-                .await?;
-            return Ok(false);
-        }
-
-        // 检查 (publish_addr, publish_port) 唯一性
-        if !seen_bind.insert((proxy.publish_addr.clone(), proxy.publish_port)) {
-            error!(
-                "Duplicate publish binding {}:{}",
-                proxy.publish_addr, proxy.publish_port
-            );
-            control_channel
-                .send_config_rejected(
-                    control_stream,
-                    id,
-                    vec![format!(
-                        "Duplicate binding: {}:{}",
-                        proxy.publish_addr, proxy.publish_port
-                    )],
-                )
-                .await?;
-            return Ok(false);
-        }
-
-        // 验证端口和地址有效性
-        if proxy.publish_port == 0
-            || proxy.local_port == 0
-            || proxy.publish_addr.trim().is_empty()
-            || proxy.name.trim().is_empty()
-        {
-            error!("Invalid proxy configuration: {}", proxy.name);
-            control_channel
-                .send_config_rejected(
-                    control_stream,
-                    id,
-                    vec![format!("Invalid proxy: {}", proxy.name)],
-                )
-                .await?;
-            return Ok(false);
-        }
-
-        // 检查是否与服务器端口冲突
-        if proxy.publish_port == world.state.config.bind_port {
-            error!("Proxy '{}' port conflicts with server port", proxy.name);
-            control_channel
-                .send_config_rejected(
-                    control_stream,
-                    id,
-                    vec![format!("Port conflict: {}", proxy.name)],
-                )
-                .await?;
-            return Ok(false);
-        }
+    if let Err(reason) = check_submitted_proxies(&proxies, world.state.config.bind_port) {
+        control_channel
+            .send_config_rejected(control_stream, id, vec![reason], BTreeMap::new())
+            .await?;
+        return Ok(false);
     }
 
-    // 预检查哪些代理会被拒绝
-    let mut rejected_proxies: Vec<String> = Vec::new();
-    {
-        let registry = world.state.proxy_registry.read().await;
-        for proxy in &proxies {
-            let key = (proxy.name.clone(), proxy.publish_port);
-            if registry.contains_key(&key) {
-                rejected_proxies.push(format!("{}:{}", proxy.name, proxy.publish_port));
-            }
-        }
-    }
+    let ProxyRegistrationOutcome {
+        accepted,
+        rejected: rejected_proxies,
+        reasons: mut reject_reasons,
+        assigned,
+    } = register_proxies(world, &proxies).await;
 
-    // 如果所有代理都会被拒绝
-    if !proxies.is_empty() && rejected_proxies.len() == proxies.len() {
+    // 如果所有代理都被拒绝
+    if !proxies.is_empty() && accepted == 0 {
         error!("All proxies rejected: {}", rejected_proxies.join(", "));
 
         // 发送异常通知给客户端
@@ -393,70 +753,36 @@ async fn handle_proxy_config_submission(
                 control_stream,
                 "error",
                 format!("所有代理配置被拒绝：{}", rejected_proxies.join(", ")),
-                Some("ALL_PROXIES_REJECTED".to_string()),
-                Some(serde_json::json!({
-                    "rejected_proxies": &rejected_proxies,
-                    "reason": "端口或名称冲突"
-                })),
+                Some(EXCEPTION_ALL_PROXIES_REJECTED.to_string()),
+                serde_json::to_value(ConfigRejectedData {
+                    rejected_proxies: rejected_proxies.clone(),
+                    reasons: reject_reasons.clone(),
+                })
+                .ok(),
             )
             .await;
 
         control_channel
-            .send_config_rejected(control_stream, id, rejected_proxies)
+            .send_config_rejected(control_stream, id, rejected_proxies, reject_reasons)
             .await?;
         return Ok(false);
     }
 
-    // 注册代理
-    {
-        let mut registry = world.state.proxy_registry.write().await;
-        for proxy in &proxies {
-            let key = (proxy.name.clone(), proxy.publish_port);
-
-            if registry.contains_key(&key) {
-                warn!(
-                    "Proxy '{}' with publish_port {} is already registered, skipping",
-                    proxy.name, proxy.publish_port
-                );
-            } else {
-                info!(
-                    "Registering proxy '{}' with publish_port {}",
-                    proxy.name, proxy.publish_port
-                );
-
-                let proxy_info = registry::ProxyInfo {
-                    name: proxy.name.clone(),
-                    proxy_type: proxy.proxy_type,
-                    publish_addr: proxy.publish_addr.clone(),
-                    publish_port: proxy.publish_port,
-                    local_port: proxy.local_port,
-                };
-
-                registry.insert(
-                    key.clone(),
-                    registry::ProxyRegistration {
-                        stream_tx: world.stream_tx.clone(),
-                        proxy_info,
-                    },
-                );
-                world.proxy_keys.push(key);
-            }
-        }
-    }
-
     // 验证 visitor 配置：检查对应的 proxy 是否存在
     let mut rejected_visitors: Vec<String> = Vec::new();
     if !visitors.is_empty() {
-        let registry = world.state.proxy_registry.read().await;
+        let registry = &world.state.proxy_registry;
         for visitor in &visitors {
-            // visitor 通过 name 和 publish_port 查找对应的 proxy
-            let key = (visitor.name.clone(), visitor.publish_port);
-            if !registry.contains_key(&key) {
+            // visitor 通过 name 和 publish_port 查找对应的 proxy（publish_port 为 0 时按名称查找，
+            // 可以引用由服务器分配端口的代理）
+            if !registry.is_registered(&visitor.name, visitor.publish_port) {
                 warn!(
                     "Visitor '{}' references non-existent proxy '{}:{}', will be unavailable",
                     visitor.name, visitor.name, visitor.publish_port
                 );
-                rejected_visitors.push(format!("{}:{}", visitor.name, visitor.publish_port));
+                let item = format!("{}:{}", visitor.name, visitor.publish_port);
+                reject_reasons.insert(item.clone(), "引用的代理不存在".to_string());
+                rejected_visitors.push(item);
             } else {
                 info!(
                     "Visitor '{}' validated: proxy '{}:{}' exists",
@@ -474,7 +800,7 @@ async fn handle_proxy_config_submission(
     if all_rejected.is_empty() {
         info!("All proxies and visitors accepted");
         control_channel
-            .send_config_accepted(control_stream, id)
+            .send_config_accepted(control_stream, id, assigned)
             .await?;
     } else {
         info!(
@@ -488,87 +814,883 @@ async fn handle_proxy_config_submission(
                 control_stream,
                 "warning",
                 format!("部分配置被拒绝：{} 项", all_rejected.len()),
-                Some("PARTIAL_CONFIG_REJECTION".to_string()),
-                Some(serde_json::json!({
-                    "rejected_items": &all_rejected,
-                    "rejected_proxies": &rejected_proxies,
-                    "rejected_visitors": &rejected_visitors
-                })),
+                Some(EXCEPTION_PARTIAL_CONFIG_REJECTION.to_string()),
+                serde_json::to_value(PartialConfigRejectionData {
+                    rejected_items: all_rejected.clone(),
+                    rejected_proxies: rejected_proxies.clone(),
+                    rejected_visitors: rejected_visitors.clone(),
+                    reasons: reject_reasons.clone(),
+                })
+                .ok(),
             )
             .await;
 
         control_channel
-            .send_config_partially_rejected(control_stream, id, all_rejected)
+            .send_config_partially_rejected(
+                control_stream,
+                id,
+                rejected_proxies,
+                rejected_visitors,
+                reject_reasons,
+                assigned,
+            )
             .await?;
     }
 
     world.session_state = SessionState::Running;
 
-    // 启动代理监听器
-    start_proxy_listeners_for_world(world, proxies).await?;
-
     Ok(true)
 }
 
-/// 启动代理监听器（独立函数）
-async fn start_proxy_listeners_for_world(
+/// 处理增量配置更新：追加注册代理，结果（包括全部被拒绝）只作为响应返回，不结束会话
+async fn handle_proxy_config_update(
+    world: &mut ServerWorld,
+    control_channel: &control_channel::ServerControlChannel,
+    control_stream: &mut ::yamux::Stream,
+    id: serde_json::Value,
+    proxies: Vec<crate::config::ProxyConfig>,
+) -> Result<()> {
+    if let Err(reason) = check_submitted_proxies(&proxies, world.state.config.bind_port) {
+        return control_channel
+            .send_error(
+                control_stream,
+                id,
+                crate::control_protocol::INVALID_PARAMS,
+                reason,
+            )
+            .await;
+    }
+
+    let outcome = register_proxies(world, &proxies).await;
+    if outcome.rejected.is_empty() {
+        info!("Config update accepted: {} proxy(ies)", outcome.accepted);
+        control_channel
+            .send_config_accepted(control_stream, id, outcome.assigned)
+            .await
+    } else {
+        info!(
+            "Config update partially accepted: {} proxy(ies) rejected",
+            outcome.rejected.len()
+        );
+        control_channel
+            .send_config_partially_rejected(
+                control_stream,
+                id,
+                outcome.rejected,
+                vec![],
+                outcome.reasons,
+                outcome.assigned,
+            )
+            .await
+    }
+}
+
+/// 处理配置校验（dry-run）：按提交配置的规则与当前注册表比对，但不绑定端口、不写入注册表、
+/// 不改变会话状态；全部被拒绝也只作为结果返回
+async fn handle_proxy_config_validation(
     world: &mut ServerWorld,
+    control_channel: &control_channel::ServerControlChannel,
+    control_stream: &mut ::yamux::Stream,
+    id: serde_json::Value,
     proxies: Vec<crate::config::ProxyConfig>,
+    visitors: Vec<crate::config::VisitorConfig>,
+) -> Result<()> {
+    if let Err(reason) = check_submitted_proxies(&proxies, world.state.config.bind_port) {
+        return control_channel
+            .send_error(
+                control_stream,
+                id,
+                crate::control_protocol::INVALID_PARAMS,
+                reason,
+            )
+            .await;
+    }
+
+    let ProxyClassification {
+        mut rejected,
+        mut reasons,
+        ..
+    } = classify_proxies(world, &proxies);
+
+    // visitor 引用的代理必须已注册，或者在本次提交中可以注册
+    {
+        let registry = &world.state.proxy_registry;
+        for visitor in &visitors {
+            let item = format!("{}:{}", visitor.name, visitor.publish_port);
+            let submitted = proxies.iter().any(|p| {
+                p.name == visitor.name
+                    && (visitor.publish_port == ANY_PUBLISH_PORT
+                        || p.publish_port == visitor.publish_port)
+            });
+            // 引用的代理本身被拒绝时沿用代理的拒绝原因
+            if !submitted
+                && !registry.is_registered(&visitor.name, visitor.publish_port)
+                && !rejected.contains(&item)
+            {
+                reasons.insert(item.clone(), "引用的代理不存在".to_string());
+                rejected.push(item);
+            }
+        }
+    }
+
+    info!(
+        "Config validation: {} proxies, {} visitors, {} item(s) rejected",
+        proxies.len(),
+        visitors.len(),
+        rejected.len()
+    );
+    control_channel
+        .send_validation_result(control_stream, id, rejected, reasons)
+        .await
+}
+
+/// 处理客户端主动下线代理：本会话是唯一后端时排空整个代理（已有连接继续转发），
+/// 共享代理还有其他后端时只移除本会话的后端；未由本会话注册的代理计入拒绝列表
+async fn handle_proxy_removal(
+    world: &mut ServerWorld,
+    control_channel: &control_channel::ServerControlChannel,
+    control_stream: &mut ::yamux::Stream,
+    id: serde_json::Value,
+    proxies: Vec<crate::control_protocol::ProxyRef>,
 ) -> Result<()> {
+    let mut rejected = Vec::new();
+    let mut reasons = BTreeMap::new();
+
     for proxy in proxies {
-        // 将 ProxyConfig 转换为 ProxyInfo
-        let proxy_info = registry::ProxyInfo {
-            name: proxy.name.clone(),
-            proxy_type: proxy.proxy_type,
-            publish_addr: proxy.publish_addr.clone(),
-            publish_port: proxy.publish_port,
-            local_port: proxy.local_port,
-        };
+        let key = (proxy.name, proxy.publish_port);
+        if !world.proxy_keys.contains(&key) {
+            let item = format!("{}:{}", key.0, key.1);
+            reasons.insert(item.clone(), "代理未由本会话注册".to_string());
+            rejected.push(item);
+            continue;
+        }
+        release_session_proxy(world, key).await;
+    }
 
-        // 注册统计追踪器
-        let tracker = world.state.stats_manager.register_proxy(
-            proxy_info.name.clone(),
-            proxy_info.publish_addr.clone(),
-            proxy_info.publish_port,
-            proxy_info.local_port,
+    if rejected.is_empty() {
+        control_channel
+            .send_config_accepted(control_stream, id, vec![])
+            .await
+    } else {
+        control_channel
+            .send_config_partially_rejected(control_stream, id, rejected, vec![], reasons, vec![])
+            .await
+    }
+}
+
+/// 下线本会话注册的代理：本会话是唯一后端时排空整个代理（已有连接继续转发），
+/// 共享代理还有其他后端时只移除本会话的后端
+async fn release_session_proxy(world: &mut ServerWorld, key: (String, u16)) {
+    world.proxy_keys.retain(|k| *k != key);
+
+    let registry = &world.state.proxy_registry;
+    let has_other_backends = registry
+        .lookup(&key.0, key.1)
+        .is_some_and(|proxy| proxy.backends > 1);
+    if !has_other_backends {
+        drain::start_drain(&world.state, key).await;
+        return;
+    }
+    if let Some(removed) = registry.unregister(&key, &world.stream_tx) {
+        info!(
+            "Backend removed from shared proxy '{}' with port {}, {} backend(s) remaining",
+            key.0, key.1, removed.remaining_backends
         );
+        world.state.events.emit(ServerEventKind::ProxyUnregistered {
+            client_id: world.client_id.clone().unwrap_or_default(),
+            name: key.0.clone(),
+            publish_port: key.1,
+            remaining_backends: removed.remaining_backends,
+        });
+        // 期间其他后端都已离开时注册表项已被移除，私有代理的统计在这里注销
+        if removed.remaining_backends == 0 && removed.visibility.is_private() {
+            world.state.stats_manager.unregister_proxy(&key.0);
+        }
+    }
+}
 
-        let stream_tx_clone = world.stream_tx.clone();
-        let mut shutdown_rx = world.shutdown_tx.subscribe();
-        let stats_manager = world.state.stats_manager.clone();
-        let proxy_name = proxy_info.name.clone();
-        let exception_tx = world.exception_tx.clone();
-
-        tokio::spawn(async move {
-            tokio::select! {
-                result = start_proxy_listener_with_notify(proxy_info, stream_tx_clone, tracker, Some(exception_tx)) => {
-                    if let Err(e) = result {
-                        error!("Proxy listener error: {}", e);
-                    }
+/// 检查提交的代理配置本身是否有效（名称和绑定不重复、端口和地址有效、不占用服务器端口），
+/// 返回第一个错误；私有代理不绑定端口，只检查名称和端口有效性。
+/// publish_port 为 0 的公开代理由服务器分配端口，分配之前不参与绑定的唯一性检查
+fn check_submitted_proxies(
+    proxies: &[crate::config::ProxyConfig],
+    server_port: u16,
+) -> std::result::Result<(), String> {
+    use std::collections::HashSet;
+
+    let mut seen_names = HashSet::new();
+    let mut seen_bind = HashSet::new();
+
+    for proxy in proxies {
+        // 检查 name 唯一性
+        if !seen_names.insert(&proxy.name) {
+            error!("Duplicate proxy name '{}'", proxy.name);
+            return Err(format!("Duplicate proxy name: {}", proxy.name));
+        }
+
+        // 私有代理的 publish_port 是 visitor 查找的标识，共享代理的其他后端需要知道端口才能加入
+        let auto_port = proxy.publish_port == 0;
+        if auto_port && (proxy.visibility.is_private() || proxy.shared) {
+            error!(
+                "Proxy '{}' cannot use an auto-assigned publish_port",
+                proxy.name
+            );
+            return Err(format!(
+                "Auto-assigned publish_port requires a public, non-shared proxy: {}",
+                proxy.name
+            ));
+        }
+
+        // 检查 (publish_addr, publish_port) 唯一性
+        if proxy.visibility.is_public()
+            && !auto_port
+            && !seen_bind.insert((
+                crate::listen_addr::normalize_host(&proxy.publish_addr),
+                proxy.publish_port,
+            ))
+        {
+            error!(
+                "Duplicate publish binding {}:{}",
+                proxy.publish_addr, proxy.publish_port
+            );
+            return Err(format!(
+                "Duplicate binding: {}:{}",
+                proxy.publish_addr, proxy.publish_port
+            ));
+        }
+
+        // 验证端口和地址有效性
+        if proxy.local_port == 0
+            || (proxy.visibility.is_public() && proxy.publish_addr.trim().is_empty())
+            || proxy.name.trim().is_empty()
+        {
+            error!("Invalid proxy configuration: {}", proxy.name);
+            return Err(format!("Invalid proxy: {}", proxy.name));
+        }
+
+        // 检查是否与服务器端口冲突
+        if proxy.visibility.is_public() && !auto_port && proxy.publish_port == server_port {
+            error!("Proxy '{}' port conflicts with server port", proxy.name);
+            return Err(format!("Port conflict: {}", proxy.name));
+        }
+    }
+
+    Ok(())
+}
+
+/// 代理注册结果
+struct ProxyRegistrationOutcome {
+    /// 注册成功（含本会话已以相同配置注册）的代理数
+    accepted: usize,
+    /// 被拒绝的代理（`name:port`）
+    rejected: Vec<String>,
+    /// 拒绝原因（键与 rejected 中的条目相同）
+    reasons: BTreeMap<String, String>,
+    /// 由服务器分配发布端口（提交的 publish_port 为 0）并注册成功的代理
+    assigned: Vec<AssignedPort>,
+}
+
+/// 代理配置与注册表比对的结果（只读取注册表，不做任何修改）
+struct ProxyClassification {
+    /// 需要新注册的代理
+    candidates: Vec<registry::ProxyInfo>,
+    /// 作为后端加入已有共享代理的代理
+    joining: Vec<registry::ProxyInfo>,
+    /// 本会话已以相同配置注册的代理数
+    accepted: usize,
+    /// 被拒绝的代理（`name:port`）
+    rejected: Vec<String>,
+    /// 拒绝原因（键与 rejected 中的条目相同）
+    reasons: BTreeMap<String, String>,
+}
+
+/// 规范化发布地址，排除与已注册代理冲突的配置（共享代理可以作为后端加入已有条目，
+/// 本会话已以相同配置注册的代理直接视为成功）
+fn classify_proxies(
+    world: &ServerWorld,
+    proxies: &[crate::config::ProxyConfig],
+) -> ProxyClassification {
+    let mut rejected_proxies: Vec<String> = Vec::new();
+    let mut reject_reasons = BTreeMap::new();
+    let mut candidates = Vec::new();
+    let mut joining = Vec::new();
+    let mut accepted = 0;
+    {
+        let interfaces = publish_addr::local_interface_addrs();
+        for proxy in proxies {
+            let key = (proxy.name.clone(), proxy.publish_port);
+            // 认证所用的密钥可能只允许发布部分端口
+            if let Err(reason) = world
+                .permissions
+                .check_publish_port(&proxy.name, proxy.publish_port)
+            {
+                warn!("{}", reason);
+                let item = format!("{}:{}", proxy.name, proxy.publish_port);
+                reject_reasons.insert(item.clone(), reason);
+                rejected_proxies.push(item);
+                continue;
+            }
+            // 套接字选项由服务器应用在发布端口接入的连接上，拒绝不合理的取值
+            if let Some(ref socket) = proxy.socket {
+                let context = format!("Proxy '{}'", proxy.name);
+                if let Err(e) = ConfigValidator::validate_socket_options_config(socket, &context) {
+                    warn!("{}", e);
+                    let item = format!("{}:{}", proxy.name, proxy.publish_port);
+                    reject_reasons.insert(item.clone(), e.to_string());
+                    rejected_proxies.push(item);
+                    continue;
                 }
-                _ = shutdown_rx.recv() => {
-                    info!("Proxy listener shutting down due to disconnection");
+            }
+            // UDP 代理只能公开、不能共享，也不支持面向 TCP 连接的选项
+            if proxy.proxy_type.is_datagram() {
+                if let Err(e) = ConfigValidator::validate_udp_proxy(proxy) {
+                    warn!("{}", e);
+                    let item = format!("{}:{}", proxy.name, proxy.publish_port);
+                    reject_reasons.insert(item.clone(), e.to_string());
+                    rejected_proxies.push(item);
+                    continue;
                 }
             }
-            stats_manager.unregister_proxy(&proxy_name);
-        });
+            // 私有代理不绑定端口，忽略 publish_addr
+            let normalized = if proxy.visibility.is_private() {
+                Ok(proxy.publish_addr.clone())
+            } else {
+                publish_addr::normalize_publish_addr(
+                    &proxy.publish_addr,
+                    interfaces.as_deref(),
+                    world.state.config.interface_prefer_ipv6,
+                )
+            };
+            let publish_addr = match normalized {
+                Ok(addr) => addr,
+                Err(reason) => {
+                    warn!(
+                        "Proxy '{}' has invalid publish_addr '{}': {}",
+                        proxy.name, proxy.publish_addr, reason
+                    );
+                    let item = format!("{}:{}", proxy.name, proxy.publish_port);
+                    reject_reasons.insert(item.clone(), reason);
+                    rejected_proxies.push(item);
+                    continue;
+                }
+            };
+            let proxy_info = registry::ProxyInfo {
+                name: proxy.name.clone(),
+                proxy_type: proxy.proxy_type,
+                publish_addr,
+                publish_port: proxy.publish_port,
+                local_port: proxy.local_port,
+                peer_id: world.peer_id.clone(),
+                identity: world.identity.clone(),
+                shared: proxy.shared,
+                weight: proxy.weight.unwrap_or(1),
+                visibility: proxy.visibility,
+                drain_timeout_secs: proxy.drain_timeout_secs,
+                sni_routing: proxy.sni_routing.clone(),
+                socket: world
+                    .state
+                    .config
+                    .publish_socket_options(proxy.socket.as_ref()),
+                // 只有声明了 idle_keepalive 能力的客户端能解析分帧的 stream
+                idle_keepalive_secs: proxy.idle_keepalive_secs.filter(|_| world.idle_keepalive),
+                require_first_byte_timeout_ms: proxy.require_first_byte_timeout_ms,
+                max_connections_per_source: proxy.max_connections_per_source,
+                // 只有声明了 peer_report 能力的客户端能解析携带外部连接信息的前导
+                report_peers: proxy.report_peers && world.peer_report,
+                // 只有声明了 integrity_check 能力的客户端能处理尾帧
+                integrity_check: proxy.integrity_check && world.integrity_check,
+            };
+            match world
+                .state
+                .proxy_registry
+                .check(&key, &world.stream_tx, &proxy_info)
+            {
+                RegistrationCheck::Vacant => candidates.push(proxy_info),
+                RegistrationCheck::Joinable => joining.push(proxy_info),
+                RegistrationCheck::RegisteredBySession => {
+                    info!(
+                        "Proxy '{}' with publish_port {} is already registered by this session",
+                        proxy.name, proxy.publish_port
+                    );
+                    accepted += 1;
+                }
+                RegistrationCheck::Draining => {
+                    warn!(
+                        "Proxy '{}' with publish_port {} is draining, rejecting",
+                        proxy.name, proxy.publish_port
+                    );
+                    let item = format!("{}:{}", proxy.name, proxy.publish_port);
+                    reject_reasons.insert(item.clone(), RegisterError::Draining.to_string());
+                    rejected_proxies.push(item);
+                }
+                RegistrationCheck::Conflict => {
+                    warn!(
+                        "Proxy '{}' with publish_port {} is already registered, rejecting",
+                        proxy.name, proxy.publish_port
+                    );
+                    let item = format!("{}:{}", proxy.name, proxy.publish_port);
+                    reject_reasons.insert(item.clone(), RegisterError::Conflict.to_string());
+                    rejected_proxies.push(item);
+                }
+            }
+        }
     }
 
-    Ok(())
+    ProxyClassification {
+        candidates,
+        joining,
+        accepted,
+        rejected: rejected_proxies,
+        reasons: reject_reasons,
+    }
+}
+
+/// 注册代理并启动监听循环
+async fn register_proxies(
+    world: &mut ServerWorld,
+    proxies: &[crate::config::ProxyConfig],
+) -> ProxyRegistrationOutcome {
+    // 1. 与注册表比对，排除冲突的配置
+    let ProxyClassification {
+        candidates,
+        mut joining,
+        mut accepted,
+        rejected: mut rejected_proxies,
+        reasons: mut reject_reasons,
+    } = classify_proxies(world, proxies);
+
+    // 2. 先绑定所有监听端口，绑定失败的代理计入拒绝列表（私有代理不绑定）；
+    //    publish_port 为 0 的代理绑定后以实际端口注册
+    let mut bound = Vec::new();
+    let mut assigned = Vec::new();
+    for mut proxy_info in candidates {
+        if proxy_info.visibility.is_private() {
+            bound.push((proxy_info, None));
+            continue;
+        }
+        // 优先使用从旧进程继承的监听端口（只交接 TCP 监听器，分配的端口不交接）
+        #[cfg(target_os = "linux")]
+        if !proxy_info.proxy_type.is_datagram() && proxy_info.publish_port != 0 {
+            if let Some(listener) = world.state.handover.claim(&proxy_info) {
+                bound.push((proxy_info, Some(PublishSocket::Tcp(listener))));
+                continue;
+            }
+        }
+        match connection::bind_proxy_listener(&proxy_info).await {
+            Ok(listener) if proxy_info.publish_port == 0 => match listener.local_port() {
+                Ok(port) => {
+                    info!(
+                        "Assigned publish_port {} to proxy '{}'",
+                        port, proxy_info.name
+                    );
+                    assigned.push(AssignedPort {
+                        name: proxy_info.name.clone(),
+                        publish_port: port,
+                    });
+                    proxy_info.publish_port = port;
+                    bound.push((proxy_info, Some(listener)));
+                }
+                Err(e) => {
+                    let item = format!("{}:0", proxy_info.name);
+                    reject_reasons.insert(item.clone(), format!("Failed to assign port: {}", e));
+                    rejected_proxies.push(item);
+                }
+            },
+            Ok(listener) => bound.push((proxy_info, Some(listener))),
+            // 比对之后其他会话注册了同一个共享代理并持有端口：改为加入
+            Err(_)
+                if proxy_info.shared
+                    && world.state.proxy_registry.check(
+                        &(proxy_info.name.clone(), proxy_info.publish_port),
+                        &world.stream_tx,
+                        &proxy_info,
+                    ) == RegistrationCheck::Joinable =>
+            {
+                joining.push(proxy_info);
+            }
+            Err(reason) => {
+                let item = format!("{}:{}", proxy_info.name, proxy_info.publish_port);
+                reject_reasons.insert(item.clone(), reason);
+                rejected_proxies.push(item);
+            }
+        }
+    }
+
+    // 3. 只为绑定成功的代理写入注册表：是否冲突在写入时的同一次写锁内重新判断
+    //    （期间被其他会话抢先注册的拒绝，可加入的共享代理改为加入）
+    let backend_id = world
+        .peer_id
+        .clone()
+        .or_else(|| {
+            world
+                .identity
+                .as_deref()
+                .map(crate::identity::short_fingerprint)
+        })
+        .or_else(|| world.client_id.clone())
+        .unwrap_or_default();
+    let registry = world.state.proxy_registry.clone();
+    let mut registered = Vec::new();
+    let mut failed = Vec::new();
+    for (proxy_info, listener) in bound {
+        let key = (proxy_info.name.clone(), proxy_info.publish_port);
+        let registration = registry::ProxyRegistration {
+            stream_tx: world.stream_tx.clone(),
+            proxy_info: proxy_info.clone(),
+            backend_stats: None,
+            visitor_mux: world.visitor_mux,
+        };
+        // 在写锁之外准备注册表项（统计追踪器、共享监听器的关闭信号）
+        let tracker = world
+            .state
+            .stats_manager
+            .new_proxy_tracker(
+                proxy_info.name.clone(),
+                proxy_info.publish_addr.clone(),
+                proxy_info.publish_port,
+                proxy_info.local_port,
+                proxy_info.visibility,
+            )
+            .with_admission(
+                proxy_info.require_first_byte_timeout_ms,
+                proxy_info.max_connections_per_source,
+            )
+            .with_connection_limit(world.state.config.max_connections_per_proxy());
+        let registration = if proxy_info.shared {
+            registry::ProxyRegistration {
+                backend_stats: Some(tracker.add_backend(backend_id.clone(), proxy_info.weight)),
+                ..registration
+            }
+        } else {
+            registration
+        };
+        let (shutdown_tx, shutdown_rx) = match &listener {
+            Some(_) if proxy_info.shared => {
+                let (tx, rx) = tokio::sync::oneshot::channel();
+                (Some(tx), Some(rx))
+            }
+            _ => (None, None),
+        };
+        let entry = registry::ProxyEntry::new(registration, tracker.clone(), shutdown_tx);
+        let drain = entry.drain.clone();
+
+        match registry.register(key.clone(), entry, backend_id.clone()) {
+            Ok(Registered::Inserted) => {
+                match listener {
+                    // 私有代理没有监听器，只能通过 visitor 访问
+                    None => info!(
+                        "Registered private proxy '{}' with publish_port {} (no listener)",
+                        proxy_info.name, proxy_info.publish_port
+                    ),
+                    Some(PublishSocket::Udp(socket)) => {
+                        info!(
+                            "Registered udp proxy '{}' with publish_port {}",
+                            proxy_info.name, proxy_info.publish_port
+                        );
+                        registered.push(ProxyListener::Datagram {
+                            proxy_info: proxy_info.clone(),
+                            socket,
+                            tracker: tracker.clone(),
+                            drain,
+                        });
+                    }
+                    Some(PublishSocket::Tcp(listener)) => {
+                        info!(
+                            "Registered proxy '{}' with publish_port {}",
+                            proxy_info.name, proxy_info.publish_port
+                        );
+                        registered.push(match shutdown_rx {
+                            Some(shutdown_rx) => ProxyListener::Shared {
+                                proxy_info: proxy_info.clone(),
+                                listener,
+                                tracker: tracker.clone(),
+                                drain,
+                                shutdown_rx,
+                            },
+                            None => ProxyListener::Session {
+                                proxy_info: proxy_info.clone(),
+                                listener,
+                                tracker: tracker.clone(),
+                                drain,
+                            },
+                        });
+                    }
+                }
+                world.state.stats_manager.add_proxy(tracker);
+                world
+                    .state
+                    .events
+                    .emit(registered_event(world, &proxy_info));
+                world.proxy_keys.push(key);
+                accepted += 1;
+            }
+            // 期间其他会话注册了同一个共享代理（私有代理没有监听器，不会因为端口被占用而失败）
+            Ok(Registered::Joined(backends)) => {
+                info!(
+                    "Proxy '{}' with publish_port {} was registered concurrently, joined as backend {}",
+                    proxy_info.name, proxy_info.publish_port, backends
+                );
+                world
+                    .state
+                    .events
+                    .emit(registered_event(world, &proxy_info));
+                world.proxy_keys.push(key);
+                accepted += 1;
+            }
+            Err(e) => failed.push((key, e)),
+        }
+    }
+
+    // 加入已有的共享代理
+    for proxy_info in joining {
+        let key = (proxy_info.name.clone(), proxy_info.publish_port);
+        info!(
+            "Joining shared proxy '{}' with publish_port {} (weight {})",
+            proxy_info.name, proxy_info.publish_port, proxy_info.weight
+        );
+        let registration = registry::ProxyRegistration {
+            stream_tx: world.stream_tx.clone(),
+            proxy_info: proxy_info.clone(),
+            backend_stats: None,
+            visitor_mux: world.visitor_mux,
+        };
+        match registry.join(&key, registration, backend_id.clone()) {
+            Ok(_) => {
+                world
+                    .state
+                    .events
+                    .emit(registered_event(world, &proxy_info));
+                world.proxy_keys.push(key);
+                accepted += 1;
+            }
+            Err(e) => failed.push((key, e)),
+        }
+    }
+
+    // 期间被其他会话抢先注册，或要加入的共享代理已经下线或正在排空
+    for ((name, publish_port), e) in failed {
+        warn!(
+            "Proxy '{}' with publish_port {} changed concurrently, rejecting: {}",
+            name, publish_port, e
+        );
+        // 分配的端口随监听器一起释放，拒绝项沿用提交时的端口
+        let publish_port = match assigned
+            .iter()
+            .position(|a| a.name == name && a.publish_port == publish_port)
+        {
+            Some(index) => {
+                assigned.remove(index);
+                0
+            }
+            None => publish_port,
+        };
+        let item = format!("{}:{}", name, publish_port);
+        reject_reasons.insert(item.clone(), e.to_string());
+        rejected_proxies.push(item);
+    }
+
+    // 4. 在确认配置之前启动监听循环
+    start_proxy_listeners_for_world(world, registered);
+
+    ProxyRegistrationOutcome {
+        accepted,
+        rejected: rejected_proxies,
+        reasons: reject_reasons,
+        assigned,
+    }
+}
+
+/// 代理注册事件
+fn registered_event(world: &ServerWorld, proxy_info: &registry::ProxyInfo) -> ServerEventKind {
+    ServerEventKind::ProxyRegistered {
+        client_id: world.client_id.clone().unwrap_or_default(),
+        name: proxy_info.name.clone(),
+        publish_addr: proxy_info.publish_addr.clone(),
+        publish_port: proxy_info.publish_port,
+        shared: proxy_info.shared,
+    }
+}
+
+/// 已绑定、待启动的代理监听器
+enum ProxyListener {
+    /// 普通代理：监听器随会话关闭
+    Session {
+        proxy_info: registry::ProxyInfo,
+        listener: tokio::net::TcpListener,
+        tracker: crate::stats::ProxyStatsTracker,
+        drain: registry::DrainSignals,
+    },
+    /// 共享代理：监听器在注册表项移除时关闭
+    Shared {
+        proxy_info: registry::ProxyInfo,
+        listener: tokio::net::TcpListener,
+        tracker: crate::stats::ProxyStatsTracker,
+        drain: registry::DrainSignals,
+        shutdown_rx: tokio::sync::oneshot::Receiver<()>,
+    },
+    /// UDP 代理：套接字随会话关闭（UDP 代理不能共享）
+    Datagram {
+        proxy_info: registry::ProxyInfo,
+        socket: tokio::net::UdpSocket,
+        tracker: crate::stats::ProxyStatsTracker,
+        drain: registry::DrainSignals,
+    },
+}
+
+/// 登记需要交接的发布端口，返回的登记在监听循环结束时移除
+#[cfg(target_os = "linux")]
+fn track_listener(
+    state: &ServerState,
+    listener: &tokio::net::TcpListener,
+) -> Option<handover::Registration> {
+    state.handover.track(listener)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn track_listener(_state: &ServerState, _listener: &tokio::net::TcpListener) {}
+
+/// 在已绑定的端口上启动代理监听循环（独立函数）
+fn start_proxy_listeners_for_world(world: &ServerWorld, listeners: Vec<ProxyListener>) {
+    let stall = StallDetector::from_config(
+        world.state.config.debug_stalls,
+        world.state.config.max_write_chunk,
+    );
+    for listener in listeners {
+        let stats_manager = world.state.stats_manager.clone();
+        let events = world.state.events.clone();
+        let stall = stall.clone();
+        let relay_memory = world.state.stats_manager.relay_memory().clone();
+        let timeouts = world.state.config.timeouts();
+        let idle_timeout = world.state.config.idle_timeout();
+
+        match listener {
+            ProxyListener::Session {
+                proxy_info,
+                listener,
+                tracker,
+                drain,
+            } => {
+                let stream_tx_clone = world.stream_tx.clone();
+                let exception_tx = world.exception_tx.clone();
+                let flows = world.state.flows.for_session(
+                    world.client_id.clone(),
+                    world.peer_id.clone(),
+                    world.identity.clone(),
+                );
+                let mut shutdown_rx = world.shutdown_tx.subscribe();
+                let proxy_name = proxy_info.name.clone();
+                let handover = track_listener(&world.state, &listener);
+
+                tokio::spawn(async move {
+                    let _handover = handover;
+                    let _task = crate::chaos::task("server.listener");
+                    tokio::select! {
+                        result = run_proxy_listener(listener, proxy_info, stream_tx_clone, tracker, drain.clone(), events, flows, stall, relay_memory, exception_tx, timeouts, idle_timeout) => {
+                            if let Err(e) = result {
+                                error!("Proxy listener error: {}", e);
+                            }
+                        }
+                        _ = shutdown_rx.recv() => {
+                            info!("Proxy listener shutting down due to disconnection");
+                        }
+                        _ = drain.accept.cancelled() => {
+                            // 排空结束后统一注销统计
+                            info!("Proxy '{}' listener stopped, draining", proxy_name);
+                            return;
+                        }
+                    }
+                    stats_manager.unregister_proxy(&proxy_name);
+                });
+            }
+            ProxyListener::Shared {
+                proxy_info,
+                listener,
+                tracker,
+                drain,
+                shutdown_rx,
+            } => {
+                let registry = world.state.proxy_registry.clone();
+                // 共享代理的连接由不同会话的后端处理，记录中不带会话标识
+                let flows = world.state.flows.clone();
+                let proxy_name = proxy_info.name.clone();
+                let handover = track_listener(&world.state, &listener);
+
+                tokio::spawn(async move {
+                    let _handover = handover;
+                    let _task = crate::chaos::task("server.listener");
+                    tokio::select! {
+                        result = run_shared_proxy_listener(listener, proxy_info, registry, tracker, drain.clone(), events, flows, stall, relay_memory, timeouts, idle_timeout) => {
+                            if let Err(e) = result {
+                                error!("Shared proxy listener error: {}", e);
+                            }
+                        }
+                        _ = shutdown_rx => {
+                            info!("Shared proxy '{}' listener shutting down, no backends left", proxy_name);
+                        }
+                        _ = drain.accept.cancelled() => {
+                            info!("Shared proxy '{}' listener stopped, draining", proxy_name);
+                            return;
+                        }
+                    }
+                    stats_manager.unregister_proxy(&proxy_name);
+                });
+            }
+            ProxyListener::Datagram {
+                proxy_info,
+                socket,
+                tracker,
+                drain,
+            } => {
+                let stream_tx_clone = world.stream_tx.clone();
+                let flows = world.state.flows.for_session(
+                    world.client_id.clone(),
+                    world.peer_id.clone(),
+                    world.identity.clone(),
+                );
+                let mut shutdown_rx = world.shutdown_tx.subscribe();
+                let proxy_name = proxy_info.name.clone();
+
+                tokio::spawn(async move {
+                    let _task = crate::chaos::task("server.listener");
+                    tokio::select! {
+                        result = udp::run_udp_proxy(socket, proxy_info, stream_tx_clone, tracker, drain.clone(), events, flows, timeouts) => {
+                            if let Err(e) = result {
+                                error!("UDP proxy socket error: {}", e);
+                            }
+                        }
+                        _ = shutdown_rx.recv() => {
+                            info!("UDP proxy socket shutting down due to disconnection");
+                        }
+                        _ = drain.accept.cancelled() => {
+                            info!("UDP proxy '{}' socket stopped, draining", proxy_name);
+                            return;
+                        }
+                    }
+                    stats_manager.unregister_proxy(&proxy_name);
+                });
+            }
+        }
+    }
 }
 
 /// 统一的服务器事件循环
 /// 集中处理：yamux I/O、控制通道事件、stream 请求等
 async fn run_server_event_loop(
     mut world: ServerWorld,
-    control_channel: control_channel::ServerControlChannel,
+    mut control_channel: control_channel::ServerControlChannel,
     mut event_rx: tokio::sync::mpsc::UnboundedReceiver<control_channel::ControlEvent>,
 ) -> Result<()> {
     info!("Starting unified server event loop");
 
     // 首先等待客户端创建控制流
     info!("Waiting for control stream from client");
-    let stream_result = poll_fn(|cx| world.yamux_conn.poll_next_inbound(cx)).await;
+    let stream_result = tokio::select! {
+        result = poll_fn(|cx| world.yamux_conn.poll_next_inbound(cx)) => result,
+        _ = world.server_shutdown.changed() => {
+            info!("Server shutting down before control stream was established");
+            return Ok(());
+        }
+    };
     let mut control_stream = match stream_result {
         Some(Ok(stream)) => {
             info!("Control stream established");
@@ -585,37 +1707,86 @@ async fn run_server_event_loop(
     };
 
     info!("Control stream established");
-
-    // 主事件循环
-    loop {
+    let timeouts = world.state.config.timeouts();
+    let setup_deadline = tokio::time::Instant::now() + timeouts.session_setup();
+    // 最近一次收到心跳或其他控制消息的时间（会话运行期间超过 heartbeat_timeout 未收到即结束会话）
+    let mut last_control_message = tokio::time::Instant::now();
+    // 空闲代理过期检查（未配置 idle_registration_expiry_secs 时不检查）
+    let idle_expiry = world
+        .state
+        .config
+        .idle_registration_expiry_secs
+        .map(Duration::from_secs);
+    let mut expiry_check =
+        tokio::time::Instant::now() + idle_expiry.map_or(Duration::ZERO, expiry::check_interval);
+    // 服务器停止时排空的截止时间
+    let mut drain_deadline: Option<tokio::time::Instant> = None;
+
+    // 主事件循环（退出时得到会话结束原因）
+    let close_reason = loop {
         tokio::select! {
-            // 1. 持续驱动 yamux 连接（处理 ping/pong 和 inbound streams）
-            stream_result = poll_fn(|cx| world.yamux_conn.poll_next_inbound(cx)) => {
-                match stream_result {
-                    Some(Ok(stream)) => {
-                        if world.session_state == SessionState::Running {
-                            debug!("Received new inbound stream from client (visitor or forwarder)");
-                            // 处理 visitor 和 forwarder 的 inbound stream
-                            let proxy_registry = world.state.proxy_registry.clone();
-                            let server_config = world.state.config.clone();
-                            tokio::spawn(async move {
-                                if let Err(e) = visitor::handle_visitor_stream(stream, proxy_registry, &server_config).await {
-                                    error!("Failed to handle inbound stream: {}", e);
+            // 1. 持续驱动 yamux 连接（处理 ping/pong、inbound streams 和排队的 outbound stream 请求）
+            event = poll_fn(|cx| world.outbound.poll_event(&mut world.yamux_conn, cx)) => {
+                match event {
+                    YamuxEvent::Outbound(((response_tx, port, proxy_name), guard), stream_result) => {
+                        match stream_result {
+                            Ok(stream) => {
+                                // 前导（publish_port，复用模式另有标记）由请求方写入
+                                world
+                                    .trace
+                                    .stream(stream.id())
+                                    .proxy(TraceDirection::Out, &proxy_name, port, None, None);
+                                if response_tx.try_send((stream, guard)).is_err() {
+                                    warn!("Failed to send yamux stream to visitor handler");
                                 }
-                            });
-                        } else {
-                            warn!("Received inbound stream before running state, dropping");
-                            drop(stream);
+                            }
+                            Err(e) => {
+                                // 丢弃 response_tx，请求方立即得到失败
+                                error!("Failed to create yamux stream for {}:{}: {}", proxy_name, port, e);
+                            }
                         }
                     }
-                    Some(Err(e)) => {
-                        error!("Yamux error: {}", e);
-                        break;
-                    }
-                    None => {
-                        info!("Yamux connection closed by client");
-                        break;
-                    }
+                    YamuxEvent::Inbound(stream_result) => match stream_result {
+                        Some(Ok(stream)) => {
+                            if world.session_state == SessionState::Running {
+                                debug!("Received new inbound stream from client (visitor or forwarder)");
+                                // 超出会话的 stream 上限时直接关闭
+                                let Some(guard) = world.acquire_stream(None) else {
+                                    drop(stream);
+                                    continue;
+                                };
+                                // 处理 visitor 和 forwarder 的 inbound stream
+                                let proxy_registry = world.state.proxy_registry.clone();
+                                let server_config = world.state.config.clone();
+                                let peer_identity = world.peer_identity;
+                                let exception_tx = world.exception_tx.clone();
+                                let events = world.state.events.clone();
+                                let forward = world.forward.clone();
+                                let visitor_access = world.visitor_access.clone();
+                                let permissions = world.permissions.clone();
+                                let flows = world.state.flows.for_session(world.client_id.clone(), world.peer_id.clone(), world.identity.clone());
+                                let client_id = world.client_id.clone().unwrap_or_default();
+                                let trace = world.trace.clone();
+                                tokio::spawn(async move {
+                                    let _guard = guard;
+                                    if let Err(e) = visitor::handle_visitor_stream(stream, proxy_registry, &server_config, peer_identity, exception_tx, forward, visitor_access, permissions, events, flows, client_id, trace).await {
+                                        error!("Failed to handle inbound stream: {}", e);
+                                    }
+                                });
+                            } else {
+                                warn!("Received inbound stream before running state, dropping");
+                                drop(stream);
+                            }
+                        }
+                        Some(Err(e)) => {
+                            error!("Yamux error: {}", e);
+                            break format!("Yamux error: {}", e);
+                        }
+                        None => {
+                            info!("Yamux connection closed by client");
+                            break "Connection closed by client".to_string();
+                        }
+                    },
                 }
             }
 
@@ -627,11 +1798,11 @@ async fn run_server_event_loop(
                     }
                     Ok(None) => {
                         info!("Control stream closed by client");
-                        break;
+                        break "Control stream closed by client".to_string();
                     }
                     Err(e) => {
                         error!("Control stream read error: {}", e);
-                        break;
+                        break format!("Control stream read error: {}", e);
                     }
                 }
             }
@@ -639,30 +1810,121 @@ async fn run_server_event_loop(
             // 3. 处理控制通道事件
             event = event_rx.recv() => {
                 if let Some(event) = event {
-                    let continue_loop = match event {
-                        control_channel::ControlEvent::AuthenticateRequest { id, auth_key } => {
-                            if auth_key == world.state.config.auth_key {
-                                let client_id = format!("client_{}", uuid::Uuid::new_v4());
-                                info!("Client authenticated successfully: {}", client_id);
-
-                                if let Err(e) = control_channel
-                                    .send_auth_success(&mut control_stream, id, client_id.clone())
-                                    .await {
-                                    error!("Failed to send auth success: {}", e);
-                                    false
-                                } else {
-                                    world.client_id = Some(client_id);
-                                    world.session_state = SessionState::Authenticated;
-                                    true
-                                }
+                    // 任何控制消息都说明客户端仍然在线
+                    last_control_message = tokio::time::Instant::now();
+                    // 需要结束会话时给出原因
+                    let stop_reason = match event {
+                        control_channel::ControlEvent::IdentityChallengeRequest { id } => {
+                            if world.session_state != SessionState::Authenticating {
+                                warn!("Received identity challenge request after authentication");
+                                Some("Received identity challenge request after authentication".to_string())
                             } else {
-                                warn!("Authentication failed: invalid key");
-                                if let Err(e) = control_channel
-                                    .send_auth_failure(&mut control_stream, id, "Invalid authentication key".to_string())
-                                    .await {
-                                    error!("Failed to send auth failure: {}", e);
+                                match crate::identity::new_nonce() {
+                                    Ok(nonce) => {
+                                        let sent = control_channel
+                                            .send_identity_challenge(&mut control_stream, id, &nonce)
+                                            .await;
+                                        world.identity_nonce = Some(nonce);
+                                        match sent {
+                                            Ok(()) => None,
+                                            Err(e) => {
+                                                error!("Failed to send identity challenge: {}", e);
+                                                Some(format!("Failed to send identity challenge: {}", e))
+                                            }
+                                        }
+                                    }
+                                    Err(e) => {
+                                        error!("Failed to generate identity challenge: {}", e);
+                                        Some(format!("Failed to generate identity challenge: {}", e))
+                                    }
+                                }
+                            }
+                        }
+
+                        control_channel::ControlEvent::AuthenticateRequest { id, auth_key, peer_id, capabilities, identity } => {
+                            match authenticate_client(&world.state.config, &auth_key, identity.as_ref(), world.identity_nonce.take()) {
+                                Ok((permissions, identity)) => {
+                                    let client_id = format!("client_{}", uuid::Uuid::new_v4());
+                                    let key_note = permissions
+                                        .name()
+                                        .map(|name| format!(" with auth key '{}'", name))
+                                        .unwrap_or_default();
+                                    match &identity {
+                                        Some(fingerprint) => info!(
+                                            "Client authenticated successfully: {} (identity {}){}",
+                                            client_id,
+                                            crate::identity::short_fingerprint(fingerprint),
+                                            key_note
+                                        ),
+                                        None => info!("Client authenticated successfully: {}{}", client_id, key_note),
+                                    }
+                                    world.state.events.emit(ServerEventKind::AuthSucceeded {
+                                        client_id: client_id.clone(),
+                                        peer_id: peer_id.clone(),
+                                        identity: identity.clone(),
+                                    });
+
+                                    if let Err(e) = control_channel
+                                        .send_auth_success(&mut control_stream, id, client_id.clone())
+                                        .await {
+                                        error!("Failed to send auth success: {}", e);
+                                        Some(format!("Failed to send auth success: {}", e))
+                                    } else {
+                                        if permissions.allow_forward() {
+                                            world.state.stats_manager.register_forward_usage(
+                                                client_id.clone(),
+                                                world.forward.usage().clone(),
+                                            );
+                                        }
+                                        // 拒绝计数按身份指纹归到客户端，没有身份时使用 peer_id 或本会话的 client_id
+                                        let client_label = identity
+                                            .clone()
+                                            .or_else(|| peer_id.clone())
+                                            .unwrap_or_else(|| client_id.clone());
+                                        world.visitor_access = world
+                                            .state
+                                            .visitor_acl
+                                            .for_client(identity.as_deref(), client_label);
+                                        world.client_id = Some(client_id);
+                                        world.peer_identity = PeerIdentity::from_capabilities(&capabilities);
+                                        world.visitor_mux = capabilities
+                                            .iter()
+                                            .any(|c| c == crate::control_protocol::CAPABILITY_VISITOR_MUX);
+                                        world.idle_keepalive = capabilities
+                                            .iter()
+                                            .any(|c| c == crate::control_protocol::CAPABILITY_IDLE_KEEPALIVE);
+                                        world.peer_report = capabilities
+                                            .iter()
+                                            .any(|c| c == crate::control_protocol::CAPABILITY_PEER_REPORT);
+                                        world.integrity_check = capabilities
+                                            .iter()
+                                            .any(|c| c == crate::control_protocol::CAPABILITY_INTEGRITY_CHECK);
+                                        world.peer_id = peer_id;
+                                        world.identity = identity;
+                                        world.permissions = permissions;
+                                        world.session_state = SessionState::Authenticated;
+                                        None
+                                    }
+                                }
+                                Err(reason) => {
+                                    warn!("Authentication failed: {}", reason);
+                                    world.state.events.emit(ServerEventKind::AuthFailed {
+                                        peer_id,
+                                        reason: reason.to_string(),
+                                    });
+                                    if let Err(e) = control_channel
+                                        .send_auth_failure(&mut control_stream, id, reason.to_string())
+                                        .await {
+                                        error!("Failed to send auth failure: {}", e);
+                                    }
+                                    // 尽量把失败原因发出去再关闭连接
+                                    let _ = tokio::time::timeout(
+                                        Duration::from_secs(1),
+                                        poll_fn(|cx| world.yamux_conn.poll_close(cx)),
+                                    )
+                                    .await;
+                                    Some("Authentication failed".to_string())
                                 }
-                                false
                             }
                         }
 
@@ -670,74 +1932,247 @@ async fn run_server_event_loop(
                             // 处理配置请求
                             if world.session_state != SessionState::Authenticated {
                                 warn!("Received config before authentication");
-                                false
+                                Some("Received config before authentication".to_string())
                             } else {
                                 world.session_state = SessionState::ConfiguringProxy;
                                 info!("Processing proxy configuration: {} proxies, {} visitors", proxies.len(), visitors.len());
 
                                 // 验证并注册代理配置
-                                let result = handle_proxy_config_submission(&mut world, &control_channel, &mut control_stream, id, proxies, visitors).await;
-                                result.unwrap_or(false)
+                                match handle_proxy_config_submission(&mut world, &control_channel, &mut control_stream, id, proxies, visitors).await {
+                                    Ok(true) => None,
+                                    Ok(false) => Some("Proxy configuration rejected".to_string()),
+                                    Err(e) => Some(format!("Failed to process proxy configuration: {}", e)),
+                                }
+                            }
+                        }
+
+                        control_channel::ControlEvent::ValidateConfigRequest { id, proxies, visitors } => {
+                            if !matches!(world.session_state, SessionState::Authenticated | SessionState::Running) {
+                                warn!("Received config validation before authentication");
+                                Some("Received config validation before authentication".to_string())
+                            } else {
+                                info!("Validating proxy configuration: {} proxies, {} visitors", proxies.len(), visitors.len());
+                                match handle_proxy_config_validation(&mut world, &control_channel, &mut control_stream, id, proxies, visitors).await {
+                                    Ok(()) => None,
+                                    Err(e) => Some(format!("Failed to process config validation: {}", e)),
+                                }
+                            }
+                        }
+
+                        control_channel::ControlEvent::UpdateConfigRequest { id, proxies } => {
+                            if world.session_state != SessionState::Running {
+                                warn!("Received config update before configuration completed");
+                                match control_channel
+                                    .send_error(
+                                        &mut control_stream,
+                                        id,
+                                        crate::control_protocol::INVALID_REQUEST,
+                                        "update_config requires an accepted configuration".to_string(),
+                                    )
+                                    .await
+                                {
+                                    Ok(()) => None,
+                                    Err(e) => Some(format!("Failed to send config update error: {}", e)),
+                                }
+                            } else {
+                                info!("Processing config update: {} proxies", proxies.len());
+                                match handle_proxy_config_update(&mut world, &control_channel, &mut control_stream, id, proxies).await {
+                                    Ok(()) => None,
+                                    Err(e) => Some(format!("Failed to process config update: {}", e)),
+                                }
+                            }
+                        }
+
+                        control_channel::ControlEvent::RemoveProxiesRequest { id, proxies } => {
+                            if world.session_state != SessionState::Running {
+                                warn!("Received proxy removal before configuration completed");
+                                match control_channel
+                                    .send_error(
+                                        &mut control_stream,
+                                        id,
+                                        crate::control_protocol::INVALID_REQUEST,
+                                        "remove_proxies requires an accepted configuration".to_string(),
+                                    )
+                                    .await
+                                {
+                                    Ok(()) => None,
+                                    Err(e) => Some(format!("Failed to send proxy removal error: {}", e)),
+                                }
+                            } else {
+                                info!("Processing proxy removal: {} proxies", proxies.len());
+                                match handle_proxy_removal(&mut world, &control_channel, &mut control_stream, id, proxies).await {
+                                    Ok(()) => None,
+                                    Err(e) => Some(format!("Failed to process proxy removal: {}", e)),
+                                }
                             }
                         }
 
                         control_channel::ControlEvent::Heartbeat => {
                             debug!("Received heartbeat from client");
-                            true
+                            None
+                        }
+
+                        control_channel::ControlEvent::HeartbeatRequest { id, params } => {
+                            debug!("Received heartbeat {} from client", params.seq);
+                            match control_channel.send_heartbeat_ack(&mut control_stream, id, params).await {
+                                Ok(()) => None,
+                                Err(e) => Some(format!("Failed to send heartbeat ack: {}", e)),
+                            }
+                        }
+
+                        control_channel::ControlEvent::ForwardReport(params) => {
+                            match world.client_id.clone() {
+                                Some(client_id) => {
+                                    debug!("Received {} direct connection report(s) from {}", params.reports.len(), client_id);
+                                    if params.dropped > 0 {
+                                        warn!("Client {} dropped {} direct connection report(s)", client_id, params.dropped);
+                                    }
+                                    let usage = world.forward.usage();
+                                    usage.record_direct_reports(&params);
+                                    // 未开启 allow_forward 时会话的 forward 用量只在收到报告后列出（重复登记无影响）
+                                    world.state.stats_manager.register_forward_usage(client_id.clone(), usage.clone());
+                                    for report in params.reports {
+                                        world.state.events.emit(ServerEventKind::DirectConnectionReported {
+                                            client_id: client_id.clone(),
+                                            forwarder: report.forwarder,
+                                            target: report.target,
+                                            rule: report.rule,
+                                            matched: report.matched,
+                                            bytes_up: report.bytes_up,
+                                            bytes_down: report.bytes_down,
+                                            duration_ms: report.duration_ms,
+                                            error: report.error,
+                                        });
+                                    }
+                                }
+                                None => warn!("Ignoring forward report before authentication"),
+                            }
+                            None
                         }
 
                         control_channel::ControlEvent::ConnectionClosed => {
                             info!("Control channel closed by client");
-                            false
+                            Some("Control channel closed by client".to_string())
                         }
                     };
 
-                    if !continue_loop {
-                        break;
+                    if let Some(reason) = stop_reason {
+                        break reason;
                     }
                 } else {
                     error!("Control event stream closed");
-                    break;
+                    break "Control event stream closed".to_string();
                 }
             }
 
-            // 4. 处理 stream 请求（visitor 需要新的 yamux stream）
-            Some((response_tx, port, visitor_addr)) = world.stream_rx.recv(), if world.session_state == SessionState::Running => {
-                debug!("Creating new yamux stream for visitor: {}:{}", visitor_addr, port);
+            // 4. 处理 stream 请求（只入队，由分支 1 创建 stream）
+            Some(request) = world.stream_rx.recv(), if world.session_state == SessionState::Running => {
+                debug!("Queueing yamux stream request for proxy: {}:{}", request.2, request.1);
+                // 超出会话的 stream 上限时丢弃请求，请求方立即得到失败
+                if let Some(guard) = world.acquire_stream(Some((&request.2, request.1))) {
+                    world.outbound.push((request, guard));
+                }
+            }
 
-                match poll_fn(|cx| world.yamux_conn.poll_new_outbound(cx)).await {
-                    Ok(stream) => {
-                        if response_tx.send(stream).await.is_err() {
-                            warn!("Failed to send yamux stream to visitor handler");
-                        }
-                    }
-                    Err(e) => {
-                        error!("Failed to create yamux stream: {}", e);
-                        // visitor handler 会超时处理
+            // 5. 处理异常通知（从代理监听器发送过来的）
+            outgoing = world.exception_rx.next_batch() => {
+                for notification in &outgoing {
+                    if let Err(e) = control_channel.send_exception(&mut control_stream, notification).await {
+                        warn!("Failed to send exception notification: {}", e);
                     }
                 }
             }
 
-            // 5. 处理异常通知（从代理监听器发送过来的）
-            Some(exception_req) = world.exception_rx.recv() => {
-                if let Err(e) = control_channel
-                    .send_exception_notification(
-                        &mut control_stream,
-                        &exception_req.level,
-                        exception_req.message,
-                        exception_req.code,
-                        exception_req.data,
+            // 6. 超时未完成认证和配置提交：结束会话
+            _ = tokio::time::sleep_until(setup_deadline), if world.session_state != SessionState::Running => {
+                warn!("Session did not complete setup within {:?}, closing", timeouts.session_setup());
+                break "Session setup timed out".to_string();
+            }
+
+            // 7. 会话运行期间长时间未收到心跳：客户端已失联（例如 NAT 状态过期），结束会话并注销代理
+            _ = tokio::time::sleep_until(last_control_message + timeouts.heartbeat_timeout()), if world.session_state == SessionState::Running => {
+                warn!(
+                    "No heartbeat from client {} for {:?}, closing session",
+                    world.client_id.as_deref().unwrap_or("<unknown>"),
+                    last_control_message.elapsed()
+                );
+                break "Heartbeat timed out".to_string();
+            }
+
+            // 8. 定期注销空闲超过 idle_registration_expiry_secs 的代理
+            _ = tokio::time::sleep_until(expiry_check), if idle_expiry.is_some() && world.session_state == SessionState::Running => {
+                if let Some(expiry) = idle_expiry {
+                    expiry::expire_idle_proxies(&mut world, expiry).await;
+                    expiry_check = tokio::time::Instant::now() + expiry::check_interval(expiry);
+                }
+            }
+
+            // 9. 服务器停止：通知客户端并停止接受代理连接，再次收到停止信号时立即结束会话
+            changed = world.server_shutdown.changed() => {
+                let phase = match changed {
+                    Ok(()) => *world.server_shutdown.borrow_and_update(),
+                    // 服务器句柄已被丢弃
+                    Err(_) => ShutdownPhase::Force,
+                };
+                if phase == ShutdownPhase::Force {
+                    info!("Server stopping immediately, closing session");
+                    let _ = tokio::time::timeout(
+                        Duration::from_secs(1),
+                        poll_fn(|cx| world.yamux_conn.poll_close(cx)),
                     )
-                    .await
-                {
-                    warn!("Failed to send exception notification: {}", e);
+                    .await;
+                    break "Server shutting down".to_string();
+                }
+                if phase == ShutdownPhase::Draining && drain_deadline.is_none() {
+                    let grace = world.state.config.shutdown_grace_period();
+                    let _ = control_channel
+                        .send_exception_notification(
+                            &mut control_stream,
+                            "warning",
+                            format!("服务器正在停止，进行中的连接最多保留 {} 秒", grace.as_secs()),
+                            Some(EXCEPTION_SERVER_SHUTDOWN.to_string()),
+                            None,
+                        )
+                        .await;
+                    world.start_shutdown_drain();
+                    info!(
+                        "Server shutting down, draining {} active relay(s) for up to {:?}",
+                        world.active_relays(),
+                        grace
+                    );
+                    drain_deadline = Some(tokio::time::Instant::now() + grace);
+                }
+            }
+
+            // 10. 排空期间进行中的转发全部结束（或排空超时）后结束会话
+            _ = tokio::time::sleep(SHUTDOWN_DRAIN_POLL_INTERVAL), if drain_deadline.is_some() => {
+                let active = world.active_relays();
+                let expired = drain_deadline.is_some_and(|deadline| tokio::time::Instant::now() >= deadline);
+                if active == 0 || expired {
+                    if active > 0 {
+                        warn!("Shutdown grace period elapsed, closing {} active relay(s)", active);
+                    } else {
+                        info!("All relays finished, closing session");
+                    }
+                    // 尽量把排队的数据发出去再关闭连接
+                    let _ = tokio::time::timeout(
+                        Duration::from_secs(1),
+                        poll_fn(|cx| world.yamux_conn.poll_close(cx)),
+                    )
+                    .await;
+                    break "Server shutting down".to_string();
                 }
             }
         }
-    }
+    };
 
     // 清理资源
+    world.trace.end(&close_reason);
     world.cleanup().await;
+    world.state.events.emit(ServerEventKind::SessionClosed {
+        client_id: world.client_id.clone(),
+        reason: close_reason,
+    });
 
     info!("Server event loop ended");
     Ok(())
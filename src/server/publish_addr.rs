@@ -0,0 +1,225 @@
+/// publish_addr 规范化
+///
+/// 在注册代理时提前检查客户端提交的发布地址，把错误以具体的拒绝原因返回给客户端，
+/// 而不是等到绑定端口时才失败
+use std::net::IpAddr;
+use tracing::warn;
+
+/// 按网卡名称指定发布地址的前缀，如 `iface:eth0`
+pub const INTERFACE_PREFIX: &str = "iface:";
+
+/// 本机网卡地址
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceAddr {
+    /// 网卡名称
+    pub name: String,
+    /// 网卡地址
+    pub ip: IpAddr,
+}
+
+/// 获取本机所有网卡地址（获取失败时返回 None，此时不检查地址归属）
+pub fn local_interface_addrs() -> Option<Vec<InterfaceAddr>> {
+    match if_addrs::get_if_addrs() {
+        Ok(interfaces) => Some(
+            interfaces
+                .into_iter()
+                .map(|interface| InterfaceAddr {
+                    ip: interface.ip(),
+                    name: interface.name,
+                })
+                .collect(),
+        ),
+        Err(e) => {
+            warn!("Failed to list local interface addresses: {}", e);
+            None
+        }
+    }
+}
+
+/// 规范化发布地址
+///
+/// - `localhost` 解析为回环地址
+/// - `iface:<name>` 解析为该网卡的主地址（默认优先 IPv4，`prefer_ipv6` 时优先 IPv6）
+/// - IP 地址必须是通配地址或已分配给本机网卡的地址
+/// - 其他主机名保持不变，由绑定时解析
+///
+/// `interfaces` 为 None 时跳过地址归属检查。失败时返回可读的拒绝原因
+pub fn normalize_publish_addr(
+    addr: &str,
+    interfaces: Option<&[InterfaceAddr]>,
+    prefer_ipv6: bool,
+) -> Result<String, String> {
+    let addr = addr.trim();
+    if addr.is_empty() {
+        return Err("publish_addr cannot be empty".to_string());
+    }
+
+    if addr.eq_ignore_ascii_case("localhost") {
+        return Ok(if prefer_ipv6 { "::1" } else { "127.0.0.1" }.to_string());
+    }
+
+    if let Some(name) = addr.strip_prefix(INTERFACE_PREFIX) {
+        let Some(interfaces) = interfaces else {
+            return Err(format!(
+                "Cannot resolve '{}': local interface addresses are unavailable",
+                addr
+            ));
+        };
+        return interface_primary_addr(interfaces, name, prefer_ipv6)
+            .map(|ip| ip.to_string())
+            .ok_or_else(|| {
+                format!(
+                    "Interface '{}' not found or has no address; available interfaces: {}",
+                    name,
+                    list_interfaces(interfaces)
+                )
+            });
+    }
+
+    let literal = addr
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .unwrap_or(addr);
+    if let Ok(ip) = literal.parse::<IpAddr>() {
+        if let Some(interfaces) = interfaces {
+            if !ip.is_unspecified() && !interfaces.iter().any(|interface| interface.ip == ip) {
+                return Err(format!(
+                    "Address {} is not assigned to any local interface; available addresses: {}",
+                    ip,
+                    list_addresses(interfaces)
+                ));
+            }
+        }
+        return Ok(ip.to_string());
+    }
+
+    // 常见错误：直接填写网卡名称
+    if let Some(interfaces) = interfaces {
+        if interfaces.iter().any(|interface| interface.name == addr) {
+            return Err(format!(
+                "'{}' is an interface name, use publish_addr = \"{}{}\" to bind to its address",
+                addr, INTERFACE_PREFIX, addr
+            ));
+        }
+    }
+
+    Ok(addr.to_string())
+}
+
+/// 选择网卡的主地址：优先指定的地址族，同一地址族中跳过链路本地地址
+fn interface_primary_addr(
+    interfaces: &[InterfaceAddr],
+    name: &str,
+    prefer_ipv6: bool,
+) -> Option<IpAddr> {
+    let addrs: Vec<IpAddr> = interfaces
+        .iter()
+        .filter(|interface| interface.name == name)
+        .map(|interface| interface.ip)
+        .collect();
+    let rank = |ip: &IpAddr| {
+        let preferred_family = ip.is_ipv6() == prefer_ipv6;
+        let link_local = match ip {
+            IpAddr::V4(v4) => v4.is_link_local(),
+            IpAddr::V6(v6) => (v6.segments()[0] & 0xffc0) == 0xfe80,
+        };
+        (!preferred_family, link_local)
+    };
+    addrs.into_iter().min_by_key(rank)
+}
+
+fn list_addresses(interfaces: &[InterfaceAddr]) -> String {
+    interfaces
+        .iter()
+        .map(|interface| format!("{} ({})", interface.ip, interface.name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn list_interfaces(interfaces: &[InterfaceAddr]) -> String {
+    let mut names: Vec<&str> = interfaces
+        .iter()
+        .map(|interface| interface.name.as_str())
+        .collect();
+    names.dedup();
+    names.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interfaces() -> Vec<InterfaceAddr> {
+        [
+            ("lo", "127.0.0.1"),
+            ("lo", "::1"),
+            ("eth0", "fe80::1"),
+            ("eth0", "2001:db8::10"),
+            ("eth0", "192.168.1.10"),
+            ("eth1", "fe80::2"),
+        ]
+        .into_iter()
+        .map(|(name, ip)| InterfaceAddr {
+            name: name.to_string(),
+            ip: ip.parse().unwrap(),
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_normalize_literals_and_localhost() {
+        let interfaces = interfaces();
+        let normalize = |addr: &str| normalize_publish_addr(addr, Some(&interfaces), false);
+
+        assert_eq!(normalize("0.0.0.0").unwrap(), "0.0.0.0");
+        assert_eq!(normalize("[::]").unwrap(), "::");
+        assert_eq!(normalize(" 192.168.1.10 ").unwrap(), "192.168.1.10");
+        assert_eq!(normalize("LocalHost").unwrap(), "127.0.0.1");
+        assert_eq!(
+            normalize_publish_addr("localhost", Some(&interfaces), true).unwrap(),
+            "::1"
+        );
+        assert_eq!(
+            normalize("tunnel.example.com").unwrap(),
+            "tunnel.example.com"
+        );
+
+        let error = normalize("203.0.113.5").unwrap_err();
+        assert!(error.contains("not assigned to any local interface"));
+        assert!(error.contains("192.168.1.10 (eth0)"));
+
+        let error = normalize("eth0").unwrap_err();
+        assert!(error.contains("iface:eth0"));
+
+        assert!(normalize("").is_err());
+
+        // 无法获取网卡列表时不检查地址归属
+        assert_eq!(
+            normalize_publish_addr("203.0.113.5", None, false).unwrap(),
+            "203.0.113.5"
+        );
+    }
+
+    #[test]
+    fn test_normalize_interface_names() {
+        let interfaces = interfaces();
+
+        assert_eq!(
+            normalize_publish_addr("iface:eth0", Some(&interfaces), false).unwrap(),
+            "192.168.1.10"
+        );
+        assert_eq!(
+            normalize_publish_addr("iface:eth0", Some(&interfaces), true).unwrap(),
+            "2001:db8::10"
+        );
+        // 没有首选地址族时退回另一个地址族
+        assert_eq!(
+            normalize_publish_addr("iface:eth1", Some(&interfaces), false).unwrap(),
+            "fe80::2"
+        );
+
+        let error = normalize_publish_addr("iface:wlan0", Some(&interfaces), false).unwrap_err();
+        assert!(error.contains("available interfaces: lo, eth0, eth1"));
+        assert!(normalize_publish_addr("iface:eth0", None, false).is_err());
+    }
+}
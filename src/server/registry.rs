@@ -1,17 +1,63 @@
-use crate::config::ProxyType;
-use crate::stats::ProxyStatsTracker;
+use crate::config::{ProxyType, ProxyVisibility, SniRoutingConfig, SocketOptionsConfig};
+use crate::protocol::ANY_PUBLISH_PORT;
+use crate::stats::{BackendStatsTracker, ProxyStatsTracker};
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::sync::{mpsc, RwLock};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
+
+/// 注册表事件通道容量（订阅者落后超过该数量时收到 Lagged）
+pub const REGISTRY_EVENT_CAPACITY: usize = 256;
+
+/// 会话为请求创建的 stream（guard 释放前计入会话的 stream 数）
+pub type SessionStream = (yamux::Stream, StreamGuard);
+
+/// 会话的 stream 请求（响应通道、目标端口、代理名称）
+pub type StreamRequest = (mpsc::Sender<SessionStream>, u16, String);
+
+/// 会话请求创建 stream 的通道
+pub type StreamRequestSender = mpsc::Sender<StreamRequest>;
 
 /// 代理配置信息（从客户端接收）
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ProxyInfo {
     pub name: String,
     pub proxy_type: ProxyType,
     pub publish_addr: String,
     pub publish_port: u16,
     pub local_port: u16,
+    /// 注册该 proxy 的客户端身份（客户端未配置 peer_id 时为空）
+    pub peer_id: Option<String>,
+    /// 注册该 proxy 的客户端身份指纹（客户端未提供身份密钥时为空）
+    pub identity: Option<String>,
+    /// 是否为多客户端共享的代理
+    pub shared: bool,
+    /// 负载均衡权重（非共享代理固定为 1）
+    pub weight: u32,
+    /// 可见性（私有代理没有发布监听器，只能通过 visitor 访问）
+    pub visibility: ProxyVisibility,
+    /// 主动下线时的排空超时（秒，未配置时使用服务器默认值）
+    pub drain_timeout_secs: Option<u64>,
+    /// SNI 路由表（未配置时所有连接转发到 local_port）
+    pub sni_routing: Option<SniRoutingConfig>,
+    /// 发布端口接入连接的套接字选项（未配置时使用默认值）
+    pub socket: Option<SocketOptionsConfig>,
+    /// 转发连接空闲时发送保活标记的间隔（秒，配置后发往客户端的 stream 分帧）
+    pub idle_keepalive_secs: Option<u64>,
+    /// 等待外部连接第一个字节的超时（毫秒，未配置时接入后立即请求 stream）
+    pub require_first_byte_timeout_ms: Option<u64>,
+    /// 同一来源 IP 的最大并发连接数（未配置时不限制）
+    pub max_connections_per_source: Option<u32>,
+    /// 是否在 stream 前导中附带外部连接的来源地址和接入时间（只对声明了 peer_report 能力的客户端生效）
+    pub report_peers: bool,
+    /// 是否在 stream 上交换完整性校验尾帧（只对声明了 integrity_check 能力的客户端生效）
+    pub integrity_check: bool,
 }
 
 /// Visitor 配置信息（从客户端接收）
@@ -24,17 +70,737 @@ pub struct VisitorInfo {
     pub publish_port: u16,
 }
 
-/// 全局代理注册表项
+/// 代理的一个后端（注册该代理的客户端会话）
 #[derive(Clone)]
 pub struct ProxyRegistration {
     /// 用于请求该客户端创建新stream的channel
-    pub stream_tx: mpsc::Sender<(mpsc::Sender<yamux::Stream>, u16, String)>,
+    pub stream_tx: StreamRequestSender,
     /// 代理信息
     pub proxy_info: ProxyInfo,
+    /// 后端统计（仅共享代理）
+    pub backend_stats: Option<BackendStatsTracker>,
+    /// 该后端会话是否支持 visitor 连接复用
+    pub visitor_mux: bool,
+}
+
+/// 代理排空信号（监听循环和转发任务持有克隆）
+#[derive(Debug, Clone, Default)]
+pub struct DrainSignals {
+    /// 停止接受新连接
+    pub accept: CancellationToken,
+    /// 关闭排空超时后仍未结束的转发
+    pub relays: CancellationToken,
+    /// 进行中的转发数（代理连接和 visitor stream）
+    active: Arc<AtomicUsize>,
+}
+
+impl DrainSignals {
+    /// 登记一个进行中的转发，guard 丢弃时注销
+    pub fn relay(&self) -> RelayGuard {
+        self.active.fetch_add(1, Ordering::Relaxed);
+        RelayGuard {
+            active: self.active.clone(),
+        }
+    }
+
+    /// 进行中的转发数
+    pub fn active_relays(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+}
+
+/// 进行中的转发 guard
+pub struct RelayGuard {
+    active: Arc<AtomicUsize>,
+}
+
+impl Drop for RelayGuard {
+    fn drop(&mut self) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// 全局代理注册表项
+///
+/// 普通代理只有一个后端，监听器归注册它的会话所有；共享代理可以有多个后端，
+/// 监听器归注册表项所有，最后一个后端离开、注册表项被移除时关闭；私有代理没有监听器
+pub struct ProxyEntry {
+    /// 是否为共享代理
+    pub shared: bool,
+    /// 可见性
+    pub visibility: ProxyVisibility,
+    /// 后端列表（按注册顺序）
+    pub backends: Vec<ProxyRegistration>,
+    /// 代理统计追踪器
+    pub tracker: ProxyStatsTracker,
+    /// 轮询计数
+    cursor: AtomicU64,
+    /// 共享监听器的关闭信号（随注册表项一起丢弃）
+    _listener_shutdown: Option<oneshot::Sender<()>>,
+    /// 排空信号
+    pub drain: DrainSignals,
+    /// 是否正在排空（不再接受新连接，等待已有转发结束后移除）
+    draining: bool,
+}
+
+impl ProxyEntry {
+    /// 创建注册表项
+    pub fn new(
+        registration: ProxyRegistration,
+        tracker: ProxyStatsTracker,
+        listener_shutdown: Option<oneshot::Sender<()>>,
+    ) -> Self {
+        Self {
+            shared: registration.proxy_info.shared,
+            visibility: registration.proxy_info.visibility,
+            backends: vec![registration],
+            tracker,
+            cursor: AtomicU64::new(0),
+            _listener_shutdown: listener_shutdown,
+            drain: DrainSignals::default(),
+            draining: false,
+        }
+    }
+
+    /// 是否正在排空
+    pub fn is_draining(&self) -> bool {
+        self.draining
+    }
+
+    /// 开始排空：停止接受新连接并在统计中标记，已经在排空时返回 false
+    pub fn start_draining(&mut self) -> bool {
+        if self.draining {
+            return false;
+        }
+        self.draining = true;
+        self.drain.accept.cancel();
+        self.tracker.set_draining();
+        true
+    }
+
+    /// 排空超时（取各后端配置的最大值，都未配置时使用 `default_secs`）
+    pub fn drain_timeout(&self, default_secs: u64) -> Duration {
+        let secs = self
+            .backends
+            .iter()
+            .filter_map(|b| b.proxy_info.drain_timeout_secs)
+            .max()
+            .unwrap_or(default_secs);
+        Duration::from_secs(secs)
+    }
+
+    /// 代理信息（取第一个后端）
+    pub fn proxy_info(&self) -> &ProxyInfo {
+        &self.backends[0].proxy_info
+    }
+
+    /// 当前状态
+    pub fn state(&self) -> ProxyState {
+        if self.draining {
+            ProxyState::Draining
+        } else {
+            ProxyState::Active
+        }
+    }
+
+    /// 只读快照
+    fn snapshot(&self) -> ProxySnapshot {
+        ProxySnapshot {
+            proxy_info: self.proxy_info().clone(),
+            backends: self.backends.len(),
+            state: self.state(),
+            drain: self.drain.clone(),
+            drain_timeout_secs: self
+                .backends
+                .iter()
+                .filter_map(|b| b.proxy_info.drain_timeout_secs)
+                .max(),
+        }
+    }
+
+    /// 新的注册能否作为后端加入该共享代理
+    pub fn accepts(&self, proxy: &ProxyInfo) -> bool {
+        let info = self.proxy_info();
+        !self.draining
+            && self.shared
+            && proxy.shared
+            && self.visibility == proxy.visibility
+            && (self.visibility.is_private() || info.publish_addr == proxy.publish_addr)
+            && info.proxy_type == proxy.proxy_type
+            && info.sni_routing == proxy.sni_routing
+            && info.socket == proxy.socket
+    }
+
+    /// 指定会话是否已以完全相同的配置注册为该代理的后端（重复提交视为成功）
+    pub fn is_registered_by(&self, stream_tx: &StreamRequestSender, proxy: &ProxyInfo) -> bool {
+        self.backends
+            .iter()
+            .any(|b| b.stream_tx.same_channel(stream_tx) && b.proxy_info == *proxy)
+    }
+
+    /// 按加权轮询选出本次连接的后端顺序（首选在前，其余依次作为失败后的备选）
+    pub fn select_backends(&self) -> Vec<ProxyRegistration> {
+        let tick = self.cursor.fetch_add(1, Ordering::Relaxed);
+        let weights: Vec<u32> = self.backends.iter().map(|b| b.proxy_info.weight).collect();
+        weighted_order(&weights, tick)
+            .into_iter()
+            .map(|i| self.backends[i].clone())
+            .collect()
+    }
+
+    /// 作为后端加入（`backend_id` 用于后端统计），返回加入后的后端数
+    fn add_backend(&mut self, registration: ProxyRegistration, backend_id: String) -> usize {
+        let backend_stats = self
+            .tracker
+            .add_backend(backend_id, registration.proxy_info.weight);
+        self.backends.push(ProxyRegistration {
+            backend_stats: Some(backend_stats),
+            ..registration
+        });
+        self.backends.len()
+    }
+
+    /// 移除属于指定会话的后端，返回是否有后端被移除
+    pub fn remove_backend(&mut self, stream_tx: &StreamRequestSender) -> bool {
+        let before = self.backends.len();
+        let tracker = &self.tracker;
+        self.backends.retain(|b| {
+            let owned = b.stream_tx.same_channel(stream_tx);
+            if owned {
+                if let Some(stats) = &b.backend_stats {
+                    tracker.remove_backend(stats.id());
+                }
+            }
+            !owned
+        });
+        self.backends.len() != before
+    }
+}
+
+/// 加权轮询：第 tick 次选择落在哪个后端，返回从该后端开始的完整轮换顺序
+fn weighted_order(weights: &[u32], tick: u64) -> Vec<usize> {
+    let total: u64 = weights.iter().map(|&w| u64::from(w.max(1))).sum();
+    if total == 0 {
+        return Vec::new();
+    }
+
+    let mut slot = tick % total;
+    let mut first = 0;
+    for (i, &w) in weights.iter().enumerate() {
+        let w = u64::from(w.max(1));
+        if slot < w {
+            first = i;
+            break;
+        }
+        slot -= w;
+    }
+
+    (0..weights.len())
+        .map(|offset| (first + offset) % weights.len())
+        .collect()
+}
+
+/// 注册表键：(proxy_name, publish_port)
+pub type RegistryKey = (String, u16);
+
+/// 代理状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyState {
+    /// 正常接受连接
+    Active,
+    /// 正在排空（不再接受新连接，等待已有转发结束后移除）
+    Draining,
+}
+
+/// 注册表变化事件（在持有写锁时发出，顺序与注册表的变化顺序一致）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RegistryEvent {
+    /// 新注册代理，或后端加入已有的共享代理
+    Registered {
+        name: String,
+        publish_port: u16,
+        backends: usize,
+    },
+    /// 后端离开或代理被移除（remaining_backends 为 0 时注册表项已移除）
+    Unregistered {
+        name: String,
+        publish_port: u16,
+        remaining_backends: usize,
+    },
+    /// 代理状态变化
+    StateChanged {
+        name: String,
+        publish_port: u16,
+        state: ProxyState,
+    },
+}
+
+/// 注册表项的只读快照
+#[derive(Debug, Clone)]
+pub struct ProxySnapshot {
+    /// 代理信息（取第一个后端）
+    pub proxy_info: ProxyInfo,
+    /// 后端数
+    pub backends: usize,
+    /// 当前状态
+    pub state: ProxyState,
+    /// 排空信号
+    pub drain: DrainSignals,
+    /// 各后端配置的排空超时的最大值（秒）
+    pub drain_timeout_secs: Option<u64>,
+}
+
+impl ProxySnapshot {
+    /// 排空超时（都未配置时使用 `default_secs`）
+    pub fn drain_timeout(&self, default_secs: u64) -> Duration {
+        Duration::from_secs(self.drain_timeout_secs.unwrap_or(default_secs))
+    }
+}
+
+/// 提交的代理与注册表现有条目比对的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationCheck {
+    /// 没有注册，可以新注册
+    Vacant,
+    /// 可以作为后端加入已有的共享代理
+    Joinable,
+    /// 指定会话已以相同配置注册
+    RegisteredBySession,
+    /// 已有的代理正在排空
+    Draining,
+    /// 端口或名称被占用
+    Conflict,
+}
+
+/// 注册失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum RegisterError {
+    #[error("代理正在下线，等待已有连接结束")]
+    Draining,
+    #[error("端口或名称冲突")]
+    Conflict,
+    #[error("共享代理已下线，请重新连接")]
+    Gone,
+}
+
+/// 后端离开的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unregistered {
+    /// 剩余后端数（为 0 时注册表项已移除）
+    pub remaining_backends: usize,
+    /// 代理可见性
+    pub visibility: ProxyVisibility,
+}
+
+/// 一次连接的后端选择结果
+pub struct BackendSelection {
+    /// 按加权轮询排列的后端（首选在前）
+    pub backends: Vec<ProxyRegistration>,
+    /// 排空信号（转发期间登记）
+    pub drain: DrainSignals,
+    /// 代理状态
+    pub state: ProxyState,
+}
+
+/// 新注册的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Registered {
+    /// 写入了新的注册表项
+    Inserted,
+    /// 期间其他会话注册了同一个可加入的共享代理，作为后端加入（加入后的后端数）
+    Joined(usize),
+}
+
+/// 持有时间直方图的桶数（第 i 个桶为 `[2^(i-1), 2^i)` 微秒，最后一个桶不设上限）
+const LOCK_HOLD_BUCKETS: usize = 24;
+
+/// 写锁持有时间统计
+#[derive(Debug, Default)]
+pub struct LockHoldStats {
+    buckets: [AtomicU64; LOCK_HOLD_BUCKETS],
+    max_nanos: AtomicU64,
 }
 
-/// 全局代理注册表，维护 (proxy_name, publish_port) -> ProxyRegistration 的映射
-pub type ProxyRegistry = Arc<RwLock<HashMap<(String, u16), ProxyRegistration>>>;
+/// 写锁持有时间快照（`/capacity` 的 `registry_lock`）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LockHoldSnapshot {
+    /// 写锁获取次数
+    pub acquisitions: u64,
+    /// 持有时间的 99 分位（微秒，直方图桶的上界）
+    pub p99_us: u64,
+    /// 最长持有时间（微秒）
+    pub max_us: u64,
+}
+
+impl LockHoldStats {
+    /// 记录一次持有时间
+    #[cfg_attr(not(feature = "lock-timing"), allow(dead_code))]
+    pub fn record(&self, held: Duration) {
+        let micros = held.as_micros() as u64;
+        let bucket = ((u64::BITS - micros.leading_zeros()) as usize).min(LOCK_HOLD_BUCKETS - 1);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.max_nanos
+            .fetch_max(held.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// 当前统计
+    pub fn snapshot(&self) -> LockHoldSnapshot {
+        let counts: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|b| b.load(Ordering::Relaxed))
+            .collect();
+        let acquisitions: u64 = counts.iter().sum();
+        let max_us = self.max_nanos.load(Ordering::Relaxed) / 1000;
+        // 第一个累计数量达到 99% 的桶，以桶的上界（不超过最大值）作为 99 分位
+        let target = acquisitions - acquisitions / 100;
+        let mut seen = 0;
+        let p99_us = counts
+            .iter()
+            .position(|&count| {
+                seen += count;
+                acquisitions > 0 && seen >= target
+            })
+            .map_or(0, |bucket| ((1u64 << bucket) - 1).min(max_us));
+        LockHoldSnapshot {
+            acquisitions,
+            p99_us,
+            max_us,
+        }
+    }
+}
+
+/// 计时的写锁 guard：释放时把持有时间记入 [`LockHoldStats`]
+#[cfg(feature = "lock-timing")]
+struct TimedWriteGuard<'a> {
+    guard: parking_lot::RwLockWriteGuard<'a, HashMap<RegistryKey, ProxyEntry>>,
+    acquired: std::time::Instant,
+    stats: &'a LockHoldStats,
+}
+
+#[cfg(feature = "lock-timing")]
+impl std::ops::Deref for TimedWriteGuard<'_> {
+    type Target = HashMap<RegistryKey, ProxyEntry>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+#[cfg(feature = "lock-timing")]
+impl std::ops::DerefMut for TimedWriteGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+#[cfg(feature = "lock-timing")]
+impl Drop for TimedWriteGuard<'_> {
+    fn drop(&mut self) {
+        self.stats.record(self.acquired.elapsed());
+    }
+}
+
+/// 全局代理注册表，维护 (proxy_name, publish_port) -> ProxyEntry 的映射
+///
+/// 锁是同步锁，只在方法内部持有，guard 不能跨越 await。写锁内只做映射操作和事件广播
+/// （保证事件顺序与变化一致），注册表项、统计追踪器和日志都在锁外准备或输出，
+/// 被移除的注册表项在锁外释放。启用 `lock-timing` 特性时记录写锁持有时间。
+/// 注册表的每次变化都通过 [`Registry::subscribe`] 广播给订阅者
+#[derive(Clone)]
+pub struct Registry {
+    entries: Arc<RwLock<HashMap<RegistryKey, ProxyEntry>>>,
+    events: broadcast::Sender<RegistryEvent>,
+    /// 写锁持有时间（仅在启用 `lock-timing` 特性时记录）
+    #[cfg_attr(not(feature = "lock-timing"), allow(dead_code))]
+    lock_holds: Arc<LockHoldStats>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Registry {
+    /// 创建空注册表
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(REGISTRY_EVENT_CAPACITY);
+        Self {
+            entries: Arc::new(RwLock::new(HashMap::new())),
+            events,
+            lock_holds: Arc::default(),
+        }
+    }
+
+    /// 获取写锁
+    #[cfg(not(feature = "lock-timing"))]
+    fn write(&self) -> parking_lot::RwLockWriteGuard<'_, HashMap<RegistryKey, ProxyEntry>> {
+        self.entries.write()
+    }
+
+    /// 获取写锁，释放时记录持有时间（不含等待时间）
+    #[cfg(feature = "lock-timing")]
+    fn write(&self) -> TimedWriteGuard<'_> {
+        let guard = self.entries.write();
+        TimedWriteGuard {
+            guard,
+            acquired: std::time::Instant::now(),
+            stats: &self.lock_holds,
+        }
+    }
+
+    /// 写锁持有时间统计（未启用 `lock-timing` 特性时为 None）
+    pub fn lock_holds(&self) -> Option<LockHoldSnapshot> {
+        cfg!(feature = "lock-timing").then(|| self.lock_holds.snapshot())
+    }
+
+    /// 订阅注册表变化事件
+    pub fn subscribe(&self) -> broadcast::Receiver<RegistryEvent> {
+        self.events.subscribe()
+    }
+
+    fn emit(&self, event: RegistryEvent) {
+        // 没有订阅者时发送失败，忽略
+        let _ = self.events.send(event);
+    }
+
+    /// 新注册代理：在同一次写锁内判断并写入（entry 在锁外准备好）
+    ///
+    /// 键已被占用时，可加入的共享代理改为把 entry 的后端加入已有注册表项（`backend_id`
+    /// 用于后端统计），其余情况返回冲突或排空；未写入的 entry 在锁外丢弃
+    pub fn register(
+        &self,
+        key: RegistryKey,
+        entry: ProxyEntry,
+        backend_id: String,
+    ) -> Result<Registered, RegisterError> {
+        let mut pending = Some(entry);
+        let mut entries = self.write();
+        let result = match entries.entry(key) {
+            Entry::Vacant(vacant) => {
+                let entry = pending.take().unwrap();
+                self.emit(RegistryEvent::Registered {
+                    name: vacant.key().0.clone(),
+                    publish_port: vacant.key().1,
+                    backends: entry.backends.len(),
+                });
+                vacant.insert(entry);
+                Ok(Registered::Inserted)
+            }
+            Entry::Occupied(mut occupied) => {
+                let existing = occupied.get_mut();
+                let entry = pending.as_mut().unwrap();
+                if existing.accepts(entry.proxy_info()) {
+                    let backends = existing.add_backend(entry.backends.remove(0), backend_id);
+                    self.emit(RegistryEvent::Registered {
+                        name: occupied.key().0.clone(),
+                        publish_port: occupied.key().1,
+                        backends,
+                    });
+                    Ok(Registered::Joined(backends))
+                } else if existing.is_draining() {
+                    Err(RegisterError::Draining)
+                } else {
+                    Err(RegisterError::Conflict)
+                }
+            }
+        };
+        // 未写入的 entry 在锁外释放
+        drop(entries);
+        drop(pending);
+        result
+    }
+
+    /// 作为后端加入已有的共享代理（`backend_id` 用于后端统计），返回加入后的后端数
+    pub fn join(
+        &self,
+        key: &RegistryKey,
+        registration: ProxyRegistration,
+        backend_id: String,
+    ) -> Result<usize, RegisterError> {
+        let mut entries = self.write();
+        let entry = match entries.get_mut(key) {
+            Some(entry) if entry.accepts(&registration.proxy_info) => entry,
+            Some(entry) if entry.is_draining() => return Err(RegisterError::Draining),
+            Some(_) => return Err(RegisterError::Conflict),
+            None => return Err(RegisterError::Gone),
+        };
+        let backends = entry.add_backend(registration, backend_id);
+        self.emit(RegistryEvent::Registered {
+            name: key.0.clone(),
+            publish_port: key.1,
+            backends,
+        });
+        Ok(backends)
+    }
+
+    /// 移除指定会话的后端，最后一个后端离开时移除整个注册表项
+    /// （同时关闭注册表项所有的共享监听器）；该会话不是后端时返回 None
+    pub fn unregister(
+        &self,
+        key: &RegistryKey,
+        stream_tx: &StreamRequestSender,
+    ) -> Option<Unregistered> {
+        let mut entries = self.write();
+        let entry = entries.get_mut(key)?;
+        if !entry.remove_backend(stream_tx) {
+            return None;
+        }
+        let result = Unregistered {
+            remaining_backends: entry.backends.len(),
+            visibility: entry.visibility,
+        };
+        let removed = entry
+            .backends
+            .is_empty()
+            .then(|| entries.remove(key))
+            .flatten();
+        self.emit(RegistryEvent::Unregistered {
+            name: key.0.clone(),
+            publish_port: key.1,
+            remaining_backends: result.remaining_backends,
+        });
+        // 在锁外释放注册表项（关闭共享监听器）
+        drop(entries);
+        drop(removed);
+        Some(result)
+    }
+
+    /// 移除正在排空的注册表项（排空完成），返回是否移除
+    pub fn remove_drained(&self, key: &RegistryKey) -> bool {
+        let mut entries = self.write();
+        if !entries.get(key).is_some_and(ProxyEntry::is_draining) {
+            return false;
+        }
+        let removed = entries.remove(key);
+        self.emit(RegistryEvent::Unregistered {
+            name: key.0.clone(),
+            publish_port: key.1,
+            remaining_backends: 0,
+        });
+        drop(entries);
+        drop(removed);
+        true
+    }
+
+    /// 设置代理状态，状态发生变化时返回 true
+    ///
+    /// 排空不可撤销：正在排空的代理不能回到 Active
+    pub fn set_state(&self, key: &RegistryKey, state: ProxyState) -> bool {
+        let mut entries = self.write();
+        let Some(entry) = entries.get_mut(key) else {
+            return false;
+        };
+        let changed = match state {
+            ProxyState::Draining => entry.start_draining(),
+            ProxyState::Active => false,
+        };
+        if changed {
+            self.emit(RegistryEvent::StateChanged {
+                name: key.0.clone(),
+                publish_port: key.1,
+                state,
+            });
+        }
+        changed
+    }
+
+    /// 按名称和发布端口查找
+    pub fn lookup(&self, name: &str, publish_port: u16) -> Option<ProxySnapshot> {
+        self.entries
+            .read()
+            .get(&(name.to_string(), publish_port))
+            .map(ProxyEntry::snapshot)
+    }
+
+    /// 查找指定名称在所有发布端口上的注册（按端口排序）
+    pub fn lookup_by_name(&self, name: &str) -> Vec<ProxySnapshot> {
+        let mut found: Vec<ProxySnapshot> = self
+            .entries
+            .read()
+            .iter()
+            .filter(|((entry_name, _), _)| entry_name == name)
+            .map(|(_, entry)| entry.snapshot())
+            .collect();
+        found.sort_by_key(|p| p.proxy_info.publish_port);
+        found
+    }
+
+    /// 代理是否已注册（publish_port 为 [`ANY_PUBLISH_PORT`] 时只按名称匹配，与 visitor stream 的查找规则相同）
+    pub fn is_registered(&self, name: &str, publish_port: u16) -> bool {
+        if publish_port == ANY_PUBLISH_PORT {
+            !self.lookup_by_name(name).is_empty()
+        } else {
+            self.lookup(name, publish_port).is_some()
+        }
+    }
+
+    /// 所有注册（按名称和发布端口排序）
+    pub fn list(&self) -> Vec<ProxySnapshot> {
+        let mut all: Vec<ProxySnapshot> = self
+            .entries
+            .read()
+            .values()
+            .map(ProxyEntry::snapshot)
+            .collect();
+        all.sort_by(|a, b| {
+            (&a.proxy_info.name, a.proxy_info.publish_port)
+                .cmp(&(&b.proxy_info.name, b.proxy_info.publish_port))
+        });
+        all
+    }
+
+    /// 注册数
+    pub fn len(&self) -> usize {
+        self.entries.read().len()
+    }
+
+    /// 是否没有任何注册
+    pub fn is_empty(&self) -> bool {
+        self.entries.read().is_empty()
+    }
+
+    /// 与现有条目比对提交的代理（不修改注册表）
+    ///
+    /// 结果只用于决定是否绑定发布端口，返回后可能已经过期；写入时由 [`Registry::register`]
+    /// 和 [`Registry::join`] 在同一次写锁内重新判断
+    pub fn check(
+        &self,
+        key: &RegistryKey,
+        stream_tx: &StreamRequestSender,
+        proxy: &ProxyInfo,
+    ) -> RegistrationCheck {
+        match self.entries.read().get(key) {
+            None => RegistrationCheck::Vacant,
+            Some(entry) if entry.is_draining() => RegistrationCheck::Draining,
+            Some(entry) if entry.is_registered_by(stream_tx, proxy) => {
+                RegistrationCheck::RegisteredBySession
+            }
+            Some(entry) if entry.accepts(proxy) => RegistrationCheck::Joinable,
+            Some(_) => RegistrationCheck::Conflict,
+        }
+    }
+
+    /// 代理没有连接的时长（有活跃连接时为 None，代理不存在时同样为 None）
+    pub fn idle_for(&self, key: &RegistryKey) -> Option<Duration> {
+        self.entries.read().get(key)?.tracker.idle_for()
+    }
+
+    /// 按加权轮询为一次连接选择后端并记录代理的活动时间（代理不存在时返回 None）
+    pub fn select_backends(&self, name: &str, publish_port: u16) -> Option<BackendSelection> {
+        let entries = self.entries.read();
+        let entry = entries.get(&(name.to_string(), publish_port))?;
+        entry.tracker.record_activity();
+        Some(BackendSelection {
+            backends: entry.select_backends(),
+            drain: entry.drain.clone(),
+            state: entry.state(),
+        })
+    }
+}
 
 /// RAII guard to automatically decrement active connections count
 pub struct ConnectionGuard {
@@ -45,6 +811,13 @@ impl ConnectionGuard {
     pub fn new(tracker: ProxyStatsTracker) -> Self {
         Self { tracker }
     }
+
+    /// 计入一个活跃连接；代理的连接数已达上限时返回 None
+    pub fn admit(tracker: &ProxyStatsTracker) -> Option<Self> {
+        tracker
+            .try_connection_started()
+            .then(|| Self::new(tracker.clone()))
+    }
 }
 
 impl Drop for ConnectionGuard {
@@ -52,3 +825,637 @@ impl Drop for ConnectionGuard {
         self.tracker.connection_ended();
     }
 }
+
+/// 单个客户端会话同时转发的 yamux stream 计数（`max_streams_per_client`）
+#[derive(Debug, Clone)]
+pub struct StreamLimiter {
+    active: Arc<AtomicUsize>,
+    max: Option<u32>,
+}
+
+impl StreamLimiter {
+    /// `max` 为 None 时只计数不限制
+    pub fn new(max: Option<u32>) -> Self {
+        Self {
+            active: Arc::new(AtomicUsize::new(0)),
+            max,
+        }
+    }
+
+    /// 计入一个 stream；已达上限时返回 None
+    pub fn try_acquire(&self) -> Option<StreamGuard> {
+        let max = self.max.map_or(usize::MAX, |max| max as usize);
+        self.active
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |active| {
+                (active < max).then_some(active + 1)
+            })
+            .ok()?;
+        Some(StreamGuard {
+            active: self.active.clone(),
+        })
+    }
+
+    /// 当前计入的 stream 数
+    #[cfg(test)]
+    fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// 配置的上限（不限制时为 None）
+    pub fn max(&self) -> Option<u32> {
+        self.max
+    }
+}
+
+/// RAII guard：转发 stream 的处理结束时释放会话的 stream 名额
+#[derive(Debug)]
+pub struct StreamGuard {
+    active: Arc<AtomicUsize>,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// 共享代理后端的活跃连接计数 guard
+pub struct BackendConnectionGuard {
+    tracker: BackendStatsTracker,
+}
+
+impl BackendConnectionGuard {
+    pub fn new(tracker: BackendStatsTracker) -> Self {
+        tracker.connection_started();
+        Self { tracker }
+    }
+}
+
+impl Drop for BackendConnectionGuard {
+    fn drop(&mut self) {
+        self.tracker.connection_ended();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weighted_order() {
+        // 权重 2:1，每 3 次选择中第一个后端被选 2 次
+        let picks: Vec<usize> = (0..6)
+            .map(|tick| weighted_order(&[2, 1], tick)[0])
+            .collect();
+        assert_eq!(picks, vec![0, 0, 1, 0, 0, 1]);
+
+        // 首选之后依次轮换其余后端
+        assert_eq!(weighted_order(&[1, 1, 1], 1), vec![1, 2, 0]);
+        assert_eq!(weighted_order(&[1, 1, 1], 5), vec![2, 0, 1]);
+
+        assert!(weighted_order(&[], 0).is_empty());
+    }
+
+    #[test]
+    fn test_is_registered_by_same_session() {
+        let proxy = ProxyInfo {
+            name: "web".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "0.0.0.0".to_string(),
+            publish_port: 8080,
+            local_port: 80,
+            peer_id: None,
+            shared: false,
+            weight: 1,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            integrity_check: false,
+            identity: None,
+        };
+        let (stream_tx, _rx) = mpsc::channel(1);
+        let (other_tx, _other_rx) = mpsc::channel(1);
+        let entry = ProxyEntry::new(
+            ProxyRegistration {
+                stream_tx: stream_tx.clone(),
+                proxy_info: proxy.clone(),
+                backend_stats: None,
+                visitor_mux: false,
+            },
+            ProxyStatsTracker::new("web".to_string(), "0.0.0.0".to_string(), 8080, 80),
+            None,
+        );
+
+        // 同一会话重复提交相同配置
+        assert!(entry.is_registered_by(&stream_tx, &proxy));
+        // 其他会话或配置不同
+        assert!(!entry.is_registered_by(&other_tx, &proxy));
+        let changed = ProxyInfo {
+            local_port: 81,
+            ..proxy
+        };
+        assert!(!entry.is_registered_by(&stream_tx, &changed));
+    }
+
+    #[test]
+    fn test_shared_entry_accepts_same_visibility() {
+        let private = ProxyInfo {
+            name: "db".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "0.0.0.0".to_string(),
+            publish_port: 5432,
+            local_port: 5432,
+            peer_id: None,
+            shared: true,
+            weight: 1,
+            visibility: ProxyVisibility::Private,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            integrity_check: false,
+            identity: None,
+        };
+        let (stream_tx, _rx) = mpsc::channel(1);
+        let entry = ProxyEntry::new(
+            ProxyRegistration {
+                stream_tx,
+                proxy_info: private.clone(),
+                backend_stats: None,
+                visitor_mux: false,
+            },
+            ProxyStatsTracker::new("db".to_string(), "0.0.0.0".to_string(), 5432, 5432),
+            None,
+        );
+
+        // 私有代理忽略 publish_addr
+        assert!(entry.accepts(&ProxyInfo {
+            publish_addr: "127.0.0.1".to_string(),
+            ..private.clone()
+        }));
+        // 公开代理不能加入私有代理
+        assert!(!entry.accepts(&ProxyInfo {
+            visibility: ProxyVisibility::Public,
+            ..private
+        }));
+    }
+
+    #[test]
+    fn test_draining_entry() {
+        let proxy = ProxyInfo {
+            name: "web".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "0.0.0.0".to_string(),
+            publish_port: 8080,
+            local_port: 80,
+            peer_id: None,
+            shared: true,
+            weight: 1,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: Some(5),
+            sni_routing: None,
+            socket: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            integrity_check: false,
+            identity: None,
+        };
+        let (stream_tx, _rx) = mpsc::channel(1);
+        let mut entry = ProxyEntry::new(
+            ProxyRegistration {
+                stream_tx,
+                proxy_info: proxy.clone(),
+                backend_stats: None,
+                visitor_mux: false,
+            },
+            ProxyStatsTracker::new("web".to_string(), "0.0.0.0".to_string(), 8080, 80),
+            None,
+        );
+        assert_eq!(entry.drain_timeout(30), Duration::from_secs(5));
+        assert!(entry.accepts(&proxy));
+
+        let relay = entry.drain.relay();
+        assert!(entry.start_draining());
+        assert!(!entry.start_draining());
+        assert!(entry.drain.accept.is_cancelled());
+        assert!(!entry.drain.relays.is_cancelled());
+        assert_eq!(entry.tracker.get_stats().core.status, "Draining");
+
+        // 排空中的共享代理不接受新后端
+        assert!(!entry.accepts(&proxy));
+
+        assert_eq!(entry.drain.active_relays(), 1);
+        drop(relay);
+        assert_eq!(entry.drain.active_relays(), 0);
+    }
+
+    fn shared_proxy(name: &str, publish_port: u16) -> ProxyInfo {
+        ProxyInfo {
+            name: name.to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "0.0.0.0".to_string(),
+            publish_port,
+            local_port: 80,
+            peer_id: None,
+            shared: true,
+            weight: 1,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            integrity_check: false,
+            identity: None,
+        }
+    }
+
+    fn registration(proxy: &ProxyInfo, stream_tx: &StreamRequestSender) -> ProxyRegistration {
+        ProxyRegistration {
+            stream_tx: stream_tx.clone(),
+            proxy_info: proxy.clone(),
+            backend_stats: None,
+            visitor_mux: false,
+        }
+    }
+
+    fn new_entry(proxy: &ProxyInfo, stream_tx: &StreamRequestSender) -> ProxyEntry {
+        ProxyEntry::new(
+            registration(proxy, stream_tx),
+            ProxyStatsTracker::new(
+                proxy.name.clone(),
+                proxy.publish_addr.clone(),
+                proxy.publish_port,
+                proxy.local_port,
+            ),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_registry_events_follow_changes_in_order() {
+        let registry = Registry::new();
+        let mut events = registry.subscribe();
+        let proxy = shared_proxy("web", 8080);
+        let key = ("web".to_string(), 8080);
+        let (first_tx, _first_rx) = mpsc::channel(1);
+        let (second_tx, _second_rx) = mpsc::channel(1);
+
+        assert_eq!(
+            registry.register(key.clone(), new_entry(&proxy, &first_tx), "a".to_string()),
+            Ok(Registered::Inserted)
+        );
+        let exclusive = ProxyInfo {
+            shared: false,
+            ..proxy.clone()
+        };
+        assert_eq!(
+            registry.register(
+                key.clone(),
+                new_entry(&exclusive, &second_tx),
+                "b".to_string()
+            ),
+            Err(RegisterError::Conflict)
+        );
+        assert_eq!(
+            registry.join(&key, registration(&proxy, &second_tx), "b".to_string()),
+            Ok(2)
+        );
+        assert_eq!(
+            registry.check(&key, &second_tx, &proxy),
+            RegistrationCheck::RegisteredBySession
+        );
+        assert!(registry.set_state(&key, ProxyState::Draining));
+        assert!(!registry.set_state(&key, ProxyState::Draining));
+        assert!(!registry.set_state(&key, ProxyState::Active));
+        assert_eq!(
+            registry.join(&key, registration(&proxy, &first_tx), "a".to_string()),
+            Err(RegisterError::Draining)
+        );
+        let removed = registry.unregister(&key, &first_tx).unwrap();
+        assert_eq!(removed.remaining_backends, 1);
+        assert!(registry.remove_drained(&key));
+        assert!(registry.unregister(&key, &second_tx).is_none());
+        assert!(registry.is_empty());
+
+        let mut received = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            received.push(event);
+        }
+        assert_eq!(
+            received,
+            vec![
+                RegistryEvent::Registered {
+                    name: "web".to_string(),
+                    publish_port: 8080,
+                    backends: 1,
+                },
+                RegistryEvent::Registered {
+                    name: "web".to_string(),
+                    publish_port: 8080,
+                    backends: 2,
+                },
+                RegistryEvent::StateChanged {
+                    name: "web".to_string(),
+                    publish_port: 8080,
+                    state: ProxyState::Draining,
+                },
+                RegistryEvent::Unregistered {
+                    name: "web".to_string(),
+                    publish_port: 8080,
+                    remaining_backends: 1,
+                },
+                RegistryEvent::Unregistered {
+                    name: "web".to_string(),
+                    publish_port: 8080,
+                    remaining_backends: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lookup_by_name_and_list() {
+        let registry = Registry::new();
+        let (stream_tx, _rx) = mpsc::channel(1);
+        for (name, port) in [("web", 9090), ("api", 7070), ("web", 8080)] {
+            let proxy = shared_proxy(name, port);
+            registry
+                .register(
+                    (name.to_string(), port),
+                    new_entry(&proxy, &stream_tx),
+                    String::new(),
+                )
+                .unwrap();
+        }
+
+        let ports: Vec<u16> = registry
+            .lookup_by_name("web")
+            .iter()
+            .map(|p| p.proxy_info.publish_port)
+            .collect();
+        assert_eq!(ports, vec![8080, 9090]);
+        let keys: Vec<(String, u16)> = registry
+            .list()
+            .into_iter()
+            .map(|p| (p.proxy_info.name, p.proxy_info.publish_port))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                ("api".to_string(), 7070),
+                ("web".to_string(), 8080),
+                ("web".to_string(), 9090)
+            ]
+        );
+        assert!(registry.lookup("web", 7070).is_none());
+        assert_eq!(registry.len(), 3);
+    }
+
+    #[test]
+    fn test_idle_for_tracks_connections() {
+        let registry = Registry::new();
+        let (stream_tx, _rx) = mpsc::channel(1);
+        let proxy = shared_proxy("web", 8080);
+        let key = ("web".to_string(), 8080);
+        let entry = new_entry(&proxy, &stream_tx);
+        let tracker = entry.tracker.clone();
+        registry
+            .register(key.clone(), entry, String::new())
+            .unwrap();
+
+        // 从注册时开始计算，从未有过连接
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(registry.idle_for(&key).unwrap() >= Duration::from_millis(50));
+        assert_eq!(tracker.get_stats().last_activity_at, None);
+
+        // 选择后端（visitor 或共享监听器的连接）记录活动时间
+        registry.select_backends("web", 8080).unwrap();
+        assert!(registry.idle_for(&key).unwrap() < Duration::from_millis(50));
+        assert!(tracker.get_stats().last_activity_at.is_some());
+
+        // 有活跃连接时不算空闲
+        tracker.connection_started();
+        assert_eq!(registry.idle_for(&key), None);
+        tracker.connection_ended();
+        assert!(registry.idle_for(&key).is_some());
+        assert_eq!(registry.idle_for(&("api".to_string(), 8080)), None);
+    }
+
+    /// 多个会话并发注册、加入、查找和离开：每个会话注册后总能查到自己的后端，结束后注册表为空
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_register_unregister_lookup() {
+        const SESSIONS: usize = 16;
+        const ROUNDS: usize = 200;
+
+        let registry = Registry::new();
+        let mut tasks = Vec::new();
+        for session in 0..SESSIONS {
+            let registry = registry.clone();
+            tasks.push(tokio::spawn(async move {
+                let (stream_tx, _rx) = mpsc::channel(1);
+                for round in 0..ROUNDS {
+                    // 所有会话竞争同一组共享代理：条目不存在时新注册，否则加入
+                    let port = 10000 + (round % 4) as u16;
+                    let proxy = shared_proxy("shared", port);
+                    let key = ("shared".to_string(), port);
+                    // 写入时在同一次写锁内判断：条目已存在时直接加入
+                    registry
+                        .register(
+                            key.clone(),
+                            new_entry(&proxy, &stream_tx),
+                            session.to_string(),
+                        )
+                        .unwrap();
+                    assert_eq!(
+                        registry.check(&key, &stream_tx, &proxy),
+                        RegistrationCheck::RegisteredBySession
+                    );
+                    assert!(registry
+                        .lookup("shared", port)
+                        .is_some_and(|p| p.backends >= 1));
+                    assert!(registry
+                        .select_backends("shared", port)
+                        .is_some_and(|s| !s.backends.is_empty()));
+                    assert!(registry.unregister(&key, &stream_tx).is_some());
+                    tokio::task::yield_now().await;
+                }
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert!(registry.is_empty());
+    }
+
+    #[test]
+    fn test_lock_hold_stats_percentiles() {
+        let stats = LockHoldStats::default();
+        assert_eq!(
+            stats.snapshot(),
+            LockHoldSnapshot {
+                acquisitions: 0,
+                p99_us: 0,
+                max_us: 0
+            }
+        );
+        for _ in 0..990 {
+            stats.record(Duration::from_micros(3));
+        }
+        for _ in 0..10 {
+            stats.record(Duration::from_micros(700));
+        }
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.acquisitions, 1000);
+        // 3 微秒落在 [2, 4) 的桶
+        assert_eq!(snapshot.p99_us, 3);
+        assert_eq!(snapshot.max_us, 700);
+
+        stats.record(Duration::from_micros(900));
+        for _ in 0..20 {
+            stats.record(Duration::from_micros(800));
+        }
+        let snapshot = stats.snapshot();
+        // 桶 [512, 1024) 的上界不超过最大值
+        assert_eq!(snapshot.p99_us, 900);
+        assert_eq!(snapshot.max_us, 900);
+    }
+
+    /// 一个线程注册 `proxies` 个代理的同时另一个线程不断查找，返回注册表和每次查找的耗时
+    ///
+    /// 查找线程同时检查读到的注册表项与查找的键一致（不会读到写入一半的注册）
+    fn register_while_looking_up(proxies: u16) -> (Registry, Vec<Duration>) {
+        let registry = Registry::new();
+        let (stream_tx, _rx) = mpsc::channel(1);
+        let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+        let lookups = {
+            let registry = registry.clone();
+            let done = done.clone();
+            std::thread::spawn(move || {
+                let mut latencies = Vec::new();
+                let mut port = 0;
+                // 单核机器上注册可能在本线程被调度之前就已完成，至少查找一次
+                loop {
+                    let started = std::time::Instant::now();
+                    let found = registry.lookup("proxy", 20000 + port);
+                    let selected = registry.select_backends("proxy", 20000 + port);
+                    latencies.push(started.elapsed());
+                    if let Some(found) = found {
+                        assert_eq!(found.proxy_info.publish_port, 20000 + port);
+                        assert!(selected.is_some());
+                    }
+                    port = (port + 1) % proxies;
+                    if done.load(Ordering::Relaxed) {
+                        break;
+                    }
+                }
+                latencies
+            })
+        };
+
+        for port in 0..proxies {
+            let proxy = ProxyInfo {
+                shared: false,
+                ..shared_proxy("proxy", 20000 + port)
+            };
+            registry
+                .register(
+                    ("proxy".to_string(), 20000 + port),
+                    new_entry(&proxy, &stream_tx),
+                    String::new(),
+                )
+                .unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+
+        let latencies = lookups.join().unwrap();
+        assert!(!latencies.is_empty());
+        (registry, latencies)
+    }
+
+    /// 一个线程注册 1000 个代理的同时另一个线程不断查找：每次注册只获取一次写锁，
+    /// 查找读到的都是完整的注册，注册结束后全部可以查到
+    #[test]
+    fn test_lookups_while_registering() {
+        const PROXIES: u16 = 1000;
+
+        let (registry, _) = register_while_looking_up(PROXIES);
+        assert_eq!(registry.len(), PROXIES as usize);
+        for port in 0..PROXIES {
+            assert!(registry.lookup("proxy", 20000 + port).is_some());
+        }
+
+        #[cfg(feature = "lock-timing")]
+        assert_eq!(registry.lock_holds().unwrap().acquisitions, PROXIES as u64);
+        #[cfg(not(feature = "lock-timing"))]
+        assert!(registry.lock_holds().is_none());
+    }
+
+    /// 注册期间的查找延迟（写锁内只有映射操作，查找延迟保持在毫秒以内）；结果取决于机器负载，默认不运行
+    #[test]
+    #[ignore = "timing benchmark; run with --ignored"]
+    fn bench_lookup_latency_while_registering() {
+        let (registry, mut latencies) = register_while_looking_up(1000);
+        latencies.sort();
+        let p99 = latencies[latencies.len() * 99 / 100];
+        assert!(
+            p99 < Duration::from_millis(5),
+            "p99 lookup latency {:?}",
+            p99
+        );
+
+        #[cfg(feature = "lock-timing")]
+        {
+            let holds = registry.lock_holds().unwrap();
+            assert!(holds.p99_us < 1000, "{:?}", holds);
+        }
+        #[cfg(not(feature = "lock-timing"))]
+        assert!(registry.lock_holds().is_none());
+    }
+
+    #[test]
+    fn test_stream_limiter() {
+        let limiter = StreamLimiter::new(Some(2));
+        let first = limiter.try_acquire().unwrap();
+        let _second = limiter.try_acquire().unwrap();
+        assert!(limiter.try_acquire().is_none());
+        assert_eq!(limiter.active(), 2);
+
+        drop(first);
+        assert_eq!(limiter.active(), 1);
+        assert!(limiter.try_acquire().is_some());
+
+        let unlimited = StreamLimiter::new(None);
+        let guards: Vec<_> = (0..100).filter_map(|_| unlimited.try_acquire()).collect();
+        assert_eq!(guards.len(), 100);
+    }
+
+    #[test]
+    fn test_connection_guard_admit() {
+        let tracker = ProxyStatsTracker::new("web".to_string(), "0.0.0.0".to_string(), 8080, 80)
+            .with_connection_limit(Some(1));
+        let guard = ConnectionGuard::admit(&tracker).unwrap();
+        assert!(ConnectionGuard::admit(&tracker).is_none());
+        let stats = tracker.get_stats();
+        assert_eq!(stats.core.active_connections, 1);
+        assert_eq!(stats.core.total_connections, 1);
+        assert_eq!(stats.core.connection_limited, 1);
+
+        drop(guard);
+        assert!(ConnectionGuard::admit(&tracker).is_some());
+    }
+}
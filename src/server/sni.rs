@@ -0,0 +1,110 @@
+/// SNI 路由：预读外部连接的 TLS ClientHello（不终止 TLS），按 SNI 选择客户端的本地端口
+///
+/// 预读的字节原样转发给客户端，后端自行完成 TLS 握手
+use rustls::server::Acceptor;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Duration};
+
+/// 预读的最大字节数，超出后不再尝试解析
+const MAX_CLIENT_HELLO_SIZE: usize = 64 * 1024;
+
+/// 预读的 ClientHello
+#[derive(Debug, Default)]
+pub(super) struct ClientHelloPeek {
+    /// 已从连接读出的全部字节（需要先于后续数据转发）
+    pub data: Vec<u8>,
+    /// ClientHello 中的 SNI（非 TLS 连接或没有 SNI 时为 None）
+    pub sni: Option<String>,
+}
+
+/// 从连接预读 ClientHello 并取出 SNI
+///
+/// 最多等待 `wait`（服务器先发言的协议等到超时后按默认路由转发）；
+/// 非 TLS 数据、解析失败、读取出错或超时都按没有 SNI 处理，已读出的字节同样保留
+pub(super) async fn peek_client_hello(stream: &mut TcpStream, wait: Duration) -> ClientHelloPeek {
+    let mut peek = ClientHelloPeek::default();
+    let mut acceptor = Acceptor::default();
+    let mut buf = [0u8; 4096];
+
+    let read = async {
+        loop {
+            let n = stream.read(&mut buf).await.ok()?;
+            if n == 0 {
+                return None;
+            }
+            peek.data.extend_from_slice(&buf[..n]);
+            // TLS 记录以 handshake 类型（0x16）开头
+            if peek.data[0] != 0x16 {
+                return None;
+            }
+
+            let mut chunk = &buf[..n];
+            while !chunk.is_empty() {
+                if !matches!(acceptor.read_tls(&mut chunk), Ok(read) if read > 0) {
+                    return None;
+                }
+            }
+            match acceptor.accept() {
+                Ok(Some(accepted)) => {
+                    return accepted.client_hello().server_name().map(str::to_string)
+                }
+                Ok(None) if peek.data.len() < MAX_CLIENT_HELLO_SIZE => {}
+                _ => return None,
+            }
+        }
+    };
+    peek.sni = timeout(wait, read).await.ok().flatten();
+    peek
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    async fn accept_peek(
+        send: impl FnOnce(TcpStream) -> tokio::task::JoinHandle<()>,
+    ) -> ClientHelloPeek {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (mut inbound, _) = listener.accept().await.unwrap();
+        let sender = send(client);
+        let peek = peek_client_hello(&mut inbound, Duration::from_secs(3)).await;
+        sender.abort();
+        peek
+    }
+
+    #[tokio::test]
+    async fn test_peek_client_hello_extracts_sni() {
+        let peek = accept_peek(|tcp| {
+            tokio::spawn(async move {
+                let config = crate::tls::load_client_config_with_alpn(None, true, None).unwrap();
+                let connector = tokio_rustls::TlsConnector::from(config);
+                let _ = connector
+                    .connect("app1.example.com".try_into().unwrap(), tcp)
+                    .await;
+            })
+        })
+        .await;
+        assert_eq!(peek.sni.as_deref(), Some("app1.example.com"));
+        assert_eq!(peek.data[0], 0x16);
+    }
+
+    #[tokio::test]
+    async fn test_peek_non_tls_keeps_data() {
+        let peek = accept_peek(|mut tcp| {
+            tokio::spawn(async move {
+                tcp.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            })
+        })
+        .await;
+        assert!(peek.sni.is_none());
+        assert!(b"GET / HTTP/1.1\r\n\r\n".starts_with(&peek.data));
+        assert!(!peek.data.is_empty());
+    }
+}
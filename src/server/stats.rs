@@ -1,92 +1,151 @@
+use super::capacity::{self, CapacitySnapshot, Gauge, HeadroomStatus};
+use super::registry::{Registry, RegistryEvent};
+use super::ServerState;
 use crate::stats::StatsManager;
+use crate::stats_http::{self, escape_html, HttpRequest, HttpResponse, StatsQuery};
+use crate::stats_ws::{self, StatsFeed};
+use crate::transport::HttpRoute;
 use anyhow::{Context, Result};
-use std::net::SocketAddr;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::sync::Arc;
 use tokio::net::TcpListener;
-use tracing::{error, info};
+use tracing::info;
 
 /// 启动统计数据 HTTP 服务器
+///
+/// /stats 返回代理统计，/forwards 返回各客户端的 forward 用量，/config 返回加载的配置版本，
+/// /registry 返回注册的代理及可以通过 visitor 访问它们的客户端，
+/// /capacity 返回各项限制与当前用量（/capacity.html 为对应的页面），/healthz 返回统计服务器的运行状态，
+/// /stats/ws 以 WebSocket 推送统计变化和注册表事件（见 [`stats_ws`]）。
+/// 端口被占用或监听套接字失效时退避后重新绑定（见 [`stats_http::supervise`]）
 pub async fn start_stats_server(
     bind_addr: String,
     port: u16,
-    stats_manager: StatsManager,
+    state: Arc<ServerState>,
 ) -> Result<()> {
-    let listener = TcpListener::bind(format!("{}:{}", bind_addr, port))
-        .await
-        .context("Failed to bind stats server port")?;
-
-    info!("Stats server listening on http://{}:{}", bind_addr, port);
-
-    loop {
-        match listener.accept().await {
-            Ok((mut stream, addr)) => {
-                let stats_manager = stats_manager.clone();
-
-                tokio::spawn(async move {
-                    handle_stats_request(&mut stream, addr, &stats_manager).await;
-                });
+    let address = format!("{}:{}", bind_addr, port);
+    let health = state
+        .stats_servers
+        .register(format!("Stats server {}", address));
+    let limits = state.config.stats_limits.clone().unwrap_or_default();
+    stats_http::supervise(
+        health,
+        limits,
+        move || {
+            let address = address.clone();
+            async move {
+                let listener = TcpListener::bind(&address)
+                    .await
+                    .context("Failed to bind stats server port")?;
+                info!("Stats server listening on http://{}", address);
+                Ok(listener)
             }
-            Err(e) => {
-                error!("Failed to accept stats connection: {}", e);
-            }
-        }
-    }
+        },
+        move |request| handle_stats_request(request, &state),
+    )
+    .await
 }
 
-/// 处理单个统计请求
-async fn handle_stats_request(
-    stream: &mut tokio::net::TcpStream,
-    _addr: SocketAddr,
-    stats_manager: &StatsManager,
-) {
-    let mut buffer = vec![0u8; 4096];
-    let n = match stream.read(&mut buffer).await {
-        Ok(n) => n,
-        Err(e) => {
-            error!("Failed to read from stats client {}: {}", _addr, e);
-            return;
-        }
-    };
-
-    // 解析HTTP请求
-    let request = String::from_utf8_lossy(&buffer[..n]);
-    let path = request
-        .lines()
-        .next()
-        .and_then(|line| line.split_whitespace().nth(1))
-        .unwrap_or("/");
-
-    let response = if path == "/stats" || path == "/stats/" {
-        // 返回JSON格式的统计信息
-        let stats = stats_manager.get_all_stats();
-        let json = serde_json::to_string_pretty(&stats).unwrap_or_default();
+/// 隧道端口上的统计路由（`stats_path`）
+///
+/// `stats_path` 下的请求去掉该前缀后按独立统计服务器的路径处理：
+/// `{stats_path}` 为统计页面，`{stats_path}/stats`、`{stats_path}/config` 为 JSON。
+/// 隧道端口上的路由不支持协议升级，`{stats_path}/stats/ws` 返回 404
+pub fn stats_route(stats_path: String, state: Arc<ServerState>) -> HttpRoute {
+    Arc::new(move |request| {
+        let rest = request.target.strip_prefix(stats_path.as_str())?;
+        let target = match rest.chars().next() {
+            None => "/".to_string(),
+            Some('/') => rest.to_string(),
+            Some('?') => format!("/{}", rest),
+            Some(_) => return None,
+        };
+        let request = HttpRequest {
+            target,
+            ..request.clone()
+        };
+        let response = handle_stats_request(&request, &state);
+        Some(match response.upgrade {
+            Some(_) => HttpResponse::not_found(),
+            None => response,
+        })
+    })
+}
 
-        format!(
-            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
-            json.len(),
-            json
+/// 处理单个统计请求
+fn handle_stats_request(request: &HttpRequest, state: &ServerState) -> HttpResponse {
+    let stats_manager = &state.stats_manager;
+    let path = request.target.as_str();
+    if path == "/config" || path == "/config/" {
+        HttpResponse::json(
+            serde_json::to_string_pretty(&state.config_generation).unwrap_or_default(),
+        )
+    } else if path == "/healthz" {
+        // 统计服务器的运行状态（独立统计端口失效时可从隧道端口的 stats_path 查看）
+        state.stats_servers.respond()
+    } else if path == "/capacity" || path == "/capacity/" {
+        // 各项限制与当前用量
+        let snapshot = capacity::snapshot(state);
+        HttpResponse::json(serde_json::to_string_pretty(&snapshot).unwrap_or_default())
+    } else if path == "/capacity.html" {
+        HttpResponse::html(generate_capacity_html(&capacity::snapshot(state)))
+    } else if path == "/forwards" || path == "/forwards/" {
+        // 各客户端会话的 forward 用量及限制
+        let usage = stats_manager.get_forward_usage();
+        HttpResponse::json(serde_json::to_string_pretty(&usage).unwrap_or_default())
+    } else if path == "/registry" || path == "/registry/" {
+        // 注册表中的代理及可以通过 visitor 访问它们的客户端
+        let listing = state.visitor_acl.registry_listing(&state.proxy_registry);
+        HttpResponse::json(serde_json::to_string_pretty(&listing).unwrap_or_default())
+    } else if request.path() == "/stats/ws" {
+        // 实时推送统计变化
+        let feed = ServerStatsFeed {
+            stats_manager: stats_manager.clone(),
+            registry: state.proxy_registry.clone(),
+        };
+        stats_ws::respond(
+            request,
+            state.config.stats_stream.clone().unwrap_or_default(),
+            &state.config.stats_limits.clone().unwrap_or_default(),
+            feed,
         )
+    } else if request.path() == "/stats" || request.path() == "/stats/" {
+        // 返回JSON格式的统计信息（支持 limit/offset/name_prefix/pretty 查询参数）
+        match StatsQuery::parse(request) {
+            Some(query) => query.respond(request, stats_manager.fingerprint().etag(), |query| {
+                stats_manager.get_stats_page(query)
+            }),
+            None => HttpResponse::error(400),
+        }
     } else if path == "/" || path.starts_with("/?") {
         // 返回HTML页面
-        let html = generate_stats_html(stats_manager);
-
-        format!(
-            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\r\n{}",
-            html.len(),
-            html
-        )
+        HttpResponse::html(generate_stats_html(stats_manager))
     } else {
-        // 404
-        let body = "404 Not Found";
-        format!(
-            "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
-            body.len(),
-            body
-        )
-    };
+        HttpResponse::not_found()
+    }
+}
 
-    if let Err(e) = stream.write_all(response.as_bytes()).await {
-        error!("Failed to write response to {}: {}", _addr, e);
+/// `/stats/ws` 推送的数据来源：代理统计和注册表事件
+struct ServerStatsFeed {
+    stats_manager: StatsManager,
+    registry: Registry,
+}
+
+impl StatsFeed for ServerStatsFeed {
+    type Event = RegistryEvent;
+
+    fn snapshot(&self) -> Vec<(String, serde_json::Value)> {
+        self.stats_manager
+            .get_all_stats()
+            .into_iter()
+            .map(|stat| {
+                let value = serde_json::to_value(&stat).unwrap_or_default();
+                (stat.core.name, value)
+            })
+            .collect()
+    }
+
+    fn subscribe(&self) -> Option<tokio::sync::broadcast::Receiver<RegistryEvent>> {
+        Some(self.registry.subscribe())
     }
 }
 
@@ -100,16 +159,38 @@ fn generate_stats_html(stats_manager: &StatsManager) -> String {
 
     let mut rows = String::new();
     for stat in &stats {
-        let uptime_seconds = now.saturating_sub(stat.start_time);
+        let uptime_seconds = now.saturating_sub(stat.core.start_time);
         let uptime = format_duration(uptime_seconds);
-        let bytes_sent = format_bytes(stat.bytes_sent);
-        let bytes_received = format_bytes(stat.bytes_received);
+        let bytes_sent = format_bytes(stat.core.bytes_sent);
+        let bytes_received = format_bytes(stat.core.bytes_received);
+
+        // 共享代理在名称下列出各后端的连接分布
+        let backends: String = stat
+            .backends
+            .iter()
+            .map(|b| {
+                format!(
+                    r#"<div class="backend">{} &times;{}: {} active / {} total</div>"#,
+                    escape_html(&b.id),
+                    b.weight,
+                    b.active_connections,
+                    b.total_connections
+                )
+            })
+            .collect();
+
+        // 排空中的代理不再接受新连接
+        let draining = if stat.core.status == "Draining" {
+            r#" <span class="badge badge-draining">draining</span>"#
+        } else {
+            ""
+        };
 
         rows.push_str(&format!(
             r#"
             <tr>
+                <td>{}{}{}</td>
                 <td>{}</td>
-                <td>{}:{}</td>
                 <td>{}</td>
                 <td>{}</td>
                 <td>{}</td>
@@ -118,12 +199,13 @@ fn generate_stats_html(stats_manager: &StatsManager) -> String {
                 <td>{}</td>
             </tr>
             "#,
-            stat.name,
-            stat.publish_addr,
-            stat.publish_port,
-            stat.local_port,
-            stat.active_connections,
-            stat.total_connections,
+            escape_html(&stat.core.name),
+            draining,
+            backends,
+            escape_html(&stat.publish_label()),
+            stat.core.target_port,
+            stat.core.active_connections,
+            stat.core.total_connections,
             bytes_sent,
             bytes_received,
             uptime
@@ -228,10 +310,19 @@ fn generate_stats_html(stats_manager: &StatsManager) -> String {
             font-size: 0.85em;
             font-weight: 600;
         }}
+        .backend {{
+            margin-top: 4px;
+            font-size: 0.85em;
+            color: #6c757d;
+        }}
         .badge-success {{
             background: #d4edda;
             color: #155724;
         }}
+        .badge-draining {{
+            background: #fff3cd;
+            color: #856404;
+        }}
         .empty {{
             text-align: center;
             padding: 60px;
@@ -287,14 +378,14 @@ fn generate_stats_html(stats_manager: &StatsManager) -> String {
 
         <footer>
             <p>TLS Tunnel Server · Powered by Rust & Tokio</p>
-            <p style="margin-top: 8px;"><a href="/stats" style="color: #667eea; text-decoration: none;">View JSON API</a></p>
+            <p style="margin-top: 8px;"><a href="/stats" style="color: #667eea; text-decoration: none;">View JSON API</a> · <a href="/capacity.html" style="color: #667eea; text-decoration: none;">Capacity</a></p>
         </footer>
     </div>
 </body>
 </html>"#,
         stats.len(),
-        stats.iter().map(|s| s.active_connections).sum::<u64>(),
-        stats.iter().map(|s| s.total_connections).sum::<u64>(),
+        stats.iter().map(|s| s.core.active_connections).sum::<u64>(),
+        stats.iter().map(|s| s.core.total_connections).sum::<u64>(),
         if stats.is_empty() {
             r#"<div class="empty">
                 <div class="empty-icon">📊</div>
@@ -327,6 +418,119 @@ fn generate_stats_html(stats_manager: &StatsManager) -> String {
     )
 }
 
+/// 生成容量面板HTML页面
+fn generate_capacity_html(snapshot: &CapacitySnapshot) -> String {
+    fn row(name: &str, gauge: &Gauge, format: fn(u64) -> String) -> String {
+        let limit = gauge.limit.map(format).unwrap_or_else(|| "-".to_string());
+        let utilization = gauge
+            .utilization
+            .map(|u| format!("{:.1}%", u))
+            .unwrap_or_else(|| "-".to_string());
+        format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape_html(name),
+            format(gauge.current),
+            limit,
+            utilization
+        )
+    }
+    let count = |n: u64| n.to_string();
+
+    let mut rows = row("Client sessions", &snapshot.sessions, count);
+    let handshakes = &snapshot.handshakes;
+    rows.push_str(&row(
+        "Handshakes in progress",
+        &handshakes.in_progress,
+        count,
+    ));
+    rows.push_str(&format!(
+        "<tr><td>Handshake results</td><td>{} queued, {} completed, {} failed, {} timed out, {} rate limited</td><td>-</td><td>-</td></tr>",
+        handshakes.queued, handshakes.completed, handshakes.failed, handshakes.timed_out, handshakes.rate_limited
+    ));
+    rows.push_str(&row("Stream request queue", &snapshot.stream_queue, count));
+    rows.push_str(&row("Yamux streams", &snapshot.yamux_streams, count));
+    if let Some(lock) = &snapshot.registry_lock {
+        rows.push_str(&format!(
+            "<tr><td>Registry write lock</td><td>{} acquisitions, p99 {} µs, max {} µs</td><td>-</td><td>-</td></tr>",
+            lock.acquisitions, lock.p99_us, lock.max_us
+        ));
+    }
+    if let Some(fds) = &snapshot.file_descriptors {
+        rows.push_str(&row("File descriptors", fds, count));
+    }
+    if let Some(rss) = &snapshot.memory_rss {
+        rows.push_str(&row("Memory RSS", rss, format_bytes));
+    }
+    let relay = &snapshot.relay_memory;
+    rows.push_str(&row("Relay buffers", &relay.used, format_bytes));
+    rows.push_str(&format!(
+        "<tr><td>Relay buffer pressure</td><td>{} buffers, {} peak, {} reduced, {} waits ({} waiting)</td><td>-</td><td>-</td></tr>",
+        relay.buffers, format_bytes(relay.high_water_bytes), relay.reduced_buffers, relay.waits, relay.waiting
+    ));
+    if let Some(rate) = &snapshot.rate_limiter {
+        let reject_rate = rate
+            .reject_rate
+            .map(|r| format!(", {:.1}% rejected", r))
+            .unwrap_or_default();
+        rows.push_str(&format!(
+            "<tr><td>Rate limiter tokens</td><td>{} available{}</td><td>{} burst, {}/s</td><td>{:.1}%</td></tr>",
+            rate.available_tokens, reject_rate, rate.burst_size, rate.requests_per_second, rate.utilization
+        ));
+    }
+    for proxy in &snapshot.proxies {
+        rows.push_str(&row(
+            &format!("Proxy {} (:{})", proxy.name, proxy.listen_port),
+            &proxy.connections,
+            count,
+        ));
+    }
+
+    let headroom = &snapshot.headroom;
+    let (status, color) = match headroom.status {
+        HeadroomStatus::Ok => ("OK", "#155724"),
+        HeadroomStatus::Warning => ("WARNING", "#856404"),
+        HeadroomStatus::Critical => ("CRITICAL", "#721c24"),
+    };
+    let summary = match (headroom.headroom, &headroom.bottleneck) {
+        (Some(free), Some(bottleneck)) => format!(
+            "{:.1}% headroom, bottleneck: {}",
+            free,
+            escape_html(bottleneck)
+        ),
+        _ => "no configured limits".to_string(),
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta http-equiv="refresh" content="5">
+    <title>TLS Tunnel - Capacity</title>
+    <style>
+        body {{ font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, Arial, sans-serif; margin: 20px; color: #495057; }}
+        h1 {{ font-size: 1.5em; margin-bottom: 8px; }}
+        .summary {{ font-weight: 600; margin-bottom: 16px; }}
+        table {{ border-collapse: collapse; }}
+        th, td {{ padding: 6px 14px; border-bottom: 1px solid #e9ecef; text-align: left; }}
+        th {{ background: #f8f9fa; }}
+        a {{ color: #667eea; text-decoration: none; }}
+    </style>
+</head>
+<body>
+    <h1>Capacity</h1>
+    <p class="summary"><span style="color: {}">{}</span> · {}</p>
+    <table>
+        <thead><tr><th>Resource</th><th>Current</th><th>Limit</th><th>Utilization</th></tr></thead>
+        <tbody>{}</tbody>
+    </table>
+    <p style="margin-top: 12px;"><a href="/">Dashboard</a> · <a href="/capacity">JSON</a></p>
+</body>
+</html>"#,
+        color, status, summary, rows
+    )
+}
+
 /// 格式化字节数为人类可读格式
 pub fn format_bytes(bytes: u64) -> String {
     const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
@@ -362,3 +566,201 @@ pub fn format_duration(seconds: u64) -> String {
         format!("{}s", secs)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{
+        ForwardLimitConfig, ProxyVisibility, ServerConfigBuilder, StatsLimitConfig,
+    };
+    use crate::server::ServerDependencies;
+    use crate::stats::ForwardUsageTracker;
+    use std::collections::HashSet;
+    use std::net::SocketAddr;
+    use std::time::{Duration, Instant};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    const TRACKERS: usize = 2000;
+
+    async fn start_test_server(stats_manager: StatsManager) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = ServerConfigBuilder::new()
+            .bind_addr("127.0.0.1")
+            .bind_port(8443)
+            .auth_key("stats-test-auth-key")
+            .build()
+            .unwrap();
+        let mut deps = ServerDependencies::new();
+        deps.stats_manager = stats_manager;
+        let state = ServerState::with_dependencies(config, deps);
+        tokio::spawn(stats_http::serve(
+            listener,
+            StatsLimitConfig::default(),
+            move |request| handle_stats_request(request, &state),
+        ));
+        addr
+    }
+
+    /// 发送 GET 请求，返回响应头和响应体
+    async fn get(addr: SocketAddr, target: &str, headers: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(format!("GET {} HTTP/1.1\r\n{}\r\n", target, headers).as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.to_string(), body.to_string())
+    }
+
+    fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+        head.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+
+    fn names(body: &str) -> Vec<String> {
+        let stats: Vec<serde_json::Value> = serde_json::from_str(body).unwrap();
+        stats
+            .iter()
+            .map(|s| s["name"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_stats_pagination_and_etag() {
+        let stats_manager = StatsManager::new();
+        let trackers: Vec<_> = (0..TRACKERS)
+            .map(|i| {
+                let tracker = stats_manager.register_proxy(
+                    format!("proxy-{:04}", i),
+                    "0.0.0.0".to_string(),
+                    10000 + i as u16,
+                    20000,
+                    ProxyVisibility::Public,
+                );
+                tracker.connection_started();
+                tracker
+            })
+            .collect();
+        let addr = start_test_server(stats_manager.clone()).await;
+
+        // 分页结果互不重叠且覆盖全部条目
+        let started = Instant::now();
+        let mut seen = HashSet::new();
+        let mut offset = 0;
+        loop {
+            let (head, body) = get(addr, &format!("/stats?limit=300&offset={}", offset), "").await;
+            assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+            assert_eq!(header(&head, "X-Total-Count"), Some("2000"));
+            let page = names(&body);
+            if page.is_empty() {
+                break;
+            }
+            assert!(page.len() <= 300);
+            for name in page {
+                assert!(seen.insert(name), "Pages overlap");
+            }
+            offset += 300;
+        }
+        assert_eq!(seen.len(), TRACKERS);
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "Paging took {:?}",
+            started.elapsed()
+        );
+
+        // 完整列表默认紧凑输出，pretty=1 时缩进
+        let (head, body) = get(addr, "/stats", "").await;
+        assert_eq!(names(&body).len(), TRACKERS);
+        assert!(!body.contains('\n'));
+        let (_, pretty) = get(addr, "/stats?name_prefix=proxy-19&pretty=1", "").await;
+        assert_eq!(names(&pretty).len(), 100);
+        assert!(pretty.starts_with("[\n  {"));
+
+        // 数据未变化时返回 304，连接数变化后 ETag 随之变化
+        let etag = header(&head, "ETag").unwrap().to_string();
+        let (head, body) = get(addr, "/stats", &format!("If-None-Match: {}\r\n", etag)).await;
+        assert!(head.starts_with("HTTP/1.1 304"), "{}", head);
+        assert!(body.is_empty());
+
+        trackers[0].connection_started();
+        let (head, _) = get(addr, "/stats", &format!("If-None-Match: {}\r\n", etag)).await;
+        assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+        assert_ne!(header(&head, "ETag"), Some(etag.as_str()));
+
+        let (head, _) = get(addr, "/stats?limit=abc", "").await;
+        assert!(head.starts_with("HTTP/1.1 400"), "{}", head);
+    }
+
+    #[tokio::test]
+    async fn test_forward_usage_endpoint() {
+        let stats_manager = StatsManager::new();
+        let usage = ForwardUsageTracker::new(ForwardLimitConfig {
+            max_bytes: Some(1024),
+            ..Default::default()
+        });
+        usage.stream_started();
+        let meter = usage.meter();
+        meter.add_sent(1000);
+        meter.add_received(24);
+        drop(meter);
+        usage.record_byte_limit_hit();
+        stats_manager.register_forward_usage("client_1".to_string(), usage);
+        let addr = start_test_server(stats_manager.clone()).await;
+
+        let (_, body) = get(addr, "/forwards", "").await;
+        let usage: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(usage[0]["client_id"], "client_1");
+        assert_eq!(usage[0]["bytes"], 1024);
+        assert_eq!(usage[0]["byte_limit_hits"], 1);
+        assert_eq!(usage[0]["limits"]["max_bytes"], 1024);
+
+        stats_manager.unregister_forward_usage("client_1");
+        let (_, body) = get(addr, "/forwards", "").await;
+        assert_eq!(body, "[]");
+    }
+
+    #[tokio::test]
+    async fn test_capacity_endpoint_and_panel() {
+        let stats_manager = StatsManager::new();
+        let tracker = stats_manager.register_proxy(
+            "web".to_string(),
+            "0.0.0.0".to_string(),
+            8080,
+            80,
+            ProxyVisibility::Public,
+        );
+        tracker.connection_started();
+        let addr = start_test_server(stats_manager).await;
+
+        let (head, body) = get(addr, "/capacity", "").await;
+        assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+        let capacity: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(capacity["sessions"]["current"], 0);
+        assert_eq!(capacity["proxies"][0]["name"], "web");
+        assert_eq!(capacity["proxies"][0]["connections"]["current"], 1);
+        assert_eq!(capacity["stream_queue"]["limit"], 100);
+        assert_eq!(capacity["yamux_streams"]["current"], 1);
+        assert!(capacity["rate_limiter"].is_null());
+        assert_eq!(capacity["handshakes"]["in_progress"]["current"], 0);
+        assert_eq!(capacity["handshakes"]["timed_out"], 0);
+        assert_eq!(
+            capacity["registry_lock"].is_null(),
+            !cfg!(feature = "lock-timing")
+        );
+        assert!(capacity["headroom"]["status"].is_string());
+
+        let (head, body) = get(addr, "/capacity.html", "").await;
+        assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+        assert!(body.contains("Stream request queue"));
+        assert!(body.contains("Proxy web (:8080)"));
+
+        let (_, dashboard) = get(addr, "/", "").await;
+        assert!(dashboard.contains(r#"href="/capacity.html""#));
+    }
+}
@@ -0,0 +1,211 @@
+/// UDP 代理的发布端口
+///
+/// `proxy_type = "udp"` 的代理在发布端口上绑定 UDP 套接字。每个外部来源地址对应一个会话
+/// （NAT 式映射）：来源的第一个数据报到达时向客户端请求一个 stream，该来源之后的数据报按帧
+/// （[`crate::datagram`]）写入同一个 stream，客户端经 stream 发回的数据报从发布端口发往该来源。
+/// 会话在两个方向都没有数据报超过 `timeouts.udp_session_idle_secs` 时结束，统计中一个会话计为
+/// 一个连接
+use super::connection::{
+    accepted_event, closed_event, drain_timeout_error, request_stream, termination_reason,
+};
+use super::events::EventExporter;
+use super::flows::{Flow, FlowExporter};
+use super::registry::{ConnectionGuard, DrainSignals, ProxyInfo, StreamRequestSender};
+use crate::accept::{AcceptBackoff, AcceptErrorKind};
+use crate::config::ServerTimeoutsConfig;
+use crate::datagram::{read_datagram, write_datagram};
+use crate::keepalive::IdleClock;
+use crate::protocol::{ProxyStreamHeader, MAX_DATAGRAM_SIZE};
+use crate::stats::ProxyStatsTracker;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::time::sleep_until;
+use tracing::{debug, error, info};
+
+/// 每个会话等待写入 stream 的数据报数，队列满时丢弃新的数据报（与 UDP 的语义一致）
+const SESSION_QUEUE: usize = 64;
+
+/// 在 UDP 代理的套接字上接收数据报并按来源地址分发到会话（主循环）
+///
+/// 会话的转发登记到 `drain`，排空超时后被关闭
+#[allow(clippy::too_many_arguments)]
+pub async fn run_udp_proxy(
+    socket: UdpSocket,
+    proxy: ProxyInfo,
+    stream_tx: StreamRequestSender,
+    tracker: ProxyStatsTracker,
+    drain: DrainSignals,
+    events: EventExporter,
+    flows: FlowExporter,
+    timeouts: ServerTimeoutsConfig,
+) -> Result<()> {
+    let socket = Arc::new(socket);
+    let mut backoff = AcceptBackoff::new(format!("UDP proxy '{}'", proxy.name));
+    let mut sessions: HashMap<SocketAddr, mpsc::Sender<Vec<u8>>> = HashMap::new();
+    let (closed_tx, mut closed_rx) = mpsc::unbounded_channel();
+    let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+
+    loop {
+        let (len, peer_addr) = tokio::select! {
+            Some(peer_addr) = closed_rx.recv() => {
+                // 同一来源可能已经开始了新的会话
+                if sessions.get(&peer_addr).is_some_and(|tx| tx.is_closed()) {
+                    sessions.remove(&peer_addr);
+                }
+                continue;
+            }
+            received = socket.recv_from(&mut buf) => match received {
+                Ok(received) => received,
+                Err(e) => {
+                    let kind = AcceptErrorKind::of_io(&e);
+                    if kind == AcceptErrorKind::Resource {
+                        tracker.record_accept_error();
+                    }
+                    if !backoff.on_error(kind, &e).await {
+                        return Err(e)
+                            .with_context(|| format!("UDP proxy '{}' socket stopped", proxy.name));
+                    }
+                    continue;
+                }
+            },
+        };
+        backoff.reset();
+        let datagram = buf[..len].to_vec();
+
+        if let Some(session) = sessions.get(&peer_addr).filter(|tx| !tx.is_closed()) {
+            if session.try_send(datagram).is_err() {
+                debug!(
+                    "UDP proxy '{}': session of {} is backed up, datagram dropped",
+                    proxy.name, peer_addr
+                );
+            }
+            continue;
+        }
+
+        let (session_tx, session_rx) = mpsc::channel(SESSION_QUEUE);
+        let _ = session_tx.try_send(datagram);
+        sessions.insert(peer_addr, session_tx);
+
+        let session = UdpSession {
+            socket: socket.clone(),
+            peer_addr,
+            stream_tx: stream_tx.clone(),
+            tracker: tracker.clone(),
+            idle: timeouts.udp_session_idle(),
+        };
+        let proxy = proxy.clone();
+        let events = events.clone();
+        let closed_tx = closed_tx.clone();
+        let relay = drain.relay();
+        let relays = drain.relays.clone();
+        events.emit(accepted_event(&proxy, peer_addr));
+        let flow = flows.proxy_flow(&proxy, peer_addr);
+
+        tokio::spawn(async move {
+            let _relay = relay;
+            let _task = crate::chaos::task("server.relay");
+            let result = tokio::select! {
+                result = session.relay(&proxy, session_rx, &flow) => result,
+                _ = relays.cancelled() => Err(drain_timeout_error(&proxy.name)),
+            };
+            if let Err(e) = &result {
+                error!("Failed to relay UDP session: {}", e);
+            }
+            flow.finish(termination_reason(&result));
+            events.emit(closed_event(proxy.name.clone(), &result));
+            let _ = closed_tx.send(peer_addr);
+        });
+    }
+}
+
+/// 一个外部来源地址的会话
+struct UdpSession {
+    socket: Arc<UdpSocket>,
+    peer_addr: SocketAddr,
+    stream_tx: StreamRequestSender,
+    tracker: ProxyStatsTracker,
+    idle: Duration,
+}
+
+impl UdpSession {
+    /// 请求 stream 并在来源与客户端之间转发数据报，直到会话空闲超时或客户端关闭 stream
+    async fn relay(
+        self,
+        proxy: &ProxyInfo,
+        mut datagrams: mpsc::Receiver<Vec<u8>>,
+        flow: &Flow,
+    ) -> Result<()> {
+        use futures::io::AsyncWriteExt;
+
+        self.tracker.connection_started();
+        let _guard = ConnectionGuard::new(self.tracker.clone());
+
+        let (mut stream, _stream_slot) =
+            request_stream(&self.stream_tx, proxy.publish_port, &proxy.name).await?;
+        let header = ProxyStreamHeader {
+            publish_port: proxy.publish_port,
+            framed: false,
+            peer: None,
+            sni_local_port: None,
+            integrity: false,
+        };
+        stream.write_all(&header.encode()).await?;
+        info!(
+            "UDP proxy '{}' opened stream for {}",
+            proxy.name, self.peer_addr
+        );
+
+        // 外部来源 → 客户端：服务器接收的数据；客户端 → 外部来源：服务器发送的数据
+        let meter = self.tracker.meter().attach(flow.traffic());
+        let clock = IdleClock::new();
+        let (mut stream_read, mut stream_write) = futures::io::AsyncReadExt::split(stream);
+
+        let to_client = async {
+            loop {
+                let deadline = clock.last_activity() + self.idle;
+                tokio::select! {
+                    datagram = datagrams.recv() => {
+                        let Some(datagram) = datagram else {
+                            break;
+                        };
+                        write_datagram(&mut stream_write, &datagram).await?;
+                        meter.add_received(datagram.len() as u64);
+                        clock.touch();
+                    }
+                    _ = sleep_until(deadline) => {
+                        if clock.last_activity() + self.idle <= tokio::time::Instant::now() {
+                            debug!(
+                                "UDP proxy '{}': session of {} idle, closing",
+                                proxy.name, self.peer_addr
+                            );
+                            break;
+                        }
+                    }
+                }
+            }
+            // 不再接收该来源的数据报，之后到达的数据报开始新的会话
+            datagrams.close();
+            stream_write.close().await
+        };
+        let to_peer = async {
+            let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+            while let Some(len) = read_datagram(&mut stream_read, &mut buf).await? {
+                self.socket.send_to(&buf[..len], self.peer_addr).await?;
+                meter.add_sent(len as u64);
+                clock.touch();
+            }
+            Ok::<_, std::io::Error>(())
+        };
+
+        tokio::select! {
+            result = to_client => result?,
+            result = to_peer => result?,
+        }
+        Ok(())
+    }
+}
@@ -1,28 +1,33 @@
-use super::registry::ProxyRegistry;
+use super::auth_keys::KeyPermissions;
+use super::connection::ExceptionNotification;
+use super::events::{ConnectionKind, EventExporter, ServerEventKind};
+use super::exceptions::ExceptionSender;
+use super::flows::FlowExporter;
+use super::forward::{ForwardEnd, ForwardLimiter, TARGET_RECENTLY_FAILED};
+use super::registry::{ProxyState, Registry, SessionStream};
+use super::visitor_acl::VisitorAccess;
 use crate::config::ServerConfig;
+use crate::control_protocol::{PeerIdMismatchData, EXCEPTION_PEER_ID_MISMATCH};
+use crate::io_util::{linger, IdleReader, IdleTimeout};
+use crate::protocol::{
+    encode_error_message, PeerIdentity, ANY_PUBLISH_PORT, CONFIRM_ACCEPTED, CONFIRM_REJECTED,
+    FORWARD_FORBIDDEN, FORWARD_NAME_PREFIX, MAX_STREAM_NAME_LEN, VISITOR_MUX_NAME_PREFIX,
+    VISITOR_MUX_STREAM_MARKER,
+};
+use crate::protocol_trace::{SessionTrace, StreamTrace, TraceDirection};
+use crate::target_addr::TargetAddr;
 use anyhow::{Context, Result};
-use std::net::{IpAddr, ToSocketAddrs};
+use std::net::IpAddr;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc};
 use tokio::time::Duration;
 use tokio_util::compat::FuturesAsyncReadCompatExt;
 use tracing::{error, info, warn};
 
-/// 服务器端读取客户端请求的超时时间（防止慢速攻击）
-const CLIENT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
-
 /// 检查目标地址是否为本地地址（禁止访问）
-fn is_local_address(target_addr: &str) -> bool {
-    // 解析地址
-    let addr_str = if target_addr.contains(':') {
-        target_addr.to_string()
-    } else {
-        format!("{}:80", target_addr) // 添加默认端口以便解析
-    };
-
+fn is_local_address(target_addr: &TargetAddr) -> bool {
     // 尝试解析域名/IP
-    match addr_str.to_socket_addrs() {
+    match target_addr.resolve() {
         Ok(addrs) => {
             for addr in addrs {
                 let ip = addr.ip();
@@ -82,27 +87,111 @@ async fn send_error_message<T>(stream: &mut T, message: &str) -> Result<()>
 where
     T: AsyncWriteExt + Unpin,
 {
-    let msg_bytes = message.as_bytes();
-    let msg_len = (msg_bytes.len() as u16).to_be_bytes();
-    stream.write_all(&msg_len).await?;
-    stream.write_all(msg_bytes).await?;
+    stream.write_all(&encode_error_message(message)).await?;
     stream.flush().await?;
     Ok(())
 }
 
+/// 拒绝 visitor stream：写入确认字节 0 和错误消息，并记录到协议跟踪
+async fn reject_stream<T>(stream: &mut T, trace: &StreamTrace, message: &str)
+where
+    T: AsyncWriteExt + Unpin,
+{
+    trace.confirm(TraceDirection::Out, false, Some(message));
+    stream.write_all(&[CONFIRM_REJECTED]).await.ok();
+    send_error_message(stream, message).await.ok();
+}
+
+/// 接受 visitor stream：写入确认字节 1，并记录到协议跟踪
+async fn accept_stream<T>(stream: &mut T, trace: &StreamTrace) -> Result<()>
+where
+    T: AsyncWriteExt + Unpin,
+{
+    trace.confirm(TraceDirection::Out, true, None);
+    stream
+        .write_all(&[CONFIRM_ACCEPTED])
+        .await
+        .context("Failed to send confirmation")?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// 发送 proxy 注册者的 peer_id（u16 长度 + 内容，长度为 0 表示未配置）
+async fn send_peer_id<T>(stream: &mut T, peer_id: Option<&str>) -> Result<()>
+where
+    T: AsyncWriteExt + Unpin,
+{
+    let id_bytes = peer_id.unwrap_or("").as_bytes();
+    let id_len = u16::try_from(id_bytes.len()).context("peer_id too long")?;
+    stream.write_all(&id_len.to_be_bytes()).await?;
+    stream.write_all(id_bytes).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// 等待代理注册，最多等待 `window`，代理已注册时立即返回
+///
+/// 先订阅注册表事件再检查，检查之后、等待之前注册的代理不会被遗漏
+async fn wait_for_proxy(
+    registry: &Registry,
+    proxy_name: &str,
+    publish_port: u16,
+    window: Duration,
+) {
+    let mut events = registry.subscribe();
+    let registered = || registry.is_registered(proxy_name, publish_port);
+    if window.is_zero() || registered() {
+        return;
+    }
+
+    info!(
+        "Proxy '{}' with publish_port {} not registered, waiting up to {:?}",
+        proxy_name, publish_port, window
+    );
+    let deadline = tokio::time::Instant::now() + window;
+    loop {
+        // 任何事件（包括落后时的 Lagged）都重新检查注册表
+        match tokio::time::timeout_at(deadline, events.recv()).await {
+            Err(_) | Ok(Err(broadcast::error::RecvError::Closed)) => return,
+            Ok(_) if registered() => return,
+            Ok(_) => {}
+        }
+    }
+}
+
 /// 处理来自客户端的 visitor stream
 /// 客户端发送目标 proxy 名称，服务器通过 yamux 连接到客户端的本地服务并转发数据
+///
+/// 客户端协商了 peer_identity 能力时，确认帧后追加 proxy 注册者的 peer_id
+/// （另协商了 client_identity 能力时再追加注册者的身份指纹），
+/// 并等待 visitor 回复 1 字节校验结果（1=接受，0=拒绝）；
+/// visitor 拒绝时通过 `exception_tx` 向其发送 PEER_ID_MISMATCH 异常通知
+///
+/// `@forward` 请求需要 `permissions` 允许 forward，并受 `forward` 中会话级的 forward 限制约束，其他请求在查找注册表之前
+/// 按 `visitor_access` 检查代理名称
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_visitor_stream(
     stream: yamux::Stream,
-    proxy_registry: ProxyRegistry,
+    proxy_registry: Registry,
     server_config: &ServerConfig,
+    peer_identity: PeerIdentity,
+    exception_tx: ExceptionSender,
+    forward: ForwardLimiter,
+    visitor_access: VisitorAccess,
+    permissions: KeyPermissions,
+    events: EventExporter,
+    flows: FlowExporter,
+    client_id: String,
+    trace: SessionTrace,
 ) -> Result<()> {
     use tokio::time::timeout;
 
+    let trace = trace.stream(stream.id());
     let mut visitor_stream = stream.compat();
+    let preamble_timeout = server_config.timeouts().preamble_read();
 
     // 使用超时包装读取操作（防止慢速攻击）
-    let (proxy_name, publish_port) = timeout(CLIENT_REQUEST_TIMEOUT, async {
+    let (proxy_name, publish_port) = timeout(preamble_timeout, async {
         // 读取目标 proxy 名称
         let mut name_len_buf = [0u8; 2];
         visitor_stream
@@ -113,13 +202,10 @@ pub async fn handle_visitor_stream(
 
         // 对于 forward 请求，允许更长的名称（包含完整域名+端口）
         // 正常代理名称限制在64字节，forward 请求限制在255字节
-        if name_len == 0 || name_len > 255 {
+        if name_len == 0 || name_len > MAX_STREAM_NAME_LEN {
             let error_msg = "Invalid proxy name length (must be 1-255 bytes)";
             error!("{}", error_msg);
-            visitor_stream.write_all(&[0]).await.ok();
-            send_error_message(&mut visitor_stream, error_msg)
-                .await
-                .ok();
+            reject_stream(&mut visitor_stream, &trace, error_msg).await;
             return Err(anyhow::anyhow!(error_msg));
         }
 
@@ -145,53 +231,225 @@ pub async fn handle_visitor_stream(
     .map_err(|_| {
         error!(
             "Visitor stream request timeout after {:?}",
-            CLIENT_REQUEST_TIMEOUT
+            preamble_timeout
         );
         anyhow::anyhow!("Client request timeout")
     })??;
+    trace.preamble(TraceDirection::In, &proxy_name, publish_port);
 
     // 检测是否为 @forward 请求
-    if let Some(target_addr) = proxy_name.strip_prefix("@forward:") {
+    if let Some(target_addr) = proxy_name.strip_prefix(FORWARD_NAME_PREFIX) {
         info!(
             "Visitor stream requesting forward to external target: '{}'",
             target_addr
         );
-        return handle_forward_request(visitor_stream, target_addr, server_config).await;
+        return handle_forward_request(
+            visitor_stream,
+            target_addr,
+            &permissions,
+            &forward,
+            &events,
+            &flows,
+            client_id,
+            &trace,
+        )
+        .await;
     }
 
+    // 复用模式：名称带 `@mux:` 前缀，stream 上承载多个子流，服务器照常转发字节
+    let (proxy_name, mux) = match proxy_name.strip_prefix(VISITOR_MUX_NAME_PREFIX) {
+        Some(name) => (name.to_string(), true),
+        None => (proxy_name, false),
+    };
+
+    let result = match visitor_access.check(&proxy_name) {
+        Ok(()) => {
+            relay_visitor_stream(
+                visitor_stream,
+                &proxy_name,
+                publish_port,
+                mux,
+                proxy_registry,
+                peer_identity,
+                exception_tx,
+                preamble_timeout,
+                server_config.visitor_retry_timeout(),
+                server_config.idle_timeout(),
+                &trace,
+            )
+            .await
+        }
+        Err(error_msg) => {
+            warn!("{}", error_msg);
+            reject_stream(&mut visitor_stream, &trace, &error_msg).await;
+            Err(anyhow::anyhow!(error_msg))
+        }
+    };
+    events.emit(ServerEventKind::ConnectionClosed {
+        kind: ConnectionKind::Visitor,
+        name: proxy_name,
+        reason: match &result {
+            Ok(()) => "closed".to_string(),
+            Err(e) => e.to_string(),
+        },
+    });
+    result
+}
+
+/// 将 visitor stream 连接到目标 proxy 所在客户端并双向转发
+///
+/// `mux` 为 true 时只选择支持 visitor 连接复用的后端，并在发往 proxy 客户端的 stream 上标记复用模式；
+/// `reply_timeout` 为等待 visitor 回复身份校验结果的超时时间；代理未注册时最多等待 `retry_window`
+/// 让其注册（例如注册者正在重连）
+#[allow(clippy::too_many_arguments)]
+async fn relay_visitor_stream<T>(
+    mut visitor_stream: T,
+    proxy_name: &str,
+    publish_port: u16,
+    mux: bool,
+    proxy_registry: Registry,
+    peer_identity: PeerIdentity,
+    exception_tx: ExceptionSender,
+    reply_timeout: Duration,
+    retry_window: Duration,
+    idle_timeout: Option<Duration>,
+    trace: &StreamTrace,
+) -> Result<()>
+where
+    T: AsyncReadExt + AsyncWriteExt + Unpin,
+{
+    use tokio::time::timeout;
+
     info!(
         "Visitor stream requesting proxy: '{}' with publish_port {}",
         proxy_name, publish_port
     );
 
-    // 从注册表查找对应的 proxy（按 name 和 publish_port 匹配）
-    let proxy_registration = {
-        let registry = proxy_registry.read().await;
-        registry.get(&(proxy_name.clone(), publish_port)).cloned()
+    // 注册者可能正在重连：先等待代理注册，超时后仍未注册时按下面的查找结果拒绝
+    wait_for_proxy(&proxy_registry, proxy_name, publish_port, retry_window).await;
+
+    // 不限发布端口时按名称匹配，同名代理注册在多个端口上时无法确定目标
+    let publish_port = if publish_port == ANY_PUBLISH_PORT {
+        match proxy_registry.lookup_by_name(proxy_name).as_slice() {
+            [proxy] => proxy.proxy_info.publish_port,
+            [] => {
+                let error_msg = format!("Proxy '{}' not found or client not connected", proxy_name);
+                error!("{}", error_msg);
+                reject_stream(&mut visitor_stream, trace, &error_msg).await;
+                return Err(anyhow::anyhow!(error_msg));
+            }
+            proxies => {
+                let ports: Vec<String> = proxies
+                    .iter()
+                    .map(|p| p.proxy_info.publish_port.to_string())
+                    .collect();
+                let error_msg = format!(
+                    "Proxy '{}' is registered on several publish ports ({}), a port must be specified",
+                    proxy_name,
+                    ports.join(", ")
+                );
+                warn!("{}", error_msg);
+                reject_stream(&mut visitor_stream, trace, &error_msg).await;
+                return Err(anyhow::anyhow!(error_msg));
+            }
+        }
+    } else {
+        publish_port
     };
 
-    let (stream_tx, local_port) = match proxy_registration {
-        Some(reg) => (reg.stream_tx, reg.proxy_info.local_port),
-        None => {
+    // 从注册表查找对应的 proxy（按 name 和 publish_port 匹配，共享代理按加权轮询选择后端）
+    // 正在排空的代理拒绝新的 visitor stream，已建立的转发登记到排空信号
+    let (backends, drain) = match proxy_registry.select_backends(proxy_name, publish_port) {
+        Some(selection) if selection.state == ProxyState::Draining => {
+            let error_msg = format!(
+                "Proxy '{}' with publish_port {} is draining and no longer accepts connections",
+                proxy_name, publish_port
+            );
+            warn!("{}", error_msg);
+            reject_stream(&mut visitor_stream, trace, &error_msg).await;
+            return Err(anyhow::anyhow!(error_msg));
+        }
+        Some(selection) => (selection.backends, Some(selection.drain)),
+        None => (Vec::new(), None),
+    };
+    let _relay = drain.as_ref().map(|drain| drain.relay());
+    let proxy_registration = backends.iter().find(|reg| !mux || reg.visitor_mux).cloned();
+
+    let (stream_tx, local_port, peer_id, identity, sni_routed) = match proxy_registration {
+        // UDP 代理的 stream 按数据报分帧，visitor 转发的是字节流
+        Some(reg) if reg.proxy_info.proxy_type.is_datagram() => {
             let error_msg = format!(
-                "Proxy '{}' with publish_port {} not found or client not connected",
+                "Proxy '{}' with publish_port {} is a udp proxy and cannot be accessed by visitors",
                 proxy_name, publish_port
             );
+            warn!("{}", error_msg);
+            reject_stream(&mut visitor_stream, trace, &error_msg).await;
+            return Err(anyhow::anyhow!(error_msg));
+        }
+        Some(reg) => (
+            reg.stream_tx,
+            reg.proxy_info.local_port,
+            reg.proxy_info.peer_id,
+            reg.proxy_info.identity,
+            reg.proxy_info.sni_routing.is_some(),
+        ),
+        None => {
+            let error_msg = if backends.is_empty() {
+                format!(
+                    "Proxy '{}' with publish_port {} not found or client not connected",
+                    proxy_name, publish_port
+                )
+            } else {
+                format!(
+                    "Proxy '{}' with publish_port {} does not support visitor connection reuse",
+                    proxy_name, publish_port
+                )
+            };
             error!("{}", error_msg);
-            visitor_stream.write_all(&[0]).await.ok();
-            send_error_message(&mut visitor_stream, &error_msg)
-                .await
-                .ok();
+            reject_stream(&mut visitor_stream, trace, &error_msg).await;
             return Err(anyhow::anyhow!(error_msg));
         }
     };
 
     // 发送确认给visitor客户端
-    visitor_stream
-        .write_all(&[1])
-        .await
-        .context("Failed to send confirmation")?;
-    visitor_stream.flush().await?;
+    accept_stream(&mut visitor_stream, trace).await?;
+
+    // 协商了 peer_identity 能力时，由 visitor 校验 proxy 注册者身份
+    if peer_identity.sends_peer_id() {
+        send_peer_id(&mut visitor_stream, peer_id.as_deref()).await?;
+        if peer_identity.sends_fingerprint() {
+            send_peer_id(&mut visitor_stream, identity.as_deref()).await?;
+        }
+
+        let mut verdict = [0u8; 1];
+        timeout(reply_timeout, visitor_stream.read_exact(&mut verdict))
+            .await
+            .map_err(|_| anyhow::anyhow!("Timeout waiting for peer identity verdict"))?
+            .context("Failed to read peer identity verdict")?;
+
+        if verdict[0] != 1 {
+            let error_msg = format!(
+                "Visitor refused proxy '{}' with publish_port {}: peer identity mismatch (registered peer_id: {})",
+                proxy_name,
+                publish_port,
+                peer_id.as_deref().unwrap_or("<none>")
+            );
+            warn!("{}", error_msg);
+            exception_tx.send(ExceptionNotification {
+                level: "error".to_string(),
+                message: error_msg.clone(),
+                code: Some(EXCEPTION_PEER_ID_MISMATCH.to_string()),
+                data: serde_json::to_value(PeerIdMismatchData {
+                    proxy_name: proxy_name.to_string(),
+                    publish_port,
+                    registered_peer_id: peer_id.clone(),
+                    registered_identity: identity.clone(),
+                })
+                .ok(),
+            });
+            return Err(anyhow::anyhow!(error_msg));
+        }
+    }
 
     info!(
         "Visitor stream confirmed for proxy '{}', requesting connection to target client local port {}",
@@ -199,15 +457,15 @@ pub async fn handle_visitor_stream(
     );
 
     // 请求目标客户端创建到其本地服务的 yamux stream
-    let (response_tx, mut response_rx) = mpsc::channel::<yamux::Stream>(1);
+    let (response_tx, mut response_rx) = mpsc::channel::<SessionStream>(1);
 
     stream_tx
-        .send((response_tx, local_port, proxy_name.clone()))
+        .send((response_tx, publish_port, proxy_name.to_string()))
         .await
         .context("Failed to request yamux stream from target client")?;
 
-    // 等待目标客户端返回 yamux stream
-    let mut client_stream = response_rx
+    // 等待目标客户端返回 yamux stream（目标会话的 stream 数已达上限时请求被拒绝）
+    let (mut client_stream, _stream_slot) = response_rx
         .recv()
         .await
         .ok_or_else(|| anyhow::anyhow!("Failed to receive yamux stream from target client"))?;
@@ -217,9 +475,19 @@ pub async fn handle_visitor_stream(
         local_port
     );
 
-    // 向客户端B的 stream 写入 publish_port（客户端需要通过此端口找到对应的 proxy 配置）
+    // 向客户端B的 stream 写入 publish_port（客户端需要通过此端口找到对应的 proxy 配置），
+    // 复用模式先写入标记
     use futures::io::AsyncWriteExt as FuturesAsyncWriteExt;
+    if mux {
+        client_stream
+            .write_all(&VISITOR_MUX_STREAM_MARKER.to_be_bytes())
+            .await?;
+    }
     client_stream.write_all(&publish_port.to_be_bytes()).await?;
+    // SNI 路由代理的非复用 stream 还需要本地端口，visitor 连接使用代理的 local_port
+    if sni_routed && !mux {
+        client_stream.write_all(&0u16.to_be_bytes()).await?;
+    }
     client_stream.flush().await?;
 
     info!("Sent publish_port {} to target client", publish_port);
@@ -227,8 +495,12 @@ pub async fn handle_visitor_stream(
     let client_stream_tokio = client_stream.compat();
 
     // 双向转发数据：visitor客户端 ↔ 服务器 ↔ proxy客户端
-    let (mut visitor_read, mut visitor_write) = tokio::io::split(visitor_stream);
-    let (mut client_read, mut client_write) = tokio::io::split(client_stream_tokio);
+    // 复用的 stream 承载多个连接，由 visitor 客户端管理，不受空闲超时限制
+    let idle = idle_timeout.filter(|_| !mux).map(IdleTimeout::new);
+    let (visitor_read, mut visitor_write) = tokio::io::split(visitor_stream);
+    let (client_read, mut client_write) = tokio::io::split(client_stream_tokio);
+    let mut visitor_read = IdleReader::new(visitor_read, idle.as_ref());
+    let mut client_read = IdleReader::new(client_read, idle.as_ref());
 
     let visitor_to_client = async {
         tokio::io::copy(&mut visitor_read, &mut client_write).await?;
@@ -242,16 +514,37 @@ pub async fn handle_visitor_stream(
         Ok::<_, std::io::Error>(())
     };
 
-    tokio::select! {
-        result = visitor_to_client => {
-            if let Err(e) = result {
-                warn!("Visitor '{}': Visitor to target client copy error: {}", proxy_name, e);
-            }
+    // 代理排空超时后关闭转发
+    let drain_timeout = async {
+        match &drain {
+            Some(drain) => drain.relays.cancelled().await,
+            None => std::future::pending().await,
         }
-        result = client_to_visitor => {
-            if let Err(e) = result {
-                warn!("Visitor '{}': Target client to visitor copy error: {}", proxy_name, e);
-            }
+    };
+
+    // 一个方向结束（已关闭写方向）后，等待另一方向读到对端的 EOF 再丢弃两端的 stream
+    let relay = async {
+        tokio::pin!(visitor_to_client, client_to_visitor);
+        tokio::select! {
+            result = &mut visitor_to_client => match result {
+                Ok(()) => {
+                    linger(&mut client_to_visitor).await;
+                }
+                Err(e) => warn!("Visitor '{}': Visitor to target client copy error: {}", proxy_name, e),
+            },
+            result = &mut client_to_visitor => match result {
+                Ok(()) => {
+                    linger(&mut visitor_to_client).await;
+                }
+                Err(e) => warn!("Visitor '{}': Target client to visitor copy error: {}", proxy_name, e),
+            },
+        }
+    };
+
+    tokio::select! {
+        _ = relay => {}
+        _ = drain_timeout => {
+            return Err(super::connection::drain_timeout_error(proxy_name));
         }
     }
 
@@ -260,98 +553,155 @@ pub async fn handle_visitor_stream(
 }
 
 /// 处理 forward 请求（连接到外部目标）
+#[allow(clippy::too_many_arguments)]
 async fn handle_forward_request<T>(
     mut visitor_stream: T,
     target_addr: &str,
-    server_config: &ServerConfig,
+    permissions: &KeyPermissions,
+    forward: &ForwardLimiter,
+    events: &EventExporter,
+    flows: &FlowExporter,
+    client_id: String,
+    trace: &StreamTrace,
 ) -> Result<()>
 where
     T: AsyncReadExt + AsyncWriteExt + Unpin,
 {
-    // 检查服务器是否允许 forward 功能
-    if !server_config.allow_forward {
-        let error_msg = "Forward feature is not enabled on server";
+    let target = target_addr.to_string();
+    let forward_event = |allowed: bool, reason: Option<String>| ServerEventKind::ForwardRequested {
+        client_id: client_id.clone(),
+        target: target.clone(),
+        allowed,
+        reason,
+    };
+
+    // 检查服务器（或认证所用的密钥）是否允许 forward 功能
+    if let Err(error_msg) = permissions.check_forward() {
         error!("{}", error_msg);
-        visitor_stream.write_all(&[0]).await.ok();
-        send_error_message(&mut visitor_stream, error_msg)
-            .await
-            .ok();
+        events.emit(forward_event(false, Some(error_msg.clone())));
+        reject_stream(&mut visitor_stream, trace, &error_msg).await;
+        return Err(anyhow::anyhow!(error_msg));
+    }
+
+    // 解析目标地址（客户端发送规范格式，IPv6 带方括号）
+    let target_addr = match TargetAddr::parse(target_addr) {
+        Ok(target) => target,
+        Err(e) => {
+            let error_msg = format!("Invalid forward target '{}': {}", target_addr, e);
+            error!("{}", error_msg);
+            events.emit(forward_event(false, Some(error_msg.clone())));
+            reject_stream(&mut visitor_stream, trace, &error_msg).await;
+            return Err(anyhow::anyhow!(error_msg));
+        }
+    };
+
+    // 配置了 forward_policy 时按策略检查解析出的地址，连接时只使用这些地址；
+    // 否则禁止访问本地地址和内网地址
+    let checked_addrs = match forward.policy() {
+        Some(policy) => {
+            let checked = target_addr
+                .resolve()
+                .map_err(|e| format!("failed to resolve target: {}", e))
+                .and_then(|addrs| policy.check(&target_addr, &addrs).map(|()| addrs));
+            match checked {
+                Ok(addrs) => Some(addrs),
+                Err(reason) => {
+                    let error_msg = format!(
+                        "{}: forward to '{}' denied by forward_policy: {}",
+                        FORWARD_FORBIDDEN, target_addr, reason
+                    );
+                    warn!("{}", error_msg);
+                    events.emit(forward_event(false, Some(error_msg.clone())));
+                    reject_stream(&mut visitor_stream, trace, &error_msg).await;
+                    return Err(anyhow::anyhow!(error_msg));
+                }
+            }
+        }
+        None => {
+            if is_local_address(&target_addr) {
+                let error_msg = format!(
+                    "Access denied: cannot forward to local or private address '{}'",
+                    target_addr
+                );
+                error!("{}", error_msg);
+                events.emit(forward_event(false, Some(error_msg.clone())));
+                reject_stream(&mut visitor_stream, trace, &error_msg).await;
+                return Err(anyhow::anyhow!(error_msg));
+            }
+            None
+        }
+    };
+
+    // 同一会话最近连接失败的目标直接拒绝，不再占用服务器等待连接超时
+    if let Some(retry_after) = forward.recently_failed(&target) {
+        let error_msg = format!(
+            "{}: forward to '{}' rejected: target failed recently, retry in {}s",
+            TARGET_RECENTLY_FAILED,
+            target_addr,
+            retry_after.as_secs().max(1)
+        );
+        warn!("{}", error_msg);
+        events.emit(forward_event(false, Some(error_msg.clone())));
+        reject_stream(&mut visitor_stream, trace, &error_msg).await;
         return Err(anyhow::anyhow!(error_msg));
     }
 
-    // 安全检查：禁止访问本地地址和内网地址
-    if is_local_address(target_addr) {
+    // 会话的并发 forward 连接数已达上限
+    let Some(_permit) = forward.try_acquire(&target) else {
         let error_msg = format!(
-            "Access denied: cannot forward to local or private address '{}'",
+            "Forward to '{}' rejected: too many concurrent forward connections",
             target_addr
         );
-        error!("{}", error_msg);
-        visitor_stream.write_all(&[0]).await.ok();
-        send_error_message(&mut visitor_stream, &error_msg)
-            .await
-            .ok();
+        events.emit(forward_event(false, Some(error_msg.clone())));
+        reject_stream(&mut visitor_stream, trace, &error_msg).await;
         return Err(anyhow::anyhow!(error_msg));
-    }
+    };
 
     info!("Attempting to connect to external target: {}", target_addr);
 
-    // 连接到外部目标
-    let external_stream = match TcpStream::connect(target_addr).await {
+    // 连接到外部目标（有超时，同时在连接中的目标数受限）
+    let connected = match checked_addrs {
+        Some(ref addrs) => forward.connect_addrs(&target_addr, addrs).await,
+        None => forward.connect(&target_addr).await,
+    };
+    let external_stream = match connected {
         Ok(stream) => stream,
         Err(e) => {
             let error_msg = format!("Failed to connect to {}: {}", target_addr, e);
             error!("{}", error_msg);
-            visitor_stream.write_all(&[0]).await.ok();
-            send_error_message(&mut visitor_stream, &error_msg)
-                .await
-                .ok();
+            events.emit(forward_event(false, Some(error_msg.clone())));
+            reject_stream(&mut visitor_stream, trace, &error_msg).await;
             return Err(anyhow::anyhow!(error_msg));
         }
     };
 
     info!("Successfully connected to external target: {}", target_addr);
+    events.emit(forward_event(true, None));
 
     // 发送确认给 visitor 客户端
-    visitor_stream
-        .write_all(&[1])
-        .await
-        .context("Failed to send confirmation")?;
-    visitor_stream.flush().await?;
+    accept_stream(&mut visitor_stream, trace).await?;
 
     info!(
         "Forward connection confirmed, starting bidirectional data transfer with {}",
         target_addr
     );
 
-    // 双向转发数据：visitor客户端 ↔ 服务器 ↔ 外部目标
-    let (mut visitor_read, mut visitor_write) = tokio::io::split(visitor_stream);
-    let (mut external_read, mut external_write) = tokio::io::split(external_stream);
-
-    let visitor_to_external = async {
-        tokio::io::copy(&mut visitor_read, &mut external_write).await?;
-        external_write.shutdown().await?;
-        Ok::<_, std::io::Error>(())
-    };
-
-    let external_to_visitor = async {
-        tokio::io::copy(&mut external_read, &mut visitor_write).await?;
-        visitor_write.shutdown().await?;
-        Ok::<_, std::io::Error>(())
+    // 双向转发数据：visitor客户端 ↔ 服务器 ↔ 外部目标（受 forward 限制约束）
+    let flow = flows.forward_flow(&target);
+    let reason = match forward
+        .relay(visitor_stream, external_stream, &target, flow.traffic())
+        .await
+    {
+        ForwardEnd::Closed(reason) => reason,
+        ForwardEnd::LimitExceeded(limit) => format!("forward limit exceeded: {}", limit.as_str()),
     };
-
-    tokio::select! {
-        result = visitor_to_external => {
-            if let Err(e) = result {
-                warn!("Forward '{}': Visitor to external copy error: {}", target_addr, e);
-            }
-        }
-        result = external_to_visitor => {
-            if let Err(e) = result {
-                warn!("Forward '{}': External to visitor copy error: {}", target_addr, e);
-            }
-        }
-    }
+    flow.finish(reason.clone());
 
     info!("Forward connection to '{}' closed", target_addr);
+    events.emit(ServerEventKind::ConnectionClosed {
+        kind: ConnectionKind::Forward,
+        name: target,
+        reason,
+    });
     Ok(())
 }
@@ -0,0 +1,270 @@
+/// Visitor 访问控制
+///
+/// 服务器配置的 `visitor_acl` 按客户端身份限制 visitor 可以访问的代理名称。
+/// 会话认证后解析出本会话适用的规则（[`VisitorAccess`]），visitor stream 在查找注册表之前
+/// 按名称检查，被拒绝的请求以 `VISITOR_FORBIDDEN` 开头的错误消息拒绝，并按客户端计数
+use super::registry::{ProxyState, Registry};
+use crate::config::{ProxyVisibility, VisitorAclConfig};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// 拒绝不允许访问的代理时错误消息使用的代码
+pub const VISITOR_FORBIDDEN: &str = "VISITOR_FORBIDDEN";
+
+/// 代理名称的 glob 匹配（`*` 匹配任意个字符，`?` 匹配一个字符）
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // 最近一个 `*` 的位置及其当前匹配到的名称位置，失配时回溯到这里多吞一个字符
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// 一组规则是否允许访问 `proxy_name`（deny 优先，未设置 allow 时允许）
+fn permits(allow: Option<&[String]>, deny: &[String], proxy_name: &str) -> bool {
+    if deny.iter().any(|pattern| glob_match(pattern, proxy_name)) {
+        return false;
+    }
+    allow.is_none_or(|allow| allow.iter().any(|pattern| glob_match(pattern, proxy_name)))
+}
+
+/// 服务器的 visitor 访问控制：配置的规则和各客户端被拒绝的次数
+#[derive(Clone, Default)]
+pub struct VisitorAcl {
+    config: Option<Arc<VisitorAclConfig>>,
+    denials: Arc<Mutex<BTreeMap<String, u64>>>,
+}
+
+impl VisitorAcl {
+    /// 从配置创建（未配置时不限制访问）
+    pub fn from_config(config: Option<&VisitorAclConfig>) -> Self {
+        Self {
+            config: config.cloned().map(Arc::new),
+            denials: Default::default(),
+        }
+    }
+
+    /// 会话适用的访问规则
+    ///
+    /// `client` 为拒绝计数使用的客户端标识（身份指纹，否则为 peer_id 或会话的 client_id）
+    pub fn for_client(&self, identity: Option<&str>, client: String) -> VisitorAccess {
+        let (allow, deny) = match &self.config {
+            Some(config) => {
+                let (allow, deny) = config.rules_for(identity);
+                (allow.map(<[String]>::to_vec), deny.to_vec())
+            }
+            None => (None, Vec::new()),
+        };
+        VisitorAccess {
+            allow,
+            deny,
+            client,
+            denials: self.denials.clone(),
+        }
+    }
+
+    /// 各客户端被拒绝的 visitor 请求数
+    pub fn denials(&self) -> BTreeMap<String, u64> {
+        self.denials.lock().clone()
+    }
+
+    /// 可以访问 `proxy_name` 的客户端
+    fn access_for(&self, proxy_name: &str) -> ProxyAccess {
+        match &self.config {
+            Some(config) => ProxyAccess {
+                default_rules: permits(
+                    config.visitor_allow.as_deref(),
+                    &config.visitor_deny,
+                    proxy_name,
+                ),
+                clients: config
+                    .clients
+                    .iter()
+                    .filter(|c| permits(c.visitor_allow.as_deref(), &c.visitor_deny, proxy_name))
+                    .map(|c| c.identity.clone())
+                    .collect(),
+            },
+            None => ProxyAccess {
+                default_rules: true,
+                clients: Vec::new(),
+            },
+        }
+    }
+
+    /// 注册表中的代理及可以通过 visitor 访问它们的客户端（`/registry`）
+    pub fn registry_listing(&self, registry: &Registry) -> RegistryListing {
+        let proxies = registry
+            .list()
+            .into_iter()
+            .map(|snapshot| {
+                let visitor_access = self.access_for(&snapshot.proxy_info.name);
+                RegistryEntry {
+                    name: snapshot.proxy_info.name,
+                    publish_port: snapshot.proxy_info.publish_port,
+                    visibility: snapshot.proxy_info.visibility,
+                    state: snapshot.state,
+                    backends: snapshot.backends,
+                    peer_id: snapshot.proxy_info.peer_id,
+                    identity: snapshot.proxy_info.identity,
+                    visitor_access,
+                }
+            })
+            .collect();
+        RegistryListing {
+            acl_configured: self.config.is_some(),
+            proxies,
+            visitor_denials: self.denials(),
+        }
+    }
+}
+
+/// 单个会话的 visitor 访问规则（默认不限制）
+#[derive(Clone, Default)]
+pub struct VisitorAccess {
+    allow: Option<Vec<String>>,
+    deny: Vec<String>,
+    client: String,
+    denials: Arc<Mutex<BTreeMap<String, u64>>>,
+}
+
+impl VisitorAccess {
+    /// 是否允许访问 `proxy_name`
+    pub fn permits(&self, proxy_name: &str) -> bool {
+        permits(self.allow.as_deref(), &self.deny, proxy_name)
+    }
+
+    /// 检查访问权限，拒绝时计数并返回发给 visitor 的错误消息
+    pub fn check(&self, proxy_name: &str) -> Result<(), String> {
+        if self.permits(proxy_name) {
+            return Ok(());
+        }
+        *self.denials.lock().entry(self.client.clone()).or_default() += 1;
+        Err(format!(
+            "{}: client '{}' is not allowed to access proxy '{}'",
+            VISITOR_FORBIDDEN, self.client, proxy_name
+        ))
+    }
+}
+
+/// 可以访问某个代理的客户端
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProxyAccess {
+    /// 使用默认规则的客户端（身份未单独配置）是否可以访问
+    pub default_rules: bool,
+    /// 单独配置了规则且可以访问的客户端身份指纹
+    pub clients: Vec<String>,
+}
+
+/// `/registry` 中的单个代理
+#[derive(Debug, Clone, Serialize)]
+pub struct RegistryEntry {
+    pub name: String,
+    pub publish_port: u16,
+    pub visibility: ProxyVisibility,
+    pub state: ProxyState,
+    /// 后端数
+    pub backends: usize,
+    /// 注册者的 peer_id
+    pub peer_id: Option<String>,
+    /// 注册者的身份指纹
+    pub identity: Option<String>,
+    /// 可以通过 visitor 访问该代理的客户端
+    pub visitor_access: ProxyAccess,
+}
+
+/// `/registry` 的响应
+#[derive(Debug, Clone, Serialize)]
+pub struct RegistryListing {
+    /// 是否配置了 `visitor_acl`（未配置时所有客户端都可以访问所有代理）
+    pub acl_configured: bool,
+    pub proxies: Vec<RegistryEntry>,
+    /// 各客户端被 `VISITOR_FORBIDDEN` 拒绝的 visitor 请求数
+    pub visitor_denials: BTreeMap<String, u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ClientVisitorAclConfig;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("db-*", "db-primary"));
+        assert!(glob_match("db-*", "db-"));
+        assert!(!glob_match("db-*", "web-admin"));
+        assert!(glob_match("*-admin", "web-admin"));
+        assert!(glob_match("db-?", "db-1"));
+        assert!(!glob_match("db-?", "db-12"));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(!glob_match("a*b*c", "axxbyy"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("exact", "exact"));
+        assert!(!glob_match("exact", "exact-not"));
+    }
+
+    fn acl() -> VisitorAcl {
+        VisitorAcl::from_config(Some(&VisitorAclConfig {
+            visitor_allow: Some(vec!["public-*".to_string()]),
+            visitor_deny: vec![],
+            clients: vec![ClientVisitorAclConfig {
+                identity: "SHA256:AA".to_string(),
+                visitor_allow: Some(vec!["db-*".to_string()]),
+                visitor_deny: vec!["db-secret".to_string()],
+            }],
+        }))
+    }
+
+    #[test]
+    fn test_client_rules_replace_default_rules() {
+        let acl = acl();
+        let client = acl.for_client(Some("sha256:aa"), "SHA256:AA".to_string());
+        assert!(client.permits("db-primary"));
+        assert!(!client.permits("db-secret"));
+        assert!(!client.permits("public-web"));
+
+        let other = acl.for_client(None, "client_1".to_string());
+        assert!(other.permits("public-web"));
+        assert!(!other.permits("db-primary"));
+
+        let unrestricted = VisitorAcl::default().for_client(None, "client_1".to_string());
+        assert!(unrestricted.permits("anything"));
+    }
+
+    #[test]
+    fn test_denials_are_counted_per_client() {
+        let acl = acl();
+        let client = acl.for_client(Some("SHA256:AA"), "SHA256:AA".to_string());
+        let error = client.check("web-admin").unwrap_err();
+        assert!(error.starts_with(VISITOR_FORBIDDEN), "{}", error);
+        assert!(client.check("db-primary").is_ok());
+        client.check("db-secret").unwrap_err();
+        assert_eq!(acl.denials().get("SHA256:AA"), Some(&2));
+
+        let access = acl.access_for("db-primary");
+        assert!(!access.default_rules);
+        assert_eq!(access.clients, vec!["SHA256:AA".to_string()]);
+    }
+}
@@ -0,0 +1,86 @@
+/// 进程退出信号
+///
+/// Unix 下 SIGINT（Ctrl+C）、SIGTERM（`systemctl stop`、`kill`）和 SIGQUIT 都触发优雅停止，
+/// 其他平台只响应 Ctrl+C
+use std::io;
+
+/// 已注册的退出信号监听器
+///
+/// 创建时立即注册信号处理，之后到达的信号不会再按默认行为直接终止进程
+pub struct ShutdownSignals {
+    #[cfg(unix)]
+    interrupt: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    quit: tokio::signal::unix::Signal,
+}
+
+impl ShutdownSignals {
+    /// 注册退出信号处理（需要在 tokio 运行时中调用）
+    #[cfg(unix)]
+    pub fn new() -> io::Result<Self> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        Ok(Self {
+            interrupt: signal(SignalKind::interrupt())?,
+            terminate: signal(SignalKind::terminate())?,
+            quit: signal(SignalKind::quit())?,
+        })
+    }
+
+    /// 注册退出信号处理（需要在 tokio 运行时中调用）
+    #[cfg(not(unix))]
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {})
+    }
+
+    /// 等待下一个退出信号，返回信号名称
+    #[cfg(unix)]
+    pub async fn recv(&mut self) -> &'static str {
+        tokio::select! {
+            _ = self.interrupt.recv() => "SIGINT",
+            _ = self.terminate.recv() => "SIGTERM",
+            _ = self.quit.recv() => "SIGQUIT",
+        }
+    }
+
+    /// 等待下一个退出信号，返回信号名称
+    #[cfg(not(unix))]
+    pub async fn recv(&mut self) -> &'static str {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+        "Ctrl+C"
+    }
+}
+
+/// 等待任意一个退出信号，返回信号名称
+pub async fn shutdown_signal() -> io::Result<&'static str> {
+    Ok(ShutdownSignals::new()?.recv().await)
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    async fn raise(signal: libc::c_int) -> &'static str {
+        let mut signals = ShutdownSignals::new().unwrap();
+        unsafe {
+            libc::kill(libc::getpid(), signal);
+        }
+        tokio::time::timeout(std::time::Duration::from_secs(5), signals.recv())
+            .await
+            .expect("Signal was not delivered")
+    }
+
+    #[tokio::test]
+    async fn test_sigterm_triggers_shutdown() {
+        assert_eq!(raise(libc::SIGTERM).await, "SIGTERM");
+    }
+
+    #[tokio::test]
+    async fn test_sigquit_triggers_shutdown() {
+        assert_eq!(raise(libc::SIGQUIT).await, "SIGQUIT");
+    }
+}
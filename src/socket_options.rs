@@ -0,0 +1,114 @@
+/// TCP 套接字选项
+///
+/// 按 [`SocketOptionsConfig`] 设置 TCP_NODELAY、收发缓冲区大小、keepalive 和 TCP_USER_TIMEOUT，
+/// 用于发布端口、本地服务、visitor/forwarder 接入以及直连目标的连接
+use crate::config::SocketOptionsConfig;
+use socket2::{SockRef, TcpKeepalive};
+use std::io;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tracing::warn;
+
+/// 在连接上应用套接字选项
+pub fn apply(stream: &TcpStream, options: &SocketOptionsConfig) -> io::Result<()> {
+    stream.set_nodelay(options.nodelay)?;
+    let sock = SockRef::from(stream);
+    if let Some(size) = options.send_buffer_bytes {
+        sock.set_send_buffer_size(size as usize)?;
+    }
+    if let Some(size) = options.recv_buffer_bytes {
+        sock.set_recv_buffer_size(size as usize)?;
+    }
+    if let Some(secs) = options.keepalive_secs {
+        let idle = Duration::from_secs(secs);
+        sock.set_tcp_keepalive(&TcpKeepalive::new().with_time(idle).with_interval(idle))?;
+    }
+    if let Some(ms) = options.user_timeout_ms {
+        set_user_timeout(&sock, Duration::from_millis(ms.into()))?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_user_timeout(sock: &SockRef<'_>, timeout: Duration) -> io::Result<()> {
+    sock.set_tcp_user_timeout(Some(timeout))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_user_timeout(_sock: &SockRef<'_>, _timeout: Duration) -> io::Result<()> {
+    // 配置校验已拒绝该选项，这里兜底返回明确的错误
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "socket.user_timeout_ms is only supported on Linux",
+    ))
+}
+
+/// 应用套接字选项（未配置时使用默认值），失败时只记录警告
+pub fn configure(stream: &TcpStream, options: Option<&SocketOptionsConfig>, context: &str) {
+    let default = SocketOptionsConfig::default();
+    if let Err(e) = apply(stream, options.unwrap_or(&default)) {
+        warn!("{}: failed to apply socket options: {}", context, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    async fn connected_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (client, server)
+    }
+
+    #[tokio::test]
+    async fn test_options_applied_to_socket() {
+        let (stream, _peer) = connected_pair().await;
+        let options = SocketOptionsConfig {
+            nodelay: false,
+            send_buffer_bytes: Some(8 * 1024),
+            recv_buffer_bytes: Some(16 * 1024),
+            keepalive_secs: Some(45),
+            user_timeout_ms: None,
+        };
+        apply(&stream, &options).unwrap();
+
+        let sock = SockRef::from(&stream);
+        assert!(!stream.nodelay().unwrap());
+        // Linux 为内核簿记预留空间，读回的值是设置值的两倍
+        let factor = if cfg!(target_os = "linux") { 2 } else { 1 };
+        assert_eq!(sock.send_buffer_size().unwrap(), 8 * 1024 * factor);
+        assert_eq!(sock.recv_buffer_size().unwrap(), 16 * 1024 * factor);
+        assert!(sock.keepalive().unwrap());
+        assert_eq!(sock.tcp_keepalive_time().unwrap(), Duration::from_secs(45));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_user_timeout_applied_to_socket() {
+        let (stream, _peer) = connected_pair().await;
+        let options = SocketOptionsConfig {
+            user_timeout_ms: Some(20_000),
+            ..Default::default()
+        };
+        apply(&stream, &options).unwrap();
+        assert_eq!(
+            SockRef::from(&stream).tcp_user_timeout().unwrap(),
+            Some(Duration::from_secs(20))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_default_enables_nodelay() {
+        let (stream, _peer) = connected_pair().await;
+        assert!(!stream.nodelay().unwrap());
+        configure(&stream, None, "test");
+        assert!(stream.nodelay().unwrap());
+        // 未配置 keepalive 时保持系统默认（不启用）
+        assert!(!SockRef::from(&stream).keepalive().unwrap());
+    }
+}
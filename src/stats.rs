@@ -1,30 +1,582 @@
+use crate::config::{ForwardLimitConfig, ProxyVisibility};
+use crate::control_protocol::{ForwardReport, ForwardReportParams};
+use crate::integrity::IntegrityCounters;
+use crate::relay_memory::RelayMemory;
+use crate::stats_http::StatsQuery;
+use crate::traffic::{TrafficMeter, TrafficScope};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
-/// Statistics for a single proxy
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ProxyStats {
+/// Bytes a relay direction accumulates locally before flushing to its [`TrafficMeter`]
+pub const STATS_FLUSH_BYTES: u64 = 256 * 1024;
+
+/// Number of stripes in a [`ShardedCounter`]
+const COUNTER_SHARDS: usize = 16;
+
+/// Address the client forwards server-published connections to
+const CLIENT_TARGET_ADDR: &str = "127.0.0.1";
+
+/// Byte-count bucket size of [`StatsFingerprint`]: traffic below this does not change the ETag
+pub const STATS_ETAG_BYTE_BUCKET: u64 = 64 * 1024;
+
+/// Cheap change detector for stats responses, used as the HTTP ETag
+///
+/// Combines a generation counter bumped on registration changes with connection
+/// counts, state changes and the total byte count rounded down to
+/// [`STATS_ETAG_BYTE_BUCKET`]; reading it never builds snapshots.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StatsFingerprint {
+    generation: u64,
+    total_connections: u64,
+    active_connections: u64,
+    states: u64,
+    bytes: u64,
+}
+
+impl StatsFingerprint {
+    pub fn new(generation: u64) -> Self {
+        Self {
+            generation,
+            ..Default::default()
+        }
+    }
+
+    /// Fold one tracker's counters in
+    pub fn add(&mut self, total_connections: u64, active_connections: u64, state: u64, bytes: u64) {
+        self.total_connections = self.total_connections.wrapping_add(total_connections);
+        self.active_connections = self.active_connections.wrapping_add(active_connections);
+        self.states = self.states.wrapping_add(state);
+        self.bytes = self.bytes.wrapping_add(bytes);
+    }
+
+    /// Quoted ETag value
+    pub fn etag(&self) -> String {
+        format!(
+            "\"{:x}-{:x}-{:x}-{:x}-{:x}\"",
+            self.generation,
+            self.total_connections,
+            self.active_connections,
+            self.states,
+            self.bytes / STATS_ETAG_BYTE_BUCKET
+        )
+    }
+}
+
+/// Atomic counter padded to its own cache line
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct PaddedCounter(AtomicU64);
+
+/// Byte counter striped across cache lines to avoid contention between threads
+///
+/// Each thread adds to its own stripe; readers sum all stripes on snapshot.
+#[derive(Debug)]
+pub struct ShardedCounter {
+    shards: Box<[PaddedCounter]>,
+}
+
+impl ShardedCounter {
+    pub fn new() -> Self {
+        Self {
+            shards: (0..COUNTER_SHARDS)
+                .map(|_| PaddedCounter::default())
+                .collect(),
+        }
+    }
+
+    /// Add to the calling thread's stripe
+    pub fn add(&self, value: u64) {
+        self.shards[shard_index()]
+            .0
+            .fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// Sum of all stripes
+    pub fn sum(&self) -> u64 {
+        self.shards
+            .iter()
+            .map(|shard| shard.0.load(Ordering::Relaxed))
+            .fold(0u64, u64::wrapping_add)
+    }
+
+    /// Reset all stripes to zero
+    pub fn reset(&self) {
+        for shard in self.shards.iter() {
+            shard.0.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for ShardedCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Stripe assigned to the current thread (round-robin on first use)
+fn shard_index() -> usize {
+    static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed) % COUNTER_SHARDS;
+    }
+    SHARD.with(|shard| *shard)
+}
+
+/// Connection-local byte accumulator
+///
+/// Relay loops add every chunk here and only touch the shared counters when
+/// a batch reaches the threshold, plus once more with the remainder at
+/// connection end. Shared totals therefore lag by less than the threshold
+/// per direction and are exact once the connection is closed.
+#[derive(Debug)]
+pub struct PendingBytes {
+    pending: u64,
+    threshold: u64,
+}
+
+impl PendingBytes {
+    pub fn new(threshold: u64) -> Self {
+        Self {
+            pending: 0,
+            threshold,
+        }
+    }
+
+    /// Accumulate `bytes`, returning the batch to flush once the threshold is reached
+    pub fn add(&mut self, bytes: u64) -> Option<u64> {
+        self.pending += bytes;
+        if self.pending >= self.threshold {
+            self.take()
+        } else {
+            None
+        }
+    }
+
+    /// Take whatever has not been flushed yet
+    pub fn take(&mut self) -> Option<u64> {
+        match std::mem::take(&mut self.pending) {
+            0 => None,
+            bytes => Some(bytes),
+        }
+    }
+}
+
+impl Default for PendingBytes {
+    fn default() -> Self {
+        Self::new(STATS_FLUSH_BYTES)
+    }
+}
+
+/// Which side of the tunnel a stats entry was reported by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsRole {
+    #[default]
+    Server,
+    Client,
+}
+
+/// Fields shared by server and client proxy statistics
+///
+/// `listen_*` is where connections for the proxy are accepted (the server's
+/// published endpoint, or the client's local bind) and `target_*` is where
+/// they are forwarded to.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProxyStatsCore {
     /// Proxy name
     pub name: String,
-    /// Server published address
-    pub publish_addr: String,
-    /// Server published port
-    pub publish_port: u16,
-    /// Client local port
-    pub local_port: u16,
+    /// Side of the tunnel that reported these stats
+    #[serde(default)]
+    pub role: StatsRole,
+    /// Address accepting connections
+    #[serde(default)]
+    pub listen_addr: String,
+    /// Port accepting connections
+    #[serde(default)]
+    pub listen_port: u16,
+    /// Address connections are forwarded to
+    #[serde(default)]
+    pub target_addr: String,
+    /// Port connections are forwarded to
+    #[serde(default)]
+    pub target_port: u16,
     /// Total number of connections
     pub total_connections: u64,
     /// Currently active connections
     pub active_connections: u64,
-    /// Total bytes sent to client
+    /// Total bytes sent towards the connecting peer
     pub bytes_sent: u64,
-    /// Total bytes received from client
+    /// Total bytes received from the connecting peer
     pub bytes_received: u64,
     /// Timestamp when this proxy was registered (Unix timestamp)
     pub start_time: u64,
+    /// Current state (`Idle`, `Connected`, ...)
+    #[serde(default)]
+    pub status: String,
+    /// Accept failures on the listener caused by resource exhaustion (EMFILE etc.)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub accept_errors: u64,
+    /// Relay directions whose data matched the peer's integrity trailer (`integrity_check`)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub integrity_verified: u64,
+    /// Relay directions whose data did NOT match the peer's integrity trailer
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub integrity_mismatches: u64,
+    /// Connections closed right after accept because the proxy was at `max_connections_per_proxy`
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub connection_limited: u64,
+}
+
+/// Statistics for a single proxy
+///
+/// Serialized with the [`ProxyStatsCore`] fields flattened in, plus the
+/// pre-unification `publish_addr`/`publish_port`/`local_port` names which are
+/// still emitted and accepted for compatibility.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "ProxyStatsRepr", from = "ProxyStatsRepr")]
+pub struct ProxyStats {
+    /// Fields shared with client stats
+    pub core: ProxyStatsCore,
+    /// Per-backend statistics (only for shared proxies)
+    pub backends: Vec<BackendStats>,
+    /// Proxy visibility (private proxies have no published listener)
+    pub visibility: ProxyVisibility,
+    /// When the proxy last had a connection, published port or visitor (Unix timestamp, None if never)
+    pub last_activity_at: Option<u64>,
+    /// Admission limits on the published port (None if neither is configured)
+    pub admission: Option<AdmissionStats>,
+}
+
+/// Admission limits of a published port and the connections they turned away
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdmissionStats {
+    /// `require_first_byte_timeout_ms` (None if disabled)
+    pub first_byte_timeout_ms: Option<u64>,
+    /// Connections closed without sending a byte within the timeout
+    pub silent_connections: u64,
+    /// `max_connections_per_source` (None if unlimited)
+    pub max_connections_per_source: Option<u32>,
+    /// Connections closed right after accept because their source IP was at the cap
+    pub source_limited: u64,
+}
+
+/// Wire representation of [`ProxyStats`]
+#[derive(Serialize, Deserialize)]
+struct ProxyStatsRepr {
+    #[serde(flatten)]
+    core: ProxyStatsCore,
+    /// Deprecated alias of `listen_addr`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    publish_addr: Option<String>,
+    /// Deprecated alias of `listen_port`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    publish_port: Option<u16>,
+    /// Deprecated alias of `target_port`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    local_port: Option<u16>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    backends: Vec<BackendStats>,
+    #[serde(default, skip_serializing_if = "ProxyVisibility::is_public")]
+    visibility: ProxyVisibility,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_activity_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    admission: Option<AdmissionStats>,
+}
+
+impl From<ProxyStats> for ProxyStatsRepr {
+    fn from(stats: ProxyStats) -> Self {
+        Self {
+            publish_addr: Some(stats.core.listen_addr.clone()),
+            publish_port: Some(stats.core.listen_port),
+            local_port: Some(stats.core.target_port),
+            core: stats.core,
+            backends: stats.backends,
+            visibility: stats.visibility,
+            last_activity_at: stats.last_activity_at,
+            admission: stats.admission,
+        }
+    }
+}
+
+impl From<ProxyStatsRepr> for ProxyStats {
+    fn from(repr: ProxyStatsRepr) -> Self {
+        let mut core = repr.core;
+        core.role = StatsRole::Server;
+        if let Some(addr) = repr.publish_addr {
+            core.listen_addr = addr;
+        }
+        if let Some(port) = repr.publish_port {
+            core.listen_port = port;
+        }
+        if let Some(port) = repr.local_port {
+            core.target_port = port;
+        }
+        if core.target_addr.is_empty() {
+            core.target_addr = CLIENT_TARGET_ADDR.to_string();
+        }
+        Self {
+            core,
+            backends: repr.backends,
+            visibility: repr.visibility,
+            last_activity_at: repr.last_activity_at,
+            admission: repr.admission,
+        }
+    }
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+impl ProxyStats {
+    /// Published endpoint for display (`private:<port>` for private proxies)
+    pub fn publish_label(&self) -> String {
+        if self.visibility.is_private() {
+            format!("private:{}", self.core.listen_port)
+        } else {
+            crate::listen_addr::format(&self.core.listen_addr, self.core.listen_port)
+        }
+    }
+}
+
+/// Statistics for one backend client of a shared proxy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackendStats {
+    /// Backend identity (peer_id, or the server-assigned client id)
+    pub id: String,
+    /// Load balancing weight
+    pub weight: u32,
+    /// Total number of connections dispatched to this backend
+    pub total_connections: u64,
+    /// Currently active connections on this backend
+    pub active_connections: u64,
+}
+
+/// Statistics tracker for one backend client of a shared proxy
+#[derive(Debug, Clone)]
+pub struct BackendStatsTracker {
+    id: String,
+    weight: u32,
+    total_connections: Arc<AtomicU64>,
+    active_connections: Arc<AtomicU64>,
+}
+
+impl BackendStatsTracker {
+    /// Backend identity
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Increment active connections (called when a connection is dispatched)
+    pub fn connection_started(&self) {
+        self.total_connections.fetch_add(1, Ordering::Relaxed);
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Decrement active connections (called when connection ends)
+    pub fn connection_ended(&self) {
+        self.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Get current snapshot of stats
+    pub fn get_stats(&self) -> BackendStats {
+        BackendStats {
+            id: self.id.clone(),
+            weight: self.weight,
+            total_connections: self.total_connections.load(Ordering::Relaxed),
+            active_connections: self.active_connections.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Forward (`@forward`) usage of one client session, against the server's forward limits
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForwardUsageStats {
+    /// Server-assigned client id of the session
+    pub client_id: String,
+    /// Currently open forward streams
+    pub active_streams: u64,
+    /// Total forward streams accepted
+    pub total_streams: u64,
+    /// Bytes relayed by forward streams (both directions)
+    pub bytes: u64,
+    /// Streams rejected because `max_concurrent_per_client` was reached
+    pub concurrency_rejections: u64,
+    /// Streams closed because they outlived `max_duration_secs`
+    pub duration_limit_hits: u64,
+    /// Streams closed because they transferred `max_bytes`
+    pub byte_limit_hits: u64,
+    /// Streams rejected with `TARGET_RECENTLY_FAILED` because their target failed within the cooldown
+    #[serde(default)]
+    pub fast_fail_rejections: u64,
+    /// Streams rejected because `max_connecting_per_client` targets were already being connected
+    #[serde(default)]
+    pub connecting_rejections: u64,
+    /// Connections to forward targets that did not complete within `connect_timeout_secs`
+    #[serde(default)]
+    pub connect_timeouts: u64,
+    /// Limits the session is held to
+    pub limits: ForwardLimitConfig,
+    /// Connections the client routed directly and reported with `forward_report`
+    #[serde(default)]
+    pub direct: DirectReportStats,
+}
+
+/// Most recent direct connection reports kept per client session
+pub const RECENT_DIRECT_REPORTS: usize = 20;
+
+/// Direct (bypassing the tunnel) forwarder connections reported by one client session
+///
+/// These connections never reach the server; the counts are only as complete as the
+/// client's reports.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DirectReportStats {
+    /// `forward_report` messages received (each batches the connections of about a second)
+    pub batches: u64,
+    /// Direct connections reported
+    pub connections: u64,
+    /// Reported connections that could not reach their target
+    pub failures: u64,
+    /// Bytes sent to the targets
+    pub bytes_up: u64,
+    /// Bytes received from the targets
+    pub bytes_down: u64,
+    /// Reports the client dropped because too many were waiting to be sent
+    pub dropped: u64,
+    /// Latest reports, oldest first (at most [`RECENT_DIRECT_REPORTS`])
+    pub recent: Vec<ForwardReport>,
+}
+
+/// Forward usage tracker for one client session
+#[derive(Debug, Clone)]
+pub struct ForwardUsageTracker {
+    limits: ForwardLimitConfig,
+    active_streams: Arc<AtomicU64>,
+    total_streams: Arc<AtomicU64>,
+    traffic: TrafficScope,
+    concurrency_rejections: Arc<AtomicU64>,
+    duration_limit_hits: Arc<AtomicU64>,
+    byte_limit_hits: Arc<AtomicU64>,
+    fast_fail_rejections: Arc<AtomicU64>,
+    connecting_rejections: Arc<AtomicU64>,
+    connect_timeouts: Arc<AtomicU64>,
+    direct: Arc<Mutex<DirectReportStats>>,
+}
+
+impl ForwardUsageTracker {
+    pub fn new(limits: ForwardLimitConfig) -> Self {
+        Self {
+            limits,
+            active_streams: Arc::new(AtomicU64::new(0)),
+            total_streams: Arc::new(AtomicU64::new(0)),
+            traffic: TrafficScope::new(),
+            concurrency_rejections: Arc::new(AtomicU64::new(0)),
+            duration_limit_hits: Arc::new(AtomicU64::new(0)),
+            byte_limit_hits: Arc::new(AtomicU64::new(0)),
+            fast_fail_rejections: Arc::new(AtomicU64::new(0)),
+            connecting_rejections: Arc::new(AtomicU64::new(0)),
+            connect_timeouts: Arc::new(AtomicU64::new(0)),
+            direct: Arc::new(Mutex::new(DirectReportStats::default())),
+        }
+    }
+
+    /// Count the session's forward traffic toward `parent` as well
+    pub fn with_traffic_parent(mut self, parent: &TrafficScope) -> Self {
+        self.traffic = parent.child();
+        self
+    }
+
+    /// Create the meter of one forward stream
+    pub fn meter(&self) -> TrafficMeter {
+        self.traffic.meter()
+    }
+
+    /// A forward stream was accepted
+    pub fn stream_started(&self) {
+        self.total_streams.fetch_add(1, Ordering::Relaxed);
+        self.active_streams.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A forward stream ended
+    pub fn stream_ended(&self) {
+        self.active_streams.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Record a stream rejected by the concurrency limit
+    pub fn record_concurrency_rejection(&self) {
+        self.concurrency_rejections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a stream closed by the duration limit
+    pub fn record_duration_limit_hit(&self) {
+        self.duration_limit_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a stream closed by the byte limit
+    pub fn record_byte_limit_hit(&self) {
+        self.byte_limit_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a stream short-circuited because its target failed recently
+    pub fn record_fast_fail_rejection(&self) {
+        self.fast_fail_rejections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a stream rejected because too many targets were being connected
+    pub fn record_connecting_rejection(&self) {
+        self.connecting_rejections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a connection to a forward target that timed out
+    pub fn record_connect_timeout(&self) {
+        self.connect_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a batch of direct connection reports from the client
+    pub fn record_direct_reports(&self, params: &ForwardReportParams) {
+        let mut direct = self.direct.lock().unwrap();
+        direct.batches += 1;
+        direct.dropped += params.dropped;
+        for report in &params.reports {
+            direct.connections += 1;
+            if report.error.is_some() {
+                direct.failures += 1;
+            }
+            direct.bytes_up += report.bytes_up;
+            direct.bytes_down += report.bytes_down;
+        }
+        direct.recent.extend(params.reports.iter().cloned());
+        let excess = direct.recent.len().saturating_sub(RECENT_DIRECT_REPORTS);
+        direct.recent.drain(..excess);
+    }
+
+    /// Get current snapshot of usage
+    pub fn get_stats(&self, client_id: &str) -> ForwardUsageStats {
+        ForwardUsageStats {
+            client_id: client_id.to_string(),
+            active_streams: self.active_streams.load(Ordering::Relaxed),
+            total_streams: self.total_streams.load(Ordering::Relaxed),
+            bytes: self.traffic.totals().total(),
+            concurrency_rejections: self.concurrency_rejections.load(Ordering::Relaxed),
+            duration_limit_hits: self.duration_limit_hits.load(Ordering::Relaxed),
+            byte_limit_hits: self.byte_limit_hits.load(Ordering::Relaxed),
+            fast_fail_rejections: self.fast_fail_rejections.load(Ordering::Relaxed),
+            connecting_rejections: self.connecting_rejections.load(Ordering::Relaxed),
+            connect_timeouts: self.connect_timeouts.load(Ordering::Relaxed),
+            limits: self.limits.clone(),
+            direct: self.direct.lock().unwrap().clone(),
+        }
+    }
 }
 
 /// Statistics tracker for a single proxy
@@ -36,13 +588,30 @@ pub struct ProxyStatsTracker {
     local_port: u16,
     total_connections: Arc<AtomicU64>,
     active_connections: Arc<AtomicU64>,
-    bytes_sent: Arc<AtomicU64>,
-    bytes_received: Arc<AtomicU64>,
+    traffic: TrafficScope,
     start_time: u64,
+    backends: Arc<Mutex<Vec<BackendStatsTracker>>>,
+    visibility: ProxyVisibility,
+    accept_errors: Arc<AtomicU64>,
+    draining: Arc<AtomicBool>,
+    /// Registration time (Unix milliseconds)
+    registered_ms: u64,
+    /// Last connection (Unix milliseconds, 0 if never)
+    last_activity_ms: Arc<AtomicU64>,
+    first_byte_timeout_ms: Option<u64>,
+    max_connections_per_source: Option<u32>,
+    silent_connections: Arc<AtomicU64>,
+    source_limited: Arc<AtomicU64>,
+    max_connections: Option<u32>,
+    connection_limited: Arc<AtomicU64>,
+    integrity: IntegrityCounters,
 }
 
 impl ProxyStatsTracker {
     pub fn new(name: String, publish_addr: String, publish_port: u16, local_port: u16) -> Self {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
         Self {
             name,
             publish_addr,
@@ -50,19 +619,119 @@ impl ProxyStatsTracker {
             local_port,
             total_connections: Arc::new(AtomicU64::new(0)),
             active_connections: Arc::new(AtomicU64::new(0)),
-            bytes_sent: Arc::new(AtomicU64::new(0)),
-            bytes_received: Arc::new(AtomicU64::new(0)),
-            start_time: SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
+            traffic: TrafficScope::new(),
+            start_time: now.as_secs(),
+            backends: Arc::new(Mutex::new(Vec::new())),
+            visibility: ProxyVisibility::Public,
+            accept_errors: Arc::new(AtomicU64::new(0)),
+            draining: Arc::new(AtomicBool::new(false)),
+            registered_ms: now.as_millis() as u64,
+            last_activity_ms: Arc::new(AtomicU64::new(0)),
+            first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            silent_connections: Arc::new(AtomicU64::new(0)),
+            source_limited: Arc::new(AtomicU64::new(0)),
+            max_connections: None,
+            connection_limited: Arc::new(AtomicU64::new(0)),
+            integrity: IntegrityCounters::default(),
         }
     }
 
+    /// Set the proxy visibility
+    pub fn with_visibility(mut self, visibility: ProxyVisibility) -> Self {
+        self.visibility = visibility;
+        self
+    }
+
+    /// Report the admission limits configured for the published port
+    pub fn with_admission(
+        mut self,
+        first_byte_timeout_ms: Option<u64>,
+        max_connections_per_source: Option<u32>,
+    ) -> Self {
+        self.first_byte_timeout_ms = first_byte_timeout_ms;
+        self.max_connections_per_source = max_connections_per_source;
+        self
+    }
+
+    /// Cap the proxy's active connections (see [`Self::try_connection_started`])
+    pub fn with_connection_limit(mut self, max_connections: Option<u32>) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Count the proxy's traffic toward `parent` as well
+    pub fn with_traffic_parent(mut self, parent: &TrafficScope) -> Self {
+        self.traffic = parent.child();
+        self
+    }
+
+    /// Add a backend to this (shared) proxy
+    pub fn add_backend(&self, id: String, weight: u32) -> BackendStatsTracker {
+        let backend = BackendStatsTracker {
+            id,
+            weight,
+            total_connections: Arc::new(AtomicU64::new(0)),
+            active_connections: Arc::new(AtomicU64::new(0)),
+        };
+        self.backends.lock().unwrap().push(backend.clone());
+        backend
+    }
+
+    /// Remove a backend from this (shared) proxy
+    pub fn remove_backend(&self, id: &str) {
+        self.backends.lock().unwrap().retain(|b| b.id != id);
+    }
+
     /// Increment active connections (called when connection starts)
     pub fn connection_started(&self) {
         self.total_connections.fetch_add(1, Ordering::Relaxed);
         self.active_connections.fetch_add(1, Ordering::Relaxed);
+        self.record_activity();
+    }
+
+    /// Like [`Self::connection_started`], but refuses the connection (and counts it)
+    /// when the proxy already has `max_connections` active connections
+    pub fn try_connection_started(&self) -> bool {
+        if let Some(max) = self.max_connections {
+            let admitted = self
+                .active_connections
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |active| {
+                    (active < max as u64).then_some(active + 1)
+                })
+                .is_ok();
+            if !admitted {
+                self.connection_limited.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+        } else {
+            self.active_connections.fetch_add(1, Ordering::Relaxed);
+        }
+        self.total_connections.fetch_add(1, Ordering::Relaxed);
+        self.record_activity();
+        true
+    }
+
+    /// Record a connection to the proxy that is not counted as a published-port
+    /// connection (visitor streams)
+    pub fn record_activity(&self) {
+        self.last_activity_ms
+            .store(unix_millis(SystemTime::now()), Ordering::Relaxed);
+    }
+
+    /// How long the proxy has had no connection (since registration if it never had one);
+    /// None while connections are active
+    pub fn idle_for(&self) -> Option<Duration> {
+        if self.active_connections.load(Ordering::Relaxed) > 0 {
+            return None;
+        }
+        let last = self
+            .last_activity_ms
+            .load(Ordering::Relaxed)
+            .max(self.registered_ms);
+        Some(Duration::from_millis(
+            unix_millis(SystemTime::now()).saturating_sub(last),
+        ))
     }
 
     /// Decrement active connections (called when connection ends)
@@ -70,45 +739,238 @@ impl ProxyStatsTracker {
         self.active_connections.fetch_sub(1, Ordering::Relaxed);
     }
 
-    /// Add bytes sent
-    pub fn add_bytes_sent(&self, bytes: u64) {
-        self.bytes_sent.fetch_add(bytes, Ordering::Relaxed);
+    /// Mark the proxy as draining (reported as status "Draining")
+    pub fn set_draining(&self) {
+        self.draining.store(true, Ordering::Relaxed);
     }
 
-    /// Add bytes received
-    pub fn add_bytes_received(&self, bytes: u64) {
-        self.bytes_received.fetch_add(bytes, Ordering::Relaxed);
+    /// Record an accept failure caused by resource exhaustion
+    pub fn record_accept_error(&self) {
+        self.accept_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a connection closed before it sent its first byte
+    pub fn record_silent_connection(&self) {
+        self.silent_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a connection closed because its source IP was at the cap
+    pub fn record_source_limited(&self) {
+        self.source_limited.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Create the meter of one connection (sent is toward the external user)
+    pub fn meter(&self) -> TrafficMeter {
+        self.traffic.meter()
+    }
+
+    /// Integrity check results of the proxy's connections
+    pub fn integrity(&self) -> &IntegrityCounters {
+        &self.integrity
+    }
+
+    /// Fold this proxy's counters into a fingerprint without building a snapshot
+    pub fn fingerprint_into(&self, fingerprint: &mut StatsFingerprint) {
+        fingerprint.add(
+            self.total_connections.load(Ordering::Relaxed),
+            self.active_connections.load(Ordering::Relaxed),
+            self.draining.load(Ordering::Relaxed) as u64
+                + self.accept_errors.load(Ordering::Relaxed)
+                + self.silent_connections.load(Ordering::Relaxed)
+                + self.source_limited.load(Ordering::Relaxed)
+                + self.connection_limited.load(Ordering::Relaxed)
+                + self.integrity.verified()
+                + self.integrity.mismatches(),
+            self.traffic.totals().total(),
+        );
     }
 
     /// Get current snapshot of stats
     pub fn get_stats(&self) -> ProxyStats {
+        let active_connections = self.active_connections.load(Ordering::Relaxed);
+        let traffic = self.traffic.totals();
         ProxyStats {
-            name: self.name.clone(),
-            publish_addr: self.publish_addr.clone(),
-            publish_port: self.publish_port,
-            local_port: self.local_port,
-            total_connections: self.total_connections.load(Ordering::Relaxed),
-            active_connections: self.active_connections.load(Ordering::Relaxed),
-            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
-            bytes_received: self.bytes_received.load(Ordering::Relaxed),
-            start_time: self.start_time,
+            core: ProxyStatsCore {
+                name: self.name.clone(),
+                role: StatsRole::Server,
+                listen_addr: self.publish_addr.clone(),
+                listen_port: self.publish_port,
+                target_addr: CLIENT_TARGET_ADDR.to_string(),
+                target_port: self.local_port,
+                total_connections: self.total_connections.load(Ordering::Relaxed),
+                active_connections,
+                bytes_sent: traffic.sent,
+                bytes_received: traffic.received,
+                start_time: self.start_time,
+                status: if self.draining.load(Ordering::Relaxed) {
+                    "Draining"
+                } else if active_connections > 0 {
+                    "Connected"
+                } else {
+                    "Idle"
+                }
+                .to_string(),
+                accept_errors: self.accept_errors.load(Ordering::Relaxed),
+                integrity_verified: self.integrity.verified(),
+                integrity_mismatches: self.integrity.mismatches(),
+                connection_limited: self.connection_limited.load(Ordering::Relaxed),
+            },
+            backends: self
+                .backends
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|b| b.get_stats())
+                .collect(),
+            visibility: self.visibility,
+            last_activity_at: match self.last_activity_ms.load(Ordering::Relaxed) {
+                0 => None,
+                ms => Some(ms / 1000),
+            },
+            admission: (self.first_byte_timeout_ms.is_some()
+                || self.max_connections_per_source.is_some())
+            .then(|| AdmissionStats {
+                first_byte_timeout_ms: self.first_byte_timeout_ms,
+                silent_connections: self.silent_connections.load(Ordering::Relaxed),
+                max_connections_per_source: self.max_connections_per_source,
+                source_limited: self.source_limited.load(Ordering::Relaxed),
+            }),
         }
     }
 }
 
+/// TLS handshake counters of the tunnel listener
+///
+/// `queued` and `in_progress` are gauges; the rest only grow.
+#[derive(Debug, Default)]
+pub struct HandshakeStats {
+    queued: AtomicU64,
+    in_progress: AtomicU64,
+    completed: AtomicU64,
+    failed: AtomicU64,
+    timed_out: AtomicU64,
+    rate_limited: AtomicU64,
+}
+
+impl HandshakeStats {
+    /// Accepted connections waiting for a handshake slot
+    pub fn queued(&self) -> u64 {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    /// Handshakes currently running
+    pub fn in_progress(&self) -> u64 {
+        self.in_progress.load(Ordering::Relaxed)
+    }
+
+    /// Handshakes that produced an established transport
+    pub fn completed(&self) -> u64 {
+        self.completed.load(Ordering::Relaxed)
+    }
+
+    /// Handshakes that failed before the timeout
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    /// Connections dropped because queueing plus handshake exceeded the timeout
+    pub fn timed_out(&self) -> u64 {
+        self.timed_out.load(Ordering::Relaxed)
+    }
+
+    /// Connections rejected by the rate limit before any handshake work
+    pub fn rate_limited(&self) -> u64 {
+        self.rate_limited.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn gauges(&self) -> (&AtomicU64, &AtomicU64) {
+        (&self.queued, &self.in_progress)
+    }
+
+    pub(crate) fn record_completed(&self) {
+        self.completed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_failed(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_timed_out(&self) {
+        self.timed_out.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_rate_limited(&self) {
+        self.rate_limited.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
 /// Global statistics manager
 #[derive(Debug, Clone)]
 pub struct StatsManager {
     proxies: Arc<Mutex<HashMap<String, ProxyStatsTracker>>>,
+    listener_accept_errors: Arc<AtomicU64>,
+    legacy_clients_rejected: Arc<AtomicU64>,
+    /// TLS handshakes of the tunnel listener
+    handshakes: Arc<HandshakeStats>,
+    /// Bumped whenever a proxy is registered or unregistered
+    generation: Arc<AtomicU64>,
+    /// Forward usage of each client session, keyed by client id
+    forward_usage: Arc<Mutex<HashMap<String, ForwardUsageTracker>>>,
+    /// Root of the byte accounting tree (all proxies and forward sessions)
+    traffic: TrafficScope,
+    /// Copy buffers of all relayed connections
+    relay_memory: RelayMemory,
 }
 
 impl StatsManager {
     pub fn new() -> Self {
         Self {
             proxies: Arc::new(Mutex::new(HashMap::new())),
+            listener_accept_errors: Arc::new(AtomicU64::new(0)),
+            legacy_clients_rejected: Arc::new(AtomicU64::new(0)),
+            handshakes: Arc::new(HandshakeStats::default()),
+            generation: Arc::new(AtomicU64::new(0)),
+            forward_usage: Arc::new(Mutex::new(HashMap::new())),
+            traffic: TrafficScope::new(),
+            relay_memory: RelayMemory::default(),
         }
     }
 
+    /// Record an accept failure on the main listener caused by resource exhaustion
+    pub fn record_listener_accept_error(&self) {
+        self.listener_accept_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Accept failures on the main listener caused by resource exhaustion
+    pub fn listener_accept_errors(&self) -> u64 {
+        self.listener_accept_errors.load(Ordering::Relaxed)
+    }
+
+    /// Record a connection from a legacy (1.0 byte protocol) client that was told to upgrade
+    pub fn record_legacy_client_rejected(&self) {
+        self.legacy_clients_rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Connections from legacy (1.0 byte protocol) clients rejected so far
+    pub fn legacy_clients_rejected(&self) -> u64 {
+        self.legacy_clients_rejected.load(Ordering::Relaxed)
+    }
+
+    /// TLS handshake counters of the tunnel listener
+    pub fn handshakes(&self) -> &HandshakeStats {
+        &self.handshakes
+    }
+
+    /// Copy buffer accounting of all relayed connections (budgeted by `relay_memory_budget_mb`)
+    pub fn relay_memory(&self) -> &RelayMemory {
+        &self.relay_memory
+    }
+
+    /// Bytes relayed by all proxies and forward streams
+    pub fn traffic(&self) -> &TrafficScope {
+        &self.traffic
+    }
+
     /// Register a new proxy
     pub fn register_proxy(
         &self,
@@ -116,15 +978,42 @@ impl StatsManager {
         publish_addr: String,
         publish_port: u16,
         local_port: u16,
+        visibility: ProxyVisibility,
     ) -> ProxyStatsTracker {
-        let tracker = ProxyStatsTracker::new(name.clone(), publish_addr, publish_port, local_port);
-        self.proxies.lock().unwrap().insert(name, tracker.clone());
+        let tracker =
+            self.new_proxy_tracker(name, publish_addr, publish_port, local_port, visibility);
+        self.add_proxy(tracker.clone());
         tracker
     }
 
+    /// Create a tracker for a proxy without listing it yet (see [`StatsManager::add_proxy`])
+    pub fn new_proxy_tracker(
+        &self,
+        name: String,
+        publish_addr: String,
+        publish_port: u16,
+        local_port: u16,
+        visibility: ProxyVisibility,
+    ) -> ProxyStatsTracker {
+        ProxyStatsTracker::new(name, publish_addr, publish_port, local_port)
+            .with_visibility(visibility)
+            .with_traffic_parent(&self.traffic)
+    }
+
+    /// List a tracker created by [`StatsManager::new_proxy_tracker`]
+    pub fn add_proxy(&self, tracker: ProxyStatsTracker) {
+        self.proxies
+            .lock()
+            .unwrap()
+            .insert(tracker.name.clone(), tracker);
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
     /// Unregister a proxy
     pub fn unregister_proxy(&self, name: &str) {
-        self.proxies.lock().unwrap().remove(name);
+        if self.proxies.lock().unwrap().remove(name).is_some() {
+            self.generation.fetch_add(1, Ordering::Relaxed);
+        }
     }
 
     /// Get stats for all proxies
@@ -137,6 +1026,34 @@ impl StatsManager {
             .collect()
     }
 
+    /// Get stats for one page of proxies, ordered by name
+    ///
+    /// The name filter and pagination are applied before any snapshot is built.
+    /// Returns the page and the number of proxies matching the filter.
+    pub fn get_stats_page(&self, query: &StatsQuery) -> (Vec<ProxyStats>, usize) {
+        let proxies = self.proxies.lock().unwrap();
+        let mut matched: Vec<&ProxyStatsTracker> = proxies
+            .values()
+            .filter(|tracker| query.matches(&tracker.name))
+            .collect();
+        matched.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        let total = matched.len();
+        let page = query
+            .paginate(matched.into_iter())
+            .map(|tracker| tracker.get_stats())
+            .collect();
+        (page, total)
+    }
+
+    /// Fingerprint of all proxy stats (changes when a response would change noticeably)
+    pub fn fingerprint(&self) -> StatsFingerprint {
+        let mut fingerprint = StatsFingerprint::new(self.generation.load(Ordering::Relaxed));
+        for tracker in self.proxies.lock().unwrap().values() {
+            tracker.fingerprint_into(&mut fingerprint);
+        }
+        fingerprint
+    }
+
     /// Get stats for a specific proxy
     #[allow(dead_code)]
     pub fn get_proxy_stats(&self, name: &str) -> Option<ProxyStats> {
@@ -147,10 +1064,37 @@ impl StatsManager {
             .map(|tracker| tracker.get_stats())
     }
 
+    /// Register the forward usage tracker of a client session
+    pub fn register_forward_usage(&self, client_id: String, tracker: ForwardUsageTracker) {
+        self.forward_usage
+            .lock()
+            .unwrap()
+            .insert(client_id, tracker);
+    }
+
+    /// Unregister the forward usage tracker of a client session
+    pub fn unregister_forward_usage(&self, client_id: &str) {
+        self.forward_usage.lock().unwrap().remove(client_id);
+    }
+
+    /// Get forward usage of all client sessions, ordered by client id
+    pub fn get_forward_usage(&self) -> Vec<ForwardUsageStats> {
+        let mut usage: Vec<ForwardUsageStats> = self
+            .forward_usage
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(client_id, tracker)| tracker.get_stats(client_id))
+            .collect();
+        usage.sort_by(|a, b| a.client_id.cmp(&b.client_id));
+        usage
+    }
+
     /// Clear all stats
     #[allow(dead_code)]
     pub fn clear(&self) {
         self.proxies.lock().unwrap().clear();
+        self.generation.fetch_add(1, Ordering::Relaxed);
     }
 }
 
@@ -0,0 +1,1279 @@
+/// 统计 HTTP 服务器公共模块
+///
+/// 服务端和客户端的统计服务器共用的连接处理逻辑：读取/写入超时、
+/// 并发连接数限制和请求大小上限，防止慢速连接（slow-loris）耗尽资源。
+/// 监听方式可以是 TCP 端口、Unix 套接字或 Windows 命名管道，见 [`StatsListener`]。
+/// [`supervise`] 在监听失败后退避并重新绑定，运行状态见 [`StatsServers`]
+use crate::accept::{AcceptBackoff, AcceptErrorKind, ListenerError};
+use crate::config::StatsLimitConfig;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+use tokio::time::{timeout, Instant};
+use tracing::{debug, error, info, warn};
+
+/// 重新绑定的初始等待时间
+const INITIAL_REBIND_DELAY: Duration = Duration::from_millis(500);
+/// 重新绑定的最大等待时间
+const MAX_REBIND_DELAY: Duration = Duration::from_secs(30);
+/// 监听器持续工作超过该时长后，再次失败时重新开始计数和退避
+const REBIND_STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// 统计服务器收到的 HTTP 请求
+#[derive(Debug, Clone, Default)]
+pub struct HttpRequest {
+    /// 请求方法（GET、POST 等）
+    pub method: String,
+    /// 请求目标（包含查询字符串）
+    pub target: String,
+    /// 请求头（保留原始大小写）
+    pub headers: Vec<(String, String)>,
+    /// 请求体
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// 解析请求行和请求头
+    pub(crate) fn parse_head(head: &[u8]) -> Self {
+        let head = String::from_utf8_lossy(head);
+        let mut lines = head.split("\r\n");
+        let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+        let method = request_line.next().unwrap_or("GET").to_string();
+        let target = request_line.next().unwrap_or("/").to_string();
+        let headers = lines
+            .take_while(|line| !line.is_empty())
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect();
+        Self {
+            method,
+            target,
+            headers,
+            body: Vec::new(),
+        }
+    }
+
+    /// GET 请求
+    pub fn get(target: impl Into<String>) -> Self {
+        Self {
+            method: "GET".to_string(),
+            target: target.into(),
+            ..Default::default()
+        }
+    }
+
+    /// 请求路径（不含查询字符串）
+    pub fn path(&self) -> &str {
+        self.target
+            .split_once('?')
+            .map_or(self.target.as_str(), |(path, _)| path)
+    }
+
+    /// 查询字符串参数
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        let (_, query) = self.target.split_once('?')?;
+        find_param(query.as_bytes(), name)
+    }
+
+    /// application/x-www-form-urlencoded 表单参数
+    pub fn form_param(&self, name: &str) -> Option<String> {
+        find_param(&self.body, name)
+    }
+
+    /// 请求头（名称不区分大小写）
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// HTTP Basic 认证中的密码
+    pub fn basic_auth_password(&self) -> Option<String> {
+        let encoded = self.header("Authorization")?.strip_prefix("Basic ")?;
+        let decoded = String::from_utf8(decode_base64(encoded.trim())?).ok()?;
+        decoded
+            .split_once(':')
+            .map(|(_, password)| password.to_string())
+    }
+
+    fn content_length(&self) -> usize {
+        self.header("Content-Length")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0)
+    }
+}
+
+fn find_param(data: &[u8], name: &str) -> Option<String> {
+    url::form_urlencoded::parse(data)
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+/// 解码标准 Base64（用于 Basic 认证），输入非法时返回 None
+fn decode_base64(input: &str) -> Option<Vec<u8>> {
+    let input = input.trim_end_matches('=');
+    let mut output = Vec::with_capacity(input.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0u32;
+    for c in input.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(output)
+}
+
+/// 统计服务器的 HTTP 响应
+#[derive(Debug)]
+pub struct HttpResponse {
+    /// 状态码
+    pub status: u16,
+    /// Content-Type
+    pub content_type: &'static str,
+    /// 额外的响应头
+    pub headers: Vec<(&'static str, String)>,
+    /// 响应体
+    pub body: String,
+    /// 逐块生成的响应体（写出时才生成，设置后忽略 `body`）
+    pub stream: Option<BodyStream>,
+    /// 协议升级（101 响应写出后接管连接，见 [`HttpResponse::switching_protocols`]）
+    pub upgrade: Option<Upgrade>,
+}
+
+/// 升级后的连接
+pub trait UpgradedIo: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> UpgradedIo for T {}
+
+/// 升级后交给 [`Upgrade`] 的连接
+pub type UpgradedConn = Box<dyn UpgradedIo>;
+
+/// 接管升级后连接的 future
+type UpgradeFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// 协议升级：101 响应写出后以连接调用，连接在返回的 future 结束前一直占用并发名额
+pub struct Upgrade(Box<dyn FnOnce(UpgradedConn) -> UpgradeFuture + Send>);
+
+impl Upgrade {
+    /// 以接管连接的异步函数创建
+    pub fn new<F, Fut>(handler: F) -> Self
+    where
+        F: FnOnce(UpgradedConn) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Self(Box::new(move |conn| Box::pin(handler(conn))))
+    }
+
+    /// 接管连接
+    pub async fn run(self, conn: UpgradedConn) {
+        (self.0)(conn).await
+    }
+}
+
+impl std::fmt::Debug for Upgrade {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Upgrade")
+    }
+}
+
+/// 逐块生成的响应体
+///
+/// 写出时按需取出下一块，响应不需要先在内存中拼成完整的字符串；
+/// 长度事先未知，HTTP/1.1 响应以关闭连接结束
+pub struct BodyStream(Box<dyn Iterator<Item = Vec<u8>> + Send>);
+
+impl BodyStream {
+    /// JSON 数组：每个元素在取出时才序列化，`pretty` 时与 `serde_json::to_string_pretty` 格式一致
+    pub fn json_array<T>(items: Vec<T>, pretty: bool) -> Self
+    where
+        T: Serialize + Send + 'static,
+    {
+        let empty = items.is_empty();
+        let elements = items.into_iter().enumerate().map(move |(index, item)| {
+            let mut chunk = Vec::new();
+            match (index, pretty) {
+                (0, true) => chunk.extend_from_slice(b"\n  "),
+                (0, false) => {}
+                (_, true) => chunk.extend_from_slice(b",\n  "),
+                (_, false) => chunk.push(b','),
+            }
+            if pretty {
+                // 元素内部的换行同样缩进一级
+                let element = serde_json::to_vec_pretty(&item).unwrap_or_default();
+                for (i, line) in element.split(|&b| b == b'\n').enumerate() {
+                    if i > 0 {
+                        chunk.extend_from_slice(b"\n  ");
+                    }
+                    chunk.extend_from_slice(line);
+                }
+            } else {
+                serde_json::to_writer(&mut chunk, &item).ok();
+            }
+            chunk
+        });
+        let close: &'static [u8] = if pretty && !empty { b"\n]" } else { b"]" };
+        Self(Box::new(
+            std::iter::once(b"[".to_vec())
+                .chain(elements)
+                .chain(std::iter::once(close.to_vec())),
+        ))
+    }
+
+    /// 取出全部内容
+    pub fn collect(self) -> Vec<u8> {
+        self.0.flatten().collect()
+    }
+}
+
+impl Iterator for BodyStream {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+impl std::fmt::Debug for BodyStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("BodyStream")
+    }
+}
+
+impl HttpResponse {
+    /// JSON 响应
+    pub fn json(body: String) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/json",
+            headers: Vec::new(),
+            body,
+            stream: None,
+            upgrade: None,
+        }
+    }
+
+    /// HTML 响应
+    pub fn html(body: String) -> Self {
+        Self {
+            status: 200,
+            content_type: "text/html; charset=utf-8",
+            headers: Vec::new(),
+            body,
+            stream: None,
+            upgrade: None,
+        }
+    }
+
+    /// 纯文本错误响应
+    pub fn error(status: u16) -> Self {
+        Self {
+            status,
+            content_type: "text/plain",
+            headers: Vec::new(),
+            body: format!("{} {}", status, status_text(status)),
+            stream: None,
+            upgrade: None,
+        }
+    }
+
+    /// 逐项序列化的 JSON 数组响应
+    pub fn json_stream(stream: BodyStream) -> Self {
+        Self {
+            stream: Some(stream),
+            ..Self::json(String::new())
+        }
+    }
+
+    /// 304 响应（请求的 If-None-Match 与当前 ETag 相同）
+    pub fn not_modified(etag: &str) -> Self {
+        Self {
+            body: String::new(),
+            ..Self::error(304)
+        }
+        .with_header("ETag", etag)
+    }
+
+    /// 404 响应
+    pub fn not_found() -> Self {
+        Self::error(404)
+    }
+
+    /// 303 重定向（表单提交后跳转）
+    pub fn redirect(location: impl Into<String>) -> Self {
+        Self::error(303).with_header("Location", location)
+    }
+
+    /// 401 响应，要求 Basic 认证
+    pub fn unauthorized(realm: &str) -> Self {
+        Self::error(401).with_header("WWW-Authenticate", format!("Basic realm=\"{}\"", realm))
+    }
+
+    /// 101 响应：写出响应头后由 `upgrade` 接管连接
+    ///
+    /// 只有独立的统计服务器（[`serve`]）支持升级，其他途径写出时连接随即关闭
+    pub fn switching_protocols(upgrade: Upgrade) -> Self {
+        Self {
+            body: String::new(),
+            upgrade: Some(upgrade),
+            ..Self::error(101)
+        }
+    }
+
+    /// 修改状态码
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// 添加响应头
+    pub fn with_header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    /// HTTP/1.1 响应头（响应后关闭连接；逐块生成的响应体没有 Content-Length；
+    /// 101 响应只有状态行和额外的响应头）
+    pub fn head_bytes(&self) -> Vec<u8> {
+        let extra_headers: String = self
+            .headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect();
+        if self.upgrade.is_some() {
+            return format!(
+                "HTTP/1.1 {} {}\r\n{}\r\n",
+                self.status,
+                status_text(self.status),
+                extra_headers
+            )
+            .into_bytes();
+        }
+        let content_length = match self.stream {
+            Some(_) => String::new(),
+            None => format!("Content-Length: {}\r\n", self.body.len()),
+        };
+        format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\n{}{}Connection: close\r\n\r\n",
+            self.status,
+            status_text(self.status),
+            self.content_type,
+            content_length,
+            extra_headers,
+        )
+        .into_bytes()
+    }
+
+    /// 完整的响应体（逐块生成的响应体在这里全部取出）
+    pub fn into_body(self) -> Vec<u8> {
+        match self.stream {
+            Some(stream) => stream.collect(),
+            None => self.body.into_bytes(),
+        }
+    }
+
+    /// 序列化为 HTTP/1.1 报文（响应后关闭连接）
+    pub fn into_bytes(self) -> Vec<u8> {
+        let mut bytes = self.head_bytes();
+        bytes.extend(self.into_body());
+        bytes
+    }
+}
+
+/// 把逐块生成的响应体合并为不小于该大小的块再写出，减少写调用
+const STREAM_WRITE_BATCH: usize = 16 * 1024;
+
+/// 写出响应并关闭连接
+pub(crate) async fn write_http_response<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    response: HttpResponse,
+) -> std::io::Result<()> {
+    stream.write_all(&response.head_bytes()).await?;
+    match response.stream {
+        Some(chunks) => {
+            let mut batch = Vec::with_capacity(STREAM_WRITE_BATCH);
+            for chunk in chunks {
+                batch.extend_from_slice(&chunk);
+                if batch.len() >= STREAM_WRITE_BATCH {
+                    stream.write_all(&batch).await?;
+                    batch.clear();
+                }
+            }
+            stream.write_all(&batch).await?;
+        }
+        None => stream.write_all(response.body.as_bytes()).await?,
+    }
+    stream.shutdown().await
+}
+
+/// 统计列表查询参数：`offset`、`limit`、`name_prefix` 在生成快照之前生效，
+/// `pretty=1` 输出缩进格式（默认紧凑）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatsQuery {
+    /// 跳过的条目数
+    pub offset: usize,
+    /// 最多返回的条目数（None 表示不限制）
+    pub limit: Option<usize>,
+    /// 只返回名称以此开头的条目
+    pub name_prefix: Option<String>,
+    /// 是否输出缩进格式
+    pub pretty: bool,
+}
+
+impl StatsQuery {
+    /// 从请求的查询字符串解析，数值参数非法时返回 None
+    pub fn parse(request: &HttpRequest) -> Option<Self> {
+        let number = |name: &str| match request.query_param(name) {
+            Some(value) => value.parse().ok().map(Some),
+            None => Some(None),
+        };
+        Some(Self {
+            offset: number("offset")?.unwrap_or(0),
+            limit: number("limit")?,
+            name_prefix: request.query_param("name_prefix"),
+            pretty: matches!(
+                request.query_param("pretty").as_deref(),
+                Some("1") | Some("true")
+            ),
+        })
+    }
+
+    /// 名称是否满足过滤条件
+    pub fn matches(&self, name: &str) -> bool {
+        self.name_prefix
+            .as_deref()
+            .is_none_or(|prefix| name.starts_with(prefix))
+    }
+
+    /// 按 offset/limit 截取（调用方需保证顺序稳定）
+    pub fn paginate<T>(&self, items: impl Iterator<Item = T>) -> impl Iterator<Item = T> {
+        items
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+    }
+
+    /// 生成统计列表响应：请求的 If-None-Match 与 `etag` 相同时返回 304，
+    /// 否则调用 `select` 取出本页条目（匹配的总数放在 X-Total-Count 中）
+    pub fn respond<T>(
+        &self,
+        request: &HttpRequest,
+        etag: String,
+        select: impl FnOnce(&Self) -> (Vec<T>, usize),
+    ) -> HttpResponse
+    where
+        T: Serialize + Send + 'static,
+    {
+        if request.header("If-None-Match") == Some(etag.as_str()) {
+            return HttpResponse::not_modified(&etag);
+        }
+        let (items, total) = select(self);
+        HttpResponse::json_stream(BodyStream::json_array(items, self.pretty))
+            .with_header("ETag", etag)
+            .with_header("X-Total-Count", total.to_string())
+    }
+}
+
+fn status_text(status: u16) -> &'static str {
+    match status {
+        101 => "Switching Protocols",
+        200 => "OK",
+        303 => "See Other",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        426 => "Upgrade Required",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+/// 统计服务器的监听器
+pub trait StatsListener: Send + 'static {
+    /// 接受的连接
+    type Conn: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    /// 等待下一个连接，返回连接及用于日志的对端描述
+    fn accept_conn(&mut self)
+        -> impl Future<Output = std::io::Result<(Self::Conn, String)>> + Send;
+}
+
+impl StatsListener for TcpListener {
+    type Conn = tokio::net::TcpStream;
+
+    async fn accept_conn(&mut self) -> std::io::Result<(Self::Conn, String)> {
+        let (stream, addr) = self.accept().await?;
+        Ok((stream, addr.to_string()))
+    }
+}
+
+#[cfg(unix)]
+impl StatsListener for tokio::net::UnixListener {
+    type Conn = tokio::net::UnixStream;
+
+    async fn accept_conn(&mut self) -> std::io::Result<(Self::Conn, String)> {
+        let (stream, _) = self.accept().await?;
+        Ok((stream, "unix socket peer".to_string()))
+    }
+}
+
+/// Windows 命名管道监听器：每个连接占用一个管道实例，连接建立后立即创建下一个实例
+#[cfg(windows)]
+pub struct NamedPipeListener {
+    name: String,
+    next: tokio::net::windows::named_pipe::NamedPipeServer,
+}
+
+#[cfg(windows)]
+impl NamedPipeListener {
+    /// 创建管道（同名管道已存在时失败，避免与其他进程共用）
+    pub fn bind(name: impl Into<String>) -> std::io::Result<Self> {
+        let name = name.into();
+        let next = tokio::net::windows::named_pipe::ServerOptions::new()
+            .first_pipe_instance(true)
+            .create(&name)?;
+        Ok(Self { name, next })
+    }
+}
+
+#[cfg(windows)]
+impl StatsListener for NamedPipeListener {
+    type Conn = tokio::net::windows::named_pipe::NamedPipeServer;
+
+    async fn accept_conn(&mut self) -> std::io::Result<(Self::Conn, String)> {
+        self.next.connect().await?;
+        let next = tokio::net::windows::named_pipe::ServerOptions::new().create(&self.name)?;
+        let conn = std::mem::replace(&mut self.next, next);
+        Ok((conn, "named pipe peer".to_string()))
+    }
+}
+
+/// 运行统计 HTTP 服务器
+///
+/// `handler` 根据请求生成响应；超过并发上限的连接直接返回 503，
+/// 在 `read_timeout_secs` 内未发送完整请求的连接返回 408 并关闭。
+/// 每个连接在独立任务中处理，单个连接的错误不会结束 accept 循环；
+/// 只有监听套接字不可用时返回错误（由 [`supervise`] 重新绑定）
+pub async fn serve<L, F>(mut listener: L, limits: StatsLimitConfig, handler: F) -> Result<()>
+where
+    L: StatsListener,
+    F: Fn(&HttpRequest) -> HttpResponse + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    let limits = Arc::new(limits);
+    let semaphore = Arc::new(Semaphore::new(limits.max_connections));
+    let mut backoff = AcceptBackoff::new("Stats server");
+
+    loop {
+        // 故障注入：监听套接字失效
+        crate::chaos::fail("stats.listener").map_err(ListenerError)?;
+
+        match listener.accept_conn().await {
+            Ok((stream, addr)) => {
+                backoff.reset();
+                let limits = limits.clone();
+                match semaphore.clone().try_acquire_owned() {
+                    Ok(permit) => {
+                        let handler = handler.clone();
+                        tokio::spawn(async move {
+                            handle_connection(stream, addr, &limits, handler.as_ref()).await;
+                            drop(permit);
+                        });
+                    }
+                    Err(_) => {
+                        warn!(
+                            "Too many stats connections (max: {}), rejecting {}",
+                            limits.max_connections, addr
+                        );
+                        tokio::spawn(async move {
+                            write_response(stream, addr, &limits, HttpResponse::error(503)).await;
+                        });
+                    }
+                }
+            }
+            Err(e) => {
+                if !backoff.on_error(AcceptErrorKind::of_io(&e), &e).await {
+                    return Err(ListenerError(e).into());
+                }
+            }
+        }
+    }
+}
+
+/// 运行统计 HTTP 服务器，监听失败后重新绑定
+///
+/// `bind` 创建监听器；绑定失败或 [`serve`] 因监听套接字不可用返回时，记录错误并
+/// 按指数退避（0.5 秒起，最多 30 秒）重新调用 `bind`。连续失败超过
+/// `limits.max_rebind_attempts` 次时返回最后一次的错误，未配置时一直重试。
+/// 运行状态（是否在监听、最近一次错误、重新绑定次数）记录在 `health` 中
+pub async fn supervise<L, B, Fut, F>(
+    health: StatsServerHealth,
+    limits: StatsLimitConfig,
+    mut bind: B,
+    handler: F,
+) -> Result<()>
+where
+    L: StatsListener,
+    B: FnMut() -> Fut,
+    Fut: Future<Output = Result<L>>,
+    F: Fn(&HttpRequest) -> HttpResponse + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    let mut failures = 0u32;
+    let mut delay = INITIAL_REBIND_DELAY;
+
+    loop {
+        let error = match bind().await {
+            Ok(listener) => {
+                if failures > 0 {
+                    info!(
+                        "{} is listening again after {} failed attempt(s)",
+                        health.label(),
+                        failures
+                    );
+                    health.record_rebind();
+                }
+                health.set_healthy();
+                let bound_at = Instant::now();
+                let handler = handler.clone();
+                let error =
+                    match serve(listener, limits.clone(), move |request| handler(request)).await {
+                        Ok(()) => return Ok(()),
+                        Err(e) => e,
+                    };
+                // 工作了一段时间之后的失败视为新的故障
+                if bound_at.elapsed() >= REBIND_STABLE_AFTER {
+                    failures = 0;
+                    delay = INITIAL_REBIND_DELAY;
+                }
+                error
+            }
+            Err(e) => e,
+        };
+
+        failures += 1;
+        health.set_failed(&error);
+        if limits.max_rebind_attempts.is_some_and(|max| failures > max) {
+            error!(
+                "{} failed: {:#}, giving up after {} attempt(s)",
+                health.label(),
+                error,
+                failures
+            );
+            return Err(error);
+        }
+        error!(
+            "{} failed: {:#}, rebinding in {:?} (attempt {})",
+            health.label(),
+            error,
+            delay,
+            failures
+        );
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_REBIND_DELAY);
+    }
+}
+
+/// 单个统计服务器的运行状态
+#[derive(Clone)]
+pub struct StatsServerHealth {
+    inner: Arc<HealthInner>,
+}
+
+struct HealthInner {
+    label: String,
+    healthy: AtomicBool,
+    last_error: parking_lot::Mutex<Option<String>>,
+    rebinds: AtomicU64,
+}
+
+impl StatsServerHealth {
+    fn new(label: String) -> Self {
+        Self {
+            inner: Arc::new(HealthInner {
+                label,
+                healthy: AtomicBool::new(false),
+                last_error: parking_lot::Mutex::new(None),
+                rebinds: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// 日志和 `/healthz` 中标识该服务器（如 `Stats server 127.0.0.1:9090`）
+    pub fn label(&self) -> &str {
+        &self.inner.label
+    }
+
+    /// 是否正在监听
+    pub fn is_healthy(&self) -> bool {
+        self.inner.healthy.load(Ordering::Relaxed)
+    }
+
+    fn set_healthy(&self) {
+        self.inner.healthy.store(true, Ordering::Relaxed);
+    }
+
+    fn set_failed(&self, error: &anyhow::Error) {
+        self.inner.healthy.store(false, Ordering::Relaxed);
+        *self.inner.last_error.lock() = Some(format!("{:#}", error));
+    }
+
+    fn record_rebind(&self) {
+        self.inner.rebinds.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 当前状态
+    pub fn status(&self) -> StatsServerStatus {
+        StatsServerStatus {
+            label: self.inner.label.clone(),
+            healthy: self.is_healthy(),
+            last_error: self.inner.last_error.lock().clone(),
+            rebinds: self.inner.rebinds.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 单个统计服务器的状态（`/healthz` 的 `servers` 项）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatsServerStatus {
+    pub label: String,
+    /// 是否正在监听
+    pub healthy: bool,
+    /// 最近一次监听失败的错误（恢复后保留，便于排查）
+    pub last_error: Option<String>,
+    /// 失败后成功重新绑定的次数
+    pub rebinds: u64,
+}
+
+/// `/healthz` 响应
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatsHealthReport {
+    /// 所有统计服务器都在监听（没有配置统计服务器时为 true）
+    pub stats_server_healthy: bool,
+    pub servers: Vec<StatsServerStatus>,
+}
+
+/// 进程内所有统计服务器的运行状态
+///
+/// 任一统计服务器（或隧道端口上的 `stats_path`）的 `/healthz` 都报告全部服务器，
+/// 某个监听器失效时可以从其他监听器查看原因
+#[derive(Clone, Default)]
+pub struct StatsServers {
+    servers: Arc<parking_lot::RwLock<Vec<StatsServerHealth>>>,
+}
+
+impl StatsServers {
+    /// 登记一个统计服务器，返回传给 [`supervise`] 的状态
+    pub fn register(&self, label: impl Into<String>) -> StatsServerHealth {
+        let health = StatsServerHealth::new(label.into());
+        self.servers.write().push(health.clone());
+        health
+    }
+
+    /// 所有统计服务器的状态
+    pub fn report(&self) -> StatsHealthReport {
+        let servers: Vec<_> = self
+            .servers
+            .read()
+            .iter()
+            .map(StatsServerHealth::status)
+            .collect();
+        StatsHealthReport {
+            stats_server_healthy: servers.iter().all(|server| server.healthy),
+            servers,
+        }
+    }
+
+    /// `/healthz` 响应（有统计服务器不在监听时状态码为 503）
+    pub fn respond(&self) -> HttpResponse {
+        let report = self.report();
+        let status = if report.stats_server_healthy {
+            200
+        } else {
+            503
+        };
+        HttpResponse::json(serde_json::to_string_pretty(&report).unwrap_or_default())
+            .with_status(status)
+    }
+}
+
+/// 处理单个统计连接
+async fn handle_connection<S, F>(
+    mut stream: S,
+    addr: String,
+    limits: &StatsLimitConfig,
+    handler: &F,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    F: Fn(&HttpRequest) -> HttpResponse,
+{
+    let read_timeout = Duration::from_secs(limits.read_timeout_secs);
+    let mut response = match timeout(read_timeout, read_request(&mut stream, limits)).await {
+        Ok(Ok(Some(request))) => handler(&request),
+        Ok(Ok(None)) => {
+            debug!("Stats client {} closed before sending a request", addr);
+            return;
+        }
+        Ok(Err(RequestError::TooLarge)) => {
+            warn!(
+                "Stats request from {} exceeds {} bytes, closing",
+                addr, limits.max_request_size
+            );
+            HttpResponse::error(413)
+        }
+        Ok(Err(RequestError::Io(e))) => {
+            error!("Failed to read from stats client {}: {}", addr, e);
+            return;
+        }
+        Err(_) => {
+            warn!(
+                "Stats client {} did not send a complete request within {}s, closing",
+                addr, limits.read_timeout_secs
+            );
+            HttpResponse::error(408)
+        }
+    };
+
+    match response.upgrade.take() {
+        Some(upgrade) => {
+            let write_timeout = Duration::from_secs(limits.write_timeout_secs);
+            let head = response.head_bytes();
+            match timeout(write_timeout, stream.write_all(&head)).await {
+                Ok(Ok(())) => {
+                    debug!("Stats client {} switched protocols", addr);
+                    upgrade.run(Box::new(stream)).await;
+                }
+                Ok(Err(e)) => error!("Failed to write response to {}: {}", addr, e),
+                Err(_) => warn!("Timed out writing stats response to {}", addr),
+            }
+        }
+        None => write_response(stream, addr, limits, response).await,
+    }
+}
+
+enum RequestError {
+    TooLarge,
+    Io(std::io::Error),
+}
+
+/// 读取请求头（直到空行）及 Content-Length 指定的请求体，整个请求超过大小上限时返回错误；
+/// 对端在发送任何数据前关闭时返回 None
+async fn read_request<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    limits: &StatsLimitConfig,
+) -> std::result::Result<Option<HttpRequest>, RequestError> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 1024];
+
+    let head_end = loop {
+        let n = stream.read(&mut buf).await.map_err(RequestError::Io)?;
+        if n == 0 {
+            // 对端半关闭：有数据就按已收到的内容处理
+            return Ok((!data.is_empty()).then(|| HttpRequest::parse_head(&data)));
+        }
+
+        data.extend_from_slice(&buf[..n]);
+        if let Some(pos) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        if data.len() > limits.max_request_size {
+            return Err(RequestError::TooLarge);
+        }
+    };
+
+    let mut request = HttpRequest::parse_head(&data[..head_end]);
+    let body_len = request.content_length();
+    if head_end + body_len > limits.max_request_size {
+        return Err(RequestError::TooLarge);
+    }
+
+    let mut body = data.split_off(head_end);
+    while body.len() < body_len {
+        let n = stream.read(&mut buf).await.map_err(RequestError::Io)?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&buf[..n]);
+    }
+    body.truncate(body_len);
+    request.body = body;
+    Ok(Some(request))
+}
+
+/// 在写超时内写出响应并关闭连接
+async fn write_response<S: AsyncWrite + Unpin>(
+    mut stream: S,
+    addr: String,
+    limits: &StatsLimitConfig,
+    response: HttpResponse,
+) {
+    let write_timeout = Duration::from_secs(limits.write_timeout_secs);
+    let result = timeout(write_timeout, write_http_response(&mut stream, response)).await;
+
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => error!("Failed to write response to {}: {}", addr, e),
+        Err(_) => warn!("Timed out writing stats response to {}", addr),
+    }
+}
+
+/// 统计服务器地址：`http://host:port`、`unix:/path/to/stats.sock`
+/// 或 `pipe:\\.\pipe\name`（供 CLI 查询统计信息）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatsEndpoint {
+    /// HTTP URL
+    Http(String),
+    /// Unix 套接字路径
+    Unix(std::path::PathBuf),
+    /// Windows 命名管道名称
+    Pipe(String),
+}
+
+impl StatsEndpoint {
+    /// 解析地址，未带 `unix:`/`pipe:` 前缀时视为 HTTP URL
+    pub fn parse(value: &str) -> Result<Self> {
+        if let Some(path) = value.strip_prefix("unix:") {
+            if path.is_empty() {
+                bail!("Unix socket path cannot be empty");
+            }
+            Ok(Self::Unix(path.into()))
+        } else if let Some(name) = value.strip_prefix("pipe:") {
+            if name.is_empty() {
+                bail!("Named pipe name cannot be empty");
+            }
+            Ok(Self::Pipe(name.to_string()))
+        } else {
+            Ok(Self::Http(value.trim_end_matches('/').to_string()))
+        }
+    }
+
+    /// 发送 GET 请求并返回响应体（非 200 响应视为错误）
+    pub async fn get(&self, target: &str) -> Result<String> {
+        match self {
+            Self::Http(url) => {
+                let response = reqwest::get(format!("{}{}", url, target)).await?;
+                let status = response.status();
+                let body = response.text().await?;
+                if !status.is_success() {
+                    bail!("{} returned {}: {}", url, status, body.trim());
+                }
+                Ok(body)
+            }
+            #[cfg(unix)]
+            Self::Unix(path) => {
+                let stream = tokio::net::UnixStream::connect(path)
+                    .await
+                    .with_context(|| format!("Failed to connect to {}", path.display()))?;
+                get_over_stream(stream, target).await
+            }
+            #[cfg(not(unix))]
+            Self::Unix(_) => bail!("Unix sockets are not supported on this platform"),
+            #[cfg(windows)]
+            Self::Pipe(name) => {
+                let stream = open_named_pipe(name).await?;
+                get_over_stream(stream, target).await
+            }
+            #[cfg(not(windows))]
+            Self::Pipe(_) => bail!("Named pipes are only supported on Windows"),
+        }
+    }
+}
+
+impl std::fmt::Display for StatsEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Http(url) => write!(f, "{}", url),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+            Self::Pipe(name) => write!(f, "pipe:{}", name),
+        }
+    }
+}
+
+/// 连接命名管道，所有实例都忙时稍后重试
+#[cfg(windows)]
+async fn open_named_pipe(name: &str) -> Result<tokio::net::windows::named_pipe::NamedPipeClient> {
+    const ERROR_PIPE_BUSY: i32 = 231;
+    let mut attempts = 0;
+    loop {
+        match tokio::net::windows::named_pipe::ClientOptions::new().open(name) {
+            Ok(client) => return Ok(client),
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) && attempts < 50 => {
+                attempts += 1;
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to open {}", name)),
+        }
+    }
+}
+
+/// 在本地连接上发送 HTTP/1.1 GET 请求，读取到连接关闭为止并返回响应体
+#[cfg_attr(not(any(unix, windows)), allow(dead_code))]
+async fn get_over_stream<S>(mut stream: S, target: &str) -> Result<String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        target
+    );
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+
+    let response = String::from_utf8_lossy(&response);
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| anyhow!("Malformed HTTP response"))?;
+    let status_line = head.lines().next().unwrap_or_default();
+    let status = status_line.split_whitespace().nth(1).unwrap_or_default();
+    if status != "200" {
+        bail!("Stats server returned '{}': {}", status_line, body.trim());
+    }
+    Ok(body.to_string())
+}
+
+/// 转义嵌入 HTML 页面的文本
+pub(crate) fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use tokio::net::TcpStream;
+
+    fn test_handler(request: &HttpRequest) -> HttpResponse {
+        match request.path() {
+            "/stats" => HttpResponse::json("[]".to_string()),
+            "/echo" => HttpResponse::json(format!(
+                "{} {}",
+                request.method,
+                request.form_param("value").unwrap_or_default()
+            )),
+            _ => HttpResponse::not_found(),
+        }
+    }
+
+    async fn start_test_server(limits: StatsLimitConfig) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, limits, test_handler));
+        addr
+    }
+
+    async fn request(addr: SocketAddr, raw: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(raw).await.unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        String::from_utf8_lossy(&response).to_string()
+    }
+
+    #[tokio::test]
+    async fn test_routes_request() {
+        let addr = start_test_server(StatsLimitConfig::default()).await;
+
+        let response = request(addr, b"GET /stats HTTP/1.1\r\nHost: x\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("[]"));
+
+        let response = request(addr, b"GET /missing HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 404"));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_request_rejected() {
+        let addr = start_test_server(StatsLimitConfig {
+            max_request_size: 64,
+            ..Default::default()
+        })
+        .await;
+
+        let mut raw = b"GET /stats HTTP/1.1\r\n".to_vec();
+        raw.extend(std::iter::repeat_n(b'a', 256));
+        let response = request(addr, &raw).await;
+        assert!(response.starts_with("HTTP/1.1 413"));
+    }
+
+    #[tokio::test]
+    async fn test_connection_limit() {
+        let addr = start_test_server(StatsLimitConfig {
+            max_connections: 1,
+            ..Default::default()
+        })
+        .await;
+
+        // 占住唯一的连接名额
+        let _idle = TcpStream::connect(addr).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let response = request(addr, b"GET /stats HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 503"));
+    }
+
+    #[tokio::test]
+    async fn test_request_body() {
+        let addr = start_test_server(StatsLimitConfig::default()).await;
+
+        let response = request(
+            addr,
+            b"POST /echo HTTP/1.1\r\nContent-Length: 15\r\n\r\nvalue=a%2Eb+c&x",
+        )
+        .await;
+        assert!(response.ends_with("POST a.b c"));
+
+        // 声明的请求体超过上限
+        let response = request(
+            addr,
+            b"POST /echo HTTP/1.1\r\nContent-Length: 100000\r\n\r\nvalue=1",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 413"));
+    }
+
+    #[test]
+    fn test_parse_endpoint() {
+        assert_eq!(
+            StatsEndpoint::parse("http://127.0.0.1:9000/").unwrap(),
+            StatsEndpoint::Http("http://127.0.0.1:9000".to_string())
+        );
+        assert_eq!(
+            StatsEndpoint::parse("unix:/run/tls-tunnel/stats.sock").unwrap(),
+            StatsEndpoint::Unix("/run/tls-tunnel/stats.sock".into())
+        );
+        assert_eq!(
+            StatsEndpoint::parse(r"pipe:\\.\pipe\tls-tunnel-client").unwrap(),
+            StatsEndpoint::Pipe(r"\\.\pipe\tls-tunnel-client".to_string())
+        );
+        assert!(StatsEndpoint::parse("unix:").is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_serve_over_unix_socket() {
+        let path =
+            std::env::temp_dir().join(format!("tls-tunnel-stats-{}.sock", uuid::Uuid::new_v4()));
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        tokio::spawn(serve(listener, StatsLimitConfig::default(), test_handler));
+
+        let endpoint = StatsEndpoint::Unix(path.clone());
+        assert_eq!(endpoint.get("/stats").await.unwrap(), "[]");
+        let err = endpoint.get("/missing").await.unwrap_err();
+        assert!(err.to_string().contains("404"));
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[cfg(windows)]
+    #[tokio::test]
+    async fn test_serve_over_named_pipe() {
+        let name = format!(r"\\.\pipe\tls-tunnel-stats-{}", uuid::Uuid::new_v4());
+        let listener = NamedPipeListener::bind(name.clone()).unwrap();
+        tokio::spawn(serve(listener, StatsLimitConfig::default(), test_handler));
+
+        let endpoint = StatsEndpoint::Pipe(name);
+        // 连续请求，确认每个连接后都会创建新的管道实例
+        for _ in 0..3 {
+            assert_eq!(endpoint.get("/stats").await.unwrap(), "[]");
+        }
+        let err = endpoint.get("/missing").await.unwrap_err();
+        assert!(err.to_string().contains("404"));
+    }
+
+    #[test]
+    fn test_json_array_stream_format() {
+        let items = vec![
+            serde_json::json!({"name": "a", "nested": {"value": 1}}),
+            serde_json::json!({"name": "b"}),
+        ];
+        let pretty = BodyStream::json_array(items.clone(), true).collect();
+        assert_eq!(
+            String::from_utf8(pretty).unwrap(),
+            serde_json::to_string_pretty(&items).unwrap()
+        );
+        let compact = BodyStream::json_array(items.clone(), false).collect();
+        assert_eq!(
+            String::from_utf8(compact).unwrap(),
+            serde_json::to_string(&items).unwrap()
+        );
+        let empty = BodyStream::json_array(Vec::<u32>::new(), true).collect();
+        assert_eq!(empty, b"[]");
+    }
+
+    #[test]
+    fn test_request_helpers() {
+        let request = HttpRequest::parse_head(
+            b"GET /routing/test?target=a.example.com%3A443 HTTP/1.1\r\nauthorization: Basic dXNlcjpzM2NyZXQ=\r\n\r\n",
+        );
+        assert_eq!(request.path(), "/routing/test");
+        assert_eq!(
+            request.query_param("target").as_deref(),
+            Some("a.example.com:443")
+        );
+        assert_eq!(request.basic_auth_password().as_deref(), Some("s3cret"));
+        assert_eq!(HttpRequest::get("/").basic_auth_password(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_supervise_gives_up_after_max_rebind_attempts() {
+        let servers = StatsServers::default();
+        let health = servers.register("Stats server test");
+        let limits = StatsLimitConfig {
+            max_rebind_attempts: Some(2),
+            ..Default::default()
+        };
+
+        let mut binds = 0;
+        let start = Instant::now();
+        let result = supervise(
+            health.clone(),
+            limits,
+            || {
+                binds += 1;
+                std::future::ready(Err::<TcpListener, _>(anyhow!("port in use")))
+            },
+            test_handler,
+        )
+        .await;
+
+        // 首次绑定加 2 次重新绑定，之间按 0.5 秒、1 秒退避
+        assert_eq!(binds, 3);
+        assert_eq!(start.elapsed(), Duration::from_millis(1500));
+        assert_eq!(result.unwrap_err().to_string(), "port in use");
+
+        let report = servers.report();
+        assert!(!report.stats_server_healthy);
+        assert_eq!(report.servers[0].last_error.as_deref(), Some("port in use"));
+        assert_eq!(report.servers[0].rebinds, 0);
+        assert_eq!(servers.respond().status, 503);
+    }
+}
@@ -0,0 +1,369 @@
+/// 统计服务器的 WebSocket 实时推送（`/stats/ws`）
+///
+/// 连接建立后（配置了 `auth_token` 时先完成认证）推送一帧完整快照，之后每隔
+/// `min_interval_ms` 推送一帧增量：内容有变化的代理、已消失的代理名称，以及期间收到的
+/// 注册表事件；没有变化时不推送。同一时刻只有一帧在写出，慢速的客户端错过的变化会合并到
+/// 下一帧中；待发送事件超过 `max_pending_events` 或事件订阅落后时改为推送完整快照，
+/// 每个连接占用的内存有上限。推送是只读的，客户端发来的其他消息被忽略
+use crate::config::{StatsLimitConfig, StatsStreamConfig};
+use crate::stats_http::{HttpRequest, HttpResponse, Upgrade, UpgradedConn};
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{interval, timeout, MissedTickBehavior};
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Message, Role};
+use tokio_tungstenite::WebSocketStream;
+use tracing::{debug, warn};
+
+/// 推送的数据来源
+pub trait StatsFeed: Send + Sync + 'static {
+    /// 注册表事件
+    type Event: Serialize + Clone + Send + 'static;
+
+    /// 当前所有代理的统计（名称及序列化后的内容）
+    fn snapshot(&self) -> Vec<(String, Value)>;
+
+    /// 订阅注册表事件（没有注册表时返回 None）
+    fn subscribe(&self) -> Option<broadcast::Receiver<Self::Event>>;
+}
+
+/// 处理 `/stats/ws` 请求：合法的 WebSocket 握手返回 101 并接管连接，
+/// 普通 HTTP 请求返回 426，握手头不完整时返回 400
+pub fn respond<F: StatsFeed>(
+    request: &HttpRequest,
+    config: StatsStreamConfig,
+    limits: &StatsLimitConfig,
+    feed: F,
+) -> HttpResponse {
+    let has_token = |name: &str, token: &str| {
+        request.header(name).is_some_and(|value| {
+            value
+                .split(',')
+                .any(|item| item.trim().eq_ignore_ascii_case(token))
+        })
+    };
+    if !has_token("Upgrade", "websocket") || !has_token("Connection", "upgrade") {
+        return HttpResponse::error(426).with_header("Upgrade", "websocket");
+    }
+    let key = match request.header("Sec-WebSocket-Key") {
+        Some(key)
+            if request.method == "GET" && request.header("Sec-WebSocket-Version") == Some("13") =>
+        {
+            key
+        }
+        _ => {
+            return HttpResponse::error(400).with_header("Sec-WebSocket-Version", "13");
+        }
+    };
+
+    let settings = Settings {
+        min_interval: Duration::from_millis(config.min_interval_ms),
+        max_pending_events: config.max_pending_events,
+        auth_token: config.auth_token,
+        read_timeout: Duration::from_secs(limits.read_timeout_secs),
+        write_timeout: Duration::from_secs(limits.write_timeout_secs),
+    };
+    HttpResponse::switching_protocols(Upgrade::new(move |conn| run(conn, feed, settings)))
+        .with_header("Upgrade", "websocket")
+        .with_header("Connection", "Upgrade")
+        .with_header("Sec-WebSocket-Accept", derive_accept_key(key.as_bytes()))
+}
+
+/// 单个连接的推送参数
+struct Settings {
+    min_interval: Duration,
+    max_pending_events: usize,
+    auth_token: Option<String>,
+    read_timeout: Duration,
+    write_timeout: Duration,
+}
+
+/// 连接结束的原因（仅用于日志）
+#[derive(Debug)]
+enum Closed {
+    /// 客户端关闭连接或连接出错
+    Peer,
+    /// 在写超时内没有写完一帧
+    WriteTimeout,
+    /// 认证失败
+    Unauthorized,
+}
+
+async fn run<F: StatsFeed>(conn: UpgradedConn, feed: F, settings: Settings) {
+    let mut ws = WebSocketStream::from_raw_socket(conn, Role::Server, None).await;
+    // 先订阅再取快照，快照之后的变化不会遗漏
+    let mut events = feed.subscribe();
+    let result = async {
+        authenticate(&mut ws, &settings).await?;
+        stream(&mut ws, &feed, &mut events, &settings).await
+    }
+    .await;
+    if let Err(reason) = result {
+        debug!("Stats stream closed: {:?}", reason);
+    }
+}
+
+/// 配置了认证令牌时，第一条消息必须是 `{"type":"auth","token":"..."}`
+async fn authenticate(
+    ws: &mut WebSocketStream<UpgradedConn>,
+    settings: &Settings,
+) -> Result<(), Closed> {
+    let Some(ref expected) = settings.auth_token else {
+        return Ok(());
+    };
+    let token = match timeout(settings.read_timeout, ws.next()).await {
+        Ok(Some(Ok(Message::Text(text)))) => serde_json::from_str::<Value>(&text)
+            .ok()
+            .filter(|message| message["type"] == "auth")
+            .and_then(|message| message["token"].as_str().map(str::to_string)),
+        Ok(None) | Ok(Some(Err(_))) => return Err(Closed::Peer),
+        _ => None,
+    };
+    if token.as_deref() == Some(expected.as_str()) {
+        return Ok(());
+    }
+    warn!("Stats stream client failed to authenticate, closing");
+    let frame = CloseFrame {
+        code: CloseCode::Policy,
+        reason: "authentication failed".into(),
+    };
+    timeout(settings.write_timeout, ws.close(Some(frame)))
+        .await
+        .ok();
+    Err(Closed::Unauthorized)
+}
+
+/// 推送快照和增量，直到连接关闭
+async fn stream<F: StatsFeed>(
+    ws: &mut WebSocketStream<UpgradedConn>,
+    feed: &F,
+    events: &mut Option<broadcast::Receiver<F::Event>>,
+    settings: &Settings,
+) -> Result<(), Closed> {
+    let mut frames = Frames::default();
+    let mut pending = PendingEvents::new(settings.max_pending_events);
+    send(ws, frames.snapshot(feed.snapshot()), settings).await?;
+
+    let mut ticker = interval(settings.min_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    ticker.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let current = feed.snapshot();
+                let frame = match pending.take() {
+                    Some(events) => frames.delta(current, events),
+                    None => Some(frames.snapshot(current)),
+                };
+                if let Some(frame) = frame {
+                    send(ws, frame, settings).await?;
+                }
+            }
+            event = next_event(events) => match event {
+                Ok(event) => pending.push(serde_json::to_value(event).unwrap_or_default()),
+                Err(RecvError::Lagged(_)) => pending.overflow(),
+                Err(RecvError::Closed) => *events = None,
+            },
+            message = ws.next() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return Err(Closed::Peer),
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// 在写超时内写出一帧
+async fn send(
+    ws: &mut WebSocketStream<UpgradedConn>,
+    frame: Value,
+    settings: &Settings,
+) -> Result<(), Closed> {
+    match timeout(
+        settings.write_timeout,
+        ws.send(Message::text(frame.to_string())),
+    )
+    .await
+    {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(_)) => Err(Closed::Peer),
+        Err(_) => {
+            warn!(
+                "Stats stream client did not accept a frame within {}s, closing",
+                settings.write_timeout.as_secs()
+            );
+            Err(Closed::WriteTimeout)
+        }
+    }
+}
+
+/// 下一个注册表事件（没有订阅时一直等待）
+async fn next_event<E: Clone>(events: &mut Option<broadcast::Receiver<E>>) -> Result<E, RecvError> {
+    match events {
+        Some(events) => events.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 待发送的注册表事件
+struct PendingEvents {
+    events: Vec<Value>,
+    limit: usize,
+    /// 事件超出上限或订阅落后，下一帧改为完整快照
+    overflowed: bool,
+}
+
+impl PendingEvents {
+    fn new(limit: usize) -> Self {
+        Self {
+            events: Vec::new(),
+            limit,
+            overflowed: false,
+        }
+    }
+
+    fn push(&mut self, event: Value) {
+        if self.overflowed {
+            return;
+        }
+        if self.events.len() >= self.limit {
+            self.overflow();
+        } else {
+            self.events.push(event);
+        }
+    }
+
+    fn overflow(&mut self) {
+        self.overflowed = true;
+        self.events = Vec::new();
+    }
+
+    /// 取出待发送事件；溢出过时返回 None（应发送完整快照）
+    fn take(&mut self) -> Option<Vec<Value>> {
+        let overflowed = std::mem::take(&mut self.overflowed);
+        let events = std::mem::take(&mut self.events);
+        (!overflowed).then_some(events)
+    }
+}
+
+/// 生成推送帧，记录上一次发出的各代理内容用于计算增量
+#[derive(Default)]
+struct Frames {
+    seq: u64,
+    sent: BTreeMap<String, Value>,
+}
+
+impl Frames {
+    /// 完整快照帧
+    fn snapshot(&mut self, current: Vec<(String, Value)>) -> Value {
+        self.sent = current.into_iter().collect();
+        self.seq += 1;
+        json!({
+            "type": "snapshot",
+            "seq": self.seq,
+            "timestamp": unix_now(),
+            "proxies": self.sent.values().collect::<Vec<_>>(),
+        })
+    }
+
+    /// 增量帧，没有任何变化时返回 None
+    fn delta(&mut self, current: Vec<(String, Value)>, events: Vec<Value>) -> Option<Value> {
+        let current: BTreeMap<String, Value> = current.into_iter().collect();
+        let changed: Vec<&Value> = current
+            .iter()
+            .filter(|(name, value)| self.sent.get(*name) != Some(*value))
+            .map(|(_, value)| value)
+            .collect();
+        let removed: Vec<&String> = self
+            .sent
+            .keys()
+            .filter(|name| !current.contains_key(*name))
+            .collect();
+        if changed.is_empty() && removed.is_empty() && events.is_empty() {
+            return None;
+        }
+
+        self.seq += 1;
+        let frame = json!({
+            "type": "delta",
+            "seq": self.seq,
+            "timestamp": unix_now(),
+            "changed": changed,
+            "removed": removed,
+            "events": events,
+        });
+        self.sent = current;
+        Some(frame)
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy(name: &str, bytes: u64) -> (String, Value) {
+        (name.to_string(), json!({"name": name, "bytes": bytes}))
+    }
+
+    #[test]
+    fn test_delta_contains_only_changes() {
+        let mut frames = Frames::default();
+        let snapshot = frames.snapshot(vec![proxy("a", 0), proxy("b", 0)]);
+        assert_eq!(snapshot["type"], "snapshot");
+        assert_eq!(snapshot["seq"], 1);
+        assert_eq!(snapshot["proxies"].as_array().unwrap().len(), 2);
+
+        assert!(frames
+            .delta(vec![proxy("a", 0), proxy("b", 0)], Vec::new())
+            .is_none());
+
+        let delta = frames
+            .delta(vec![proxy("a", 10), proxy("c", 0)], Vec::new())
+            .unwrap();
+        assert_eq!(delta["type"], "delta");
+        assert_eq!(delta["seq"], 2);
+        assert_eq!(
+            delta["changed"],
+            json!([{"name": "a", "bytes": 10}, {"name": "c", "bytes": 0}])
+        );
+        assert_eq!(delta["removed"], json!(["b"]));
+    }
+
+    #[test]
+    fn test_delta_sent_for_events_alone() {
+        let mut frames = Frames::default();
+        frames.snapshot(vec![proxy("a", 0)]);
+        let delta = frames
+            .delta(vec![proxy("a", 0)], vec![json!({"event": "registered"})])
+            .unwrap();
+        assert_eq!(delta["changed"], json!([]));
+        assert_eq!(delta["events"], json!([{"event": "registered"}]));
+    }
+
+    #[test]
+    fn test_pending_events_overflow_requests_snapshot() {
+        let mut pending = PendingEvents::new(2);
+        pending.push(json!(1));
+        pending.push(json!(2));
+        assert_eq!(pending.take(), Some(vec![json!(1), json!(2)]));
+
+        for i in 0..3 {
+            pending.push(json!(i));
+        }
+        assert_eq!(pending.take(), None);
+        // 溢出后重新开始缓存
+        pending.push(json!(4));
+        assert_eq!(pending.take(), Some(vec![json!(4)]));
+    }
+}
@@ -0,0 +1,169 @@
+#[cfg(unix)]
+use std::io;
+/// systemd 集成（sd_notify 协议）
+///
+/// 启用 `systemd` 特性后，在 systemd 下运行时（存在 `INVOCATION_ID` 或 `JOURNAL_STREAM`）
+/// 向 `NOTIFY_SOCKET` 报告状态：服务器绑定监听端口或客户端会话进入运行状态时发送 `READY=1`，
+/// 开始停止时发送 `STOPPING=1`，设置了 `WATCHDOG_USEC` 时由主事件循环定期发送 `WATCHDOG=1`，
+/// 事件循环卡死时由 systemd 重启服务。未启用特性时这些调用都是空操作。
+use std::time::Duration;
+#[cfg(unix)]
+use tracing::{debug, warn};
+
+/// 是否在 systemd 环境中运行（systemd 会设置 INVOCATION_ID 或 JOURNAL_STREAM 环境变量）
+pub fn is_systemd() -> bool {
+    std::env::var_os("INVOCATION_ID").is_some() || std::env::var_os("JOURNAL_STREAM").is_some()
+}
+
+/// 向 `NOTIFY_SOCKET` 发送一条状态消息，未设置该变量时返回 `Ok(false)`
+///
+/// 不受 `systemd` 特性控制，供监听交接更新 `MAINPID` 使用
+#[cfg(unix)]
+pub fn notify(state: &str) -> io::Result<bool> {
+    match std::env::var_os("NOTIFY_SOCKET") {
+        Some(path) => send_to(&path, state).map(|_| true),
+        None => Ok(false),
+    }
+}
+
+/// 向指定的通知套接字发送状态消息
+#[cfg(unix)]
+fn send_to(path: &std::ffi::OsStr, state: &str) -> io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound()?;
+    let message = format!("{}\n", state);
+
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::net::SocketAddr as UnixAddr;
+
+        // 以 '@' 开头的是抽象命名空间地址
+        if let Some(name) = path.to_str().and_then(|p| p.strip_prefix('@')) {
+            let addr = UnixAddr::from_abstract_name(name)?;
+            socket.send_to_addr(message.as_bytes(), &addr)?;
+            return Ok(());
+        }
+    }
+
+    socket.send_to(message.as_bytes(), path)?;
+    Ok(())
+}
+
+/// 是否向 systemd 报告服务状态
+fn enabled() -> bool {
+    cfg!(all(unix, feature = "systemd")) && is_systemd()
+}
+
+/// 按 `systemd` 特性和运行环境决定是否发送状态消息，失败时只记录日志
+fn notify_state(state: &str) {
+    if !enabled() {
+        return;
+    }
+    #[cfg(not(unix))]
+    let _ = state;
+    #[cfg(unix)]
+    match notify(state) {
+        Ok(true) => debug!("Notified systemd: {}", state),
+        Ok(false) => {}
+        Err(e) => warn!("Failed to notify systemd ({}): {}", state, e),
+    }
+}
+
+/// 报告服务已就绪
+pub fn notify_ready() {
+    notify_state("READY=1");
+}
+
+/// 报告服务开始停止
+pub fn notify_stopping() {
+    notify_state("STOPPING=1");
+}
+
+/// systemd 看门狗
+///
+/// 不检查 `WATCHDOG_PID`：监听交接后的新进程（`MAINPID` 已更新）需要继续喂狗
+pub struct Watchdog {
+    interval: Option<tokio::time::Interval>,
+}
+
+impl Watchdog {
+    /// 按 `WATCHDOG_USEC` 创建看门狗，以超时时间的一半发送心跳；未启用时 [`Self::keepalive`]
+    /// 永远不会完成
+    pub fn from_env() -> Self {
+        let period = if enabled() {
+            std::env::var("WATCHDOG_USEC")
+                .ok()
+                .and_then(|usec| watchdog_period(&usec))
+        } else {
+            None
+        };
+        let interval = period.map(|period| {
+            let mut interval = tokio::time::interval(period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            interval
+        });
+        Self { interval }
+    }
+
+    /// 看门狗是否启用
+    pub fn is_enabled(&self) -> bool {
+        self.interval.is_some()
+    }
+
+    /// 等到下一次心跳时间并发送 `WATCHDOG=1`（可安全地在 `select!` 中取消）
+    pub async fn keepalive(&mut self) {
+        match self.interval.as_mut() {
+            Some(interval) => {
+                interval.tick().await;
+                notify_state("WATCHDOG=1");
+            }
+            None => std::future::pending().await,
+        }
+    }
+}
+
+/// 由 `WATCHDOG_USEC` 计算心跳间隔（超时时间的一半）
+fn watchdog_period(usec: &str) -> Option<Duration> {
+    let usec: u64 = usec.trim().parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watchdog_period_is_half_of_timeout() {
+        assert_eq!(watchdog_period("30000000"), Some(Duration::from_secs(15)));
+        assert_eq!(watchdog_period("0"), None);
+        assert_eq!(watchdog_period("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_watchdog_never_fires() {
+        let mut watchdog = Watchdog { interval: None };
+        assert!(!watchdog.is_enabled());
+        let result = tokio::time::timeout(Duration::from_millis(50), watchdog.keepalive()).await;
+        assert!(result.is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_notify_sends_datagram() {
+        use std::os::unix::net::UnixDatagram;
+
+        let path =
+            std::env::temp_dir().join(format!("tls-tunnel-notify-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        send_to(path.as_os_str(), "READY=1").unwrap();
+
+        let mut buf = [0u8; 64];
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1\n");
+        let _ = std::fs::remove_file(&path);
+    }
+}
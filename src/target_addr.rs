@@ -0,0 +1,263 @@
+/// 转发目标地址
+///
+/// forwarder（SOCKS5 / HTTP）解析出的目标统一表示为 `TargetAddr`，安全检查、
+/// 路由和 `@forward:` 前导都基于该类型；显示时 IPv6 地址带方括号（`[::1]:443`）
+use anyhow::{bail, Result};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::str::FromStr;
+use tokio::net::TcpStream;
+
+/// 目标地址：域名 + 端口，或 IP 套接字地址
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TargetAddr {
+    Domain(String, u16),
+    Ip(SocketAddr),
+}
+
+impl TargetAddr {
+    /// 解析 `host:port`（IPv6 需要方括号，如 `[::1]:443`）
+    pub fn parse(input: &str) -> Result<Self> {
+        Self::parse_inner(input, None)
+    }
+
+    /// 解析 `host[:port]`，未指定端口时使用 `default_port`
+    ///
+    /// 不带方括号的 IPv6 地址（如 `::1`）视为未指定端口
+    pub fn parse_with_default_port(input: &str, default_port: u16) -> Result<Self> {
+        Self::parse_inner(input, Some(default_port))
+    }
+
+    /// 由主机名和端口构造（主机名为 IP 字面量时得到 `Ip`）
+    pub fn from_host_port(host: &str, port: u16) -> Result<Self> {
+        let host = host
+            .strip_prefix('[')
+            .and_then(|h| h.strip_suffix(']'))
+            .unwrap_or(host);
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(TargetAddr::Ip(SocketAddr::new(ip, port)));
+        }
+        validate_domain(host)?;
+        Ok(TargetAddr::Domain(host.to_string(), port))
+    }
+
+    fn parse_inner(input: &str, default_port: Option<u16>) -> Result<Self> {
+        let input = input.trim();
+        if input.is_empty() {
+            bail!("Empty target address");
+        }
+
+        // [IPv6] 或 [IPv6]:port
+        if let Some(rest) = input.strip_prefix('[') {
+            let Some((host, after)) = rest.split_once(']') else {
+                bail!("Invalid target address '{}': missing ']'", input);
+            };
+            let ip = host
+                .parse::<std::net::Ipv6Addr>()
+                .map_err(|_| anyhow::anyhow!("Invalid IPv6 address in '{}'", input))?;
+            let port = match after.strip_prefix(':') {
+                Some(port) => parse_port(port, input)?,
+                None if after.is_empty() => require_port(default_port, input)?,
+                None => bail!("Invalid target address '{}'", input),
+            };
+            return Ok(TargetAddr::Ip(SocketAddr::new(IpAddr::V6(ip), port)));
+        }
+
+        // 不带方括号的 IP 地址（IPv6 无法携带端口）
+        if let Ok(ip) = input.parse::<IpAddr>() {
+            let port = require_port(default_port, input)?;
+            return Ok(TargetAddr::Ip(SocketAddr::new(ip, port)));
+        }
+
+        match input.rsplit_once(':') {
+            Some((host, port)) => Self::from_host_port(host, parse_port(port, input)?),
+            None => Self::from_host_port(input, require_port(default_port, input)?),
+        }
+    }
+
+    /// 主机部分（域名或 IP，不含方括号）
+    pub fn host(&self) -> String {
+        match self {
+            TargetAddr::Domain(domain, _) => domain.clone(),
+            TargetAddr::Ip(addr) => addr.ip().to_string(),
+        }
+    }
+
+    /// 端口
+    pub fn port(&self) -> u16 {
+        match self {
+            TargetAddr::Domain(_, port) => *port,
+            TargetAddr::Ip(addr) => addr.port(),
+        }
+    }
+
+    /// IP 地址（域名目标返回 None）
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self {
+            TargetAddr::Domain(..) => None,
+            TargetAddr::Ip(addr) => Some(addr.ip()),
+        }
+    }
+
+    /// 解析为套接字地址（域名会触发 DNS 查询）
+    pub fn resolve(&self) -> std::io::Result<Vec<SocketAddr>> {
+        match self {
+            TargetAddr::Domain(domain, port) => {
+                Ok((domain.as_str(), *port).to_socket_addrs()?.collect())
+            }
+            TargetAddr::Ip(addr) => Ok(vec![*addr]),
+        }
+    }
+
+    /// 建立 TCP 连接
+    pub async fn connect(&self) -> std::io::Result<TcpStream> {
+        match self {
+            TargetAddr::Domain(domain, port) => TcpStream::connect((domain.as_str(), *port)).await,
+            TargetAddr::Ip(addr) => TcpStream::connect(addr).await,
+        }
+    }
+}
+
+impl fmt::Display for TargetAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TargetAddr::Domain(domain, port) => write!(f, "{}:{}", domain, port),
+            TargetAddr::Ip(addr) => write!(f, "{}", addr),
+        }
+    }
+}
+
+impl FromStr for TargetAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+fn parse_port(port: &str, input: &str) -> Result<u16> {
+    match port.parse::<u16>() {
+        Ok(port) if port != 0 => Ok(port),
+        _ => bail!("Invalid port in target address '{}'", input),
+    }
+}
+
+fn require_port(default_port: Option<u16>, input: &str) -> Result<u16> {
+    match default_port {
+        Some(port) => Ok(port),
+        None => bail!("Missing port in target address '{}'", input),
+    }
+}
+
+fn validate_domain(domain: &str) -> Result<()> {
+    if domain.is_empty() || domain.len() > 255 {
+        bail!(
+            "Invalid domain '{}': length must be 1-255 characters",
+            domain
+        );
+    }
+    if !domain
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_'))
+    {
+        bail!("Invalid domain '{}'", domain);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn test_parse_ipv6() {
+        let target = TargetAddr::parse("[::1]:443").unwrap();
+        assert_eq!(
+            target,
+            TargetAddr::Ip(SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 443))
+        );
+        assert_eq!(target.to_string(), "[::1]:443");
+        assert_eq!(target.host(), "::1");
+
+        // 不带方括号的 IPv6 视为未指定端口
+        let target = TargetAddr::parse_with_default_port("2001:db8::1", 80).unwrap();
+        assert_eq!(target.to_string(), "[2001:db8::1]:80");
+        assert!(TargetAddr::parse("2001:db8::1").is_err());
+
+        // 带方括号但未指定端口
+        let target = TargetAddr::parse_with_default_port("[2001:db8::1]", 8080).unwrap();
+        assert_eq!(target.port(), 8080);
+        assert!(TargetAddr::parse("[2001:db8::1]").is_err());
+
+        assert!(TargetAddr::parse("[::1").is_err());
+        assert!(TargetAddr::parse("[not-ip]:80").is_err());
+        assert!(TargetAddr::parse("[::1]80").is_err());
+    }
+
+    #[test]
+    fn test_parse_ipv4_and_domains() {
+        let target = TargetAddr::parse("127.0.0.1:8080").unwrap();
+        assert_eq!(
+            target,
+            TargetAddr::Ip(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 8080))
+        );
+        assert_eq!(
+            TargetAddr::parse_with_default_port("10.0.0.1", 80)
+                .unwrap()
+                .to_string(),
+            "10.0.0.1:80"
+        );
+
+        let target = TargetAddr::parse("example.com:443").unwrap();
+        assert_eq!(target, TargetAddr::Domain("example.com".to_string(), 443));
+        assert_eq!(target.to_string(), "example.com:443");
+        assert!(target.ip().is_none());
+
+        assert_eq!(
+            TargetAddr::parse_with_default_port("example.com", 80).unwrap(),
+            TargetAddr::Domain("example.com".to_string(), 80)
+        );
+        assert!(TargetAddr::parse("example.com").is_err());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(TargetAddr::parse("").is_err());
+        assert!(TargetAddr::parse(":80").is_err());
+        assert!(TargetAddr::parse("example.com:").is_err());
+        assert!(TargetAddr::parse("example.com:0").is_err());
+        assert!(TargetAddr::parse("example.com:65536").is_err());
+        assert!(TargetAddr::parse("exa mple.com:80").is_err());
+        assert!(TargetAddr::parse("example.com/path:80").is_err());
+    }
+
+    #[test]
+    fn test_from_host_port() {
+        assert_eq!(
+            TargetAddr::from_host_port("::1", 22).unwrap().to_string(),
+            "[::1]:22"
+        );
+        assert_eq!(
+            TargetAddr::from_host_port("[::1]", 22).unwrap().to_string(),
+            "[::1]:22"
+        );
+        assert_eq!(
+            TargetAddr::from_host_port("192.168.1.1", 22).unwrap(),
+            TargetAddr::Ip("192.168.1.1:22".parse().unwrap())
+        );
+        assert_eq!(
+            TargetAddr::from_host_port("localhost", 22).unwrap(),
+            TargetAddr::Domain("localhost".to_string(), 22)
+        );
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for input in ["[2001:db8::1]:443", "1.2.3.4:80", "api.example.com:8443"] {
+            let target = TargetAddr::parse(input).unwrap();
+            assert_eq!(target.to_string(), input);
+            assert_eq!(TargetAddr::parse(&target.to_string()).unwrap(), target);
+        }
+    }
+}
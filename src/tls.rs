@@ -1,6 +1,12 @@
+use crate::config::ClientConfig;
+use crate::transport::TransportType;
 use anyhow::{Context, Result};
-use rcgen::generate_simple_self_signed;
-use rustls::pki_types::CertificateDer;
+use rcgen::{
+    generate_simple_self_signed, BasicConstraints, CertificateParams, CertifiedIssuer, DnType,
+    ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose,
+};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
@@ -19,26 +25,48 @@ pub fn load_server_config_with_alpn(
     key_path: &Path,
     alpn_protocols: Option<Vec<Vec<u8>>>,
 ) -> Result<Arc<rustls::ServerConfig>> {
-    // 加载证书
-    let cert_file = File::open(cert_path)
-        .with_context(|| format!("Failed to open cert file: {:?}", cert_path))?;
-    let mut cert_reader = BufReader::new(cert_file);
-    let certs: Vec<CertificateDer> = rustls_pemfile::certs(&mut cert_reader)
-        .collect::<Result<Vec<_>, _>>()
-        .context("Failed to parse certificates")?;
-
-    // 加载私钥
-    let key_file =
-        File::open(key_path).with_context(|| format!("Failed to open key file: {:?}", key_path))?;
-    let mut key_reader = BufReader::new(key_file);
-
-    let key = rustls_pemfile::private_key(&mut key_reader)
-        .context("Failed to parse private key")?
-        .context("No private key found")?;
+    load_server_config_with_client_auth(cert_path, key_path, alpn_protocols, None, false)
+}
+
+/// 加载服务器 TLS 配置，并按 `client_ca_path` 验证客户端证书（mTLS）
+///
+/// `require_client_cert` 为 true 时没有出示证书的客户端在握手阶段被拒绝；
+/// 为 false 时允许不带证书的客户端，但出示的证书必须由该 CA 签发
+pub fn load_server_config_with_client_auth(
+    cert_path: &Path,
+    key_path: &Path,
+    alpn_protocols: Option<Vec<Vec<u8>>>,
+    client_ca_path: Option<&Path>,
+    require_client_cert: bool,
+) -> Result<Arc<rustls::ServerConfig>> {
+    let certs = load_certs(cert_path).context("Failed to parse certificates")?;
+    let key = load_private_key(key_path)?;
 
     // 创建 TLS 配置
-    let mut config = rustls::ServerConfig::builder()
-        .with_no_client_auth()
+    let builder = rustls::ServerConfig::builder();
+    let builder = match client_ca_path {
+        Some(ca_path) => {
+            let mut roots = rustls::RootCertStore::empty();
+            for cert in load_certs(ca_path).context("Failed to parse client CA certificates")? {
+                roots
+                    .add(cert)
+                    .context("Failed to add client CA certificate to trust store")?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots));
+            let verifier = if require_client_cert {
+                verifier.build()
+            } else {
+                verifier.allow_unauthenticated().build()
+            }
+            .context("Failed to create client certificate verifier")?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None if require_client_cert => {
+            anyhow::bail!("require_client_cert needs client_ca_path to verify client certificates")
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder
         .with_single_cert(certs, key)
         .context("Failed to create server config")?;
 
@@ -64,6 +92,31 @@ pub fn load_client_config_with_alpn(
     ca_cert_path: Option<&Path>,
     skip_verify: bool,
     alpn_protocols: Option<Vec<Vec<u8>>>,
+) -> Result<Arc<rustls::ClientConfig>> {
+    load_client_config_with_cert(ca_cert_path, skip_verify, alpn_protocols, None)
+}
+
+/// 按客户端配置加载 TLS 配置（http2 传输需要 ALPN h2，配置了客户端证书时用于 mTLS）
+pub fn load_client_config_from(config: &ClientConfig) -> Result<Arc<rustls::ClientConfig>> {
+    let alpn_protocols = (config.transport == TransportType::Http2).then(|| vec![b"h2".to_vec()]);
+    let client_cert = config
+        .client_cert_path
+        .as_deref()
+        .zip(config.client_key_path.as_deref());
+    load_client_config_with_cert(
+        config.ca_cert_path.as_deref(),
+        config.skip_verify,
+        alpn_protocols,
+        client_cert,
+    )
+}
+
+/// 加载客户端 TLS 配置，`client_cert` 为 (证书, 私钥) 路径时在握手中出示客户端证书（mTLS）
+pub fn load_client_config_with_cert(
+    ca_cert_path: Option<&Path>,
+    skip_verify: bool,
+    alpn_protocols: Option<Vec<Vec<u8>>>,
+    client_cert: Option<(&Path, &Path)>,
 ) -> Result<Arc<rustls::ClientConfig>> {
     let mut root_store = rustls::RootCertStore::empty();
 
@@ -104,9 +157,16 @@ pub fn load_client_config_with_alpn(
         }
     }
 
-    let mut config = rustls::ClientConfig::builder()
-        .with_root_certificates(root_store)
-        .with_no_client_auth();
+    let builder = rustls::ClientConfig::builder().with_root_certificates(root_store);
+    let mut config = match client_cert {
+        Some((cert_path, key_path)) => {
+            let certs = load_certs(cert_path).context("Failed to parse client certificate")?;
+            builder
+                .with_client_auth_cert(certs, load_private_key(key_path)?)
+                .context("Failed to use client certificate")?
+        }
+        None => builder.with_no_client_auth(),
+    };
 
     // 如果跳过证书验证（仅用于测试）
     if skip_verify {
@@ -123,6 +183,39 @@ pub fn load_client_config_with_alpn(
     Ok(Arc::new(config))
 }
 
+/// 读取 PEM 文件中的全部证书（文件中没有证书时报错）
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).with_context(|| format!("Failed to open cert file: {:?}", path))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file)).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        anyhow::bail!("No certificates found in {:?}", path);
+    }
+    Ok(certs)
+}
+
+/// 读取 PEM 文件中的私钥
+fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).with_context(|| format!("Failed to open key file: {:?}", path))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .context("Failed to parse private key")?
+        .context("No private key found")
+}
+
+/// 握手失败是否因为客户端没有出示有效的证书（服务器配置了 `client_ca_path`）
+pub fn is_client_cert_rejection(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        let tls_error = cause
+            .downcast_ref::<std::io::Error>()
+            .and_then(|e| e.get_ref())
+            .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+            .or_else(|| cause.downcast_ref::<rustls::Error>());
+        matches!(
+            tls_error,
+            Some(rustls::Error::NoCertificatesPresented | rustls::Error::InvalidCertificate(_))
+        )
+    })
+}
+
 /// 生成自签名证书和私钥并写入指定路径
 pub fn generate_self_signed_cert(
     common_name: &str,
@@ -149,12 +242,75 @@ pub fn generate_self_signed_cert(
 
     std::fs::write(cert_out, cert_pem)
         .with_context(|| format!("Failed to write certificate to {:?}", cert_out))?;
-    std::fs::write(key_out, key_pem)
+    write_private_key(key_out, &key_pem)
+        .with_context(|| format!("Failed to write private key to {:?}", key_out))?;
+
+    Ok(())
+}
+
+/// 生成一个 CA 以及由它签发的客户端证书（用于 mTLS）
+///
+/// CA 证书写入 `ca_cert_out`，作为服务器的 `client_ca_path`；客户端证书和私钥写入
+/// `cert_out`/`key_out`，作为客户端的 `client_cert_path`/`client_key_path`。CA 私钥不保存
+pub fn generate_client_cert(
+    common_name: &str,
+    ca_cert_out: &Path,
+    cert_out: &Path,
+    key_out: &Path,
+) -> Result<()> {
+    let mut ca_params = CertificateParams::new(Vec::<String>::new())?;
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, format!("{} CA", common_name));
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    let ca = CertifiedIssuer::self_signed(ca_params, KeyPair::generate()?)
+        .context("Failed to generate client CA certificate")?;
+
+    let mut params = CertificateParams::new(vec![common_name.to_string()])?;
+    params
+        .distinguished_name
+        .push(DnType::CommonName, common_name);
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    let key = KeyPair::generate()?;
+    let cert = params
+        .signed_by(&key, &ca)
+        .context("Failed to sign client certificate")?;
+
+    std::fs::write(ca_cert_out, ca.pem())
+        .with_context(|| format!("Failed to write CA certificate to {:?}", ca_cert_out))?;
+    std::fs::write(cert_out, cert.pem())
+        .with_context(|| format!("Failed to write certificate to {:?}", cert_out))?;
+    write_private_key(key_out, &key.serialize_pem())
         .with_context(|| format!("Failed to write private key to {:?}", key_out))?;
 
     Ok(())
 }
 
+/// 写入私钥文件，unix 上只有所有者可读写
+#[cfg(unix)]
+fn write_private_key(path: &Path, pem: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // mode 只对新建的文件生效，覆盖已有文件（如 cert renew）时需要显式收紧权限
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    file.write_all(pem.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private_key(path: &Path, pem: &str) -> std::io::Result<()> {
+    std::fs::write(path, pem)
+}
+
 /// 不验证证书的验证器（仅用于测试）
 #[derive(Debug)]
 struct NoCertificateVerification;
@@ -1,4 +1,5 @@
 use crate::stats::ProxyStats;
+use crate::stats_http::StatsEndpoint;
 use anyhow::{Context, Result};
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
@@ -18,7 +19,7 @@ use std::time::{Duration, SystemTime};
 
 /// Statistics dashboard state
 pub struct Dashboard {
-    url: String,
+    endpoint: StatsEndpoint,
     interval: Duration,
     stats: Vec<ProxyStats>,
     last_update: Option<SystemTime>,
@@ -26,9 +27,9 @@ pub struct Dashboard {
 }
 
 impl Dashboard {
-    pub fn new(url: String, interval: u64) -> Self {
+    pub fn new(endpoint: StatsEndpoint, interval: u64) -> Self {
         Self {
-            url,
+            endpoint,
             interval: Duration::from_secs(interval),
             stats: Vec::new(),
             last_update: None,
@@ -38,19 +39,14 @@ impl Dashboard {
 
     /// Fetch statistics from server
     async fn fetch_stats(&mut self) -> Result<()> {
-        let url = format!("{}/stats", self.url);
-        let response = reqwest::get(&url)
+        let body = self
+            .endpoint
+            .get("/stats")
             .await
             .context("Failed to fetch statistics")?;
 
-        if !response.status().is_success() {
-            anyhow::bail!("Server returned error: {}", response.status());
-        }
-
-        self.stats = response
-            .json::<Vec<ProxyStats>>()
-            .await
-            .context("Failed to parse statistics")?;
+        self.stats =
+            serde_json::from_str::<Vec<ProxyStats>>(&body).context("Failed to parse statistics")?;
         self.last_update = Some(SystemTime::now());
         self.error_message = None;
 
@@ -85,11 +81,14 @@ impl Dashboard {
                 .unwrap_or_default();
             format!(
                 "TLS Tunnel Statistics - {} - Last update: {}s ago",
-                self.url,
+                self.endpoint,
                 elapsed.as_secs()
             )
         } else {
-            format!("TLS Tunnel Statistics - {} - Waiting for data...", self.url)
+            format!(
+                "TLS Tunnel Statistics - {} - Waiting for data...",
+                self.endpoint
+            )
         };
 
         let header = Paragraph::new(title)
@@ -134,23 +133,23 @@ impl Dashboard {
             .stats
             .iter()
             .map(|stat| {
-                let uptime_secs = now.saturating_sub(stat.start_time);
+                let uptime_secs = now.saturating_sub(stat.core.start_time);
                 let uptime = format_duration(uptime_secs);
-                let bytes_sent = format_bytes(stat.bytes_sent);
-                let bytes_received = format_bytes(stat.bytes_received);
+                let bytes_sent = format_bytes(stat.core.bytes_sent);
+                let bytes_received = format_bytes(stat.core.bytes_received);
 
                 Row::new(vec![
-                    Cell::from(stat.name.clone()),
-                    Cell::from(format!("{}:{}", stat.publish_addr, stat.publish_port)),
-                    Cell::from(stat.local_port.to_string()),
-                    Cell::from(stat.active_connections.to_string()).style(Style::default().fg(
-                        if stat.active_connections > 0 {
+                    Cell::from(stat.core.name.clone()),
+                    Cell::from(stat.publish_label()),
+                    Cell::from(stat.core.target_port.to_string()),
+                    Cell::from(stat.core.active_connections.to_string()).style(
+                        Style::default().fg(if stat.core.active_connections > 0 {
                             Color::Green
                         } else {
                             Color::Gray
-                        },
-                    )),
-                    Cell::from(stat.total_connections.to_string()),
+                        }),
+                    ),
+                    Cell::from(stat.core.total_connections.to_string()),
                     Cell::from(bytes_sent),
                     Cell::from(bytes_received),
                     Cell::from(uptime),
@@ -261,7 +260,9 @@ fn format_duration(seconds: u64) -> String {
 }
 
 /// Run the statistics dashboard
-pub async fn run_dashboard(url: String, interval: u64) -> Result<()> {
+///
+/// `endpoint` is an HTTP URL, `unix:<path>` or `pipe:<name>` (see [`StatsEndpoint`])
+pub async fn run_dashboard(endpoint: StatsEndpoint, interval: u64) -> Result<()> {
     // Setup terminal
     enable_raw_mode().context("Failed to enable raw mode")?;
     let mut stdout = io::stdout();
@@ -270,7 +271,7 @@ pub async fn run_dashboard(url: String, interval: u64) -> Result<()> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend).context("Failed to create terminal")?;
 
-    let mut dashboard = Dashboard::new(url, interval);
+    let mut dashboard = Dashboard::new(endpoint, interval);
 
     // Fetch initial data
     if let Err(e) = dashboard.fetch_stats().await {
@@ -0,0 +1,308 @@
+/// Byte accounting shared by statistics and limit enforcement
+///
+/// Each relayed connection gets one [`TrafficMeter`] and its relay loop adds every chunk to it
+/// exactly once. The meter's counters belong to that connection alone, so adding never
+/// contends with other connections. Everything that needs byte counts reads them from the
+/// meters:
+/// - stats snapshots (proxy, target, routing path and global totals)
+/// - routing quota consumption
+/// - per-stream and per-session forward limits
+///
+/// A meter is attached to one or more [`TrafficScope`]s. Scopes form a tree (a server proxy or
+/// forward session under the server-wide total; a client proxy beside its targets, routing paths
+/// and quota), and attaching a meter to a scope also attaches it to every ancestor. Totals are rolled up lazily: a scope's snapshot is the bytes of meters
+/// that already ended plus the current counters of its live meters, so a snapshot is exact
+/// even while connections are still transferring.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Bytes transferred in each direction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrafficTotals {
+    pub sent: u64,
+    pub received: u64,
+}
+
+impl TrafficTotals {
+    /// Bytes in both directions
+    pub fn total(&self) -> u64 {
+        self.sent.saturating_add(self.received)
+    }
+
+    fn reversed(self) -> Self {
+        Self {
+            sent: self.received,
+            received: self.sent,
+        }
+    }
+
+    fn add(&mut self, other: TrafficTotals) {
+        self.sent = self.sent.wrapping_add(other.sent);
+        self.received = self.received.wrapping_add(other.received);
+    }
+
+    fn saturating_sub(self, other: TrafficTotals) -> Self {
+        Self {
+            sent: self.sent.saturating_sub(other.sent),
+            received: self.received.saturating_sub(other.received),
+        }
+    }
+}
+
+/// Counters of one connection
+#[derive(Debug, Default)]
+struct MeterCounters {
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+impl MeterCounters {
+    fn totals(&self) -> TrafficTotals {
+        TrafficTotals {
+            sent: self.sent.load(Ordering::Relaxed),
+            received: self.received.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ScopeState {
+    /// Bytes of meters that have ended
+    retired: TrafficTotals,
+    /// Live meters by id, and whether this scope counts them with directions swapped
+    live: HashMap<u64, (Arc<MeterCounters>, bool)>,
+    /// Totals at the last [`TrafficScope::reset`]
+    baseline: TrafficTotals,
+}
+
+impl ScopeState {
+    fn raw_totals(&self) -> TrafficTotals {
+        let mut totals = self.retired;
+        for (counters, reversed) in self.live.values() {
+            let meter = counters.totals();
+            totals.add(if *reversed { meter.reversed() } else { meter });
+        }
+        totals
+    }
+}
+
+#[derive(Debug)]
+struct ScopeInner {
+    parent: Option<TrafficScope>,
+    state: Mutex<ScopeState>,
+}
+
+/// A level of the accounting tree (proxy, target, session, global...)
+///
+/// Cloning shares the same totals.
+#[derive(Debug, Clone)]
+pub struct TrafficScope {
+    inner: Arc<ScopeInner>,
+}
+
+impl TrafficScope {
+    /// Create a root scope
+    pub fn new() -> Self {
+        Self::with_parent(None)
+    }
+
+    /// Create a scope whose meters also count toward this one
+    pub fn child(&self) -> Self {
+        Self::with_parent(Some(self.clone()))
+    }
+
+    fn with_parent(parent: Option<TrafficScope>) -> Self {
+        Self {
+            inner: Arc::new(ScopeInner {
+                parent,
+                state: Mutex::new(ScopeState::default()),
+            }),
+        }
+    }
+
+    /// Create a meter attached to this scope (and its ancestors)
+    pub fn meter(&self) -> TrafficMeter {
+        TrafficMeter::new().attach(self)
+    }
+
+    /// Bytes transferred since creation or the last [`reset`](Self::reset), live meters included
+    pub fn totals(&self) -> TrafficTotals {
+        let state = self.inner.state.lock().unwrap();
+        state.raw_totals().saturating_sub(state.baseline)
+    }
+
+    /// Start counting from zero again (ancestors are not affected)
+    pub fn reset(&self) {
+        let mut state = self.inner.state.lock().unwrap();
+        state.baseline = state.raw_totals();
+    }
+
+    fn ptr_eq(&self, other: &TrafficScope) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl Default for TrafficScope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Byte counter of one relayed connection
+///
+/// Attach it to its scopes before transferring; when the meter is dropped its bytes are moved
+/// into the scopes' totals.
+#[derive(Debug)]
+pub struct TrafficMeter {
+    id: u64,
+    counters: Arc<MeterCounters>,
+    scopes: Vec<(TrafficScope, bool)>,
+}
+
+impl TrafficMeter {
+    /// Create a meter that is not attached to any scope
+    pub fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            counters: Arc::new(MeterCounters::default()),
+            scopes: Vec::new(),
+        }
+    }
+
+    /// Also count toward `scope` and its ancestors
+    pub fn attach(self, scope: &TrafficScope) -> Self {
+        self.attach_as(scope, false)
+    }
+
+    /// Also count toward `scope` and its ancestors, with sent and received swapped
+    ///
+    /// For scopes that describe the other end of the connection (bytes the proxy sends into
+    /// the tunnel are bytes received from its local target)
+    pub fn attach_reversed(self, scope: &TrafficScope) -> Self {
+        self.attach_as(scope, true)
+    }
+
+    fn attach_as(mut self, scope: &TrafficScope, reversed: bool) -> Self {
+        let mut next = Some(scope.clone());
+        while let Some(scope) = next {
+            next = scope.inner.parent.clone();
+            if self.scopes.iter().any(|(s, _)| s.ptr_eq(&scope)) {
+                continue;
+            }
+            scope
+                .inner
+                .state
+                .lock()
+                .unwrap()
+                .live
+                .insert(self.id, (self.counters.clone(), reversed));
+            self.scopes.push((scope, reversed));
+        }
+        self
+    }
+
+    /// Record bytes sent
+    pub fn add_sent(&self, bytes: u64) {
+        self.counters.sent.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Record bytes received
+    pub fn add_received(&self, bytes: u64) {
+        self.counters.received.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Bytes recorded by this meter
+    pub fn totals(&self) -> TrafficTotals {
+        self.counters.totals()
+    }
+}
+
+impl Default for TrafficMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for TrafficMeter {
+    fn drop(&mut self) {
+        let totals = self.counters.totals();
+        for (scope, reversed) in &self.scopes {
+            let mut state = scope.inner.state.lock().unwrap();
+            if state.live.remove(&self.id).is_some() {
+                state
+                    .retired
+                    .add(if *reversed { totals.reversed() } else { totals });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn totals(sent: u64, received: u64) -> TrafficTotals {
+        TrafficTotals { sent, received }
+    }
+
+    #[test]
+    fn test_rollup_includes_live_and_ended_meters() {
+        let global = TrafficScope::new();
+        let proxy = global.child();
+        let other = global.child();
+
+        let first = proxy.meter();
+        first.add_sent(100);
+        first.add_received(10);
+        let second = other.meter();
+        second.add_sent(5);
+
+        // 快照时汇总存活的计数器
+        assert_eq!(proxy.totals(), totals(100, 10));
+        assert_eq!(global.totals(), totals(105, 10));
+
+        // 结束的计数器并入各级总数
+        drop(first);
+        assert_eq!(proxy.totals(), totals(100, 10));
+        assert_eq!(global.totals(), totals(105, 10));
+        second.add_received(7);
+        assert_eq!(global.totals(), totals(105, 17));
+        drop(second);
+        assert_eq!(other.totals(), totals(5, 7));
+        assert_eq!(global.totals(), totals(105, 17));
+    }
+
+    #[test]
+    fn test_attach_reversed_and_deduplicate_ancestors() {
+        let global = TrafficScope::new();
+        let proxy = global.child();
+        let target = TrafficScope::new();
+
+        let meter = proxy.meter().attach(&global).attach_reversed(&target);
+        meter.add_sent(3);
+        meter.add_received(8);
+        // 已经通过 proxy 挂到 global，不会重复计数
+        assert_eq!(global.totals(), totals(3, 8));
+        assert_eq!(target.totals(), totals(8, 3));
+        drop(meter);
+        assert_eq!(global.totals(), totals(3, 8));
+        assert_eq!(target.totals(), totals(8, 3));
+    }
+
+    #[test]
+    fn test_reset_only_affects_scope() {
+        let global = TrafficScope::new();
+        let proxy = global.child();
+
+        let meter = proxy.meter();
+        meter.add_sent(50);
+        proxy.reset();
+        assert_eq!(proxy.totals(), totals(0, 0));
+        meter.add_sent(20);
+        drop(meter);
+        assert_eq!(proxy.totals(), totals(20, 0));
+        assert_eq!(global.totals(), totals(70, 0));
+    }
+}
@@ -0,0 +1,123 @@
+// 限制单次写入大小的传输层包装
+
+use super::Transport;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// 把每次写入截断到 `max_write_chunk` 字节
+///
+/// 包装在 TLS 连接外层时，每条 TLS 记录都不超过该大小；
+/// 用于排查路径 MTU 问题导致的大记录传输停滞
+pub struct ChunkedStream<T> {
+    inner: T,
+    max_write_chunk: usize,
+}
+
+impl<T> ChunkedStream<T> {
+    /// 创建包装（`max_write_chunk` 至少为 1）
+    pub fn new(inner: T, max_write_chunk: usize) -> Self {
+        Self {
+            inner,
+            max_write_chunk: max_write_chunk.max(1),
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for ChunkedStream<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for ChunkedStream<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let len = buf.len().min(self.max_write_chunk);
+        Pin::new(&mut self.inner).poll_write(cx, &buf[..len])
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// 按配置限制传输连接的单次写入大小（未配置时原样返回）
+pub fn limit_write_chunk(
+    stream: Pin<Box<dyn Transport>>,
+    max_write_chunk: Option<usize>,
+) -> Pin<Box<dyn Transport>> {
+    match max_write_chunk {
+        Some(max) => Box::pin(ChunkedStream::new(stream, max)),
+        None => stream,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// 记录每次写入大小的写端
+    #[derive(Default)]
+    struct RecordingWriter {
+        writes: Vec<usize>,
+        data: Vec<u8>,
+    }
+
+    impl AsyncWrite for RecordingWriter {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.writes.push(buf.len());
+            self.data.extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_writes_are_capped() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+        let mut stream = ChunkedStream::new(RecordingWriter::default(), 1200);
+        stream.write_all(&data).await.unwrap();
+
+        let writer = stream.inner;
+        assert_eq!(writer.data, data);
+        assert!(writer.writes.iter().all(|&n| n <= 1200));
+        assert_eq!(writer.writes.len(), 9);
+    }
+
+    #[tokio::test]
+    async fn test_reads_pass_through() {
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut stream = ChunkedStream::new(client, 4);
+        server.write_all(b"hello world").await.unwrap();
+        drop(server);
+
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"hello world");
+    }
+}
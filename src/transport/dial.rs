@@ -0,0 +1,353 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::{sleep, Instant};
+use tracing::{debug, info};
+
+/// 依次发起连接尝试的间隔：前一个地址在该时间内没有连通时并行尝试下一个（Happy Eyeballs）
+pub const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// 连接失败的地址在该时间内排在最后尝试
+pub const FAILURE_TTL: Duration = Duration::from_secs(60);
+
+/// 服务器地址解析
+#[async_trait]
+pub trait Resolve: Send + Sync {
+    /// 解析主机名，返回全部地址（按解析器给出的顺序）
+    async fn resolve(&self, host: &str, port: u16) -> std::io::Result<Vec<SocketAddr>>;
+}
+
+/// 系统解析器（每次连接都重新解析，不缓存结果）
+pub struct SystemResolver;
+
+#[async_trait]
+impl Resolve for SystemResolver {
+    async fn resolve(&self, host: &str, port: u16) -> std::io::Result<Vec<SocketAddr>> {
+        Ok(tokio::net::lookup_host((host, port)).await?.collect())
+    }
+}
+
+#[derive(Default)]
+struct DialState {
+    /// 最近一次连接成功的地址，下次最先尝试
+    preferred: Option<SocketAddr>,
+    /// 最近连接失败的地址及失败时间
+    failed: HashMap<SocketAddr, Instant>,
+}
+
+/// 服务器拨号器
+///
+/// 每次连接都重新解析 `server_addr`，按顺序尝试全部 A/AAAA 记录（IPv6/IPv4 交替，
+/// 间隔 [`ATTEMPT_DELAY`] 错开）。最近连接成功的地址排在最前，[`FAILURE_TTL`] 内
+/// 失败过的地址排在最后，重连时不会反复卡在已经不可用的地址上
+pub struct ServerDialer {
+    host: String,
+    port: u16,
+    resolver: Arc<dyn Resolve>,
+    state: parking_lot::Mutex<DialState>,
+}
+
+impl ServerDialer {
+    pub fn new(host: String, port: u16) -> Self {
+        Self {
+            host,
+            port,
+            resolver: Arc::new(SystemResolver),
+            state: parking_lot::Mutex::new(DialState::default()),
+        }
+    }
+
+    /// 使用指定的解析器
+    pub fn with_resolver(mut self, resolver: Arc<dyn Resolve>) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// 最近一次连接成功的地址
+    pub fn last_connected(&self) -> Option<SocketAddr> {
+        self.state.lock().preferred
+    }
+
+    /// 解析并连接服务器，返回建立的连接和连接的地址
+    pub async fn connect(&self) -> Result<(TcpStream, SocketAddr)> {
+        self.connect_with(TcpStream::connect).await
+    }
+
+    /// 按尝试顺序排列的候选地址
+    pub async fn candidates(&self) -> Result<Vec<SocketAddr>> {
+        let addrs = self
+            .resolver
+            .resolve(&self.host, self.port)
+            .await
+            .with_context(|| format!("Failed to resolve {}:{}", self.host, self.port))?;
+        if addrs.is_empty() {
+            anyhow::bail!("{}:{} resolved to no addresses", self.host, self.port);
+        }
+        Ok(self.order(addrs))
+    }
+
+    async fn connect_with<T, F, Fut>(&self, connect: F) -> Result<(T, SocketAddr)>
+    where
+        F: Fn(SocketAddr) -> Fut,
+        Fut: Future<Output = std::io::Result<T>>,
+    {
+        let candidates = self.candidates().await?;
+        if candidates.len() > 1 {
+            debug!("Resolved {}:{} to {:?}", self.host, self.port, candidates);
+        }
+
+        let mut pending = candidates.into_iter();
+        let mut attempts = FuturesUnordered::new();
+        let mut last_error = None;
+        loop {
+            // 没有进行中的尝试时立即开始下一个，否则等待尝试间隔
+            if attempts.is_empty() {
+                match pending.next() {
+                    Some(addr) => attempts.push(attempt(addr, &connect)),
+                    None => break,
+                }
+            }
+            let next_attempt = sleep(ATTEMPT_DELAY);
+            tokio::select! {
+                Some((addr, result)) = attempts.next() => match result {
+                    Ok(stream) => {
+                        self.record_success(addr);
+                        return Ok((stream, addr));
+                    }
+                    Err(e) => {
+                        debug!("Connection to {} failed: {}", addr, e);
+                        self.record_failure(addr);
+                        last_error = Some(
+                            anyhow::Error::new(e).context(format!("Failed to connect to {}", addr)),
+                        );
+                        // 失败后不必等待尝试间隔
+                        if let Some(addr) = pending.next() {
+                            attempts.push(attempt(addr, &connect));
+                        }
+                    }
+                },
+                _ = next_attempt, if pending.len() > 0 => {
+                    if let Some(addr) = pending.next() {
+                        attempts.push(attempt(addr, &connect));
+                    }
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No addresses to connect to")))
+    }
+
+    /// 候选地址排序：去重后 IPv6/IPv4 交替，最近成功的地址最先，最近失败的地址最后
+    /// （失败越早越靠前）
+    fn order(&self, addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        let mut unique = Vec::with_capacity(addrs.len());
+        for addr in addrs {
+            if !unique.contains(&addr) {
+                unique.push(addr);
+            }
+        }
+        let first_v6 = unique.first().is_some_and(SocketAddr::is_ipv6);
+        let (primary, secondary): (Vec<_>, Vec<_>) =
+            unique.into_iter().partition(|a| a.is_ipv6() == first_v6);
+        let mut ordered = Vec::with_capacity(primary.len() + secondary.len());
+        let (mut primary, mut secondary) = (primary.into_iter(), secondary.into_iter());
+        loop {
+            match (primary.next(), secondary.next()) {
+                (None, None) => break,
+                (a, b) => ordered.extend(a.into_iter().chain(b)),
+            }
+        }
+
+        let mut state = self.state.lock();
+        state
+            .failed
+            .retain(|_, failed_at| failed_at.elapsed() < FAILURE_TTL);
+        if let Some(preferred) = state.preferred {
+            if let Some(index) = ordered.iter().position(|a| *a == preferred) {
+                ordered[..=index].rotate_right(1);
+            }
+        }
+        // 稳定排序：未失败的地址保持原有顺序
+        ordered.sort_by_key(|addr| state.failed.get(addr).copied());
+        ordered
+    }
+
+    fn record_success(&self, addr: SocketAddr) {
+        let mut state = self.state.lock();
+        if state.preferred != Some(addr) {
+            info!("Connected to {}:{} at {}", self.host, self.port, addr);
+        }
+        state.preferred = Some(addr);
+        state.failed.remove(&addr);
+    }
+
+    fn record_failure(&self, addr: SocketAddr) {
+        let mut state = self.state.lock();
+        if state.preferred == Some(addr) {
+            state.preferred = None;
+        }
+        state.failed.insert(addr, Instant::now());
+    }
+}
+
+async fn attempt<T, F, Fut>(addr: SocketAddr, connect: &F) -> (SocketAddr, std::io::Result<T>)
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = std::io::Result<T>>,
+{
+    (addr, connect(addr).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// 返回固定地址列表的解析器
+    struct StubResolver(Vec<SocketAddr>);
+
+    #[async_trait]
+    impl Resolve for StubResolver {
+        async fn resolve(&self, _host: &str, _port: u16) -> std::io::Result<Vec<SocketAddr>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn dialer(addrs: &[&str]) -> ServerDialer {
+        let addrs = addrs.iter().map(|a| a.parse().unwrap()).collect();
+        ServerDialer::new("tunnel.example.com".to_string(), 443)
+            .with_resolver(Arc::new(StubResolver(addrs)))
+    }
+
+    /// 连接 `dead` 时一直挂起（模拟丢包的主机），其他地址立即成功；记录尝试过的地址
+    async fn dial(
+        dialer: &ServerDialer,
+        dead: SocketAddr,
+        attempts: &parking_lot::Mutex<Vec<SocketAddr>>,
+    ) -> Result<SocketAddr> {
+        let (connected, addr) = dialer
+            .connect_with(|addr| {
+                attempts.lock().push(addr);
+                async move {
+                    if addr == dead {
+                        std::future::pending::<()>().await;
+                    }
+                    Ok(addr)
+                }
+            })
+            .await?;
+        assert_eq!(connected, addr);
+        Ok(addr)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dead_address_is_skipped_within_one_cycle() {
+        let dead: SocketAddr = "192.0.2.1:443".parse().unwrap();
+        let live: SocketAddr = "192.0.2.2:443".parse().unwrap();
+        let dialer = dialer(&["192.0.2.1:443", "192.0.2.2:443"]);
+        let attempts = parking_lot::Mutex::new(Vec::new());
+
+        // 首次连接：死地址在前，一个尝试间隔后连上活地址
+        let started = Instant::now();
+        assert_eq!(dial(&dialer, dead, &attempts).await.unwrap(), live);
+        assert_eq!(started.elapsed(), ATTEMPT_DELAY);
+        assert_eq!(*attempts.lock(), vec![dead, live]);
+        assert_eq!(dialer.last_connected(), Some(live));
+
+        // 重连直接使用活地址
+        for _ in 0..3 {
+            attempts.lock().clear();
+            let started = Instant::now();
+            assert_eq!(dial(&dialer, dead, &attempts).await.unwrap(), live);
+            assert_eq!(started.elapsed(), Duration::ZERO);
+            assert_eq!(*attempts.lock(), vec![live]);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failed_address_is_tried_last_until_ttl_expires() {
+        let dialer = dialer(&["192.0.2.1:443", "192.0.2.2:443", "192.0.2.3:443"]);
+        let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+        let refused = addr("192.0.2.1:443");
+
+        // 第一个地址拒绝连接：不等待尝试间隔，立即尝试下一个
+        let started = Instant::now();
+        let (connected, _) = dialer
+            .connect_with(|a| async move {
+                if a == refused {
+                    Err(std::io::ErrorKind::ConnectionRefused.into())
+                } else {
+                    Ok(a)
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(connected, addr("192.0.2.2:443"));
+        assert_eq!(started.elapsed(), Duration::ZERO);
+
+        // 最近成功的地址最先，失败的地址最后
+        dialer.record_failure(addr("192.0.2.2:443"));
+        assert_eq!(
+            dialer.candidates().await.unwrap(),
+            vec![addr("192.0.2.3:443"), refused, addr("192.0.2.2:443")]
+        );
+
+        tokio::time::advance(FAILURE_TTL).await;
+        assert_eq!(
+            dialer.candidates().await.unwrap(),
+            vec![refused, addr("192.0.2.2:443"), addr("192.0.2.3:443")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_address_families_are_interleaved() {
+        let dialer = dialer(&[
+            "[2001:db8::1]:443",
+            "[2001:db8::2]:443",
+            "192.0.2.1:443",
+            "[2001:db8::1]:443",
+            "192.0.2.2:443",
+        ]);
+        let ordered: Vec<String> = dialer
+            .candidates()
+            .await
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            ordered,
+            vec![
+                "[2001:db8::1]:443",
+                "192.0.2.1:443",
+                "[2001:db8::2]:443",
+                "192.0.2.2:443"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect_tries_every_address() {
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_addr = closed.local_addr().unwrap();
+        drop(closed);
+        let live = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live_addr = live.local_addr().unwrap();
+
+        let dialer = ServerDialer::new("localhost".to_string(), 0)
+            .with_resolver(Arc::new(StubResolver(vec![closed_addr, live_addr])));
+        let (stream, addr) = dialer.connect().await.unwrap();
+        assert_eq!(addr, live_addr);
+        assert_eq!(stream.peer_addr().unwrap(), live_addr);
+
+        let dialer = ServerDialer::new("localhost".to_string(), 0)
+            .with_resolver(Arc::new(StubResolver(vec![closed_addr])));
+        let err = dialer.connect().await.unwrap_err();
+        assert!(format!("{:#}", err).contains(&closed_addr.to_string()));
+    }
+}
@@ -2,11 +2,12 @@
 
 use crate::config::{ClientConfig, ServerConfig};
 use crate::transport::{
-    Http2TransportClient, Http2TransportServer, TlsTransportClient, TlsTransportServer,
+    Http2TransportClient, Http2TransportServer, HttpRoute, TlsTransportClient, TlsTransportServer,
     TransportClient, TransportServer, TransportType, WssTransportClient, WssTransportServer,
 };
 use anyhow::{Context, Result};
 use std::sync::Arc;
+use tokio::net::TcpListener;
 use tokio_rustls::{TlsAcceptor, TlsConnector};
 
 /// 创建传输层客户端
@@ -41,9 +42,12 @@ pub fn create_transport_client(
 }
 
 /// 创建传输层服务器
+///
+/// `route` 处理隧道端口上的普通 HTTP 请求（仅 http2/wss 传输）
 pub async fn create_transport_server(
     config: &ServerConfig,
     acceptor: TlsAcceptor,
+    route: Option<HttpRoute>,
 ) -> Result<Arc<dyn TransportServer>> {
     let server: Arc<dyn TransportServer> = match config.transport {
         TransportType::Tls => {
@@ -51,7 +55,7 @@ pub async fn create_transport_server(
                 TlsTransportServer::bind(config.bind_addr.clone(), config.bind_port, acceptor)
                     .await
                     .context("Failed to bind TLS transport server")?;
-            Arc::new(server)
+            Arc::new(server.debug_handshakes(config.debug_tls_handshakes))
         }
         TransportType::Http2 => {
             let server = Http2TransportServer::bind(
@@ -62,7 +66,11 @@ pub async fn create_transport_server(
             )
             .await
             .context("Failed to bind HTTP/2 transport server")?;
-            Arc::new(server)
+            Arc::new(
+                server
+                    .debug_handshakes(config.debug_tls_handshakes)
+                    .with_route(route),
+            )
         }
         TransportType::Wss => {
             let server = WssTransportServer::bind(
@@ -73,7 +81,11 @@ pub async fn create_transport_server(
             )
             .await
             .context("Failed to bind WebSocket transport server")?;
-            Arc::new(server)
+            Arc::new(
+                server
+                    .debug_handshakes(config.debug_tls_handshakes)
+                    .with_route(route),
+            )
         }
         TransportType::Unknown => {
             anyhow::bail!("Unknown transport type is not supported, please upgrade server or use a supported transport type (tls, http2, wss)")
@@ -82,3 +94,33 @@ pub async fn create_transport_server(
 
     Ok(server)
 }
+
+/// 在已绑定的监听套接字上创建传输层服务器（例如从旧进程交接而来的套接字）
+pub fn create_transport_server_with_listener(
+    config: &ServerConfig,
+    acceptor: TlsAcceptor,
+    listener: TcpListener,
+    route: Option<HttpRoute>,
+) -> Result<Arc<dyn TransportServer>> {
+    let server: Arc<dyn TransportServer> = match config.transport {
+        TransportType::Tls => Arc::new(
+            TlsTransportServer::with_listener(listener, acceptor)
+                .debug_handshakes(config.debug_tls_handshakes),
+        ),
+        TransportType::Http2 => Arc::new(
+            Http2TransportServer::with_listener(listener, acceptor, config.behind_proxy)
+                .debug_handshakes(config.debug_tls_handshakes)
+                .with_route(route),
+        ),
+        TransportType::Wss => Arc::new(
+            WssTransportServer::with_listener(listener, acceptor, config.behind_proxy)
+                .debug_handshakes(config.debug_tls_handshakes)
+                .with_route(route),
+        ),
+        TransportType::Unknown => {
+            anyhow::bail!("Unknown transport type is not supported, please upgrade server or use a supported transport type (tls, http2, wss)")
+        }
+    };
+
+    Ok(server)
+}
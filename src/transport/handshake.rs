@@ -0,0 +1,381 @@
+// TLS 握手诊断（debug_tls_handshakes）
+
+use parking_lot::Mutex;
+use rustls::server::{Acceptor, ClientHello};
+use rustls::{CipherSuite, ProtocolVersion};
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::{LazyConfigAcceptor, TlsAcceptor};
+use tracing::{trace, warn};
+
+/// 每个时间窗口内最多记录的握手失败数
+const MAX_REPORTS_PER_WINDOW: u32 = 10;
+
+/// 限流时间窗口
+const REPORT_WINDOW: Duration = Duration::from_secs(60);
+
+/// 读取 ClientHello 时预读的最大字节数（一条完整的 TLS 记录）
+const PEEK_LEN: usize = 5 + 16384;
+
+/// 完成服务器端 TLS 握手（启用诊断时失败会记录 ClientHello 摘要）
+pub(super) async fn accept_tls(
+    acceptor: &TlsAcceptor,
+    diagnostics: Option<&HandshakeDiagnostics>,
+    stream: TcpStream,
+    peer_addr: SocketAddr,
+) -> io::Result<TlsStream<TcpStream>> {
+    match diagnostics {
+        Some(diagnostics) => diagnostics.accept(acceptor, stream, peer_addr).await,
+        None => acceptor.accept(stream).await,
+    }
+}
+
+/// 握手失败诊断：记录客户端 ClientHello 的摘要，按时间窗口限流
+pub struct HandshakeDiagnostics {
+    window: Mutex<ReportWindow>,
+}
+
+struct ReportWindow {
+    started: Instant,
+    reported: u32,
+    suppressed: u64,
+}
+
+impl Default for HandshakeDiagnostics {
+    fn default() -> Self {
+        Self {
+            window: Mutex::new(ReportWindow {
+                started: Instant::now(),
+                reported: 0,
+                suppressed: 0,
+            }),
+        }
+    }
+}
+
+impl HandshakeDiagnostics {
+    /// 完成 TLS 握手，失败时记录 ClientHello 摘要
+    pub async fn accept(
+        &self,
+        acceptor: &TlsAcceptor,
+        stream: TcpStream,
+        peer_addr: SocketAddr,
+    ) -> io::Result<TlsStream<TcpStream>> {
+        // rustls 不公开客户端提供的版本列表，且缺少 TLS 1.2 必需扩展的 ClientHello 在解析阶段
+        // 就会被拒绝，因此同时从预读的原始记录中解析摘要
+        let mut buf = vec![0u8; PEEK_LEN];
+        let raw = match stream.peek(&mut buf).await {
+            Ok(n) => parse_client_hello(&buf[..n]),
+            Err(_) => None,
+        };
+
+        let start = match LazyConfigAcceptor::new(Acceptor::default(), stream).await {
+            Ok(start) => start,
+            Err(e) => {
+                self.report(peer_addr, &raw.unwrap_or_default(), &e);
+                return Err(e);
+            }
+        };
+
+        let versions = raw.and_then(|raw| raw.versions);
+        let summary = HelloSummary::new(&start.client_hello(), versions);
+        match start.into_stream(acceptor.config().clone()).await {
+            Ok(stream) => {
+                trace!("TLS handshake with {} succeeded: {}", peer_addr, summary);
+                Ok(stream)
+            }
+            Err(e) => {
+                self.report(peer_addr, &summary, &e);
+                Err(e)
+            }
+        }
+    }
+
+    fn report(&self, peer_addr: SocketAddr, summary: &HelloSummary, error: &io::Error) {
+        let Some(suppressed) = self.allow() else {
+            return;
+        };
+        let suppressed = if suppressed > 0 {
+            format!(" ({} earlier failures suppressed)", suppressed)
+        } else {
+            String::new()
+        };
+        warn!(
+            "TLS handshake from {} failed: {}; client hello: {}{}",
+            peer_addr, error, summary, suppressed
+        );
+    }
+
+    /// 是否允许记录，允许时返回此前被限流丢弃的数量
+    fn allow(&self) -> Option<u64> {
+        let mut window = self.window.lock();
+        if window.started.elapsed() >= REPORT_WINDOW {
+            window.started = Instant::now();
+            window.reported = 0;
+        }
+        if window.reported >= MAX_REPORTS_PER_WINDOW {
+            window.suppressed += 1;
+            return None;
+        }
+        window.reported += 1;
+        Some(std::mem::take(&mut window.suppressed))
+    }
+}
+
+/// ClientHello 摘要
+#[derive(Debug, Default)]
+struct HelloSummary {
+    versions: Option<Vec<ProtocolVersion>>,
+    cipher_suites: Vec<CipherSuite>,
+    alpn: Vec<String>,
+    sni: Option<String>,
+}
+
+impl HelloSummary {
+    fn new(hello: &ClientHello<'_>, versions: Option<Vec<ProtocolVersion>>) -> Self {
+        Self {
+            versions,
+            cipher_suites: hello.cipher_suites().to_vec(),
+            alpn: hello
+                .alpn()
+                .map(|protocols| {
+                    protocols
+                        .map(|p| String::from_utf8_lossy(p).into_owned())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            sni: hello.server_name().map(str::to_string),
+        }
+    }
+}
+
+impl fmt::Display for HelloSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.versions {
+            Some(versions) => write!(f, "versions={:?}", versions)?,
+            None => write!(f, "versions=unknown")?,
+        }
+        // Debug 只输出 rustls 支持的套件名称，其余的名称通过 as_str 获取
+        let cipher_suites: Vec<String> = self
+            .cipher_suites
+            .iter()
+            .map(|suite| match suite.as_str() {
+                Some(name) => name.to_string(),
+                None => format!("0x{:04x}", u16::from(*suite)),
+            })
+            .collect();
+        write!(
+            f,
+            " cipher_suites=[{}] alpn={:?} sni={}",
+            cipher_suites.join(", "),
+            self.alpn,
+            self.sni.as_deref().unwrap_or("-")
+        )
+    }
+}
+
+/// 从原始 ClientHello 记录中解析摘要
+///
+/// 有 supported_versions 扩展时使用扩展中的版本（忽略 GREASE），否则使用 legacy_version；
+/// 不是 ClientHello 时返回 None，扩展不完整时只返回已解析的部分
+fn parse_client_hello(record: &[u8]) -> Option<HelloSummary> {
+    let mut r = Reader(record);
+    // 记录头：type(1) version(2) length(2)，之后是握手消息头：type(1) length(3)
+    if r.u8()? != 0x16 {
+        return None;
+    }
+    r.skip(4)?;
+    if r.u8()? != 0x01 {
+        return None;
+    }
+    r.skip(3)?;
+    let legacy_version = r.u16()?;
+    r.skip(32)?;
+    let session_id_len = r.u8()? as usize;
+    r.skip(session_id_len)?;
+    let suites_len = r.u16()? as usize;
+    let mut suites = Reader(r.take(suites_len)?);
+    let mut summary = HelloSummary {
+        versions: Some(vec![ProtocolVersion::from(legacy_version)]),
+        ..Default::default()
+    };
+    while let Some(suite) = suites.u16() {
+        summary.cipher_suites.push(CipherSuite::from(suite));
+    }
+    let compression_len = r.u8()? as usize;
+    r.skip(compression_len)?;
+
+    let Some(mut extensions) = r.u16().and_then(|len| r.take(len as usize)).map(Reader) else {
+        return Some(summary);
+    };
+    while let Some(ext_type) = extensions.u16() {
+        let Some(mut data) = extensions
+            .u16()
+            .and_then(|len| extensions.take(len as usize))
+            .map(Reader)
+        else {
+            break;
+        };
+        match ext_type {
+            // server_name：只取第一个 host_name
+            0x0000 => {
+                let name = data.skip(2).and_then(|()| {
+                    (data.u8()? == 0).then_some(())?;
+                    let len = data.u16()? as usize;
+                    data.take(len)
+                });
+                summary.sni = name.map(|name| String::from_utf8_lossy(name).into_owned());
+            }
+            // application_layer_protocol_negotiation
+            0x0010 => {
+                data.skip(2);
+                while let Some(len) = data.u8() {
+                    let Some(protocol) = data.take(len as usize) else {
+                        break;
+                    };
+                    summary
+                        .alpn
+                        .push(String::from_utf8_lossy(protocol).into_owned());
+                }
+            }
+            // supported_versions
+            0x002b => {
+                let Some(mut list) = data
+                    .u8()
+                    .and_then(|len| data.take(len as usize))
+                    .map(Reader)
+                else {
+                    continue;
+                };
+                let mut versions = Vec::new();
+                while let Some(version) = list.u16() {
+                    if version & 0x0f0f != 0x0a0a {
+                        versions.push(ProtocolVersion::from(version));
+                    }
+                }
+                summary.versions = Some(versions);
+            }
+            _ => {}
+        }
+    }
+    Some(summary)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn skip(&mut self, len: usize) -> Option<()> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 构造 TLS 1.1 时代的 ClientHello 记录（可以追加额外的扩展）
+    fn client_hello(sni: &str, extra_extensions: &[u8]) -> Vec<u8> {
+        let name = sni.as_bytes();
+        let mut extensions = Vec::new();
+        extensions.extend_from_slice(&0x0000u16.to_be_bytes());
+        extensions.extend_from_slice(&((name.len() + 5) as u16).to_be_bytes());
+        extensions.extend_from_slice(&((name.len() + 3) as u16).to_be_bytes());
+        extensions.push(0);
+        extensions.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        extensions.extend_from_slice(name);
+        extensions.extend_from_slice(extra_extensions);
+
+        let mut body = vec![0x03, 0x02];
+        body.extend_from_slice(&[0x11; 32]);
+        body.push(0);
+        // TLS_RSA_WITH_AES_128_CBC_SHA, TLS_RSA_WITH_3DES_EDE_CBC_SHA
+        body.extend_from_slice(&[0x00, 0x04, 0x00, 0x2f, 0x00, 0x0a]);
+        body.extend_from_slice(&[0x01, 0x00]);
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut handshake = vec![0x01];
+        handshake.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        handshake.extend_from_slice(&body);
+
+        let mut record = vec![0x16, 0x03, 0x01];
+        record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+        record.extend_from_slice(&handshake);
+        record
+    }
+
+    #[test]
+    fn test_parse_legacy_client_hello() {
+        let summary = parse_client_hello(&client_hello("device.local", &[])).unwrap();
+        assert_eq!(summary.versions, Some(vec![ProtocolVersion::TLSv1_1]));
+        assert_eq!(
+            summary.cipher_suites,
+            vec![
+                CipherSuite::TLS_RSA_WITH_AES_128_CBC_SHA,
+                CipherSuite::TLS_RSA_WITH_3DES_EDE_CBC_SHA
+            ]
+        );
+        assert_eq!(summary.sni.as_deref(), Some("device.local"));
+        assert!(summary.alpn.is_empty());
+    }
+
+    #[test]
+    fn test_parse_supported_versions_and_alpn() {
+        // supported_versions 扩展（GREASE、TLS 1.3、TLS 1.2）和 ALPN 扩展（h2、http/1.1）
+        let hello = client_hello(
+            "a",
+            &[
+                0x00, 0x2b, 0x00, 0x07, 0x06, 0x7a, 0x7a, 0x03, 0x04, 0x03, 0x03, 0x00, 0x10, 0x00,
+                0x0e, 0x00, 0x0c, 0x02, b'h', b'2', 0x08, b'h', b't', b't', b'p', b'/', b'1', b'.',
+                b'1',
+            ],
+        );
+        let summary = parse_client_hello(&hello).unwrap();
+        assert_eq!(
+            summary.versions,
+            Some(vec![ProtocolVersion::TLSv1_3, ProtocolVersion::TLSv1_2])
+        );
+        assert_eq!(summary.alpn, vec!["h2", "http/1.1"]);
+    }
+
+    #[test]
+    fn test_parse_rejects_non_tls() {
+        assert!(parse_client_hello(b"GET / HTTP/1.1\r\n\r\n").is_none());
+        assert!(parse_client_hello(&[0x16, 0x03, 0x01]).is_none());
+    }
+
+    #[test]
+    fn test_reports_are_rate_limited() {
+        let diagnostics = HandshakeDiagnostics::default();
+        for _ in 0..MAX_REPORTS_PER_WINDOW {
+            assert_eq!(diagnostics.allow(), Some(0));
+        }
+        assert_eq!(diagnostics.allow(), None);
+        assert_eq!(diagnostics.allow(), None);
+
+        diagnostics.window.lock().started -= REPORT_WINDOW;
+        assert_eq!(diagnostics.allow(), Some(2));
+        assert_eq!(diagnostics.allow(), Some(0));
+    }
+}
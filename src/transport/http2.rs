@@ -1,19 +1,29 @@
 // HTTP/2传输实现
 // 使用 HTTP/2 CONNECT 方法建立隧道
 
-use super::{Transport, TransportClient, TransportServer, TransportType};
+use super::dial::ServerDialer;
+use super::handshake::{accept_tls, HandshakeDiagnostics};
+use super::route::{h2_response, route_h2, HttpRoute};
+use super::{PendingTransport, Transport, TransportClient, TransportServer, TransportType};
+use crate::accept::ListenerError;
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use bytes::{Buf, Bytes};
+use h2::server::SendResponse;
 use h2::{RecvStream, SendStream};
 use rustls::pki_types::ServerName;
 use std::io;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context as TaskContext, Poll};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{timeout, Duration};
 use tokio_rustls::{TlsAcceptor, TlsConnector};
 
+/// 路由到普通 HTTP 处理的连接在空闲多久后关闭
+const HTTP_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// 服务器端流类型枚举，用于统一处理 TLS 和 plain TCP
 enum ServerStreamType {
     Tls(Box<tokio_rustls::server::TlsStream<TcpStream>>),
@@ -176,6 +186,7 @@ pub struct Http2TransportClient {
     server_port: u16,
     #[allow(dead_code)]
     server_path: String,
+    dialer: ServerDialer,
     connector: TlsConnector,
 }
 
@@ -187,12 +198,19 @@ impl Http2TransportClient {
         connector: TlsConnector,
     ) -> Self {
         Self {
+            dialer: ServerDialer::new(server_addr.clone(), server_port),
             server_addr,
             server_port,
             server_path,
             connector,
         }
     }
+
+    /// 使用指定的服务器地址拨号器
+    pub fn with_dialer(mut self, dialer: ServerDialer) -> Self {
+        self.dialer = dialer;
+        self
+    }
 }
 
 #[async_trait]
@@ -204,7 +222,9 @@ impl TransportClient for Http2TransportClient {
             self.server_addr,
             self.server_port
         );
-        let tcp = TcpStream::connect((&self.server_addr as &str, self.server_port))
+        let (tcp, _) = self
+            .dialer
+            .connect()
             .await
             .context("Failed to connect to server")?;
         tracing::debug!("HTTP/2 client: TCP connected");
@@ -291,11 +311,17 @@ impl TransportClient for Http2TransportClient {
     fn transport_type(&self) -> TransportType {
         TransportType::Http2
     }
+
+    fn server_addr(&self) -> Option<std::net::SocketAddr> {
+        self.dialer.last_connected()
+    }
 }
 
 pub struct Http2TransportServer {
     listener: TcpListener,
     acceptor: Option<TlsAcceptor>,
+    diagnostics: Option<Arc<HandshakeDiagnostics>>,
+    route: Option<HttpRoute>,
 }
 
 impl Http2TransportServer {
@@ -309,115 +335,213 @@ impl Http2TransportServer {
             .await
             .context("Failed to bind HTTP/2 server")?;
 
-        Ok(Self {
+        Ok(Self::with_listener(listener, acceptor, behind_proxy))
+    }
+
+    /// 使用已绑定的监听套接字（例如从旧进程交接而来）
+    pub fn with_listener(listener: TcpListener, acceptor: TlsAcceptor, behind_proxy: bool) -> Self {
+        Self {
             listener,
             acceptor: if behind_proxy { None } else { Some(acceptor) },
-        })
+            diagnostics: None,
+            route: None,
+        }
     }
-}
 
-#[async_trait]
-impl TransportServer for Http2TransportServer {
-    async fn accept(&self) -> Result<Pin<Box<dyn Transport>>> {
-        // 1. 接受 TCP 连接
-        tracing::debug!("HTTP/2 server: Waiting for TCP connection");
-        let (tcp_stream, peer_addr) = self
-            .listener
-            .accept()
-            .await
-            .context("Failed to accept TCP")?;
-        tracing::debug!("HTTP/2 server: Accepted TCP connection from {}", peer_addr);
-
-        // 2. 创建统一的流类型
-        let stream = if let Some(ref acceptor) = self.acceptor {
-            // 标准 TLS 模式
-            tracing::debug!("HTTP/2 server: Starting TLS handshake");
-            let tls_stream = acceptor
-                .accept(tcp_stream)
-                .await
-                .context("TLS handshake failed")?;
-            tracing::debug!("HTTP/2 server: TLS handshake completed");
-            Box::new(ServerStreamType::Tls(Box::new(tls_stream)))
-        } else {
-            // 反向代理模式 - 直接使用 TCP（TLS 由前端代理处理）
-            tracing::debug!("HTTP/2 server: Using plain TCP (behind proxy)");
-            Box::new(ServerStreamType::Plain(tcp_stream))
-        };
-
-        // 3. HTTP/2 握手
-        tracing::debug!("HTTP/2 server: Starting HTTP/2 handshake");
-        let mut connection = h2::server::handshake(stream)
-            .await
-            .context("HTTP/2 handshake failed")?;
-        tracing::debug!("HTTP/2 server: HTTP/2 handshake completed");
+    /// 启用 TLS 握手诊断（`debug_tls_handshakes`）
+    pub fn debug_handshakes(mut self, enabled: bool) -> Self {
+        self.diagnostics = enabled.then(Default::default);
+        self
+    }
 
-        // 4. 接受第一个 HTTP/2 流（应该是 CONNECT 请求）
-        // 直接在主流程中 accept，因为 accept 本身会驱动 connection
-        tracing::debug!("HTTP/2 server: Waiting for first HTTP/2 stream");
+    /// 设置普通 HTTP 请求的路由（例如同端口的统计页面），CONNECT 请求仍作为隧道
+    pub fn with_route(mut self, route: Option<HttpRoute>) -> Self {
+        self.route = route;
+        self
+    }
+}
 
-        let (request, mut response_stream) = match connection.accept().await {
-            Some(Ok(stream)) => {
-                tracing::debug!("HTTP/2 server: Received first stream");
-                stream
-            }
-            Some(Err(e)) => {
-                tracing::error!("HTTP/2 server: Failed to accept stream: {:?}", e);
-                return Err(e).context("Failed to accept HTTP/2 stream");
+/// 应答已路由的第一个请求，并继续用路由处理该连接上的后续请求
+async fn serve_routed<T>(
+    mut connection: h2::server::Connection<T, Bytes>,
+    first: (SendResponse<Bytes>, crate::stats_http::HttpResponse),
+    route: HttpRoute,
+) where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let (respond, response) = first;
+    send_routed(respond, response);
+    loop {
+        match timeout(HTTP_IDLE_TIMEOUT, connection.accept()).await {
+            Ok(Some(Ok((request, respond)))) => {
+                let response = route_h2(&route, &request)
+                    .unwrap_or_else(crate::stats_http::HttpResponse::not_found);
+                send_routed(respond, response);
             }
-            None => {
-                tracing::error!("HTTP/2 server: Connection closed before receiving request");
-                anyhow::bail!("Connection closed before receiving request");
+            Ok(Some(Err(e))) => {
+                tracing::debug!("HTTP/2 server: Routed connection error: {}", e);
+                break;
             }
-        };
+            Ok(None) | Err(_) => break,
+        }
+    }
+}
 
-        // 在后台继续运行 HTTP/2 连接处理
-        // 注意：对于 tls-tunnel，我们只使用第一个 stream
-        tokio::spawn(async move {
-            tracing::debug!("HTTP/2 server: Connection driver started");
-            while let Some(result) = connection.accept().await {
-                if let Err(e) = result {
-                    tracing::error!("HTTP/2 server: Accept error: {:?}", e);
-                    break;
-                }
-                tracing::debug!("HTTP/2 server: Accepted additional stream (will be ignored)");
-            }
-            tracing::debug!("HTTP/2 server: Connection driver stopped");
-        });
+fn send_routed(mut respond: SendResponse<Bytes>, response: crate::stats_http::HttpResponse) {
+    let (head, body) = h2_response(response);
+    let result = respond
+        .send_response(head, false)
+        .and_then(|mut stream| stream.send_data(body, true));
+    if let Err(e) = result {
+        tracing::debug!("HTTP/2 server: Failed to send routed response: {}", e);
+    }
+}
 
-        // 5. 验证是 CONNECT 请求
-        tracing::debug!(
-            "HTTP/2 server: Request method: {}, URI: {}",
-            request.method(),
-            request.uri()
-        );
-        if request.method() != http::Method::CONNECT {
-            tracing::error!("HTTP/2 server: Expected CONNECT, got {}", request.method());
-            let response = http::Response::builder()
-                .status(http::StatusCode::METHOD_NOT_ALLOWED)
-                .body(())
-                .unwrap();
-            response_stream.send_response(response, true)?;
-            anyhow::bail!("Expected CONNECT method, got {}", request.method());
+/// 完成 TLS 和 HTTP/2 握手并接受 CONNECT 请求
+///
+/// 第一个请求命中同端口的 HTTP 路由时在后台应答，返回 None
+async fn handshake(
+    tcp_stream: TcpStream,
+    peer_addr: std::net::SocketAddr,
+    acceptor: Option<TlsAcceptor>,
+    diagnostics: Option<Arc<HandshakeDiagnostics>>,
+    route: Option<HttpRoute>,
+) -> Result<Option<Pin<Box<dyn Transport>>>> {
+    // 2. 创建统一的流类型
+    let stream = if let Some(ref acceptor) = acceptor {
+        // 标准 TLS 模式
+        tracing::debug!("HTTP/2 server: Starting TLS handshake");
+        let tls_stream = accept_tls(acceptor, diagnostics.as_deref(), tcp_stream, peer_addr)
+            .await
+            .context("TLS handshake failed")?;
+        tracing::debug!("HTTP/2 server: TLS handshake completed");
+        Box::new(ServerStreamType::Tls(Box::new(tls_stream)))
+    } else {
+        // 反向代理模式 - 直接使用 TCP（TLS 由前端代理处理）
+        tracing::debug!("HTTP/2 server: Using plain TCP (behind proxy)");
+        Box::new(ServerStreamType::Plain(tcp_stream))
+    };
+
+    // 3. HTTP/2 握手
+    tracing::debug!("HTTP/2 server: Starting HTTP/2 handshake");
+    let mut connection = h2::server::handshake(stream)
+        .await
+        .context("HTTP/2 handshake failed")?;
+    tracing::debug!("HTTP/2 server: HTTP/2 handshake completed");
+
+    // 4. 接受第一个 HTTP/2 流（应该是 CONNECT 请求）
+    // 直接在主流程中 accept，因为 accept 本身会驱动 connection
+    tracing::debug!("HTTP/2 server: Waiting for first HTTP/2 stream");
+
+    let (request, mut response_stream) = match connection.accept().await {
+        Some(Ok(stream)) => {
+            tracing::debug!("HTTP/2 server: Received first stream");
+            stream
+        }
+        Some(Err(e)) => {
+            tracing::error!("HTTP/2 server: Failed to accept stream: {:?}", e);
+            return Err(e).context("Failed to accept HTTP/2 stream");
         }
+        None => {
+            tracing::error!("HTTP/2 server: Connection closed before receiving request");
+            anyhow::bail!("Connection closed before receiving request");
+        }
+    };
+
+    // 路由命中的普通 HTTP 请求在后台应答，不建立隧道
+    if let Some(route) = &route {
+        if let Some(response) = route_h2(route, &request) {
+            tracing::debug!(
+                "HTTP/2 server: Routing {} {} from {} to HTTP handler",
+                request.method(),
+                request.uri(),
+                peer_addr
+            );
+            tokio::spawn(serve_routed(
+                connection,
+                (response_stream, response),
+                route.clone(),
+            ));
+            return Ok(None);
+        }
+    }
 
-        // 6. 发送 200 OK 响应，表示建立隧道
-        tracing::debug!("HTTP/2 server: Sending 200 OK response");
+    // 在后台继续运行 HTTP/2 连接处理
+    // 注意：对于 tls-tunnel，我们只使用第一个 stream
+    tokio::spawn(async move {
+        tracing::debug!("HTTP/2 server: Connection driver started");
+        while let Some(result) = connection.accept().await {
+            if let Err(e) = result {
+                tracing::error!("HTTP/2 server: Accept error: {:?}", e);
+                break;
+            }
+            tracing::debug!("HTTP/2 server: Accepted additional stream (will be ignored)");
+        }
+        tracing::debug!("HTTP/2 server: Connection driver stopped");
+    });
+
+    // 5. 验证是 CONNECT 请求
+    tracing::debug!(
+        "HTTP/2 server: Request method: {}, URI: {}",
+        request.method(),
+        request.uri()
+    );
+    if request.method() != http::Method::CONNECT {
+        tracing::error!("HTTP/2 server: Expected CONNECT, got {}", request.method());
         let response = http::Response::builder()
-            .status(http::StatusCode::OK)
+            .status(http::StatusCode::METHOD_NOT_ALLOWED)
             .body(())
-            .context("Failed to build response")?;
+            .unwrap();
+        response_stream.send_response(response, true)?;
+        anyhow::bail!("Expected CONNECT method, got {}", request.method());
+    }
 
-        // false 表示连接保持打开，用于后续数据传输
-        let send_stream = response_stream
-            .send_response(response, false)
-            .context("Failed to send response")?;
-        tracing::debug!("HTTP/2 server: Response sent");
+    // 6. 发送 200 OK 响应，表示建立隧道
+    tracing::debug!("HTTP/2 server: Sending 200 OK response");
+    let response = http::Response::builder()
+        .status(http::StatusCode::OK)
+        .body(())
+        .context("Failed to build response")?;
 
-        let recv_stream = request.into_body();
+    // false 表示连接保持打开，用于后续数据传输
+    let send_stream = response_stream
+        .send_response(response, false)
+        .context("Failed to send response")?;
+    tracing::debug!("HTTP/2 server: Response sent");
 
-        // 7. 返回包装的 HTTP/2 流，用于双向通信
-        tracing::debug!("HTTP/2 server: Connection established successfully");
-        Ok(Box::pin(Http2Stream::new(send_stream, recv_stream)))
+    let recv_stream = request.into_body();
+
+    // 7. 返回包装的 HTTP/2 流，用于双向通信
+    tracing::debug!("HTTP/2 server: Connection established successfully");
+    Ok(Some(Box::pin(Http2Stream::new(send_stream, recv_stream))))
+}
+
+#[async_trait]
+impl TransportServer for Http2TransportServer {
+    async fn accept(&self) -> Result<Pin<Box<dyn Transport>>> {
+        Ok(self.accept_from().await?.0)
+    }
+
+    async fn accept_from(&self) -> Result<(Pin<Box<dyn Transport>>, Option<std::net::SocketAddr>)> {
+        super::accept_established(self).await
+    }
+
+    async fn accept_pending(&self) -> Result<PendingTransport> {
+        // 1. 接受 TCP 连接
+        tracing::debug!("HTTP/2 server: Waiting for TCP connection");
+        let (tcp_stream, peer_addr) = self.listener.accept().await.map_err(ListenerError)?;
+        tracing::debug!("HTTP/2 server: Accepted TCP connection from {}", peer_addr);
+
+        let acceptor = self.acceptor.clone();
+        let diagnostics = self.diagnostics.clone();
+        let route = self.route.clone();
+        Ok(PendingTransport::new(Some(peer_addr), async move {
+            handshake(tcp_stream, peer_addr, acceptor, diagnostics, route).await
+        }))
+    }
+
+    fn local_addr(&self) -> Result<std::net::SocketAddr> {
+        Ok(self.listener.local_addr()?)
     }
 
     fn transport_type(&self) -> TransportType {
@@ -1,16 +1,27 @@
+mod chunked;
+mod dial;
 mod factory;
+mod handshake;
 mod http2;
+mod route;
 mod tls;
 mod wss;
 
-pub use factory::{create_transport_client, create_transport_server};
+pub use chunked::{limit_write_chunk, ChunkedStream};
+pub use dial::{Resolve, ServerDialer, SystemResolver, ATTEMPT_DELAY, FAILURE_TTL};
+pub use factory::{
+    create_transport_client, create_transport_server, create_transport_server_with_listener,
+};
 pub use http2::{Http2TransportClient, Http2TransportServer};
+pub use route::HttpRoute;
+pub(crate) use route::Rewind;
 pub use tls::{TlsTransportClient, TlsTransportServer};
 pub use wss::{WssTransportClient, WssTransportServer};
 
 use anyhow::Result;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::future::Future;
 use std::pin::Pin;
 use tokio::io::{AsyncRead, AsyncWrite};
 
@@ -72,6 +83,55 @@ pub trait TransportClient: Send + Sync {
 
     /// 获取传输类型
     fn transport_type(&self) -> TransportType;
+
+    /// 最近一次连接成功的服务器地址（`server_addr` 解析后的地址）
+    fn server_addr(&self) -> Option<std::net::SocketAddr> {
+        None
+    }
+}
+
+/// 握手的结果：建立的传输层连接，连接被同端口的 HTTP 路由处理时为 None
+type HandshakeFuture =
+    Pin<Box<dyn Future<Output = Result<Option<Pin<Box<dyn Transport>>>>> + Send>>;
+
+/// 已接受 TCP 连接、尚未完成握手（TLS、HTTP/2 或 WebSocket）的传输层连接
+///
+/// 服务器先对原始连接做速率限制和并发控制，再在单独的任务中完成握手，
+/// 握手的 CPU 开销不会阻塞 accept 循环。丢弃时关闭连接
+pub struct PendingTransport {
+    peer_addr: Option<std::net::SocketAddr>,
+    handshake: HandshakeFuture,
+}
+
+impl PendingTransport {
+    /// 由握手过程创建
+    pub fn new<F>(peer_addr: Option<std::net::SocketAddr>, handshake: F) -> Self
+    where
+        F: Future<Output = Result<Option<Pin<Box<dyn Transport>>>>> + Send + 'static,
+    {
+        Self {
+            peer_addr,
+            handshake: Box::pin(handshake),
+        }
+    }
+
+    /// 已经建立的连接（不需要握手的传输层）
+    pub fn established(
+        transport: Pin<Box<dyn Transport>>,
+        peer_addr: Option<std::net::SocketAddr>,
+    ) -> Self {
+        Self::new(peer_addr, async move { Ok(Some(transport)) })
+    }
+
+    /// 对端地址（传输层无法提供时为 None）
+    pub fn peer_addr(&self) -> Option<std::net::SocketAddr> {
+        self.peer_addr
+    }
+
+    /// 完成握手；连接被同端口的 HTTP 路由处理时返回 None
+    pub async fn handshake(self) -> Result<Option<Pin<Box<dyn Transport>>>> {
+        self.handshake.await
+    }
 }
 
 /// 传输层服务器接口
@@ -80,6 +140,40 @@ pub trait TransportServer: Send + Sync {
     /// 接受新的传输层连接
     async fn accept(&self) -> Result<Pin<Box<dyn Transport>>>;
 
+    /// 接受新的传输层连接，同时返回对端地址（传输层无法提供时为 None）
+    async fn accept_from(&self) -> Result<(Pin<Box<dyn Transport>>, Option<std::net::SocketAddr>)> {
+        Ok((self.accept().await?, None))
+    }
+
+    /// 只接受原始连接，握手推迟到 [`PendingTransport::handshake`]
+    ///
+    /// 默认在 accept 中完成握手（握手无法拆分的传输层）
+    async fn accept_pending(&self) -> Result<PendingTransport> {
+        let (transport, peer_addr) = self.accept_from().await?;
+        Ok(PendingTransport::established(transport, peer_addr))
+    }
+
+    /// 实际监听的地址（绑定端口为 0 时由系统分配）
+    fn local_addr(&self) -> Result<std::net::SocketAddr>;
+
     /// 获取传输类型
     fn transport_type(&self) -> TransportType;
 }
+
+/// 接受连接并在当前任务中完成握手，跳过被 HTTP 路由处理的连接
+///
+/// 用于实现了 [`TransportServer::accept_pending`] 的传输层的 `accept_from`
+async fn accept_established<S>(
+    server: &S,
+) -> Result<(Pin<Box<dyn Transport>>, Option<std::net::SocketAddr>)>
+where
+    S: TransportServer + ?Sized,
+{
+    loop {
+        let pending = server.accept_pending().await?;
+        let peer_addr = pending.peer_addr();
+        if let Some(transport) = pending.handshake().await? {
+            return Ok((transport, peer_addr));
+        }
+    }
+}
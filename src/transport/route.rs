@@ -0,0 +1,199 @@
+// 隧道端口上的普通 HTTP 请求路由
+// http2/wss 传输服务器把非隧道请求（非 CONNECT、非 WebSocket 升级）交给 HttpRoute，
+// 回调返回响应时直接应答该请求，返回 None 时按原来的隧道逻辑处理
+
+use crate::stats_http::{HttpRequest, HttpResponse};
+use bytes::Bytes;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+
+/// 普通 HTTP 请求的处理回调，返回 None 表示不处理该请求
+pub type HttpRoute = Arc<dyn Fn(&HttpRequest) -> Option<HttpResponse> + Send + Sync>;
+
+/// 预读请求头的最大字节数，超出后不再尝试路由
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+
+/// 预读 HTTP/1.1 请求头（直到空行、EOF 或超过 [`MAX_HEAD_SIZE`]）
+///
+/// 返回读到的全部数据，可能包含请求头之后的数据，需要用 [`Rewind`] 交还给后续处理
+pub(super) async fn read_head<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 1024];
+    while data.len() <= MAX_HEAD_SIZE && !data.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..n]);
+    }
+    Ok(data)
+}
+
+/// 按路由处理预读的 HTTP/1.1 请求，WebSocket 升级请求始终交给隧道
+pub(super) fn route_http1(route: &HttpRoute, head: &[u8]) -> Option<HttpResponse> {
+    let end = head.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let request = HttpRequest::parse_head(&head[..end + 4]);
+    let upgrade = request
+        .header("Upgrade")
+        .is_some_and(|v| v.eq_ignore_ascii_case("websocket"));
+    if upgrade {
+        return None;
+    }
+    route(&request)
+}
+
+/// 按路由处理 HTTP/2 请求，CONNECT 请求始终交给隧道
+pub(super) fn route_h2<B>(route: &HttpRoute, request: &http::Request<B>) -> Option<HttpResponse> {
+    if request.method() == http::Method::CONNECT {
+        return None;
+    }
+    let headers = request
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect();
+    let target = request
+        .uri()
+        .path_and_query()
+        .map_or("/", |p| p.as_str())
+        .to_string();
+    route(&HttpRequest {
+        method: request.method().to_string(),
+        target,
+        headers,
+        body: Vec::new(),
+    })
+}
+
+/// 把响应转换为 HTTP/2 响应头和响应体
+pub(super) fn h2_response(response: HttpResponse) -> (http::Response<()>, Bytes) {
+    let mut builder = http::Response::builder()
+        .status(response.status)
+        .header(http::header::CONTENT_TYPE, response.content_type);
+    for (name, value) in &response.headers {
+        builder = builder.header(*name, value.as_str());
+    }
+    let head = builder.body(()).unwrap_or_else(|_| {
+        http::Response::builder()
+            .status(http::StatusCode::INTERNAL_SERVER_ERROR)
+            .body(())
+            .unwrap()
+    });
+    (head, Bytes::from(response.into_body()))
+}
+
+/// 先读出预读数据，再读底层流
+pub(crate) struct Rewind<S> {
+    prefix: Vec<u8>,
+    pos: usize,
+    inner: S,
+}
+
+impl<S> Rewind<S> {
+    pub(crate) fn new(prefix: Vec<u8>, inner: S) -> Self {
+        Self {
+            prefix,
+            pos: 0,
+            inner,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Rewind<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.pos < this.prefix.len() {
+            let to_read = std::cmp::min(this.prefix.len() - this.pos, buf.remaining());
+            buf.put_slice(&this.prefix[this.pos..this.pos + to_read]);
+            this.pos += to_read;
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Rewind<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats_route() -> HttpRoute {
+        Arc::new(|request: &HttpRequest| {
+            (request.path() == "/stats").then(|| HttpResponse::json("{}".to_string()))
+        })
+    }
+
+    #[test]
+    fn test_route_http1_skips_websocket_upgrade() {
+        let route = stats_route();
+        let plain = b"GET /stats HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        assert_eq!(route_http1(&route, plain).unwrap().status, 200);
+
+        let upgrade =
+            b"GET /stats HTTP/1.1\r\nHost: example.com\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n";
+        assert!(route_http1(&route, upgrade).is_none());
+
+        // 其他路径和不完整的请求头交给隧道
+        assert!(route_http1(&route, b"GET /tunnel HTTP/1.1\r\n\r\n").is_none());
+        assert!(route_http1(&route, b"GET /stats HTTP/1.1\r\n").is_none());
+    }
+
+    #[test]
+    fn test_route_h2_skips_connect() {
+        let route = stats_route();
+        let get = http::Request::get("https://example.com/stats")
+            .body(())
+            .unwrap();
+        assert!(route_h2(&route, &get).is_some());
+
+        let connect = http::Request::builder()
+            .method(http::Method::CONNECT)
+            .uri("example.com:443")
+            .body(())
+            .unwrap();
+        assert!(route_h2(&route, &connect).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rewind_replays_prefix() {
+        let (mut client, server) = tokio::io::duplex(64);
+        tokio::io::AsyncWriteExt::write_all(&mut client, b" world")
+            .await
+            .unwrap();
+        drop(client);
+
+        let mut stream = Rewind::new(b"hello".to_vec(), server);
+        let mut data = String::new();
+        stream.read_to_string(&mut data).await.unwrap();
+        assert_eq!(data, "hello world");
+    }
+}
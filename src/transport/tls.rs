@@ -1,10 +1,13 @@
-use super::{Transport, TransportClient, TransportServer, TransportType};
+use super::dial::ServerDialer;
+use super::handshake::{accept_tls, HandshakeDiagnostics};
+use super::{PendingTransport, Transport, TransportClient, TransportServer, TransportType};
+use crate::accept::ListenerError;
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use rustls::pki_types::ServerName;
 use std::pin::Pin;
 use std::sync::Arc;
-use tokio::net::{TcpListener, TcpStream};
+use tokio::net::TcpListener;
 use tokio_rustls::{TlsAcceptor, TlsConnector};
 use tracing::info;
 
@@ -12,17 +15,25 @@ use tracing::info;
 pub struct TlsTransportClient {
     server_addr: String,
     server_port: u16,
+    dialer: ServerDialer,
     connector: TlsConnector,
 }
 
 impl TlsTransportClient {
     pub fn new(server_addr: String, server_port: u16, connector: TlsConnector) -> Self {
         Self {
+            dialer: ServerDialer::new(server_addr.clone(), server_port),
             server_addr,
             server_port,
             connector,
         }
     }
+
+    /// 使用指定的服务器地址拨号器
+    pub fn with_dialer(mut self, dialer: ServerDialer) -> Self {
+        self.dialer = dialer;
+        self
+    }
 }
 
 #[async_trait]
@@ -31,7 +42,9 @@ impl TransportClient for TlsTransportClient {
         let addr = format!("{}:{}", self.server_addr, self.server_port);
         info!("Connecting to {} via TLS", addr);
 
-        let tcp_stream = TcpStream::connect(&addr)
+        let (tcp_stream, remote) = self
+            .dialer
+            .connect()
             .await
             .with_context(|| format!("Failed to connect to {}", addr))?;
 
@@ -45,19 +58,24 @@ impl TransportClient for TlsTransportClient {
             .await
             .context("TLS handshake failed. This could be caused by:\n  - Certificate verification failure (try setting skip_verify = true for self-signed certificates)\n  - Invalid server certificate\n  - Certificate expired\n  - Server name mismatch\n  - Network issues")?;
 
-        info!("TLS connection established to {}", addr);
+        info!("TLS connection established to {} ({})", addr, remote);
         Ok(Box::pin(tls_stream))
     }
 
     fn transport_type(&self) -> TransportType {
         TransportType::Tls
     }
+
+    fn server_addr(&self) -> Option<std::net::SocketAddr> {
+        self.dialer.last_connected()
+    }
 }
 
 /// TLS 传输服务器
 pub struct TlsTransportServer {
     listener: Arc<TcpListener>,
     acceptor: TlsAcceptor,
+    diagnostics: Option<Arc<HandshakeDiagnostics>>,
 }
 
 impl TlsTransportServer {
@@ -69,32 +87,51 @@ impl TlsTransportServer {
 
         info!("TLS transport server listening on {}", addr);
 
-        Ok(Self {
+        Ok(Self::with_listener(listener, acceptor))
+    }
+
+    /// 使用已绑定的监听套接字（例如从旧进程交接而来）
+    pub fn with_listener(listener: TcpListener, acceptor: TlsAcceptor) -> Self {
+        Self {
             listener: Arc::new(listener),
             acceptor,
-        })
+            diagnostics: None,
+        }
+    }
+
+    /// 启用 TLS 握手诊断（`debug_tls_handshakes`）
+    pub fn debug_handshakes(mut self, enabled: bool) -> Self {
+        self.diagnostics = enabled.then(Default::default);
+        self
     }
 }
 
 #[async_trait]
 impl TransportServer for TlsTransportServer {
     async fn accept(&self) -> Result<Pin<Box<dyn Transport>>> {
-        let (tcp_stream, peer_addr) = self
-            .listener
-            .accept()
-            .await
-            .context("Failed to accept TCP connection")?;
+        Ok(self.accept_from().await?.0)
+    }
 
-        info!("Accepted TCP connection from {}", peer_addr);
+    async fn accept_from(&self) -> Result<(Pin<Box<dyn Transport>>, Option<std::net::SocketAddr>)> {
+        super::accept_established(self).await
+    }
 
-        let tls_stream = self
-            .acceptor
-            .accept(tcp_stream)
-            .await
-            .context("TLS handshake failed")?;
+    async fn accept_pending(&self) -> Result<PendingTransport> {
+        let (tcp_stream, peer_addr) = self.listener.accept().await.map_err(ListenerError)?;
+        info!("Accepted TCP connection from {}", peer_addr);
+        let acceptor = self.acceptor.clone();
+        let diagnostics = self.diagnostics.clone();
+        Ok(PendingTransport::new(Some(peer_addr), async move {
+            let tls_stream = accept_tls(&acceptor, diagnostics.as_deref(), tcp_stream, peer_addr)
+                .await
+                .context("TLS handshake failed")?;
+            info!("TLS handshake completed with {}", peer_addr);
+            Ok(Some(Box::pin(tls_stream) as Pin<Box<dyn Transport>>))
+        }))
+    }
 
-        info!("TLS handshake completed with {}", peer_addr);
-        Ok(Box::pin(tls_stream))
+    fn local_addr(&self) -> Result<std::net::SocketAddr> {
+        Ok(self.listener.local_addr()?)
     }
 
     fn transport_type(&self) -> TransportType {
@@ -1,7 +1,11 @@
 // WebSocket 传输实现
 // 使用 WebSocket Secure (WSS) 协议建立隧道
 
-use super::{Transport, TransportClient, TransportServer, TransportType};
+use super::dial::ServerDialer;
+use super::handshake::{accept_tls, HandshakeDiagnostics};
+use super::route::{read_head, route_http1, HttpRoute, Rewind};
+use super::{PendingTransport, Transport, TransportClient, TransportServer, TransportType};
+use crate::accept::ListenerError;
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use bytes::Bytes;
@@ -9,9 +13,11 @@ use futures::{SinkExt, StreamExt};
 use rustls::pki_types::ServerName;
 use std::io;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context as TaskContext, Poll};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{timeout, Duration};
 use tokio_rustls::{TlsAcceptor, TlsConnector};
 use tokio_tungstenite::tungstenite::protocol::Message;
 use tokio_tungstenite::WebSocketStream;
@@ -190,8 +196,8 @@ where
 
 pub struct WssTransportClient {
     server_addr: String,
-    server_port: u16,
     server_path: String,
+    dialer: ServerDialer,
     connector: TlsConnector,
 }
 
@@ -203,19 +209,27 @@ impl WssTransportClient {
         connector: TlsConnector,
     ) -> Self {
         Self {
+            dialer: ServerDialer::new(server_addr.clone(), server_port),
             server_addr,
-            server_port,
             server_path,
             connector,
         }
     }
+
+    /// 使用指定的服务器地址拨号器
+    pub fn with_dialer(mut self, dialer: ServerDialer) -> Self {
+        self.dialer = dialer;
+        self
+    }
 }
 
 #[async_trait]
 impl TransportClient for WssTransportClient {
     async fn connect(&self) -> Result<Pin<Box<dyn Transport>>> {
         // 1. 建立 TCP 连接
-        let tcp = TcpStream::connect((&self.server_addr as &str, self.server_port))
+        let (tcp, _) = self
+            .dialer
+            .connect()
             .await
             .context("Failed to connect to server")?;
 
@@ -244,11 +258,20 @@ impl TransportClient for WssTransportClient {
     fn transport_type(&self) -> TransportType {
         TransportType::Wss
     }
+
+    fn server_addr(&self) -> Option<std::net::SocketAddr> {
+        self.dialer.last_connected()
+    }
 }
 
+/// 写出路由响应的超时时间
+const HTTP_WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub struct WssTransportServer {
     listener: TcpListener,
     acceptor: Option<TlsAcceptor>,
+    diagnostics: Option<Arc<HandshakeDiagnostics>>,
+    route: Option<HttpRoute>,
 }
 
 impl WssTransportServer {
@@ -262,43 +285,119 @@ impl WssTransportServer {
             .await
             .context("Failed to bind WebSocket server")?;
 
-        Ok(Self {
+        Ok(Self::with_listener(listener, acceptor, behind_proxy))
+    }
+
+    /// 使用已绑定的监听套接字（例如从旧进程交接而来）
+    pub fn with_listener(listener: TcpListener, acceptor: TlsAcceptor, behind_proxy: bool) -> Self {
+        Self {
             listener,
             acceptor: if behind_proxy { None } else { Some(acceptor) },
-        })
+            diagnostics: None,
+            route: None,
+        }
     }
+
+    /// 启用 TLS 握手诊断（`debug_tls_handshakes`）
+    pub fn debug_handshakes(mut self, enabled: bool) -> Self {
+        self.diagnostics = enabled.then(Default::default);
+        self
+    }
+
+    /// 设置普通 HTTP 请求的路由（例如同端口的统计页面），WebSocket 升级请求仍作为隧道
+    pub fn with_route(mut self, route: Option<HttpRoute>) -> Self {
+        self.route = route;
+        self
+    }
+}
+
+/// 完成 TLS 和 WebSocket 握手
+///
+/// 请求命中同端口的 HTTP 路由时在后台应答，返回 None
+async fn handshake(
+    tcp_stream: TcpStream,
+    peer_addr: std::net::SocketAddr,
+    acceptor: Option<TlsAcceptor>,
+    diagnostics: Option<Arc<HandshakeDiagnostics>>,
+    route: Option<HttpRoute>,
+) -> Result<Option<Pin<Box<dyn Transport>>>> {
+    // 2. 创建统一的流类型
+    let mut stream = if let Some(ref acceptor) = acceptor {
+        // 标准 TLS 模式
+        let tls_stream = accept_tls(acceptor, diagnostics.as_deref(), tcp_stream, peer_addr)
+            .await
+            .context("TLS handshake failed")?;
+        Box::new(ServerStreamType::Tls(Box::new(tls_stream)))
+    } else {
+        // 反向代理模式 - 直接使用 TCP（TLS 由前端代理处理）
+        Box::new(ServerStreamType::Plain(tcp_stream))
+    };
+
+    let stream = match &route {
+        None => Rewind::new(Vec::new(), stream),
+        Some(route) => {
+            // 预读请求头，路由命中的普通 HTTP 请求在后台应答，不建立隧道
+            let head = read_head(&mut stream)
+                .await
+                .context("Failed to read WebSocket handshake request")?;
+            match route_http1(route, &head) {
+                Some(response) => {
+                    tracing::debug!(
+                        "WebSocket server: Routing request from {} to HTTP handler",
+                        peer_addr
+                    );
+                    tokio::spawn(async move {
+                        let result = timeout(HTTP_WRITE_TIMEOUT, async {
+                            crate::stats_http::write_http_response(&mut stream, response).await
+                        })
+                        .await;
+                        if !matches!(result, Ok(Ok(()))) {
+                            tracing::debug!(
+                                "WebSocket server: Failed to send routed response to {}",
+                                peer_addr
+                            );
+                        }
+                    });
+                    return Ok(None);
+                }
+                None => Rewind::new(head, stream),
+            }
+        }
+    };
+
+    // 3. WebSocket 握手
+    let ws_stream = tokio_tungstenite::accept_async(stream)
+        .await
+        .context("WebSocket handshake failed")?;
+
+    // 4. 返回包装的 WebSocket 流
+    Ok(Some(Box::pin(WssStream::new(ws_stream))))
 }
 
 #[async_trait]
 impl TransportServer for WssTransportServer {
     async fn accept(&self) -> Result<Pin<Box<dyn Transport>>> {
-        // 1. 接受 TCP 连接
-        let (tcp_stream, _) = self
-            .listener
-            .accept()
-            .await
-            .context("Failed to accept TCP")?;
+        Ok(self.accept_from().await?.0)
+    }
 
-        // 2. 创建统一的流类型
-        let stream = if let Some(ref acceptor) = self.acceptor {
-            // 标准 TLS 模式
-            let tls_stream = acceptor
-                .accept(tcp_stream)
-                .await
-                .context("TLS handshake failed")?;
-            Box::new(ServerStreamType::Tls(Box::new(tls_stream)))
-        } else {
-            // 反向代理模式 - 直接使用 TCP（TLS 由前端代理处理）
-            Box::new(ServerStreamType::Plain(tcp_stream))
-        };
+    async fn accept_from(&self) -> Result<(Pin<Box<dyn Transport>>, Option<std::net::SocketAddr>)> {
+        super::accept_established(self).await
+    }
 
-        // 3. WebSocket 握手
-        let ws_stream = tokio_tungstenite::accept_async(stream)
-            .await
-            .context("WebSocket handshake failed")?;
+    async fn accept_pending(&self) -> Result<PendingTransport> {
+        // 1. 接受 TCP 连接
+        let (tcp_stream, peer_addr) = self.listener.accept().await.map_err(ListenerError)?;
+
+        let acceptor = self.acceptor.clone();
+        let diagnostics = self.diagnostics.clone();
+        let route = self.route.clone();
+        Ok(PendingTransport::new(Some(peer_addr), async move {
+            handshake(tcp_stream, peer_addr, acceptor, diagnostics, route).await
+        }))
+    }
 
-        // 4. 返回包装的 WebSocket 流
-        Ok(Box::pin(WssStream::new(ws_stream)))
+    fn local_addr(&self) -> Result<std::net::SocketAddr> {
+        Ok(self.listener.local_addr()?)
     }
 
     fn transport_type(&self) -> TransportType {
@@ -0,0 +1,159 @@
+/// yamux 连接驱动：在同一次 poll 中处理入站 stream 和排队的出站 stream 请求
+///
+/// 事件循环在 `select!` 中等待 [`OutboundQueue::poll_event`]。每次 poll 要么在一次同步调用内
+/// 完成一个出站请求（出队并创建 stream），要么不改变任何状态，因此其他分支先完成时取消它
+/// 不会丢失请求。出站 stream 因等待对端 ACK 暂时无法创建时，连接仍被持续驱动
+use futures::io::{AsyncRead, AsyncWrite};
+use std::collections::VecDeque;
+use std::task::{Context, Poll};
+use yamux::{Connection, ConnectionError, Stream};
+
+/// 连接驱动产生的事件
+pub enum YamuxEvent<R> {
+    /// 对端打开的 stream（None 表示连接已关闭）
+    Inbound(Option<Result<Stream, ConnectionError>>),
+    /// 排队的出站请求及其创建结果
+    Outbound(R, Result<Stream, ConnectionError>),
+}
+
+/// 等待创建 stream 的出站请求队列（按请求顺序完成）
+pub struct OutboundQueue<R> {
+    pending: VecDeque<R>,
+}
+
+impl<R> Default for OutboundQueue<R> {
+    fn default() -> Self {
+        Self {
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<R> OutboundQueue<R> {
+    /// 创建空队列
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 加入一个出站请求（只入队，不触碰连接）
+    pub fn push(&mut self, request: R) {
+        self.pending.push_back(request);
+    }
+
+    /// 取出所有等待中的请求（会话失效时由调用方逐个回复失败）
+    pub fn drain(&mut self) -> impl Iterator<Item = R> + '_ {
+        self.pending.drain(..)
+    }
+
+    /// 等待中的请求数
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// 是否没有等待中的请求
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// 驱动连接：有排队的请求时先尝试创建出站 stream，暂时无法创建或没有请求时处理入站 stream
+    pub fn poll_event<T>(
+        &mut self,
+        conn: &mut Connection<T>,
+        cx: &mut Context<'_>,
+    ) -> Poll<YamuxEvent<R>>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
+        if !self.pending.is_empty() {
+            if let Poll::Ready(result) = conn.poll_new_outbound(cx) {
+                if let Some(request) = self.pending.pop_front() {
+                    let result = result.and_then(|stream| {
+                        crate::chaos::fail("yamux.outbound")
+                            .map(|()| stream)
+                            .map_err(ConnectionError::Io)
+                    });
+                    return Poll::Ready(YamuxEvent::Outbound(request, result));
+                }
+            }
+        }
+        conn.poll_next_inbound(cx).map(YamuxEvent::Inbound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::poll_fn;
+    use futures::io::{AsyncReadExt, AsyncWriteExt};
+    use std::time::Duration;
+    use tokio::sync::{mpsc, oneshot};
+    use tokio_util::compat::TokioAsyncReadCompatExt;
+    use yamux::{Config, Mode};
+
+    /// 大量并发请求期间不断有其他分支先完成（取消连接驱动），所有请求仍然得到 stream
+    #[tokio::test]
+    async fn test_outbound_requests_survive_cancellation() {
+        const REQUESTS: usize = 2000;
+
+        let (local, remote) = tokio::io::duplex(64 * 1024);
+        let mut local = Connection::new(local.compat(), Config::default(), Mode::Client);
+        let mut remote = Connection::new(remote.compat(), Config::default(), Mode::Server);
+
+        // 对端：每个入站 stream 回显一个字节
+        tokio::spawn(async move {
+            while let Some(Ok(mut stream)) = poll_fn(|cx| remote.poll_next_inbound(cx)).await {
+                tokio::spawn(async move {
+                    let mut byte = [0u8; 1];
+                    if stream.read_exact(&mut byte).await.is_ok() {
+                        let _ = stream.write_all(&byte).await;
+                        let _ = stream.close().await;
+                    }
+                });
+            }
+        });
+
+        let (request_tx, mut request_rx) = mpsc::channel::<oneshot::Sender<Stream>>(64);
+        let driver = tokio::spawn(async move {
+            let mut queue = OutboundQueue::<oneshot::Sender<Stream>>::new();
+            let mut tick = tokio::time::interval(Duration::from_micros(100));
+            loop {
+                tokio::select! {
+                    event = poll_fn(|cx| queue.poll_event(&mut local, cx)) => match event {
+                        YamuxEvent::Outbound(response_tx, result) => {
+                            let _ = response_tx.send(result.unwrap());
+                        }
+                        YamuxEvent::Inbound(None) | YamuxEvent::Inbound(Some(Err(_))) => break,
+                        YamuxEvent::Inbound(Some(Ok(_))) => {}
+                    },
+                    Some(response_tx) = request_rx.recv() => queue.push(response_tx),
+                    _ = tick.tick() => {}
+                }
+            }
+        });
+
+        let mut tasks = Vec::with_capacity(REQUESTS);
+        for i in 0..REQUESTS {
+            let request_tx = request_tx.clone();
+            tasks.push(tokio::spawn(async move {
+                let (response_tx, response_rx) = oneshot::channel();
+                request_tx.send(response_tx).await.unwrap();
+                let mut stream = response_rx.await.unwrap();
+                let byte = [(i % 251) as u8];
+                stream.write_all(&byte).await.unwrap();
+                let mut echo = [0u8; 1];
+                stream.read_exact(&mut echo).await.unwrap();
+                assert_eq!(echo, byte);
+            }));
+        }
+
+        let all = async {
+            for task in tasks {
+                task.await.unwrap();
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(60), all)
+            .await
+            .expect("Outbound stream requests were lost");
+        driver.abort();
+    }
+}
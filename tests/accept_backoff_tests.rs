@@ -0,0 +1,171 @@
+/// Listener accept error handling tests
+///
+/// 通过注入返回指定错误的传输层服务器，验证资源耗尽时退避、监听套接字失效时停止、
+/// 单个连接错误时继续接受
+use anyhow::Result;
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tls_tunnel::accept::ListenerError;
+use tls_tunnel::config::ServerConfig;
+use tls_tunnel::server::{Server, ServerHandle};
+use tls_tunnel::transport::{Transport, TransportServer, TransportType};
+use tokio::time::{sleep, timeout};
+
+/// 按脚本返回 accept 错误的传输层服务器
+///
+/// 脚本用完后重复返回 `repeat`，没有 `repeat` 时一直挂起
+struct ScriptedTransportServer {
+    script: Mutex<VecDeque<fn() -> anyhow::Error>>,
+    repeat: Option<fn() -> anyhow::Error>,
+    accepts: AtomicU64,
+}
+
+impl ScriptedTransportServer {
+    fn new(
+        script: impl IntoIterator<Item = fn() -> anyhow::Error>,
+        repeat: Option<fn() -> anyhow::Error>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            script: Mutex::new(script.into_iter().collect()),
+            repeat,
+            accepts: AtomicU64::new(0),
+        })
+    }
+
+    fn accepts(&self) -> u64 {
+        self.accepts.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl TransportServer for ScriptedTransportServer {
+    async fn accept(&self) -> Result<Pin<Box<dyn Transport>>> {
+        let next = self.script.lock().pop_front().or(self.repeat);
+        match next {
+            Some(error) => {
+                self.accepts.fetch_add(1, Ordering::Relaxed);
+                Err(error())
+            }
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(SocketAddr::from(([127, 0, 0, 1], 0)))
+    }
+
+    fn transport_type(&self) -> TransportType {
+        TransportType::Tls
+    }
+}
+
+fn emfile() -> anyhow::Error {
+    ListenerError(io::Error::from_raw_os_error(24)).into()
+}
+
+fn ebadf() -> anyhow::Error {
+    ListenerError(io::Error::from_raw_os_error(9)).into()
+}
+
+fn aborted() -> anyhow::Error {
+    ListenerError(io::Error::from(io::ErrorKind::ConnectionAborted)).into()
+}
+
+fn handshake_failed() -> anyhow::Error {
+    anyhow::anyhow!("TLS handshake failed: unexpected EOF")
+}
+
+async fn spawn_with(transport: Arc<ScriptedTransportServer>) -> ServerHandle {
+    let config: ServerConfig = toml::from_str(
+        r#"
+bind_addr = "127.0.0.1"
+bind_port = 0
+auth_key = "accept-backoff-test-key"
+"#,
+    )
+    .expect("Failed to parse server config");
+    Server::builder()
+        .config(config)
+        .transport(transport)
+        .spawn()
+        .await
+        .expect("Failed to start server")
+}
+
+#[tokio::test]
+async fn test_resource_exhaustion_backs_off() {
+    let transport = ScriptedTransportServer::new([], Some(emfile));
+    let server = spawn_with(transport.clone()).await;
+
+    sleep(Duration::from_secs(1)).await;
+
+    // 10ms 起步指数退避，1 秒内只会重试少数几次，而不是空转
+    let accepts = transport.accepts();
+    assert!(accepts >= 3, "accept retried only {} times", accepts);
+    assert!(accepts < 20, "accept retried {} times in 1s", accepts);
+    assert!(server.stats().listener_accept_errors() >= accepts - 1);
+
+    // 退避期间仍然可以正常停止
+    timeout(Duration::from_secs(5), server.shutdown())
+        .await
+        .expect("Server did not stop while backing off")
+        .expect("Server should stop cleanly");
+}
+
+#[tokio::test]
+async fn test_unusable_listener_stops_server() {
+    let transport = ScriptedTransportServer::new([ebadf as fn() -> anyhow::Error], None);
+    let server = spawn_with(transport.clone()).await;
+
+    // 等到服务器处理了 accept 错误再停止，否则停止信号可能先被处理
+    timeout(Duration::from_secs(5), async {
+        while transport.accepts() == 0 {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Server never called accept");
+    let result = timeout(Duration::from_secs(5), server.shutdown())
+        .await
+        .expect("Server did not stop");
+    let error = result.expect_err("Server should report the listener failure");
+    assert!(
+        format!("{:#}", error).contains("Server listener stopped"),
+        "unexpected error: {:#}",
+        error
+    );
+    assert_eq!(transport.accepts(), 1);
+}
+
+#[tokio::test]
+async fn test_transient_errors_do_not_back_off() {
+    let script: Vec<fn() -> anyhow::Error> = (0..200)
+        .map(|i| {
+            if i % 2 == 0 {
+                aborted as fn() -> anyhow::Error
+            } else {
+                handshake_failed
+            }
+        })
+        .collect();
+    let transport = ScriptedTransportServer::new(script, None);
+    let server = spawn_with(transport.clone()).await;
+
+    timeout(Duration::from_secs(1), async {
+        while transport.accepts() < 200 {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Transient errors should not delay accepting");
+    assert_eq!(server.stats().listener_accept_errors(), 0);
+
+    server.shutdown().await.expect("Server should stop cleanly");
+}
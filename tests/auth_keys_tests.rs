@@ -0,0 +1,318 @@
+/// Per-key permission tests
+///
+/// 服务器的 `auth_keys` 为每个附加密钥单独限制发布端口和 forward：发布端口不在
+/// `allowed_ports` 中的代理被拒绝（原因中写明代理名称和密钥名称），同一配置中的其他代理照常注册；
+/// 不允许 forward 的密钥发起的 forward 请求被拒绝；`auth_key` 不受这些限制
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+use tls_tunnel::client::SessionEvent;
+use tls_tunnel::config::{
+    AuthKeyConfig, ClientConfig, ClientFullConfig, ForwarderConfig, ProxyConfig, ProxyType,
+    ProxyVisibility, ServerConfig,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-auth-keys-legacy-key";
+const TEAM_A_KEY: &str = "test-auth-keys-team-a-key";
+const TEAM_B_KEY: &str = "test-auth-keys-team-b-key";
+
+fn server_config(cert_path: &Path, key_path: &Path, team_a_port: u16) -> ServerConfig {
+    ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        allow_forward: true,
+        auth_keys: Some(vec![
+            AuthKeyConfig {
+                name: "teamA".to_string(),
+                key: TEAM_A_KEY.to_string(),
+                allowed_ports: Some(vec![team_a_port.to_string().parse().unwrap()]),
+                allow_forward: None,
+            },
+            AuthKeyConfig {
+                name: "teamB".to_string(),
+                key: TEAM_B_KEY.to_string(),
+                allowed_ports: None,
+                allow_forward: Some(false),
+            },
+        ]),
+        ..common::test_server_config()
+    }
+}
+
+fn proxy(name: &str, publish_port: u16, local_port: u16) -> ProxyConfig {
+    ProxyConfig {
+        name: name.to_string(),
+        proxy_type: ProxyType::Tcp,
+        publish_addr: "127.0.0.1".to_string(),
+        publish_port,
+        local_port,
+        pool: None,
+        shared: false,
+        weight: None,
+        visibility: ProxyVisibility::Public,
+        drain_timeout_secs: None,
+        sni_routing: None,
+        socket: None,
+        local_targets: None,
+        idle_keepalive_secs: None,
+        require_first_byte_timeout_ms: None,
+        max_connections_per_source: None,
+        report_peers: false,
+        ws_idle_timeout_secs: None,
+        integrity_check: false,
+    }
+}
+
+fn forwarder(bind_port: u16) -> ForwarderConfig {
+    ForwarderConfig {
+        name: "egress".to_string(),
+        proxy_type: ProxyType::HttpProxy,
+        bind_addr: "127.0.0.1".to_string(),
+        bind_port,
+        routing: None,
+        fast_fail: None,
+        direct_egress: None,
+        socket: None,
+        report_direct: false,
+        idle_timeout_secs: None,
+        header_rules: None,
+    }
+}
+
+fn spawn_client(
+    server_port: u16,
+    cert_path: &Path,
+    auth_key: &str,
+    proxies: Vec<ProxyConfig>,
+    forwarders: Vec<ForwarderConfig>,
+    events: broadcast::Sender<SessionEvent>,
+) -> JoinHandle<()> {
+    let config = ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: auth_key.to_string(),
+            ..common::test_client_config()
+        },
+        proxies,
+        visitors: vec![],
+        forwarders,
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client_with_events(config, connector, events)
+            .await
+            .ok();
+    })
+}
+
+/// 等待会话因部分代理被拒绝而降级，返回原因
+async fn degraded_reason(rx: &mut broadcast::Receiver<SessionEvent>) -> String {
+    timeout(Duration::from_secs(10), async {
+        loop {
+            match rx.recv().await.expect("Session event channel closed") {
+                SessionEvent::Degraded(reason) => break reason,
+                SessionEvent::Disconnected(reason) => panic!("Session ended: {}", reason),
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("Timed out waiting for partial rejection")
+}
+
+/// 经 HTTP forwarder 发起 CONNECT（本地目标），返回收到的全部响应
+async fn forward_response(forwarder_port: u16) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", forwarder_port))
+        .await
+        .expect("Failed to connect to forwarder");
+    stream
+        .write_all(b"CONNECT 127.0.0.1:80 HTTP/1.1\r\nHost: 127.0.0.1:80\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    let _ = timeout(Duration::from_secs(5), stream.read_to_end(&mut response)).await;
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+#[tokio::test]
+async fn test_key_limited_to_allowed_ports() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let echo_port = common::get_available_port();
+    let _echo = common::start_echo_server(echo_port).await;
+    let allowed_port = common::get_available_port();
+    let blocked_port = common::get_available_port();
+    let server = common::spawn_server(server_config(&cert_path, &key_path, allowed_port)).await;
+    let server_port = server.bound_addr().port();
+
+    let (events, mut rx) = broadcast::channel(tls_tunnel::client::SESSION_EVENT_CAPACITY);
+    let team_a = spawn_client(
+        server_port,
+        &cert_path,
+        TEAM_A_KEY,
+        vec![
+            proxy("allowed", allowed_port, echo_port),
+            proxy("blocked", blocked_port, echo_port),
+        ],
+        vec![],
+        events,
+    );
+    let reason = degraded_reason(&mut rx).await;
+    assert!(
+        reason.contains(&format!(
+            "Proxy 'blocked': publish_port {} is not allowed for auth key 'teamA' (allowed: {})",
+            blocked_port, allowed_port
+        )),
+        "{}",
+        reason
+    );
+    assert!(!reason.contains("'allowed'"), "{}", reason);
+
+    let response = common::test_proxy_connection(allowed_port, b"team a", Duration::from_secs(5))
+        .await
+        .expect("Allowed proxy is not reachable");
+    assert_eq!(response, b"team a");
+    assert!(
+        !common::test_proxy_connection(blocked_port, b"blocked", Duration::from_millis(500))
+            .await
+            .is_ok_and(|data| data == b"blocked"),
+        "Blocked proxy was published"
+    );
+
+    // auth_key 不受端口限制
+    let (events, _rx) = broadcast::channel(tls_tunnel::client::SESSION_EVENT_CAPACITY);
+    let legacy = spawn_client(
+        server_port,
+        &cert_path,
+        AUTH_KEY,
+        vec![proxy("legacy", blocked_port, echo_port)],
+        vec![],
+        events,
+    );
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            common::test_proxy_connection(blocked_port, b"legacy", Duration::from_millis(500))
+                .await
+                .is_ok_and(|data| data == b"legacy")
+        })
+        .await,
+        "Proxy registered with auth_key is not reachable"
+    );
+
+    team_a.abort();
+    legacy.abort();
+}
+
+#[tokio::test]
+async fn test_forward_denied_for_key_without_permission() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server = common::spawn_server(server_config(
+        &cert_path,
+        &key_path,
+        common::get_available_port(),
+    ))
+    .await;
+    let server_port = server.bound_addr().port();
+
+    let team_b_port = common::get_available_port();
+    let (events, _rx) = broadcast::channel(tls_tunnel::client::SESSION_EVENT_CAPACITY);
+    let team_b = spawn_client(
+        server_port,
+        &cert_path,
+        TEAM_B_KEY,
+        vec![],
+        vec![forwarder(team_b_port)],
+        events,
+    );
+    assert!(
+        common::wait_for_server(team_b_port, 50).await,
+        "Forwarder did not start listening"
+    );
+    let response = forward_response(team_b_port).await;
+    assert!(
+        response.contains("Forward is not allowed for auth key 'teamB'"),
+        "{}",
+        response
+    );
+
+    // 未设置 allow_forward 的密钥沿用服务器的设置：请求通过权限检查（本地目标仍被拒绝）
+    let team_a_port = common::get_available_port();
+    let (events, _rx) = broadcast::channel(tls_tunnel::client::SESSION_EVENT_CAPACITY);
+    let team_a = spawn_client(
+        server_port,
+        &cert_path,
+        TEAM_A_KEY,
+        vec![],
+        vec![forwarder(team_a_port)],
+        events,
+    );
+    assert!(
+        common::wait_for_server(team_a_port, 50).await,
+        "Forwarder did not start listening"
+    );
+    let response = forward_response(team_a_port).await;
+    assert!(response.contains("HTTP/1.1 502"), "{}", response);
+    assert!(
+        !response.contains("not allowed for auth key"),
+        "{}",
+        response
+    );
+
+    team_b.abort();
+    team_a.abort();
+}
+
+#[tokio::test]
+async fn test_unknown_key_is_rejected() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server = common::spawn_server(server_config(
+        &cert_path,
+        &key_path,
+        common::get_available_port(),
+    ))
+    .await;
+
+    let (events, mut rx) = broadcast::channel(tls_tunnel::client::SESSION_EVENT_CAPACITY);
+    let client = spawn_client(
+        server.bound_addr().port(),
+        &cert_path,
+        "test-auth-keys-unknown-key",
+        vec![],
+        vec![],
+        events,
+    );
+    timeout(Duration::from_secs(10), async {
+        loop {
+            match rx.recv().await.expect("Session event channel closed") {
+                SessionEvent::Authenticated => {
+                    panic!("Client with an unknown key was authenticated")
+                }
+                SessionEvent::Disconnected(_) => break,
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("Client with an unknown key was not disconnected");
+
+    client.abort();
+}
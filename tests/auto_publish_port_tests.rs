@@ -0,0 +1,164 @@
+/// Auto-assigned publish port tests
+///
+/// `publish_port = 0` 的公开代理由服务器绑定系统分配的端口并以实际端口注册，
+/// 配置确认中返回分配的端口；visitor 的 publish_port 为 0 时按名称访问该代理
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+    VisitorConfig,
+};
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-auto-publish-port-key";
+
+fn server_config(cert_path: &Path, key_path: &Path) -> ServerConfig {
+    ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        ..common::test_server_config()
+    }
+}
+
+fn client_config(server_port: u16, cert_path: &Path) -> ClientConfig {
+    ClientConfig {
+        server_port,
+        skip_verify: true,
+        ca_cert_path: Some(cert_path.to_path_buf()),
+        auth_key: AUTH_KEY.to_string(),
+        ..common::test_client_config()
+    }
+}
+
+fn auto_proxy(name: &str, local_port: u16) -> ProxyConfig {
+    ProxyConfig {
+        name: name.to_string(),
+        proxy_type: ProxyType::Tcp,
+        publish_addr: "127.0.0.1".to_string(),
+        publish_port: 0,
+        local_port,
+        pool: None,
+        shared: false,
+        weight: None,
+        visibility: ProxyVisibility::Public,
+        drain_timeout_secs: None,
+        sni_routing: None,
+        socket: None,
+        local_targets: None,
+        idle_keepalive_secs: None,
+        require_first_byte_timeout_ms: None,
+        max_connections_per_source: None,
+        report_peers: false,
+        ws_idle_timeout_secs: None,
+        integrity_check: false,
+    }
+}
+
+fn spawn_client(config: ClientFullConfig, cert_path: &Path) -> JoinHandle<()> {
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    })
+}
+
+/// 经指定端口完成一次回显，客户端收到配置确认之前的连接会被关闭，失败时重试
+async fn echo_through(port: u16, data: &[u8]) -> bool {
+    for _ in 0..25 {
+        let echoed = common::test_proxy_connection(port, data, Duration::from_secs(2)).await;
+        if echoed.is_ok_and(|echoed| echoed == data) {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn test_auto_assigned_ports_are_reachable() {
+    let first_local = common::get_available_port();
+    let second_local = common::get_available_port();
+    let visitor_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _first_echo = common::start_echo_server(first_local).await;
+    let _second_echo = common::start_echo_server(second_local).await;
+
+    let server = common::spawn_server(server_config(&cert_path, &key_path)).await;
+    let server_port = server.bound_addr().port();
+
+    // 两个代理都请求分配端口，不视为重复的发布绑定
+    let proxy_client = spawn_client(
+        ClientFullConfig {
+            client: client_config(server_port, &cert_path),
+            proxies: vec![
+                auto_proxy("dev-a", first_local),
+                auto_proxy("dev-b", second_local),
+            ],
+            visitors: vec![],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        &cert_path,
+    );
+
+    let stats = server.stats();
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            stats.get_proxy_stats("dev-a").is_some() && stats.get_proxy_stats("dev-b").is_some()
+        })
+        .await,
+        "Proxies with auto-assigned ports were not registered"
+    );
+    let first_port = stats.get_proxy_stats("dev-a").unwrap().core.listen_port;
+    let second_port = stats.get_proxy_stats("dev-b").unwrap().core.listen_port;
+    assert_ne!(first_port, 0);
+    assert_ne!(second_port, 0);
+    assert_ne!(first_port, second_port);
+
+    // 客户端按分配的端口找到对应的本地服务
+    assert!(
+        echo_through(first_port, b"auto port a").await,
+        "Proxy was not reachable on the assigned port"
+    );
+    assert!(
+        echo_through(second_port, b"auto port b").await,
+        "Proxy was not reachable on the assigned port"
+    );
+
+    // visitor 只按名称引用分配端口的代理
+    let visitor_client = spawn_client(
+        ClientFullConfig {
+            client: client_config(server_port, &cert_path),
+            proxies: vec![],
+            visitors: vec![VisitorConfig {
+                name: "dev-a".to_string(),
+                proxy_type: ProxyType::Tcp,
+                bind_addr: "127.0.0.1".to_string(),
+                bind_port: visitor_port,
+                publish_port: 0,
+                expected_peer_id: None,
+                connection_reuse: false,
+                socket: None,
+            }],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        &cert_path,
+    );
+    assert!(
+        echo_through(visitor_port, b"auto port visitor").await,
+        "Visitor could not reach the proxy by name"
+    );
+
+    visitor_client.abort();
+    proxy_client.abort();
+    server.shutdown().await.ok();
+}
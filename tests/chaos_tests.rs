@@ -0,0 +1,549 @@
+#![cfg(feature = "chaos")]
+/// Chaos tests（需要 `chaos` 特性：`cargo test --features chaos --test chaos_tests`）
+///
+/// 在配置确认、出站 stream 创建、代理端口绑定、转发循环、会话清理和统计监听器处注入故障，验证服务器和
+/// 客户端事件循环能恢复，并且最终注册表为空、连接计数归零、没有遗留的后台任务
+mod common;
+
+use std::time::Duration;
+use tls_tunnel::chaos::{self, Action};
+use tls_tunnel::client::SessionEvent;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyRetryConfig, ProxyType, ProxyVisibility,
+    ServerConfig,
+};
+use tls_tunnel::server::{Registry, Server, ServerDependencies, ServerHandle};
+use tls_tunnel::stats_http::StatsEndpoint;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout, Instant};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+const AUTH_KEY: &str = "test-chaos-auth-key";
+
+/// 测试中统计的后台任务
+const TASKS: [&str; 5] = [
+    "server.session",
+    "server.listener",
+    "server.relay",
+    "client.session",
+    "client.relay",
+];
+
+/// 一个服务器、一个客户端和各代理的本地回显服务
+struct Harness {
+    server: Option<ServerHandle>,
+    registry: Registry,
+    client: JoinHandle<()>,
+    events: broadcast::Receiver<SessionEvent>,
+    stats: StatsEndpoint,
+    /// 各代理的 (名称, 发布端口)
+    proxies: Vec<(String, u16)>,
+    _echo: Vec<JoinHandle<()>>,
+    _cleanup: common::TestCleanup,
+}
+
+impl Harness {
+    /// 启动服务器和客户端（`retry` 为被拒绝代理的重试配置）
+    async fn start(names: &[&str], retry: Option<ProxyRetryConfig>) -> Self {
+        Self::launch(names, retry, false).await
+    }
+
+    /// 启动服务器和客户端，代理开启完整性校验
+    async fn start_checked(names: &[&str]) -> Self {
+        Self::launch(names, None, true).await
+    }
+
+    async fn launch(
+        names: &[&str],
+        retry: Option<ProxyRetryConfig>,
+        integrity_check: bool,
+    ) -> Self {
+        std::env::set_var("TLS_TUNNEL_RECONNECT_DELAY_SECS", "1");
+
+        let (cert_path, key_path) = common::generate_test_certs();
+        let cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+        let deps = ServerDependencies::new();
+        let registry = deps.proxy_registry.clone();
+        let tls_config =
+            tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None).unwrap();
+        let server = Server::builder()
+            .config(ServerConfig {
+                auth_key: AUTH_KEY.to_string(),
+                cert_path: Some(cert_path.clone()),
+                key_path: Some(key_path.clone()),
+                ..common::test_server_config()
+            })
+            .acceptor(TlsAcceptor::from(tls_config))
+            .dependencies(deps)
+            .spawn()
+            .await
+            .expect("Failed to start server");
+
+        let mut echo = Vec::new();
+        let mut proxies = Vec::new();
+        let mut proxy_configs = Vec::new();
+        for name in names {
+            let local_port = common::get_available_port();
+            let publish_port = common::get_available_port();
+            echo.push(common::start_echo_server(local_port).await);
+            proxies.push((name.to_string(), publish_port));
+            proxy_configs.push(ProxyConfig {
+                name: name.to_string(),
+                proxy_type: ProxyType::Tcp,
+                publish_addr: "127.0.0.1".to_string(),
+                publish_port,
+                local_port,
+                pool: None,
+                shared: false,
+                weight: None,
+                visibility: ProxyVisibility::Public,
+                drain_timeout_secs: None,
+                sni_routing: None,
+                socket: None,
+                local_targets: None,
+                idle_keepalive_secs: None,
+                require_first_byte_timeout_ms: None,
+                max_connections_per_source: None,
+                report_peers: false,
+                ws_idle_timeout_secs: None,
+                integrity_check,
+            });
+        }
+
+        let stats_port = common::get_available_port();
+        let config = ClientFullConfig {
+            client: ClientConfig {
+                server_port: server.bound_addr().port(),
+                skip_verify: true,
+                ca_cert_path: Some(cert_path.clone()),
+                auth_key: AUTH_KEY.to_string(),
+                stats_port: Some(stats_port),
+                proxy_retry: retry,
+                ..common::test_client_config()
+            },
+            proxies: proxy_configs,
+            visitors: vec![],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        };
+        config
+            .validate()
+            .expect("Chaos test config should be valid");
+
+        let tls_config =
+            tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None).unwrap();
+        let connector = TlsConnector::from(tls_config);
+        let (events_tx, events) = broadcast::channel(64);
+        let client = tokio::spawn(async move {
+            tls_tunnel::client::run_client_with_events(config, connector, events_tx)
+                .await
+                .ok();
+        });
+
+        Self {
+            server: Some(server),
+            registry,
+            client,
+            events,
+            stats: StatsEndpoint::Http(format!("http://127.0.0.1:{}", stats_port)),
+            proxies,
+            _echo: echo,
+            _cleanup: cleanup,
+        }
+    }
+
+    /// 代理的发布端口
+    fn port(&self, name: &str) -> u16 {
+        self.proxies
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, port)| *port)
+            .unwrap()
+    }
+
+    /// 等待下一个满足条件的会话事件
+    async fn wait_event(&mut self, what: &str, matches: impl Fn(&SessionEvent) -> bool) {
+        timeout(Duration::from_secs(15), async {
+            loop {
+                match self.events.recv().await {
+                    Ok(event) if matches(&event) => return,
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(e) => panic!("Session event channel closed: {}", e),
+                }
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("Timed out waiting for {}", what));
+    }
+
+    /// 等待代理可以转发数据
+    async fn wait_reachable(&self, name: &str) {
+        let port = self.port(name);
+        wait_until(&format!("proxy '{}' to become reachable", name), || async {
+            common::test_proxy_connection(port, b"chaos", Duration::from_secs(2)).await
+                == Ok(b"chaos".to_vec())
+        })
+        .await;
+    }
+
+    /// 注册表中代理的后端数（未注册时为 0）
+    fn backends(&self, name: &str) -> usize {
+        self.registry
+            .lookup(name, self.port(name))
+            .map_or(0, |snapshot| snapshot.backends)
+    }
+
+    /// 服务器和客户端统计的活动连接数都归零，并且没有遗留的转发任务
+    async fn wait_idle(&self) {
+        let server = self.server.as_ref().unwrap().stats();
+        wait_until("server active connections to return to zero", || async {
+            server
+                .get_all_stats()
+                .iter()
+                .all(|stats| stats.core.active_connections == 0)
+        })
+        .await;
+        wait_until("client active connections to return to zero", || async {
+            let Ok(body) = self.stats.get("/stats").await else {
+                return false;
+            };
+            let stats: Vec<serde_json::Value> = serde_json::from_str(&body).unwrap();
+            stats.iter().all(|s| s["active_connections"] == 0)
+        })
+        .await;
+        wait_until("relay tasks to exit", || async {
+            chaos::live_tasks("server.relay") == 0 && chaos::live_tasks("client.relay") == 0
+        })
+        .await;
+    }
+
+    /// 停止客户端和服务器，检查注册表为空、统计已注销、所有后台任务都已退出
+    async fn stop(mut self) {
+        self.client.abort();
+        let _ = (&mut self.client).await;
+        let server = self.server.take().unwrap();
+        wait_until("registry to become empty", || async {
+            self.registry.is_empty()
+        })
+        .await;
+        wait_until("proxy stats to be unregistered", || async {
+            server.stats().get_all_stats().is_empty()
+        })
+        .await;
+        server.shutdown().await.expect("Server shutdown failed");
+        wait_until("background tasks to exit", || async {
+            TASKS.iter().all(|name| chaos::live_tasks(name) == 0)
+        })
+        .await;
+        assert!(
+            chaos::all_live_tasks().is_empty(),
+            "Leaked tasks: {:?}",
+            chaos::all_live_tasks()
+        );
+    }
+}
+
+/// 轮询直到条件成立（最多 15 秒）
+async fn wait_until<F, Fut>(what: &str, mut condition: F)
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = bool>,
+{
+    let deadline = Instant::now() + Duration::from_secs(15);
+    while !condition().await {
+        assert!(
+            Instant::now() < deadline,
+            "Timed out waiting for {} (live tasks: {:?})",
+            what,
+            chaos::all_live_tasks()
+        );
+        sleep(Duration::from_millis(50)).await;
+    }
+}
+
+/// 服务器注册代理后写入配置确认之前失败：会话结束时注销代理，客户端重连后重新注册
+#[tokio::test]
+async fn test_config_ack_write_fails() {
+    let _scenario = chaos::scenario().await;
+    chaos::enable("server.config_ack.before", Action::Error, Some(1));
+
+    let mut harness = Harness::start(&["web"], None).await;
+    harness
+        .wait_event("disconnect", |e| matches!(e, SessionEvent::Disconnected(_)))
+        .await;
+    assert_eq!(chaos::hits("server.config_ack.before"), 1);
+
+    harness
+        .wait_event("config accepted", |e| {
+            matches!(e, SessionEvent::ConfigAccepted)
+        })
+        .await;
+    harness.wait_reachable("web").await;
+    assert_eq!(harness.backends("web"), 1);
+    assert_eq!(chaos::live_tasks("server.session"), 1);
+    harness.wait_idle().await;
+    harness.stop().await;
+}
+
+/// 配置确认已经发出后服务器会话失败：客户端重连并恢复代理，旧会话的注册不会残留
+#[tokio::test]
+async fn test_session_fails_after_config_ack() {
+    let _scenario = chaos::scenario().await;
+    chaos::enable("server.config_ack.after", Action::Error, Some(1));
+
+    let mut harness = Harness::start(&["web"], None).await;
+    harness
+        .wait_event("disconnect", |e| matches!(e, SessionEvent::Disconnected(_)))
+        .await;
+    assert_eq!(chaos::hits("server.config_ack.after"), 1);
+
+    harness
+        .wait_event("config accepted", |e| {
+            matches!(e, SessionEvent::ConfigAccepted)
+        })
+        .await;
+    harness.wait_reachable("web").await;
+    assert_eq!(harness.backends("web"), 1);
+    harness.wait_idle().await;
+    harness.stop().await;
+}
+
+/// 客户端处理配置确认时失败：客户端断开后服务器注销代理，重连后恢复
+#[tokio::test]
+async fn test_client_fails_on_config_accepted() {
+    let _scenario = chaos::scenario().await;
+    chaos::enable("client.config_accepted", Action::Error, Some(1));
+
+    let mut harness = Harness::start(&["web"], None).await;
+    harness
+        .wait_event("disconnect", |e| matches!(e, SessionEvent::Disconnected(_)))
+        .await;
+    assert_eq!(chaos::hits("client.config_accepted"), 1);
+
+    harness
+        .wait_event("config accepted", |e| {
+            matches!(e, SessionEvent::ConfigAccepted)
+        })
+        .await;
+    harness.wait_reachable("web").await;
+    assert_eq!(harness.backends("web"), 1);
+    assert_eq!(chaos::live_tasks("client.session"), 1);
+    harness.wait_idle().await;
+    harness.stop().await;
+}
+
+/// 代理端口绑定失败：只拒绝该代理，客户端按重试配置重新提交后注册成功
+#[tokio::test]
+async fn test_proxy_bind_fails() {
+    let _scenario = chaos::scenario().await;
+    chaos::enable("server.proxy_bind", Action::Error, Some(1));
+
+    let mut harness = Harness::start(
+        &["web", "api"],
+        Some(ProxyRetryConfig {
+            interval_secs: 1,
+            max_interval_secs: 1,
+        }),
+    )
+    .await;
+    harness
+        .wait_event("degraded session", |e| {
+            matches!(e, SessionEvent::Degraded(_))
+        })
+        .await;
+    assert_eq!(chaos::hits("server.proxy_bind"), 1);
+    // 第一个代理绑定失败，没有留下注册表项或监听器
+    assert_eq!(harness.backends("web"), 0);
+    assert_eq!(harness.backends("api"), 1);
+
+    harness.wait_reachable("web").await;
+    harness.wait_reachable("api").await;
+    assert_eq!(harness.backends("web"), 1);
+    assert_eq!(chaos::live_tasks("server.listener"), 2);
+    harness.wait_idle().await;
+    harness.stop().await;
+}
+
+/// 出站 stream 创建后立即失败：用户连接被关闭，会话和后续连接不受影响
+#[tokio::test]
+async fn test_outbound_stream_fails() {
+    let _scenario = chaos::scenario().await;
+    let mut harness = Harness::start(&["web"], None).await;
+    harness
+        .wait_event("running", |e| matches!(e, SessionEvent::Running))
+        .await;
+    harness.wait_reachable("web").await;
+
+    chaos::enable("yamux.outbound", Action::Error, Some(1));
+    let result =
+        common::test_proxy_connection(harness.port("web"), b"chaos", Duration::from_secs(5)).await;
+    assert_ne!(result, Ok(b"chaos".to_vec()));
+    assert_eq!(chaos::hits("yamux.outbound"), 1);
+
+    harness.wait_idle().await;
+    harness.wait_reachable("web").await;
+    assert_eq!(chaos::live_tasks("server.session"), 1);
+    harness.stop().await;
+}
+
+/// 转发中途出错：两端连接都被关闭，统计计数和转发任务归零
+#[tokio::test]
+async fn test_relay_fails_mid_transfer() {
+    let _scenario = chaos::scenario().await;
+    let mut harness = Harness::start(&["web"], None).await;
+    harness
+        .wait_event("running", |e| matches!(e, SessionEvent::Running))
+        .await;
+    harness.wait_reachable("web").await;
+    harness.wait_idle().await;
+
+    let mut stream = TcpStream::connect(("127.0.0.1", harness.port("web")))
+        .await
+        .unwrap();
+    stream.write_all(b"first").await.unwrap();
+    let mut echo = [0u8; 5];
+    stream.read_exact(&mut echo).await.unwrap();
+    assert_eq!(&echo, b"first");
+
+    chaos::enable("relay.transfer", Action::Error, Some(1));
+    stream.write_all(b"second").await.unwrap();
+    // 连接被关闭（对端关闭或重置）而不是一直挂起
+    let mut buf = [0u8; 16];
+    let closed = timeout(Duration::from_secs(5), stream.read(&mut buf))
+        .await
+        .expect("Connection was not closed after relay error");
+    assert!(matches!(closed, Ok(0) | Err(_)), "{:?}", closed);
+    assert_eq!(chaos::hits("relay.transfer"), 1);
+
+    harness.wait_idle().await;
+    harness.wait_reachable("web").await;
+    harness.stop().await;
+}
+
+/// 转发数据在两端之间被改动一个字节：接收方比较尾帧时发现不一致，服务器和客户端统计中
+/// 合计记录一次 `integrity_mismatches`
+#[tokio::test]
+async fn test_integrity_check_detects_corruption() {
+    let _scenario = chaos::scenario().await;
+    let mut harness = Harness::start_checked(&["web"]).await;
+    harness
+        .wait_event("running", |e| matches!(e, SessionEvent::Running))
+        .await;
+    harness.wait_reachable("web").await;
+    harness.wait_idle().await;
+
+    chaos::enable("relay.corrupt", Action::Corrupt, Some(1));
+    let mut stream = TcpStream::connect(("127.0.0.1", harness.port("web")))
+        .await
+        .unwrap();
+    stream.write_all(b"integrity").await.unwrap();
+    let mut echo = [0u8; 9];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut echo))
+        .await
+        .expect("Timed out reading echo")
+        .unwrap();
+    assert_ne!(&echo, b"integrity");
+    assert_eq!(chaos::hits("relay.corrupt"), 1);
+    drop(stream);
+
+    let server = harness.server.as_ref().unwrap().stats();
+    let mismatches = || async {
+        let server = server
+            .get_proxy_stats("web")
+            .map_or(0, |s| s.core.integrity_mismatches);
+        let client = match harness.stats.get("/stats").await {
+            Ok(body) => {
+                let stats: Vec<serde_json::Value> = serde_json::from_str(&body).unwrap();
+                stats
+                    .iter()
+                    .filter_map(|s| s["integrity_mismatches"].as_u64())
+                    .sum()
+            }
+            Err(_) => 0,
+        };
+        server + client
+    };
+    wait_until("the corrupted direction to be reported", || async {
+        mismatches().await >= 1
+    })
+    .await;
+    harness.wait_idle().await;
+    assert_eq!(mismatches().await, 1);
+
+    harness.stop().await;
+}
+
+/// 会话清理变慢时客户端已经重连：新会话的注册在旧会话注销之后成功，最终只有一个后端
+#[tokio::test]
+async fn test_slow_session_cleanup() {
+    let _scenario = chaos::scenario().await;
+    chaos::enable("server.config_ack.after", Action::Error, Some(1));
+    chaos::enable(
+        "server.session_cleanup",
+        Action::Delay(Duration::from_secs(2)),
+        Some(1),
+    );
+
+    let mut harness = Harness::start(&["web"], None).await;
+    harness
+        .wait_event("disconnect", |e| matches!(e, SessionEvent::Disconnected(_)))
+        .await;
+    harness
+        .wait_event("reconnect", |e| matches!(e, SessionEvent::Connecting))
+        .await;
+    assert_eq!(chaos::hits("server.session_cleanup"), 1);
+
+    harness.wait_reachable("web").await;
+    assert_eq!(harness.backends("web"), 1);
+    assert_eq!(chaos::live_tasks("server.session"), 1);
+    harness.wait_idle().await;
+    harness.stop().await;
+}
+
+/// 统计服务器的监听套接字失效：退避后重新绑定同一端口，/healthz 记录失败原因和重新绑定次数
+#[tokio::test]
+async fn test_stats_listener_fails() {
+    let _scenario = chaos::scenario().await;
+
+    let harness = Harness::start(&["web"], None).await;
+    harness.wait_reachable("web").await;
+    wait_until("client stats server to listen", || async {
+        harness.stats.get("/healthz").await.is_ok()
+    })
+    .await;
+
+    // 处理完下一个请求后监听器失效
+    chaos::enable("stats.listener", Action::Error, Some(1));
+    harness.stats.get("/stats").await.unwrap();
+    wait_until("stats listener to fail", || async {
+        chaos::hits("stats.listener") == 1
+    })
+    .await;
+
+    wait_until("stats server to be rebound", || async {
+        let Ok(body) = harness.stats.get("/healthz").await else {
+            return false;
+        };
+        let report: serde_json::Value = serde_json::from_str(&body).unwrap();
+        report["servers"][0]["rebinds"] == 1
+    })
+    .await;
+    let body = harness.stats.get("/healthz").await.unwrap();
+    let report: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(report["stats_server_healthy"], true);
+    assert!(
+        report["servers"][0]["last_error"]
+            .as_str()
+            .is_some_and(|error| error.contains("stats.listener")),
+        "{}",
+        body
+    );
+
+    harness.wait_idle().await;
+    harness.stop().await;
+}
@@ -0,0 +1,172 @@
+/// Remote config check tests
+///
+/// `validate_config` 按提交配置的规则与服务器当前注册表比对并返回结果，
+/// 但不绑定端口、不写入注册表，已有注册不受影响
+mod common;
+
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+    VisitorConfig,
+};
+use tls_tunnel::server::ServerDependencies;
+use tokio::net::TcpListener;
+use tokio::time::sleep;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-check-remote-key";
+
+fn client_config(server_port: u16, cert_path: &std::path::Path) -> ClientConfig {
+    ClientConfig {
+        server_port,
+        skip_verify: true,
+        ca_cert_path: Some(cert_path.to_path_buf()),
+        auth_key: AUTH_KEY.to_string(),
+        ..common::test_client_config()
+    }
+}
+
+fn proxy(name: &str, publish_port: u16, local_port: u16) -> ProxyConfig {
+    ProxyConfig {
+        name: name.to_string(),
+        proxy_type: ProxyType::Tcp,
+        publish_addr: "127.0.0.1".to_string(),
+        publish_port,
+        local_port,
+        pool: None,
+        shared: false,
+        weight: None,
+        visibility: ProxyVisibility::Public,
+        drain_timeout_secs: None,
+        sni_routing: None,
+        socket: None,
+        local_targets: None,
+        idle_keepalive_secs: None,
+        require_first_byte_timeout_ms: None,
+        max_connections_per_source: None,
+        report_peers: false,
+        ws_idle_timeout_secs: None,
+        integrity_check: false,
+    }
+}
+
+fn connector(cert_path: &std::path::Path) -> TlsConnector {
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    TlsConnector::from(tls_config)
+}
+
+#[tokio::test]
+async fn test_dry_run_reports_conflict_and_leaves_registry_untouched() {
+    let web_port = common::get_available_port();
+    let fresh_port = common::get_available_port();
+    let missing_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let visitor_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+
+    let server_config = ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        ..common::test_server_config()
+    };
+    let deps = ServerDependencies::new();
+    let registry = deps.proxy_registry.clone();
+    let stats_manager = deps.stats_manager.clone();
+
+    let server = common::spawn_server_with_dependencies(server_config, deps).await;
+    let server_port = server.bound_addr().port();
+
+    // 客户端 A 注册 web 代理
+    let registered = ClientFullConfig {
+        client: client_config(server_port, &cert_path),
+        proxies: vec![proxy("web", web_port, local_port)],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let connector_a = connector(&cert_path);
+    let client_a = tokio::spawn(async move {
+        tls_tunnel::client::run_client(registered, connector_a)
+            .await
+            .ok();
+    });
+
+    let mut ready = false;
+    for _ in 0..50 {
+        if let Ok(data) =
+            common::test_proxy_connection(web_port, b"before", Duration::from_secs(2)).await
+        {
+            if data == b"before" {
+                ready = true;
+                break;
+            }
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert!(ready, "Proxy 'web' never became reachable");
+
+    // 待上线的配置：web 与已有注册冲突，fresh 可以注册，visitor 引用不存在的代理
+    let candidate = ClientFullConfig {
+        client: client_config(server_port, &cert_path),
+        proxies: vec![
+            proxy("web", web_port, local_port),
+            proxy("fresh", fresh_port, local_port),
+        ],
+        visitors: vec![VisitorConfig {
+            name: "missing".to_string(),
+            proxy_type: ProxyType::Tcp,
+            bind_addr: "127.0.0.1".to_string(),
+            bind_port: visitor_port,
+            publish_port: missing_port,
+            expected_peer_id: None,
+            connection_reuse: false,
+            socket: None,
+        }],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let report = tls_tunnel::client::check_remote(candidate, connector(&cert_path))
+        .await
+        .expect("Remote check failed");
+
+    assert!(!report.is_accepted());
+    assert_eq!(report.items.len(), 3);
+    let web = &report.items[0];
+    assert_eq!((web.kind, web.name.as_str()), ("proxy", "web"));
+    assert_eq!(web.rejection.as_deref(), Some("端口或名称冲突"));
+    let fresh = &report.items[1];
+    assert_eq!((fresh.kind, fresh.name.as_str()), ("proxy", "fresh"));
+    assert!(fresh.is_accepted(), "{:?}", fresh.rejection);
+    let missing = &report.items[2];
+    assert_eq!(
+        (missing.kind, missing.name.as_str()),
+        ("visitor", "missing")
+    );
+    assert!(!missing.is_accepted());
+
+    // 注册表和统计只包含客户端 A 的代理，可以注册的代理也没有绑定端口
+    let keys: Vec<_> = registry
+        .list()
+        .into_iter()
+        .map(|p| (p.proxy_info.name, p.proxy_info.publish_port))
+        .collect();
+    assert_eq!(keys, vec![("web".to_string(), web_port)]);
+    assert!(stats_manager.get_proxy_stats("fresh").is_none());
+    assert!(TcpListener::bind(("127.0.0.1", fresh_port)).await.is_ok());
+
+    // 客户端 A 的代理仍然可用
+    let data = common::test_proxy_connection(web_port, b"after", Duration::from_secs(5))
+        .await
+        .expect("Proxy 'web' should still be reachable");
+    assert_eq!(data, b"after");
+
+    client_a.abort();
+    server.shutdown().await.ok();
+}
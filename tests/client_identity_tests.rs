@@ -0,0 +1,392 @@
+/// Client identity tests
+///
+/// 客户端以持久化的 Ed25519 密钥证明身份：服务器的 known_clients 只接受列表中的身份，
+/// 签名与本次挑战不符的认证被拒绝，visitor 可以用身份指纹固定 proxy 的注册者
+mod common;
+
+use futures::future::poll_fn;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+    VisitorConfig,
+};
+use tls_tunnel::control_protocol::{
+    read_frame, write_frame, AuthenticateParams, IdentityChallengeResult, JsonRpcRequest,
+    JsonRpcResponse,
+};
+use tls_tunnel::identity::ClientIdentity;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+
+const AUTH_KEY: &str = "test-client-identity-key";
+
+fn server_config(
+    cert_path: &Path,
+    key_path: &Path,
+    known_clients: Option<Vec<String>>,
+) -> ServerConfig {
+    ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        known_clients,
+        ..common::test_server_config()
+    }
+}
+
+fn client_config(server_port: u16, cert_path: &Path, identity_path: &Path) -> ClientConfig {
+    ClientConfig {
+        server_port,
+        skip_verify: true,
+        ca_cert_path: Some(cert_path.to_path_buf()),
+        auth_key: AUTH_KEY.to_string(),
+        peer_id: Some("tenant".to_string()),
+        identity_path: Some(identity_path.to_path_buf()),
+        ..common::test_client_config()
+    }
+}
+
+fn proxy_client(
+    server_port: u16,
+    cert_path: &Path,
+    identity_path: &Path,
+    publish_port: u16,
+    local_port: u16,
+) -> ClientFullConfig {
+    ClientFullConfig {
+        client: client_config(server_port, cert_path, identity_path),
+        proxies: vec![ProxyConfig {
+            name: "service".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    }
+}
+
+fn spawn_client(config: ClientFullConfig, cert_path: &Path) -> JoinHandle<()> {
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    })
+}
+
+/// 启动一个接受连接后写出固定标识并关闭的后端
+async fn start_banner_server(port: u16, banner: &'static [u8]) -> JoinHandle<()> {
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port))
+        .await
+        .expect("Failed to bind banner server");
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let _ = socket.write_all(banner).await;
+            let _ = socket.shutdown().await;
+        }
+    })
+}
+
+/// 连接端口并读取全部数据（连接被拒绝时返回空）
+async fn read_banner(port: u16) -> Vec<u8> {
+    let mut data = Vec::new();
+    if let Ok(mut stream) = TcpStream::connect(format!("127.0.0.1:{}", port)).await {
+        let _ = timeout(Duration::from_secs(5), stream.read_to_end(&mut data)).await;
+    }
+    data
+}
+
+/// 等待端口上的后端返回 `banner`
+async fn wait_for_banner(port: u16, banner: &[u8]) -> bool {
+    for _ in 0..50 {
+        if read_banner(port).await == banner {
+            return true;
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+    false
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "tls-tunnel-identity-{}-{}",
+        name,
+        std::process::id()
+    ));
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[tokio::test]
+async fn test_known_clients_admit_only_listed_identities() {
+    std::env::set_var("TLS_TUNNEL_RECONNECT_DELAY_SECS", "1");
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let dir = temp_dir("known");
+
+    let known_path = dir.join("known.identity.pem");
+    let unknown_path = dir.join("unknown.identity.pem");
+    let known = ClientIdentity::load_or_create(&known_path).unwrap();
+
+    let server = common::spawn_server(server_config(
+        &cert_path,
+        &key_path,
+        Some(vec![known.fingerprint()]),
+    ))
+    .await;
+    let server_port = server.bound_addr().port();
+
+    let known_publish = common::get_available_port();
+    let unknown_publish = common::get_available_port();
+    let backend_port = common::get_available_port();
+    let backend = start_banner_server(backend_port, b"backend").await;
+
+    // 1. 列表中的身份注册成功
+    let client = spawn_client(
+        proxy_client(
+            server_port,
+            &cert_path,
+            &known_path,
+            known_publish,
+            backend_port,
+        ),
+        &cert_path,
+    );
+    assert!(wait_for_banner(known_publish, b"backend").await);
+
+    // 2. 重新连接时从同一文件读取身份，仍被接受
+    client.abort();
+    for _ in 0..50 {
+        if read_banner(known_publish).await.is_empty() {
+            break;
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+    let client = spawn_client(
+        proxy_client(
+            server_port,
+            &cert_path,
+            &known_path,
+            known_publish,
+            backend_port,
+        ),
+        &cert_path,
+    );
+    assert!(wait_for_banner(known_publish, b"backend").await);
+    assert_eq!(
+        ClientIdentity::load(&known_path).unwrap().fingerprint(),
+        known.fingerprint()
+    );
+
+    // 3. 不在列表中的身份（客户端首次运行时自动生成）认证失败，不会注册 proxy
+    let unknown = spawn_client(
+        proxy_client(
+            server_port,
+            &cert_path,
+            &unknown_path,
+            unknown_publish,
+            backend_port,
+        ),
+        &cert_path,
+    );
+    sleep(Duration::from_secs(2)).await;
+    assert!(unknown_path.exists());
+    assert!(read_banner(unknown_publish).await.is_empty());
+
+    unknown.abort();
+    client.abort();
+    backend.abort();
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+/// 以原始控制协议完成一次身份挑战，并用 `sign_nonce` 返回的随机数签名认证
+///
+/// 返回服务器对认证请求的响应
+async fn authenticate_raw(
+    cert_path: &Path,
+    port: u16,
+    identity: &ClientIdentity,
+    sign_nonce: impl FnOnce(Vec<u8>) -> Vec<u8>,
+) -> JsonRpcResponse {
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let tcp = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let server_name = rustls::pki_types::ServerName::try_from("localhost").unwrap();
+    let tls = TlsConnector::from(tls_config)
+        .connect(server_name, tcp)
+        .await
+        .expect("TLS handshake failed");
+
+    let mut conn =
+        yamux::Connection::new(tls.compat(), yamux::Config::default(), yamux::Mode::Client);
+    let mut stream = poll_fn(|cx| conn.poll_new_outbound(cx)).await.unwrap();
+    let driver = tokio::spawn(async move {
+        while let Some(Ok(_)) = poll_fn(|cx| conn.poll_next_inbound(cx)).await {}
+    });
+
+    let request = JsonRpcRequest::new("identity_challenge".to_string(), serde_json::json!({}), 1);
+    write_frame(&mut stream, &serde_json::to_vec(&request).unwrap())
+        .await
+        .unwrap();
+    let frame = read_frame(&mut stream).await.unwrap().unwrap();
+    let response: JsonRpcResponse = serde_json::from_slice(&frame).unwrap();
+    let challenge: IdentityChallengeResult =
+        serde_json::from_value(response.result.expect("Challenge should succeed")).unwrap();
+    let nonce = tls_tunnel::identity::from_hex(&challenge.nonce).unwrap();
+
+    let params = AuthenticateParams {
+        auth_key: AUTH_KEY.to_string(),
+        protocol_version: env!("CARGO_PKG_VERSION").to_string(),
+        capabilities: Vec::new(),
+        peer_id: None,
+        identity: Some(identity.prove(&sign_nonce(nonce))),
+    };
+    let request = JsonRpcRequest::new(
+        "authenticate".to_string(),
+        serde_json::to_value(params).unwrap(),
+        2,
+    );
+    write_frame(&mut stream, &serde_json::to_vec(&request).unwrap())
+        .await
+        .unwrap();
+    let frame = timeout(Duration::from_secs(5), read_frame(&mut stream))
+        .await
+        .expect("Server should answer the authentication request")
+        .unwrap()
+        .unwrap();
+    driver.abort();
+    serde_json::from_slice(&frame).unwrap()
+}
+
+#[tokio::test]
+async fn test_identity_proof_must_sign_the_session_nonce() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server = common::spawn_server(server_config(&cert_path, &key_path, None)).await;
+    let port = server.bound_addr().port();
+    let identity = ClientIdentity::ephemeral().unwrap();
+
+    // 对本次下发的随机数签名：认证成功
+    let response = authenticate_raw(&cert_path, port, &identity, |nonce| nonce).await;
+    assert!(response.result.is_some(), "{:?}", response.error);
+
+    // 签名的不是本次的随机数（例如重放其他会话的签名）：认证失败
+    let response = authenticate_raw(&cert_path, port, &identity, |mut nonce| {
+        nonce[0] ^= 0xff;
+        nonce
+    })
+    .await;
+    let error = response.error.expect("Forged proof should be rejected");
+    assert!(error.message.contains("identity"), "{}", error.message);
+}
+
+#[tokio::test]
+async fn test_visitor_pins_registrant_by_fingerprint() {
+    std::env::set_var("TLS_TUNNEL_RECONNECT_DELAY_SECS", "1");
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let dir = temp_dir("pinning");
+
+    let server = common::spawn_server(server_config(&cert_path, &key_path, None)).await;
+    let server_port = server.bound_addr().port();
+
+    let publish_port = common::get_available_port();
+    let visitor_port = common::get_available_port();
+    let backend_a_port = common::get_available_port();
+    let backend_b_port = common::get_available_port();
+    let backend_a = start_banner_server(backend_a_port, b"backend-a").await;
+    let backend_b = start_banner_server(backend_b_port, b"backend-b").await;
+
+    let path_a = dir.join("a.identity.pem");
+    let path_b = dir.join("b.identity.pem");
+    let identity_a = ClientIdentity::load_or_create(&path_a).unwrap();
+
+    let client_a = spawn_client(
+        proxy_client(
+            server_port,
+            &cert_path,
+            &path_a,
+            publish_port,
+            backend_a_port,
+        ),
+        &cert_path,
+    );
+    assert!(wait_for_banner(publish_port, b"backend-a").await);
+
+    // visitor 以指纹固定 A 的身份
+    let visitor_config = ClientFullConfig {
+        client: client_config(server_port, &cert_path, &dir.join("visitor.identity.pem")),
+        proxies: vec![],
+        visitors: vec![VisitorConfig {
+            name: "service".to_string(),
+            proxy_type: ProxyType::Tcp,
+            bind_addr: "127.0.0.1".to_string(),
+            bind_port: visitor_port,
+            publish_port,
+            expected_peer_id: Some(identity_a.fingerprint()),
+            connection_reuse: false,
+            socket: None,
+        }],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let visitor_client = spawn_client(visitor_config, &cert_path);
+
+    // 1. 指纹匹配：正常访问 A 的后端
+    assert!(wait_for_banner(visitor_port, b"backend-a").await);
+
+    // 2. A 断开，B 以相同的 peer_id 和 name/publish_port 接管（peer_id 可以伪造，密钥不行）
+    client_a.abort();
+    sleep(Duration::from_millis(300)).await;
+    let client_b = spawn_client(
+        proxy_client(
+            server_port,
+            &cert_path,
+            &path_b,
+            publish_port,
+            backend_b_port,
+        ),
+        &cert_path,
+    );
+    assert!(
+        wait_for_banner(publish_port, b"backend-b").await,
+        "Client B should have taken over the proxy"
+    );
+
+    // 3. 指纹不匹配：visitor 拒绝连接
+    assert!(read_banner(visitor_port).await.is_empty());
+
+    visitor_client.abort();
+    client_b.abort();
+    backend_a.abort();
+    backend_b.abort();
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -1,18 +1,53 @@
+#![allow(dead_code)]
+
 /// Common utilities for integration tests
 use std::net::TcpListener;
 use std::path::PathBuf;
 use std::time::Duration;
+use tls_tunnel::config::{ClientConfig, ServerConfig};
+use tls_tunnel::server::{Server, ServerBuilder, ServerDependencies, ServerHandle};
+use tls_tunnel::transport::TransportType;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener as TokioTcpListener, TcpStream};
 use tokio::time::timeout;
+use tokio_rustls::TlsAcceptor;
+
+/// First port handed out by `get_available_port`
+///
+/// The range stays below the Linux ephemeral range (32768-60999) so the kernel never
+/// hands one of our ports to an outgoing connection or a `bind(0)` listener
+const PORT_RANGE_START: u16 = 10000;
+/// End (exclusive) of the test port range
+const PORT_RANGE_END: u16 = 32000;
+/// Ports reserved for one test binary before it spills into the next block
+const PORT_BLOCK_SIZE: u16 = 1000;
 
-/// Find an available port
+/// Next port offset (relative to `PORT_RANGE_START`) for this process
+static NEXT_PORT: std::sync::OnceLock<std::sync::atomic::AtomicU32> = std::sync::OnceLock::new();
+
+/// Allocate a port that no other test in this process will receive
+///
+/// Ports come from a process-wide counter that starts in a block chosen by the
+/// process id, so concurrently running test binaries use disjoint blocks and tests
+/// inside one binary never race each other for the same port. Ports that are busy
+/// (e.g. held by an unrelated process) are skipped. Prefer `bind_port: 0` together
+/// with `ServerHandle::bound_addr()` where the code under test supports it.
 pub fn get_available_port() -> u16 {
-    TcpListener::bind("127.0.0.1:0")
-        .expect("Failed to bind to random port")
-        .local_addr()
-        .expect("Failed to get local addr")
-        .port()
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    let span = u32::from(PORT_RANGE_END - PORT_RANGE_START);
+    let next = NEXT_PORT.get_or_init(|| {
+        let blocks = span / u32::from(PORT_BLOCK_SIZE);
+        AtomicU32::new(std::process::id() % blocks * u32::from(PORT_BLOCK_SIZE))
+    });
+    for _ in 0..span {
+        let offset = next.fetch_add(1, Ordering::Relaxed) % span;
+        let port = PORT_RANGE_START + offset as u16;
+        if TcpListener::bind(("127.0.0.1", port)).is_ok() {
+            return port;
+        }
+    }
+    panic!("No free port in {}..{}", PORT_RANGE_START, PORT_RANGE_END);
 }
 
 /// Generate temporary certificate files for testing
@@ -44,33 +79,69 @@ pub fn generate_test_certs() -> (PathBuf, PathBuf) {
     (cert_path, key_path)
 }
 
-/// Create a simple echo server for testing
+/// Client certificate, its key and the CA that issued it (files removed on drop)
+pub struct ClientCerts {
+    pub ca_path: PathBuf,
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+impl Drop for ClientCerts {
+    fn drop(&mut self) {
+        for path in [&self.ca_path, &self.cert_path, &self.key_path] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Generate a fresh CA and a client certificate signed by it for mTLS tests
+pub fn generate_client_certs(common_name: &str) -> ClientCerts {
+    static COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+    let unique_id = format!(
+        "{}-{}-{}",
+        common_name,
+        COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst),
+        std::process::id()
+    );
+    let temp_dir = std::env::temp_dir();
+    let certs = ClientCerts {
+        ca_path: temp_dir.join(format!("test-client-ca-{}.pem", unique_id)),
+        cert_path: temp_dir.join(format!("test-client-cert-{}.pem", unique_id)),
+        key_path: temp_dir.join(format!("test-client-key-{}.pem", unique_id)),
+    };
+    tls_tunnel::tls::generate_client_cert(
+        common_name,
+        &certs.ca_path,
+        &certs.cert_path,
+        &certs.key_path,
+    )
+    .expect("Failed to generate client certificates");
+    certs
+}
+
+/// Create a simple echo server for testing; it is listening once this returns
 pub async fn start_echo_server(port: u16) -> tokio::task::JoinHandle<()> {
-    tokio::spawn(async move {
-        let listener = TokioTcpListener::bind(format!("127.0.0.1:{}", port))
-            .await
-            .expect("Failed to bind echo server");
+    let listener = TokioTcpListener::bind(format!("127.0.0.1:{}", port))
+        .await
+        .expect("Failed to bind echo server");
 
-        loop {
-            match listener.accept().await {
-                Ok((mut socket, _)) => {
-                    tokio::spawn(async move {
-                        let mut buf = vec![0u8; 8192];
-                        loop {
-                            match socket.read(&mut buf).await {
-                                Ok(0) => break, // Connection closed
-                                Ok(n) => {
-                                    if socket.write_all(&buf[..n]).await.is_err() {
-                                        break;
-                                    }
-                                }
-                                Err(_) => break,
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buf = vec![0u8; 8192];
+                loop {
+                    match socket.read(&mut buf).await {
+                        Ok(0) => break, // Connection closed
+                        Ok(n) => {
+                            if socket.write_all(&buf[..n]).await.is_err() {
+                                break;
                             }
                         }
-                    });
+                        Err(_) => break,
+                    }
                 }
-                Err(_) => break,
-            }
+            });
         }
     })
 }
@@ -115,6 +186,141 @@ pub async fn test_proxy_connection(
     result
 }
 
+/// Server config for tests: TLS on 127.0.0.1 with every optional setting at its default
+///
+/// Set the fields a test cares about with struct update syntax:
+/// `ServerConfig { auth_key, cert_path, key_path, ..common::test_server_config() }`
+pub fn test_server_config() -> ServerConfig {
+    ServerConfig {
+        bind_addr: "127.0.0.1".to_string(),
+        bind_port: 0,
+        transport: TransportType::Tls,
+        behind_proxy: false,
+        cert_path: None,
+        key_path: None,
+        client_ca_path: None,
+        require_client_cert: false,
+        auth_key: String::new(),
+        auth_keys: None,
+        stats_port: None,
+        stats_addr: None,
+        stats_path: None,
+        allow_forward: false,
+        rate_limit: None,
+        size_limits: None,
+        stats_limits: None,
+        stats_stream: None,
+        interface_prefer_ipv6: false,
+        event_export: None,
+        max_write_chunk: None,
+        debug_stalls: false,
+        debug_tls_handshakes: false,
+        exception_limits: None,
+        drain_timeout_secs: None,
+        protocol_trace_path: None,
+        forward_limits: None,
+        forward_policy: None,
+        max_concurrent_handshakes: None,
+        handshake_timeout_secs: None,
+        idle_registration_expiry_secs: None,
+        relay_memory_budget_mb: None,
+        known_clients: None,
+        visitor_acl: None,
+        timeouts: None,
+        flow_export: None,
+        shutdown_grace_period_secs: None,
+        visitor_retry_timeout_secs: None,
+        tcp_keepalive_secs: None,
+        idle_timeout_secs: None,
+        max_streams_per_client: None,
+        max_connections_per_proxy: None,
+    }
+}
+
+/// Client config for tests: TLS to 127.0.0.1 with every optional setting at its default
+///
+/// Like `test_server_config`, override fields with struct update syntax
+pub fn test_client_config() -> ClientConfig {
+    ClientConfig {
+        server_addr: "127.0.0.1".to_string(),
+        server_port: 0,
+        server_path: "/".to_string(),
+        transport: TransportType::Tls,
+        skip_verify: false,
+        ca_cert_path: None,
+        client_cert_path: None,
+        client_key_path: None,
+        auth_key: String::new(),
+        stats_port: None,
+        stats_addr: None,
+        stats_limits: None,
+        stats_stream: None,
+        stats_socket: None,
+        stats_pipe: None,
+        events_socket: None,
+        peer_id: None,
+        identity_path: None,
+        state_dir: None,
+        fallback_to_last_good: false,
+        watch_config: false,
+        routing_overrides_path: None,
+        routing_ui_token: None,
+        size_limits: None,
+        proxy_retry: None,
+        max_write_chunk: None,
+        debug_stalls: false,
+        protocol_trace_path: None,
+        keep_listening_on_disconnect: true,
+        max_missed_heartbeats: None,
+        relay_memory_budget_mb: None,
+        timeouts: None,
+    }
+}
+
+/// Start a server in-process; it is listening once this returns
+///
+/// Use `bind_port: 0` and `ServerHandle::bound_addr()` to get an ephemeral port
+pub async fn spawn_server(config: ServerConfig) -> ServerHandle {
+    server_builder(config)
+        .spawn()
+        .await
+        .expect("Failed to start server")
+}
+
+/// Like `spawn_server`, sharing the registry and stats in `deps` with the test
+pub async fn spawn_server_with_dependencies(
+    config: ServerConfig,
+    deps: ServerDependencies,
+) -> ServerHandle {
+    server_builder(config)
+        .dependencies(deps)
+        .spawn()
+        .await
+        .expect("Failed to start server")
+}
+
+fn server_builder(config: ServerConfig) -> ServerBuilder {
+    let cert_path = config
+        .cert_path
+        .clone()
+        .expect("Server cert path is required");
+    let key_path = config
+        .key_path
+        .clone()
+        .expect("Server key path is required");
+    let tls_config = tls_tunnel::tls::load_server_config_with_client_auth(
+        &cert_path,
+        &key_path,
+        None,
+        config.client_ca_path.as_deref(),
+        config.require_client_cert,
+    )
+    .expect("Failed to load server TLS config");
+    Server::builder()
+        .config(config)
+        .acceptor(TlsAcceptor::from(tls_config))
+}
+
 /// Wait for server to be ready
 pub async fn wait_for_server(port: u16, max_attempts: u32) -> bool {
     for _ in 0..max_attempts {
@@ -129,6 +335,40 @@ pub async fn wait_for_server(port: u16, max_attempts: u32) -> bool {
     false
 }
 
+/// Wait until a round trip through `port` to an echo backend succeeds
+///
+/// A listening publish port only means the server registered the proxy; the client may
+/// not be serving streams yet, so readiness of a tunnel is checked end to end
+pub async fn wait_for_echo(port: u16, max_attempts: u32) -> bool {
+    for _ in 0..max_attempts {
+        if let Ok(data) = test_proxy_connection(port, b"ready", Duration::from_secs(2)).await {
+            if data == b"ready" {
+                return true;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    false
+}
+
+/// Poll `check` every 50ms until it returns true; gives up (false) after `deadline`
+pub async fn wait_until<F, Fut>(deadline: Duration, mut check: F) -> bool
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = bool>,
+{
+    let start = tokio::time::Instant::now();
+    loop {
+        if check().await {
+            return true;
+        }
+        if start.elapsed() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
 /// Cleanup function for test resources
 pub struct TestCleanup {
     cert_path: Option<PathBuf>,
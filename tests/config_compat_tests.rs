@@ -0,0 +1,158 @@
+/// Config compat tests
+///
+/// 使用其他工具（如 frp）字段名的配置文件通过别名加载为同样的 ClientFullConfig，
+/// 别名与规范字段取值冲突的配置文件被拒绝
+use std::path::PathBuf;
+use tls_tunnel::config::{compat, AppConfig, ClientFullConfig, ProxyType};
+
+/// frpc.toml 风格的字段名（remote_port、listen_addr、listen_port）
+const FRP_FLAVORED: &str = r#"
+# 从 frpc.toml 迁移而来
+[client]
+server_addr = "frps.example.com"
+server_port = 7000
+auth_key = "Xk9#mP2$vL7@qR4!"
+
+[[proxies]]
+name = "ssh"
+proxy_type = "tcp"
+local_port = 22
+remote_port = 6000
+
+[[proxies]]
+name = "web"
+proxy_type = "http/1.1"
+local_port = 8080
+remote_port = 8080
+
+[[visitors]]
+name = "secret-ssh"
+publish_port = 6000
+listen_addr = "127.0.0.1"
+listen_port = 9000
+
+[[forwarders]]
+name = "office"
+proxy_type = "socks5"
+listen_addr = "127.0.0.1"
+listen_port = 1080
+"#;
+
+/// 与 FRP_FLAVORED 等价、使用规范字段名的配置
+const CANONICAL: &str = r#"
+[client]
+server_addr = "frps.example.com"
+server_port = 7000
+auth_key = "Xk9#mP2$vL7@qR4!"
+
+[[proxies]]
+name = "ssh"
+proxy_type = "tcp"
+local_port = 22
+publish_port = 6000
+
+[[proxies]]
+name = "web"
+proxy_type = "http/1.1"
+local_port = 8080
+publish_port = 8080
+
+[[visitors]]
+name = "secret-ssh"
+publish_port = 6000
+bind_addr = "127.0.0.1"
+bind_port = 9000
+
+[[forwarders]]
+name = "office"
+proxy_type = "socks5"
+bind_addr = "127.0.0.1"
+bind_port = 1080
+"#;
+
+/// 写入临时配置文件，drop 时删除
+struct TempConfig(PathBuf);
+
+impl TempConfig {
+    fn new(name: &str, content: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "tls-tunnel-compat-{}-{}.toml",
+            name,
+            std::process::id()
+        ));
+        std::fs::write(&path, content).unwrap();
+        Self(path)
+    }
+
+    fn load(&self) -> anyhow::Result<ClientFullConfig> {
+        AppConfig::load_client_config(self.0.to_str().unwrap())
+    }
+}
+
+impl Drop for TempConfig {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[test]
+fn test_frp_flavored_config_loads_as_canonical() {
+    let frp = TempConfig::new("frp", FRP_FLAVORED).load().unwrap();
+    let canonical = TempConfig::new("canonical", CANONICAL).load().unwrap();
+
+    assert_eq!(
+        serde_json::to_value(&frp).unwrap(),
+        serde_json::to_value(&canonical).unwrap()
+    );
+    assert_eq!(frp.client.server_addr, "frps.example.com");
+    assert_eq!(frp.proxies[0].proxy_type, ProxyType::Tcp);
+    assert_eq!(frp.proxies[0].publish_port, 6000);
+    assert_eq!(frp.proxies[0].local_port, 22);
+    assert_eq!(frp.proxies[1].publish_port, 8080);
+    assert_eq!(frp.visitors[0].bind_addr, "127.0.0.1");
+    assert_eq!(frp.visitors[0].bind_port, 9000);
+    assert_eq!(frp.forwarders[0].proxy_type, ProxyType::Socks5Proxy);
+    assert_eq!(frp.forwarders[0].bind_port, 1080);
+}
+
+#[test]
+fn test_frp_flavored_config_reports_aliases() {
+    let (_, report) = compat::parse_client_config(FRP_FLAVORED).unwrap();
+    assert!(report.conflicts.is_empty());
+    let warning = report.deprecation_warning().unwrap();
+    for expected in [
+        "proxies.remote_port -> publish_port (2 uses)",
+        "visitors.listen_addr -> bind_addr (1 use)",
+        "visitors.listen_port -> bind_port (1 use)",
+        "forwarders.listen_addr -> bind_addr (1 use)",
+        "forwarders.listen_port -> bind_port (1 use)",
+    ] {
+        assert!(warning.contains(expected), "{}", warning);
+    }
+
+    let (_, report) = compat::parse_client_config(CANONICAL).unwrap();
+    assert!(report.is_empty());
+}
+
+#[test]
+fn test_conflicting_alias_is_rejected_on_load() {
+    let conflicting = FRP_FLAVORED.replace(
+        "remote_port = 6000",
+        "remote_port = 6000\npublish_port = 6001",
+    );
+    let err = TempConfig::new("conflict", &conflicting)
+        .load()
+        .unwrap_err();
+    let message = format!("{:#}", err);
+    assert!(message.contains("'ssh'"), "{}", message);
+    assert!(message.contains("remote_port = 6000"), "{}", message);
+    assert!(message.contains("publish_port = 6001"), "{}", message);
+
+    // 取值相同的重复设置只产生弃用警告
+    let duplicated = FRP_FLAVORED.replace(
+        "remote_port = 6000",
+        "remote_port = 6000\npublish_port = 6000",
+    );
+    let config = TempConfig::new("duplicate", &duplicated).load().unwrap();
+    assert_eq!(config.proxies[0].publish_port, 6000);
+}
@@ -0,0 +1,170 @@
+/// Config rejection details tests
+///
+/// 服务器部分拒绝配置时在响应中给出结构化的拒绝条目（名称、发布端口、原因和类型），
+/// 客户端据此在统计中把被拒绝的代理和 visitor 标记为 "Rejected: <原因>"，
+/// 并且不启动被拒绝的 visitor 的监听器
+mod common;
+
+use std::time::Duration;
+use tls_tunnel::client::SessionEvent;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+    VisitorConfig,
+};
+use tls_tunnel::control_protocol::{RejectedEntry, RejectedKind, SubmitConfigResult};
+use tls_tunnel::stats_http::StatsEndpoint;
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-config-rejection-key";
+
+fn proxy(name: &str, publish_port: u16, local_port: u16) -> ProxyConfig {
+    ProxyConfig {
+        name: name.to_string(),
+        proxy_type: ProxyType::Tcp,
+        publish_addr: "127.0.0.1".to_string(),
+        publish_port,
+        local_port,
+        pool: None,
+        shared: false,
+        weight: None,
+        visibility: ProxyVisibility::Public,
+        drain_timeout_secs: None,
+        sni_routing: None,
+        socket: None,
+        local_targets: None,
+        idle_keepalive_secs: None,
+        require_first_byte_timeout_ms: None,
+        max_connections_per_source: None,
+        report_peers: false,
+        ws_idle_timeout_secs: None,
+        integrity_check: false,
+    }
+}
+
+/// 读取条目在统计信息中的状态
+async fn status_of(endpoint: &StatsEndpoint, name: &str) -> Option<String> {
+    let body = endpoint.get("/stats").await.ok()?;
+    let stats: Vec<serde_json::Value> = serde_json::from_str(&body).ok()?;
+    stats
+        .iter()
+        .find(|s| s["name"] == name)
+        .and_then(|s| s["status"].as_str().map(str::to_string))
+}
+
+#[tokio::test]
+async fn test_rejected_entries_marked_in_stats() {
+    let blocked_port = common::get_available_port();
+    let ok_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let visitor_port = common::get_available_port();
+    let missing_port = common::get_available_port();
+    let stats_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+
+    // 其他进程占用了 web 的发布端口
+    let _blocker = std::net::TcpListener::bind(("127.0.0.1", blocked_port)).unwrap();
+
+    let server = common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        ..common::test_server_config()
+    })
+    .await;
+
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.clone()),
+            auth_key: AUTH_KEY.to_string(),
+            stats_port: Some(stats_port),
+            stats_addr: Some("127.0.0.1".to_string()),
+            ..common::test_client_config()
+        },
+        // 全部代理被拒绝时会话会结束，这里另配置一个正常的代理
+        proxies: vec![
+            proxy("web", blocked_port, local_port),
+            proxy("ok", ok_port, local_port),
+        ],
+        // 引用不存在的代理的 visitor
+        visitors: vec![VisitorConfig {
+            name: "ghost".to_string(),
+            proxy_type: ProxyType::Tcp,
+            bind_addr: "127.0.0.1".to_string(),
+            bind_port: visitor_port,
+            publish_port: missing_port,
+            expected_peer_id: None,
+            connection_reuse: false,
+            socket: None,
+        }],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let (events_tx, mut events_rx) = broadcast::channel(64);
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client_with_events(client_config, connector, events_tx)
+            .await
+            .ok();
+    });
+
+    timeout(Duration::from_secs(10), async {
+        while !matches!(events_rx.recv().await, Ok(SessionEvent::Degraded(_))) {}
+    })
+    .await
+    .expect("Session was not degraded");
+
+    let endpoint = StatsEndpoint::Http(format!("http://127.0.0.1:{}", stats_port));
+    assert!(
+        common::wait_until(Duration::from_secs(5), || async {
+            status_of(&endpoint, "ghost").await.as_deref() == Some("Rejected: 引用的代理不存在")
+        })
+        .await,
+        "Visitor status: {:?}",
+        status_of(&endpoint, "ghost").await
+    );
+    let web = status_of(&endpoint, "web").await.unwrap_or_default();
+    assert!(web.starts_with("Rejected: "), "Proxy status: {}", web);
+    assert!(!status_of(&endpoint, "ok")
+        .await
+        .unwrap_or_default()
+        .starts_with("Rejected"));
+
+    // 被拒绝的 visitor 没有启动监听器
+    assert!(TcpStream::connect(("127.0.0.1", visitor_port))
+        .await
+        .is_err());
+
+    client.abort();
+}
+
+#[test]
+fn test_submit_config_result_without_entries() {
+    // 旧版本服务器只发送 name:port 列表
+    let result: SubmitConfigResult = serde_json::from_value(serde_json::json!({
+        "rejected_proxies": ["web:8080"],
+        "reasons": {"web:8080": "publish_port 8080 is already in use"},
+    }))
+    .unwrap();
+    assert!(result.rejected_entries.is_empty());
+
+    let entry = RejectedEntry::from_item("web:8080", RejectedKind::Proxy, &result.reasons);
+    assert_eq!(entry.name, "web");
+    assert_eq!(entry.publish_port, 8080);
+    assert_eq!(entry.reason, "publish_port 8080 is already in use");
+    assert_eq!(entry.item(), "web:8080");
+    assert_eq!(
+        serde_json::to_value(&entry).unwrap()["kind"],
+        serde_json::json!("proxy")
+    );
+}
@@ -0,0 +1,245 @@
+/// Config reload tests
+///
+/// 从配置文件运行的客户端在文件变化（`watch_config`）或收到 SIGHUP 时应用 proxies 的增删，
+/// 不断开会话：已有连接继续转发，单个代理被拒绝不影响其他代理
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tls_tunnel::config::{AppConfig, ServerConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-config-reload-key";
+
+fn server_config(cert_path: &Path, key_path: &Path) -> ServerConfig {
+    ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        ..common::test_server_config()
+    }
+}
+
+/// 客户端配置文件（proxies 为 (名称, 发布端口, 本地回显服务端口)）
+struct ClientFile {
+    path: PathBuf,
+    server_port: u16,
+    cert_path: PathBuf,
+    watch: bool,
+}
+
+impl ClientFile {
+    fn new(name: &str, server_port: u16, cert_path: &Path, watch: bool) -> Self {
+        Self {
+            path: std::env::temp_dir().join(format!(
+                "tls-tunnel-config-reload-{}-{}.toml",
+                name,
+                std::process::id()
+            )),
+            server_port,
+            cert_path: cert_path.to_path_buf(),
+            watch,
+        }
+    }
+
+    fn write(&self, proxies: &[(&str, u16, u16)]) {
+        let mut content = format!(
+            "[client]\nserver_addr = \"127.0.0.1\"\nserver_port = {}\nauth_key = \"{}\"\n\
+             skip_verify = true\nca_cert_path = {:?}\nwatch_config = {}\n",
+            self.server_port,
+            AUTH_KEY,
+            self.cert_path.display().to_string(),
+            self.watch,
+        );
+        for (name, publish_port, local_port) in proxies {
+            content.push_str(&format!(
+                "\n[[proxies]]\nname = \"{}\"\npublish_port = {}\nlocal_port = {}\n",
+                name, publish_port, local_port
+            ));
+        }
+        std::fs::write(&self.path, content).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&self.path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        }
+    }
+
+    fn spawn_client(&self) -> JoinHandle<()> {
+        let path = self.path.to_string_lossy().to_string();
+        let config = AppConfig::load_client_config(&path).expect("Invalid client config");
+        let tls_config =
+            tls_tunnel::tls::load_client_config_with_alpn(Some(&self.cert_path), true, None)
+                .expect("Failed to load client TLS config");
+        let connector = TlsConnector::from(tls_config);
+        tokio::spawn(async move {
+            tls_tunnel::client::run_client_with_source(config, path.into(), connector)
+                .await
+                .ok();
+        })
+    }
+}
+
+impl Drop for ClientFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// 发送数据并读取等长的回显
+async fn echo(stream: &mut TcpStream, data: &[u8]) -> std::io::Result<Vec<u8>> {
+    stream.write_all(data).await?;
+    let mut received = vec![0u8; data.len()];
+    timeout(Duration::from_secs(10), stream.read_exact(&mut received))
+        .await
+        .map_err(|_| std::io::Error::other("Timed out reading echo"))??;
+    Ok(received)
+}
+
+/// 连接发布端口并完成第一次回显
+async fn connect_ready(publish_port: u16) -> TcpStream {
+    for _ in 0..100 {
+        if let Ok(mut stream) = TcpStream::connect(("127.0.0.1", publish_port)).await {
+            if echo(&mut stream, b"first")
+                .await
+                .is_ok_and(|data| data == b"first")
+            {
+                return stream;
+            }
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Proxy on port {} never became reachable", publish_port);
+}
+
+/// 新连接能否通过发布端口完成回显
+async fn reachable(publish_port: u16) -> bool {
+    common::test_proxy_connection(publish_port, b"probe", Duration::from_millis(500))
+        .await
+        .is_ok_and(|data| data == b"probe")
+}
+
+#[tokio::test]
+async fn test_watch_config_adds_and_removes_proxies() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server = common::spawn_server(server_config(&cert_path, &key_path)).await;
+    let (first_echo, second_echo) = (common::get_available_port(), common::get_available_port());
+    let _first_echo = common::start_echo_server(first_echo).await;
+    let _second_echo = common::start_echo_server(second_echo).await;
+    let first_port = common::get_available_port();
+    let second_port = common::get_available_port();
+
+    let file = ClientFile::new("watch", server.bound_addr().port(), &cert_path, true);
+    file.write(&[("first", first_port, first_echo)]);
+    let client = file.spawn_client();
+    let mut first = connect_ready(first_port).await;
+    assert!(!reachable(second_port).await);
+
+    // 新增代理：不断开会话，已有连接继续转发
+    file.write(&[
+        ("first", first_port, first_echo),
+        ("second", second_port, second_echo),
+    ]);
+    assert!(
+        common::wait_until(Duration::from_secs(15), || reachable(second_port)).await,
+        "Added proxy never became reachable"
+    );
+    assert_eq!(echo(&mut first, b"still").await.unwrap(), b"still");
+
+    // 删除代理：不再接受新连接，进行中的连接排空
+    file.write(&[("second", second_port, second_echo)]);
+    assert!(
+        common::wait_until(Duration::from_secs(15), || async {
+            !reachable(first_port).await
+        })
+        .await,
+        "Removed proxy kept accepting connections"
+    );
+    assert_eq!(echo(&mut first, b"drain").await.unwrap(), b"drain");
+    assert!(reachable(second_port).await);
+
+    client.abort();
+}
+
+#[tokio::test]
+async fn test_rejected_reload_keeps_session() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server = common::spawn_server(server_config(&cert_path, &key_path)).await;
+    let (first_echo, second_echo) = (common::get_available_port(), common::get_available_port());
+    let _first_echo = common::start_echo_server(first_echo).await;
+    let _second_echo = common::start_echo_server(second_echo).await;
+    let first_port = common::get_available_port();
+    let second_port = common::get_available_port();
+    // 发布端口已被占用，服务器拒绝该代理
+    let occupied = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let occupied_port = occupied.local_addr().unwrap().port();
+
+    let file = ClientFile::new("rejected", server.bound_addr().port(), &cert_path, true);
+    file.write(&[("first", first_port, first_echo)]);
+    let client = file.spawn_client();
+    let mut first = connect_ready(first_port).await;
+
+    file.write(&[
+        ("first", first_port, first_echo),
+        ("blocked", occupied_port, occupied_port),
+        ("second", second_port, second_echo),
+    ]);
+    assert!(
+        common::wait_until(Duration::from_secs(15), || reachable(second_port)).await,
+        "Proxy added next to a rejected one never became reachable"
+    );
+    assert_eq!(echo(&mut first, b"still").await.unwrap(), b"still");
+    assert!(reachable(first_port).await);
+
+    client.abort();
+    drop(occupied);
+}
+
+/// 未开启 `watch_config` 时只在收到 SIGHUP 后重新加载
+#[cfg(unix)]
+#[tokio::test]
+async fn test_sighup_reloads_config() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server = common::spawn_server(server_config(&cert_path, &key_path)).await;
+    let (first_echo, second_echo) = (common::get_available_port(), common::get_available_port());
+    let _first_echo = common::start_echo_server(first_echo).await;
+    let _second_echo = common::start_echo_server(second_echo).await;
+    let first_port = common::get_available_port();
+    let second_port = common::get_available_port();
+
+    let file = ClientFile::new("sighup", server.bound_addr().port(), &cert_path, false);
+    file.write(&[("first", first_port, first_echo)]);
+    let client = file.spawn_client();
+    let mut first = connect_ready(first_port).await;
+
+    file.write(&[
+        ("first", first_port, first_echo),
+        ("second", second_port, second_echo),
+    ]);
+    sleep(Duration::from_secs(3)).await;
+    assert!(
+        !reachable(second_port).await,
+        "Config was reloaded without SIGHUP"
+    );
+
+    unsafe {
+        libc::kill(libc::getpid(), libc::SIGHUP);
+    }
+    assert!(
+        common::wait_until(Duration::from_secs(10), || reachable(second_port)).await,
+        "Proxy added before SIGHUP never became reachable"
+    );
+    assert_eq!(echo(&mut first, b"still").await.unwrap(), b"still");
+
+    client.abort();
+}
@@ -16,6 +16,7 @@ fn test_exception_notification_structure() {
             "field": "port",
             "value": -1
         })),
+        occurrences: None,
     };
 
     assert_eq!(error_notification.level, "error");
@@ -31,6 +32,7 @@ fn test_exception_notification_serialization() {
         message: "连接数接近限制".to_string(),
         code: Some("CONN_LIMIT".to_string()),
         data: Some(json!({"current": 900, "limit": 1000})),
+        occurrences: None,
     };
 
     // 序列化为 JSON
@@ -53,6 +55,7 @@ fn test_exception_notification_optional_fields() {
         message: "配置已更新".to_string(),
         code: None,
         data: None,
+        occurrences: None,
     };
 
     // 序列化时应该跳过 None 字段
@@ -68,6 +71,7 @@ fn test_jsonrpc_notification_format() {
         message: "测试错误".to_string(),
         code: Some("TEST_ERROR".to_string()),
         data: None,
+        occurrences: None,
     };
 
     let request = JsonRpcRequest {
@@ -81,6 +85,92 @@ fn test_jsonrpc_notification_format() {
     assert_eq!(request.method, "push_exception");
 }
 
+#[test]
+fn test_parse_message_classification() {
+    let request = br#"{"jsonrpc":"2.0","method":"heartbeat","params":null}"#;
+    assert!(matches!(parse_message(request), ControlMessage::Request(_)));
+
+    let response = br#"{"jsonrpc":"2.0","result":{},"id":1}"#;
+    assert!(matches!(
+        parse_message(response),
+        ControlMessage::Response(_)
+    ));
+
+    match parse_message(b"{broken") {
+        ControlMessage::Malformed { id, error } => {
+            assert!(id.is_null());
+            assert_eq!(error.code, PARSE_ERROR);
+        }
+        other => panic!("Unexpected message: {:?}", other),
+    }
+
+    // 能识别出 id 时错误响应带上原请求 id
+    match parse_message(br#"{"jsonrpc":"2.0","id":3,"params":{}}"#) {
+        ControlMessage::Malformed { id, error } => {
+            assert_eq!(id, json!(3));
+            assert_eq!(error.code, INVALID_REQUEST);
+        }
+        other => panic!("Unexpected message: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_read_frame_fatal_conditions() {
+    // 未分帧的 JSON 视为帧不同步
+    let mut reader = futures::io::Cursor::new(br#"{"jsonrpc":"2.0"}"#.to_vec());
+    let err = read_frame(&mut reader).await.unwrap_err();
+    assert_eq!(err.condition(), "framing_desync");
+
+    // 超过长度上限
+    let len = (MAX_CONTROL_MESSAGE_SIZE as u32 + 1).to_be_bytes();
+    let mut reader = futures::io::Cursor::new(len.to_vec());
+    let err = read_frame(&mut reader).await.unwrap_err();
+    assert_eq!(err.condition(), "size_limit_exceeded");
+
+    // 正常帧与正常关闭
+    let mut data = 2u32.to_be_bytes().to_vec();
+    data.extend_from_slice(b"{}");
+    let mut reader = futures::io::Cursor::new(data);
+    assert_eq!(read_frame(&mut reader).await.unwrap().unwrap(), b"{}");
+    assert!(read_frame(&mut reader).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_frame_reader_survives_cancelled_read() {
+    use tokio_util::compat::TokioAsyncReadCompatExt;
+
+    let (mut writer, reader) = tokio::io::duplex(64);
+    let mut reader = reader.compat();
+    let mut frames = FrameReader::new();
+
+    // 只收到长度前缀时读取被取消，已读的前缀不能丢失
+    tokio::io::AsyncWriteExt::write_all(&mut writer, &2u32.to_be_bytes())
+        .await
+        .unwrap();
+    let cancelled = tokio::time::timeout(
+        std::time::Duration::from_millis(50),
+        frames.read_frame(&mut reader),
+    )
+    .await;
+    assert!(cancelled.is_err());
+
+    tokio::io::AsyncWriteExt::write_all(&mut writer, b"{}")
+        .await
+        .unwrap();
+    assert_eq!(
+        frames.read_frame(&mut reader).await.unwrap().unwrap(),
+        b"{}"
+    );
+
+    // 消息体中途关闭视为帧不同步
+    tokio::io::AsyncWriteExt::write_all(&mut writer, &[0, 0, 0, 8, b'{'])
+        .await
+        .unwrap();
+    drop(writer);
+    let err = frames.read_frame(&mut reader).await.unwrap_err();
+    assert_eq!(err.condition(), "framing_desync");
+}
+
 // 使用示例（集成到实际代码中）
 mod usage_examples {
     use tls_tunnel::control_protocol::ExceptionNotification;
@@ -97,6 +187,7 @@ mod usage_examples {
                 "invalid_port": -1,
                 "valid_range": "1-65535"
             })),
+            occurrences: None,
         }
     }
 
@@ -112,6 +203,7 @@ mod usage_examples {
                 "response_time_ms": 5000,
                 "threshold_ms": 1000
             })),
+            occurrences: None,
         }
     }
 
@@ -127,6 +219,7 @@ mod usage_examples {
                 "publish_port": 8080,
                 "started_at": "2025-12-27T10:30:00Z"
             })),
+            occurrences: None,
         }
     }
 
@@ -143,6 +236,7 @@ mod usage_examples {
                 "percentage": 80,
                 "recommendation": "考虑升级带宽或限制连接数"
             })),
+            occurrences: None,
         }
     }
 
@@ -158,6 +252,7 @@ mod usage_examples {
                 "attempt_count": 3,
                 "locked_until": "2025-12-27T10:35:00Z"
             })),
+            occurrences: None,
         }
     }
 }
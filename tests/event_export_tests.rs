@@ -0,0 +1,318 @@
+/// Connection event export tests
+///
+/// 服务器配置 `event_export` 后，把认证、代理注册、外部连接、forward 请求和终止事件
+/// 以 JSON 行写入本地采集器；这里按脚本驱动一个客户端的完整生命周期并检查事件顺序
+mod common;
+
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, EventExportConfig, EventExportFormat, ForwarderConfig,
+    ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+};
+use tls_tunnel::server::events::{ConnectionKind, ServerEvent, ServerEventKind};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-event-export-key";
+
+/// 本地采集器：接受导出连接并逐行解析事件
+struct Collector {
+    rx: mpsc::UnboundedReceiver<ServerEvent>,
+    /// 等待其他事件时跳过的事件
+    skipped: Vec<ServerEventKind>,
+}
+
+impl Collector {
+    async fn start() -> (u16, Self) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let mut lines = BufReader::new(socket).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let event: ServerEvent = serde_json::from_str(&line)
+                        .unwrap_or_else(|e| panic!("Invalid event line '{}': {}", line, e));
+                    if tx.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        let collector = Self {
+            rx,
+            skipped: Vec::new(),
+        };
+        (port, collector)
+    }
+
+    /// 等待下一个满足条件的事件（跳过其他事件），按调用顺序等待即可断言事件先后
+    async fn expect(
+        &mut self,
+        what: &str,
+        matches: impl Fn(&ServerEventKind) -> bool,
+    ) -> ServerEventKind {
+        let Self { rx, skipped } = self;
+        timeout(Duration::from_secs(10), async {
+            loop {
+                let event = rx.recv().await.expect("Collector closed");
+                assert_eq!(
+                    event.version,
+                    tls_tunnel::server::events::EVENT_SCHEMA_VERSION
+                );
+                if matches(&event.kind) {
+                    return event.kind;
+                }
+                skipped.push(event.kind);
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("Timed out waiting for {}", what))
+    }
+
+    /// 等待满足条件的事件，不要求与其他事件的先后顺序
+    async fn expect_unordered(
+        &mut self,
+        what: &str,
+        matches: impl Fn(&ServerEventKind) -> bool,
+    ) -> ServerEventKind {
+        match self.skipped.iter().position(&matches) {
+            Some(index) => self.skipped.remove(index),
+            None => self.expect(what, matches).await,
+        }
+    }
+}
+
+fn client_config(
+    server_port: u16,
+    auth_key: &str,
+    cert_path: &std::path::Path,
+    proxies: Vec<ProxyConfig>,
+    forwarders: Vec<ForwarderConfig>,
+) -> ClientFullConfig {
+    ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: auth_key.to_string(),
+            ..common::test_client_config()
+        },
+        proxies,
+        visitors: vec![],
+        forwarders,
+        visitor_gateway: None,
+        health: None,
+    }
+}
+
+fn spawn_client(
+    config: ClientFullConfig,
+    cert_path: &std::path::Path,
+) -> tokio::task::JoinHandle<()> {
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    })
+}
+
+#[tokio::test]
+async fn test_client_lifecycle_events_are_exported() {
+    let (collector_port, mut collector) = Collector::start().await;
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let forwarder_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+
+    let server_config = ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        event_export: Some(EventExportConfig {
+            target: format!("tcp:127.0.0.1:{}", collector_port),
+            format: EventExportFormat::Jsonl,
+            queue_size: 256,
+        }),
+        ..common::test_server_config()
+    };
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
+
+    // 1. 认证失败的客户端
+    let bad_client = spawn_client(
+        client_config(
+            server_port,
+            "wrong-key-for-event-export",
+            &cert_path,
+            vec![],
+            vec![],
+        ),
+        &cert_path,
+    );
+    collector.expect("auth_failed", |e| {
+        matches!(e, ServerEventKind::AuthFailed { reason, .. } if reason == "Invalid authentication key")
+    })
+    .await;
+    collector.expect("session_closed after auth failure", |e| {
+        matches!(e, ServerEventKind::SessionClosed { client_id: None, reason } if reason == "Authentication failed")
+    })
+    .await;
+    bad_client.abort();
+
+    // 2. 正常客户端：认证并注册代理
+    let client = spawn_client(
+        client_config(
+            server_port,
+            AUTH_KEY,
+            &cert_path,
+            vec![ProxyConfig {
+                name: "web".to_string(),
+                proxy_type: ProxyType::Tcp,
+                publish_addr: "127.0.0.1".to_string(),
+                publish_port,
+                local_port,
+                pool: None,
+                shared: false,
+                weight: None,
+                visibility: ProxyVisibility::Public,
+                drain_timeout_secs: None,
+                sni_routing: None,
+                socket: None,
+                local_targets: None,
+                idle_keepalive_secs: None,
+                require_first_byte_timeout_ms: None,
+                max_connections_per_source: None,
+                report_peers: false,
+                ws_idle_timeout_secs: None,
+                integrity_check: false,
+            }],
+            vec![ForwarderConfig {
+                name: "egress".to_string(),
+                proxy_type: ProxyType::HttpProxy,
+                bind_addr: "127.0.0.1".to_string(),
+                bind_port: forwarder_port,
+                routing: None,
+                fast_fail: None,
+                direct_egress: None,
+                socket: None,
+                report_direct: false,
+                idle_timeout_secs: None,
+                header_rules: None,
+            }],
+        ),
+        &cert_path,
+    );
+    let ServerEventKind::AuthSucceeded { client_id, .. } = collector
+        .expect("auth_succeeded", |e| {
+            matches!(e, ServerEventKind::AuthSucceeded { .. })
+        })
+        .await
+    else {
+        unreachable!()
+    };
+    let registered = collector
+        .expect("proxy_registered", |e| {
+            matches!(e, ServerEventKind::ProxyRegistered { .. })
+        })
+        .await;
+    assert_eq!(
+        registered,
+        ServerEventKind::ProxyRegistered {
+            client_id: client_id.clone(),
+            name: "web".to_string(),
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            shared: false,
+        }
+    );
+    // 客户端在处理完注册结果后才启动 forwarder 监听
+    assert!(
+        common::wait_for_server(forwarder_port, 50).await,
+        "Client session never became ready"
+    );
+
+    // 3. 外部连接经代理转发
+    let response =
+        common::test_proxy_connection(publish_port, b"hello events", Duration::from_secs(5))
+            .await
+            .expect("Proxy connection failed");
+    assert_eq!(response, b"hello events");
+    let accepted = collector
+        .expect("connection_accepted", |e| {
+            matches!(e, ServerEventKind::ConnectionAccepted { .. })
+        })
+        .await;
+    let ServerEventKind::ConnectionAccepted {
+        name, peer_addr, ..
+    } = accepted
+    else {
+        unreachable!()
+    };
+    assert_eq!(name, "web");
+    assert!(peer_addr.starts_with("127.0.0.1:"));
+    // 4. forward 请求（服务器未启用 forward，请求被拒绝）
+    let mut stream = TcpStream::connect(("127.0.0.1", forwarder_port))
+        .await
+        .expect("Failed to connect to forwarder");
+    stream
+        .write_all(b"CONNECT 192.0.2.1:443 HTTP/1.1\r\nHost: 192.0.2.1:443\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = [0u8; 256];
+    let _ = timeout(Duration::from_secs(5), stream.read(&mut buf)).await;
+    let forward = collector
+        .expect("forward_requested", |e| {
+            matches!(e, ServerEventKind::ForwardRequested { .. })
+        })
+        .await;
+    assert_eq!(
+        forward,
+        ServerEventKind::ForwardRequested {
+            client_id: client_id.clone(),
+            target: "192.0.2.1:443".to_string(),
+            allowed: false,
+            reason: Some("Forward feature is not enabled on server".to_string()),
+        }
+    );
+
+    // 5. 客户端断开：注销代理并结束会话
+    client.abort();
+    collector.expect("proxy_unregistered", |e| {
+        matches!(e, ServerEventKind::ProxyUnregistered { name, remaining_backends: 0, .. } if name == "web")
+    })
+    .await;
+    let closed = collector
+        .expect("session_closed", |e| {
+            matches!(
+                e,
+                ServerEventKind::SessionClosed {
+                    client_id: Some(_),
+                    ..
+                }
+            )
+        })
+        .await;
+    let ServerEventKind::SessionClosed {
+        client_id: closed_id,
+        ..
+    } = closed
+    else {
+        unreachable!()
+    };
+    assert_eq!(closed_id, Some(client_id));
+
+    // 代理连接随会话结束而终止（与注销事件的先后不固定）
+    collector
+        .expect_unordered("proxy connection_closed", |e| {
+            matches!(e, ServerEventKind::ConnectionClosed { kind: ConnectionKind::Proxy, name, .. } if name == "web")
+        })
+        .await;
+}
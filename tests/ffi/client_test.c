@@ -0,0 +1,117 @@
+/*
+ * C ABI smoke test: start a client against a running server, wait for the
+ * "running" session event, then stop and free it.
+ *
+ * Usage: client_test <client-config.toml>
+ * Built by .github/workflows/ffi.yml with AddressSanitizer so that leaks fail the run.
+ */
+#define _POSIX_C_SOURCE 200809L
+
+#include <stdatomic.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <time.h>
+
+#include "tls_tunnel.h"
+
+static void on_event(const char *event_json, void *user_data) {
+    atomic_int *running = user_data;
+    printf("event: %s\n", event_json);
+    if (strstr(event_json, "\"event\":\"running\"") != NULL) {
+        atomic_store(running, 1);
+    }
+}
+
+static char *read_file(const char *path) {
+    FILE *file = fopen(path, "rb");
+    if (file == NULL) {
+        return NULL;
+    }
+    fseek(file, 0, SEEK_END);
+    long size = ftell(file);
+    fseek(file, 0, SEEK_SET);
+    char *content = malloc((size_t)size + 1);
+    if (content != NULL && fread(content, 1, (size_t)size, file) == (size_t)size) {
+        content[size] = '\0';
+    } else {
+        free(content);
+        content = NULL;
+    }
+    fclose(file);
+    return content;
+}
+
+static int fail(const char *message, TtClient *client) {
+    fprintf(stderr, "FAILED: %s\n", message);
+    tt_client_free(client);
+    return 1;
+}
+
+int main(int argc, char **argv) {
+    if (argc != 2) {
+        fprintf(stderr, "usage: %s <client-config.toml>\n", argv[0]);
+        return 2;
+    }
+    char *config = read_file(argv[1]);
+    if (config == NULL) {
+        fprintf(stderr, "cannot read %s\n", argv[1]);
+        return 2;
+    }
+
+    if (tt_client_new("not = [valid") != NULL) {
+        free(config);
+        return fail("invalid configuration was accepted", NULL);
+    }
+
+    TtClient *client = tt_client_new(config);
+    free(config);
+    if (client == NULL) {
+        return fail("tt_client_new returned NULL", NULL);
+    }
+
+    atomic_int running = 0;
+    if (tt_client_set_event_callback(client, on_event, &running) != 0) {
+        return fail("tt_client_set_event_callback", client);
+    }
+    if (tt_client_start(client) != 0) {
+        return fail("tt_client_start", client);
+    }
+    if (tt_client_start(client) == 0) {
+        return fail("second tt_client_start should fail while running", client);
+    }
+
+    struct timespec interval = {0, 100 * 1000 * 1000};
+    for (int i = 0; i < 100 && !atomic_load(&running); i++) {
+        nanosleep(&interval, NULL);
+    }
+    if (!atomic_load(&running)) {
+        return fail("no running event within 10 seconds", client);
+    }
+
+    char *status = tt_client_status_json(client);
+    if (status == NULL) {
+        return fail("tt_client_status_json returned NULL", client);
+    }
+    printf("status: %s\n", status);
+    int status_ok = strstr(status, "\"state\":\"running\"") != NULL;
+    tt_string_free(status);
+    if (!status_ok) {
+        return fail("status does not report running", client);
+    }
+
+    if (tt_client_stop(client) != 0) {
+        return fail("tt_client_stop", client);
+    }
+    status = tt_client_status_json(client);
+    status_ok = status != NULL && strstr(status, "\"running\":false") != NULL;
+    tt_string_free(status);
+    if (!status_ok) {
+        return fail("status still reports running after stop", client);
+    }
+
+    tt_client_set_event_callback(client, NULL, NULL);
+    tt_client_free(client);
+    printf("ok\n");
+    return 0;
+}
@@ -0,0 +1,107 @@
+#![cfg(feature = "ffi")]
+
+/// C ABI tests (requires the `ffi` feature)
+///
+/// 通过导出的 C 函数启动客户端，等待 running 事件后停止并释放
+mod common;
+
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tls_tunnel::config::ServerConfig;
+use tls_tunnel::ffi::*;
+
+extern "C" fn record_event(event_json: *const c_char, user_data: *mut c_void) {
+    let events = unsafe { &*(user_data as *const Mutex<Vec<String>>) };
+    let event = unsafe { CStr::from_ptr(event_json) }
+        .to_string_lossy()
+        .into_owned();
+    events.lock().unwrap().push(event);
+}
+
+fn status(client: *const TtClient) -> serde_json::Value {
+    let raw = unsafe { tt_client_status_json(client) };
+    assert!(!raw.is_null());
+    let json = unsafe { CStr::from_ptr(raw) }.to_str().unwrap().to_string();
+    unsafe { tt_string_free(raw) };
+    serde_json::from_str(&json).unwrap()
+}
+
+#[test]
+fn test_ffi_client_lifecycle() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let auth_key = "test-ffi-client-key";
+
+    // 服务器运行在独立的运行时中，客户端使用自己内部的运行时
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let server = runtime.block_on(common::spawn_server(ServerConfig {
+        auth_key: auth_key.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        ..common::test_server_config()
+    }));
+
+    let config = CString::new(format!(
+        "[client]\nserver_addr = \"127.0.0.1\"\nserver_port = {}\nauth_key = \"{}\"\nca_cert_path = {:?}\n\n\
+         [[proxies]]\nname = \"ffi\"\npublish_port = {}\nlocal_port = {}\n",
+        server.bound_addr().port(),
+        auth_key,
+        cert_path.display().to_string(),
+        common::get_available_port(),
+        common::get_available_port(),
+    ))
+    .unwrap();
+    let client = unsafe { tt_client_new(config.as_ptr()) };
+    assert!(!client.is_null(), "tt_client_new failed");
+
+    let events = Box::new(Mutex::new(Vec::<String>::new()));
+    let user_data = &*events as *const Mutex<Vec<String>> as *mut c_void;
+    unsafe {
+        assert_eq!(
+            tt_client_set_event_callback(client, Some(record_event), user_data),
+            0
+        );
+        assert_eq!(tt_client_start(client), 0);
+        assert_eq!(tt_client_start(client), -1, "already running");
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while status(client)["state"] != "running" {
+        assert!(Instant::now() < deadline, "client never reached running");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert_eq!(status(client)["running"], true);
+    let names: Vec<String> = events
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|e| serde_json::from_str::<serde_json::Value>(e).unwrap()["event"].to_string())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "\"connecting\"",
+            "\"connected\"",
+            "\"authenticated\"",
+            "\"config_accepted\"",
+            "\"running\""
+        ]
+    );
+
+    unsafe {
+        assert_eq!(tt_client_stop(client), 0);
+    }
+    assert_eq!(status(client)["running"], false);
+
+    unsafe {
+        assert_eq!(
+            tt_client_set_event_callback(client, None, std::ptr::null_mut()),
+            0
+        );
+        tt_client_free(client);
+    }
+    drop(events);
+
+    runtime.block_on(server.shutdown()).unwrap();
+}
@@ -0,0 +1,232 @@
+/// Flow export tests
+///
+/// 服务器配置 `flow_export` 后，经代理端口转发的连接在存活期间按间隔输出中间记录，
+/// 结束时输出带累计字节数和终止原因的最终记录，成批追加到 JSONL 文件
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, FlowExportConfig, ProxyConfig, ProxyType, ProxyVisibility,
+    ServerConfig,
+};
+use tls_tunnel::server::events::{ConnectionKind, ServerEvent, ServerEventKind};
+use tls_tunnel::server::flows::{FlowRecord, FlowRecordType};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-flow-export-key";
+
+fn server_config(cert_path: &Path, key_path: &Path, flows_path: &Path) -> ServerConfig {
+    ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        flow_export: Some(FlowExportConfig {
+            sink: format!("file:{}", flows_path.display()),
+            interim_interval_secs: 1,
+            batch_size: 100,
+            flush_interval_ms: 100,
+            queue_size: 1024,
+        }),
+        ..common::test_server_config()
+    }
+}
+
+fn client_config(
+    server_port: u16,
+    cert_path: &Path,
+    publish_port: u16,
+    local_port: u16,
+) -> ClientFullConfig {
+    ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            peer_id: Some("billing-edge".to_string()),
+            keep_listening_on_disconnect: false,
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "metered".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    }
+}
+
+/// 读取文件中已写出的流记录
+fn read_records(path: &Path) -> Vec<FlowRecord> {
+    std::fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| {
+            let event: ServerEvent = serde_json::from_str(line)
+                .unwrap_or_else(|e| panic!("Invalid flow line '{}': {}", line, e));
+            match event.kind {
+                ServerEventKind::FlowRecord(record) => record,
+                other => panic!("Unexpected event {:?}", other),
+            }
+        })
+        .collect()
+}
+
+struct RemoveOnDrop(PathBuf);
+
+impl Drop for RemoveOnDrop {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+    }
+}
+
+#[tokio::test]
+async fn test_long_transfer_emits_interim_and_final_records() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let flows_path = std::env::temp_dir().join(format!(
+        "tls-tunnel-flow-export-{}.jsonl",
+        uuid::Uuid::new_v4()
+    ));
+    let _remove_flows = RemoveOnDrop(flows_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+
+    let server = common::spawn_server(server_config(&cert_path, &key_path, &flows_path)).await;
+    let config = client_config(
+        server.bound_addr().port(),
+        &cert_path,
+        publish_port,
+        local_port,
+    );
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    });
+    // 等待代理可达（探测连接也是流，下面按来源地址区分）
+    let mut reachable = false;
+    for _ in 0..50 {
+        if common::test_proxy_connection(publish_port, b"ready", Duration::from_secs(2))
+            .await
+            .is_ok_and(|echo| echo == b"ready")
+        {
+            reachable = true;
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert!(reachable, "Proxy never became reachable");
+    // 持续约 3.5 秒的传输：每 500ms 发送一块数据，回显由另一任务读取
+    const CHUNK: usize = 256 * 1024;
+    const CHUNKS: usize = 8;
+    let stream = TcpStream::connect(("127.0.0.1", publish_port))
+        .await
+        .expect("Failed to connect to proxy");
+    let peer_addr = stream.local_addr().unwrap().to_string();
+    let (mut reader, mut writer) = stream.into_split();
+    let echoed = tokio::spawn(async move {
+        let mut total = 0usize;
+        let mut buf = vec![0u8; 16 * 1024];
+        loop {
+            match reader.read(&mut buf).await {
+                Ok(0) | Err(_) => return total,
+                Ok(n) => total += n,
+            }
+        }
+    });
+    for i in 0..CHUNKS {
+        writer.write_all(&vec![i as u8; CHUNK]).await.unwrap();
+        sleep(Duration::from_millis(500)).await;
+    }
+    writer.shutdown().await.unwrap();
+    let echoed = timeout(Duration::from_secs(10), echoed)
+        .await
+        .expect("Timed out waiting for the echo")
+        .unwrap();
+    assert_eq!(echoed, CHUNK * CHUNKS);
+
+    // 等待最终记录写出
+    let records = timeout(Duration::from_secs(5), async {
+        loop {
+            let records: Vec<_> = read_records(&flows_path)
+                .into_iter()
+                .filter(|r| r.peer_addr.as_deref() == Some(peer_addr.as_str()))
+                .collect();
+            if records.iter().any(|r| r.record == FlowRecordType::Final) {
+                return records;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("Final flow record was not written");
+    assert!(records.iter().all(|r| r.flow_id == records[0].flow_id));
+
+    // 中间记录按 1 秒的间隔输出，字节数是单调增长的累计值
+    let (interim, finals): (Vec<_>, Vec<_>) = records
+        .iter()
+        .partition(|r| r.record == FlowRecordType::Interim);
+    assert!(
+        interim.len() >= 3,
+        "Expected at least 3 interim records, got {:?}",
+        interim
+    );
+    for (i, record) in interim.iter().enumerate() {
+        let expected = (i as u64 + 1) * 1000;
+        assert!(
+            record.duration_ms >= expected && record.duration_ms < expected + 500,
+            "Interim record {} at {}ms",
+            i,
+            record.duration_ms
+        );
+        assert!(record.reason.is_none());
+    }
+    assert!(interim
+        .windows(2)
+        .all(|w| w[0].bytes_in <= w[1].bytes_in && w[0].bytes_out <= w[1].bytes_out));
+    assert!(interim[0].bytes_in > 0);
+
+    // 最终记录的字节数与传输的数据量一致
+    assert_eq!(finals.len(), 1);
+    let last = finals[0];
+    assert_eq!(last.kind, ConnectionKind::Proxy);
+    assert_eq!(last.name, "metered");
+    assert_eq!(last.publish_port, Some(publish_port));
+    assert_eq!(last.local_port, Some(local_port));
+    assert_eq!(last.peer_id.as_deref(), Some("billing-edge"));
+    assert!(last.client_id.is_some());
+    assert_eq!(last.bytes_in, (CHUNK * CHUNKS) as u64);
+    assert_eq!(last.bytes_out, (CHUNK * CHUNKS) as u64);
+    assert_eq!(last.reason.as_deref(), Some("closed"));
+    assert!(last.duration_ms >= 3500);
+    assert!(last.end_ms.abs_diff(last.start_ms + last.duration_ms) < 50);
+
+    client.abort();
+    server.shutdown().await.ok();
+}
@@ -0,0 +1,198 @@
+/// Forward policy tests
+///
+/// 服务器的 `forward_policy` 在连接外部目标之前检查 forward 请求：被拒绝的请求以
+/// `FORWARD_FORBIDDEN` 开头的错误消息拒绝，HTTP forwarder 向本地应用返回 403；
+/// 允许的域名照常转发，未被任何允许规则命中的目标默认拒绝
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ForwardPolicyConfig, ForwarderConfig, ProxyType, ServerConfig,
+};
+use tls_tunnel::server::ServerHandle;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-forward-policy-key";
+
+fn server_config(cert_path: &Path, key_path: &Path, policy: ForwardPolicyConfig) -> ServerConfig {
+    ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        allow_forward: true,
+        forward_policy: Some(policy),
+        ..common::test_server_config()
+    }
+}
+
+/// 启动服务器和带一个 HTTP forwarder 的客户端
+async fn start_tunnel(
+    cert_path: &Path,
+    key_path: &Path,
+    policy: ForwardPolicyConfig,
+    forwarder_port: u16,
+) -> (ServerHandle, JoinHandle<()>) {
+    let server = common::spawn_server(server_config(cert_path, key_path, policy)).await;
+    let config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![],
+        visitors: vec![],
+        forwarders: vec![ForwarderConfig {
+            name: "egress".to_string(),
+            proxy_type: ProxyType::HttpProxy,
+            bind_addr: "127.0.0.1".to_string(),
+            bind_port: forwarder_port,
+            routing: None,
+            fast_fail: None,
+            direct_egress: None,
+            socket: None,
+            report_direct: false,
+            idle_timeout_secs: None,
+            header_rules: None,
+        }],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    });
+    assert!(
+        common::wait_for_server(forwarder_port, 50).await,
+        "Forwarder did not start listening"
+    );
+    (server, client)
+}
+
+/// 经 HTTP forwarder 发起 CONNECT，返回收到的全部响应（被拒绝时连接随后关闭）
+async fn connect_response(forwarder_port: u16, target: &str) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", forwarder_port))
+        .await
+        .expect("Failed to connect to forwarder");
+    stream
+        .write_all(format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", target).as_bytes())
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    let _ = timeout(Duration::from_secs(5), stream.read_to_end(&mut response)).await;
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+#[tokio::test]
+async fn test_denied_cidr_returns_forbidden() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let echo_port = common::get_available_port();
+    let _echo = common::start_echo_server(echo_port).await;
+
+    // 拒绝规则优先于覆盖同一地址的允许规则
+    let policy = ForwardPolicyConfig {
+        allowed_cidrs: vec!["127.0.0.0/8".to_string()],
+        denied_cidrs: vec!["127.0.0.1/32".to_string()],
+        ..Default::default()
+    };
+    let forwarder_port = common::get_available_port();
+    let (_server, client) = start_tunnel(&cert_path, &key_path, policy, forwarder_port).await;
+
+    let response = connect_response(forwarder_port, &format!("127.0.0.1:{}", echo_port)).await;
+    assert!(response.starts_with("HTTP/1.1 403"), "{}", response);
+    assert!(response.contains("FORWARD_FORBIDDEN"), "{}", response);
+    assert!(
+        response.contains("127.0.0.1 matches denied_cidrs entry '127.0.0.1/32'"),
+        "{}",
+        response
+    );
+
+    client.abort();
+}
+
+#[tokio::test]
+async fn test_allowed_domain_is_forwarded() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let echo_port = common::get_available_port();
+    let _echo = common::start_echo_server(echo_port).await;
+
+    // 允许的域名不受内置的本地地址检查限制
+    let policy = ForwardPolicyConfig {
+        allowed_domains: vec!["localhost".to_string()],
+        allowed_ports: vec![echo_port],
+        ..Default::default()
+    };
+    let forwarder_port = common::get_available_port();
+    let (_server, client) = start_tunnel(&cert_path, &key_path, policy, forwarder_port).await;
+
+    let mut stream = TcpStream::connect(("127.0.0.1", forwarder_port))
+        .await
+        .expect("Failed to connect to forwarder");
+    let target = format!("localhost:{}", echo_port);
+    stream
+        .write_all(format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", target).as_bytes())
+        .await
+        .unwrap();
+    let established = b"HTTP/1.1 200 Connection Established\r\n\r\n";
+    let mut response = vec![0u8; established.len()];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut response))
+        .await
+        .expect("Timed out waiting for CONNECT response")
+        .expect("Forwarder closed the connection");
+    assert_eq!(
+        response,
+        established,
+        "{}",
+        String::from_utf8_lossy(&response)
+    );
+
+    stream.write_all(b"policy allowed").await.unwrap();
+    let mut echoed = [0u8; 14];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut echoed))
+        .await
+        .expect("Timed out waiting for echo")
+        .expect("Tunnel closed before echo");
+    assert_eq!(&echoed, b"policy allowed");
+
+    // 允许的域名仍然受端口限制
+    let response = connect_response(forwarder_port, &format!("localhost:{}", echo_port + 1)).await;
+    assert!(response.starts_with("HTTP/1.1 403"), "{}", response);
+    assert!(response.contains("allowed_ports"), "{}", response);
+
+    client.abort();
+}
+
+#[tokio::test]
+async fn test_unlisted_target_denied_by_default() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let echo_port = common::get_available_port();
+    let _echo = common::start_echo_server(echo_port).await;
+
+    let policy = ForwardPolicyConfig {
+        allowed_domains: vec!["*.internal.corp".to_string()],
+        ..Default::default()
+    };
+    let forwarder_port = common::get_available_port();
+    let (_server, client) = start_tunnel(&cert_path, &key_path, policy, forwarder_port).await;
+
+    let response = connect_response(forwarder_port, &format!("localhost:{}", echo_port)).await;
+    assert!(response.starts_with("HTTP/1.1 403"), "{}", response);
+    assert!(
+        response.contains("target is not in allowed_domains or allowed_cidrs"),
+        "{}",
+        response
+    );
+
+    client.abort();
+}
@@ -0,0 +1,216 @@
+/// Forward report tests
+///
+/// 开启 `report_direct` 的 forwarder 把直连（不经隧道）的连接合并为 `forward_report` 通知上报，
+/// 服务器把报告记入该客户端会话的 forward 用量；未开启时服务器看不到直连的连接
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ForwarderConfig, ProxyType, RoutingConfig, RoutingStrategy,
+    ServerConfig,
+};
+use tls_tunnel::server::ServerHandle;
+use tls_tunnel::stats::{ForwardUsageStats, StatsManager};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-forward-report-key";
+
+/// 启动服务器和带一个 SOCKS5 forwarder 的客户端（127.0.0.0/8 直连）
+async fn start_tunnel(
+    forwarder_port: u16,
+    report_direct: bool,
+    cert_path: &Path,
+    key_path: &Path,
+) -> (ServerHandle, JoinHandle<()>) {
+    let server = common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        ..common::test_server_config()
+    })
+    .await;
+
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            keep_listening_on_disconnect: false,
+            ..common::test_client_config()
+        },
+        proxies: vec![],
+        visitors: vec![],
+        forwarders: vec![ForwarderConfig {
+            name: "office".to_string(),
+            proxy_type: ProxyType::Socks5Proxy,
+            bind_addr: "127.0.0.1".to_string(),
+            bind_port: forwarder_port,
+            routing: Some(RoutingConfig {
+                geoip_db: None,
+                direct_countries: vec![],
+                proxy_countries: vec![],
+                direct_ips: vec!["127.0.0.0/8".to_string()],
+                proxy_ips: vec![],
+                direct_domains: vec![],
+                proxy_domains: vec![],
+                default_strategy: RoutingStrategy::Proxy,
+                schedules: vec![],
+                utc_offset: None,
+                quota: None,
+            }),
+            fast_fail: None,
+            direct_egress: None,
+            socket: None,
+            report_direct,
+            idle_timeout_secs: None,
+            header_rules: None,
+        }],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client(client_config, connector)
+            .await
+            .ok();
+    });
+    assert!(
+        common::wait_for_server(forwarder_port, 50).await,
+        "Forwarder did not start listening"
+    );
+
+    (server, client_handle)
+}
+
+/// 读取 4 字节请求，回复 8 字节后关闭连接的目标服务器
+///
+/// 直连把到目标的连接放回连接池复用，所以每次请求使用新的目标
+async fn start_target() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut request = [0u8; 4];
+                if socket.read_exact(&mut request).await.is_ok() {
+                    let _ = socket.write_all(b"response").await;
+                }
+            });
+        }
+    });
+    port
+}
+
+/// 经 SOCKS5 forwarder 连接目标，完成一次请求和响应后关闭连接
+async fn socks5_request(forwarder_port: u16, target_port: u16) {
+    let mut socks = TcpStream::connect(("127.0.0.1", forwarder_port))
+        .await
+        .expect("Failed to connect to forwarder");
+    socks.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut greeting = [0u8; 2];
+    socks.read_exact(&mut greeting).await.unwrap();
+
+    let mut connect_request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    connect_request.extend_from_slice(&target_port.to_be_bytes());
+    socks.write_all(&connect_request).await.unwrap();
+    let mut reply = [0u8; 10];
+    socks.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[..2], [0x05, 0x00]);
+
+    socks.write_all(b"ping").await.unwrap();
+    let mut response = [0u8; 8];
+    timeout(Duration::from_secs(5), socks.read_exact(&mut response))
+        .await
+        .expect("Timed out waiting for the response")
+        .unwrap();
+    assert_eq!(&response, b"response");
+}
+
+/// 等待服务器记录 `connections` 个直连报告（最多 5 秒）
+async fn wait_for_reports(stats: &StatsManager, connections: u64) -> Option<ForwardUsageStats> {
+    for _ in 0..100 {
+        if let Some(usage) = stats
+            .get_forward_usage()
+            .into_iter()
+            .find(|u| u.direct.connections >= connections)
+        {
+            return Some(usage);
+        }
+        sleep(Duration::from_millis(50)).await;
+    }
+    None
+}
+
+#[tokio::test]
+async fn test_direct_connections_are_reported_in_batches() {
+    let forwarder_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let (server, client_handle) = start_tunnel(forwarder_port, true, &cert_path, &key_path).await;
+    let stats = server.stats();
+
+    let mut target_ports = Vec::new();
+    for _ in 0..3 {
+        let target_port = start_target().await;
+        socks5_request(forwarder_port, target_port).await;
+        target_ports.push(target_port);
+    }
+
+    let usage = wait_for_reports(&stats, 3)
+        .await
+        .expect("Server did not record the direct connections");
+    let direct = usage.direct;
+    assert_eq!(direct.connections, 3);
+    assert_eq!(direct.failures, 0);
+    assert_eq!(direct.bytes_up, 12);
+    assert_eq!(direct.bytes_down, 24);
+    // 同一秒内结束的连接合并发送
+    assert!(direct.batches < 3, "{} batches", direct.batches);
+    assert_eq!(direct.recent.len(), 3);
+    for (report, target_port) in direct.recent.iter().zip(&target_ports) {
+        assert_eq!(report.forwarder, "office");
+        assert_eq!(report.target, format!("127.0.0.1:{}", target_port));
+        assert_eq!(report.decision, "direct");
+        assert_eq!(report.rule.as_deref(), Some("direct_by_ip"));
+        assert_eq!(report.matched.as_deref(), Some("127.0.0.0/8"));
+        assert_eq!(report.error, None);
+    }
+    // 直连的连接没有经过服务器的 forward
+    assert_eq!(usage.total_streams, 0);
+
+    client_handle.abort();
+    server.shutdown().await.ok();
+}
+
+#[tokio::test]
+async fn test_no_reports_without_report_direct() {
+    let forwarder_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let (server, client_handle) = start_tunnel(forwarder_port, false, &cert_path, &key_path).await;
+    let stats = server.stats();
+
+    for _ in 0..3 {
+        let target_port = start_target().await;
+        socks5_request(forwarder_port, target_port).await;
+    }
+
+    // 超过两个发送间隔后仍然没有报告
+    sleep(Duration::from_millis(2500)).await;
+    assert!(
+        stats.get_forward_usage().is_empty(),
+        "{:?}",
+        stats.get_forward_usage()
+    );
+
+    client_handle.abort();
+    server.shutdown().await.ok();
+}
@@ -0,0 +1,178 @@
+/// Auto-detecting forwarder tests
+///
+/// 同一个 `proxy_type = "auto"` 的 forwarder 端口同时服务 HTTP 和 SOCKS5 客户端，
+/// 无法识别的首字节立即关闭连接
+mod common;
+
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ForwarderConfig, ProxyType, RoutingConfig, RoutingStrategy,
+    ServerConfig,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+/// 启动服务器和带一个 auto forwarder 的客户端（127.0.0.0/8 直连）
+async fn start_tunnel(
+    forwarder_port: u16,
+    cert_path: &std::path::Path,
+    key_path: &std::path::Path,
+) -> (tls_tunnel::server::ServerHandle, JoinHandle<()>) {
+    let auth_key = "test-forwarder-auto-key";
+
+    let server = common::spawn_server(ServerConfig {
+        auth_key: auth_key.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        allow_forward: true,
+        ..common::test_server_config()
+    })
+    .await;
+
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: auth_key.to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![],
+        visitors: vec![],
+        forwarders: vec![ForwarderConfig {
+            name: "auto".to_string(),
+            proxy_type: ProxyType::AutoProxy,
+            bind_addr: "127.0.0.1".to_string(),
+            bind_port: forwarder_port,
+            routing: Some(RoutingConfig {
+                geoip_db: None,
+                direct_countries: vec![],
+                proxy_countries: vec![],
+                direct_ips: vec!["127.0.0.0/8".to_string()],
+                proxy_ips: vec![],
+                direct_domains: vec![],
+                proxy_domains: vec![],
+                default_strategy: RoutingStrategy::Direct,
+                schedules: vec![],
+                utc_offset: None,
+                quota: None,
+            }),
+            fast_fail: None,
+            direct_egress: None,
+            socket: None,
+            report_direct: false,
+            idle_timeout_secs: None,
+            header_rules: None,
+        }],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client(client_config, connector)
+            .await
+            .ok();
+    });
+    assert!(
+        common::wait_for_server(forwarder_port, 50).await,
+        "Forwarder did not start listening"
+    );
+
+    (server, client_handle)
+}
+
+#[tokio::test]
+async fn test_auto_forwarder_serves_http_and_socks5() {
+    let forwarder_port = common::get_available_port();
+    let echo_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo_server = common::start_echo_server(echo_port).await;
+    let (server, client_handle) = start_tunnel(forwarder_port, &cert_path, &key_path).await;
+
+    // HTTP 客户端：绝对 URL 请求被转发到目标（echo 服务器原样返回转发的请求）
+    let mut http = TcpStream::connect(("127.0.0.1", forwarder_port))
+        .await
+        .expect("Failed to connect to forwarder");
+    let request = format!(
+        "GET http://127.0.0.1:{}/auto HTTP/1.1\r\nHost: 127.0.0.1:{}\r\n\r\n",
+        echo_port, echo_port
+    );
+    http.write_all(request.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    timeout(Duration::from_secs(5), async {
+        let mut buf = [0u8; 1024];
+        while !String::from_utf8_lossy(&response).contains("\r\n\r\n") {
+            let n = http.read(&mut buf).await.unwrap();
+            assert!(n > 0, "Connection closed early");
+            response.extend_from_slice(&buf[..n]);
+        }
+    })
+    .await
+    .expect("Timed out waiting for forwarded HTTP request");
+    let response = String::from_utf8_lossy(&response);
+    assert!(
+        response.starts_with("GET /auto HTTP/1.1"),
+        "Unexpected forwarded request: {}",
+        response
+    );
+
+    // SOCKS5 客户端：同一端口完成握手并转发数据
+    let mut socks = TcpStream::connect(("127.0.0.1", forwarder_port))
+        .await
+        .expect("Failed to connect to forwarder");
+    socks.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut greeting = [0u8; 2];
+    socks.read_exact(&mut greeting).await.unwrap();
+    assert_eq!(greeting, [0x05, 0x00]);
+
+    let mut connect_request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    connect_request.extend_from_slice(&echo_port.to_be_bytes());
+    socks.write_all(&connect_request).await.unwrap();
+    let mut reply = [0u8; 10];
+    timeout(Duration::from_secs(5), socks.read_exact(&mut reply))
+        .await
+        .expect("Timed out waiting for SOCKS5 reply")
+        .expect("Failed to read SOCKS5 reply");
+    assert_eq!(reply[..2], [0x05, 0x00]);
+
+    socks.write_all(b"auto socks5").await.unwrap();
+    let mut echoed = [0u8; 11];
+    timeout(Duration::from_secs(5), socks.read_exact(&mut echoed))
+        .await
+        .expect("Timed out waiting for echo")
+        .expect("Failed to read echo");
+    assert_eq!(&echoed, b"auto socks5");
+
+    client_handle.abort();
+    server.shutdown().await.ok();
+}
+
+#[tokio::test]
+async fn test_auto_forwarder_closes_unrecognized_protocol() {
+    let forwarder_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let (server, client_handle) = start_tunnel(forwarder_port, &cert_path, &key_path).await;
+
+    // TLS ClientHello 的首字节既不是 SOCKS5 也不是 HTTP 方法
+    let mut stream = TcpStream::connect(("127.0.0.1", forwarder_port))
+        .await
+        .expect("Failed to connect to forwarder");
+    stream.write_all(&[0x16, 0x03, 0x01, 0x00]).await.unwrap();
+
+    let mut buf = [0u8; 16];
+    let n = timeout(Duration::from_secs(2), stream.read(&mut buf))
+        .await
+        .expect("Forwarder should close the connection promptly")
+        .unwrap_or(0);
+    assert_eq!(n, 0, "Expected the connection to be closed");
+
+    client_handle.abort();
+    server.shutdown().await.ok();
+}
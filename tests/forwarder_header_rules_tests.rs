@@ -0,0 +1,201 @@
+/// Forwarder header rule tests
+///
+/// HTTP forwarder 按 `header_rules` 改写直接转发的请求头：匹配的目标注入密钥头部并删除指定头部，
+/// 不匹配的目标和 CONNECT 隧道内的数据保持原样
+mod common;
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ForwarderConfig, HeaderRuleConfig, ProxyType, RoutingConfig,
+    RoutingStrategy, ServerConfig,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-header-rules-key";
+
+/// 注入的密钥来自该环境变量
+const TOKEN_ENV: &str = "TLS_TUNNEL_TEST_HEADER_RULES_TOKEN";
+
+fn forwarder(name: &str, bind_port: u16, match_domain: &str) -> ForwarderConfig {
+    ForwarderConfig {
+        name: name.to_string(),
+        proxy_type: ProxyType::HttpProxy,
+        bind_addr: "127.0.0.1".to_string(),
+        bind_port,
+        routing: Some(RoutingConfig {
+            geoip_db: None,
+            direct_countries: vec![],
+            proxy_countries: vec![],
+            direct_ips: vec!["127.0.0.0/8".to_string()],
+            proxy_ips: vec![],
+            direct_domains: vec![],
+            proxy_domains: vec![],
+            default_strategy: RoutingStrategy::Direct,
+            schedules: vec![],
+            utc_offset: None,
+            quota: None,
+        }),
+        fast_fail: None,
+        direct_egress: None,
+        socket: None,
+        report_direct: false,
+        idle_timeout_secs: None,
+        header_rules: Some(vec![HeaderRuleConfig {
+            match_domains: vec![match_domain.to_string()],
+            add: BTreeMap::from([("Authorization".to_string(), format!("env:{}", TOKEN_ENV))]),
+            remove: vec!["X-Forwarded-*".to_string()],
+        }]),
+    }
+}
+
+/// 启动服务器和两个 HTTP forwarder：`matching` 的规则匹配 127.0.0.1，`other` 的规则匹配其他域名
+async fn start_tunnel(
+    matching_port: u16,
+    other_port: u16,
+    cert_path: &std::path::Path,
+    key_path: &std::path::Path,
+) -> (tls_tunnel::server::ServerHandle, JoinHandle<()>) {
+    let server = common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        allow_forward: true,
+        ..common::test_server_config()
+    })
+    .await;
+
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![],
+        visitors: vec![],
+        forwarders: vec![
+            forwarder("matching", matching_port, "127.0.0.1"),
+            forwarder("other", other_port, "*.internal.example"),
+        ],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client(client_config, connector)
+            .await
+            .ok();
+    });
+    for port in [matching_port, other_port] {
+        assert!(
+            common::wait_for_server(port, 50).await,
+            "Forwarder did not start listening"
+        );
+    }
+
+    (server, client_handle)
+}
+
+/// 读取到请求头结束（echo 服务器原样返回收到的数据）
+async fn read_head(stream: &mut TcpStream) -> String {
+    let mut response = Vec::new();
+    timeout(Duration::from_secs(5), async {
+        let mut buf = [0u8; 1024];
+        while !String::from_utf8_lossy(&response).contains("\r\n\r\n") {
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0, "Connection closed early");
+            response.extend_from_slice(&buf[..n]);
+        }
+    })
+    .await
+    .expect("Timed out waiting for the forwarded request");
+    String::from_utf8_lossy(&response).to_lowercase()
+}
+
+/// 经 forwarder 发送一个绝对 URL 请求，返回目标收到的请求（小写）
+async fn forward_request(forwarder_port: u16, echo_port: u16) -> String {
+    let mut http = TcpStream::connect(("127.0.0.1", forwarder_port))
+        .await
+        .expect("Failed to connect to forwarder");
+    let request = format!(
+        "GET http://127.0.0.1:{}/data HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nX-Forwarded-For: 203.0.113.9\r\nX-Forwarded-Proto: https\r\nAuthorization: Basic client\r\nAccept: */*\r\n\r\n",
+        echo_port, echo_port
+    );
+    http.write_all(request.as_bytes()).await.unwrap();
+    read_head(&mut http).await
+}
+
+#[tokio::test]
+async fn test_header_rules_rewrite_only_matching_http_requests() {
+    std::env::set_var(TOKEN_ENV, "Bearer internal-token");
+    let matching_port = common::get_available_port();
+    let other_port = common::get_available_port();
+    let echo_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo_server = common::start_echo_server(echo_port).await;
+    let (server, client_handle) =
+        start_tunnel(matching_port, other_port, &cert_path, &key_path).await;
+
+    // 匹配的目标：注入 Authorization（覆盖客户端的值），删除 X-Forwarded-*
+    let forwarded = forward_request(matching_port, echo_port).await;
+    assert!(forwarded.starts_with("get /data http/1.1"), "{}", forwarded);
+    assert!(
+        forwarded.contains("authorization: bearer internal-token\r\n"),
+        "{}",
+        forwarded
+    );
+    assert!(!forwarded.contains("basic client"), "{}", forwarded);
+    assert!(!forwarded.contains("x-forwarded-"), "{}", forwarded);
+    assert!(forwarded.contains("accept: */*\r\n"), "{}", forwarded);
+
+    // 不匹配的目标：请求头保持原样
+    let forwarded = forward_request(other_port, echo_port).await;
+    assert!(
+        forwarded.contains("authorization: basic client\r\n"),
+        "{}",
+        forwarded
+    );
+    assert!(
+        forwarded.contains("x-forwarded-for: 203.0.113.9\r\n"),
+        "{}",
+        forwarded
+    );
+    assert!(!forwarded.contains("internal-token"), "{}", forwarded);
+
+    // CONNECT 隧道内的数据不经过改写（即使目标匹配规则）
+    let mut tunnel = TcpStream::connect(("127.0.0.1", matching_port))
+        .await
+        .expect("Failed to connect to forwarder");
+    tunnel
+        .write_all(
+            format!(
+                "CONNECT 127.0.0.1:{} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\n\r\n",
+                echo_port, echo_port
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+    let established = read_head(&mut tunnel).await;
+    assert!(
+        established.starts_with("http/1.1 200"),
+        "Unexpected CONNECT response: {}",
+        established
+    );
+    let inner = "GET /inner HTTP/1.1\r\nHost: 127.0.0.1\r\nX-Forwarded-For: 203.0.113.9\r\n\r\n";
+    tunnel.write_all(inner.as_bytes()).await.unwrap();
+    let echoed = read_head(&mut tunnel).await;
+    assert_eq!(echoed, inner.to_lowercase());
+
+    client_handle.abort();
+    server.shutdown().await.ok();
+}
@@ -0,0 +1,347 @@
+/// Forwarder HTTP keep-alive tests
+///
+/// HTTP forwarder 直接转发时按 Content-Length / chunked 确定请求和响应的边界，
+/// 在同一本地连接上处理多个请求：目标不变时复用同一上游连接，目标变化时切换上游连接
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ForwarderConfig, ProxyType, ServerConfig,
+};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-forwarder-keepalive-key";
+
+/// 启动服务器和 HTTP forwarder
+async fn start_tunnel(
+    forwarder_port: u16,
+    cert_path: &std::path::Path,
+    key_path: &std::path::Path,
+) -> (tls_tunnel::server::ServerHandle, JoinHandle<()>) {
+    let server = common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        allow_forward: true,
+        ..common::test_server_config()
+    })
+    .await;
+
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![],
+        visitors: vec![],
+        forwarders: vec![ForwarderConfig {
+            name: "http".to_string(),
+            proxy_type: ProxyType::HttpProxy,
+            bind_addr: "127.0.0.1".to_string(),
+            bind_port: forwarder_port,
+            routing: None,
+            fast_fail: None,
+            direct_egress: None,
+            socket: None,
+            report_direct: false,
+            idle_timeout_secs: None,
+            header_rules: None,
+        }],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client(client_config, connector)
+            .await
+            .ok();
+    });
+    assert!(
+        common::wait_for_server(forwarder_port, 50).await,
+        "Forwarder did not start listening"
+    );
+
+    (server, client_handle)
+}
+
+/// 读取到空行为止的头部（小写）
+async fn read_head<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> Option<String> {
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await.ok()? == 0 {
+            return None;
+        }
+        if line == "\r\n" {
+            return Some(head.to_lowercase());
+        }
+        head.push_str(&line);
+    }
+}
+
+/// 按 Content-Length 或 chunked 编码读取消息体
+async fn read_body<R: AsyncBufReadExt + Unpin>(reader: &mut R, head: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    if head.contains("transfer-encoding: chunked") {
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            let size = usize::from_str_radix(line.trim(), 16).unwrap();
+            let mut chunk = vec![0u8; size + 2];
+            reader.read_exact(&mut chunk).await.unwrap();
+            if size == 0 {
+                return body;
+            }
+            body.extend_from_slice(&chunk[..size]);
+        }
+    }
+    let length = head
+        .lines()
+        .find_map(|line| line.strip_prefix("content-length:"))
+        .map_or(0, |value| value.trim().parse().unwrap());
+    body.resize(length, 0);
+    reader.read_exact(&mut body).await.unwrap();
+    body
+}
+
+/// 启动支持 keep-alive 的 HTTP 服务器，返回接受的连接数
+///
+/// 响应体为 `<端口> <方法> <路径> <请求体>`，路径以 `/chunked` 结尾时使用 chunked 编码
+async fn start_http_server(port: u16) -> (Arc<AtomicUsize>, JoinHandle<()>) {
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .await
+        .expect("Failed to bind HTTP server");
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let counter = accepted.clone();
+    let handle = tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            counter.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let mut reader = BufReader::new(socket);
+                while let Some(head) = read_head(&mut reader).await {
+                    let mut request_line = head.lines().next().unwrap().split_whitespace();
+                    let method = request_line.next().unwrap().to_uppercase();
+                    let path = request_line.next().unwrap().to_string();
+                    let body = read_body(&mut reader, &head).await;
+
+                    let body = format!(
+                        "{} {} {} {}",
+                        port,
+                        method,
+                        path,
+                        String::from_utf8_lossy(&body)
+                    );
+                    let response = if path.ends_with("/chunked") {
+                        let (first, second) = body.split_at(body.len() / 2);
+                        format!(
+                            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+                            first.len(),
+                            first,
+                            second.len(),
+                            second
+                        )
+                    } else {
+                        format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                            body.len(),
+                            body
+                        )
+                    };
+                    if reader
+                        .get_mut()
+                        .write_all(response.as_bytes())
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    (accepted, handle)
+}
+
+/// 读取一个响应，返回头部（小写）和响应体
+async fn read_response(reader: &mut BufReader<TcpStream>) -> (String, String) {
+    timeout(Duration::from_secs(5), async {
+        let head = read_head(reader)
+            .await
+            .expect("Connection closed before response");
+        let body = read_body(reader, &head).await;
+        (head, String::from_utf8(body).unwrap())
+    })
+    .await
+    .expect("Timed out waiting for response")
+}
+
+async fn send(reader: &mut BufReader<TcpStream>, request: String) {
+    reader
+        .get_mut()
+        .write_all(request.as_bytes())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_sequential_requests_reuse_upstream_connection() {
+    let forwarder_port = common::get_available_port();
+    let http_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let (accepted, _http_server) = start_http_server(http_port).await;
+    let (server, client_handle) = start_tunnel(forwarder_port, &cert_path, &key_path).await;
+
+    let stream = TcpStream::connect(("127.0.0.1", forwarder_port))
+        .await
+        .expect("Failed to connect to forwarder");
+    let mut client = BufReader::new(stream);
+
+    // 两个连续的 GET（Content-Length 和 chunked 响应）
+    send(
+        &mut client,
+        format!("GET http://127.0.0.1:{}/first HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nProxy-Connection: keep-alive\r\n\r\n", http_port, http_port),
+    )
+    .await;
+    let (head, body) = read_response(&mut client).await;
+    assert!(head.starts_with("http/1.1 200"), "{}", head);
+    assert!(head.contains("connection: keep-alive"), "{}", head);
+    assert_eq!(body, format!("{} GET /first ", http_port));
+
+    send(
+        &mut client,
+        format!(
+            "GET http://127.0.0.1:{}/second/chunked HTTP/1.1\r\nHost: 127.0.0.1:{}\r\n\r\n",
+            http_port, http_port
+        ),
+    )
+    .await;
+    let (_, body) = read_response(&mut client).await;
+    assert_eq!(body, format!("{} GET /second/chunked ", http_port));
+
+    // 带请求体的 POST（chunked 请求体）
+    send(
+        &mut client,
+        format!("POST http://127.0.0.1:{}/third HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n", http_port, http_port),
+    )
+    .await;
+    let (_, body) = read_response(&mut client).await;
+    assert_eq!(body, format!("{} POST /third hello", http_port));
+
+    // 三个请求共用一个上游连接
+    assert_eq!(accepted.load(Ordering::SeqCst), 1);
+
+    client_handle.abort();
+    server.shutdown().await.ok();
+}
+
+#[tokio::test]
+async fn test_requests_to_different_hosts_switch_upstream() {
+    let forwarder_port = common::get_available_port();
+    let first_port = common::get_available_port();
+    let second_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let (_, _first_server) = start_http_server(first_port).await;
+    let (second_accepted, _second_server) = start_http_server(second_port).await;
+    let (server, client_handle) = start_tunnel(forwarder_port, &cert_path, &key_path).await;
+
+    let stream = TcpStream::connect(("127.0.0.1", forwarder_port))
+        .await
+        .expect("Failed to connect to forwarder");
+    let mut client = BufReader::new(stream);
+
+    // 同一本地连接上依次访问两个目标，再回到第一个目标
+    for (port, path) in [(first_port, "/a"), (second_port, "/b"), (first_port, "/c")] {
+        send(
+            &mut client,
+            format!(
+                "GET http://127.0.0.1:{}{} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\n\r\n",
+                port, path, port
+            ),
+        )
+        .await;
+        let (_, body) = read_response(&mut client).await;
+        assert_eq!(body, format!("{} GET {} ", port, path));
+    }
+    assert_eq!(second_accepted.load(Ordering::SeqCst), 1);
+
+    // 本地客户端要求关闭时，响应之后关闭本地连接
+    send(
+        &mut client,
+        format!(
+            "POST http://127.0.0.1:{}/last HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nContent-Length: 3\r\nConnection: close\r\n\r\nbye",
+            second_port, second_port
+        ),
+    )
+    .await;
+    let (head, body) = read_response(&mut client).await;
+    assert!(head.contains("connection: close"), "{}", head);
+    assert_eq!(body, format!("{} POST /last bye", second_port));
+    let mut rest = Vec::new();
+    let n = timeout(Duration::from_secs(5), client.read_to_end(&mut rest))
+        .await
+        .expect("Local connection was not closed")
+        .unwrap();
+    assert_eq!(n, 0);
+
+    client_handle.abort();
+    server.shutdown().await.ok();
+}
+
+#[tokio::test]
+async fn test_ambiguous_request_length_is_rejected() {
+    let forwarder_port = common::get_available_port();
+    let http_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let (accepted, _http_server) = start_http_server(http_port).await;
+    let (server, client_handle) = start_tunnel(forwarder_port, &cert_path, &key_path).await;
+
+    // 同时带 Transfer-Encoding 和 Content-Length，以及两个不一致的 Content-Length
+    let ambiguous = [
+        "Transfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n0\r\n\r\n",
+        "Content-Length: 3\r\nContent-Length: 10\r\n\r\nabc",
+    ];
+    for headers in ambiguous {
+        let stream = TcpStream::connect(("127.0.0.1", forwarder_port))
+            .await
+            .expect("Failed to connect to forwarder");
+        let mut client = BufReader::new(stream);
+        send(
+            &mut client,
+            format!(
+                "POST http://127.0.0.1:{}/smuggle HTTP/1.1\r\nHost: 127.0.0.1:{}\r\n{}",
+                http_port, http_port, headers
+            ),
+        )
+        .await;
+        let (head, _) = read_response(&mut client).await;
+        assert!(head.starts_with("http/1.1 400"), "{}", head);
+        assert!(head.contains("connection: close"), "{}", head);
+
+        let mut rest = Vec::new();
+        let n = timeout(Duration::from_secs(5), client.read_to_end(&mut rest))
+            .await
+            .expect("Local connection was not closed")
+            .unwrap_or(0);
+        assert_eq!(n, 0);
+    }
+
+    // 请求没有转发给上游
+    assert_eq!(accepted.load(Ordering::SeqCst), 0);
+
+    client_handle.abort();
+    server.shutdown().await.ok();
+}
@@ -0,0 +1,170 @@
+/// Client-side forwarder size limit tests
+///
+/// 客户端 forwarder 在本地拒绝超限请求：HTTP 请求头超过 `size_limits.max_header_size`
+/// 时返回 431，无法放入 `@forward:` 前导的 SOCKS5 域名返回对应的 SOCKS 错误码
+mod common;
+
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ForwarderConfig, ProxyType, ServerConfig, SizeLimitConfig,
+};
+use tls_tunnel::server::ServerHandle;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+/// 启动服务器和带一个 forwarder 的客户端
+async fn start_tunnel(
+    proxy_type: ProxyType,
+    forwarder_port: u16,
+    cert_path: &std::path::Path,
+    key_path: &std::path::Path,
+) -> (ServerHandle, JoinHandle<()>) {
+    let auth_key = "test-forwarder-limits-key";
+
+    let server_config = ServerConfig {
+        auth_key: auth_key.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        allow_forward: true,
+        ..common::test_server_config()
+    };
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
+
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: auth_key.to_string(),
+            size_limits: Some(SizeLimitConfig {
+                max_request_size: 1024 * 1024,
+                max_header_size: 8 * 1024,
+            }),
+            ..common::test_client_config()
+        },
+        proxies: vec![],
+        visitors: vec![],
+        forwarders: vec![ForwarderConfig {
+            name: "limited".to_string(),
+            proxy_type,
+            bind_addr: "127.0.0.1".to_string(),
+            bind_port: forwarder_port,
+            routing: None,
+            fast_fail: None,
+            direct_egress: None,
+            socket: None,
+            report_direct: false,
+            idle_timeout_secs: None,
+            header_rules: None,
+        }],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client(client_config, connector)
+            .await
+            .ok();
+    });
+    assert!(
+        common::wait_for_server(forwarder_port, 50).await,
+        "Forwarder did not start listening"
+    );
+
+    (server, client_handle)
+}
+
+#[tokio::test]
+async fn test_http_forwarder_rejects_oversized_header() {
+    let forwarder_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let (server, client_handle) =
+        start_tunnel(ProxyType::HttpProxy, forwarder_port, &cert_path, &key_path).await;
+
+    let stream = TcpStream::connect(("127.0.0.1", forwarder_port))
+        .await
+        .expect("Failed to connect to HTTP forwarder");
+    let (mut reader, mut writer) = stream.into_split();
+
+    // 80KB 请求头；forwarder 可能在写完之前就关闭连接，忽略写入错误
+    let mut request =
+        b"GET http://example.com/ HTTP/1.1\r\nHost: example.com\r\nX-Padding: ".to_vec();
+    request.extend(std::iter::repeat_n(b'a', 80 * 1024));
+    request.extend_from_slice(b"\r\n\r\n");
+    let sender = tokio::spawn(async move {
+        let _ = writer.write_all(&request).await;
+        writer
+    });
+
+    let mut response = Vec::new();
+    let mut buf = [0u8; 1024];
+    timeout(Duration::from_secs(5), async {
+        while let Ok(n) = reader.read(&mut buf).await {
+            if n == 0 {
+                break;
+            }
+            response.extend_from_slice(&buf[..n]);
+        }
+    })
+    .await
+    .expect("Timed out waiting for 431 response");
+
+    let response = String::from_utf8_lossy(&response);
+    assert!(
+        response.starts_with("HTTP/1.1 431 Request Header Fields Too Large"),
+        "Unexpected response: {}",
+        response
+    );
+
+    sender.abort();
+    server.shutdown().await.ok();
+    client_handle.abort();
+}
+
+#[tokio::test]
+async fn test_socks5_forwarder_rejects_overlong_domain() {
+    let forwarder_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let (server, client_handle) = start_tunnel(
+        ProxyType::Socks5Proxy,
+        forwarder_port,
+        &cert_path,
+        &key_path,
+    )
+    .await;
+
+    let mut stream = TcpStream::connect(("127.0.0.1", forwarder_port))
+        .await
+        .expect("Failed to connect to SOCKS5 forwarder");
+
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut greeting = [0u8; 2];
+    stream.read_exact(&mut greeting).await.unwrap();
+    assert_eq!(greeting, [0x05, 0x00]);
+
+    // 250 字节的域名是合法的 SOCKS5 地址，但 "@forward:<domain>:80" 超过 255 字节
+    let domain = "a".repeat(250);
+    let mut connect_request = vec![0x05, 0x01, 0x00, 0x03, domain.len() as u8];
+    connect_request.extend_from_slice(domain.as_bytes());
+    connect_request.extend_from_slice(&80u16.to_be_bytes());
+    stream.write_all(&connect_request).await.unwrap();
+
+    let mut reply = [0u8; 10];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut reply))
+        .await
+        .expect("Timed out waiting for SOCKS5 reply")
+        .expect("Failed to read SOCKS5 reply");
+    assert_eq!(reply[0], 0x05);
+    assert_eq!(reply[1], 0x08, "Expected 'address type not supported'");
+
+    server.shutdown().await.ok();
+    client_handle.abort();
+}
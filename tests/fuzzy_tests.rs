@@ -5,17 +5,16 @@
 mod common;
 
 use std::time::Duration;
-use tls_tunnel::config::ServerConfig;
+use tls_tunnel::config::{ServerConfig, StatsLimitConfig};
 use tls_tunnel::transport::TransportType;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 use tokio::time::{sleep, timeout};
-use tokio_rustls::{TlsAcceptor, TlsConnector};
+use tokio_rustls::TlsConnector;
 
 /// 测试服务器对畸形认证消息的处理
 #[tokio::test]
 async fn test_malformed_auth_message() {
-    let server_port = common::get_available_port();
     let auth_key = "test-fuzzy-auth";
 
     let (cert_path, key_path) = common::generate_test_certs();
@@ -23,31 +22,14 @@ async fn test_malformed_auth_message() {
 
     // 启动服务器
     let server_config = ServerConfig {
-        bind_addr: "127.0.0.1".to_string(),
-        bind_port: server_port,
         auth_key: auth_key.to_string(),
         cert_path: Some(cert_path.clone()),
         key_path: Some(key_path.clone()),
-        transport: TransportType::Tls,
-        behind_proxy: false,
-        allow_forward: false,
-        rate_limit: None,
-        size_limits: None,
-        stats_port: None,
-        stats_addr: None,
+        ..common::test_server_config()
     };
 
-    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None)
-        .expect("Failed to load server TLS config");
-    let acceptor = TlsAcceptor::from(tls_config);
-
-    let server_handle = tokio::spawn(async move {
-        tls_tunnel::server::run_server(server_config, acceptor)
-            .await
-            .ok();
-    });
-
-    sleep(Duration::from_millis(300)).await;
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
 
     // 连接到服务器
     let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
@@ -87,47 +69,30 @@ async fn test_malformed_auth_message() {
     }
 
     // 服务器应该保持运行，而不是崩溃
-    server_handle.abort();
+    server.shutdown().await.ok();
 }
 
 /// 测试服务器对超大消息的处理
 #[tokio::test]
 async fn test_oversized_messages() {
-    let server_port = common::get_available_port();
     let auth_key = "test-fuzzy-oversized";
 
     let (cert_path, key_path) = common::generate_test_certs();
     let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
 
     let server_config = ServerConfig {
-        bind_addr: "127.0.0.1".to_string(),
-        bind_port: server_port,
         auth_key: auth_key.to_string(),
         cert_path: Some(cert_path.clone()),
         key_path: Some(key_path.clone()),
-        transport: TransportType::Tls,
-        behind_proxy: false,
-        allow_forward: false,
-        rate_limit: None,
         size_limits: Some(tls_tunnel::config::SizeLimitConfig {
             max_request_size: 1024 * 1024, // 1MB
             max_header_size: 8 * 1024,     // 8KB
         }),
-        stats_port: None,
-        stats_addr: None,
+        ..common::test_server_config()
     };
 
-    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None)
-        .expect("Failed to load server TLS config");
-    let acceptor = TlsAcceptor::from(tls_config);
-
-    let server_handle = tokio::spawn(async move {
-        tls_tunnel::server::run_server(server_config, acceptor)
-            .await
-            .ok();
-    });
-
-    sleep(Duration::from_millis(300)).await;
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
 
     let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
         .expect("Failed to load client TLS config");
@@ -154,44 +119,26 @@ async fn test_oversized_messages() {
     // 服务器应该拒绝或关闭连接，但不应崩溃
     sleep(Duration::from_millis(500)).await;
 
-    server_handle.abort();
+    server.shutdown().await.ok();
 }
 
 /// 测试快速连接和断开
 #[tokio::test]
 async fn test_rapid_connect_disconnect() {
-    let server_port = common::get_available_port();
     let auth_key = "test-fuzzy-rapid";
 
     let (cert_path, key_path) = common::generate_test_certs();
     let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
 
     let server_config = ServerConfig {
-        bind_addr: "127.0.0.1".to_string(),
-        bind_port: server_port,
         auth_key: auth_key.to_string(),
         cert_path: Some(cert_path.clone()),
         key_path: Some(key_path.clone()),
-        transport: TransportType::Tls,
-        behind_proxy: false,
-        allow_forward: false,
-        rate_limit: None,
-        size_limits: None,
-        stats_port: None,
-        stats_addr: None,
+        ..common::test_server_config()
     };
 
-    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None)
-        .expect("Failed to load server TLS config");
-    let acceptor = TlsAcceptor::from(tls_config);
-
-    let server_handle = tokio::spawn(async move {
-        tls_tunnel::server::run_server(server_config, acceptor)
-            .await
-            .ok();
-    });
-
-    sleep(Duration::from_millis(300)).await;
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
 
     // 快速连接和断开 50 次
     for _ in 0..50 {
@@ -209,47 +156,30 @@ async fn test_rapid_connect_disconnect() {
     // 服务器应该仍然运行
     sleep(Duration::from_millis(500)).await;
 
-    server_handle.abort();
+    server.shutdown().await.ok();
 }
 
 /// 测试并发大量连接
 #[tokio::test]
 async fn test_concurrent_connections() {
-    let server_port = common::get_available_port();
     let auth_key = "test-fuzzy-concurrent";
 
     let (cert_path, key_path) = common::generate_test_certs();
     let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
 
     let server_config = ServerConfig {
-        bind_addr: "127.0.0.1".to_string(),
-        bind_port: server_port,
         auth_key: auth_key.to_string(),
         cert_path: Some(cert_path.clone()),
         key_path: Some(key_path.clone()),
-        transport: TransportType::Tls,
-        behind_proxy: false,
-        allow_forward: false,
         rate_limit: Some(tls_tunnel::config::RateLimitConfig {
             requests_per_second: 100,
             burst_size: 200,
         }),
-        size_limits: None,
-        stats_port: None,
-        stats_addr: None,
+        ..common::test_server_config()
     };
 
-    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None)
-        .expect("Failed to load server TLS config");
-    let acceptor = TlsAcceptor::from(tls_config);
-
-    let server_handle = tokio::spawn(async move {
-        tls_tunnel::server::run_server(server_config, acceptor)
-            .await
-            .ok();
-    });
-
-    sleep(Duration::from_millis(300)).await;
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
 
     // 并发创建 30 个连接
     let mut handles = vec![];
@@ -294,44 +224,26 @@ async fn test_concurrent_connections() {
     // 服务器应该仍然运行
     sleep(Duration::from_millis(500)).await;
 
-    server_handle.abort();
+    server.shutdown().await.ok();
 }
 
 /// 测试不完整的协议握手
 #[tokio::test]
 async fn test_incomplete_handshake() {
-    let server_port = common::get_available_port();
     let auth_key = "test-fuzzy-incomplete";
 
     let (cert_path, key_path) = common::generate_test_certs();
     let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
 
     let server_config = ServerConfig {
-        bind_addr: "127.0.0.1".to_string(),
-        bind_port: server_port,
         auth_key: auth_key.to_string(),
         cert_path: Some(cert_path.clone()),
         key_path: Some(key_path.clone()),
-        transport: TransportType::Tls,
-        behind_proxy: false,
-        allow_forward: false,
-        rate_limit: None,
-        size_limits: None,
-        stats_port: None,
-        stats_addr: None,
+        ..common::test_server_config()
     };
 
-    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None)
-        .expect("Failed to load server TLS config");
-    let acceptor = TlsAcceptor::from(tls_config);
-
-    let server_handle = tokio::spawn(async move {
-        tls_tunnel::server::run_server(server_config, acceptor)
-            .await
-            .ok();
-    });
-
-    sleep(Duration::from_millis(300)).await;
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
 
     // 建立 TLS 连接但不发送任何数据就关闭
     for _ in 0..10 {
@@ -362,44 +274,26 @@ async fn test_incomplete_handshake() {
     // 服务器应该仍然运行
     sleep(Duration::from_millis(500)).await;
 
-    server_handle.abort();
+    server.shutdown().await.ok();
 }
 
 /// 测试随机数据注入
 #[tokio::test]
 async fn test_random_data_injection() {
-    let server_port = common::get_available_port();
     let auth_key = "test-fuzzy-random";
 
     let (cert_path, key_path) = common::generate_test_certs();
     let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
 
     let server_config = ServerConfig {
-        bind_addr: "127.0.0.1".to_string(),
-        bind_port: server_port,
         auth_key: auth_key.to_string(),
         cert_path: Some(cert_path.clone()),
         key_path: Some(key_path.clone()),
-        transport: TransportType::Tls,
-        behind_proxy: false,
-        allow_forward: false,
-        rate_limit: None,
-        size_limits: None,
-        stats_port: None,
-        stats_addr: None,
+        ..common::test_server_config()
     };
 
-    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None)
-        .expect("Failed to load server TLS config");
-    let acceptor = TlsAcceptor::from(tls_config);
-
-    let server_handle = tokio::spawn(async move {
-        tls_tunnel::server::run_server(server_config, acceptor)
-            .await
-            .ok();
-    });
-
-    sleep(Duration::from_millis(300)).await;
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
 
     let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
         .expect("Failed to load client TLS config");
@@ -420,11 +314,11 @@ async fn test_random_data_injection() {
 
     // 发送随机数据
     use rand::Rng;
-    let mut rng = rand::thread_rng();
+    let mut rng = rand::rng();
 
     for _ in 0..20 {
-        let len = rng.gen_range(1..1000);
-        let random_data: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+        let len = rng.random_range(1..1000);
+        let random_data: Vec<u8> = (0..len).map(|_| rng.random()).collect();
 
         let _ = tls_stream.write_all(&random_data).await;
         let _ = tls_stream.flush().await;
@@ -435,44 +329,26 @@ async fn test_random_data_injection() {
     // 服务器应该保持运行
     sleep(Duration::from_millis(500)).await;
 
-    server_handle.abort();
+    server.shutdown().await.ok();
 }
 
 /// 测试空连接（连接后不发送任何数据）
 #[tokio::test]
 async fn test_idle_connections() {
-    let server_port = common::get_available_port();
     let auth_key = "test-fuzzy-idle";
 
     let (cert_path, key_path) = common::generate_test_certs();
     let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
 
     let server_config = ServerConfig {
-        bind_addr: "127.0.0.1".to_string(),
-        bind_port: server_port,
         auth_key: auth_key.to_string(),
         cert_path: Some(cert_path.clone()),
         key_path: Some(key_path.clone()),
-        transport: TransportType::Tls,
-        behind_proxy: false,
-        allow_forward: false,
-        rate_limit: None,
-        size_limits: None,
-        stats_port: None,
-        stats_addr: None,
+        ..common::test_server_config()
     };
 
-    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None)
-        .expect("Failed to load server TLS config");
-    let acceptor = TlsAcceptor::from(tls_config);
-
-    let server_handle = tokio::spawn(async move {
-        tls_tunnel::server::run_server(server_config, acceptor)
-            .await
-            .ok();
-    });
-
-    sleep(Duration::from_millis(300)).await;
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
 
     // 创建多个空闲连接
     let mut connections = vec![];
@@ -508,44 +384,26 @@ async fn test_idle_connections() {
     // 服务器应该仍然运行
     sleep(Duration::from_millis(500)).await;
 
-    server_handle.abort();
+    server.shutdown().await.ok();
 }
 
 /// 测试混合有效和无效的消息
 #[tokio::test]
 async fn test_mixed_valid_invalid_messages() {
-    let server_port = common::get_available_port();
     let auth_key = "test-fuzzy-mixed";
 
     let (cert_path, key_path) = common::generate_test_certs();
     let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
 
     let server_config = ServerConfig {
-        bind_addr: "127.0.0.1".to_string(),
-        bind_port: server_port,
         auth_key: auth_key.to_string(),
         cert_path: Some(cert_path.clone()),
         key_path: Some(key_path.clone()),
-        transport: TransportType::Tls,
-        behind_proxy: false,
-        allow_forward: false,
-        rate_limit: None,
-        size_limits: None,
-        stats_port: None,
-        stats_addr: None,
+        ..common::test_server_config()
     };
 
-    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None)
-        .expect("Failed to load server TLS config");
-    let acceptor = TlsAcceptor::from(tls_config);
-
-    let server_handle = tokio::spawn(async move {
-        tls_tunnel::server::run_server(server_config, acceptor)
-            .await
-            .ok();
-    });
-
-    sleep(Duration::from_millis(300)).await;
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
 
     let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
         .expect("Failed to load client TLS config");
@@ -592,5 +450,125 @@ async fn test_mixed_valid_invalid_messages() {
     // 服务器应该仍然运行
     sleep(Duration::from_millis(500)).await;
 
-    server_handle.abort();
+    server.shutdown().await.ok();
+}
+
+/// 测试统计服务器对慢速连接（slow-loris）的防护
+#[tokio::test]
+async fn test_stats_slow_loris() {
+    let stats_port = common::get_available_port();
+    let auth_key = "test-fuzzy-slow-loris";
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server_config = ServerConfig {
+        bind_addr: "127.0.0.1".to_string(),
+        bind_port: 0,
+        auth_key: auth_key.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        transport: TransportType::Tls,
+        behind_proxy: false,
+        allow_forward: false,
+        rate_limit: None,
+        size_limits: None,
+        stats_port: Some(stats_port),
+        stats_addr: None,
+        stats_path: None,
+        stats_limits: Some(StatsLimitConfig {
+            read_timeout_secs: 2,
+            ..Default::default()
+        }),
+        stats_stream: None,
+        interface_prefer_ipv6: false,
+        event_export: None,
+        max_write_chunk: None,
+        debug_stalls: false,
+        debug_tls_handshakes: false,
+        exception_limits: None,
+        drain_timeout_secs: None,
+        protocol_trace_path: None,
+        forward_limits: None,
+        forward_policy: None,
+        max_concurrent_handshakes: None,
+        handshake_timeout_secs: None,
+        idle_registration_expiry_secs: None,
+        relay_memory_budget_mb: None,
+        known_clients: None,
+        visitor_acl: None,
+        timeouts: None,
+        flow_export: None,
+        shutdown_grace_period_secs: None,
+        visitor_retry_timeout_secs: None,
+        tcp_keepalive_secs: None,
+        idle_timeout_secs: None,
+        max_streams_per_client: None,
+        max_connections_per_proxy: None,
+        auth_keys: None,
+        client_ca_path: None,
+        require_client_cert: false,
+    };
+
+    let server = common::spawn_server(server_config).await;
+    assert!(
+        common::wait_for_server(stats_port, 50).await,
+        "Stats server did not start listening"
+    );
+
+    // 慢速客户端：每 300ms 发送一个字节，永远发不完请求头
+    let mut slow_stream = TcpStream::connect(format!("127.0.0.1:{}", stats_port))
+        .await
+        .expect("Failed to connect to stats port");
+    let started = std::time::Instant::now();
+    let slow_client = tokio::spawn(async move {
+        for byte in b"GET /stats HTTP/1.1\r\nX-Slow: ".iter().cycle() {
+            if slow_stream.write_all(&[*byte]).await.is_err() {
+                break;
+            }
+            sleep(Duration::from_millis(300)).await;
+            if started.elapsed() > Duration::from_secs(10) {
+                break;
+            }
+        }
+        let mut response = Vec::new();
+        let _ = timeout(
+            Duration::from_secs(1),
+            slow_stream.read_to_end(&mut response),
+        )
+        .await;
+        (
+            started.elapsed(),
+            String::from_utf8_lossy(&response).to_string(),
+        )
+    });
+
+    // 慢速连接存在期间，正常请求仍然能得到响应
+    for _ in 0..3 {
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", stats_port))
+            .await
+            .expect("Failed to connect to stats port");
+        stream
+            .write_all(b"GET /stats HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        timeout(Duration::from_secs(2), stream.read_to_end(&mut response))
+            .await
+            .expect("Stats request should not hang")
+            .unwrap();
+        assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 200 OK"));
+        sleep(Duration::from_millis(200)).await;
+    }
+
+    // 慢速连接应该在读取超时后被回收
+    let (elapsed, response) = slow_client.await.unwrap();
+    assert!(
+        elapsed < Duration::from_secs(5),
+        "Slow connection was not reaped in time: {:?}",
+        elapsed
+    );
+    assert!(response.starts_with("HTTP/1.1 408"));
+
+    server.shutdown().await.ok();
 }
@@ -0,0 +1,266 @@
+/// Graceful shutdown tests
+///
+/// 服务器停止时先排空：不再接受新的代理连接，进行中的转发继续，全部结束（或超过
+/// `shutdown_grace_period_secs`）后才结束会话并退出；服务器进程排空期间再次收到退出信号时立即退出
+mod common;
+
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-graceful-shutdown-key";
+
+fn server_config(cert_path: &Path, key_path: &Path, grace_secs: u64) -> ServerConfig {
+    ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        shutdown_grace_period_secs: Some(grace_secs),
+        ..common::test_server_config()
+    }
+}
+
+fn spawn_client(
+    server_port: u16,
+    cert_path: &Path,
+    publish_port: u16,
+    local_port: u16,
+) -> JoinHandle<()> {
+    let config = ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "transfer".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    })
+}
+
+/// 发送数据并读取等长的回显
+async fn echo(stream: &mut TcpStream, data: &[u8]) -> std::io::Result<Vec<u8>> {
+    stream.write_all(data).await?;
+    let mut received = vec![0u8; data.len()];
+    timeout(Duration::from_secs(10), stream.read_exact(&mut received))
+        .await
+        .map_err(|_| std::io::Error::other("Timed out reading echo"))??;
+    Ok(received)
+}
+
+/// 连接发布端口并完成第一次回显
+async fn connect_ready(publish_port: u16) -> TcpStream {
+    for _ in 0..50 {
+        if let Ok(mut stream) = TcpStream::connect(("127.0.0.1", publish_port)).await {
+            if echo(&mut stream, b"first")
+                .await
+                .is_ok_and(|data| data == b"first")
+            {
+                return stream;
+            }
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Proxy 'transfer' never became reachable");
+}
+
+#[tokio::test]
+async fn test_in_flight_transfer_completes_during_grace_period() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server = common::spawn_server(server_config(&cert_path, &key_path, 30)).await;
+    let echo_port = common::get_available_port();
+    let _echo = common::start_echo_server(echo_port).await;
+    let publish_port = common::get_available_port();
+    let client = spawn_client(
+        server.bound_addr().port(),
+        &cert_path,
+        publish_port,
+        echo_port,
+    );
+    let mut stream = connect_ready(publish_port).await;
+
+    let stats = server.stats();
+    let shutdown = tokio::spawn(server.shutdown());
+
+    // 发布端口停止接受新连接
+    assert!(
+        common::wait_until(Duration::from_secs(5), || async {
+            common::test_proxy_connection(publish_port, b"new", Duration::from_millis(500))
+                .await
+                .map_or(true, |data| data.is_empty())
+        })
+        .await,
+        "Proxy kept accepting connections while draining"
+    );
+
+    // 进行中的连接在排空期间继续转发
+    for round in 0..5u8 {
+        let chunk: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8 ^ round).collect();
+        assert_eq!(echo(&mut stream, &chunk).await.unwrap(), chunk);
+        sleep(Duration::from_millis(200)).await;
+    }
+    assert!(
+        !shutdown.is_finished(),
+        "Server stopped before the relay ended"
+    );
+    assert_eq!(
+        stats
+            .get_proxy_stats("transfer")
+            .unwrap()
+            .core
+            .active_connections,
+        1
+    );
+
+    // 连接结束后服务器很快停止，不需要等满排空时长
+    let closed_at = Instant::now();
+    drop(stream);
+    timeout(Duration::from_secs(10), shutdown)
+        .await
+        .expect("Server did not stop after the relay ended")
+        .unwrap()
+        .unwrap();
+    assert!(closed_at.elapsed() < Duration::from_secs(10));
+    assert!(stats.get_proxy_stats("transfer").is_none());
+
+    client.abort();
+}
+
+/// 服务器进程排空期间再次收到 SIGTERM 时立即退出
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_second_signal_stops_immediately() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server_port = common::get_available_port();
+    let config_path = std::env::temp_dir().join(format!(
+        "tls-tunnel-graceful-shutdown-test-{}.toml",
+        std::process::id()
+    ));
+    std::fs::write(
+        &config_path,
+        format!(
+            "[server]\nbind_addr = \"127.0.0.1\"\nbind_port = {}\nauth_key = \"{}\"\n\
+             cert_path = {:?}\nkey_path = {:?}\nshutdown_grace_period_secs = 60\n",
+            server_port,
+            AUTH_KEY,
+            cert_path.display().to_string(),
+            key_path.display().to_string(),
+        ),
+    )
+    .unwrap();
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&config_path, std::fs::Permissions::from_mode(0o600)).unwrap();
+    }
+
+    let mut server = Command::new(env!("CARGO_BIN_EXE_tls-tunnel"))
+        .args(["server", "-c"])
+        .arg(&config_path)
+        .env("RUST_LOG", "info")
+        .env("NO_COLOR", "1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .expect("Failed to start server");
+    let pid = server.id() as libc::pid_t;
+    let stdout = server.stdout.take().unwrap();
+    let (log_tx, logs) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if log_tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    assert!(common::wait_for_server(server_port, 50).await);
+
+    let echo_port = common::get_available_port();
+    let _echo = common::start_echo_server(echo_port).await;
+    let publish_port = common::get_available_port();
+    let client = spawn_client(server_port, &cert_path, publish_port, echo_port);
+    let mut stream = connect_ready(publish_port).await;
+
+    // 第一次信号：有进行中的转发，服务器继续运行
+    unsafe {
+        libc::kill(pid, libc::SIGTERM);
+    }
+    sleep(Duration::from_secs(1)).await;
+    assert!(server.try_wait().unwrap().is_none(), "Server did not drain");
+    assert_eq!(echo(&mut stream, b"still").await.unwrap(), b"still");
+
+    // 第二次信号：立即退出
+    let signalled_at = Instant::now();
+    unsafe {
+        libc::kill(pid, libc::SIGTERM);
+    }
+    let status = loop {
+        if let Some(status) = server.try_wait().unwrap() {
+            break status;
+        }
+        assert!(
+            signalled_at.elapsed() < Duration::from_secs(15),
+            "Server did not stop after the second signal"
+        );
+        sleep(Duration::from_millis(50)).await;
+    };
+    assert!(status.success(), "Server exited with {}", status);
+
+    let mut output = Vec::new();
+    while let Ok(line) = logs.recv_timeout(Duration::from_secs(2)) {
+        output.push(line);
+    }
+    let output = output.join("\n");
+    assert!(output.contains("draining 1 active relay(s)"), "{}", output);
+    assert!(
+        output.contains("Received SIGTERM again, stopping immediately"),
+        "{}",
+        output
+    );
+
+    client.abort();
+    let _ = std::fs::remove_file(&config_path);
+}
@@ -0,0 +1,280 @@
+/// Half-open connection tests
+///
+/// 配置 `require_first_byte_timeout_ms` 的代理在外部连接发来第一个字节之前不请求隧道 stream，
+/// 只连接不发送数据的连接不会让客户端连接本地服务；`max_connections_per_source` 限制同一来源 IP
+/// 的并发连接，超出的连接被立即关闭，其他来源不受影响
+mod common;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+};
+use tls_tunnel::server::ServerHandle;
+use tls_tunnel::stats::AdmissionStats;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-half-open-key";
+
+/// 统计接受连接数的回显服务器（客户端连接本地服务的次数）
+async fn start_counting_echo() -> (u16, Arc<AtomicU64>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let accepted = Arc::new(AtomicU64::new(0));
+    let counter = accepted.clone();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            counter.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let mut buf = [0u8; 1024];
+                while let Ok(n) = socket.read(&mut buf).await {
+                    if n == 0 || socket.write_all(&buf[..n]).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    (port, accepted)
+}
+
+/// 启动服务器和注册一个代理的客户端
+async fn start_tunnel(
+    proxy: ProxyConfig,
+    cert_path: &std::path::Path,
+    key_path: &std::path::Path,
+) -> (ServerHandle, JoinHandle<()>) {
+    let server = common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        ..common::test_server_config()
+    })
+    .await;
+
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            keep_listening_on_disconnect: false,
+            ..common::test_client_config()
+        },
+        proxies: vec![proxy],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client(client_config, connector)
+            .await
+            .ok();
+    });
+
+    (server, client_handle)
+}
+
+fn proxy(
+    publish_port: u16,
+    local_port: u16,
+    require_first_byte_timeout_ms: Option<u64>,
+    max_connections_per_source: Option<u32>,
+) -> ProxyConfig {
+    ProxyConfig {
+        name: "guarded".to_string(),
+        proxy_type: ProxyType::Tcp,
+        publish_addr: "127.0.0.1".to_string(),
+        publish_port,
+        local_port,
+        pool: None,
+        shared: false,
+        weight: None,
+        visibility: ProxyVisibility::Public,
+        drain_timeout_secs: None,
+        sni_routing: None,
+        socket: None,
+        local_targets: None,
+        idle_keepalive_secs: None,
+        require_first_byte_timeout_ms,
+        max_connections_per_source,
+        report_peers: false,
+        ws_idle_timeout_secs: None,
+        integrity_check: false,
+    }
+}
+
+/// 从指定的本地地址连接发布端口
+async fn connect_from(source: &str, publish_port: u16) -> TcpStream {
+    let socket = TcpSocket::new_v4().unwrap();
+    socket
+        .bind(format!("{}:0", source).parse().unwrap())
+        .unwrap();
+    socket
+        .connect(format!("127.0.0.1:{}", publish_port).parse().unwrap())
+        .await
+        .expect("Failed to connect to the published port")
+}
+
+/// 完成一次回显
+async fn echo(stream: &mut TcpStream, data: &[u8]) -> bool {
+    if stream.write_all(data).await.is_err() {
+        return false;
+    }
+    let mut received = vec![0u8; data.len()];
+    matches!(
+        timeout(Duration::from_secs(5), stream.read_exact(&mut received)).await,
+        Ok(Ok(_))
+    ) && received == data
+}
+
+/// 等待代理可用（完成一次回显）
+async fn wait_for_proxy(publish_port: u16) {
+    for _ in 0..50 {
+        if let Ok(mut stream) = TcpStream::connect(("127.0.0.1", publish_port)).await {
+            if echo(&mut stream, b"ready").await {
+                return;
+            }
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+    panic!("Proxy did not come up");
+}
+
+/// 服务器统计中代理的准入统计
+fn admission(server: &ServerHandle) -> AdmissionStats {
+    server
+        .stats()
+        .get_all_stats()
+        .into_iter()
+        .find(|s| s.core.name == "guarded")
+        .and_then(|s| s.admission)
+        .expect("Admission stats missing")
+}
+
+#[tokio::test]
+async fn test_silent_connections_never_reach_the_backend() {
+    let publish_port = common::get_available_port();
+    let (local_port, accepted) = start_counting_echo().await;
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let (server, client_handle) = start_tunnel(
+        proxy(publish_port, local_port, Some(300), None),
+        &cert_path,
+        &key_path,
+    )
+    .await;
+    wait_for_proxy(publish_port).await;
+    let dials_before = accepted.load(Ordering::SeqCst);
+
+    // 只连接不发送数据：超时后被服务器关闭，客户端没有连接本地服务
+    let mut silent = Vec::new();
+    for _ in 0..20 {
+        silent.push(
+            TcpStream::connect(("127.0.0.1", publish_port))
+                .await
+                .unwrap(),
+        );
+    }
+    for stream in &mut silent {
+        let mut buf = [0u8; 1];
+        let read = timeout(Duration::from_secs(3), stream.read(&mut buf))
+            .await
+            .expect("Silent connection was not closed");
+        assert!(matches!(read, Ok(0) | Err(_)), "{:?}", read);
+    }
+    assert_eq!(accepted.load(Ordering::SeqCst), dials_before);
+
+    let stats = admission(&server);
+    assert_eq!(stats.first_byte_timeout_ms, Some(300));
+    assert_eq!(stats.silent_connections, 20);
+    assert_eq!(stats.max_connections_per_source, None);
+
+    // 先发数据的连接正常转发，第一个字节没有丢失
+    let mut stream = TcpStream::connect(("127.0.0.1", publish_port))
+        .await
+        .unwrap();
+    assert!(echo(&mut stream, b"first byte kept").await);
+    assert_eq!(accepted.load(Ordering::SeqCst), dials_before + 1);
+    // 稍后才发数据（仍在超时内）的连接同样正常
+    let mut stream = TcpStream::connect(("127.0.0.1", publish_port))
+        .await
+        .unwrap();
+    sleep(Duration::from_millis(100)).await;
+    assert!(echo(&mut stream, b"late").await);
+    assert_eq!(admission(&server).silent_connections, 20);
+
+    client_handle.abort();
+    server.shutdown().await.ok();
+}
+
+#[tokio::test]
+async fn test_per_source_cap_contains_one_ip() {
+    const CAP: u32 = 3;
+
+    let publish_port = common::get_available_port();
+    let (local_port, _accepted) = start_counting_echo().await;
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let (server, client_handle) = start_tunnel(
+        proxy(publish_port, local_port, None, Some(CAP)),
+        &cert_path,
+        &key_path,
+    )
+    .await;
+    wait_for_proxy(publish_port).await;
+
+    // 127.0.0.2 持有 CAP 个连接后，之后的连接被立即关闭
+    let mut held = Vec::new();
+    for _ in 0..CAP {
+        let mut stream = connect_from("127.0.0.2", publish_port).await;
+        assert!(echo(&mut stream, b"held").await);
+        held.push(stream);
+    }
+    for _ in 0..10 {
+        let mut stream = connect_from("127.0.0.2", publish_port).await;
+        let mut buf = [0u8; 1];
+        let read = timeout(Duration::from_secs(3), stream.read(&mut buf))
+            .await
+            .expect("Excess connection was not closed");
+        assert!(matches!(read, Ok(0) | Err(_)), "{:?}", read);
+    }
+    let stats = admission(&server);
+    assert_eq!(stats.max_connections_per_source, Some(CAP));
+    assert_eq!(stats.source_limited, 10);
+
+    // 其他来源不受影响
+    let mut other = connect_from("127.0.0.3", publish_port).await;
+    assert!(echo(&mut other, b"other source").await);
+    for stream in &mut held {
+        assert!(echo(stream, b"still relayed").await);
+    }
+
+    // 连接结束后名额归还
+    drop(held);
+    let mut admitted = false;
+    for _ in 0..20 {
+        sleep(Duration::from_millis(100)).await;
+        let mut stream = connect_from("127.0.0.2", publish_port).await;
+        if echo(&mut stream, b"again").await {
+            admitted = true;
+            break;
+        }
+    }
+    assert!(
+        admitted,
+        "Source was not admitted after its connections ended"
+    );
+
+    client_handle.abort();
+    server.shutdown().await.ok();
+}
@@ -0,0 +1,227 @@
+#![cfg(target_os = "linux")]
+
+/// Listener handover tests (`server --upgrade-handover`, Linux only)
+///
+/// 向运行中的服务器进程发送 SIGUSR2，新进程接管主监听端口和代理发布端口，旧进程排空会话后
+/// 退出；整个过程中发布端口的连接不会被拒绝
+mod common;
+
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+use tls_tunnel::config::{ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility};
+use tokio::net::TcpStream;
+use tokio::time::sleep;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-handover-key";
+
+/// 结束时杀掉仍在运行的服务器进程
+struct KillOnDrop(Vec<u32>);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        for pid in &self.0 {
+            unsafe {
+                libc::kill(*pid as libc::pid_t, libc::SIGKILL);
+            }
+        }
+    }
+}
+
+/// 启动服务器进程，日志逐行发送到返回的通道
+fn spawn_server_process(config_path: &std::path::Path) -> (Child, mpsc::Receiver<String>) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tls-tunnel"))
+        .args(["server", "--upgrade-handover", "-c"])
+        .arg(config_path)
+        .env("RUST_LOG", "info")
+        .env("NO_COLOR", "1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .expect("Failed to start server process");
+
+    // 新进程继承同一个 stdout，读取线程持续排空管道
+    let stdout = child.stdout.take().unwrap();
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    (child, rx)
+}
+
+/// 从日志中等待新进程的 PID
+fn wait_for_new_pid(logs: &mpsc::Receiver<String>) -> u32 {
+    const MARKER: &str = "Started new process (pid ";
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while let Ok(line) = logs.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        if let Some(rest) = line.split(MARKER).nth(1) {
+            let pid: String = rest.chars().take_while(char::is_ascii_digit).collect();
+            return pid.parse().expect("Invalid pid in log");
+        }
+    }
+    panic!("New process was never started");
+}
+
+#[tokio::test]
+async fn test_handover_never_refuses_connections() {
+    let server_port = common::get_available_port();
+    let publish_port = common::get_available_port();
+    let echo_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo_server = common::start_echo_server(echo_port).await;
+
+    let config_path = std::env::temp_dir().join(format!(
+        "tls-tunnel-handover-test-{}.toml",
+        std::process::id()
+    ));
+    std::fs::write(
+        &config_path,
+        format!(
+            "[server]\nbind_addr = \"127.0.0.1\"\nbind_port = {}\nauth_key = \"{}\"\n\
+             cert_path = {:?}\nkey_path = {:?}\n",
+            server_port,
+            AUTH_KEY,
+            cert_path.display().to_string(),
+            key_path.display().to_string(),
+        ),
+    )
+    .unwrap();
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&config_path, std::fs::Permissions::from_mode(0o600)).unwrap();
+    }
+
+    let (mut old_server, logs) = spawn_server_process(&config_path);
+    let mut pids = KillOnDrop(vec![old_server.id()]);
+    assert!(
+        common::wait_for_server(server_port, 50).await,
+        "Server did not start listening"
+    );
+
+    // 客户端在服务器关闭会话后 1 秒内重连
+    std::env::set_var("TLS_TUNNEL_RECONNECT_DELAY_SECS", "1");
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.clone()),
+            auth_key: AUTH_KEY.to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "echo".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port: echo_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client(client_config, connector)
+            .await
+            .ok();
+    });
+
+    let echo_through_proxy = || async {
+        for _ in 0..100 {
+            if let Ok(response) =
+                common::test_proxy_connection(publish_port, b"handover", Duration::from_secs(2))
+                    .await
+            {
+                if response == b"handover" {
+                    return true;
+                }
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        false
+    };
+    assert!(echo_through_proxy().await, "Proxy never became ready");
+
+    // 交接期间持续连接发布端口，统计被拒绝的次数
+    let stop = Arc::new(AtomicBool::new(false));
+    let attempts = Arc::new(AtomicU64::new(0));
+    let refused = Arc::new(AtomicU64::new(0));
+    let probe = {
+        let (stop, attempts, refused) = (stop.clone(), attempts.clone(), refused.clone());
+        tokio::spawn(async move {
+            while !stop.load(Ordering::Relaxed) {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                if let Err(e) = TcpStream::connect(("127.0.0.1", publish_port)).await {
+                    if e.kind() == std::io::ErrorKind::ConnectionRefused {
+                        refused.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+    };
+    sleep(Duration::from_millis(200)).await;
+
+    unsafe {
+        libc::kill(old_server.id() as libc::pid_t, libc::SIGUSR2);
+    }
+    let new_pid = wait_for_new_pid(&logs);
+    pids.0.push(new_pid);
+
+    // 旧进程排空会话后退出
+    let deadline = Instant::now() + Duration::from_secs(20);
+    loop {
+        if let Some(status) = old_server.try_wait().unwrap() {
+            assert!(status.success(), "Old server exited with {}", status);
+            break;
+        }
+        assert!(Instant::now() < deadline, "Old server did not exit");
+        sleep(Duration::from_millis(100)).await;
+    }
+
+    // 客户端重连到新进程后代理恢复
+    assert!(
+        echo_through_proxy().await,
+        "Proxy did not recover after handover"
+    );
+    stop.store(true, Ordering::Relaxed);
+    probe.await.unwrap();
+
+    assert!(attempts.load(Ordering::Relaxed) > 0);
+    assert_eq!(
+        refused.load(Ordering::Relaxed),
+        0,
+        "Connections were refused during handover"
+    );
+
+    client_handle.abort();
+    drop(pids);
+    let _ = std::fs::remove_file(&config_path);
+}
@@ -0,0 +1,272 @@
+/// Handshake limit tests
+///
+/// 服务器只在 accept 循环中接受原始连接，速率限制在握手之前生效；配置了
+/// `max_concurrent_handshakes` 时超出上限的连接排队，排队和握手超过 `handshake_timeout_secs`
+/// 的连接被关闭并释放名额。负载测试（默认忽略）比较握手洪泛期间已建立隧道的回显延迟
+mod common;
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, RateLimitConfig,
+    ServerConfig,
+};
+use tls_tunnel::server::ServerHandle;
+use tls_tunnel::stats::StatsManager;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-handshake-limit-key";
+
+fn server_config(cert_path: &Path, key_path: &Path) -> ServerConfig {
+    ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        ..common::test_server_config()
+    }
+}
+
+fn connector(cert_path: &Path) -> TlsConnector {
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    TlsConnector::from(tls_config)
+}
+
+/// 完成一次 TLS 握手
+async fn tls_handshake(connector: &TlsConnector, port: u16) -> std::io::Result<()> {
+    let tcp = TcpStream::connect(("127.0.0.1", port)).await?;
+    let server_name = rustls::pki_types::ServerName::try_from("localhost").unwrap();
+    connector.connect(server_name, tcp).await.map(drop)
+}
+
+/// 等待条件成立（最多 5 秒）
+async fn wait_until(stats: &StatsManager, check: impl Fn(&StatsManager) -> bool) -> bool {
+    for _ in 0..100 {
+        if check(stats) {
+            return true;
+        }
+        sleep(Duration::from_millis(50)).await;
+    }
+    false
+}
+
+/// 等待服务器关闭连接
+async fn wait_closed(stream: &mut TcpStream) -> bool {
+    let mut buf = [0u8; 16];
+    timeout(Duration::from_secs(5), async {
+        while let Ok(n) = stream.read(&mut buf).await {
+            if n == 0 {
+                break;
+            }
+        }
+    })
+    .await
+    .is_ok()
+}
+
+#[tokio::test]
+async fn test_silent_connections_queue_and_time_out() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let mut config = server_config(&cert_path, &key_path);
+    config.max_concurrent_handshakes = Some(2);
+    config.handshake_timeout_secs = Some(1);
+    let server = common::spawn_server(config).await;
+    let port = server.bound_addr().port();
+    let stats = server.stats();
+
+    // 不发送 ClientHello 的连接占满握手名额，其余排队
+    let mut silent = Vec::new();
+    for _ in 0..4 {
+        silent.push(TcpStream::connect(("127.0.0.1", port)).await.unwrap());
+    }
+    assert!(
+        wait_until(&stats, |s| s.handshakes().in_progress() == 2
+            && s.handshakes().queued() == 2)
+        .await,
+        "in_progress={} queued={}",
+        stats.handshakes().in_progress(),
+        stats.handshakes().queued()
+    );
+
+    // 排队等待同样计入超时，所有连接都被关闭
+    for stream in &mut silent {
+        assert!(
+            wait_closed(stream).await,
+            "Server should close silent connections"
+        );
+    }
+    assert!(wait_until(&stats, |s| s.handshakes().timed_out() == 4).await);
+    assert_eq!(stats.handshakes().in_progress(), 0);
+    assert_eq!(stats.handshakes().queued(), 0);
+
+    // 名额释放后正常握手
+    tls_handshake(&connector(&cert_path), port)
+        .await
+        .expect("Handshake should succeed after the slots are released");
+    assert!(wait_until(&stats, |s| s.handshakes().completed() == 1).await);
+    assert_eq!(stats.handshakes().failed(), 0);
+
+    server.shutdown().await.ok();
+}
+
+#[tokio::test]
+async fn test_rate_limit_rejects_before_handshake() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let mut config = server_config(&cert_path, &key_path);
+    config.rate_limit = Some(RateLimitConfig {
+        requests_per_second: 1,
+        burst_size: 2,
+    });
+    let server = common::spawn_server(config).await;
+    let port = server.bound_addr().port();
+    let stats = server.stats();
+
+    let mut streams = Vec::new();
+    for _ in 0..5 {
+        streams.push(TcpStream::connect(("127.0.0.1", port)).await.unwrap());
+    }
+    assert!(
+        wait_until(&stats, |s| s.handshakes().rate_limited() == 3).await,
+        "rate_limited={}",
+        stats.handshakes().rate_limited()
+    );
+    // 被拒绝的连接没有进入握手
+    assert_eq!(stats.handshakes().in_progress(), 2);
+
+    server.shutdown().await.ok();
+}
+
+/// 启动带回显代理的隧道，返回服务器、客户端任务和发布端口
+async fn start_tunnel(
+    cert_path: &Path,
+    key_path: &Path,
+    max_concurrent_handshakes: Option<usize>,
+) -> (ServerHandle, JoinHandle<()>, u16) {
+    let mut config = server_config(cert_path, key_path);
+    config.max_concurrent_handshakes = max_concurrent_handshakes;
+    let server = common::spawn_server(config).await;
+
+    let local_port = common::get_available_port();
+    common::start_echo_server(local_port).await;
+    let publish_port = common::get_available_port();
+    let config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "echo".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let connector = connector(cert_path);
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    });
+    (server, client, publish_port)
+}
+
+/// 连接发布端口并完成第一次回显
+async fn connect_echo(publish_port: u16) -> TcpStream {
+    for _ in 0..50 {
+        if let Ok(mut stream) = TcpStream::connect(("127.0.0.1", publish_port)).await {
+            let mut buf = [0u8; 4];
+            if stream.write_all(b"ping").await.is_ok()
+                && timeout(Duration::from_secs(2), stream.read_exact(&mut buf))
+                    .await
+                    .is_ok_and(|r| r.is_ok())
+            {
+                return stream;
+            }
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Tunnel did not become ready");
+}
+
+/// 在 `flood` 个并发握手期间测量回显延迟的中位数和最大值
+async fn echo_latency_under_flood(
+    cert_path: &Path,
+    key_path: &Path,
+    max_concurrent_handshakes: Option<usize>,
+    flood: usize,
+) -> (Duration, Duration) {
+    let (server, client, publish_port) =
+        start_tunnel(cert_path, key_path, max_concurrent_handshakes).await;
+    let mut stream = connect_echo(publish_port).await;
+    let port = server.bound_addr().port();
+
+    let connector = connector(cert_path);
+    let attackers: Vec<_> = (0..flood)
+        .map(|_| {
+            let connector = connector.clone();
+            tokio::spawn(async move {
+                let _ = tls_handshake(&connector, port).await;
+            })
+        })
+        .collect();
+
+    let mut samples = Vec::new();
+    let mut buf = [0u8; 64];
+    while samples.len() < 50 || !attackers.iter().all(|a| a.is_finished()) {
+        let started = Instant::now();
+        stream.write_all(&[7u8; 64]).await.unwrap();
+        stream.read_exact(&mut buf).await.unwrap();
+        samples.push(started.elapsed());
+        sleep(Duration::from_millis(10)).await;
+    }
+    samples.sort();
+
+    client.abort();
+    server.shutdown().await.ok();
+    (samples[samples.len() / 2], samples[samples.len() - 1])
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "load test; run with --ignored --nocapture"]
+async fn test_handshake_flood_echo_latency() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    const FLOOD: usize = 500;
+
+    let (median, max) = echo_latency_under_flood(&cert_path, &key_path, None, FLOOD).await;
+    println!("uncapped: median {:?}, max {:?}", median, max);
+    let (capped_median, capped_max) =
+        echo_latency_under_flood(&cert_path, &key_path, Some(4), FLOOD).await;
+    println!(
+        "max_concurrent_handshakes = 4: median {:?}, max {:?}",
+        capped_median, capped_max
+    );
+}
@@ -0,0 +1,172 @@
+/// Health score tests
+///
+/// 客户端 `/health/score` 端点在隧道正常时为 green，服务器断开后为 red 并说明扣分原因；
+/// 配置 `[health] notify = true` 时总体状态变化发布 `health_changed` 会话事件
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+use tls_tunnel::client::{HealthReport, HealthStatus, SessionEvent};
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, HealthConfig, ProxyConfig, ProxyType, ProxyVisibility,
+    ServerConfig,
+};
+use tls_tunnel::stats_http::StatsEndpoint;
+use tokio::sync::broadcast;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-health-score-key";
+
+fn server_config(cert_path: &Path, key_path: &Path) -> ServerConfig {
+    ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        ..common::test_server_config()
+    }
+}
+
+fn client_config(
+    server_port: u16,
+    cert_path: &Path,
+    stats_port: u16,
+    publish_port: u16,
+    local_port: u16,
+) -> ClientFullConfig {
+    ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            stats_port: Some(stats_port),
+            stats_addr: Some("127.0.0.1".to_string()),
+            keep_listening_on_disconnect: false,
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "echo".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: Some(HealthConfig {
+            interval_secs: 1,
+            notify: true,
+            ..Default::default()
+        }),
+    }
+}
+
+async fn health_report(endpoint: &StatsEndpoint) -> HealthReport {
+    let body = endpoint
+        .get("/health/score")
+        .await
+        .expect("Failed to read /health/score");
+    serde_json::from_str(&body).unwrap_or_else(|e| panic!("Invalid report '{}': {}", body, e))
+}
+
+#[tokio::test]
+async fn test_health_score_follows_tunnel_state() {
+    let stats_port = common::get_available_port();
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+
+    let server = common::spawn_server(server_config(&cert_path, &key_path)).await;
+    let config = client_config(
+        server.bound_addr().port(),
+        &cert_path,
+        stats_port,
+        publish_port,
+        local_port,
+    );
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let (events, mut rx) = broadcast::channel(tls_tunnel::client::SESSION_EVENT_CAPACITY);
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client_with_events(config, connector, events)
+            .await
+            .ok();
+    });
+
+    let mut reachable = false;
+    for _ in 0..50 {
+        if common::test_proxy_connection(publish_port, b"ping", Duration::from_secs(2))
+            .await
+            .is_ok_and(|echo| echo == b"ping")
+        {
+            reachable = true;
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert!(reachable, "Proxy never became reachable");
+
+    // 隧道正常：满分，没有扣分项
+    let endpoint = StatsEndpoint::Http(format!("http://127.0.0.1:{}", stats_port));
+    let report = health_report(&endpoint).await;
+    assert_eq!(report.status, HealthStatus::Green, "{:?}", report);
+    assert_eq!(report.score, 100);
+    assert_eq!(report.proxies.len(), 1);
+    assert_eq!(report.proxies[0].name, "echo");
+    assert!(report.tunnel.contributions.is_empty());
+
+    // 控制面板显示状态横幅
+    let html = endpoint.get("/").await.expect("Failed to read dashboard");
+    assert!(html.contains("health-banner health-green"), "{}", html);
+
+    // 等待后台评估记录 green 状态后关闭服务器
+    sleep(Duration::from_millis(1500)).await;
+    server.shutdown().await.ok();
+
+    let changed = timeout(Duration::from_secs(15), async {
+        loop {
+            match rx.recv().await {
+                Ok(event @ SessionEvent::HealthChanged { .. }) => return event,
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(e) => panic!("Event channel closed: {}", e),
+            }
+        }
+    })
+    .await
+    .expect("No health_changed event after the server went away");
+    assert_eq!(
+        changed,
+        SessionEvent::HealthChanged {
+            previous: HealthStatus::Green,
+            current: HealthStatus::Red,
+            score: 0,
+        }
+    );
+
+    let report = health_report(&endpoint).await;
+    assert_eq!(report.status, HealthStatus::Red);
+    assert_eq!(report.tunnel.contributions[0].component, "connection");
+    let html = endpoint.get("/").await.expect("Failed to read dashboard");
+    assert!(html.contains("health-banner health-red"), "{}", html);
+
+    client.abort();
+}
@@ -0,0 +1,276 @@
+/// Heartbeat timeout tests
+///
+/// 客户端与服务器之间的连接静默失效（例如防火墙丢弃 NAT 状态）时，服务器在
+/// `timeouts.heartbeat_timeout_secs` 内没有收到心跳或其他控制消息即结束会话并注销代理，
+/// 客户端在连续的心跳得不到确认时主动断开重连，而不是等待 TCP 超时
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tls_tunnel::client::SessionEvent;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ClientTimeoutsConfig, ProxyConfig, ProxyType, ProxyVisibility,
+    ServerConfig, ServerTimeoutsConfig,
+};
+use tls_tunnel::server::ServerDependencies;
+use tls_tunnel::stats::StatsManager;
+use tls_tunnel::transport::TransportType;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-heartbeat-timeout-key";
+
+/// 客户端与服务器之间的 TCP 中继，冻结后不再转发任何数据，也不建立新的连接
+/// （连接保持打开，两端都收不到 FIN 或 RST）
+struct FreezableRelay {
+    port: u16,
+    frozen: Arc<AtomicBool>,
+}
+
+impl FreezableRelay {
+    async fn start(server_port: u16) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let frozen = Arc::new(AtomicBool::new(false));
+        let state = frozen.clone();
+        tokio::spawn(async move {
+            while let Ok((inbound, _)) = listener.accept().await {
+                let frozen = state.clone();
+                tokio::spawn(async move {
+                    wait_unless_frozen(&frozen).await;
+                    let outbound = TcpStream::connect(("127.0.0.1", server_port)).await?;
+                    let (inbound_read, inbound_write) = inbound.into_split();
+                    let (outbound_read, outbound_write) = outbound.into_split();
+                    tokio::select! {
+                        _ = pipe(inbound_read, outbound_write, &frozen) => {}
+                        _ = pipe(outbound_read, inbound_write, &frozen) => {}
+                    }
+                    Ok::<_, std::io::Error>(())
+                });
+            }
+        });
+        Self { port, frozen }
+    }
+
+    fn freeze(&self) {
+        self.frozen.store(true, Ordering::SeqCst);
+    }
+}
+
+/// 冻结后永远挂起
+async fn wait_unless_frozen(frozen: &AtomicBool) {
+    if frozen.load(Ordering::SeqCst) {
+        std::future::pending::<()>().await;
+    }
+}
+
+async fn pipe(
+    mut reader: tokio::net::tcp::OwnedReadHalf,
+    mut writer: tokio::net::tcp::OwnedWriteHalf,
+    frozen: &AtomicBool,
+) -> std::io::Result<()> {
+    let mut buf = vec![0u8; 16 * 1024];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        wait_unless_frozen(frozen).await;
+        writer.write_all(&buf[..n]).await?;
+    }
+}
+
+fn server_config(cert_path: &std::path::Path, key_path: &std::path::Path) -> ServerConfig {
+    ServerConfig {
+        bind_addr: "127.0.0.1".to_string(),
+        bind_port: 0,
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        transport: TransportType::Tls,
+        behind_proxy: false,
+        allow_forward: false,
+        rate_limit: None,
+        size_limits: None,
+        stats_port: None,
+        stats_addr: None,
+        stats_path: None,
+        stats_limits: None,
+        stats_stream: None,
+        interface_prefer_ipv6: false,
+        event_export: None,
+        max_write_chunk: None,
+        debug_stalls: false,
+        debug_tls_handshakes: false,
+        exception_limits: None,
+        drain_timeout_secs: None,
+        protocol_trace_path: None,
+        forward_limits: None,
+        forward_policy: None,
+        max_concurrent_handshakes: None,
+        handshake_timeout_secs: None,
+        idle_registration_expiry_secs: None,
+        relay_memory_budget_mb: None,
+        known_clients: None,
+        visitor_acl: None,
+        timeouts: Some(ServerTimeoutsConfig {
+            heartbeat_timeout_secs: 3,
+            ..Default::default()
+        }),
+        flow_export: None,
+        shutdown_grace_period_secs: None,
+        visitor_retry_timeout_secs: None,
+        tcp_keepalive_secs: None,
+        idle_timeout_secs: None,
+        max_streams_per_client: None,
+        max_connections_per_proxy: None,
+        auth_keys: None,
+        client_ca_path: None,
+        require_client_cert: false,
+    }
+}
+
+fn client_config(
+    relay_port: u16,
+    cert_path: &std::path::Path,
+    publish_port: u16,
+    local_port: u16,
+) -> ClientFullConfig {
+    ClientFullConfig {
+        client: ClientConfig {
+            server_addr: "127.0.0.1".to_string(),
+            server_port: relay_port,
+            server_path: "/".to_string(),
+            transport: TransportType::Tls,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            stats_port: None,
+            stats_addr: None,
+            stats_limits: None,
+            stats_stream: None,
+            events_socket: None,
+            peer_id: None,
+            routing_overrides_path: None,
+            routing_ui_token: None,
+            size_limits: None,
+            stats_socket: None,
+            stats_pipe: None,
+            proxy_retry: None,
+            max_write_chunk: None,
+            debug_stalls: false,
+            protocol_trace_path: None,
+            keep_listening_on_disconnect: true,
+            max_missed_heartbeats: Some(2),
+            relay_memory_budget_mb: None,
+            identity_path: None,
+            state_dir: None,
+            fallback_to_last_good: false,
+            watch_config: false,
+            client_cert_path: None,
+            client_key_path: None,
+            timeouts: Some(ClientTimeoutsConfig {
+                heartbeat_interval_secs: 1,
+                ..Default::default()
+            }),
+        },
+        proxies: vec![ProxyConfig {
+            name: "frozen".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    }
+}
+
+#[tokio::test]
+async fn test_frozen_client_is_unregistered() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let echo_port = common::get_available_port();
+    let _echo = common::start_echo_server(echo_port).await;
+    let deps = ServerDependencies::new();
+    let stats_manager: StatsManager = deps.stats_manager.clone();
+    let server =
+        common::spawn_server_with_dependencies(server_config(&cert_path, &key_path), deps).await;
+    let relay = FreezableRelay::start(server.bound_addr().port()).await;
+
+    let publish_port = common::get_available_port();
+    let config = client_config(relay.port, &cert_path, publish_port, echo_port);
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let (events, mut event_rx) = broadcast::channel(tls_tunnel::client::SESSION_EVENT_CAPACITY);
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client_with_events(config, connector, events)
+            .await
+            .ok();
+    });
+
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            common::test_proxy_connection(publish_port, b"alive", Duration::from_millis(500))
+                .await
+                .is_ok_and(|data| data == b"alive")
+        })
+        .await,
+        "Proxy never became reachable"
+    );
+
+    // 心跳间隔小于超时：正常的会话不会过期
+    sleep(Duration::from_secs(4)).await;
+    assert!(stats_manager.get_proxy_stats("frozen").is_some());
+    while event_rx.try_recv().is_ok() {}
+
+    relay.freeze();
+
+    // 服务器在心跳超时后结束会话并注销代理，发布端口不再接受连接
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            stats_manager.get_proxy_stats("frozen").is_none()
+                && TcpStream::connect(("127.0.0.1", publish_port))
+                    .await
+                    .is_err()
+        })
+        .await,
+        "Server kept the frozen client's proxy registered"
+    );
+
+    // 客户端连续的心跳得不到确认，主动断开
+    let reason = timeout(Duration::from_secs(10), async {
+        loop {
+            if let Ok(SessionEvent::Disconnected(reason)) = event_rx.recv().await {
+                break reason;
+            }
+        }
+    })
+    .await
+    .expect("Client did not notice the missing heartbeat acks");
+    assert!(reason.contains("Heartbeats not acknowledged"), "{}", reason);
+
+    client.abort();
+}
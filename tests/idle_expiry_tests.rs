@@ -0,0 +1,206 @@
+/// Idle registration expiry tests
+///
+/// 服务器配置 `idle_registration_expiry_secs` 后，长时间没有连接的公开代理被注销（发布端口停止监听），
+/// 客户端收到 `PROXY_EXPIRED_IDLE` 通知后通过 `proxy_retry` 重新注册；私有代理不会过期
+mod common;
+
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyRetryConfig, ProxyType, ProxyVisibility,
+    ServerConfig,
+};
+use tls_tunnel::stats::StatsManager;
+use tls_tunnel::stats_http::StatsEndpoint;
+use tokio::net::TcpStream;
+use tokio::time::{sleep, Instant};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-idle-expiry-key";
+
+/// 过期时间（秒）
+const EXPIRY_SECS: u64 = 2;
+
+fn proxy(
+    name: &str,
+    publish_port: u16,
+    local_port: u16,
+    visibility: ProxyVisibility,
+) -> ProxyConfig {
+    ProxyConfig {
+        name: name.to_string(),
+        proxy_type: ProxyType::Tcp,
+        publish_addr: "127.0.0.1".to_string(),
+        publish_port,
+        local_port,
+        pool: None,
+        shared: false,
+        weight: None,
+        visibility,
+        drain_timeout_secs: None,
+        sni_routing: None,
+        socket: None,
+        local_targets: None,
+        idle_keepalive_secs: None,
+        require_first_byte_timeout_ms: None,
+        max_connections_per_source: None,
+        report_peers: false,
+        ws_idle_timeout_secs: None,
+        integrity_check: false,
+    }
+}
+
+/// 读取代理在客户端统计信息中的状态
+async fn proxy_status(endpoint: &StatsEndpoint, name: &str) -> Option<String> {
+    let body = endpoint.get("/stats").await.ok()?;
+    let stats: Vec<serde_json::Value> = serde_json::from_str(&body).ok()?;
+    stats
+        .iter()
+        .find(|s| s["name"] == name)
+        .and_then(|s| s["status"].as_str().map(str::to_string))
+}
+
+/// 服务器是否登记了该代理
+fn server_has_proxy(stats: &StatsManager, name: &str) -> bool {
+    stats.get_all_stats().iter().any(|s| s.core.name == name)
+}
+
+/// 经代理完成一次回显，隧道就绪之前的连接可能直接被关闭，最多重试 `attempts` 次
+async fn echo_through(publish_port: u16, attempts: u32) -> bool {
+    for _ in 0..attempts {
+        if common::test_proxy_connection(publish_port, b"hello expiry", Duration::from_secs(2))
+            .await
+            .is_ok_and(|data| data == b"hello expiry")
+        {
+            return true;
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn test_idle_proxy_expires_and_registers_again() {
+    let publish_port = common::get_available_port();
+    let private_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let stats_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+
+    let server = common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        idle_registration_expiry_secs: Some(EXPIRY_SECS),
+        ..common::test_server_config()
+    })
+    .await;
+    let server_stats = server.stats();
+
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.clone()),
+            auth_key: AUTH_KEY.to_string(),
+            stats_port: Some(stats_port),
+            stats_addr: Some("127.0.0.1".to_string()),
+            // 重试间隔长于检查间隔，留出观察注销状态的时间
+            proxy_retry: Some(ProxyRetryConfig {
+                interval_secs: 3,
+                max_interval_secs: 3,
+            }),
+            keep_listening_on_disconnect: false,
+            ..common::test_client_config()
+        },
+        proxies: vec![
+            proxy("web", publish_port, local_port, ProxyVisibility::Public),
+            proxy("secret", private_port, local_port, ProxyVisibility::Private),
+        ],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client(client_config, connector)
+            .await
+            .ok();
+    });
+
+    // 连接记为活动，服务器统计中带最近活动时间
+    assert!(
+        echo_through(publish_port, 50).await,
+        "Proxy did not come up"
+    );
+    let web = server_stats
+        .get_all_stats()
+        .into_iter()
+        .find(|s| s.core.name == "web")
+        .expect("Server stats missing web");
+    assert!(web.last_activity_at.is_some());
+    assert!(web.core.total_connections >= 1);
+
+    // 空闲超过过期时间后代理被注销（探测连接本身也算活动，所以只看服务器统计）
+    let idle_since = Instant::now();
+    while server_has_proxy(&server_stats, "web")
+        && idle_since.elapsed() < Duration::from_secs(EXPIRY_SECS + 5)
+    {
+        sleep(Duration::from_millis(50)).await;
+    }
+    assert!(
+        !server_has_proxy(&server_stats, "web"),
+        "Proxy was not expired"
+    );
+    assert!(
+        idle_since.elapsed() >= Duration::from_secs(EXPIRY_SECS - 1),
+        "Proxy expired too early: {:?}",
+        idle_since.elapsed()
+    );
+    // 发布端口停止监听
+    assert!(TcpStream::connect(("127.0.0.1", publish_port))
+        .await
+        .is_err());
+
+    // 客户端收到通知，等待重试
+    let endpoint = StatsEndpoint::Http(format!("http://127.0.0.1:{}", stats_port));
+    let mut status = None;
+    for _ in 0..20 {
+        status = proxy_status(&endpoint, "web").await;
+        if status.as_deref().is_some_and(|s| s.contains("expired")) {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    let status = status.expect("Proxy missing from client stats");
+    assert!(
+        status.starts_with("pending (rejected: expired after "),
+        "Unexpected status: {}",
+        status
+    );
+
+    // 私有代理没有过期
+    assert!(server_has_proxy(&server_stats, "secret"));
+
+    // 重试后重新注册，连接再次可用
+    assert!(
+        echo_through(publish_port, 50).await,
+        "Proxy was not registered again"
+    );
+    assert!(server_has_proxy(&server_stats, "web"));
+    let status = proxy_status(&endpoint, "web").await.unwrap();
+    assert!(
+        !status.starts_with("pending"),
+        "Unexpected status: {}",
+        status
+    );
+    assert!(server_has_proxy(&server_stats, "secret"));
+
+    client_handle.abort();
+    server.shutdown().await.ok();
+}
@@ -0,0 +1,199 @@
+/// Idle keepalive tests
+///
+/// 配置了 `idle_keepalive_secs` 的代理，服务器与客户端之间的 stream 分帧传输，连接空闲时两端发送
+/// 保活标记。连接空闲足够长时间（两端都发送了若干标记）之后恢复传输，外部连接和本地服务收到的
+/// 数据与发送的完全一致；SNI 路由代理预读的数据同样按帧转发
+mod common;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+    SniRoutingConfig,
+};
+use tls_tunnel::server::ServerHandle;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-idle-keepalive-key";
+const IDLE_KEEPALIVE_SECS: u64 = 1;
+
+/// 启动回显服务
+async fn start_echo_server(port: u16) -> JoinHandle<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut read, mut write) = stream.split();
+                let _ = tokio::io::copy(&mut read, &mut write).await;
+            });
+        }
+    })
+}
+
+fn proxy(publish_port: u16, local_port: u16, sni_routing: Option<SniRoutingConfig>) -> ProxyConfig {
+    ProxyConfig {
+        name: "keepalive".to_string(),
+        proxy_type: ProxyType::Tcp,
+        publish_addr: "127.0.0.1".to_string(),
+        publish_port,
+        local_port,
+        pool: None,
+        shared: false,
+        weight: None,
+        visibility: ProxyVisibility::Public,
+        drain_timeout_secs: None,
+        sni_routing,
+        socket: None,
+        local_targets: None,
+        idle_keepalive_secs: Some(IDLE_KEEPALIVE_SECS),
+        require_first_byte_timeout_ms: None,
+        max_connections_per_source: None,
+        report_peers: false,
+        ws_idle_timeout_secs: None,
+        integrity_check: false,
+    }
+}
+
+/// 启动服务器和客户端
+async fn start_tunnel(
+    cert_path: &Path,
+    key_path: &Path,
+    proxy: ProxyConfig,
+) -> (ServerHandle, JoinHandle<()>) {
+    let server = common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        ..common::test_server_config()
+    })
+    .await;
+
+    let config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![proxy],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    });
+    (server, client)
+}
+
+/// 发送数据并读取等长的回显
+async fn echo(stream: &mut TcpStream, data: &[u8]) -> std::io::Result<Vec<u8>> {
+    stream.write_all(data).await?;
+    let mut received = vec![0u8; data.len()];
+    timeout(Duration::from_secs(10), stream.read_exact(&mut received))
+        .await
+        .map_err(|_| std::io::Error::other("Timed out reading echo"))??;
+    Ok(received)
+}
+
+/// 连接发布端口并完成第一次回显（隧道就绪之前的连接可能直接被关闭）
+async fn connect_ready(publish_port: u16, first: &[u8]) -> TcpStream {
+    for _ in 0..50 {
+        if let Ok(mut stream) = TcpStream::connect(("127.0.0.1", publish_port)).await {
+            if echo(&mut stream, first)
+                .await
+                .is_ok_and(|data| data == first)
+            {
+                return stream;
+            }
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Proxy 'keepalive' never became reachable");
+}
+
+fn pattern(len: usize, seed: u8) -> Vec<u8> {
+    (0..len)
+        .map(|i| (i % 251) as u8 ^ seed)
+        .collect::<Vec<u8>>()
+}
+
+/// 空闲期间两端都发送保活标记，恢复传输后数据完整
+async fn assert_idle_then_resume(mut stream: TcpStream) {
+    // 跨越多个保活间隔
+    sleep(Duration::from_millis(IDLE_KEEPALIVE_SECS * 3500)).await;
+
+    let resumed = pattern(512 * 1024, 0x5a);
+    assert_eq!(echo(&mut stream, &resumed).await.unwrap(), resumed);
+
+    // 再次空闲后发送包含 0 的小数据块，确认不会被当作保活标记
+    sleep(Duration::from_millis(IDLE_KEEPALIVE_SECS * 2500)).await;
+    for round in 0..20u8 {
+        let chunk = [0u8, 0, round, 0];
+        assert_eq!(echo(&mut stream, &chunk).await.unwrap(), chunk);
+    }
+
+    // 正常关闭：回显服务读到 EOF 后关闭连接
+    stream.shutdown().await.unwrap();
+    let mut rest = Vec::new();
+    timeout(Duration::from_secs(10), stream.read_to_end(&mut rest))
+        .await
+        .expect("Connection was not closed")
+        .unwrap();
+    assert!(rest.is_empty());
+}
+
+#[tokio::test]
+async fn test_idle_keepalive_preserves_payload() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _backend = start_echo_server(local_port).await;
+    let (server, client) =
+        start_tunnel(&cert_path, &key_path, proxy(publish_port, local_port, None)).await;
+
+    let stream = connect_ready(publish_port, &pattern(64 * 1024, 0x11)).await;
+    assert_idle_then_resume(stream).await;
+
+    client.abort();
+    server.shutdown().await.ok();
+}
+
+#[tokio::test]
+async fn test_idle_keepalive_with_sni_routing() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _backend = start_echo_server(local_port).await;
+    // 非 TLS 数据按没有 SNI 处理，转发到 local_port；预读的数据在分帧 stream 上转发
+    let sni_routing = SniRoutingConfig {
+        sni_map: BTreeMap::from([("default".to_string(), local_port)]),
+    };
+    let (server, client) = start_tunnel(
+        &cert_path,
+        &key_path,
+        proxy(publish_port, local_port, Some(sni_routing)),
+    )
+    .await;
+
+    let stream = connect_ready(publish_port, b"plain text before idle").await;
+    assert_idle_then_resume(stream).await;
+
+    client.abort();
+    server.shutdown().await.ok();
+}
@@ -0,0 +1,158 @@
+/// Idle timeout tests
+///
+/// 服务器按 `idle_timeout_secs` 关闭两个方向都没有数据的发布端口连接（如端口扫描遗留的连接），
+/// 关闭后代理统计中的活跃连接数随之减少；持续有数据的连接不受影响
+mod common;
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+};
+use tls_tunnel::server::ServerHandle;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-idle-timeout-key";
+const PROXY_NAME: &str = "idle";
+
+/// 启动空闲超时为 1 秒的服务器和注册代理的客户端，等待代理注册完成
+async fn start_tunnel(
+    cert_path: &Path,
+    key_path: &Path,
+    publish_port: u16,
+    local_port: u16,
+) -> (ServerHandle, JoinHandle<()>) {
+    let server = common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        idle_timeout_secs: Some(1),
+        ..common::test_server_config()
+    })
+    .await;
+
+    let config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: PROXY_NAME.to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    });
+
+    let stats = server.stats();
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            stats.get_proxy_stats(PROXY_NAME).is_some()
+        })
+        .await,
+        "Proxy was not registered"
+    );
+    (server, client)
+}
+
+#[tokio::test]
+async fn test_silent_connection_closed_after_idle_timeout() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+    let (server, client) = start_tunnel(&cert_path, &key_path, publish_port, local_port).await;
+    let stats = server.stats();
+
+    // 连接后不发送任何数据
+    let started = Instant::now();
+    let mut stream = TcpStream::connect(("127.0.0.1", publish_port))
+        .await
+        .expect("Failed to connect to publish port");
+    let mut buf = [0u8; 16];
+    let n = timeout(Duration::from_secs(5), stream.read(&mut buf))
+        .await
+        .expect("Idle connection was not closed")
+        .unwrap_or(0);
+    assert_eq!(n, 0);
+    assert!(
+        started.elapsed() >= Duration::from_millis(900),
+        "Closed before the idle timeout elapsed: {:?}",
+        started.elapsed()
+    );
+
+    // 关闭的连接不再计入活跃连接
+    assert!(
+        common::wait_until(Duration::from_secs(5), || async {
+            stats
+                .get_proxy_stats(PROXY_NAME)
+                .is_some_and(|s| s.core.total_connections == 1 && s.core.active_connections == 0)
+        })
+        .await,
+        "Idle connection still counted as active"
+    );
+
+    client.abort();
+}
+
+#[tokio::test]
+async fn test_active_connection_outlives_idle_timeout() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+    let (_server, client) = start_tunnel(&cert_path, &key_path, publish_port, local_port).await;
+
+    let mut stream = TcpStream::connect(("127.0.0.1", publish_port))
+        .await
+        .expect("Failed to connect to publish port");
+    // 每次间隔都短于空闲超时，总时长超过空闲超时
+    for round in 0..6u8 {
+        let data = [round; 8];
+        stream.write_all(&data).await.unwrap();
+        let mut echoed = [0u8; 8];
+        timeout(Duration::from_secs(5), stream.read_exact(&mut echoed))
+            .await
+            .expect("Timed out waiting for echo")
+            .expect("Active connection was closed");
+        assert_eq!(echoed, data);
+        sleep(Duration::from_millis(500)).await;
+    }
+
+    client.abort();
+}
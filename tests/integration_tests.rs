@@ -2,7 +2,10 @@
 mod common;
 
 use std::time::Duration;
-use tls_tunnel::config::{ClientConfig, ClientFullConfig, ProxyConfig, ServerConfig};
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyVisibility, ServerConfig,
+};
+use tls_tunnel::server::Server;
 use tls_tunnel::transport::TransportType;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
@@ -13,23 +16,17 @@ use tokio_rustls::{TlsAcceptor, TlsConnector};
 fn create_server_config(
     port: u16,
     auth_key: &str,
-    cert: &std::path::PathBuf,
-    key: &std::path::PathBuf,
+    cert: &std::path::Path,
+    key: &std::path::Path,
     transport: TransportType,
 ) -> ServerConfig {
     ServerConfig {
-        bind_addr: "127.0.0.1".to_string(),
         bind_port: port,
         auth_key: auth_key.to_string(),
-        cert_path: Some(cert.clone()),
-        key_path: Some(key.clone()),
+        cert_path: Some(cert.to_path_buf()),
+        key_path: Some(key.to_path_buf()),
         transport,
-        behind_proxy: false,
-        allow_forward: false,
-        rate_limit: None,
-        size_limits: None,
-        stats_port: None,
-        stats_addr: None,
+        ..common::test_server_config()
     }
 }
 
@@ -39,20 +36,17 @@ fn create_client_config(
     proxy_port: u16,
     echo_port: u16,
     auth_key: &str,
-    cert: &std::path::PathBuf,
+    cert: &std::path::Path,
     transport: TransportType,
 ) -> ClientFullConfig {
     ClientFullConfig {
         client: ClientConfig {
-            server_addr: "127.0.0.1".to_string(),
             server_port,
-            server_path: "/".to_string(),
             auth_key: auth_key.to_string(),
-            ca_cert_path: Some(cert.clone()),
+            ca_cert_path: Some(cert.to_path_buf()),
             skip_verify: true,
             transport,
-            stats_port: None,
-            stats_addr: None,
+            ..common::test_client_config()
         },
         proxies: vec![ProxyConfig {
             name: "test-proxy".to_string(),
@@ -60,15 +54,30 @@ fn create_client_config(
             publish_port: proxy_port,
             local_port: echo_port,
             proxy_type: tls_tunnel::config::ProxyType::Tcp,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
         }],
         visitors: vec![],
         forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
     }
 }
 
 #[tokio::test]
 async fn test_basic_tcp_proxy() {
-    let server_port = common::get_available_port();
     let proxy_port = common::get_available_port();
     let echo_port = common::get_available_port();
     let auth_key = "test-basic";
@@ -77,27 +86,17 @@ async fn test_basic_tcp_proxy() {
     let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
 
     let _echo_server = common::start_echo_server(echo_port).await;
-    sleep(Duration::from_millis(100)).await;
 
     // Start server
-    let server_config = create_server_config(
-        server_port,
+    let server = common::spawn_server(create_server_config(
+        0,
         auth_key,
         &cert_path,
         &key_path,
         TransportType::Tls,
-    );
-    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None)
-        .expect("Failed to load server TLS config");
-    let acceptor = TlsAcceptor::from(tls_config);
-
-    let server_handle = tokio::spawn(async move {
-        tls_tunnel::server::run_server(server_config, acceptor)
-            .await
-            .ok();
-    });
-
-    sleep(Duration::from_millis(300)).await;
+    ))
+    .await;
+    let server_port = server.bound_addr().port();
 
     // Start client
     let client_config = create_client_config(
@@ -118,7 +117,10 @@ async fn test_basic_tcp_proxy() {
             .ok();
     });
 
-    sleep(Duration::from_millis(500)).await;
+    assert!(
+        common::wait_for_echo(proxy_port, 50).await,
+        "Proxy never became reachable"
+    );
 
     // Test
     let test_data = b"Hello, World!";
@@ -128,41 +130,90 @@ async fn test_basic_tcp_proxy() {
 
     assert_eq!(response, test_data, "Response should match sent data");
 
-    server_handle.abort();
+    // Graceful shutdown closes the listener before returning
+    server.shutdown().await.expect("Server shutdown failed");
+    assert!(TcpStream::connect(("127.0.0.1", server_port))
+        .await
+        .is_err());
+
     client_handle.abort();
 }
 
 #[tokio::test]
-async fn test_auth_correct_key() {
-    let server_port = common::get_available_port();
+async fn test_max_write_chunk_large_transfer() {
     let proxy_port = common::get_available_port();
     let echo_port = common::get_available_port();
-    let auth_key = "correct-key-123";
+    let auth_key = "test-max-write-chunk";
 
     let (cert_path, key_path) = common::generate_test_certs();
     let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
 
     let _echo_server = common::start_echo_server(echo_port).await;
-    sleep(Duration::from_millis(100)).await;
 
-    let server_config = create_server_config(
-        server_port,
+    // Small TLS records and stall diagnostics on both sides
+    let mut server_config =
+        create_server_config(0, auth_key, &cert_path, &key_path, TransportType::Tls);
+    server_config.max_write_chunk = Some(1200);
+    server_config.debug_stalls = true;
+    let server = common::spawn_server(server_config).await;
+
+    let mut client_config = create_client_config(
+        server.bound_addr().port(),
+        proxy_port,
+        echo_port,
         auth_key,
         &cert_path,
-        &key_path,
         TransportType::Tls,
     );
-    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None)
-        .expect("Failed to load server TLS config");
-    let acceptor = TlsAcceptor::from(tls_config);
+    client_config.client.max_write_chunk = Some(1200);
+    client_config.client.debug_stalls = true;
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
 
-    let server_handle = tokio::spawn(async move {
-        tls_tunnel::server::run_server(server_config, acceptor)
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client(client_config, connector)
             .await
             .ok();
     });
 
-    sleep(Duration::from_millis(300)).await;
+    assert!(
+        common::wait_for_echo(proxy_port, 50).await,
+        "Proxy never became reachable"
+    );
+
+    let test_data: Vec<u8> = (0..256 * 1024u32).map(|i| (i % 251) as u8).collect();
+    let response = common::test_proxy_connection(proxy_port, &test_data, Duration::from_secs(10))
+        .await
+        .expect("Failed to test proxy connection");
+    assert_eq!(
+        response, test_data,
+        "Large payload should survive chunked writes"
+    );
+
+    client_handle.abort();
+}
+
+#[tokio::test]
+async fn test_auth_correct_key() {
+    let proxy_port = common::get_available_port();
+    let echo_port = common::get_available_port();
+    let auth_key = "correct-key-123";
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let _echo_server = common::start_echo_server(echo_port).await;
+
+    let server = common::spawn_server(create_server_config(
+        0,
+        auth_key,
+        &cert_path,
+        &key_path,
+        TransportType::Tls,
+    ))
+    .await;
+    let server_port = server.bound_addr().port();
 
     let client_config = create_client_config(
         server_port,
@@ -182,7 +233,10 @@ async fn test_auth_correct_key() {
             .ok();
     });
 
-    sleep(Duration::from_millis(800)).await;
+    assert!(
+        common::wait_for_echo(proxy_port, 50).await,
+        "Proxy never became reachable"
+    );
 
     let test_data = b"Auth test";
     let result = common::test_proxy_connection(proxy_port, test_data, Duration::from_secs(3)).await;
@@ -190,13 +244,11 @@ async fn test_auth_correct_key() {
     assert!(result.is_ok(), "Should connect with correct auth key");
     assert_eq!(result.unwrap(), test_data);
 
-    server_handle.abort();
     client_handle.abort();
 }
 
 #[tokio::test]
 async fn test_auth_wrong_key() {
-    let server_port = common::get_available_port();
     let proxy_port = common::get_available_port();
     let echo_port = common::get_available_port();
 
@@ -204,27 +256,12 @@ async fn test_auth_wrong_key() {
     let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
 
     let _echo_server = common::start_echo_server(echo_port).await;
-    sleep(Duration::from_millis(100)).await;
 
     // Server with one key
-    let server_config = create_server_config(
-        server_port,
-        "correct-key",
-        &cert_path,
-        &key_path,
-        TransportType::Tls,
-    );
-    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None)
-        .expect("Failed to load server TLS config");
-    let acceptor = TlsAcceptor::from(tls_config);
-
-    let server_handle = tokio::spawn(async move {
-        tls_tunnel::server::run_server(server_config, acceptor)
-            .await
-            .ok();
-    });
-
-    sleep(Duration::from_millis(300)).await;
+    let server_config =
+        create_server_config(0, "correct-key", &cert_path, &key_path, TransportType::Tls);
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
 
     // Client with wrong key
     let client_config = create_client_config(
@@ -250,12 +287,11 @@ async fn test_auth_wrong_key() {
 
     assert!(result.is_err(), "Should fail with wrong auth key");
 
-    server_handle.abort();
+    server.shutdown().await.ok();
 }
 
 #[tokio::test]
 async fn test_multiple_connections() {
-    let server_port = common::get_available_port();
     let proxy_port = common::get_available_port();
     let echo_port = common::get_available_port();
     let auth_key = "test-multi";
@@ -264,26 +300,11 @@ async fn test_multiple_connections() {
     let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
 
     let _echo_server = common::start_echo_server(echo_port).await;
-    sleep(Duration::from_millis(100)).await;
 
-    let server_config = create_server_config(
-        server_port,
-        auth_key,
-        &cert_path,
-        &key_path,
-        TransportType::Tls,
-    );
-    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None)
-        .expect("Failed to load server TLS config");
-    let acceptor = TlsAcceptor::from(tls_config);
-
-    let server_handle = tokio::spawn(async move {
-        tls_tunnel::server::run_server(server_config, acceptor)
-            .await
-            .ok();
-    });
-
-    sleep(Duration::from_millis(300)).await;
+    let server_config =
+        create_server_config(0, auth_key, &cert_path, &key_path, TransportType::Tls);
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
 
     let client_config = create_client_config(
         server_port,
@@ -303,7 +324,10 @@ async fn test_multiple_connections() {
             .ok();
     });
 
-    sleep(Duration::from_millis(500)).await;
+    assert!(
+        common::wait_for_echo(proxy_port, 50).await,
+        "Proxy never became reachable"
+    );
 
     // Test 10 concurrent connections
     let mut handles = vec![];
@@ -332,13 +356,12 @@ async fn test_multiple_connections() {
         success_count
     );
 
-    server_handle.abort();
+    server.shutdown().await.ok();
     client_handle.abort();
 }
 
 #[tokio::test]
 async fn test_large_data_transfer() {
-    let server_port = common::get_available_port();
     let proxy_port = common::get_available_port();
     let echo_port = common::get_available_port();
     let auth_key = "test-large";
@@ -347,26 +370,11 @@ async fn test_large_data_transfer() {
     let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
 
     let _echo_server = common::start_echo_server(echo_port).await;
-    sleep(Duration::from_millis(100)).await;
 
-    let server_config = create_server_config(
-        server_port,
-        auth_key,
-        &cert_path,
-        &key_path,
-        TransportType::Tls,
-    );
-    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None)
-        .expect("Failed to load server TLS config");
-    let acceptor = TlsAcceptor::from(tls_config);
-
-    let server_handle = tokio::spawn(async move {
-        tls_tunnel::server::run_server(server_config, acceptor)
-            .await
-            .ok();
-    });
-
-    sleep(Duration::from_millis(300)).await;
+    let server_config =
+        create_server_config(0, auth_key, &cert_path, &key_path, TransportType::Tls);
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
 
     let client_config = create_client_config(
         server_port,
@@ -386,7 +394,10 @@ async fn test_large_data_transfer() {
             .ok();
     });
 
-    sleep(Duration::from_millis(500)).await;
+    assert!(
+        common::wait_for_echo(proxy_port, 50).await,
+        "Proxy never became reachable"
+    );
 
     // Transfer 512KB
     let test_data: Vec<u8> = (0..512 * 1024).map(|i| (i % 256) as u8).collect();
@@ -397,13 +408,12 @@ async fn test_large_data_transfer() {
     assert_eq!(response.len(), test_data.len());
     assert_eq!(response, test_data);
 
-    server_handle.abort();
+    server.shutdown().await.ok();
     client_handle.abort();
 }
 
 #[tokio::test]
 async fn test_wss_transport() {
-    let server_port = common::get_available_port();
     let proxy_port = common::get_available_port();
     let echo_port = common::get_available_port();
     let auth_key = "test-wss";
@@ -412,26 +422,11 @@ async fn test_wss_transport() {
     let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
 
     let _echo_server = common::start_echo_server(echo_port).await;
-    sleep(Duration::from_millis(100)).await;
-
-    let server_config = create_server_config(
-        server_port,
-        auth_key,
-        &cert_path,
-        &key_path,
-        TransportType::Wss,
-    );
-    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None)
-        .expect("Failed to load server TLS config");
-    let acceptor = TlsAcceptor::from(tls_config);
 
-    let server_handle = tokio::spawn(async move {
-        tls_tunnel::server::run_server(server_config, acceptor)
-            .await
-            .ok();
-    });
-
-    sleep(Duration::from_millis(300)).await;
+    let server_config =
+        create_server_config(0, auth_key, &cert_path, &key_path, TransportType::Wss);
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
 
     let client_config = create_client_config(
         server_port,
@@ -451,7 +446,10 @@ async fn test_wss_transport() {
             .ok();
     });
 
-    sleep(Duration::from_millis(500)).await;
+    assert!(
+        common::wait_for_echo(proxy_port, 50).await,
+        "Proxy never became reachable"
+    );
 
     let test_data = b"WebSocket test";
     let response = common::test_proxy_connection(proxy_port, test_data, Duration::from_secs(5))
@@ -460,13 +458,12 @@ async fn test_wss_transport() {
 
     assert_eq!(response, test_data);
 
-    server_handle.abort();
+    server.shutdown().await.ok();
     client_handle.abort();
 }
 
 #[tokio::test]
 async fn test_http2_transport() {
-    let server_port = common::get_available_port();
     let proxy_port = common::get_available_port();
     let echo_port = common::get_available_port();
     let auth_key = "test-http2";
@@ -475,28 +472,20 @@ async fn test_http2_transport() {
     let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
 
     let _echo_server = common::start_echo_server(echo_port).await;
-    sleep(Duration::from_millis(100)).await;
 
-    let server_config = create_server_config(
-        server_port,
-        auth_key,
-        &cert_path,
-        &key_path,
-        TransportType::Http2,
-    );
+    let server_config =
+        create_server_config(0, auth_key, &cert_path, &key_path, TransportType::Http2);
     let alpn_protocols = Some(vec![b"h2".to_vec()]);
     let tls_config =
         tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, alpn_protocols)
             .expect("Failed to load server TLS config");
-    let acceptor = TlsAcceptor::from(tls_config);
-
-    let server_handle = tokio::spawn(async move {
-        tls_tunnel::server::run_server(server_config, acceptor)
-            .await
-            .ok();
-    });
-
-    sleep(Duration::from_millis(300)).await;
+    let server = Server::builder()
+        .config(server_config)
+        .acceptor(TlsAcceptor::from(tls_config))
+        .spawn()
+        .await
+        .expect("Failed to start server");
+    let server_port = server.bound_addr().port();
 
     let client_config = create_client_config(
         server_port,
@@ -518,7 +507,10 @@ async fn test_http2_transport() {
             .ok();
     });
 
-    sleep(Duration::from_millis(500)).await;
+    assert!(
+        common::wait_for_echo(proxy_port, 50).await,
+        "Proxy never became reachable"
+    );
 
     let test_data = b"HTTP/2 test";
     let response = common::test_proxy_connection(proxy_port, test_data, Duration::from_secs(5))
@@ -527,7 +519,7 @@ async fn test_http2_transport() {
 
     assert_eq!(response, test_data);
 
-    server_handle.abort();
+    server.shutdown().await.ok();
     client_handle.abort();
 }
 
@@ -542,7 +534,6 @@ async fn test_visitor_mode() {
 
     use tls_tunnel::config::{ProxyType, VisitorConfig};
 
-    let server_port = common::get_available_port();
     let publish_port = common::get_available_port(); // 服务器发布的端口（不使用）
     let visitor_port = common::get_available_port(); // visitor 客户端本地监听端口
     let echo_port = common::get_available_port(); // Echo 服务器端口
@@ -553,47 +544,25 @@ async fn test_visitor_mode() {
 
     // 启动 Echo 服务器（模拟客户端B的本地服务）
     let _echo_server = common::start_echo_server(echo_port).await;
-    sleep(Duration::from_millis(100)).await;
 
     // 配置服务器 - 作为中转服务器
     let server_config = ServerConfig {
-        bind_addr: "127.0.0.1".to_string(),
-        bind_port: server_port,
         auth_key: auth_key.to_string(),
         cert_path: Some(cert_path.clone()),
         key_path: Some(key_path.clone()),
-        transport: TransportType::Tls,
-        behind_proxy: false,
-        allow_forward: false,
-        rate_limit: None,
-        size_limits: None,
-        stats_port: None,
-        stats_addr: None,
+        ..common::test_server_config()
     };
-    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None)
-        .expect("Failed to load server TLS config");
-    let acceptor = TlsAcceptor::from(tls_config);
-
-    let server_handle = tokio::spawn(async move {
-        tls_tunnel::server::run_server(server_config, acceptor)
-            .await
-            .ok();
-    });
-
-    sleep(Duration::from_millis(300)).await;
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
 
     // 客户端B（proxy端）- 发布服务到服务器
     let client_b_config = ClientFullConfig {
         client: ClientConfig {
-            server_addr: "127.0.0.1".to_string(),
             server_port,
-            server_path: "/".to_string(),
             auth_key: auth_key.to_string(),
             ca_cert_path: Some(cert_path.clone()),
             skip_verify: true,
-            transport: TransportType::Tls,
-            stats_port: None,
-            stats_addr: None,
+            ..common::test_client_config()
         },
         proxies: vec![ProxyConfig {
             name: "visitor-test".to_string(),
@@ -601,9 +570,25 @@ async fn test_visitor_mode() {
             publish_port,          // 让服务器注册这个 proxy
             local_port: echo_port, // 指向本地 echo 服务器
             proxy_type: ProxyType::Tcp,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
         }],
         visitors: vec![],
         forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
     };
     let tls_config_b = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
         .expect("Failed to load client TLS config");
@@ -615,20 +600,19 @@ async fn test_visitor_mode() {
             .ok();
     });
 
-    sleep(Duration::from_millis(500)).await;
+    assert!(
+        common::wait_for_echo(publish_port, 50).await,
+        "Proxy never became reachable"
+    );
 
     // 客户端C（visitor端）- 访问客户端B的服务
     let client_c_config = ClientFullConfig {
         client: ClientConfig {
-            server_addr: "127.0.0.1".to_string(),
             server_port,
-            server_path: "/".to_string(),
             auth_key: auth_key.to_string(),
             ca_cert_path: Some(cert_path.clone()),
             skip_verify: true,
-            transport: TransportType::Tls,
-            stats_port: None,
-            stats_addr: None,
+            ..common::test_client_config()
         },
         proxies: vec![],
         visitors: vec![VisitorConfig {
@@ -637,8 +621,13 @@ async fn test_visitor_mode() {
             bind_addr: "127.0.0.1".to_string(),
             bind_port: visitor_port, // 客户端C本地监听
             publish_port,            // 匹配客户端B的 proxy
+            expected_peer_id: None,
+            connection_reuse: false,
+            socket: None,
         }],
         forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
     };
     let tls_config_c = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
         .expect("Failed to load client TLS config");
@@ -650,7 +639,10 @@ async fn test_visitor_mode() {
             .ok();
     });
 
-    sleep(Duration::from_millis(500)).await;
+    assert!(
+        common::wait_for_echo(visitor_port, 50).await,
+        "Visitor never became reachable"
+    );
 
     // 测试：连接到客户端C的 visitor 端口，数据应该通过服务器中转到达客户端B的 echo 服务器
     println!("Testing visitor connection on port {}", visitor_port);
@@ -661,7 +653,7 @@ async fn test_visitor_mode() {
 
     assert_eq!(response, test_data);
 
-    server_handle.abort();
+    server.shutdown().await.ok();
     client_b_handle.abort();
     client_c_handle.abort();
 }
@@ -671,7 +663,6 @@ async fn test_visitor_mode() {
 async fn test_forwarder_http_proxy() {
     use tls_tunnel::config::{ForwarderConfig, ProxyType, RoutingConfig};
 
-    let server_port = common::get_available_port();
     let forwarder_port = common::get_available_port(); // HTTP 代理端口
     let target_port = common::get_available_port(); // 目标 HTTP 服务器端口
     let auth_key = "test-forwarder-http";
@@ -680,11 +671,10 @@ async fn test_forwarder_http_proxy() {
     let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
 
     // 启动简单的 HTTP 服务器
+    let listener = tokio::net::TcpListener::bind(format!("127.0.0.1:{}", target_port))
+        .await
+        .expect("Failed to bind HTTP server");
     let http_server = tokio::spawn(async move {
-        let listener = tokio::net::TcpListener::bind(format!("127.0.0.1:{}", target_port))
-            .await
-            .expect("Failed to bind HTTP server");
-
         loop {
             if let Ok((socket, _)) = listener.accept().await {
                 tokio::spawn(async move {
@@ -717,48 +707,26 @@ async fn test_forwarder_http_proxy() {
         }
     });
 
-    sleep(Duration::from_millis(100)).await;
-
     // 配置服务器 - 允许 forwarder
     let server_config = ServerConfig {
-        bind_addr: "127.0.0.1".to_string(),
-        bind_port: server_port,
         auth_key: auth_key.to_string(),
         cert_path: Some(cert_path.clone()),
         key_path: Some(key_path.clone()),
-        transport: TransportType::Tls,
-        behind_proxy: false,
         allow_forward: true, // 允许 forwarder
-        rate_limit: None,
-        size_limits: None,
-        stats_port: None,
-        stats_addr: None,
+        ..common::test_server_config()
     };
-    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None)
-        .expect("Failed to load server TLS config");
-    let acceptor = TlsAcceptor::from(tls_config);
-
-    let server_handle = tokio::spawn(async move {
-        tls_tunnel::server::run_server(server_config, acceptor)
-            .await
-            .ok();
-    });
-
-    sleep(Duration::from_millis(300)).await;
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
 
     // 配置客户端 - forwarder 模式（HTTP 代理）
     // 使用路由配置使 127.0.0.1 直连（避免被服务器安全检查拒绝）
     let client_config = ClientFullConfig {
         client: ClientConfig {
-            server_addr: "127.0.0.1".to_string(),
             server_port,
-            server_path: "/".to_string(),
             auth_key: auth_key.to_string(),
             ca_cert_path: Some(cert_path.clone()),
             skip_verify: true,
-            transport: TransportType::Tls,
-            stats_port: None,
-            stats_addr: None,
+            ..common::test_client_config()
         },
         proxies: vec![],
         visitors: vec![],
@@ -777,8 +745,19 @@ async fn test_forwarder_http_proxy() {
                 direct_domains: vec![],
                 proxy_domains: vec![],
                 default_strategy: tls_tunnel::config::RoutingStrategy::Direct, // 默认直连
+                schedules: vec![],
+                utc_offset: None,
+                quota: None,
             }),
+            fast_fail: None,
+            direct_egress: None,
+            socket: None,
+            report_direct: false,
+            idle_timeout_secs: None,
+            header_rules: None,
         }],
+        visitor_gateway: None,
+        health: None,
     };
     let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
         .expect("Failed to load client TLS config");
@@ -790,7 +769,10 @@ async fn test_forwarder_http_proxy() {
             .ok();
     });
 
-    sleep(Duration::from_millis(500)).await;
+    assert!(
+        common::wait_for_server(forwarder_port, 50).await,
+        "Forwarder did not start listening"
+    );
 
     // 测试 HTTP 代理（使用绝对 URL 模式）
     // 注意：不能测试 CONNECT 隧道模式连接本地地址，因为客户端和服务器都有安全检查阻止访问本地/私有地址
@@ -834,7 +816,7 @@ async fn test_forwarder_http_proxy() {
     );
 
     http_server.abort();
-    server_handle.abort();
+    server.shutdown().await.ok();
     client_handle.abort();
 }
 
@@ -843,7 +825,6 @@ async fn test_forwarder_http_proxy() {
 async fn test_forwarder_socks5_proxy() {
     use tls_tunnel::config::{ForwarderConfig, ProxyType, RoutingConfig};
 
-    let server_port = common::get_available_port();
     let forwarder_port = common::get_available_port(); // SOCKS5 代理端口
     let echo_port = common::get_available_port(); // Echo 服务器端口
     let auth_key = "test-forwarder-socks5";
@@ -853,48 +834,27 @@ async fn test_forwarder_socks5_proxy() {
 
     // 启动 Echo 服务器
     let _echo_server = common::start_echo_server(echo_port).await;
-    sleep(Duration::from_millis(100)).await;
 
     // 配置服务器 - 允许 forwarder
     let server_config = ServerConfig {
-        bind_addr: "127.0.0.1".to_string(),
-        bind_port: server_port,
         auth_key: auth_key.to_string(),
         cert_path: Some(cert_path.clone()),
         key_path: Some(key_path.clone()),
-        transport: TransportType::Tls,
-        behind_proxy: false,
         allow_forward: true, // 允许 forwarder
-        rate_limit: None,
-        size_limits: None,
-        stats_port: None,
-        stats_addr: None,
+        ..common::test_server_config()
     };
-    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None)
-        .expect("Failed to load server TLS config");
-    let acceptor = TlsAcceptor::from(tls_config);
-
-    let server_handle = tokio::spawn(async move {
-        tls_tunnel::server::run_server(server_config, acceptor)
-            .await
-            .ok();
-    });
-
-    sleep(Duration::from_millis(300)).await;
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
 
     // 配置客户端 - forwarder 模式（SOCKS5 代理）
     // 使用路由配置使 127.0.0.1 直连（避免被服务器安全检查拒绝）
     let client_config = ClientFullConfig {
         client: ClientConfig {
-            server_addr: "127.0.0.1".to_string(),
             server_port,
-            server_path: "/".to_string(),
             auth_key: auth_key.to_string(),
             ca_cert_path: Some(cert_path.clone()),
             skip_verify: true,
-            transport: TransportType::Tls,
-            stats_port: None,
-            stats_addr: None,
+            ..common::test_client_config()
         },
         proxies: vec![],
         visitors: vec![],
@@ -913,8 +873,19 @@ async fn test_forwarder_socks5_proxy() {
                 direct_domains: vec![],
                 proxy_domains: vec![],
                 default_strategy: tls_tunnel::config::RoutingStrategy::Direct, // 默认直连
+                schedules: vec![],
+                utc_offset: None,
+                quota: None,
             }),
+            fast_fail: None,
+            direct_egress: None,
+            socket: None,
+            report_direct: false,
+            idle_timeout_secs: None,
+            header_rules: None,
         }],
+        visitor_gateway: None,
+        health: None,
     };
     let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
         .expect("Failed to load client TLS config");
@@ -926,7 +897,10 @@ async fn test_forwarder_socks5_proxy() {
             .ok();
     });
 
-    sleep(Duration::from_millis(500)).await;
+    assert!(
+        common::wait_for_server(forwarder_port, 50).await,
+        "Forwarder did not start listening"
+    );
 
     // 测试 SOCKS5 连接
     let mut stream = TcpStream::connect(format!("127.0.0.1:{}", forwarder_port))
@@ -970,9 +944,6 @@ async fn test_forwarder_socks5_proxy() {
         buf[1]
     );
 
-    // 等待连接完全建立
-    sleep(Duration::from_millis(100)).await;
-
     // 通过 SOCKS5 代理发送数据到 echo 服务器
     let test_data = b"SOCKS5 forwarder test";
     stream
@@ -995,6 +966,135 @@ async fn test_forwarder_socks5_proxy() {
         }
     }
 
-    server_handle.abort();
+    server.shutdown().await.ok();
+    client_handle.abort();
+}
+
+#[tokio::test]
+async fn test_forwarder_socks5_ipv6_target() {
+    use tls_tunnel::config::{ForwarderConfig, ProxyType, RoutingConfig};
+
+    // 启动 IPv6 回环 Echo 服务器（环境不支持 IPv6 时跳过）
+    let echo_listener = match tokio::net::TcpListener::bind("[::1]:0").await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!(
+                "Skipping IPv6 forwarder test, IPv6 loopback unavailable: {}",
+                e
+            );
+            return;
+        }
+    };
+    let echo_port = echo_listener.local_addr().unwrap().port();
+    let echo_server = tokio::spawn(async move {
+        while let Ok((mut socket, _)) = echo_listener.accept().await {
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = socket.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+            });
+        }
+    });
+
+    let forwarder_port = common::get_available_port();
+    let auth_key = "test-forwarder-socks5-ipv6";
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server_config = ServerConfig {
+        auth_key: auth_key.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        allow_forward: true,
+        ..common::test_server_config()
+    };
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
+
+    // 路由规则：IPv6 回环地址直连
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            auth_key: auth_key.to_string(),
+            ca_cert_path: Some(cert_path.clone()),
+            skip_verify: true,
+            ..common::test_client_config()
+        },
+        proxies: vec![],
+        visitors: vec![],
+        forwarders: vec![ForwarderConfig {
+            name: "socks5-ipv6".to_string(),
+            proxy_type: ProxyType::Socks5Proxy,
+            bind_addr: "127.0.0.1".to_string(),
+            bind_port: forwarder_port,
+            routing: Some(RoutingConfig {
+                geoip_db: None,
+                direct_countries: vec![],
+                proxy_countries: vec![],
+                direct_ips: vec!["::1/128".to_string()],
+                proxy_ips: vec![],
+                direct_domains: vec![],
+                proxy_domains: vec![],
+                default_strategy: tls_tunnel::config::RoutingStrategy::Proxy,
+                schedules: vec![],
+                utc_offset: None,
+                quota: None,
+            }),
+            fast_fail: None,
+            direct_egress: None,
+            socket: None,
+            report_direct: false,
+            idle_timeout_secs: None,
+            header_rules: None,
+        }],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client(client_config, connector)
+            .await
+            .ok();
+    });
+
+    assert!(
+        common::wait_for_server(forwarder_port, 50).await,
+        "Forwarder did not start listening"
+    );
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", forwarder_port))
+        .await
+        .expect("Failed to connect to SOCKS5 proxy");
+
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut buf = [0u8; 2];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf, [0x05, 0x00], "SOCKS5 greeting should succeed");
+
+    // SOCKS5 CONNECT 请求 - IPv6 (ATYP = 0x04)
+    let mut connect_request = vec![0x05, 0x01, 0x00, 0x04];
+    connect_request.extend_from_slice(&std::net::Ipv6Addr::LOCALHOST.octets());
+    connect_request.extend_from_slice(&echo_port.to_be_bytes());
+    stream.write_all(&connect_request).await.unwrap();
+
+    let mut buf = [0u8; 10];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf[1], 0x00, "SOCKS5 CONNECT should succeed");
+
+    let test_data = b"SOCKS5 IPv6 forwarder test";
+    stream.write_all(test_data).await.unwrap();
+
+    let mut response = vec![0u8; test_data.len()];
+    tokio::time::timeout(Duration::from_secs(2), stream.read_exact(&mut response))
+        .await
+        .expect("Timeout reading echo response")
+        .expect("Failed to read echo response");
+    assert_eq!(response, test_data, "Should receive echoed data over IPv6");
+
+    server.shutdown().await.ok();
     client_handle.abort();
+    echo_server.abort();
 }
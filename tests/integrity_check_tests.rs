@@ -0,0 +1,167 @@
+/// Integrity check tests
+///
+/// 开启 `integrity_check` 的代理在两端累计每个方向的 CRC32C，连接结束时交换尾帧比较：
+/// 正常转发的数据两个方向都校验一致（服务器和客户端统计的 `integrity_verified` 增加，
+/// `integrity_mismatches` 为 0），外部连接和本地服务收到的数据不受影响
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+};
+use tls_tunnel::stats_http::StatsEndpoint;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-integrity-check-key";
+
+fn server_config(cert_path: &Path, key_path: &Path) -> ServerConfig {
+    ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        ..common::test_server_config()
+    }
+}
+
+fn client_config(server_port: u16, cert_path: &Path, stats_port: u16) -> ClientConfig {
+    ClientConfig {
+        server_port,
+        skip_verify: true,
+        ca_cert_path: Some(cert_path.to_path_buf()),
+        auth_key: AUTH_KEY.to_string(),
+        stats_port: Some(stats_port),
+        ..common::test_client_config()
+    }
+}
+
+fn proxy(publish_port: u16, local_port: u16) -> ProxyConfig {
+    ProxyConfig {
+        name: "checked".to_string(),
+        proxy_type: ProxyType::Tcp,
+        publish_addr: "127.0.0.1".to_string(),
+        publish_port,
+        local_port,
+        pool: None,
+        shared: false,
+        weight: None,
+        visibility: ProxyVisibility::Public,
+        drain_timeout_secs: None,
+        sni_routing: None,
+        socket: None,
+        local_targets: None,
+        idle_keepalive_secs: None,
+        require_first_byte_timeout_ms: None,
+        max_connections_per_source: None,
+        report_peers: false,
+        ws_idle_timeout_secs: None,
+        integrity_check: true,
+    }
+}
+
+fn spawn_client(config: ClientFullConfig, cert_path: &Path) -> JoinHandle<()> {
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    })
+}
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// 客户端统计中代理的完整性校验计数 (verified, mismatches)
+async fn client_counts(endpoint: &StatsEndpoint) -> Option<(u64, u64)> {
+    let body = endpoint.get("/stats").await.ok()?;
+    let stats: serde_json::Value = serde_json::from_str(&body).ok()?;
+    let proxy = stats
+        .as_array()?
+        .iter()
+        .find(|s| s["name"] == "checked")?
+        .clone();
+    Some((
+        proxy["integrity_verified"].as_u64().unwrap_or(0),
+        proxy["integrity_mismatches"].as_u64().unwrap_or(0),
+    ))
+}
+
+#[tokio::test]
+async fn test_clean_transfer_verifies_both_directions() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server = common::spawn_server(server_config(&cert_path, &key_path)).await;
+    let echo_port = common::get_available_port();
+    let echo = common::start_echo_server(echo_port).await;
+    let publish_port = common::get_available_port();
+    let stats_port = common::get_available_port();
+    let client = spawn_client(
+        ClientFullConfig {
+            client: client_config(server.bound_addr().port(), &cert_path, stats_port),
+            proxies: vec![proxy(publish_port, echo_port)],
+            visitors: vec![],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        &cert_path,
+    );
+    assert!(common::wait_for_echo(publish_port, 50).await);
+
+    // 跨越多个帧的数据，外部连接收到的回显与发送的一致
+    let data = pattern(256 * 1024);
+    let mut stream = TcpStream::connect(("127.0.0.1", publish_port))
+        .await
+        .unwrap();
+    let (mut read, mut write) = stream.split();
+    let send = async {
+        write.write_all(&data).await.unwrap();
+        write.shutdown().await.unwrap();
+    };
+    let mut received = Vec::new();
+    let (_, result) = tokio::join!(
+        send,
+        timeout(Duration::from_secs(10), read.read_to_end(&mut received))
+    );
+    result.expect("Timed out reading echo").unwrap();
+    assert_eq!(received, data);
+    drop(stream);
+
+    // 服务器校验客户端发来的方向，客户端校验服务器发来的方向
+    let stats = server.stats();
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            stats
+                .get_proxy_stats("checked")
+                .is_some_and(|s| s.core.integrity_verified >= 1)
+        })
+        .await
+    );
+    let endpoint = StatsEndpoint::Http(format!("http://127.0.0.1:{}", stats_port));
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            client_counts(&endpoint)
+                .await
+                .is_some_and(|(verified, _)| verified >= 1)
+        })
+        .await
+    );
+    assert_eq!(
+        stats
+            .get_proxy_stats("checked")
+            .unwrap()
+            .core
+            .integrity_mismatches,
+        0
+    );
+    assert_eq!(client_counts(&endpoint).await.unwrap().1, 0);
+
+    client.abort();
+    echo.abort();
+}
@@ -0,0 +1,263 @@
+/// IPv6 publish address tests
+///
+/// 代理的 `publish_addr` 和 visitor 的 `bind_addr` 可以是带或不带方括号的 IPv6 字面量：
+/// 发布在 `[::1]` 的代理通过 IPv6 回环地址访问，`::` 在系统允许双栈时同时接受 IPv4 连接，
+/// visitor 可以监听在 `::1`。没有 IPv6 的环境跳过这些测试
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+    VisitorConfig,
+};
+use tls_tunnel::server::ServerHandle;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-ipv6-publish-key";
+
+/// 本机是否有 IPv6 回环地址
+fn ipv6_available() -> bool {
+    std::net::TcpListener::bind("[::1]:0").is_ok()
+}
+
+async fn start_server(cert_path: &Path, key_path: &Path) -> ServerHandle {
+    common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        ..common::test_server_config()
+    })
+    .await
+}
+
+fn client_config(server_port: u16, cert_path: &Path) -> ClientConfig {
+    ClientConfig {
+        server_port,
+        skip_verify: true,
+        ca_cert_path: Some(cert_path.to_path_buf()),
+        auth_key: AUTH_KEY.to_string(),
+        ..common::test_client_config()
+    }
+}
+
+fn proxy(
+    name: &str,
+    publish_addr: &str,
+    publish_port: u16,
+    local_port: u16,
+    visibility: ProxyVisibility,
+) -> ProxyConfig {
+    ProxyConfig {
+        name: name.to_string(),
+        proxy_type: ProxyType::Tcp,
+        publish_addr: publish_addr.to_string(),
+        publish_port,
+        local_port,
+        pool: None,
+        shared: false,
+        weight: None,
+        visibility,
+        drain_timeout_secs: None,
+        sni_routing: None,
+        socket: None,
+        local_targets: None,
+        idle_keepalive_secs: None,
+        require_first_byte_timeout_ms: None,
+        max_connections_per_source: None,
+        report_peers: false,
+        ws_idle_timeout_secs: None,
+        integrity_check: false,
+    }
+}
+
+fn spawn_client(config: ClientFullConfig, cert_path: &Path) -> JoinHandle<()> {
+    config.validate().expect("IPv6 test config should be valid");
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    })
+}
+
+/// 连接指定地址并完成一次回显
+async fn echo_via(host: &str, port: u16) -> bool {
+    let Ok(Ok(mut stream)) =
+        timeout(Duration::from_secs(2), TcpStream::connect((host, port))).await
+    else {
+        return false;
+    };
+    if stream.write_all(b"ipv6").await.is_err() {
+        return false;
+    }
+    let mut buf = [0u8; 4];
+    matches!(
+        timeout(Duration::from_secs(2), stream.read_exact(&mut buf)).await,
+        Ok(Ok(_))
+    ) && &buf == b"ipv6"
+}
+
+/// 等待地址可以完成回显
+async fn wait_for_echo_via(host: &str, port: u16) -> bool {
+    for _ in 0..50 {
+        if echo_via(host, port).await {
+            return true;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn test_proxy_published_on_ipv6_loopback() {
+    if !ipv6_available() {
+        return;
+    }
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server = start_server(&cert_path, &key_path).await;
+    let echo_port = common::get_available_port();
+    let echo = common::start_echo_server(echo_port).await;
+    let publish_port = common::get_available_port();
+    let client = spawn_client(
+        ClientFullConfig {
+            client: client_config(server.bound_addr().port(), &cert_path),
+            proxies: vec![proxy(
+                "v6",
+                "[::1]",
+                publish_port,
+                echo_port,
+                ProxyVisibility::Public,
+            )],
+            visitors: vec![],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        &cert_path,
+    );
+
+    assert!(wait_for_echo_via("::1", publish_port).await);
+    // 只监听 IPv6 回环地址
+    assert!(!echo_via("127.0.0.1", publish_port).await);
+    let stats = server.stats().get_proxy_stats("v6").unwrap();
+    assert_eq!(stats.core.listen_addr, "::1");
+    assert_eq!(stats.publish_label(), format!("[::1]:{}", publish_port));
+
+    client.abort();
+    echo.abort();
+}
+
+#[tokio::test]
+async fn test_unspecified_ipv6_publish_is_dual_stack() {
+    if !ipv6_available() {
+        return;
+    }
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server = start_server(&cert_path, &key_path).await;
+    let echo_port = common::get_available_port();
+    let echo = common::start_echo_server(echo_port).await;
+    let publish_port = common::get_available_port();
+    let client = spawn_client(
+        ClientFullConfig {
+            client: client_config(server.bound_addr().port(), &cert_path),
+            proxies: vec![proxy(
+                "any",
+                "::",
+                publish_port,
+                echo_port,
+                ProxyVisibility::Public,
+            )],
+            visitors: vec![],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        &cert_path,
+    );
+
+    assert!(wait_for_echo_via("::1", publish_port).await);
+    // 系统禁用双栈（net.ipv6.bindv6only = 1）时只接受 IPv6 连接
+    let probe = tls_tunnel::listen_addr::bind("::", 0).await.unwrap();
+    if !socket2::SockRef::from(&probe).only_v6().unwrap() {
+        assert!(echo_via("127.0.0.1", publish_port).await);
+    }
+
+    client.abort();
+    echo.abort();
+}
+
+#[tokio::test]
+async fn test_visitor_binds_ipv6_loopback() {
+    if !ipv6_available() {
+        return;
+    }
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server = start_server(&cert_path, &key_path).await;
+    let server_port = server.bound_addr().port();
+    let echo_port = common::get_available_port();
+    let echo = common::start_echo_server(echo_port).await;
+    let publish_port = common::get_available_port();
+    let owner = spawn_client(
+        ClientFullConfig {
+            client: client_config(server_port, &cert_path),
+            proxies: vec![proxy(
+                "private-v6",
+                "0.0.0.0",
+                publish_port,
+                echo_port,
+                ProxyVisibility::Private,
+            )],
+            visitors: vec![],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        &cert_path,
+    );
+    let stats = server.stats();
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            stats.get_proxy_stats("private-v6").is_some()
+        })
+        .await
+    );
+
+    let bind_port = common::get_available_port();
+    let visitor = spawn_client(
+        ClientFullConfig {
+            client: client_config(server_port, &cert_path),
+            proxies: vec![],
+            visitors: vec![VisitorConfig {
+                name: "private-v6".to_string(),
+                proxy_type: ProxyType::Tcp,
+                bind_addr: "::1".to_string(),
+                bind_port,
+                publish_port,
+                expected_peer_id: None,
+                connection_reuse: false,
+                socket: None,
+            }],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        &cert_path,
+    );
+
+    assert!(wait_for_echo_via("::1", bind_port).await);
+
+    visitor.abort();
+    owner.abort();
+    echo.abort();
+}
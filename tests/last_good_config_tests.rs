@@ -0,0 +1,172 @@
+/// Last known good config tests
+///
+/// 开启 `fallback_to_last_good` 的客户端在服务器接受配置后保存 `last_good_config.json`；
+/// 配置文件之后被改坏时以缓存的配置启动隧道，配置文件修复后切换过去。
+/// 没有缓存时保持原来的行为：加载失败
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tls_tunnel::config::{AppConfig, ServerConfig};
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-last-good-config-key";
+
+fn server_config(cert_path: &Path, key_path: &Path) -> ServerConfig {
+    ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        ..common::test_server_config()
+    }
+}
+
+/// 客户端配置文件内容
+fn client_toml(
+    server_port: u16,
+    cert_path: &Path,
+    publish_port: u16,
+    local_port: u16,
+    fallback: bool,
+) -> String {
+    format!(
+        r#"[client]
+server_addr = "127.0.0.1"
+server_port = {server_port}
+skip_verify = true
+ca_cert_path = "{cert}"
+auth_key = "{AUTH_KEY}"
+fallback_to_last_good = {fallback}
+
+[[proxies]]
+name = "web"
+publish_addr = "127.0.0.1"
+publish_port = {publish_port}
+local_port = {local_port}
+"#,
+        cert = cert_path.display(),
+    )
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "tls-tunnel-last-good-{}-{}",
+        name,
+        std::process::id()
+    ));
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn connector(cert_path: &Path) -> TlsConnector {
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    TlsConnector::from(tls_config)
+}
+
+fn spawn_client(config_path: &Path, cert_path: &Path) -> JoinHandle<()> {
+    let config = AppConfig::load_client_config(&config_path.to_string_lossy()).unwrap();
+    let connector = connector(cert_path);
+    let source = config_path.to_path_buf();
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client_with_source(config, source, connector)
+            .await
+            .ok();
+    })
+}
+
+#[tokio::test]
+async fn test_corrupt_config_falls_back_to_last_good() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let dir = temp_dir("fallback");
+
+    let server = common::spawn_server(server_config(&cert_path, &key_path)).await;
+    let server_port = server.bound_addr().port();
+    let echo_port = common::get_available_port();
+    let echo = common::start_echo_server(echo_port).await;
+    let publish_port = common::get_available_port();
+
+    // 1. 配置被服务器接受后保存到配置文件所在目录
+    let config_path = dir.join("client.toml");
+    std::fs::write(
+        &config_path,
+        client_toml(server_port, &cert_path, publish_port, echo_port, true),
+    )
+    .unwrap();
+    let cache_path = dir.join(tls_tunnel::client::LAST_GOOD_CONFIG_FILE);
+    let client = spawn_client(&config_path, &cert_path);
+    assert!(common::wait_for_echo(publish_port, 50).await);
+    assert!(common::wait_until(Duration::from_secs(5), || async { cache_path.exists() }).await);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&cache_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+    client.abort();
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            common::test_proxy_connection(publish_port, b"x", Duration::from_millis(500))
+                .await
+                .is_err()
+        })
+        .await
+    );
+
+    // 2. 配置文件改到一半：改用缓存的配置，并报告配置文件的错误
+    std::fs::write(&config_path, "[client]\nserver_addr = \"127.0.0.1\n").unwrap();
+    assert!(AppConfig::load_client_config(&config_path.to_string_lossy()).is_err());
+    let loaded = tls_tunnel::client::load_with_fallback(&config_path.to_string_lossy()).unwrap();
+    let fallback = loaded.fallback.expect("should fall back to the cache");
+    assert_eq!(fallback.cache_path, cache_path);
+    assert!(
+        format!("{:#}", fallback.error).contains("Failed to parse client configuration"),
+        "{:#}",
+        fallback.error
+    );
+    assert_eq!(loaded.config.client.auth_key, AUTH_KEY);
+
+    let source = config_path.clone();
+    let connector = connector(&cert_path);
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client_from_last_good(loaded.config, source, connector)
+            .await
+            .ok();
+    });
+    assert!(common::wait_for_echo(publish_port, 50).await);
+
+    // 3. 配置文件修复后切换过去（这里换了发布端口）
+    let repaired_port = common::get_available_port();
+    std::fs::write(
+        &config_path,
+        client_toml(server_port, &cert_path, repaired_port, echo_port, true),
+    )
+    .unwrap();
+    assert!(common::wait_for_echo(repaired_port, 150).await);
+
+    client.abort();
+    echo.abort();
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[tokio::test]
+async fn test_corrupt_config_without_cache_fails() {
+    let dir = temp_dir("no-cache");
+    let config_path = dir.join("client.toml");
+    std::fs::write(
+        &config_path,
+        "[client]\nfallback_to_last_good = true\nserver_addr = \"127.0.0.1\"\n",
+    )
+    .unwrap();
+
+    let error = match tls_tunnel::client::load_with_fallback(&config_path.to_string_lossy()) {
+        Ok(_) => panic!("loading should fail without a cached configuration"),
+        Err(e) => e,
+    };
+    let direct = AppConfig::load_client_config(&config_path.to_string_lossy()).unwrap_err();
+    assert_eq!(format!("{:#}", error), format!("{:#}", direct));
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,110 @@
+/// Legacy client detection tests
+///
+/// 1.0 版本的客户端在 TLS 建立后直接发送 `[u32 密钥长度][密钥]`；新服务器应按旧版认证失败的格式
+/// 回复升级提示并关闭连接，而不是等到会话超时，同时计入旧版客户端计数
+mod common;
+
+use std::time::Duration;
+use tls_tunnel::config::ServerConfig;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-legacy-client-key";
+
+fn server_config(cert_path: &std::path::Path, key_path: &std::path::Path) -> ServerConfig {
+    ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        ..common::test_server_config()
+    }
+}
+
+async fn connect_tls(
+    cert_path: &std::path::Path,
+    port: u16,
+) -> tokio_rustls::client::TlsStream<TcpStream> {
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let tcp = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let server_name = rustls::pki_types::ServerName::try_from("localhost").unwrap();
+    TlsConnector::from(tls_config)
+        .connect(server_name, tcp)
+        .await
+        .expect("TLS handshake failed")
+}
+
+/// 按 1.0 协议发送认证密钥并读取认证结果（状态字节 + 可选的错误消息）
+async fn legacy_handshake(
+    stream: &mut tokio_rustls::client::TlsStream<TcpStream>,
+    key: &str,
+) -> (u8, Option<String>) {
+    stream
+        .write_all(&(key.len() as u32).to_be_bytes())
+        .await
+        .unwrap();
+    stream.write_all(key.as_bytes()).await.unwrap();
+    stream.flush().await.unwrap();
+
+    let status = stream.read_u8().await.expect("No authentication result");
+    if status != 0 {
+        return (status, None);
+    }
+    let len = stream.read_u16().await.expect("No error message length") as usize;
+    let mut message = vec![0u8; len];
+    stream
+        .read_exact(&mut message)
+        .await
+        .expect("Truncated error message");
+    (status, Some(String::from_utf8(message).unwrap()))
+}
+
+#[tokio::test]
+async fn test_legacy_client_is_told_to_upgrade() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server = common::spawn_server(server_config(&cert_path, &key_path)).await;
+    let port = server.bound_addr().port();
+
+    let mut stream = connect_tls(&cert_path, port).await;
+    let (status, message) = timeout(
+        Duration::from_secs(5),
+        legacy_handshake(&mut stream, AUTH_KEY),
+    )
+    .await
+    .expect("Server should answer the legacy handshake instead of stalling");
+    assert_eq!(status, 0);
+    let message = message.unwrap();
+    assert!(message.contains("too old"), "{}", message);
+    assert!(message.contains("upgrade"), "{}", message);
+
+    // 回复之后服务器关闭连接
+    let mut rest = Vec::new();
+    timeout(Duration::from_secs(5), stream.read_to_end(&mut rest))
+        .await
+        .expect("Server should close the connection")
+        .ok();
+    assert!(rest.is_empty());
+    assert_eq!(server.stats().legacy_clients_rejected(), 1);
+
+    // 密钥正确与否不影响结果，每个旧版连接都计数
+    let mut stream = connect_tls(&cert_path, port).await;
+    let (status, _) = timeout(
+        Duration::from_secs(5),
+        legacy_handshake(&mut stream, "some-other-legacy-key"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(status, 0);
+    assert_eq!(server.stats().legacy_clients_rejected(), 2);
+
+    // 未发送任何数据就断开的连接不算旧版客户端
+    drop(connect_tls(&cert_path, port).await);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(server.stats().legacy_clients_rejected(), 2);
+
+    server.shutdown().await.ok();
+}
@@ -0,0 +1,267 @@
+/// Listener reconnect tests
+///
+/// 服务器反复重启期间持续连接客户端的 visitor/forwarder 本地端口，
+/// 断线期间连接快速失败，重连后恢复转发，端口不会永久不可用
+mod common;
+
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ForwarderConfig, ProxyConfig, ProxyType, ProxyVisibility,
+    ServerConfig, VisitorConfig,
+};
+use tls_tunnel::server::ServerHandle;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout, Instant};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-listener-reconnect-key";
+
+async fn start_server(
+    port: u16,
+    cert_path: &std::path::Path,
+    key_path: &std::path::Path,
+) -> ServerHandle {
+    common::spawn_server(ServerConfig {
+        bind_port: port,
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        allow_forward: true,
+        ..common::test_server_config()
+    })
+    .await
+}
+
+/// 一个 echo 代理、一个访问它的 visitor 和一个 HTTP forwarder
+fn client_config(
+    server_port: u16,
+    cert_path: &std::path::Path,
+    ports: &Ports,
+    keep_listening: bool,
+) -> ClientFullConfig {
+    ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            keep_listening_on_disconnect: keep_listening,
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "echo".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port: ports.publish,
+            local_port: ports.local,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![VisitorConfig {
+            name: "echo".to_string(),
+            proxy_type: ProxyType::Tcp,
+            bind_addr: "127.0.0.1".to_string(),
+            bind_port: ports.visitor,
+            publish_port: ports.publish,
+            expected_peer_id: None,
+            connection_reuse: false,
+            socket: None,
+        }],
+        forwarders: vec![ForwarderConfig {
+            name: "http".to_string(),
+            proxy_type: ProxyType::HttpProxy,
+            bind_addr: "127.0.0.1".to_string(),
+            bind_port: ports.forwarder,
+            routing: None,
+            fast_fail: None,
+            direct_egress: None,
+            socket: None,
+            report_direct: false,
+            idle_timeout_secs: None,
+            header_rules: None,
+        }],
+        visitor_gateway: None,
+        health: None,
+    }
+}
+
+struct Ports {
+    server: u16,
+    publish: u16,
+    local: u16,
+    visitor: u16,
+    forwarder: u16,
+}
+
+impl Ports {
+    fn new() -> Self {
+        Self {
+            server: common::get_available_port(),
+            publish: common::get_available_port(),
+            local: common::get_available_port(),
+            visitor: common::get_available_port(),
+            forwarder: common::get_available_port(),
+        }
+    }
+}
+
+/// 经 visitor 收到回显时返回 true
+async fn echo_through(port: u16) -> bool {
+    for _ in 0..100 {
+        if let Ok(data) =
+            common::test_proxy_connection(port, b"reconnect", Duration::from_secs(2)).await
+        {
+            if data == b"reconnect" {
+                return true;
+            }
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    false
+}
+
+/// 连接 forwarder 并发送 CONNECT 请求
+///
+/// 返回 None 表示端口拒绝连接；否则返回是否在超时前得到应答或被关闭（没有挂起）
+async fn probe_forwarder(port: u16) -> Option<bool> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.ok()?;
+    let answered = timeout(Duration::from_secs(5), async {
+        stream
+            .write_all(b"CONNECT 127.0.0.1:9 HTTP/1.1\r\nHost: 127.0.0.1:9\r\n\r\n")
+            .await
+            .ok();
+        let mut buf = [0u8; 256];
+        let _ = stream.read(&mut buf).await;
+    })
+    .await
+    .is_ok();
+    Some(answered)
+}
+
+/// 服务器重启期间持续探测本地端口，记录拒绝次数和挂起次数
+async fn cycle_server(
+    ports: &Ports,
+    cert_path: &std::path::Path,
+    key_path: &std::path::Path,
+    mut server: ServerHandle,
+    cycles: usize,
+) -> (ServerHandle, usize, usize) {
+    let mut refused = 0;
+    let mut hung = 0;
+    for _ in 0..cycles {
+        server.shutdown().await.ok();
+
+        // 断线期间持续访问 forwarder 端口
+        let gap_end = Instant::now() + Duration::from_millis(1500);
+        while Instant::now() < gap_end {
+            match probe_forwarder(ports.forwarder).await {
+                None => refused += 1,
+                Some(false) => hung += 1,
+                Some(true) => {}
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+
+        server = start_server(ports.server, cert_path, key_path).await;
+        assert!(
+            echo_through(ports.visitor).await,
+            "Visitor never recovered after server restart"
+        );
+        match probe_forwarder(ports.forwarder).await {
+            None => refused += 1,
+            Some(false) => hung += 1,
+            Some(true) => {}
+        }
+    }
+    (server, refused, hung)
+}
+
+#[tokio::test]
+async fn test_listeners_survive_server_restarts() {
+    std::env::set_var("TLS_TUNNEL_RECONNECT_DELAY_SECS", "1");
+    let ports = Ports::new();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(ports.local).await;
+
+    let server = start_server(ports.server, &cert_path, &key_path).await;
+    let config = client_config(ports.server, &cert_path, &ports, true);
+    let tls_config =
+        tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None).unwrap();
+    let connector = TlsConnector::from(tls_config);
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    });
+
+    assert!(
+        echo_through(ports.visitor).await,
+        "Visitor never became reachable"
+    );
+
+    let (server, refused, hung) = cycle_server(&ports, &cert_path, &key_path, server, 3).await;
+    assert_eq!(refused, 0, "Forwarder port refused connections");
+    assert_eq!(hung, 0, "Forwarder connections hung while tunnel was down");
+
+    // 断线期间 visitor 端口仍接受连接，并立即关闭
+    server.shutdown().await.ok();
+    sleep(Duration::from_millis(300)).await;
+    let mut stream = TcpStream::connect(("127.0.0.1", ports.visitor))
+        .await
+        .expect("Visitor port refused connection while tunnel was down");
+    stream.write_all(b"reconnect").await.ok();
+    let mut buf = [0u8; 16];
+    let read = timeout(Duration::from_secs(5), stream.read(&mut buf)).await;
+    assert!(
+        matches!(read, Ok(Ok(0)) | Ok(Err(_))),
+        "Visitor connection was not closed while tunnel was down"
+    );
+
+    client.abort();
+}
+
+#[tokio::test]
+async fn test_listeners_rebind_without_keep_listening() {
+    std::env::set_var("TLS_TUNNEL_RECONNECT_DELAY_SECS", "1");
+    let ports = Ports::new();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(ports.local).await;
+
+    let server = start_server(ports.server, &cert_path, &key_path).await;
+    let config = client_config(ports.server, &cert_path, &ports, false);
+    let tls_config =
+        tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None).unwrap();
+    let connector = TlsConnector::from(tls_config);
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    });
+
+    assert!(
+        echo_through(ports.visitor).await,
+        "Visitor never became reachable"
+    );
+
+    // 断线期间端口关闭，重连后重新绑定
+    let (server, _refused, hung) = cycle_server(&ports, &cert_path, &key_path, server, 2).await;
+    assert_eq!(hung, 0, "Forwarder connections hung while tunnel was down");
+    assert!(
+        probe_forwarder(ports.forwarder).await.is_some(),
+        "Forwarder port was not rebound after reconnect"
+    );
+
+    client.abort();
+    server.shutdown().await.ok();
+}
@@ -0,0 +1,198 @@
+/// Local targets failover tests
+///
+/// 一个代理配置多个本地目标：连接按轮询分发到各目标，某个目标停止后新连接在同一次
+/// 转发中改用其他目标，用户连接不失败；客户端统计按目标地址分别计数
+mod common;
+
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+};
+use tls_tunnel::stats_http::StatsEndpoint;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-local-targets-key";
+const BANNER_LEN: usize = 9;
+
+/// 启动本地服务：接受连接后先发送服务名称（BANNER_LEN 字节），然后回显收到的数据
+async fn start_named_server(port: u16, name: &'static str) -> JoinHandle<()> {
+    assert_eq!(name.len(), BANNER_LEN);
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                if stream.write_all(name.as_bytes()).await.is_err() {
+                    return;
+                }
+                let mut buf = [0u8; 1024];
+                while let Ok(n) = stream.read(&mut buf).await {
+                    if n == 0 || stream.write_all(&buf[..n]).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    })
+}
+
+/// 通过发布端口建立一条用户连接，返回服务该连接的本地目标名称
+async fn user_connection(publish_port: u16) -> Result<String, String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", publish_port))
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut banner = [0u8; BANNER_LEN];
+    timeout(Duration::from_secs(10), stream.read_exact(&mut banner))
+        .await
+        .map_err(|_| "Timed out waiting for banner".to_string())?
+        .map_err(|e| e.to_string())?;
+
+    stream.write_all(b"ping").await.map_err(|e| e.to_string())?;
+    let mut echo = [0u8; 4];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut echo))
+        .await
+        .map_err(|_| "Timed out waiting for echo".to_string())?
+        .map_err(|e| e.to_string())?;
+    if &echo != b"ping" {
+        return Err(format!("Unexpected echo {:?}", echo));
+    }
+    Ok(String::from_utf8_lossy(&banner).into_owned())
+}
+
+/// 读取代理的客户端统计条目
+async fn proxy_stats(endpoint: &StatsEndpoint, name: &str) -> Option<serde_json::Value> {
+    let body = endpoint.get("/stats").await.ok()?;
+    let stats: Vec<serde_json::Value> = serde_json::from_str(&body).ok()?;
+    stats.into_iter().find(|s| s["name"] == name)
+}
+
+#[tokio::test]
+async fn test_failover_between_local_targets() {
+    let publish_port = common::get_available_port();
+    let first_port = common::get_available_port();
+    let second_port = common::get_available_port();
+    let stats_port = common::get_available_port();
+    let first = format!("127.0.0.1:{}", first_port);
+    let second = format!("127.0.0.1:{}", second_port);
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _first_server = start_named_server(first_port, "backend-1").await;
+    let second_server = start_named_server(second_port, "backend-2").await;
+
+    let server = common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        ..common::test_server_config()
+    })
+    .await;
+
+    let config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.clone()),
+            auth_key: AUTH_KEY.to_string(),
+            stats_port: Some(stats_port),
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "web".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port: 0,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: Some(vec![first.clone(), second.clone()]),
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    config
+        .validate()
+        .expect("local_targets config should be valid");
+
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    });
+
+    let mut ready = false;
+    for _ in 0..50 {
+        if user_connection(publish_port).await.is_ok() {
+            ready = true;
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert!(ready, "Proxy 'web' never became reachable");
+
+    // 连接轮流分发到两个目标
+    let mut served = Vec::new();
+    for _ in 0..6 {
+        served.push(
+            user_connection(publish_port)
+                .await
+                .expect("Connection failed"),
+        );
+    }
+    assert!(served.iter().any(|s| s == "backend-1"), "{:?}", served);
+    assert!(served.iter().any(|s| s == "backend-2"), "{:?}", served);
+
+    // 停止第二个目标后，所有新连接都由第一个目标服务
+    second_server.abort();
+    let _ = second_server.await;
+    assert!(TcpStream::connect(&second).await.is_err());
+    for i in 0..10 {
+        let served = user_connection(publish_port)
+            .await
+            .unwrap_or_else(|e| panic!("Connection {} failed after failover: {}", i, e));
+        assert_eq!(served, "backend-1");
+    }
+
+    // 客户端统计按目标地址分别计数
+    let endpoint = StatsEndpoint::Http(format!("http://127.0.0.1:{}", stats_port));
+    let entry = proxy_stats(&endpoint, "web")
+        .await
+        .expect("Proxy stats unavailable");
+    let targets = &entry["targets"];
+    let first_total = targets[&first]["total_connections"].as_u64().unwrap();
+    let second_total = targets[&second]["total_connections"].as_u64().unwrap();
+    assert!(first_total >= 10, "{}", entry);
+    assert!(second_total >= 1, "{}", entry);
+    assert!(
+        targets[&second]["failures"].as_u64().unwrap() >= 1,
+        "{}",
+        entry
+    );
+    assert_eq!(targets[&first]["failures"], 0, "{}", entry);
+    assert_eq!(
+        entry["total_connections"].as_u64().unwrap(),
+        first_total + second_total,
+        "{}",
+        entry
+    );
+
+    client.abort();
+    server.shutdown().await.ok();
+}
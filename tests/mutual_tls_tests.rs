@@ -0,0 +1,314 @@
+/// Mutual TLS tests
+///
+/// 服务器配置 `client_ca_path` 后在 TLS 握手中验证客户端证书：由该 CA 签发证书的客户端正常注册代理，
+/// 没有证书（`require_client_cert = true` 时）或证书来自其他 CA 的客户端在握手阶段被拒绝，
+/// 服务器以 warn 级别记录对端地址
+mod common;
+
+use common::ClientCerts;
+use parking_lot::Mutex;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tls_tunnel::config::{ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ServerConfig};
+use tls_tunnel::server::ServerHandle;
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-mutual-tls-key";
+
+const REJECTION_MARKER: &str = "without a valid client certificate";
+
+/// 收集日志输出（同一测试进程只能初始化一次订阅者）
+#[derive(Clone, Default)]
+struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock()).into_owned()
+    }
+}
+
+impl Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn logs() -> &'static CapturedLogs {
+    static LOGS: OnceLock<CapturedLogs> = OnceLock::new();
+    LOGS.get_or_init(|| {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        tracing_subscriber::fmt()
+            .with_env_filter("tls_tunnel=info")
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .init();
+        logs
+    })
+}
+
+fn server_config(
+    cert_path: &Path,
+    key_path: &Path,
+    client_ca_path: &Path,
+    require_client_cert: bool,
+) -> ServerConfig {
+    ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        client_ca_path: Some(client_ca_path.to_path_buf()),
+        require_client_cert,
+        ..common::test_server_config()
+    }
+}
+
+fn client_config(
+    server_port: u16,
+    cert_path: &Path,
+    client_cert: Option<&ClientCerts>,
+) -> ClientConfig {
+    ClientConfig {
+        server_port,
+        skip_verify: true,
+        ca_cert_path: Some(cert_path.to_path_buf()),
+        auth_key: AUTH_KEY.to_string(),
+        client_cert_path: client_cert.map(|certs| certs.cert_path.clone()),
+        client_key_path: client_cert.map(|certs| certs.key_path.clone()),
+        ..common::test_client_config()
+    }
+}
+
+/// 启动注册代理 `web` 的客户端（TLS 连接器按客户端配置创建，包括客户端证书）
+fn spawn_proxy_client(
+    server: &ServerHandle,
+    cert_path: &Path,
+    client_cert: Option<&ClientCerts>,
+    publish_port: u16,
+    local_port: u16,
+) -> JoinHandle<()> {
+    let config = ClientFullConfig {
+        client: client_config(server.bound_addr().port(), cert_path, client_cert),
+        proxies: vec![ProxyConfig {
+            name: "web".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: Default::default(),
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_from(&config.client)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    })
+}
+
+/// 客户端的握手被拒绝：代理不注册，服务器记录失败的握手和对端地址
+async fn assert_rejected(server: &ServerHandle, client: JoinHandle<()>) {
+    let stats = server.stats();
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            stats.handshakes().failed() > 0
+        })
+        .await,
+        "Handshake without a valid client certificate was not rejected"
+    );
+    sleep(Duration::from_millis(500)).await;
+    assert!(stats.get_proxy_stats("web").is_none());
+    client.abort();
+}
+
+/// 日志中有一条 warn 级别的拒绝记录，包含客户端地址和 rustls 给出的原因
+async fn assert_rejection_logged(reason: &str) {
+    let find_line = |text: &str| {
+        text.lines()
+            .find(|line| line.contains(REJECTION_MARKER) && line.contains(reason))
+            .map(str::to_string)
+    };
+    assert!(
+        common::wait_until(Duration::from_secs(5), || async {
+            find_line(&logs().text()).is_some()
+        })
+        .await,
+        "{}",
+        logs().text()
+    );
+    let text = logs().text();
+    let line = find_line(&text).unwrap();
+    assert!(line.contains("WARN"), "{}", line);
+    assert!(line.contains("127.0.0.1:"), "{}", line);
+    assert!(!text.contains("Client error"), "{}", text);
+}
+
+#[tokio::test]
+async fn test_client_with_valid_certificate_is_accepted() {
+    logs();
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let client_certs = common::generate_client_certs("client-a");
+    let _echo = common::start_echo_server(local_port).await;
+    let server = common::spawn_server(server_config(
+        &cert_path,
+        &key_path,
+        &client_certs.ca_path,
+        true,
+    ))
+    .await;
+
+    let client = spawn_proxy_client(
+        &server,
+        &cert_path,
+        Some(&client_certs),
+        publish_port,
+        local_port,
+    );
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            common::test_proxy_connection(publish_port, b"mtls", Duration::from_secs(2))
+                .await
+                .is_ok_and(|data| data == b"mtls")
+        })
+        .await,
+        "Client with a valid certificate could not register its proxy"
+    );
+    assert_eq!(server.stats().handshakes().failed(), 0);
+
+    client.abort();
+}
+
+#[tokio::test]
+async fn test_client_without_certificate_is_rejected() {
+    logs();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let client_certs = common::generate_client_certs("client-b");
+    let server = common::spawn_server(server_config(
+        &cert_path,
+        &key_path,
+        &client_certs.ca_path,
+        true,
+    ))
+    .await;
+
+    let client = spawn_proxy_client(
+        &server,
+        &cert_path,
+        None,
+        common::get_available_port(),
+        common::get_available_port(),
+    );
+    assert_rejected(&server, client).await;
+    assert_rejection_logged("peer sent no certificates").await;
+}
+
+#[tokio::test]
+async fn test_certificate_from_other_ca_is_rejected() {
+    logs();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let trusted = common::generate_client_certs("trusted");
+    let untrusted = common::generate_client_certs("untrusted");
+    // 不要求证书时，出示的证书仍然必须由 client_ca_path 签发
+    let server = common::spawn_server(server_config(
+        &cert_path,
+        &key_path,
+        &trusted.ca_path,
+        false,
+    ))
+    .await;
+
+    let client = spawn_proxy_client(
+        &server,
+        &cert_path,
+        Some(&untrusted),
+        common::get_available_port(),
+        common::get_available_port(),
+    );
+    assert_rejected(&server, client).await;
+    assert_rejection_logged("UnknownIssuer").await;
+}
+
+#[tokio::test]
+async fn test_certificate_optional_when_not_required() {
+    logs();
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let client_certs = common::generate_client_certs("client-c");
+    let _echo = common::start_echo_server(local_port).await;
+    let server = common::spawn_server(server_config(
+        &cert_path,
+        &key_path,
+        &client_certs.ca_path,
+        false,
+    ))
+    .await;
+
+    let client = spawn_proxy_client(&server, &cert_path, None, publish_port, local_port);
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            common::test_proxy_connection(publish_port, b"optional", Duration::from_secs(2))
+                .await
+                .is_ok_and(|data| data == b"optional")
+        })
+        .await,
+        "Client without a certificate should be accepted when it is optional"
+    );
+
+    client.abort();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_generated_private_keys_are_owner_only() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let client_certs = common::generate_client_certs("client-perm");
+    let mode = std::fs::metadata(&client_certs.key_path)
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    // 覆盖一个已存在且全局可读的私钥文件时同样收紧权限
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    std::fs::set_permissions(&key_path, std::fs::Permissions::from_mode(0o644)).unwrap();
+    tls_tunnel::tls::generate_self_signed_cert("localhost", &[], &cert_path, &key_path).unwrap();
+    let mode = std::fs::metadata(&key_path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
@@ -0,0 +1,203 @@
+/// Visitor peer identity pinning tests
+///
+/// visitor 通过 expected_peer_id 固定目标 proxy 的注册者身份：
+/// 原注册者断开后，其他租户以相同 name/publish_port 注册时 visitor 应拒绝连接
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+    VisitorConfig,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-peer-identity-key";
+
+/// 启动一个接受连接后写出固定标识并关闭的后端
+async fn start_banner_server(port: u16, banner: &'static [u8]) -> JoinHandle<()> {
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port))
+        .await
+        .expect("Failed to bind banner server");
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let _ = socket.write_all(banner).await;
+            let _ = socket.shutdown().await;
+        }
+    })
+}
+
+/// 连接端口并读取全部数据（连接被拒绝时返回空）
+async fn read_banner(port: u16) -> Vec<u8> {
+    let mut data = Vec::new();
+    if let Ok(mut stream) = TcpStream::connect(format!("127.0.0.1:{}", port)).await {
+        let _ = timeout(Duration::from_secs(5), stream.read_to_end(&mut data)).await;
+    }
+    data
+}
+
+fn client_config(server_port: u16, cert_path: &Path, peer_id: &str) -> ClientConfig {
+    ClientConfig {
+        server_port,
+        skip_verify: true,
+        ca_cert_path: Some(cert_path.to_path_buf()),
+        auth_key: AUTH_KEY.to_string(),
+        peer_id: Some(peer_id.to_string()),
+        ..common::test_client_config()
+    }
+}
+
+fn spawn_client(config: ClientFullConfig, cert_path: &Path) -> JoinHandle<()> {
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    })
+}
+
+fn proxy_client(
+    server_port: u16,
+    cert_path: &Path,
+    peer_id: &str,
+    publish_port: u16,
+    local_port: u16,
+) -> ClientFullConfig {
+    ClientFullConfig {
+        client: client_config(server_port, cert_path, peer_id),
+        proxies: vec![ProxyConfig {
+            name: "shared-service".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    }
+}
+
+#[tokio::test]
+async fn test_visitor_peer_identity_pinning() {
+    std::env::set_var("TLS_TUNNEL_RECONNECT_DELAY_SECS", "1");
+
+    let publish_port = common::get_available_port();
+    let visitor_port = common::get_available_port();
+    let backend_a_port = common::get_available_port();
+    let backend_b_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let backend_a = start_banner_server(backend_a_port, b"backend-a").await;
+    let backend_b = start_banner_server(backend_b_port, b"backend-b").await;
+
+    let server_config = ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        ..common::test_server_config()
+    };
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
+
+    // 租户 A 注册 proxy
+    let client_a = spawn_client(
+        proxy_client(
+            server_port,
+            &cert_path,
+            "tenant-a",
+            publish_port,
+            backend_a_port,
+        ),
+        &cert_path,
+    );
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            read_banner(publish_port).await == b"backend-a"
+        })
+        .await,
+        "Tenant A's proxy never became reachable"
+    );
+
+    // visitor 固定租户 A 的身份
+    let visitor_config = ClientFullConfig {
+        client: client_config(server_port, &cert_path, "visitor"),
+        proxies: vec![],
+        visitors: vec![VisitorConfig {
+            name: "shared-service".to_string(),
+            proxy_type: ProxyType::Tcp,
+            bind_addr: "127.0.0.1".to_string(),
+            bind_port: visitor_port,
+            publish_port,
+            expected_peer_id: Some("tenant-a".to_string()),
+            connection_reuse: false,
+            socket: None,
+        }],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let visitor_client = spawn_client(visitor_config, &cert_path);
+    assert!(
+        common::wait_for_server(visitor_port, 50).await,
+        "Visitor did not start listening"
+    );
+
+    // 1. 身份匹配：正常访问租户 A 的后端
+    assert_eq!(read_banner(visitor_port).await, b"backend-a");
+
+    // 2. 租户 A 断开，租户 B 以相同 name/publish_port 接管
+    client_a.abort();
+    sleep(Duration::from_millis(300)).await;
+    let client_b = spawn_client(
+        proxy_client(
+            server_port,
+            &cert_path,
+            "tenant-b",
+            publish_port,
+            backend_b_port,
+        ),
+        &cert_path,
+    );
+
+    // 等待租户 B 的公开端口可用，确认接管已经完成
+    let mut taken_over = false;
+    for _ in 0..50 {
+        if read_banner(publish_port).await == b"backend-b" {
+            taken_over = true;
+            break;
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+    assert!(taken_over, "Tenant B should have taken over the proxy");
+
+    // 3. 身份不匹配：visitor 拒绝连接，不会收到租户 B 的数据
+    assert!(read_banner(visitor_port).await.is_empty());
+
+    visitor_client.abort();
+    client_b.abort();
+    server.shutdown().await.ok();
+    backend_a.abort();
+    backend_b.abort();
+}
@@ -0,0 +1,217 @@
+/// Peer report tests
+///
+/// 开启 `report_peers` 的代理由服务器在 stream 前导中附带外部连接的来源地址和接入时间，
+/// 客户端记录到统计的最近连接列表；转发的数据不变（分帧的 stream 同样适用），关闭时不记录
+mod common;
+
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+};
+use tls_tunnel::server::ServerHandle;
+use tls_tunnel::stats_http::StatsEndpoint;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpSocket, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-peer-report-key";
+
+/// 外部连接的来源地址（回环网段内与 127.0.0.1 不同的地址）
+const SOURCE_IP: &str = "127.0.0.2";
+
+async fn start_server(cert_path: &std::path::Path, key_path: &std::path::Path) -> ServerHandle {
+    common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        ..common::test_server_config()
+    })
+    .await
+}
+
+fn start_client(
+    cert_path: &std::path::Path,
+    server_port: u16,
+    stats_port: u16,
+    proxy: ProxyConfig,
+) -> JoinHandle<()> {
+    let config = ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            stats_port: Some(stats_port),
+            ..common::test_client_config()
+        },
+        proxies: vec![proxy],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    config.validate().expect("Proxy config should be valid");
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    })
+}
+
+fn proxy_config(publish_port: u16, local_port: u16, report_peers: bool) -> ProxyConfig {
+    ProxyConfig {
+        name: "web".to_string(),
+        proxy_type: ProxyType::Tcp,
+        publish_addr: "127.0.0.1".to_string(),
+        publish_port,
+        local_port,
+        pool: None,
+        shared: false,
+        weight: None,
+        visibility: ProxyVisibility::Public,
+        drain_timeout_secs: None,
+        sni_routing: None,
+        socket: None,
+        local_targets: None,
+        idle_keepalive_secs: None,
+        require_first_byte_timeout_ms: None,
+        max_connections_per_source: None,
+        report_peers,
+        ws_idle_timeout_secs: None,
+        integrity_check: false,
+    }
+}
+
+/// 从指定的来源地址连接发布端口，完成一次回显后关闭，返回本地使用的来源地址
+async fn echo_from(source: &str, publish_port: u16, data: &[u8]) -> Option<String> {
+    let socket = TcpSocket::new_v4().unwrap();
+    socket
+        .bind(format!("{}:0", source).parse().unwrap())
+        .unwrap();
+    let mut stream: TcpStream = socket
+        .connect(format!("127.0.0.1:{}", publish_port).parse().unwrap())
+        .await
+        .ok()?;
+    let local_addr = stream.local_addr().ok()?.to_string();
+    stream.write_all(data).await.ok()?;
+    let mut received = vec![0u8; data.len()];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut received))
+        .await
+        .ok()?
+        .ok()?;
+    (received == data).then_some(local_addr)
+}
+
+/// 读取代理的客户端统计条目
+async fn proxy_stats(endpoint: &StatsEndpoint, name: &str) -> Option<serde_json::Value> {
+    let body = endpoint.get("/stats").await.ok()?;
+    let stats: Vec<serde_json::Value> = serde_json::from_str(&body).ok()?;
+    stats.into_iter().find(|s| s["name"] == name)
+}
+
+/// 等待代理可达（期间的连接也会被记录）
+async fn wait_reachable(publish_port: u16) {
+    for _ in 0..50 {
+        if echo_from(SOURCE_IP, publish_port, b"ready").await.is_some() {
+            return;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Proxy 'web' never became reachable");
+}
+
+/// 等待来源地址出现在最近连接列表中
+async fn wait_recent_connection(
+    endpoint: &StatsEndpoint,
+    peer_addr: &str,
+) -> Option<serde_json::Value> {
+    for _ in 0..50 {
+        if let Some(recent) = proxy_stats(endpoint, "web")
+            .await
+            .and_then(|entry| entry["recent_connections"].as_array().cloned())
+        {
+            if let Some(record) = recent.into_iter().find(|r| r["peer_addr"] == peer_addr) {
+                return Some(record);
+            }
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    None
+}
+
+async fn run_reported(idle_keepalive_secs: Option<u64>) {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let stats_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+
+    let server = start_server(&cert_path, &key_path).await;
+    let mut proxy = proxy_config(publish_port, local_port, true);
+    proxy.idle_keepalive_secs = idle_keepalive_secs;
+    let client = start_client(&cert_path, server.bound_addr().port(), stats_port, proxy);
+    wait_reachable(publish_port).await;
+
+    // 转发的数据与外部连接发送的完全一致
+    let data = b"peer report payload";
+    let source = echo_from(SOURCE_IP, publish_port, data)
+        .await
+        .expect("Echo through the proxy failed");
+
+    let endpoint = StatsEndpoint::Http(format!("http://127.0.0.1:{}", stats_port));
+    let record = wait_recent_connection(&endpoint, &source)
+        .await
+        .unwrap_or_else(|| panic!("Connection from {} was not recorded", source));
+    assert!(record["accepted_at_ms"].as_u64().unwrap() > 0, "{}", record);
+    assert_eq!(record["bytes_received"], data.len() as u64, "{}", record);
+    assert_eq!(record["bytes_sent"], data.len() as u64, "{}", record);
+
+    client.abort();
+    server.shutdown().await.ok();
+}
+
+#[tokio::test]
+async fn test_client_records_peer_address() {
+    run_reported(None).await;
+}
+
+#[tokio::test]
+async fn test_client_records_peer_address_on_framed_stream() {
+    run_reported(Some(30)).await;
+}
+
+#[tokio::test]
+async fn test_disabled_report_peers_omits_peer() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let stats_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+
+    let server = start_server(&cert_path, &key_path).await;
+    let proxy = proxy_config(publish_port, local_port, false);
+    let client = start_client(&cert_path, server.bound_addr().port(), stats_port, proxy);
+    wait_reachable(publish_port).await;
+    echo_from(SOURCE_IP, publish_port, b"no peer report")
+        .await
+        .expect("Echo through the proxy failed");
+
+    let endpoint = StatsEndpoint::Http(format!("http://127.0.0.1:{}", stats_port));
+    let entry = proxy_stats(&endpoint, "web")
+        .await
+        .expect("Proxy stats unavailable");
+    assert!(
+        entry["total_connections"].as_u64().unwrap() >= 2,
+        "{}",
+        entry
+    );
+    assert!(entry.get("recent_connections").is_none(), "{}", entry);
+
+    client.abort();
+    server.shutdown().await.ok();
+}
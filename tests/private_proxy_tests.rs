@@ -0,0 +1,169 @@
+/// Private proxy tests
+///
+/// `visibility = "private"` 的代理注册到服务器但不绑定发布端口，
+/// 只能由其他客户端通过 visitor 访问
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+    VisitorConfig,
+};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-private-proxy-key";
+
+fn client_config(server_port: u16, cert_path: &Path) -> ClientConfig {
+    ClientConfig {
+        server_port,
+        skip_verify: true,
+        ca_cert_path: Some(cert_path.to_path_buf()),
+        auth_key: AUTH_KEY.to_string(),
+        ..common::test_client_config()
+    }
+}
+
+fn spawn_client(config: ClientFullConfig, cert_path: &Path) -> JoinHandle<()> {
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    })
+}
+
+#[tokio::test]
+async fn test_private_proxy_reachable_only_through_visitor() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let visitor_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+
+    let server_config = ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        ..common::test_server_config()
+    };
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
+
+    // 客户端 A 注册私有代理
+    let proxy_client = spawn_client(
+        ClientFullConfig {
+            client: client_config(server_port, &cert_path),
+            proxies: vec![ProxyConfig {
+                name: "private-db".to_string(),
+                proxy_type: ProxyType::Tcp,
+                publish_addr: "0.0.0.0".to_string(),
+                publish_port,
+                local_port,
+                pool: None,
+                shared: false,
+                weight: None,
+                visibility: ProxyVisibility::Private,
+                drain_timeout_secs: None,
+                sni_routing: None,
+                socket: None,
+                local_targets: None,
+                idle_keepalive_secs: None,
+                require_first_byte_timeout_ms: None,
+                max_connections_per_source: None,
+                report_peers: false,
+                ws_idle_timeout_secs: None,
+                integrity_check: false,
+            }],
+            visitors: vec![],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        &cert_path,
+    );
+
+    // 等待代理注册，统计中标记为私有
+    let stats = server.stats();
+    let mut registered = None;
+    for _ in 0..50 {
+        registered = stats.get_proxy_stats("private-db");
+        if registered.is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    let registered = registered.expect("Private proxy was not registered");
+    assert_eq!(registered.visibility, ProxyVisibility::Private);
+    assert_eq!(
+        registered.publish_label(),
+        format!("private:{}", publish_port)
+    );
+
+    // 服务器没有绑定发布端口
+    assert!(
+        TcpStream::connect(("127.0.0.1", publish_port))
+            .await
+            .is_err(),
+        "Private proxy must not have a public listener"
+    );
+
+    // 客户端 B 通过 visitor 访问
+    let visitor_client = spawn_client(
+        ClientFullConfig {
+            client: client_config(server_port, &cert_path),
+            proxies: vec![],
+            visitors: vec![VisitorConfig {
+                name: "private-db".to_string(),
+                proxy_type: ProxyType::Tcp,
+                bind_addr: "127.0.0.1".to_string(),
+                bind_port: visitor_port,
+                publish_port,
+                expected_peer_id: None,
+                connection_reuse: false,
+                socket: None,
+            }],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        &cert_path,
+    );
+
+    let mut response = None;
+    for _ in 0..25 {
+        if let Ok(data) =
+            common::test_proxy_connection(visitor_port, b"hello private", Duration::from_secs(2))
+                .await
+        {
+            response = Some(data);
+            break;
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+    assert_eq!(response.as_deref(), Some(&b"hello private"[..]));
+
+    // 发布端口仍然不可访问
+    assert!(TcpStream::connect(("127.0.0.1", publish_port))
+        .await
+        .is_err());
+
+    // 代理客户端断开后注销统计
+    proxy_client.abort();
+    let mut unregistered = false;
+    for _ in 0..50 {
+        if stats.get_proxy_stats("private-db").is_none() {
+            unregistered = true;
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert!(unregistered, "Private proxy stats were not unregistered");
+
+    visitor_client.abort();
+}
@@ -0,0 +1,142 @@
+/// 协议规范的 golden 文件测试
+///
+/// `tests/fixtures/protocol/` 下每个控制消息的规范报文（控制帧的消息体加换行）必须与当前序列化结果
+/// 逐字节一致，并且能按载荷类型解析后原样编码回来；stream 前导和帧的规范文件同时保存字段和编码。
+/// `docs/PROTOCOL.md` 由 [`spec::render_markdown`] 生成。线上格式有意变化时以 `UPDATE_GOLDEN=1`
+/// 运行本测试重新生成这些文件，并在评审中确认差异
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tls_tunnel::control_protocol::ControlMethod;
+use tls_tunnel::protocol::spec;
+
+fn fixture_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/protocol")
+}
+
+fn update_golden() -> bool {
+    std::env::var_os("UPDATE_GOLDEN").is_some()
+}
+
+/// 读取 golden 文件，`UPDATE_GOLDEN` 设置时先以期望内容覆盖
+fn golden(path: &Path, expected: &[u8]) -> Vec<u8> {
+    if update_golden() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, expected).unwrap();
+    }
+    std::fs::read(path).unwrap_or_else(|e| {
+        panic!(
+            "{}: {} (run with UPDATE_GOLDEN=1 to generate)",
+            path.display(),
+            e
+        )
+    })
+}
+
+fn with_newline(mut bytes: Vec<u8>) -> Vec<u8> {
+    bytes.push(b'\n');
+    bytes
+}
+
+#[test]
+fn test_message_fixtures_match_serialization() {
+    for message in spec::messages() {
+        let path = fixture_dir().join(format!("{}.json", message.fixture));
+        let expected = with_newline(message.example());
+        let actual = golden(&path, &expected);
+        assert_eq!(
+            String::from_utf8_lossy(&actual),
+            String::from_utf8_lossy(&expected),
+            "wire format of {} changed",
+            message.fixture
+        );
+    }
+}
+
+#[test]
+fn test_message_fixtures_round_trip() {
+    for message in spec::messages() {
+        let path = fixture_dir().join(format!("{}.json", message.fixture));
+        let fixture = golden(&path, &with_newline(message.example()));
+        let body = fixture.strip_suffix(b"\n").unwrap_or(&fixture);
+        let reencoded = message
+            .reencode(body)
+            .unwrap_or_else(|e| panic!("{} does not decode: {}", message.fixture, e));
+        assert_eq!(
+            String::from_utf8_lossy(&reencoded),
+            String::from_utf8_lossy(body),
+            "{} does not round-trip through its payload type",
+            message.fixture
+        );
+    }
+}
+
+#[test]
+fn test_framing_fixtures_match_encoding() {
+    for framing in spec::framings() {
+        let path = fixture_dir().join(format!("{}.json", framing.fixture));
+        let expected = with_newline(serde_json::to_vec_pretty(&framing.example()).unwrap());
+        let actual = golden(&path, &expected);
+        assert_eq!(
+            String::from_utf8_lossy(&actual),
+            String::from_utf8_lossy(&expected),
+            "encoding of {} changed",
+            framing.fixture
+        );
+
+        // 规范文件中的字段按类型解析后编码出相同的字节
+        let fixture: serde_json::Value = serde_json::from_slice(&actual).unwrap();
+        let encoded = framing.encode(&fixture["fields"]).unwrap();
+        assert_eq!(
+            tls_tunnel::identity::to_hex(&encoded),
+            fixture["encoded"].as_str().unwrap(),
+            "{} fields do not encode to the recorded bytes",
+            framing.fixture
+        );
+    }
+}
+
+#[test]
+fn test_every_method_is_specified() {
+    let messages = spec::messages();
+    for method in ControlMethod::ALL {
+        let specified = messages.iter().any(|m| m.method == Some(method));
+        let reserved = spec::RESERVED_METHODS.contains(&method);
+        assert!(
+            specified != reserved,
+            "{} must be either specified or reserved",
+            method.as_str()
+        );
+    }
+}
+
+#[test]
+fn test_fixture_names_are_unique_and_tracked() {
+    let mut names = HashSet::new();
+    for name in spec::messages()
+        .iter()
+        .map(|m| m.fixture)
+        .chain(spec::framings().iter().map(|f| f.fixture))
+    {
+        assert!(names.insert(name), "duplicate fixture name {}", name);
+    }
+
+    // 目录中不应残留已不再生成的规范文件
+    for entry in std::fs::read_dir(fixture_dir()).unwrap() {
+        let file_name = entry.unwrap().file_name();
+        let file_name = file_name.to_string_lossy();
+        let stem = file_name.strip_suffix(".json").unwrap_or(&file_name);
+        assert!(names.contains(stem), "stale fixture {}", file_name);
+    }
+}
+
+#[test]
+fn test_protocol_doc_is_generated_from_spec() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("docs/PROTOCOL.md");
+    let expected = spec::render_markdown();
+    let actual = golden(&path, expected.as_bytes());
+    assert_eq!(
+        String::from_utf8_lossy(&actual),
+        expected,
+        "docs/PROTOCOL.md is out of date"
+    );
+}
@@ -0,0 +1,202 @@
+/// Protocol trace tests
+///
+/// 服务器和客户端都开启协议跟踪，经过认证、配置提交、代理连接和 visitor 连接后，
+/// 分析两端的跟踪文件还原出一致的时间线
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+    VisitorConfig,
+};
+use tls_tunnel::protocol_trace::analyze::{analyze_file, Outcome, Phase, SessionTimeline};
+use tls_tunnel::protocol_trace::TraceSide;
+use tokio::time::sleep;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-protocol-trace-key";
+
+fn trace_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "tls-tunnel-trace-{}-{}",
+        std::process::id(),
+        common::get_available_port()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// 等待时间线中出现满足条件的步骤
+async fn wait_for_step(path: &Path, label: &str, outcome: &Outcome) -> SessionTimeline {
+    for _ in 0..50 {
+        if let Ok(analysis) = analyze_file(path) {
+            if let Some(session) = analysis.sessions.into_iter().next() {
+                if session
+                    .steps
+                    .iter()
+                    .any(|s| s.label == label && &s.outcome == outcome)
+                {
+                    return session;
+                }
+            }
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    panic!(
+        "Step '{}' ({:?}) never appeared in {:?}",
+        label, outcome, path
+    );
+}
+
+/// 经指定端口发送数据，收到回显时返回 true
+async fn echo_through(port: u16) -> bool {
+    for _ in 0..50 {
+        if let Ok(data) =
+            common::test_proxy_connection(port, b"trace", Duration::from_secs(2)).await
+        {
+            if data == b"trace" {
+                return true;
+            }
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn test_trace_reconstructs_session_timeline() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let visitor_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+
+    let dir = trace_dir();
+    let server_trace = dir.join("server.jsonl");
+    let client_trace = dir.join("client.jsonl");
+
+    let server = common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        protocol_trace_path: Some(server_trace.clone()),
+        ..common::test_server_config()
+    })
+    .await;
+
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.clone()),
+            auth_key: AUTH_KEY.to_string(),
+            protocol_trace_path: Some(client_trace.clone()),
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "echo".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![VisitorConfig {
+            name: "echo".to_string(),
+            proxy_type: ProxyType::Tcp,
+            bind_addr: "127.0.0.1".to_string(),
+            bind_port: visitor_port,
+            publish_port,
+            expected_peer_id: None,
+            connection_reuse: false,
+            socket: None,
+        }],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config =
+        tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None).unwrap();
+    let connector = TlsConnector::from(tls_config);
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client(client_config, connector)
+            .await
+            .ok();
+    });
+
+    // 分别经发布端口和 visitor 访问代理
+    assert!(
+        echo_through(publish_port).await,
+        "Proxy never became reachable"
+    );
+    assert!(
+        echo_through(visitor_port).await,
+        "Visitor never became reachable"
+    );
+
+    let visitor_label = format!("visitor echo:{}", publish_port);
+    let client_session = wait_for_step(&client_trace, &visitor_label, &Outcome::Ok).await;
+    let server_session = wait_for_step(&server_trace, &visitor_label, &Outcome::Ok).await;
+
+    client.abort();
+    server.shutdown().await.ok();
+
+    for (session, side) in [
+        (&client_session, TraceSide::Client),
+        (&server_session, TraceSide::Server),
+    ] {
+        assert_eq!(session.side, side);
+        assert_eq!(session.session, 1);
+        assert!(session.anomalies.is_empty(), "{:?}", session.anomalies);
+
+        // 认证前先完成一次身份挑战
+        let challenge = &session.steps[0];
+        assert_eq!(challenge.phase, Phase::Auth);
+        assert_eq!(challenge.label, "identity_challenge");
+        assert_eq!(challenge.outcome, Outcome::Ok);
+
+        let auth = &session.steps[1];
+        assert_eq!(auth.phase, Phase::Auth);
+        assert_eq!(auth.label, "authenticate");
+        assert_eq!(auth.outcome, Outcome::Ok);
+
+        let config = &session.steps[2];
+        assert_eq!(config.phase, Phase::Config);
+        assert_eq!(config.label, "submit_config");
+        assert_eq!(config.outcome, Outcome::Ok);
+    }
+
+    // 服务器打开、客户端接收的代理 stream
+    let proxy_label = format!("proxy echo:{}", publish_port);
+    assert!(server_session
+        .steps
+        .iter()
+        .any(|s| s.label == proxy_label && s.outcome == Outcome::Ok));
+    assert!(client_session
+        .steps
+        .iter()
+        .any(|s| s.label == proxy_label && s.outcome == Outcome::Ok));
+
+    // 跟踪中不包含认证密钥
+    let raw = std::fs::read_to_string(&client_trace).unwrap();
+    assert!(!raw.contains(AUTH_KEY));
+    let raw = std::fs::read_to_string(&server_trace).unwrap();
+    assert!(!raw.contains(AUTH_KEY));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,240 @@
+/// Proxy drain tests
+///
+/// 停用代理后发布端口立即不再接受新连接，已有的转发继续到结束；
+/// 超过排空超时仍未结束的转发被关闭，代理随后从统计中移除
+mod common;
+
+use serde_json::Value;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+};
+use tls_tunnel::server::ServerHandle;
+use tls_tunnel::stats_http::StatsEndpoint;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-proxy-drain-key";
+
+/// 慢速传输的分块数和分块大小
+const CHUNKS: usize = 40;
+const CHUNK_SIZE: usize = 1024;
+
+/// 本地服务：每个连接先读取一个字节，然后慢速写出 CHUNKS 个分块后关闭
+async fn start_slow_server(port: u16) -> JoinHandle<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut start = [0u8; 1];
+                if stream.read_exact(&mut start).await.is_err() {
+                    return;
+                }
+                for _ in 0..CHUNKS {
+                    if stream.write_all(&[0x5a; CHUNK_SIZE]).await.is_err() {
+                        return;
+                    }
+                    sleep(Duration::from_millis(50)).await;
+                }
+            });
+        }
+    })
+}
+
+/// 启动服务器和注册了单个代理的客户端，返回服务器句柄、客户端任务和服务器统计端点
+async fn start_tunnel(
+    publish_port: u16,
+    local_port: u16,
+    drain_timeout_secs: Option<u64>,
+    certs: &(std::path::PathBuf, std::path::PathBuf),
+) -> (ServerHandle, JoinHandle<()>, StatsEndpoint) {
+    let (cert_path, key_path) = certs;
+    let stats_port = common::get_available_port();
+
+    let server_config = ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        stats_port: Some(stats_port),
+        ..common::test_server_config()
+    };
+    let server = common::spawn_server(server_config).await;
+
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.clone()),
+            auth_key: AUTH_KEY.to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "web".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client(client_config, connector)
+            .await
+            .ok();
+    });
+
+    let endpoint = StatsEndpoint::Http(format!("http://127.0.0.1:{}", stats_port));
+    (server, client, endpoint)
+}
+
+/// 连接发布端口并开始传输（随后关闭写方向），读到第一批数据时返回
+async fn open_transfer(publish_port: u16) -> (TcpStream, usize) {
+    for _ in 0..50 {
+        if let Ok(mut stream) = TcpStream::connect(("127.0.0.1", publish_port)).await {
+            let mut buf = [0u8; CHUNK_SIZE];
+            if stream.write_all(b"g").await.is_ok() && stream.shutdown().await.is_ok() {
+                if let Ok(Ok(n)) = timeout(Duration::from_secs(2), stream.read(&mut buf)).await {
+                    if n > 0 {
+                        return (stream, n);
+                    }
+                }
+            }
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Proxy never became reachable");
+}
+
+/// 读取 /stats 中名为 `name` 的条目
+async fn stats_entry(endpoint: &StatsEndpoint, name: &str) -> Option<Value> {
+    let body = endpoint.get("/stats").await.ok()?;
+    let stats: Vec<Value> = serde_json::from_str(&body).ok()?;
+    stats.into_iter().find(|s| s["name"] == name)
+}
+
+/// 等待发布端口拒绝新连接
+async fn wait_until_refused(publish_port: u16) -> bool {
+    for _ in 0..50 {
+        if TcpStream::connect(("127.0.0.1", publish_port))
+            .await
+            .is_err()
+        {
+            return true;
+        }
+        sleep(Duration::from_millis(50)).await;
+    }
+    false
+}
+
+/// 等待代理从统计中移除
+async fn wait_until_removed(endpoint: &StatsEndpoint, name: &str) -> bool {
+    for _ in 0..50 {
+        if stats_entry(endpoint, name).await.is_none() {
+            return true;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn test_disabled_proxy_drains_active_transfer() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let certs = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(certs.0.clone(), certs.1.clone());
+    let _local = start_slow_server(local_port).await;
+
+    let (server, client, endpoint) = start_tunnel(publish_port, local_port, None, &certs).await;
+    let (mut transfer, mut received) = open_transfer(publish_port).await;
+
+    assert!(server.disable_proxy("web", publish_port).await);
+    assert!(!server.disable_proxy("web", publish_port).await);
+
+    // 不再接受新连接，统计中显示为 Draining
+    assert!(
+        wait_until_refused(publish_port).await,
+        "Publish port still accepts connections"
+    );
+    let entry = stats_entry(&endpoint, "web")
+        .await
+        .expect("Draining proxy missing from stats");
+    assert_eq!(entry["status"], "Draining");
+
+    // 已有的传输完整结束
+    let mut buf = [0u8; CHUNK_SIZE];
+    loop {
+        let n = timeout(Duration::from_secs(5), transfer.read(&mut buf))
+            .await
+            .expect("Transfer stalled while draining")
+            .expect("Transfer failed while draining");
+        if n == 0 {
+            break;
+        }
+        received += n;
+    }
+    assert_eq!(received, CHUNKS * CHUNK_SIZE);
+
+    // 转发结束后代理被移除
+    assert!(wait_until_removed(&endpoint, "web").await);
+
+    client.abort();
+    server.shutdown().await.ok();
+}
+
+#[tokio::test]
+async fn test_drain_timeout_closes_remaining_relays() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let certs = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(certs.0.clone(), certs.1.clone());
+    let _local = start_slow_server(local_port).await;
+
+    // 传输需要约 2 秒，排空超时为 1 秒
+    let (server, client, endpoint) = start_tunnel(publish_port, local_port, Some(1), &certs).await;
+    let (mut transfer, mut received) = open_transfer(publish_port).await;
+
+    assert!(server.disable_proxy("web", publish_port).await);
+
+    let mut buf = [0u8; CHUNK_SIZE];
+    let closed = timeout(Duration::from_secs(5), async {
+        loop {
+            match transfer.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => received += n,
+            }
+        }
+    })
+    .await;
+    assert!(closed.is_ok(), "Relay was not closed after drain timeout");
+    assert!(received < CHUNKS * CHUNK_SIZE);
+
+    assert!(wait_until_removed(&endpoint, "web").await);
+    assert!(!server.disable_proxy("web", publish_port).await);
+
+    client.abort();
+    server.shutdown().await.ok();
+}
@@ -0,0 +1,243 @@
+/// Proxy registration atomicity tests
+///
+/// 服务器先绑定所有代理端口，再写入注册表并确认配置：
+/// 绑定失败或发布地址无效的代理出现在配置响应的拒绝列表中（附带原因），
+/// 注册表只包含实际在监听的代理
+mod common;
+
+use std::time::Duration;
+use tls_tunnel::client::SessionEvent;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+};
+use tls_tunnel::server::ServerDependencies;
+use tls_tunnel::stats::StatsManager;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+fn proxy(name: &str, publish_port: u16, local_port: u16) -> ProxyConfig {
+    ProxyConfig {
+        name: name.to_string(),
+        proxy_type: ProxyType::Tcp,
+        publish_addr: "127.0.0.1".to_string(),
+        publish_port,
+        local_port,
+        pool: None,
+        shared: false,
+        weight: None,
+        visibility: ProxyVisibility::Public,
+        drain_timeout_secs: None,
+        sni_routing: None,
+        socket: None,
+        local_targets: None,
+        idle_keepalive_secs: None,
+        require_first_byte_timeout_ms: None,
+        max_connections_per_source: None,
+        report_peers: false,
+        ws_idle_timeout_secs: None,
+        integrity_check: false,
+    }
+}
+
+#[tokio::test]
+async fn test_bind_failure_is_rejected_in_config_response() {
+    let port_a = common::get_available_port();
+    let port_b = common::get_available_port();
+    let local_port = common::get_available_port();
+    let auth_key = "test-registration-key";
+
+    // 占用一个公开端口，迫使该代理绑定失败
+    let occupied = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port_busy = occupied.local_addr().unwrap().port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server_config = ServerConfig {
+        auth_key: auth_key.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        ..common::test_server_config()
+    };
+    let deps = ServerDependencies::new();
+    let registry = deps.proxy_registry.clone();
+    let stats_manager: StatsManager = deps.stats_manager.clone();
+
+    let server = common::spawn_server_with_dependencies(server_config, deps).await;
+    let server_port = server.bound_addr().port();
+
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.clone()),
+            auth_key: auth_key.to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![
+            proxy("first", port_a, local_port),
+            proxy("occupied", port_busy, local_port),
+            proxy("second", port_b, local_port),
+        ],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+
+    let (events, mut rx) = broadcast::channel(tls_tunnel::client::SESSION_EVENT_CAPACITY);
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client_with_events(client_config, connector, events)
+            .await
+            .ok();
+    });
+
+    // 配置响应中列出绑定失败的代理
+    let reason = timeout(Duration::from_secs(10), async {
+        loop {
+            match rx.recv().await.expect("Session event channel closed") {
+                SessionEvent::Degraded(reason) => break reason,
+                SessionEvent::Disconnected(reason) => panic!("Session ended: {}", reason),
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("Timed out waiting for partial rejection");
+    assert!(reason.contains(&format!("occupied:{}", port_busy)));
+    assert!(!reason.contains("first"));
+    assert!(!reason.contains("second"));
+
+    // 注册表和统计只包含两个实际监听的代理
+    {
+        let keys: Vec<_> = registry
+            .list()
+            .into_iter()
+            .map(|p| (p.proxy_info.name, p.proxy_info.publish_port))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                ("first".to_string(), port_a),
+                ("second".to_string(), port_b)
+            ]
+        );
+    }
+    assert!(stats_manager.get_proxy_stats("first").is_some());
+    assert!(stats_manager.get_proxy_stats("occupied").is_none());
+
+    // 确认时监听器已经就绪
+    assert!(tokio::net::TcpStream::connect(("127.0.0.1", port_a))
+        .await
+        .is_ok());
+    assert!(tokio::net::TcpStream::connect(("127.0.0.1", port_b))
+        .await
+        .is_ok());
+
+    // 会话结束后注册表被清空
+    client_handle.abort();
+    let cleaned = timeout(Duration::from_secs(10), async {
+        while !registry.is_empty() {
+            sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await;
+    assert!(cleaned.is_ok(), "Registry should be cleaned up");
+
+    server.shutdown().await.ok();
+    drop(occupied);
+}
+
+#[tokio::test]
+async fn test_unassigned_publish_addr_is_rejected_with_reason() {
+    let port_ok = common::get_available_port();
+    let port_bogus = common::get_available_port();
+    let local_port = common::get_available_port();
+    let auth_key = "test-registration-key";
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server_config = ServerConfig {
+        auth_key: auth_key.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        ..common::test_server_config()
+    };
+    let deps = ServerDependencies::new();
+    let registry = deps.proxy_registry.clone();
+
+    let server = common::spawn_server_with_dependencies(server_config, deps).await;
+    let server_port = server.bound_addr().port();
+
+    // 192.0.2.0/24 是文档保留网段，不会分配给本机网卡
+    let mut bogus = proxy("bogus", port_bogus, local_port);
+    bogus.publish_addr = "192.0.2.123".to_string();
+    let mut loopback = proxy("loopback", port_ok, local_port);
+    loopback.publish_addr = "localhost".to_string();
+
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.clone()),
+            auth_key: auth_key.to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![loopback, bogus],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+
+    let (events, mut rx) = broadcast::channel(tls_tunnel::client::SESSION_EVENT_CAPACITY);
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client_with_events(client_config, connector, events)
+            .await
+            .ok();
+    });
+
+    // 配置响应中带有具体的拒绝原因
+    let reason = timeout(Duration::from_secs(10), async {
+        loop {
+            match rx.recv().await.expect("Session event channel closed") {
+                SessionEvent::Degraded(reason) => break reason,
+                SessionEvent::Disconnected(reason) => panic!("Session ended: {}", reason),
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("Timed out waiting for publish_addr rejection");
+    assert!(
+        reason.contains(&format!(
+            "bogus:{} (Address 192.0.2.123 is not assigned to any local interface",
+            port_bogus
+        )),
+        "Unexpected rejection: {}",
+        reason
+    );
+    assert!(
+        reason.contains("127.0.0.1 ("),
+        "Should list local addresses"
+    );
+    assert!(!reason.contains("loopback"));
+
+    // localhost 被规范化为 127.0.0.1 并正常监听
+    assert!(registry.lookup("loopback", port_ok).is_some());
+    assert!(tokio::net::TcpStream::connect(("127.0.0.1", port_ok))
+        .await
+        .is_ok());
+
+    client_handle.abort();
+    server.shutdown().await.ok();
+}
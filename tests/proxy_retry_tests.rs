@@ -0,0 +1,208 @@
+/// Rejected proxy retry tests
+///
+/// 发布端口被占用时代理被服务器拒绝，客户端配置 `proxy_retry` 后在同一会话中
+/// 通过增量配置更新重新提交，端口释放后代理无需重连即可上线
+mod common;
+
+use std::time::Duration;
+use tls_tunnel::client::SessionEvent;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyRetryConfig, ProxyType, ProxyVisibility,
+    ServerConfig,
+};
+use tls_tunnel::stats_http::StatsEndpoint;
+use tokio::sync::broadcast;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-proxy-retry-key";
+
+/// 等待满足条件的事件，期间出现断开或重连则失败
+async fn expect_event(
+    rx: &mut broadcast::Receiver<SessionEvent>,
+    matches: impl Fn(&SessionEvent) -> bool,
+) {
+    timeout(Duration::from_secs(10), async {
+        loop {
+            let event = rx.recv().await.expect("Session event channel closed");
+            assert!(
+                !matches!(
+                    event,
+                    SessionEvent::Disconnected(_) | SessionEvent::Reconnecting { .. }
+                ),
+                "Unexpected event: {:?}",
+                event
+            );
+            if matches(&event) {
+                return;
+            }
+        }
+    })
+    .await
+    .expect("Timed out waiting for session event");
+}
+
+/// 读取代理在统计信息中的状态
+async fn proxy_status(endpoint: &StatsEndpoint, name: &str) -> Option<String> {
+    let body = endpoint.get("/stats").await.ok()?;
+    let stats: Vec<serde_json::Value> = serde_json::from_str(&body).ok()?;
+    stats
+        .iter()
+        .find(|s| s["name"] == name)
+        .and_then(|s| s["status"].as_str().map(str::to_string))
+}
+
+#[tokio::test]
+async fn test_rejected_proxy_is_retried_without_reconnect() {
+    let publish_port = common::get_available_port();
+    let other_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let stats_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+
+    // 其他进程占用了 web 的发布端口（全部代理被拒绝时会话会结束，这里另配置一个正常的代理）
+    let blocker = std::net::TcpListener::bind(("127.0.0.1", publish_port)).unwrap();
+
+    let server_config = ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        ..common::test_server_config()
+    };
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
+
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.clone()),
+            auth_key: AUTH_KEY.to_string(),
+            stats_port: Some(stats_port),
+            stats_addr: Some("127.0.0.1".to_string()),
+            proxy_retry: Some(ProxyRetryConfig {
+                interval_secs: 1,
+                max_interval_secs: 2,
+            }),
+            ..common::test_client_config()
+        },
+        proxies: vec![
+            ProxyConfig {
+                name: "web".to_string(),
+                proxy_type: ProxyType::Tcp,
+                publish_addr: "127.0.0.1".to_string(),
+                publish_port,
+                local_port,
+                pool: None,
+                shared: false,
+                weight: None,
+                visibility: ProxyVisibility::Public,
+                drain_timeout_secs: None,
+                sni_routing: None,
+                socket: None,
+                local_targets: None,
+                idle_keepalive_secs: None,
+                require_first_byte_timeout_ms: None,
+                max_connections_per_source: None,
+                report_peers: false,
+                ws_idle_timeout_secs: None,
+                integrity_check: false,
+            },
+            ProxyConfig {
+                name: "other".to_string(),
+                proxy_type: ProxyType::Tcp,
+                publish_addr: "127.0.0.1".to_string(),
+                publish_port: other_port,
+                local_port,
+                pool: None,
+                shared: false,
+                weight: None,
+                visibility: ProxyVisibility::Public,
+                drain_timeout_secs: None,
+                sni_routing: None,
+                socket: None,
+                local_targets: None,
+                idle_keepalive_secs: None,
+                require_first_byte_timeout_ms: None,
+                max_connections_per_source: None,
+                report_peers: false,
+                ws_idle_timeout_secs: None,
+                integrity_check: false,
+            },
+        ],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let (events_tx, mut events_rx) = broadcast::channel(64);
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client_with_events(client_config, connector, events_tx)
+            .await
+            .ok();
+    });
+
+    // 代理被拒绝，会话降级运行
+    expect_event(&mut events_rx, |e| matches!(e, SessionEvent::Degraded(_))).await;
+
+    // 统计中显示为等待重试
+    let endpoint = StatsEndpoint::Http(format!("http://127.0.0.1:{}", stats_port));
+    let mut status = None;
+    for _ in 0..50 {
+        status = proxy_status(&endpoint, "web").await;
+        if status.as_deref().is_some_and(|s| s.starts_with("pending")) {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    let status = status.expect("Proxy missing from stats");
+    assert!(
+        status.starts_with("pending (rejected: "),
+        "Unexpected status: {}",
+        status
+    );
+
+    // 释放端口后代理在下一次重试时上线
+    drop(blocker);
+    let mut response = None;
+    for _ in 0..50 {
+        // 隧道就绪之前的连接可能直接被关闭（响应为空）
+        if let Some(data) =
+            common::test_proxy_connection(publish_port, b"hello retry", Duration::from_secs(2))
+                .await
+                .ok()
+                .filter(|data| !data.is_empty())
+        {
+            response = Some(data);
+            break;
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+    assert_eq!(response.as_deref(), Some(&b"hello retry"[..]));
+
+    // 整个过程中会话没有断开
+    while let Ok(event) = events_rx.try_recv() {
+        assert!(
+            !matches!(
+                event,
+                SessionEvent::Disconnected(_) | SessionEvent::Reconnecting { .. }
+            ),
+            "Unexpected event: {:?}",
+            event
+        );
+    }
+    let status = proxy_status(&endpoint, "web").await.unwrap();
+    assert!(
+        !status.starts_with("pending"),
+        "Unexpected status: {}",
+        status
+    );
+
+    client_handle.abort();
+}
@@ -0,0 +1,214 @@
+/// Relay memory budget tests
+///
+/// 服务器和客户端配置 `relay_memory_budget_mb` 后，大量并发的慢连接从预算领取转发缓冲区：
+/// 记账的字节数从不超过预算，预算紧张时缓冲区缩小或等待释放，所有连接都完整收到回显
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+};
+use tls_tunnel::relay_memory::RelayMemoryStats;
+use tls_tunnel::stats_http::StatsEndpoint;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-relay-memory-key";
+
+/// 预算（MB）
+const BUDGET_MB: u64 = 1;
+
+const BUDGET_BYTES: u64 = BUDGET_MB * 1024 * 1024;
+
+/// 并发连接数（远超预算能容纳的默认大小缓冲区）
+const CONNECTIONS: usize = 160;
+
+/// 每个连接发送的块数和块大小
+const CHUNKS: usize = 8;
+const CHUNK_SIZE: usize = 4096;
+
+/// 经代理慢速发送数据并读回回显
+async fn slow_echo(publish_port: u16, seed: u8) -> Result<(), String> {
+    let stream = TcpStream::connect(("127.0.0.1", publish_port))
+        .await
+        .map_err(|e| format!("Failed to connect: {}", e))?;
+    let (mut reader, mut writer) = stream.into_split();
+    let expected: Vec<u8> = (0..CHUNKS * CHUNK_SIZE)
+        .map(|i| seed.wrapping_add(i as u8))
+        .collect();
+
+    let data = expected.clone();
+    let write = async move {
+        for chunk in data.chunks(CHUNK_SIZE) {
+            writer.write_all(chunk).await?;
+            sleep(Duration::from_millis(20)).await;
+        }
+        Ok::<_, std::io::Error>(writer)
+    };
+    let read = async move {
+        let mut received = vec![0u8; expected.len()];
+        reader.read_exact(&mut received).await?;
+        Ok::<_, std::io::Error>(received == expected)
+    };
+    let (write, read) = tokio::join!(write, read);
+    write.map_err(|e| format!("Write failed: {}", e))?;
+    match read {
+        Ok(true) => Ok(()),
+        Ok(false) => Err("Echo does not match".to_string()),
+        Err(e) => Err(format!("Read failed: {}", e)),
+    }
+}
+
+/// 读取客户端 `/tunnel` 中的转发缓冲区用量
+async fn client_relay_memory(endpoint: &StatsEndpoint) -> RelayMemoryStats {
+    let body = endpoint
+        .get("/tunnel")
+        .await
+        .expect("Failed to read /tunnel");
+    let tunnel: serde_json::Value = serde_json::from_str(&body).unwrap();
+    serde_json::from_value(tunnel["relay_memory"].clone()).unwrap()
+}
+
+#[tokio::test]
+async fn test_concurrent_slow_transfers_stay_within_budget() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let stats_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+
+    let server = common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        relay_memory_budget_mb: Some(BUDGET_MB),
+        ..common::test_server_config()
+    })
+    .await;
+    let server_memory = server.stats().relay_memory().clone();
+
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.clone()),
+            auth_key: AUTH_KEY.to_string(),
+            stats_port: Some(stats_port),
+            stats_addr: Some("127.0.0.1".to_string()),
+            keep_listening_on_disconnect: false,
+            relay_memory_budget_mb: Some(BUDGET_MB),
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "echo".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client(client_config, connector)
+            .await
+            .ok();
+    });
+
+    let mut ready = false;
+    for _ in 0..50 {
+        if slow_echo(publish_port, 0).await.is_ok() {
+            ready = true;
+            break;
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+    assert!(ready, "Proxy did not come up");
+
+    // 传输期间持续采样服务器的记账
+    let done = Arc::new(AtomicBool::new(false));
+    let sampler = {
+        let memory = server_memory.clone();
+        let done = done.clone();
+        tokio::spawn(async move {
+            let mut samples = 0u64;
+            while !done.load(Ordering::SeqCst) {
+                let stats = memory.snapshot();
+                assert!(
+                    stats.used_bytes <= BUDGET_BYTES,
+                    "Accounted {} bytes over the budget",
+                    stats.used_bytes
+                );
+                samples += 1;
+                sleep(Duration::from_millis(5)).await;
+            }
+            samples
+        })
+    };
+
+    let transfers: Vec<_> = (0..CONNECTIONS)
+        .map(|i| tokio::spawn(slow_echo(publish_port, i as u8)))
+        .collect();
+    for (i, transfer) in transfers.into_iter().enumerate() {
+        let result = timeout(Duration::from_secs(120), transfer)
+            .await
+            .unwrap_or_else(|_| panic!("Transfer {} timed out", i))
+            .unwrap();
+        assert_eq!(result, Ok(()), "Transfer {} failed", i);
+    }
+    done.store(true, Ordering::SeqCst);
+    assert!(sampler.await.unwrap() > 0);
+
+    let stats = server_memory.snapshot();
+    assert_eq!(stats.budget_bytes, Some(BUDGET_BYTES));
+    assert!(stats.high_water_bytes <= BUDGET_BYTES, "{:?}", stats);
+    // 预算放不下全部默认大小的缓冲区：后来的连接缩小缓冲区，连下限都放不下时等待
+    assert!(stats.reduced_buffers > 0, "{:?}", stats);
+    assert!(stats.waits > 0, "{:?}", stats);
+    assert_eq!(stats.waiting, 0);
+
+    // 客户端同样在预算内（/tunnel 端点）
+    let endpoint = StatsEndpoint::Http(format!("http://127.0.0.1:{}", stats_port));
+    let client = client_relay_memory(&endpoint).await;
+    assert_eq!(client.budget_bytes, Some(BUDGET_BYTES));
+    assert!(client.high_water_bytes <= BUDGET_BYTES, "{:?}", client);
+    assert!(client.reduced_buffers > 0, "{:?}", client);
+
+    // 连接结束后缓冲区全部归还
+    for _ in 0..50 {
+        if server_memory.snapshot().used_bytes == 0 {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(server_memory.snapshot().used_bytes, 0);
+    assert_eq!(server_memory.snapshot().buffers, 0);
+
+    client_handle.abort();
+    server.shutdown().await.ok();
+}
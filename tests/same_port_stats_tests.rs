@@ -0,0 +1,242 @@
+/// Same-port stats tests
+///
+/// 配置 `stats_path` 后，同一个 http2/wss 监听端口既承载隧道会话，
+/// 也在该路径下提供统计页面
+mod common;
+
+use bytes::Bytes;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+};
+use tls_tunnel::server::{Server, ServerHandle};
+use tls_tunnel::transport::TransportType;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::sleep;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+const AUTH_KEY: &str = "test-same-port-stats-key";
+
+async fn spawn_server(
+    transport: TransportType,
+    cert_path: &std::path::Path,
+    key_path: &std::path::Path,
+) -> ServerHandle {
+    let config = ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        transport,
+        stats_path: Some("/stats".to_string()),
+        ..common::test_server_config()
+    };
+    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(
+        cert_path,
+        key_path,
+        Some(vec![b"h2".to_vec(), b"http/1.1".to_vec()]),
+    )
+    .unwrap();
+    Server::builder()
+        .config(config)
+        .acceptor(TlsAcceptor::from(tls_config))
+        .spawn()
+        .await
+        .expect("Failed to start server")
+}
+
+/// 启动客户端，发布一个 echo 代理
+fn spawn_client(
+    transport: TransportType,
+    server_port: u16,
+    cert_path: &std::path::Path,
+    publish_port: u16,
+    local_port: u16,
+) -> tokio::task::JoinHandle<()> {
+    let config = ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            server_path: "/tunnel".to_string(),
+            transport,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "echo".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let alpn = (transport == TransportType::Http2).then(|| vec![b"h2".to_vec()]);
+    let tls_config =
+        tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, alpn).unwrap();
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    })
+}
+
+async fn tls_connect(
+    port: u16,
+    cert_path: &std::path::Path,
+    alpn: Vec<Vec<u8>>,
+) -> tokio_rustls::client::TlsStream<TcpStream> {
+    let tls_config =
+        tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, Some(alpn)).unwrap();
+    let tcp = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    TlsConnector::from(tls_config)
+        .connect("localhost".try_into().unwrap(), tcp)
+        .await
+        .unwrap()
+}
+
+/// 经 HTTP/2 发送 GET 请求，返回状态码和响应体
+async fn h2_get(port: u16, cert_path: &std::path::Path, path: &str) -> (u16, String) {
+    let tls = tls_connect(port, cert_path, vec![b"h2".to_vec()]).await;
+    let (send_request, connection) = h2::client::handshake(tls).await.unwrap();
+    tokio::spawn(async move {
+        connection.await.ok();
+    });
+
+    let request = http::Request::get(format!("https://localhost{}", path))
+        .body(())
+        .unwrap();
+    let mut send_request = send_request.ready().await.unwrap();
+    let (response, _) = send_request.send_request(request, true).unwrap();
+    let response = response.await.unwrap();
+    let status = response.status().as_u16();
+
+    let mut body = response.into_body();
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk: Bytes = chunk.unwrap();
+        body.flow_control().release_capacity(chunk.len()).ok();
+        data.extend_from_slice(&chunk);
+    }
+    (status, String::from_utf8(data).unwrap())
+}
+
+/// 经 HTTP/1.1 发送 GET 请求，返回完整响应
+async fn http1_get(port: u16, cert_path: &std::path::Path, path: &str) -> String {
+    let mut tls = tls_connect(port, cert_path, vec![b"http/1.1".to_vec()]).await;
+    tls.write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes())
+        .await
+        .unwrap();
+    let mut response = String::new();
+    tls.read_to_string(&mut response).await.ok();
+    response
+}
+
+/// 经发布端口收到回显时返回 true
+async fn echo_through(port: u16) -> bool {
+    for _ in 0..50 {
+        if let Ok(data) =
+            common::test_proxy_connection(port, b"same-port", Duration::from_secs(2)).await
+        {
+            if data == b"same-port" {
+                return true;
+            }
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn test_http2_listener_serves_tunnel_and_stats() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+
+    let server = spawn_server(TransportType::Http2, &cert_path, &key_path).await;
+    let port = server.bound_addr().port();
+    let client = spawn_client(
+        TransportType::Http2,
+        port,
+        &cert_path,
+        publish_port,
+        local_port,
+    );
+
+    assert!(
+        echo_through(publish_port).await,
+        "Tunnel session never became usable"
+    );
+
+    let (status, body) = h2_get(port, &cert_path, "/stats").await;
+    assert_eq!(status, 200);
+    assert!(body.contains("TLS Tunnel"), "{}", body);
+
+    let (status, body) = h2_get(port, &cert_path, "/stats/stats").await;
+    assert_eq!(status, 200);
+    let stats: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert!(stats
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|s| s["name"] == "echo"));
+
+    // 统计请求不影响已有的隧道会话和新的连接
+    assert!(echo_through(publish_port).await);
+
+    client.abort();
+    server.shutdown().await.ok();
+}
+
+#[tokio::test]
+async fn test_wss_listener_serves_tunnel_and_stats() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+
+    let server = spawn_server(TransportType::Wss, &cert_path, &key_path).await;
+    let port = server.bound_addr().port();
+
+    let response = http1_get(port, &cert_path, "/stats/stats").await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+    let client = spawn_client(
+        TransportType::Wss,
+        port,
+        &cert_path,
+        publish_port,
+        local_port,
+    );
+    assert!(
+        echo_through(publish_port).await,
+        "Tunnel session never became usable"
+    );
+
+    let response = http1_get(port, &cert_path, "/stats/stats").await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains("\"echo\""), "{}", response);
+
+    client.abort();
+    server.shutdown().await.ok();
+}
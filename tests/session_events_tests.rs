@@ -0,0 +1,181 @@
+/// Session lifecycle event tests
+///
+/// 通过可随时关闭的 TCP 中继模拟服务器断开，验证客户端发布的会话事件顺序
+mod common;
+
+use std::time::Duration;
+use tls_tunnel::client::SessionEvent;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+/// 启动一个转发到 target_port 的 TCP 中继，abort 返回的任务即可断开所有经过它的连接
+async fn start_relay(listen_port: u16, target_port: u16) -> JoinHandle<()> {
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", listen_port))
+        .await
+        .expect("Failed to bind relay");
+
+    tokio::spawn(async move {
+        let mut connections = tokio::task::JoinSet::new();
+        while let Ok((mut inbound, _)) = listener.accept().await {
+            connections.spawn(async move {
+                if let Ok(mut outbound) =
+                    TcpStream::connect(format!("127.0.0.1:{}", target_port)).await
+                {
+                    let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+                }
+            });
+        }
+    })
+}
+
+/// 断言事件为 `Reconnecting`，等待时间在 `base` 的 ±20% 抖动范围内
+fn assert_reconnecting(event: SessionEvent, expected_attempt: u32, base: Duration) {
+    let SessionEvent::Reconnecting { attempt, delay } = event else {
+        panic!("Expected Reconnecting, got {:?}", event);
+    };
+    assert_eq!(attempt, expected_attempt);
+    assert!(
+        delay >= base.mul_f64(0.8) && delay < base.mul_f64(1.2),
+        "Reconnect delay {:?} is not within 20% of {:?}",
+        delay,
+        base
+    );
+}
+
+/// 等待下一个事件
+async fn next_event(rx: &mut broadcast::Receiver<SessionEvent>) -> SessionEvent {
+    timeout(Duration::from_secs(10), rx.recv())
+        .await
+        .expect("Timed out waiting for session event")
+        .expect("Session event channel closed")
+}
+
+#[tokio::test]
+async fn test_session_events_across_reconnect() {
+    std::env::set_var("TLS_TUNNEL_RECONNECT_DELAY_SECS", "1");
+
+    let relay_port = common::get_available_port();
+    let proxy_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let auth_key = "test-session-events-key";
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server_config = ServerConfig {
+        auth_key: auth_key.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        ..common::test_server_config()
+    };
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
+
+    let relay = start_relay(relay_port, server_port).await;
+
+    // 客户端经由中继连接服务器
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: relay_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.clone()),
+            auth_key: auth_key.to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "events-proxy".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port: proxy_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+
+    let (events, mut rx) = broadcast::channel(tls_tunnel::client::SESSION_EVENT_CAPACITY);
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client_with_events(client_config, connector, events)
+            .await
+            .ok();
+    });
+
+    // 1. 首次连接（Connected 带有解析后的服务器地址）
+    let relay_addr: std::net::SocketAddr = ([127, 0, 0, 1], relay_port).into();
+    assert_eq!(next_event(&mut rx).await, SessionEvent::Connecting);
+    assert_eq!(
+        next_event(&mut rx).await,
+        SessionEvent::Connected(relay_addr)
+    );
+    assert_eq!(next_event(&mut rx).await, SessionEvent::Authenticated);
+    assert_eq!(next_event(&mut rx).await, SessionEvent::ConfigAccepted);
+    assert_eq!(next_event(&mut rx).await, SessionEvent::Running);
+
+    // 2. 关闭中继，模拟服务器不可达
+    relay.abort();
+
+    assert!(matches!(
+        next_event(&mut rx).await,
+        SessionEvent::Disconnected(_)
+    ));
+    assert_reconnecting(next_event(&mut rx).await, 1, Duration::from_secs(1));
+
+    // 3. 中继关闭期间的重连失败
+    assert_eq!(next_event(&mut rx).await, SessionEvent::Connecting);
+    assert!(matches!(
+        next_event(&mut rx).await,
+        SessionEvent::Disconnected(_)
+    ));
+    assert_reconnecting(next_event(&mut rx).await, 2, Duration::from_secs(2));
+
+    // 4. 恢复中继后重新进入运行状态
+    let relay = start_relay(relay_port, server_port).await;
+    let mut sequence = Vec::new();
+    loop {
+        let event = next_event(&mut rx).await;
+        let done = event == SessionEvent::Running;
+        sequence.push(event);
+        if done {
+            break;
+        }
+    }
+    assert_eq!(
+        &sequence[sequence.len() - 5..],
+        &[
+            SessionEvent::Connecting,
+            SessionEvent::Connected(relay_addr),
+            SessionEvent::Authenticated,
+            SessionEvent::ConfigAccepted,
+            SessionEvent::Running,
+        ]
+    );
+
+    client_handle.abort();
+    relay.abort();
+    server.shutdown().await.ok();
+}
@@ -0,0 +1,238 @@
+/// Session limits tests
+///
+/// 服务器按 `max_connections_per_proxy` 限制发布端口的并发连接，按 `max_streams_per_client`
+/// 限制单个客户端会话同时转发的 stream；超出上限的连接被关闭，释放名额后恢复接入
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+};
+use tls_tunnel::server::ServerHandle;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-session-limits-key";
+const PROXY_NAME: &str = "limited";
+
+/// 服务器的上限设置
+struct Limits {
+    max_streams_per_client: Option<u32>,
+    max_connections_per_proxy: Option<u32>,
+    protocol_trace_path: Option<PathBuf>,
+}
+
+/// 启动带上限的服务器和注册代理的客户端，等待代理注册完成
+async fn start_tunnel(
+    cert_path: &Path,
+    key_path: &Path,
+    publish_port: u16,
+    local_port: u16,
+    limits: Limits,
+) -> (ServerHandle, JoinHandle<()>) {
+    let server = common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        protocol_trace_path: limits.protocol_trace_path,
+        max_streams_per_client: limits.max_streams_per_client,
+        max_connections_per_proxy: limits.max_connections_per_proxy,
+        ..common::test_server_config()
+    })
+    .await;
+
+    let config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: PROXY_NAME.to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    });
+
+    let stats = server.stats();
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            stats.get_proxy_stats(PROXY_NAME).is_some()
+        })
+        .await,
+        "Proxy was not registered"
+    );
+    (server, client)
+}
+
+/// 经发布端口建立一个完成往返的连接（保持打开，占用一个连接和一个 stream）
+async fn open_relayed(publish_port: u16, tag: u8) -> TcpStream {
+    let mut stream = TcpStream::connect(("127.0.0.1", publish_port))
+        .await
+        .expect("Failed to connect to publish port");
+    stream.write_all(&[tag; 4]).await.unwrap();
+    let mut echoed = [0u8; 4];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut echoed))
+        .await
+        .expect("Timed out waiting for echo")
+        .expect("Relayed connection was closed");
+    assert_eq!(echoed, [tag; 4]);
+    stream
+}
+
+/// 超出上限的连接被接受后关闭，发送的数据得不到回显
+async fn assert_refused(publish_port: u16) {
+    let mut stream = TcpStream::connect(("127.0.0.1", publish_port))
+        .await
+        .expect("Failed to connect to publish port");
+    stream.write_all(b"over").await.ok();
+    let mut buf = [0u8; 4];
+    let read = timeout(Duration::from_secs(5), stream.read(&mut buf))
+        .await
+        .expect("Connection over the limit was not closed");
+    assert!(
+        matches!(read, Ok(0) | Err(_)),
+        "Connection over the limit was relayed: {:?}",
+        read
+    );
+}
+
+#[tokio::test]
+async fn test_connections_over_proxy_limit_are_closed() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+    let (server, client) = start_tunnel(
+        &cert_path,
+        &key_path,
+        publish_port,
+        local_port,
+        Limits {
+            max_streams_per_client: None,
+            max_connections_per_proxy: Some(2),
+            protocol_trace_path: None,
+        },
+    )
+    .await;
+    let stats = server.stats();
+
+    let first = open_relayed(publish_port, 1).await;
+    let _second = open_relayed(publish_port, 2).await;
+    assert_refused(publish_port).await;
+
+    let proxy = stats.get_proxy_stats(PROXY_NAME).unwrap();
+    assert_eq!(proxy.core.connection_limited, 1);
+    assert_eq!(proxy.core.active_connections, 2);
+
+    // 释放一个名额后恢复接入
+    drop(first);
+    assert!(
+        common::wait_until(Duration::from_secs(5), || async {
+            stats
+                .get_proxy_stats(PROXY_NAME)
+                .is_some_and(|s| s.core.active_connections == 1)
+        })
+        .await,
+        "Closed connection still counted as active"
+    );
+    let _third = open_relayed(publish_port, 3).await;
+
+    client.abort();
+}
+
+#[tokio::test]
+async fn test_streams_over_client_limit_are_refused() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+    let trace_path = std::env::temp_dir().join(format!(
+        "tls-tunnel-stream-limit-{}-{}.jsonl",
+        std::process::id(),
+        publish_port
+    ));
+    let (server, client) = start_tunnel(
+        &cert_path,
+        &key_path,
+        publish_port,
+        local_port,
+        Limits {
+            max_streams_per_client: Some(2),
+            max_connections_per_proxy: Some(0),
+            protocol_trace_path: Some(trace_path.clone()),
+        },
+    )
+    .await;
+    let stats = server.stats();
+
+    let first = open_relayed(publish_port, 1).await;
+    let _second = open_relayed(publish_port, 2).await;
+    assert_refused(publish_port).await;
+
+    // 被拒绝的 stream 通过控制通道通知客户端
+    assert!(
+        common::wait_until(Duration::from_secs(5), || async {
+            std::fs::read_to_string(&trace_path)
+                .is_ok_and(|trace| trace.contains("STREAM_LIMIT_EXCEEDED"))
+        })
+        .await,
+        "Stream limit warning was not sent to the client"
+    );
+
+    // 发布端口不限制连接数，拒绝来自会话的 stream 上限
+    let proxy = stats.get_proxy_stats(PROXY_NAME).unwrap();
+    assert_eq!(proxy.core.connection_limited, 0);
+
+    // stream 随连接结束释放，新的连接恢复转发
+    drop(first);
+    assert!(
+        common::wait_until(Duration::from_secs(5), || async {
+            stats
+                .get_proxy_stats(PROXY_NAME)
+                .is_some_and(|s| s.core.active_connections == 1)
+        })
+        .await,
+        "Closed connection still counted as active"
+    );
+    let _third = open_relayed(publish_port, 3).await;
+
+    client.abort();
+    server.shutdown().await.ok();
+    std::fs::remove_file(&trace_path).ok();
+}
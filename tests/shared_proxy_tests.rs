@@ -0,0 +1,239 @@
+/// Shared proxy load balancing tests
+///
+/// 两个客户端以相同名称和端口注册共享代理：服务器按权重轮询分发连接，
+/// 一个客户端断开后其余后端继续提供服务，最后一个后端离开时关闭监听器
+mod common;
+
+use std::collections::HashSet;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+};
+use tls_tunnel::server::{Registry, Server, ServerDependencies};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+/// 启动本地服务：每个连接先写入一个标识字节，然后回显
+async fn start_tagged_service(tag: u8) -> (u16, JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                if socket.write_all(&[tag]).await.is_err() {
+                    return;
+                }
+                let (mut reader, mut writer) = socket.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+            });
+        }
+    });
+    (port, handle)
+}
+
+fn start_client(
+    server_port: u16,
+    auth_key: &str,
+    cert_path: &std::path::Path,
+    peer_id: &str,
+    publish_port: u16,
+    local_port: u16,
+) -> JoinHandle<()> {
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: auth_key.to_string(),
+            peer_id: Some(peer_id.to_string()),
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "shared-svc".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: true,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(client_config, connector)
+            .await
+            .ok();
+    })
+}
+
+/// 等待共享代理的后端数量达到 expected
+async fn wait_for_backends(registry: &Registry, publish_port: u16, expected: usize) {
+    let result = timeout(Duration::from_secs(10), async {
+        loop {
+            let count = registry
+                .lookup("shared-svc", publish_port)
+                .map_or(0, |proxy| proxy.backends);
+            if count == expected {
+                break;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await;
+    assert!(
+        result.is_ok(),
+        "Timed out waiting for {} backend(s)",
+        expected
+    );
+}
+
+/// 通过共享代理建立连接，返回后端标识并校验回显
+async fn request_tag(publish_port: u16) -> u8 {
+    let mut stream = TcpStream::connect(("127.0.0.1", publish_port))
+        .await
+        .expect("Failed to connect to shared proxy");
+
+    let mut tag = [0u8; 1];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut tag))
+        .await
+        .expect("Timed out reading backend tag")
+        .expect("Failed to read backend tag");
+
+    stream.write_all(b"ping").await.unwrap();
+    let mut echo = [0u8; 4];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut echo))
+        .await
+        .expect("Timed out reading echo")
+        .expect("Failed to read echo");
+    assert_eq!(&echo, b"ping");
+
+    tag[0]
+}
+
+#[tokio::test]
+async fn test_shared_proxy_balances_and_fails_over() {
+    let publish_port = common::get_available_port();
+    let auth_key = "test-shared-proxy-key";
+
+    let (local_a, service_a) = start_tagged_service(b'A').await;
+    let (local_b, service_b) = start_tagged_service(b'B').await;
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server_config = ServerConfig {
+        auth_key: auth_key.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        ..common::test_server_config()
+    };
+    let deps = ServerDependencies::new();
+    let registry = deps.proxy_registry.clone();
+    let stats_manager = deps.stats_manager.clone();
+
+    let tls_config = tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None)
+        .expect("Failed to load server TLS config");
+    let server = Server::builder()
+        .config(server_config)
+        .acceptor(TlsAcceptor::from(tls_config))
+        .dependencies(deps)
+        .spawn()
+        .await
+        .expect("Failed to start server");
+    let server_port = server.bound_addr().port();
+
+    // 1. 两个客户端注册同一个共享代理
+    let client_a = start_client(
+        server_port,
+        auth_key,
+        &cert_path,
+        "backend-a",
+        publish_port,
+        local_a,
+    );
+    wait_for_backends(&registry, publish_port, 1).await;
+    let client_b = start_client(
+        server_port,
+        auth_key,
+        &cert_path,
+        "backend-b",
+        publish_port,
+        local_b,
+    );
+    wait_for_backends(&registry, publish_port, 2).await;
+
+    // 2. 连接被分发到两个后端
+    let mut seen = HashSet::new();
+    for _ in 0..4 {
+        seen.insert(request_tag(publish_port).await);
+    }
+    assert_eq!(seen, HashSet::from([b'A', b'B']));
+
+    let stats = stats_manager
+        .get_proxy_stats("shared-svc")
+        .expect("Shared proxy stats should exist");
+    assert_eq!(stats.core.total_connections, 4);
+    let mut backends: Vec<_> = stats
+        .backends
+        .iter()
+        .map(|b| (b.id.clone(), b.total_connections))
+        .collect();
+    backends.sort();
+    assert_eq!(
+        backends,
+        vec![("backend-a".to_string(), 2), ("backend-b".to_string(), 2)]
+    );
+
+    // 3. 断开一个客户端后服务不中断，连接全部落到剩余后端
+    client_a.abort();
+    wait_for_backends(&registry, publish_port, 1).await;
+    for _ in 0..4 {
+        assert_eq!(request_tag(publish_port).await, b'B');
+    }
+    let stats = stats_manager.get_proxy_stats("shared-svc").unwrap();
+    assert_eq!(stats.backends.len(), 1);
+    assert_eq!(stats.backends[0].id, "backend-b");
+
+    // 4. 最后一个后端离开后移除注册表项并关闭监听器
+    client_b.abort();
+    let closed = timeout(Duration::from_secs(10), async {
+        loop {
+            let registered = registry.lookup("shared-svc", publish_port).is_some();
+            if !registered
+                && TcpStream::connect(("127.0.0.1", publish_port))
+                    .await
+                    .is_err()
+            {
+                break;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await;
+    assert!(closed.is_ok(), "Shared listener should shut down");
+
+    service_a.abort();
+    service_b.abort();
+}
@@ -0,0 +1,272 @@
+#![cfg(target_os = "linux")]
+
+/// Shutdown signal tests (Linux only)
+///
+/// 服务器和客户端进程收到 SIGTERM / SIGQUIT 时与 Ctrl+C 一样优雅停止：客户端关闭会话后
+/// 正常退出，服务器随即注销其代理；服务器停止接受连接后正常退出
+mod common;
+
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use tls_tunnel::config::ServerConfig;
+use tls_tunnel::server::{Server, ServerDependencies};
+use tokio::time::sleep;
+use tokio_rustls::TlsAcceptor;
+
+const AUTH_KEY: &str = "test-shutdown-signal-key";
+
+/// 结束时杀掉仍在运行的进程
+struct KillOnDrop(u32);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        unsafe {
+            libc::kill(self.0 as libc::pid_t, libc::SIGKILL);
+        }
+    }
+}
+
+/// 写入仅当前用户可读的配置文件
+fn write_config(name: &str, content: &str) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = std::env::temp_dir().join(format!(
+        "tls-tunnel-{}-test-{}.toml",
+        name,
+        std::process::id()
+    ));
+    std::fs::write(&path, content).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+    path
+}
+
+/// 启动 tls-tunnel 进程，日志逐行发送到返回的通道
+fn spawn_process(
+    command: &str,
+    config_path: &Path,
+    envs: &[(&str, &str)],
+) -> (Child, mpsc::Receiver<String>) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tls-tunnel"))
+        .args([command, "-c"])
+        .arg(config_path)
+        .env("RUST_LOG", "info")
+        .env("NO_COLOR", "1")
+        .envs(envs.iter().copied())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .expect("Failed to start process");
+
+    let stdout = child.stdout.take().unwrap();
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    (child, rx)
+}
+
+/// 发送信号并等待进程退出
+async fn signal_and_wait(child: &mut Child, signal: libc::c_int) -> ExitStatus {
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, signal);
+    }
+    let deadline = Instant::now() + Duration::from_secs(15);
+    loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            return status;
+        }
+        assert!(Instant::now() < deadline, "Process did not exit");
+        sleep(Duration::from_millis(50)).await;
+    }
+}
+
+/// 进程退出后读完剩余日志，检查是否出现指定内容
+fn logged(logs: &mpsc::Receiver<String>, needle: &str) -> bool {
+    while let Ok(line) = logs.recv_timeout(Duration::from_secs(5)) {
+        if line.contains(needle) {
+            return true;
+        }
+    }
+    false
+}
+
+fn server_config(cert_path: &Path, key_path: &Path) -> ServerConfig {
+    ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        ..common::test_server_config()
+    }
+}
+
+fn server_toml(server_port: u16, cert_path: &Path, key_path: &Path) -> String {
+    format!(
+        "[server]\nbind_addr = \"127.0.0.1\"\nbind_port = {}\nauth_key = \"{}\"\n\
+         cert_path = {:?}\nkey_path = {:?}\n",
+        server_port,
+        AUTH_KEY,
+        cert_path.display().to_string(),
+        key_path.display().to_string(),
+    )
+}
+
+#[tokio::test]
+async fn test_sigterm_stops_client_and_unregisters_proxy() {
+    let publish_port = common::get_available_port();
+    let echo_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(echo_port).await;
+
+    let deps = ServerDependencies::new();
+    let registry = deps.proxy_registry.clone();
+    let tls_config =
+        tls_tunnel::tls::load_server_config_with_alpn(&cert_path, &key_path, None).unwrap();
+    let server = Server::builder()
+        .config(server_config(&cert_path, &key_path))
+        .acceptor(TlsAcceptor::from(tls_config))
+        .dependencies(deps)
+        .spawn()
+        .await
+        .expect("Failed to start server");
+
+    let config_path = write_config(
+        "sigterm-client",
+        &format!(
+            "[client]\nserver_addr = \"127.0.0.1\"\nserver_port = {}\ntransport = \"tls\"\n\
+             skip_verify = true\nca_cert_path = {:?}\nauth_key = \"{}\"\n\n\
+             [[proxies]]\nname = \"echo\"\npublish_addr = \"127.0.0.1\"\n\
+             publish_port = {}\nlocal_port = {}\n",
+            server.bound_addr().port(),
+            cert_path.display().to_string(),
+            AUTH_KEY,
+            publish_port,
+            echo_port,
+        ),
+    );
+    let (mut client, logs) = spawn_process("client", &config_path, &[]);
+    let _kill = KillOnDrop(client.id());
+
+    let mut ready = false;
+    for _ in 0..100 {
+        if let Ok(data) =
+            common::test_proxy_connection(publish_port, b"ping", Duration::from_secs(2)).await
+        {
+            if data == b"ping" {
+                ready = true;
+                break;
+            }
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert!(ready, "Proxy never became reachable");
+    assert_eq!(registry.len(), 1);
+
+    let status = signal_and_wait(&mut client, libc::SIGTERM).await;
+    assert!(status.success(), "Client exited with {}", status);
+    assert!(logged(&logs, "Received SIGTERM, stopping client"));
+
+    // 服务器随即注销代理
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !registry.is_empty() {
+        assert!(Instant::now() < deadline, "Proxy was not unregistered");
+        sleep(Duration::from_millis(50)).await;
+    }
+
+    server.shutdown().await.ok();
+    let _ = std::fs::remove_file(&config_path);
+}
+
+#[tokio::test]
+async fn test_sigterm_and_sigquit_stop_server_gracefully() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    for (name, signal) in [("SIGTERM", libc::SIGTERM), ("SIGQUIT", libc::SIGQUIT)] {
+        let server_port = common::get_available_port();
+        let config_path = write_config(
+            &format!("{}-server", name.to_lowercase()),
+            &server_toml(server_port, &cert_path, &key_path),
+        );
+        let (mut server, logs) = spawn_process("server", &config_path, &[]);
+        let _kill = KillOnDrop(server.id());
+        assert!(
+            common::wait_for_server(server_port, 50).await,
+            "Server did not start listening"
+        );
+
+        let status = signal_and_wait(&mut server, signal).await;
+        assert!(
+            status.success(),
+            "Server exited with {} on {}",
+            status,
+            name
+        );
+        assert!(logged(
+            &logs,
+            &format!("Received {}, stopping server", name)
+        ));
+        let _ = std::fs::remove_file(&config_path);
+    }
+}
+
+/// 启用 `systemd` 特性时，服务器就绪后报告 READY=1，收到 SIGTERM 后报告 STOPPING=1
+#[cfg(feature = "systemd")]
+#[tokio::test]
+async fn test_server_notifies_systemd() {
+    use std::os::unix::net::UnixDatagram;
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let server_port = common::get_available_port();
+    let config_path = write_config(
+        "notify-server",
+        &server_toml(server_port, &cert_path, &key_path),
+    );
+
+    let socket_path = std::env::temp_dir().join(format!(
+        "tls-tunnel-notify-test-{}.sock",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&socket_path);
+    let notify_socket = UnixDatagram::bind(&socket_path).unwrap();
+    notify_socket
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    let receive = || {
+        let mut buf = [0u8; 256];
+        let n = notify_socket
+            .recv(&mut buf)
+            .expect("No notification received");
+        String::from_utf8_lossy(&buf[..n]).into_owned()
+    };
+
+    let (mut server, _logs) = spawn_process(
+        "server",
+        &config_path,
+        &[
+            ("INVOCATION_ID", "test"),
+            ("NOTIFY_SOCKET", socket_path.to_str().unwrap()),
+        ],
+    );
+    let _kill = KillOnDrop(server.id());
+
+    assert_eq!(receive(), "READY=1\n");
+    assert!(common::wait_for_server(server_port, 50).await);
+
+    let status = signal_and_wait(&mut server, libc::SIGTERM).await;
+    assert!(status.success(), "Server exited with {}", status);
+    assert_eq!(receive(), "STOPPING=1\n");
+
+    let _ = std::fs::remove_file(&socket_path);
+    let _ = std::fs::remove_file(&config_path);
+}
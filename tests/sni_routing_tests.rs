@@ -0,0 +1,194 @@
+/// SNI routing tests
+///
+/// 一个发布端口按外部客户端的 SNI 分发到多个本地 TLS 后端，
+/// 服务器只预读 ClientHello，TLS 由后端自己完成
+mod common;
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+    SniRoutingConfig,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, timeout};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+const AUTH_KEY: &str = "test-sni-routing-key";
+
+/// 为主机名生成独立的自签名证书
+fn backend_cert(host: &str) -> (PathBuf, PathBuf) {
+    let dir = std::env::temp_dir();
+    let id = format!("{}-{}", std::process::id(), host);
+    let cert_path = dir.join(format!("test-sni-cert-{}.pem", id));
+    let key_path = dir.join(format!("test-sni-key-{}.pem", id));
+    tls_tunnel::tls::generate_self_signed_cert(host, &[host.to_string()], &cert_path, &key_path)
+        .unwrap();
+    (cert_path, key_path)
+}
+
+/// TLS echo 后端：握手后先发送自己的名称，再回显收到的数据
+async fn start_tls_backend(port: u16, name: &'static str, cert: &Path, key: &Path) {
+    let config = tls_tunnel::tls::load_server_config_with_alpn(cert, key, None).unwrap();
+    let acceptor = TlsAcceptor::from(config);
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(mut tls) = acceptor.accept(socket).await else {
+                    return;
+                };
+                if tls.write_all(name.as_bytes()).await.is_err() {
+                    return;
+                }
+                let mut buf = [0u8; 1024];
+                while let Ok(n) = tls.read(&mut buf).await {
+                    if n == 0 || tls.write_all(&buf[..n]).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// 以指定 SNI 连接发布端口，只信任该主机名的后端证书，返回后端名称
+async fn connect_with_sni(port: u16, host: &str, ca_cert: &Path) -> Option<String> {
+    let config = tls_tunnel::tls::load_client_config_with_alpn(Some(ca_cert), false, None).ok()?;
+    let tcp = TcpStream::connect(("127.0.0.1", port)).await.ok()?;
+    let mut tls = timeout(
+        Duration::from_secs(5),
+        TlsConnector::from(config).connect(host.to_string().try_into().ok()?, tcp),
+    )
+    .await
+    .ok()?
+    .ok()?;
+
+    let mut name = [0u8; 4];
+    timeout(Duration::from_secs(5), tls.read_exact(&mut name))
+        .await
+        .ok()?
+        .ok()?;
+    tls.write_all(b"ping").await.ok()?;
+    let mut echo = [0u8; 4];
+    timeout(Duration::from_secs(5), tls.read_exact(&mut echo))
+        .await
+        .ok()?
+        .ok()?;
+    assert_eq!(&echo, b"ping");
+    Some(String::from_utf8_lossy(&name).into_owned())
+}
+
+#[tokio::test]
+async fn test_sni_routes_to_matching_backend() {
+    let publish_port = common::get_available_port();
+    let app1_port = common::get_available_port();
+    let app2_port = common::get_available_port();
+    let default_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let (app1_cert, app1_key) = backend_cert("app1.example.com");
+    let _app1_cleanup = common::TestCleanup::new(app1_cert.clone(), app1_key.clone());
+    let (app2_cert, app2_key) = backend_cert("app2.example.com");
+    let _app2_cleanup = common::TestCleanup::new(app2_cert.clone(), app2_key.clone());
+
+    start_tls_backend(app1_port, "app1", &app1_cert, &app1_key).await;
+    start_tls_backend(app2_port, "app2", &app2_cert, &app2_key).await;
+    let _default = common::start_echo_server(default_port).await;
+
+    let server = common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        ..common::test_server_config()
+    })
+    .await;
+
+    let sni_map = BTreeMap::from([
+        ("app1.example.com".to_string(), app1_port),
+        ("APP2.example.com".to_string(), app2_port),
+        ("default".to_string(), default_port),
+    ]);
+    let config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.clone()),
+            auth_key: AUTH_KEY.to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "https".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port: default_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: Some(SniRoutingConfig { sni_map }),
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config =
+        tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None).unwrap();
+    let connector = TlsConnector::from(tls_config);
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    });
+
+    // 非 TLS 连接转发到 default
+    let mut ready = false;
+    for _ in 0..50 {
+        if let Ok(data) =
+            common::test_proxy_connection(publish_port, b"plain", Duration::from_secs(5)).await
+        {
+            if data == b"plain" {
+                ready = true;
+                break;
+            }
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert!(ready, "Default route never became reachable");
+
+    // 每个主机名到达持有对应证书的后端（主机名不区分大小写）
+    assert_eq!(
+        connect_with_sni(publish_port, "app1.example.com", &app1_cert)
+            .await
+            .as_deref(),
+        Some("app1")
+    );
+    assert_eq!(
+        connect_with_sni(publish_port, "app2.example.com", &app2_cert)
+            .await
+            .as_deref(),
+        Some("app2")
+    );
+    assert_eq!(
+        connect_with_sni(publish_port, "app1.example.com", &app1_cert)
+            .await
+            .as_deref(),
+        Some("app1")
+    );
+
+    client.abort();
+    server.shutdown().await.ok();
+}
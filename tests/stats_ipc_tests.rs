@@ -0,0 +1,114 @@
+#![cfg(unix)]
+/// Stats IPC tests
+///
+/// 客户端配置 `stats_socket` 后，不占用 TCP 端口也能通过 Unix 套接字查询统计信息，
+/// 响应与统计端口相同；启动时清理上次运行残留的套接字文件
+mod common;
+
+use std::os::unix::fs::PermissionsExt;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+};
+use tls_tunnel::stats_http::StatsEndpoint;
+use tokio::time::sleep;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-stats-ipc-key";
+
+#[tokio::test]
+async fn test_client_stats_over_unix_socket() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+
+    let server_config = ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        ..common::test_server_config()
+    };
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
+
+    // 上次运行残留的套接字文件
+    let socket_path =
+        std::env::temp_dir().join(format!("tls-tunnel-stats-{}.sock", uuid::Uuid::new_v4()));
+    drop(std::os::unix::net::UnixListener::bind(&socket_path).unwrap());
+    assert!(socket_path.exists());
+
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.clone()),
+            auth_key: AUTH_KEY.to_string(),
+            stats_socket: Some(socket_path.clone()),
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "web".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client(client_config, connector)
+            .await
+            .ok();
+    });
+
+    // 等待代理注册后出现在统计信息中
+    let endpoint = StatsEndpoint::Unix(socket_path.clone());
+    let mut stats = Vec::new();
+    for _ in 0..50 {
+        if let Ok(body) = endpoint.get("/stats").await {
+            stats = serde_json::from_str::<Vec<serde_json::Value>>(&body).unwrap();
+            if !stats.is_empty() {
+                break;
+            }
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0]["name"], "web");
+
+    // 套接字仅当前用户可读写
+    let mode = std::fs::metadata(&socket_path)
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    let err = endpoint.get("/missing").await.unwrap_err();
+    assert!(err.to_string().contains("404"));
+
+    client_handle.abort();
+    std::fs::remove_file(&socket_path).ok();
+}
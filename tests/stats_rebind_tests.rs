@@ -0,0 +1,117 @@
+#![cfg(unix)]
+/// Stats rebind tests
+///
+/// 统计端口被其他进程占用时，客户端统计服务器退避后重新绑定，端口释放后无需重启即可访问；
+/// 期间通过另一个监听器（Unix 套接字）的 /healthz 可以看到失败原因
+mod common;
+
+use std::time::Duration;
+use tls_tunnel::config::{ClientConfig, ClientFullConfig};
+use tls_tunnel::stats_http::{StatsEndpoint, StatsHealthReport};
+use tokio::time::{sleep, Instant};
+use tokio_rustls::TlsConnector;
+
+/// 轮询直到条件成立（最多 15 秒）
+async fn wait_until<F, Fut>(what: &str, mut condition: F)
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = bool>,
+{
+    let deadline = Instant::now() + Duration::from_secs(15);
+    while !condition().await {
+        assert!(Instant::now() < deadline, "Timed out waiting for {}", what);
+        sleep(Duration::from_millis(100)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_client_stats_server_rebinds_after_port_is_released() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    // 其他进程占用了统计端口
+    let squatter = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let stats_port = squatter.local_addr().unwrap().port();
+
+    let socket_path =
+        std::env::temp_dir().join(format!("tls-tunnel-rebind-{}.sock", uuid::Uuid::new_v4()));
+
+    // 统计服务器与隧道连接无关，服务器不可达时同样启动
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: common::get_available_port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.clone()),
+            auth_key: "test-stats-rebind-key".to_string(),
+            stats_port: Some(stats_port),
+            stats_addr: Some("127.0.0.1".to_string()),
+            stats_socket: Some(socket_path.clone()),
+            ..common::test_client_config()
+        },
+        proxies: vec![],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client(client_config, connector)
+            .await
+            .ok();
+    });
+
+    // 端口被占用期间，Unix 套接字上的 /healthz 报告 TCP 统计服务器的绑定错误
+    let socket = StatsEndpoint::Unix(socket_path.clone());
+    let deadline = Instant::now() + Duration::from_secs(15);
+    let unhealthy = loop {
+        match socket.get("/healthz").await {
+            Err(e) if e.to_string().contains("Failed to bind") => break e.to_string(),
+            _ => {}
+        }
+        assert!(
+            Instant::now() < deadline,
+            "Timed out waiting for the bind failure to be reported"
+        );
+        sleep(Duration::from_millis(100)).await;
+    };
+    assert!(unhealthy.contains("503"), "{}", unhealthy);
+    assert!(
+        unhealthy.contains("\"stats_server_healthy\": false"),
+        "{}",
+        unhealthy
+    );
+
+    // 端口释放后无需重启客户端即可访问
+    drop(squatter);
+    let tcp = StatsEndpoint::Http(format!("http://127.0.0.1:{}", stats_port));
+    wait_until("the stats port to be rebound", || async {
+        tcp.get("/stats").await.is_ok()
+    })
+    .await;
+
+    let report: StatsHealthReport =
+        serde_json::from_str(&tcp.get("/healthz").await.unwrap()).unwrap();
+    assert!(report.stats_server_healthy, "{:?}", report);
+    assert_eq!(report.servers.len(), 2);
+    let server = report
+        .servers
+        .iter()
+        .find(|server| server.label.contains(&stats_port.to_string()))
+        .unwrap();
+    assert!(server.healthy);
+    assert_eq!(server.rebinds, 1);
+    assert!(
+        server
+            .last_error
+            .as_deref()
+            .is_some_and(|error| error.contains("Failed to bind")),
+        "{:?}",
+        server
+    );
+
+    client_handle.abort();
+    std::fs::remove_file(&socket_path).ok();
+}
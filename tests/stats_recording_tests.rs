@@ -0,0 +1,235 @@
+/// Stats recording tests
+///
+/// 转发循环在本地累加字节数并分批写入连接的计数器（`TrafficMeter`）：连接结束后总数必须与实际
+/// 转发的字节数完全一致，连接进行中的快照与实际值的差距不超过每个方向一个批量阈值
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tls_tunnel::io_util::copy_counted;
+use tls_tunnel::stats::{PendingBytes, ProxyStatsTracker, ShardedCounter, STATS_FLUSH_BYTES};
+use tokio::io::AsyncWriteExt;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+
+fn tracker() -> ProxyStatsTracker {
+    ProxyStatsTracker::new("stress".to_string(), "0.0.0.0".to_string(), 8080, 80)
+}
+
+#[test]
+fn test_pending_bytes_lag_is_bounded() {
+    let counter = ShardedCounter::new();
+    let mut pending = PendingBytes::new(1000);
+    let mut transferred = 0u64;
+
+    for chunk in [1u64, 999, 3, 500, 2000, 7, 0, 640] {
+        transferred += chunk;
+        if let Some(batch) = pending.add(chunk) {
+            counter.add(batch);
+        }
+        assert!(counter.sum() <= transferred);
+        assert!(transferred - counter.sum() < 1000);
+    }
+
+    // 连接结束时补上剩余部分，总数精确
+    if let Some(rest) = pending.take() {
+        counter.add(rest);
+    }
+    assert_eq!(counter.sum(), transferred);
+    assert_eq!(pending.take(), None);
+
+    counter.reset();
+    assert_eq!(counter.sum(), 0);
+}
+
+#[test]
+fn test_sharded_counter_sums_across_threads() {
+    let counter = Arc::new(ShardedCounter::new());
+    let handles: Vec<_> = (0..32u64)
+        .map(|i| {
+            let counter = counter.clone();
+            std::thread::spawn(move || {
+                for _ in 0..10_000 {
+                    counter.add(i + 1);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(counter.sum(), 10_000 * (1..=32u64).sum::<u64>());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_relays_record_exact_totals() {
+    const CONNECTIONS: u64 = 64;
+
+    let tracker = tracker();
+    let mut tasks = Vec::new();
+    let mut expected_sent = 0u64;
+    let mut expected_received = 0u64;
+
+    for i in 0..CONNECTIONS {
+        // 大小各不相同，覆盖不足一个阈值、恰好若干个阈值和有余数的情况
+        let upload = (i * 37_813) % (3 * STATS_FLUSH_BYTES) + i;
+        let download = if i % 8 == 0 {
+            2 * STATS_FLUSH_BYTES
+        } else {
+            (i * 91_199) % (2 * STATS_FLUSH_BYTES)
+        };
+        expected_received += upload;
+        expected_sent += download;
+
+        let meter = tracker.meter();
+        tasks.push(tokio::spawn(async move {
+            tokio::join!(
+                relay(upload, |bytes| meter.add_received(bytes)),
+                relay(download, |bytes| meter.add_sent(bytes)),
+            )
+        }));
+    }
+
+    for task in tasks {
+        let (up, down) = task.await.unwrap();
+        assert!(up.is_ok() && down.is_ok());
+    }
+
+    let stats = tracker.get_stats();
+    assert_eq!(stats.core.bytes_received, expected_received);
+    assert_eq!(stats.core.bytes_sent, expected_sent);
+}
+
+#[tokio::test]
+async fn test_relay_error_still_flushes_transferred_bytes() {
+    let tracker = tracker();
+    let meter = tracker.meter();
+    let (mut source, relay_side) = tokio::io::duplex(64 * 1024);
+    let mut reader = FailingReader {
+        inner: relay_side.compat(),
+        remaining: 10_000,
+    };
+    source.write_all(&[7u8; 20_000]).await.unwrap();
+    drop(source);
+
+    let mut sink = futures::io::sink();
+    let result = copy_counted(&mut reader, &mut sink, STATS_FLUSH_BYTES, |bytes| {
+        meter.add_received(bytes)
+    })
+    .await;
+
+    assert!(result.is_err());
+    // 出错前已转发的字节也要计入统计
+    let received = tracker.get_stats().core.bytes_received;
+    assert!(received > 0 && received <= 10_000);
+}
+
+/// 读取 `remaining` 字节后返回错误
+struct FailingReader<R> {
+    inner: R,
+    remaining: usize,
+}
+
+impl<R: futures::io::AsyncRead + Unpin> futures::io::AsyncRead for FailingReader<R> {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        if self.remaining == 0 {
+            return std::task::Poll::Ready(Err(std::io::Error::new(
+                std::io::ErrorKind::ConnectionReset,
+                "reset",
+            )));
+        }
+        let len = buf.len().min(self.remaining);
+        let this = &mut *self;
+        let poll = std::pin::Pin::new(&mut this.inner).poll_read(cx, &mut buf[..len]);
+        if let std::task::Poll::Ready(Ok(n)) = poll {
+            this.remaining -= n;
+        }
+        poll
+    }
+}
+
+/// 通过 duplex 管道转发 `len` 字节并记录统计
+async fn relay(len: u64, on_flush: impl FnMut(u64)) -> std::io::Result<u64> {
+    let (mut source, relay_side) = tokio::io::duplex(16 * 1024);
+    let writer = tokio::spawn(async move {
+        let chunk = vec![0xabu8; 8 * 1024];
+        let mut left = len as usize;
+        while left > 0 {
+            let n = left.min(chunk.len());
+            source.write_all(&chunk[..n]).await.unwrap();
+            left -= n;
+        }
+        source.shutdown().await.unwrap();
+    });
+
+    let mut reader = relay_side.compat();
+    let mut sink = futures::io::sink();
+    let copied = copy_counted(&mut reader, &mut sink, STATS_FLUSH_BYTES, on_flush).await?;
+    drop(reader);
+    writer.await.unwrap();
+    assert_eq!(copied, len);
+    Ok(copied)
+}
+
+/// 微基准：许多线程同时以小数据块记录字节数
+///
+/// 对比每个数据块直接 fetch_add 到共享原子计数器，与本地累加后批量写入分片计数器的耗时。
+/// 运行：cargo test --release --test stats_recording_tests -- --ignored --nocapture
+#[test]
+#[ignore]
+fn bench_stats_recording_contention() {
+    const THREADS: usize = 16;
+    const CHUNKS: u64 = 2_000_000;
+    const CHUNK: u64 = 1024;
+
+    let shared = Arc::new(AtomicU64::new(0));
+    let per_chunk = run_threads(THREADS, {
+        let shared = shared.clone();
+        move || {
+            for _ in 0..CHUNKS {
+                shared.fetch_add(CHUNK, Ordering::Relaxed);
+            }
+        }
+    });
+
+    let sharded = Arc::new(ShardedCounter::new());
+    let batched = run_threads(THREADS, {
+        let sharded = sharded.clone();
+        move || {
+            let mut pending = PendingBytes::new(STATS_FLUSH_BYTES);
+            for _ in 0..CHUNKS {
+                if let Some(batch) = pending.add(CHUNK) {
+                    sharded.add(batch);
+                }
+            }
+            if let Some(rest) = pending.take() {
+                sharded.add(rest);
+            }
+        }
+    });
+
+    let expected = THREADS as u64 * CHUNKS * CHUNK;
+    assert_eq!(shared.load(Ordering::Relaxed), expected);
+    assert_eq!(sharded.sum(), expected);
+    println!(
+        "per-chunk shared atomic: {:?}, batched sharded counter: {:?}",
+        per_chunk, batched
+    );
+    assert!(batched < per_chunk);
+}
+
+fn run_threads<F>(threads: usize, work: F) -> Duration
+where
+    F: Fn() + Send + Clone + 'static,
+{
+    let start = Instant::now();
+    let handles: Vec<_> = (0..threads)
+        .map(|_| std::thread::spawn(work.clone()))
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    start.elapsed()
+}
@@ -0,0 +1,218 @@
+/// Stats JSON schema tests
+///
+/// 服务端和客户端的 /stats 输出相同结构的公共字段（`ProxyStatsCore`），同时继续输出
+/// 旧字段名，旧格式的 JSON 仍能被解析
+mod common;
+
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tls_tunnel::client::ClientProxyStats;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+};
+use tls_tunnel::stats::{ProxyStats, ProxyStatsCore, StatsRole};
+use tls_tunnel::stats_http::StatsEndpoint;
+use tokio::time::sleep;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-stats-schema-key";
+
+/// 公共字段名及其 JSON 类型
+fn core_shape(entry: &Value) -> BTreeMap<String, &'static str> {
+    let core = serde_json::to_value(ProxyStatsCore::default()).unwrap();
+    core.as_object()
+        .unwrap()
+        .keys()
+        .map(|key| {
+            let kind = match &entry[key] {
+                Value::Null => "missing",
+                Value::Bool(_) => "bool",
+                Value::Number(_) => "number",
+                Value::String(_) => "string",
+                Value::Array(_) => "array",
+                Value::Object(_) => "object",
+            };
+            (key.clone(), kind)
+        })
+        .collect()
+}
+
+/// 读取 /stats 中名为 `name` 的条目
+async fn stats_entry(endpoint: &StatsEndpoint, name: &str) -> Option<Value> {
+    let body = endpoint.get("/stats").await.ok()?;
+    let stats: Vec<Value> = serde_json::from_str(&body).ok()?;
+    stats.into_iter().find(|s| s["name"] == name)
+}
+
+#[tokio::test]
+async fn test_server_and_client_emit_same_core_shape() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let server_stats_port = common::get_available_port();
+    let client_stats_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+
+    let server_config = ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        stats_port: Some(server_stats_port),
+        ..common::test_server_config()
+    };
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
+
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.clone()),
+            auth_key: AUTH_KEY.to_string(),
+            stats_port: Some(client_stats_port),
+            stats_addr: Some("127.0.0.1".to_string()),
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "web".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client(client_config, connector)
+            .await
+            .ok();
+    });
+
+    let mut response = None;
+    for _ in 0..50 {
+        // 隧道就绪之前的连接可能直接被关闭（响应为空）
+        if let Some(data) =
+            common::test_proxy_connection(publish_port, b"schema", Duration::from_secs(2))
+                .await
+                .ok()
+                .filter(|data| !data.is_empty())
+        {
+            response = Some(data);
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(response.as_deref(), Some(&b"schema"[..]));
+
+    let server_endpoint = StatsEndpoint::Http(format!("http://127.0.0.1:{}", server_stats_port));
+    let client_endpoint = StatsEndpoint::Http(format!("http://127.0.0.1:{}", client_stats_port));
+    let mut entries = (None, None);
+    for _ in 0..50 {
+        entries = (
+            stats_entry(&server_endpoint, "web").await,
+            stats_entry(&client_endpoint, "web").await,
+        );
+        if entries.0.is_some() && entries.1.is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    let server_entry = entries.0.expect("Server stats missing proxy");
+    let client_entry = entries.1.expect("Client stats missing proxy");
+
+    // 公共部分结构一致
+    let server_shape = core_shape(&server_entry);
+    assert!(!server_shape.values().any(|kind| *kind == "missing"));
+    assert_eq!(server_shape, core_shape(&client_entry));
+    assert_eq!(server_entry["role"], "server");
+    assert_eq!(client_entry["role"], "client");
+    assert_eq!(server_entry["listen_port"], publish_port);
+    assert_eq!(server_entry["target_port"], local_port);
+    assert_eq!(client_entry["listen_port"], local_port);
+
+    // 旧字段名继续输出
+    assert_eq!(server_entry["publish_addr"], "127.0.0.1");
+    assert_eq!(server_entry["publish_port"], publish_port);
+    assert_eq!(server_entry["local_port"], local_port);
+    assert_eq!(client_entry["bind_addr"], client_entry["listen_addr"]);
+    assert_eq!(client_entry["bind_port"], client_entry["listen_port"]);
+
+    // 同时包含新旧字段名的输出可以解析回来
+    let parsed: ProxyStats = serde_json::from_value(server_entry).unwrap();
+    assert_eq!(parsed.core.listen_port, publish_port);
+    let parsed: ClientProxyStats = serde_json::from_value(client_entry).unwrap();
+    assert_eq!(parsed.core.role, StatsRole::Client);
+
+    client_handle.abort();
+    server.shutdown().await.ok();
+}
+
+#[test]
+fn test_old_field_names_are_accepted() {
+    let server: ProxyStats = serde_json::from_str(
+        r#"{
+            "name": "web",
+            "publish_addr": "0.0.0.0",
+            "publish_port": 8888,
+            "local_port": 80,
+            "total_connections": 42,
+            "active_connections": 3,
+            "bytes_sent": 1048576,
+            "bytes_received": 524288,
+            "start_time": 1700000000
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(server.core.role, StatsRole::Server);
+    assert_eq!(server.core.listen_addr, "0.0.0.0");
+    assert_eq!(server.core.listen_port, 8888);
+    assert_eq!(server.core.target_port, 80);
+    assert_eq!(server.core.active_connections, 3);
+    assert_eq!(server.publish_label(), "0.0.0.0:8888");
+
+    let client: ClientProxyStats = serde_json::from_str(
+        r#"{
+            "name": "web",
+            "proxy_type": "Tcp",
+            "bind_addr": "127.0.0.1",
+            "bind_port": 8080,
+            "target_addr": "example.com",
+            "target_port": 80,
+            "active_connections": 5,
+            "total_connections": 123,
+            "bytes_sent": 1048576,
+            "bytes_received": 2097152,
+            "start_time": 1704067200,
+            "status": "Connected"
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(client.core.role, StatsRole::Client);
+    assert_eq!(client.core.listen_addr, "127.0.0.1");
+    assert_eq!(client.core.listen_port, 8080);
+    assert_eq!(client.core.target_addr, "example.com");
+    assert_eq!(client.core.status, "Connected");
+}
@@ -0,0 +1,277 @@
+/// Stats WebSocket stream tests
+///
+/// `/stats/ws` 先推送完整快照，之后推送变化的代理和注册表事件；
+/// 配置了 auth_token 时第一条消息必须是认证消息
+mod common;
+
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+    StatsStreamConfig,
+};
+use tls_tunnel::transport::TransportType;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+type Ws = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+const AUTH_TOKEN: &str = "dashboard-token";
+
+fn server_config(
+    cert_path: std::path::PathBuf,
+    key_path: std::path::PathBuf,
+    stats_port: u16,
+) -> ServerConfig {
+    ServerConfig {
+        bind_addr: "127.0.0.1".to_string(),
+        bind_port: 0,
+        auth_key: "test-stats-stream-key".to_string(),
+        cert_path: Some(cert_path),
+        key_path: Some(key_path),
+        transport: TransportType::Tls,
+        behind_proxy: false,
+        allow_forward: false,
+        rate_limit: None,
+        size_limits: None,
+        stats_port: Some(stats_port),
+        stats_addr: Some("127.0.0.1".to_string()),
+        stats_path: None,
+        stats_limits: None,
+        stats_stream: Some(StatsStreamConfig {
+            min_interval_ms: 100,
+            auth_token: Some(AUTH_TOKEN.to_string()),
+            ..Default::default()
+        }),
+        interface_prefer_ipv6: false,
+        event_export: None,
+        max_write_chunk: None,
+        debug_stalls: false,
+        debug_tls_handshakes: false,
+        exception_limits: None,
+        drain_timeout_secs: None,
+        protocol_trace_path: None,
+        forward_limits: None,
+        forward_policy: None,
+        max_concurrent_handshakes: None,
+        handshake_timeout_secs: None,
+        idle_registration_expiry_secs: None,
+        relay_memory_budget_mb: None,
+        known_clients: None,
+        visitor_acl: None,
+        timeouts: None,
+        flow_export: None,
+        shutdown_grace_period_secs: None,
+        visitor_retry_timeout_secs: None,
+        tcp_keepalive_secs: None,
+        idle_timeout_secs: None,
+        max_streams_per_client: None,
+        max_connections_per_proxy: None,
+        auth_keys: None,
+        client_ca_path: None,
+        require_client_cert: false,
+    }
+}
+
+fn client_config(
+    cert_path: std::path::PathBuf,
+    server_port: u16,
+    publish_port: u16,
+    local_port: u16,
+) -> ClientFullConfig {
+    ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path),
+            auth_key: "test-stats-stream-key".to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "live".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    }
+}
+
+async fn connect(stats_port: u16, token: &str) -> Ws {
+    let url = format!("ws://127.0.0.1:{}/stats/ws", stats_port);
+    let (mut ws, _) = tokio_tungstenite::connect_async(url)
+        .await
+        .expect("WebSocket handshake failed");
+    let auth = json!({"type": "auth", "token": token}).to_string();
+    ws.send(Message::text(auth)).await.unwrap();
+    ws
+}
+
+/// 下一帧（JSON）
+async fn next_frame(ws: &mut Ws) -> Value {
+    loop {
+        let message = timeout(Duration::from_secs(10), ws.next())
+            .await
+            .expect("Timed out waiting for a stats frame")
+            .expect("Stats stream ended")
+            .expect("Stats stream failed");
+        if let Message::Text(text) = message {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+/// 读取增量帧直到 `done` 满足，返回期间的所有帧
+async fn deltas_until(ws: &mut Ws, mut done: impl FnMut(&[Value]) -> bool) -> Vec<Value> {
+    let mut frames = Vec::new();
+    while !done(&frames) {
+        let frame = next_frame(ws).await;
+        assert_eq!(frame["type"], "delta", "unexpected frame: {}", frame);
+        frames.push(frame);
+    }
+    frames
+}
+
+fn has_event(frames: &[Value], event: &str) -> bool {
+    frames.iter().any(|frame| {
+        frame["events"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|e| e["event"] == event && e["name"] == "live")
+    })
+}
+
+fn changed_live(frames: &[Value]) -> Vec<&Value> {
+    frames
+        .iter()
+        .flat_map(|frame| frame["changed"].as_array().unwrap())
+        .filter(|proxy| proxy["name"] == "live")
+        .collect()
+}
+
+#[tokio::test]
+async fn test_stats_stream_snapshot_and_deltas() {
+    let stats_port = common::get_available_port();
+    let publish_port = common::get_available_port();
+    let echo_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server = common::spawn_server(server_config(
+        cert_path.clone(),
+        key_path.clone(),
+        stats_port,
+    ))
+    .await;
+    assert!(common::wait_for_server(stats_port, 50).await);
+
+    // 认证后先收到完整快照（还没有代理）
+    let mut ws = connect(stats_port, AUTH_TOKEN).await;
+    let snapshot = next_frame(&mut ws).await;
+    assert_eq!(snapshot["type"], "snapshot");
+    assert_eq!(snapshot["seq"], 1);
+    assert_eq!(snapshot["proxies"], json!([]));
+
+    // 注册代理：增量中出现 registered 事件和新代理的统计
+    let _echo = common::start_echo_server(echo_port).await;
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let config = client_config(
+        cert_path.clone(),
+        server.bound_addr().port(),
+        publish_port,
+        echo_port,
+    );
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    });
+    let frames = deltas_until(&mut ws, |frames| {
+        has_event(frames, "registered") && !changed_live(frames).is_empty()
+    })
+    .await;
+    let seqs: Vec<u64> = frames.iter().map(|f| f["seq"].as_u64().unwrap()).collect();
+    assert_eq!(seqs, (2..2 + seqs.len() as u64).collect::<Vec<_>>());
+
+    // 转发流量：增量中出现更新后的连接数
+    assert!(common::wait_for_echo(publish_port, 50).await);
+    let response = common::test_proxy_connection(publish_port, b"stream", Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(response, b"stream");
+    deltas_until(&mut ws, |frames| {
+        changed_live(frames)
+            .iter()
+            .any(|proxy| proxy["total_connections"].as_u64() >= Some(2))
+    })
+    .await;
+
+    // 注销代理：增量中出现 unregistered 事件，代理出现在 removed 中
+    client.abort();
+    deltas_until(&mut ws, |frames| {
+        has_event(frames, "unregistered")
+            && frames.iter().any(|frame| {
+                frame["removed"]
+                    .as_array()
+                    .unwrap()
+                    .contains(&json!("live"))
+            })
+    })
+    .await;
+
+    server.shutdown().await.ok();
+}
+
+#[tokio::test]
+async fn test_stats_stream_rejects_wrong_token() {
+    let stats_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server = common::spawn_server(server_config(cert_path, key_path, stats_port)).await;
+    assert!(common::wait_for_server(stats_port, 50).await);
+
+    let mut ws = connect(stats_port, "wrong-token").await;
+    let message = timeout(Duration::from_secs(10), ws.next())
+        .await
+        .expect("Timed out waiting for close")
+        .expect("Stats stream ended")
+        .expect("Stats stream failed");
+    match message {
+        Message::Close(Some(frame)) => assert_eq!(frame.code, CloseCode::Policy),
+        other => panic!("Expected a close frame, got {:?}", other),
+    }
+
+    // 普通 HTTP 请求需要升级
+    let response = reqwest::get(format!("http://127.0.0.1:{}/stats/ws", stats_port))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 426);
+
+    server.shutdown().await.ok();
+}
@@ -0,0 +1,233 @@
+/// Stream close ordering tests
+///
+/// 转发的一个方向结束后，客户端以前直接丢弃仍处于打开状态的 yamux stream，yamux 随即发送 RST。
+/// 现在先关闭写方向（FIN），在期限内等待对端关闭后再丢弃：
+/// - 本地服务写完最后一块数据后立即关闭连接，用户连接收到完整的数据。这是一个竞争，反复传输；
+///   yamux 0.13 在报告 RST 之前仍交付已缓冲的数据，修复前在 1 CPU 的环境中 100 次传输未观察到截断
+/// - 本地服务先关闭写方向，用户之后上传的数据仍然送达。修复前服务器写入被重置的 stream 失败并
+///   断开用户连接，每一轮都会丢失
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+};
+use tls_tunnel::server::ServerHandle;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-stream-close-key";
+const ROUNDS: usize = 100;
+
+/// 响应内容：前面是填充数据，最后一块是可识别的结尾
+fn payload() -> Vec<u8> {
+    let mut data: Vec<u8> = (0..256 * 1024).map(|i| (i % 251) as u8).collect();
+    data.extend_from_slice(b"--end-of-response--");
+    data
+}
+
+/// 启动本地服务：接受连接后写出完整响应并立即关闭
+async fn start_closing_server(port: u16) -> JoinHandle<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        let data = payload();
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let data = data.clone();
+            tokio::spawn(async move {
+                let _ = stream.write_all(&data).await;
+            });
+        }
+    })
+}
+
+/// 通过发布端口读取一次完整响应
+async fn fetch(publish_port: u16) -> std::io::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect(("127.0.0.1", publish_port)).await?;
+    let mut received = Vec::new();
+    timeout(Duration::from_secs(10), stream.read_to_end(&mut received))
+        .await
+        .map_err(|_| std::io::Error::other("Timed out reading response"))??;
+    Ok(received)
+}
+
+/// 启动服务器和客户端，代理 `closing` 把发布端口转发到 `local_port`
+async fn start_tunnel(
+    cert_path: &Path,
+    key_path: &Path,
+    publish_port: u16,
+    local_port: u16,
+) -> (ServerHandle, JoinHandle<()>) {
+    let server = common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        ..common::test_server_config()
+    })
+    .await;
+
+    let config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "closing".to_string(),
+            proxy_type: ProxyType::Tcp,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            pool: None,
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: None,
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    });
+    (server, client)
+}
+
+#[tokio::test]
+async fn test_backend_close_does_not_truncate_response() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _backend = start_closing_server(local_port).await;
+    let (server, client) = start_tunnel(&cert_path, &key_path, publish_port, local_port).await;
+
+    // 隧道就绪之前的连接可能直接被关闭（响应为空）
+    let mut ready = false;
+    for _ in 0..50 {
+        if fetch(publish_port).await.is_ok_and(|data| !data.is_empty()) {
+            ready = true;
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert!(ready, "Proxy 'closing' never became reachable");
+
+    let expected = payload();
+    let mut truncated = Vec::new();
+    for round in 0..ROUNDS {
+        let received = fetch(publish_port)
+            .await
+            .unwrap_or_else(|e| panic!("Round {} failed: {}", round, e));
+        if received != expected {
+            truncated.push((round, received.len()));
+        }
+    }
+    assert!(
+        truncated.is_empty(),
+        "{} of {} responses were truncated (round, bytes): {:?}",
+        truncated.len(),
+        ROUNDS,
+        truncated
+    );
+
+    client.abort();
+    server.shutdown().await.ok();
+}
+
+/// 启动本地服务：先写出响应并关闭写方向，再读完请求，通过 `received` 报告收到的数据
+async fn start_half_closing_server(
+    port: u16,
+    received: tokio::sync::mpsc::UnboundedSender<Vec<u8>>,
+) -> JoinHandle<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let received = received.clone();
+            tokio::spawn(async move {
+                if stream.write_all(b"ready").await.is_err() || stream.shutdown().await.is_err() {
+                    return;
+                }
+                let mut request = Vec::new();
+                let _ = stream.read_to_end(&mut request).await;
+                let _ = received.send(request);
+            });
+        }
+    })
+}
+
+/// 本地服务先关闭写方向时，用户连接之后发送的数据仍然送达本地服务
+#[tokio::test]
+async fn test_backend_half_close_keeps_upload() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let (received_tx, mut received_rx) = tokio::sync::mpsc::unbounded_channel();
+    let _backend = start_half_closing_server(local_port, received_tx).await;
+    let (server, client) = start_tunnel(&cert_path, &key_path, publish_port, local_port).await;
+
+    for round in 0..10 {
+        // 隧道就绪之前的连接可能直接被关闭（响应为空），重试直到收到响应
+        let mut attempts = 0;
+        let mut stream = loop {
+            attempts += 1;
+            assert!(attempts <= 50, "Proxy 'closing' never became reachable");
+            let Ok(mut stream) = TcpStream::connect(("127.0.0.1", publish_port)).await else {
+                sleep(Duration::from_millis(100)).await;
+                continue;
+            };
+            let mut response = Vec::new();
+            if timeout(Duration::from_secs(10), stream.read_to_end(&mut response))
+                .await
+                .is_ok_and(|result| result.is_ok())
+                && response == b"ready"
+            {
+                break stream;
+            }
+            while received_rx.try_recv().is_ok() {}
+            sleep(Duration::from_millis(100)).await;
+        };
+
+        // 收到本地服务的 FIN 之后再上传数据
+        let upload = format!("upload-{}", round);
+        stream.write_all(upload.as_bytes()).await.unwrap();
+        stream.shutdown().await.unwrap();
+
+        let request = timeout(Duration::from_secs(10), received_rx.recv())
+            .await
+            .expect("Backend never finished reading the request")
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&request),
+            upload,
+            "Round {}: upload after the backend's FIN was lost",
+            round
+        );
+    }
+
+    client.abort();
+    server.shutdown().await.ok();
+}
@@ -0,0 +1,241 @@
+/// Stream flood tests
+///
+/// 同时大量打开 visitor 连接和发布端口连接：visitor 客户端的事件循环同时创建 outbound stream
+/// 和接收 inbound stream，服务器同时向两个客户端创建 stream，所有请求都必须在超时前得到 stream
+mod common;
+
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+    VisitorConfig,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-stream-flood-key";
+
+/// 每个连接回显的消息长度（`flood-{:08}`）
+const PAYLOAD_LEN: usize = 14;
+
+/// 每个端口的连接数
+const CONNECTIONS_PER_PORT: usize = 1000;
+
+/// visitor 端口的并发连接数（超过 yamux 等待 ACK 的 stream 上限 256）
+const VISITOR_CONCURRENCY: usize = 300;
+
+/// 发布端口的并发连接数（与 visitor 合计不超过 yamux 单连接的 stream 上限 512）
+const PUBLISHED_CONCURRENCY: usize = 64;
+
+fn client_config(server_port: u16, cert_path: &Path) -> ClientConfig {
+    ClientConfig {
+        server_port,
+        skip_verify: true,
+        ca_cert_path: Some(cert_path.to_path_buf()),
+        auth_key: AUTH_KEY.to_string(),
+        ..common::test_client_config()
+    }
+}
+
+fn proxy(
+    name: &str,
+    publish_port: u16,
+    local_port: u16,
+    visibility: ProxyVisibility,
+) -> ProxyConfig {
+    ProxyConfig {
+        name: name.to_string(),
+        proxy_type: ProxyType::Tcp,
+        publish_addr: "127.0.0.1".to_string(),
+        publish_port,
+        local_port,
+        pool: None,
+        shared: false,
+        weight: None,
+        visibility,
+        drain_timeout_secs: None,
+        sni_routing: None,
+        socket: None,
+        local_targets: None,
+        idle_keepalive_secs: None,
+        require_first_byte_timeout_ms: None,
+        max_connections_per_source: None,
+        report_peers: false,
+        ws_idle_timeout_secs: None,
+        integrity_check: false,
+    }
+}
+
+/// 回显一条消息后关闭连接的后端（不依赖对端关闭，连接及其 stream 可以及时释放）
+async fn start_echo_once_server(port: u16) -> JoinHandle<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buf = vec![0u8; PAYLOAD_LEN];
+                if socket.read_exact(&mut buf).await.is_ok() {
+                    let _ = socket.write_all(&buf).await;
+                }
+            });
+        }
+    })
+}
+
+fn spawn_client(config: ClientFullConfig, cert_path: &Path) -> JoinHandle<()> {
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    })
+}
+
+/// 经端口完成一次回显，成功时返回 true
+async fn echo_once(port: u16, id: usize) -> bool {
+    let payload = format!("flood-{:08}", id).into_bytes();
+    let result = timeout(Duration::from_secs(20), async {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.ok()?;
+        stream.write_all(&payload).await.ok()?;
+        let mut response = vec![0u8; payload.len()];
+        stream.read_exact(&mut response).await.ok()?;
+        Some(response == payload)
+    })
+    .await;
+    matches!(result, Ok(Some(true)))
+}
+
+/// 并发打开大量连接，返回失败（丢失或超时）的数量
+fn flood(port: u16, concurrency: usize, failures: Arc<AtomicUsize>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let limit = Arc::new(Semaphore::new(concurrency));
+        let mut tasks = Vec::with_capacity(CONNECTIONS_PER_PORT);
+        for id in 0..CONNECTIONS_PER_PORT {
+            let permit = limit.clone().acquire_owned().await.unwrap();
+            let failures = failures.clone();
+            tasks.push(tokio::spawn(async move {
+                if !echo_once(port, id).await {
+                    failures.fetch_add(1, Ordering::Relaxed);
+                }
+                drop(permit);
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+    })
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_stream_requests_are_not_lost() {
+    let private_publish_port = common::get_available_port();
+    let private_local_port = common::get_available_port();
+    let public_publish_port = common::get_available_port();
+    let public_local_port = common::get_available_port();
+    let visitor_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo_private = start_echo_once_server(private_local_port).await;
+    let _echo_public = start_echo_once_server(public_local_port).await;
+
+    let server = common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        ..common::test_server_config()
+    })
+    .await;
+    let server_port = server.bound_addr().port();
+
+    // 客户端 P 注册私有代理
+    let proxy_client = spawn_client(
+        ClientFullConfig {
+            client: client_config(server_port, &cert_path),
+            proxies: vec![proxy(
+                "flood-private",
+                private_publish_port,
+                private_local_port,
+                ProxyVisibility::Private,
+            )],
+            visitors: vec![],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        &cert_path,
+    );
+    let stats = server.stats();
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            stats
+                .get_all_stats()
+                .iter()
+                .any(|s| s.core.name == "flood-private")
+        })
+        .await,
+        "Private proxy was never registered"
+    );
+
+    // 客户端 V 发布公开代理，同时通过 visitor 访问 P 的私有代理
+    let visitor_client = spawn_client(
+        ClientFullConfig {
+            client: client_config(server_port, &cert_path),
+            proxies: vec![proxy(
+                "flood-public",
+                public_publish_port,
+                public_local_port,
+                ProxyVisibility::Public,
+            )],
+            visitors: vec![VisitorConfig {
+                name: "flood-private".to_string(),
+                proxy_type: ProxyType::Tcp,
+                bind_addr: "127.0.0.1".to_string(),
+                bind_port: visitor_port,
+                publish_port: private_publish_port,
+                expected_peer_id: None,
+                connection_reuse: false,
+                socket: None,
+            }],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        &cert_path,
+    );
+
+    for port in [visitor_port, public_publish_port] {
+        let mut ready = false;
+        for attempt in 0..100 {
+            if echo_once(port, attempt).await {
+                ready = true;
+                break;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        assert!(ready, "Port {} never became usable", port);
+    }
+
+    // visitor 连接（V outbound、服务器到 P 的 outbound）与发布端口连接（服务器到 V 的 outbound、
+    // V inbound）同时进行
+    let failures = Arc::new(AtomicUsize::new(0));
+    let visitors = flood(visitor_port, VISITOR_CONCURRENCY, failures.clone());
+    let published = flood(public_publish_port, PUBLISHED_CONCURRENCY, failures.clone());
+    visitors.await.unwrap();
+    published.await.unwrap();
+
+    assert_eq!(
+        failures.load(Ordering::Relaxed),
+        0,
+        "Stream requests were lost or timed out under load"
+    );
+
+    proxy_client.abort();
+    visitor_client.abort();
+    server.shutdown().await.ok();
+}
@@ -0,0 +1,206 @@
+/// Timeouts tests
+///
+/// `[client.timeouts]` 中的连接空闲超时作用于 forwarder 的连接，forwarder 的 `idle_timeout_secs`
+/// 覆盖客户端的设置：空闲超过覆盖值的连接被关闭，覆盖值更长时连接保持打开
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ClientTimeoutsConfig, ForwarderConfig, ProxyType,
+    RoutingConfig, RoutingStrategy, ServerConfig,
+};
+use tls_tunnel::server::ServerHandle;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::{timeout, Instant};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-timeouts-key";
+
+/// 启动服务器和带一个 SOCKS5 forwarder 的客户端（127.0.0.0/8 直连）
+async fn start_tunnel(
+    forwarder_port: u16,
+    timeouts: ClientTimeoutsConfig,
+    idle_timeout_secs: Option<u64>,
+    cert_path: &Path,
+    key_path: &Path,
+) -> (ServerHandle, JoinHandle<()>) {
+    let server = common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        ..common::test_server_config()
+    })
+    .await;
+
+    let client_config = ClientFullConfig {
+        client: ClientConfig {
+            server_port: server.bound_addr().port(),
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            keep_listening_on_disconnect: false,
+            timeouts: Some(timeouts),
+            ..common::test_client_config()
+        },
+        proxies: vec![],
+        visitors: vec![],
+        forwarders: vec![ForwarderConfig {
+            name: "office".to_string(),
+            proxy_type: ProxyType::Socks5Proxy,
+            bind_addr: "127.0.0.1".to_string(),
+            bind_port: forwarder_port,
+            routing: Some(RoutingConfig {
+                geoip_db: None,
+                direct_countries: vec![],
+                proxy_countries: vec![],
+                direct_ips: vec!["127.0.0.0/8".to_string()],
+                proxy_ips: vec![],
+                direct_domains: vec![],
+                proxy_domains: vec![],
+                default_strategy: RoutingStrategy::Proxy,
+                schedules: vec![],
+                utc_offset: None,
+                quota: None,
+            }),
+            fast_fail: None,
+            direct_egress: None,
+            socket: None,
+            report_direct: false,
+            idle_timeout_secs,
+            header_rules: None,
+        }],
+        visitor_gateway: None,
+        health: None,
+    };
+    client_config
+        .validate()
+        .expect("Client config should be valid");
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let client_handle = tokio::spawn(async move {
+        tls_tunnel::client::run_client(client_config, connector)
+            .await
+            .ok();
+    });
+    assert!(
+        common::wait_for_server(forwarder_port, 50).await,
+        "Forwarder did not start listening"
+    );
+
+    (server, client_handle)
+}
+
+/// 回显数据且从不主动关闭连接的目标服务器
+async fn start_target() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buf = [0u8; 1024];
+                while let Ok(n) = socket.read(&mut buf).await {
+                    if n == 0 || socket.write_all(&buf[..n]).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    port
+}
+
+/// 经 SOCKS5 forwarder 连接目标并完成一次回显，返回保持打开的连接
+async fn socks5_connect(forwarder_port: u16, target_port: u16) -> TcpStream {
+    let mut socks = TcpStream::connect(("127.0.0.1", forwarder_port))
+        .await
+        .expect("Failed to connect to forwarder");
+    socks.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut greeting = [0u8; 2];
+    socks.read_exact(&mut greeting).await.unwrap();
+
+    let mut connect_request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    connect_request.extend_from_slice(&target_port.to_be_bytes());
+    socks.write_all(&connect_request).await.unwrap();
+    let mut reply = [0u8; 10];
+    socks.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[..2], [0x05, 0x00]);
+
+    socks.write_all(b"ping").await.unwrap();
+    let mut echo = [0u8; 4];
+    timeout(Duration::from_secs(5), socks.read_exact(&mut echo))
+        .await
+        .expect("Timed out waiting for the echo")
+        .unwrap();
+    assert_eq!(&echo, b"ping");
+    socks
+}
+
+/// 等待连接被 forwarder 关闭（读到 EOF 或出错），返回等待的时长
+async fn wait_closed(socks: &mut TcpStream, limit: Duration) -> Option<Duration> {
+    let started = Instant::now();
+    let mut buf = [0u8; 16];
+    match timeout(limit, socks.read(&mut buf)).await {
+        Ok(Ok(0)) | Ok(Err(_)) => Some(started.elapsed()),
+        Ok(Ok(n)) => panic!("Unexpected {} bytes on an idle connection", n),
+        Err(_) => None,
+    }
+}
+
+#[tokio::test]
+async fn test_forwarder_idle_override_closes_idle_connection() {
+    let forwarder_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let timeouts = ClientTimeoutsConfig {
+        protocol_parse_secs: 1,
+        ..Default::default()
+    };
+    let (server, client_handle) =
+        start_tunnel(forwarder_port, timeouts, Some(2), &cert_path, &key_path).await;
+
+    let mut socks = socks5_connect(forwarder_port, start_target().await).await;
+    // 默认的空闲超时为 5 分钟，覆盖为 2 秒后空闲连接很快被关闭
+    let closed_after = wait_closed(&mut socks, Duration::from_secs(10))
+        .await
+        .expect("Idle connection was not closed");
+    assert!(
+        closed_after >= Duration::from_millis(1500),
+        "Closed after {:?}",
+        closed_after
+    );
+
+    client_handle.abort();
+    server.shutdown().await.ok();
+}
+
+#[tokio::test]
+async fn test_forwarder_idle_override_outlasts_client_idle() {
+    let forwarder_port = common::get_available_port();
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let timeouts = ClientTimeoutsConfig {
+        protocol_parse_secs: 1,
+        connection_idle_secs: 2,
+        ..Default::default()
+    };
+    let (server, client_handle) =
+        start_tunnel(forwarder_port, timeouts, Some(30), &cert_path, &key_path).await;
+
+    // 客户端级的空闲超时为 2 秒，forwarder 覆盖为 30 秒，连接空闲 4 秒后仍然可用
+    let mut socks = socks5_connect(forwarder_port, start_target().await).await;
+    assert_eq!(wait_closed(&mut socks, Duration::from_secs(4)).await, None);
+    socks.write_all(b"pong").await.unwrap();
+    let mut echo = [0u8; 4];
+    timeout(Duration::from_secs(5), socks.read_exact(&mut echo))
+        .await
+        .expect("Timed out waiting for the echo")
+        .unwrap();
+    assert_eq!(&echo, b"pong");
+
+    client_handle.abort();
+    server.shutdown().await.ok();
+}
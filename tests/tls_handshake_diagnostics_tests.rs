@@ -0,0 +1,172 @@
+/// TLS handshake diagnostics tests
+///
+/// 启用 `debug_tls_handshakes` 后，只提供 TLS 1.1 时代参数的客户端握手失败时记录
+/// ClientHello 摘要；成功的握手和未启用诊断的服务器不记录
+mod common;
+
+use parking_lot::Mutex;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+use tls_tunnel::config::ServerConfig;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const MARKER: &str = "TLS handshake from";
+
+/// 收集日志输出
+#[derive(Clone, Default)]
+struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock()).into_owned()
+    }
+
+    fn count(&self) -> usize {
+        self.text().matches(MARKER).count()
+    }
+}
+
+impl Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 构造只提供 TLS 1.1 和 CBC 密码套件的 ClientHello 记录
+fn legacy_client_hello(sni: &str) -> Vec<u8> {
+    let name = sni.as_bytes();
+    let mut extensions = Vec::new();
+    extensions.extend_from_slice(&0x0000u16.to_be_bytes());
+    extensions.extend_from_slice(&((name.len() + 5) as u16).to_be_bytes());
+    extensions.extend_from_slice(&((name.len() + 3) as u16).to_be_bytes());
+    extensions.push(0);
+    extensions.extend_from_slice(&(name.len() as u16).to_be_bytes());
+    extensions.extend_from_slice(name);
+
+    let mut body = vec![0x03, 0x02];
+    body.extend_from_slice(&[0x42; 32]);
+    body.push(0);
+    // TLS_RSA_WITH_AES_128_CBC_SHA, TLS_RSA_WITH_3DES_EDE_CBC_SHA
+    body.extend_from_slice(&[0x00, 0x04, 0x00, 0x2f, 0x00, 0x0a]);
+    body.extend_from_slice(&[0x01, 0x00]);
+    body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    body.extend_from_slice(&extensions);
+
+    let mut handshake = vec![0x01];
+    handshake.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    handshake.extend_from_slice(&body);
+
+    let mut record = vec![0x16, 0x03, 0x01];
+    record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+    record.extend_from_slice(&handshake);
+    record
+}
+
+fn server_config(
+    cert_path: &std::path::Path,
+    key_path: &std::path::Path,
+    debug_tls_handshakes: bool,
+) -> ServerConfig {
+    ServerConfig {
+        auth_key: "test-tls-diagnostics-key".to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        debug_tls_handshakes,
+        ..common::test_server_config()
+    }
+}
+
+/// 发送旧版 ClientHello 并等待服务器关闭连接
+async fn send_legacy_hello(port: u16) {
+    let mut stream = TcpStream::connect(("127.0.0.1", port))
+        .await
+        .expect("Failed to connect to server");
+    stream
+        .write_all(&legacy_client_hello("legacy-device.local"))
+        .await
+        .unwrap();
+    let mut buf = [0u8; 64];
+    timeout(Duration::from_secs(5), async {
+        while let Ok(n) = stream.read(&mut buf).await {
+            if n == 0 {
+                break;
+            }
+        }
+    })
+    .await
+    .expect("Server should close the connection");
+}
+
+/// 等待日志中出现 `expected` 条诊断
+async fn wait_for_reports(logs: &CapturedLogs, expected: usize) {
+    for _ in 0..50 {
+        if logs.count() >= expected {
+            break;
+        }
+        sleep(Duration::from_millis(50)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_failed_handshake_logs_client_hello() {
+    let logs = CapturedLogs::default();
+    let writer = logs.clone();
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .init();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    // 未启用诊断：握手失败不记录摘要
+    let quiet = common::spawn_server(server_config(&cert_path, &key_path, false)).await;
+    send_legacy_hello(quiet.bound_addr().port()).await;
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(logs.count(), 0, "{}", logs.text());
+    quiet.shutdown().await.ok();
+
+    // 启用诊断：记录版本、密码套件、SNI 和 rustls 错误
+    let server = common::spawn_server(server_config(&cert_path, &key_path, true)).await;
+    let port = server.bound_addr().port();
+    send_legacy_hello(port).await;
+    wait_for_reports(&logs, 1).await;
+
+    let text = logs.text();
+    let line = text
+        .lines()
+        .find(|line| line.contains(MARKER))
+        .unwrap_or_else(|| panic!("No diagnostic line in logs:\n{}", text));
+    assert!(line.contains("WARN"), "{}", line);
+    assert!(line.contains("versions=[TLSv1_1]"), "{}", line);
+    assert!(line.contains("TLS_RSA_WITH_AES_128_CBC_SHA"), "{}", line);
+    assert!(line.contains("TLS_RSA_WITH_3DES_EDE_CBC_SHA"), "{}", line);
+    assert!(line.contains("sni=legacy-device.local"), "{}", line);
+    assert!(line.contains("alpn=[]"), "{}", line);
+    assert!(line.to_lowercase().contains("peer"), "{}", line);
+
+    // 成功的握手不记录（低于 trace 级别）
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let tcp = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let server_name = rustls::pki_types::ServerName::try_from("localhost").unwrap();
+    let tls = TlsConnector::from(tls_config)
+        .connect(server_name, tcp)
+        .await
+        .expect("Modern client should complete the handshake");
+    drop(tls);
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(logs.count(), 1, "{}", logs.text());
+
+    server.shutdown().await.ok();
+}
@@ -0,0 +1,203 @@
+/// UDP proxy tests
+///
+/// `proxy_type = "udp"` 的代理：服务器在发布端口接收数据报，按来源地址各开一个 stream 转发给
+/// 客户端，客户端发往本地 UDP 服务并把回复送回同一个来源；会话空闲超时后结束，
+/// 统计把数据报计为收发的字节
+mod common;
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+    ServerTimeoutsConfig,
+};
+use tls_tunnel::server::ServerDependencies;
+use tls_tunnel::stats::StatsManager;
+use tls_tunnel::transport::TransportType;
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-udp-proxy-key";
+
+fn server_config(cert_path: &Path, key_path: &Path) -> ServerConfig {
+    ServerConfig {
+        bind_addr: "127.0.0.1".to_string(),
+        bind_port: 0,
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        transport: TransportType::Tls,
+        behind_proxy: false,
+        allow_forward: false,
+        rate_limit: None,
+        size_limits: None,
+        stats_port: None,
+        stats_addr: None,
+        stats_path: None,
+        stats_limits: None,
+        stats_stream: None,
+        interface_prefer_ipv6: false,
+        event_export: None,
+        max_write_chunk: None,
+        debug_stalls: false,
+        debug_tls_handshakes: false,
+        exception_limits: None,
+        drain_timeout_secs: None,
+        protocol_trace_path: None,
+        forward_limits: None,
+        forward_policy: None,
+        max_concurrent_handshakes: None,
+        handshake_timeout_secs: None,
+        idle_registration_expiry_secs: None,
+        relay_memory_budget_mb: None,
+        known_clients: None,
+        visitor_acl: None,
+        timeouts: Some(ServerTimeoutsConfig {
+            udp_session_idle_secs: 1,
+            ..Default::default()
+        }),
+        flow_export: None,
+        shutdown_grace_period_secs: None,
+        visitor_retry_timeout_secs: None,
+        tcp_keepalive_secs: None,
+        idle_timeout_secs: None,
+        max_streams_per_client: None,
+        max_connections_per_proxy: None,
+        auth_keys: None,
+        client_ca_path: None,
+        require_client_cert: false,
+    }
+}
+
+fn spawn_client(server_port: u16, cert_path: &Path, proxy: ProxyConfig) -> JoinHandle<()> {
+    let config = ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path.to_path_buf()),
+            auth_key: AUTH_KEY.to_string(),
+            ..common::test_client_config()
+        },
+        proxies: vec![proxy],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    };
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    })
+}
+
+fn udp_proxy(publish_port: u16, local_port: u16) -> ProxyConfig {
+    ProxyConfig {
+        name: "dns".to_string(),
+        proxy_type: ProxyType::Udp,
+        publish_addr: "127.0.0.1".to_string(),
+        publish_port,
+        local_port,
+        pool: None,
+        shared: false,
+        weight: None,
+        visibility: ProxyVisibility::Public,
+        drain_timeout_secs: None,
+        sni_routing: None,
+        socket: None,
+        local_targets: None,
+        idle_keepalive_secs: None,
+        require_first_byte_timeout_ms: None,
+        max_connections_per_source: None,
+        report_peers: false,
+        ws_idle_timeout_secs: None,
+        integrity_check: false,
+    }
+}
+
+/// UDP 回显服务：每个数据报原样发回来源
+async fn start_udp_echo_server() -> (u16, JoinHandle<()>) {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let port = socket.local_addr().unwrap().port();
+    let handle = tokio::spawn(async move {
+        let mut buf = vec![0u8; 65535];
+        while let Ok((len, peer)) = socket.recv_from(&mut buf).await {
+            let _ = socket.send_to(&buf[..len], peer).await;
+        }
+    });
+    (port, handle)
+}
+
+/// 发送一个数据报并等待回复
+async fn exchange(socket: &UdpSocket, target: SocketAddr, data: &[u8]) -> Option<Vec<u8>> {
+    socket.send_to(data, target).await.ok()?;
+    let mut buf = vec![0u8; 65535];
+    let (len, from) = timeout(Duration::from_millis(500), socket.recv_from(&mut buf))
+        .await
+        .ok()?
+        .ok()?;
+    (from == target).then(|| buf[..len].to_vec())
+}
+
+#[tokio::test]
+async fn test_udp_proxy_relays_datagrams() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let (echo_port, _echo) = start_udp_echo_server().await;
+    let deps = ServerDependencies::new();
+    let stats_manager: StatsManager = deps.stats_manager.clone();
+    let server =
+        common::spawn_server_with_dependencies(server_config(&cert_path, &key_path), deps).await;
+    let publish_port = common::get_available_port();
+    let client = spawn_client(
+        server.bound_addr().port(),
+        &cert_path,
+        udp_proxy(publish_port, echo_port),
+    );
+
+    let target: SocketAddr = ([127, 0, 0, 1], publish_port).into();
+    let first = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            exchange(&first, target, b"ping").await.as_deref() == Some(&b"ping"[..])
+        })
+        .await,
+        "UDP proxy never relayed a datagram"
+    );
+
+    // 数据报的边界保持不变，每个来源只收到自己的回复
+    let second = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let large = vec![0x5a; 4000];
+    assert_eq!(exchange(&second, target, &large).await.unwrap(), large);
+    assert_eq!(exchange(&first, target, b"").await.unwrap(), b"");
+    assert_eq!(
+        exchange(&first, target, b"first again").await.unwrap(),
+        b"first again"
+    );
+
+    // 两个来源各一个会话，空闲超时后结束；字节数包含所有回显的数据报
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            stats_manager
+                .get_proxy_stats("dns")
+                .is_some_and(|stats| stats.core.active_connections == 0)
+        })
+        .await,
+        "UDP sessions did not expire"
+    );
+    let stats = stats_manager.get_proxy_stats("dns").unwrap();
+    assert!(stats.core.total_connections >= 2, "{:?}", stats.core);
+    let relayed = (b"first again".len() + large.len()) as u64;
+    assert!(stats.core.bytes_received >= relayed, "{:?}", stats.core);
+    assert!(stats.core.bytes_sent >= relayed, "{:?}", stats.core);
+
+    // 会话结束后同一来源的数据报开始新的会话
+    assert_eq!(exchange(&second, target, b"again").await.unwrap(), b"again");
+
+    client.abort();
+}
@@ -0,0 +1,289 @@
+/// Visitor access control tests
+///
+/// 服务器的 visitor_acl 按客户端身份限制可访问的代理：被拒绝的请求以 `VISITOR_FORBIDDEN`
+/// 开头的错误消息拒绝（visitor 统计的 `failures.server_rejected` 记录该消息），
+/// `/registry` 列出可以访问各代理的客户端和各客户端被拒绝的次数
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ClientVisitorAclConfig, ProxyConfig, ProxyType,
+    ProxyVisibility, ServerConfig, VisitorAclConfig, VisitorConfig,
+};
+use tls_tunnel::identity::ClientIdentity;
+use tls_tunnel::stats_http::StatsEndpoint;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-visitor-acl-key";
+
+fn server_config(
+    cert_path: &Path,
+    key_path: &Path,
+    stats_port: u16,
+    visitor_acl: VisitorAclConfig,
+) -> ServerConfig {
+    ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        stats_port: Some(stats_port),
+        stats_addr: Some("127.0.0.1".to_string()),
+        visitor_acl: Some(visitor_acl),
+        ..common::test_server_config()
+    }
+}
+
+fn client_config(
+    server_port: u16,
+    cert_path: &Path,
+    identity_path: &Path,
+    stats_port: Option<u16>,
+) -> ClientConfig {
+    ClientConfig {
+        server_port,
+        skip_verify: true,
+        ca_cert_path: Some(cert_path.to_path_buf()),
+        auth_key: AUTH_KEY.to_string(),
+        stats_port,
+        identity_path: Some(identity_path.to_path_buf()),
+        ..common::test_client_config()
+    }
+}
+
+fn private_proxy(name: &str, publish_port: u16, local_port: u16) -> ProxyConfig {
+    ProxyConfig {
+        name: name.to_string(),
+        proxy_type: ProxyType::Tcp,
+        publish_addr: "127.0.0.1".to_string(),
+        publish_port,
+        local_port,
+        pool: None,
+        shared: false,
+        weight: None,
+        visibility: ProxyVisibility::Private,
+        drain_timeout_secs: None,
+        sni_routing: None,
+        socket: None,
+        local_targets: None,
+        idle_keepalive_secs: None,
+        require_first_byte_timeout_ms: None,
+        max_connections_per_source: None,
+        report_peers: false,
+        ws_idle_timeout_secs: None,
+        integrity_check: false,
+    }
+}
+
+fn visitor(name: &str, bind_port: u16, publish_port: u16) -> VisitorConfig {
+    VisitorConfig {
+        name: name.to_string(),
+        proxy_type: ProxyType::Tcp,
+        bind_addr: "127.0.0.1".to_string(),
+        bind_port,
+        publish_port,
+        expected_peer_id: None,
+        connection_reuse: false,
+        socket: None,
+    }
+}
+
+fn spawn_client(config: ClientFullConfig, cert_path: &Path) -> JoinHandle<()> {
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    })
+}
+
+/// 启动一个接受连接后写出固定标识并关闭的后端
+async fn start_banner_server(port: u16, banner: &'static [u8]) -> JoinHandle<()> {
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port))
+        .await
+        .expect("Failed to bind banner server");
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let _ = socket.write_all(banner).await;
+            let _ = socket.shutdown().await;
+        }
+    })
+}
+
+/// 连接端口并读取全部数据（连接被拒绝或被关闭时返回空）
+async fn read_banner(port: u16) -> Vec<u8> {
+    let mut data = Vec::new();
+    if let Ok(mut stream) = TcpStream::connect(format!("127.0.0.1:{}", port)).await {
+        let _ = timeout(Duration::from_secs(5), stream.read_to_end(&mut data)).await;
+    }
+    data
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "tls-tunnel-visitor-acl-{}-{}",
+        name,
+        std::process::id()
+    ));
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+async fn get_json(endpoint: &StatsEndpoint, path: &str) -> Option<serde_json::Value> {
+    let body = endpoint.get(path).await.ok()?;
+    serde_json::from_str(&body).ok()
+}
+
+#[tokio::test]
+async fn test_visitor_allow_list_limits_reachable_proxies() {
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let dir = temp_dir("allow");
+
+    // 客户端 A 只能访问 db-*；其他客户端使用默认规则，不能访问 db-*
+    let path_a = dir.join("a.identity.pem");
+    let identity_a = ClientIdentity::load_or_create(&path_a).unwrap();
+    let server_stats_port = common::get_available_port();
+    let server = common::spawn_server(server_config(
+        &cert_path,
+        &key_path,
+        server_stats_port,
+        VisitorAclConfig {
+            visitor_allow: None,
+            visitor_deny: vec!["db-*".to_string()],
+            clients: vec![ClientVisitorAclConfig {
+                identity: identity_a.fingerprint(),
+                visitor_allow: Some(vec!["db-*".to_string()]),
+                visitor_deny: vec![],
+            }],
+        },
+    ))
+    .await;
+    let server_port = server.bound_addr().port();
+
+    let db_port = common::get_available_port();
+    let web_port = common::get_available_port();
+    let db_backend = start_banner_server(db_port, b"db-primary").await;
+    let web_backend = start_banner_server(web_port, b"web-admin").await;
+    let db_publish = common::get_available_port();
+    let web_publish = common::get_available_port();
+
+    let owner = spawn_client(
+        ClientFullConfig {
+            client: client_config(
+                server_port,
+                &cert_path,
+                &dir.join("owner.identity.pem"),
+                None,
+            ),
+            proxies: vec![
+                private_proxy("db-primary", db_publish, db_port),
+                private_proxy("web-admin", web_publish, web_port),
+            ],
+            visitors: vec![],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        &cert_path,
+    );
+    let stats = server.stats();
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            stats.get_proxy_stats("db-primary").is_some()
+                && stats.get_proxy_stats("web-admin").is_some()
+        })
+        .await
+    );
+
+    let visitor_stats_port = common::get_available_port();
+    let db_visitor = common::get_available_port();
+    let web_visitor = common::get_available_port();
+    let client_a = spawn_client(
+        ClientFullConfig {
+            client: client_config(server_port, &cert_path, &path_a, Some(visitor_stats_port)),
+            proxies: vec![],
+            visitors: vec![
+                visitor("db-primary", db_visitor, db_publish),
+                visitor("web-admin", web_visitor, web_publish),
+            ],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        &cert_path,
+    );
+
+    // 允许的代理正常转发
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            read_banner(db_visitor).await == b"db-primary"
+        })
+        .await
+    );
+
+    // 不允许的代理被拒绝，visitor 端记录服务器给出的 VISITOR_FORBIDDEN 错误
+    assert!(read_banner(web_visitor).await.is_empty());
+    let endpoint = StatsEndpoint::Http(format!("http://127.0.0.1:{}", visitor_stats_port));
+    let rejected = get_json(&endpoint, "/stats")
+        .await
+        .and_then(|stats| {
+            stats
+                .as_array()?
+                .iter()
+                .find(|s| s["name"] == "web-admin")
+                .cloned()
+        })
+        .expect("visitor stats for web-admin");
+    let last_error = rejected["failures"]["server_rejected"]["last_error"]
+        .as_str()
+        .unwrap_or_default();
+    assert!(
+        last_error.starts_with("VISITOR_FORBIDDEN: "),
+        "{}",
+        rejected
+    );
+    assert!(last_error.contains("web-admin"), "{}", last_error);
+
+    // /registry 列出可以访问各代理的客户端及拒绝计数
+    let server_endpoint = StatsEndpoint::Http(format!("http://127.0.0.1:{}", server_stats_port));
+    let registry = get_json(&server_endpoint, "/registry")
+        .await
+        .expect("registry listing");
+    assert_eq!(registry["acl_configured"], true);
+    let access = |name: &str| {
+        registry["proxies"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|p| p["name"] == name)
+            .map(|p| p["visitor_access"].clone())
+            .unwrap()
+    };
+    assert_eq!(access("db-primary")["default_rules"], false);
+    assert_eq!(
+        access("db-primary")["clients"],
+        serde_json::json!([identity_a.fingerprint()])
+    );
+    assert_eq!(access("web-admin")["default_rules"], true);
+    assert_eq!(access("web-admin")["clients"], serde_json::json!([]));
+    assert!(
+        registry["visitor_denials"][identity_a.fingerprint()]
+            .as_u64()
+            .unwrap_or(0)
+            >= 1,
+        "{}",
+        registry
+    );
+
+    client_a.abort();
+    owner.abort();
+    db_backend.abort();
+    web_backend.abort();
+    std::fs::remove_dir_all(&dir).ok();
+}
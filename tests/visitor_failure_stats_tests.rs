@@ -0,0 +1,208 @@
+/// Visitor stream failure stats tests
+///
+/// visitor 指向的 proxy 下线后，服务器拒绝每次请求：客户端统计的
+/// `failures.server_rejected` 随之递增，`total_connections` 仍然计入这些尝试
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+    VisitorConfig,
+};
+use tls_tunnel::stats_http::StatsEndpoint;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-visitor-failure-key";
+
+fn client_config(server_port: u16, cert_path: &Path, stats_port: Option<u16>) -> ClientConfig {
+    ClientConfig {
+        server_port,
+        skip_verify: true,
+        ca_cert_path: Some(cert_path.to_path_buf()),
+        auth_key: AUTH_KEY.to_string(),
+        stats_port,
+        ..common::test_client_config()
+    }
+}
+
+fn spawn_client(config: ClientFullConfig, cert_path: &Path) -> JoinHandle<()> {
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    })
+}
+
+/// 读取 visitor 的统计条目
+async fn visitor_stats(endpoint: &StatsEndpoint, name: &str) -> Option<serde_json::Value> {
+    let body = endpoint.get("/stats").await.ok()?;
+    let stats: Vec<serde_json::Value> = serde_json::from_str(&body).ok()?;
+    stats.into_iter().find(|s| s["name"] == name)
+}
+
+#[tokio::test]
+async fn test_rejected_visitor_streams_are_counted() {
+    let publish_port = common::get_available_port();
+    let visitor_port = common::get_available_port();
+    let stats_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+
+    let server = common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        // 不等待代理重新注册，每次请求立即被拒绝
+        visitor_retry_timeout_secs: Some(0),
+        ..common::test_server_config()
+    })
+    .await;
+
+    let server_port = server.bound_addr().port();
+
+    // 客户端 A 注册代理
+    let proxy_client = spawn_client(
+        ClientFullConfig {
+            client: client_config(server_port, &cert_path, None),
+            proxies: vec![ProxyConfig {
+                name: "db".to_string(),
+                proxy_type: ProxyType::Tcp,
+                publish_addr: "0.0.0.0".to_string(),
+                publish_port,
+                local_port: common::get_available_port(),
+                pool: None,
+                shared: false,
+                weight: None,
+                visibility: ProxyVisibility::Private,
+                drain_timeout_secs: None,
+                sni_routing: None,
+                socket: None,
+                local_targets: None,
+                idle_keepalive_secs: None,
+                require_first_byte_timeout_ms: None,
+                max_connections_per_source: None,
+                report_peers: false,
+                ws_idle_timeout_secs: None,
+                integrity_check: false,
+            }],
+            visitors: vec![],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        &cert_path,
+    );
+    let stats = server.stats();
+    for _ in 0..50 {
+        if stats.get_proxy_stats("db").is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert!(stats.get_proxy_stats("db").is_some());
+
+    // 客户端 B 启动 visitor
+    let visitor_client = spawn_client(
+        ClientFullConfig {
+            client: client_config(server_port, &cert_path, Some(stats_port)),
+            proxies: vec![],
+            visitors: vec![VisitorConfig {
+                name: "db".to_string(),
+                proxy_type: ProxyType::Tcp,
+                bind_addr: "127.0.0.1".to_string(),
+                bind_port: visitor_port,
+                publish_port,
+                expected_peer_id: None,
+                connection_reuse: false,
+                socket: None,
+            }],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        &cert_path,
+    );
+    // 通过统计等待 visitor 启动（探测 visitor 端口本身也会计为一次连接）
+    let endpoint = StatsEndpoint::Http(format!("http://127.0.0.1:{}", stats_port));
+    for _ in 0..50 {
+        if visitor_stats(&endpoint, "db").await.is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert!(visitor_stats(&endpoint, "db").await.is_some());
+
+    // 客户端 A 下线，proxy 从服务器注销
+    proxy_client.abort();
+    for _ in 0..50 {
+        if stats.get_proxy_stats("db").is_none() {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert!(stats.get_proxy_stats("db").is_none());
+
+    // 每次连接都被服务器拒绝，本地连接随即被关闭
+    const ATTEMPTS: u64 = 3;
+    for _ in 0..ATTEMPTS {
+        let mut stream = None;
+        for _ in 0..50 {
+            match TcpStream::connect(("127.0.0.1", visitor_port)).await {
+                Ok(s) => {
+                    stream = Some(s);
+                    break;
+                }
+                Err(_) => sleep(Duration::from_millis(100)).await,
+            }
+        }
+        let mut stream = stream.expect("Failed to connect to visitor");
+        let mut buf = [0u8; 16];
+        let n = timeout(Duration::from_secs(5), stream.read(&mut buf))
+            .await
+            .expect("Visitor should close rejected connections")
+            .unwrap_or(0);
+        assert_eq!(n, 0);
+    }
+
+    let mut entry = None;
+    for _ in 0..50 {
+        entry = visitor_stats(&endpoint, "db").await;
+        if entry
+            .as_ref()
+            .and_then(|e| e["failures"]["server_rejected"]["count"].as_u64())
+            .is_some_and(|count| count >= ATTEMPTS)
+        {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    let entry = entry.expect("Visitor stats unavailable");
+    let failures = &entry["failures"];
+    assert_eq!(failures["server_rejected"]["count"], ATTEMPTS, "{}", entry);
+    assert!(
+        failures["server_rejected"]["last_error"]
+            .as_str()
+            .is_some_and(|e| e.contains("not found")),
+        "{}",
+        entry
+    );
+    assert!(failures["server_rejected"]["last_at"].as_u64().is_some());
+    assert_eq!(failures["stream_open_failed"]["count"], 0);
+    assert_eq!(failures["recent"], ATTEMPTS);
+    assert_eq!(entry["total_connections"], ATTEMPTS);
+    assert_eq!(entry["active_connections"], 0);
+
+    // 仪表板以警告颜色显示失败
+    let html = endpoint.get("/").await.expect("Failed to fetch dashboard");
+    assert!(html.contains("badge-warning"), "{}", html);
+
+    visitor_client.abort();
+    server.shutdown().await.ok();
+}
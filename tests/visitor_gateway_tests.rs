@@ -0,0 +1,286 @@
+/// Visitor gateway tests
+///
+/// 一个 SOCKS5 网关端口按目标主机名访问多个代理：`name.tunnel:port` 选择代理，
+/// 未知名称回复 host unreachable，网关统计按目标代理名称分别计数
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, GatewayPortPolicy, ProxyConfig, ProxyType, ProxyVisibility,
+    ServerConfig, VisitorGatewayConfig,
+};
+use tls_tunnel::stats_http::StatsEndpoint;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-visitor-gateway-key";
+
+fn client_config(server_port: u16, cert_path: &Path, stats_port: Option<u16>) -> ClientConfig {
+    ClientConfig {
+        server_port,
+        skip_verify: true,
+        ca_cert_path: Some(cert_path.to_path_buf()),
+        auth_key: AUTH_KEY.to_string(),
+        stats_port,
+        ..common::test_client_config()
+    }
+}
+
+fn private_proxy(name: &str, publish_port: u16, local_port: u16) -> ProxyConfig {
+    ProxyConfig {
+        name: name.to_string(),
+        proxy_type: ProxyType::Tcp,
+        publish_addr: "0.0.0.0".to_string(),
+        publish_port,
+        local_port,
+        pool: None,
+        shared: false,
+        weight: None,
+        visibility: ProxyVisibility::Private,
+        drain_timeout_secs: None,
+        sni_routing: None,
+        socket: None,
+        local_targets: None,
+        idle_keepalive_secs: None,
+        require_first_byte_timeout_ms: None,
+        max_connections_per_source: None,
+        report_peers: false,
+        ws_idle_timeout_secs: None,
+        integrity_check: false,
+    }
+}
+
+fn gateway(bind_port: u16, port_policy: GatewayPortPolicy) -> VisitorGatewayConfig {
+    VisitorGatewayConfig {
+        bind_addr: "127.0.0.1".to_string(),
+        bind_port,
+        domain: "tunnel".to_string(),
+        port_policy,
+        expected_peer_ids: Default::default(),
+        socket: None,
+    }
+}
+
+fn spawn_client(config: ClientFullConfig, cert_path: &Path) -> JoinHandle<()> {
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    })
+}
+
+/// 启动本地服务：接受连接后先发送服务名称，然后回显收到的数据
+async fn start_named_server(port: u16, name: &'static str) -> JoinHandle<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                if stream.write_all(name.as_bytes()).await.is_err() {
+                    return;
+                }
+                let mut buf = [0u8; 1024];
+                while let Ok(n) = stream.read(&mut buf).await {
+                    if n == 0 || stream.write_all(&buf[..n]).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    })
+}
+
+/// 通过 SOCKS5 网关发起 CONNECT，返回回复码和连接
+async fn socks5_connect(gateway_port: u16, host: &str, port: u16) -> (u8, TcpStream) {
+    let mut stream = TcpStream::connect(("127.0.0.1", gateway_port))
+        .await
+        .expect("Failed to connect to gateway");
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [0x05, 0x00]);
+
+    let mut request = vec![0x05, 0x01, 0x00, 0x03, host.len() as u8];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+
+    let mut reply = [0u8; 10];
+    timeout(Duration::from_secs(10), stream.read_exact(&mut reply))
+        .await
+        .expect("Timed out waiting for SOCKS5 reply")
+        .unwrap();
+    assert_eq!(reply[0], 0x05);
+    (reply[1], stream)
+}
+
+/// 通过网关连接到代理，校验服务名称和回显
+async fn check_target(gateway_port: u16, host: &str, port: u16, expected: &str) {
+    let (rep, mut stream) = socks5_connect(gateway_port, host, port).await;
+    assert_eq!(rep, 0x00, "Gateway refused {}:{}", host, port);
+
+    let mut banner = vec![0u8; expected.len()];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut banner))
+        .await
+        .expect("Timed out waiting for banner")
+        .unwrap();
+    assert_eq!(banner, expected.as_bytes());
+
+    stream.write_all(b"ping").await.unwrap();
+    let mut echo = [0u8; 4];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut echo))
+        .await
+        .expect("Timed out waiting for echo")
+        .unwrap();
+    assert_eq!(&echo, b"ping");
+}
+
+/// 等待网关开始监听（探测连接不发送请求，网关在握手超时前关闭它）
+async fn wait_for_gateway(port: u16) {
+    for _ in 0..50 {
+        if TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+            return;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Visitor gateway never started listening");
+}
+
+/// 读取网关的统计条目
+async fn gateway_stats(endpoint: &StatsEndpoint) -> Option<serde_json::Value> {
+    let body = endpoint.get("/stats").await.ok()?;
+    let stats: Vec<serde_json::Value> = serde_json::from_str(&body).ok()?;
+    stats.into_iter().find(|s| s["name"] == "visitor_gateway")
+}
+
+#[tokio::test]
+async fn test_gateway_routes_by_host_name() {
+    let alpha_port = common::get_available_port();
+    let beta_port = common::get_available_port();
+    let alpha_local = common::get_available_port();
+    let beta_local = common::get_available_port();
+    let gateway_port = common::get_available_port();
+    let any_port_gateway = common::get_available_port();
+    let stats_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _alpha = start_named_server(alpha_local, "alpha").await;
+    let _beta = start_named_server(beta_local, "beta").await;
+
+    let server = common::spawn_server(ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        // 未注册的代理立即被拒绝
+        visitor_retry_timeout_secs: Some(0),
+        ..common::test_server_config()
+    })
+    .await;
+    let server_port = server.bound_addr().port();
+
+    // 客户端 A 注册两个私有代理
+    let proxy_client = spawn_client(
+        ClientFullConfig {
+            client: client_config(server_port, &cert_path, None),
+            proxies: vec![
+                private_proxy("alpha", alpha_port, alpha_local),
+                private_proxy("beta", beta_port, beta_local),
+            ],
+            visitors: vec![],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        &cert_path,
+    );
+    let stats = server.stats();
+    for _ in 0..50 {
+        if stats.get_proxy_stats("alpha").is_some() && stats.get_proxy_stats("beta").is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert!(stats.get_proxy_stats("beta").is_some());
+
+    // 客户端 B 通过一个网关访问两个代理
+    let gateway_client = spawn_client(
+        ClientFullConfig {
+            client: client_config(server_port, &cert_path, Some(stats_port)),
+            proxies: vec![],
+            visitors: vec![],
+            forwarders: vec![],
+            visitor_gateway: Some(gateway(gateway_port, GatewayPortPolicy::Match)),
+            health: None,
+        },
+        &cert_path,
+    );
+    wait_for_gateway(gateway_port).await;
+
+    check_target(gateway_port, "alpha.tunnel", alpha_port, "alpha").await;
+    check_target(gateway_port, "beta.Tunnel.", beta_port, "beta").await;
+    check_target(gateway_port, "beta", beta_port, "beta").await;
+
+    // 未知名称、其他域名和不匹配的端口都回复 host unreachable
+    let (rep, _) = socks5_connect(gateway_port, "missing.tunnel", alpha_port).await;
+    assert_eq!(rep, 0x04);
+    let (rep, _) = socks5_connect(gateway_port, "alpha.example.com", alpha_port).await;
+    assert_eq!(rep, 0x04);
+    let (rep, _) = socks5_connect(gateway_port, "alpha.tunnel", beta_port).await;
+    assert_eq!(rep, 0x04);
+
+    // 网关统计按目标代理名称分别计数
+    let endpoint = StatsEndpoint::Http(format!("http://127.0.0.1:{}", stats_port));
+    let mut entry = None;
+    for _ in 0..50 {
+        entry = gateway_stats(&endpoint).await;
+        if entry
+            .as_ref()
+            .is_some_and(|e| e["targets"]["beta"]["active_connections"] == 0)
+        {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    let entry = entry.expect("Gateway stats unavailable");
+    let targets = &entry["targets"];
+    assert_eq!(targets["alpha"]["total_connections"], 2, "{}", entry);
+    assert_eq!(targets["alpha"]["failures"], 1, "{}", entry);
+    assert_eq!(targets["beta"]["total_connections"], 2, "{}", entry);
+    assert_eq!(targets["beta"]["failures"], 0, "{}", entry);
+    assert!(targets["beta"]["bytes_sent"].as_u64().unwrap() >= 8);
+    assert!(targets["beta"]["bytes_received"].as_u64().unwrap() >= 16);
+    assert_eq!(targets["missing"]["failures"], 1, "{}", entry);
+    assert_eq!(
+        entry["failures"]["server_rejected"]["count"], 2,
+        "{}",
+        entry
+    );
+
+    // port_policy = "ignore" 时只按名称选择代理
+    let any_port_client = spawn_client(
+        ClientFullConfig {
+            client: client_config(server_port, &cert_path, None),
+            proxies: vec![],
+            visitors: vec![],
+            forwarders: vec![],
+            visitor_gateway: Some(gateway(any_port_gateway, GatewayPortPolicy::Ignore)),
+            health: None,
+        },
+        &cert_path,
+    );
+    wait_for_gateway(any_port_gateway).await;
+    check_target(any_port_gateway, "alpha.tunnel", 80, "alpha").await;
+    check_target(any_port_gateway, "beta.tunnel", 443, "beta").await;
+    let (rep, _) = socks5_connect(any_port_gateway, "missing.tunnel", 80).await;
+    assert_eq!(rep, 0x04);
+
+    any_port_client.abort();
+    gateway_client.abort();
+    proxy_client.abort();
+    server.shutdown().await.ok();
+}
@@ -0,0 +1,201 @@
+/// Visitor connection reuse tests
+///
+/// `connection_reuse = true` 的 visitor 将所有本地连接作为子流复用在一条隧道 stream 上，
+/// 客户端统计中的 `tunnel_streams` 记录实际占用的 stream 数
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+    VisitorConfig,
+};
+use tls_tunnel::stats_http::StatsEndpoint;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-visitor-mux-key";
+
+fn client_config(server_port: u16, cert_path: &Path, stats_port: Option<u16>) -> ClientConfig {
+    ClientConfig {
+        server_port,
+        skip_verify: true,
+        ca_cert_path: Some(cert_path.to_path_buf()),
+        auth_key: AUTH_KEY.to_string(),
+        stats_port,
+        ..common::test_client_config()
+    }
+}
+
+fn spawn_client(config: ClientFullConfig, cert_path: &Path) -> JoinHandle<()> {
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    })
+}
+
+/// 通过 visitor 发送数据并读取完整回显
+async fn echo_through(port: u16, payload: Vec<u8>) -> Vec<u8> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream.write_all(&payload).await.unwrap();
+    let mut response = vec![0u8; payload.len()];
+    tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut response))
+        .await
+        .expect("Timed out reading echo")
+        .unwrap();
+    response
+}
+
+/// 读取 visitor 的连接数和隧道 stream 数
+async fn visitor_stats(endpoint: &StatsEndpoint, name: &str) -> Option<(u64, u64)> {
+    let body = endpoint.get("/stats").await.ok()?;
+    let stats: Vec<serde_json::Value> = serde_json::from_str(&body).ok()?;
+    let entry = stats.iter().find(|s| s["name"] == name)?;
+    Some((
+        entry["total_connections"].as_u64()?,
+        entry["tunnel_streams"].as_u64()?,
+    ))
+}
+
+#[tokio::test]
+async fn test_visitor_connections_share_one_tunnel_stream() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let visitor_port = common::get_available_port();
+    let stats_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+
+    let server_config = ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.clone()),
+        key_path: Some(key_path.clone()),
+        ..common::test_server_config()
+    };
+    let server = common::spawn_server(server_config).await;
+    let server_port = server.bound_addr().port();
+
+    // 客户端 A 注册代理
+    let proxy_client = spawn_client(
+        ClientFullConfig {
+            client: client_config(server_port, &cert_path, None),
+            proxies: vec![ProxyConfig {
+                name: "mux-echo".to_string(),
+                proxy_type: ProxyType::Tcp,
+                publish_addr: "0.0.0.0".to_string(),
+                publish_port,
+                local_port,
+                pool: None,
+                shared: false,
+                weight: None,
+                visibility: ProxyVisibility::Private,
+                drain_timeout_secs: None,
+                sni_routing: None,
+                socket: None,
+                local_targets: None,
+                idle_keepalive_secs: None,
+                require_first_byte_timeout_ms: None,
+                max_connections_per_source: None,
+                report_peers: false,
+                ws_idle_timeout_secs: None,
+                integrity_check: false,
+            }],
+            visitors: vec![],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        &cert_path,
+    );
+
+    let stats = server.stats();
+    for _ in 0..50 {
+        if stats.get_proxy_stats("mux-echo").is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert!(stats.get_proxy_stats("mux-echo").is_some());
+
+    // 客户端 B 通过启用连接复用的 visitor 访问
+    let visitor_client = spawn_client(
+        ClientFullConfig {
+            client: client_config(server_port, &cert_path, Some(stats_port)),
+            proxies: vec![],
+            visitors: vec![VisitorConfig {
+                name: "mux-echo".to_string(),
+                proxy_type: ProxyType::Tcp,
+                bind_addr: "127.0.0.1".to_string(),
+                bind_port: visitor_port,
+                publish_port,
+                expected_peer_id: None,
+                connection_reuse: true,
+                socket: None,
+            }],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        &cert_path,
+    );
+
+    let mut ready = false;
+    for _ in 0..25 {
+        if let Ok(data) =
+            common::test_proxy_connection(visitor_port, b"warmup", Duration::from_secs(2)).await
+        {
+            assert_eq!(data, b"warmup");
+            ready = true;
+            break;
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+    assert!(ready, "Visitor did not become ready");
+
+    // 顺序连接
+    for i in 0..10 {
+        let payload = format!("sequential-{}", i).into_bytes();
+        assert_eq!(echo_through(visitor_port, payload.clone()).await, payload);
+    }
+
+    // 并发连接，每个连接的数据互不串扰
+    let tasks: Vec<_> = (0..10)
+        .map(|i| {
+            tokio::spawn(async move {
+                let payload: Vec<u8> = (0..64 * 1024).map(|n| (n * 7 + i) as u8).collect();
+                assert_eq!(echo_through(visitor_port, payload.clone()).await, payload);
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    // 所有连接只占用一条隧道 stream
+    let endpoint = StatsEndpoint::Http(format!("http://127.0.0.1:{}", stats_port));
+    let mut observed = None;
+    for _ in 0..50 {
+        observed = visitor_stats(&endpoint, "mux-echo").await;
+        if observed.is_some_and(|(total, _)| total >= 21) {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    let (total_connections, tunnel_streams) = observed.expect("Visitor stats unavailable");
+    assert!(
+        total_connections >= 21,
+        "total_connections = {}",
+        total_connections
+    );
+    assert_eq!(tunnel_streams, 1);
+
+    visitor_client.abort();
+    proxy_client.abort();
+}
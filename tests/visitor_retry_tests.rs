@@ -0,0 +1,232 @@
+/// Visitor retry tests
+///
+/// 注册代理的客户端断线重连期间，visitor 请求不会立即失败：服务器最多等待
+/// `visitor_retry_timeout_secs` 让代理重新注册，注册后照常转发；超时后仍未注册时拒绝请求
+mod common;
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyType, ProxyVisibility, ServerConfig,
+    VisitorConfig,
+};
+use tls_tunnel::server::ServerHandle;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsConnector;
+
+const AUTH_KEY: &str = "test-visitor-retry-key";
+
+fn server_config(cert_path: &Path, key_path: &Path, retry_secs: u64) -> ServerConfig {
+    ServerConfig {
+        auth_key: AUTH_KEY.to_string(),
+        cert_path: Some(cert_path.to_path_buf()),
+        key_path: Some(key_path.to_path_buf()),
+        visitor_retry_timeout_secs: Some(retry_secs),
+        ..common::test_server_config()
+    }
+}
+
+fn client_config(server_port: u16, cert_path: &Path) -> ClientConfig {
+    ClientConfig {
+        server_port,
+        skip_verify: true,
+        ca_cert_path: Some(cert_path.to_path_buf()),
+        auth_key: AUTH_KEY.to_string(),
+        ..common::test_client_config()
+    }
+}
+
+fn spawn_client(config: ClientFullConfig, cert_path: &Path) -> JoinHandle<()> {
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    })
+}
+
+/// 客户端 B：注册私有代理 `db`，转发到本地的 echo 服务
+fn spawn_proxy_client(
+    server_port: u16,
+    cert_path: &Path,
+    publish_port: u16,
+    local_port: u16,
+) -> JoinHandle<()> {
+    spawn_client(
+        ClientFullConfig {
+            client: client_config(server_port, cert_path),
+            proxies: vec![ProxyConfig {
+                name: "db".to_string(),
+                proxy_type: ProxyType::Tcp,
+                publish_addr: "0.0.0.0".to_string(),
+                publish_port,
+                local_port,
+                pool: None,
+                shared: false,
+                weight: None,
+                visibility: ProxyVisibility::Private,
+                drain_timeout_secs: None,
+                sni_routing: None,
+                socket: None,
+                local_targets: None,
+                idle_keepalive_secs: None,
+                require_first_byte_timeout_ms: None,
+                max_connections_per_source: None,
+                report_peers: false,
+                ws_idle_timeout_secs: None,
+                integrity_check: false,
+            }],
+            visitors: vec![],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        cert_path,
+    )
+}
+
+/// 客户端 C：访问代理 `db` 的 visitor
+fn spawn_visitor_client(
+    server_port: u16,
+    cert_path: &Path,
+    publish_port: u16,
+    visitor_port: u16,
+) -> JoinHandle<()> {
+    spawn_client(
+        ClientFullConfig {
+            client: client_config(server_port, cert_path),
+            proxies: vec![],
+            visitors: vec![VisitorConfig {
+                name: "db".to_string(),
+                proxy_type: ProxyType::Tcp,
+                bind_addr: "127.0.0.1".to_string(),
+                bind_port: visitor_port,
+                publish_port,
+                expected_peer_id: None,
+                connection_reuse: false,
+                socket: None,
+            }],
+            forwarders: vec![],
+            visitor_gateway: None,
+            health: None,
+        },
+        cert_path,
+    )
+}
+
+/// 启动代理和 visitor 并确认可以访问，然后让代理客户端下线
+async fn start_and_drop_owner(
+    server: &ServerHandle,
+    cert_path: &Path,
+    publish_port: u16,
+    local_port: u16,
+    visitor_port: u16,
+) -> JoinHandle<()> {
+    let server_port = server.bound_addr().port();
+    let stats = server.stats();
+    let proxy_client = spawn_proxy_client(server_port, cert_path, publish_port, local_port);
+    // 服务器拒绝引用未注册代理的 visitor 配置，visitor 客户端在代理注册后再启动
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            stats.get_proxy_stats("db").is_some()
+        })
+        .await,
+        "Proxy was not registered"
+    );
+    let visitor_client = spawn_visitor_client(server_port, cert_path, publish_port, visitor_port);
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            common::test_proxy_connection(visitor_port, b"before", Duration::from_secs(2))
+                .await
+                .is_ok_and(|data| data == b"before")
+        })
+        .await,
+        "Visitor never reached the proxy"
+    );
+
+    proxy_client.abort();
+    assert!(
+        common::wait_until(Duration::from_secs(10), || async {
+            stats.get_proxy_stats("db").is_none()
+        })
+        .await,
+        "Proxy was not unregistered"
+    );
+    visitor_client
+}
+
+#[tokio::test]
+async fn test_visitor_waits_for_owner_to_reconnect() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let visitor_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+    let server = common::spawn_server(server_config(&cert_path, &key_path, 10)).await;
+    let visitor_client =
+        start_and_drop_owner(&server, &cert_path, publish_port, local_port, visitor_port).await;
+
+    // 代理下线期间发起 visitor 连接
+    let mut stream = TcpStream::connect(("127.0.0.1", visitor_port))
+        .await
+        .expect("Failed to connect to visitor");
+    stream.write_all(b"during gap").await.unwrap();
+
+    // 客户端 B 在等待窗口内重新上线
+    sleep(Duration::from_secs(2)).await;
+    let proxy_client = spawn_proxy_client(
+        server.bound_addr().port(),
+        &cert_path,
+        publish_port,
+        local_port,
+    );
+
+    let mut buf = [0u8; 10];
+    timeout(Duration::from_secs(10), stream.read_exact(&mut buf))
+        .await
+        .expect("Visitor connection was not relayed after the owner reconnected")
+        .expect("Visitor connection failed while the owner was offline");
+    assert_eq!(&buf, b"during gap");
+
+    proxy_client.abort();
+    visitor_client.abort();
+}
+
+#[tokio::test]
+async fn test_visitor_rejected_after_retry_window() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let visitor_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let _echo = common::start_echo_server(local_port).await;
+    let server = common::spawn_server(server_config(&cert_path, &key_path, 1)).await;
+    let visitor_client =
+        start_and_drop_owner(&server, &cert_path, publish_port, local_port, visitor_port).await;
+
+    // 代理没有重新注册：等待窗口结束后连接被关闭
+    let started = Instant::now();
+    let mut stream = TcpStream::connect(("127.0.0.1", visitor_port))
+        .await
+        .expect("Failed to connect to visitor");
+    stream.write_all(b"nobody home").await.unwrap();
+    let mut buf = [0u8; 16];
+    let n = timeout(Duration::from_secs(5), stream.read(&mut buf))
+        .await
+        .expect("Visitor should close the rejected connection")
+        .unwrap_or(0);
+    assert_eq!(n, 0);
+    assert!(
+        started.elapsed() >= Duration::from_millis(900),
+        "Rejected before the retry window elapsed: {:?}",
+        started.elapsed()
+    );
+
+    visitor_client.abort();
+}
@@ -0,0 +1,252 @@
+/// WebSocket upgrade tests
+///
+/// http/1.1 代理的本地服务返回 `101 Switching Protocols` 后，连接切换为纯转发：
+/// 不会按 HTTP 消息边界归还连接池，只受 `ws_idle_timeout_secs` 的空闲超时约束
+mod common;
+
+use futures::{SinkExt, StreamExt};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tls_tunnel::config::{
+    ClientConfig, ClientFullConfig, ProxyConfig, ProxyPoolConfig, ProxyType, ProxyVisibility,
+    ServerConfig, ServerTimeoutsConfig,
+};
+use tls_tunnel::stats_http::StatsEndpoint;
+use tls_tunnel::transport::TransportType;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+const WS_IDLE_TIMEOUT_SECS: u64 = 120;
+
+/// 启动 WebSocket 回显服务，返回已接受的 TCP 连接数
+async fn start_ws_echo_server(port: u16) -> Arc<AtomicUsize> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let counter = accepted.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            counter.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let Ok(mut ws) = tokio_tungstenite::accept_async(stream).await else {
+                    return;
+                };
+                while let Some(Ok(message)) = ws.next().await {
+                    if (message.is_text() || message.is_binary()) && ws.send(message).await.is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    accepted
+}
+
+fn server_config(cert_path: std::path::PathBuf, key_path: std::path::PathBuf) -> ServerConfig {
+    ServerConfig {
+        bind_addr: "127.0.0.1".to_string(),
+        bind_port: 0,
+        auth_key: "test-websocket-key".to_string(),
+        cert_path: Some(cert_path),
+        key_path: Some(key_path),
+        transport: TransportType::Tls,
+        behind_proxy: false,
+        allow_forward: false,
+        rate_limit: None,
+        size_limits: None,
+        stats_port: None,
+        stats_addr: None,
+        stats_path: None,
+        stats_limits: None,
+        stats_stream: None,
+        interface_prefer_ipv6: false,
+        event_export: None,
+        max_write_chunk: None,
+        debug_stalls: false,
+        debug_tls_handshakes: false,
+        exception_limits: None,
+        drain_timeout_secs: None,
+        protocol_trace_path: None,
+        forward_limits: None,
+        forward_policy: None,
+        max_concurrent_handshakes: None,
+        handshake_timeout_secs: None,
+        idle_registration_expiry_secs: None,
+        relay_memory_budget_mb: None,
+        known_clients: None,
+        visitor_acl: None,
+        // 模拟时间自动推进时心跳可能晚到，放宽心跳超时避免会话在空闲期间断开
+        timeouts: Some(ServerTimeoutsConfig {
+            heartbeat_timeout_secs: 3600,
+            ..Default::default()
+        }),
+        flow_export: None,
+        shutdown_grace_period_secs: None,
+        visitor_retry_timeout_secs: None,
+        tcp_keepalive_secs: None,
+        idle_timeout_secs: None,
+        max_streams_per_client: None,
+        max_connections_per_proxy: None,
+        auth_keys: None,
+        client_ca_path: None,
+        require_client_cert: false,
+    }
+}
+
+fn client_config(
+    cert_path: std::path::PathBuf,
+    server_port: u16,
+    stats_port: u16,
+    publish_port: u16,
+    local_port: u16,
+) -> ClientFullConfig {
+    ClientFullConfig {
+        client: ClientConfig {
+            server_port,
+            skip_verify: true,
+            ca_cert_path: Some(cert_path),
+            auth_key: "test-websocket-key".to_string(),
+            stats_port: Some(stats_port),
+            stats_addr: Some("127.0.0.1".to_string()),
+            // 模拟时间自动推进时心跳确认可能晚于下一次心跳，不因此重连
+            max_missed_heartbeats: Some(1000),
+            ..common::test_client_config()
+        },
+        proxies: vec![ProxyConfig {
+            name: "ws".to_string(),
+            proxy_type: ProxyType::Http11,
+            publish_addr: "127.0.0.1".to_string(),
+            publish_port,
+            local_port,
+            // 普通 HTTP 连接 30 秒后轮换，WebSocket 连接的寿命远超这个时间
+            pool: Some(ProxyPoolConfig {
+                max_size: Some(4),
+                min_idle: Some(0),
+                warmup: Some(false),
+                reuse: Some(true),
+                max_lifetime_secs: Some(30),
+            }),
+            shared: false,
+            weight: None,
+            visibility: ProxyVisibility::Public,
+            drain_timeout_secs: None,
+            sni_routing: None,
+            socket: None,
+            local_targets: None,
+            idle_keepalive_secs: None,
+            require_first_byte_timeout_ms: None,
+            max_connections_per_source: None,
+            report_peers: false,
+            ws_idle_timeout_secs: Some(WS_IDLE_TIMEOUT_SECS),
+            integrity_check: false,
+        }],
+        visitors: vec![],
+        forwarders: vec![],
+        visitor_gateway: None,
+        health: None,
+    }
+}
+
+/// 通过发布端口完成 WebSocket 握手（发布端口监听后客户端可能还没开始处理 stream，失败时重试）
+async fn connect_ws(publish_port: u16) -> WebSocketStream<TcpStream> {
+    let url = format!("ws://127.0.0.1:{}/echo", publish_port);
+    for _ in 0..50 {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", publish_port)).await {
+            if let Ok((ws, _)) = tokio_tungstenite::client_async(url.as_str(), stream).await {
+                return ws;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("WebSocket handshake through the tunnel failed");
+}
+
+async fn echo(ws: &mut WebSocketStream<TcpStream>, text: &str) {
+    ws.send(Message::text(text)).await.unwrap();
+    let reply = timeout(Duration::from_secs(10), ws.next())
+        .await
+        .expect("Timed out waiting for echo")
+        .expect("WebSocket closed")
+        .expect("WebSocket failed");
+    assert_eq!(reply, Message::text(text));
+}
+
+/// 客户端统计中代理的 WebSocket 升级数
+async fn upgraded_connections(endpoint: &StatsEndpoint) -> Option<u64> {
+    let body = endpoint.get("/stats").await.ok()?;
+    let stats: Vec<serde_json::Value> = serde_json::from_str(&body).ok()?;
+    let entry = stats.iter().find(|s| s["name"] == "ws")?;
+    entry["upgraded_connections"].as_u64()
+}
+
+#[tokio::test]
+async fn test_websocket_survives_and_is_never_pooled() {
+    let publish_port = common::get_available_port();
+    let local_port = common::get_available_port();
+    let stats_port = common::get_available_port();
+
+    let (cert_path, key_path) = common::generate_test_certs();
+    let _cleanup = common::TestCleanup::new(cert_path.clone(), key_path.clone());
+    let accepted = start_ws_echo_server(local_port).await;
+
+    let server = common::spawn_server(server_config(cert_path.clone(), key_path)).await;
+    let tls_config = tls_tunnel::tls::load_client_config_with_alpn(Some(&cert_path), true, None)
+        .expect("Failed to load client TLS config");
+    let connector = TlsConnector::from(tls_config);
+    let config = client_config(
+        cert_path.clone(),
+        server.bound_addr().port(),
+        stats_port,
+        publish_port,
+        local_port,
+    );
+    let client = tokio::spawn(async move {
+        tls_tunnel::client::run_client(config, connector).await.ok();
+    });
+    assert!(common::wait_for_server(publish_port, 50).await);
+
+    let mut ws = connect_ws(publish_port).await;
+    echo(&mut ws, "hello").await;
+    let accepted_before = accepted.load(Ordering::SeqCst);
+
+    // 模拟时间内每分钟交换一次消息，超过连接池寿命和 WebSocket 空闲超时的总时长
+    tokio::time::pause();
+    for minute in 1..=5 {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        echo(&mut ws, &format!("minute {}", minute)).await;
+    }
+    assert_eq!(accepted.load(Ordering::SeqCst), accepted_before);
+
+    // 两个方向都空闲超过 ws_idle_timeout_secs 后关闭连接
+    tokio::time::sleep(Duration::from_secs(WS_IDLE_TIMEOUT_SECS + 1)).await;
+    tokio::time::resume();
+    let closed = timeout(Duration::from_secs(10), ws.next())
+        .await
+        .expect("Idle WebSocket was not closed");
+    assert!(
+        !matches!(closed, Some(Ok(Message::Text(_)))),
+        "unexpected message: {:?}",
+        closed
+    );
+
+    // 升级的连接没有归还连接池：新的 WebSocket 使用新的本地连接
+    let mut ws = connect_ws(publish_port).await;
+    echo(&mut ws, "again").await;
+    assert_eq!(accepted.load(Ordering::SeqCst), accepted_before + 1);
+
+    let endpoint = StatsEndpoint::Http(format!("http://127.0.0.1:{}", stats_port));
+    assert!(
+        common::wait_until(Duration::from_secs(5), || async {
+            upgraded_connections(&endpoint).await == Some(2)
+        })
+        .await
+    );
+
+    ws.close(None).await.ok();
+    client.abort();
+    server.shutdown().await.ok();
+}